@@ -0,0 +1,10 @@
+-- Content-addressed storage for chat document attachments.
+-- Messages keep only attachment metadata plus `content_hash`; the payload
+-- (extracted text / base64) is stored once per SHA-256 fingerprint here.
+
+CREATE TABLE IF NOT EXISTS doc_attachment_blobs (
+    content_hash TEXT PRIMARY KEY NOT NULL,
+    text_content TEXT,
+    base64_content TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ','now'))
+);
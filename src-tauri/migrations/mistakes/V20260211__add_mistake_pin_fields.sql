@@ -0,0 +1,10 @@
+-- ============================================================================
+-- Mistakes: 置顶与星级标记
+-- ============================================================================
+-- pinned: 置顶标记（NULL 视为未置顶，兼容旧数据）
+-- star_rating: 可选 1-5 星重要度
+
+ALTER TABLE mistakes ADD COLUMN pinned INTEGER;
+ALTER TABLE mistakes ADD COLUMN star_rating INTEGER CHECK (star_rating IS NULL OR star_rating BETWEEN 1 AND 5);
+
+CREATE INDEX IF NOT EXISTS idx_mistakes_pinned ON mistakes(pinned) WHERE pinned = 1;
@@ -0,0 +1,13 @@
+-- ============================================================================
+-- Mistakes: 归档（隐藏已掌握错题，不删除）
+-- ============================================================================
+-- archived_at: 归档时间（RFC3339），NULL 表示未归档。与回收站（deleted_at）相互独立。
+
+ALTER TABLE mistakes ADD COLUMN archived_at TEXT;
+
+-- 旧的自动归档仅修改 status，补写归档时间
+UPDATE mistakes
+SET archived_at = COALESCE(updated_at, created_at)
+WHERE status = 'archived' AND archived_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_mistakes_archived_at ON mistakes(archived_at);
@@ -0,0 +1,7 @@
+-- ============================================================================
+-- Mistakes: 用户批注
+-- ============================================================================
+-- user_notes: 用户（教师）自己的自由文本批注，与 AI 生成的 user_error_analysis 区分。
+-- 仅用于展示、搜索与导出，默认不发送给模型。
+
+ALTER TABLE mistakes ADD COLUMN user_notes TEXT;
@@ -0,0 +1,13 @@
+-- ============================================================================
+-- Mistakes: 难度与掌握度
+-- ============================================================================
+-- difficulty: 可选 1-5 难度（旧库兼容列可能已存在且默认 0，读取时 0 视为未设置）
+-- mastery_level: 掌握度 0-100，由复习结果更新
+-- review_count / last_reviewed_at: 复习次数与最近复习时间（旧库可能已存在）
+
+ALTER TABLE mistakes ADD COLUMN difficulty INTEGER CHECK (difficulty IS NULL OR difficulty BETWEEN 1 AND 5);
+ALTER TABLE mistakes ADD COLUMN mastery_level INTEGER NOT NULL DEFAULT 0 CHECK (mastery_level BETWEEN 0 AND 100);
+ALTER TABLE mistakes ADD COLUMN review_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE mistakes ADD COLUMN last_reviewed_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_mistakes_mastery_level ON mistakes(mastery_level);
@@ -0,0 +1,8 @@
+-- ============================================================================
+-- Mistakes: 总结生成追踪
+-- ============================================================================
+-- summary_content_hash: 生成总结时题目与对话内容的 SHA-256，内容未变时跳过重新生成
+-- last_summarized_at: 最近一次生成总结的时间，供界面提示总结是否过时
+
+ALTER TABLE mistakes ADD COLUMN summary_content_hash TEXT;
+ALTER TABLE mistakes ADD COLUMN last_summarized_at TEXT;
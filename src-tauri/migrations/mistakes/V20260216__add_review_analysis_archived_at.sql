@@ -0,0 +1,8 @@
+-- ============================================================================
+-- Mistakes: 回顾分析归档（隐藏已完成的分析，不删除）
+-- ============================================================================
+-- archived_at: 归档时间（RFC3339），NULL 表示未归档。与同步墓碑（deleted_at）相互独立。
+
+ALTER TABLE review_analyses ADD COLUMN archived_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_review_analyses_archived_at ON review_analyses(archived_at);
@@ -0,0 +1,19 @@
+-- ============================================================================
+-- Mistakes: 错题之间的手动关联
+-- ============================================================================
+-- relation_type: prerequisite（from 是 to 的前置）/ related / duplicate，后两者不区分方向。
+-- 同一对错题同一类型只保留一条；不允许自关联。错题被彻底删除时关联随之删除
+-- （软删除不影响，恢复后关联仍在）。
+
+CREATE TABLE IF NOT EXISTS mistake_relations (
+    from_mistake_id TEXT NOT NULL,
+    to_mistake_id TEXT NOT NULL,
+    relation_type TEXT NOT NULL CHECK (relation_type IN ('prerequisite', 'related', 'duplicate')),
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (from_mistake_id, to_mistake_id, relation_type),
+    CHECK (from_mistake_id <> to_mistake_id),
+    FOREIGN KEY (from_mistake_id) REFERENCES mistakes(id) ON DELETE CASCADE,
+    FOREIGN KEY (to_mistake_id) REFERENCES mistakes(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_mistake_relations_to ON mistake_relations(to_mistake_id);
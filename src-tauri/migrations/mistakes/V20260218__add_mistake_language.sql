@@ -0,0 +1,7 @@
+-- ============================================================================
+-- Mistakes: 题目语言
+-- ============================================================================
+-- language: OCR 识别出的题目主要语言（zh / en / ja / ko，混排为 mixed），
+-- 可手动修改；为空表示未识别，后续处理按内容自行推断
+
+ALTER TABLE mistakes ADD COLUMN language TEXT;
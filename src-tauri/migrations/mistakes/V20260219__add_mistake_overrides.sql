@@ -0,0 +1,8 @@
+-- ============================================================================
+-- Mistakes: 错题级覆盖信息
+-- ============================================================================
+-- overrides: JSON 对象，记录非用户手动产生的字段来源等附加信息；
+-- 目前用于 ai_tags（模型自动分配的标签、置信度与所用模型），
+-- 以便区分 AI 分配的标签与用户手动填写的标签。为空表示没有覆盖信息
+
+ALTER TABLE mistakes ADD COLUMN overrides TEXT;
@@ -0,0 +1,7 @@
+-- ============================================================================
+-- RAG 配置: 嵌入批大小
+-- ============================================================================
+-- embedding_batch_size: 导入文档时每次嵌入请求包含的分块数，默认 16；
+-- 整批失败时逐个重试，避免单个分块拖累整批
+
+ALTER TABLE rag_configurations ADD COLUMN embedding_batch_size INTEGER NOT NULL DEFAULT 16;
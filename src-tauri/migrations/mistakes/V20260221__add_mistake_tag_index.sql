@@ -0,0 +1,43 @@
+-- ============================================================================
+-- Mistakes: 错题标签索引
+-- ============================================================================
+-- mistakes.tags 以 JSON 数组存储，按标签筛选时逐行解析 JSON 代价较高。
+-- mistake_tag_index 为 (错题, 标签) 的规范化索引：标签去除首尾空白，空标签不入索引。
+-- 由触发器在错题插入、标签更新与彻底删除时维护，任何写入路径都无需额外处理；
+-- 软删除不影响索引，查询时仍按 deleted_at 过滤。
+
+CREATE TABLE IF NOT EXISTS mistake_tag_index (
+    mistake_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (mistake_id, tag)
+) WITHOUT ROWID;
+
+CREATE INDEX IF NOT EXISTS idx_mistake_tag_index_tag ON mistake_tag_index(tag, mistake_id);
+
+-- 回填已有错题
+INSERT OR IGNORE INTO mistake_tag_index (mistake_id, tag)
+SELECT mistakes.id, TRIM(je.value)
+FROM mistakes, json_each(CASE WHEN json_valid(mistakes.tags) THEN mistakes.tags ELSE '[]' END) je
+WHERE TRIM(je.value) <> '';
+
+CREATE TRIGGER IF NOT EXISTS trg_mistake_tag_index_insert AFTER INSERT ON mistakes
+BEGIN
+    INSERT OR IGNORE INTO mistake_tag_index (mistake_id, tag)
+    SELECT NEW.id, TRIM(je.value)
+    FROM json_each(CASE WHEN json_valid(NEW.tags) THEN NEW.tags ELSE '[]' END) je
+    WHERE TRIM(je.value) <> '';
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_mistake_tag_index_update AFTER UPDATE OF id, tags ON mistakes
+BEGIN
+    DELETE FROM mistake_tag_index WHERE mistake_id = OLD.id;
+    INSERT OR IGNORE INTO mistake_tag_index (mistake_id, tag)
+    SELECT NEW.id, TRIM(je.value)
+    FROM json_each(CASE WHEN json_valid(NEW.tags) THEN NEW.tags ELSE '[]' END) je
+    WHERE TRIM(je.value) <> '';
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_mistake_tag_index_delete AFTER DELETE ON mistakes
+BEGIN
+    DELETE FROM mistake_tag_index WHERE mistake_id = OLD.id;
+END;
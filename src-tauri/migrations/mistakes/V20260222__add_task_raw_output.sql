@@ -0,0 +1,8 @@
+-- ============================================================================
+-- 制卡任务: 模型原始输出
+-- ============================================================================
+-- raw_output: 解析失败任务的模型原始输出（按上限截断），供调试查看
+-- raw_output_bytes: 截断前的字节数
+
+ALTER TABLE document_tasks ADD COLUMN raw_output TEXT;
+ALTER TABLE document_tasks ADD COLUMN raw_output_bytes INTEGER;
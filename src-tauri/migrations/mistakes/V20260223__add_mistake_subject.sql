@@ -0,0 +1,7 @@
+-- ============================================================================
+-- Mistakes: 科目
+-- ============================================================================
+-- subject: 错题所属科目，为空表示未分类；导入时写入，
+-- 标签建议、去重、导出分组、自动总结模型覆盖等按科目筛选
+
+ALTER TABLE mistakes ADD COLUMN subject TEXT;
@@ -0,0 +1,164 @@
+//! 制卡数量硬上限
+//!
+//! 部分文档会被拆成成百上千张低价值卡片。`max_cards_per_segment` 与 `max_cards_per_document`
+//! 为硬上限：提示词中告知模型数量限制；模型仍超量输出时多余的卡片不保存，任务标记为
+//! `Truncated`，`error_message` 说明丢弃了多少张，便于用户调高上限后重新生成。
+//!
+//! 与期望生成数 `max_cards_per_mistake` 不同，达到期望数只是正常结束。文档上限由同一文档的
+//! 并发分段共享：保存前在文档锁内按数据库中已有的有效卡片数判断，暂停恢复与重新生成同样适用。
+
+use std::sync::{Arc, LazyLock};
+
+use dashmap::DashMap;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::models::AnkiGenerationOptions;
+
+/// 文档级保存锁：同一文档的卡片按顺序检查额度并保存
+static DOCUMENT_CARD_LOCKS: LazyLock<DashMap<String, Arc<Mutex<()>>>> = LazyLock::new(DashMap::new);
+
+fn positive(limit: Option<i32>) -> Option<u32> {
+    limit.filter(|n| *n > 0).map(|n| n as u32)
+}
+
+pub fn segment_cap(options: &AnkiGenerationOptions) -> Option<u32> {
+    positive(options.max_cards_per_segment)
+}
+
+pub fn document_cap(options: &AnkiGenerationOptions) -> Option<u32> {
+    positive(options.max_cards_per_document)
+}
+
+/// 提示词中要求的分段卡片数（0 表示由模型决定）：期望生成数不超过分段上限
+pub fn requested_cards(options: &AnkiGenerationOptions) -> i32 {
+    match segment_cap(options) {
+        Some(cap) if options.max_cards_per_mistake > cap as i32 => cap as i32,
+        _ => options.max_cards_per_mistake,
+    }
+}
+
+/// 追加在数量要求之后的上限说明；未设置上限时为 None
+pub fn limit_instruction(options: &AnkiGenerationOptions) -> Option<String> {
+    let mut lines = Vec::new();
+    if let Some(cap) = segment_cap(options) {
+        lines.push(format!(
+            "- 本段内容最多输出 {} 张卡片，超出部分会被直接丢弃",
+            cap
+        ));
+    }
+    if let Some(cap) = document_cap(options) {
+        lines.push(format!(
+            "- 整份文档合计最多保留 {} 张卡片，请只为最重要的知识点制卡",
+            cap
+        ));
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    Some(format!("卡片数量上限：\n{}\n\n", lines.join("\n")))
+}
+
+/// 获取文档保存锁，持有期间检查文档额度并保存卡片
+pub async fn lock_document(document_id: &str) -> OwnedMutexGuard<()> {
+    let lock = DOCUMENT_CARD_LOCKS
+        .entry(document_id.to_string())
+        .or_default()
+        .clone();
+    lock.lock_owned().await
+}
+
+/// 文档处理结束或会话删除后释放锁条目
+pub fn release_document(document_id: &str) {
+    DOCUMENT_CARD_LOCKS.remove(document_id);
+}
+
+/// 单个任务因上限丢弃的卡片
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CardCapReport {
+    pub dropped: u32,
+    pub segment_cap_hit: bool,
+    pub document_cap_hit: bool,
+}
+
+impl CardCapReport {
+    pub fn drop_for_segment(&mut self) {
+        self.dropped += 1;
+        self.segment_cap_hit = true;
+    }
+
+    pub fn drop_for_document(&mut self) {
+        self.dropped += 1;
+        self.document_cap_hit = true;
+    }
+
+    /// 截断说明，写入任务的 `error_message`；未丢弃卡片时为 None
+    pub fn truncation_message(&self, options: &AnkiGenerationOptions) -> Option<String> {
+        if self.dropped == 0 {
+            return None;
+        }
+        let mut limits = Vec::new();
+        if self.segment_cap_hit {
+            if let Some(cap) = segment_cap(options) {
+                limits.push(format!("每分段 {} 张", cap));
+            }
+        }
+        if self.document_cap_hit {
+            if let Some(cap) = document_cap(options) {
+                limits.push(format!("每文档 {} 张", cap));
+            }
+        }
+        Some(format!(
+            "已达到卡片数量上限（{}），模型多生成的 {} 张卡片未保存；如需保留，请调高上限后重新生成该分段",
+            limits.join("，"),
+            self.dropped
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(
+        per_mistake: i32,
+        per_segment: Option<i32>,
+        per_document: Option<i32>,
+    ) -> AnkiGenerationOptions {
+        let mut options: AnkiGenerationOptions = serde_json::from_value(serde_json::json!({
+            "deck_name": "默认牌组",
+            "note_type": "Basic",
+            "enable_images": false,
+            "max_cards_per_mistake": per_mistake,
+        }))
+        .unwrap();
+        options.max_cards_per_segment = per_segment;
+        options.max_cards_per_document = per_document;
+        options
+    }
+
+    #[test]
+    fn test_requested_cards_respects_segment_cap() {
+        assert_eq!(requested_cards(&options(30, Some(10), None)), 10);
+        assert_eq!(requested_cards(&options(5, Some(10), None)), 5);
+        assert_eq!(requested_cards(&options(0, Some(10), None)), 0);
+        assert_eq!(requested_cards(&options(30, Some(0), None)), 30);
+        assert!(limit_instruction(&options(30, None, Some(0))).is_none());
+        let instruction = limit_instruction(&options(0, Some(10), Some(50))).unwrap();
+        assert!(instruction.contains("最多输出 10 张"));
+        assert!(instruction.contains("最多保留 50 张"));
+    }
+
+    #[test]
+    fn test_truncation_message_names_hit_limits() {
+        let opts = options(0, Some(10), Some(50));
+        let mut report = CardCapReport::default();
+        assert!(report.truncation_message(&opts).is_none());
+        report.drop_for_segment();
+        report.drop_for_segment();
+        report.drop_for_document();
+        let message = report.truncation_message(&opts).unwrap();
+        assert!(message.contains("每分段 10 张"));
+        assert!(message.contains("每文档 50 张"));
+        assert!(message.contains("3 张卡片未保存"));
+    }
+}
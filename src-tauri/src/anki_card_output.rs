@@ -0,0 +1,374 @@
+//! Anki 制卡输出格式与容错解析
+//!
+//! 默认的「分隔符」格式要求模型逐张输出卡片 JSON 并以 `<<<ANKI_CARD_JSON_END>>>` 分隔；
+//! 模型遗漏或写坏分隔符时整段都会解析失败。支持 JSON 模式的服务商可改用结构化输出：
+//! 请求中携带 `response_format`（`json_object` 或按模板字段生成的 `json_schema`），
+//! 模型输出 `{"cards": [...]}`，流式过程中由 [`CardJsonScanner`] 逐张截取已完整的卡片对象。
+//!
+//! 输出格式由设置项 `anki.output_mode` 决定，单次任务可通过
+//! `AnkiGenerationOptions.output_mode` 覆盖：`off`（分隔符格式）、`json_object`、
+//! `json_schema` 或 `auto`（默认，按模型适配器判断是否支持）。
+//!
+//! 无论哪种格式，扫描器都能从残缺的数组、混入说明文字或漏写分隔符的输出中抢救出
+//! 完整的卡片对象；实在无法解析的片段由调用方保存为错误卡片（保留原始文本），
+//! 而不是丢弃整个分段。
+
+use serde_json::{json, Value};
+
+use crate::database::Database;
+use crate::llm_manager::ApiConfig;
+
+pub const ANKI_OUTPUT_MODE_SETTING_KEY: &str = "anki.output_mode";
+
+/// 结构化输出时卡片数组所在的键
+pub const CARDS_KEY: &str = "cards";
+
+/// 单张候选卡片的最大长度，超过视为输出失控，交由调用方作为错误卡片处理
+const MAX_CARD_CANDIDATE_CHARS: usize = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardOutputMode {
+    /// 逐张输出 + 分隔符（原有格式）
+    Delimited,
+    /// `response_format: json_object`
+    JsonObject,
+    /// `response_format: json_schema`，schema 由模板字段生成
+    JsonSchema,
+}
+
+impl CardOutputMode {
+    /// 解析配置取值；`auto` 与无法识别的取值返回 None
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().replace('-', "_").as_str() {
+            "off" | "delimited" | "none" => Some(Self::Delimited),
+            "json" | "json_object" => Some(Self::JsonObject),
+            "schema" | "json_schema" => Some(Self::JsonSchema),
+            _ => None,
+        }
+    }
+
+    pub fn is_json(self) -> bool {
+        !matches!(self, Self::Delimited)
+    }
+
+    /// 按模型适配器推断：OpenAI / Gemini 支持 JSON Schema；声明支持函数调用的
+    /// OpenAI 兼容模型通常支持 `json_object`；其余（含 Anthropic）保持分隔符格式。
+    pub fn auto_for(api_config: &ApiConfig) -> Self {
+        match api_config.model_adapter.as_str() {
+            "openai" | "google" | "gemini" => Self::JsonSchema,
+            "anthropic" | "claude" => Self::Delimited,
+            _ if api_config.supports_tools => Self::JsonObject,
+            _ => Self::Delimited,
+        }
+    }
+}
+
+/// 确定本次制卡的输出格式：任务选项 > 设置项 > 自动推断
+pub fn resolve_output_mode(
+    request: Option<&str>,
+    setting: Option<&str>,
+    api_config: &ApiConfig,
+) -> CardOutputMode {
+    [request, setting]
+        .into_iter()
+        .flatten()
+        .find(|value| !value.trim().is_empty())
+        .and_then(CardOutputMode::parse)
+        .unwrap_or_else(|| CardOutputMode::auto_for(api_config))
+}
+
+pub fn load_output_mode_setting(db: &Database) -> Option<String> {
+    db.get_setting(ANKI_OUTPUT_MODE_SETTING_KEY).ok().flatten()
+}
+
+/// 按模板字段生成卡片 JSON Schema
+///
+/// 单模板时列出字段（`tags` 为字符串数组，其余为字符串，`front`/`back` 必填）；
+/// 多模板时要求 `template_id` 并允许任意字段。
+pub fn card_output_schema(fields: Option<&[String]>, template_ids: &[String]) -> Value {
+    let item = match fields {
+        Some(fields) if !fields.is_empty() => {
+            let mut properties = serde_json::Map::new();
+            for field in fields {
+                let schema = if field.eq_ignore_ascii_case("tags") {
+                    json!({ "type": "array", "items": { "type": "string" } })
+                } else {
+                    json!({ "type": "string" })
+                };
+                properties.insert(field.clone(), schema);
+            }
+            let required: Vec<&String> = fields
+                .iter()
+                .filter(|f| matches!(f.to_lowercase().as_str(), "front" | "back"))
+                .collect();
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+                "additionalProperties": false,
+            })
+        }
+        _ => {
+            let mut template_id = json!({ "type": "string" });
+            if !template_ids.is_empty() {
+                template_id["enum"] = json!(template_ids);
+            }
+            json!({
+                "type": "object",
+                "properties": { "template_id": template_id },
+                "required": ["template_id"],
+                "additionalProperties": true,
+            })
+        }
+    };
+    json!({
+        "type": "object",
+        "properties": { CARDS_KEY: { "type": "array", "items": item } },
+        "required": [CARDS_KEY],
+        "additionalProperties": false,
+    })
+}
+
+/// 请求体中的 `response_format`；分隔符格式返回 None
+pub fn response_format(mode: CardOutputMode, schema: Value) -> Option<Value> {
+    match mode {
+        CardOutputMode::Delimited => None,
+        CardOutputMode::JsonObject => Some(json!({ "type": "json_object" })),
+        CardOutputMode::JsonSchema => Some(json!({
+            "type": "json_schema",
+            "json_schema": { "name": "anki_cards", "strict": false, "schema": schema },
+        })),
+    }
+}
+
+/// 去掉对象/数组末尾多余的逗号（字符串内的内容保持不变）
+pub fn strip_trailing_commas(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let (mut in_string, mut escaped) = (false, false);
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        if c == ',' {
+            let next = chars[i + 1..].iter().find(|n| !n.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        if c == '"' {
+            in_string = true;
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// 容错解析单张卡片：先按标准 JSON 解析，失败后去掉多余逗号再试
+pub fn parse_card_value(text: &str) -> Result<Value, serde_json::Error> {
+    serde_json::from_str(text)
+        .or_else(|err| serde_json::from_str(&strip_trailing_commas(text)).map_err(|_| err))
+}
+
+/// 从完整文本中抢救出所有完整的卡片对象，并返回无法构成卡片的剩余文本
+pub fn salvage_card_objects(text: &str) -> (Vec<String>, String) {
+    let mut scanner = CardJsonScanner::default();
+    let cards = scanner.push(text);
+    (cards, scanner.take_remainder())
+}
+
+struct OpenContainer {
+    is_object: bool,
+    start: usize,
+    /// 内部已截取过卡片（说明自身是 `{"cards": [...]}` 这样的外层包装）
+    had_card: bool,
+}
+
+/// 流式卡片扫描器
+///
+/// 逐段喂入模型输出，返回其中已完整的卡片对象文本。卡片为：数组中的对象
+/// （`[{..}, {..}]` 或 `{"cards": [{..}]}`），以及不含卡片的顶层对象（分隔符格式或
+/// 模型漏写外层包装）。字符串内的括号与转义会被正确跳过；数组外的说明文字、
+/// 代码围栏与分隔符被忽略。
+#[derive(Default)]
+pub struct CardJsonScanner {
+    buffer: String,
+    scanned: usize,
+    stack: Vec<OpenContainer>,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl CardJsonScanner {
+    pub fn push(&mut self, chunk: &str) -> Vec<String> {
+        self.buffer.push_str(chunk);
+        let mut cards = Vec::new();
+        let mut consumed = 0;
+        let mut index = self.scanned;
+        while let Some(c) = self.buffer[index..].chars().next() {
+            let pos = index;
+            index += c.len_utf8();
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if c == '\\' {
+                    self.escaped = true;
+                } else if c == '"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+            match c {
+                '"' if !self.stack.is_empty() => self.in_string = true,
+                '{' | '[' => self.stack.push(OpenContainer {
+                    is_object: c == '{',
+                    start: pos,
+                    had_card: false,
+                }),
+                '}' | ']' => {
+                    let Some(closed) = self.stack.pop() else {
+                        continue;
+                    };
+                    if !closed.is_object || closed.had_card {
+                        if self.stack.is_empty() {
+                            consumed = index;
+                        }
+                        continue;
+                    }
+                    let parent_is_array = self.stack.last().is_some_and(|p| !p.is_object);
+                    let nested_in_card = self
+                        .stack
+                        .iter()
+                        .any(|open| open.is_object && !open.had_card)
+                        && !self.is_card_container();
+                    if (self.stack.is_empty() || parent_is_array) && !nested_in_card {
+                        cards.push(self.buffer[closed.start..index].to_string());
+                        for open in &mut self.stack {
+                            open.had_card = true;
+                        }
+                        consumed = index;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.scanned = index;
+        if consumed > 0 {
+            self.drain(consumed);
+        }
+        cards
+    }
+
+    /// 当前所在数组是否为卡片数组：顶层数组，或顶层对象的直接子数组
+    fn is_card_container(&self) -> bool {
+        match self.stack.as_slice() {
+            [array] => !array.is_object,
+            [wrapper, array] => wrapper.is_object && !array.is_object,
+            _ => false,
+        }
+    }
+
+    fn drain(&mut self, consumed: usize) {
+        self.buffer.drain(..consumed);
+        self.scanned -= consumed;
+        for open in &mut self.stack {
+            open.start = open.start.saturating_sub(consumed);
+        }
+    }
+
+    /// 当前未完成的卡片是否已超长（模型输出失控或缺少闭合括号）
+    pub fn is_overflowing(&self) -> bool {
+        self.buffer.len() > MAX_CARD_CANDIDATE_CHARS
+    }
+
+    /// 取出尚未构成完整卡片的剩余文本并重置扫描状态
+    pub fn take_remainder(&mut self) -> String {
+        let rest = std::mem::take(&mut self.buffer);
+        *self = Self::default();
+        rest
+    }
+}
+
+/// 剩余文本是否只是包装残片（空白、围栏、分隔符、括号与逗号），无需生成错误卡片
+pub fn is_insignificant_remainder(text: &str) -> bool {
+    text.replace("<<<ANKI_CARD_JSON_END>>>", "")
+        .replace("```json", "")
+        .replace("```", "")
+        .chars()
+        .all(|c| c.is_whitespace() || matches!(c, '[' | ']' | '{' | '}' | ','))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(chunks: &[&str]) -> (Vec<String>, String) {
+        let mut scanner = CardJsonScanner::default();
+        let mut cards = Vec::new();
+        for chunk in chunks {
+            cards.extend(scanner.push(chunk));
+        }
+        (cards, scanner.take_remainder())
+    }
+
+    #[test]
+    fn test_scanner_streams_wrapped_cards_and_keeps_truncated_tail() {
+        let (cards, rest) = feed(&[
+            "{\"cards\": [{\"front\": \"a {x}\", \"back\": \"b\\\"]\", \"tags\": [\"t\"]},",
+            " {\"front\": \"c\", \"back\": \"d\"}, {\"front\": \"半截",
+        ]);
+        assert_eq!(cards.len(), 2);
+        let first: Value = serde_json::from_str(&cards[0]).unwrap();
+        assert_eq!(first["front"], "a {x}");
+        assert_eq!(first["back"], "b\"]");
+        assert_eq!(first["tags"][0], "t");
+        assert!(rest.contains("半截"));
+        assert!(!is_insignificant_remainder(&rest));
+    }
+
+    #[test]
+    fn test_scanner_handles_bare_objects_and_arrays() {
+        let (cards, rest) = feed(&[
+            "```json\n{\"front\": \"q1\", \"back\": \"a1\"}\n<<<ANKI_CARD_JSON_END>>>\n",
+            "说明文字 {\"front\": \"q2\", \"back\": \"a2\", \"extra\": {\"k\": 1}}",
+        ]);
+        assert_eq!(cards.len(), 2);
+        assert!(cards[1].contains("\"extra\""));
+        assert!(is_insignificant_remainder(&rest));
+
+        let (salvaged, rest) = salvage_card_objects("[{\"front\": \"x\"}, {\"front\": \"y\"},]");
+        assert_eq!(salvaged.len(), 2);
+        assert!(is_insignificant_remainder(&rest));
+    }
+
+    #[test]
+    fn test_tolerant_parse_and_schema() {
+        let value = parse_card_value("{\"front\": \"a,}\", \"tags\": [\"x\",],}").unwrap();
+        assert_eq!(value["front"], "a,}");
+        assert_eq!(value["tags"][0], "x");
+
+        let fields = vec!["front".to_string(), "back".to_string(), "tags".to_string()];
+        let schema = card_output_schema(Some(&fields), &[]);
+        let item = &schema["properties"]["cards"]["items"];
+        assert_eq!(item["properties"]["tags"]["type"], "array");
+        assert_eq!(item["required"], json!(["front", "back"]));
+        let multi = card_output_schema(None, &["basic".to_string()]);
+        assert_eq!(
+            multi["properties"]["cards"]["items"]["properties"]["template_id"]["enum"],
+            json!(["basic"])
+        );
+        assert_eq!(
+            CardOutputMode::parse("JSON-Schema"),
+            Some(CardOutputMode::JsonSchema)
+        );
+        assert_eq!(CardOutputMode::parse("auto"), None);
+    }
+}
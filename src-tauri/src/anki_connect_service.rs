@@ -1,10 +1,132 @@
+use crate::anki_field_mapping::AnkiFieldMapping;
 use crate::models::AnkiCard;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::net::TcpStream;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{LazyLock, RwLock};
 use std::time::Duration;
 
-const ANKI_CONNECT_URL: &str = "http://127.0.0.1:8765";
+const DEFAULT_ANKI_CONNECT_URL: &str = "http://127.0.0.1:8765";
+/// settings 键：AnkiConnect 连接配置（JSON）
+pub const ANKI_CONNECT_SETTINGS_KEY: &str = "anki_connect.settings";
+/// `storeMediaFile` 与 `multi` 需要 AnkiConnect API 版本 6 及以上
+pub const MIN_ANKI_CONNECT_VERSION: u64 = 6;
+
+/// AnkiConnect 连接配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnkiConnectSettings {
+    pub url: String,
+    /// 连接被拒绝时的最大重试次数（Anki 启动过程中插件尚未监听）
+    pub max_retries: u32,
+    /// 首次重试等待时间，之后每次翻倍
+    pub retry_base_delay_ms: u64,
+}
+
+impl Default for AnkiConnectSettings {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_ANKI_CONNECT_URL.to_string(),
+            max_retries: 3,
+            retry_base_delay_ms: 500,
+        }
+    }
+}
+
+impl AnkiConnectSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        let parsed =
+            url::Url::parse(&self.url).map_err(|e| format!("AnkiConnect 地址无效: {}", e))?;
+        if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+            return Err("AnkiConnect 地址必须是 http(s)://主机:端口 形式".to_string());
+        }
+        if self.max_retries > 10 {
+            return Err("重试次数最多为 10 次".to_string());
+        }
+        Ok(())
+    }
+
+    /// 第 `attempt` 次重试前的等待时间（指数退避，上限 5 秒）
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << attempt.min(10);
+        Duration::from_millis(self.retry_base_delay_ms.saturating_mul(factor).min(5_000))
+    }
+}
+
+static ANKI_CONNECT_SETTINGS: LazyLock<RwLock<AnkiConnectSettings>> =
+    LazyLock::new(|| RwLock::new(AnkiConnectSettings::default()));
+
+pub fn anki_connect_settings() -> AnkiConnectSettings {
+    ANKI_CONNECT_SETTINGS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+pub fn apply_anki_connect_settings(settings: AnkiConnectSettings) -> Result<(), String> {
+    settings.validate()?;
+    *ANKI_CONNECT_SETTINGS
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = settings;
+    Ok(())
+}
+
+/// 启动时从数据库加载连接配置；缺失或无效时保持默认值
+pub fn load_anki_connect_settings(db: &crate::database::Database) {
+    let Ok(Some(raw)) = db.get_setting(ANKI_CONNECT_SETTINGS_KEY) else {
+        return;
+    };
+    match serde_json::from_str::<AnkiConnectSettings>(&raw)
+        .map_err(|e| e.to_string())
+        .and_then(apply_anki_connect_settings)
+    {
+        Ok(()) => {}
+        Err(e) => println!("⚠️ AnkiConnect 配置无效，使用默认值: {}", e),
+    }
+}
+
+/// AnkiConnect 调用错误
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnkiConnectError {
+    /// 无法连接（重试后仍失败）
+    Unreachable(String),
+    /// API 版本过旧，不支持 `storeMediaFile` / `multi`
+    VersionTooOld { found: u64, required: u64 },
+    /// HTTP 或 AnkiConnect 返回的错误
+    Api(String),
+    /// 离线模式下 AnkiConnect 地址不在本机
+    Offline(String),
+}
+
+impl AnkiConnectError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Unreachable(_) => "ANKI_CONNECT_UNREACHABLE",
+            Self::VersionTooOld { .. } => "ANKI_CONNECT_VERSION_TOO_OLD",
+            Self::Api(_) => "ANKI_CONNECT_ERROR",
+            Self::Offline(_) => crate::offline_mode::OFFLINE_MODE_CODE,
+        }
+    }
+}
+
+impl std::fmt::Display for AnkiConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unreachable(msg) | Self::Api(msg) | Self::Offline(msg) => write!(f, "{}", msg),
+            Self::VersionTooOld { found, required } => write!(
+                f,
+                "AnkiConnect 版本过旧（当前 API 版本 {}，至少需要 {}），请在 Anki 中更新 AnkiConnect 插件",
+                found, required
+            ),
+        }
+    }
+}
+
+impl From<AnkiConnectError> for String {
+    fn from(e: AnkiConnectError) -> Self {
+        e.to_string()
+    }
+}
 
 #[derive(Serialize)]
 struct AnkiConnectRequest {
@@ -30,6 +152,117 @@ struct Note {
     tags: Vec<String>,
 }
 
+/// 单条笔记的添加结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnkiNoteOutcome {
+    pub card_id: String,
+    pub note_id: Option<u64>,
+    pub error: Option<String>,
+    /// Anki 判定为重复笔记
+    pub duplicate: bool,
+}
+
+fn is_duplicate_error(error: &str) -> bool {
+    error.to_lowercase().contains("duplicate")
+}
+
+/// 发送单个 AnkiConnect 动作，连接被拒绝时按配置指数退避重试
+async fn invoke_action(
+    action: &str,
+    params: Option<serde_json::Value>,
+    timeout: Duration,
+) -> Result<Option<serde_json::Value>, AnkiConnectError> {
+    let settings = anki_connect_settings();
+    crate::offline_mode::ensure_endpoint_allowed("AnkiConnect", &settings.url)
+        .map_err(|e| AnkiConnectError::Offline(e.message))?;
+    let request = AnkiConnectRequest {
+        action: action.to_string(),
+        version: 6,
+        params,
+    };
+    let client = reqwest::Client::new();
+
+    let mut attempt = 0u32;
+    let response = loop {
+        match client
+            .post(&settings.url)
+            .json(&request)
+            .timeout(timeout)
+            .send()
+            .await
+        {
+            Ok(response) => break response,
+            Err(e) if e.is_connect() && attempt < settings.max_retries => {
+                let delay = settings.retry_delay(attempt);
+                attempt += 1;
+                println!(
+                    "⏳ AnkiConnect 连接失败（{}），{}ms 后第 {} 次重试",
+                    action,
+                    delay.as_millis(),
+                    attempt
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) if e.is_connect() => {
+                return Err(AnkiConnectError::Unreachable(format!(
+                    "无法连接到AnkiConnect（{}，已重试 {} 次），请确保Anki正在运行且AnkiConnect插件已启用: {}",
+                    settings.url, attempt, e
+                )));
+            }
+            Err(e) => {
+                return Err(AnkiConnectError::Api(format!(
+                    "AnkiConnect请求失败（{}）: {}",
+                    action, e
+                )))
+            }
+        }
+    };
+
+    if !response.status().is_success() {
+        return Err(AnkiConnectError::Api(format!(
+            "AnkiConnect HTTP错误: {}",
+            response.status()
+        )));
+    }
+    let body = response
+        .json::<AnkiConnectResponse>()
+        .await
+        .map_err(|e| AnkiConnectError::Api(format!("解析AnkiConnect响应失败: {}", e)))?;
+    match body.error {
+        Some(error) => Err(AnkiConnectError::Api(format!("AnkiConnect错误: {}", error))),
+        None => Ok(body.result),
+    }
+}
+
+async fn invoke_for<T: serde::de::DeserializeOwned>(
+    action: &str,
+    params: Option<serde_json::Value>,
+    timeout: Duration,
+    what: &str,
+) -> Result<T, AnkiConnectError> {
+    let result = invoke_action(action, params, timeout)
+        .await?
+        .ok_or_else(|| AnkiConnectError::Api("AnkiConnect返回空结果".to_string()))?;
+    serde_json::from_value::<T>(result)
+        .map_err(|e| AnkiConnectError::Api(format!("解析{}失败: {}", what, e)))
+}
+
+/// 查询 AnkiConnect API 版本，过旧时返回 [`AnkiConnectError::VersionTooOld`]
+pub async fn check_anki_connect_version() -> Result<u64, AnkiConnectError> {
+    let version = invoke_action("version", None, Duration::from_secs(15))
+        .await?
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| AnkiConnectError::Api("AnkiConnect未返回版本号".to_string()))?;
+    if version < MIN_ANKI_CONNECT_VERSION {
+        return Err(AnkiConnectError::VersionTooOld {
+            found: version,
+            required: MIN_ANKI_CONNECT_VERSION,
+        });
+    }
+    Ok(version)
+}
+
 fn normalize_key(key: &str) -> String {
     key.chars()
         .filter(|c| c.is_alphanumeric())
@@ -153,249 +386,60 @@ fn build_fields_with_model_names(
         .collect()
 }
 
-/// 检查AnkiConnect是否可用
+/// 检查AnkiConnect是否可用（含 API 版本校验）
 #[tauri::command]
 pub async fn check_anki_connect_availability() -> Result<bool, String> {
-    println!("🔍 正在检查AnkiConnect连接到: {}", ANKI_CONNECT_URL);
-
-    // 首先检查端口8765是否开放
-    println!("🔍 第0步：检查端口8765是否开放...");
-    let local_anki_addr = std::net::SocketAddr::from(([127, 0, 0, 1], 8765));
-    match TcpStream::connect_timeout(&local_anki_addr, Duration::from_secs(5)) {
-        Ok(_) => {
-            println!("✅ 端口8765可访问");
-        }
-        Err(e) => {
-            println!("❌ 端口8765无法访问: {}", e);
-            return Err(format!("端口8765无法访问: {} \n\n这通常意味着：\n1. Anki桌面程序未运行\n2. AnkiConnect插件未安装或未启用\n3. 端口被其他程序占用\n\n解决方法：\n1. 启动Anki桌面程序\n2. 安装AnkiConnect插件（代码：2055492159）\n3. 重启Anki以激活插件", e));
-        }
-    }
-
-    // 首先尝试简单的GET请求检查服务是否运行
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .tcp_keepalive(Some(std::time::Duration::from_secs(30)))
-        .connect_timeout(std::time::Duration::from_secs(5))
-        .build()
-        .map_err(|e| format!("创建HTTP客户端失败: {}", e))?;
-
-    println!("🔍 第一步：尝试探测AnkiConnect（GET 非阻塞）...");
-    match client.get(ANKI_CONNECT_URL).send().await {
-        Ok(response) => {
-            println!("✅ AnkiConnect GET 响应状态: {}", response.status());
-        }
-        Err(e) => {
-            // 有些版本/配置可能不响应GET，这里仅记录告警并继续进行POST版本探测
-            println!("⚠️ AnkiConnect GET 探测失败（忽略，继续版本检测）: {}", e);
+    let url = anki_connect_settings().url;
+    println!("🔍 正在检查AnkiConnect连接到: {}", url);
+    crate::offline_mode::ensure_endpoint_allowed("AnkiConnect", &url).map_err(|e| e.message)?;
+
+    // 先探测端口，便于给出更明确的提示；Anki 启动中时交给带重试的版本检查处理
+    if let Some(addr) = url::Url::parse(&url)
+        .ok()
+        .and_then(|u| Some((u.host_str()?.to_string(), u.port_or_known_default()?)))
+        .and_then(|(host, port)| (host.as_str(), port).to_socket_addrs().ok()?.next())
+    {
+        if let Err(e) = TcpStream::connect_timeout(&addr, Duration::from_secs(5)) {
+            println!("⚠️ 端口 {} 暂不可访问（{}），将重试版本检查", addr, e);
         }
     }
 
-    // 如果基础连接成功，再尝试API请求
-    println!("🔍 第二步：测试AnkiConnect API...");
-    let request = AnkiConnectRequest {
-        action: "version".to_string(),
-        version: 6,
-        params: None,
-    };
-
-    println!(
-        "📤 发送API请求: {}",
-        serde_json::to_string(&request).unwrap_or_else(|_| "序列化失败".to_string())
-    );
-
-    match client
-        .post(ANKI_CONNECT_URL)
-        .header("Content-Type", "application/json")
-        .header("Accept", "application/json")
-        .header("User-Agent", "DeepStudent/1.0")
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(15))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            let status_code = response.status();
-            println!("📥 收到响应状态: {}", status_code);
-            if status_code.is_success() {
-                let response_text = response
-                    .text()
-                    .await
-                    .map_err(|e| format!("读取响应内容失败: {}", e))?;
-                println!("📥 响应内容: {}", response_text);
-
-                match serde_json::from_str::<AnkiConnectResponse>(&response_text) {
-                    Ok(anki_response) => {
-                        if anki_response.error.is_none() {
-                            println!("✅ AnkiConnect版本检查成功");
-                            Ok(true)
-                        } else {
-                            Err(format!(
-                                "AnkiConnect错误: {}",
-                                anki_response.error.unwrap_or_default()
-                            ))
-                        }
-                    }
-                    Err(e) => Err(format!(
-                        "解析AnkiConnect响应失败: {} - 响应内容: {}",
-                        e, response_text
-                    )),
-                }
-            } else {
-                let error_text = response
-                    .text()
-                    .await
-                    .unwrap_or_else(|_| "无法读取错误内容".to_string());
-                Err(format!(
-                    "AnkiConnect HTTP错误: {} - 内容: {}",
-                    status_code, error_text
-                ))
-            }
-        }
-        Err(e) => {
-            println!("❌ AnkiConnect连接错误详情: {:?}", e);
-            if e.is_timeout() {
-                Err(
-                    "AnkiConnect连接超时（5秒），请确保Anki桌面程序正在运行并启用了AnkiConnect插件"
-                        .to_string(),
-                )
-            } else if e.is_connect() {
-                Err("无法连接到AnkiConnect服务器，请确保：1)Anki正在运行 2)AnkiConnect插件已安装并启用 3)端口8765未被占用".to_string())
-            } else if e.to_string().contains("connection closed") {
-                Err("连接被AnkiConnect服务器关闭，可能原因：1)AnkiConnect版本过旧 2)请求格式不兼容 3)需要重启Anki".to_string())
-            } else {
-                Err(format!("AnkiConnect连接失败: {}", e))
-            }
+    match check_anki_connect_version().await {
+        Ok(version) => {
+            println!("✅ AnkiConnect版本检查成功: API v{}", version);
+            Ok(true)
         }
+        Err(AnkiConnectError::Unreachable(msg)) => Err(format!(
+            "{}\n\n这通常意味着：\n1. Anki桌面程序未运行\n2. AnkiConnect插件未安装或未启用\n3. 端口被其他程序占用或地址配置错误\n\n解决方法：\n1. 启动Anki桌面程序\n2. 安装AnkiConnect插件（代码：2055492159）\n3. 重启Anki以激活插件",
+            msg
+        )),
+        Err(e) => Err(e.to_string()),
     }
 }
 
 /// 获取所有牌组名称
 pub async fn get_deck_names() -> Result<Vec<String>, String> {
-    let request = AnkiConnectRequest {
-        action: "deckNames".to_string(),
-        version: 6,
-        params: None,
-    };
-
-    let client = reqwest::Client::new();
-
-    match client
-        .post(ANKI_CONNECT_URL)
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(5))
-        .send()
+    invoke_for("deckNames", None, Duration::from_secs(5), "牌组列表")
         .await
-    {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<AnkiConnectResponse>().await {
-                    Ok(anki_response) => {
-                        if let Some(error) = anki_response.error {
-                            Err(format!("AnkiConnect错误: {}", error))
-                        } else if let Some(result) = anki_response.result {
-                            match serde_json::from_value::<Vec<String>>(result) {
-                                Ok(deck_names) => Ok(deck_names),
-                                Err(e) => Err(format!("解析牌组列表失败: {}", e)),
-                            }
-                        } else {
-                            Err("AnkiConnect返回空结果".to_string())
-                        }
-                    }
-                    Err(e) => Err(format!("解析AnkiConnect响应失败: {}", e)),
-                }
-            } else {
-                Err(format!("AnkiConnect HTTP错误: {}", response.status()))
-            }
-        }
-        Err(e) => Err(format!("请求牌组列表失败: {}", e)),
-    }
+        .map_err(String::from)
 }
 
 /// 获取所有笔记类型名称
 pub async fn get_model_names() -> Result<Vec<String>, String> {
-    let request = AnkiConnectRequest {
-        action: "modelNames".to_string(),
-        version: 6,
-        params: None,
-    };
-
-    let client = reqwest::Client::new();
-
-    match client
-        .post(ANKI_CONNECT_URL)
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(5))
-        .send()
+    invoke_for("modelNames", None, Duration::from_secs(5), "笔记类型列表")
         .await
-    {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<AnkiConnectResponse>().await {
-                    Ok(anki_response) => {
-                        if let Some(error) = anki_response.error {
-                            Err(format!("AnkiConnect错误: {}", error))
-                        } else if let Some(result) = anki_response.result {
-                            match serde_json::from_value::<Vec<String>>(result) {
-                                Ok(model_names) => Ok(model_names),
-                                Err(e) => Err(format!("解析笔记类型列表失败: {}", e)),
-                            }
-                        } else {
-                            Err("AnkiConnect返回空结果".to_string())
-                        }
-                    }
-                    Err(e) => Err(format!("解析AnkiConnect响应失败: {}", e)),
-                }
-            } else {
-                Err(format!("AnkiConnect HTTP错误: {}", response.status()))
-            }
-        }
-        Err(e) => Err(format!("请求笔记类型列表失败: {}", e)),
-    }
+        .map_err(String::from)
 }
 
 pub async fn get_model_field_names(model_name: &str) -> Result<Vec<String>, String> {
-    check_anki_connect_availability().await?;
-
-    let params = serde_json::json!({
-        "modelName": model_name
-    });
-
-    let request = AnkiConnectRequest {
-        action: "modelFieldNames".to_string(),
-        version: 6,
-        params: Some(params),
-    };
-
-    let client = reqwest::Client::new();
-
-    match client
-        .post(ANKI_CONNECT_URL)
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(10))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<AnkiConnectResponse>().await {
-                    Ok(resp) => {
-                        if let Some(error) = resp.error {
-                            Err(format!("获取模型字段失败: {}", error))
-                        } else if let Some(result) = resp.result {
-                            serde_json::from_value::<Vec<String>>(result)
-                                .map_err(|e| format!("解析模型字段失败: {}", e))
-                        } else {
-                            Err("AnkiConnect返回空结果".to_string())
-                        }
-                    }
-                    Err(e) => Err(format!("解析AnkiConnect响应失败: {}", e)),
-                }
-            } else {
-                Err(format!("AnkiConnect HTTP错误: {}", response.status()))
-            }
-        }
-        Err(e) => Err(format!("获取模型字段失败: {}", e)),
-    }
+    invoke_for(
+        "modelFieldNames",
+        Some(serde_json::json!({ "modelName": model_name })),
+        Duration::from_secs(10),
+        "模型字段",
+    )
+    .await
+    .map_err(|e| format!("获取模型字段失败: {}", e))
 }
 
 /// 将AnkiCard列表添加到Anki
@@ -413,8 +457,25 @@ pub async fn add_notes_to_anki_with_card_models(
     note_type: String,
     card_models: HashMap<String, String>,
 ) -> Result<Vec<Option<u64>>, String> {
-    // 首先检查AnkiConnect可用性
-    check_anki_connect_availability().await?;
+    let outcomes =
+        add_notes_to_anki_detailed(cards, deck_name, note_type, card_models, None).await?;
+    Ok(outcomes.into_iter().map(|o| o.note_id).collect())
+}
+
+/// 逐条添加笔记并返回每条的结果
+///
+/// 通过 `multi` 包装多个 `addNote`，单条重复或格式错误只记录在对应结果中，不会中断整批。
+/// `field_mapping` 对其笔记类型的卡片生效（需已校验），其余卡片按字段名自动匹配。
+/// 连接失败、版本过旧等整批错误保留为 [`AnkiConnectError`]，由命令层映射为对应错误码。
+pub async fn add_notes_to_anki_detailed(
+    cards: Vec<AnkiCard>,
+    deck_name: String,
+    note_type: String,
+    card_models: HashMap<String, String>,
+    field_mapping: Option<&AnkiFieldMapping>,
+) -> Result<Vec<AnkiNoteOutcome>, AnkiConnectError> {
+    // 首先检查AnkiConnect可用性与版本（连接被拒绝时按配置重试）
+    check_anki_connect_version().await?;
 
     let mut model_field_names_cache: HashMap<String, Option<Vec<String>>> = HashMap::new();
     let mut model_names: Vec<String> = cards
@@ -441,8 +502,10 @@ pub async fn add_notes_to_anki_with_card_models(
         model_field_names_cache.insert(model_name, loaded);
     }
 
-    // 构建notes数组
-    let notes: Vec<Note> = cards
+    let card_ids: Vec<String> = cards.iter().map(|card| card.id.clone()).collect();
+
+    // 构建 addNote 动作列表
+    let actions: Vec<serde_json::Value> = cards
         .into_iter()
         .map(|card| {
             let model_name = card_models
@@ -455,108 +518,84 @@ pub async fn add_notes_to_anki_with_card_models(
                 .cloned()
                 .unwrap_or(None);
 
-            let fields = if let Some(names) = model_field_names.as_ref() {
+            let mapping = field_mapping.filter(|m| m.anki_model == model_name);
+            let fields = if let (Some(mapping), Some(names)) = (mapping, model_field_names.as_ref())
+            {
+                mapping.build_fields(&card, names)
+            } else if let Some(names) = model_field_names.as_ref() {
                 build_fields_with_model_names(&card, names, &model_name)
             } else {
                 build_basic_fields(&card, &model_name)
             };
 
-            Note {
+            let note = Note {
                 deck_name: deck_name.clone(),
                 model_name,
                 fields,
                 tags: card.tags,
-            }
+            };
+            serde_json::json!({
+                "action": "addNote",
+                "version": 6,
+                "params": { "note": note },
+            })
         })
         .collect();
 
-    let params = serde_json::json!({
-        "notes": notes
-    });
-
-    let request = AnkiConnectRequest {
-        action: "addNotes".to_string(),
-        version: 6,
-        params: Some(params),
-    };
-
-    let client = reqwest::Client::new();
+    let results: Vec<AnkiConnectResponse> = invoke_for(
+        "multi",
+        Some(serde_json::json!({ "actions": actions })),
+        Duration::from_secs(30),
+        "批量添加结果",
+    )
+    .await
+    .map_err(|e| match e {
+        AnkiConnectError::Api(msg) => AnkiConnectError::Api(format!("添加笔记到Anki失败: {}", msg)),
+        other => other,
+    })?;
+
+    Ok(collect_note_outcomes(card_ids, results))
+}
 
-    match client
-        .post(ANKI_CONNECT_URL)
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(30))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<AnkiConnectResponse>().await {
-                    Ok(anki_response) => {
-                        if let Some(error) = anki_response.error {
-                            Err(format!("AnkiConnect错误: {}", error))
-                        } else if let Some(result) = anki_response.result {
-                            match serde_json::from_value::<Vec<Option<u64>>>(result) {
-                                Ok(note_ids) => Ok(note_ids),
-                                Err(e) => Err(format!("解析笔记ID列表失败: {}", e)),
-                            }
-                        } else {
-                            Err("AnkiConnect返回空结果".to_string())
-                        }
-                    }
-                    Err(e) => Err(format!("解析AnkiConnect响应失败: {}", e)),
-                }
-            } else {
-                Err(format!("AnkiConnect HTTP错误: {}", response.status()))
+fn collect_note_outcomes(
+    card_ids: Vec<String>,
+    results: Vec<AnkiConnectResponse>,
+) -> Vec<AnkiNoteOutcome> {
+    let mut results = results.into_iter();
+    card_ids
+        .into_iter()
+        .map(|card_id| {
+            let (note_id, error) = match results.next() {
+                Some(resp) => (resp.result.and_then(|v| v.as_u64()), resp.error),
+                None => (None, Some("AnkiConnect未返回该笔记的结果".to_string())),
+            };
+            let error = match (note_id, error) {
+                (None, None) => Some("AnkiConnect未返回笔记ID".to_string()),
+                (_, error) => error,
+            };
+            AnkiNoteOutcome {
+                duplicate: error.as_deref().is_some_and(is_duplicate_error),
+                card_id,
+                note_id,
+                error,
             }
-        }
-        Err(e) => Err(format!("添加笔记到Anki失败: {}", e)),
-    }
+        })
+        .collect()
 }
 
 /// 创建牌组（如果不存在）
 pub async fn create_deck_if_not_exists(deck_name: &str) -> Result<(), String> {
-    let params = serde_json::json!({
-        "deck": deck_name
-    });
-
-    let request = AnkiConnectRequest {
-        action: "createDeck".to_string(),
-        version: 6,
-        params: Some(params),
-    };
-
-    let client = reqwest::Client::new();
-
-    match client
-        .post(ANKI_CONNECT_URL)
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(10))
-        .send()
-        .await
+    match invoke_action(
+        "createDeck",
+        Some(serde_json::json!({ "deck": deck_name })),
+        Duration::from_secs(10),
+    )
+    .await
     {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<AnkiConnectResponse>().await {
-                    Ok(anki_response) => {
-                        if let Some(error) = anki_response.error {
-                            // 如果牌组已存在，这不算错误
-                            if error.contains("already exists") {
-                                Ok(())
-                            } else {
-                                Err(format!("创建牌组时出错: {}", error))
-                            }
-                        } else {
-                            Ok(())
-                        }
-                    }
-                    Err(e) => Err(format!("解析AnkiConnect响应失败: {}", e)),
-                }
-            } else {
-                Err(format!("AnkiConnect HTTP错误: {}", response.status()))
-            }
-        }
-        Err(e) => Err(format!("创建牌组失败: {}", e)),
+        Ok(_) => Ok(()),
+        // 如果牌组已存在，这不算错误
+        Err(AnkiConnectError::Api(error)) if error.contains("already exists") => Ok(()),
+        Err(e) => Err(format!("创建牌组时出错: {}", e)),
     }
 }
 
@@ -572,40 +611,61 @@ pub async fn import_apkg(path: &str) -> Result<bool, String> {
 
     // 处理各平台路径：AnkiConnect 需要绝对路径字符串
     // 这里假设前端传入的已是绝对路径
-    let params = serde_json::json!({
-        "path": path
-    });
+    invoke_action(
+        "importPackage",
+        Some(serde_json::json!({ "path": path })),
+        Duration::from_secs(60),
+    )
+    .await
+    .map(|_| true)
+    .map_err(|e| format!("导入APKG失败: {}", e))
+}
 
-    let request = AnkiConnectRequest {
-        action: "importPackage".to_string(),
-        version: 6,
-        params: Some(params),
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let client = reqwest::Client::new();
-    match client
-        .post(ANKI_CONNECT_URL)
-        .json(&request)
-        .timeout(std::time::Duration::from_secs(60))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<AnkiConnectResponse>().await {
-                    Ok(resp) => {
-                        if let Some(err) = resp.error {
-                            Err(format!("导入APKG失败: {}", err))
-                        } else {
-                            Ok(true)
-                        }
-                    }
-                    Err(e) => Err(format!("解析AnkiConnect响应失败: {}", e)),
-                }
-            } else {
-                Err(format!("AnkiConnect HTTP错误: {}", response.status()))
-            }
-        }
-        Err(e) => Err(format!("请求AnkiConnect导入失败: {}", e)),
+    #[test]
+    fn test_retry_delay_backs_off_with_cap() {
+        let settings = AnkiConnectSettings::default();
+        assert_eq!(settings.retry_delay(0), Duration::from_millis(500));
+        assert_eq!(settings.retry_delay(1), Duration::from_millis(1000));
+        assert_eq!(settings.retry_delay(8), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        assert!(AnkiConnectSettings::default().validate().is_ok());
+        let custom = AnkiConnectSettings {
+            url: "http://192.168.1.5:9876".to_string(),
+            ..Default::default()
+        };
+        assert!(custom.validate().is_ok());
+        let bad = AnkiConnectSettings {
+            url: "localhost8765".to_string(),
+            ..Default::default()
+        };
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_note_outcomes_keep_per_note_errors() {
+        let results: Vec<AnkiConnectResponse> = serde_json::from_value(serde_json::json!([
+            { "result": 1001, "error": null },
+            { "result": null, "error": "cannot create note because it is a duplicate" },
+            { "result": null, "error": "model was not found: Foo" },
+        ]))
+        .unwrap();
+        let ids = vec!["a", "b", "c", "d"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let outcomes = collect_note_outcomes(ids, results);
+
+        assert_eq!(outcomes[0].note_id, Some(1001));
+        assert!(outcomes[0].error.is_none());
+        assert!(outcomes[1].duplicate);
+        assert!(!outcomes[2].duplicate && outcomes[2].error.is_some());
+        assert!(outcomes[3].note_id.is_none() && outcomes[3].error.is_some());
     }
 }
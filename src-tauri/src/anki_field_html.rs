@@ -0,0 +1,255 @@
+//! 卡片字段中的 HTML 处理
+//!
+//! Anki 字段按 HTML 渲染，模型输出的 HTML 质量参差不齐：有的直接可用，有的夹带 `<script>`、
+//! 内联样式或残缺标签。处理方式由设置项 `anki.html_mode` 决定，单次任务可通过
+//! `AnkiGenerationOptions.html_mode` 覆盖：
+//!
+//! - `preserve`：原样保留；
+//! - `sanitize`（默认）：只保留白名单标签（去掉全部属性，`img` 仅保留安全的 `src`），
+//!   其余标签和零散的尖括号转义为实体，在 Anki 中显示为原文；
+//! - `strip`：删除所有标签得到纯文本，`<br>` 换成换行。
+//!
+//! 数学公式（`$...$`、`$$...$$`、`\(...\)`、`\[...\]`）内部不按标签解析，公式中的 `a<b`
+//! 不会被当成标签删除；`sanitize` 下其中的 `<`、`>` 转为实体，MathJax 渲染结果不变。
+//!
+//! 生成时在保存卡片前处理，APKG 导出与 AnkiConnect 添加前按设置项再处理一次，手动编辑过的
+//! 卡片同样适用。`sanitize` 的输出再次处理结果不变。
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::database::Database;
+use crate::models::AnkiCard;
+
+pub const ANKI_HTML_MODE_SETTING_KEY: &str = "anki.html_mode";
+
+/// `sanitize` 模式保留的标签
+pub const ALLOWED_TAGS: &[&str] = &["b", "i", "u", "sub", "sup", "ul", "ol", "li", "br", "img"];
+
+/// 数学公式定界符，长的在前
+const MATH_DELIMITERS: &[(&str, &str)] =
+    &[("$$", "$$"), ("\\[", "\\]"), ("\\(", "\\)"), ("$", "$")];
+
+static TAG_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^<(/?)([A-Za-z][A-Za-z0-9]*)(\s[^<>]*?)?\s*/?>").unwrap());
+
+static IMG_SRC_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\bsrc\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap());
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CardHtmlMode {
+    Preserve,
+    #[default]
+    Sanitize,
+    Strip,
+}
+
+impl CardHtmlMode {
+    /// 解析配置取值；无法识别时返回 None
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "preserve" => Some(Self::Preserve),
+            "sanitize" => Some(Self::Sanitize),
+            "strip" => Some(Self::Strip),
+            _ => None,
+        }
+    }
+}
+
+/// 确定字段的 HTML 处理方式：任务选项 > 设置项 > `sanitize`
+pub fn resolve_html_mode(request: Option<&str>, setting: Option<&str>) -> CardHtmlMode {
+    [request, setting]
+        .into_iter()
+        .flatten()
+        .find_map(CardHtmlMode::parse)
+        .unwrap_or_default()
+}
+
+pub fn load_html_mode_setting(db: &Database) -> Option<String> {
+    db.get_setting(ANKI_HTML_MODE_SETTING_KEY).ok().flatten()
+}
+
+/// 导出时使用的处理方式（只看设置项）
+pub fn load_html_mode(db: &Database) -> CardHtmlMode {
+    resolve_html_mode(None, load_html_mode_setting(db).as_deref())
+}
+
+fn escape_angle_brackets(text: &str) -> String {
+    text.replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// 从 `text[at..]` 开始的数学公式结束位置；不是公式或未闭合时为 None
+fn math_span_end(text: &str, at: usize) -> Option<usize> {
+    let rest = &text[at..];
+    MATH_DELIMITERS.iter().find_map(|(open, close)| {
+        let body = rest.strip_prefix(open)?;
+        let end = body.find(close)?;
+        (end > 0).then_some(at + open.len() + end + close.len())
+    })
+}
+
+fn safe_img_src(attrs: &str) -> Option<String> {
+    let caps = IMG_SRC_RE.captures(attrs)?;
+    let src = caps
+        .get(1)
+        .or_else(|| caps.get(2))
+        .or_else(|| caps.get(3))?
+        .as_str()
+        .trim();
+    let scheme: String = src
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .take(11)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if src.is_empty() || scheme.starts_with("javascript:") || scheme.starts_with("vbscript:") {
+        return None;
+    }
+    Some(escape_angle_brackets(src).replace('"', "&quot;"))
+}
+
+/// 白名单标签去掉属性后输出，其余标签整体转义
+fn sanitize_tag(raw: &str, closing: bool, name: &str, attrs: &str) -> String {
+    if !ALLOWED_TAGS.contains(&name) {
+        return escape_angle_brackets(raw);
+    }
+    match name {
+        "br" => "<br>".to_string(),
+        "img" if closing => String::new(),
+        "img" => safe_img_src(attrs)
+            .map(|src| format!("<img src=\"{}\">", src))
+            .unwrap_or_default(),
+        _ if closing => format!("</{}>", name),
+        _ => format!("<{}>", name),
+    }
+}
+
+/// 按处理方式处理单个字段
+pub fn apply_html_mode(text: &str, mode: CardHtmlMode) -> String {
+    if mode == CardHtmlMode::Preserve {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        // 转义的美元符号不是公式定界符
+        if rest.starts_with("\\$") {
+            out.push_str("\\$");
+            i += 2;
+            continue;
+        }
+        if let Some(end) = math_span_end(text, i) {
+            let math = &text[i..end];
+            if mode == CardHtmlMode::Sanitize {
+                out.push_str(&escape_angle_brackets(math));
+            } else {
+                out.push_str(math);
+            }
+            i = end;
+            continue;
+        }
+        if let Some(caps) = TAG_RE.captures(rest) {
+            let raw = caps.get(0).map_or("", |m| m.as_str());
+            let closing = !caps[1].is_empty();
+            let name = caps[2].to_ascii_lowercase();
+            if mode == CardHtmlMode::Sanitize {
+                let attrs = caps.get(3).map_or("", |m| m.as_str());
+                out.push_str(&sanitize_tag(raw, closing, &name, attrs));
+            } else if name == "br" {
+                out.push('\n');
+            }
+            i += raw.len();
+            continue;
+        }
+        let ch = rest.chars().next().unwrap_or_default();
+        match ch {
+            '<' if mode == CardHtmlMode::Sanitize => out.push_str("&lt;"),
+            '>' if mode == CardHtmlMode::Sanitize => out.push_str("&gt;"),
+            _ => out.push(ch),
+        }
+        i += ch.len_utf8();
+    }
+    out
+}
+
+/// 处理卡片的全部字段（不含标签）
+pub fn apply_to_card(card: &mut AnkiCard, mode: CardHtmlMode) {
+    if mode == CardHtmlMode::Preserve {
+        return;
+    }
+    card.front = apply_html_mode(&card.front, mode);
+    card.back = apply_html_mode(&card.back, mode);
+    if let Some(text) = card.text.as_mut() {
+        *text = apply_html_mode(text, mode);
+    }
+    for value in card.extra_fields.values_mut() {
+        *value = apply_html_mode(value, mode);
+    }
+}
+
+pub fn apply_to_cards(cards: &mut [AnkiCard], mode: CardHtmlMode) {
+    for card in cards {
+        apply_to_card(card, mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_three_modes_on_sample_fields() {
+        let samples = [
+            (
+                "<b>熵</b>是<span style=\"color:red\">状态函数</span><br/>",
+                "<b>熵</b>是&lt;span style=\"color:red\"&gt;状态函数&lt;/span&gt;<br>",
+                "熵是状态函数\n",
+            ),
+            (
+                "<script>alert(1)</script><img src=\"a.png\" onerror=\"x()\">",
+                "&lt;script&gt;alert(1)&lt;/script&gt;<img src=\"a.png\">",
+                "alert(1)",
+            ),
+            (
+                "当 $a<b$ 且 \\(x > y\\) 时，$$\\frac{1}{n}<1$$",
+                "当 $a&lt;b$ 且 \\(x &gt; y\\) 时，$$\\frac{1}{n}&lt;1$$",
+                "当 $a<b$ 且 \\(x > y\\) 时，$$\\frac{1}{n}<1$$",
+            ),
+            (
+                "<UL><li>{{c1::1 < 2}}</li></UL><img src=\"javascript:x()\">",
+                "<ul><li>{{c1::1 &lt; 2}}</li></ul>",
+                "{{c1::1 < 2}}",
+            ),
+        ];
+        for (input, sanitized, stripped) in samples {
+            assert_eq!(apply_html_mode(input, CardHtmlMode::Preserve), input);
+            assert_eq!(apply_html_mode(input, CardHtmlMode::Sanitize), sanitized);
+            assert_eq!(apply_html_mode(input, CardHtmlMode::Strip), stripped);
+            // 已处理的字段再次处理不变
+            assert_eq!(
+                apply_html_mode(sanitized, CardHtmlMode::Sanitize),
+                sanitized
+            );
+        }
+        assert_eq!(
+            apply_html_mode("售价 \\$5 <i>起</i>", CardHtmlMode::Sanitize),
+            "售价 \\$5 <i>起</i>"
+        );
+    }
+
+    #[test]
+    fn test_resolve_html_mode_defaults_to_sanitize() {
+        assert_eq!(resolve_html_mode(None, None), CardHtmlMode::Sanitize);
+        assert_eq!(resolve_html_mode(None, Some("strip")), CardHtmlMode::Strip);
+        assert_eq!(
+            resolve_html_mode(Some("Preserve"), Some("strip")),
+            CardHtmlMode::Preserve
+        );
+        assert_eq!(
+            resolve_html_mode(Some("unknown"), None),
+            CardHtmlMode::Sanitize
+        );
+    }
+}
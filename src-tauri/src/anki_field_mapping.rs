@@ -0,0 +1,281 @@
+//! AnkiConnect 推送时的字段映射
+//!
+//! 自定义笔记类型的字段名与卡片字段对不上时，按映射把卡片字段写入 Anki 字段。映射以 Anki
+//! 字段名为键、卡片字段为值：内置字段为 `front`、`back`、`text`、`tags`，其余按名称取卡片的
+//! `extra_fields`（不区分大小写）。映射中没有的 Anki 字段留空。
+//!
+//! 映射按（制卡模板 → Anki 笔记类型）保存在设置项 `anki_connect.field_mappings` 中。推送时
+//! 显式传入的映射校验通过后即保存，之后同一组合的推送自动沿用；没有映射时保持按字段名自动
+//! 匹配的原有行为。
+//!
+//! 校验在创建任何笔记之前进行：映射中的字段必须存在于笔记类型中，笔记类型的第一个字段
+//! （排序字段，Anki 要求非空）必须有映射。
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::Database;
+use crate::models::{AnkiCard, AppError};
+
+pub const ANKI_FIELD_MAPPINGS_SETTING_KEY: &str = "anki_connect.field_mappings";
+/// 未指定模板的卡片使用的模板键
+pub const DEFAULT_TEMPLATE_KEY: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnkiFieldMapping {
+    /// 制卡模板；为空时对应未指定模板的卡片
+    #[serde(default)]
+    pub template_id: Option<String>,
+    pub anki_model: String,
+    /// Anki 字段名 → 卡片字段
+    pub fields: BTreeMap<String, String>,
+}
+
+fn template_key(template_id: Option<&str>) -> &str {
+    template_id
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .unwrap_or(DEFAULT_TEMPLATE_KEY)
+}
+
+impl AnkiFieldMapping {
+    fn matches(&self, template_id: Option<&str>, anki_model: &str) -> bool {
+        template_key(self.template_id.as_deref()) == template_key(template_id)
+            && self.anki_model == anki_model
+    }
+
+    /// 按笔记类型的字段列表校验映射
+    pub fn validate(&self, model_field_names: &[String]) -> Result<(), AppError> {
+        if self.fields.is_empty() {
+            return Err(AppError::validation("字段映射不能为空"));
+        }
+        let unknown: Vec<&str> = self
+            .fields
+            .keys()
+            .map(String::as_str)
+            .filter(|name| !model_field_names.iter().any(|f| f == name))
+            .collect();
+        if !unknown.is_empty() {
+            return Err(AppError::validation(format!(
+                "笔记类型「{}」中不存在以下字段: {}（可选字段: {}）",
+                self.anki_model,
+                unknown.join(", "),
+                model_field_names.join(", ")
+            )));
+        }
+        if let Some((name, _)) = self
+            .fields
+            .iter()
+            .find(|(_, source)| source.trim().is_empty())
+        {
+            return Err(AppError::validation(format!(
+                "字段「{}」未指定卡片字段",
+                name
+            )));
+        }
+        if let Some(required) = model_field_names.first() {
+            if !self.fields.contains_key(required) {
+                return Err(AppError::validation(format!(
+                    "笔记类型「{}」的必填字段「{}」未设置映射",
+                    self.anki_model, required
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// 按映射生成笔记字段，笔记类型的其余字段留空
+    pub fn build_fields(
+        &self,
+        card: &AnkiCard,
+        model_field_names: &[String],
+    ) -> HashMap<String, String> {
+        model_field_names
+            .iter()
+            .map(|name| {
+                let value = self
+                    .fields
+                    .get(name)
+                    .map(|source| card_field_value(card, source))
+                    .unwrap_or_default();
+                (name.clone(), value)
+            })
+            .collect()
+    }
+}
+
+fn card_field_value(card: &AnkiCard, source: &str) -> String {
+    let source = source.trim().to_lowercase();
+    let extra = || {
+        card.extra_fields
+            .iter()
+            .find(|(key, _)| key.to_lowercase() == source)
+            .map(|(_, value)| value.clone())
+    };
+    match source.as_str() {
+        "front" => card.front.clone(),
+        "back" => card.back.clone(),
+        "text" => card.text.clone().or_else(extra).unwrap_or_default(),
+        "tags" => card.tags.join(" "),
+        _ => extra().unwrap_or_default(),
+    }
+}
+
+/// 所有卡片使用同一模板时返回其模板键（未指定模板为 `default`），混用模板时返回 None
+pub fn common_template_key(cards: &[AnkiCard]) -> Option<&str> {
+    let mut keys = cards
+        .iter()
+        .map(|card| template_key(card.template_id.as_deref()));
+    let first = keys.next()?;
+    keys.all(|key| key == first).then_some(first)
+}
+
+pub fn load_field_mappings(db: &Database) -> Vec<AnkiFieldMapping> {
+    let Some(raw) = db
+        .get_setting(ANKI_FIELD_MAPPINGS_SETTING_KEY)
+        .ok()
+        .flatten()
+    else {
+        return Vec::new();
+    };
+    serde_json::from_str(&raw).unwrap_or_else(|e| {
+        log::warn!("[AnkiFieldMapping] 字段映射配置无法解析，已忽略: {}", e);
+        Vec::new()
+    })
+}
+
+pub fn find_field_mapping(
+    db: &Database,
+    template_id: Option<&str>,
+    anki_model: &str,
+) -> Option<AnkiFieldMapping> {
+    load_field_mappings(db)
+        .into_iter()
+        .find(|m| m.matches(template_id, anki_model))
+}
+
+/// 同一（模板, 笔记类型）只保留一份映射
+fn upsert_mapping(mappings: &mut Vec<AnkiFieldMapping>, mapping: AnkiFieldMapping) {
+    mappings.retain(|m| !m.matches(mapping.template_id.as_deref(), &mapping.anki_model));
+    mappings.push(mapping);
+}
+
+fn store_field_mappings(db: &Database, mappings: &[AnkiFieldMapping]) -> Result<(), AppError> {
+    let raw = serde_json::to_string(mappings)
+        .map_err(|e| AppError::internal(format!("序列化字段映射失败: {}", e)))?;
+    db.save_setting(ANKI_FIELD_MAPPINGS_SETTING_KEY, &raw)
+        .map_err(|e| AppError::database(format!("保存字段映射失败: {}", e)))
+}
+
+/// 保存映射（调用方负责先校验）
+pub fn save_field_mapping(db: &Database, mapping: AnkiFieldMapping) -> Result<(), AppError> {
+    let mut mappings = load_field_mappings(db);
+    upsert_mapping(&mut mappings, mapping);
+    store_field_mappings(db, &mappings)
+}
+
+/// 删除映射，返回是否存在
+pub fn delete_field_mapping(
+    db: &Database,
+    template_id: Option<&str>,
+    anki_model: &str,
+) -> Result<bool, AppError> {
+    let mut mappings = load_field_mappings(db);
+    let before = mappings.len();
+    mappings.retain(|m| !m.matches(template_id, anki_model));
+    if mappings.len() == before {
+        return Ok(false);
+    }
+    store_field_mappings(db, &mappings)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card() -> AnkiCard {
+        AnkiCard {
+            front: "问题".to_string(),
+            back: "答案".to_string(),
+            text: None,
+            tags: vec!["数学".to_string(), "错题".to_string()],
+            images: vec![],
+            id: "c1".to_string(),
+            task_id: "t1".to_string(),
+            is_error_card: false,
+            error_content: None,
+            created_at: String::new(),
+            updated_at: String::new(),
+            extra_fields: HashMap::from([("Source".to_string(), "教材 P12".to_string())]),
+            template_id: None,
+        }
+    }
+
+    fn mapping(fields: &[(&str, &str)]) -> AnkiFieldMapping {
+        AnkiFieldMapping {
+            template_id: None,
+            anki_model: "我的问答".to_string(),
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    fn model_fields() -> Vec<String> {
+        ["题目", "解答", "出处", "备注"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_build_fields_follows_mapping_and_leaves_rest_empty() {
+        let m = mapping(&[("题目", "front"), ("解答", "back"), ("出处", "source")]);
+        m.validate(&model_fields()).unwrap();
+        let fields = m.build_fields(&card(), &model_fields());
+        assert_eq!(fields["题目"], "问题");
+        assert_eq!(fields["解答"], "答案");
+        assert_eq!(fields["出处"], "教材 P12");
+        assert_eq!(fields["备注"], "");
+    }
+
+    #[test]
+    fn test_validate_rejects_unknown_and_unmapped_required_fields() {
+        let unknown = mapping(&[("题目", "front"), ("Back", "back")]);
+        assert!(unknown
+            .validate(&model_fields())
+            .unwrap_err()
+            .message
+            .contains("Back"));
+        let missing_first = mapping(&[("解答", "back")]);
+        assert!(missing_first
+            .validate(&model_fields())
+            .unwrap_err()
+            .message
+            .contains("题目"));
+    }
+
+    #[test]
+    fn test_upsert_replaces_same_template_and_model() {
+        let mut mappings = vec![mapping(&[("题目", "front")])];
+        let mut other_template = mapping(&[("题目", "text")]);
+        other_template.template_id = Some("cloze-tpl".to_string());
+        upsert_mapping(&mut mappings, other_template);
+        let mut replaced = mapping(&[("题目", "back")]);
+        replaced.template_id = Some("  ".to_string());
+        upsert_mapping(&mut mappings, replaced);
+
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(
+            common_template_key(&[card(), card()]),
+            Some(DEFAULT_TEMPLATE_KEY)
+        );
+        assert!(mappings
+            .iter()
+            .any(|m| m.matches(None, "我的问答") && m.fields["题目"] == "back"));
+    }
+}
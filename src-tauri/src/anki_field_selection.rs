@@ -0,0 +1,205 @@
+//! 导出时的卡片字段选择
+//!
+//! 分享牌组时可以去掉不想公开的字段（如私有的扩展字段、`error_content`）。字段名不区分大小写：
+//! 内置字段为 `front`、`back`、`text`、`tags`、`error_content`，其余为模板字段与卡片的
+//! `extra_fields` 键。
+//!
+//! `include` 非空时只保留其中的字段，`exclude` 中的字段总是去掉；两者都为空时导出全部字段。
+//! 去掉的字段在导出副本中清空，笔记类型的字段结构保持不变。请求的字段必须存在于笔记类型或卡片中。
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::Database;
+use crate::models::{AnkiCard, AppError};
+
+/// 卡片自带的字段
+pub const BUILTIN_FIELDS: &[&str] = &["front", "back", "text", "tags", "error_content"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CardFieldSelection {
+    /// 只导出这些字段；为空时包含全部字段
+    pub include: Vec<String>,
+    /// 不导出的字段
+    pub exclude: Vec<String>,
+}
+
+fn normalize(field: &str) -> String {
+    field.trim().to_lowercase()
+}
+
+impl CardFieldSelection {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn keeps(&self, field: &str) -> bool {
+        let field = normalize(field);
+        if self.exclude.iter().any(|f| normalize(f) == field) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|f| normalize(f) == field)
+    }
+
+    /// 校验请求的字段都存在，且至少保留一个内容字段
+    pub fn validate(&self, available: &BTreeSet<String>) -> Result<(), AppError> {
+        let unknown: Vec<&str> = self
+            .include
+            .iter()
+            .chain(&self.exclude)
+            .map(|f| f.trim())
+            .filter(|f| !available.contains(&normalize(f)))
+            .collect();
+        if !unknown.is_empty() {
+            return Err(AppError::validation(format!(
+                "笔记类型中不存在以下字段: {}（可选字段: {}）",
+                unknown.join(", "),
+                available.iter().cloned().collect::<Vec<_>>().join(", ")
+            )));
+        }
+        let keeps_content = available
+            .iter()
+            .filter(|f| !matches!(f.as_str(), "tags" | "error_content"))
+            .any(|f| self.keeps(f));
+        if !keeps_content {
+            return Err(AppError::validation("至少需要保留一个内容字段"));
+        }
+        Ok(())
+    }
+}
+
+/// 可选字段：内置字段、模板字段与卡片扩展字段（小写）
+pub fn available_fields(cards: &[AnkiCard], template_fields: &[String]) -> BTreeSet<String> {
+    BUILTIN_FIELDS
+        .iter()
+        .map(|f| f.to_string())
+        .chain(template_fields.iter().map(|f| normalize(f)))
+        .chain(
+            cards
+                .iter()
+                .flat_map(|c| c.extra_fields.keys().map(|k| normalize(k))),
+        )
+        .filter(|f| !f.is_empty())
+        .collect()
+}
+
+/// 模板的字段列表；未指定或模板不存在时为空
+pub fn template_fields(db: &Database, template_id: Option<&str>) -> Vec<String> {
+    template_id
+        .filter(|id| !id.trim().is_empty())
+        .and_then(|id| db.get_custom_template_by_id(id).ok().flatten())
+        .map(|t| t.fields)
+        .unwrap_or_default()
+}
+
+fn apply_to_card(card: &mut AnkiCard, selection: &CardFieldSelection) {
+    if !selection.keeps("front") {
+        card.front.clear();
+    }
+    if !selection.keeps("back") {
+        card.back.clear();
+    }
+    if !selection.keeps("text") {
+        card.text = None;
+    }
+    if !selection.keeps("tags") {
+        card.tags.clear();
+    }
+    if !selection.keeps("error_content") {
+        card.error_content = None;
+    }
+    card.extra_fields.retain(|key, _| selection.keeps(key));
+}
+
+/// 校验并应用字段选择；未选择时不做任何处理
+pub fn apply_to_cards(
+    cards: &mut [AnkiCard],
+    selection: Option<&CardFieldSelection>,
+    template_fields: &[String],
+) -> Result<(), AppError> {
+    let Some(selection) = selection.filter(|s| !s.is_empty()) else {
+        return Ok(());
+    };
+    selection.validate(&available_fields(cards, template_fields))?;
+    for card in cards {
+        apply_to_card(card, selection);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn card() -> AnkiCard {
+        AnkiCard {
+            front: "问题".to_string(),
+            back: "答案".to_string(),
+            text: None,
+            tags: vec!["班级A".to_string()],
+            images: Vec::new(),
+            id: "c1".to_string(),
+            task_id: String::new(),
+            is_error_card: false,
+            error_content: Some("学生错解".to_string()),
+            created_at: String::new(),
+            updated_at: String::new(),
+            extra_fields: HashMap::from([
+                ("Hint".to_string(), "提示".to_string()),
+                ("StudentName".to_string(), "张三".to_string()),
+            ]),
+            template_id: None,
+        }
+    }
+
+    #[test]
+    fn test_exclude_private_fields() {
+        let mut cards = vec![card()];
+        let selection = CardFieldSelection {
+            include: Vec::new(),
+            exclude: vec!["studentname".to_string(), " Error_Content ".to_string()],
+        };
+        apply_to_cards(&mut cards, Some(&selection), &[]).unwrap();
+        assert_eq!(cards[0].front, "问题");
+        assert_eq!(cards[0].tags, ["班级A"]);
+        assert!(cards[0].error_content.is_none());
+        assert_eq!(cards[0].extra_fields.keys().collect::<Vec<_>>(), ["Hint"]);
+
+        // 未选择时保持原样
+        let mut untouched = vec![card()];
+        apply_to_cards(&mut untouched, None, &[]).unwrap();
+        assert_eq!(untouched[0].extra_fields.len(), 2);
+        assert!(untouched[0].error_content.is_some());
+    }
+
+    #[test]
+    fn test_include_and_validation() {
+        let mut cards = vec![card()];
+        let selection = CardFieldSelection {
+            include: vec!["Front".to_string(), "Back".to_string(), "Hint".to_string()],
+            exclude: Vec::new(),
+        };
+        apply_to_cards(&mut cards, Some(&selection), &["Front".to_string()]).unwrap();
+        assert!(cards[0].tags.is_empty());
+        assert!(cards[0].error_content.is_none());
+        assert_eq!(cards[0].extra_fields.len(), 1);
+
+        let unknown = CardFieldSelection {
+            include: vec!["Source".to_string()],
+            exclude: Vec::new(),
+        };
+        let err = apply_to_cards(&mut [card()], Some(&unknown), &[]).unwrap_err();
+        assert!(err.message.contains("Source"));
+        // 模板中定义的字段即使卡片没有也可以选择
+        assert!(apply_to_cards(&mut [card()], Some(&unknown), &["Source".to_string()]).is_ok());
+
+        let only_tags = CardFieldSelection {
+            include: vec!["tags".to_string()],
+            exclude: Vec::new(),
+        };
+        assert!(apply_to_cards(&mut [card()], Some(&only_tags), &[]).is_err());
+    }
+}
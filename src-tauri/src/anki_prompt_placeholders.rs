@@ -0,0 +1,179 @@
+//! 制卡模板提示词中的占位符
+//!
+//! 模板的 `generation_prompt` 可引用文档上下文：`{document_name}`、`{segment}`、
+//! `{subject}`、`{num_cards_hint}`，由 `EnhancedAnkiService` 在每个分段任务发送前替换。
+//! 双花括号（Anki 字段语法，如 `{{Front}}`、`{{c1::...}}`）原样保留；保存模板时
+//! 未知占位符直接报错，而不是留在提示词里原样发给模型。
+
+use crate::models::AppError;
+
+/// 支持的占位符名称
+pub const PROMPT_PLACEHOLDERS: &[&str] = &["document_name", "segment", "subject", "num_cards_hint"];
+
+/// 占位符取值
+#[derive(Debug, Clone, Default)]
+pub struct PromptPlaceholderContext<'a> {
+    pub document_name: &'a str,
+    pub segment: &'a str,
+    pub subject: Option<&'a str>,
+    /// 本分段的卡片数上限，0 或负数表示不限
+    pub max_cards: i32,
+}
+
+impl PromptPlaceholderContext<'_> {
+    fn value(&self, name: &str) -> Option<String> {
+        match name {
+            "document_name" => Some(self.document_name.to_string()),
+            "segment" => Some(self.segment.to_string()),
+            "subject" => Some(self.subject.unwrap_or("未指定").to_string()),
+            "num_cards_hint" => Some(if self.max_cards > 0 {
+                self.max_cards.to_string()
+            } else {
+                "适量".to_string()
+            }),
+            _ => None,
+        }
+    }
+}
+
+enum Piece<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
+
+/// 切分提示词：`{name}`（name 为字母、数字、下划线）为占位符，其余为原文
+fn split_prompt(prompt: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+    let bytes = prompt.as_bytes();
+    while i < bytes.len() {
+        if bytes[i] != b'{' {
+            i += 1;
+            continue;
+        }
+        // `{{...}}` 为 Anki 语法，跳到配对的 `}}`
+        if bytes.get(i + 1) == Some(&b'{') {
+            i = prompt[i..]
+                .find("}}")
+                .map(|end| i + end + 2)
+                .unwrap_or(bytes.len());
+            continue;
+        }
+        let name_len = bytes[i + 1..]
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+            .count();
+        if name_len > 0 && bytes.get(i + 1 + name_len) == Some(&b'}') {
+            if literal_start < i {
+                pieces.push(Piece::Literal(&prompt[literal_start..i]));
+            }
+            pieces.push(Piece::Placeholder(&prompt[i + 1..i + 1 + name_len]));
+            i += name_len + 2;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    if literal_start < prompt.len() {
+        pieces.push(Piece::Literal(&prompt[literal_start..]));
+    }
+    pieces
+}
+
+/// 提示词中不受支持的占位符（去重，按出现顺序）
+pub fn unknown_placeholders(prompt: &str) -> Vec<String> {
+    let mut unknown: Vec<String> = Vec::new();
+    for piece in split_prompt(prompt) {
+        if let Piece::Placeholder(name) = piece {
+            if !PROMPT_PLACEHOLDERS.contains(&name) && !unknown.iter().any(|u| u == name) {
+                unknown.push(name.to_string());
+            }
+        }
+    }
+    unknown
+}
+
+/// 保存模板时校验生成提示词的占位符
+pub fn validate_prompt_placeholders(prompt: &str) -> Result<(), AppError> {
+    let unknown = unknown_placeholders(prompt);
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(AppError::validation(format!(
+        "生成提示词包含未知占位符: {}。支持的占位符: {}；Anki 字段请使用双花括号",
+        unknown
+            .iter()
+            .map(|name| format!("{{{}}}", name))
+            .collect::<Vec<_>>()
+            .join(", "),
+        PROMPT_PLACEHOLDERS
+            .iter()
+            .map(|name| format!("{{{}}}", name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )))
+}
+
+/// 替换占位符；未知占位符保留原文（保存时已校验，这里只兜底旧模板）
+pub fn resolve_prompt_placeholders(prompt: &str, ctx: &PromptPlaceholderContext<'_>) -> String {
+    let mut resolved = String::with_capacity(prompt.len());
+    for piece in split_prompt(prompt) {
+        match piece {
+            Piece::Literal(text) => resolved.push_str(text),
+            Piece::Placeholder(name) => match ctx.value(name) {
+                Some(value) => resolved.push_str(&value),
+                None => {
+                    resolved.push('{');
+                    resolved.push_str(name);
+                    resolved.push('}');
+                }
+            },
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_keeps_anki_syntax_and_json() {
+        let prompt = "从《{document_name}》（{subject}）中生成 {num_cards_hint} 张填空卡，\
+                      格式 {{c1::答案}}，输出 {\"Text\": \"...\"}：\n{segment}";
+        let ctx = PromptPlaceholderContext {
+            document_name: "热力学",
+            segment: "熵增原理",
+            subject: Some("物理"),
+            max_cards: 3,
+        };
+        assert_eq!(
+            resolve_prompt_placeholders(prompt, &ctx),
+            "从《热力学》（物理）中生成 3 张填空卡，格式 {{c1::答案}}，输出 {\"Text\": \"...\"}：\n熵增原理"
+        );
+        let unlimited = PromptPlaceholderContext {
+            max_cards: 0,
+            ..ctx
+        };
+        assert_eq!(
+            resolve_prompt_placeholders("{num_cards_hint}{subject}", &unlimited),
+            "适量物理"
+        );
+    }
+
+    #[test]
+    fn test_unknown_placeholders_are_reported() {
+        assert!(validate_prompt_placeholders("按 {{Front}} 生成 {segment}").is_ok());
+        assert_eq!(
+            unknown_placeholders("{Front} {segment} {Front} {count}"),
+            vec!["Front".to_string(), "count".to_string()]
+        );
+        let err = validate_prompt_placeholders("{topic}").unwrap_err();
+        assert!(err.message.contains("{topic}"));
+        assert_eq!(
+            resolve_prompt_placeholders("{topic}-{segment}", &Default::default()),
+            "{topic}-"
+        );
+    }
+}
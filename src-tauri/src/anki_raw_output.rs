@@ -0,0 +1,150 @@
+//! 制卡模型原始输出捕获（调试用）
+//!
+//! 错误卡片的 `error_content` 只保留无法解析的片段。开启设置项 `anki.raw_output_capture`
+//! 后，流式制卡会累积模型的完整原始输出（含重连后的输出与 HTTP 错误响应体），任务失败或
+//! 产生错误卡片时保存到任务上，供 `get_task_raw_output` 查看。原始输出按上限截断后保存，
+//! 不做脱敏；可通过 `clear_task_raw_outputs` 批量清空。
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::Database;
+
+pub const RAW_OUTPUT_CAPTURE_SETTINGS_KEY: &str = "anki.raw_output_capture";
+const DEFAULT_MAX_BYTES: usize = 256 * 1024;
+const MIN_MAX_BYTES: usize = 1024;
+const MAX_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RawOutputCaptureSettings {
+    pub enabled: bool,
+    /// 每个任务保存的原始输出上限（字节）
+    pub max_bytes: usize,
+}
+
+impl Default for RawOutputCaptureSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+impl RawOutputCaptureSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_MAX_BYTES..=MAX_MAX_BYTES).contains(&self.max_bytes) {
+            return Err(format!(
+                "原始输出上限需在 {}~{} 字节之间",
+                MIN_MAX_BYTES, MAX_MAX_BYTES
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn load_raw_output_capture_settings(db: &Database) -> RawOutputCaptureSettings {
+    db.get_setting(RAW_OUTPUT_CAPTURE_SETTINGS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<RawOutputCaptureSettings>(&raw).ok())
+        .filter(|settings| settings.validate().is_ok())
+        .unwrap_or_default()
+}
+
+/// 单个任务的原始输出累积器；未开启时不保留任何内容
+#[derive(Debug, Default)]
+pub struct RawOutputCapture {
+    enabled: bool,
+    max_bytes: usize,
+    captured: String,
+    /// 截断前的总字节数
+    total_bytes: usize,
+}
+
+impl RawOutputCapture {
+    pub fn new(settings: &RawOutputCaptureSettings) -> Self {
+        Self {
+            enabled: settings.enabled,
+            max_bytes: settings.max_bytes,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// 追加一段输出，超出上限的部分只计入总字节数
+    pub fn push(&mut self, chunk: &str) {
+        if !self.enabled {
+            return;
+        }
+        self.total_bytes += chunk.len();
+        let room = self.max_bytes.saturating_sub(self.captured.len());
+        if room == 0 {
+            return;
+        }
+        let mut end = room.min(chunk.len());
+        while !chunk.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.captured.push_str(&chunk[..end]);
+    }
+
+    /// 保存到任务上；未开启或没有输出时跳过
+    pub fn persist(&self, db: &Database, task_id: &str) {
+        if !self.enabled || self.total_bytes == 0 {
+            return;
+        }
+        if let Err(e) = db.save_task_raw_output(task_id, &self.captured, self.total_bytes) {
+            log::warn!(
+                "[RawOutputCapture] 保存任务 {} 的原始输出失败: {}",
+                task_id,
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(max_bytes: usize) -> RawOutputCapture {
+        RawOutputCapture::new(&RawOutputCaptureSettings {
+            enabled: true,
+            max_bytes,
+        })
+    }
+
+    #[test]
+    fn test_push_truncates_at_char_boundary_and_counts_total() {
+        let mut c = capture(7);
+        c.push("ab");
+        c.push("卡片内容");
+        c.push("溢出");
+        // "ab" + "卡" (3 字节) 后剩 2 字节，不足以容纳下一个汉字
+        assert_eq!(c.captured, "ab卡");
+        assert_eq!(c.total_bytes, 2 + 12 + 6);
+    }
+
+    #[test]
+    fn test_disabled_capture_keeps_nothing() {
+        let mut c = RawOutputCapture::new(&RawOutputCaptureSettings::default());
+        c.push("{\"front\": \"问题\"}");
+        assert!(!c.is_enabled());
+        assert!(c.captured.is_empty());
+        assert_eq!(c.total_bytes, 0);
+    }
+
+    #[test]
+    fn test_settings_reject_out_of_range_cap() {
+        let mut settings = RawOutputCaptureSettings::default();
+        assert!(settings.validate().is_ok());
+        settings.max_bytes = 10;
+        assert!(settings.validate().is_err());
+        settings.max_bytes = MAX_MAX_BYTES + 1;
+        assert!(settings.validate().is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! 制卡来源标签
+//!
+//! 生成的卡片默认不记录出自哪份文档。`source_tag_facets` 选择要包含的维度后，每张卡片会自动
+//! 加上一个层级标签，如 `source::物理::热力学.pdf::segment_003`，写入 `tags_json`，便于按来源
+//! 筛选卡片库。维度固定按 科目 > 文档 > 分段 的顺序组成层级，与传入顺序无关；缺少取值的维度
+//! （如未指定科目）直接省略。
+//!
+//! 标签在分段任务发送前由 `EnhancedAnkiService` 计算，保存卡片时先移除已有的 `source::` 标签
+//! 再添加，重新生成或重复套用都不会产生重复标签；模型输出的其他标签原样保留。
+
+use crate::models::{AnkiGenerationOptions, AppError};
+
+/// 来源标签的根节点
+pub const SOURCE_TAG_ROOT: &str = "source";
+
+/// 支持的维度（同时也是层级顺序）
+pub const SOURCE_TAG_FACETS: &[&str] = &["subject", "document", "segment"];
+
+/// 校验维度名称
+pub fn validate_source_tag_facets(facets: &[String]) -> Result<(), AppError> {
+    let unknown: Vec<&str> = facets
+        .iter()
+        .map(String::as_str)
+        .filter(|facet| !SOURCE_TAG_FACETS.contains(facet))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(AppError::validation(format!(
+        "未知的来源标签维度: {}。支持的维度: {}",
+        unknown.join(", "),
+        SOURCE_TAG_FACETS.join(", ")
+    )))
+}
+
+/// Anki 标签不能含空白，`::` 是层级分隔符，都替换为下划线
+fn tag_component(value: &str) -> Option<String> {
+    let component = value
+        .trim()
+        .split(|c: char| c.is_whitespace() || c == ':' || c == '"')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    (!component.is_empty()).then_some(component)
+}
+
+/// 计算分段的来源标签；未启用或所有维度都没有取值时为 None
+pub fn source_tag(
+    options: &AnkiGenerationOptions,
+    document_name: &str,
+    segment_index: u32,
+) -> Option<String> {
+    let facets = options.source_tag_facets.as_deref()?;
+    let enabled = |facet: &str| facets.iter().any(|f| f == facet);
+    let mut path = Vec::new();
+    if enabled("subject") {
+        path.extend(options.subject.as_deref().and_then(tag_component));
+    }
+    if enabled("document") {
+        path.extend(tag_component(document_name));
+    }
+    if enabled("segment") {
+        path.push(format!("segment_{:03}", segment_index + 1));
+    }
+    if path.is_empty() {
+        return None;
+    }
+    Some(format!("{}::{}", SOURCE_TAG_ROOT, path.join("::")))
+}
+
+fn is_source_tag(tag: &str) -> bool {
+    tag == SOURCE_TAG_ROOT
+        || tag
+            .strip_prefix(SOURCE_TAG_ROOT)
+            .is_some_and(|rest| rest.starts_with("::"))
+}
+
+/// 替换卡片上的来源标签，保留其余标签
+pub fn apply_source_tag(tags: &mut Vec<String>, source_tag: Option<&str>) {
+    let Some(source_tag) = source_tag else {
+        return;
+    };
+    tags.retain(|tag| !is_source_tag(tag));
+    tags.push(source_tag.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(facets: Option<Vec<&str>>, subject: Option<&str>) -> AnkiGenerationOptions {
+        let mut options: AnkiGenerationOptions = serde_json::from_value(serde_json::json!({
+            "deck_name": "默认牌组",
+            "note_type": "Basic",
+            "enable_images": false,
+            "max_cards_per_mistake": 0,
+        }))
+        .unwrap();
+        options.source_tag_facets = facets.map(|f| f.into_iter().map(str::to_string).collect());
+        options.subject = subject.map(str::to_string);
+        options
+    }
+
+    #[test]
+    fn test_source_tag_follows_fixed_hierarchy() {
+        let all = options(
+            Some(vec!["segment", "document", "subject"]),
+            Some("大学 物理"),
+        );
+        assert_eq!(
+            source_tag(&all, "热力学 第二章.pdf", 2).as_deref(),
+            Some("source::大学_物理::热力学_第二章.pdf::segment_003")
+        );
+        let no_subject = options(Some(vec!["subject", "document"]), None);
+        assert_eq!(
+            source_tag(&no_subject, "a::b", 0).as_deref(),
+            Some("source::a_b")
+        );
+        assert!(source_tag(&options(None, Some("物理")), "doc", 0).is_none());
+        assert!(source_tag(&options(Some(vec!["subject"]), None), "doc", 0).is_none());
+        assert!(validate_source_tag_facets(&["page".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_apply_source_tag_is_idempotent_and_keeps_manual_tags() {
+        let mut tags = vec![
+            "热力学".to_string(),
+            "source::旧文档::segment_001".to_string(),
+            "sourcecode".to_string(),
+        ];
+        apply_source_tag(&mut tags, Some("source::新文档::segment_001"));
+        apply_source_tag(&mut tags, Some("source::新文档::segment_001"));
+        assert_eq!(
+            tags,
+            vec!["热力学", "sourcecode", "source::新文档::segment_001"]
+        );
+        apply_source_tag(&mut tags, None);
+        assert_eq!(tags.len(), 3);
+    }
+}
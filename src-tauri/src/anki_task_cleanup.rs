@@ -0,0 +1,85 @@
+//! 已完成制卡任务的自动清理
+//!
+//! 设置项 `anki.task_cleanup`（JSON）开启后，应用启动时清理超过保留天数的已完成任务及其原文片段；
+//! 生成的卡片保留在卡片库中，见 [`Database::prune_completed_document_tasks`]。默认关闭，
+//! 也可通过 `prune_completed_tasks` 命令手动清理。
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::{Database, DocumentTaskPruneReport};
+use crate::models::AppError;
+
+pub const TASK_CLEANUP_SETTINGS_KEY: &str = "anki.task_cleanup";
+/// 保留天数上限（约十年）
+const MAX_RETENTION_DAYS: u32 = 3650;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TaskCleanupSettings {
+    pub enabled: bool,
+    /// 已完成任务的保留天数
+    pub retention_days: u32,
+}
+
+impl Default for TaskCleanupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retention_days: 30,
+        }
+    }
+}
+
+impl TaskCleanupSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_retention_days(self.retention_days)
+    }
+}
+
+fn validate_retention_days(days: u32) -> Result<(), String> {
+    if days == 0 || days > MAX_RETENTION_DAYS {
+        return Err(format!("保留天数需在 1~{} 之间", MAX_RETENTION_DAYS));
+    }
+    Ok(())
+}
+
+pub fn load_task_cleanup_settings(db: &Database) -> TaskCleanupSettings {
+    db.get_setting(TASK_CLEANUP_SETTINGS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// 清理 `older_than_days` 天前完成的任务
+pub fn prune_completed_tasks(
+    anki_db: &Database,
+    older_than_days: u32,
+) -> Result<DocumentTaskPruneReport, AppError> {
+    validate_retention_days(older_than_days).map_err(AppError::validation)?;
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(i64::from(older_than_days));
+    let report = anki_db
+        .prune_completed_document_tasks(cutoff)
+        .map_err(|e| AppError::database(format!("清理已完成任务失败: {}", e)))?;
+    if report.pruned_tasks > 0 {
+        log::info!(
+            "[TaskCleanup] 清理 {} 天前完成的任务 {} 个，保留卡片 {} 张，清空文档 {} 个",
+            older_than_days,
+            report.pruned_tasks,
+            report.preserved_cards,
+            report.cleared_documents
+        );
+    }
+    Ok(report)
+}
+
+/// 启动时按设置自动清理（设置存于主库，任务与卡片在 Anki 库）
+pub fn run_scheduled_task_cleanup(db: &Database, anki_db: &Database) {
+    let settings = load_task_cleanup_settings(db);
+    if !settings.enabled {
+        return;
+    }
+    if let Err(e) = prune_completed_tasks(anki_db, settings.retention_days) {
+        log::warn!("[TaskCleanup] 自动清理失败: {}", e);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::models::{AnkiCard, CustomAnkiTemplate};
 use chrono::Utc;
+use regex::Regex;
 use rusqlite::{params, Connection, Result as SqliteResult};
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
@@ -30,6 +31,160 @@ fn clean_template_placeholders(content: &str) -> String {
     content.trim().to_string()
 }
 
+/// CSS `url(...)` 引用（字体、背景图）
+static CSS_URL_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"url\(\s*['"]?([^'")]+?)['"]?\s*\)"#).unwrap());
+/// 模板 HTML 中的 `src="..."` 引用
+static HTML_SRC_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\bsrc\s*=\s*["']([^"']+)["']"#).unwrap());
+/// 模板中的字段引用 `{{...}}`
+static FIELD_REF_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{([^{}]+)\}\}").unwrap());
+
+/// Anki 内置的特殊字段，不需要出现在 note type 字段列表中
+const ANKI_SPECIAL_FIELDS: &[&str] = &[
+    "FrontSide",
+    "Tags",
+    "Type",
+    "Deck",
+    "Subdeck",
+    "Card",
+    "CardFlag",
+    "CardID",
+];
+
+/// 模板样式与正反面（已把本地媒体引用改写为包内文件名）及需要打包的媒体
+struct TemplateAssets {
+    css: String,
+    front: String,
+    back: String,
+    media: Vec<(String, String)>, // (包内文件名, 本地路径)
+}
+
+/// 收集模板 CSS / 正反面引用的本地媒体（`@font-face` 字体、背景图等），
+/// 改写为 `_文件名` 形式（Anki 约定下划线前缀的模板媒体不会被“检查媒体”清理）。
+///
+/// 远程地址与 `data:` 内联资源保持原样；找不到的本地文件只记录警告，
+/// 由用户自行保证其已存在于 Anki 媒体库中。
+fn package_template_media(css: &str, front: &str, back: &str) -> TemplateAssets {
+    let mut assets = TemplateAssets {
+        css: css.to_string(),
+        front: front.to_string(),
+        back: back.to_string(),
+        media: Vec::new(),
+    };
+
+    let mut references: Vec<String> = Vec::new();
+    for text in [css, front, back] {
+        for caps in CSS_URL_RE
+            .captures_iter(text)
+            .chain(HTML_SRC_RE.captures_iter(text))
+        {
+            let reference = caps[1].trim().to_string();
+            if !references.contains(&reference) {
+                references.push(reference);
+            }
+        }
+    }
+
+    for reference in references {
+        let lower = reference.to_ascii_lowercase();
+        if reference.contains("{{")
+            || reference.starts_with('#')
+            || reference.starts_with("//")
+            || ["data:", "http:", "https:"]
+                .iter()
+                .any(|scheme| lower.starts_with(scheme))
+        {
+            continue;
+        }
+
+        let local = reference.strip_prefix("file://").unwrap_or(&reference);
+        let path = std::path::Path::new(local);
+        if !path.is_absolute() {
+            continue;
+        }
+        if !path.is_file() {
+            warn!("模板引用的媒体文件不存在，已跳过打包: {}", reference);
+            continue;
+        }
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+
+        let base = if file_name.starts_with('_') {
+            file_name.to_string()
+        } else {
+            format!("_{}", file_name)
+        };
+        let mut packaged = base.clone();
+        let mut suffix = 1;
+        while assets.media.iter().any(|(name, _)| name == &packaged) {
+            packaged = format!("_{}{}", suffix, base);
+            suffix += 1;
+        }
+
+        assets.css = assets.css.replace(&reference, &packaged);
+        assets.front = assets.front.replace(&reference, &packaged);
+        assets.back = assets.back.replace(&reference, &packaged);
+        assets.media.push((packaged, local.to_string()));
+    }
+
+    assets
+}
+
+/// 校验 note type 字段：模板字段名合法且不重复、均包含在导出字段中，
+/// 且正反面引用的字段都存在，避免 Anki 导入时报 “no field called …”
+fn validate_template_fields(
+    template_fields: &[String],
+    model_fields: &[String],
+    front: &str,
+    back: &str,
+) -> Result<(), String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    for field in template_fields {
+        let name = field.trim();
+        if name.is_empty() {
+            return Err("模板字段名不能为空".to_string());
+        }
+        if name.starts_with(['#', '/', '^']) || name.contains([':', '"', '{', '}']) {
+            return Err(format!("模板字段名 '{}' 含有 Anki 不支持的字符", name));
+        }
+        if !seen.insert(name.to_lowercase()) {
+            return Err(format!(
+                "模板字段名 '{}' 重复（Anki 字段名不区分大小写）",
+                name
+            ));
+        }
+        if !model_fields.iter().any(|f| f.eq_ignore_ascii_case(name)) {
+            return Err(format!("模板字段 '{}' 未写入导出的笔记类型", name));
+        }
+    }
+
+    for caps in FIELD_REF_RE
+        .captures_iter(front)
+        .chain(FIELD_REF_RE.captures_iter(back))
+    {
+        // {{#Field}} / {{^Field}} / {{/Field}} / {{cloze:Field}} / {{hint:Field}}
+        let reference = caps[1].trim().trim_start_matches(['#', '^', '/']);
+        let name = reference.rsplit(':').next().unwrap_or(reference).trim();
+        if name.is_empty()
+            || ANKI_SPECIAL_FIELDS
+                .iter()
+                .any(|special| special.eq_ignore_ascii_case(name))
+        {
+            continue;
+        }
+        if !model_fields.iter().any(|f| f.eq_ignore_ascii_case(name)) {
+            return Err(format!(
+                "模板引用了不存在的字段 '{{{{{}}}}}'，请在模板字段中添加该字段",
+                name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 /// Anki的基本配置
 const ANKI_COLLECTION_CONFIG: &str = r#"{
     "nextPos": 1,
@@ -807,8 +962,13 @@ pub async fn export_cards_to_apkg_with_full_template(
         }
 
         // Build a template config for the exported model so model fields == note fields.
-        let template_config_for_model = if let Some((name, _fields, front, back, css)) = template_config {
-            (name, final_fields.clone(), front, back, css)
+        let mut template_media: Vec<(String, String)> = Vec::new();
+        let template_config_for_model = if let Some((name, fields, front, back, css)) = template_config {
+            validate_template_fields(&fields, &final_fields, &front, &back)?;
+            // 模板 CSS 写入 note type styling，其引用的本地字体/背景图一并打包
+            let assets = package_template_media(&css, &front, &back);
+            template_media = assets.media;
+            (name, final_fields.clone(), assets.front, assets.back, assets.css)
         } else if is_cloze_model {
             (
                 "Cloze".to_string(),
@@ -890,6 +1050,11 @@ pub async fn export_cards_to_apkg_with_full_template(
         let mut media_entries: Vec<(String, String)> = Vec::new(); // (original_filename, path)
         let mut seen_media_names: HashSet<String> = HashSet::new();
 
+        for (fname, path) in template_media {
+            if seen_media_names.insert(fname.clone()) {
+                media_entries.push((fname, path));
+            }
+        }
         for card in &cards_clone_for_media { // 使用克隆的数据进行媒体处理
             for image_path in &card.images {
                 if let Some(fname) = std::path::Path::new(image_path).file_name().and_then(|n| n.to_str()) {
@@ -1062,6 +1227,7 @@ pub async fn export_multi_template_apkg(
         let mut model_id_map: HashMap<String, i64> = HashMap::new(); // template_id → model_id
         let mut model_fields_map: HashMap<String, Vec<String>> = HashMap::new(); // template_id → field names
 
+        let mut template_media: Vec<(String, String)> = Vec::new();
         let base_model_id = 1425279200000i64;
         for (idx, (tid, group_cards)) in groups.iter().enumerate() {
             let model_id = base_model_id + idx as i64;
@@ -1091,13 +1257,22 @@ pub async fn export_multi_template_apkg(
                 let is_cloze = tmpl.note_type.eq_ignore_ascii_case("Cloze");
                 let model_type = if is_cloze { 1 } else { 0 };
 
+                validate_template_fields(&tmpl.fields, &fields, &tmpl.front_template, &tmpl.back_template)
+                    .map_err(|e| format!("模板 '{}': {}", tmpl.name, e))?;
+                let assets = package_template_media(&tmpl.css_style, &tmpl.front_template, &tmpl.back_template);
+                for (fname, path) in assets.media {
+                    if !template_media.iter().any(|(name, _)| name == &fname) {
+                        template_media.push((fname, path));
+                    }
+                }
+
                 let model = create_template_model(
                     Some(&model_id.to_string()),
                     &tmpl.name,
                     &fields,
-                    &tmpl.front_template,
-                    &tmpl.back_template,
-                    &tmpl.css_style,
+                    &assets.front,
+                    &assets.back,
+                    &assets.css,
                     model_type,
                 );
                 model_fields_map.insert(tid.clone(), fields);
@@ -1226,6 +1401,11 @@ pub async fn export_multi_template_apkg(
         let mut media_map = serde_json::Map::new();
         let mut media_entries: Vec<(String, String)> = Vec::new();
         let mut seen_media_names: HashSet<String> = HashSet::new();
+        for (fname, path) in template_media {
+            if seen_media_names.insert(fname.clone()) {
+                media_entries.push((fname, path));
+            }
+        }
         for card in &cards_for_media {
             for image_path in &card.images {
                 if let Some(fname) = std::path::Path::new(image_path).file_name().and_then(|n| n.to_str()) {
@@ -1433,4 +1613,144 @@ mod tests {
         // actual media blob should be stored under the numeric index
         assert!(zip.by_name("0").is_ok());
     }
+
+    #[test]
+    fn test_validate_template_fields_rejects_mismatch() {
+        let model_fields: Vec<String> = vec!["Front".into(), "Back".into(), "Hint".into()];
+        let template_fields: Vec<String> = vec!["Front".into(), "Back".into(), "Hint".into()];
+        assert!(validate_template_fields(
+            &template_fields,
+            &model_fields,
+            "{{Front}}{{#Hint}}{{hint:Hint}}{{/Hint}}",
+            "{{FrontSide}}<hr id=answer>{{Back}}",
+        )
+        .is_ok());
+
+        // 引用了不存在的字段
+        let err = validate_template_fields(&template_fields, &model_fields, "{{Missing}}", "")
+            .unwrap_err();
+        assert!(err.contains("Missing"));
+
+        // 字段名大小写重复
+        let duplicated: Vec<String> = vec!["Front".into(), "front".into()];
+        assert!(validate_template_fields(&duplicated, &model_fields, "", "").is_err());
+
+        // 非法字段名
+        let invalid: Vec<String> = vec!["a:b".into()];
+        assert!(validate_template_fields(&invalid, &model_fields, "", "").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_apkg_note_type_matches_template() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let out = tmp.path().join("template.apkg");
+
+        let font_path = tmp.path().join("custom.ttf");
+        std::fs::write(&font_path, b"font").expect("write font");
+        let bg_path = tmp.path().join("bg.png");
+        std::fs::write(&bg_path, b"\x89PNG\r\n\x1a\n").expect("write bg");
+
+        let fields: Vec<String> = vec!["Front".into(), "Back".into(), "Hint".into()];
+        let css = format!(
+            "@font-face {{ font-family: Custom; src: url('{}'); }}\n.card {{ background: url(https://example.com/remote.png); }}",
+            font_path.display()
+        );
+        let front = format!(
+            "<img src=\"{}\">{{{{Front}}}}{{{{#Hint}}}}{{{{hint:Hint}}}}{{{{/Hint}}}}",
+            bg_path.display()
+        );
+        let back = "{{FrontSide}}<hr id=answer>{{Back}}".to_string();
+
+        let mut extra_fields = HashMap::new();
+        extra_fields.insert("Hint".to_string(), "H".to_string());
+        let card = AnkiCard {
+            front: "Q".to_string(),
+            back: "A".to_string(),
+            text: None,
+            tags: vec![],
+            images: vec![],
+            id: "1".to_string(),
+            task_id: "".to_string(),
+            is_error_card: false,
+            error_content: None,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+            extra_fields,
+            template_id: None,
+        };
+
+        export_cards_to_apkg_with_full_template(
+            vec![card],
+            "TestDeck".to_string(),
+            "Basic".to_string(),
+            out.clone(),
+            Some((
+                "Custom".to_string(),
+                fields.clone(),
+                front,
+                back.clone(),
+                css,
+            )),
+            None,
+        )
+        .await
+        .expect("export apkg");
+
+        let f = std::fs::File::open(&out).expect("open apkg");
+        let mut zip = zip::ZipArchive::new(f).expect("zip open");
+
+        let media_map: serde_json::Value = {
+            let mut media_file = zip.by_name("media").expect("media file");
+            let mut media_json = String::new();
+            media_file
+                .read_to_string(&mut media_json)
+                .expect("read media");
+            serde_json::from_str(&media_json).expect("parse media json")
+        };
+        let mut packaged: Vec<&str> = media_map
+            .as_object()
+            .expect("media object")
+            .values()
+            .filter_map(|v| v.as_str())
+            .collect();
+        packaged.sort();
+        assert_eq!(packaged, vec!["_bg.png", "_custom.ttf"]);
+
+        let db_bytes = {
+            let mut db_file = zip.by_name("collection.anki2").expect("collection.anki2");
+            let mut db_bytes = Vec::new();
+            db_file.read_to_end(&mut db_bytes).expect("read db");
+            db_bytes
+        };
+        let db_path = tmp.path().join("collection.anki2");
+        std::fs::write(&db_path, &db_bytes).expect("write db");
+
+        let conn = Connection::open(&db_path).expect("open sqlite");
+        let models_json: String = conn
+            .query_row("SELECT models FROM col LIMIT 1", [], |row| row.get(0))
+            .expect("load models");
+        let models: serde_json::Value =
+            serde_json::from_str(&models_json).expect("parse models json");
+        let model = models
+            .as_object()
+            .and_then(|o| o.values().next())
+            .expect("model object");
+
+        let model_fields: Vec<&str> = model["flds"]
+            .as_array()
+            .expect("model flds")
+            .iter()
+            .filter_map(|f| f["name"].as_str())
+            .collect();
+        assert_eq!(model_fields, vec!["Front", "Back", "Hint"]);
+        assert_eq!(
+            model["css"].as_str(),
+            Some("@font-face { font-family: Custom; src: url('_custom.ttf'); }\n.card { background: url(https://example.com/remote.png); }")
+        );
+        assert_eq!(
+            model["tmpls"][0]["qfmt"].as_str(),
+            Some("<img src=\"_bg.png\">{{Front}}{{#Hint}}{{hint:Hint}}{{/Hint}}")
+        );
+        assert_eq!(model["tmpls"][0]["afmt"].as_str(), Some(back.as_str()));
+    }
 }
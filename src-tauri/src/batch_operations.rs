@@ -1,6 +1,219 @@
+use crate::cmd::mistake_relations::purge_mistake_relations;
+use crate::database::{has_column, mistake_image_paths, table_exists};
 use anyhow::Result;
 use chrono::Utc;
-use rusqlite::{params, Connection};
+use rusqlite::{params, params_from_iter, types::Value, Connection};
+use serde::{Deserialize, Serialize};
+
+/// Criteria for filter-based mistake status updates. Empty fields are ignored.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeStatusFilter {
+    /// Only honoured on databases that still carry the legacy `subject` column.
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// A mistake must carry every listed tag.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Inclusive lower bound on `created_at` (RFC 3339).
+    #[serde(default)]
+    pub created_after: Option<String>,
+    /// Exclusive upper bound on `created_at` (RFC 3339).
+    #[serde(default)]
+    pub created_before: Option<String>,
+    /// Only mistakes currently in one of these statuses.
+    #[serde(default)]
+    pub current_statuses: Vec<String>,
+    /// Also update archived mistakes. Soft-deleted mistakes are never touched.
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+impl MistakeStatusFilter {
+    pub fn is_empty(&self) -> bool {
+        self.subject.as_deref().map_or(true, |s| s.trim().is_empty())
+            && self.tags.is_empty()
+            && self.created_after.is_none()
+            && self.created_before.is_none()
+            && self.current_statuses.is_empty()
+    }
+}
+
+/// How many rows a cleanup category would remove and roughly how much space they hold.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CleanupCategoryStats {
+    pub count: usize,
+    /// Sum of the stored text lengths; page overhead and indexes are not included.
+    pub estimated_bytes: u64,
+}
+
+/// Database-side result of `scan_cleanup_candidates` / `batch_cleanup_database`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseCleanupCounts {
+    /// Knowledge-base chunks whose document row no longer exists.
+    pub dangling_chunks: CleanupCategoryStats,
+    /// Chat messages whose mistake is gone (or trashed).
+    pub orphaned_messages: CleanupCategoryStats,
+    /// Finished card-generation tasks that produced no cards.
+    pub empty_tasks: CleanupCategoryStats,
+    /// Soft-deleted mistakes (only on databases carrying `deleted_at`).
+    pub trashed_mistakes: CleanupCategoryStats,
+    /// Stored document-attachment payloads no remaining message points to.
+    pub orphaned_attachments: CleanupCategoryStats,
+}
+
+impl DatabaseCleanupCounts {
+    pub fn total_estimated_bytes(&self) -> u64 {
+        self.dangling_chunks.estimated_bytes
+            + self.orphaned_messages.estimated_bytes
+            + self.empty_tasks.estimated_bytes
+            + self.trashed_mistakes.estimated_bytes
+            + self.orphaned_attachments.estimated_bytes
+    }
+}
+
+/// Table, WHERE clause and size expression of one cleanup category.
+struct CleanupTarget {
+    table: &'static str,
+    where_sql: String,
+    bytes_sql: &'static str,
+}
+
+/// Payloads in `doc_attachment_blobs` that no message references. With `live_mistakes`
+/// set, only messages of those mistakes count, so the payloads of messages removed by the
+/// same cleanup are already treated as orphaned.
+fn orphaned_attachments_target(
+    conn: &Connection,
+    live_mistakes: Option<&str>,
+) -> Result<Option<CleanupTarget>> {
+    if !table_exists(conn, "doc_attachment_blobs")? {
+        return Ok(None);
+    }
+    let mut references: Vec<String> = Vec::new();
+    for table in ["chat_messages", "review_chat_messages"] {
+        if !table_exists(conn, table)? || !has_column(conn, table, "doc_attachments")? {
+            continue;
+        }
+        let scope = match (table, live_mistakes) {
+            ("chat_messages", Some(live)) => format!(" AND {}.mistake_id IN ({})", table, live),
+            _ => String::new(),
+        };
+        // A NULL hash in the subquery would make every NOT IN comparison NULL.
+        references.push(format!(
+            "SELECT json_extract(je.value, '$.content_hash') FROM {table},
+                 json_each(CASE WHEN json_valid({table}.doc_attachments) THEN {table}.doc_attachments ELSE '[]' END) je
+             WHERE json_extract(je.value, '$.content_hash') IS NOT NULL{scope}",
+            table = table,
+            scope = scope
+        ));
+    }
+    let where_sql = if references.is_empty() {
+        "1 = 1".to_string()
+    } else {
+        format!("content_hash NOT IN ({})", references.join(" UNION "))
+    };
+    Ok(Some(CleanupTarget {
+        table: "doc_attachment_blobs",
+        where_sql,
+        bytes_sql: "COALESCE(LENGTH(CAST(text_content AS BLOB)), 0) + COALESCE(LENGTH(CAST(base64_content AS BLOB)), 0)",
+    }))
+}
+
+/// Cleanup categories in deletion order. Messages of trashed mistakes count as orphaned,
+/// so they are removed before the mistakes themselves and the dry-run matches the real run.
+fn cleanup_targets(conn: &Connection) -> Result<Vec<(&'static str, Option<CleanupTarget>)>> {
+    let has_trash = has_column(conn, "mistakes", "deleted_at")?;
+    let live_mistakes = if has_trash {
+        "SELECT id FROM mistakes WHERE deleted_at IS NULL"
+    } else {
+        "SELECT id FROM mistakes"
+    };
+    let present = |table: &str| table_exists(conn, table);
+
+    Ok(vec![
+        (
+            "dangling_chunks",
+            (present("rag_document_chunks")? && present("rag_documents")?).then(|| CleanupTarget {
+                table: "rag_document_chunks",
+                where_sql: "document_id NOT IN (SELECT id FROM rag_documents)".to_string(),
+                bytes_sql: "LENGTH(CAST(text AS BLOB)) + LENGTH(CAST(metadata AS BLOB))",
+            }),
+        ),
+        (
+            "orphaned_messages",
+            present("chat_messages")?.then(|| CleanupTarget {
+                table: "chat_messages",
+                where_sql: format!("mistake_id NOT IN ({})", live_mistakes),
+                bytes_sql: "LENGTH(CAST(content AS BLOB))",
+            }),
+        ),
+        (
+            "empty_tasks",
+            (present("document_tasks")? && present("anki_cards")?).then(|| CleanupTarget {
+                table: "document_tasks",
+                where_sql: "status IN ('Completed', 'Failed', 'Cancelled', 'Truncated')
+                    AND NOT EXISTS (SELECT 1 FROM anki_cards WHERE anki_cards.task_id = document_tasks.id)"
+                    .to_string(),
+                bytes_sql: "LENGTH(CAST(content_segment AS BLOB)) + LENGTH(CAST(anki_generation_options_json AS BLOB))",
+            }),
+        ),
+        (
+            "trashed_mistakes",
+            has_trash.then(|| CleanupTarget {
+                table: "mistakes",
+                where_sql: "deleted_at IS NOT NULL".to_string(),
+                bytes_sql: "LENGTH(CAST(question_images AS BLOB)) + LENGTH(CAST(analysis_images AS BLOB)) + COALESCE(LENGTH(CAST(ocr_text AS BLOB)), 0)",
+            }),
+        ),
+        (
+            "orphaned_attachments",
+            orphaned_attachments_target(conn, Some(live_mistakes))?,
+        ),
+    ])
+}
+
+/// Purge the manual relations of the trashed mistakes before their rows are deleted,
+/// and return their image paths. Cascades only fire on connections with `foreign_keys` on.
+fn purge_trashed_mistake_dependents(conn: &Connection) -> Result<Vec<String>> {
+    let ids: Vec<String> = conn
+        .prepare("SELECT id FROM mistakes WHERE deleted_at IS NOT NULL")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut images = Vec::new();
+    for id in &ids {
+        images.extend(mistake_image_paths(conn, id)?);
+        purge_mistake_relations(conn, id)?;
+    }
+    Ok(images)
+}
+
+fn count_target(conn: &Connection, target: &CleanupTarget) -> Result<CleanupCategoryStats> {
+    let (count, bytes): (i64, i64) = conn.query_row(
+        &format!(
+            "SELECT COUNT(*), COALESCE(SUM({}), 0) FROM {} WHERE {}",
+            target.bytes_sql, target.table, target.where_sql
+        ),
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(CleanupCategoryStats {
+        count: count.max(0) as usize,
+        estimated_bytes: bytes.max(0) as u64,
+    })
+}
+
+fn assign_stats(counts: &mut DatabaseCleanupCounts, name: &str, stats: CleanupCategoryStats) {
+    match name {
+        "dangling_chunks" => counts.dangling_chunks = stats,
+        "orphaned_messages" => counts.orphaned_messages = stats,
+        "empty_tasks" => counts.empty_tasks = stats,
+        "trashed_mistakes" => counts.trashed_mistakes = stats,
+        "orphaned_attachments" => counts.orphaned_attachments = stats,
+        _ => {}
+    }
+}
 
 pub struct BatchOperations<'a> {
     conn: &'a mut Connection,
@@ -11,15 +224,18 @@ impl<'a> BatchOperations<'a> {
         Self { conn }
     }
 
-    /// Batch operation: Archive old mistakes (change status to "archived")
+    /// Batch operation: Archive old mistakes (change status to "archived" and set `archived_at`)
+    ///
+    /// Pinned mistakes are never auto-archived.
     pub fn batch_archive_old_mistakes(&mut self, days_old: i64) -> Result<usize> {
         let tx = self.conn.transaction()?;
 
         let cutoff_date = (Utc::now() - chrono::Duration::days(days_old)).to_rfc3339();
 
         let updated_count = tx.execute(
-            "UPDATE mistakes SET status = 'archived', updated_at = ?1, last_accessed_at = ?1
-             WHERE created_at < ?2 AND status != 'archived'",
+            "UPDATE mistakes SET status = 'archived', archived_at = COALESCE(archived_at, ?1),
+                 updated_at = ?1, last_accessed_at = ?1
+             WHERE created_at < ?2 AND status != 'archived' AND COALESCE(pinned, 0) = 0",
             params![Utc::now().to_rfc3339(), cutoff_date],
         )?;
 
@@ -27,6 +243,83 @@ impl<'a> BatchOperations<'a> {
         Ok(updated_count)
     }
 
+    /// Batch operation: Set the status of every mistake matching `filter` in a single UPDATE.
+    ///
+    /// An empty filter would touch every mistake, so it is rejected unless `force` is set.
+    pub fn update_mistake_statuses_by_filter(
+        &mut self,
+        filter: &MistakeStatusFilter,
+        new_status: &str,
+        force: bool,
+    ) -> Result<usize> {
+        if new_status.trim().is_empty() {
+            return Err(anyhow::anyhow!("新状态不能为空"));
+        }
+        if filter.is_empty() && !force {
+            return Err(anyhow::anyhow!(
+                "筛选条件为空会更新全部错题，如确需执行请设置 force"
+            ));
+        }
+
+        let tx = self.conn.transaction()?;
+        let now = Utc::now().to_rfc3339();
+        let mut clauses: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = vec![Value::from(new_status.to_string()), Value::from(now)];
+
+        if has_column(&tx, "mistakes", "deleted_at")? {
+            clauses.push("deleted_at IS NULL".to_string());
+        }
+        if !filter.include_archived && has_column(&tx, "mistakes", "archived_at")? {
+            clauses.push("archived_at IS NULL".to_string());
+        }
+        if let Some(subject) = filter.subject.as_deref().filter(|s| !s.trim().is_empty()) {
+            if !has_column(&tx, "mistakes", "subject")? {
+                return Err(anyhow::anyhow!("当前错题库不含科目字段，无法按科目筛选"));
+            }
+            values.push(Value::from(subject.to_string()));
+            clauses.push(format!("subject = ?{}", values.len()));
+        }
+        for tag in &filter.tags {
+            values.push(Value::from(tag.clone()));
+            clauses.push(format!(
+                "(json_valid(tags) AND EXISTS (SELECT 1 FROM json_each(mistakes.tags) WHERE json_each.value = ?{}))",
+                values.len()
+            ));
+        }
+        if let Some(after) = &filter.created_after {
+            values.push(Value::from(after.clone()));
+            clauses.push(format!("created_at >= ?{}", values.len()));
+        }
+        if let Some(before) = &filter.created_before {
+            values.push(Value::from(before.clone()));
+            clauses.push(format!("created_at < ?{}", values.len()));
+        }
+        if !filter.current_statuses.is_empty() {
+            let start = values.len() + 1;
+            let placeholders: Vec<String> = (0..filter.current_statuses.len())
+                .map(|i| format!("?{}", start + i))
+                .collect();
+            values.extend(filter.current_statuses.iter().cloned().map(Value::from));
+            clauses.push(format!("status IN ({})", placeholders.join(", ")));
+        }
+
+        let where_sql = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", clauses.join(" AND "))
+        };
+        let updated_count = tx.execute(
+            &format!(
+                "UPDATE mistakes SET status = ?1, updated_at = ?2{}",
+                where_sql
+            ),
+            params_from_iter(values),
+        )?;
+
+        tx.commit()?;
+        Ok(updated_count)
+    }
+
     /// Batch cleanup: Remove orphaned chat messages
     pub fn batch_cleanup_orphaned_messages(&mut self) -> Result<usize> {
         let tx = self.conn.transaction()?;
@@ -40,6 +333,59 @@ impl<'a> BatchOperations<'a> {
         tx.commit()?;
         Ok(deleted_count)
     }
+
+    /// Dry-run of `batch_cleanup_database`: count what would be removed without writing.
+    pub fn scan_cleanup_candidates(&mut self) -> Result<DatabaseCleanupCounts> {
+        let mut counts = DatabaseCleanupCounts::default();
+        for (name, target) in cleanup_targets(self.conn)? {
+            if let Some(target) = target {
+                assign_stats(&mut counts, name, count_target(self.conn, &target)?);
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Remove document-attachment payloads that no chat or review message references.
+    pub fn gc_orphan_attachments(&mut self) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        crate::database::ensure_doc_attachment_blobs_table(&tx)?;
+        let removed = match orphaned_attachments_target(&tx, None)? {
+            Some(target) => tx.execute(
+                &format!("DELETE FROM {} WHERE {}", target.table, target.where_sql),
+                [],
+            )?,
+            None => 0,
+        };
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Batch cleanup: remove dangling chunks, orphaned messages, empty tasks, trashed
+    /// mistakes and unreferenced attachment payloads in a single transaction. Returns what was actually removed,
+    /// plus the images of the purged mistakes so the caller can release their dedup references after commit.
+    pub fn batch_cleanup_database(&mut self) -> Result<(DatabaseCleanupCounts, Vec<String>)> {
+        let tx = self.conn.transaction()?;
+        let mut counts = DatabaseCleanupCounts::default();
+        let mut dropped_images = Vec::new();
+
+        for (name, target) in cleanup_targets(&tx)? {
+            let Some(target) = target else {
+                continue;
+            };
+            if name == "trashed_mistakes" {
+                dropped_images = purge_trashed_mistake_dependents(&tx)?;
+            }
+            let mut stats = count_target(&tx, &target)?;
+            stats.count = tx.execute(
+                &format!("DELETE FROM {} WHERE {}", target.table, target.where_sql),
+                [],
+            )?;
+            assign_stats(&mut counts, name, stats);
+        }
+
+        tx.commit()?;
+        Ok((counts, dropped_images))
+    }
 }
 
 /// Extension trait for Database to add batch operations
@@ -61,3 +407,161 @@ impl BatchOperationExt for crate::database::Database {
         f(&mut batch_ops)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{
+        insert_mistake, migrated_database, migrated_database_with_rag,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn update_mistake_statuses_by_filter_matches_tags_dates_and_status() -> Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            for (id, created_at, tags, status) in [
+                ("m1", "2026-01-05T00:00:00Z", r#"["函数","导数"]"#, "active"),
+                ("m2", "2026-01-20T00:00:00Z", r#"["函数"]"#, "active"),
+                ("m3", "2026-01-06T00:00:00Z", r#"["函数"]"#, "mastered"),
+                ("m4", "2026-01-07T00:00:00Z", r#"["几何"]"#, "active"),
+                ("m5", "2026-01-08T00:00:00Z", r#"["函数"]"#, "active"),
+                ("m6", "2026-01-09T00:00:00Z", r#"["函数"]"#, "active"),
+            ] {
+                insert_mistake(
+                    &conn,
+                    id,
+                    &[
+                        ("created_at", &created_at),
+                        ("tags", &tags),
+                        ("status", &status),
+                    ],
+                )?;
+            }
+            conn.execute_batch(
+                "UPDATE mistakes SET deleted_at = '2026-02-01T00:00:00Z' WHERE id = 'm5';
+                 UPDATE mistakes SET archived_at = '2026-02-01T00:00:00Z' WHERE id = 'm6';",
+            )?;
+        }
+
+        let mut filter = MistakeStatusFilter {
+            tags: vec!["函数".to_string()],
+            created_after: Some("2026-01-01T00:00:00Z".to_string()),
+            created_before: Some("2026-01-10T00:00:00Z".to_string()),
+            current_statuses: vec!["active".to_string()],
+            ..Default::default()
+        };
+        let updated = db.with_batch_operations(|ops| {
+            ops.update_mistake_statuses_by_filter(&filter, "mastered", false)
+        })?;
+        assert_eq!(updated, 1);
+        let status: String = db.get_conn_safe()?.query_row(
+            "SELECT status FROM mistakes WHERE id = 'm1'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(status, "mastered");
+        // Archived rows need an explicit opt-in; soft-deleted rows are never updated.
+        filter.include_archived = true;
+        let updated = db.with_batch_operations(|ops| {
+            ops.update_mistake_statuses_by_filter(&filter, "mastered", false)
+        })?;
+        assert_eq!(updated, 1);
+
+        let empty = MistakeStatusFilter::default();
+        assert!(db
+            .with_batch_operations(
+                |ops| ops.update_mistake_statuses_by_filter(&empty, "active", false)
+            )
+            .is_err());
+        // The migrated schema has no legacy `subject` column.
+        let subject = MistakeStatusFilter {
+            subject: Some("数学".to_string()),
+            ..Default::default()
+        };
+        assert!(db
+            .with_batch_operations(
+                |ops| ops.update_mistake_statuses_by_filter(&subject, "active", false)
+            )
+            .is_err());
+        assert_eq!(
+            db.with_batch_operations(
+                |ops| ops.update_mistake_statuses_by_filter(&empty, "active", true)
+            )?,
+            4
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn batch_cleanup_dry_run_matches_real_run() -> Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database_with_rag(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            insert_mistake(&conn, "m-live", &[("ocr_text", &"x")])?;
+            insert_mistake(
+                &conn,
+                "m-trash",
+                &[
+                    ("ocr_text", &"y"),
+                    ("question_images", &r#"["images/t.png"]"#),
+                    ("deleted_at", &"2026-01-01T00:00:00Z"),
+                ],
+            )?;
+            conn.execute_batch(
+                "INSERT INTO chat_messages (mistake_id, role, content, timestamp) VALUES
+                    ('m-live', 'user', 'keep', '2026-01-01T00:00:00Z'),
+                    ('m-trash', 'user', 'abc', '2026-01-01T00:00:00Z'),
+                    ('m-gone', 'user', 'de', '2026-01-01T00:00:00Z');
+                 INSERT INTO rag_documents (id, file_name, created_at, updated_at)
+                    VALUES ('d1', 'a.pdf', '2026-01-01T00:00:00Z', '2026-01-01T00:00:00Z');
+                 INSERT INTO rag_document_chunks (id, document_id, chunk_index, text, metadata) VALUES
+                    ('c1', 'd1', 0, 'keep', '{}'), ('c2', 'd-gone', 0, 'abcd', '{}');
+                 INSERT INTO document_tasks (id, document_id, original_document_name, segment_index,
+                    content_segment, status, anki_generation_options_json) VALUES
+                    ('t-cards', 'doc', 'a.pdf', 0, 'a', 'Completed', '{}'),
+                    ('t-empty', 'doc', 'a.pdf', 1, 'seg', 'Failed', '{}'),
+                    ('t-running', 'doc', 'a.pdf', 2, 'seg', 'Processing', '{}');
+                 INSERT INTO anki_cards (id, task_id, front, back) VALUES ('card1', 't-cards', '问', '答');
+                 INSERT INTO mistake_relations (from_mistake_id, to_mistake_id, relation_type, created_at)
+                    VALUES ('m-live', 'm-trash', 'related', '2026-01-01T00:00:00Z');",
+            )?;
+        }
+
+        let preview = db.with_batch_operations(|ops| ops.scan_cleanup_candidates())?;
+        assert_eq!(preview.dangling_chunks.count, 1);
+        assert_eq!(preview.dangling_chunks.estimated_bytes, 6);
+        assert_eq!(preview.orphaned_messages.count, 2);
+        assert_eq!(preview.orphaned_messages.estimated_bytes, 5);
+        assert_eq!(preview.empty_tasks.count, 1);
+        assert_eq!(preview.trashed_mistakes.count, 1);
+        // A dry run must not modify anything.
+        assert_eq!(
+            db.with_batch_operations(|ops| ops.scan_cleanup_candidates())?,
+            preview
+        );
+
+        let (removed, dropped_images) =
+            db.with_batch_operations(|ops| ops.batch_cleanup_database())?;
+        assert_eq!(removed, preview);
+        assert_eq!(dropped_images, vec!["images/t.png"]);
+        let after = db.with_batch_operations(|ops| ops.scan_cleanup_candidates())?;
+        assert_eq!(after.total_estimated_bytes(), 0);
+        let remaining: i64 = db.get_conn_safe()?.query_row(
+            "SELECT (SELECT COUNT(*) FROM chat_messages) + (SELECT COUNT(*) FROM document_tasks)",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(remaining, 3);
+        let relations: i64 = db.get_conn_safe()?.query_row(
+            "SELECT COUNT(*) FROM mistake_relations WHERE to_mistake_id = 'm-trash'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(relations, 0);
+        Ok(())
+    }
+}
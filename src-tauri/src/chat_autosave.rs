@@ -0,0 +1,329 @@
+//! 错题聊天自动保存合并（防抖）
+//!
+//! 流式输出期间前端会高频提交整段消息快照。这里只保留每个会话的最新快照，
+//! 距上次落盘超过防抖间隔才写库；轮次完成或取消时调用 [`ChatAutosaveDebouncer::finish`]
+//! 强制落盘。写库前比较 `mistakes.autosave_signature`，内容未变则跳过。
+//! 间隔内暂存的快照由 [`ChatAutosaveDebouncer::schedule_deferred_flush`] 的定时器
+//! 在间隔到期时落盘，不依赖下一次提交。
+//!
+//! 持久性取舍：间隔内的增量只在内存中，进程崩溃最多丢失一个间隔（默认 2 秒）的
+//! 流式内容；已完成或已取消的轮次总会落盘。间隔设为 0 时每次提交都立即写库。
+//! 间隔由设置项 `chat.autosave_debounce_ms` 控制。
+
+use crate::database::Database;
+use crate::models::ChatMessage;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+pub const AUTOSAVE_DEBOUNCE_SETTING_KEY: &str = "chat.autosave_debounce_ms";
+pub const DEFAULT_AUTOSAVE_DEBOUNCE_MS: u64 = 2000;
+
+/// 全局合并器（按 mistake_id 区分会话）
+pub static CHAT_AUTOSAVE: LazyLock<ChatAutosaveDebouncer> =
+    LazyLock::new(ChatAutosaveDebouncer::default);
+
+/// 读取防抖间隔；未设置或无法解析时使用默认值
+pub fn autosave_debounce_interval(db: &Database) -> Duration {
+    let ms = db
+        .get_setting(AUTOSAVE_DEBOUNCE_SETTING_KEY)
+        .ok()
+        .flatten()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_AUTOSAVE_DEBOUNCE_MS);
+    Duration::from_millis(ms)
+}
+
+/// 待落盘的会话快照
+#[derive(Debug, Clone)]
+pub struct PendingChatSnapshot {
+    pub messages: Vec<ChatMessage>,
+    pub chat_category: Option<String>,
+}
+
+#[derive(Default)]
+struct SessionState {
+    pending: Option<PendingChatSnapshot>,
+    last_flush: Option<Instant>,
+    flush_timer_armed: bool,
+}
+
+#[derive(Default)]
+pub struct ChatAutosaveDebouncer {
+    sessions: Mutex<HashMap<String, SessionState>>,
+}
+
+impl ChatAutosaveDebouncer {
+    fn sessions(&self) -> std::sync::MutexGuard<'_, HashMap<String, SessionState>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 暂存最新快照；到达落盘时机时取出并返回，由调用方写库
+    ///
+    /// 每个会话的首次提交立即落盘，之后按 `interval` 合并。
+    pub fn stage(
+        &self,
+        mistake_id: &str,
+        snapshot: PendingChatSnapshot,
+        interval: Duration,
+    ) -> Option<PendingChatSnapshot> {
+        self.stage_at(mistake_id, snapshot, interval, Instant::now())
+    }
+
+    fn stage_at(
+        &self,
+        mistake_id: &str,
+        snapshot: PendingChatSnapshot,
+        interval: Duration,
+        now: Instant,
+    ) -> Option<PendingChatSnapshot> {
+        let mut sessions = self.sessions();
+        let session = sessions.entry(mistake_id.to_string()).or_default();
+        session.pending = Some(snapshot);
+        let due = session
+            .last_flush
+            .map_or(true, |t| now.saturating_duration_since(t) >= interval);
+        if due {
+            session.last_flush = Some(now);
+            session.pending.take()
+        } else {
+            None
+        }
+    }
+
+    /// 轮次完成/取消：取出剩余快照并结束会话状态
+    pub fn finish(&self, mistake_id: &str) -> Option<PendingChatSnapshot> {
+        self.sessions()
+            .remove(mistake_id)
+            .and_then(|session| session.pending)
+    }
+
+    /// 为暂存中的快照安排定时落盘；已有定时器或没有待写快照时不重复安排
+    ///
+    /// 定时器在持有会话锁期间写库，`finish` 之后的最终写入因此不会被较旧的快照覆盖。
+    pub fn schedule_deferred_flush(
+        &'static self,
+        db: Arc<Database>,
+        mistake_id: &str,
+        interval: Duration,
+    ) {
+        let Some(delay) = self.arm_flush_timer_at(mistake_id, interval, Instant::now()) else {
+            return;
+        };
+        let mistake_id = mistake_id.to_string();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let mut sessions = self.sessions();
+            let Some(snapshot) = Self::take_deferred_at(&mut sessions, &mistake_id, Instant::now())
+            else {
+                return;
+            };
+            if let Err(e) = db.append_mistake_chat_messages_if_changed(
+                &mistake_id,
+                &snapshot.messages,
+                snapshot.chat_category.as_deref(),
+            ) {
+                warn!("[ChatAutosave] {} 定时落盘失败: {}", mistake_id, e);
+                if let Some(session) = sessions.get_mut(&mistake_id) {
+                    if session.pending.is_none() {
+                        session.pending = Some(snapshot);
+                    }
+                    session.last_flush = None;
+                }
+            }
+        });
+    }
+
+    /// 标记定时器已安排，返回距落盘时机的等待时长
+    fn arm_flush_timer_at(
+        &self,
+        mistake_id: &str,
+        interval: Duration,
+        now: Instant,
+    ) -> Option<Duration> {
+        let mut sessions = self.sessions();
+        let session = sessions.get_mut(mistake_id)?;
+        if session.pending.is_none() || session.flush_timer_armed {
+            return None;
+        }
+        session.flush_timer_armed = true;
+        let elapsed = session
+            .last_flush
+            .map_or(interval, |t| now.saturating_duration_since(t));
+        Some(interval.saturating_sub(elapsed))
+    }
+
+    /// 定时器到期：取出暂存快照并记为已落盘
+    fn take_deferred_at(
+        sessions: &mut HashMap<String, SessionState>,
+        mistake_id: &str,
+        now: Instant,
+    ) -> Option<PendingChatSnapshot> {
+        let session = sessions.get_mut(mistake_id)?;
+        session.flush_timer_armed = false;
+        let snapshot = session.pending.take()?;
+        session.last_flush = Some(now);
+        Some(snapshot)
+    }
+
+    /// 写库失败时放回快照（若期间没有更新的快照），等待下次提交重试
+    pub fn restore(&self, mistake_id: &str, snapshot: PendingChatSnapshot) {
+        let mut sessions = self.sessions();
+        let session = sessions.entry(mistake_id.to_string()).or_default();
+        if session.pending.is_none() {
+            session.pending = Some(snapshot);
+        }
+        session.last_flush = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(content: &str) -> PendingChatSnapshot {
+        PendingChatSnapshot {
+            messages: vec![ChatMessage {
+                role: "assistant".to_string(),
+                content: content.to_string(),
+                timestamp: chrono::Utc::now(),
+                thinking_content: None,
+                thought_signature: None,
+                rag_sources: None,
+                memory_sources: None,
+                graph_sources: None,
+                web_search_sources: None,
+                image_paths: None,
+                image_base64: None,
+                doc_attachments: None,
+                tool_call: None,
+                tool_result: None,
+                overrides: None,
+                relations: None,
+                persistent_stable_id: Some("a-1".to_string()),
+                metadata: None,
+                multimodal_content: None,
+            }],
+            chat_category: None,
+        }
+    }
+
+    #[test]
+    fn test_stage_coalesces_within_interval_and_finish_flushes_latest() {
+        let debouncer = ChatAutosaveDebouncer::default();
+        let interval = Duration::from_secs(2);
+        let start = Instant::now();
+
+        assert!(debouncer
+            .stage_at("m1", snapshot("a"), interval, start)
+            .is_some());
+        assert!(debouncer
+            .stage_at(
+                "m1",
+                snapshot("ab"),
+                interval,
+                start + Duration::from_millis(500)
+            )
+            .is_none());
+        assert!(debouncer
+            .stage_at(
+                "m1",
+                snapshot("abc"),
+                interval,
+                start + Duration::from_millis(900)
+            )
+            .is_none());
+        let due = debouncer
+            .stage_at(
+                "m1",
+                snapshot("abcd"),
+                interval,
+                start + Duration::from_secs(3),
+            )
+            .expect("间隔到达后应落盘");
+        assert_eq!(due.messages[0].content, "abcd");
+
+        debouncer.stage_at(
+            "m1",
+            snapshot("abcde"),
+            interval,
+            start + Duration::from_secs(4),
+        );
+        let last = debouncer.finish("m1").expect("结束时应取出剩余快照");
+        assert_eq!(last.messages[0].content, "abcde");
+        assert!(debouncer.finish("m1").is_none());
+    }
+
+    #[test]
+    fn test_deferred_flush_takes_pending_once_interval_elapses() {
+        let debouncer = ChatAutosaveDebouncer::default();
+        let interval = Duration::from_secs(2);
+        let start = Instant::now();
+
+        debouncer.stage_at("m1", snapshot("a"), interval, start);
+        assert_eq!(
+            debouncer.arm_flush_timer_at("m1", interval, start),
+            None,
+            "没有暂存快照时不安排定时器"
+        );
+        let staged_at = start + Duration::from_millis(500);
+        assert!(debouncer
+            .stage_at("m1", snapshot("ab"), interval, staged_at)
+            .is_none());
+        assert_eq!(
+            debouncer.arm_flush_timer_at("m1", interval, staged_at),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            debouncer.arm_flush_timer_at("m1", interval, staged_at),
+            None
+        );
+
+        let fired_at = start + interval;
+        let mut sessions = debouncer.sessions();
+        let flushed = ChatAutosaveDebouncer::take_deferred_at(&mut sessions, "m1", fired_at)
+            .expect("定时器到期应取出暂存快照");
+        assert_eq!(flushed.messages[0].content, "ab");
+        assert!(ChatAutosaveDebouncer::take_deferred_at(&mut sessions, "m1", fired_at).is_none());
+        drop(sessions);
+
+        assert!(debouncer
+            .stage_at(
+                "m1",
+                snapshot("abc"),
+                interval,
+                fired_at + Duration::from_millis(100)
+            )
+            .is_none());
+        assert!(debouncer.finish("m1").is_some());
+    }
+
+    #[test]
+    fn test_zero_interval_writes_every_snapshot() {
+        let debouncer = ChatAutosaveDebouncer::default();
+        let now = Instant::now();
+        for content in ["a", "ab", "abc"] {
+            assert!(debouncer
+                .stage_at("m1", snapshot(content), Duration::ZERO, now)
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn test_signature_detects_changes() {
+        let original = snapshot("a").messages;
+        let mut edited = original.clone();
+        edited[0].content.push('b');
+
+        let signature = crate::database::chat_messages_signature(&original).unwrap();
+        assert_eq!(
+            signature,
+            crate::database::chat_messages_signature(&original.clone()).unwrap()
+        );
+        assert_ne!(
+            signature,
+            crate::database::chat_messages_signature(&edited).unwrap()
+        );
+    }
+}
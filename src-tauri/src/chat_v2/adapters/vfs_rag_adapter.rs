@@ -205,6 +205,7 @@ impl ChatV2VfsRagAdapter {
             resource_types,
             modality: MODALITY_TEXT.to_string(),
             top_k,
+            folder_weights: None,
         };
 
         // 3. 执行检索
@@ -8,6 +8,7 @@ use std::time::Instant;
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+use crate::llm_manager::ThinkingDelivery;
 use crate::models::ChatMessage as LegacyChatMessage;
 
 use super::pipeline::ChatV2LLMAdapter;
@@ -46,6 +47,8 @@ pub(crate) struct PipelineContext {
     pub(crate) final_content: String,
     /// 最终生成的思维链
     pub(crate) final_reasoning: Option<String>,
+    /// 思维链推送/保存策略（执行前按请求、模型与全局设置解析）
+    pub(crate) thinking_delivery: ThinkingDelivery,
     /// 活跃的块 ID 映射（event_type -> block_id）
     pub(crate) active_blocks: HashMap<String, String>,
     /// 生成的块列表（用于持久化）
@@ -134,6 +137,7 @@ impl PipelineContext {
             tool_results: Vec::new(),
             final_content: String::new(),
             final_reasoning: None,
+            thinking_delivery: ThinkingDelivery::default(),
             active_blocks: HashMap::new(),
             generated_blocks: Vec::new(),
             streaming_thinking_block_id: None,
@@ -438,9 +442,9 @@ impl PipelineContext {
         let now_ms = chrono::Utc::now().timestamp_millis();
         let context_start_ms = now_ms - self.elapsed_ms() as i64;
 
-        // 添加 thinking 块（如果有）
+        // 添加 thinking 块（如果有；策略不保存思维链时跳过）
         if let (Some(block_id), Some(content)) = (thinking_block_id, thinking_content) {
-            if !content.is_empty() {
+            if !content.is_empty() && self.thinking_delivery.persists() {
                 // 🔧 P3修复：使用上一个块的结束时间作为本块的开始时间
                 // 第一个块使用 context 开始时间
                 let started_at = self.last_block_ended_at.unwrap_or(context_start_ms);
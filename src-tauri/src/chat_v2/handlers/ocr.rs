@@ -14,6 +14,9 @@ use crate::commands::AppState;
 pub struct OcrRequest {
     /// 图片 base64 列表（支持 data:image/... 格式或纯 base64）
     pub images: Vec<String>,
+    /// 可选科目；该科目在模型分配中设置了 OCR 模型覆盖时使用覆盖模型
+    #[serde(default)]
+    pub subject: Option<String>,
 }
 
 /// OCR 响应
@@ -25,6 +28,8 @@ pub struct OcrResponse {
     pub tags: Vec<String>,
     /// 题型
     pub mistake_type: String,
+    /// 识别文本的主要语言（`zh`、`en` 等，混排为 `mixed`）；保存错题时写入其 `language` 字段
+    pub language: Option<String>,
 }
 
 /// 执行 OCR 识别
@@ -56,80 +61,170 @@ pub async fn chat_v2_perform_ocr(
 
     // 获取当前 OCR 引擎适配器
     let adapter = state.llm_manager.get_ocr_adapter().await;
+    let subject = request.subject.as_deref();
+    // 科目指定了 OCR 模型时走 VLM 路径
+    let has_subject_ocr_model = state
+        .llm_manager
+        .get_model_assignments()
+        .await
+        .is_ok_and(|a| a.ocr_model_for_subject(subject).is_some());
 
-    let ocr_text = if adapter.engine_type().is_native_ocr() {
-        // ===== 系统原生 OCR 路径 =====
-        // 直接调用操作系统内置 OCR 引擎，不经过 LLM 云端
-        log::info!("[ChatV2::OCR] Using system native OCR engine");
+    let (ocr_text, used_config_id) =
+        if adapter.engine_type().is_native_ocr() && !has_subject_ocr_model {
+            // ===== 系统原生 OCR 路径 =====
+            // 直接调用操作系统内置 OCR 引擎，不经过 LLM 云端
+            log::info!("[ChatV2::OCR] Using system native OCR engine");
 
-        let mut all_text = String::new();
-        for (index, base64_data) in request.images.iter().enumerate() {
-            let image_bytes = parse_base64_image(base64_data).map_err(|e| {
-                ChatV2Error::Validation(format!("Failed to parse image {}: {}", index, e))
-                    .to_string()
-            })?;
+            let mut all_text = String::new();
+            for (index, base64_data) in request.images.iter().enumerate() {
+                let image_bytes = parse_base64_image(base64_data).map_err(|e| {
+                    ChatV2Error::Validation(format!("Failed to parse image {}: {}", index, e))
+                        .to_string()
+                })?;
+
+                let text = crate::ocr_adapters::system_ocr::perform_system_ocr(&image_bytes)
+                    .await
+                    .map_err(|e| {
+                        log::error!("[ChatV2::OCR] System OCR failed for image {}: {}", index, e);
+                        ChatV2Error::Llm(format!("System OCR failed: {}", e)).to_string()
+                    })?;
 
-            let text = crate::ocr_adapters::system_ocr::perform_system_ocr(&image_bytes)
+                if !all_text.is_empty() && !text.is_empty() {
+                    all_text.push_str("\n\n");
+                }
+                all_text.push_str(&text);
+            }
+            (all_text, None)
+        } else {
+            // ===== VLM 云端 OCR 路径 =====
+            let (config, engine) = state
+                .llm_manager
+                .get_ocr_config_with_effective_engine_for_subject(subject)
                 .await
                 .map_err(|e| {
-                    log::error!("[ChatV2::OCR] System OCR failed for image {}: {}", index, e);
-                    ChatV2Error::Llm(format!("System OCR failed: {}", e)).to_string()
+                    log::error!("[ChatV2::OCR] OCR failed: {}", e);
+                    ChatV2Error::Llm(format!("OCR failed: {}", e)).to_string()
                 })?;
+            let prompt = crate::ocr_language::free_ocr_prompt(adapter.engine_type());
+            let image_payloads = build_image_payloads(&request.images)?;
 
-            if !all_text.is_empty() && !text.is_empty() {
-                all_text.push_str("\n\n");
-            }
-            all_text.push_str(&text);
-        }
-        all_text
-    } else {
-        // ===== VLM 云端 OCR 路径（现有逻辑，完全不变）=====
-        let prompt = adapter.build_prompt(crate::ocr_adapters::OcrMode::FreeOcr);
-
-        let mut image_payloads = Vec::new();
-        for (index, base64_data) in request.images.iter().enumerate() {
-            use base64::Engine;
-            let image_bytes = parse_base64_image(base64_data).map_err(|e| {
-                ChatV2Error::Validation(format!("Failed to parse image {}: {}", index, e))
-                    .to_string()
-            })?;
-            let mime = infer_mime_from_data_url(base64_data);
-            let normalized_base64 = base64::engine::general_purpose::STANDARD.encode(&image_bytes);
-            image_payloads.push(crate::llm_manager::ImagePayload {
-                mime: mime.to_string(),
-                base64: normalized_base64,
-            });
-        }
+            let ocr_raw = state
+                .llm_manager
+                .call_ocr_model_raw_prompt_with_config(
+                    &config,
+                    engine,
+                    prompt.as_str(),
+                    Some(image_payloads),
+                )
+                .await
+                .map_err(|e| {
+                    log::error!("[ChatV2::OCR] OCR failed: {}", e);
+                    ChatV2Error::Llm(format!("OCR failed: {}", e)).to_string()
+                })?;
 
-        let ocr_raw = state
-            .llm_manager
-            .call_ocr_model_raw_prompt(prompt.as_str(), Some(image_payloads))
-            .await
-            .map_err(|e| {
-                log::error!("[ChatV2::OCR] OCR failed: {}", e);
-                ChatV2Error::Llm(format!("OCR failed: {}", e)).to_string()
-            })?;
+            (
+                ocr_raw.assistant_message.trim().to_string(),
+                Some(config.id),
+            )
+        };
 
-        ocr_raw.assistant_message.trim().to_string()
-    };
+    let mut final_text = ocr_text;
+    let mut language = crate::ocr_language::detect_ocr_language(&final_text);
 
-    // OCR 分类已废弃：仅返回 OCR 结果
-    let final_text = ocr_text;
+    // 按识别语言改用指定模型重新识别；科目覆盖优先，失败时沿用首次结果
+    let routing = crate::ocr_language::load_ocr_language_routing(&state.database);
+    let routed_config_id = if has_subject_ocr_model {
+        None
+    } else {
+        routing
+            .route(
+                language.as_deref(),
+                used_config_id.as_deref().unwrap_or_default(),
+            )
+            .map(str::to_string)
+    };
+    if let Some(config_id) = routed_config_id {
+        match rerun_ocr_with_config(&state, &config_id, &request.images).await {
+            Ok(text) if !text.is_empty() => {
+                log::info!(
+                    "[ChatV2::OCR] Re-ran OCR for language {:?} with config {}",
+                    language,
+                    config_id
+                );
+                language = crate::ocr_language::detect_ocr_language(&text).or(language);
+                final_text = text;
+            }
+            Ok(_) => log::warn!(
+                "[ChatV2::OCR] Language-routed OCR returned empty text, keeping first pass"
+            ),
+            Err(e) => log::warn!(
+                "[ChatV2::OCR] Language-routed OCR with config {} failed, keeping first pass: {}",
+                config_id,
+                e
+            ),
+        }
+    }
 
+    // OCR 分类已废弃：仅返回 OCR 结果与识别语言
     log::info!(
-        "[ChatV2::OCR] OCR completed: text_len={}, tags_count={}, type={}",
+        "[ChatV2::OCR] OCR completed: text_len={}, language={:?}",
         final_text.len(),
-        0,
-        ""
+        language
     );
 
     Ok(OcrResponse {
         ocr_text: final_text,
         tags: Vec::new(),
         mistake_type: String::new(),
+        language,
     })
 }
 
+/// 使用按语言路由的模型重新识别
+async fn rerun_ocr_with_config(
+    state: &AppState,
+    config_id: &str,
+    images: &[String],
+) -> Result<String, String> {
+    let (config, engine) = state
+        .llm_manager
+        .get_ocr_config_by_id_with_effective_engine(config_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let prompt = crate::ocr_language::free_ocr_prompt(engine);
+    let output = state
+        .llm_manager
+        .call_ocr_model_raw_prompt_with_config(
+            &config,
+            engine,
+            prompt.as_str(),
+            Some(build_image_payloads(images)?),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(output.assistant_message.trim().to_string())
+}
+
+fn build_image_payloads(
+    images: &[String],
+) -> Result<Vec<crate::llm_manager::ImagePayload>, String> {
+    use base64::Engine;
+
+    let mut image_payloads = Vec::with_capacity(images.len());
+    for (index, base64_data) in images.iter().enumerate() {
+        let image_bytes = parse_base64_image(base64_data).map_err(|e| {
+            ChatV2Error::Validation(format!("Failed to parse image {}: {}", index, e)).to_string()
+        })?;
+        let mime = infer_mime_from_data_url(base64_data);
+        let normalized_base64 = base64::engine::general_purpose::STANDARD.encode(&image_bytes);
+        image_payloads.push(crate::llm_manager::ImagePayload {
+            mime: mime.to_string(),
+            base64: normalized_base64,
+        });
+    }
+    Ok(image_payloads)
+}
+
 /// 解析 base64 图片数据
 ///
 /// 支持两种格式：
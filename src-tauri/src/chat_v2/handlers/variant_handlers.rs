@@ -77,6 +77,18 @@ fn resolve_retry_options(
             .and_then(|v| v.as_u64())
             .map(|v| v as u32);
         options.enable_thinking = params.get("enableThinking").and_then(|v| v.as_bool());
+        options.thinking_delivery = params
+            .get("thinkingDelivery")
+            .and_then(|v| serde_json::from_value(v.clone()).ok());
+        // 重试沿用原回答语言，不受之后全局设置变更影响
+        options.response_language = params
+            .get("responseLanguage")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        options.verbosity = params
+            .get("verbosity")
+            .and_then(|v| v.as_str())
+            .and_then(crate::response_verbosity::Verbosity::parse);
     }
 
     options
@@ -1304,7 +1316,9 @@ mod tests {
         let chat_params = serde_json::json!({
             "temperature": 0.2,
             "maxTokens": 2048,
-            "enableThinking": true
+            "enableThinking": true,
+            "responseLanguage": "en",
+            "verbosity": "concise"
         });
 
         let merged = resolve_retry_options(Some(&chat_params), "cfg-2", None);
@@ -1314,6 +1328,11 @@ mod tests {
         assert_eq!(merged.temperature, Some(0.2));
         assert_eq!(merged.max_tokens, Some(2048));
         assert_eq!(merged.enable_thinking, Some(true));
+        assert_eq!(merged.response_language.as_deref(), Some("en"));
+        assert_eq!(
+            merged.verbosity,
+            Some(crate::response_verbosity::Verbosity::Concise)
+        );
         assert!(merged.mcp_tool_schemas.is_none());
         assert!(merged.schema_tool_ids.is_none());
     }
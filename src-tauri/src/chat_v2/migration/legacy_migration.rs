@@ -272,7 +272,7 @@ impl MigrationExecutor {
             .prepare(sql)
             .map_err(|e| ChatV2Error::Database(format!("准备查询失败: {}", e)))?;
 
-        let messages = stmt
+        let mut messages = stmt
             .query_map([], |row| {
                 Ok(LegacyMessage {
                     id: row.get(0)?,
@@ -298,6 +298,16 @@ impl MigrationExecutor {
             .collect::<Result<Vec<_>, _>>()
             .map_err(|e| ChatV2Error::Database(format!("收集消息失败: {}", e)))?;
 
+        // 文档附件载荷按指纹去重存储，消息中只有引用，迁移前回填
+        for msg in messages.iter_mut() {
+            if let Some(raw) = msg.doc_attachments.take() {
+                msg.doc_attachments = Some(
+                    crate::database::rehydrate_doc_attachments_json(conn, raw)
+                        .map_err(|e| ChatV2Error::Database(format!("回填文档附件失败: {}", e)))?,
+                );
+            }
+        }
+
         Ok(messages)
     }
 
@@ -738,14 +748,29 @@ impl MigrationExecutor {
         if let Some(ref docs) = msg.doc_attachments {
             if let Ok(docs_vec) = serde_json::from_str::<Vec<Value>>(docs) {
                 for doc in docs_vec {
-                    attachments.push(serde_json::json!({
+                    let mime_type = doc
+                        .get("mimeType")
+                        .or_else(|| doc.get("mime_type"))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("application/octet-stream");
+                    let mut attachment = serde_json::json!({
                         "id": format!("att_{}", uuid::Uuid::new_v4()),
                         "name": doc.get("name").and_then(|v| v.as_str()).unwrap_or("document"),
                         "type": "document",
-                        "mimeType": doc.get("mimeType").and_then(|v| v.as_str()).unwrap_or("application/octet-stream"),
-                        "size": doc.get("size").and_then(|v| v.as_u64()).unwrap_or(0),
+                        "mimeType": mime_type,
+                        "size": doc
+                            .get("size")
+                            .or_else(|| doc.get("size_bytes"))
+                            .and_then(|v| v.as_u64())
+                            .unwrap_or(0),
                         "status": "ready",
-                    }));
+                    });
+                    // 回填后的原始内容作为预览保留，避免迁移后附件只剩文件名
+                    if let Some(b64) = doc.get("base64_content").and_then(|v| v.as_str()) {
+                        attachment["previewUrl"] =
+                            Value::String(format!("data:{};base64,{}", mime_type, b64));
+                    }
+                    attachments.push(attachment);
                 }
             }
         }
@@ -62,15 +62,16 @@ pub(crate) use crate::vfs::multimodal_service::VfsMultimodalService;
 pub(crate) use super::context::PipelineContext;
 pub(crate) use super::resource_types::{ContentBlock, ContextRef, ContextSnapshot};
 pub(crate) use super::types::{
-    block_status, block_types, feature_flags, variant_status, AttachmentInput, ChatMessage,
-    MessageBlock, MessageMeta, MessageRole, MessageSources, SendMessageRequest, SendOptions,
-    SharedContext, SourceInfo, TokenUsage, ToolCall, ToolResultInfo, Variant,
+    block_status, block_types, feature_flags, message_lifecycle, variant_status, AttachmentInput,
+    ChatMessage, MessageBlock, MessageMeta, MessageRole, MessageSources, SendMessageRequest,
+    SendOptions, SharedContext, SourceInfo, TokenUsage, ToolCall, ToolResultInfo, Variant,
 };
 pub(crate) use super::user_message_builder::{build_user_message, UserMessageParams};
 pub(crate) use super::workspace::WorkspaceCoordinator;
 pub(crate) use std::sync::Mutex;
 
 pub mod constants;
+pub mod context_sources;
 pub mod helpers;
 pub mod history;
 pub mod llm_adapter;
@@ -84,6 +85,7 @@ pub mod tool_loop;
 pub mod variant_adapter;
 
 pub use constants::*;
+pub use context_sources::*;
 pub use helpers::*;
 pub use history::*;
 pub use llm_adapter::*;
@@ -489,6 +491,17 @@ impl ChatV2Pipeline {
         // 🔧 Bug修复：将模型显示名称存储到 ctx，用于消息保存
         ctx.model_display_name = model_name.clone();
 
+        // 思维链推送/保存策略：请求覆盖 > 模型配置 > 全局设置
+        let delivery_model_id = ctx
+            .options
+            .model2_override_id
+            .clone()
+            .or_else(|| ctx.options.model_id.clone());
+        ctx.thinking_delivery = self
+            .llm_manager
+            .resolve_thinking_delivery(delivery_model_id.as_deref(), ctx.options.thinking_delivery)
+            .await;
+
         // 发射流式开始事件（带模型名称）
         log::info!(
             "[ChatV2::pipeline] Emitting stream_start with model_name: {:?}",
@@ -703,6 +716,15 @@ impl ChatV2Pipeline {
         // 阶段 2：加载聊天历史
         self.load_chat_history(ctx).await?;
 
+        // 阶段 2.5：确定回答语言与详略程度（写回 options，随 chatParams 快照持久化）
+        ctx.options.response_language = self.resolve_response_language(
+            &ctx.session_id,
+            &ctx.assistant_message_id,
+            &ctx.options,
+            &ctx.user_content,
+        );
+        ctx.options.verbosity = self.resolve_verbosity(&ctx.options);
+
         // 阶段 3：并行执行检索
         if cancel_token.is_cancelled() {
             return Err(ChatV2Error::Cancelled);
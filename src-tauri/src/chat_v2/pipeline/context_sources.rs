@@ -0,0 +1,130 @@
+//! 单轮上下文来源开关（RAG / 图谱 / 记忆 / 网络搜索）
+//!
+//! 检索已改为工具化模式，开关通过过滤注入给 LLM 的内置检索工具生效。
+//! 取值优先级：本次请求的 `SendOptions::{rag_enabled, graph_rag_enabled, memory_enabled,
+//! web_search_enabled}` > 设置项 `chat.context_sources.{rag,graph,memory,web_search}` > 开启。
+//! 全部开启时不做任何过滤，与未配置时的行为一致。
+
+use super::*;
+
+pub const CONTEXT_SOURCE_SETTING_PREFIX: &str = "chat.context_sources.";
+
+/// 本轮生效的上下文来源开关
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextSourceToggles {
+    pub rag: bool,
+    /// 当前没有内置图谱检索工具，关闭后不过滤任何工具，仅随 chatParams 记录
+    pub graph: bool,
+    pub memory: bool,
+    pub web_search: bool,
+}
+
+impl Default for ContextSourceToggles {
+    fn default() -> Self {
+        Self {
+            rag: true,
+            graph: true,
+            memory: true,
+            web_search: true,
+        }
+    }
+}
+
+fn parse_toggle(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "on" | "yes" => Some(true),
+        "false" | "0" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+impl ContextSourceToggles {
+    /// 合并设置默认值与本次请求覆盖
+    pub fn resolve(options: &SendOptions, main_db: Option<&MainDatabase>) -> Self {
+        let setting = |key: &str| {
+            main_db
+                .and_then(|db| {
+                    db.get_setting(&format!("{}{}", CONTEXT_SOURCE_SETTING_PREFIX, key))
+                        .ok()
+                        .flatten()
+                })
+                .and_then(|v| parse_toggle(&v))
+        };
+        let defaults = Self {
+            rag: setting("rag").unwrap_or(true),
+            graph: setting("graph").unwrap_or(true),
+            memory: setting("memory").unwrap_or(true),
+            web_search: setting("web_search").unwrap_or(true),
+        };
+        defaults.with_overrides(options)
+    }
+
+    fn with_overrides(self, options: &SendOptions) -> Self {
+        Self {
+            rag: options.rag_enabled.unwrap_or(self.rag),
+            graph: options.graph_rag_enabled.unwrap_or(self.graph),
+            memory: options.memory_enabled.unwrap_or(self.memory),
+            web_search: options.web_search_enabled.unwrap_or(self.web_search),
+        }
+    }
+
+    /// 工具是否允许注入；只拦截对应来源的内置检索工具
+    pub fn allows_tool(&self, tool_name: &str) -> bool {
+        let Some(stripped) = tool_name.strip_prefix(BUILTIN_NAMESPACE) else {
+            return true;
+        };
+        match stripped {
+            "rag_search" | "multimodal_search" | "unified_search" | "resource_search" => self.rag,
+            "memory_search" | "memory_read" | "memory_list" => self.memory,
+            "web_search" | "web_fetch" => self.web_search,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_take_precedence_over_defaults() {
+        let defaults = ContextSourceToggles {
+            web_search: false,
+            ..Default::default()
+        };
+        let options = SendOptions {
+            rag_enabled: Some(false),
+            web_search_enabled: Some(true),
+            ..Default::default()
+        };
+        let resolved = defaults.with_overrides(&options);
+        assert!(!resolved.rag);
+        assert!(resolved.web_search);
+        assert!(resolved.memory && resolved.graph);
+        assert_eq!(defaults.with_overrides(&SendOptions::default()), defaults);
+    }
+
+    #[test]
+    fn test_pure_model_turn_filters_only_retrieval_tools() {
+        let none = ContextSourceToggles {
+            rag: false,
+            graph: false,
+            memory: false,
+            web_search: false,
+        };
+        for tool in [
+            "builtin-rag_search",
+            "builtin-unified_search",
+            "builtin-resource_search",
+            "builtin-memory_search",
+            "builtin-web_search",
+            "builtin-web_fetch",
+        ] {
+            assert!(!none.allows_tool(tool), "{} 应被过滤", tool);
+        }
+        assert!(none.allows_tool("builtin-note_search"));
+        assert!(none.allows_tool("builtin-memory_write"));
+        assert!(none.allows_tool("web_search"));
+        assert!(ContextSourceToggles::default().allows_tool("builtin-rag_search"));
+    }
+}
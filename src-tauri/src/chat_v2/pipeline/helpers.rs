@@ -297,36 +297,25 @@ pub(crate) fn inject_synthetic_load_skills(
     chat_history.insert(1, tool_msg);
 }
 
-/// 启发式估算文本的 token 数量（支持中英混排）
-pub(crate) fn estimate_token_count(text: &str) -> usize {
-    let mut cjk_chars = 0usize;
-    let mut ascii_chars = 0usize;
-    for c in text.chars() {
-        if c.is_ascii() {
-            ascii_chars += 1;
-        } else {
-            cjk_chars += 1;
-        }
-    }
-    let tokens = (cjk_chars as f64 * CHARS_PER_TOKEN_CJK)
-        + (ascii_chars as f64 * CHARS_PER_TOKEN_ASCII);
-    tokens.ceil() as usize
-}
-
 /// 按 token 预算裁剪聊天历史（从最旧消息开始移除）
+///
+/// 计数与 `count_tokens` 命令共用 `llm_manager::tokenizer`，按模型选择编码。
 pub(crate) fn trim_history_by_token_budget(
     history: &mut Vec<LegacyChatMessage>,
     max_tokens: usize,
+    model_id: Option<&str>,
 ) {
+    use crate::llm_manager::tokenizer::token_len;
+
     let mut total_tokens: usize = history
         .iter()
-        .map(|m| estimate_token_count(&m.content))
+        .map(|m| token_len(&m.content, model_id))
         .sum();
 
     let original_len = history.len();
     while total_tokens > max_tokens && history.len() > 2 {
         let removed = history.remove(0);
-        total_tokens = total_tokens.saturating_sub(estimate_token_count(&removed.content));
+        total_tokens = total_tokens.saturating_sub(token_len(&removed.content, model_id));
     }
 
     if history.len() < original_len {
@@ -326,6 +326,7 @@ impl ChatV2Pipeline {
                                 size_bytes: a.size as usize,
                                 text_content,
                                 base64_content,
+                                content_hash: None,
                             }
                         })
                         .collect::<Vec<_>>()
@@ -376,7 +377,12 @@ impl ChatV2Pipeline {
             .context_limit
             .map(|v| (v as usize).min(DEFAULT_MAX_HISTORY_TOKENS))
             .unwrap_or(DEFAULT_MAX_HISTORY_TOKENS);
-        trim_history_by_token_budget(&mut chat_history, max_tokens);
+        // model_id 是 API 配置 ID，需解析为模型名再选择分词编码（与 count_tokens 一致）
+        let tokenizer_model = self
+            .llm_manager
+            .resolve_tokenizer_model(ctx.options.model_id.as_deref())
+            .await;
+        trim_history_by_token_budget(&mut chat_history, max_tokens, tokenizer_model.as_deref());
 
         ctx.chat_history = chat_history;
         Ok(())
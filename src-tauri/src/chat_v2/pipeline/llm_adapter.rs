@@ -118,6 +118,8 @@ pub struct ChatV2LLMAdapter {
     emitter: Arc<ChatV2EventEmitter>,
     message_id: String,
     enable_thinking: bool,
+    /// 是否向前端推送思维链（关闭时仍累积，按策略保存）
+    stream_thinking: bool,
     /// thinking 块 ID（活跃的）
     thinking_block_id: std::sync::Mutex<Option<String>>,
     /// 🔧 修复：已结束的 thinking 块 ID（finalize 后保留，确保 collect_round_blocks 能获取）
@@ -149,11 +151,13 @@ impl ChatV2LLMAdapter {
         emitter: Arc<ChatV2EventEmitter>,
         message_id: String,
         enable_thinking: bool,
+        stream_thinking: bool,
     ) -> Self {
         Self {
             emitter,
             message_id,
             enable_thinking,
+            stream_thinking,
             thinking_block_id: std::sync::Mutex::new(None),
             finalized_thinking_block_id: std::sync::Mutex::new(None),
             content_block_id: std::sync::Mutex::new(None),
@@ -208,18 +212,28 @@ impl ChatV2LLMAdapter {
             .unwrap_or_else(|e| e.into_inner());
         if guard.is_none() {
             let block_id = Self::generate_block_id();
-            self.emitter.emit_start(
-                event_types::THINKING,
-                &self.message_id,
-                Some(&block_id),
-                None,
-                None, // variant_id
-            );
+            if self.stream_thinking {
+                self.emitter.emit_start(
+                    event_types::THINKING,
+                    &self.message_id,
+                    Some(&block_id),
+                    None,
+                    None, // variant_id
+                );
+            }
             *guard = Some(block_id.clone());
         }
         guard.clone()
     }
 
+    /// 关闭思维链推送时只累积内容，不发射 thinking 块增量
+    fn emit_thinking_chunk(&self, block_id: &str, text: &str) {
+        if self.stream_thinking {
+            self.emitter
+                .emit_chunk(event_types::THINKING, block_id, text, None);
+        }
+    }
+
     /// 确保 content 块已启动（必须在 thinking 块之后）
     fn ensure_content_started(&self) -> String {
         // 先结束 thinking 块（如果有）
@@ -257,8 +271,10 @@ impl ChatV2LLMAdapter {
                 .finalized_thinking_block_id
                 .lock()
                 .unwrap_or_else(|e| e.into_inner()) = Some(block_id.clone());
-            self.emitter
-                .emit_end(event_types::THINKING, &block_id, None, None); // variant_id
+            if self.stream_thinking {
+                self.emitter
+                    .emit_end(event_types::THINKING, &block_id, None, None); // variant_id
+            }
         }
     }
 
@@ -311,8 +327,7 @@ impl ChatV2LLMAdapter {
                 guard.push_str(&remaining);
             }
             if let Some(block_id) = self.ensure_thinking_started() {
-                self.emitter
-                    .emit_chunk(event_types::THINKING, &block_id, &remaining, None);
+                self.emit_thinking_chunk(&block_id, &remaining);
             }
         } else if !remaining.is_empty() {
             // 剩余内容属于 content
@@ -567,12 +582,7 @@ impl ChatV2LLMAdapter {
                         }
                         // 发射 thinking chunk
                         if let Some(block_id) = self.ensure_thinking_started() {
-                            self.emitter.emit_chunk(
-                                event_types::THINKING,
-                                &block_id,
-                                &thinking_content,
-                                None,
-                            );
+                            self.emit_thinking_chunk(&block_id, &thinking_content);
                         }
                     }
 
@@ -598,12 +608,7 @@ impl ChatV2LLMAdapter {
                             guard.push_str(&thinking_content);
                         }
                         if let Some(block_id) = self.ensure_thinking_started() {
-                            self.emitter.emit_chunk(
-                                event_types::THINKING,
-                                &block_id,
-                                &thinking_content,
-                                None,
-                            );
+                            self.emit_thinking_chunk(&block_id, &thinking_content);
                         }
                     }
                     return;
@@ -747,8 +752,7 @@ impl LLMStreamHooks for ChatV2LLMAdapter {
         }
 
         if let Some(block_id) = self.ensure_thinking_started() {
-            self.emitter
-                .emit_chunk(event_types::THINKING, &block_id, text, None);
+            self.emit_thinking_chunk(&block_id, text);
         }
     }
 
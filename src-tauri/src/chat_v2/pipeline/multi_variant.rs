@@ -175,6 +175,15 @@ impl ChatV2Pipeline {
             .clone()
             .unwrap_or_else(ChatMessage::generate_id);
 
+        // 回答语言与详略程度对所有变体一致
+        options.response_language = self.resolve_response_language(
+            &session_id,
+            &assistant_message_id,
+            &options,
+            &user_content,
+        );
+        options.verbosity = self.resolve_verbosity(&options);
+
         // === 3. 创建事件发射器 ===
         let emitter = Arc::new(ChatV2EventEmitter::new(window.clone(), session_id.clone()));
 
@@ -293,6 +302,7 @@ impl ChatV2Pipeline {
                     anki_cards: None,
                     usage: None,
                     context_snapshot: None,
+                    lifecycle: None,
                 }),
                 attachments: None,
                 active_variant_id: first_variant_id,
@@ -648,13 +658,22 @@ impl ChatV2Pipeline {
             .context_limit
             .map(|v| (v as usize).min(DEFAULT_MAX_HISTORY_TOKENS))
             .unwrap_or(DEFAULT_MAX_HISTORY_TOKENS);
-        trim_history_by_token_budget(&mut chat_history, max_tokens);
+        let tokenizer_model = self
+            .llm_manager
+            .resolve_tokenizer_model(options.model_id.as_deref())
+            .await;
+        trim_history_by_token_budget(&mut chat_history, max_tokens, tokenizer_model.as_deref());
 
         // 构建当前用户消息
         let current_user_message = self.build_variant_user_message(&user_content, &attachments);
 
         // 创建 LLM 适配器（使用变体的事件发射）
         let enable_thinking = options.enable_thinking.unwrap_or(true);
+        ctx.set_thinking_delivery(
+            self.llm_manager
+                .resolve_thinking_delivery(options.model_id.as_deref(), options.thinking_delivery)
+                .await,
+        );
         let emitter = Arc::new(VariantLLMAdapter::new(Arc::clone(&ctx), enable_thinking));
 
         // 注册 LLM 流式回调 hooks
@@ -719,8 +738,11 @@ impl ChatV2Pipeline {
         // 如需完整的工具调用循环，请使用 execute_single_variant_with_config
         if !disable_tools {
             if let Some(ref tool_schemas) = options.mcp_tool_schemas {
+                let context_sources =
+                    ContextSourceToggles::resolve(&options, self.main_db.as_deref());
                 let mcp_tool_values: Vec<Value> = tool_schemas
                     .iter()
+                    .filter(|tool| context_sources.allows_tool(&tool.name))
                     .map(|tool| {
                         let raw_tool_name = if tool.name.starts_with(BUILTIN_NAMESPACE) {
                             tool.name.clone()
@@ -770,7 +792,11 @@ impl ChatV2Pipeline {
             options.top_p,
             options.frequency_penalty,
             options.presence_penalty,
-            options.max_tokens,
+            crate::response_verbosity::effective_max_tokens(
+                options.max_tokens,
+                options.verbosity,
+                enable_thinking,
+            ),
         );
 
         let call_result =
@@ -851,7 +877,15 @@ impl ChatV2Pipeline {
             .context_limit
             .map(|v| (v as usize).min(DEFAULT_MAX_HISTORY_TOKENS))
             .unwrap_or(DEFAULT_MAX_HISTORY_TOKENS);
-        trim_history_by_token_budget(&mut chat_history, max_tokens_budget);
+        let tokenizer_model = self
+            .llm_manager
+            .resolve_tokenizer_model(options.model_id.as_deref())
+            .await;
+        trim_history_by_token_budget(
+            &mut chat_history,
+            max_tokens_budget,
+            tokenizer_model.as_deref(),
+        );
         let current_user_message = self.build_variant_user_message(&user_content, &attachments);
 
         let enable_thinking = options.enable_thinking.unwrap_or(true);
@@ -866,6 +900,11 @@ impl ChatV2Pipeline {
         let mut messages = chat_history;
         messages.push(current_user_message);
 
+        ctx.set_thinking_delivery(
+            self.llm_manager
+                .resolve_thinking_delivery(options.model_id.as_deref(), options.thinking_delivery)
+                .await,
+        );
         let adapter = Arc::new(VariantLLMAdapter::new(Arc::clone(&ctx), enable_thinking));
         let stream_event = format!("chat_v2_event_{}_{}", session_id, ctx.variant_id());
         self.llm_manager
@@ -908,8 +947,11 @@ impl ChatV2Pipeline {
 
         if !disable_tools {
             if let Some(ref tool_schemas) = options.mcp_tool_schemas {
+                let context_sources =
+                    ContextSourceToggles::resolve(&options, self.main_db.as_deref());
                 let mcp_tool_values: Vec<Value> = tool_schemas
                     .iter()
+                    .filter(|tool| context_sources.allows_tool(&tool.name))
                     .map(|tool| {
                         let raw_tool_name = if tool.name.starts_with(BUILTIN_NAMESPACE) {
                             tool.name.clone()
@@ -981,7 +1023,11 @@ impl ChatV2Pipeline {
                 options.top_p,
                 options.frequency_penalty,
                 options.presence_penalty,
-                options.max_tokens,
+                crate::response_verbosity::effective_max_tokens(
+                    options.max_tokens,
+                    options.verbosity,
+                    enable_thinking,
+                ),
             );
 
             // 使用 tokio::select! 支持取消（与单变体 pipeline 对齐）
@@ -1068,6 +1114,7 @@ impl ChatV2Pipeline {
             let cancel_token = Some(ctx.cancel_token());
             let rag_top_k = options.rag_top_k;
             let rag_enable_reranking = options.rag_enable_reranking;
+            let rag_min_score = options.rag_min_score;
             let tool_results = self
                 .execute_tool_calls(
                     &tool_calls,
@@ -1081,6 +1128,7 @@ impl ChatV2Pipeline {
                     cancel_token,
                     rag_top_k,
                     rag_enable_reranking,
+                    rag_min_score,
                     &variant_tool_name_mapping,
                 )
                 .await?;
@@ -1692,6 +1740,7 @@ impl ChatV2Pipeline {
                                 size_bytes: a.size as usize,
                                 text_content,
                                 base64_content,
+                                content_hash: None,
                             }
                         })
                         .collect::<Vec<_>>()
@@ -1801,6 +1850,7 @@ impl ChatV2Pipeline {
                             .unwrap_or(0),
                         text_content,
                         base64_content: a.base64_content.clone(),
+                        content_hash: None,
                     }
                 })
                 .collect();
@@ -2187,8 +2237,11 @@ impl ChatV2Pipeline {
             );
         }
 
-        // 保存 thinking 块（如果有）
-        if let Some(thinking_block_id) = ctx.get_thinking_block_id() {
+        // 保存 thinking 块（如果有；策略不保存思维链时跳过）
+        if let Some(thinking_block_id) = ctx
+            .get_thinking_block_id()
+            .filter(|_| ctx.thinking_delivery().persists())
+        {
             let thinking_content = ctx.get_accumulated_reasoning();
             let thinking_block = MessageBlock {
                 id: thinking_block_id.clone(),
@@ -2422,8 +2475,11 @@ impl ChatV2Pipeline {
         for ctx in variant_contexts {
             let mut block_index = 0;
 
-            // 保存 thinking 块（如果有）
-            if let Some(thinking_block_id) = ctx.get_thinking_block_id() {
+            // 保存 thinking 块（如果有；策略不保存思维链时跳过）
+            if let Some(thinking_block_id) = ctx
+                .get_thinking_block_id()
+                .filter(|_| ctx.thinking_delivery().persists())
+            {
                 let thinking_content = ctx.get_accumulated_reasoning();
                 let thinking_block = MessageBlock {
                     id: thinking_block_id.clone(),
@@ -2510,6 +2566,8 @@ impl ChatV2Pipeline {
                     "temperature": options.temperature,
                     "maxTokens": options.max_tokens,
                     "enableThinking": options.enable_thinking,
+                    "responseLanguage": options.response_language,
+                    "verbosity": options.verbosity,
                     "multiVariantMode": true,
                 })),
                 sources: if shared_context.has_sources() {
@@ -2529,6 +2587,8 @@ impl ChatV2Pipeline {
                 usage: None,
                 // 🆕 统一上下文注入系统：多变体模式支持 context_snapshot
                 context_snapshot: context_snapshot.clone(),
+                // 多变体模式的取消记录在各变体 status 中
+                lifecycle: None,
             }),
             attachments: None,
             active_variant_id: active_variant_id.map(|s| s.to_string()),
@@ -532,9 +532,9 @@ impl ChatV2Pipeline {
                     ctx.final_content.len(),
                     ctx.final_reasoning.as_ref().map(|r| format!("{}chars", r.len()))
                 );
-                // 1. thinking 块：使用流式过程中创建的块 ID，确保与前端一致
+                // 1. thinking 块：使用流式过程中创建的块 ID，确保与前端一致（策略不保存思维链时跳过）
                 if let Some(ref reasoning) = ctx.final_reasoning {
-                    if !reasoning.is_empty() {
+                    if !reasoning.is_empty() && ctx.thinking_delivery.persists() {
                         let thinking_block_id = ctx
                             .streaming_thinking_block_id
                             .clone()
@@ -731,14 +731,23 @@ impl ChatV2Pipeline {
             blocks_to_save.iter().map(|b| b.id.clone()).collect();
 
         // 构建 chatParams 快照（从 SendOptions 中提取相关参数）
+        // 上下文来源记录的是合并设置默认值后的实际取值
+        let context_sources = ContextSourceToggles::resolve(&ctx.options, self.main_db.as_deref());
         let chat_params_snapshot = json!({
             "modelId": ctx.options.model_id,
             "temperature": ctx.options.temperature,
             "contextLimit": ctx.options.context_limit,
             "maxTokens": ctx.options.max_tokens,
             "enableThinking": ctx.options.enable_thinking,
+            "thinkingDelivery": ctx.options.thinking_delivery,
             "disableTools": ctx.options.disable_tools,
             "model2OverrideId": ctx.options.model2_override_id,
+            "ragEnabled": context_sources.rag,
+            "graphRagEnabled": context_sources.graph,
+            "memoryEnabled": context_sources.memory,
+            "webSearchEnabled": context_sources.web_search,
+            "responseLanguage": ctx.options.response_language,
+            "verbosity": ctx.options.verbosity,
         });
 
         // 构建助手消息元数据
@@ -784,6 +793,11 @@ impl ChatV2Pipeline {
             } else {
                 None
             },
+            // 取消时保存的是部分内容，标记后前端可提示并通过重试覆盖，不会留下孤立消息
+            lifecycle: ctx
+                .cancellation_token()
+                .filter(|token| token.is_cancelled())
+                .map(|_| message_lifecycle::CANCELLED.to_string()),
         };
 
         let assistant_message = ChatMessage {
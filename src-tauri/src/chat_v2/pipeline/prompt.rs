@@ -20,6 +20,48 @@ impl ChatV2Pipeline {
         )
     }
 
+    /// 解析本轮回答语言
+    ///
+    /// 优先级：本次请求 `response_language` > 会话最近一条助手消息记录的
+    /// `chatParams.responseLanguage` > 设置项 `chat.response_language`；`auto` 按用户问题推断。
+    /// `exclude_message_id` 为当前助手消息（重试时其旧记录不应参与判断）。
+    pub(crate) fn resolve_response_language(
+        &self,
+        session_id: &str,
+        exclude_message_id: &str,
+        options: &SendOptions,
+        question: &str,
+    ) -> Option<String> {
+        let recorded = self.db.get_conn_safe().ok().and_then(|conn| {
+            recorded_response_language(&conn, session_id, Some(exclude_message_id))
+        });
+        let setting = self
+            .main_db
+            .as_deref()
+            .and_then(crate::response_language::load_response_language_setting);
+        crate::response_language::resolve_response_language(
+            options.response_language.as_deref(),
+            recorded.as_deref(),
+            setting.as_deref(),
+            question,
+        )
+    }
+
+    /// 解析本轮回答详略程度：本次请求 `verbosity` > 设置项 `chat.verbosity`
+    pub(crate) fn resolve_verbosity(
+        &self,
+        options: &SendOptions,
+    ) -> Option<crate::response_verbosity::Verbosity> {
+        options.verbosity.or_else(|| {
+            self.main_db.as_deref().and_then(|db| {
+                crate::response_verbosity::load_verbosity_setting(
+                    db,
+                    crate::response_verbosity::CHAT_VERBOSITY_SETTING_KEY,
+                )
+            })
+        })
+    }
+
     /// 从 MemoryService 读取用户画像 + 分类摘要（双模检索的 LLM 直读模式）
     ///
     /// 受 memU dual-mode retrieval 启发：
@@ -211,3 +253,35 @@ impl ChatV2Pipeline {
         }
     }
 }
+
+/// 会话最近一条助手消息记录的回答语言（`chatParams.responseLanguage`）
+pub(crate) fn recorded_response_language(
+    conn: &rusqlite::Connection,
+    session_id: &str,
+    exclude_message_id: Option<&str>,
+) -> Option<String> {
+    let messages = match ChatV2Repo::get_session_messages_with_conn(conn, session_id) {
+        Ok(messages) => messages,
+        Err(e) => {
+            log::debug!(
+                "[ChatV2::pipeline] Failed to load session language for {}: {}",
+                session_id,
+                e
+            );
+            return None;
+        }
+    };
+    messages
+        .iter()
+        .rev()
+        .filter(|m| m.role == MessageRole::Assistant && Some(m.id.as_str()) != exclude_message_id)
+        .find_map(|m| {
+            m.meta
+                .as_ref()?
+                .chat_params
+                .as_ref()?
+                .get("responseLanguage")?
+                .as_str()
+                .map(str::to_string)
+        })
+}
@@ -98,6 +98,7 @@ impl ChatV2Pipeline {
             resource_types,
             modality: MODALITY_TEXT.to_string(),
             top_k,
+            folder_weights: None,
         };
 
         // 执行搜索（30秒超时保护，防止 LanceDB 或 reranker 挂起）
@@ -134,6 +135,7 @@ impl ChatV2Pipeline {
                             "sourceType": "vfs_rag",
                             "pageIndex": r.page_index,
                             "sourceId": r.source_id,
+                            "folderId": r.folder_id,
                         })),
                     })
                     .collect();
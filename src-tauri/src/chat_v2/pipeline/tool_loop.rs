@@ -167,6 +167,7 @@ impl ChatV2Pipeline {
             emitter.clone(),
             ctx.assistant_message_id.clone(),
             enable_thinking,
+            ctx.thinking_delivery.streams(),
         ));
 
         // 🔧 修复：存储 adapter 引用到 ctx，确保取消时可以获取已累积内容
@@ -420,10 +421,15 @@ impl ChatV2Pipeline {
                     blacklist
                 );
 
+                // 本轮关闭的上下文来源不注入对应的内置检索工具
+                let context_sources =
+                    ContextSourceToggles::resolve(&ctx.options, self.main_db.as_deref());
+
                 // 将前端传递的 MCP 工具 Schema 转换为 LLM 可用的格式
                 // 🔧 P1-49: 应用 whitelist/blacklist 过滤
                 let mcp_tool_values: Vec<Value> = tool_schemas
                     .iter()
+                    .filter(|tool| context_sources.allows_tool(&tool.name))
                     .filter(|tool| {
                         // builtin- 前缀的工具不受策略过滤影响
                         if tool.name.starts_with(BUILTIN_NAMESPACE) {
@@ -533,7 +539,12 @@ impl ChatV2Pipeline {
         let top_p_override = ctx.options.top_p;
         let frequency_penalty_override = ctx.options.frequency_penalty;
         let presence_penalty_override = ctx.options.presence_penalty;
-        let max_tokens_override = ctx.options.max_tokens;
+        // 未显式设置时按详略程度附加输出上限
+        let max_tokens_override = crate::response_verbosity::effective_max_tokens(
+            ctx.options.max_tokens,
+            ctx.options.verbosity,
+            enable_thinking,
+        );
         // 🔧 P1修复：将 context_limit 作为 max_input_tokens_override 传递给 LLM
         let max_input_tokens_override = ctx.options.context_limit.map(|v| v as usize);
         // 🔧 P2修复：始终使用 prompt_builder 生成的 system_prompt（XML 格式）
@@ -725,6 +736,7 @@ impl ChatV2Pipeline {
                     None, // reasoning_tokens - adapter 层面已单独处理
                     None, // cached_tokens
                     Some(ctx.session_id.clone()),
+                    model_override.clone(),
                     None, // duration_ms - 在 adapter 层面已记录
                     true,
                     None,
@@ -750,6 +762,7 @@ impl ChatV2Pipeline {
                     None,
                     None,
                     Some(ctx.session_id.clone()),
+                    model_override.clone(),
                     None,
                     false,
                     Some(e.to_string()),
@@ -834,6 +847,7 @@ impl ChatV2Pipeline {
             let active_skill_ids = ctx.options.active_skill_ids.clone();
             let rag_top_k = ctx.options.rag_top_k;
             let rag_enable_reranking = ctx.options.rag_enable_reranking;
+            let rag_min_score = ctx.options.rag_min_score;
             // 🆕 取消支持：传递取消令牌给工具执行器
             let cancel_token = ctx.cancellation_token();
             let tool_results = self
@@ -849,6 +863,7 @@ impl ChatV2Pipeline {
                     cancel_token,
                     rag_top_k,
                     rag_enable_reranking,
+                    rag_min_score,
                     &mcp_tool_name_mapping,
                 )
                 .await?;
@@ -1284,6 +1299,7 @@ impl ChatV2Pipeline {
         cancellation_token: Option<&CancellationToken>,
         rag_top_k: Option<u32>,
         rag_enable_reranking: Option<bool>,
+        rag_min_score: Option<f32>,
         tool_name_mapping: &HashMap<String, String>,
     ) -> ChatV2Result<Vec<ToolResultInfo>> {
         // 🔧 反向映射：LLM 返回的 sanitized 工具名 → 原始名（含 `:` 等特殊字符）
@@ -1419,6 +1435,7 @@ impl ChatV2Pipeline {
                     cancellation_token.cloned(),
                     rag_top_k,
                     rag_enable_reranking,
+                    rag_min_score,
                 )
                 .await
             {
@@ -1618,6 +1635,7 @@ impl ChatV2Pipeline {
         cancellation_token: Option<CancellationToken>,
         rag_top_k: Option<u32>,
         rag_enable_reranking: Option<bool>,
+        rag_min_score: Option<f32>,
     ) -> ChatV2Result<ToolResultInfo> {
         let block_id = MessageBlock::generate_id();
 
@@ -1880,7 +1898,7 @@ impl ChatV2Pipeline {
         .with_chat_v2_db(Some(self.db.clone())) // 🆕 工具块防闪退保存
         .with_question_bank_service(self.question_bank_service.clone()) // 🆕 智能题目集工具
         .with_pdf_processing_service(self.pdf_processing_service.clone()) // 🆕 论文保存触发 Pipeline
-        .with_rag_config(rag_top_k, rag_enable_reranking);
+        .with_rag_config(rag_top_k, rag_enable_reranking, rag_min_score);
 
         // 🆕 渐进披露：传递 skill_contents
         ctx.skill_contents = skill_contents.clone();
@@ -7,6 +7,8 @@ use super::*;
 pub(crate) struct VariantLLMAdapter {
     ctx: Arc<super::super::variant_context::VariantExecutionContext>,
     enable_thinking: bool,
+    /// 是否向前端推送思维链（关闭时仍累积，按策略保存）
+    stream_thinking: bool,
     content_block_initialized: Mutex<bool>,
     thinking_block_initialized: Mutex<bool>,
     finalized_thinking_block_id: Mutex<Option<String>>,
@@ -25,9 +27,11 @@ impl VariantLLMAdapter {
         ctx: Arc<super::super::variant_context::VariantExecutionContext>,
         enable_thinking: bool,
     ) -> Self {
+        let stream_thinking = ctx.thinking_delivery().streams();
         Self {
             ctx,
             enable_thinking,
+            stream_thinking,
             content_block_initialized: Mutex::new(false),
             thinking_block_initialized: Mutex::new(false),
             finalized_thinking_block_id: Mutex::new(None),
@@ -38,6 +42,13 @@ impl VariantLLMAdapter {
         }
     }
 
+    /// 关闭思维链推送时只累积内容，不发射 thinking 块增量
+    fn emit_thinking_chunk(&self, block_id: &str, text: &str) {
+        if self.stream_thinking {
+            self.ctx.emit_chunk(event_types::THINKING, block_id, text);
+        }
+    }
+
     fn finalize_thinking(&self) {
         let mut initialized = self
             .thinking_block_initialized
@@ -49,7 +60,9 @@ impl VariantLLMAdapter {
                     .finalized_thinking_block_id
                     .lock()
                     .unwrap_or_else(|e| e.into_inner()) = Some(block_id.clone());
-                self.ctx.emit_end(event_types::THINKING, &block_id, None);
+                if self.stream_thinking {
+                    self.ctx.emit_end(event_types::THINKING, &block_id, None);
+                }
             }
             *initialized = false;
         }
@@ -93,8 +106,7 @@ impl VariantLLMAdapter {
             );
             self.ctx.append_reasoning(&remaining);
             if let Some(block_id) = self.ctx.get_thinking_block_id() {
-                self.ctx
-                    .emit_chunk(event_types::THINKING, &block_id, &remaining);
+                self.emit_thinking_chunk(&block_id, &remaining);
             }
         } else if !remaining.is_empty() {
             // 剩余内容属于 content
@@ -119,7 +131,9 @@ impl VariantLLMAdapter {
         if !*initialized {
             let block_id = MessageBlock::generate_id();
             self.ctx.set_thinking_block_id(&block_id);
-            self.ctx.emit_start(event_types::THINKING, &block_id, None);
+            if self.stream_thinking {
+                self.ctx.emit_start(event_types::THINKING, &block_id, None);
+            }
             *initialized = true;
         }
         drop(initialized);
@@ -193,11 +207,7 @@ impl VariantLLMAdapter {
                         self.ctx.append_reasoning(&thinking_content);
                         // 发射 thinking chunk
                         if let Some(block_id) = self.ensure_thinking_started_for_tag() {
-                            self.ctx.emit_chunk(
-                                event_types::THINKING,
-                                &block_id,
-                                &thinking_content,
-                            );
+                            self.emit_thinking_chunk(&block_id, &thinking_content);
                         }
                     }
 
@@ -217,11 +227,7 @@ impl VariantLLMAdapter {
                     if !thinking_content.is_empty() && self.enable_thinking {
                         self.ctx.append_reasoning(&thinking_content);
                         if let Some(block_id) = self.ensure_thinking_started_for_tag() {
-                            self.ctx.emit_chunk(
-                                event_types::THINKING,
-                                &block_id,
-                                &thinking_content,
-                            );
+                            self.emit_thinking_chunk(&block_id, &thinking_content);
                         }
                     }
                     return;
@@ -382,13 +388,15 @@ impl crate::llm_manager::LLMStreamHooks for VariantLLMAdapter {
         if !*initialized {
             let block_id = MessageBlock::generate_id();
             self.ctx.set_thinking_block_id(&block_id);
-            self.ctx.emit_start(event_types::THINKING, &block_id, None);
+            if self.stream_thinking {
+                self.ctx.emit_start(event_types::THINKING, &block_id, None);
+            }
             *initialized = true;
         }
         drop(initialized);
 
         if let Some(block_id) = self.ctx.get_thinking_block_id() {
-            self.ctx.emit_chunk(event_types::THINKING, &block_id, text);
+            self.emit_thinking_chunk(&block_id, text);
             self.ctx.append_reasoning(text);
         }
     }
@@ -393,6 +393,7 @@ fn test_assistant_message_meta_with_sources() {
         anki_cards: None,
         usage: None,
         context_snapshot: None,
+        lifecycle: None,
     };
 
     assert!(meta.sources.is_some());
@@ -427,6 +428,7 @@ fn test_assistant_message_meta_with_tool_results() {
         anki_cards: None,
         usage: None,
         context_snapshot: None,
+        lifecycle: None,
     };
 
     assert!(meta.tool_results.is_some());
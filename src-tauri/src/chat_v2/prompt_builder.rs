@@ -30,6 +30,7 @@
 
 use super::types::{MessageSources, SendOptions, SharedContext, SourceInfo};
 use super::vfs_resolver::escape_xml_content;
+use crate::response_verbosity::Verbosity;
 
 // ============================================================================
 // 常量定义
@@ -389,6 +390,10 @@ pub struct PromptBuilder {
     context_type_hints: Vec<String>,
     /// 用户画像摘要（始终注入，不依赖 query 匹配）
     user_profile: Option<String>,
+    /// 回答语言（已解析的语言代码）
+    response_language: Option<String>,
+    /// 回答详略程度
+    verbosity: Option<Verbosity>,
 }
 
 impl PromptBuilder {
@@ -410,6 +415,8 @@ impl PromptBuilder {
             canvas_note: None,
             context_type_hints: Vec::new(),
             user_profile: None,
+            response_language: None,
+            verbosity: None,
         }
     }
 
@@ -486,6 +493,20 @@ impl PromptBuilder {
         self
     }
 
+    /// 设置回答语言（`auto` 应在调用前解析为具体语言）
+    pub fn with_response_language(mut self, language: Option<&str>) -> Self {
+        self.response_language = language
+            .and_then(crate::response_language::normalize_language)
+            .filter(|l| l != crate::response_language::AUTO_LANGUAGE);
+        self
+    }
+
+    /// 设置回答详略程度
+    pub fn with_verbosity(mut self, verbosity: Option<Verbosity>) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
     /// 从 MessageSources 添加所有来源
     /// ★ 2026-01 清理：移除 graph 来源（错题系统废弃）
     pub fn with_message_sources(self, sources: &MessageSources) -> Self {
@@ -506,6 +527,8 @@ impl PromptBuilder {
     pub fn with_options(self, options: &SendOptions) -> Self {
         self.with_user_append(options.system_prompt_append.as_deref())
             .with_context_type_hints(options.context_type_hints.as_ref())
+            .with_response_language(options.response_language.as_deref())
+            .with_verbosity(options.verbosity)
     }
 
     /// 添加上下文类型 Hints
@@ -584,6 +607,18 @@ impl PromptBuilder {
             ));
         }
 
+        // 4.5 回答语言（与界面语言无关）
+        if let Some(language) = self.response_language {
+            parts.push(crate::response_language::response_language_instruction(
+                &language,
+            ));
+        }
+
+        // 4.6 回答详略程度
+        if let Some(verbosity) = self.verbosity {
+            parts.push(verbosity.chat_instruction());
+        }
+
         // 5. Canvas 笔记块（如果有）
         // 实现长短笔记策略：短笔记（<3000字）全量注入，长笔记仅注入摘要
         if let Some(note) = self.canvas_note {
@@ -848,4 +883,35 @@ mod tests {
         assert!(instructions_pos < context_pos);
         assert!(context_pos < prefs_pos);
     }
+
+    #[test]
+    fn test_response_language_injected_only_when_resolved() {
+        let options = SendOptions {
+            response_language: Some("English".to_string()),
+            ..Default::default()
+        };
+        let prompt = PromptBuilder::new(None).with_options(&options).build();
+        assert!(prompt.contains("<response_language>"));
+        assert!(prompt.contains("English"));
+
+        let unresolved = PromptBuilder::new(None)
+            .with_response_language(Some("auto"))
+            .build();
+        assert!(!unresolved.contains("<response_language>"));
+        let default_prompt = PromptBuilder::new(None).build();
+        assert!(!default_prompt.contains("<response_language>"));
+    }
+
+    #[test]
+    fn test_verbosity_instruction_follows_options() {
+        let options = SendOptions {
+            verbosity: Some(Verbosity::Concise),
+            ..Default::default()
+        };
+        let prompt = PromptBuilder::new(None).with_options(&options).build();
+        assert!(prompt.contains(&Verbosity::Concise.chat_instruction()));
+        assert!(!PromptBuilder::new(None)
+            .build()
+            .contains("<response_verbosity>"));
+    }
 }
@@ -919,6 +919,9 @@ impl ToolExecutor for AcademicSearchExecutor {
         );
 
         let result = match tool_name {
+            _ if crate::offline_mode::is_enabled() => {
+                Err(crate::offline_mode::offline_message("学术检索"))
+            }
             "arxiv_search" => self.execute_arxiv_search(call, ctx).await,
             "scholar_search" => self.execute_scholar_search(call, ctx).await,
             _ => Err(format!("Unknown academic search tool: {}", tool_name)),
@@ -179,6 +179,7 @@ impl BuiltinRetrievalExecutor {
             resource_types,
             modality: MODALITY_TEXT.to_string(),
             top_k,
+            folder_weights: None,
         };
 
         // 🆕 取消检查：在执行检索前检查
@@ -282,6 +283,7 @@ impl BuiltinRetrievalExecutor {
                             "chunkIndex": r.chunk_index,
                             "embeddingId": r.embedding_id,
                             "pageIndex": r.page_index,
+                            "folderId": r.folder_id,
                             "sourceType": "vfs_rag",
                             "imageUrl": image_url,
                             "imageCitation": image_citation,
@@ -747,6 +749,20 @@ impl BuiltinRetrievalExecutor {
             .get("enable_reranking")
             .and_then(|v| v.as_bool())
             .unwrap_or(true);
+        let retrieval_settings = ctx
+            .main_db
+            .as_ref()
+            .map(|db| crate::rag_settings::load_rag_retrieval_settings(db))
+            .unwrap_or_default();
+        // 最低相似度阈值：工具参数 > 发送选项 > 全局设置
+        let min_score = call
+            .arguments
+            .get("min_score")
+            .and_then(|v| v.as_f64())
+            .map(|v| v as f32)
+            .or(ctx.rag_min_score)
+            .unwrap_or(retrieval_settings.min_score)
+            .clamp(0.0, 1.0);
 
         // 发射 start 事件
         ctx.emitter.emit_start(
@@ -759,6 +775,7 @@ impl BuiltinRetrievalExecutor {
                 "resource_ids": resource_ids,
                 "resource_types": resource_types,
                 "max_per_resource": max_per_resource,
+                "min_score": min_score,
                 "source": "unified_search"
             })),
             None,
@@ -807,6 +824,7 @@ impl BuiltinRetrievalExecutor {
             resource_types: resource_types.clone(),
             modality: MODALITY_TEXT.to_string(),
             top_k: top_k as u32,
+            folder_weights: None,
         };
 
         let text_result = if let Some(cancel_token) = ctx.cancellation_token() {
@@ -821,6 +839,31 @@ impl BuiltinRetrievalExecutor {
             search_service.search_with_embedding(query, &shared_embedding, &text_params, false).await.ok()
         };
 
+        // 查询扩展：逐条检索后与原查询结果合并（未启用时为空，流程不变）
+        let expanded_queries =
+            crate::rag_query_expansion::expand_query(llm_manager, &retrieval_settings, query).await;
+        let text_result = if expanded_queries.is_empty() {
+            text_result
+        } else {
+            let mut result_sets: Vec<_> = text_result.into_iter().collect();
+            for expanded in &expanded_queries {
+                if ctx.is_cancelled() {
+                    return Err("Unified search cancelled during query expansion".to_string());
+                }
+                match search_service.search(expanded, &text_params, false).await {
+                    Ok(results) => result_sets.push(results),
+                    Err(e) => log::warn!(
+                        "[BuiltinRetrievalExecutor] Expanded query '{}' failed: {}",
+                        expanded,
+                        e
+                    ),
+                }
+            }
+            Some(crate::rag_query_expansion::merge_search_results(
+                result_sets,
+            ))
+        };
+
         // 获取记忆文件夹下所有资源 ID 集合，从文本搜索结果中排除（源头去重）。
         // 这比事后跨源去重更可靠：不依赖 sourceId/title 匹配。
         let memory_resource_ids: std::collections::HashSet<String> = {
@@ -1062,6 +1105,18 @@ impl BuiltinRetrievalExecutor {
                     == Some("memory")
             });
 
+        // 3a'. 最低相似度过滤（记忆得分量纲不同，不参与）
+        let candidate_count = kb_sources.len();
+        let below_min_score = drop_below_min_score(&mut kb_sources, min_score);
+        if below_min_score > 0 {
+            log::debug!(
+                "[BuiltinRetrievalExecutor] Dropped {}/{} KB results below min_score={:.3}",
+                below_min_score,
+                candidate_count,
+                min_score
+            );
+        }
+
         // 3b. 跨源去重：从知识库结果中移除与记忆重复的 VFS 笔记
         //     记忆笔记同时被索引在 VFS 中，Step 1 可能返回同一条记忆作为 text_search 结果。
         //     优先使用 noteId 精确匹配，回退到标题匹配。
@@ -1178,6 +1233,9 @@ impl BuiltinRetrievalExecutor {
                 "sources": all_sources,
                 "durationMs": duration,
                 "source": "unified_search",
+                "candidateCount": candidate_count,
+                "minScore": min_score,
+                "expandedQueries": expanded_queries,
             })),
             None,
         );
@@ -1247,10 +1305,32 @@ impl BuiltinRetrievalExecutor {
             }));
         }
 
+        // 没有任何可用上下文时显式告知模型，避免凭空作答
+        if all_sources.is_empty() {
+            return Ok(json!({
+                "success": true,
+                "noRelevantContext": true,
+                "sources": [],
+                "count": 0,
+                "candidateCount": candidate_count,
+                "belowMinScore": below_min_score,
+                "minScore": min_score,
+                "expandedQueries": expanded_queries,
+                "durationMs": duration,
+                "source": "unified_search",
+                "message": "本地知识库中没有找到与问题相关的内容。请如实告知用户未找到相关资料，不要编造引用或内容。",
+            }));
+        }
+
         Ok(json!({
             "success": true,
+            "noRelevantContext": false,
             "sources": numbered_sources,
             "count": all_sources.len(),
+            "candidateCount": candidate_count,
+            "belowMinScore": below_min_score,
+            "minScore": min_score,
+            "expandedQueries": expanded_queries,
             "durationMs": duration,
             "source": "unified_search",
             "citationGuide": "引用方式：[知识库-N]/[图片-N]/[记忆-N]（N 为同类来源编号）显示角标，[知识库-N:图片]/[图片-N:图片] 渲染对应页面图片。结果中 pageIndex 字段不为空时表示有图片可渲染。需要读取完整文档时优先使用 readResourceId 调用 builtin-resource_read。禁止输出 URL 或 Markdown 图片语法。"
@@ -1569,6 +1649,16 @@ fn filter_retrieval_results(
         .collect()
 }
 
+/// 丢弃低于最低相似度的结果（无分数的保留），返回丢弃数量
+fn drop_below_min_score(sources: &mut Vec<SourceInfo>, min_score: f32) -> usize {
+    if min_score <= 0.0 {
+        return 0;
+    }
+    let before = sources.len();
+    sources.retain(|s| s.score.is_none_or(|score| score >= min_score));
+    before - sources.len()
+}
+
 fn should_route_to_unified_search(tool_name: &str) -> bool {
     matches!(
         tool_name,
@@ -1717,6 +1807,32 @@ mod tests {
         assert_eq!(filtered[1].title, Some("Doc2".to_string()));
     }
 
+    #[test]
+    fn test_drop_below_min_score() {
+        let source = |title: &str, score: Option<f32>| SourceInfo {
+            title: Some(title.to_string()),
+            url: None,
+            snippet: None,
+            score,
+            metadata: None,
+        };
+        let mut sources = vec![
+            source("high", Some(0.8)),
+            source("low", Some(0.1)),
+            source("unscored", None),
+        ];
+
+        assert_eq!(drop_below_min_score(&mut sources, 0.0), 0);
+        assert_eq!(sources.len(), 3);
+
+        assert_eq!(drop_below_min_score(&mut sources, 0.5), 1);
+        let titles: Vec<_> = sources.iter().filter_map(|s| s.title.as_deref()).collect();
+        assert_eq!(titles, vec!["high", "unscored"]);
+
+        assert_eq!(drop_below_min_score(&mut sources, 0.9), 1);
+        assert_eq!(sources.len(), 1);
+    }
+
     #[test]
     fn test_route_to_unified_search() {
         assert!(should_route_to_unified_search("rag_search"));
@@ -26,6 +26,7 @@ use tokio::time::{sleep, Duration};
 
 use super::executor::{ExecutionContext, ToolExecutor, ToolSensitivity};
 use super::strip_tool_namespace;
+use crate::anki_field_html;
 use crate::chat_v2::events::event_types;
 use crate::chat_v2::repo::ChatV2Repo;
 use crate::chat_v2::resource_types::ContextRef;
@@ -1395,6 +1396,11 @@ impl ChatAnkiToolExecutor {
                 }
             }
 
+            anki_field_html::apply_to_cards(
+                &mut cards,
+                anki_field_html::load_html_mode(ctx.main_db.as_deref().unwrap_or(&db)),
+            );
+
             // 多模板 APKG 导出：每种 template_id 创建独立的 Anki model，
             // 每张卡片的 notes.mid 指向自己模板对应的 model。
             // Anki 格式支持一个 APKG 内多个 note type（model），字段和 card template 各自独立。
@@ -1647,8 +1653,13 @@ impl ChatAnkiToolExecutor {
             }
         }
 
+        let mut anki_cards = cards.clone();
+        anki_field_html::apply_to_cards(
+            &mut anki_cards,
+            anki_field_html::load_html_mode(ctx.main_db.as_deref().unwrap_or(&db)),
+        );
         let note_ids = match crate::anki_connect_service::add_notes_to_anki_with_card_models(
-            cards.clone(),
+            anki_cards,
             deck_name.clone(),
             note_type.clone(),
             card_note_types,
@@ -2981,6 +2992,11 @@ async fn run_chatanki_pipeline_background(params: BackgroundParams) -> Result<()
         params.max_cards,
     );
 
+    // 与所在会话的回答语言保持一致；会话尚未记录时由制卡服务按设置 / 材料推断
+    options.response_language = params.chat_db.get_conn_safe().ok().and_then(|conn| {
+        crate::chat_v2::pipeline::recorded_response_language(&conn, &params.session_id, None)
+    });
+
     // 多模板模式：使用启动阶段已校验过的 template_ids，避免隐式“全模板”导致体验偏差。
     if template.is_none() {
         if let Some(template_ids) = params.template_ids.as_ref() {
@@ -3079,6 +3095,7 @@ async fn run_chatanki_pipeline_background(params: BackgroundParams) -> Result<()
         document_content: content_text,
         original_document_name: Some(doc_name),
         options: Some(options),
+        subject: None,
     };
 
     // 使用预分配的 document_id，确保与 tool output 中的 ID 一致
@@ -4372,6 +4389,8 @@ fn build_generation_options(
         // ChatAnki 的 maxCards 语义是“整次制卡总上限”，不是“每段上限”。
         // 分段后会在 DocumentProcessingService 内按段分配，避免 10 -> 20 的放大。
         max_cards_total: max_cards_override,
+        max_cards_per_segment: None,
+        max_cards_per_document: None,
         max_tokens: None,
         temperature: Some(if glossary_mode { 0.2 } else { 0.3 }),
         max_output_tokens_override: if glossary_mode { Some(2400) } else { None },
@@ -4389,6 +4408,13 @@ fn build_generation_options(
         template_ids: None,
         template_descriptions: None,
         enable_llm_boundary_detection: Some(true),
+        response_language: None,
+        output_mode: None,
+        subject: None,
+        source_tag_facets: None,
+        source_tag: None,
+        html_mode: None,
+        verbosity: None,
     }
 }
 
@@ -108,6 +108,8 @@ pub struct ExecutionContext {
     pub rag_top_k: Option<u32>,
     /// 🆕 RAG 启用重排序设置（从 UI chatParams 传递）
     pub rag_enable_reranking: Option<bool>,
+    /// RAG 最低相似度阈值覆盖（从 SendOptions 传递）
+    pub rag_min_score: Option<f32>,
     /// 🆕 PDF 处理服务（用于论文保存后触发 OCR/压缩 Pipeline）
     pub pdf_processing_service: Option<Arc<PdfProcessingService>>,
 }
@@ -143,6 +145,7 @@ impl ExecutionContext {
             cancellation_token: None,
             rag_top_k: None,
             rag_enable_reranking: None,
+            rag_min_score: None,
             pdf_processing_service: None,
         }
     }
@@ -373,9 +376,15 @@ impl ExecutionContext {
         Ok(())
     }
 
-    pub fn with_rag_config(mut self, top_k: Option<u32>, enable_reranking: Option<bool>) -> Self {
+    pub fn with_rag_config(
+        mut self,
+        top_k: Option<u32>,
+        enable_reranking: Option<bool>,
+        min_score: Option<f32>,
+    ) -> Self {
         self.rag_top_k = top_k;
         self.rag_enable_reranking = enable_reranking;
+        self.rag_min_score = min_score;
         self
     }
 }
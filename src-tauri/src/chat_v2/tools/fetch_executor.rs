@@ -386,6 +386,9 @@ impl FetchExecutor {
         if ctx.is_cancelled() {
             return Err("Fetch cancelled before start".to_string());
         }
+        if crate::offline_mode::is_enabled() {
+            return Err(crate::offline_mode::offline_message("网页抓取"));
+        }
 
         // 解析参数
         let url = call
@@ -1219,6 +1219,9 @@ impl ToolExecutor for PaperSaveExecutor {
         );
 
         let result = match tool_name {
+            "paper_save" if crate::offline_mode::is_enabled() => {
+                Err(crate::offline_mode::offline_message("论文下载"))
+            }
             "paper_save" => self.execute_paper_save(call, ctx).await,
             "cite_format" => self.execute_cite_format(call),
             _ => Err(format!("Unknown paper tool: {}", tool_name)),
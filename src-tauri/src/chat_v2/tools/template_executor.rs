@@ -666,6 +666,11 @@ impl TemplateDesignerExecutor {
         if req.generation_prompt.trim().is_empty() {
             errors.push("generationPrompt 不能为空。请提供 AI 生成卡片的指导提示词".to_string());
         }
+        if let Err(e) =
+            crate::anki_prompt_placeholders::validate_prompt_placeholders(&req.generation_prompt)
+        {
+            errors.push(e.message);
+        }
 
         // 字段与 field_extraction_rules 一致性
         let field_set: HashSet<&String> = req.fields.iter().collect();
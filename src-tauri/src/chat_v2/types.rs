@@ -128,6 +128,12 @@ pub mod variant_status {
     pub const INTERRUPTED: &str = "interrupted";
 }
 
+/// 消息生命周期常量（写入 `MessageMeta.lifecycle`，与前端 MessageMeta 对齐）
+pub mod message_lifecycle {
+    /// 被用户取消：内容可能不完整，重试该消息会覆盖并清除此标记
+    pub const CANCELLED: &str = "cancelled";
+}
+
 // ============================================================================
 // Token 统计类型
 // ============================================================================
@@ -1029,6 +1035,10 @@ pub struct MessageMeta {
     /// 记录消息发送时的上下文引用，只存 ContextRef 不存实际内容
     #[serde(skip_serializing_if = "Option::is_none")]
     pub context_snapshot: Option<ContextSnapshot>,
+
+    /// 消息生命周期（见 `message_lifecycle`），None 表示正常完成
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lifecycle: Option<String>,
 }
 
 impl Default for MessageMeta {
@@ -1041,6 +1051,7 @@ impl Default for MessageMeta {
             anki_cards: None,
             usage: None,
             context_snapshot: None,
+            lifecycle: None,
         }
     }
 }
@@ -1614,6 +1625,10 @@ pub struct SendOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enable_thinking: Option<bool>,
 
+    /// 本次请求的思维链推送/保存策略（覆盖模型配置与全局设置）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking_delivery: Option<crate::llm_manager::ThinkingDelivery>,
+
     /// 禁用工具调用
     #[serde(skip_serializing_if = "Option::is_none")]
     pub disable_tools: Option<bool>,
@@ -1639,6 +1654,10 @@ pub struct SendOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rag_enable_reranking: Option<bool>,
 
+    /// RAG 最低相似度阈值（覆盖全局设置 `rag.retrieval.config`）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rag_min_score: Option<f32>,
+
     /// 启用知识图谱 RAG
     #[serde(skip_serializing_if = "Option::is_none")]
     pub graph_rag_enabled: Option<bool>,
@@ -1722,6 +1741,20 @@ pub struct SendOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system_prompt_append: Option<String>,
 
+    /// 本轮回答语言覆盖（语言代码或 `auto`），与界面语言无关
+    ///
+    /// 未指定时沿用会话已记录的语言，再回退到设置项 `chat.response_language`；
+    /// Pipeline 解析后写回实际使用的语言，并记入助手消息的 `chatParams.responseLanguage`。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_language: Option<String>,
+
+    /// 本轮回答详略程度覆盖（`concise` / `balanced` / `detailed`）
+    ///
+    /// 未指定时使用设置项 `chat.verbosity`；Pipeline 解析后写回，并记入助手消息的
+    /// `chatParams.verbosity`。未显式设置 `max_tokens` 时“简洁”另附输出上限。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbosity: Option<crate::response_verbosity::Verbosity>,
+
     // ========== 内部控制选项 ==========
     /// 跳过用户消息保存（编辑重发场景使用）
     /// 当为 true 时，Pipeline 不会创建新的用户消息，仅创建助手消息
@@ -2183,6 +2216,7 @@ mod tests {
                 anki_cards: None,
                 usage: None,
                 context_snapshot: None,
+                lifecycle: None,
             }),
             attachments: None,
             active_variant_id: None,
@@ -3077,6 +3111,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_message_meta_lifecycle_roundtrip() {
+        let meta = MessageMeta {
+            lifecycle: Some(message_lifecycle::CANCELLED.to_string()),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&meta).unwrap();
+        assert!(
+            json.contains("\"lifecycle\":\"cancelled\""),
+            "got: {}",
+            json
+        );
+
+        // 旧数据没有 lifecycle 字段，反序列化为 None 且不会被序列化
+        let legacy: MessageMeta = serde_json::from_str(r#"{"modelId":"gpt-4"}"#).unwrap();
+        assert!(legacy.lifecycle.is_none());
+        assert!(!serde_json::to_string(&legacy)
+            .unwrap()
+            .contains("lifecycle"));
+    }
+
     #[test]
     fn test_variant_with_usage_builder() {
         let usage = TokenUsage::from_api(1000, 500, None);
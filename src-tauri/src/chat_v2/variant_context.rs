@@ -17,6 +17,7 @@ use crate::chat_v2::types::{
     MessageBlock, SharedContext, TokenUsage, ToolCall, ToolResultInfo, Variant,
 };
 use crate::chat_v2::variant_status;
+use crate::llm_manager::ThinkingDelivery;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
@@ -133,6 +134,9 @@ pub struct VariantExecutionContext {
 
     /// 待回传给 LLM 的 reasoning_content（DeepSeek Thinking Mode）
     pending_reasoning_for_api: Mutex<Option<String>>,
+
+    /// 思维链推送/保存策略（按变体模型解析）
+    thinking_delivery: Mutex<ThinkingDelivery>,
 }
 
 impl VariantExecutionContext {
@@ -195,6 +199,7 @@ impl VariantExecutionContext {
             interleaved_block_ids: Mutex::new(Vec::new()),
             interleaved_blocks: Mutex::new(Vec::new()),
             pending_reasoning_for_api: Mutex::new(None),
+            thinking_delivery: Mutex::new(ThinkingDelivery::default()),
         }
     }
 
@@ -211,6 +216,21 @@ impl VariantExecutionContext {
             .clone()
     }
 
+    /// 设置思维链推送/保存策略（需在创建 VariantLLMAdapter 之前调用）
+    pub fn set_thinking_delivery(&self, delivery: ThinkingDelivery) {
+        *self
+            .thinking_delivery
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = delivery;
+    }
+
+    pub fn thinking_delivery(&self) -> ThinkingDelivery {
+        *self
+            .thinking_delivery
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
     // ========== Getter 方法 ==========
 
     /// 获取变体 ID
@@ -409,12 +429,12 @@ impl VariantExecutionContext {
 
     /// 设置 thinking block ID
     ///
-    /// 注意：如果 block_id 已在 block_ids 中，不会重复添加
+    /// 注意：如果 block_id 已在 block_ids 中，不会重复添加；思维链不保存时不计入 block_ids
     pub fn set_thinking_block_id(&self, block_id: impl Into<String>) {
         let block_id = block_id.into();
         // 检查是否已存在，避免重复添加
         let mut block_ids = self.block_ids.lock().unwrap_or_else(|e| e.into_inner());
-        if !block_ids.contains(&block_id) {
+        if self.thinking_delivery().persists() && !block_ids.contains(&block_id) {
             block_ids.push(block_id.clone());
         }
         drop(block_ids);
@@ -109,8 +109,21 @@ pub struct CloudStorageConfig {
     /// 根目录路径（所有操作都在此目录下）
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub root: Option<String>,
+    /// 同步上传的分块大小（MB），压缩后超过一个分块的变更按分块续传上传
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_chunk_size_mb: Option<u32>,
+    /// 同时上传的分块数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_uploads: Option<u32>,
 }
 
+/// 同步上传分块大小默认值与范围（MB）
+const DEFAULT_UPLOAD_CHUNK_SIZE_MB: u32 = 4;
+const MAX_UPLOAD_CHUNK_SIZE_MB: u32 = 64;
+/// 并发上传分块数默认值与上限
+const DEFAULT_MAX_CONCURRENT_UPLOADS: u32 = 3;
+const MAX_CONCURRENT_UPLOADS_LIMIT: u32 = 8;
+
 impl CloudStorageConfig {
     /// 获取根目录路径，默认为 "deep-student-sync"
     pub fn root(&self) -> String {
@@ -122,6 +135,30 @@ impl CloudStorageConfig {
             .to_string()
     }
 
+    /// 同步上传分块大小（字节），限制在 1~64MB
+    pub fn upload_chunk_size(&self) -> usize {
+        let mb = self
+            .upload_chunk_size_mb
+            .unwrap_or(DEFAULT_UPLOAD_CHUNK_SIZE_MB)
+            .clamp(1, MAX_UPLOAD_CHUNK_SIZE_MB);
+        mb as usize * 1024 * 1024
+    }
+
+    /// 同时上传的分块数，限制在 1~8
+    pub fn max_concurrent_uploads(&self) -> usize {
+        self.max_concurrent_uploads
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_UPLOADS)
+            .clamp(1, MAX_CONCURRENT_UPLOADS_LIMIT) as usize
+    }
+
+    /// 当前提供商的服务地址
+    pub fn endpoint(&self) -> Option<&str> {
+        match self.provider {
+            StorageProvider::WebDav => self.webdav.as_ref().map(|c| c.endpoint.as_str()),
+            StorageProvider::S3 => self.s3.as_ref().map(|c| c.endpoint.as_str()),
+        }
+    }
+
     /// 通过 URL 解析精确判断 endpoint 是否为本地地址。
     ///
     /// 使用 `url::Url` 解析后对 host 做精确匹配，
@@ -306,6 +343,21 @@ mod tests {
         assert_eq!(config.root(), "custom/path");
     }
 
+    #[test]
+    fn test_upload_chunk_options_are_clamped() {
+        let config = CloudStorageConfig::default();
+        assert_eq!(config.upload_chunk_size(), 4 * 1024 * 1024);
+        assert_eq!(config.max_concurrent_uploads(), 3);
+
+        let config = CloudStorageConfig {
+            upload_chunk_size_mb: Some(0),
+            max_concurrent_uploads: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(config.upload_chunk_size(), 1024 * 1024);
+        assert_eq!(config.max_concurrent_uploads(), 8);
+    }
+
     #[test]
     fn test_is_local_endpoint() {
         assert!(CloudStorageConfig::is_local_endpoint("http://localhost:8080/dav"));
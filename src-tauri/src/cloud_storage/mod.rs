@@ -77,6 +77,9 @@ pub async fn create_storage(config: &CloudStorageConfig) -> Result<Box<dyn Cloud
     // 验证配置
     config.validate().map_err(|e| AppError::validation(e))?;
 
+    // 离线模式下只允许连接本机的存储服务
+    crate::offline_mode::ensure_endpoint_allowed("云同步", config.endpoint().unwrap_or_default())?;
+
     let root = config.root();
 
     match config.provider {
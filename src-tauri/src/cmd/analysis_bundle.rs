@@ -0,0 +1,445 @@
+//! 分析结果一次性保存命令
+//!
+//! 常见的录题流程是“分析 → 保存错题 → 制卡”。分步调用时中途失败会留下只有错题、
+//! 没有对话，或有卡片却找不到来源的半成品。`save_analysis_bundle` 把这几步合为一次保存：
+//! - 需要联网的制卡先完成（暂存在内存中），失败时直接返回，数据库不做任何改动；
+//! - 错题、对话消息、制卡任务与卡片在同一个 SQLite 事务中写入，任一步出错整体回滚。
+//!
+//! 卡片以 `source_type = 'mistake'`、`source_id = 错题 ID` 记录来源，挂在一条已完成的
+//! 制卡任务下，可在卡片库中按来源查看。
+
+use crate::commands::{resolve_generation_template, AppState};
+use crate::database::{has_column, Database};
+use crate::models::{AnkiCard, AnkiGenerationOptions, AppError, ChatMessage};
+use rusqlite::{params, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 送入制卡模型的对话内容最大字符数
+const MAX_CARD_SOURCE_CHARS: usize = 12_000;
+/// 卡片来源类型
+const CARD_SOURCE_TYPE: &str = "mistake";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnalysisBundleMistake {
+    /// 前端预先生成的错题 ID（如分析会话 ID）；为空时自动生成
+    pub id: Option<String>,
+    pub subject: Option<String>,
+    pub user_question: String,
+    pub ocr_text: String,
+    /// 已保存到受管目录的题目图片路径
+    pub question_images: Vec<String>,
+    pub tags: Vec<String>,
+    pub mistake_type: Option<String>,
+    /// OCR 识别出的题目语言
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveAnalysisBundleRequest {
+    pub mistake: AnalysisBundleMistake,
+    #[serde(default)]
+    pub chat_history: Vec<ChatMessage>,
+    /// 是否根据题目与分析对话生成卡片
+    #[serde(default)]
+    pub generate_cards: bool,
+    #[serde(default)]
+    pub card_options: Option<AnkiGenerationOptions>,
+    /// 前端已生成、需一并保存的卡片
+    #[serde(default)]
+    pub cards: Vec<AnkiCard>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveAnalysisBundleResult {
+    pub mistake_id: String,
+    /// 新写入的用户消息 ID（chat_messages.id）
+    pub user_message_ids: Vec<i64>,
+    pub chat_message_count: usize,
+    /// 卡片所属的制卡任务；未保存卡片时为 None
+    pub task_id: Option<String>,
+    /// 实际写入的卡片 ID（与已有卡片内容重复的被跳过）
+    pub card_ids: Vec<String>,
+}
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// 制卡素材：题目、OCR 文本与模型回答（超长时保留开头）
+fn card_source_text(mistake: &AnalysisBundleMistake, chat_history: &[ChatMessage]) -> String {
+    let mut text = format!(
+        "【题目】\n{}\n{}",
+        mistake.user_question.trim(),
+        mistake.ocr_text.trim()
+    );
+    let replies: Vec<&str> = chat_history
+        .iter()
+        .filter(|m| m.role == "assistant")
+        .map(|m| m.content.trim())
+        .filter(|c| !c.is_empty())
+        .collect();
+    if !replies.is_empty() {
+        text.push_str("\n\n【解析】\n");
+        text.push_str(&replies.join("\n\n"));
+    }
+    if text.chars().count() > MAX_CARD_SOURCE_CHARS {
+        text = text.chars().take(MAX_CARD_SOURCE_CHARS).collect();
+    }
+    text
+}
+
+fn insert_mistake(tx: &Transaction<'_>, id: &str, mistake: &AnalysisBundleMistake) -> Result<()> {
+    let exists = tx
+        .query_row("SELECT 1 FROM mistakes WHERE id = ?1", params![id], |_| {
+            Ok(())
+        })
+        .optional()?
+        .is_some();
+    if exists {
+        return Err(AppError::validation(format!("错题已存在: {}", id)));
+    }
+    let now = chrono::Utc::now().to_rfc3339();
+    tx.execute(
+        "INSERT INTO mistakes (id, created_at, question_images, analysis_images, user_question, \
+                               ocr_text, tags, mistake_type, status, chat_category, updated_at, \
+                               last_accessed_at) \
+         VALUES (?1, ?2, ?3, '[]', ?4, ?5, ?6, ?7, 'active', 'analysis', ?2, ?2)",
+        params![
+            id,
+            now,
+            serde_json::to_string(&mistake.question_images)?,
+            mistake.user_question.trim(),
+            mistake.ocr_text.trim(),
+            serde_json::to_string(&mistake.tags)?,
+            non_empty(mistake.mistake_type.as_deref()).unwrap_or("analysis"),
+        ],
+    )?;
+    // 旧表缺少的列直接跳过
+    for (column, value) in [
+        ("subject", non_empty(mistake.subject.as_deref())),
+        ("language", non_empty(mistake.language.as_deref())),
+    ] {
+        if let Some(value) = value {
+            if has_column(tx, "mistakes", column)? {
+                tx.execute(
+                    &format!("UPDATE mistakes SET {} = ?1 WHERE id = ?2", column),
+                    params![value, id],
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 写入一条已完成的制卡任务及其卡片，返回任务 ID 与实际写入的卡片 ID
+fn insert_cards(
+    tx: &Transaction<'_>,
+    mistake_id: &str,
+    source_text: &str,
+    options: &AnkiGenerationOptions,
+    cards: &[AnkiCard],
+) -> Result<(String, Vec<String>)> {
+    let task_id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    let name: String = source_text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('【'))
+        .unwrap_or(mistake_id)
+        .chars()
+        .take(40)
+        .collect();
+    tx.execute(
+        "INSERT INTO document_tasks (id, document_id, original_document_name, segment_index, \
+                                     content_segment, status, created_at, updated_at, \
+                                     anki_generation_options_json) \
+         VALUES (?1, ?2, ?3, 0, ?4, 'Completed', ?5, ?5, ?6)",
+        params![
+            task_id,
+            mistake_id,
+            name,
+            source_text,
+            now,
+            serde_json::to_string(options)?
+        ],
+    )?;
+
+    let mut card_ids = Vec::with_capacity(cards.len());
+    for (order, card) in cards.iter().enumerate() {
+        // 卡片 ID 可能来自前端预览，重新生成以免与已保存的卡片冲突
+        let card_id = uuid::Uuid::new_v4().to_string();
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO anki_cards
+             (id, task_id, front, back, text, tags_json, images_json, is_error_card,
+              error_content, card_order_in_task, created_at, updated_at, extra_fields_json,
+              template_id, source_type, source_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, NULL, ?8, ?9, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                card_id,
+                task_id,
+                card.front,
+                card.back,
+                card.text,
+                serde_json::to_string(&card.tags)?,
+                serde_json::to_string(&card.images)?,
+                order as i64,
+                now,
+                serde_json::to_string(&card.extra_fields)?,
+                card.template_id,
+                CARD_SOURCE_TYPE,
+                mistake_id,
+            ],
+        )?;
+        if inserted > 0 {
+            card_ids.push(card_id);
+        }
+    }
+    Ok((task_id, card_ids))
+}
+
+/// 在单个事务中写入错题、对话与卡片；出错时事务随之回滚
+fn persist_bundle(
+    db: &Database,
+    mistake_id: &str,
+    request: &SaveAnalysisBundleRequest,
+    card_source: &str,
+    card_options: &AnkiGenerationOptions,
+    cards: &[AnkiCard],
+) -> Result<SaveAnalysisBundleResult> {
+    let mut conn = db
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    let tx = conn.transaction()?;
+
+    insert_mistake(&tx, mistake_id, &request.mistake)?;
+    let change_set = db
+        .append_mistake_chat_messages_in_tx(
+            &tx,
+            mistake_id,
+            &request.chat_history,
+            Some("analysis"),
+        )
+        .map_err(|e| AppError::database(format!("保存对话失败: {}", e)))?;
+    let (task_id, card_ids) = if cards.is_empty() {
+        (None, Vec::new())
+    } else {
+        let (task_id, card_ids) = insert_cards(&tx, mistake_id, card_source, card_options, cards)?;
+        (Some(task_id), card_ids)
+    };
+
+    tx.commit()?;
+    Ok(SaveAnalysisBundleResult {
+        mistake_id: mistake_id.to_string(),
+        user_message_ids: change_set.inserted_user_message_ids,
+        chat_message_count: change_set.total_processed,
+        task_id,
+        card_ids,
+    })
+}
+
+/// 一次性保存分析结果：错题、对话消息与（可选）卡片
+///
+/// 制卡失败或任一写入失败时不保留任何数据，可直接重试。
+#[tauri::command]
+pub async fn save_analysis_bundle(
+    request: SaveAnalysisBundleRequest,
+    state: State<'_, AppState>,
+) -> Result<SaveAnalysisBundleResult> {
+    let mistake = &request.mistake;
+    if mistake.user_question.trim().is_empty() && mistake.ocr_text.trim().is_empty() {
+        return Err(AppError::validation("题目内容不能为空"));
+    }
+    let mistake_id = non_empty(mistake.id.as_deref())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let subject = non_empty(mistake.subject.as_deref());
+
+    // 联网制卡在事务之外完成，失败时数据库保持原样
+    let card_source = card_source_text(mistake, &request.chat_history);
+    let mut card_options = request.card_options.clone().unwrap_or_default();
+    let mut cards = request.cards.clone();
+    if request.generate_cards {
+        let applied_template =
+            resolve_generation_template(&state.database, &mut card_options, subject)?;
+        let mut generated = state
+            .llm_manager
+            .generate_anki_cards_from_document(
+                &card_source,
+                subject.unwrap_or("通用"),
+                Some(&card_options),
+            )
+            .await
+            .map_err(|e| AppError::llm(format!("制卡失败，未保存任何内容: {}", e)))?;
+        if let Some(applied) = applied_template {
+            for card in generated.iter_mut().filter(|c| c.template_id.is_none()) {
+                card.template_id = Some(applied.template_id.clone());
+            }
+        }
+        cards.extend(generated);
+    }
+    cards.retain(|c| !c.is_error_card);
+
+    let result = persist_bundle(
+        &state.database,
+        &mistake_id,
+        &request,
+        &card_source,
+        &card_options,
+        &cards,
+    )?;
+    log::info!(
+        "[AnalysisBundle] 保存完成: mistake_id={}, 消息 {} 条, 卡片 {} 张",
+        result.mistake_id,
+        result.chat_message_count,
+        result.card_ids.len()
+    );
+
+    crate::mistake_auto_tagging::spawn_auto_tag_mistakes(
+        state.database.clone(),
+        state.llm_manager.clone(),
+        vec![mistake_id.clone()],
+    );
+    super::mistake_kb_sync::spawn_sync_mistakes(
+        state.database.clone(),
+        state.vfs_db.clone(),
+        state.llm_manager.clone(),
+        vec![mistake_id],
+    );
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        serde_json::from_value(serde_json::json!({
+            "role": role,
+            "content": content,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        }))
+        .unwrap()
+    }
+
+    fn card(front: &str) -> AnkiCard {
+        serde_json::from_value(serde_json::json!({ "front": front, "back": "答案" })).unwrap()
+    }
+
+    fn request() -> SaveAnalysisBundleRequest {
+        SaveAnalysisBundleRequest {
+            mistake: AnalysisBundleMistake {
+                subject: Some("数学".to_string()),
+                user_question: "求 f(x) = x^2 的导数".to_string(),
+                tags: vec!["导数".to_string()],
+                ..Default::default()
+            },
+            chat_history: vec![
+                message("user", "怎么做？"),
+                message("assistant", "f'(x) = 2x"),
+            ],
+            generate_cards: false,
+            card_options: None,
+            cards: vec![card("x^2 的导数"), card("x^2 的导数")],
+        }
+    }
+
+    fn setup_db(dir: &std::path::Path) -> Database {
+        let db = Database::new(&dir.join("bundle.db")).unwrap();
+        db.get_conn_safe()
+            .unwrap()
+            .execute_batch(concat!(
+                include_str!("../../migrations/mistakes/V20260130__init.sql"),
+                include_str!("../../migrations/mistakes/V20260209__anki_card_dedup_unique.sql")
+            ))
+            .unwrap();
+        db
+    }
+
+    fn count(db: &Database, sql: &str) -> i64 {
+        db.get_conn_safe()
+            .unwrap()
+            .query_row(sql, [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bundle_persists_mistake_messages_and_deduped_cards() {
+        let dir = tempdir().unwrap();
+        let db = setup_db(dir.path());
+        let request = request();
+        let source = card_source_text(&request.mistake, &request.chat_history);
+        assert!(source.contains("f'(x) = 2x") && !source.contains("怎么做"));
+
+        let result = persist_bundle(
+            &db,
+            "m1",
+            &request,
+            &source,
+            &AnkiGenerationOptions::default(),
+            &request.cards,
+        )
+        .unwrap();
+        assert_eq!(result.chat_message_count, 2);
+        assert_eq!(result.user_message_ids.len(), 1);
+        assert_eq!(result.card_ids.len(), 1);
+        let source_filter = "source_type = 'mistake' AND source_id = 'm1'";
+        assert_eq!(
+            count(
+                &db,
+                &format!("SELECT COUNT(*) FROM anki_cards WHERE {}", source_filter)
+            ),
+            1
+        );
+        // 同一 ID 不可重复保存
+        assert!(persist_bundle(&db, "m1", &request, &source, &Default::default(), &[]).is_err());
+    }
+
+    #[test]
+    fn test_bundle_rolls_back_when_card_insert_fails() {
+        let dir = tempdir().unwrap();
+        let db = setup_db(dir.path());
+        db.get_conn_safe()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER fail_cards BEFORE INSERT ON anki_cards
+                 BEGIN SELECT RAISE(ABORT, 'boom'); END;",
+            )
+            .unwrap();
+        let request = request();
+
+        let result = persist_bundle(
+            &db,
+            "m2",
+            &request,
+            "source",
+            &AnkiGenerationOptions::default(),
+            &request.cards,
+        );
+        assert!(result.is_err());
+        assert_eq!(
+            count(&db, "SELECT COUNT(*) FROM mistakes WHERE id = 'm2'"),
+            0
+        );
+        assert_eq!(
+            count(
+                &db,
+                "SELECT COUNT(*) FROM chat_messages WHERE mistake_id = 'm2'"
+            ),
+            0
+        );
+        assert_eq!(
+            count(
+                &db,
+                "SELECT COUNT(*) FROM document_tasks WHERE document_id = 'm2'"
+            ),
+            0
+        );
+    }
+}
@@ -2,10 +2,16 @@
 //!
 //! 从 commands.rs 拆分：AnkiConnect 连接、导入导出
 
+use crate::anki_connect_service::{
+    AnkiConnectError, AnkiConnectSettings, AnkiNoteOutcome, ANKI_CONNECT_SETTINGS_KEY,
+};
+use crate::anki_field_html;
+use crate::anki_field_mapping::{self, AnkiFieldMapping, DEFAULT_TEMPLATE_KEY};
+use crate::anki_field_selection::{self, CardFieldSelection};
 use crate::commands::{get_template_config, AppState};
-use crate::models::{AnkiCard, AnkiGenerationOptions, AppError};
+use crate::models::{AnkiCard, AnkiGenerationOptions, AppError, AppErrorType};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use tauri::State;
 use uuid::Uuid;
 
@@ -30,13 +36,46 @@ fn card_has_cloze_markup(card: &AnkiCard) -> bool {
 
 // ==================== AnkiConnect集成功能 ====================
 
+fn anki_connect_error(e: AnkiConnectError) -> AppError {
+    let mut details = serde_json::json!({ "code": e.code() });
+    if let AnkiConnectError::VersionTooOld { found, required } = &e {
+        details["found"] = serde_json::json!(found);
+        details["required"] = serde_json::json!(required);
+    }
+    AppError::with_details(AppErrorType::Validation, e.to_string(), details)
+}
+
 /// 检查AnkiConnect连接状态
+///
+/// 版本过旧时返回带 `ANKI_CONNECT_VERSION_TOO_OLD` 错误码的错误。
 #[tauri::command]
 pub async fn check_anki_connect_status() -> Result<bool> {
-    match crate::anki_connect_service::check_anki_connect_availability().await {
-        Ok(available) => Ok(available),
-        Err(e) => Err(AppError::validation(e)),
-    }
+    crate::anki_connect_service::check_anki_connect_version()
+        .await
+        .map(|_| true)
+        .map_err(anki_connect_error)
+}
+
+/// 获取 AnkiConnect 连接配置（地址、重试次数与退避间隔）
+#[tauri::command]
+pub async fn get_anki_connect_settings() -> Result<AnkiConnectSettings> {
+    Ok(crate::anki_connect_service::anki_connect_settings())
+}
+
+/// 保存 AnkiConnect 连接配置，立即生效并持久化
+#[tauri::command]
+pub async fn save_anki_connect_settings(
+    settings: AnkiConnectSettings,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    crate::anki_connect_service::apply_anki_connect_settings(settings.clone())
+        .map_err(AppError::validation)?;
+    let raw = serde_json::to_string(&settings)
+        .map_err(|e| AppError::internal(format!("序列化AnkiConnect配置失败: {}", e)))?;
+    state
+        .database
+        .save_setting(ANKI_CONNECT_SETTINGS_KEY, &raw)
+        .map_err(|e| AppError::database(format!("保存AnkiConnect配置失败: {}", e)))
 }
 
 /// 获取所有牌组名称
@@ -71,13 +110,108 @@ pub async fn create_anki_deck(deck_name: String) -> Result<()> {
         Err(e) => Err(AppError::validation(e)),
     }
 }
-/// 将选定的卡片添加到AnkiConnect
+
+/// 获取笔记类型的字段名（按 Anki 中的顺序，第一个为必填的排序字段）
 #[tauri::command]
-pub async fn add_cards_to_anki_connect(
-    selected_cards: Vec<crate::models::AnkiCard>,
+pub async fn get_anki_model_field_names(model_name: String) -> Result<Vec<String>> {
+    crate::anki_connect_service::get_model_field_names(&model_name)
+        .await
+        .map_err(AppError::validation)
+}
+
+/// 对照 Anki 中的笔记类型校验字段映射，返回该笔记类型的字段名
+async fn validate_field_mapping(mapping: &AnkiFieldMapping) -> Result<Vec<String>> {
+    let model_names = crate::anki_connect_service::get_model_names()
+        .await
+        .map_err(|e| AppError::validation(format!("获取Anki笔记类型失败: {}", e)))?;
+    if !model_names.iter().any(|name| name == &mapping.anki_model) {
+        return Err(AppError::validation(format!(
+            "Anki中不存在笔记类型「{}」",
+            mapping.anki_model
+        )));
+    }
+    let field_names = crate::anki_connect_service::get_model_field_names(&mapping.anki_model)
+        .await
+        .map_err(AppError::validation)?;
+    mapping.validate(&field_names)?;
+    Ok(field_names)
+}
+
+/// 获取已保存的字段映射
+#[tauri::command]
+pub async fn get_anki_field_mappings(state: State<'_, AppState>) -> Result<Vec<AnkiFieldMapping>> {
+    Ok(anki_field_mapping::load_field_mappings(&state.database))
+}
+
+/// 校验并保存（模板 → 笔记类型）的字段映射
+#[tauri::command]
+pub async fn save_anki_field_mapping(
+    mapping: AnkiFieldMapping,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    validate_field_mapping(&mapping).await?;
+    anki_field_mapping::save_field_mapping(&state.database, mapping)
+}
+
+/// 删除字段映射，之后该组合恢复按字段名自动匹配
+#[tauri::command]
+pub async fn delete_anki_field_mapping(
+    template_id: Option<String>,
+    anki_model: String,
+    state: State<'_, AppState>,
+) -> Result<bool> {
+    anki_field_mapping::delete_field_mapping(&state.database, template_id.as_deref(), &anki_model)
+}
+
+/// 确定本次推送使用的字段映射
+///
+/// 显式传入的映射校验后保存到卡片所用模板（混用模板时只用于本次推送）；
+/// 未传入时使用该模板与笔记类型已保存的映射。映射校验在创建任何笔记之前完成。
+async fn resolve_field_mapping(
+    cards: &[crate::models::AnkiCard],
+    note_type: &str,
+    field_mapping: Option<BTreeMap<String, String>>,
+    database: &crate::database::Database,
+) -> Result<Option<AnkiFieldMapping>> {
+    let template_key = anki_field_mapping::common_template_key(cards);
+    let template_id = template_key
+        .filter(|key| *key != DEFAULT_TEMPLATE_KEY)
+        .map(str::to_string);
+
+    let Some(fields) = field_mapping else {
+        let Some(template_key) = template_key else {
+            return Ok(None);
+        };
+        let Some(saved) =
+            anki_field_mapping::find_field_mapping(database, Some(template_key), note_type)
+        else {
+            return Ok(None);
+        };
+        // 笔记类型的字段可能已在 Anki 中修改，推送前重新校验
+        validate_field_mapping(&saved).await?;
+        return Ok(Some(saved));
+    };
+
+    let mapping = AnkiFieldMapping {
+        template_id,
+        anki_model: note_type.to_string(),
+        fields,
+    };
+    validate_field_mapping(&mapping).await?;
+    if template_key.is_some() {
+        anki_field_mapping::save_field_mapping(database, mapping.clone())?;
+    } else {
+        println!("卡片混用多个模板，字段映射仅用于本次推送");
+    }
+    Ok(Some(mapping))
+}
+async fn add_cards_with_outcomes(
+    mut selected_cards: Vec<crate::models::AnkiCard>,
     deck_name: String,
     mut note_type: String,
-) -> Result<Vec<Option<u64>>> {
+    field_mapping: Option<BTreeMap<String, String>>,
+    state: &AppState,
+) -> Result<Vec<AnkiNoteOutcome>> {
     if selected_cards.is_empty() {
         return Err(AppError::validation("没有选择任何卡片".to_string()));
     }
@@ -121,6 +255,9 @@ pub async fn add_cards_to_anki_connect(
         }
     }
 
+    let field_mapping =
+        resolve_field_mapping(&selected_cards, &note_type, field_mapping, &state.database).await?;
+
     println!(
         "📤 开始添加 {} 张卡片到Anki牌组: {} (笔记类型: {})",
         selected_cards.len(),
@@ -128,35 +265,80 @@ pub async fn add_cards_to_anki_connect(
         note_type
     );
 
+    let html_mode = anki_field_html::load_html_mode(&state.database);
+    anki_field_html::apply_to_cards(&mut selected_cards, html_mode);
+
     // 首先尝试创建牌组（如果不存在）
     if let Err(e) = crate::anki_connect_service::create_deck_if_not_exists(&deck_name).await {
         println!("创建牌组失败（可能已存在）: {}", e);
     }
 
-    match crate::anki_connect_service::add_notes_to_anki(selected_cards, deck_name, note_type).await
+    match crate::anki_connect_service::add_notes_to_anki_detailed(
+        selected_cards,
+        deck_name,
+        note_type,
+        HashMap::new(),
+        field_mapping.as_ref(),
+    )
+    .await
     {
-        Ok(note_ids) => {
-            let successful_count = note_ids.iter().filter(|id| id.is_some()).count();
-            let failed_count = note_ids.len() - successful_count;
+        Ok(outcomes) => {
+            let successful_count = outcomes.iter().filter(|o| o.note_id.is_some()).count();
+            let duplicate_count = outcomes.iter().filter(|o| o.duplicate).count();
 
             println!(
-                "卡片添加完成: 成功 {} 张, 失败 {} 张",
-                successful_count, failed_count
+                "卡片添加完成: 成功 {} 张, 重复 {} 张, 其他失败 {} 张",
+                successful_count,
+                duplicate_count,
+                outcomes.len() - successful_count - duplicate_count
             );
-
-            if failed_count > 0 {
-                println!("部分卡片添加失败，可能是重复卡片或格式错误");
+            for outcome in outcomes.iter().filter(|o| o.note_id.is_none()) {
+                println!(
+                    "卡片 {} 添加失败: {}",
+                    outcome.card_id,
+                    outcome.error.as_deref().unwrap_or("未知错误")
+                );
             }
 
-            Ok(note_ids)
+            Ok(outcomes)
         }
         Err(e) => {
             println!("添加卡片到Anki失败: {}", e);
-            Err(AppError::validation(e))
+            Err(anki_connect_error(e))
         }
     }
 }
 
+/// 将选定的卡片添加到AnkiConnect
+///
+/// 单条重复或失败不会中断整批，对应位置返回 `None`。
+/// `field_mapping` 为 Anki 字段名 → 卡片字段，见 `anki_field_mapping`。
+#[tauri::command]
+pub async fn add_cards_to_anki_connect(
+    selected_cards: Vec<crate::models::AnkiCard>,
+    deck_name: String,
+    note_type: String,
+    field_mapping: Option<BTreeMap<String, String>>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Option<u64>>> {
+    let outcomes =
+        add_cards_with_outcomes(selected_cards, deck_name, note_type, field_mapping, &state)
+            .await?;
+    Ok(outcomes.into_iter().map(|o| o.note_id).collect())
+}
+
+/// 添加卡片并返回逐条结果（含 Anki 的重复/错误信息）
+#[tauri::command]
+pub async fn add_cards_to_anki_connect_detailed(
+    selected_cards: Vec<crate::models::AnkiCard>,
+    deck_name: String,
+    note_type: String,
+    field_mapping: Option<BTreeMap<String, String>>,
+    state: State<'_, AppState>,
+) -> Result<Vec<AnkiNoteOutcome>> {
+    add_cards_with_outcomes(selected_cards, deck_name, note_type, field_mapping, &state).await
+}
+
 /// 导入 APKG 到本机 Anki（通过 AnkiConnect）
 #[tauri::command]
 pub async fn import_anki_package(path: String) -> Result<bool> {
@@ -321,22 +503,34 @@ pub async fn save_anki_cards(
 }
 
 /// 导出选定的卡片为.apkg文件
+///
+/// `field_selection` 指定导出哪些字段，未指定时导出全部字段。
 #[tauri::command]
 pub async fn export_cards_as_apkg(
     selected_cards: Vec<crate::models::AnkiCard>,
     deck_name: String,
     note_type: String,
+    field_selection: Option<CardFieldSelection>,
     state: State<'_, AppState>,
 ) -> Result<String> {
-    export_cards_as_apkg_with_template(selected_cards, deck_name, note_type, None, state).await
+    export_cards_as_apkg_with_template(
+        selected_cards,
+        deck_name,
+        note_type,
+        None,
+        field_selection,
+        state,
+    )
+    .await
 }
 /// 导出选定的卡片为.apkg文件（支持模板）
 #[tauri::command]
 pub async fn export_cards_as_apkg_with_template(
-    selected_cards: Vec<crate::models::AnkiCard>,
+    mut selected_cards: Vec<crate::models::AnkiCard>,
     deck_name: String,
     mut note_type: String,
     template_id: Option<String>,
+    field_selection: Option<CardFieldSelection>,
     state: State<'_, AppState>,
 ) -> Result<String> {
     if selected_cards.is_empty() {
@@ -375,6 +569,16 @@ pub async fn export_cards_as_apkg_with_template(
         return Err(AppError::validation("笔记类型不能为空".to_string()));
     }
 
+    let template_fields = template_config
+        .as_ref()
+        .map(|(_, fields, ..)| fields.clone())
+        .unwrap_or_default();
+    anki_field_selection::apply_to_cards(
+        &mut selected_cards,
+        field_selection.as_ref(),
+        &template_fields,
+    )?;
+
     // 检查是否为填空题
     let cloze_count = selected_cards
         .iter()
@@ -416,6 +620,11 @@ pub async fn export_cards_as_apkg_with_template(
 
     println!("📁 导出路径: {:?}", output_path);
 
+    anki_field_html::apply_to_cards(
+        &mut selected_cards,
+        anki_field_html::load_html_mode(&state.database),
+    );
+
     match crate::apkg_exporter_service::export_cards_to_apkg_with_full_template(
         selected_cards,
         deck_name,
@@ -441,7 +650,7 @@ pub async fn export_cards_as_apkg_with_template(
 /// 每种 template_id 创建独立的 Anki model，每张卡片用自己的模板渲染
 #[tauri::command]
 pub async fn export_multi_template_apkg(
-    cards: Vec<crate::models::AnkiCard>,
+    mut cards: Vec<crate::models::AnkiCard>,
     deck_name: String,
     output_path: Option<String>,
     state: State<'_, AppState>,
@@ -490,6 +699,8 @@ pub async fn export_multi_template_apkg(
         output_path.set_extension("apkg");
     }
 
+    anki_field_html::apply_to_cards(&mut cards, anki_field_html::load_html_mode(db));
+
     crate::apkg_exporter_service::export_multi_template_apkg(
         cards.into_iter().filter(|c| !c.is_error_card).collect(),
         deck_name,
@@ -582,6 +793,7 @@ pub async fn batch_export_cards(
                 deck_name,
                 note_type,
                 options.template_id,
+                None,
                 state,
             )
             .await
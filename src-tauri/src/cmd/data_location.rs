@@ -0,0 +1,331 @@
+//! 数据目录迁移命令
+//!
+//! 将当前活动数据空间（插槽目录）整体迁移到用户指定的目录：主数据库、VFS / Chat V2 /
+//! LLM 用量数据库、`lance/`（含 VFS 向量库 `lance/vfs`）、`images/`、`vfs_blobs/` 等全部随之移动。
+//! 流程：
+//! 1. 预检：目标路径合法、可写、为空，磁盘空间充足
+//! 2. 所有数据库进入维护模式（释放文件句柄）并复制整个插槽目录
+//! 3. 校验副本（文件清单与大小一致 + 每个 SQLite 数据库 integrity_check）
+//! 4. 标记下次启动生效；数据库保持维护模式直到重启，避免迁移后的写入落在旧位置
+//! 5. 重启时数据空间管理器再次校验副本，通过后切换插槽位置并删除旧数据；
+//!    任一步失败都保留旧数据，复制阶段失败时清理目标目录
+//!
+//! 切换后数据治理（启动迁移、备份、恢复）都通过 `DataSpaceManager::active_dir()`
+//! 解析路径，自动指向新位置。
+//!
+//! 同时提供磁盘剩余空间保护（`disk_guard`）的配置命令。
+
+use crate::backup_common::check_disk_space;
+use crate::chat_v2::database::ChatV2Database;
+use crate::commands::AppState;
+use crate::data_space::{
+    copy_slot_contents, get_data_space_manager, remove_dir_contents, verify_slot_copy,
+    DataSpaceManager,
+};
+use crate::disk_guard::{self, DiskGuardSettings, DiskSpaceGuard};
+use crate::llm_usage::LlmUsageDatabase;
+use crate::models::AppError;
+use crate::vfs::VfsDatabase;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, State};
+use tracing::{error, info, warn};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataLocationInfo {
+    pub data_root: String,
+    pub database_path: String,
+    pub is_custom: bool,
+    /// 已复制完成、等待重启生效的新位置
+    pub pending_root: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelocationReport {
+    pub old_root: String,
+    pub new_root: String,
+    pub copied_bytes: u64,
+    /// 需要重启应用以切换到新位置；旧数据在重启校验通过后删除
+    pub restart_required: bool,
+}
+
+/// 校验迁移目标：绝对路径、与当前目录互不嵌套、为空目录（或不存在）、可写
+///
+/// 返回目标目录是否由本次校验新建，后续检查失败时调用方据此删除。
+pub(crate) fn validate_relocation_target(current_root: &Path, target: &Path) -> Result<bool> {
+    if !target.is_absolute() {
+        return Err(AppError::validation("目标路径必须为绝对路径"));
+    }
+    if target
+        .components()
+        .any(|c| c == std::path::Component::ParentDir)
+    {
+        return Err(AppError::validation("目标路径不能包含 '..'"));
+    }
+
+    let current = fs::canonicalize(current_root).unwrap_or_else(|_| current_root.to_path_buf());
+    let target_abs = if target.exists() {
+        fs::canonicalize(target)
+            .map_err(|e| AppError::file_system(format!("解析目标路径失败: {}", e)))?
+    } else {
+        target.to_path_buf()
+    };
+    if target_abs == current {
+        return Err(AppError::validation("目标路径与当前数据目录相同"));
+    }
+    if target_abs.starts_with(&current) || current.starts_with(&target_abs) {
+        return Err(AppError::validation("目标路径不能与当前数据目录互相嵌套"));
+    }
+
+    if target.exists() {
+        if !target.is_dir() {
+            return Err(AppError::validation("目标路径不是目录"));
+        }
+        let non_empty = fs::read_dir(target)
+            .map_err(|e| AppError::file_system(format!("读取目标目录失败: {}", e)))?
+            .next()
+            .is_some();
+        if non_empty {
+            return Err(AppError::validation("目标目录必须为空，避免覆盖已有数据"));
+        }
+    }
+
+    let created = !target.exists();
+    fs::create_dir_all(target)
+        .map_err(|e| AppError::file_system(format!("创建目标目录失败: {}", e)))?;
+    let probe = target.join(".write_test");
+    if let Err(e) = fs::write(&probe, b"ok") {
+        remove_created_target(target, created);
+        return Err(AppError::file_system(format!("目标目录不可写: {}", e)));
+    }
+    let _ = fs::remove_file(&probe);
+    Ok(created)
+}
+
+/// 删除校验时新建的（仍为空的）目标目录；已存在的目录保持原样
+fn remove_created_target(target: &Path, created: bool) {
+    if created {
+        if let Err(e) = fs::remove_dir(target) {
+            warn!(
+                "[DataLocation] 清理新建的目标目录失败 {}: {}",
+                target.display(),
+                e
+            );
+        }
+    }
+}
+
+/// 迁移期间进入维护模式的数据库，复制失败时据此恢复连接
+struct RelocationMaintenance {
+    database: Arc<crate::database::Database>,
+    vfs: Option<Arc<VfsDatabase>>,
+    chat_v2: Option<Arc<ChatV2Database>>,
+    llm_usage: Option<Arc<LlmUsageDatabase>>,
+}
+
+impl RelocationMaintenance {
+    /// 所有数据库执行 WAL checkpoint 并释放文件句柄；任一失败时恢复已进入的数据库
+    fn enter(app: &AppHandle, state: &AppState) -> Result<Self> {
+        state
+            .database
+            .enter_maintenance_mode()
+            .map_err(|e| AppError::database(format!("进入维护模式失败: {}", e)))?;
+        let mut guard = Self {
+            database: state.database.clone(),
+            vfs: None,
+            chat_v2: None,
+            llm_usage: None,
+        };
+        let result = (|| {
+            if let Some(db) = app.try_state::<Arc<VfsDatabase>>() {
+                db.enter_maintenance_mode()
+                    .map_err(|e| format!("VFS 数据库: {}", e))?;
+                guard.vfs = Some(db.inner().clone());
+            }
+            if let Some(db) = app.try_state::<Arc<ChatV2Database>>() {
+                db.enter_maintenance_mode()
+                    .map_err(|e| format!("Chat V2 数据库: {}", e))?;
+                guard.chat_v2 = Some(db.inner().clone());
+            }
+            if let Some(db) = app.try_state::<Arc<LlmUsageDatabase>>() {
+                db.enter_maintenance_mode()
+                    .map_err(|e| format!("LLM 用量数据库: {}", e))?;
+                guard.llm_usage = Some(db.inner().clone());
+            }
+            Ok::<(), String>(())
+        })();
+        if let Err(e) = result {
+            guard.exit();
+            return Err(AppError::database(format!("进入维护模式失败: {}", e)));
+        }
+        Ok(guard)
+    }
+
+    /// 恢复所有数据库的文件连接（迁移失败时使用）
+    fn exit(self) {
+        if let Some(db) = &self.vfs {
+            if let Err(e) = db.exit_maintenance_mode() {
+                error!("[DataLocation] 恢复 VFS 数据库连接失败: {}", e);
+            }
+        }
+        if let Some(db) = &self.chat_v2 {
+            if let Err(e) = db.exit_maintenance_mode() {
+                error!("[DataLocation] 恢复 Chat V2 数据库连接失败: {}", e);
+            }
+        }
+        if let Some(db) = &self.llm_usage {
+            if let Err(e) = db.exit_maintenance_mode() {
+                error!("[DataLocation] 恢复 LLM 用量数据库连接失败: {}", e);
+            }
+        }
+        if let Err(e) = self.database.exit_maintenance_mode() {
+            error!("[DataLocation] 恢复主数据库连接失败: {}", e);
+        }
+    }
+}
+
+fn data_space() -> Result<&'static DataSpaceManager> {
+    get_data_space_manager().ok_or_else(|| AppError::internal("数据空间管理器未初始化"))
+}
+
+/// 获取当前数据位置
+#[tauri::command]
+pub async fn get_data_location() -> Result<DataLocationInfo> {
+    let mgr = data_space()?;
+    let root = mgr.active_dir();
+    Ok(DataLocationInfo {
+        database_path: root.join("mistakes.db").to_string_lossy().to_string(),
+        data_root: root.to_string_lossy().to_string(),
+        is_custom: mgr.slot_location(mgr.active_slot()).is_some(),
+        pending_root: mgr
+            .pending_relocation()
+            .map(|(_, dir)| dir.to_string_lossy().to_string()),
+    })
+}
+
+/// 将当前数据空间整体迁移到新目录，重启后生效
+///
+/// 成功返回后所有数据库保持维护模式，前端应提示用户并调用 `restart_app` 完成切换。
+#[tauri::command]
+pub async fn relocate_database(
+    new_path: String,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<RelocationReport> {
+    let mgr = data_space()?;
+    let slot = mgr.active_slot();
+    let old_root = mgr.active_dir();
+    let new_root = PathBuf::from(new_path.trim());
+
+    if state.database.is_in_maintenance_mode() {
+        return Err(AppError::conflict("数据库正处于维护模式，请稍后再试"));
+    }
+    if mgr.pending_relocation().is_some() {
+        return Err(AppError::conflict("已有等待重启生效的数据目录迁移"));
+    }
+    let created_target = validate_relocation_target(&old_root, &new_root)?;
+    let size = DataSpaceManager::calculate_dir_size(&old_root).unwrap_or(0);
+    // 复制完成后目标磁盘仍需保留最低剩余空间
+    let space_check = check_disk_space(&new_root, size)
+        .and_then(|_| DiskSpaceGuard::from_db(&state.database, &new_root).preflight(size));
+    if let Err(e) = space_check {
+        remove_created_target(&new_root, created_target);
+        return Err(e);
+    }
+
+    info!(
+        "[DataLocation] 开始迁移数据目录: {} -> {}",
+        old_root.display(),
+        new_root.display()
+    );
+    let maintenance = RelocationMaintenance::enter(&app, &state)?;
+
+    let copy_result = {
+        let (src, dst) = (old_root.clone(), new_root.clone());
+        tokio::task::spawn_blocking(move || {
+            let copied = copy_slot_contents(&src, &dst)?;
+            verify_slot_copy(&src, &dst)?;
+            Ok::<u64, AppError>(copied)
+        })
+        .await
+        .map_err(|e| AppError::internal(format!("迁移任务执行失败: {}", e)))
+        .and_then(|r| r)
+        .and_then(|copied| {
+            mgr.mark_pending_relocation(slot, &new_root)
+                .map_err(|e| AppError::file_system(format!("记录迁移位置失败: {}", e)))?;
+            Ok(copied)
+        })
+    };
+
+    let copied_bytes = match copy_result {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("[DataLocation] 复制或校验失败，回滚: {}", e);
+            for w in remove_dir_contents(&new_root) {
+                warn!("[DataLocation] 回滚清理: {}", w);
+            }
+            maintenance.exit();
+            return Err(e);
+        }
+    };
+
+    info!(
+        "[DataLocation] 数据目录已复制并校验 ({} 字节)，重启后切换到 {}",
+        copied_bytes,
+        new_root.display()
+    );
+    Ok(RelocationReport {
+        old_root: old_root.to_string_lossy().to_string(),
+        new_root: new_root.to_string_lossy().to_string(),
+        copied_bytes,
+        restart_required: true,
+    })
+}
+
+/// 获取磁盘剩余空间保护配置
+#[tauri::command]
+pub async fn get_disk_guard_settings(state: State<'_, AppState>) -> Result<DiskGuardSettings> {
+    Ok(disk_guard::load_disk_guard_settings(&state.database))
+}
+
+/// 保存磁盘剩余空间保护配置（对之后启动的写入操作生效）
+#[tauri::command]
+pub async fn save_disk_guard_settings(
+    settings: DiskGuardSettings,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    disk_guard::save_disk_guard_settings(&state.database, &settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_validate_rejects_nested_and_non_empty() {
+        let tmp = TempDir::new().unwrap();
+        let current = tmp.path().join("current");
+        fs::create_dir_all(&current).unwrap();
+
+        assert!(validate_relocation_target(&current, &current.join("sub")).is_err());
+        assert!(validate_relocation_target(&current, Path::new("relative/dir")).is_err());
+
+        let occupied = tmp.path().join("occupied");
+        fs::create_dir_all(&occupied).unwrap();
+        fs::write(occupied.join("file"), b"x").unwrap();
+        assert!(validate_relocation_target(&current, &occupied).is_err());
+
+        let fresh = tmp.path().join("fresh");
+        assert!(validate_relocation_target(&current, &fresh).unwrap());
+        assert!(!validate_relocation_target(&current, &fresh).unwrap());
+        remove_created_target(&fresh, true);
+        assert!(!fresh.exists());
+    }
+}
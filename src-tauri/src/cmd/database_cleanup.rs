@@ -0,0 +1,204 @@
+//! 数据库批量清理命令
+//!
+//! 清理对象：孤立图片、无文档归属的知识库分块、孤立聊天消息、未产出卡片的已结束制卡任务、
+//! 回收站中的错题、不再被任何消息引用的文档附件载荷。默认只预览（dry-run），返回各类数量与
+//! 空间估算及确认令牌；正式执行必须回传令牌，且令牌与执行前重新扫描的结果一致，防止预览后数据变化导致误删。
+//! 预览中的孤立图片按回收站已清空计算。数据库部分在单个事务内完成；提交后释放被删错题的图片引用，
+//! 再重新扫描孤立图片并删除，报告中的图片数与空间为实际删除的结果。
+
+use crate::batch_operations::{BatchOperationExt, CleanupCategoryStats, DatabaseCleanupCounts};
+use crate::commands::AppState;
+use crate::models::AppError;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::ipc::Channel;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CleanupStage {
+    ScanningImages,
+    ScanningDatabase,
+    CleaningDatabase,
+    DeletingImages,
+    Done,
+}
+
+/// 清理进度（通过 Channel 推送）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CleanupProgress {
+    pub stage: CleanupStage,
+    /// 当前阶段已处理的条目数
+    pub processed: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseCleanupReport {
+    pub dry_run: bool,
+    pub orphaned_images: CleanupCategoryStats,
+    #[serde(flatten)]
+    pub database: DatabaseCleanupCounts,
+    pub total_estimated_bytes: u64,
+    /// 预览结果的指纹；正式执行时需原样回传
+    pub confirmation_token: String,
+    /// 正式执行时删除失败的图片
+    pub failed_images: Vec<String>,
+}
+
+fn compute_confirmation_token(
+    orphaned_images: &[(String, u64)],
+    database: &DatabaseCleanupCounts,
+) -> String {
+    let mut hasher = Sha256::new();
+    for (path, size) in orphaned_images {
+        hasher.update(path.as_bytes());
+        hasher.update(size.to_le_bytes());
+    }
+    hasher.update(serde_json::to_vec(database).unwrap_or_default());
+    hex::encode(hasher.finalize())[..16].to_string()
+}
+
+fn image_stats(orphaned_images: &[(String, u64)]) -> CleanupCategoryStats {
+    CleanupCategoryStats {
+        count: orphaned_images.len(),
+        estimated_bytes: orphaned_images.iter().map(|(_, size)| size).sum(),
+    }
+}
+
+/// 批量清理数据库
+///
+/// `dry_run` 默认为 true，只返回报告不做任何修改；传 `dry_run: false` 时必须携带
+/// 预览返回的 `confirmation_token`。
+#[tauri::command]
+pub async fn batch_cleanup_database(
+    dry_run: Option<bool>,
+    confirmation_token: Option<String>,
+    on_progress: Option<Channel<CleanupProgress>>,
+    state: State<'_, AppState>,
+) -> Result<DatabaseCleanupReport> {
+    let dry_run = dry_run.unwrap_or(true);
+    let send_progress = |stage: CleanupStage, processed: usize| {
+        if let Some(channel) = &on_progress {
+            let _ = channel.send(CleanupProgress { stage, processed });
+        }
+    };
+
+    send_progress(CleanupStage::ScanningImages, 0);
+    let orphaned_images = state
+        .file_manager
+        .find_orphaned_images(&state.database, true, &|scanned| {
+            send_progress(CleanupStage::ScanningImages, scanned)
+        })
+        .await?;
+
+    send_progress(CleanupStage::ScanningDatabase, 0);
+    let preview = state
+        .database
+        .with_batch_operations(|ops| ops.scan_cleanup_candidates())
+        .map_err(|e| AppError::database(format!("扫描待清理数据失败: {}", e)))?;
+    let token = compute_confirmation_token(&orphaned_images, &preview);
+
+    if dry_run {
+        send_progress(CleanupStage::Done, 0);
+        let images = image_stats(&orphaned_images);
+        return Ok(DatabaseCleanupReport {
+            dry_run,
+            total_estimated_bytes: preview.total_estimated_bytes() + images.estimated_bytes,
+            orphaned_images: images,
+            database: preview,
+            confirmation_token: token,
+            failed_images: Vec::new(),
+        });
+    }
+
+    match confirmation_token.as_deref() {
+        None => {
+            return Err(AppError::validation(
+                "正式清理需要提供预览返回的 confirmation_token",
+            ))
+        }
+        Some(provided) if provided != token => {
+            return Err(AppError::validation(
+                "数据在预览后已发生变化，请重新预览后再确认清理",
+            ))
+        }
+        _ => {}
+    }
+
+    send_progress(CleanupStage::CleaningDatabase, 0);
+    let (removed, dropped_images) = state
+        .database
+        .with_batch_operations(|ops| ops.batch_cleanup_database())
+        .map_err(|e| AppError::database(format!("清理数据库失败，已回滚: {}", e)))?;
+    let removed_rows = removed.dangling_chunks.count
+        + removed.orphaned_messages.count
+        + removed.empty_tasks.count
+        + removed.trashed_mistakes.count
+        + removed.orphaned_attachments.count;
+    if let Err(e) = state.file_manager.release_images(&dropped_images) {
+        log::warn!("[DatabaseCleanup] 释放回收站错题的图片引用失败: {}", e);
+    }
+    send_progress(CleanupStage::CleaningDatabase, removed_rows);
+
+    // 回收站清空后重新扫描，删除的是此刻真正无人引用的图片
+    let orphaned_images = state
+        .file_manager
+        .find_orphaned_images(&state.database, false, &|_| {})
+        .await?;
+
+    let mut failed_images = Vec::new();
+    for (index, (path, _)) in orphaned_images.iter().enumerate() {
+        if let Err(e) = state.file_manager.delete_orphaned_image(path).await {
+            log::warn!("[DatabaseCleanup] 删除孤立图片失败: {} - {}", path, e);
+            failed_images.push(path.clone());
+        }
+        send_progress(CleanupStage::DeletingImages, index + 1);
+    }
+    let deleted_images: Vec<(String, u64)> = orphaned_images
+        .into_iter()
+        .filter(|(path, _)| !failed_images.contains(path))
+        .collect();
+
+    log::info!(
+        "[DatabaseCleanup] 清理完成: 行 {}，图片 {}（失败 {}）",
+        removed_rows,
+        deleted_images.len(),
+        failed_images.len()
+    );
+    send_progress(CleanupStage::Done, removed_rows + deleted_images.len());
+
+    let images = image_stats(&deleted_images);
+    Ok(DatabaseCleanupReport {
+        dry_run,
+        total_estimated_bytes: removed.total_estimated_bytes() + images.estimated_bytes,
+        orphaned_images: images,
+        database: removed,
+        confirmation_token: token,
+        failed_images,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirmation_token_tracks_candidates() {
+        let images = vec![("images/a.png".to_string(), 10)];
+        let mut counts = DatabaseCleanupCounts::default();
+        let token = compute_confirmation_token(&images, &counts);
+        assert_eq!(token.len(), 16);
+        assert_eq!(token, compute_confirmation_token(&images, &counts));
+
+        counts.empty_tasks.count = 1;
+        assert_ne!(token, compute_confirmation_token(&images, &counts));
+        assert_ne!(
+            compute_confirmation_token(&images, &DatabaseCleanupCounts::default()),
+            compute_confirmation_token(&[], &DatabaseCleanupCounts::default())
+        );
+    }
+}
@@ -0,0 +1,428 @@
+//! 系统诊断快照
+//!
+//! 汇总各子系统已有的状态查询，一次返回便于粘贴到问题反馈中：数据库版本与文件大小、
+//! 维护模式、各角色模型分配、知识库嵌入模型与维度、向量库规模、安全存储及待嵌入积压。
+//!
+//! 只读且不触发任何初始化：不打开 LanceDB 连接（行数取自维度注册表的记录数），
+//! 不刷新 SchemaRegistry 状态。单个子系统读取失败只记入 `warnings`，不影响其余部分。
+//! 不输出 API Key 等敏感信息。
+
+use crate::cmd::recommendation_cache::{
+    load_cache_ttl, RecommendationCacheStats, RECOMMENDATION_CACHE,
+};
+use crate::command_metrics::{self, CommandMetric};
+use crate::commands::AppState;
+use crate::data_governance::commands_backup::resolve_database_path;
+use crate::data_governance::init::get_current_schema_state;
+use crate::data_governance::schema_registry::DatabaseId;
+use crate::database::has_column;
+use crate::models::{AppError, ModelAssignments};
+use crate::vfs::repos::embedding_dim_repo;
+use crate::vfs::repos::MODALITY_TEXT;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseDiagnostics {
+    pub id: String,
+    /// 迁移版本（`refinery_schema_history` 最大版本；未初始化为 0）
+    pub schema_version: u32,
+    /// 数据库文件大小（文件不存在时为空）
+    pub file_size_bytes: Option<u64>,
+    /// WAL 文件大小（无 WAL 文件时为空）
+    pub wal_size_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelRoleDiagnostics {
+    /// 角色名（`ModelAssignments` 字段名去掉 `_config_id` 后缀）
+    pub role: String,
+    pub config_id: Option<String>,
+    pub config_name: Option<String>,
+    pub model: Option<String>,
+    /// 已分配且能找到对应配置
+    pub config_found: bool,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingDimensionDiagnostics {
+    pub modality: String,
+    pub dimension: i32,
+    pub model_name: Option<String>,
+    pub record_count: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingDiagnostics {
+    pub model_config_id: Option<String>,
+    pub model: Option<String>,
+    /// 当前嵌入模型对应的文本维度（尚未建立索引时为空）
+    pub dimension: Option<i32>,
+    /// 已注册的全部维度（含多模态）
+    pub registered_dimensions: Vec<EmbeddingDimensionDiagnostics>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanceDiagnostics {
+    /// 向量库目录存在且非空
+    pub present: bool,
+    pub table_count: usize,
+    pub row_count: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingBacklogDiagnostics {
+    /// 待索引（含未设置状态）
+    pub pending: i64,
+    pub indexing: i64,
+    pub failed: i64,
+    pub stale: i64,
+    /// 旧版聊天记录中标记为待重新嵌入的条数
+    pub chat_embedding_retry: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemDiagnostics {
+    pub generated_at: String,
+    pub app_version: String,
+    pub platform: String,
+    pub maintenance_mode: bool,
+    /// 离线模式已开启（除本机地址外不发起网络请求）
+    pub offline_mode: bool,
+    pub databases: Vec<DatabaseDiagnostics>,
+    pub models: Vec<ModelRoleDiagnostics>,
+    pub embedding: EmbeddingDiagnostics,
+    pub lance: LanceDiagnostics,
+    pub secure_store_available: bool,
+    pub embedding_backlog: EmbeddingBacklogDiagnostics,
+    /// 错题推荐缓存的条目数与命中统计
+    pub recommendation_cache: RecommendationCacheStats,
+    /// 读取失败的子系统及原因
+    pub warnings: Vec<String>,
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().map(|m| m.len())
+}
+
+fn wal_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_os_string();
+    name.push("-wal");
+    PathBuf::from(name)
+}
+
+/// 将模型分配展开为 (角色, 配置 ID) 列表；新增角色无需改动此处
+fn model_roles(assignments: &ModelAssignments) -> Vec<(String, Option<String>)> {
+    let Ok(serde_json::Value::Object(map)) = serde_json::to_value(assignments) else {
+        return Vec::new();
+    };
+    let mut roles: Vec<(String, Option<String>)> = map
+        .into_iter()
+        .filter(|(_, value)| value.is_string() || value.is_null())
+        .map(|(key, value)| {
+            let role = key.strip_suffix("_config_id").unwrap_or(&key).to_string();
+            (role, value.as_str().map(str::to_string))
+        })
+        .collect();
+    // 科目覆盖按 `subject:<科目>:<角色>` 列出，便于发现指向已删除配置的覆盖
+    let mut subjects: Vec<_> = assignments.subject_model_overrides.iter().collect();
+    subjects.sort_by(|a, b| a.0.cmp(b.0));
+    for (subject, entry) in subjects {
+        for (role, config_id) in [
+            ("analysis_model", &entry.analysis_model_config_id),
+            ("ocr_model", &entry.ocr_model_config_id),
+        ] {
+            if config_id.is_some() {
+                roles.push((format!("subject:{}:{}", subject, role), config_id.clone()));
+            }
+        }
+    }
+    roles
+}
+
+fn count_embedding_backlog(conn: &Connection) -> rusqlite::Result<EmbeddingBacklogDiagnostics> {
+    if !has_column(conn, "resources", "index_state")? {
+        return Ok(EmbeddingBacklogDiagnostics::default());
+    }
+    conn.query_row(
+        "SELECT
+            COALESCE(SUM(CASE WHEN index_state = 'pending' OR index_state IS NULL THEN 1 ELSE 0 END), 0),
+            COALESCE(SUM(CASE WHEN index_state = 'indexing' THEN 1 ELSE 0 END), 0),
+            COALESCE(SUM(CASE WHEN index_state = 'failed' THEN 1 ELSE 0 END), 0),
+            COALESCE(SUM(CASE WHEN index_state = 'stale' THEN 1 ELSE 0 END), 0)
+         FROM resources",
+        [],
+        |row| {
+            Ok(EmbeddingBacklogDiagnostics {
+                pending: row.get(0)?,
+                indexing: row.get(1)?,
+                failed: row.get(2)?,
+                stale: row.get(3)?,
+                chat_embedding_retry: 0,
+            })
+        },
+    )
+}
+
+fn count_chat_embedding_retry(conn: &Connection) -> rusqlite::Result<i64> {
+    if !has_column(conn, "chat_messages", "embedding_retry")? {
+        return Ok(0);
+    }
+    conn.query_row(
+        "SELECT COUNT(*) FROM chat_messages WHERE embedding_retry = 1",
+        [],
+        |row| row.get(0),
+    )
+}
+
+fn lance_dir_present(dir: &Path) -> bool {
+    std::fs::read_dir(dir)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false)
+}
+
+/// 获取系统诊断快照（只读）
+#[tauri::command]
+pub async fn get_diagnostics(state: State<'_, AppState>) -> Result<SystemDiagnostics> {
+    let mut warnings = Vec::new();
+    let app_data_dir = state.file_manager.get_writable_app_data_dir();
+
+    // 数据库版本与文件大小
+    let registry = match get_current_schema_state(&app_data_dir) {
+        Ok(registry) => Some(registry),
+        Err(e) => {
+            warnings.push(format!("读取数据库版本失败: {}", e));
+            None
+        }
+    };
+    let databases = DatabaseId::all_ordered()
+        .into_iter()
+        .map(|id| {
+            let path = resolve_database_path(&id, &app_data_dir);
+            DatabaseDiagnostics {
+                id: id.as_str().to_string(),
+                schema_version: registry
+                    .as_ref()
+                    .and_then(|r| r.get_schema_version(&id))
+                    .unwrap_or(0),
+                file_size_bytes: file_size(&path),
+                wal_size_bytes: file_size(&wal_path(&path)),
+            }
+        })
+        .collect();
+
+    // 各角色模型分配
+    let assignments = match state.llm_manager.get_model_assignments().await {
+        Ok(assignments) => assignments,
+        Err(e) => {
+            warnings.push(format!("读取模型分配失败: {}", e));
+            ModelAssignments::default()
+        }
+    };
+    let api_configs = match state.llm_manager.get_api_configs().await {
+        Ok(configs) => configs,
+        Err(e) => {
+            warnings.push(format!("读取模型配置失败: {}", e));
+            Vec::new()
+        }
+    };
+    let models = model_roles(&assignments)
+        .into_iter()
+        .map(|(role, config_id)| {
+            let config = config_id
+                .as_deref()
+                .and_then(|id| api_configs.iter().find(|c| c.id == id));
+            ModelRoleDiagnostics {
+                role,
+                config_found: config.is_some(),
+                config_name: config.map(|c| c.name.clone()),
+                model: config.map(|c| c.model.clone()),
+                enabled: config.map(|c| c.enabled),
+                config_id,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut embedding = EmbeddingDiagnostics {
+        model_config_id: assignments.embedding_model_config_id.clone(),
+        model: models
+            .iter()
+            .find(|m| m.role == "embedding_model")
+            .and_then(|m| m.model.clone()),
+        ..Default::default()
+    };
+    let mut lance = LanceDiagnostics::default();
+    let mut embedding_backlog = EmbeddingBacklogDiagnostics::default();
+
+    // 嵌入维度、向量库规模与待嵌入积压（VFS）
+    if let Some(vfs_db) = state.vfs_db.as_ref() {
+        if let Some(base) = vfs_db.db_path().parent() {
+            lance.present = lance_dir_present(&base.join("lance").join("vfs"));
+        }
+        match vfs_db.get_conn_safe() {
+            Ok(conn) => {
+                match embedding_dim_repo::list_all(&conn) {
+                    Ok(dims) => {
+                        lance.table_count = dims.len();
+                        lance.row_count = dims.iter().map(|d| d.record_count).sum();
+                        embedding.dimension = dims
+                            .iter()
+                            .filter(|d| d.modality == MODALITY_TEXT)
+                            .find(|d| {
+                                d.model_config_id.is_some()
+                                    && d.model_config_id == embedding.model_config_id
+                            })
+                            .map(|d| d.dimension);
+                        embedding.registered_dimensions = dims
+                            .into_iter()
+                            .map(|d| EmbeddingDimensionDiagnostics {
+                                modality: d.modality,
+                                dimension: d.dimension,
+                                model_name: d.model_name,
+                                record_count: d.record_count,
+                            })
+                            .collect();
+                    }
+                    Err(e) => warnings.push(format!("读取嵌入维度失败: {}", e)),
+                }
+                match count_embedding_backlog(&conn) {
+                    Ok(backlog) => embedding_backlog = backlog,
+                    Err(e) => warnings.push(format!("统计待索引资源失败: {}", e)),
+                }
+            }
+            Err(e) => warnings.push(format!("连接 VFS 数据库失败: {}", e)),
+        }
+    } else {
+        warnings.push("VFS 数据库未初始化".to_string());
+    }
+
+    match state.database.get_conn_safe() {
+        Ok(conn) => match count_chat_embedding_retry(&conn) {
+            Ok(count) => embedding_backlog.chat_embedding_retry = count,
+            Err(e) => warnings.push(format!("统计待重新嵌入消息失败: {}", e)),
+        },
+        Err(e) => warnings.push(format!("连接主数据库失败: {}", e)),
+    }
+
+    Ok(SystemDiagnostics {
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        platform: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        maintenance_mode: state.database.is_in_maintenance_mode(),
+        offline_mode: crate::offline_mode::is_enabled(),
+        databases,
+        models,
+        embedding,
+        lance,
+        secure_store_available: state.database.is_secure_store_available(),
+        embedding_backlog,
+        recommendation_cache: RECOMMENDATION_CACHE.stats(load_cache_ttl(&state.database)),
+        warnings,
+    })
+}
+
+/// 获取命令耗时统计（需开启设置项 `diagnostics.command_metrics`）
+#[tauri::command]
+pub async fn get_command_metrics() -> Result<Vec<CommandMetric>> {
+    Ok(command_metrics::snapshot())
+}
+
+/// 清空命令耗时统计
+#[tauri::command]
+pub async fn reset_command_metrics() -> Result<()> {
+    command_metrics::reset();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_roles_cover_all_assignment_fields() {
+        let assignments = ModelAssignments {
+            embedding_model_config_id: Some("cfg-emb".to_string()),
+            ..Default::default()
+        };
+        let roles = model_roles(&assignments);
+        assert!(roles.len() >= 10);
+        assert!(roles
+            .iter()
+            .any(|(role, id)| role == "embedding_model" && id.as_deref() == Some("cfg-emb")));
+        assert!(roles
+            .iter()
+            .any(|(role, id)| role == "model2" && id.is_none()));
+        assert!(!roles
+            .iter()
+            .any(|(role, _)| role == "subject_model_overrides"));
+
+        let mut with_override = assignments.clone();
+        with_override.subject_model_overrides.insert(
+            "物理".to_string(),
+            crate::models::SubjectModelOverride {
+                analysis_model_config_id: Some("cfg-strong".to_string()),
+                ocr_model_config_id: None,
+            },
+        );
+        let roles = model_roles(&with_override);
+        assert!(roles
+            .iter()
+            .any(|(role, id)| role == "subject:物理:analysis_model"
+                && id.as_deref() == Some("cfg-strong")));
+        assert!(!roles
+            .iter()
+            .any(|(role, _)| role == "subject:物理:ocr_model"));
+    }
+
+    #[test]
+    fn test_backlog_counts_and_legacy_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE resources (id TEXT PRIMARY KEY);")
+            .unwrap();
+        assert_eq!(count_embedding_backlog(&conn).unwrap().pending, 0);
+
+        conn.execute_batch(
+            "ALTER TABLE resources ADD COLUMN index_state TEXT;
+             INSERT INTO resources VALUES ('a', NULL), ('b', 'pending'), ('c', 'failed'),
+                 ('d', 'indexed'), ('e', 'stale');
+             CREATE TABLE chat_messages (id INTEGER PRIMARY KEY,
+                 embedding_retry INTEGER NOT NULL DEFAULT 0);
+             INSERT INTO chat_messages (embedding_retry) VALUES (1), (0), (1);",
+        )
+        .unwrap();
+        let backlog = count_embedding_backlog(&conn).unwrap();
+        assert_eq!((backlog.pending, backlog.failed, backlog.stale), (2, 1, 1));
+        assert_eq!(backlog.indexing, 0);
+        assert_eq!(count_chat_embedding_retry(&conn).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_file_sizes_and_lance_presence() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("mistakes.db");
+        std::fs::write(&db, b"0123456789").unwrap();
+        assert_eq!(file_size(&db), Some(10));
+        assert_eq!(file_size(&wal_path(&db)), None);
+        std::fs::write(dir.path().join("mistakes.db-wal"), b"abc").unwrap();
+        assert_eq!(file_size(&wal_path(&db)), Some(3));
+
+        let lance = dir.path().join("lance");
+        assert!(!lance_dir_present(&lance));
+        std::fs::create_dir_all(&lance).unwrap();
+        assert!(!lance_dir_present(&lance));
+        std::fs::create_dir_all(lance.join("vfs_text_768.lance")).unwrap();
+        assert!(lance_dir_present(&lance));
+    }
+}
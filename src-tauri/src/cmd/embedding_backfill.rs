@@ -0,0 +1,496 @@
+//! 历史数据嵌入回填
+//!
+//! 先积累了错题与对话、后开启 RAG / 记忆功能时，已有数据没有向量，无法参与相似检索。
+//! `backfill_embeddings` 为缺少向量的数据补齐嵌入，写入 Lance 对话向量表：
+//!
+//! - 对话：`chat_messages` 中的用户消息，以消息 ID 入库；
+//! - 错题：题干 + OCR 文本，以 `mistake:{id}` 为 ID、`role = "mistake"` 入库。
+//!
+//! 已有向量的行直接跳过，重复执行不会重复嵌入；中断后再次执行即从未完成处继续。
+//! 按批调用嵌入模型，批间等待以免触发限流；对话消息嵌入失败时标记
+//! `embedding_retry = 1`，成功后清除。进度通过 `embedding-backfill-progress` 事件推送。
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::commands::AppState;
+use crate::database::{has_column, table_exists};
+use crate::lance_vector_store::{LanceChatRow, LanceVectorStore};
+use crate::llm_manager::{embedding_retry_after, is_retryable_embedding_error, LLMManager};
+use crate::models::AppError;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+const BACKFILL_PROGRESS_EVENT: &str = "embedding-backfill-progress";
+const DEFAULT_BATCH_SIZE: usize = 16;
+const MAX_BATCH_SIZE: usize = 64;
+/// 批间默认等待时间，避免连续请求触发嵌入服务限流
+const DEFAULT_BATCH_DELAY_MS: u64 = 500;
+/// 单条文本的嵌入长度上限（字符）
+const MAX_EMBED_CHARS: usize = 2000;
+/// 错题向量的 ID 前缀与角色，与对话消息区分
+pub const MISTAKE_ROW_PREFIX: &str = "mistake:";
+pub const MISTAKE_ROW_ROLE: &str = "mistake";
+
+/// 同一时间只允许一个回填任务
+static BACKFILL_RUNNING: AtomicBool = AtomicBool::new(false);
+
+struct BackfillGuard;
+
+impl Drop for BackfillGuard {
+    fn drop(&mut self) {
+        BACKFILL_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingBackfillScope {
+    /// 错题对话中的用户消息
+    Chat,
+    /// 错题题干
+    Mistakes,
+    All,
+}
+
+impl EmbeddingBackfillScope {
+    fn includes(self, source: BackfillSource) -> bool {
+        match self {
+            Self::Chat => source == BackfillSource::Chat,
+            Self::Mistakes => source == BackfillSource::Mistakes,
+            Self::All => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackfillSource {
+    Chat,
+    Mistakes,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingBackfillReport {
+    /// 范围内的全部行数（含已有向量的行）
+    pub total: usize,
+    /// 本次新写入向量的行数
+    pub processed: usize,
+    /// 已有向量而跳过的行数
+    pub skipped: usize,
+    pub failed: usize,
+    /// 遇到不可重试的错误（如认证失败）而提前结束时的错误信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingBackfillProgress {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<BackfillSource>,
+    #[serde(flatten)]
+    pub report: EmbeddingBackfillReport,
+    pub finished: bool,
+}
+
+/// 待嵌入的一行数据
+#[derive(Debug, Clone)]
+struct BackfillItem {
+    /// 源表中的 ID，用作分页游标
+    source_id: String,
+    row: LanceChatRow,
+}
+
+fn truncate_text(text: &str) -> String {
+    text.trim().chars().take(MAX_EMBED_CHARS).collect()
+}
+
+fn mistake_deleted_filter(conn: &Connection) -> rusqlite::Result<&'static str> {
+    Ok(if has_column(conn, "mistakes", "deleted_at")? {
+        " AND deleted_at IS NULL"
+    } else {
+        ""
+    })
+}
+
+/// 范围内的行数（含已有向量的行，用于进度总数）
+fn count_source(conn: &Connection, source: BackfillSource) -> rusqlite::Result<usize> {
+    let sql = match source {
+        BackfillSource::Chat => {
+            if !table_exists(conn, "chat_messages")? {
+                return Ok(0);
+            }
+            "SELECT COUNT(*) FROM chat_messages WHERE role = 'user' AND TRIM(content) <> ''"
+                .to_string()
+        }
+        BackfillSource::Mistakes => {
+            if !table_exists(conn, "mistakes")? {
+                return Ok(0);
+            }
+            format!(
+                "SELECT COUNT(*) FROM mistakes \
+                 WHERE TRIM(COALESCE(user_question, '') || COALESCE(ocr_text, '')) <> ''{}",
+                mistake_deleted_filter(conn)?
+            )
+        }
+    };
+    conn.query_row(&sql, [], |row| row.get::<_, i64>(0))
+        .map(|n| n as usize)
+}
+
+/// 按 ID 升序读取游标之后的一页数据
+fn load_page(
+    conn: &Connection,
+    source: BackfillSource,
+    after: Option<&str>,
+    limit: usize,
+) -> rusqlite::Result<Vec<BackfillItem>> {
+    match source {
+        BackfillSource::Chat => {
+            if !table_exists(conn, "chat_messages")? {
+                return Ok(Vec::new());
+            }
+            let after_id = after.and_then(|v| v.parse::<i64>().ok()).unwrap_or(0);
+            let mut stmt = conn.prepare(
+                "SELECT id, COALESCE(mistake_id, ''), content, COALESCE(timestamp, '') \
+                 FROM chat_messages \
+                 WHERE role = 'user' AND TRIM(content) <> '' AND id > ?1 \
+                 ORDER BY id LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(rusqlite::params![after_id, limit as i64], |row| {
+                let id: i64 = row.get(0)?;
+                let content: String = row.get(2)?;
+                Ok(BackfillItem {
+                    source_id: id.to_string(),
+                    row: LanceChatRow {
+                        message_id: id.to_string(),
+                        mistake_id: row.get(1)?,
+                        role: "user".to_string(),
+                        timestamp: row.get(3)?,
+                        text: truncate_text(&crate::lance_vector_store::extract_plain_text(
+                            &content,
+                        )),
+                        embedding: Vec::new(),
+                    },
+                })
+            })?;
+            rows.collect()
+        }
+        BackfillSource::Mistakes => {
+            if !table_exists(conn, "mistakes")? {
+                return Ok(Vec::new());
+            }
+            let sql = format!(
+                "SELECT id, COALESCE(user_question, ''), COALESCE(ocr_text, ''), \
+                 COALESCE(updated_at, created_at, '') FROM mistakes \
+                 WHERE TRIM(COALESCE(user_question, '') || COALESCE(ocr_text, '')) <> '' \
+                 AND id > ?1{} ORDER BY id LIMIT ?2",
+                mistake_deleted_filter(conn)?
+            );
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(
+                rusqlite::params![after.unwrap_or_default(), limit as i64],
+                |row| {
+                    let id: String = row.get(0)?;
+                    let question: String = row.get(1)?;
+                    let ocr_text: String = row.get(2)?;
+                    Ok(BackfillItem {
+                        source_id: id.clone(),
+                        row: LanceChatRow {
+                            message_id: format!("{}{}", MISTAKE_ROW_PREFIX, id),
+                            mistake_id: id,
+                            role: MISTAKE_ROW_ROLE.to_string(),
+                            timestamp: row.get(3)?,
+                            text: truncate_text(&format!(
+                                "{}\n{}",
+                                question.trim(),
+                                ocr_text.trim()
+                            )),
+                            embedding: Vec::new(),
+                        },
+                    })
+                },
+            )?;
+            rows.collect()
+        }
+    }
+}
+
+/// 去掉已有向量与提取后为空的行
+fn pending_items(items: Vec<BackfillItem>, existing: &HashSet<String>) -> Vec<BackfillItem> {
+    items
+        .into_iter()
+        .filter(|item| !existing.contains(&item.row.message_id) && !item.row.text.is_empty())
+        .collect()
+}
+
+/// 按重试策略嵌入一批文本；不可重试的错误立即返回
+async fn embed_batch(
+    llm_manager: &LLMManager,
+    texts: Vec<String>,
+    model_config_id: &str,
+) -> Result<Vec<Vec<f32>>> {
+    let policy = llm_manager.embedding_retry_policy();
+    let expected = texts.len();
+    let mut attempt = 0u32;
+    loop {
+        match llm_manager
+            .call_embedding_api(texts.clone(), model_config_id)
+            .await
+        {
+            Ok(embeddings) if embeddings.len() == expected => return Ok(embeddings),
+            Ok(_) => return Err(AppError::llm("嵌入返回数量与文本数量不一致")),
+            Err(e) if is_retryable_embedding_error(&e) && attempt < policy.max_retries => {
+                attempt += 1;
+                let wait = policy.backoff_for(attempt, embedding_retry_after(&e));
+                log::warn!(
+                    "[EmbeddingBackfill] 嵌入失败，{}ms 后第 {} 次重试: {}",
+                    wait.as_millis(),
+                    attempt,
+                    e
+                );
+                tokio::time::sleep(wait).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn chat_message_ids(items: &[BackfillItem]) -> Vec<i64> {
+    items
+        .iter()
+        .filter_map(|item| item.source_id.parse().ok())
+        .collect()
+}
+
+/// 为缺少向量的历史对话消息与错题补齐嵌入
+///
+/// - `scope`：`chat` / `mistakes` / `all`
+/// - `batch_size`：每批嵌入条数，默认 16，最多 64
+/// - `batch_delay_ms`：批间等待毫秒数，默认 500
+///
+/// 同步执行并返回统计；进度同时通过 `embedding-backfill-progress` 事件推送。
+#[tauri::command]
+pub async fn backfill_embeddings(
+    scope: EmbeddingBackfillScope,
+    batch_size: Option<usize>,
+    batch_delay_ms: Option<u64>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<EmbeddingBackfillReport> {
+    let batch_size = batch_size
+        .unwrap_or(DEFAULT_BATCH_SIZE)
+        .clamp(1, MAX_BATCH_SIZE);
+    let batch_delay = Duration::from_millis(batch_delay_ms.unwrap_or(DEFAULT_BATCH_DELAY_MS));
+
+    let config = state.llm_manager.require_embedding_model().await?;
+    crate::offline_mode::ensure_endpoint_allowed("嵌入模型", &config.base_url)?;
+
+    if BACKFILL_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(AppError::validation("已有嵌入回填任务在进行中"));
+    }
+    let _guard = BackfillGuard;
+
+    let db = state.database.clone();
+    let store = LanceVectorStore::new(db.clone())?;
+    let sources: Vec<BackfillSource> = [BackfillSource::Chat, BackfillSource::Mistakes]
+        .into_iter()
+        .filter(|source| scope.includes(*source))
+        .collect();
+
+    let mut report = EmbeddingBackfillReport::default();
+    {
+        let conn = db
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        for source in &sources {
+            report.total += count_source(&conn, *source)
+                .map_err(|e| AppError::database(format!("统计待回填数据失败: {}", e)))?;
+        }
+    }
+
+    let emit = |source: Option<BackfillSource>, report: &EmbeddingBackfillReport, finished| {
+        let _ = app_handle.emit(
+            BACKFILL_PROGRESS_EVENT,
+            EmbeddingBackfillProgress {
+                source,
+                report: report.clone(),
+                finished,
+            },
+        );
+    };
+    emit(None, &report, false);
+
+    'sources: for source in sources {
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = {
+                let conn = db
+                    .get_conn_safe()
+                    .map_err(|e| AppError::database(e.to_string()))?;
+                load_page(&conn, source, cursor.as_deref(), batch_size)
+                    .map_err(|e| AppError::database(format!("读取待回填数据失败: {}", e)))?
+            };
+            let Some(last) = page.last() else {
+                break;
+            };
+            cursor = Some(last.source_id.clone());
+
+            let page_len = page.len();
+            let ids: Vec<String> = page
+                .iter()
+                .map(|item| item.row.message_id.clone())
+                .collect();
+            let existing = store.existing_chat_message_ids(&ids).await?;
+            let pending = pending_items(page, &existing);
+            report.skipped += page_len - pending.len();
+            if pending.is_empty() {
+                emit(Some(source), &report, false);
+                continue;
+            }
+
+            let texts: Vec<String> = pending.iter().map(|item| item.row.text.clone()).collect();
+            let outcome = match embed_batch(&state.llm_manager, texts, &config.id).await {
+                Ok(embeddings) => {
+                    let rows: Vec<LanceChatRow> = pending
+                        .iter()
+                        .zip(embeddings)
+                        .map(|(item, embedding)| LanceChatRow {
+                            embedding,
+                            ..item.row.clone()
+                        })
+                        .collect();
+                    store.upsert_chat_embeddings_batch(&rows).await
+                }
+                Err(e) => Err(e),
+            };
+
+            let retry_ids = if source == BackfillSource::Chat {
+                chat_message_ids(&pending)
+            } else {
+                Vec::new()
+            };
+            match outcome {
+                Ok(written) => {
+                    report.processed += written;
+                    if let Err(e) = db.mark_chat_embedding_retry(&retry_ids, false) {
+                        log::warn!("[EmbeddingBackfill] 清除 embedding_retry 失败: {}", e);
+                    }
+                }
+                Err(e) => {
+                    report.failed += pending.len();
+                    if let Err(me) = db.mark_chat_embedding_retry(&retry_ids, true) {
+                        log::warn!("[EmbeddingBackfill] 标记 embedding_retry 失败: {}", me);
+                    }
+                    log::warn!(
+                        "[EmbeddingBackfill] {:?} 批次嵌入失败（{} 条）: {}",
+                        source,
+                        pending.len(),
+                        e
+                    );
+                    // 认证失败、配置错误等重试无益，后续批次同样会失败
+                    if !is_retryable_embedding_error(&e) {
+                        report.error = Some(e.message);
+                        break 'sources;
+                    }
+                }
+            }
+            emit(Some(source), &report, false);
+            tokio::time::sleep(batch_delay).await;
+        }
+    }
+
+    log::info!(
+        "[EmbeddingBackfill] 回填结束: 共 {} 条，新增 {}，跳过 {}，失败 {}",
+        report.total,
+        report.processed,
+        report.skipped,
+        report.failed
+    );
+    emit(None, &report, true);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn setup() -> Connection {
+        let conn = migrated_conn().unwrap();
+        for (id, question, ocr_text, updated_at) in [
+            ("m1", "求导", "f(x)=x^2", "u1"),
+            ("m2", "", "", "u2"),
+            ("m3", "积分", "", "u3"),
+            ("m4", "已删除", "", "u4"),
+        ] {
+            insert_mistake(
+                &conn,
+                id,
+                &[
+                    ("user_question", &question),
+                    ("ocr_text", &ocr_text),
+                    ("updated_at", &updated_at),
+                ],
+            )
+            .unwrap();
+        }
+        conn.execute_batch(
+            "UPDATE mistakes SET deleted_at = 'd4' WHERE id = 'm4';
+             INSERT INTO chat_messages (id, mistake_id, role, content, timestamp) VALUES
+                 (1, 'm1', 'user', '为什么选 B？', 't1'),
+                 (2, 'm1', 'assistant', '因为……', 't2'),
+                 (3, 'm1', 'user', '   ', 't3'),
+                 (4, 'm2', 'user', '[{\"type\":\"text\",\"text\":\"再讲一遍\"}]', 't4'),
+                 (5, 'm2', 'user', '换一道题', 't5');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn ids(items: &[BackfillItem]) -> Vec<&str> {
+        items.iter().map(|i| i.row.message_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_pages_follow_cursor_and_skip_empty_rows() {
+        let conn = setup();
+        assert_eq!(count_source(&conn, BackfillSource::Chat).unwrap(), 3);
+        assert_eq!(count_source(&conn, BackfillSource::Mistakes).unwrap(), 2);
+
+        let first = load_page(&conn, BackfillSource::Chat, None, 2).unwrap();
+        assert_eq!(ids(&first), vec!["1", "4"]);
+        assert_eq!(first[1].row.text, "再讲一遍");
+        let second = load_page(&conn, BackfillSource::Chat, Some("4"), 2).unwrap();
+        assert_eq!(ids(&second), vec!["5"]);
+        assert!(load_page(&conn, BackfillSource::Chat, Some("5"), 2)
+            .unwrap()
+            .is_empty());
+
+        let mistakes = load_page(&conn, BackfillSource::Mistakes, None, 10).unwrap();
+        assert_eq!(ids(&mistakes), vec!["mistake:m1", "mistake:m3"]);
+        assert_eq!(mistakes[0].row.text, "求导\nf(x)=x^2");
+        assert_eq!(mistakes[0].row.role, MISTAKE_ROW_ROLE);
+        assert_eq!(mistakes[1].row.timestamp, "u3");
+    }
+
+    #[test]
+    fn test_pending_items_skips_already_embedded() {
+        let conn = setup();
+        let page = load_page(&conn, BackfillSource::Chat, None, 10).unwrap();
+        let existing: HashSet<String> = ["4".to_string()].into_iter().collect();
+        let pending = pending_items(page, &existing);
+        assert_eq!(ids(&pending), vec!["1", "5"]);
+        assert_eq!(chat_message_ids(&pending), vec![1, 5]);
+
+        assert!(EmbeddingBackfillScope::All.includes(BackfillSource::Mistakes));
+        assert!(!EmbeddingBackfillScope::Chat.includes(BackfillSource::Mistakes));
+    }
+}
@@ -0,0 +1,93 @@
+//! 向量导出命令
+//!
+//! 只读地导出错题对话向量与知识库块向量（LanceDB），供外部聚类分析。
+//! 扫描按批进行并逐条写盘，大语料不会整体载入内存。
+
+use crate::commands::AppState;
+use crate::embedding_export::{
+    EmbeddingExportFormat, EmbeddingExportHeader, EmbeddingExportSummary, EmbeddingExportWriter,
+};
+use crate::lance_vector_store::{EmbeddingScanFilter, LanceVectorStore};
+use crate::models::AppError;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingExportScope {
+    /// 错题对话消息向量
+    Chat,
+    /// 知识库文档块向量
+    Kb,
+    All,
+}
+
+impl EmbeddingExportScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Chat => "chat",
+            Self::Kb => "kb",
+            Self::All => "all",
+        }
+    }
+}
+
+fn non_empty(ids: Option<Vec<String>>) -> Option<Vec<String>> {
+    ids.filter(|ids| !ids.is_empty())
+}
+
+/// 导出向量到 `output_path`
+///
+/// - `sub_library_ids`：限定知识库分库
+/// - `mistake_ids`：限定错题（对话向量按错题归属）
+/// - `dimension`：限定向量维度；库中存在多种维度时导出需指定
+#[tauri::command]
+pub async fn export_embeddings(
+    scope: EmbeddingExportScope,
+    format: EmbeddingExportFormat,
+    output_path: String,
+    sub_library_ids: Option<Vec<String>>,
+    mistake_ids: Option<Vec<String>>,
+    dimension: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<EmbeddingExportSummary> {
+    let output_path = PathBuf::from(output_path.trim());
+    if output_path.as_os_str().is_empty() {
+        return Err(AppError::validation("导出路径不能为空"));
+    }
+
+    // 模型信息尽力而为：未配置嵌入模型时仍允许导出已有向量
+    let model = state.llm_manager.get_embedding_model_config().await.ok();
+    let header = EmbeddingExportHeader {
+        scope: scope.as_str().to_string(),
+        embedding_model_id: model.as_ref().map(|m| m.model.clone()),
+        embedding_model_config_id: model.as_ref().map(|m| m.id.clone()),
+        exported_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let filter = EmbeddingScanFilter {
+        include_kb: scope != EmbeddingExportScope::Chat,
+        include_chat: scope != EmbeddingExportScope::Kb,
+        sub_library_ids: non_empty(sub_library_ids),
+        mistake_ids: non_empty(mistake_ids),
+        dimension,
+    };
+
+    let store = LanceVectorStore::new(state.database.clone())?;
+    let mut writer = EmbeddingExportWriter::create(&output_path, format, header)?;
+    let scanned = store
+        .scan_embeddings_for_export(&filter, |row| writer.write(&row))
+        .await;
+    match scanned.and_then(|_| writer.finish()) {
+        Ok(summary) => Ok(summary),
+        Err(e) => {
+            let _ = std::fs::remove_file(&output_path);
+            if format == EmbeddingExportFormat::Npy {
+                let _ = std::fs::remove_file(output_path.with_extension("meta.jsonl"));
+            }
+            Err(e)
+        }
+    }
+}
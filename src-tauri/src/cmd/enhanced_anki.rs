@@ -2,13 +2,19 @@
 //!
 //! 从 commands.rs 拆分：增强版文档处理、制卡、记忆提取
 
-use crate::commands::{export_cards_as_apkg_with_template, AppState};
+use crate::anki_field_selection::{self, CardFieldSelection};
+use crate::anki_raw_output::{self, RawOutputCaptureSettings};
+use crate::anki_task_cleanup::{self, TaskCleanupSettings};
+use crate::commands::{
+    export_cards_as_apkg_with_template, resolve_generation_template, AppState,
+};
+use crate::database::{DocumentTaskPruneReport, TaskRawOutput};
 use crate::models::{
     AnkiDocumentGenerationRequest, AnkiGenerationOptions, AppError, MemoryCandidate,
 };
 use rusqlite::params;
 use serde::{Deserialize, Serialize};
-use tauri::{State, Window};
+use tauri::{Emitter, State, Window};
 
 type Result<T> = std::result::Result<T, AppError>;
 
@@ -23,6 +29,7 @@ pub async fn start_enhanced_document_processing(
     document_content: String,
     original_document_name: String,
     options: AnkiGenerationOptions,
+    subject: Option<String>,
     window: Window,
     state: State<'_, AppState>,
 ) -> Result<String> {
@@ -32,6 +39,11 @@ pub async fn start_enhanced_document_processing(
         document_content.len()
     );
 
+    // 未指定模板时回退到科目默认 / 全局默认模板；模板已删除则直接报错
+    let mut options = options;
+    let applied_template =
+        resolve_generation_template(&state.database, &mut options, subject.as_deref())?;
+
     // 创建增强ANKI服务实例
     let enhanced_service = crate::enhanced_anki_service::EnhancedAnkiService::new(
         state.anki_database.clone(),
@@ -43,13 +55,28 @@ pub async fn start_enhanced_document_processing(
         document_content,
         original_document_name: Some(original_document_name),
         options: Some(options),
+        subject,
     };
 
     // 开始处理
     let document_id = enhanced_service
-        .start_document_processing(request, window)
+        .start_document_processing(request, window.clone())
         .await?;
 
+    // 返回值保持为 document_id（前端契约），实际模板通过事件告知
+    if let Some(applied) = applied_template {
+        println!(
+            "文档 {} 使用模板: {} ({:?})",
+            document_id, applied.template_id, applied.source
+        );
+        if let Err(e) = window.emit(
+            "anki_template_applied",
+            serde_json::json!({ "documentId": document_id, "appliedTemplate": applied }),
+        ) {
+            println!("发送模板应用事件失败: {}", e);
+        }
+    }
+
     println!("文档处理已启动: {}", document_id);
     Ok(document_id)
 }
@@ -261,6 +288,8 @@ pub async fn delete_document_session(
     Ok(true)
 }
 /// 导出选定内容为APKG文件
+///
+/// `fieldSelection` 指定导出哪些字段，未指定时导出全部字段。
 #[tauri::command]
 #[allow(non_snake_case)] // Tauri 前端传入 camelCase 参数名
 pub async fn export_apkg_for_selection(
@@ -268,6 +297,7 @@ pub async fn export_apkg_for_selection(
     taskIds: Option<Vec<String>>,
     cardIds: Option<Vec<String>>,
     options: AnkiGenerationOptions,
+    fieldSelection: Option<CardFieldSelection>,
     state: State<'_, AppState>,
 ) -> Result<String> {
     println!("📦 导出选定内容为APKG文件");
@@ -285,7 +315,7 @@ pub async fn export_apkg_for_selection(
     );
 
     let export_path = enhanced_service
-        .export_apkg_for_selection(documentId, taskIds, cardIds, options)
+        .export_apkg_for_selection(documentId, taskIds, cardIds, options, fieldSelection)
         .await?;
 
     println!("APKG文件导出成功: {}", export_path);
@@ -349,6 +379,84 @@ pub async fn recover_stuck_document_tasks(state: State<'_, AppState>) -> Result<
     Ok(count)
 }
 
+/// 手动清理 `older_than_days` 天前完成的制卡任务（卡片保留在卡片库中）
+#[tauri::command]
+pub async fn prune_completed_tasks(
+    older_than_days: u32,
+    state: State<'_, AppState>,
+) -> Result<DocumentTaskPruneReport> {
+    anki_task_cleanup::prune_completed_tasks(&state.anki_database, older_than_days)
+}
+
+/// 获取已完成任务自动清理配置
+#[tauri::command]
+pub async fn get_task_cleanup_settings(state: State<'_, AppState>) -> Result<TaskCleanupSettings> {
+    Ok(anki_task_cleanup::load_task_cleanup_settings(
+        &state.database,
+    ))
+}
+
+/// 保存已完成任务自动清理配置（下次启动时生效）
+#[tauri::command]
+pub async fn save_task_cleanup_settings(
+    settings: TaskCleanupSettings,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    settings.validate().map_err(AppError::validation)?;
+    let raw = serde_json::to_string(&settings)
+        .map_err(|e| AppError::internal(format!("序列化任务清理配置失败: {}", e)))?;
+    state
+        .database
+        .save_setting(anki_task_cleanup::TASK_CLEANUP_SETTINGS_KEY, &raw)
+        .map_err(|e| AppError::database(format!("保存任务清理配置失败: {}", e)))
+}
+
+/// 获取任务保存的模型原始输出（需开启原始输出捕获，仅失败或有错误卡片的任务会保存）
+#[tauri::command]
+pub async fn get_task_raw_output(
+    task_id: String,
+    state: State<'_, AppState>,
+) -> Result<Option<TaskRawOutput>> {
+    state
+        .anki_database
+        .get_task_raw_output(&task_id)
+        .map_err(|e| AppError::database(format!("读取原始输出失败: {}", e)))
+}
+
+/// 清空所有任务保存的模型原始输出，返回清空的任务数
+#[tauri::command]
+pub async fn clear_task_raw_outputs(state: State<'_, AppState>) -> Result<usize> {
+    state
+        .anki_database
+        .clear_task_raw_outputs()
+        .map_err(|e| AppError::database(format!("清空原始输出失败: {}", e)))
+}
+
+/// 获取原始输出捕获配置
+#[tauri::command]
+pub async fn get_raw_output_capture_settings(
+    state: State<'_, AppState>,
+) -> Result<RawOutputCaptureSettings> {
+    Ok(anki_raw_output::load_raw_output_capture_settings(
+        &state.database,
+    ))
+}
+
+/// 保存原始输出捕获配置（对之后开始的任务生效）
+#[tauri::command]
+pub async fn save_raw_output_capture_settings(
+    settings: RawOutputCaptureSettings,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    settings.validate().map_err(AppError::validation)?;
+    let raw = serde_json::to_string(&settings)
+        .map_err(|e| AppError::internal(format!("序列化原始输出捕获配置失败: {}", e)))?;
+    state
+        .database
+        .save_setting(anki_raw_output::RAW_OUTPUT_CAPTURE_SETTINGS_KEY, &raw)
+        .map_err(|e| AppError::database(format!("保存原始输出捕获配置失败: {}", e)))
+}
+
 /// 🔧 Phase 1: 按 document_id 汇总任务列表（任务管理页面）
 #[tauri::command]
 pub async fn list_document_sessions(
@@ -431,6 +539,14 @@ pub async fn export_anki_cards(
 
     let format = request.format.trim().to_lowercase();
     if format == "json" {
+        let mut cards = cards;
+        let template_fields =
+            anki_field_selection::template_fields(&state.database, request.template_id.as_deref());
+        anki_field_selection::apply_to_cards(
+            &mut cards,
+            request.field_selection.as_ref(),
+            &template_fields,
+        )?;
         let filename = format!(
             "anki_cards_{}.json",
             chrono::Utc::now().format("%Y%m%d%H%M%S")
@@ -466,6 +582,7 @@ pub async fn export_anki_cards(
         deck_name,
         note_type,
         request.template_id.clone(),
+        request.field_selection.clone(),
         state,
     )
     .await?;
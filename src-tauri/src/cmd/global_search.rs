@@ -0,0 +1,534 @@
+//! 全局搜索命令
+//!
+//! `global_search` 是应用的统一搜索入口：同一关键词并发检索错题、卡片库、错题对话与知识库
+//! 文档分块（关键词匹配，不调用嵌入模型），合并为带来源标记与相关度的结果列表。
+//! 知识库同时检索 VFS 索引分块与尚未迁移的旧知识库分块。
+//! - 各来源共用同一时限，超时或出错的来源跳过并在结果中注明，不影响其他来源；
+//! - 相关度按关键词在各字段中的出现次数与占比计算，不同来源之间可以直接比较；
+//! - 同一错题对话只保留得分最高的一条消息，内容相同的知识库分块只保留一条；
+//! - 按 `offset` / `limit` 分页，每个来源最多取 `offset + limit` 条参与合并。
+
+use crate::commands::AppState;
+use crate::database::{
+    ChatMessageSearchHit, Database, KnowledgeChunkSearchHit, MistakeSearchHit, TagMatchMode,
+};
+use crate::models::{AnkiLibraryCard, AppError};
+use crate::vfs::indexing::{VfsSearchResult, VfsSearchService};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+/// 每个来源参与合并的最大条数，限制深分页的开销
+const MAX_FETCH_PER_SOURCE: usize = 200;
+/// 全部来源的总时限
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(3);
+/// 摘要中关键词前后保留的字符数
+const SNIPPET_RADIUS: usize = 40;
+/// SQL 已确认命中但可展示字段中没有关键词（如只命中 OCR 文本）时的最低得分
+const MIN_MATCH_SCORE: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GlobalSearchScope {
+    Mistakes,
+    Cards,
+    ChatMessages,
+    KnowledgeBase,
+}
+
+impl GlobalSearchScope {
+    const ALL: [Self; 4] = [
+        Self::Mistakes,
+        Self::Cards,
+        Self::ChatMessages,
+        Self::KnowledgeBase,
+    ];
+}
+
+/// 各来源的原始结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "source", content = "data", rename_all = "snake_case")]
+pub enum GlobalSearchItem {
+    Mistake(MistakeSearchHit),
+    Card(AnkiLibraryCard),
+    ChatMessage(ChatMessageSearchHit),
+    /// 旧知识库（rag_documents）中的分块
+    KnowledgeChunk(KnowledgeChunkSearchHit),
+    /// VFS 索引中的分块
+    VfsChunk(VfsSearchResult),
+}
+
+impl GlobalSearchItem {
+    pub fn scope(&self) -> GlobalSearchScope {
+        match self {
+            Self::Mistake(_) => GlobalSearchScope::Mistakes,
+            Self::Card(_) => GlobalSearchScope::Cards,
+            Self::ChatMessage(_) => GlobalSearchScope::ChatMessages,
+            Self::KnowledgeChunk(_) | Self::VfsChunk(_) => GlobalSearchScope::KnowledgeBase,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalSearchHit {
+    /// 相关度（0~1）
+    pub score: f32,
+    pub title: String,
+    /// 关键词附近的内容片段
+    pub snippet: String,
+    pub item: GlobalSearchItem,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalSearchSourceError {
+    pub scope: GlobalSearchScope,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalSearchPage {
+    pub items: Vec<GlobalSearchHit>,
+    /// 去重后参与合并的结果数（每个来源最多取 `offset + limit` 条）
+    pub total: usize,
+    pub has_more: bool,
+    pub offset: usize,
+    pub limit: usize,
+    /// 超时未返回的来源
+    pub timed_out: Vec<GlobalSearchScope>,
+    pub errors: Vec<GlobalSearchSourceError>,
+}
+
+/// 关键词在单个字段中的相关度：出现次数（递减收益）与关键词占字段长度的比例
+fn field_relevance(query: &str, text: &str) -> f32 {
+    let text = text.to_lowercase();
+    let count = text.matches(query).count();
+    if count == 0 {
+        return 0.0;
+    }
+    let frequency = count as f32 / (count as f32 + 1.0);
+    let coverage = (query.chars().count() * count) as f32 / text.chars().count().max(1) as f32;
+    0.6 * frequency + 0.4 * coverage.min(1.0)
+}
+
+/// 按字段权重取最高的字段相关度
+fn keyword_relevance(query: &str, fields: &[(&str, f32)]) -> f32 {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return 0.0;
+    }
+    fields
+        .iter()
+        .map(|(text, weight)| weight * field_relevance(&query, text))
+        .fold(MIN_MATCH_SCORE, f32::max)
+}
+
+/// 截取关键词附近的片段；未命中时取开头
+fn snippet(text: &str, query: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = text.to_lowercase().chars().collect();
+    let needle: Vec<char> = query.trim().to_lowercase().chars().collect();
+    // 大小写转换改变字符数时无法对应位置，退化为取开头
+    let position = (lower.len() == chars.len() && !needle.is_empty())
+        .then(|| {
+            lower
+                .windows(needle.len())
+                .position(|w| w == needle.as_slice())
+        })
+        .flatten()
+        .unwrap_or(0);
+    let start = position.saturating_sub(SNIPPET_RADIUS);
+    let end = (position + needle.len() + SNIPPET_RADIUS).min(chars.len());
+    let mut out: String = chars[start..end].iter().collect();
+    out = out.split_whitespace().collect::<Vec<_>>().join(" ");
+    if start > 0 {
+        out.insert(0, '…');
+    }
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+/// 待合并的结果；`key` 相同的结果只保留得分最高的一条
+struct Candidate {
+    key: String,
+    hit: GlobalSearchHit,
+}
+
+fn mistake_candidate(query: &str, hit: MistakeSearchHit) -> Candidate {
+    let notes = hit.user_notes.clone().unwrap_or_default();
+    let score = keyword_relevance(query, &[(&hit.user_question, 1.0), (&notes, 0.8)]);
+    let body = if hit.matched_user_notes {
+        &notes
+    } else {
+        &hit.user_question
+    };
+    Candidate {
+        key: hit.id.clone(),
+        hit: GlobalSearchHit {
+            score,
+            title: hit.user_question.clone(),
+            snippet: snippet(body, query),
+            item: GlobalSearchItem::Mistake(hit),
+        },
+    }
+}
+
+fn card_candidate(query: &str, card: AnkiLibraryCard) -> Candidate {
+    let text = card.card.text.clone().unwrap_or_default();
+    let score = keyword_relevance(
+        query,
+        &[
+            (&card.card.front, 1.0),
+            (&card.card.back, 0.8),
+            (&text, 0.8),
+        ],
+    );
+    let body = [&card.card.front, &card.card.back, &text]
+        .into_iter()
+        .find(|field| field.to_lowercase().contains(&query.trim().to_lowercase()))
+        .unwrap_or(&card.card.front);
+    Candidate {
+        key: card.card.id.clone(),
+        hit: GlobalSearchHit {
+            score,
+            title: card.card.front.clone(),
+            snippet: snippet(body, query),
+            item: GlobalSearchItem::Card(card),
+        },
+    }
+}
+
+fn chat_candidate(query: &str, hit: ChatMessageSearchHit) -> Candidate {
+    let score = keyword_relevance(query, &[(&hit.content, 1.0)]);
+    Candidate {
+        // 同一错题对话只保留一条
+        key: hit.mistake_id.clone(),
+        hit: GlobalSearchHit {
+            score,
+            title: hit.mistake_question.clone(),
+            snippet: snippet(&hit.content, query),
+            item: GlobalSearchItem::ChatMessage(hit),
+        },
+    }
+}
+
+fn knowledge_candidate(query: &str, hit: KnowledgeChunkSearchHit) -> Candidate {
+    let score = keyword_relevance(query, &[(&hit.text, 1.0), (&hit.file_name, 0.8)]);
+    Candidate {
+        // 重复导入的文档内容相同，只保留一条
+        key: hit.text.split_whitespace().collect::<Vec<_>>().join(" "),
+        hit: GlobalSearchHit {
+            score,
+            title: hit.file_name.clone(),
+            snippet: snippet(&hit.text, query),
+            item: GlobalSearchItem::KnowledgeChunk(hit),
+        },
+    }
+}
+
+fn vfs_chunk_candidate(query: &str, hit: VfsSearchResult) -> Candidate {
+    let title = hit.resource_title.clone().unwrap_or_default();
+    let score = keyword_relevance(query, &[(&hit.chunk_text, 1.0), (&title, 0.8)]);
+    Candidate {
+        // 与旧知识库中内容相同的分块（迁移前后各一份）只保留一条
+        key: hit
+            .chunk_text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+        hit: GlobalSearchHit {
+            score,
+            title,
+            snippet: snippet(&hit.chunk_text, query),
+            item: GlobalSearchItem::VfsChunk(hit),
+        },
+    }
+}
+
+/// 去重、按得分排序并分页
+fn merge_candidates(
+    candidates: Vec<Candidate>,
+    offset: usize,
+    limit: usize,
+) -> (Vec<GlobalSearchHit>, usize) {
+    let mut best: HashMap<(GlobalSearchScope, String), GlobalSearchHit> = HashMap::new();
+    for Candidate { key, hit } in candidates {
+        let slot = (hit.item.scope(), key);
+        match best.get(&slot) {
+            Some(existing) if existing.score >= hit.score => {}
+            _ => {
+                best.insert(slot, hit);
+            }
+        }
+    }
+    let mut hits: Vec<GlobalSearchHit> = best.into_values().collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.item.scope().cmp(&b.item.scope()))
+            .then_with(|| a.title.cmp(&b.title))
+    });
+    let total = hits.len();
+    (hits.into_iter().skip(offset).take(limit).collect(), total)
+}
+
+enum SourceOutcome<T> {
+    Done(T),
+    TimedOut,
+    Failed(String),
+}
+
+/// 在阻塞线程池中执行一次检索，超过时限即放弃等待
+async fn run_source<T, F>(deadline: Duration, search: F) -> SourceOutcome<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+{
+    match tokio::time::timeout(deadline, tokio::task::spawn_blocking(search)).await {
+        Err(_) => SourceOutcome::TimedOut,
+        Ok(Err(e)) => SourceOutcome::Failed(e.to_string()),
+        Ok(Ok(Err(e))) => SourceOutcome::Failed(e.to_string()),
+        Ok(Ok(Ok(value))) => SourceOutcome::Done(value),
+    }
+}
+
+/// 仅在选中该来源时检索
+async fn search_scope<F>(
+    scopes: &[GlobalSearchScope],
+    scope: GlobalSearchScope,
+    search: F,
+) -> Option<SourceOutcome<Vec<Candidate>>>
+where
+    F: FnOnce() -> anyhow::Result<Vec<Candidate>> + Send + 'static,
+{
+    if scopes.contains(&scope) {
+        Some(run_source(SEARCH_TIMEOUT, search).await)
+    } else {
+        None
+    }
+}
+
+/// 统一搜索错题、卡片、错题对话与知识库文档
+///
+/// - `scopes`：要检索的来源，为空时检索全部
+/// - `limit`：每页条数，默认 20，最多 100；`offset`：跳过的条数
+#[tauri::command]
+pub async fn global_search(
+    query: String,
+    scopes: Option<Vec<GlobalSearchScope>>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<GlobalSearchPage> {
+    let _timer = crate::command_metrics::start("global_search");
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = offset.unwrap_or(0);
+    let mut page = GlobalSearchPage {
+        offset,
+        limit,
+        ..Default::default()
+    };
+    let query = query.trim().to_string();
+    if query.is_empty() {
+        return Ok(page);
+    }
+    let scopes = scopes
+        .filter(|scopes| !scopes.is_empty())
+        .unwrap_or_else(|| GlobalSearchScope::ALL.to_vec());
+    let fetch = (offset + limit).min(MAX_FETCH_PER_SOURCE) as u32;
+
+    let db: Arc<Database> = state.database.clone();
+    let anki_db: Arc<Database> = state.anki_database.clone();
+    let vfs_db = state.vfs_db.clone();
+    let (mistakes, cards, chat, knowledge) = tokio::join!(
+        search_scope(&scopes, GlobalSearchScope::Mistakes, {
+            let (db, query) = (db.clone(), query.clone());
+            move || {
+                let page =
+                    db.search_mistakes_fulltext(&query, Some(fetch), None, &[], TagMatchMode::All)?;
+                Ok(page
+                    .items
+                    .into_iter()
+                    .map(|hit| mistake_candidate(&query, hit))
+                    .collect())
+            }
+        }),
+        search_scope(&scopes, GlobalSearchScope::Cards, {
+            let query = query.clone();
+            move || {
+                let (cards, _) =
+                    anki_db.list_anki_library_cards(None, None, Some(&query), 1, fetch)?;
+                Ok(cards
+                    .into_iter()
+                    .map(|card| card_candidate(&query, card))
+                    .collect())
+            }
+        }),
+        search_scope(&scopes, GlobalSearchScope::ChatMessages, {
+            let (db, query) = (db.clone(), query.clone());
+            move || {
+                let hits = db.search_chat_messages(&query, Some(fetch))?;
+                Ok(hits
+                    .into_iter()
+                    .map(|hit| chat_candidate(&query, hit))
+                    .collect())
+            }
+        }),
+        search_scope(&scopes, GlobalSearchScope::KnowledgeBase, {
+            let query = query.clone();
+            move || {
+                let mut candidates: Vec<Candidate> = db
+                    .search_knowledge_base_chunks(&query, Some(fetch))?
+                    .into_iter()
+                    .map(|hit| knowledge_candidate(&query, hit))
+                    .collect();
+                if let Some(vfs_db) = vfs_db {
+                    let hits = VfsSearchService::new(vfs_db)
+                        .search_fts_with_resource_info(&query, fetch)?;
+                    candidates.extend(hits.into_iter().map(|hit| vfs_chunk_candidate(&query, hit)));
+                }
+                Ok(candidates)
+            }
+        }),
+    );
+
+    let mut candidates = Vec::new();
+    // 任一来源取满时，合并结果之外可能还有更多
+    let mut saturated = false;
+    for (scope, outcome) in [
+        (GlobalSearchScope::Mistakes, mistakes),
+        (GlobalSearchScope::Cards, cards),
+        (GlobalSearchScope::ChatMessages, chat),
+        (GlobalSearchScope::KnowledgeBase, knowledge),
+    ] {
+        match outcome {
+            Some(SourceOutcome::Done(mut found)) => {
+                saturated |= found.len() >= fetch as usize;
+                candidates.append(&mut found);
+            }
+            Some(SourceOutcome::TimedOut) => {
+                log::warn!("[GlobalSearch] {:?} 检索超时，已跳过", scope);
+                page.timed_out.push(scope);
+            }
+            Some(SourceOutcome::Failed(message)) => {
+                log::warn!("[GlobalSearch] {:?} 检索失败: {}", scope, message);
+                page.errors.push(GlobalSearchSourceError { scope, message });
+            }
+            None => {}
+        }
+    }
+
+    let (items, total) = merge_candidates(candidates, offset, limit);
+    page.has_more = offset + items.len() < total || saturated;
+    page.items = items;
+    page.total = total;
+    Ok(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat_hit(message_id: i64, mistake_id: &str, content: &str) -> ChatMessageSearchHit {
+        ChatMessageSearchHit {
+            message_id,
+            mistake_id: mistake_id.to_string(),
+            role: "assistant".to_string(),
+            content: content.to_string(),
+            timestamp: "2026-01-01T00:00:00Z".to_string(),
+            mistake_question: format!("题目 {}", mistake_id),
+        }
+    }
+
+    fn chunk_hit(chunk_id: &str, text: &str) -> KnowledgeChunkSearchHit {
+        KnowledgeChunkSearchHit {
+            chunk_id: chunk_id.to_string(),
+            document_id: format!("doc-{}", chunk_id),
+            file_name: "讲义.pdf".to_string(),
+            sub_library_id: "default".to_string(),
+            chunk_index: 0,
+            text: text.to_string(),
+        }
+    }
+
+    fn vfs_hit(embedding_id: &str, text: &str) -> VfsSearchResult {
+        VfsSearchResult {
+            embedding_id: embedding_id.to_string(),
+            resource_id: format!("res-{}", embedding_id),
+            chunk_index: 0,
+            chunk_text: text.to_string(),
+            score: 1.0,
+            resource_title: Some("讲义.pdf".to_string()),
+            resource_type: Some("file".to_string()),
+            page_index: None,
+            source_id: None,
+            folder_id: None,
+        }
+    }
+
+    #[test]
+    fn test_relevance_prefers_frequent_and_dense_matches() {
+        let dense = keyword_relevance("导数", &[("导数", 1.0)]);
+        let repeated = keyword_relevance("导数", &[("导数与导数的应用，以及二阶导数", 1.0)]);
+        let sparse =
+            keyword_relevance("导数", &[("这是一道很长的函数题，最后一步需要求导数", 1.0)]);
+        assert!(dense > sparse && repeated > sparse);
+        assert!(dense <= 1.0);
+        // 字段权重与不区分大小写
+        assert!(
+            keyword_relevance("Vector", &[("vector", 0.8)])
+                < keyword_relevance("Vector", &[("vector", 1.0)])
+        );
+        assert_eq!(keyword_relevance("导数", &[("积分", 1.0)]), MIN_MATCH_SCORE);
+    }
+
+    #[test]
+    fn test_snippet_centers_on_first_match() {
+        let text = format!("{}关键词{}", "前".repeat(60), "后".repeat(60));
+        let out = snippet(&text, "关键词");
+        assert!(out.starts_with('…') && out.ends_with('…'));
+        assert!(out.contains("关键词"));
+        assert_eq!(out.chars().count(), 2 + SNIPPET_RADIUS * 2 + 3);
+        assert_eq!(snippet("short  text", "missing"), "short text");
+    }
+
+    #[test]
+    fn test_merge_dedupes_and_paginates() {
+        let query = "受力";
+        let candidates = vec![
+            chat_candidate(query, chat_hit(1, "m1", "先做受力分析")),
+            chat_candidate(query, chat_hit(2, "m1", "受力")),
+            chat_candidate(
+                query,
+                chat_hit(3, "m2", "画出受力图后再列方程，注意摩擦力方向"),
+            ),
+            knowledge_candidate(query, chunk_hit("c1", "受力分析 步骤")),
+            knowledge_candidate(query, chunk_hit("c2", "受力分析\n步骤")),
+            vfs_chunk_candidate(query, vfs_hit("seg-1", "受力分析  步骤")),
+        ];
+        let (page, total) = merge_candidates(candidates, 0, 10);
+        assert_eq!(total, 3);
+        // 同一错题对话只保留得分最高的消息
+        let GlobalSearchItem::ChatMessage(best) = &page[0].item else {
+            panic!("expected chat message first");
+        };
+        assert_eq!(best.message_id, 2);
+        assert!(page.windows(2).all(|w| w[0].score >= w[1].score));
+
+        let candidates = (0..5)
+            .map(|i| knowledge_candidate(query, chunk_hit(&i.to_string(), &format!("受力 {}", i))))
+            .collect();
+        let (page, total) = merge_candidates(candidates, 3, 10);
+        assert_eq!((page.len(), total), (2, 5));
+    }
+}
@@ -0,0 +1,352 @@
+//! 应用日志查看命令
+//!
+//! 提供应用内日志查看能力：读取当前日志尾部、实时流式推送新日志行。
+//! 日志文件由 tauri-plugin-log 写入平台日志目录（文件名 `deep-student.log`），
+//! 插件轮转时会将旧文件改名并新建同名文件，跟随逻辑需据此重新打开活动文件。
+
+use crate::models::AppError;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{watch, Mutex};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 与 lib.rs 中 `TargetKind::LogDir { file_name: Some("deep-student") }` 保持一致
+const ACTIVE_LOG_FILE_NAME: &str = "deep-student.log";
+/// 实时日志事件名
+pub const LOG_STREAM_EVENT: &str = "log_stream";
+
+const DEFAULT_TAIL_LINES: usize = 200;
+const MAX_TAIL_LINES: usize = 5000;
+const DEFAULT_POLL_INTERVAL_MS: u64 = 500;
+const MIN_POLL_INTERVAL_MS: u64 = 100;
+/// 单次轮询最多读取的字节数，避免日志暴涨时一次性推送过大的事件
+const MAX_READ_BYTES_PER_POLL: u64 = 256 * 1024;
+const TAIL_READ_BLOCK: u64 = 8 * 1024;
+
+/// 活跃的日志流：stream_id -> 停止信号
+static LOG_STREAMS: LazyLock<Mutex<HashMap<String, watch::Sender<bool>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogTailResponse {
+    pub path: String,
+    pub lines: Vec<String>,
+    /// 文件是否还有更早的内容未返回
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogStreamPayload {
+    pub stream_id: String,
+    pub lines: Vec<String>,
+    /// 本批次之前检测到日志轮转（已切换到新的活动文件）
+    pub rotated: bool,
+}
+
+fn active_log_path(app: &AppHandle) -> Result<PathBuf> {
+    let dir = app
+        .path()
+        .app_log_dir()
+        .map_err(|e| AppError::file_system(format!("获取日志目录失败: {}", e)))?;
+    Ok(dir.join(ACTIVE_LOG_FILE_NAME))
+}
+
+/// 从文件末尾向前按块读取，返回最后 `n` 行（不含换行符）
+pub(crate) fn tail_lines(path: &Path, n: usize) -> std::io::Result<(Vec<String>, bool)> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    if n == 0 || len == 0 {
+        return Ok((Vec::new(), len > 0));
+    }
+
+    let mut pos = len;
+    let mut buf: Vec<u8> = Vec::new();
+    // 需要 n 个完整行：找到 n+1 个换行（忽略末尾换行）或读到文件头
+    loop {
+        let trailing = usize::from(buf.last() == Some(&b'\n'));
+        let newlines = buf.iter().filter(|&&b| b == b'\n').count() - trailing;
+        if newlines > n || pos == 0 {
+            break;
+        }
+        let step = TAIL_READ_BLOCK.min(pos);
+        pos -= step;
+        file.seek(SeekFrom::Start(pos))?;
+        let mut chunk = vec![0u8; step as usize];
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
+    }
+
+    let text = String::from_utf8_lossy(&buf);
+    let all: Vec<&str> = text.lines().collect();
+    // 非文件头开始时，第一行可能是截断的半行，丢弃
+    let complete = if pos > 0 && !all.is_empty() {
+        &all[1..]
+    } else {
+        &all[..]
+    };
+    let start = complete.len().saturating_sub(n);
+    let truncated = pos > 0 || start > 0;
+    Ok((
+        complete[start..].iter().map(|s| s.to_string()).collect(),
+        truncated,
+    ))
+}
+
+/// 跟随活动日志文件的增量读取器，能识别轮转（文件被替换或截断）
+pub(crate) struct LogFollower {
+    path: PathBuf,
+    offset: u64,
+    /// 尚未遇到换行的字节；按字节缓存，避免多字节字符被读取边界截断后解码成替换符
+    partial: Vec<u8>,
+    #[cfg(unix)]
+    inode: Option<u64>,
+}
+
+impl LogFollower {
+    /// 从当前文件末尾开始跟随（只推送此后新写入的内容）
+    pub(crate) fn from_end(path: PathBuf) -> Self {
+        let meta = std::fs::metadata(&path).ok();
+        Self {
+            offset: meta.as_ref().map(|m| m.len()).unwrap_or(0),
+            #[cfg(unix)]
+            inode: meta.as_ref().map(|m| {
+                use std::os::unix::fs::MetadataExt;
+                m.ino()
+            }),
+            path,
+            partial: Vec::new(),
+        }
+    }
+
+    /// 读取自上次调用以来新增的完整行；返回 (lines, rotated)
+    pub(crate) fn poll(&mut self) -> std::io::Result<(Vec<String>, bool)> {
+        let meta = match std::fs::metadata(&self.path) {
+            Ok(m) => m,
+            // 轮转间隙文件可能暂不存在，下一轮再试
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), false)),
+            Err(e) => return Err(e),
+        };
+
+        let mut rotated = meta.len() < self.offset;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if self.inode.is_some_and(|ino| ino != meta.ino()) {
+                rotated = true;
+            }
+            self.inode = Some(meta.ino());
+        }
+        if rotated {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        if meta.len() == self.offset {
+            return Ok((Vec::new(), rotated));
+        }
+
+        let mut file = std::fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut chunk = Vec::new();
+        file.take(MAX_READ_BYTES_PER_POLL).read_to_end(&mut chunk)?;
+        self.offset += chunk.len() as u64;
+
+        self.partial.extend_from_slice(&chunk);
+        let mut lines = Vec::new();
+        while let Some(idx) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=idx).collect();
+            lines.push(
+                String::from_utf8_lossy(&line)
+                    .trim_end_matches(['\r', '\n'])
+                    .to_string(),
+            );
+        }
+        Ok((lines, rotated))
+    }
+}
+
+/// 读取当前活动日志的最后 N 行
+#[tauri::command]
+pub async fn read_log_tail(lines: Option<usize>, app: AppHandle) -> Result<LogTailResponse> {
+    let n = lines.unwrap_or(DEFAULT_TAIL_LINES).min(MAX_TAIL_LINES);
+    let path = active_log_path(&app)?;
+    if !path.exists() {
+        return Err(AppError::not_found(format!(
+            "日志文件不存在: {}",
+            path.display()
+        )));
+    }
+
+    let read_path = path.clone();
+    let (lines, truncated) = tokio::task::spawn_blocking(move || tail_lines(&read_path, n))
+        .await
+        .map_err(|e| AppError::internal(format!("读取日志任务失败: {}", e)))?
+        .map_err(|e| AppError::file_system(format!("读取日志文件失败: {}", e)))?;
+
+    Ok(LogTailResponse {
+        path: path.to_string_lossy().to_string(),
+        lines,
+        truncated,
+    })
+}
+
+/// 开始实时推送新日志行，返回 stream_id；新行通过 `log_stream` 事件发送
+#[tauri::command]
+pub async fn stream_logs(poll_interval_ms: Option<u64>, app: AppHandle) -> Result<String> {
+    let path = active_log_path(&app)?;
+    let interval = Duration::from_millis(
+        poll_interval_ms
+            .unwrap_or(DEFAULT_POLL_INTERVAL_MS)
+            .max(MIN_POLL_INTERVAL_MS),
+    );
+    let stream_id = uuid::Uuid::new_v4().to_string();
+    let (stop_tx, mut stop_rx) = watch::channel(false);
+    LOG_STREAMS.lock().await.insert(stream_id.clone(), stop_tx);
+
+    let id = stream_id.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut follower = LogFollower::from_end(path);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                changed = stop_rx.changed() => {
+                    if changed.is_err() || *stop_rx.borrow() {
+                        break;
+                    }
+                }
+                _ = ticker.tick() => {
+                    let (lines, rotated) = match follower.poll() {
+                        Ok(r) => r,
+                        Err(e) => {
+                            log::warn!("[logs] 读取日志增量失败: {}", e);
+                            continue;
+                        }
+                    };
+                    if lines.is_empty() && !rotated {
+                        continue;
+                    }
+                    let payload = LogStreamPayload {
+                        stream_id: id.clone(),
+                        lines,
+                        rotated,
+                    };
+                    if let Err(e) = app.emit(LOG_STREAM_EVENT, payload) {
+                        log::warn!("[logs] 推送日志事件失败，停止日志流: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+        LOG_STREAMS.lock().await.remove(&id);
+    });
+
+    Ok(stream_id)
+}
+
+/// 停止实时日志推送
+#[tauri::command]
+pub async fn stop_log_stream(stream_id: String) -> Result<bool> {
+    match LOG_STREAMS.lock().await.remove(&stream_id) {
+        Some(tx) => {
+            let _ = tx.send(true);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_lines(path: &Path, count: usize) {
+        let mut f = std::fs::File::create(path).unwrap();
+        for i in 0..count {
+            writeln!(f, "line {}", i).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_tail_lines_returns_last_n() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.log");
+        write_lines(&path, 5000);
+        let (lines, truncated) = tail_lines(&path, 3).unwrap();
+        assert_eq!(lines, vec!["line 4997", "line 4998", "line 4999"]);
+        assert!(truncated);
+    }
+
+    #[test]
+    fn test_tail_lines_small_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.log");
+        write_lines(&path, 2);
+        let (lines, truncated) = tail_lines(&path, 10).unwrap();
+        assert_eq!(lines, vec!["line 0", "line 1"]);
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_follower_reads_appended_lines_and_buffers_partial() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.log");
+        write_lines(&path, 2);
+        let mut follower = LogFollower::from_end(path.clone());
+
+        let mut f = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        write!(f, "new 1\nnew").unwrap();
+        f.flush().unwrap();
+        let (lines, rotated) = follower.poll().unwrap();
+        assert_eq!(lines, vec!["new 1"]);
+        assert!(!rotated);
+
+        writeln!(f, " 2").unwrap();
+        let (lines, _) = follower.poll().unwrap();
+        assert_eq!(lines, vec!["new 2"]);
+    }
+
+    #[test]
+    fn test_follower_keeps_multibyte_char_split_across_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.log");
+        std::fs::File::create(&path).unwrap();
+        let mut follower = LogFollower::from_end(path.clone());
+
+        let bytes = "错题\n".as_bytes();
+        let mut f = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        // 在“错”字的第二个字节后截断
+        f.write_all(&bytes[..2]).unwrap();
+        f.flush().unwrap();
+        assert!(follower.poll().unwrap().0.is_empty());
+
+        f.write_all(&bytes[2..]).unwrap();
+        f.flush().unwrap();
+        assert_eq!(follower.poll().unwrap().0, vec!["错题"]);
+    }
+
+    #[test]
+    fn test_follower_detects_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.log");
+        write_lines(&path, 50);
+        let mut follower = LogFollower::from_end(path.clone());
+
+        std::fs::rename(&path, dir.path().join("a_old.log")).unwrap();
+        write_lines(&path, 1);
+        let (lines, rotated) = follower.poll().unwrap();
+        assert!(rotated);
+        assert_eq!(lines, vec!["line 0"]);
+    }
+}
@@ -98,6 +98,7 @@ pub async fn test_mcp_websocket(
     _state: State<'_, AppState>,
 ) -> Result<serde_json::Value> {
     let _ = env; // 兼容旧参数（预留环境变量），当前未使用
+    crate::offline_mode::ensure_endpoint_allowed("MCP 连接", &url)?;
     Ok(mcp_test_helpers::test_websocket(url).await)
 }
 
@@ -127,6 +128,7 @@ pub async fn test_mcp_sse(
     } else {
         Some(trimmed)
     };
+    crate::offline_mode::ensure_endpoint_allowed("MCP 连接", &endpoint)?;
     Ok(mcp_test_helpers::test_sse(endpoint, api_key, env).await)
 }
 
@@ -157,6 +159,7 @@ pub async fn test_mcp_http(
     } else {
         Some(trimmed)
     };
+    crate::offline_mode::ensure_endpoint_allowed("MCP 连接", &endpoint)?;
     Ok(mcp_test_helpers::test_http(endpoint, api_key, env).await)
 }
 
@@ -230,6 +233,7 @@ pub async fn test_rmcp_streamable_http(
     url: String,
     api_key: Option<String>,
 ) -> Result<serde_json::Value> {
+    crate::offline_mode::ensure_endpoint_allowed("MCP 连接", &url)?;
     Ok(mcp_test_helpers::test_streamable_http_rmcp(url, api_key).await)
 }
 
@@ -0,0 +1,62 @@
+//! 错题归档命令
+//!
+//! 已掌握的错题可以归档隐藏，保留全部数据与对话记录，随时可取消归档。
+//! 归档与回收站（软删除）相互独立：归档错题不计入活跃统计，但不会被清理。
+
+use crate::commands::AppState;
+use crate::database::{ArchivedMistakeSummary, MistakeArchiveCounts};
+use crate::models::AppError;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[tauri::command]
+pub async fn archive_mistake(id: String, state: State<'_, AppState>) -> Result<()> {
+    state
+        .database
+        .archive_mistake(&id)
+        .map_err(|e| AppError::database(format!("归档错题失败: {}", e)))
+}
+
+#[tauri::command]
+pub async fn unarchive_mistake(id: String, state: State<'_, AppState>) -> Result<()> {
+    state
+        .database
+        .unarchive_mistake(&id)
+        .map_err(|e| AppError::database(format!("取消归档失败: {}", e)))
+}
+
+/// 批量归档，返回本次新归档的数量
+#[tauri::command]
+pub async fn batch_archive_mistakes(
+    ids: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<usize> {
+    state
+        .database
+        .batch_archive_mistakes(&ids)
+        .map_err(|e| AppError::database(format!("批量归档错题失败: {}", e)))
+}
+
+#[tauri::command]
+pub async fn list_archived_mistakes(
+    limit: Option<u32>,
+    offset: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ArchivedMistakeSummary>> {
+    state
+        .database
+        .list_archived_mistakes(limit, offset)
+        .map_err(|e| AppError::database(format!("获取已归档错题失败: {}", e)))
+}
+
+/// 活跃 / 已归档错题数量，供统计页区分展示
+#[tauri::command]
+pub async fn get_mistake_archive_counts(
+    state: State<'_, AppState>,
+) -> Result<MistakeArchiveCounts> {
+    state
+        .database
+        .get_mistake_archive_counts()
+        .map_err(|e| AppError::database(format!("获取错题归档统计失败: {}", e)))
+}
@@ -0,0 +1,480 @@
+//! 错题对话的回合分支（同一提问的多个备选回答）
+//!
+//! 沿用现有回合模型，不新增表结构：同一 `turn_id` 下用户提问为 `turn_seq = 0`，
+//! 每个备选回答占用一个 `turn_seq >= 1`，即分支号。默认展示的分支记录在用户提问
+//! `relations.active_branch_seq` 中；未指定（或指向的分支已删除）时展示最新的分支。
+//!
+//! 新分支由前端按 [`ChatTurnBranches::next_branch_seq`] 写入 `relations.turn_seq` 后照常保存。
+//! 删除与导出均按分支进行；自动总结只读取各回合的当前分支，见 [`load_active_conversation`]。
+
+use crate::commands::AppState;
+use crate::models::{AppError, DeleteChatTurnResult};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 用户提问 `relations` 中记录当前分支的键
+pub const ACTIVE_BRANCH_KEY: &str = "active_branch_seq";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatBranchMessage {
+    pub id: i64,
+    pub stable_id: Option<String>,
+    pub content: String,
+    pub thinking_content: Option<String>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatBranch {
+    /// 分支号（即助手消息的 `turn_seq`）
+    pub branch_seq: i64,
+    pub is_active: bool,
+    /// 分支内的助手消息（工具调用续写时可能有多条）
+    pub messages: Vec<ChatBranchMessage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatTurnBranches {
+    pub turn_id: String,
+    pub user_message: Option<ChatBranchMessage>,
+    pub active_branch_seq: Option<i64>,
+    /// 新增备选回答时应使用的分支号
+    pub next_branch_seq: i64,
+    pub branches: Vec<ChatBranch>,
+}
+
+impl ChatTurnBranches {
+    fn active_branch(&self) -> Option<&ChatBranch> {
+        self.branches.iter().find(|b| b.is_active)
+    }
+}
+
+fn db_err(e: rusqlite::Error) -> AppError {
+    AppError::database(format!("读写对话分支失败: {}", e))
+}
+
+/// 当前分支：记录的分支仍存在时使用之，否则取最新分支
+pub fn resolve_active_branch(recorded: Option<i64>, branch_seqs: &[i64]) -> Option<i64> {
+    recorded
+        .filter(|seq| branch_seqs.contains(seq))
+        .or_else(|| branch_seqs.iter().copied().max())
+}
+
+fn recorded_active_branch(relations: Option<&str>) -> Option<i64> {
+    let value: Value = serde_json::from_str(relations?).ok()?;
+    value.get(ACTIVE_BRANCH_KEY)?.as_i64()
+}
+
+/// 按回合分组读取对话（回合顺序按首条消息时间）
+///
+/// 未分配 `turn_id` 的历史消息各自视为一个回合，保证旧数据不丢失。
+pub(crate) fn load_turn_branches(
+    conn: &Connection,
+    mistake_id: &str,
+) -> Result<Vec<ChatTurnBranches>> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, role, content, thinking_content, timestamp, stable_id, \
+                    turn_id, turn_seq, relations \
+             FROM chat_messages \
+             WHERE mistake_id = ?1 AND role IN ('user', 'assistant') \
+             ORDER BY timestamp ASC, id ASC",
+        )
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map(params![mistake_id], |row| {
+            Ok((
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(6)?,
+                row.get::<_, Option<i64>>(7)?,
+                row.get::<_, Option<String>>(8)?,
+                ChatBranchMessage {
+                    id: row.get(0)?,
+                    content: row.get(2)?,
+                    thinking_content: row.get(3)?,
+                    timestamp: row.get(4)?,
+                    stable_id: row.get(5)?,
+                },
+            ))
+        })
+        .map_err(db_err)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(db_err)?;
+
+    let mut turns: Vec<(ChatTurnBranches, Option<i64>)> = Vec::new();
+    for (role, turn_id, turn_seq, relations, message) in rows {
+        let turn_id = turn_id
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| format!("legacy-{}", message.id));
+        let idx = match turns.iter().position(|(t, _)| t.turn_id == turn_id) {
+            Some(idx) => idx,
+            None => {
+                turns.push((
+                    ChatTurnBranches {
+                        turn_id: turn_id.clone(),
+                        user_message: None,
+                        active_branch_seq: None,
+                        next_branch_seq: 1,
+                        branches: Vec::new(),
+                    },
+                    None,
+                ));
+                turns.len() - 1
+            }
+        };
+        let (turn, recorded) = &mut turns[idx];
+        if role == "user" {
+            *recorded = recorded_active_branch(relations.as_deref());
+            turn.user_message = Some(message);
+            continue;
+        }
+        let seq = turn_seq.filter(|s| *s >= 1).unwrap_or(1);
+        match turn.branches.iter_mut().find(|b| b.branch_seq == seq) {
+            Some(branch) => branch.messages.push(message),
+            None => turn.branches.push(ChatBranch {
+                branch_seq: seq,
+                is_active: false,
+                messages: vec![message],
+            }),
+        }
+    }
+
+    Ok(turns
+        .into_iter()
+        .map(|(mut turn, recorded)| {
+            turn.branches.sort_by_key(|b| b.branch_seq);
+            let seqs: Vec<i64> = turn.branches.iter().map(|b| b.branch_seq).collect();
+            turn.active_branch_seq = resolve_active_branch(recorded, &seqs);
+            turn.next_branch_seq = seqs.iter().max().map_or(1, |max| max + 1);
+            for branch in &mut turn.branches {
+                branch.is_active = Some(branch.branch_seq) == turn.active_branch_seq;
+            }
+            turn
+        })
+        .collect())
+}
+
+/// 按当前分支展开的线性对话 (role, content)，供自动总结等只需单一历史的场景使用
+pub(crate) fn load_active_conversation(
+    conn: &Connection,
+    mistake_id: &str,
+) -> Result<Vec<(String, String)>> {
+    let mut conversation = Vec::new();
+    for turn in load_turn_branches(conn, mistake_id)? {
+        if let Some(user) = &turn.user_message {
+            conversation.push(("user".to_string(), user.content.clone()));
+        }
+        if let Some(branch) = turn.active_branch() {
+            conversation.extend(
+                branch
+                    .messages
+                    .iter()
+                    .map(|m| ("assistant".to_string(), m.content.clone())),
+            );
+        }
+    }
+    Ok(conversation)
+}
+
+fn branch_exists(conn: &Connection, mistake_id: &str, turn_id: &str, seq: i64) -> Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(1) FROM chat_messages \
+         WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq = ?3 AND role = 'assistant'",
+        params![mistake_id, turn_id, seq],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count > 0)
+    .map_err(db_err)
+}
+
+/// 改写用户提问 `relations` 中的当前分支（None 为清除）
+fn write_active_branch(
+    conn: &Connection,
+    mistake_id: &str,
+    turn_id: &str,
+    seq: Option<i64>,
+) -> Result<()> {
+    let user_row: Option<(i64, Option<String>)> = conn
+        .query_row(
+            "SELECT id, relations FROM chat_messages \
+             WHERE mistake_id = ?1 AND turn_id = ?2 AND role = 'user' \
+             ORDER BY id ASC LIMIT 1",
+            params![mistake_id, turn_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(db_err)?;
+    let Some((user_id, relations)) = user_row else {
+        return Err(AppError::not_found(format!("回合不存在: {}", turn_id)));
+    };
+    let mut relations = relations
+        .and_then(|raw| serde_json::from_str::<Value>(&raw).ok())
+        .filter(Value::is_object)
+        .unwrap_or_else(|| json!({}));
+    if let Some(obj) = relations.as_object_mut() {
+        match seq {
+            Some(seq) => obj.insert(ACTIVE_BRANCH_KEY.to_string(), json!(seq)),
+            None => obj.remove(ACTIVE_BRANCH_KEY),
+        };
+    }
+    conn.execute(
+        "UPDATE chat_messages SET relations = ?1 WHERE id = ?2",
+        params![relations.to_string(), user_id],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+pub(crate) fn set_active_branch(
+    conn: &Connection,
+    mistake_id: &str,
+    turn_id: &str,
+    branch_seq: i64,
+) -> Result<()> {
+    if !branch_exists(conn, mistake_id, turn_id, branch_seq)? {
+        return Err(AppError::not_found(format!(
+            "回合 {} 不存在分支 {}",
+            turn_id, branch_seq
+        )));
+    }
+    write_active_branch(conn, mistake_id, turn_id, Some(branch_seq))
+}
+
+/// 删除单个分支；删除的是当前分支时清除记录，回到“最新分支”
+pub(crate) fn delete_branch(
+    conn: &mut Connection,
+    mistake_id: &str,
+    turn_id: &str,
+    branch_seq: i64,
+) -> Result<DeleteChatTurnResult> {
+    if branch_seq < 1 {
+        return Err(AppError::validation(
+            "分支号须大于 0；删除整个回合请使用按回合删除",
+        ));
+    }
+    let tx = conn.transaction().map_err(db_err)?;
+    let deleted = tx
+        .execute(
+            "DELETE FROM chat_messages \
+             WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq = ?3 AND role <> 'user'",
+            params![mistake_id, turn_id, branch_seq],
+        )
+        .map_err(db_err)?;
+    if deleted == 0 {
+        return Err(AppError::not_found(format!(
+            "回合 {} 不存在分支 {}",
+            turn_id, branch_seq
+        )));
+    }
+    let recorded: Option<String> = tx
+        .query_row(
+            "SELECT relations FROM chat_messages \
+             WHERE mistake_id = ?1 AND turn_id = ?2 AND role = 'user' \
+             ORDER BY id ASC LIMIT 1",
+            params![mistake_id, turn_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(db_err)?
+        .flatten();
+    let was_active = recorded_active_branch(recorded.as_deref()) == Some(branch_seq);
+    if was_active {
+        write_active_branch(&tx, mistake_id, turn_id, None)?;
+    }
+    // 与按回合删除一致：清除自动保存签名，避免旧快照被当作未变化而跳过
+    tx.execute(
+        "UPDATE mistakes SET updated_at = ?1, autosave_signature = NULL WHERE id = ?2",
+        params![chrono::Utc::now().to_rfc3339(), mistake_id],
+    )
+    .map_err(db_err)?;
+    tx.commit().map_err(db_err)?;
+
+    Ok(DeleteChatTurnResult {
+        mistake_id: mistake_id.to_string(),
+        turn_id: turn_id.to_string(),
+        deleted_count: deleted,
+        full_turn_deleted: false,
+        note: was_active.then(|| "已删除当前分支，改为展示最新分支".to_string()),
+    })
+}
+
+/// 渲染 Markdown：各回合使用当前分支，`selected` 指定的回合改用给定分支
+pub(crate) fn render_conversation_markdown(
+    turns: &[ChatTurnBranches],
+    selected: Option<(&str, i64)>,
+) -> String {
+    let mut out = String::from("# 错题对话\n");
+    for (idx, turn) in turns.iter().enumerate() {
+        let branch = match selected {
+            Some((turn_id, seq)) if turn_id == turn.turn_id => {
+                turn.branches.iter().find(|b| b.branch_seq == seq)
+            }
+            _ => turn.active_branch(),
+        };
+        out.push_str(&format!("\n## 第 {} 轮\n", idx + 1));
+        if let Some(user) = &turn.user_message {
+            out.push_str(&format!("\n**提问**\n\n{}\n", user.content.trim()));
+        }
+        if let Some(branch) = branch {
+            let label = if turn.branches.len() > 1 {
+                format!(
+                    "**回答**（分支 {}/{}）",
+                    branch.branch_seq,
+                    turn.branches.len()
+                )
+            } else {
+                "**回答**".to_string()
+            };
+            out.push_str(&format!("\n{}\n", label));
+            for message in &branch.messages {
+                out.push_str(&format!("\n{}\n", message.content.trim()));
+            }
+        }
+    }
+    out
+}
+
+/// 读取错题对话的全部回合及其分支
+#[tauri::command]
+pub async fn get_mistake_chat_branches(
+    mistake_id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<ChatTurnBranches>> {
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    load_turn_branches(&conn, &mistake_id)
+}
+
+/// 设置某回合默认展示的分支
+#[tauri::command]
+pub async fn set_active_chat_branch(
+    mistake_id: String,
+    turn_id: String,
+    branch_seq: i64,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    set_active_branch(&conn, &mistake_id, &turn_id, branch_seq)
+}
+
+/// 删除某回合的单个备选回答
+#[tauri::command]
+pub async fn delete_chat_branch(
+    mistake_id: String,
+    turn_id: String,
+    branch_seq: i64,
+    state: State<'_, AppState>,
+) -> Result<DeleteChatTurnResult> {
+    let mut conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    delete_branch(&mut conn, &mistake_id, &turn_id, branch_seq)
+}
+
+/// 导出对话为 Markdown；指定 `turn_id` 与 `branch_seq` 时该回合导出指定分支
+#[tauri::command]
+pub async fn export_mistake_chat_branch(
+    mistake_id: String,
+    turn_id: Option<String>,
+    branch_seq: Option<i64>,
+    state: State<'_, AppState>,
+) -> Result<String> {
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    let turns = load_turn_branches(&conn, &mistake_id)?;
+    let selected = match (turn_id.as_deref(), branch_seq) {
+        (Some(turn_id), Some(seq)) => {
+            let exists = turns
+                .iter()
+                .any(|t| t.turn_id == turn_id && t.branches.iter().any(|b| b.branch_seq == seq));
+            if !exists {
+                return Err(AppError::not_found(format!(
+                    "回合 {} 不存在分支 {}",
+                    turn_id, seq
+                )));
+            }
+            Some((turn_id, seq))
+        }
+        (None, None) => None,
+        _ => {
+            return Err(AppError::validation(
+                "导出指定分支需同时提供 turn_id 与 branch_seq",
+            ))
+        }
+    };
+    Ok(render_conversation_markdown(&turns, selected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn setup_conn() -> Connection {
+        let conn = migrated_conn().unwrap();
+        insert_mistake(&conn, "m1", &[]).unwrap();
+        conn.execute_batch(
+            "INSERT INTO chat_messages (mistake_id, role, content, timestamp, turn_id, turn_seq) VALUES
+                ('m1', 'user', '为什么是 2x？', '2026-01-01T00:00:01Z', 't1', 0),
+                ('m1', 'assistant', '幂函数法则', '2026-01-01T00:00:02Z', 't1', 1),
+                ('m1', 'assistant', '用极限定义', '2026-01-01T00:00:03Z', 't1', 2),
+                ('m1', 'user', '那 x^3 呢？', '2026-01-01T00:00:04Z', 't2', 0),
+                ('m1', 'assistant', '3x^2', '2026-01-01T00:00:05Z', 't2', 1);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_branches_grouped_and_latest_active_by_default() {
+        let conn = setup_conn();
+        let turns = load_turn_branches(&conn, "m1").unwrap();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].branches.len(), 2);
+        assert_eq!(turns[0].active_branch_seq, Some(2));
+        assert_eq!(turns[0].next_branch_seq, 3);
+        assert_eq!(
+            load_active_conversation(&conn, "m1").unwrap()[1].1,
+            "用极限定义"
+        );
+        assert_eq!(resolve_active_branch(Some(5), &[1, 2]), Some(2));
+    }
+
+    #[test]
+    fn test_set_active_and_delete_branch() {
+        let mut conn = setup_conn();
+        set_active_branch(&conn, "m1", "t1", 1).unwrap();
+        assert!(set_active_branch(&conn, "m1", "t1", 9).is_err());
+        let turns = load_turn_branches(&conn, "m1").unwrap();
+        assert_eq!(turns[0].active_branch_seq, Some(1));
+        let markdown = render_conversation_markdown(&turns, None);
+        assert!(markdown.contains("幂函数法则") && !markdown.contains("用极限定义"));
+        let markdown = render_conversation_markdown(&turns, Some(("t1", 2)));
+        assert!(markdown.contains("用极限定义") && markdown.contains("分支 2/2"));
+
+        // 删除当前分支后回到最新分支，提问与其他回合保留
+        let result = delete_branch(&mut conn, "m1", "t1", 1).unwrap();
+        assert_eq!(result.deleted_count, 1);
+        assert!(result.note.is_some());
+        let turns = load_turn_branches(&conn, "m1").unwrap();
+        assert_eq!(turns[0].active_branch_seq, Some(2));
+        assert!(turns[0].user_message.is_some());
+        assert_eq!(turns[1].branches.len(), 1);
+        assert!(delete_branch(&mut conn, "m1", "t1", 0).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! 错题聊天自动保存命令
+//!
+//! 流式过程中的快照经 [`crate::chat_autosave`] 合并后再落盘，
+//! 轮次完成（`is_final`）或取消（`flush_mistake_chat_autosave`）时保证最终写入，
+//! 并通知 [`crate::mistake_auto_summary`] 开始自动总结的空闲计时。
+
+use crate::chat_autosave::{autosave_debounce_interval, PendingChatSnapshot, CHAT_AUTOSAVE};
+use crate::commands::AppState;
+use crate::database::Database;
+use crate::mistake_auto_summary::AUTO_SUMMARY;
+use crate::models::{AppError, ChatMessage};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 写入一份快照；返回是否实际写库（签名未变时跳过）
+fn persist_snapshot(
+    db: &Database,
+    mistake_id: &str,
+    snapshot: &PendingChatSnapshot,
+) -> Result<bool> {
+    db.append_mistake_chat_messages_if_changed(
+        mistake_id,
+        &snapshot.messages,
+        snapshot.chat_category.as_deref(),
+    )
+    .map(|change_set| change_set.is_some())
+    .map_err(|e| AppError::database(format!("自动保存聊天记录失败: {}", e)))
+}
+
+/// 提交聊天快照；流式中按防抖间隔合并，`is_final` 时立即落盘并结束合并
+#[tauri::command]
+pub async fn autosave_mistake_chat(
+    mistake_id: String,
+    messages: Vec<ChatMessage>,
+    chat_category: Option<String>,
+    is_final: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<bool> {
+    let snapshot = PendingChatSnapshot {
+        messages,
+        chat_category,
+    };
+
+    if is_final.unwrap_or(false) {
+        // 最终快照覆盖所有尚未落盘的中间快照
+        CHAT_AUTOSAVE.finish(&mistake_id);
+        let saved = persist_snapshot(&state.database, &mistake_id, &snapshot)?;
+        AUTO_SUMMARY.on_turn_completed(
+            state.database.clone(),
+            state.vfs_db.clone(),
+            state.llm_manager.clone(),
+            &mistake_id,
+        );
+        return Ok(saved);
+    }
+
+    let interval = autosave_debounce_interval(&state.database);
+    let Some(due) = CHAT_AUTOSAVE.stage(&mistake_id, snapshot, interval) else {
+        // 间隔内的快照由定时器落盘，流式停顿时不会一直滞留在内存
+        CHAT_AUTOSAVE.schedule_deferred_flush(state.database.clone(), &mistake_id, interval);
+        return Ok(false);
+    };
+    persist_snapshot(&state.database, &mistake_id, &due).inspect_err(|_| {
+        CHAT_AUTOSAVE.restore(&mistake_id, due.clone());
+    })
+}
+
+/// 取消生成时调用：立即写入尚未落盘的最新快照
+#[tauri::command]
+pub async fn flush_mistake_chat_autosave(
+    mistake_id: String,
+    state: State<'_, AppState>,
+) -> Result<bool> {
+    let saved = match CHAT_AUTOSAVE.finish(&mistake_id) {
+        Some(snapshot) => persist_snapshot(&state.database, &mistake_id, &snapshot)?,
+        None => false,
+    };
+    AUTO_SUMMARY.on_turn_completed(
+        state.database.clone(),
+        state.vfs_db.clone(),
+        state.llm_manager.clone(),
+        &mistake_id,
+    );
+    Ok(saved)
+}
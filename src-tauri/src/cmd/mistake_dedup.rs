@@ -0,0 +1,756 @@
+//! 错题去重与合并
+//!
+//! 反复处理同一份手写/拍照输入会产生内容几乎相同的错题，复习推荐时会被重复计数。
+//! - `find_duplicate_mistakes`：按题干 + OCR 文本的向量相似度把错题聚成重复组；
+//! - `merge_mistakes`：在单个事务内把被合并错题的关联（聊天记录、整理会话、回顾分析、
+//!   试卷会话）改指向保留项，合并标签后删除被合并错题，返回各类关联的改写数量；
+//! - `check_mistake_duplicate`：保存新错题前检查是否已有相同或高度相似的错题，只给出提示，
+//!   是否继续保存、关联为 `duplicate` 或取消由用户决定。
+//!
+//! 相似度阈值保存在设置项 `mistake.duplicate.similarity_threshold`，两个查找命令共用。
+
+use super::mistake_import::{content_hash, existing_content_hashes};
+use super::mistake_relations::purge_mistake_relations;
+use super::mistake_tags::{cosine_similarity, mistake_filter};
+use crate::commands::AppState;
+use crate::database::{has_column, mistake_image_paths, table_exists, Database};
+use crate::models::AppError;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const DUPLICATE_THRESHOLD_KEY: &str = "mistake.duplicate.similarity_threshold";
+
+/// 默认相似度阈值
+const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.92;
+/// 参与比较的近期错题数上限（两两比较为平方复杂度）
+const MAX_DEDUP_CANDIDATES: usize = 500;
+/// 保存前检查时参与向量比较的同科目近期错题数上限
+const MAX_CHECK_CANDIDATES: usize = 100;
+/// 单条错题送入嵌入模型的最大字符数
+const MAX_EMBED_CHARS: usize = 500;
+/// 每批嵌入的文本数
+const EMBED_BATCH_SIZE: usize = 32;
+/// 预览文本长度
+const PREVIEW_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateMistake {
+    pub id: String,
+    pub subject: Option<String>,
+    pub preview: String,
+    pub created_at: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateMistakeGroup {
+    /// 按创建时间升序；第一项为建议保留的错题
+    pub members: Vec<DuplicateMistake>,
+    /// 组内最高的两两相似度
+    pub max_similarity: f32,
+}
+
+/// 合并结果：各类关联改指向保留项的数量
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeMergeResult {
+    pub keep_id: String,
+    pub merged_ids: Vec<String>,
+    /// 合并后的标签
+    pub tags: Vec<String>,
+    pub chat_messages: usize,
+    pub review_sessions: usize,
+    pub review_analyses: usize,
+    pub exam_sessions: usize,
+}
+
+/// 保存前检查发现的疑似重复错题
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PossibleDuplicate {
+    pub candidate_id: String,
+    pub preview: String,
+    /// 内容完全相同时为 1.0
+    pub similarity: f32,
+    /// `exact`（内容哈希相同）或 `embedding`（向量相似度达到阈值）
+    pub match_kind: &'static str,
+}
+
+impl MistakeMergeResult {
+    pub fn total_rewired(&self) -> usize {
+        self.chat_messages + self.review_sessions + self.review_analyses + self.exam_sessions
+    }
+}
+
+fn db_err(e: rusqlite::Error) -> AppError {
+    AppError::database(format!("错题去重数据库操作失败: {}", e))
+}
+
+fn validate_threshold(threshold: f32) -> Result<()> {
+    if (0.0..=1.0).contains(&threshold) {
+        Ok(())
+    } else {
+        Err(AppError::validation("相似度阈值必须在 0 到 1 之间"))
+    }
+}
+
+pub fn load_duplicate_threshold(db: &Database) -> f32 {
+    db.get_setting(DUPLICATE_THRESHOLD_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| raw.trim().parse::<f32>().ok())
+        .filter(|t| validate_threshold(*t).is_ok())
+        .unwrap_or(DEFAULT_SIMILARITY_THRESHOLD)
+}
+
+fn parse_tags(tags_json: &str) -> Vec<String> {
+    serde_json::from_str::<Vec<String>>(tags_json)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// 近期错题及其用于嵌入的文本（空文本的错题不参与比较）
+fn load_candidates(
+    conn: &Connection,
+    subject: Option<&str>,
+    limit: usize,
+) -> rusqlite::Result<Vec<(DuplicateMistake, String)>> {
+    let (where_sql, values) = mistake_filter(conn, subject)?;
+    let sql = format!(
+        "SELECT id, subject, COALESCE(user_question, ''), COALESCE(ocr_text, ''), created_at, \
+         COALESCE(tags, '[]') FROM mistakes {} ORDER BY created_at DESC LIMIT {}",
+        where_sql, limit
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+        let question: String = row.get(2)?;
+        let ocr_text: String = row.get(3)?;
+        let text = format!("{}\n{}", question.trim(), ocr_text.trim())
+            .trim()
+            .chars()
+            .take(MAX_EMBED_CHARS)
+            .collect::<String>();
+        let mistake = DuplicateMistake {
+            id: row.get(0)?,
+            subject: row.get(1)?,
+            preview: text.chars().take(PREVIEW_CHARS).collect(),
+            created_at: row.get(4)?,
+            tags: parse_tags(&row.get::<_, String>(5)?),
+        };
+        Ok((mistake, text))
+    })?;
+    Ok(rows
+        .filter_map(|r| r.ok())
+        .filter(|(_, text)| !text.is_empty())
+        .collect())
+}
+
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// 与查询向量最相似且不低于阈值的候选（下标, 相似度）
+fn closest_above(query: &[f32], vectors: &[Vec<f32>], threshold: f32) -> Option<(usize, f32)> {
+    vectors
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (i, cosine_similarity(query, v)))
+        .filter(|(_, sim)| *sim >= threshold)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// 相似度不低于阈值的错题两两相连，返回连通分量（成员下标, 组内最高相似度）
+fn group_duplicates(vectors: &[Vec<f32>], threshold: f32) -> Vec<(Vec<usize>, f32)> {
+    let mut parent: Vec<usize> = (0..vectors.len()).collect();
+    let mut best: HashMap<usize, f32> = HashMap::new();
+    let mut edges = Vec::new();
+    for i in 0..vectors.len() {
+        for j in (i + 1)..vectors.len() {
+            let sim = cosine_similarity(&vectors[i], &vectors[j]);
+            if sim >= threshold {
+                edges.push((i, sim));
+                let (a, b) = (find_root(&mut parent, i), find_root(&mut parent, j));
+                if a != b {
+                    parent[b] = a;
+                }
+            }
+        }
+    }
+    for (i, sim) in edges {
+        let root = find_root(&mut parent, i);
+        let entry = best.entry(root).or_insert(sim);
+        *entry = entry.max(sim);
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..vectors.len() {
+        let root = find_root(&mut parent, i);
+        groups.entry(root).or_default().push(i);
+    }
+    let mut result: Vec<(Vec<usize>, f32)> = groups
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(root, members)| (members, best.get(&root).copied().unwrap_or(threshold)))
+        .collect();
+    result.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    result
+}
+
+/// 把 JSON 数组列中的被合并 ID 替换为保留 ID（去重保序），返回改写的行数
+fn rewrite_id_array_column(
+    tx: &Transaction<'_>,
+    table: &str,
+    column: &str,
+    keep_id: &str,
+    merge_ids: &HashSet<&str>,
+) -> rusqlite::Result<usize> {
+    if !has_column(tx, table, column)? {
+        return Ok(0);
+    }
+    let rows: Vec<(String, String)> = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT id, {col} FROM {table} WHERE {col} IS NOT NULL AND json_valid({col})",
+            col = column,
+            table = table
+        ))?;
+        let mapped = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        mapped.collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut updated = 0;
+    for (id, raw) in rows {
+        let Ok(ids) = serde_json::from_str::<Vec<String>>(&raw) else {
+            continue;
+        };
+        if !ids.iter().any(|m| merge_ids.contains(m.as_str())) {
+            continue;
+        }
+        let mut seen = HashSet::new();
+        let rewritten: Vec<String> = ids
+            .into_iter()
+            .map(|m| {
+                if merge_ids.contains(m.as_str()) {
+                    keep_id.to_string()
+                } else {
+                    m
+                }
+            })
+            .filter(|m| seen.insert(m.clone()))
+            .collect();
+        let json = serde_json::to_string(&rewritten).unwrap_or_else(|_| "[]".to_string());
+        updated += tx.execute(
+            &format!("UPDATE {} SET {} = ?1 WHERE id = ?2", table, column),
+            params![json, id],
+        )?;
+    }
+    Ok(updated)
+}
+
+/// 在单个事务内合并错题；任何一步失败都会整体回滚
+///
+/// 同时返回被删除错题引用的图片，由调用方在提交后释放去重引用。
+fn merge_mistakes_in_tx(
+    conn: &mut Connection,
+    keep_id: &str,
+    merge_ids: &[String],
+) -> Result<(MistakeMergeResult, Vec<String>)> {
+    let tx = conn.transaction().map_err(db_err)?;
+
+    let tags_of = |id: &str| -> Result<Vec<String>> {
+        tx.query_row(
+            "SELECT COALESCE(tags, '[]') FROM mistakes WHERE id = ?1",
+            params![id],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map_err(db_err)?
+        .map(|raw| parse_tags(&raw))
+        .ok_or_else(|| AppError::not_found(format!("错题不存在: {}", id)))
+    };
+
+    let mut tags = tags_of(keep_id)?;
+    for id in merge_ids {
+        for tag in tags_of(id)? {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+
+    let mut result = MistakeMergeResult {
+        keep_id: keep_id.to_string(),
+        merged_ids: merge_ids.to_vec(),
+        ..Default::default()
+    };
+    let has_sessions = table_exists(&tx, "review_session_mistakes").map_err(db_err)?;
+    for id in merge_ids {
+        result.chat_messages += tx
+            .execute(
+                "UPDATE chat_messages SET mistake_id = ?1 WHERE mistake_id = ?2",
+                params![keep_id, id],
+            )
+            .map_err(db_err)?;
+        if has_sessions {
+            tx.execute(
+                "INSERT OR IGNORE INTO review_session_mistakes (session_id, mistake_id, added_at) \
+                 SELECT session_id, ?1, added_at FROM review_session_mistakes WHERE mistake_id = ?2",
+                params![keep_id, id],
+            )
+            .map_err(db_err)?;
+            result.review_sessions += tx
+                .execute(
+                    "DELETE FROM review_session_mistakes WHERE mistake_id = ?1",
+                    params![id],
+                )
+                .map_err(db_err)?;
+        }
+    }
+
+    let merge_set: HashSet<&str> = merge_ids.iter().map(String::as_str).collect();
+    if table_exists(&tx, "review_analyses").map_err(db_err)? {
+        result.review_analyses =
+            rewrite_id_array_column(&tx, "review_analyses", "mistake_ids", keep_id, &merge_set)
+                .map_err(db_err)?;
+    }
+    if table_exists(&tx, "exam_sheet_sessions").map_err(db_err)? {
+        result.exam_sessions = rewrite_id_array_column(
+            &tx,
+            "exam_sheet_sessions",
+            "linked_mistake_ids",
+            keep_id,
+            &merge_set,
+        )
+        .map_err(db_err)?;
+    }
+
+    let tags_json = serde_json::to_string(&tags).unwrap_or_else(|_| "[]".to_string());
+    tx.execute(
+        "UPDATE mistakes SET tags = ?1, updated_at = ?2, autosave_signature = NULL WHERE id = ?3",
+        params![tags_json, chrono::Utc::now().to_rfc3339(), keep_id],
+    )
+    .map_err(db_err)?;
+    let mut dropped_images = Vec::new();
+    for id in merge_ids {
+        dropped_images.extend(mistake_image_paths(&tx, id).map_err(db_err)?);
+        purge_mistake_relations(&tx, id).map_err(db_err)?;
+        // 向量化数据等随外键级联删除
+        tx.execute("DELETE FROM mistakes WHERE id = ?1", params![id])
+            .map_err(db_err)?;
+    }
+
+    tx.commit().map_err(db_err)?;
+    result.tags = tags;
+    Ok((result, dropped_images))
+}
+
+/// 查找内容相近的重复错题
+///
+/// `similarity_threshold` 默认取设置中的阈值（0.92）；`subject` 为空时在全部错题范围内比较。
+/// 需要配置嵌入模型。
+#[tauri::command]
+pub async fn find_duplicate_mistakes(
+    similarity_threshold: Option<f32>,
+    subject: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<DuplicateMistakeGroup>> {
+    let threshold =
+        similarity_threshold.unwrap_or_else(|| load_duplicate_threshold(&state.database));
+    validate_threshold(threshold)?;
+    let subject = subject
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+
+    let candidates = {
+        let conn = state
+            .database
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        load_candidates(&conn, subject.as_deref(), MAX_DEDUP_CANDIDATES).map_err(db_err)?
+    };
+    if candidates.len() < 2 {
+        return Ok(Vec::new());
+    }
+
+    if !state.llm_manager.has_embedding_model().await {
+        return Err(AppError::configuration("查找重复错题需要先配置嵌入模型"));
+    }
+    let config = state.llm_manager.get_embedding_model_config().await?;
+    let mut vectors = Vec::with_capacity(candidates.len());
+    for batch in candidates.chunks(EMBED_BATCH_SIZE) {
+        let texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+        let embedded = state
+            .llm_manager
+            .call_embedding_api(texts, &config.id)
+            .await?;
+        if embedded.len() != batch.len() {
+            return Err(AppError::llm("嵌入返回数量与错题数量不一致"));
+        }
+        vectors.extend(embedded);
+    }
+
+    let groups = group_duplicates(&vectors, threshold)
+        .into_iter()
+        .map(|(indices, max_similarity)| {
+            let mut members: Vec<DuplicateMistake> = indices
+                .into_iter()
+                .map(|i| candidates[i].0.clone())
+                .collect();
+            members.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+            DuplicateMistakeGroup {
+                members,
+                max_similarity,
+            }
+        })
+        .collect();
+    Ok(groups)
+}
+
+/// 保存前检查的候选：内容完全相同的错题（不限科目），没有时返回同科目近期错题供相似度比较
+fn duplicate_check_candidates(
+    conn: &Connection,
+    question: &str,
+    subject: Option<&str>,
+) -> rusqlite::Result<(Option<String>, Vec<(DuplicateMistake, String)>)> {
+    let exact = existing_content_hashes(conn)?.remove(&content_hash(question));
+    let candidates = match exact {
+        Some(_) => Vec::new(),
+        None => load_candidates(conn, subject, MAX_CHECK_CANDIDATES)?,
+    };
+    Ok((exact, candidates))
+}
+
+/// 保存错题前检查是否已有重复内容，未发现时返回 `None`
+///
+/// 先按内容哈希查找完全相同的错题；没有时若已配置嵌入模型，再与同科目近期错题比较向量相似度。
+/// 嵌入调用失败只记录日志，不影响保存。
+#[tauri::command]
+pub async fn check_mistake_duplicate(
+    question: String,
+    subject: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Option<PossibleDuplicate>> {
+    let question = question.trim().to_string();
+    if question.is_empty() {
+        return Ok(None);
+    }
+    let subject = subject
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+    let threshold = load_duplicate_threshold(&state.database);
+
+    let (exact, candidates) = {
+        let conn = state
+            .database
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        duplicate_check_candidates(&conn, &question, subject.as_deref()).map_err(db_err)?
+    };
+    if let Some(candidate_id) = exact {
+        return Ok(Some(PossibleDuplicate {
+            candidate_id,
+            preview: question.chars().take(PREVIEW_CHARS).collect(),
+            similarity: 1.0,
+            match_kind: "exact",
+        }));
+    }
+    if candidates.is_empty() || !state.llm_manager.has_embedding_model().await {
+        return Ok(None);
+    }
+
+    let config = state.llm_manager.get_embedding_model_config().await?;
+    let query_text: String = question.chars().take(MAX_EMBED_CHARS).collect();
+    let mut vectors = Vec::with_capacity(candidates.len());
+    for (i, batch) in candidates.chunks(EMBED_BATCH_SIZE).enumerate() {
+        let mut texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+        if i == 0 {
+            texts.insert(0, query_text.clone());
+        }
+        let expected = texts.len();
+        match state
+            .llm_manager
+            .call_embedding_api(texts, &config.id)
+            .await
+        {
+            Ok(embedded) if embedded.len() == expected => vectors.extend(embedded),
+            Ok(_) => {
+                log::warn!("[MistakeDedup] 嵌入返回数量不匹配，跳过相似度检查");
+                return Ok(None);
+            }
+            Err(e) => {
+                log::warn!("[MistakeDedup] 生成嵌入失败，跳过相似度检查: {}", e);
+                return Ok(None);
+            }
+        }
+    }
+    let query = vectors.remove(0);
+    Ok(
+        closest_above(&query, &vectors, threshold).map(|(i, similarity)| PossibleDuplicate {
+            candidate_id: candidates[i].0.id.clone(),
+            preview: candidates[i].0.preview.clone(),
+            similarity,
+            match_kind: "embedding",
+        }),
+    )
+}
+
+#[tauri::command]
+pub async fn get_duplicate_threshold(state: State<'_, AppState>) -> Result<f32> {
+    Ok(load_duplicate_threshold(&state.database))
+}
+
+/// 保存重复检测的相似度阈值（0 到 1）
+#[tauri::command]
+pub async fn save_duplicate_threshold(threshold: f32, state: State<'_, AppState>) -> Result<f32> {
+    validate_threshold(threshold)?;
+    state
+        .database
+        .save_setting(DUPLICATE_THRESHOLD_KEY, &threshold.to_string())
+        .map_err(|e| AppError::database(e.to_string()))?;
+    Ok(threshold)
+}
+
+/// 合并重复错题：关联改指向 `keep_id`、合并标签并删除 `merge_ids`
+#[tauri::command]
+pub async fn merge_mistakes(
+    keep_id: String,
+    merge_ids: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<MistakeMergeResult> {
+    let mut seen = HashSet::new();
+    let merge_ids: Vec<String> = merge_ids
+        .into_iter()
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty() && id != &keep_id && seen.insert(id.clone()))
+        .collect();
+    if keep_id.trim().is_empty() || merge_ids.is_empty() {
+        return Err(AppError::validation(
+            "需要指定保留的错题和至少一条待合并错题",
+        ));
+    }
+
+    let mut conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    let (result, dropped_images) = merge_mistakes_in_tx(&mut conn, &keep_id, &merge_ids)?;
+    drop(conn);
+    if let Err(e) = state.file_manager.release_images(&dropped_images) {
+        log::warn!("[MistakeDedup] 释放已合并错题的图片引用失败: {}", e);
+    }
+    super::recommendation_cache::invalidate_recommendation_cache();
+    if let Some(vfs_db) = &state.vfs_db {
+        if let Err(e) = super::mistake_kb_sync::remove_mistake_documents(
+            &state.database,
+            vfs_db,
+            &state.llm_manager,
+            &result.merged_ids,
+        )
+        .await
+        {
+            log::warn!("[MistakeDedup] 移除已合并错题的知识库笔记失败: {}", e);
+        }
+    }
+    log::info!(
+        "[MistakeDedup] 合并 {} 条错题到 {}，改写关联 {} 条",
+        result.merged_ids.len(),
+        result.keep_id,
+        result.total_rewired()
+    );
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn setup_conn() -> Connection {
+        let conn = migrated_conn().unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+        for (id, tags, date) in [
+            ("a", r#"["受力分析"]"#, "2026-01-01"),
+            ("b", r#"["牛顿定律","受力分析"]"#, "2026-01-02"),
+            ("c", "[]", "2026-01-03"),
+        ] {
+            insert_mistake(
+                &conn,
+                id,
+                &[
+                    ("user_question", &"小球受力"),
+                    ("tags", &tags),
+                    ("created_at", &date),
+                    ("updated_at", &date),
+                ],
+            )
+            .unwrap();
+        }
+        conn.execute_batch(
+            "INSERT INTO chat_messages (mistake_id, role, content, timestamp) VALUES
+                ('a', 'user', '1', 'x'), ('b', 'user', '2', 'x'), ('c', 'user', '3', 'x');
+            INSERT INTO review_sessions (id, title, start_date, end_date) VALUES
+                ('s1', '第一轮', 'x', 'x'), ('s2', '第二轮', 'x', 'x');
+            INSERT INTO review_session_mistakes VALUES ('s1', 'a', 'x'), ('s1', 'b', 'x'), ('s2', 'c', 'x');
+            INSERT INTO review_analyses
+                (id, name, created_at, updated_at, mistake_ids, consolidated_input, user_question, status, tags)
+            VALUES
+                ('r1', '回顾', 'x', 'x', '[\"b\",\"a\",\"c\"]', '', '', 'completed', '[]'),
+                ('r2', '回顾', 'x', 'x', '[\"a\"]', '', '', 'completed', '[]');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_load_candidates_scopes_by_subject() {
+        let conn = setup_conn();
+        conn.execute(
+            "UPDATE mistakes SET subject = '物理' WHERE id IN ('a', 'b')",
+            [],
+        )
+        .unwrap();
+        conn.execute("UPDATE mistakes SET subject = '数学' WHERE id = 'c'", [])
+            .unwrap();
+
+        let ids = |subject: Option<&str>| -> Vec<String> {
+            load_candidates(&conn, subject, MAX_DEDUP_CANDIDATES)
+                .unwrap()
+                .into_iter()
+                .map(|(mistake, _)| mistake.id)
+                .collect()
+        };
+        assert_eq!(ids(Some("物理")), vec!["b", "a"]);
+        assert_eq!(ids(None), vec!["c", "b", "a"]);
+        let (physics, _) = &load_candidates(&conn, Some("物理"), 1).unwrap()[0];
+        assert_eq!(physics.subject.as_deref(), Some("物理"));
+    }
+
+    #[test]
+    fn test_duplicate_check_matches_exact_across_subjects_and_embeds_within_subject() {
+        let conn = setup_conn();
+        conn.execute("UPDATE mistakes SET subject = '物理' WHERE id = 'a'", [])
+            .unwrap();
+        conn.execute(
+            "UPDATE mistakes SET subject = '数学', user_question = '函数求导' WHERE id = 'b'",
+            [],
+        )
+        .unwrap();
+
+        let (exact, candidates) =
+            duplicate_check_candidates(&conn, "函数求导", Some("物理")).unwrap();
+        assert_eq!(exact.as_deref(), Some("b"));
+        assert!(candidates.is_empty());
+
+        let (exact, candidates) =
+            duplicate_check_candidates(&conn, "斜面上的小球", Some("物理")).unwrap();
+        assert_eq!(exact, None);
+        let ids: Vec<_> = candidates.iter().map(|(m, _)| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["a"]);
+    }
+
+    #[test]
+    fn test_group_duplicates_links_transitively() {
+        let vectors = vec![
+            vec![1.0, 0.0],
+            vec![0.99, 0.1],
+            vec![0.0, 1.0],
+            vec![0.97, 0.25],
+        ];
+        let groups = group_duplicates(&vectors, 0.95);
+        assert_eq!(groups.len(), 1);
+        let (mut members, best) = groups[0].clone();
+        members.sort();
+        assert_eq!(members, vec![0, 1, 3]);
+        assert!(best > 0.99);
+        assert!(group_duplicates(&vectors, 0.9999).is_empty());
+    }
+
+    #[test]
+    fn test_closest_above_picks_best_match_over_threshold() {
+        let vectors = vec![vec![0.0, 1.0], vec![0.95, 0.3], vec![1.0, 0.05]];
+        let (index, sim) = closest_above(&[1.0, 0.0], &vectors, 0.9).unwrap();
+        assert_eq!(index, 2);
+        assert!(sim > 0.99);
+        assert!(closest_above(&[1.0, 0.0], &vectors, 0.9999).is_none());
+        assert!(closest_above(&[1.0, 0.0], &[], 0.5).is_none());
+        assert!(validate_threshold(1.2).is_err());
+    }
+
+    #[test]
+    fn test_merge_rewires_relationships_in_one_transaction() {
+        let mut conn = setup_conn();
+        conn.execute(
+            "UPDATE mistakes SET question_images = '[\"images/b.png\"]' WHERE id = 'b'",
+            [],
+        )
+        .unwrap();
+        let (merged, dropped_images) =
+            merge_mistakes_in_tx(&mut conn, "a", &["b".to_string(), "c".to_string()]).unwrap();
+        assert_eq!(dropped_images, vec!["images/b.png"]);
+        assert_eq!(merged.tags, vec!["受力分析", "牛顿定律"]);
+        assert_eq!(merged.chat_messages, 2);
+        assert_eq!(merged.review_sessions, 2);
+        assert_eq!(merged.review_analyses, 1);
+        assert_eq!(merged.exam_sessions, 0);
+
+        let remaining: i64 = conn
+            .query_row("SELECT COUNT(*) FROM mistakes", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(remaining, 1);
+        let messages: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM chat_messages WHERE mistake_id = 'a'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(messages, 3);
+        let sessions: Vec<String> = conn
+            .prepare("SELECT session_id FROM review_session_mistakes ORDER BY session_id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(sessions, vec!["s1", "s2"]);
+        let ids: String = conn
+            .query_row(
+                "SELECT mistake_ids FROM review_analyses WHERE id = 'r1'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(ids, "[\"a\"]");
+
+        // 待合并错题不存在时整体回滚
+        let mut conn = setup_conn();
+        assert!(
+            merge_mistakes_in_tx(&mut conn, "a", &["b".to_string(), "missing".to_string()])
+                .is_err()
+        );
+        let messages: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM chat_messages WHERE mistake_id = 'b'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(messages, 1);
+    }
+}
@@ -0,0 +1,933 @@
+//! 错题批量导出命令
+//!
+//! 导出为 JSON 对象数组，字段与 [`super::mistake_import`] 的 JSON 格式一致
+//! （question / answer / subject / tags / images），可直接导入到另一台设备。图片有两种处理方式：
+//! - `inline`（默认）：内联为 `data:` base64，单个文件即可携带全部内容，但体积较大；
+//! - `assetPack`：图片写入导出目录的 `assets/`，JSON 中以相对路径引用，并生成
+//!   `images.manifest` 记录每张图片的 SHA-256 与大小；内容相同的图片只写一份。
+//!
+//! 导入资源包时把 `imageBaseDir` 设为导出目录，导入端按清单校验图片内容。
+//!
+//! 另可导出为便于阅读的 Markdown（`format: "markdown"`）：开头是按科目分组的目录，每道错题
+//! 包含题目、识别文本、标签、图片与完整的对话记录（思考过程折叠显示）。没有图片时只写
+//! `mistakes.md`；有图片时写 `mistakes.zip`，内含 `mistakes.md` 与 `images/`，Markdown 中以
+//! 相对路径引用图片。Markdown 不能再导入，不受导入条数上限限制。
+
+use super::mistake_import::MAX_IMPORT_ROWS;
+use crate::commands::AppState;
+use crate::database::{has_column, Database};
+use crate::file_manager::FileManager;
+use crate::models::AppError;
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tauri::State;
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const EXPORT_JSON_FILE: &str = "mistakes.json";
+pub const IMAGE_MANIFEST_FILE: &str = "images.manifest";
+pub const EXPORT_MARKDOWN_FILE: &str = "mistakes.md";
+pub const EXPORT_MARKDOWN_ARCHIVE: &str = "mistakes.zip";
+const ASSETS_DIR: &str = "assets";
+/// Markdown 压缩包中的图片目录
+const MARKDOWN_IMAGES_DIR: &str = "images";
+/// 目录中错题标题的最大字符数
+const MARKDOWN_TITLE_MAX_CHARS: usize = 40;
+const IMAGE_MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MistakeExportImageMode {
+    /// 内联为 data URI
+    #[default]
+    Inline,
+    /// 写入 `assets/` 并以相对路径引用
+    AssetPack,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MistakeExportFormat {
+    /// 可重新导入的 JSON
+    #[default]
+    Json,
+    /// 便于阅读的 Markdown（含对话记录）
+    #[serde(alias = "md")]
+    Markdown,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MistakeExportOptions {
+    pub format: MistakeExportFormat,
+    /// 仅 JSON 格式使用；Markdown 始终以相对路径引用图片
+    pub image_mode: MistakeExportImageMode,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeExportSummary {
+    pub format: MistakeExportFormat,
+    /// 导出的文件路径（`.json`、`.md` 或 `.zip`）
+    pub output_path: String,
+    /// 导出的 JSON 文件路径（仅 JSON 格式，保留兼容）
+    pub json_path: String,
+    pub exported: usize,
+    /// 不存在或已删除的错题
+    pub missing_mistake_ids: Vec<String>,
+    /// 图片引用总数
+    pub image_references: usize,
+    /// 资源包 / Markdown 压缩包中去重后的图片数（内联模式为 0）
+    pub unique_images: usize,
+    /// 读取失败、未导出的图片（相对路径）
+    pub missing_images: Vec<String>,
+}
+
+/// 单条导出记录，字段名与导入格式一致
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ExportRow {
+    question: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    answer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<String>,
+    tags: Vec<String>,
+    images: Vec<String>,
+}
+
+/// 资源包清单中的单张图片
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageManifestEntry {
+    /// 相对导出目录的路径（`assets/<sha256>.<ext>`）
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+    pub mime: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageManifest {
+    pub version: u32,
+    pub images: Vec<ImageManifestEntry>,
+}
+
+impl ImageManifest {
+    /// 读取目录中的清单；没有清单时返回 None
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(IMAGE_MANIFEST_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let raw = std::fs::read_to_string(&path)
+            .map_err(|e| AppError::file_system(format!("读取图片清单失败: {}", e)))?;
+        let manifest: Self = serde_json::from_str(&raw)
+            .map_err(|e| AppError::validation(format!("图片清单格式无效: {}", e)))?;
+        if manifest.version > IMAGE_MANIFEST_VERSION {
+            return Err(AppError::validation(format!(
+                "不支持的图片清单版本: {}",
+                manifest.version
+            )));
+        }
+        Ok(Some(manifest))
+    }
+
+    /// 按相对路径查找（兼容 Windows 分隔符）
+    pub fn find(&self, reference: &str) -> Option<&ImageManifestEntry> {
+        let normalized = reference.trim().replace('\\', "/");
+        let normalized = normalized.trim_start_matches("./");
+        self.images.iter().find(|entry| entry.path == normalized)
+    }
+}
+
+/// 累积资源包中的图片，按内容哈希去重
+#[derive(Debug)]
+struct AssetPack {
+    /// 图片所在的相对目录
+    dir: &'static str,
+    /// SHA-256 → 清单中的序号
+    by_hash: HashMap<String, usize>,
+    entries: Vec<ImageManifestEntry>,
+}
+
+impl AssetPack {
+    fn new(dir: &'static str) -> Self {
+        Self {
+            dir,
+            by_hash: HashMap::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// 登记一张图片，返回其相对路径与是否需要写入（首次出现）
+    fn add(&mut self, bytes: &[u8], source_path: &Path) -> (String, bool) {
+        let hash = format!("{:x}", Sha256::digest(bytes));
+        if let Some(&index) = self.by_hash.get(&hash) {
+            return (self.entries[index].path.clone(), false);
+        }
+        let extension = source_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase)
+            .unwrap_or_else(|| "png".to_string());
+        let path = format!("{}/{}.{}", self.dir, hash, extension);
+        self.by_hash.insert(hash.clone(), self.entries.len());
+        self.entries.push(ImageManifestEntry {
+            path: path.clone(),
+            sha256: hash,
+            size: bytes.len() as u64,
+            mime: FileManager::infer_mime_from_path(source_path).to_string(),
+        });
+        (path, true)
+    }
+
+    fn into_manifest(self) -> ImageManifest {
+        ImageManifest {
+            version: IMAGE_MANIFEST_VERSION,
+            images: self.entries,
+        }
+    }
+}
+
+/// 导出记录与错题中引用的图片（受管目录中的相对路径）
+fn load_export_row(conn: &Connection, mistake_id: &str) -> rusqlite::Result<Option<ExportRow>> {
+    let deleted_sql = if has_column(conn, "mistakes", "deleted_at")? {
+        " AND deleted_at IS NULL"
+    } else {
+        ""
+    };
+    let row = conn
+        .query_row(
+            &format!(
+                "SELECT COALESCE(user_question, ''), COALESCE(ocr_text, ''), mistake_summary, subject, \
+                        COALESCE(tags, '[]'), COALESCE(question_images, '[]') \
+                 FROM mistakes WHERE id = ?1{}",
+                deleted_sql
+            ),
+            params![mistake_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            },
+        )
+        .optional()?;
+    let non_empty = |value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    Ok(row.map(
+        |(user_question, ocr_text, summary, subject, tags, images)| ExportRow {
+            // 导入只有一个题目字段，提问与 OCR 题干合并导出
+            question: format!("{}\n{}", user_question.trim(), ocr_text.trim())
+                .trim()
+                .to_string(),
+            answer: non_empty(summary),
+            subject: non_empty(subject),
+            tags: serde_json::from_str(&tags).unwrap_or_default(),
+            images: serde_json::from_str(&images).unwrap_or_default(),
+        },
+    ))
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    std::fs::write(path, bytes)
+        .map_err(|e| AppError::file_system(format!("写入 {} 失败: {}", path.display(), e)))
+}
+
+/// 批量导出错题到 `output_dir`
+///
+/// JSON 格式写入 `mistakes.json`，资源包模式同时写入 `assets/` 与 `images.manifest`；
+/// Markdown 格式写入 `mistakes.md`，有图片时改为写入 `mistakes.zip`。
+/// 不存在的错题与读取失败的图片不会中断导出，在结果中列出。
+#[tauri::command]
+pub async fn batch_export_mistakes(
+    mistake_ids: Vec<String>,
+    output_dir: String,
+    options: Option<MistakeExportOptions>,
+    state: State<'_, AppState>,
+) -> Result<MistakeExportSummary> {
+    let options = options.unwrap_or_default();
+    let output_dir = PathBuf::from(output_dir.trim());
+    if output_dir.as_os_str().is_empty() {
+        return Err(AppError::validation("导出目录不能为空"));
+    }
+    if mistake_ids.is_empty() {
+        return Err(AppError::validation("请选择要导出的错题"));
+    }
+    // 超出导入上限的导出文件无法再导入
+    if options.format == MistakeExportFormat::Json && mistake_ids.len() > MAX_IMPORT_ROWS {
+        return Err(AppError::validation(format!(
+            "单次最多导出 {} 道错题",
+            MAX_IMPORT_ROWS
+        )));
+    }
+
+    // 读取图片与写入导出文件都是阻塞 I/O，放到阻塞线程池中执行
+    let db = state.database.clone();
+    let file_manager = state.file_manager.clone();
+    let summary = tokio::task::spawn_blocking(move || match options.format {
+        MistakeExportFormat::Json => export_json(
+            &db,
+            &file_manager,
+            &mistake_ids,
+            &output_dir,
+            options.image_mode,
+        ),
+        MistakeExportFormat::Markdown => {
+            export_markdown(&db, &file_manager, &mistake_ids, &output_dir)
+        }
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("导出错题任务失败: {}", e)))??;
+    log::info!(
+        "[MistakeExport] 导出完成({:?}): {} 道错题，图片引用 {}（去重后 {} 张），缺失图片 {}",
+        summary.format,
+        summary.exported,
+        summary.image_references,
+        summary.unique_images,
+        summary.missing_images.len()
+    );
+    Ok(summary)
+}
+
+fn export_json(
+    db: &Database,
+    file_manager: &FileManager,
+    mistake_ids: &[String],
+    output_dir: &Path,
+    image_mode: MistakeExportImageMode,
+) -> Result<MistakeExportSummary> {
+    let asset_pack_mode = image_mode == MistakeExportImageMode::AssetPack;
+    let assets_dir = output_dir.join(ASSETS_DIR);
+    std::fs::create_dir_all(if asset_pack_mode {
+        &assets_dir
+    } else {
+        output_dir
+    })
+    .map_err(|e| AppError::file_system(format!("创建导出目录失败: {}", e)))?;
+
+    let mut summary = MistakeExportSummary::default();
+    let mut rows = Vec::with_capacity(mistake_ids.len());
+    {
+        let conn = db
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        for mistake_id in mistake_ids {
+            match load_export_row(&conn, mistake_id)
+                .map_err(|e| AppError::database(format!("读取错题失败: {}", e)))?
+            {
+                Some(row) => rows.push(row),
+                None => summary.missing_mistake_ids.push(mistake_id.clone()),
+            }
+        }
+    }
+
+    let mut pack = AssetPack::new(ASSETS_DIR);
+    for row in &mut rows {
+        let mut images = Vec::with_capacity(row.images.len());
+        for relative_path in &row.images {
+            summary.image_references += 1;
+            let source_path = file_manager.get_image_absolute_path(relative_path);
+            let bytes = match std::fs::read(&source_path) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    log::warn!("[MistakeExport] 读取图片失败 {}: {}", relative_path, e);
+                    summary.missing_images.push(relative_path.clone());
+                    continue;
+                }
+            };
+            if asset_pack_mode {
+                let (path, is_new) = pack.add(&bytes, &source_path);
+                if is_new {
+                    write_file(&output_dir.join(&path), &bytes)?;
+                }
+                images.push(path);
+            } else {
+                images.push(format!(
+                    "data:{};base64,{}",
+                    FileManager::infer_mime_from_path(&source_path),
+                    general_purpose::STANDARD.encode(&bytes)
+                ));
+            }
+        }
+        row.images = images;
+    }
+
+    if asset_pack_mode {
+        let manifest = pack.into_manifest();
+        summary.unique_images = manifest.images.len();
+        let manifest_json = serde_json::to_vec_pretty(&manifest)
+            .map_err(|e| AppError::internal(format!("生成图片清单失败: {}", e)))?;
+        write_file(&output_dir.join(IMAGE_MANIFEST_FILE), &manifest_json)?;
+    }
+    let json_path = output_dir.join(EXPORT_JSON_FILE);
+    let json = serde_json::to_vec_pretty(&rows)
+        .map_err(|e| AppError::internal(format!("序列化导出数据失败: {}", e)))?;
+    write_file(&json_path, &json)?;
+
+    summary.format = MistakeExportFormat::Json;
+    summary.exported = rows.len();
+    summary.json_path = json_path.to_string_lossy().to_string();
+    summary.output_path = summary.json_path.clone();
+    Ok(summary)
+}
+
+/// Markdown 导出中的一道错题
+#[derive(Debug, Clone, PartialEq)]
+struct MarkdownMistake {
+    id: String,
+    subject: Option<String>,
+    created_at: String,
+    user_question: String,
+    ocr_text: String,
+    tags: Vec<String>,
+    images: Vec<MarkdownImage>,
+    messages: Vec<MarkdownMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MarkdownMessage {
+    role: String,
+    content: String,
+    timestamp: String,
+    thinking_content: Option<String>,
+    images: Vec<MarkdownImage>,
+}
+
+/// 图片引用：`path` 为导出包内的相对路径，读取失败时为 None
+#[derive(Debug, Clone, PartialEq)]
+struct MarkdownImage {
+    source: String,
+    path: Option<String>,
+}
+
+impl MarkdownImage {
+    fn unresolved(paths: Vec<String>) -> Vec<Self> {
+        paths
+            .into_iter()
+            .map(|source| Self { source, path: None })
+            .collect()
+    }
+}
+
+fn parse_json_list(raw: Option<String>) -> Vec<String> {
+    raw.and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// 读取错题与其对话记录（图片尚未解析）
+fn load_markdown_mistake(
+    conn: &Connection,
+    mistake_id: &str,
+) -> rusqlite::Result<Option<MarkdownMistake>> {
+    let deleted_sql = if has_column(conn, "mistakes", "deleted_at")? {
+        " AND deleted_at IS NULL"
+    } else {
+        ""
+    };
+    let mistake = conn
+        .query_row(
+            &format!(
+                "SELECT subject, COALESCE(created_at, ''), COALESCE(user_question, ''), \
+                        COALESCE(ocr_text, ''), tags, question_images \
+                 FROM mistakes WHERE id = ?1{}",
+                deleted_sql
+            ),
+            params![mistake_id],
+            |row| {
+                Ok(MarkdownMistake {
+                    id: mistake_id.to_string(),
+                    subject: row
+                        .get::<_, Option<String>>(0)?
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty()),
+                    created_at: row.get(1)?,
+                    user_question: row.get(2)?,
+                    ocr_text: row.get(3)?,
+                    tags: parse_json_list(row.get(4)?),
+                    images: MarkdownImage::unresolved(parse_json_list(row.get(5)?)),
+                    messages: Vec::new(),
+                })
+            },
+        )
+        .optional()?;
+    let Some(mut mistake) = mistake else {
+        return Ok(None);
+    };
+
+    let image_paths_sql = if has_column(conn, "chat_messages", "image_paths")? {
+        "image_paths"
+    } else {
+        "NULL"
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT role, content, timestamp, thinking_content, {} \
+         FROM chat_messages WHERE mistake_id = ?1 ORDER BY timestamp ASC, id ASC",
+        image_paths_sql
+    ))?;
+    let messages = stmt.query_map(params![mistake_id], |row| {
+        Ok(MarkdownMessage {
+            role: row.get(0)?,
+            content: row.get(1)?,
+            timestamp: row.get(2)?,
+            thinking_content: row
+                .get::<_, Option<String>>(3)?
+                .filter(|t| !t.trim().is_empty()),
+            images: MarkdownImage::unresolved(parse_json_list(row.get(4)?)),
+        })
+    })?;
+    mistake.messages = messages.collect::<rusqlite::Result<_>>()?;
+    Ok(Some(mistake))
+}
+
+/// 按科目分组（科目按首次出现的顺序，未设置科目的排在最后），组内保持原顺序
+fn group_by_subject(mistakes: Vec<MarkdownMistake>) -> Vec<(Option<String>, Vec<MarkdownMistake>)> {
+    let mut groups: Vec<(Option<String>, Vec<MarkdownMistake>)> = Vec::new();
+    for mistake in mistakes {
+        match groups
+            .iter_mut()
+            .find(|(subject, _)| *subject == mistake.subject)
+        {
+            Some((_, items)) => items.push(mistake),
+            None => groups.push((mistake.subject.clone(), vec![mistake])),
+        }
+    }
+    groups.sort_by_key(|(subject, _)| subject.is_none());
+    groups
+}
+
+fn role_label(role: &str) -> &str {
+    match role {
+        "user" => "用户",
+        "assistant" => "助手",
+        "system" => "系统",
+        "tool" => "工具",
+        other => other,
+    }
+}
+
+/// 目录与标题用的错题摘要：提问或识别文本的首行
+fn markdown_title(mistake: &MarkdownMistake, number: usize) -> String {
+    let first_line = [&mistake.user_question, &mistake.ocr_text]
+        .into_iter()
+        .filter_map(|text| text.lines().map(str::trim).find(|line| !line.is_empty()))
+        .next();
+    let Some(line) = first_line else {
+        return format!("错题 {}", number);
+    };
+    let mut title: String = line.chars().take(MARKDOWN_TITLE_MAX_CHARS).collect();
+    if line.chars().count() > MARKDOWN_TITLE_MAX_CHARS {
+        title.push('…');
+    }
+    // 避免方括号破坏目录中的链接语法
+    title.replace('[', "\\[").replace(']', "\\]")
+}
+
+fn push_markdown_images(out: &mut String, images: &[MarkdownImage]) {
+    for (idx, image) in images.iter().enumerate() {
+        match &image.path {
+            Some(path) => out.push_str(&format!("![图片 {}]({})\n\n", idx + 1, path)),
+            None => out.push_str(&format!("> 图片缺失：{}\n\n", image.source)),
+        }
+    }
+}
+
+fn render_markdown(groups: &[(Option<String>, Vec<MarkdownMistake>)], exported_at: &str) -> String {
+    let total: usize = groups.iter().map(|(_, items)| items.len()).sum();
+    let mut out = format!(
+        "# 错题导出\n\n导出时间：{}  \n共 {} 道错题\n\n## 目录\n",
+        exported_at, total
+    );
+    let subject_label =
+        |subject: &Option<String>| subject.clone().unwrap_or_else(|| "未分类".to_string());
+    let mut number = 0;
+    for (subject, items) in groups {
+        out.push_str(&format!(
+            "\n### {}（{}）\n\n",
+            subject_label(subject),
+            items.len()
+        ));
+        for mistake in items {
+            number += 1;
+            out.push_str(&format!(
+                "- [{}. {}](#mistake-{})\n",
+                number,
+                markdown_title(mistake, number),
+                number
+            ));
+        }
+    }
+
+    let mut number = 0;
+    for (subject, items) in groups {
+        for mistake in items {
+            number += 1;
+            out.push_str(&format!(
+                "\n---\n\n<a id=\"mistake-{}\"></a>\n\n## {}. {}\n\n",
+                number,
+                number,
+                markdown_title(mistake, number)
+            ));
+            out.push_str(&format!("- 科目：{}\n", subject_label(subject)));
+            if !mistake.tags.is_empty() {
+                out.push_str(&format!("- 标签：{}\n", mistake.tags.join("、")));
+            }
+            if !mistake.created_at.is_empty() {
+                out.push_str(&format!("- 创建：{}\n", mistake.created_at));
+            }
+            out.push_str(&format!("- ID：`{}`\n", mistake.id));
+
+            if !mistake.user_question.trim().is_empty() {
+                out.push_str(&format!("\n### 题目\n\n{}\n", mistake.user_question.trim()));
+            }
+            if !mistake.ocr_text.trim().is_empty() {
+                out.push_str(&format!("\n### 识别文本\n\n{}\n", mistake.ocr_text.trim()));
+            }
+            if !mistake.images.is_empty() {
+                out.push_str("\n### 图片\n\n");
+                push_markdown_images(&mut out, &mistake.images);
+            }
+            if !mistake.messages.is_empty() {
+                out.push_str("\n### 对话记录\n");
+                for message in &mistake.messages {
+                    out.push_str(&format!(
+                        "\n#### {} · {}\n\n",
+                        role_label(&message.role),
+                        message.timestamp
+                    ));
+                    if let Some(thinking) = &message.thinking_content {
+                        out.push_str("<details><summary>思考过程</summary>\n\n");
+                        out.push_str(thinking.trim());
+                        out.push_str("\n\n</details>\n\n");
+                    }
+                    if !message.content.trim().is_empty() {
+                        out.push_str(message.content.trim());
+                        out.push_str("\n\n");
+                    }
+                    push_markdown_images(&mut out, &message.images);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// 包含 Markdown 与图片的压缩包
+///
+/// 第一张图片写入时才创建文件；图片读出后立即写入压缩包，不在内存中累积。
+/// Markdown 需在全部图片路径确定后生成，最后写入。
+struct MarkdownArchive {
+    path: PathBuf,
+    zip: Option<zip::ZipWriter<std::fs::File>>,
+}
+
+impl MarkdownArchive {
+    fn new(path: PathBuf) -> Self {
+        Self { path, zip: None }
+    }
+
+    fn has_images(&self) -> bool {
+        self.zip.is_some()
+    }
+
+    fn add_image(&mut self, image_path: &str, bytes: &[u8]) -> Result<()> {
+        let path = &self.path;
+        let zip = match self.zip.take() {
+            Some(zip) => zip,
+            None => zip::ZipWriter::new(
+                std::fs::File::create(path).map_err(|e| archive_io_error(path, e))?,
+            ),
+        };
+        let zip = self.zip.insert(zip);
+        // 图片本身已压缩，直接存储
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file(image_path, stored)
+            .map_err(|e| archive_zip_error(path, e))?;
+        zip.write_all(bytes).map_err(|e| archive_io_error(path, e))
+    }
+
+    /// 写入 Markdown 并结束压缩包；没有写入过图片时不做任何事
+    fn finish(self, markdown: &str) -> Result<()> {
+        let path = &self.path;
+        let Some(mut zip) = self.zip else {
+            return Ok(());
+        };
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file(EXPORT_MARKDOWN_FILE, options)
+            .map_err(|e| archive_zip_error(path, e))?;
+        zip.write_all(markdown.as_bytes())
+            .map_err(|e| archive_io_error(path, e))?;
+        zip.finish().map_err(|e| archive_zip_error(path, e))?;
+        Ok(())
+    }
+}
+
+fn archive_zip_error(path: &Path, e: zip::result::ZipError) -> AppError {
+    AppError::file_system(format!("写入 {} 失败: {}", path.display(), e))
+}
+
+fn archive_io_error(path: &Path, e: std::io::Error) -> AppError {
+    AppError::file_system(format!("写入 {} 失败: {}", path.display(), e))
+}
+
+fn export_markdown(
+    db: &Database,
+    file_manager: &FileManager,
+    mistake_ids: &[String],
+    output_dir: &Path,
+) -> Result<MistakeExportSummary> {
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| AppError::file_system(format!("创建导出目录失败: {}", e)))?;
+
+    let mut summary = MistakeExportSummary {
+        format: MistakeExportFormat::Markdown,
+        ..Default::default()
+    };
+    let mut mistakes = Vec::with_capacity(mistake_ids.len());
+    {
+        let conn = db
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        for mistake_id in mistake_ids {
+            match load_markdown_mistake(&conn, mistake_id)
+                .map_err(|e| AppError::database(format!("读取错题失败: {}", e)))?
+            {
+                Some(mistake) => mistakes.push(mistake),
+                None => summary.missing_mistake_ids.push(mistake_id.clone()),
+            }
+        }
+    }
+
+    let mut pack = AssetPack::new(MARKDOWN_IMAGES_DIR);
+    let mut archive = MarkdownArchive::new(output_dir.join(EXPORT_MARKDOWN_ARCHIVE));
+    let mut resolve = |image: &mut MarkdownImage, summary: &mut MistakeExportSummary| {
+        summary.image_references += 1;
+        let source_path = file_manager.get_image_absolute_path(&image.source);
+        match std::fs::read(&source_path) {
+            Ok(bytes) => {
+                let (path, is_new) = pack.add(&bytes, &source_path);
+                if is_new {
+                    archive.add_image(&path, &bytes)?;
+                    summary.unique_images += 1;
+                }
+                image.path = Some(path);
+            }
+            Err(e) => {
+                log::warn!("[MistakeExport] 读取图片失败 {}: {}", image.source, e);
+                summary.missing_images.push(image.source.clone());
+            }
+        }
+        Ok::<(), AppError>(())
+    };
+    for mistake in &mut mistakes {
+        for image in &mut mistake.images {
+            resolve(image, &mut summary)?;
+        }
+        for message in &mut mistake.messages {
+            for image in &mut message.images {
+                resolve(image, &mut summary)?;
+            }
+        }
+    }
+
+    summary.exported = mistakes.len();
+    let markdown = render_markdown(
+        &group_by_subject(mistakes),
+        &chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    );
+    let output_path = if archive.has_images() {
+        let path = archive.path.clone();
+        archive.finish(&markdown)?;
+        path
+    } else {
+        let path = output_dir.join(EXPORT_MARKDOWN_FILE);
+        write_file(&path, markdown.as_bytes())?;
+        path
+    };
+    summary.output_path = output_path.to_string_lossy().to_string();
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    #[test]
+    fn test_asset_pack_dedupes_identical_images() {
+        let mut pack = AssetPack::new(ASSETS_DIR);
+        let (first, first_new) = pack.add(b"image-a", Path::new("/data/images/x.PNG"));
+        let (second, second_new) = pack.add(b"image-a", Path::new("/data/images/y.jpg"));
+        let (third, third_new) = pack.add(b"image-b", Path::new("/data/images/z.jpg"));
+        assert!(first_new && !second_new && third_new);
+        assert_eq!(first, second);
+        assert!(first.starts_with("assets/") && first.ends_with(".png"));
+        assert_ne!(first, third);
+
+        let manifest = pack.into_manifest();
+        assert_eq!(manifest.images.len(), 2);
+        assert_eq!(manifest.images[0].size, 7);
+        assert_eq!(manifest.images[1].mime, "image/jpeg");
+        assert_eq!(
+            manifest.find(&first.replace('/', "\\")).map(|e| &e.path),
+            Some(&first)
+        );
+        assert!(manifest.find(&format!("./{}", first)).is_some());
+        assert!(manifest.find("assets/unknown.png").is_none());
+    }
+
+    #[test]
+    fn test_manifest_roundtrip_and_export_row() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(ImageManifest::load(dir.path()).unwrap(), None);
+        let mut pack = AssetPack::new(ASSETS_DIR);
+        pack.add(b"image", Path::new("a.webp"));
+        let manifest = pack.into_manifest();
+        std::fs::write(
+            dir.path().join(IMAGE_MANIFEST_FILE),
+            serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(ImageManifest::load(dir.path()).unwrap(), Some(manifest));
+
+        let conn = migrated_conn().unwrap();
+        insert_mistake(
+            &conn,
+            "m1",
+            &[
+                ("subject", &"数学"),
+                ("user_question", &"为什么？"),
+                ("ocr_text", &"求 y = x^2 的导数"),
+                ("mistake_summary", &" 2x "),
+                ("tags", &r#"["导数"]"#),
+                ("question_images", &r#"["images/a.png"]"#),
+            ],
+        )
+        .unwrap();
+        insert_mistake(
+            &conn,
+            "m2",
+            &[
+                ("subject", &"数学"),
+                ("user_question", &"已删除"),
+                ("deleted_at", &"2026-01-01"),
+            ],
+        )
+        .unwrap();
+        let row = load_export_row(&conn, "m1").unwrap().unwrap();
+        assert_eq!(
+            row,
+            ExportRow {
+                question: "为什么？\n求 y = x^2 的导数".into(),
+                answer: Some("2x".into()),
+                subject: Some("数学".into()),
+                tags: vec!["导数".into()],
+                images: vec!["images/a.png".into()],
+            }
+        );
+        assert_eq!(load_export_row(&conn, "m2").unwrap(), None);
+    }
+
+    #[test]
+    fn test_markdown_groups_subjects_and_renders_chat() {
+        let conn = migrated_conn().unwrap();
+        insert_mistake(
+            &conn,
+            "m1",
+            &[
+                ("subject", &"数学"),
+                ("created_at", &"2026-01-01"),
+                ("user_question", &"求导 [基础]"),
+                ("ocr_text", &"y = x^2"),
+                ("tags", &r#"["导数"]"#),
+                ("question_images", &r#"["images/a.png"]"#),
+            ],
+        )
+        .unwrap();
+        insert_mistake(
+            &conn,
+            "m2",
+            &[("created_at", &"2026-01-02"), ("ocr_text", &"默写古诗")],
+        )
+        .unwrap();
+        insert_mistake(
+            &conn,
+            "m3",
+            &[
+                ("subject", &"数学"),
+                ("created_at", &"2026-01-03"),
+                ("user_question", &"极限"),
+            ],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "INSERT INTO chat_messages (mistake_id, role, content, timestamp, thinking_content, image_paths)
+            VALUES
+                ('m1', 'assistant', '答案是 2x', '2026-01-01T00:00:02', '先用幂函数求导', NULL),
+                ('m1', 'user', '为什么？', '2026-01-01T00:00:01', NULL, '[\"images/b.png\"]');",
+        )
+        .unwrap();
+
+        let mut mistakes: Vec<MarkdownMistake> = ["m2", "m1", "m3"]
+            .iter()
+            .map(|id| load_markdown_mistake(&conn, id).unwrap().unwrap())
+            .collect();
+        let m1 = &mut mistakes[1];
+        assert_eq!(m1.tags, vec!["导数".to_string()]);
+        assert_eq!(m1.messages.len(), 2);
+        assert_eq!(m1.messages[0].role, "user");
+        m1.images[0].path = Some("images/abc.png".into());
+
+        let groups = group_by_subject(mistakes);
+        assert_eq!(groups[0].0.as_deref(), Some("数学"));
+        assert_eq!(groups[0].1.len(), 2);
+        assert_eq!(groups[1].0, None);
+
+        let markdown = render_markdown(&groups, "2026-01-04 10:00:00");
+        assert!(markdown.contains("### 数学（2）"));
+        assert!(markdown.contains("- [1. 求导 \\[基础\\]](#mistake-1)"));
+        assert!(markdown.contains("- [3. 默写古诗](#mistake-3)"));
+        assert!(markdown.contains("<a id=\"mistake-2\"></a>"));
+        assert!(markdown.contains("![图片 1](images/abc.png)"));
+        assert!(markdown.contains("> 图片缺失：images/b.png"));
+        assert!(markdown
+            .contains("<details><summary>思考过程</summary>\n\n先用幂函数求导\n\n</details>"));
+        let user_at = markdown.find("#### 用户").unwrap();
+        let assistant_at = markdown.find("#### 助手").unwrap();
+        assert!(user_at < assistant_at);
+    }
+
+    #[test]
+    fn test_markdown_archive_contains_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(EXPORT_MARKDOWN_ARCHIVE);
+        let mut archive = MarkdownArchive::new(path.clone());
+        assert!(!archive.has_images());
+        archive.add_image("images/abc.png", b"png").unwrap();
+        assert!(archive.has_images());
+        archive.finish("# 错题导出").unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut markdown = String::new();
+        std::io::Read::read_to_string(
+            &mut archive.by_name(EXPORT_MARKDOWN_FILE).unwrap(),
+            &mut markdown,
+        )
+        .unwrap();
+        assert_eq!(markdown, "# 错题导出");
+        assert_eq!(archive.by_name("images/abc.png").unwrap().size(), 3);
+    }
+}
@@ -0,0 +1,655 @@
+//! 错题批量导入命令
+//!
+//! 新用户可以把已有题库（CSV 或 JSON）一次性导入为错题：
+//! - 列 / 字段：题目（必填）、答案或解析、科目、标签、图片引用；
+//! - 图片引用支持本地路径（相对路径按 `imageBaseDir` 解析）与 `data:` base64，
+//!   导入时复制到受管图片目录；
+//! - 逐行校验，出错的行记录行号与原因后跳过，不中断整个导入；
+//! - `dedupe` 为 true 时按题目文本的内容哈希与已有错题（及本次已导入的行）去重；
+//!   为 false 时照常导入，但在 `possibleDuplicates` 中列出内容重复的行。
+//!
+//! 答案 / 解析写入 `mistake_summary`，与自动生成的错题总结共用同一字段。
+//!
+//! `imageBaseDir` 下存在 `images.manifest`（[`super::mistake_export`] 的资源包）时，
+//! 清单中列出的图片按 SHA-256 校验后再导入，内容不符的行记为错误。
+
+use super::mistake_export::ImageManifest;
+use super::mistake_tags::mistake_filter;
+use crate::commands::AppState;
+use crate::database::has_column;
+use crate::file_manager::FileManager;
+use crate::models::AppError;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 单次导入的最大行数
+pub(super) const MAX_IMPORT_ROWS: usize = 5000;
+/// 题目文本的最大字符数
+const MAX_QUESTION_CHARS: usize = 20_000;
+const SUPPORTED_IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MistakeImportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MistakeImportOptions {
+    /// 按内容哈希跳过与已有错题重复的行
+    pub dedupe: bool,
+    /// 相对图片路径的基准目录（通常为导入文件所在目录）
+    pub image_base_dir: Option<String>,
+    /// 行内未填写科目时使用的科目
+    pub default_subject: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeImportRowError {
+    /// 数据行号（从 1 开始，不含 CSV 表头）
+    pub row: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeImportReport {
+    pub total_rows: usize,
+    pub imported_ids: Vec<String>,
+    /// 因内容重复被跳过的行号
+    pub duplicate_rows: Vec<usize>,
+    /// 未去重时，已导入但与已有错题内容相同的行；可据此关联为重复或删除
+    pub possible_duplicates: Vec<ImportPossibleDuplicate>,
+    pub errors: Vec<MistakeImportRowError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPossibleDuplicate {
+    pub row: usize,
+    /// 本次导入生成的错题
+    pub mistake_id: String,
+    /// 内容相同的已有错题
+    pub candidate_id: String,
+    pub similarity: f32,
+}
+
+/// JSON 导入的单条记录（字段名兼容常见写法）
+#[derive(Debug, Default, Deserialize)]
+struct RawImportRow {
+    #[serde(alias = "question_text", alias = "user_question", alias = "题目")]
+    question: Option<String>,
+    #[serde(alias = "analysis", alias = "解析", alias = "答案")]
+    answer: Option<String>,
+    #[serde(alias = "科目")]
+    subject: Option<String>,
+    #[serde(default, alias = "标签")]
+    tags: Option<StringOrList>,
+    #[serde(default, alias = "image", alias = "图片")]
+    images: Option<StringOrList>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StringOrList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl StringOrList {
+    fn into_items(self) -> Vec<String> {
+        match self {
+            Self::One(value) => split_list(&value),
+            Self::Many(values) => values
+                .into_iter()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect(),
+        }
+    }
+}
+
+/// 校验通过的导入行
+#[derive(Debug, Clone, PartialEq)]
+struct ImportRow {
+    question: String,
+    answer: Option<String>,
+    subject: Option<String>,
+    tags: Vec<String>,
+    images: Vec<String>,
+}
+
+/// 按常见分隔符拆分单元格中的列表（标签、图片）
+fn split_list(value: &str) -> Vec<String> {
+    let mut items: Vec<String> = Vec::new();
+    for item in value.split([';', '；', '|', '、', ',', '，']) {
+        let item = item.trim();
+        if !item.is_empty() && !items.iter().any(|i| i == item) {
+            items.push(item.to_string());
+        }
+    }
+    items
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn validate_row(
+    raw: RawImportRow,
+    default_subject: Option<&str>,
+) -> std::result::Result<ImportRow, String> {
+    let question = non_empty(raw.question).ok_or("缺少题目内容")?;
+    if question.chars().count() > MAX_QUESTION_CHARS {
+        return Err(format!("题目内容超过 {} 个字符", MAX_QUESTION_CHARS));
+    }
+    Ok(ImportRow {
+        question,
+        answer: non_empty(raw.answer),
+        subject: non_empty(raw.subject).or_else(|| default_subject.map(str::to_string)),
+        tags: raw.tags.map(StringOrList::into_items).unwrap_or_default(),
+        images: raw.images.map(StringOrList::into_items).unwrap_or_default(),
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CsvField {
+    Question,
+    Answer,
+    Subject,
+    Tags,
+    Images,
+}
+
+fn csv_field(header: &str) -> Option<CsvField> {
+    match header
+        .trim()
+        .trim_start_matches('\u{feff}')
+        .to_lowercase()
+        .as_str()
+    {
+        "question" | "question_text" | "user_question" | "题目" | "题干" => {
+            Some(CsvField::Question)
+        }
+        "answer" | "analysis" | "答案" | "解析" => Some(CsvField::Answer),
+        "subject" | "科目" | "学科" => Some(CsvField::Subject),
+        "tags" | "tag" | "标签" => Some(CsvField::Tags),
+        "images" | "image" | "图片" => Some(CsvField::Images),
+        _ => None,
+    }
+}
+
+fn parse_csv_rows(data: &str) -> Result<Vec<std::result::Result<RawImportRow, String>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .has_headers(true)
+        .from_reader(data.as_bytes());
+    let fields: Vec<Option<CsvField>> = reader
+        .headers()
+        .map_err(|e| AppError::validation(format!("读取 CSV 表头失败: {}", e)))?
+        .iter()
+        .map(csv_field)
+        .collect();
+    if !fields.iter().any(|f| matches!(f, Some(CsvField::Question))) {
+        return Err(AppError::validation("CSV 缺少题目列（question / 题目）"));
+    }
+
+    Ok(reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| format!("CSV 行格式错误: {}", e))?;
+            let mut raw = RawImportRow::default();
+            for (field, value) in fields.iter().zip(record.iter()) {
+                let value = Some(value.to_string());
+                match field {
+                    Some(CsvField::Question) => raw.question = value,
+                    Some(CsvField::Answer) => raw.answer = value,
+                    Some(CsvField::Subject) => raw.subject = value,
+                    Some(CsvField::Tags) => raw.tags = value.map(StringOrList::One),
+                    Some(CsvField::Images) => raw.images = value.map(StringOrList::One),
+                    None => {}
+                }
+            }
+            Ok(raw)
+        })
+        .collect())
+}
+
+fn parse_json_rows(data: &str) -> Result<Vec<std::result::Result<RawImportRow, String>>> {
+    let items: Vec<serde_json::Value> = serde_json::from_str(data)
+        .map_err(|e| AppError::validation(format!("JSON 格式无效，应为对象数组: {}", e)))?;
+    Ok(items
+        .into_iter()
+        .map(|item| {
+            serde_json::from_value::<RawImportRow>(item).map_err(|e| format!("字段格式错误: {}", e))
+        })
+        .collect())
+}
+
+/// 解析导入数据；整体格式错误时返回 Err，单行错误保留在结果中
+fn parse_rows(
+    format: MistakeImportFormat,
+    data: &str,
+    default_subject: Option<&str>,
+) -> Result<Vec<std::result::Result<ImportRow, String>>> {
+    let raw_rows = match format {
+        MistakeImportFormat::Csv => parse_csv_rows(data)?,
+        MistakeImportFormat::Json => parse_json_rows(data)?,
+    };
+    if raw_rows.len() > MAX_IMPORT_ROWS {
+        return Err(AppError::validation(format!(
+            "单次最多导入 {} 行，当前 {} 行",
+            MAX_IMPORT_ROWS,
+            raw_rows.len()
+        )));
+    }
+    Ok(raw_rows
+        .into_iter()
+        .map(|raw| raw.and_then(|raw| validate_row(raw, default_subject)))
+        .collect())
+}
+
+/// 题目内容哈希：忽略首尾空白、连续空白与大小写差异
+pub(super) fn content_hash(text: &str) -> String {
+    let normalized = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+/// 已有错题（未删除）的题干与 OCR 文本哈希 → 错题 ID（同一内容取最早的错题）
+pub(super) fn existing_content_hashes(
+    conn: &Connection,
+) -> rusqlite::Result<HashMap<String, String>> {
+    let (where_sql, values) = mistake_filter(conn, None)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, COALESCE(user_question, ''), COALESCE(ocr_text, '') FROM mistakes {} \
+         ORDER BY created_at",
+        where_sql
+    ))?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })?;
+    let mut hashes = HashMap::new();
+    for row in rows {
+        let (id, question, ocr_text) = row?;
+        for text in [question, ocr_text] {
+            if !text.trim().is_empty() {
+                hashes
+                    .entry(content_hash(&text))
+                    .or_insert_with(|| id.clone());
+            }
+        }
+    }
+    Ok(hashes)
+}
+
+fn insert_imported_mistake(
+    conn: &Connection,
+    row: &ImportRow,
+    question_images: &[String],
+) -> rusqlite::Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    let tags = serde_json::to_string(&row.tags).unwrap_or_else(|_| "[]".to_string());
+    let images = serde_json::to_string(question_images).unwrap_or_else(|_| "[]".to_string());
+    conn.execute(
+        "INSERT INTO mistakes (id, created_at, question_images, analysis_images, user_question, \
+                               ocr_text, tags, mistake_type, status, chat_category, updated_at, \
+                               last_accessed_at, mistake_summary, subject) \
+         VALUES (?1, ?2, ?3, '[]', ?4, '', ?5, 'analysis', 'active', 'analysis', ?2, ?2, ?6, ?7)",
+        params![id, now, images, row.question, tags, row.answer, row.subject],
+    )?;
+    if let Some(language) = crate::ocr_language::detect_ocr_language(&row.question) {
+        if has_column(conn, "mistakes", "language")? {
+            conn.execute(
+                "UPDATE mistakes SET language = ?1 WHERE id = ?2",
+                params![language, id],
+            )?;
+        }
+    }
+    Ok(id)
+}
+
+/// 解析单个本地图片引用为绝对路径并校验格式
+fn resolve_image_path(
+    reference: &str,
+    base_dir: Option<&Path>,
+) -> std::result::Result<PathBuf, String> {
+    let lower = reference.to_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+        return Err(format!("不支持远程图片，请先下载到本地: {}", reference));
+    }
+    let path = Path::new(reference);
+    let path = match base_dir {
+        Some(base) if path.is_relative() => base.join(path),
+        _ => path.to_path_buf(),
+    };
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    if !SUPPORTED_IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+        return Err(format!("不支持的图片格式: {}", reference));
+    }
+    if !path.is_file() {
+        return Err(format!("图片不存在: {}", path.display()));
+    }
+    Ok(path)
+}
+
+/// 资源包清单中列出的图片须与清单记录的内容一致
+fn verify_manifest_image(
+    manifest: Option<&ImageManifest>,
+    reference: &str,
+    bytes: &[u8],
+) -> std::result::Result<(), String> {
+    let Some(entry) = manifest.and_then(|m| m.find(reference)) else {
+        return Ok(());
+    };
+    if format!("{:x}", Sha256::digest(bytes)) != entry.sha256 {
+        return Err(format!("资源包图片内容与清单不符: {}", reference));
+    }
+    Ok(())
+}
+
+/// 把行内引用的图片复制到受管目录；任一图片失败时释放已复制的图片
+async fn copy_row_images(
+    file_manager: &FileManager,
+    references: &[String],
+    base_dir: Option<&Path>,
+    manifest: Option<&ImageManifest>,
+) -> std::result::Result<Vec<String>, String> {
+    let mut saved = Vec::with_capacity(references.len());
+    for reference in references {
+        let result = if reference.starts_with("data:image/") {
+            file_manager
+                .save_image_from_base64_dedup(reference)
+                .await
+                .map_err(|e| e.message)
+        } else {
+            resolve_image_path(reference, base_dir).and_then(|path| {
+                let bytes = std::fs::read(&path)
+                    .map_err(|e| format!("读取图片失败 {}: {}", path.display(), e))?;
+                verify_manifest_image(manifest, reference, &bytes)?;
+                let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("png");
+                file_manager
+                    .save_image_from_bytes(&bytes, extension)
+                    .map_err(|e| e.message)
+            })
+        };
+        match result {
+            Ok(relative_path) => saved.push(relative_path),
+            Err(message) => {
+                let _ = file_manager.delete_images(&saved);
+                return Err(message);
+            }
+        }
+    }
+    Ok(saved)
+}
+
+/// 从 CSV / JSON 批量导入错题
+///
+/// `data` 为文件文本内容。CSV 需有表头，题目列必填；JSON 为对象数组。
+/// 逐行导入，单行失败不影响其他行，结果中返回成功的错题 ID、重复行与错误行。
+#[tauri::command]
+pub async fn import_mistakes(
+    format: MistakeImportFormat,
+    data: String,
+    options: Option<MistakeImportOptions>,
+    state: State<'_, AppState>,
+) -> Result<MistakeImportReport> {
+    let options = options.unwrap_or_default();
+    let default_subject = non_empty(options.default_subject.clone());
+    let rows = parse_rows(format, &data, default_subject.as_deref())?;
+    let base_dir = non_empty(options.image_base_dir.clone()).map(PathBuf::from);
+    let manifest = match &base_dir {
+        Some(dir) => ImageManifest::load(dir)?,
+        None => None,
+    };
+    let db_err = |e: rusqlite::Error| AppError::database(format!("导入错题失败: {}", e));
+
+    // 不去重时同样比对内容哈希，导入重复内容的行给出提示
+    let mut seen = {
+        let conn = state
+            .database
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        existing_content_hashes(&conn).map_err(db_err)?
+    };
+
+    let mut report = MistakeImportReport {
+        total_rows: rows.len(),
+        ..Default::default()
+    };
+    for (index, row) in rows.into_iter().enumerate() {
+        let row_number = index + 1;
+        let row = match row {
+            Ok(row) => row,
+            Err(message) => {
+                report.errors.push(MistakeImportRowError {
+                    row: row_number,
+                    message,
+                });
+                continue;
+            }
+        };
+        let hash = content_hash(&row.question);
+        let duplicate_of = seen.get(&hash).cloned();
+        if options.dedupe && duplicate_of.is_some() {
+            report.duplicate_rows.push(row_number);
+            continue;
+        }
+
+        let images = match copy_row_images(
+            &state.file_manager,
+            &row.images,
+            base_dir.as_deref(),
+            manifest.as_ref(),
+        )
+        .await
+        {
+            Ok(images) => images,
+            Err(message) => {
+                report.errors.push(MistakeImportRowError {
+                    row: row_number,
+                    message,
+                });
+                continue;
+            }
+        };
+        let inserted = {
+            let conn = state
+                .database
+                .get_conn_safe()
+                .map_err(|e| AppError::database(e.to_string()))?;
+            insert_imported_mistake(&conn, &row, &images)
+        };
+        match inserted {
+            Ok(id) => {
+                if let Some(candidate_id) = duplicate_of {
+                    report.possible_duplicates.push(ImportPossibleDuplicate {
+                        row: row_number,
+                        mistake_id: id.clone(),
+                        candidate_id,
+                        similarity: 1.0,
+                    });
+                } else {
+                    seen.insert(hash, id.clone());
+                }
+                report.imported_ids.push(id);
+            }
+            Err(e) => {
+                let _ = state.file_manager.delete_images(&images);
+                report.errors.push(MistakeImportRowError {
+                    row: row_number,
+                    message: format!("写入数据库失败: {}", e),
+                });
+            }
+        }
+    }
+
+    log::info!(
+        "[MistakeImport] 导入完成: 共 {} 行，成功 {}，重复 {}，失败 {}",
+        report.total_rows,
+        report.imported_ids.len(),
+        report.duplicate_rows.len(),
+        report.errors.len()
+    );
+    crate::mistake_auto_tagging::spawn_auto_tag_mistakes(
+        state.database.clone(),
+        state.llm_manager.clone(),
+        report.imported_ids.clone(),
+    );
+    super::mistake_kb_sync::spawn_sync_mistakes(
+        state.database.clone(),
+        state.vfs_db.clone(),
+        state.llm_manager.clone(),
+        report.imported_ids.clone(),
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn setup_conn() -> Connection {
+        let conn = migrated_conn().unwrap();
+        insert_mistake(
+            &conn,
+            "old",
+            &[("subject", &"数学"), ("ocr_text", &"求  y = X^2 的导数")],
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_csv_rows_map_headers_and_report_row_errors() {
+        let csv = "\u{feff}题目,解析,科目,标签,images\n\
+                   求 y = x^2 的导数,2x,,\"导数;函数\",\n\
+                   ,缺题目,物理,,\n\
+                   牛顿第二定律,F=ma,物理,力学 | 力学,a.png";
+        let rows = parse_rows(MistakeImportFormat::Csv, csv, Some("数学")).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0],
+            Ok(ImportRow {
+                question: "求 y = x^2 的导数".into(),
+                answer: Some("2x".into()),
+                subject: Some("数学".into()),
+                tags: vec!["导数".into(), "函数".into()],
+                images: Vec::new(),
+            })
+        );
+        assert_eq!(rows[1], Err("缺少题目内容".to_string()));
+        let third = rows[2].as_ref().unwrap();
+        assert_eq!(third.tags, vec!["力学"]);
+        assert_eq!(third.images, vec!["a.png"]);
+
+        assert!(parse_rows(MistakeImportFormat::Csv, "科目\n数学", None).is_err());
+    }
+
+    #[test]
+    fn test_json_rows_accept_lists_and_strings() {
+        let json = r#"[
+            {"question": "题一", "tags": ["a", " b "], "images": "x.png;y.png"},
+            {"题目": "题二", "答案": "B", "subject": "英语"},
+            {"question": 3},
+            {"answer": "无题目"}
+        ]"#;
+        let rows = parse_rows(MistakeImportFormat::Json, json, None).unwrap();
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!(first.tags, vec!["a", "b"]);
+        assert_eq!(first.images, vec!["x.png", "y.png"]);
+        let second = rows[1].as_ref().unwrap();
+        assert_eq!(second.answer.as_deref(), Some("B"));
+        assert_eq!(second.subject.as_deref(), Some("英语"));
+        assert!(rows[2].is_err());
+        assert!(rows[3].is_err());
+
+        assert!(parse_rows(MistakeImportFormat::Json, "{}", None).is_err());
+    }
+
+    #[test]
+    fn test_dedupe_hash_matches_existing_content_and_insert() {
+        let conn = setup_conn();
+        let hashes = existing_content_hashes(&conn).unwrap();
+        assert_eq!(
+            hashes
+                .get(&content_hash("求 y = x^2 的导数"))
+                .map(String::as_str),
+            Some("old")
+        );
+
+        let row = ImportRow {
+            question: "新题".into(),
+            answer: Some("解析".into()),
+            subject: Some("物理".into()),
+            tags: vec!["力学".into()],
+            images: Vec::new(),
+        };
+        let id = insert_imported_mistake(&conn, &row, &["images/a.png".to_string()]).unwrap();
+        let (subject, tags, images, summary): (String, String, String, String) = conn
+            .query_row(
+                "SELECT subject, tags, question_images, mistake_summary FROM mistakes WHERE id = ?1",
+                params![id],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(subject, "物理");
+        assert_eq!(tags, "[\"力学\"]");
+        assert_eq!(images, "[\"images/a.png\"]");
+        assert_eq!(summary, "解析");
+    }
+
+    #[test]
+    fn test_resolve_image_path_checks_scheme_extension_and_existence() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.PNG"), b"png").unwrap();
+        assert!(resolve_image_path("a.PNG", Some(dir.path())).is_ok());
+        assert!(resolve_image_path("missing.png", Some(dir.path())).is_err());
+        assert!(resolve_image_path("notes.txt", Some(dir.path())).is_err());
+        assert!(resolve_image_path("https://example.com/a.png", None).is_err());
+    }
+
+    #[test]
+    fn test_manifest_images_are_verified_by_hash() {
+        let manifest: ImageManifest = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "images": [{
+                "path": "assets/a.png",
+                "sha256": format!("{:x}", Sha256::digest(b"png")),
+                "size": 3,
+                "mime": "image/png"
+            }]
+        }))
+        .unwrap();
+        assert!(verify_manifest_image(Some(&manifest), "assets/a.png", b"png").is_ok());
+        assert!(verify_manifest_image(Some(&manifest), "assets\\a.png", b"jpg").is_err());
+        // 清单外的图片与无清单时不校验
+        assert!(verify_manifest_image(Some(&manifest), "other.png", b"jpg").is_ok());
+        assert!(verify_manifest_image(None, "assets/a.png", b"jpg").is_ok());
+    }
+}
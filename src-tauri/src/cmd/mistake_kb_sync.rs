@@ -0,0 +1,702 @@
+//! 错题自动同步到知识库
+//!
+//! 开启设置项 `mistake.kb_sync.enabled` 并指定目标文件夹 `mistake.kb_sync.folder_id` 后，
+//! 错题（题目 + OCR 文本 + 解析总结）作为 VFS 笔记写入该文件夹并立即建立索引，
+//! RAG 对话经 VFS 检索即可命中用户自己的错题。
+//!
+//! - 错题对应的笔记 ID 与内容哈希记录在设置项 `mistake.kb_sync.doc.{id}` 中，内容未变时不重新嵌入；
+//! - 仅目标文件夹变化时移动笔记，并按新文件夹重建索引；
+//! - 错题被删除（含软删除、合并）后对应笔记随之删除，索引一并清理。
+//!
+//! 导入错题、生成总结后在后台同步，合并错题时移除被合并错题的笔记；
+//! `sync_mistakes_to_knowledge_base` 用于开启设置后的全量补齐与修复。
+
+use crate::commands::AppState;
+use crate::database::{has_column, Database};
+use crate::llm_manager::LLMManager;
+use crate::models::AppError;
+use crate::vfs::database::VfsDatabase;
+use crate::vfs::error::VfsError;
+use crate::vfs::indexing::VfsFullIndexingService;
+use crate::vfs::lance_store::VfsLanceStore;
+use crate::vfs::repos::{VfsFolderRepo, VfsNoteRepo};
+use crate::vfs::types::{VfsCreateNoteParams, VfsNote, VfsUpdateNoteParams};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const KB_SYNC_ENABLED_KEY: &str = "mistake.kb_sync.enabled";
+pub const KB_SYNC_FOLDER_KEY: &str = "mistake.kb_sync.folder_id";
+/// 每条已同步错题的记录：`mistake.kb_sync.doc.{mistake_id}` -> [`SyncRecord`] JSON
+const SYNC_RECORD_PREFIX: &str = "mistake.kb_sync.doc.";
+/// 同步生成的笔记统一带上的标签
+const NOTE_TAG: &str = "错题";
+/// 笔记标题中题目预览的最大字符数
+const TITLE_PREVIEW_CHARS: usize = 40;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeKbSyncSettings {
+    pub enabled: bool,
+    /// 目标 VFS 文件夹；未指定时不同步
+    pub folder_id: Option<String>,
+}
+
+impl MistakeKbSyncSettings {
+    /// 开启且指定了文件夹时返回目标文件夹
+    pub fn target_folder(&self) -> Option<&str> {
+        if self.enabled {
+            self.folder_id.as_deref().filter(|id| !id.trim().is_empty())
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MistakeKbSyncOutcome {
+    /// 新增或内容变化后重新索引
+    Synced,
+    /// 内容未变，仅移动到了新的目标文件夹
+    Moved,
+    Unchanged,
+    /// 错题已删除或内容为空，笔记已移除
+    Removed,
+    /// 同步未开启
+    Disabled,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeKbSyncReport {
+    pub synced: usize,
+    pub moved: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub failed: usize,
+}
+
+impl MistakeKbSyncReport {
+    fn record(&mut self, outcome: MistakeKbSyncOutcome) {
+        match outcome {
+            MistakeKbSyncOutcome::Synced => self.synced += 1,
+            MistakeKbSyncOutcome::Moved => self.moved += 1,
+            MistakeKbSyncOutcome::Unchanged | MistakeKbSyncOutcome::Disabled => self.unchanged += 1,
+            MistakeKbSyncOutcome::Removed => self.removed += 1,
+        }
+    }
+}
+
+/// 持久化的同步记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncRecord {
+    note_id: String,
+    content_hash: String,
+}
+
+/// 已同步笔记的当前状态；笔记被用户删除后视为未同步
+#[derive(Debug, Clone, PartialEq)]
+struct SyncedDocument {
+    note_id: String,
+    resource_id: String,
+    content_hash: String,
+    folder_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SyncAction {
+    Skip,
+    Upsert { text: String, hash: String },
+    Move,
+    Remove,
+}
+
+pub fn load_kb_sync_settings(db: &Database) -> MistakeKbSyncSettings {
+    let enabled = db
+        .get_setting(KB_SYNC_ENABLED_KEY)
+        .ok()
+        .flatten()
+        .is_some_and(|v| v.trim() == "true");
+    let folder_id = db
+        .get_setting(KB_SYNC_FOLDER_KEY)
+        .ok()
+        .flatten()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+    MistakeKbSyncSettings { enabled, folder_id }
+}
+
+fn record_key(mistake_id: &str) -> String {
+    format!("{}{}", SYNC_RECORD_PREFIX, mistake_id)
+}
+
+/// 拼接写入知识库的文本；各部分均为空时返回 None
+fn build_document_text(question: &str, ocr_text: &str, summary: &str) -> Option<String> {
+    let question = question.trim();
+    let ocr_text = ocr_text.trim();
+    let mut sections = Vec::new();
+    if !question.is_empty() {
+        sections.push(format!("题目：{}", question));
+    }
+    // OCR 文本与题目相同时只保留一份
+    if !ocr_text.is_empty() && ocr_text != question {
+        sections.push(format!("题目原文：{}", ocr_text));
+    }
+    if !summary.trim().is_empty() {
+        sections.push(format!("解析与总结：{}", summary.trim()));
+    }
+    if sections.is_empty() {
+        None
+    } else {
+        Some(sections.join("\n\n"))
+    }
+}
+
+fn text_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+fn plan_sync(
+    text: Option<String>,
+    existing: Option<&SyncedDocument>,
+    target_folder: &str,
+) -> SyncAction {
+    let Some(text) = text else {
+        return if existing.is_some() {
+            SyncAction::Remove
+        } else {
+            SyncAction::Skip
+        };
+    };
+    let hash = text_hash(&text);
+    match existing {
+        Some(doc) if doc.content_hash == hash => {
+            if doc.folder_id == target_folder {
+                SyncAction::Skip
+            } else {
+                SyncAction::Move
+            }
+        }
+        _ => SyncAction::Upsert { text, hash },
+    }
+}
+
+/// 读取错题当前的同步文本；错题不存在或已软删除时返回 None
+fn load_mistake_text(conn: &Connection, mistake_id: &str) -> rusqlite::Result<Option<String>> {
+    let deleted_filter = if has_column(conn, "mistakes", "deleted_at")? {
+        " AND deleted_at IS NULL"
+    } else {
+        ""
+    };
+    let summary_col = if has_column(conn, "mistakes", "mistake_summary")? {
+        "COALESCE(mistake_summary, '')"
+    } else {
+        "''"
+    };
+    let row = conn
+        .query_row(
+            &format!(
+                "SELECT COALESCE(user_question, ''), COALESCE(ocr_text, ''), {} \
+                 FROM mistakes WHERE id = ?1{}",
+                summary_col, deleted_filter
+            ),
+            params![mistake_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            },
+        )
+        .optional()?;
+    Ok(row.and_then(|(question, ocr_text, summary)| {
+        build_document_text(&question, &ocr_text, &summary)
+    }))
+}
+
+/// 读取同步记录；记录损坏时视为不存在
+fn load_sync_record(db: &Database, mistake_id: &str) -> Result<Option<SyncRecord>> {
+    let value = db
+        .get_setting(&record_key(mistake_id))
+        .map_err(|e| AppError::database(format!("读取错题同步记录失败: {}", e)))?;
+    Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+}
+
+fn save_sync_record(db: &Database, mistake_id: &str, record: &SyncRecord) -> Result<()> {
+    let value = serde_json::to_string(record)
+        .map_err(|e| AppError::internal(format!("序列化错题同步记录失败: {}", e)))?;
+    db.save_setting(&record_key(mistake_id), &value)
+        .map_err(|e| AppError::database(format!("保存错题同步记录失败: {}", e)))
+}
+
+fn delete_sync_record(db: &Database, mistake_id: &str) -> Result<()> {
+    db.delete_setting(&record_key(mistake_id))
+        .map(|_| ())
+        .map_err(|e| AppError::database(format!("删除错题同步记录失败: {}", e)))
+}
+
+/// 已同步的错题 ID
+fn synced_mistake_ids(db: &Database) -> Result<Vec<String>> {
+    let rows = db
+        .get_settings_by_prefix(SYNC_RECORD_PREFIX)
+        .map_err(|e| AppError::database(format!("读取错题同步记录失败: {}", e)))?;
+    Ok(rows
+        .into_iter()
+        .map(|(key, _, _)| key[SYNC_RECORD_PREFIX.len()..].to_string())
+        .collect())
+}
+
+fn vfs_err(e: VfsError) -> AppError {
+    AppError::database(format!("VFS 操作失败: {}", e))
+}
+
+/// 按同步记录找到仍然存在的笔记
+fn load_synced_document(
+    vfs_db: &VfsDatabase,
+    record: &SyncRecord,
+) -> Result<Option<SyncedDocument>> {
+    let conn = vfs_db.get_conn_safe().map_err(vfs_err)?;
+    let Some(note) = VfsNoteRepo::get_note_with_conn(&conn, &record.note_id).map_err(vfs_err)?
+    else {
+        return Ok(None);
+    };
+    let folder_id = VfsNoteRepo::get_note_location_with_conn(&conn, &note.id)
+        .map_err(vfs_err)?
+        .and_then(|location| location.folder_id)
+        .unwrap_or_default();
+    Ok(Some(SyncedDocument {
+        note_id: note.id,
+        resource_id: note.resource_id,
+        content_hash: record.content_hash.clone(),
+        folder_id,
+    }))
+}
+
+fn document_title(text: &str) -> String {
+    let first_line = text.lines().next().unwrap_or_default();
+    let preview: String = first_line
+        .trim_start_matches("题目：")
+        .trim_start_matches("题目原文：")
+        .chars()
+        .take(TITLE_PREVIEW_CHARS)
+        .collect();
+    format!("错题：{}", preview)
+}
+
+fn indexing_service(
+    vfs_db: &Arc<VfsDatabase>,
+    llm_manager: &Arc<LLMManager>,
+) -> Result<VfsFullIndexingService> {
+    let lance_store = VfsLanceStore::new(vfs_db.clone()).map_err(vfs_err)?;
+    VfsFullIndexingService::new(vfs_db.clone(), llm_manager.clone(), Arc::new(lance_store))
+        .map_err(vfs_err)
+}
+
+/// 按当前设置同步单条错题
+pub async fn sync_mistake(
+    db: &Database,
+    vfs_db: &Arc<VfsDatabase>,
+    llm_manager: &Arc<LLMManager>,
+    mistake_id: &str,
+) -> Result<MistakeKbSyncOutcome> {
+    let settings = load_kb_sync_settings(db);
+    let Some(folder_id) = settings.target_folder() else {
+        return Ok(MistakeKbSyncOutcome::Disabled);
+    };
+    let text = {
+        let conn = db
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        load_mistake_text(&conn, mistake_id)
+            .map_err(|e| AppError::database(format!("读取错题失败: {}", e)))?
+    };
+    let record = load_sync_record(db, mistake_id)?;
+    let existing = match &record {
+        Some(record) => load_synced_document(vfs_db, record)?,
+        None => None,
+    };
+
+    match plan_sync(text, existing.as_ref(), folder_id) {
+        SyncAction::Skip => {
+            // 笔记已被用户删除且错题也无内容时，清理残留记录
+            if record.is_some() && existing.is_none() {
+                delete_sync_record(db, mistake_id)?;
+            }
+            Ok(MistakeKbSyncOutcome::Unchanged)
+        }
+        SyncAction::Remove => {
+            remove_mistake_documents(db, vfs_db, llm_manager, &[mistake_id.to_string()]).await?;
+            Ok(MistakeKbSyncOutcome::Removed)
+        }
+        SyncAction::Move => {
+            let doc = existing.ok_or_else(|| AppError::internal("移动错题笔记时缺少同步记录"))?;
+            VfsFolderRepo::move_item_by_item_id(vfs_db, "note", &doc.note_id, Some(folder_id))
+                .map_err(vfs_err)?;
+            // 向量行带有 folder_id，需要重建索引才能按新文件夹检索
+            indexing_service(vfs_db, llm_manager)?
+                .index_resource(&doc.resource_id, Some(folder_id), None)
+                .await
+                .map_err(vfs_err)?;
+            Ok(MistakeKbSyncOutcome::Moved)
+        }
+        SyncAction::Upsert { text, hash } => {
+            let note = upsert_note(vfs_db, existing.as_ref(), folder_id, &text)?;
+            let service = indexing_service(vfs_db, llm_manager)?;
+            // 内容变化后笔记指向新资源，旧资源的向量不再需要
+            if let Some(doc) = existing
+                .as_ref()
+                .filter(|d| d.resource_id != note.resource_id)
+            {
+                service
+                    .delete_resource_index(&doc.resource_id)
+                    .await
+                    .map_err(vfs_err)?;
+            }
+            service
+                .index_resource(&note.resource_id, Some(folder_id), None)
+                .await
+                .map_err(vfs_err)?;
+            // 索引成功后才记录哈希，失败时下次同步会重试
+            save_sync_record(
+                db,
+                mistake_id,
+                &SyncRecord {
+                    note_id: note.id,
+                    content_hash: hash,
+                },
+            )?;
+            Ok(MistakeKbSyncOutcome::Synced)
+        }
+    }
+}
+
+/// 写入笔记内容：已有笔记时原地更新（必要时移动到目标文件夹），否则在目标文件夹新建
+fn upsert_note(
+    vfs_db: &VfsDatabase,
+    existing: Option<&SyncedDocument>,
+    folder_id: &str,
+    text: &str,
+) -> Result<VfsNote> {
+    let title = document_title(text);
+    let Some(doc) = existing else {
+        return VfsNoteRepo::create_note_in_folder(
+            vfs_db,
+            VfsCreateNoteParams {
+                title,
+                content: text.to_string(),
+                tags: vec![NOTE_TAG.to_string()],
+            },
+            Some(folder_id),
+        )
+        .map_err(vfs_err);
+    };
+    let note = VfsNoteRepo::update_note(
+        vfs_db,
+        &doc.note_id,
+        VfsUpdateNoteParams {
+            content: Some(text.to_string()),
+            title: Some(title),
+            tags: None,
+            expected_updated_at: None,
+        },
+    )
+    .map_err(vfs_err)?;
+    if doc.folder_id != folder_id {
+        VfsFolderRepo::move_item_by_item_id(vfs_db, "note", &doc.note_id, Some(folder_id))
+            .map_err(vfs_err)?;
+    }
+    Ok(note)
+}
+
+/// 逐条同步，单条失败只计数并记录日志
+pub async fn sync_mistakes(
+    db: &Database,
+    vfs_db: &Arc<VfsDatabase>,
+    llm_manager: &Arc<LLMManager>,
+    mistake_ids: &[String],
+) -> MistakeKbSyncReport {
+    let mut report = MistakeKbSyncReport::default();
+    for mistake_id in mistake_ids {
+        match sync_mistake(db, vfs_db, llm_manager, mistake_id).await {
+            Ok(outcome) => report.record(outcome),
+            Err(e) => {
+                report.failed += 1;
+                log::warn!("[MistakeKbSync] 同步错题 {} 失败: {}", mistake_id, e);
+            }
+        }
+    }
+    report
+}
+
+/// 错题被删除后移除对应的笔记与索引（与同步是否开启无关），返回移除的笔记数
+pub async fn remove_mistake_documents(
+    db: &Database,
+    vfs_db: &Arc<VfsDatabase>,
+    llm_manager: &Arc<LLMManager>,
+    mistake_ids: &[String],
+) -> Result<usize> {
+    let mut service = None;
+    let mut removed = 0;
+    for mistake_id in mistake_ids {
+        let Some(record) = load_sync_record(db, mistake_id)? else {
+            continue;
+        };
+        if let Some(doc) = load_synced_document(vfs_db, &record)? {
+            VfsNoteRepo::delete_note_with_folder_item(vfs_db, &doc.note_id).map_err(vfs_err)?;
+            if service.is_none() {
+                service = Some(indexing_service(vfs_db, llm_manager)?);
+            }
+            if let Some(service) = &service {
+                service
+                    .delete_resource_index(&doc.resource_id)
+                    .await
+                    .map_err(vfs_err)?;
+            }
+            removed += 1;
+        }
+        delete_sync_record(db, mistake_id)?;
+    }
+    Ok(removed)
+}
+
+/// 同步开启时在后台同步给定错题，不阻塞调用方
+pub fn spawn_sync_mistakes(
+    db: Arc<Database>,
+    vfs_db: Option<Arc<VfsDatabase>>,
+    llm_manager: Arc<LLMManager>,
+    mistake_ids: Vec<String>,
+) {
+    if mistake_ids.is_empty() || load_kb_sync_settings(&db).target_folder().is_none() {
+        return;
+    }
+    let Some(vfs_db) = vfs_db else {
+        log::warn!("[MistakeKbSync] VFS 数据库不可用，跳过错题同步");
+        return;
+    };
+    tokio::spawn(async move {
+        let report = sync_mistakes(&db, &vfs_db, &llm_manager, &mistake_ids).await;
+        log::info!(
+            "[MistakeKbSync] 后台同步 {} 条错题：新增/更新 {}，失败 {}",
+            mistake_ids.len(),
+            report.synced,
+            report.failed
+        );
+    });
+}
+
+fn require_vfs_db(state: &AppState) -> Result<Arc<VfsDatabase>> {
+    state
+        .vfs_db
+        .clone()
+        .ok_or_else(|| AppError::configuration("VFS database not configured"))
+}
+
+#[tauri::command]
+pub async fn get_mistake_kb_sync_settings(
+    state: State<'_, AppState>,
+) -> Result<MistakeKbSyncSettings> {
+    Ok(load_kb_sync_settings(&state.database))
+}
+
+/// 保存同步设置；开启时目标文件夹必须存在
+///
+/// 不会立即同步已有错题，前端确认后调用 `sync_mistakes_to_knowledge_base`。
+#[tauri::command]
+pub async fn save_mistake_kb_sync_settings(
+    settings: MistakeKbSyncSettings,
+    state: State<'_, AppState>,
+) -> Result<MistakeKbSyncSettings> {
+    let folder_id = settings
+        .folder_id
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty());
+    if settings.enabled && folder_id.is_none() {
+        return Err(AppError::validation("开启错题同步需要指定目标文件夹"));
+    }
+    if let Some(id) = &folder_id {
+        let vfs_db = require_vfs_db(&state)?;
+        if !VfsFolderRepo::folder_exists(&vfs_db, id).map_err(vfs_err)? {
+            return Err(AppError::not_found(format!("文件夹不存在: {}", id)));
+        }
+    }
+
+    let save_err = |e: anyhow::Error| AppError::database(format!("保存错题同步设置失败: {}", e));
+    state
+        .database
+        .save_setting(KB_SYNC_ENABLED_KEY, &settings.enabled.to_string())
+        .map_err(save_err)?;
+    state
+        .database
+        .save_setting(KB_SYNC_FOLDER_KEY, folder_id.as_deref().unwrap_or(""))
+        .map_err(save_err)?;
+    Ok(MistakeKbSyncSettings {
+        enabled: settings.enabled,
+        folder_id,
+    })
+}
+
+/// 同步单条错题（新建或编辑后调用）
+#[tauri::command]
+pub async fn sync_mistake_to_knowledge_base(
+    mistake_id: String,
+    state: State<'_, AppState>,
+) -> Result<MistakeKbSyncOutcome> {
+    let vfs_db = require_vfs_db(&state)?;
+    sync_mistake(&state.database, &vfs_db, &state.llm_manager, &mistake_id).await
+}
+
+/// 全量同步：补齐所有错题，并移除已删除错题的笔记
+#[tauri::command]
+pub async fn sync_mistakes_to_knowledge_base(
+    state: State<'_, AppState>,
+) -> Result<MistakeKbSyncReport> {
+    if load_kb_sync_settings(&state.database)
+        .target_folder()
+        .is_none()
+    {
+        return Err(AppError::validation("错题同步未开启或未指定目标文件夹"));
+    }
+    let vfs_db = require_vfs_db(&state)?;
+    let mut mistake_ids = {
+        let conn = state
+            .database
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        conn.prepare("SELECT id FROM mistakes ORDER BY created_at")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<String>>>()
+            })
+            .map_err(|e| AppError::database(format!("读取错题失败: {}", e)))?
+    };
+    // 错题已被硬删除的笔记同样需要处理
+    for id in synced_mistake_ids(&state.database)? {
+        if !mistake_ids.contains(&id) {
+            mistake_ids.push(id);
+        }
+    }
+
+    let report = sync_mistakes(&state.database, &vfs_db, &state.llm_manager, &mistake_ids).await;
+    log::info!(
+        "[MistakeKbSync] 全量同步完成：新增/更新 {}，移动 {}，未变 {}，移除 {}，失败 {}",
+        report.synced,
+        report.moved,
+        report.unchanged,
+        report.removed,
+        report.failed
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn, migrated_database};
+    use tempfile::tempdir;
+
+    fn synced(content_hash: &str, folder_id: &str) -> SyncedDocument {
+        SyncedDocument {
+            note_id: "note_1".to_string(),
+            resource_id: "res_1".to_string(),
+            content_hash: content_hash.to_string(),
+            folder_id: folder_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_document_text_skips_empty_and_duplicate_sections() {
+        assert_eq!(build_document_text("  ", "", " "), None);
+        assert_eq!(
+            build_document_text("求导", "求导", "").as_deref(),
+            Some("题目：求导")
+        );
+        let text = build_document_text("", "y=x^2", "2x").unwrap();
+        assert_eq!(text, "题目原文：y=x^2\n\n解析与总结：2x");
+        assert_eq!(document_title(&text), "错题：y=x^2");
+    }
+
+    #[test]
+    fn test_plan_sync_uses_content_hash() {
+        let text = "题目：求导".to_string();
+        let synced = synced(&text_hash(&text), "fld-a");
+        assert_eq!(
+            plan_sync(Some(text.clone()), Some(&synced), "fld-a"),
+            SyncAction::Skip
+        );
+        assert_eq!(
+            plan_sync(Some(text.clone()), Some(&synced), "fld-b"),
+            SyncAction::Move
+        );
+        assert!(matches!(
+            plan_sync(Some("题目：积分".to_string()), Some(&synced), "fld-a"),
+            SyncAction::Upsert { .. }
+        ));
+        assert!(matches!(
+            plan_sync(Some(text), None, "fld-a"),
+            SyncAction::Upsert { .. }
+        ));
+        assert_eq!(plan_sync(None, Some(&synced), "fld-a"), SyncAction::Remove);
+        assert_eq!(plan_sync(None, None, "fld-a"), SyncAction::Skip);
+    }
+
+    #[test]
+    fn test_loads_mistake_text_skipping_deleted() {
+        let conn = migrated_conn().unwrap();
+        insert_mistake(
+            &conn,
+            "a",
+            &[("user_question", &"求导"), ("mistake_summary", &"2x")],
+        )
+        .unwrap();
+        insert_mistake(
+            &conn,
+            "b",
+            &[("user_question", &"积分"), ("deleted_at", &"2026-01-01")],
+        )
+        .unwrap();
+
+        assert_eq!(
+            load_mistake_text(&conn, "a").unwrap().as_deref(),
+            Some("题目：求导\n\n解析与总结：2x")
+        );
+        assert_eq!(load_mistake_text(&conn, "b").unwrap(), None);
+        assert_eq!(load_mistake_text(&conn, "missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_sync_records_round_trip() {
+        let dir = tempdir().unwrap();
+        let db = migrated_database(dir.path()).unwrap();
+        let record = SyncRecord {
+            note_id: "note_a".to_string(),
+            content_hash: "hash".to_string(),
+        };
+        save_sync_record(&db, "a", &record).unwrap();
+        save_sync_record(
+            &db,
+            "gone",
+            &SyncRecord {
+                note_id: "note_gone".to_string(),
+                content_hash: "hash".to_string(),
+            },
+        )
+        .unwrap();
+        db.save_setting(&record_key("broken"), "not json").unwrap();
+
+        assert_eq!(load_sync_record(&db, "a").unwrap(), Some(record));
+        assert_eq!(load_sync_record(&db, "broken").unwrap(), None);
+        let mut ids = synced_mistake_ids(&db).unwrap();
+        ids.sort();
+        assert_eq!(ids, vec!["a", "broken", "gone"]);
+
+        delete_sync_record(&db, "gone").unwrap();
+        assert_eq!(load_sync_record(&db, "gone").unwrap(), None);
+    }
+}
@@ -0,0 +1,92 @@
+//! 错题语言命令
+//!
+//! `language` 由 OCR 识别时自动写入（见 [`crate::ocr_language`]），识别有误时可手动修改。
+//! 错题总结等后续处理按该语言回答；`mixed` 与空值表示不指定语言。
+
+use crate::commands::AppState;
+use crate::database::has_column;
+use crate::models::AppError;
+use crate::ocr_language::MIXED_LANGUAGE;
+use crate::response_language::{normalize_language, AUTO_LANGUAGE};
+use rusqlite::{params, Connection};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+fn db_err(e: rusqlite::Error) -> AppError {
+    AppError::database(format!("保存错题语言失败: {}", e))
+}
+
+/// 语言代码统一为规范写法；空白与 `auto` 视为清除
+fn parse_mistake_language(language: Option<&str>) -> Option<String> {
+    let language = language?.trim();
+    if language.eq_ignore_ascii_case(MIXED_LANGUAGE) {
+        return Some(MIXED_LANGUAGE.to_string());
+    }
+    normalize_language(language).filter(|l| l != AUTO_LANGUAGE)
+}
+
+fn set_mistake_language(conn: &Connection, mistake_id: &str, language: Option<&str>) -> Result<()> {
+    if !has_column(conn, "mistakes", "language").map_err(db_err)? {
+        return Err(AppError::configuration(
+            "错题库尚未升级到支持题目语言的版本，请重启应用完成数据库迁移",
+        ));
+    }
+    let updated = conn
+        .execute(
+            "UPDATE mistakes SET language = ?1, updated_at = ?2 WHERE id = ?3",
+            params![language, chrono::Utc::now().to_rfc3339(), mistake_id],
+        )
+        .map_err(db_err)?;
+    if updated == 0 {
+        return Err(AppError::not_found(format!("错题不存在: {}", mistake_id)));
+    }
+    Ok(())
+}
+
+/// 修改错题语言，返回规范化后的语言代码；传入 None 或空白内容即清除
+#[tauri::command]
+pub async fn update_mistake_language(
+    mistake_id: String,
+    language: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Option<String>> {
+    let language = parse_mistake_language(language.as_deref());
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    set_mistake_language(&conn, mistake_id.trim(), language.as_deref())?;
+    Ok(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    #[test]
+    fn test_language_is_normalized_and_missing_mistake_is_rejected() {
+        assert_eq!(parse_mistake_language(Some(" ")), None);
+        assert_eq!(parse_mistake_language(Some("auto")), None);
+        assert_eq!(
+            parse_mistake_language(Some("Mixed")).as_deref(),
+            Some(MIXED_LANGUAGE)
+        );
+        assert_eq!(
+            parse_mistake_language(Some("English")).as_deref(),
+            Some("en")
+        );
+
+        let conn = migrated_conn().unwrap();
+        insert_mistake(&conn, "m1", &[]).unwrap();
+        set_mistake_language(&conn, "m1", Some("en")).unwrap();
+        let stored: Option<String> = conn
+            .query_row("SELECT language FROM mistakes WHERE id = 'm1'", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(stored.as_deref(), Some("en"));
+        assert!(set_mistake_language(&conn, "missing", None).is_err());
+    }
+}
@@ -0,0 +1,407 @@
+//! 错题难度与掌握度
+//!
+//! 每道错题可设置 1-5 难度，并维护 0-100 的掌握度。复习时记录答对 / 答错，
+//! 按 [`next_mastery_level`] 更新掌握度（简化的 SM-2 思路：答对按剩余差距的一定比例
+//! 增长，难题增长更慢；答错减半）。复习清单优先选择掌握度低、久未复习的错题。
+//!
+//! 掌握度算法通过 [`MasteryUpdateFn`] 传入，替换算法只需换一个函数。
+
+use crate::cmd::mistake_tags::mistake_filter;
+use crate::commands::AppState;
+use crate::database::has_column;
+use crate::models::AppError;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const MAX_MASTERY_LEVEL: u8 = 100;
+/// 答对时的最小增量，避免掌握度接近上限后几乎不再变化
+const MIN_CORRECT_GAIN: u8 = 5;
+/// 未设置难度时的增长比例
+const DEFAULT_GAIN_RATE: f64 = 0.25;
+/// 超过该天数未复习视为“时间因素”已满
+const STALE_AFTER_DAYS: f64 = 30.0;
+/// 复习优先级中掌握度的权重（其余为距上次复习时间的权重）
+const MASTERY_WEIGHT: f64 = 0.7;
+const DEFAULT_REVIEW_SET_LIMIT: usize = 20;
+const MAX_REVIEW_SET_LIMIT: usize = 200;
+
+/// 掌握度更新算法：(当前掌握度, 是否答对, 难度) -> 新掌握度
+pub type MasteryUpdateFn = fn(u8, bool, Option<u8>) -> u8;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeMastery {
+    pub id: String,
+    /// 难度 1-5，未设置为空
+    pub difficulty: Option<u8>,
+    /// 掌握度 0-100
+    pub mastery_level: u8,
+    pub review_count: i64,
+    pub last_reviewed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewSetItem {
+    pub id: String,
+    pub user_question: String,
+    pub tags: Vec<String>,
+    pub difficulty: Option<u8>,
+    pub mastery_level: u8,
+    pub review_count: i64,
+    pub last_reviewed_at: Option<String>,
+    /// 复习优先级（0~1，越大越优先）
+    pub priority: f64,
+}
+
+/// 默认掌握度算法
+///
+/// 答对：增加 `(100 - 当前) × 比例`，比例随难度从 0.35（难度 1）降到 0.15（难度 5），
+/// 至少增加 5；答错：掌握度减半。
+pub fn next_mastery_level(current: u8, correct: bool, difficulty: Option<u8>) -> u8 {
+    let current = current.min(MAX_MASTERY_LEVEL);
+    if !correct {
+        return current / 2;
+    }
+    let rate = match difficulty {
+        Some(d @ 1..=5) => 0.35 - f64::from(d - 1) * 0.05,
+        _ => DEFAULT_GAIN_RATE,
+    };
+    let gain = (f64::from(MAX_MASTERY_LEVEL - current) * rate).round() as u8;
+    current
+        .saturating_add(gain.max(MIN_CORRECT_GAIN))
+        .min(MAX_MASTERY_LEVEL)
+}
+
+/// 复习优先级：掌握度越低、距上次复习（从未复习则从创建）越久越优先
+pub fn review_priority(mastery_level: u8, days_since_review: f64) -> f64 {
+    let unmastered = 1.0 - f64::from(mastery_level.min(MAX_MASTERY_LEVEL)) / 100.0;
+    let staleness = (days_since_review.max(0.0) / STALE_AFTER_DAYS).min(1.0);
+    MASTERY_WEIGHT * unmastered + (1.0 - MASTERY_WEIGHT) * staleness
+}
+
+fn db_err(e: rusqlite::Error) -> AppError {
+    AppError::database(format!("读写错题掌握度失败: {}", e))
+}
+
+fn ensure_mastery_columns(conn: &Connection) -> Result<()> {
+    if has_column(conn, "mistakes", "mastery_level").map_err(db_err)? {
+        Ok(())
+    } else {
+        Err(AppError::configuration(
+            "错题库尚未升级到支持掌握度的版本，请重启应用完成数据库迁移",
+        ))
+    }
+}
+
+fn validate_difficulty(difficulty: Option<u8>) -> Result<()> {
+    match difficulty {
+        Some(d) if !(1..=5).contains(&d) => Err(AppError::validation("难度必须在 1 到 5 之间")),
+        _ => Ok(()),
+    }
+}
+
+fn load_mastery(conn: &Connection, mistake_id: &str) -> Result<MistakeMastery> {
+    conn.query_row(
+        "SELECT id, NULLIF(difficulty, 0), mastery_level, COALESCE(review_count, 0), \
+                last_reviewed_at \
+         FROM mistakes WHERE id = ?1",
+        params![mistake_id],
+        |row| {
+            Ok(MistakeMastery {
+                id: row.get(0)?,
+                difficulty: row.get(1)?,
+                mastery_level: row.get::<_, i64>(2)?.clamp(0, 100) as u8,
+                review_count: row.get(3)?,
+                last_reviewed_at: row.get(4)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(db_err)?
+    .ok_or_else(|| AppError::not_found(format!("错题不存在: {}", mistake_id)))
+}
+
+/// 设置难度（None 清除）与掌握度（None 保持不变）
+pub(crate) fn set_mistake_mastery(
+    conn: &Connection,
+    mistake_id: &str,
+    difficulty: Option<u8>,
+    mastery_level: Option<u8>,
+) -> Result<MistakeMastery> {
+    ensure_mastery_columns(conn)?;
+    validate_difficulty(difficulty)?;
+    if mastery_level.is_some_and(|m| m > MAX_MASTERY_LEVEL) {
+        return Err(AppError::validation("掌握度必须在 0 到 100 之间"));
+    }
+    let updated = conn
+        .execute(
+            "UPDATE mistakes SET difficulty = ?1, \
+                    mastery_level = COALESCE(?2, mastery_level), updated_at = ?3 \
+             WHERE id = ?4",
+            params![
+                difficulty,
+                mastery_level,
+                Utc::now().to_rfc3339(),
+                mistake_id
+            ],
+        )
+        .map_err(db_err)?;
+    if updated == 0 {
+        return Err(AppError::not_found(format!("错题不存在: {}", mistake_id)));
+    }
+    load_mastery(conn, mistake_id)
+}
+
+/// 记录一次复习结果：按 `update` 更新掌握度，复习次数 +1，并刷新复习与访问时间
+pub(crate) fn apply_review_outcome(
+    conn: &Connection,
+    mistake_id: &str,
+    correct: bool,
+    update: MasteryUpdateFn,
+) -> Result<MistakeMastery> {
+    ensure_mastery_columns(conn)?;
+    let current = load_mastery(conn, mistake_id)?;
+    let next = update(current.mastery_level, correct, current.difficulty).min(MAX_MASTERY_LEVEL);
+    let now = Utc::now().to_rfc3339();
+    let touch_accessed = if has_column(conn, "mistakes", "last_accessed_at").map_err(db_err)? {
+        ", last_accessed_at = ?2"
+    } else {
+        ""
+    };
+    conn.execute(
+        &format!(
+            "UPDATE mistakes SET mastery_level = ?1, review_count = COALESCE(review_count, 0) + 1, \
+                    last_reviewed_at = ?2, updated_at = ?2{} \
+             WHERE id = ?3",
+            touch_accessed
+        ),
+        params![next, now, mistake_id],
+    )
+    .map_err(db_err)?;
+    load_mastery(conn, mistake_id)
+}
+
+fn days_between(from: &str, now: DateTime<Utc>) -> Option<f64> {
+    let from = DateTime::parse_from_rfc3339(from).ok()?;
+    Some((now - from.with_timezone(&Utc)).num_seconds() as f64 / 86_400.0)
+}
+
+/// 生成复习清单：排除已删除与已归档的错题，按复习优先级降序取前 `limit` 道
+pub(crate) fn select_review_set(
+    conn: &Connection,
+    subject: Option<&str>,
+    limit: usize,
+    now: DateTime<Utc>,
+) -> Result<Vec<ReviewSetItem>> {
+    ensure_mastery_columns(conn)?;
+    let (mut where_sql, values) = mistake_filter(conn, subject).map_err(db_err)?;
+    if has_column(conn, "mistakes", "archived_at").map_err(db_err)? {
+        let archived = "mistakes.archived_at IS NULL";
+        where_sql = if where_sql.is_empty() {
+            format!("WHERE {}", archived)
+        } else {
+            format!("{} AND {}", where_sql, archived)
+        };
+    }
+    let sql = format!(
+        "SELECT id, COALESCE(user_question, ''), tags, NULLIF(difficulty, 0), mastery_level, \
+                COALESCE(review_count, 0), last_reviewed_at, created_at \
+         FROM mistakes {}",
+        where_sql
+    );
+    let mut stmt = conn.prepare(&sql).map_err(db_err)?;
+    let rows = stmt
+        .query_map(rusqlite::params_from_iter(values), |row| {
+            let tags_json: Option<String> = row.get(2)?;
+            let last_reviewed_at: Option<String> = row.get(6)?;
+            let created_at: String = row.get(7)?;
+            let mastery_level = row.get::<_, i64>(4)?.clamp(0, 100) as u8;
+            let days = last_reviewed_at
+                .as_deref()
+                .and_then(|t| days_between(t, now))
+                .or_else(|| days_between(&created_at, now))
+                .unwrap_or(STALE_AFTER_DAYS);
+            Ok(ReviewSetItem {
+                id: row.get(0)?,
+                user_question: row.get(1)?,
+                tags: tags_json
+                    .and_then(|t| serde_json::from_str(&t).ok())
+                    .unwrap_or_default(),
+                difficulty: row.get(3)?,
+                mastery_level,
+                review_count: row.get(5)?,
+                last_reviewed_at,
+                priority: review_priority(mastery_level, days),
+            })
+        })
+        .map_err(db_err)?;
+    let mut items = rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err)?;
+    items.sort_by(|a, b| {
+        b.priority
+            .total_cmp(&a.priority)
+            .then(a.mastery_level.cmp(&b.mastery_level))
+            .then(a.id.cmp(&b.id))
+    });
+    items.truncate(limit);
+    Ok(items)
+}
+
+/// 更新错题难度与掌握度
+///
+/// `difficulty` 为空时清除难度；`mastery_level` 为空时保持原值（用于手动校正掌握度）。
+#[tauri::command]
+pub async fn update_mistake_mastery(
+    id: String,
+    difficulty: Option<u8>,
+    mastery_level: Option<u8>,
+    state: State<'_, AppState>,
+) -> Result<MistakeMastery> {
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    set_mistake_mastery(&conn, &id, difficulty, mastery_level)
+}
+
+/// 记录一次复习结果（答对 / 答错），返回更新后的掌握度
+#[tauri::command]
+pub async fn record_review_outcome(
+    mistake_id: String,
+    correct: bool,
+    state: State<'_, AppState>,
+) -> Result<MistakeMastery> {
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    apply_review_outcome(&conn, &mistake_id, correct, next_mastery_level)
+}
+
+/// 生成复习清单；`limit` 默认 20，`subject` 为空时覆盖全部科目
+#[tauri::command]
+pub async fn build_review_set(
+    limit: Option<usize>,
+    subject: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ReviewSetItem>> {
+    let limit = limit
+        .unwrap_or(DEFAULT_REVIEW_SET_LIMIT)
+        .clamp(1, MAX_REVIEW_SET_LIMIT);
+    let subject = subject.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    select_review_set(&conn, subject, limit, Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn setup_conn() -> Connection {
+        let conn = migrated_conn().unwrap();
+        for (id, subject, question) in [
+            ("fresh", "数学", "新错题"),
+            ("stale", "数学", "久未复习"),
+            ("mastered", "数学", "已掌握"),
+            ("physics", "物理", "物理题"),
+            ("deleted", "数学", "已删除"),
+            ("archived", "数学", "已归档"),
+        ] {
+            insert_mistake(
+                &conn,
+                id,
+                &[("subject", &subject), ("user_question", &question)],
+            )
+            .unwrap();
+        }
+        conn.execute_batch(
+            "UPDATE mistakes SET created_at = '2026-03-01T00:00:00Z', tags = '[\"函数\"]'
+                WHERE id = 'fresh';
+             UPDATE mistakes SET mastery_level = 40, last_reviewed_at = '2026-01-10T00:00:00Z'
+                WHERE id = 'stale';
+             UPDATE mistakes SET mastery_level = 95, last_reviewed_at = '2026-03-01T00:00:00Z'
+                WHERE id = 'mastered';
+             UPDATE mistakes SET deleted_at = '2026-02-01' WHERE id = 'deleted';
+             UPDATE mistakes SET archived_at = '2026-02-01' WHERE id = 'archived';",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_next_mastery_level_depends_on_outcome_and_difficulty() {
+        assert_eq!(next_mastery_level(0, true, None), 25);
+        assert_eq!(next_mastery_level(0, true, Some(1)), 35);
+        assert_eq!(next_mastery_level(0, true, Some(5)), 15);
+        // 接近上限时仍有最小增量，且不超过 100
+        assert_eq!(next_mastery_level(98, true, Some(5)), 100);
+        assert_eq!(next_mastery_level(80, false, None), 40);
+        assert_eq!(next_mastery_level(1, false, None), 0);
+    }
+
+    #[test]
+    fn test_review_outcome_updates_counters_and_uses_plugged_algorithm() {
+        let conn = setup_conn();
+        let after = apply_review_outcome(&conn, "stale", true, next_mastery_level).unwrap();
+        assert_eq!(after.mastery_level, 55);
+        assert_eq!(after.review_count, 1);
+        assert!(after.last_reviewed_at.is_some());
+        let accessed: String = conn
+            .query_row(
+                "SELECT last_accessed_at FROM mistakes WHERE id = 'stale'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(Some(accessed), after.last_reviewed_at);
+
+        let always_full: MasteryUpdateFn = |_, _, _| 100;
+        let after = apply_review_outcome(&conn, "stale", false, always_full).unwrap();
+        assert_eq!(after.mastery_level, 100);
+        assert_eq!(after.review_count, 2);
+
+        assert!(apply_review_outcome(&conn, "missing", true, next_mastery_level).is_err());
+    }
+
+    #[test]
+    fn test_set_mastery_validates_and_updates_fields() {
+        let conn = setup_conn();
+        let current = load_mastery(&conn, "fresh").unwrap();
+        assert_eq!(current.difficulty, None);
+
+        let updated = set_mistake_mastery(&conn, "fresh", Some(4), None).unwrap();
+        assert_eq!(updated.difficulty, Some(4));
+        assert_eq!(updated.mastery_level, 0);
+        let updated = set_mistake_mastery(&conn, "fresh", None, Some(60)).unwrap();
+        assert_eq!(updated.difficulty, None);
+        assert_eq!(updated.mastery_level, 60);
+
+        assert!(set_mistake_mastery(&conn, "fresh", Some(6), None).is_err());
+        assert!(set_mistake_mastery(&conn, "fresh", None, Some(101)).is_err());
+    }
+
+    #[test]
+    fn test_review_set_prioritises_low_mastery_and_stale_items() {
+        let conn = setup_conn();
+        let now = DateTime::parse_from_rfc3339("2026-03-02T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let items = select_review_set(&conn, Some("数学"), 10, now).unwrap();
+        let ids: Vec<_> = items.iter().map(|i| i.id.as_str()).collect();
+        // 久未复习的中等掌握度错题排在刚录入的新错题之前
+        assert_eq!(ids, vec!["stale", "fresh", "mastered"]);
+        assert!(items[0].priority > items[1].priority);
+
+        let all = select_review_set(&conn, None, 2, now).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].id, "physics");
+    }
+}
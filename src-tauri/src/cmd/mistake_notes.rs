@@ -0,0 +1,64 @@
+//! 错题用户批注命令
+//!
+//! `user_notes` 是用户（教师）自己写的讲评笔记，与 AI 生成的 `user_error_analysis`
+//! 相互独立。批注只用于展示、检索与导出，不会自动拼入发送给模型的上下文。
+
+use crate::commands::AppState;
+use crate::database::{MistakeSearchPage, TagMatchMode};
+use crate::models::AppError;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 更新错题批注；传入 None 或空白内容即清除
+#[tauri::command]
+pub async fn update_mistake_user_notes(
+    id: String,
+    user_notes: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    state
+        .database
+        .set_mistake_user_notes(&id, user_notes.as_deref())
+        .map_err(|e| AppError::database(format!("保存错题批注失败: {}", e)))
+}
+
+/// 读取错题批注；错题不存在时返回 not_found，数据库错误按 database 返回
+#[tauri::command]
+pub async fn get_mistake_user_notes(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<Option<String>> {
+    state
+        .database
+        .get_mistake_user_notes(&id)
+        .map_err(|e| AppError::database(format!("读取错题批注失败: {}", e)))?
+        .ok_or_else(|| AppError::not_found(format!("错题不存在: {}", id)))
+}
+
+/// 按关键词检索错题（题干、OCR 文本与用户批注）
+///
+/// 传入 `tags` 时只返回带有这些标签的错题：`tag_match` 为 `all`（默认）需同时带有全部标签，
+/// 为 `any` 时带有任一标签即可。关键词与标签同时生效；关键词为空时仅按标签筛选。
+/// 按 `offset` / `limit` 分页，`total` 为满足条件的总数，供前端渲染分页器。
+#[tauri::command]
+pub async fn search_mistakes_fulltext(
+    query: String,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    tags: Option<Vec<String>>,
+    tag_match: Option<TagMatchMode>,
+    state: State<'_, AppState>,
+) -> Result<MistakeSearchPage> {
+    let _timer = crate::command_metrics::start("search_mistakes_fulltext");
+    state
+        .database
+        .search_mistakes_fulltext(
+            &query,
+            limit,
+            offset,
+            tags.as_deref().unwrap_or_default(),
+            tag_match.unwrap_or_default(),
+        )
+        .map_err(|e| AppError::database(format!("检索错题失败: {}", e)))
+}
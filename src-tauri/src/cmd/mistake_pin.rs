@@ -0,0 +1,54 @@
+//! 错题置顶与星级命令
+//!
+//! 置顶错题在检索结果与归档列表中排在最前，且不会被自动归档。
+//! 星级（1-5）只用于标记与展示，不参与排序。
+
+use crate::commands::AppState;
+use crate::models::AppError;
+use serde::Serialize;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakePin {
+    pub pinned: bool,
+    pub star_rating: Option<u8>,
+}
+
+/// 设置错题置顶与星级；`star_rating` 传 None 表示清除星级
+#[tauri::command]
+pub async fn set_mistake_pin(
+    id: String,
+    pinned: bool,
+    star_rating: Option<u8>,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    if let Some(rating) = star_rating {
+        if !(1..=5).contains(&rating) {
+            return Err(AppError::validation(format!(
+                "星级必须在 1-5 之间: {}",
+                rating
+            )));
+        }
+    }
+    state
+        .database
+        .set_mistake_pin(&id, pinned, star_rating)
+        .map_err(|e| AppError::database(format!("设置错题置顶失败: {}", e)))
+}
+
+#[tauri::command]
+pub async fn get_mistake_pin(id: String, state: State<'_, AppState>) -> Result<MistakePin> {
+    let pin = state
+        .database
+        .get_mistake_pin(&id)
+        .map_err(|e| AppError::database(format!("读取错题置顶状态失败: {}", e)))?;
+    let (pinned, star_rating) =
+        pin.ok_or_else(|| AppError::not_found(format!("错题不存在: {}", id)))?;
+    Ok(MistakePin {
+        pinned,
+        star_rating,
+    })
+}
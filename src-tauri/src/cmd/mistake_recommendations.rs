@@ -0,0 +1,495 @@
+//! 相关错题推荐
+//!
+//! 以一道错题为起点推荐需要一起复习的错题。排序由四个因子加权得到：
+//! - 标签重合度（Jaccard）；
+//! - 题干 + OCR 文本的向量相似度（未配置嵌入模型时为 0）；
+//! - 新近程度（按 30 天半衰期衰减）；
+//! - 错题频度：候选错题所属标签在科目内出错次数越多，越可能是薄弱点。
+//!
+//! 权重可在调用时传入并保存到设置项 `mistake.recommendation.weights`，之后的调用沿用；
+//! 每条推荐都返回各因子的贡献值，便于解释排序。
+
+use super::mistake_tags::{cosine_similarity, mistake_filter, tag_frequencies};
+use super::recommendation_cache::{data_fingerprint, load_cache_ttl, RECOMMENDATION_CACHE};
+use crate::commands::AppState;
+use crate::database::{has_column, Database};
+use crate::models::AppError;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+use tauri::State;
+use tracing::warn;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const RECOMMENDATION_WEIGHTS_KEY: &str = "mistake.recommendation.weights";
+
+/// 参与排序的候选错题数上限
+const MAX_CANDIDATES: usize = 300;
+/// 单条错题送入嵌入模型的最大字符数
+const MAX_EMBED_CHARS: usize = 500;
+/// 新近程度的半衰期（天）
+const RECENCY_HALF_LIFE_DAYS: f64 = 30.0;
+const PREVIEW_CHARS: usize = 80;
+
+/// 推荐排序权重（非负，计算时按总和归一化）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RecommendationWeights {
+    pub tag_overlap: f32,
+    pub embedding_similarity: f32,
+    pub recency: f32,
+    pub mistake_frequency: f32,
+}
+
+impl Default for RecommendationWeights {
+    fn default() -> Self {
+        Self {
+            tag_overlap: 0.3,
+            embedding_similarity: 0.35,
+            recency: 0.15,
+            mistake_frequency: 0.2,
+        }
+    }
+}
+
+impl RecommendationWeights {
+    pub fn validate(&self) -> Result<()> {
+        let values = [
+            self.tag_overlap,
+            self.embedding_similarity,
+            self.recency,
+            self.mistake_frequency,
+        ];
+        if values.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return Err(AppError::validation("推荐权重必须为非负数"));
+        }
+        if values.iter().sum::<f32>() <= 0.0 {
+            return Err(AppError::validation("推荐权重不能全部为 0"));
+        }
+        Ok(())
+    }
+
+    fn normalized(&self) -> Self {
+        let total =
+            self.tag_overlap + self.embedding_similarity + self.recency + self.mistake_frequency;
+        Self {
+            tag_overlap: self.tag_overlap / total,
+            embedding_similarity: self.embedding_similarity / total,
+            recency: self.recency / total,
+            mistake_frequency: self.mistake_frequency / total,
+        }
+    }
+}
+
+/// 读取保存的权重；未设置或解析失败时使用默认值
+pub fn load_recommendation_weights(db: &Database) -> RecommendationWeights {
+    db.get_setting(RECOMMENDATION_WEIGHTS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<RecommendationWeights>(&raw).ok())
+        .filter(|w| w.validate().is_ok())
+        .unwrap_or_default()
+}
+
+pub fn save_recommendation_weights(db: &Database, weights: &RecommendationWeights) -> Result<()> {
+    weights.validate()?;
+    let json = serde_json::to_string(weights)
+        .map_err(|e| AppError::internal(format!("序列化推荐权重失败: {}", e)))?;
+    db.save_setting(RECOMMENDATION_WEIGHTS_KEY, &json)
+        .map_err(|e| AppError::database(e.to_string()))
+}
+
+/// 各因子对最终得分的贡献（= 归一化权重 × 因子值）
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FactorContributions {
+    pub tag_overlap: f32,
+    pub embedding_similarity: f32,
+    pub recency: f32,
+    pub mistake_frequency: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeRecommendation {
+    pub mistake_id: String,
+    pub preview: String,
+    pub tags: Vec<String>,
+    pub created_at: String,
+    /// 综合得分（0~1）
+    pub score: f32,
+    pub contributions: FactorContributions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendationResult {
+    pub weights: RecommendationWeights,
+    /// 是否使用了向量相似度（未配置嵌入模型时为 false）
+    pub embedding_used: bool,
+    pub recommendations: Vec<MistakeRecommendation>,
+}
+
+#[derive(Debug, Clone)]
+struct MistakeEntry {
+    id: String,
+    text: String,
+    tags: Vec<String>,
+    created_at: String,
+}
+
+/// 单个候选的原始因子值（均在 0~1）
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct FactorValues {
+    tag_overlap: f32,
+    embedding_similarity: f32,
+    recency: f32,
+    mistake_frequency: f32,
+}
+
+fn db_err(e: rusqlite::Error) -> AppError {
+    AppError::database(format!("读取推荐候选错题失败: {}", e))
+}
+
+fn row_to_entry(row: &rusqlite::Row<'_>) -> rusqlite::Result<MistakeEntry> {
+    let question: String = row.get(1)?;
+    let ocr_text: String = row.get(2)?;
+    let tags_json: String = row.get(3)?;
+    Ok(MistakeEntry {
+        id: row.get(0)?,
+        text: format!("{}\n{}", question.trim(), ocr_text.trim())
+            .trim()
+            .chars()
+            .take(MAX_EMBED_CHARS)
+            .collect(),
+        tags: serde_json::from_str::<Vec<String>>(&tags_json)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect(),
+        created_at: row.get(4)?,
+    })
+}
+
+const ENTRY_COLUMNS: &str = "mistakes.id, COALESCE(user_question, ''), COALESCE(ocr_text, ''), \
+     COALESCE(tags, '[]'), created_at";
+
+/// 读取起点错题及其科目
+fn load_target(
+    conn: &Connection,
+    mistake_id: &str,
+) -> rusqlite::Result<Option<(MistakeEntry, Option<String>)>> {
+    let subject_col = if has_column(conn, "mistakes", "subject")? {
+        "subject"
+    } else {
+        "NULL"
+    };
+    conn.query_row(
+        &format!(
+            "SELECT {}, {} FROM mistakes WHERE id = ?1",
+            ENTRY_COLUMNS, subject_col
+        ),
+        params![mistake_id],
+        |row| Ok((row_to_entry(row)?, row.get::<_, Option<String>>(5)?)),
+    )
+    .optional()
+}
+
+fn load_candidates(
+    conn: &Connection,
+    subject: Option<&str>,
+    exclude_id: &str,
+) -> rusqlite::Result<Vec<MistakeEntry>> {
+    let (where_sql, values) = mistake_filter(conn, subject)?;
+    let sql = format!(
+        "SELECT {} FROM mistakes {} ORDER BY created_at DESC LIMIT {}",
+        ENTRY_COLUMNS,
+        where_sql,
+        MAX_CANDIDATES + 1
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(values), row_to_entry)?;
+    Ok(rows
+        .filter_map(|r| r.ok())
+        .filter(|entry| entry.id != exclude_id)
+        .take(MAX_CANDIDATES)
+        .collect())
+}
+
+fn jaccard(a: &[String], b: &[String]) -> f32 {
+    let a: HashSet<&String> = a.iter().collect();
+    let b: HashSet<&String> = b.iter().collect();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f32 / union as f32
+}
+
+fn recency_score(created_at: &str, now: chrono::DateTime<chrono::Utc>) -> f32 {
+    let Ok(created) = chrono::DateTime::parse_from_rfc3339(created_at) else {
+        return 0.0;
+    };
+    let age_days = (now - created.with_timezone(&chrono::Utc))
+        .num_seconds()
+        .max(0) as f64
+        / 86400.0;
+    0.5f64.powf(age_days / RECENCY_HALF_LIFE_DAYS) as f32
+}
+
+/// 候选所属标签中出错最多的那个标签的次数 / 科目内最高标签次数
+fn frequency_score(tags: &[String], frequencies: &HashMap<String, i64>, max_freq: i64) -> f32 {
+    if max_freq <= 0 {
+        return 0.0;
+    }
+    tags.iter()
+        .filter_map(|t| frequencies.get(t))
+        .max()
+        .map(|count| *count as f32 / max_freq as f32)
+        .unwrap_or(0.0)
+}
+
+fn score(values: FactorValues, weights: &RecommendationWeights) -> (f32, FactorContributions) {
+    let contributions = FactorContributions {
+        tag_overlap: weights.tag_overlap * values.tag_overlap,
+        embedding_similarity: weights.embedding_similarity * values.embedding_similarity,
+        recency: weights.recency * values.recency,
+        mistake_frequency: weights.mistake_frequency * values.mistake_frequency,
+    };
+    let total = contributions.tag_overlap
+        + contributions.embedding_similarity
+        + contributions.recency
+        + contributions.mistake_frequency;
+    (total.clamp(0.0, 1.0), contributions)
+}
+
+/// 计算起点与各候选的向量相似度；未配置嵌入模型或调用失败时返回 None
+async fn embedding_similarities(
+    state: &State<'_, AppState>,
+    target: &MistakeEntry,
+    candidates: &[MistakeEntry],
+) -> Option<Vec<f32>> {
+    if target.text.is_empty() || !state.llm_manager.has_embedding_model().await {
+        return None;
+    }
+    let config = state.llm_manager.get_embedding_model_config().await.ok()?;
+    let mut texts = Vec::with_capacity(candidates.len() + 1);
+    texts.push(target.text.clone());
+    texts.extend(candidates.iter().map(|c| c.text.clone()));
+
+    match state
+        .llm_manager
+        .call_embedding_api(texts, &config.id)
+        .await
+    {
+        Ok(vectors) if vectors.len() == candidates.len() + 1 => {
+            let query = &vectors[0];
+            Some(
+                vectors[1..]
+                    .iter()
+                    .map(|v| cosine_similarity(query, v).max(0.0))
+                    .collect(),
+            )
+        }
+        Ok(_) => {
+            warn!("[Recommend] 嵌入返回数量不匹配，跳过向量相似度");
+            None
+        }
+        Err(e) => {
+            warn!("[Recommend] 生成嵌入失败，跳过向量相似度: {}", e);
+            None
+        }
+    }
+}
+
+/// 推荐与指定错题相关、值得一起复习的错题
+///
+/// 传入 `weights` 时会校验并保存为新的默认权重；`limit` 默认 10。
+/// 结果会在短时间内缓存，见 [`super::recommendation_cache`]。
+#[tauri::command]
+pub async fn get_ai_recommendations(
+    mistake_id: String,
+    weights: Option<RecommendationWeights>,
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<RecommendationResult> {
+    let _timer = crate::command_metrics::start("get_ai_recommendations");
+    let weights = match weights {
+        Some(weights) => {
+            save_recommendation_weights(&state.database, &weights)?;
+            weights
+        }
+        None => load_recommendation_weights(&state.database),
+    };
+    let limit = limit.unwrap_or(10).clamp(1, 100);
+    let cache_ttl = load_cache_ttl(&state.database);
+    let cache_key = format!(
+        "{}|{}|{}",
+        mistake_id,
+        limit,
+        serde_json::to_string(&weights).unwrap_or_default()
+    );
+
+    let (target, candidates, frequencies, fingerprint) = {
+        let conn = state
+            .database
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        let fingerprint = data_fingerprint(&conn).map_err(db_err)?;
+        if !cache_ttl.is_zero() {
+            if let Some(cached) =
+                RECOMMENDATION_CACHE.get(&cache_key, &fingerprint, cache_ttl, Instant::now())
+            {
+                return Ok(cached);
+            }
+        }
+        let (target, subject) = load_target(&conn, &mistake_id)
+            .map_err(db_err)?
+            .ok_or_else(|| AppError::not_found(format!("错题不存在: {}", mistake_id)))?;
+        let candidates = load_candidates(&conn, subject.as_deref(), &mistake_id).map_err(db_err)?;
+        let frequencies: HashMap<String, i64> = tag_frequencies(&conn, subject.as_deref())
+            .map_err(db_err)?
+            .into_iter()
+            .collect();
+        (target, candidates, frequencies, fingerprint)
+    };
+
+    let similarities = embedding_similarities(&state, &target, &candidates).await;
+    let max_freq = frequencies.values().copied().max().unwrap_or(0);
+    let normalized = weights.normalized();
+    let now = chrono::Utc::now();
+
+    let mut recommendations: Vec<MistakeRecommendation> = candidates
+        .into_iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let values = FactorValues {
+                tag_overlap: jaccard(&target.tags, &candidate.tags),
+                embedding_similarity: similarities.as_ref().map_or(0.0, |s| s[i]),
+                recency: recency_score(&candidate.created_at, now),
+                mistake_frequency: frequency_score(&candidate.tags, &frequencies, max_freq),
+            };
+            let (score, contributions) = score(values, &normalized);
+            MistakeRecommendation {
+                mistake_id: candidate.id,
+                preview: candidate.text.chars().take(PREVIEW_CHARS).collect(),
+                tags: candidate.tags,
+                created_at: candidate.created_at,
+                score,
+                contributions,
+            }
+        })
+        .collect();
+    recommendations.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b.created_at.cmp(&a.created_at))
+    });
+    recommendations.truncate(limit);
+
+    let result = RecommendationResult {
+        weights,
+        embedding_used: similarities.is_some(),
+        recommendations,
+    };
+    if !cache_ttl.is_zero() {
+        RECOMMENDATION_CACHE.insert(cache_key, fingerprint, result.clone(), Instant::now());
+    }
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn get_recommendation_weights(
+    state: State<'_, AppState>,
+) -> Result<RecommendationWeights> {
+    Ok(load_recommendation_weights(&state.database))
+}
+
+#[tauri::command]
+pub async fn save_recommendation_weights_cmd(
+    weights: RecommendationWeights,
+    state: State<'_, AppState>,
+) -> Result<RecommendationWeights> {
+    save_recommendation_weights(&state.database, &weights)?;
+    Ok(weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(values: &[&str]) -> Vec<String> {
+        values.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_weights_validate_and_normalize() {
+        let parsed: RecommendationWeights =
+            serde_json::from_str(r#"{"tagOverlap": 2, "recency": 0}"#).unwrap();
+        assert_eq!(parsed.tag_overlap, 2.0);
+        assert_eq!(parsed.embedding_similarity, 0.35);
+        assert!(parsed.validate().is_ok());
+
+        let normalized = RecommendationWeights {
+            tag_overlap: 1.0,
+            embedding_similarity: 1.0,
+            recency: 0.0,
+            mistake_frequency: 2.0,
+        }
+        .normalized();
+        assert_eq!(normalized.mistake_frequency, 0.5);
+
+        let zero = RecommendationWeights {
+            tag_overlap: 0.0,
+            embedding_similarity: 0.0,
+            recency: 0.0,
+            mistake_frequency: 0.0,
+        };
+        assert!(zero.validate().is_err());
+        let negative = RecommendationWeights {
+            recency: -1.0,
+            ..Default::default()
+        };
+        assert!(negative.validate().is_err());
+    }
+
+    #[test]
+    fn test_factor_values_and_contributions() {
+        assert_eq!(jaccard(&tags(&["a", "b"]), &tags(&["b", "c"])), 1.0 / 3.0);
+        assert_eq!(jaccard(&[], &[]), 0.0);
+
+        let now = chrono::DateTime::parse_from_rfc3339("2026-03-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        assert!((recency_score("2026-03-01T00:00:00Z", now) - 0.5).abs() < 1e-6);
+        assert_eq!(recency_score("not a date", now), 0.0);
+
+        let frequencies: HashMap<String, i64> = [("a".to_string(), 4), ("b".to_string(), 2)]
+            .into_iter()
+            .collect();
+        assert_eq!(frequency_score(&tags(&["b"]), &frequencies, 4), 0.5);
+        assert_eq!(frequency_score(&tags(&["a", "b"]), &frequencies, 4), 1.0);
+
+        let weights = RecommendationWeights {
+            tag_overlap: 1.0,
+            embedding_similarity: 0.0,
+            recency: 0.0,
+            mistake_frequency: 1.0,
+        }
+        .normalized();
+        let values = FactorValues {
+            tag_overlap: 0.5,
+            embedding_similarity: 0.9,
+            recency: 1.0,
+            mistake_frequency: 1.0,
+        };
+        let (total, contributions) = score(values, &weights);
+        assert_eq!(contributions.tag_overlap, 0.25);
+        assert_eq!(contributions.embedding_similarity, 0.0);
+        assert_eq!(contributions.mistake_frequency, 0.5);
+        assert_eq!(total, 0.75);
+    }
+}
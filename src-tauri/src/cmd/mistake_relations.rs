@@ -0,0 +1,376 @@
+//! 错题之间的手动关联
+//!
+//! 用户可把错题手动关联为 `prerequisite`（from 是 to 的前置知识）、`related` 或 `duplicate`，
+//! 与自动生成的关系无关。`related` / `duplicate` 不区分方向：A→B 已存在时再建 B→A 视为重复。
+//! 不允许自关联。
+//!
+//! 软删除的错题保留关联（恢复后仍在），查询时不返回；错题被彻底删除（如合并重复错题）时
+//! 关联随之删除。
+
+use crate::commands::AppState;
+use crate::database::{has_column, table_exists};
+use crate::models::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MistakeRelationType {
+    Prerequisite,
+    Related,
+    Duplicate,
+}
+
+impl MistakeRelationType {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim() {
+            "prerequisite" => Ok(Self::Prerequisite),
+            "related" => Ok(Self::Related),
+            "duplicate" => Ok(Self::Duplicate),
+            other => Err(AppError::validation(format!(
+                "未知的关联类型: {}（支持 prerequisite / related / duplicate）",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Prerequisite => "prerequisite",
+            Self::Related => "related",
+            Self::Duplicate => "duplicate",
+        }
+    }
+
+    /// 是否不区分方向
+    pub fn is_symmetric(self) -> bool {
+        !matches!(self, Self::Prerequisite)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeRelation {
+    pub from_mistake_id: String,
+    pub to_mistake_id: String,
+    pub relation_type: MistakeRelationType,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelatedMistake {
+    /// 关联另一端的错题
+    pub mistake_id: String,
+    pub user_question: String,
+    pub relation_type: MistakeRelationType,
+    /// 关联由查询的错题发出（`outgoing`）还是指向它（`incoming`）
+    pub direction: &'static str,
+    pub created_at: String,
+}
+
+fn db_err(e: rusqlite::Error) -> AppError {
+    AppError::database(format!("读写错题关联失败: {}", e))
+}
+
+fn ensure_relations_table(conn: &Connection) -> Result<()> {
+    if table_exists(conn, "mistake_relations").map_err(db_err)? {
+        Ok(())
+    } else {
+        Err(AppError::configuration(
+            "错题库尚未升级到支持错题关联的版本，请重启应用完成数据库迁移",
+        ))
+    }
+}
+
+/// 错题存在且未被软删除
+fn ensure_active_mistake(conn: &Connection, mistake_id: &str) -> Result<()> {
+    let deleted_filter = if has_column(conn, "mistakes", "deleted_at").map_err(db_err)? {
+        " AND deleted_at IS NULL"
+    } else {
+        ""
+    };
+    let exists = conn
+        .query_row(
+            &format!("SELECT 1 FROM mistakes WHERE id = ?1{}", deleted_filter),
+            params![mistake_id],
+            |_| Ok(()),
+        )
+        .optional()
+        .map_err(db_err)?
+        .is_some();
+    if exists {
+        Ok(())
+    } else {
+        Err(AppError::not_found(format!("错题不存在: {}", mistake_id)))
+    }
+}
+
+/// 建立关联；自关联、重复关联（含无方向类型的反向关联）直接报错
+pub(crate) fn insert_relation(
+    conn: &Connection,
+    from_id: &str,
+    to_id: &str,
+    relation_type: MistakeRelationType,
+) -> Result<MistakeRelation> {
+    ensure_relations_table(conn)?;
+    if from_id == to_id {
+        return Err(AppError::validation("不能将错题与自身关联"));
+    }
+    ensure_active_mistake(conn, from_id)?;
+    ensure_active_mistake(conn, to_id)?;
+
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM mistake_relations WHERE relation_type = ?3 AND \
+                ((from_mistake_id = ?1 AND to_mistake_id = ?2) \
+                 OR (?4 AND from_mistake_id = ?2 AND to_mistake_id = ?1)))",
+            params![
+                from_id,
+                to_id,
+                relation_type.as_str(),
+                relation_type.is_symmetric()
+            ],
+            |row| row.get(0),
+        )
+        .map_err(db_err)?;
+    if exists {
+        return Err(AppError::validation(format!(
+            "错题 {} 与 {} 之间已存在 {} 关联",
+            from_id,
+            to_id,
+            relation_type.as_str()
+        )));
+    }
+
+    let relation = MistakeRelation {
+        from_mistake_id: from_id.to_string(),
+        to_mistake_id: to_id.to_string(),
+        relation_type,
+        created_at: Utc::now().to_rfc3339(),
+    };
+    conn.execute(
+        "INSERT INTO mistake_relations (from_mistake_id, to_mistake_id, relation_type, created_at) \
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            relation.from_mistake_id,
+            relation.to_mistake_id,
+            relation.relation_type.as_str(),
+            relation.created_at
+        ],
+    )
+    .map_err(db_err)?;
+    Ok(relation)
+}
+
+/// 删除关联，返回删除条数；`relation_type` 为空时删除两题之间 from→to 的全部关联
+/// （无方向类型同时匹配反向）
+pub(crate) fn delete_relation(
+    conn: &Connection,
+    from_id: &str,
+    to_id: &str,
+    relation_type: Option<MistakeRelationType>,
+) -> Result<usize> {
+    ensure_relations_table(conn)?;
+    conn.execute(
+        "DELETE FROM mistake_relations WHERE (?3 IS NULL OR relation_type = ?3) AND \
+            ((from_mistake_id = ?1 AND to_mistake_id = ?2) \
+             OR (relation_type <> 'prerequisite' AND from_mistake_id = ?2 AND to_mistake_id = ?1))",
+        params![
+            from_id,
+            to_id,
+            relation_type.map(MistakeRelationType::as_str)
+        ],
+    )
+    .map_err(db_err)
+}
+
+/// 查询错题的关联（双向），不返回已软删除的错题
+pub(crate) fn list_related(conn: &Connection, mistake_id: &str) -> Result<Vec<RelatedMistake>> {
+    ensure_relations_table(conn)?;
+    let deleted_filter = if has_column(conn, "mistakes", "deleted_at").map_err(db_err)? {
+        " AND m.deleted_at IS NULL"
+    } else {
+        ""
+    };
+    let sql = format!(
+        "SELECT r.other_id, COALESCE(m.user_question, ''), r.relation_type, r.outgoing, r.created_at \
+         FROM ( \
+             SELECT to_mistake_id AS other_id, relation_type, 1 AS outgoing, created_at \
+             FROM mistake_relations WHERE from_mistake_id = ?1 \
+             UNION ALL \
+             SELECT from_mistake_id, relation_type, 0, created_at \
+             FROM mistake_relations WHERE to_mistake_id = ?1 \
+         ) r JOIN mistakes m ON m.id = r.other_id{} \
+         ORDER BY r.created_at, r.other_id",
+        deleted_filter
+    );
+    let mut stmt = conn.prepare(&sql).map_err(db_err)?;
+    let rows = stmt
+        .query_map(params![mistake_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, bool>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })
+        .map_err(db_err)?;
+    let mut related = Vec::new();
+    for row in rows {
+        let (mistake_id, user_question, relation_type, outgoing, created_at) =
+            row.map_err(db_err)?;
+        related.push(RelatedMistake {
+            mistake_id,
+            user_question,
+            relation_type: MistakeRelationType::parse(&relation_type)?,
+            direction: if outgoing { "outgoing" } else { "incoming" },
+            created_at,
+        });
+    }
+    Ok(related)
+}
+
+/// 彻底删除错题前清理其关联；外键级联只在开启 `foreign_keys` 的连接上生效，这里显式删除
+pub(crate) fn purge_mistake_relations(
+    conn: &Connection,
+    mistake_id: &str,
+) -> rusqlite::Result<usize> {
+    if !table_exists(conn, "mistake_relations")? {
+        return Ok(0);
+    }
+    conn.execute(
+        "DELETE FROM mistake_relations WHERE from_mistake_id = ?1 OR to_mistake_id = ?1",
+        params![mistake_id],
+    )
+}
+
+/// 关联两道错题；`relation_type` 为 prerequisite / related / duplicate
+#[tauri::command]
+pub async fn link_mistakes(
+    from: String,
+    to: String,
+    relation_type: String,
+    state: State<'_, AppState>,
+) -> Result<MistakeRelation> {
+    let relation_type = MistakeRelationType::parse(&relation_type)?;
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    insert_relation(&conn, from.trim(), to.trim(), relation_type)
+}
+
+/// 取消关联，返回删除的关联条数；未指定 `relation_type` 时删除两题之间的全部关联
+#[tauri::command]
+pub async fn unlink_mistakes(
+    from: String,
+    to: String,
+    relation_type: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<usize> {
+    let relation_type = relation_type
+        .as_deref()
+        .filter(|t| !t.trim().is_empty())
+        .map(MistakeRelationType::parse)
+        .transpose()?;
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    delete_relation(&conn, from.trim(), to.trim(), relation_type)
+}
+
+/// 获取错题的全部关联（含指向它的关联）
+#[tauri::command]
+pub async fn get_related_mistakes(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<RelatedMistake>> {
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    list_related(&conn, id.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn setup_conn() -> Connection {
+        let conn = migrated_conn().unwrap();
+        for (id, question) in [("a", "导数定义"), ("b", "求导法则"), ("c", "链式法则")]
+        {
+            insert_mistake(&conn, id, &[("user_question", &question)]).unwrap();
+        }
+        insert_mistake(
+            &conn,
+            "gone",
+            &[("user_question", &"已删除"), ("deleted_at", &"2026-01-01")],
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_link_rejects_self_duplicate_and_reverse_symmetric_links() {
+        let conn = setup_conn();
+        insert_relation(&conn, "a", "b", MistakeRelationType::Prerequisite).unwrap();
+        // 前置关系有方向，反向是另一条关联
+        insert_relation(&conn, "b", "a", MistakeRelationType::Prerequisite).unwrap();
+        assert!(insert_relation(&conn, "a", "b", MistakeRelationType::Prerequisite).is_err());
+        insert_relation(&conn, "a", "c", MistakeRelationType::Related).unwrap();
+        assert!(insert_relation(&conn, "c", "a", MistakeRelationType::Related).is_err());
+        assert!(insert_relation(&conn, "a", "a", MistakeRelationType::Duplicate).is_err());
+        assert!(insert_relation(&conn, "a", "gone", MistakeRelationType::Related).is_err());
+        assert!(insert_relation(&conn, "a", "missing", MistakeRelationType::Related).is_err());
+        assert!(MistakeRelationType::parse("sibling").is_err());
+
+        assert_eq!(
+            delete_relation(&conn, "c", "a", Some(MistakeRelationType::Related)).unwrap(),
+            1
+        );
+        assert_eq!(delete_relation(&conn, "a", "b", None).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_related_lists_both_directions_and_purge_cleans_up() {
+        let conn = setup_conn();
+        insert_relation(&conn, "a", "b", MistakeRelationType::Prerequisite).unwrap();
+        insert_relation(&conn, "c", "a", MistakeRelationType::Duplicate).unwrap();
+
+        let related = list_related(&conn, "a").unwrap();
+        let summary: Vec<_> = related
+            .iter()
+            .map(|r| (r.mistake_id.as_str(), r.relation_type, r.direction))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("b", MistakeRelationType::Prerequisite, "outgoing"),
+                ("c", MistakeRelationType::Duplicate, "incoming"),
+            ]
+        );
+
+        // 软删除只隐藏，不删除关联
+        conn.execute(
+            "UPDATE mistakes SET deleted_at = '2026-02-01' WHERE id = 'b'",
+            [],
+        )
+        .unwrap();
+        assert_eq!(list_related(&conn, "a").unwrap().len(), 1);
+
+        assert_eq!(purge_mistake_relations(&conn, "a").unwrap(), 2);
+        let remaining: i64 = conn
+            .query_row("SELECT COUNT(*) FROM mistake_relations", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+}
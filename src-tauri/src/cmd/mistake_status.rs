@@ -0,0 +1,36 @@
+//! 错题状态批量更新命令
+//!
+//! 按筛选条件（科目、标签、创建时间范围、当前状态）一次性更新状态，
+//! 无需前端先分页拉取全部 ID。空条件会命中全部错题，必须显式传入 `force`。
+//! 已删除的错题不会被更新；已归档的错题需在筛选中设置 `includeArchived`。
+
+use crate::batch_operations::{BatchOperationExt, MistakeStatusFilter};
+use crate::commands::AppState;
+use crate::models::AppError;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 按条件批量更新错题状态，返回受影响的数量
+#[tauri::command]
+pub async fn update_mistake_statuses_by_filter(
+    filter: MistakeStatusFilter,
+    new_status: String,
+    force: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<usize> {
+    if new_status.trim().is_empty() {
+        return Err(AppError::validation("新状态不能为空"));
+    }
+    if filter.is_empty() && !force.unwrap_or(false) {
+        return Err(AppError::validation(
+            "筛选条件为空会更新全部错题，如确需执行请设置 force",
+        ));
+    }
+    state
+        .database
+        .with_batch_operations(|ops| {
+            ops.update_mistake_statuses_by_filter(&filter, &new_status, force.unwrap_or(false))
+        })
+        .map_err(|e| AppError::database(format!("按条件更新错题状态失败: {}", e)))
+}
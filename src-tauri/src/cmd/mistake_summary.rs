@@ -0,0 +1,370 @@
+//! 错题总结生成命令
+//!
+//! 手动生成与自动生成共用 [`crate::mistake_auto_summary`] 的流程；
+//! 自动生成的触发点见 [`crate::cmd::mistake_chat`] 与 [`close_mistake_chat_session`]。
+//!
+//! `batch_generate_summaries` 为整个科目补齐缺失或过时的总结：按每分钟请求数限速逐题
+//! 调用总结模型，进度通过 `mistake-summary-batch-progress` 事件推送，可随时通过
+//! `cancel_batch_generate_summaries` 取消（当前这道题完成后停止）。
+
+use crate::commands::AppState;
+use crate::mistake_auto_summary::{
+    self, AutoSummarySettings, MistakeSummaryStatus, SummaryOutcome, AUTO_SUMMARY,
+};
+use crate::models::{
+    AppError, AppErrorType, GenerateMistakeSummaryRequest, GenerateMistakeSummaryResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+const BATCH_SUMMARY_PROGRESS_EVENT: &str = "mistake-summary-batch-progress";
+const DEFAULT_REQUESTS_PER_MINUTE: u32 = 10;
+const MAX_REQUESTS_PER_MINUTE: u32 = 60;
+
+/// 同一时间只允许一个批量总结任务
+static BATCH_SUMMARY_RUNNING: AtomicBool = AtomicBool::new(false);
+static BATCH_SUMMARY_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+struct BatchSummaryGuard;
+
+impl Drop for BatchSummaryGuard {
+    fn drop(&mut self) {
+        BATCH_SUMMARY_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// 手动生成错题总结
+///
+/// 内容自上次总结以来未变化时直接返回已有总结；`force_regenerate` 为 true 时总是重新生成。
+#[tauri::command]
+pub async fn generate_mistake_summary(
+    request: GenerateMistakeSummaryRequest,
+    state: State<'_, AppState>,
+) -> Result<GenerateMistakeSummaryResponse> {
+    let settings = mistake_auto_summary::load_auto_summary_settings(&state.database);
+    let outcome = mistake_auto_summary::summarize_mistake(
+        &state.database,
+        &state.llm_manager,
+        settings.summary_model_id.as_deref(),
+        &request.mistake_id,
+        Duration::ZERO,
+        request.force_regenerate.unwrap_or(false),
+    )
+    .await;
+    if matches!(outcome, Ok(SummaryOutcome::Generated { .. })) {
+        super::mistake_kb_sync::spawn_sync_mistakes(
+            state.database.clone(),
+            state.vfs_db.clone(),
+            state.llm_manager.clone(),
+            vec![request.mistake_id.clone()],
+        );
+    }
+    Ok(match outcome {
+        Ok(SummaryOutcome::Generated {
+            mistake_summary,
+            user_error_analysis,
+            model_config_id,
+            model_name,
+        }) => GenerateMistakeSummaryResponse {
+            success: true,
+            mistake_summary: Some(mistake_summary),
+            user_error_analysis,
+            error_message: None,
+            model_config_id: Some(model_config_id),
+            model_name: Some(model_name),
+        },
+        Ok(SummaryOutcome::Skipped {
+            reason,
+            mistake_summary,
+            user_error_analysis,
+        }) => GenerateMistakeSummaryResponse {
+            success: mistake_summary.is_some(),
+            error_message: mistake_summary.is_none().then(|| reason.to_string()),
+            mistake_summary,
+            user_error_analysis,
+            model_config_id: None,
+            model_name: None,
+        },
+        // 最小间隔为 0，手动生成不会被推迟
+        Ok(SummaryOutcome::Deferred(_)) => GenerateMistakeSummaryResponse {
+            success: false,
+            mistake_summary: None,
+            user_error_analysis: None,
+            error_message: Some("总结生成已推迟，请稍后重试".to_string()),
+            model_config_id: None,
+            model_name: None,
+        },
+        Err(e) if matches!(e.error_type, AppErrorType::NotFound) => return Err(e),
+        Err(e) => GenerateMistakeSummaryResponse {
+            success: false,
+            mistake_summary: None,
+            user_error_analysis: None,
+            error_message: Some(e.message),
+            model_config_id: None,
+            model_name: None,
+        },
+    })
+}
+
+/// 错题总结状态（最近总结时间、是否过时）
+#[tauri::command]
+pub async fn get_mistake_summary_status(
+    mistake_id: String,
+    state: State<'_, AppState>,
+) -> Result<MistakeSummaryStatus> {
+    mistake_auto_summary::get_summary_status(&state.database, &mistake_id)
+}
+
+/// 用户关闭错题对话时调用；启用自动总结时立即尝试生成
+#[tauri::command]
+pub async fn close_mistake_chat_session(
+    mistake_id: String,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    AUTO_SUMMARY.on_session_closed(
+        state.database.clone(),
+        state.vfs_db.clone(),
+        state.llm_manager.clone(),
+        &mistake_id,
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_auto_summary_settings(state: State<'_, AppState>) -> Result<AutoSummarySettings> {
+    Ok(mistake_auto_summary::load_auto_summary_settings(
+        &state.database,
+    ))
+}
+
+/// 保存自动总结配置（对之后的触发生效）
+#[tauri::command]
+pub async fn save_auto_summary_settings(
+    settings: AutoSummarySettings,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    mistake_auto_summary::save_auto_summary_settings(&state.database, &settings)
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BatchSummaryFilter {
+    /// 为空时处理全部科目
+    pub subject: Option<String>,
+    /// 每分钟最多调用总结模型的次数，默认 10，最多 60
+    pub requests_per_minute: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchSummaryItemStatus {
+    Generated,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchSummaryItem {
+    pub mistake_id: String,
+    pub status: BatchSummaryItemStatus,
+    /// 跳过原因或失败信息
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchSummaryReport {
+    /// 符合筛选条件的错题数
+    pub total: usize,
+    /// 总结已是最新、无需处理的错题数
+    pub up_to_date: usize,
+    pub generated: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub cancelled: bool,
+    /// 逐题结果（不含已是最新的错题）
+    pub items: Vec<BatchSummaryItem>,
+}
+
+impl BatchSummaryReport {
+    fn record(&mut self, item: BatchSummaryItem) {
+        match item.status {
+            BatchSummaryItemStatus::Generated => self.generated += 1,
+            BatchSummaryItemStatus::Skipped => self.skipped += 1,
+            BatchSummaryItemStatus::Failed => self.failed += 1,
+        }
+        self.items.push(item);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchSummaryProgress {
+    /// 需要调用模型的错题数
+    pending: usize,
+    processed: usize,
+    generated: usize,
+    skipped: usize,
+    failed: usize,
+    /// 刚处理完的错题
+    item: Option<BatchSummaryItem>,
+    finished: bool,
+}
+
+/// 批量生成错题总结
+///
+/// 处理符合筛选条件、缺少总结或总结已过时（内容哈希变化）的错题；对话中还没有模型回复的
+/// 错题记为跳过。使用与自动总结相同的模型选择（科目覆盖 → 总结模型 → 对话模型）。
+/// 同步执行并返回逐题结果；进度同时通过 `mistake-summary-batch-progress` 事件推送。
+#[tauri::command]
+pub async fn batch_generate_summaries(
+    filter: BatchSummaryFilter,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<BatchSummaryReport> {
+    let requests_per_minute = filter
+        .requests_per_minute
+        .unwrap_or(DEFAULT_REQUESTS_PER_MINUTE)
+        .clamp(1, MAX_REQUESTS_PER_MINUTE);
+    let min_request_interval = Duration::from_secs(60) / requests_per_minute;
+    let subject = filter
+        .subject
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+
+    if BATCH_SUMMARY_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(AppError::validation("已有批量总结任务在进行中"));
+    }
+    let _guard = BatchSummaryGuard;
+    BATCH_SUMMARY_CANCELLED.store(false, Ordering::SeqCst);
+
+    let db = state.database.clone();
+    let settings = mistake_auto_summary::load_auto_summary_settings(&db);
+    let candidates = mistake_auto_summary::batch_summary_candidates(&db, subject)?;
+    let mut report = BatchSummaryReport {
+        total: candidates.pending.len() + candidates.without_chat.len() + candidates.up_to_date,
+        up_to_date: candidates.up_to_date,
+        ..Default::default()
+    };
+    for mistake_id in candidates.without_chat {
+        report.record(BatchSummaryItem {
+            mistake_id,
+            status: BatchSummaryItemStatus::Skipped,
+            message: Some("对话中还没有模型回复".to_string()),
+        });
+    }
+
+    let pending = candidates.pending.len();
+    let emit =
+        |report: &BatchSummaryReport, processed, item: Option<BatchSummaryItem>, finished| {
+            let _ = app_handle.emit(
+                BATCH_SUMMARY_PROGRESS_EVENT,
+                BatchSummaryProgress {
+                    pending,
+                    processed,
+                    generated: report.generated,
+                    skipped: report.skipped,
+                    failed: report.failed,
+                    item,
+                    finished,
+                },
+            );
+        };
+    emit(&report, 0, None, false);
+    log::info!(
+        "[MistakeSummaryBatch] 开始批量总结: subject={:?}, 待生成 {}，无对话 {}，已是最新 {}",
+        subject,
+        pending,
+        report.skipped,
+        report.up_to_date
+    );
+
+    let mut last_request: Option<Instant> = None;
+    let mut processed = 0;
+    let mut generated_ids = Vec::new();
+    for mistake_id in candidates.pending {
+        if let Some(wait) =
+            last_request.and_then(|last| min_request_interval.checked_sub(last.elapsed()))
+        {
+            tokio::time::sleep(wait).await;
+        }
+        if BATCH_SUMMARY_CANCELLED.load(Ordering::SeqCst) {
+            report.cancelled = true;
+            break;
+        }
+        last_request = Some(Instant::now());
+        // 候选筛选时已比对过内容哈希，这里强制生成
+        let outcome = mistake_auto_summary::summarize_mistake(
+            &db,
+            &state.llm_manager,
+            settings.summary_model_id.as_deref(),
+            &mistake_id,
+            Duration::ZERO,
+            true,
+        )
+        .await;
+        let (status, message) = match outcome {
+            Ok(SummaryOutcome::Generated { .. }) => {
+                generated_ids.push(mistake_id.clone());
+                (BatchSummaryItemStatus::Generated, None)
+            }
+            Ok(SummaryOutcome::Skipped { reason, .. }) => {
+                (BatchSummaryItemStatus::Skipped, Some(reason.to_string()))
+            }
+            Ok(SummaryOutcome::Deferred(_)) => (
+                BatchSummaryItemStatus::Skipped,
+                Some("总结生成已推迟".to_string()),
+            ),
+            Err(e) => {
+                log::warn!("[MistakeSummaryBatch] 错题 {} 总结失败: {}", mistake_id, e);
+                (BatchSummaryItemStatus::Failed, Some(e.message))
+            }
+        };
+        let item = BatchSummaryItem {
+            mistake_id,
+            status,
+            message,
+        };
+        report.record(item.clone());
+        processed += 1;
+        emit(&report, processed, Some(item), false);
+    }
+
+    log::info!(
+        "[MistakeSummaryBatch] 批量总结结束: 共 {}，生成 {}，跳过 {}，失败 {}，已是最新 {}{}",
+        report.total,
+        report.generated,
+        report.skipped,
+        report.failed,
+        report.up_to_date,
+        if report.cancelled {
+            "（已取消）"
+        } else {
+            ""
+        }
+    );
+    emit(&report, processed, None, true);
+    super::mistake_kb_sync::spawn_sync_mistakes(
+        db,
+        state.vfs_db.clone(),
+        state.llm_manager.clone(),
+        generated_ids,
+    );
+    Ok(report)
+}
+
+/// 取消正在进行的批量总结；返回是否有任务在进行
+#[tauri::command]
+pub async fn cancel_batch_generate_summaries() -> Result<bool> {
+    let running = BATCH_SUMMARY_RUNNING.load(Ordering::SeqCst);
+    if running {
+        BATCH_SUMMARY_CANCELLED.store(true, Ordering::SeqCst);
+    }
+    Ok(running)
+}
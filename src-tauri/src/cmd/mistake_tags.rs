@@ -0,0 +1,493 @@
+//! 错题标签建议命令
+//!
+//! 保存错题时根据两类信号推荐已有标签：
+//! - 该科目下各标签的使用频率；
+//! - 当前题目与近期已打标签错题的向量相似度（k 近邻加权投票，需配置嵌入模型）。
+//!
+//! 默认只返回题库中已经存在的标签；`allow_new` 为 true 时才会请模型补充新标签，
+//! 新标签以 `isNew` 标记且置信度固定偏低，需用户确认。
+//!
+//! 保存后由模型直接分配标签见 [`crate::mistake_auto_tagging`]。
+
+use crate::commands::AppState;
+use crate::database::has_column;
+use crate::mistake_auto_tagging::{self, AutoTagOutcome, AutoTaggingSettings};
+use crate::models::AppError;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tauri::State;
+use tracing::warn;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 参与相似度计算的近期已打标签错题数上限
+const MAX_NEIGHBOR_CANDIDATES: usize = 100;
+/// k 近邻投票的邻居数
+const KNN_NEIGHBORS: usize = 10;
+/// 单条错题送入嵌入模型的最大字符数
+const MAX_EMBED_CHARS: usize = 500;
+/// 有相似度信号时的权重（其余为频率权重）
+const SIMILARITY_WEIGHT: f32 = 0.65;
+/// 题目文本直接包含标签名时的加分
+const LITERAL_MATCH_BONUS: f32 = 0.2;
+/// 模型补充的新标签的置信度
+const NEW_TAG_CONFIDENCE: f32 = 0.3;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagSuggestion {
+    pub tag: String,
+    /// 置信度（0~1）
+    pub confidence: f32,
+    /// 该标签在科目内的使用次数；新标签为 0
+    pub frequency: i64,
+    /// 是否为题库中尚不存在的新标签（仅 allow_new 时出现）
+    pub is_new: bool,
+}
+
+fn db_err(e: rusqlite::Error) -> AppError {
+    AppError::database(format!("读取错题标签失败: {}", e))
+}
+
+/// 构造科目与软删除过滤条件
+pub(crate) fn mistake_filter(
+    conn: &Connection,
+    subject: Option<&str>,
+) -> rusqlite::Result<(String, Vec<Value>)> {
+    let mut clauses = Vec::new();
+    let mut values = Vec::new();
+    if has_column(conn, "mistakes", "deleted_at")? {
+        clauses.push("mistakes.deleted_at IS NULL".to_string());
+    }
+    if let Some(subject) = subject {
+        values.push(Value::from(subject.to_string()));
+        clauses.push(format!("mistakes.subject = ?{}", values.len()));
+    }
+    let sql = if clauses.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", clauses.join(" AND "))
+    };
+    Ok((sql, values))
+}
+
+pub(crate) fn tag_frequencies(
+    conn: &Connection,
+    subject: Option<&str>,
+) -> rusqlite::Result<Vec<(String, i64)>> {
+    let (where_sql, values) = mistake_filter(conn, subject)?;
+    let sql = format!(
+        "SELECT TRIM(je.value) AS k, COUNT(*) FROM mistakes, \
+         json_each(CASE WHEN json_valid(mistakes.tags) THEN mistakes.tags ELSE '[]' END) je \
+         {} GROUP BY k HAVING k <> '' ORDER BY 2 DESC, k",
+        where_sql
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+    })?;
+    rows.collect()
+}
+
+/// 近期已打标签的错题（题干 + OCR 文本，标签列表）
+fn recent_tagged_mistakes(
+    conn: &Connection,
+    subject: Option<&str>,
+    limit: usize,
+) -> rusqlite::Result<Vec<(String, Vec<String>)>> {
+    let (where_sql, values) = mistake_filter(conn, subject)?;
+    let tagged = "json_valid(mistakes.tags) AND json_array_length(mistakes.tags) > 0";
+    let where_sql = if where_sql.is_empty() {
+        format!("WHERE {}", tagged)
+    } else {
+        format!("{} AND {}", where_sql, tagged)
+    };
+    let sql = format!(
+        "SELECT COALESCE(user_question, ''), COALESCE(ocr_text, ''), tags FROM mistakes \
+         {} ORDER BY created_at DESC LIMIT {}",
+        where_sql, limit
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+        let question: String = row.get(0)?;
+        let ocr_text: String = row.get(1)?;
+        let tags_json: String = row.get(2)?;
+        let text = format!("{}\n{}", question.trim(), ocr_text.trim())
+            .trim()
+            .chars()
+            .take(MAX_EMBED_CHARS)
+            .collect::<String>();
+        let tags: Vec<String> = serde_json::from_str::<Vec<String>>(&tags_json)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+        Ok((text, tags))
+    })?;
+    Ok(rows
+        .filter_map(|r| r.ok())
+        .filter(|(text, tags)| !text.is_empty() && !tags.is_empty())
+        .collect())
+}
+
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// k 近邻加权投票：标签得分 = 带该标签邻居的相似度之和 / 全部邻居相似度之和
+fn knn_tag_scores(
+    query: &[f32],
+    neighbors: &[(Vec<f32>, Vec<String>)],
+    k: usize,
+) -> HashMap<String, f32> {
+    let mut sims: Vec<(f32, &Vec<String>)> = neighbors
+        .iter()
+        .map(|(vector, tags)| (cosine_similarity(query, vector).max(0.0), tags))
+        .collect();
+    sims.sort_by(|a, b| b.0.total_cmp(&a.0));
+    sims.truncate(k);
+
+    let total: f32 = sims.iter().map(|(s, _)| s).sum();
+    let mut scores = HashMap::new();
+    if total <= 0.0 {
+        return scores;
+    }
+    for (sim, tags) in sims {
+        let unique: HashSet<&String> = tags.iter().collect();
+        for tag in unique {
+            *scores.entry(tag.clone()).or_insert(0.0) += sim / total;
+        }
+    }
+    scores
+}
+
+/// 综合频率与相似度信号，返回按置信度降序的前 `top_k` 个已有标签
+fn rank_suggestions(
+    text: &str,
+    frequencies: &[(String, i64)],
+    similarity: Option<&HashMap<String, f32>>,
+    top_k: usize,
+) -> Vec<TagSuggestion> {
+    let max_freq = frequencies
+        .iter()
+        .map(|(_, c)| *c)
+        .max()
+        .unwrap_or(0)
+        .max(1) as f32;
+    let text_lower = text.to_lowercase();
+    let mut suggestions: Vec<TagSuggestion> = frequencies
+        .iter()
+        .map(|(tag, count)| {
+            let freq_score = *count as f32 / max_freq;
+            let mut confidence = match similarity {
+                Some(scores) => {
+                    let sim = scores.get(tag).copied().unwrap_or(0.0);
+                    SIMILARITY_WEIGHT * sim + (1.0 - SIMILARITY_WEIGHT) * freq_score
+                }
+                None => freq_score,
+            };
+            if !tag.is_empty() && text_lower.contains(&tag.to_lowercase()) {
+                confidence += LITERAL_MATCH_BONUS;
+            }
+            TagSuggestion {
+                tag: tag.clone(),
+                confidence: confidence.clamp(0.0, 1.0),
+                frequency: *count,
+                is_new: false,
+            }
+        })
+        .collect();
+    suggestions.sort_by(|a, b| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then(b.frequency.cmp(&a.frequency))
+            .then(a.tag.cmp(&b.tag))
+    });
+    suggestions.truncate(top_k);
+    suggestions
+}
+
+/// 从模型输出中提取 JSON 字符串数组
+fn parse_new_tags(response: &str) -> Vec<String> {
+    let (Some(start), Some(end)) = (response.find('['), response.rfind(']')) else {
+        return Vec::new();
+    };
+    if end < start {
+        return Vec::new();
+    }
+    serde_json::from_str::<Vec<String>>(&response[start..=end])
+        .unwrap_or_default()
+        .into_iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty() && t.chars().count() <= 20)
+        .collect()
+}
+
+/// 计算相似度信号；未配置嵌入模型或调用失败时返回 None（仅按频率推荐）
+async fn similarity_scores(
+    state: &State<'_, AppState>,
+    text: &str,
+    neighbors: Vec<(String, Vec<String>)>,
+) -> Option<HashMap<String, f32>> {
+    if neighbors.is_empty() || !state.llm_manager.has_embedding_model().await {
+        return None;
+    }
+    let config = state.llm_manager.get_embedding_model_config().await.ok()?;
+    let mut texts = Vec::with_capacity(neighbors.len() + 1);
+    texts.push(text.chars().take(MAX_EMBED_CHARS).collect::<String>());
+    texts.extend(neighbors.iter().map(|(t, _)| t.clone()));
+
+    let mut vectors = match state
+        .llm_manager
+        .call_embedding_api(texts, &config.id)
+        .await
+    {
+        Ok(vectors) if vectors.len() == neighbors.len() + 1 => vectors,
+        Ok(_) => {
+            warn!("[TagSuggest] 嵌入返回数量不匹配，退化为按频率推荐");
+            return None;
+        }
+        Err(e) => {
+            warn!("[TagSuggest] 生成嵌入失败，退化为按频率推荐: {}", e);
+            return None;
+        }
+    };
+    let query = vectors.remove(0);
+    let labeled: Vec<(Vec<f32>, Vec<String>)> = vectors
+        .into_iter()
+        .zip(neighbors.into_iter().map(|(_, tags)| tags))
+        .collect();
+    Some(knn_tag_scores(&query, &labeled, KNN_NEIGHBORS))
+}
+
+/// 请模型补充题库中尚不存在的标签
+async fn propose_new_tags(
+    state: &State<'_, AppState>,
+    text: &str,
+    existing: &[(String, i64)],
+    limit: usize,
+) -> Vec<String> {
+    let known: Vec<&str> = existing.iter().take(50).map(|(t, _)| t.as_str()).collect();
+    let prompt = format!(
+        "请为下面这道错题推荐不超过 {} 个简短的知识点标签（每个不超过 10 个字）。\n\
+         已有标签：{}\n\
+         优先复用已有标签，只有确实不合适时才给出新标签。只输出 JSON 字符串数组，例如 [\"受力分析\"]。\n\n\
+         【题目】\n{}",
+        limit,
+        if known.is_empty() { "无".to_string() } else { known.join("、") },
+        text.chars().take(MAX_EMBED_CHARS * 2).collect::<String>()
+    );
+    match state
+        .llm_manager
+        .call_raw_prompt_with_model(None, &prompt)
+        .await
+    {
+        Ok(output) => parse_new_tags(&output.assistant_message),
+        Err(e) => {
+            warn!("[TagSuggest] 模型补充新标签失败: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// 为待保存的错题推荐标签
+///
+/// `subject` 为空时在全部错题范围内统计；`top_k` 默认 5。
+#[tauri::command]
+pub async fn suggest_tags(
+    text: String,
+    subject: Option<String>,
+    top_k: Option<usize>,
+    allow_new: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<TagSuggestion>> {
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err(AppError::validation("题目内容为空"));
+    }
+    let top_k = top_k.unwrap_or(5).clamp(1, 50);
+    let subject = subject
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+
+    let (frequencies, neighbors) = {
+        let conn = state
+            .database
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        let frequencies = tag_frequencies(&conn, subject.as_deref()).map_err(db_err)?;
+        let neighbors = recent_tagged_mistakes(&conn, subject.as_deref(), MAX_NEIGHBOR_CANDIDATES)
+            .map_err(db_err)?;
+        (frequencies, neighbors)
+    };
+
+    let similarity = similarity_scores(&state, &text, neighbors).await;
+    let mut suggestions = rank_suggestions(&text, &frequencies, similarity.as_ref(), top_k);
+
+    if allow_new.unwrap_or(false) && suggestions.len() < top_k {
+        let known: HashSet<String> = frequencies.iter().map(|(t, _)| t.to_lowercase()).collect();
+        let remaining = top_k - suggestions.len();
+        for tag in propose_new_tags(&state, &text, &frequencies, remaining).await {
+            if suggestions.len() >= top_k {
+                break;
+            }
+            if known.contains(&tag.to_lowercase()) || suggestions.iter().any(|s| s.tag == tag) {
+                continue;
+            }
+            suggestions.push(TagSuggestion {
+                tag,
+                confidence: NEW_TAG_CONFIDENCE,
+                frequency: 0,
+                is_new: true,
+            });
+        }
+    }
+
+    Ok(suggestions)
+}
+
+/// 保存错题后调用；开启自动打标签时请模型从科目标签体系中分配标签
+///
+/// 未开启时不做任何修改，仅在 `skippedReason` 中注明原因。
+#[tauri::command]
+pub async fn auto_tag_mistake(
+    mistake_id: String,
+    state: State<'_, AppState>,
+) -> Result<AutoTagOutcome> {
+    let settings = mistake_auto_tagging::load_auto_tagging_settings(&state.database);
+    if !settings.enabled {
+        return Ok(AutoTagOutcome {
+            tags: Vec::new(),
+            ai_tags: Vec::new(),
+            skipped_reason: Some("未开启自动打标签".to_string()),
+        });
+    }
+    mistake_auto_tagging::auto_tag_mistake(
+        &state.database,
+        &state.llm_manager,
+        &settings,
+        &mistake_id,
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn get_auto_tagging_settings(state: State<'_, AppState>) -> Result<AutoTaggingSettings> {
+    Ok(mistake_auto_tagging::load_auto_tagging_settings(
+        &state.database,
+    ))
+}
+
+#[tauri::command]
+pub async fn save_auto_tagging_settings(
+    settings: AutoTaggingSettings,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    mistake_auto_tagging::save_auto_tagging_settings(&state.database, &settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn setup_conn() -> Connection {
+        let conn = migrated_conn().unwrap();
+        for (id, subject, question, tags) in [
+            (
+                "1",
+                "物理",
+                "小球受力分析",
+                r#"["受力分析","牛顿第二定律"]"#,
+            ),
+            ("2", "物理", "斜面上的物块", r#"["受力分析"]"#),
+            ("3", "物理", "电路串并联", r#"["电路"]"#),
+            ("4", "物理", "已删除", r#"["电路"]"#),
+            ("5", "数学", "二次函数", r#"["函数"]"#),
+            ("6", "物理", "未打标签", "[]"),
+        ] {
+            let created_at = format!("2026-01-0{}", id);
+            insert_mistake(
+                &conn,
+                id,
+                &[
+                    ("subject", &subject),
+                    ("user_question", &question),
+                    ("tags", &tags),
+                    ("created_at", &created_at),
+                ],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "UPDATE mistakes SET deleted_at = '2026-01-05' WHERE id = '4'",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_frequencies_and_neighbors_respect_subject_and_soft_delete() {
+        let conn = setup_conn();
+        let freq = tag_frequencies(&conn, Some("物理")).unwrap();
+        assert_eq!(
+            freq,
+            vec![
+                ("受力分析".to_string(), 2),
+                ("牛顿第二定律".to_string(), 1),
+                ("电路".to_string(), 1),
+            ]
+        );
+
+        let neighbors = recent_tagged_mistakes(&conn, Some("物理"), 10).unwrap();
+        assert_eq!(neighbors.len(), 3);
+        assert_eq!(neighbors[0].0, "电路串并联");
+    }
+
+    #[test]
+    fn test_rank_combines_similarity_and_frequency() {
+        let freq = vec![("受力分析".to_string(), 4), ("电路".to_string(), 1)];
+        let neighbors = vec![
+            (vec![1.0, 0.0], vec!["电路".to_string()]),
+            (vec![0.0, 1.0], vec!["受力分析".to_string()]),
+        ];
+        let sim = knn_tag_scores(&[1.0, 0.1], &neighbors, 10);
+        assert!(sim["电路"] > sim["受力分析"]);
+
+        let ranked = rank_suggestions("题目", &freq, Some(&sim), 5);
+        assert_eq!(ranked[0].tag, "电路");
+        assert!(ranked.iter().all(|s| !s.is_new && s.confidence <= 1.0));
+
+        // 无相似度信号时仅按频率排序，文本命中标签名加分
+        let ranked = rank_suggestions("串联电路", &freq, None, 1);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].tag, "受力分析");
+        let ranked = rank_suggestions("串联电路", &freq, None, 5);
+        assert!((ranked[1].confidence - (0.25 + LITERAL_MATCH_BONUS)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_parse_new_tags() {
+        assert_eq!(
+            parse_new_tags("```json\n[\"受力分析\", \" 动量守恒 \", \"\"]\n```"),
+            vec!["受力分析".to_string(), "动量守恒".to_string()]
+        );
+        assert!(parse_new_tags("无法推荐").is_empty());
+    }
+}
@@ -5,15 +5,49 @@
 //! 清理说明（2026-01）：
 //! - 移除废弃模块：mistakes, bridge, canvas_board
 
+pub mod analysis_bundle;
 pub mod anki_cards;
 pub mod anki_connect;
+pub mod data_location;
+pub mod database_cleanup;
+pub mod diagnostics;
+pub mod embedding_backfill;
+pub mod embedding_export;
 pub mod enhanced_anki;
+pub mod global_search;
 pub mod helpers;
+pub mod logs;
 pub mod mcp;
+pub mod mistake_archive;
+pub mod mistake_branches;
+pub mod mistake_chat;
+pub mod mistake_dedup;
+pub mod mistake_export;
+pub mod mistake_import;
+pub mod mistake_kb_sync;
+pub mod mistake_language;
+pub mod mistake_mastery;
+pub mod mistake_notes;
+pub mod mistake_pin;
+pub mod mistake_recommendations;
+pub mod mistake_relations;
+pub mod mistake_status;
+pub mod mistake_summary;
+pub mod mistake_tags;
 pub mod notes;
 pub mod ocr;
+pub mod privacy;
+pub mod rag_documents;
+pub mod rag_ingest;
+pub mod recommendation_cache;
+pub mod review_analyses;
+pub mod review_export;
+pub mod statistics_export;
+pub mod study_guide;
+pub mod subject_configs;
 pub mod textbooks;
 pub mod translation;
+pub mod turn_metadata;
 pub mod web_search; // OCR 引擎配置命令
 
 // Re-export AppState from the main commands module
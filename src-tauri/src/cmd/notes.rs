@@ -795,7 +795,7 @@ pub async fn notes_rag_rebuild_fts_index(state: State<'_, AppState>) -> Result<u
 }
 
 // Notes 专属 RAG 学科参数（每学科 chunk_size/overlap/rerank）
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
 pub struct NotesSubjectRagConfig {
     pub chunk_size: i32,
     pub chunk_overlap: i32,
@@ -803,6 +803,36 @@ pub struct NotesSubjectRagConfig {
     pub rerank_enabled: bool,
 }
 
+/// settings 键前缀：学科专属 RAG 参数（`notes.rag.config.<学科>`）
+pub const NOTES_SUBJECT_RAG_CONFIG_PREFIX: &str = "notes.rag.config.";
+
+/// 学科 RAG 参数校验（与全局RAG设置保持一致并加上更严格的重叠约束）
+pub(crate) fn validate_subject_rag_config(cfg: &NotesSubjectRagConfig) -> Result<()> {
+    if cfg.chunk_size < 50 || cfg.chunk_size > 2048 {
+        return Err(AppError::validation("分块大小必须在50-2048之间"));
+    }
+    if cfg.min_chunk_size < 10 || cfg.min_chunk_size > cfg.chunk_size {
+        return Err(AppError::validation("最小分块大小必须在10和分块大小之间"));
+    }
+    // 基础约束：重叠 < 分块
+    if cfg.chunk_overlap < 0 || cfg.chunk_overlap >= cfg.chunk_size {
+        return Err(AppError::validation("重叠大小必须非负且小于分块大小"));
+    }
+    // 额外安全约束：限制最大重叠比例（避免步长接近1导致爆炸性分块）
+    // 要求步长 >= max(64, chunk_size/4)
+    let min_stride = std::cmp::max(64, (cfg.chunk_size / 4).max(1));
+    let stride = cfg.chunk_size - cfg.chunk_overlap;
+    if stride < min_stride {
+        return Err(AppError::validation(format!(
+            "重叠过大：当前步长{}，需>= {}（重叠<= {}）",
+            stride,
+            min_stride,
+            cfg.chunk_size - min_stride
+        )));
+    }
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn notes_get_subject_rag_config(
     subject: String,
@@ -811,7 +841,7 @@ pub async fn notes_get_subject_rag_config(
     // 从 notes_database.settings 中读取，没有则使用 rag_configurations 默认
     if let Ok(Some(json)) = state
         .notes_database
-        .get_setting(&format!("notes.rag.config.{}", subject))
+        .get_setting(&format!("{}{}", NOTES_SUBJECT_RAG_CONFIG_PREFIX, subject))
     {
         if let Ok(cfg) = serde_json::from_str::<NotesSubjectRagConfig>(&json) {
             return Ok(cfg);
@@ -839,35 +869,13 @@ pub async fn notes_update_subject_rag_config(
     cfg: NotesSubjectRagConfig,
     state: State<'_, AppState>,
 ) -> Result<bool> {
-    // 参数校验（与全局RAG设置保持一致并加上更严格的重叠约束）
-    if cfg.chunk_size < 50 || cfg.chunk_size > 2048 {
-        return Err(AppError::validation("分块大小必须在50-2048之间"));
-    }
-    if cfg.min_chunk_size < 10 || cfg.min_chunk_size > cfg.chunk_size {
-        return Err(AppError::validation("最小分块大小必须在10和分块大小之间"));
-    }
-    // 基础约束：重叠 < 分块
-    if cfg.chunk_overlap < 0 || cfg.chunk_overlap >= cfg.chunk_size {
-        return Err(AppError::validation("重叠大小必须非负且小于分块大小"));
-    }
-    // 额外安全约束：限制最大重叠比例（避免步长接近1导致爆炸性分块）
-    // 要求步长 >= max(64, chunk_size/4)
-    let min_stride = std::cmp::max(64, (cfg.chunk_size / 4).max(1));
-    let stride = cfg.chunk_size - cfg.chunk_overlap;
-    if stride < min_stride {
-        return Err(AppError::validation(format!(
-            "重叠过大：当前步长{}，需>= {}（重叠<= {}）",
-            stride,
-            min_stride,
-            cfg.chunk_size - min_stride
-        )));
-    }
+    validate_subject_rag_config(&cfg)?;
 
     // 保存科目专属配置
     let json = serde_json::to_string(&cfg).map_err(|e| AppError::database(e.to_string()))?;
     state
         .notes_database
-        .save_setting(&format!("notes.rag.config.{}", subject), &json)
+        .save_setting(&format!("{}{}", NOTES_SUBJECT_RAG_CONFIG_PREFIX, subject), &json)
         .map_err(|e| AppError::database(e.to_string()))?;
 
     // 同步覆盖 notes 数据库中的默认 rag_configurations，使后续嵌入过程生效
@@ -880,6 +888,7 @@ pub async fn notes_update_subject_rag_config(
             min_chunk_size: cfg.min_chunk_size,
             default_top_k: 5,
             default_rerank_enabled: cfg.rerank_enabled,
+            embedding_batch_size: None,
         })
         .map_err(|e| AppError::database(e.to_string()))?;
     Ok(true)
@@ -543,6 +543,36 @@ pub async fn remove_ocr_engine(config_id: String, state: State<'_, AppState>) ->
     Ok(true)
 }
 
+/// 获取按语言路由 OCR 模型的配置
+#[tauri::command]
+pub async fn get_ocr_language_routing(
+    state: State<'_, AppState>,
+) -> Result<crate::ocr_language::OcrLanguageRouting> {
+    Ok(crate::ocr_language::load_ocr_language_routing(
+        &state.database,
+    ))
+}
+
+/// 保存按语言路由 OCR 模型的配置；每个模型须为已存在的多模态配置
+#[tauri::command]
+pub async fn save_ocr_language_routing(
+    routing: crate::ocr_language::OcrLanguageRouting,
+    state: State<'_, AppState>,
+) -> Result<crate::ocr_language::OcrLanguageRouting> {
+    let routing = routing.normalized();
+    for (language, config_id) in &routing.models {
+        state
+            .llm_manager
+            .get_ocr_config_by_id_with_effective_engine(config_id)
+            .await
+            .map_err(|e| {
+                AppError::validation(format!("语言 {} 的 OCR 模型不可用: {}", language, e))
+            })?;
+    }
+    crate::ocr_language::save_ocr_language_routing(&state.database, &routing)?;
+    Ok(routing)
+}
+
 // ==================== OCR 引擎测试功能 ====================
 
 /// OCR 测试请求
@@ -0,0 +1,35 @@
+//! 隐私脱敏设置命令
+//!
+//! 发送云端模型前的姓名、学号等信息脱敏，规则见 [`crate::pii_redaction`]。
+
+use crate::commands::AppState;
+use crate::models::AppError;
+use crate::pii_redaction::{PiiRedactionSettings, PII_REDACTION_SETTINGS_KEY};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 获取隐私脱敏配置（开关、姓名名单与自定义规则）
+#[tauri::command]
+pub async fn get_pii_redaction_settings(
+    state: State<'_, AppState>,
+) -> Result<PiiRedactionSettings> {
+    Ok(crate::pii_redaction::load_pii_redaction_settings(
+        &state.database,
+    ))
+}
+
+/// 保存隐私脱敏配置；自定义正则无效时拒绝保存，校验通过后立即生效并持久化
+#[tauri::command]
+pub async fn save_pii_redaction_settings(
+    settings: PiiRedactionSettings,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    crate::pii_redaction::apply_pii_redaction_settings(&settings).map_err(AppError::validation)?;
+    let raw = serde_json::to_string(&settings)
+        .map_err(|e| AppError::internal(format!("序列化隐私脱敏配置失败: {}", e)))?;
+    state
+        .database
+        .save_setting(PII_REDACTION_SETTINGS_KEY, &raw)
+        .map_err(|e| AppError::database(format!("保存隐私脱敏配置失败: {}", e)))
+}
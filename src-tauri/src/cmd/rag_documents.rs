@@ -0,0 +1,1147 @@
+//! 知识库文档管理命令
+//!
+//! 分库归属同时记录在 SQLite（`rag_documents.sub_library_id`）与 Lance 块表中，
+//! 两侧需保持一致，按分库限定的检索才能立即反映变更。
+
+use crate::commands::AppState;
+use crate::database::SubLibraryIndexStats;
+use crate::disk_guard::DiskSpaceGuard;
+use crate::lance_vector_store::{LanceVectorStore, LibrarySummary};
+use crate::models::{
+    AppError, DeleteSubLibraryOptions, DocumentChunk, DocumentChunkWithEmbedding, RagSourceInfo,
+};
+use crate::rag_grounding::{
+    check_answer_grounding, load_grounding_check_settings, GroundingCheckSettings, GroundingReport,
+};
+use crate::rag_settings::{
+    load_embedding_batch_size, load_rag_retrieval_settings, save_embedding_batch_size,
+    save_rag_retrieval_settings, validate_embedding_batch_size, RagRetrievalSettings,
+};
+use crate::vector_index::{
+    load_vector_index_settings, VectorIndexBuildReport, VectorIndexSettings,
+};
+use crate::vector_store::VectorStore;
+use crate::vfs::database::VfsDatabase;
+use crate::vfs::embedding_service::EmbeddingProgressCallback;
+use crate::vfs::indexing::{ChunkingConfig, VfsFullIndexingService, VfsIndexingService};
+use crate::vfs::lance_store::VfsLanceStore;
+use crate::vfs::repos::{VfsFolderRepo, VfsIndexStateRepo};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+const DEFAULT_CHUNK_PAGE_SIZE: usize = 50;
+const MAX_CHUNK_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveDocumentsResult {
+    pub moved_count: usize,
+    pub moved_ids: Vec<String>,
+    /// 已在目标分库、未做改动的文档数
+    pub unchanged_count: usize,
+}
+
+/// 批量移动文档到目标分库
+///
+/// SQLite 侧单事务完成；Lance 侧对全部请求文档同步归属，
+/// 因此 Lance 更新失败后重试同一请求即可修复不一致。
+#[tauri::command]
+pub async fn move_documents_to_library(
+    document_ids: Vec<String>,
+    target_library_id: String,
+    state: State<'_, AppState>,
+) -> Result<MoveDocumentsResult> {
+    let mut document_ids = document_ids;
+    document_ids.sort();
+    document_ids.dedup();
+    if document_ids.is_empty() {
+        return Err(AppError::validation("文档ID列表不能为空"));
+    }
+
+    let moved_ids = state
+        .database
+        .move_documents_to_sub_library(&document_ids, &target_library_id)
+        .map_err(|e| AppError::database(format!("批量移动文档失败: {}", e)))?;
+
+    let store = LanceVectorStore::new(state.database.clone())?;
+    store
+        .reassign_documents_sub_library(&document_ids, &target_library_id)
+        .await?;
+
+    Ok(MoveDocumentsResult {
+        moved_count: moved_ids.len(),
+        unchanged_count: document_ids.len() - moved_ids.len(),
+        moved_ids,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubLibraryDeletionPreview {
+    pub library_id: String,
+    /// 预览所按的删除方式：是否同时删除分库中的文档
+    pub delete_contained_documents: bool,
+    pub document_count: usize,
+    pub chunk_count: usize,
+    pub vector_count: usize,
+    pub document_bytes: u64,
+    pub chunk_text_bytes: u64,
+    pub embedding_bytes: u64,
+    /// 删除文档时释放的总字节数（原始文件 + 分块文本 + 向量）
+    pub total_bytes: u64,
+    /// 删除文档时将被删除的文档文件名；保留文档时为空
+    pub document_file_names: Vec<String>,
+    /// 保留文档时将移到默认分库的文档数；删除文档时为 0
+    pub reassigned_document_count: usize,
+}
+
+/// 删除分库的演练：以与 `delete_sub_library` 相同的参数与查询报告删除文档时将移除的
+/// 文档、分块、向量与文件名，或保留文档时将移到默认分库的文档数，不做任何修改
+#[tauri::command]
+pub async fn preview_sub_library_deletion(
+    id: String,
+    options: Option<DeleteSubLibraryOptions>,
+    state: State<'_, AppState>,
+) -> Result<SubLibraryDeletionPreview> {
+    let delete_documents = options
+        .and_then(|o| o.delete_contained_documents)
+        .unwrap_or(false);
+    let stats = state
+        .database
+        .get_sub_library_content_stats(&id)
+        .map_err(|e| AppError::validation(format!("无法预览分库删除: {}", e)))?;
+    let impact = state
+        .database
+        .preview_delete_sub_library(&id, delete_documents)
+        .map_err(|e| AppError::validation(format!("无法预览分库删除: {}", e)))?;
+
+    let store = LanceVectorStore::new(state.database.clone())?;
+    let vectors = store.summarize_library(Some(&id)).await?;
+    let embedding_bytes = vectors.embedding_bytes as u64;
+
+    Ok(SubLibraryDeletionPreview {
+        library_id: id,
+        delete_contained_documents: impact.documents_deleted,
+        document_count: stats.document_count,
+        chunk_count: stats.chunk_count,
+        vector_count: vectors.chunk_count,
+        document_bytes: stats.document_bytes,
+        chunk_text_bytes: stats.chunk_text_bytes,
+        embedding_bytes,
+        total_bytes: stats.document_bytes + stats.chunk_text_bytes + embedding_bytes,
+        document_file_names: impact.document_file_names,
+        reassigned_document_count: impact.reassigned_document_count,
+    })
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryIndexStats {
+    pub library_id: String,
+    pub name: String,
+    pub document_count: usize,
+    pub chunk_count: usize,
+    /// 已有向量的分块数（按向量最多的嵌入维度计）
+    pub embedded_chunks: usize,
+    /// 尚无向量的分块数
+    pub pending_chunks: usize,
+    /// 未就绪或有重试记录的文档数
+    pub pending_documents: usize,
+    /// Lance 中的向量行数（换模型后旧维度的向量也计入）
+    pub vector_count: usize,
+    /// 向量所在的嵌入维度；多于一个说明部分分块仍是旧模型的向量
+    pub embedding_dimensions: Vec<usize>,
+    pub document_bytes: u64,
+    pub chunk_text_bytes: u64,
+    pub embedding_bytes: u64,
+    pub total_bytes: u64,
+    pub last_updated: Option<String>,
+    /// 存在待嵌入分块、待处理文档或混用多个嵌入维度
+    pub needs_reindex: bool,
+}
+
+impl LibraryIndexStats {
+    fn new(stats: SubLibraryIndexStats, vectors: &LibrarySummary) -> Self {
+        let embedded_chunks = vectors
+            .vectors_by_dimension
+            .values()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .min(stats.chunk_count);
+        let pending_chunks = stats.chunk_count - embedded_chunks;
+        let embedding_dimensions: Vec<usize> =
+            vectors.vectors_by_dimension.keys().copied().collect();
+        let embedding_bytes = vectors.embedding_bytes as u64;
+        Self {
+            needs_reindex: pending_chunks > 0
+                || stats.pending_documents > 0
+                || embedding_dimensions.len() > 1,
+            library_id: stats.library_id,
+            name: stats.name,
+            document_count: stats.document_count,
+            chunk_count: stats.chunk_count,
+            embedded_chunks,
+            pending_chunks,
+            pending_documents: stats.pending_documents,
+            vector_count: vectors.chunk_count,
+            embedding_dimensions,
+            document_bytes: stats.document_bytes,
+            chunk_text_bytes: stats.chunk_text_bytes,
+            embedding_bytes,
+            total_bytes: stats.document_bytes + stats.chunk_text_bytes + embedding_bytes,
+            last_updated: stats.last_updated,
+        }
+    }
+
+    /// 汇总各分库；嵌入维度取并集，更新时间取最新
+    fn rollup(libraries: &[LibraryIndexStats]) -> Self {
+        let mut total = Self {
+            library_id: "all".to_string(),
+            ..Self::default()
+        };
+        for library in libraries {
+            total.document_count += library.document_count;
+            total.chunk_count += library.chunk_count;
+            total.embedded_chunks += library.embedded_chunks;
+            total.pending_chunks += library.pending_chunks;
+            total.pending_documents += library.pending_documents;
+            total.vector_count += library.vector_count;
+            total
+                .embedding_dimensions
+                .extend(&library.embedding_dimensions);
+            total.document_bytes += library.document_bytes;
+            total.chunk_text_bytes += library.chunk_text_bytes;
+            total.embedding_bytes += library.embedding_bytes;
+            total.total_bytes += library.total_bytes;
+            total.last_updated = total.last_updated.max(library.last_updated.clone());
+            total.needs_reindex |= library.needs_reindex;
+        }
+        total.embedding_dimensions.sort_unstable();
+        total.embedding_dimensions.dedup();
+        total
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KnowledgeBaseLibraryStats {
+    pub libraries: Vec<LibraryIndexStats>,
+    pub total: LibraryIndexStats,
+    /// 当前配置的嵌入模型；分库不单独记录模型，以嵌入维度区分新旧向量
+    pub embedding_model: Option<String>,
+}
+
+/// 按分库统计知识库：文档与分块数、已嵌入与待嵌入分块、占用字节与最近更新时间，并给出汇总
+///
+/// 每个分库扫描一次 Lance 向量表，库多时耗时较长，适合在统计面板中按需调用。
+#[tauri::command]
+pub async fn get_knowledge_base_library_stats(
+    state: State<'_, AppState>,
+) -> Result<KnowledgeBaseLibraryStats> {
+    let sql_stats = state
+        .database
+        .list_sub_library_index_stats()
+        .map_err(|e| AppError::database(format!("获取分库统计失败: {}", e)))?;
+
+    let store = LanceVectorStore::new(state.database.clone())?;
+    let mut libraries = Vec::with_capacity(sql_stats.len());
+    for stats in sql_stats {
+        let vectors = store.summarize_library(Some(&stats.library_id)).await?;
+        libraries.push(LibraryIndexStats::new(stats, &vectors));
+    }
+
+    let embedding_model = state
+        .llm_manager
+        .get_embedding_model_config()
+        .await
+        .ok()
+        .map(|config| config.model);
+
+    Ok(KnowledgeBaseLibraryStats {
+        total: LibraryIndexStats::rollup(&libraries),
+        libraries,
+        embedding_model,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubLibraryDeletionSummary {
+    pub library_id: String,
+    pub documents_deleted: usize,
+    pub documents_moved_to_default: usize,
+    pub chunks_deleted: usize,
+    pub vectors_deleted: usize,
+    /// 清理后该分库（及被删文档）在 Lance 中的残余向量数
+    pub vectors_remaining: usize,
+    /// 向量已确认清理完毕
+    pub vectors_cleaned: bool,
+    /// 向量清理失败原因；分库本身已删除，可稍后通过索引维护清理
+    pub vector_cleanup_error: Option<String>,
+}
+
+/// 删除分库，并同步清理 Lance 中的向量
+///
+/// 删除文档时先删除其向量，失败则中止、SQLite 保持不变，重试即可；
+/// 保留文档时将其向量归属改为默认分库。SQLite 提交后再清理一遍仍标记为
+/// 该分库的向量，并以残余数确认清理完成。
+#[tauri::command]
+pub async fn delete_sub_library(
+    id: String,
+    options: Option<DeleteSubLibraryOptions>,
+    state: State<'_, AppState>,
+) -> Result<SubLibraryDeletionSummary> {
+    let delete_documents = options
+        .and_then(|o| o.delete_contained_documents)
+        .unwrap_or(false);
+
+    let store = LanceVectorStore::new(state.database.clone())?;
+    let mut vectors_deleted = 0;
+    if delete_documents {
+        let document_ids = state
+            .database
+            .list_deletable_sub_library_documents(&id)
+            .map_err(|e| AppError::validation(format!("删除分库失败: {}", e)))?;
+        vectors_deleted += store
+            .purge_sub_library_vectors(&id, &document_ids)
+            .await?
+            .removed;
+    }
+
+    let deleted = state
+        .database
+        .delete_sub_library(&id, delete_documents)
+        .map_err(|e| AppError::database(format!("删除分库失败: {}", e)))?;
+
+    let cleanup = async {
+        if delete_documents {
+            store
+                .purge_sub_library_vectors(&id, &deleted.document_ids)
+                .await
+        } else {
+            store
+                .reassign_documents_sub_library(&deleted.document_ids, "default")
+                .await?;
+            store.purge_sub_library_vectors(&id, &[]).await
+        }
+    }
+    .await;
+
+    let document_count = deleted.document_ids.len();
+    let (documents_deleted, documents_moved_to_default) = if deleted.documents_deleted {
+        (document_count, 0)
+    } else {
+        (0, document_count)
+    };
+    let mut summary = SubLibraryDeletionSummary {
+        library_id: id,
+        documents_deleted,
+        documents_moved_to_default,
+        chunks_deleted: deleted.deleted_chunk_count,
+        vectors_deleted,
+        vectors_remaining: 0,
+        vectors_cleaned: false,
+        vector_cleanup_error: None,
+    };
+    match cleanup {
+        Ok(report) => {
+            summary.vectors_deleted += report.removed;
+            summary.vectors_remaining = report.remaining;
+            summary.vectors_cleaned = report.remaining == 0;
+        }
+        Err(e) => {
+            log::warn!("分库 {} 已删除，但向量清理失败: {}", summary.library_id, e);
+            summary.vector_cleanup_error = Some(e.message);
+        }
+    }
+    Ok(summary)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDeletionSummary {
+    pub document_id: String,
+    pub chunks_deleted: usize,
+    pub vectors_deleted: usize,
+    pub vectors_remaining: usize,
+    pub vectors_cleaned: bool,
+}
+
+/// 删除知识库文档：先按文档ID与分块ID删除 Lance 向量，再删除 SQLite 中的文档与分块
+///
+/// 向量删除失败时中止，文档保持不变。
+#[tauri::command]
+pub async fn rag_delete_document(
+    document_id: String,
+    state: State<'_, AppState>,
+) -> Result<DocumentDeletionSummary> {
+    let chunk_ids = state
+        .database
+        .get_rag_document_chunk_ids(&document_id)
+        .map_err(|e| AppError::database(format!("读取文档分块失败: {}", e)))?
+        .ok_or_else(|| AppError::not_found(format!("文档不存在: {}", document_id)))?;
+
+    let store = LanceVectorStore::new(state.database.clone())?;
+    let report = store
+        .purge_document_vectors(std::slice::from_ref(&document_id), &chunk_ids)
+        .await?;
+
+    let chunks_deleted = state
+        .database
+        .delete_rag_document(&document_id)
+        .map_err(|e| AppError::database(format!("删除文档失败: {}", e)))?;
+
+    Ok(DocumentDeletionSummary {
+        document_id,
+        chunks_deleted,
+        vectors_deleted: report.removed,
+        vectors_remaining: report.remaining,
+        vectors_cleaned: report.remaining == 0,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanVectorGcResult {
+    pub scanned_vectors: usize,
+    pub orphan_chunks: usize,
+    /// 回收的向量行数
+    pub reclaimed: usize,
+    pub remaining: usize,
+}
+
+/// 维护：删除 Lance 中没有对应 `rag_document_chunks` 行的孤儿向量
+#[tauri::command]
+pub async fn gc_orphan_vectors(state: State<'_, AppState>) -> Result<OrphanVectorGcResult> {
+    let store = LanceVectorStore::new(state.database.clone())?;
+    let report = store.gc_orphan_vectors().await?;
+    log::info!(
+        "[RagMaintenance] 孤儿向量回收: 扫描 {} 行，孤儿块 {} 个，删除 {} 行",
+        report.scanned,
+        report.orphan_chunks,
+        report.removed
+    );
+    Ok(OrphanVectorGcResult {
+        scanned_vectors: report.scanned,
+        orphan_chunks: report.orphan_chunks,
+        reclaimed: report.removed,
+        remaining: report.remaining,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentChunkView {
+    pub id: String,
+    pub chunk_index: usize,
+    pub text: String,
+    pub page_number: Option<String>,
+    pub heading: Option<String>,
+    pub metadata: HashMap<String, String>,
+    /// Lance 中是否已有该块的向量；为 false 即块已存储但仍在等待（重新）向量化
+    pub has_embedding: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentChunksPage {
+    pub document_id: String,
+    pub total_chunks: usize,
+    pub page: usize,
+    pub page_size: usize,
+    pub update_state: String,
+    pub update_retry: i64,
+    pub chunks: Vec<DocumentChunkView>,
+}
+
+fn metadata_value(metadata: &HashMap<String, String>, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .find_map(|key| metadata.get(*key))
+        .filter(|v| !v.trim().is_empty())
+        .cloned()
+}
+
+/// 分页查看文档的实际分块，用于排查检索效果（只读）
+#[tauri::command]
+pub async fn get_document_chunks(
+    document_id: String,
+    page: Option<usize>,
+    page_size: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<DocumentChunksPage> {
+    let page = page.unwrap_or(1).max(1);
+    let page_size = page_size
+        .unwrap_or(DEFAULT_CHUNK_PAGE_SIZE)
+        .clamp(1, MAX_CHUNK_PAGE_SIZE);
+
+    let stored = state
+        .database
+        .get_rag_document_chunks_page(&document_id, page, page_size)
+        .map_err(|e| AppError::database(format!("读取文档分块失败: {}", e)))?
+        .ok_or_else(|| AppError::not_found(format!("文档不存在: {}", document_id)))?;
+
+    let store = LanceVectorStore::new(state.database.clone())?;
+    let embedded = store.embedded_chunk_ids_for_document(&document_id).await?;
+
+    let chunks = stored
+        .chunks
+        .into_iter()
+        .map(|chunk| DocumentChunkView {
+            page_number: metadata_value(&chunk.metadata, &["page_number", "page"]),
+            heading: metadata_value(&chunk.metadata, &["heading", "section", "title"]),
+            has_embedding: embedded.contains(&chunk.id),
+            id: chunk.id,
+            chunk_index: chunk.chunk_index,
+            text: chunk.text,
+            metadata: chunk.metadata,
+        })
+        .collect();
+
+    Ok(DocumentChunksPage {
+        document_id,
+        total_chunks: stored.total_chunks,
+        page,
+        page_size,
+        update_state: stored.update_state,
+        update_retry: stored.update_retry,
+        chunks,
+    })
+}
+
+/// 导入失败文档的最大自动重试次数，超过后需重新导入
+const MAX_INGESTION_RETRIES: i64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestionRetryStatus {
+    /// 缺失的向量已补齐，文档恢复为 ready
+    Recovered,
+    /// 本次重试仍失败，重试次数加一
+    Failed,
+    /// 已达重试上限，未重试
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestionRetryOutcome {
+    pub document_id: String,
+    pub file_name: String,
+    pub status: IngestionRetryStatus,
+    /// 重试前缺失向量的分块数
+    pub pending_chunks: usize,
+    /// 本次补齐的分块数
+    pub recovered_chunks: usize,
+    pub update_retry: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 重新嵌入 failed / partial 文档中缺失向量的分块
+///
+/// 只处理已落库但 Lance 中没有向量的分块，已有向量的分块不重复嵌入。
+/// 文档逐个串行处理，嵌入请求沿用嵌入接口的限流退避；
+/// 达到 `MAX_INGESTION_RETRIES` 的文档直接跳过。
+#[tauri::command]
+pub async fn retry_failed_ingestions(
+    state: State<'_, AppState>,
+) -> Result<Vec<IngestionRetryOutcome>> {
+    let documents = state
+        .database
+        .list_failed_rag_documents()
+        .map_err(|e| AppError::database(format!("读取导入失败的文档失败: {}", e)))?;
+    if documents.is_empty() {
+        return Ok(Vec::new());
+    }
+    let embedding_config = state.llm_manager.require_embedding_model().await?;
+    crate::offline_mode::ensure_endpoint_allowed("嵌入模型", &embedding_config.base_url)?;
+    let store = LanceVectorStore::new(state.database.clone())?;
+
+    let mut outcomes = Vec::with_capacity(documents.len());
+    for document in documents {
+        let mut outcome = IngestionRetryOutcome {
+            document_id: document.id.clone(),
+            file_name: document.file_name.clone(),
+            status: IngestionRetryStatus::Skipped,
+            pending_chunks: 0,
+            recovered_chunks: 0,
+            update_retry: document.update_retry,
+            error: document.last_error.clone(),
+        };
+        if document.update_retry >= MAX_INGESTION_RETRIES {
+            outcomes.push(outcome);
+            continue;
+        }
+
+        let pending = match pending_chunks_for_document(&state, &store, &document.id).await {
+            Ok(pending) => pending,
+            Err(e) => {
+                outcome.status = IngestionRetryStatus::Failed;
+                outcome.error = Some(e.message);
+                outcomes.push(outcome);
+                continue;
+            }
+        };
+        outcome.pending_chunks = pending.len();
+
+        match embed_pending_chunks(&state, &store, &embedding_config.id, pending).await {
+            Ok(recovered) => {
+                state
+                    .database
+                    .mark_rag_document_ready(&document.id)
+                    .map_err(|e| AppError::database(e.to_string()))?;
+                outcome.status = IngestionRetryStatus::Recovered;
+                outcome.recovered_chunks = recovered;
+                outcome.update_retry = 0;
+                outcome.error = None;
+            }
+            Err(e) => {
+                state
+                    .database
+                    .mark_rag_document_failed(&document.id, "failed", &e.message)
+                    .map_err(|e| AppError::database(e.to_string()))?;
+                outcome.status = IngestionRetryStatus::Failed;
+                outcome.update_retry = document.update_retry + 1;
+                outcome.error = Some(e.message);
+            }
+        }
+        outcomes.push(outcome);
+    }
+
+    log::info!(
+        "[RagMaintenance] 导入失败文档重试: 恢复 {} 个，失败 {} 个，跳过 {} 个",
+        count_status(&outcomes, IngestionRetryStatus::Recovered),
+        count_status(&outcomes, IngestionRetryStatus::Failed),
+        count_status(&outcomes, IngestionRetryStatus::Skipped)
+    );
+    Ok(outcomes)
+}
+
+fn count_status(outcomes: &[IngestionRetryOutcome], status: IngestionRetryStatus) -> usize {
+    outcomes.iter().filter(|o| o.status == status).count()
+}
+
+/// 已落库但 Lance 中没有向量的分块
+async fn pending_chunks_for_document(
+    state: &AppState,
+    store: &LanceVectorStore,
+    document_id: &str,
+) -> Result<Vec<DocumentChunk>> {
+    let chunks = state
+        .database
+        .list_rag_document_chunks(document_id)
+        .map_err(|e| AppError::database(format!("读取文档分块失败: {}", e)))?;
+    let embedded = store.embedded_chunk_ids_for_document(document_id).await?;
+    Ok(chunks
+        .into_iter()
+        .filter(|chunk| !embedded.contains(&chunk.id))
+        .collect())
+}
+
+/// 嵌入并写入缺失向量的分块，返回补齐的分块数
+async fn embed_pending_chunks(
+    state: &AppState,
+    store: &LanceVectorStore,
+    model_config_id: &str,
+    pending: Vec<DocumentChunk>,
+) -> Result<usize> {
+    if pending.is_empty() {
+        return Ok(0);
+    }
+    let texts: Vec<String> = pending.iter().map(|chunk| chunk.text.clone()).collect();
+    let embeddings = state
+        .llm_manager
+        .call_embedding_api(texts, model_config_id)
+        .await?;
+    if embeddings.len() != pending.len() {
+        return Err(AppError::llm("嵌入返回数量与分块数量不一致"));
+    }
+    let recovered = pending.len();
+    let chunks = pending
+        .into_iter()
+        .zip(embeddings)
+        .map(|(chunk, embedding)| DocumentChunkWithEmbedding { chunk, embedding })
+        .collect();
+    store.add_chunks(chunks).await?;
+    Ok(recovered)
+}
+
+/// 获取知识库向量索引配置
+#[tauri::command]
+pub async fn get_vector_index_settings(state: State<'_, AppState>) -> Result<VectorIndexSettings> {
+    Ok(load_vector_index_settings(&state.database))
+}
+
+/// 保存知识库向量索引配置（仅影响之后的自动构建与查询）
+#[tauri::command]
+pub async fn save_vector_index_settings(
+    settings: VectorIndexSettings,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    crate::vector_index::save_vector_index_settings(&state.database, &settings)
+}
+
+/// 忽略行数阈值，立即为分库所在的向量表构建（重建）ANN 索引
+#[tauri::command]
+pub async fn build_vector_index(
+    library_id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<VectorIndexBuildReport>> {
+    if library_id.trim().is_empty() {
+        return Err(AppError::validation("分库ID不能为空"));
+    }
+    let store = LanceVectorStore::new(state.database.clone())?;
+    store.build_vector_index(&library_id).await
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorStoreOptimizeResult {
+    pub library_id: String,
+    /// 实际整理的向量表（按维度共享，可能包含其它分库的数据）
+    pub tables: Vec<String>,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub versions_removed: u64,
+    pub files_removed: usize,
+    pub index_reports: Vec<VectorIndexBuildReport>,
+    pub elapsed_ms: u64,
+}
+
+/// 维护：压缩分库所在向量表、清理旧版本并重建 ANN 索引
+///
+/// 导入或重建索引进行中、维护模式下拒绝执行。
+#[tauri::command]
+pub async fn optimize_vector_store(
+    library_id: String,
+    state: State<'_, AppState>,
+) -> Result<VectorStoreOptimizeResult> {
+    if library_id.trim().is_empty() {
+        return Err(AppError::validation("分库ID不能为空"));
+    }
+    if STALE_REINDEX_RUNNING.load(Ordering::SeqCst) {
+        return Err(AppError::validation("重建索引进行中，请稍后再整理向量库"));
+    }
+    let store = LanceVectorStore::new(state.database.clone())?;
+    let report = store.optimize_library_tables(&library_id).await?;
+    log::info!(
+        "[RagMaintenance] 向量库整理: 分库 {}，{} 张表，删除 {} 个旧版本，目录 {} -> {} 字节",
+        library_id,
+        report.tables.len(),
+        report.versions_removed,
+        report.bytes_before,
+        report.bytes_after
+    );
+    Ok(VectorStoreOptimizeResult {
+        library_id,
+        tables: report.tables,
+        bytes_before: report.bytes_before,
+        bytes_after: report.bytes_after,
+        versions_removed: report.versions_removed,
+        files_removed: report.files_removed,
+        index_reports: report.index_reports,
+        elapsed_ms: report.elapsed_ms,
+    })
+}
+
+/// 获取回答溯源校验配置
+#[tauri::command]
+pub async fn get_grounding_check_settings(
+    state: State<'_, AppState>,
+) -> Result<GroundingCheckSettings> {
+    Ok(load_grounding_check_settings(&state.database))
+}
+
+/// 保存回答溯源校验配置
+#[tauri::command]
+pub async fn save_grounding_check_settings(
+    settings: GroundingCheckSettings,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    crate::rag_grounding::save_grounding_check_settings(&state.database, &settings)
+}
+
+/// 校验 RAG 回答是否被引用片段支持；未启用校验时返回 None
+#[tauri::command]
+pub async fn verify_rag_answer_grounding(
+    answer: String,
+    sources: Vec<RagSourceInfo>,
+    state: State<'_, AppState>,
+) -> Result<Option<GroundingReport>> {
+    let settings = load_grounding_check_settings(&state.database);
+    if !settings.enabled {
+        return Ok(None);
+    }
+    check_answer_grounding(&state.llm_manager, &settings, &answer, &sources)
+        .await
+        .map(Some)
+}
+
+/// 获取知识库检索参数
+#[tauri::command]
+pub async fn get_rag_settings(state: State<'_, AppState>) -> Result<RagRetrievalSettings> {
+    Ok(load_rag_retrieval_settings(&state.database))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RagSettingsUpdateResult {
+    /// 分块参数变更后待重建索引的资源数
+    pub stale_documents: usize,
+}
+
+/// 获取导入与重建索引时每次嵌入请求的分块数
+#[tauri::command]
+pub async fn get_rag_embedding_batch_size(state: State<'_, AppState>) -> Result<usize> {
+    Ok(load_embedding_batch_size(&state.database))
+}
+
+/// 更新知识库检索参数（最低相似度阈值等），可同时更新分块参数与嵌入批大小
+///
+/// 分块参数生效值变化时，已索引资源只被标记为 stale，不会自动重建；
+/// 前端根据返回的 `stale_documents` 提示用户，确认后调用 `reindex_stale_documents`。
+#[tauri::command]
+pub async fn update_rag_settings(
+    settings: RagRetrievalSettings,
+    chunking: Option<ChunkingConfig>,
+    embedding_batch_size: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<RagSettingsUpdateResult> {
+    settings.validate()?;
+    if let Some(batch_size) = embedding_batch_size {
+        validate_embedding_batch_size(batch_size)?;
+    }
+    if let Some(chunking) = &chunking {
+        chunking
+            .validate()
+            .map_err(|e| AppError::validation(e.to_string()))?;
+        let vfs_db = require_vfs_db(&state)?;
+        let marked = VfsIndexingService::new(vfs_db)
+            .update_chunking_config(chunking)
+            .map_err(|e| AppError::database(e.to_string()))?;
+        if marked > 0 {
+            log::info!(
+                "[RagSettings] 分块参数已变更，{} 个资源标记为待重建索引",
+                marked
+            );
+        }
+    }
+    save_rag_retrieval_settings(&state.database, &settings)?;
+    if let Some(batch_size) = embedding_batch_size {
+        // VFS 索引服务从自身配置读取批大小，与分块参数一样两侧同时保存
+        VfsIndexingService::new(require_vfs_db(&state)?)
+            .update_embedding_batch_size(batch_size)
+            .map_err(|e| AppError::database(e.to_string()))?;
+        save_embedding_batch_size(&state.database, batch_size)?;
+    }
+
+    Ok(RagSettingsUpdateResult {
+        stale_documents: count_stale_documents(&state)?,
+    })
+}
+
+/// 恢复知识库检索参数与 RAG 配置（含嵌入批大小）的默认值
+///
+/// 不改动 VFS 分块参数，已索引资源不受影响。
+#[tauri::command]
+pub async fn reset_rag_settings(state: State<'_, AppState>) -> Result<RagRetrievalSettings> {
+    let defaults = RagRetrievalSettings::default();
+    save_rag_retrieval_settings(&state.database, &defaults)?;
+    state
+        .database
+        .reset_rag_configuration()
+        .map_err(|e| AppError::database(format!("重置 RAG 配置失败: {}", e)))?;
+    if let Some(vfs_db) = state.vfs_db.clone() {
+        VfsIndexingService::new(vfs_db)
+            .update_embedding_batch_size(load_embedding_batch_size(&state.database))
+            .map_err(|e| AppError::database(e.to_string()))?;
+    }
+    Ok(defaults)
+}
+
+/// 获取因分块参数变更而待重建索引的资源数
+#[tauri::command]
+pub async fn get_stale_document_count(state: State<'_, AppState>) -> Result<usize> {
+    count_stale_documents(&state)
+}
+
+fn require_vfs_db(state: &AppState) -> Result<Arc<VfsDatabase>> {
+    state
+        .vfs_db
+        .clone()
+        .ok_or_else(|| AppError::configuration("VFS database not configured"))
+}
+
+fn count_stale_documents(state: &AppState) -> Result<usize> {
+    let Some(vfs_db) = state.vfs_db.as_ref() else {
+        return Ok(0);
+    };
+    VfsIndexStateRepo::count_stale_resources(vfs_db, None)
+        .map_err(|e| AppError::database(e.to_string()))
+}
+
+/// 知识库（VFS 文件夹，含子文件夹）下所有资源的 ID
+fn library_resource_ids(vfs_db: &VfsDatabase, library_id: &str) -> Result<Vec<String>> {
+    let folder = VfsFolderRepo::get_all_resources(vfs_db, library_id, true, false)
+        .map_err(|e| AppError::database(e.to_string()))?;
+    Ok(folder
+        .resources
+        .into_iter()
+        .filter_map(|resource| resource.resource_id)
+        .collect())
+}
+
+/// 重建任务进度事件
+const STALE_REINDEX_PROGRESS_EVENT: &str = "rag-stale-reindex-progress";
+/// 每次从 stale 队列 claim 的资源数
+const STALE_REINDEX_BATCH_SIZE: u32 = 10;
+
+/// 同一时间只允许一个重建任务
+static STALE_REINDEX_RUNNING: AtomicBool = AtomicBool::new(false);
+static STALE_REINDEX_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+struct StaleReindexGuard;
+
+impl Drop for StaleReindexGuard {
+    fn drop(&mut self) {
+        STALE_REINDEX_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StaleReindexProgress {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_id: Option<String>,
+    /// 已处理（成功或失败）的资源数
+    pub completed: usize,
+    pub failed: usize,
+    pub total: usize,
+    /// 当前资源已嵌入与总分块数
+    pub chunks_embedded: usize,
+    pub total_chunks: usize,
+    /// 任务是否已结束
+    pub finished: bool,
+    /// 任务是否因取消而结束
+    pub cancelled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 按当前分块参数重新分块并嵌入所有 stale 资源
+///
+/// 立即返回待处理数量，实际重建在后台进行，进度（含当前资源的分块嵌入进度）通过
+/// `rag-stale-reindex-progress` 事件推送。重建期间旧向量仍可检索；单个资源失败会标记为 failed，
+/// 不影响其余资源。`cancel_reindex_stale_documents` 在当前资源完成后停止，
+/// 未处理的资源仍为 stale，再次调用即从剩余资源继续。
+#[tauri::command]
+pub async fn reindex_stale_documents(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    lance_store: State<'_, Arc<VfsLanceStore>>,
+) -> Result<usize> {
+    start_stale_reindex(app_handle, &state, lance_store.inner().clone(), None).await
+}
+
+/// 按当前分块参数重建指定知识库（VFS 文件夹，含子文件夹）内的 stale 资源
+///
+/// `library_id` 为空时等同于 `reindex_stale_documents`。只处理分块参数变更后被标记为 stale 的资源，
+/// 已按当前参数重建的资源不会重复处理；进度事件与取消方式同 `reindex_stale_documents`。
+#[tauri::command]
+pub async fn rag_reindex_documents(
+    library_id: Option<String>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    lance_store: State<'_, Arc<VfsLanceStore>>,
+) -> Result<usize> {
+    let scope = match library_id.as_deref() {
+        Some(library_id) => Some(library_resource_ids(&require_vfs_db(&state)?, library_id)?),
+        None => None,
+    };
+    start_stale_reindex(app_handle, &state, lance_store.inner().clone(), scope).await
+}
+
+/// 启动后台 stale 重建任务，`scope` 为 `Some` 时只处理其中列出的资源
+async fn start_stale_reindex(
+    app_handle: AppHandle,
+    state: &AppState,
+    lance_store: Arc<VfsLanceStore>,
+    scope: Option<Vec<String>>,
+) -> Result<usize> {
+    let vfs_db = require_vfs_db(state)?;
+    let total = VfsIndexStateRepo::count_stale_resources(&vfs_db, scope.as_deref())
+        .map_err(|e| AppError::database(e.to_string()))?;
+    if total == 0 {
+        return Ok(0);
+    }
+    let embedding_config = state.llm_manager.require_embedding_model().await?;
+    crate::offline_mode::ensure_endpoint_allowed("嵌入模型", &embedding_config.base_url)?;
+
+    let mut disk_guard = DiskSpaceGuard::from_db(&state.database, vfs_db.db_path().to_path_buf());
+    disk_guard.preflight(0)?;
+
+    if STALE_REINDEX_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(AppError::validation("已有重建索引任务在进行中"));
+    }
+    let guard = StaleReindexGuard;
+    STALE_REINDEX_CANCELLED.store(false, Ordering::SeqCst);
+    let llm_manager = state.llm_manager.clone();
+
+    tokio::spawn(async move {
+        let _guard = guard;
+        let emit = |progress: StaleReindexProgress| {
+            let _ = app_handle.emit(STALE_REINDEX_PROGRESS_EVENT, progress);
+        };
+
+        let service =
+            match VfsFullIndexingService::new(Arc::clone(&vfs_db), llm_manager, lance_store) {
+                Ok(service) => service,
+                Err(e) => {
+                    log::error!("[RagSettings] 重建索引服务初始化失败: {}", e);
+                    emit(StaleReindexProgress {
+                        resource_id: None,
+                        completed: 0,
+                        failed: 0,
+                        total,
+                        chunks_embedded: 0,
+                        total_chunks: 0,
+                        finished: true,
+                        cancelled: false,
+                        error: Some(e.to_string()),
+                    });
+                    return;
+                }
+            };
+
+        let mut completed = 0usize;
+        let mut failed = 0usize;
+        let mut aborted: Option<String> = None;
+        let mut cancelled = false;
+        'batches: loop {
+            let batch = match VfsIndexStateRepo::claim_stale_resources(
+                &vfs_db,
+                STALE_REINDEX_BATCH_SIZE,
+                scope.as_deref(),
+            ) {
+                Ok(batch) => batch,
+                Err(e) => {
+                    log::error!("[RagSettings] 读取待重建资源失败: {}", e);
+                    break;
+                }
+            };
+            if batch.is_empty() {
+                break;
+            }
+
+            for (idx, resource_id) in batch.iter().enumerate() {
+                // 取消或磁盘空间不足时在资源边界停止，未处理的资源退回 stale，旧向量仍可检索
+                let stop = if STALE_REINDEX_CANCELLED.load(Ordering::SeqCst) {
+                    cancelled = true;
+                    true
+                } else if let Err(e) = disk_guard.check() {
+                    aborted = Some(e.message);
+                    true
+                } else {
+                    false
+                };
+                if stop {
+                    if let Err(re) = VfsIndexStateRepo::release_stale_claims(&vfs_db, &batch[idx..])
+                    {
+                        log::error!("[RagSettings] 退回待重建资源失败: {}", re);
+                    }
+                    break 'batches;
+                }
+                let resource_id = resource_id.clone();
+                let chunk_progress: EmbeddingProgressCallback = {
+                    let app_handle = app_handle.clone();
+                    let resource_id = resource_id.clone();
+                    Box::new(move |chunks_embedded, total_chunks| {
+                        let _ = app_handle.emit(
+                            STALE_REINDEX_PROGRESS_EVENT,
+                            StaleReindexProgress {
+                                resource_id: Some(resource_id.clone()),
+                                completed,
+                                failed,
+                                total: total.max(completed),
+                                chunks_embedded,
+                                total_chunks,
+                                finished: false,
+                                cancelled: false,
+                                error: None,
+                            },
+                        );
+                    })
+                };
+                let (chunks_embedded, error) = match service
+                    .reindex_resource(&resource_id, None, Some(chunk_progress))
+                    .await
+                {
+                    Ok((chunk_count, _)) => (chunk_count, None),
+                    Err(e) => {
+                        let _ =
+                            VfsIndexStateRepo::mark_failed(&vfs_db, &resource_id, &e.to_string());
+                        failed += 1;
+                        (0, Some(e.to_string()))
+                    }
+                };
+                completed += 1;
+                emit(StaleReindexProgress {
+                    resource_id: Some(resource_id),
+                    completed,
+                    failed,
+                    // 重建期间分块参数可能再次变更，总数随之增长
+                    total: total.max(completed),
+                    chunks_embedded,
+                    total_chunks: chunks_embedded,
+                    finished: false,
+                    cancelled: false,
+                    error,
+                });
+            }
+        }
+
+        log::info!(
+            "[RagSettings] stale 资源重建结束: {} 个，失败 {} 个{}",
+            completed,
+            failed,
+            if aborted.is_some() {
+                "（磁盘空间不足，已中止）"
+            } else if cancelled {
+                "（已取消）"
+            } else {
+                ""
+            }
+        );
+        emit(StaleReindexProgress {
+            resource_id: None,
+            completed,
+            failed,
+            total: total.max(completed),
+            chunks_embedded: 0,
+            total_chunks: 0,
+            finished: true,
+            cancelled,
+            error: aborted,
+        });
+    });
+
+    Ok(total)
+}
+
+/// 请求停止 stale 资源重建，返回是否有任务在进行
+///
+/// 当前资源完成后停止；未处理的资源仍为 stale，再次调用 `reindex_stale_documents` 即可继续。
+#[tauri::command]
+pub async fn cancel_reindex_stale_documents() -> Result<bool> {
+    if !STALE_REINDEX_RUNNING.load(Ordering::SeqCst) {
+        return Ok(false);
+    }
+    STALE_REINDEX_CANCELLED.store(true, Ordering::SeqCst);
+    Ok(true)
+}
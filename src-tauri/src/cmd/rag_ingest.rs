@@ -0,0 +1,513 @@
+//! 知识库文档导入（解析、分块、嵌入）及进度推送
+//!
+//! 文档以文件资源写入 VFS，再由 `VfsFullIndexingService` 分块、嵌入并写入 VFS 向量表，
+//! 与知识库检索读取的是同一份索引。文件夹即知识库中的分库，向量行带文件夹ID供检索限定范围。
+//!
+//! `rag_add_documents_with_progress` 逐个文档导入，并通过 `rag-ingest-progress` 事件推送
+//! 当前文档、已嵌入分块数、总分块数与已用时间；`rag_add_documents` /
+//! `rag_add_documents_to_library` 使用不推送事件的发射器走同一流程。
+//! 单个文档解析或嵌入失败时推送错误事件并继续导入其余文档；失败的资源记为索引失败，
+//! 可在索引管理中重试。
+
+use crate::commands::AppState;
+use crate::document_parser::DocumentParser;
+use crate::models::AppError;
+use crate::vfs::database::VfsDatabase;
+use crate::vfs::embedding_service::EmbeddingProgressCallback;
+use crate::vfs::indexing::VfsFullIndexingService;
+use crate::vfs::lance_store::VfsLanceStore;
+use crate::vfs::repos::{VfsBlobRepo, VfsFileRepo, VfsFolderRepo};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const RAG_INGEST_PROGRESS_EVENT: &str = "rag-ingest-progress";
+
+/// 分块进度事件的节流间隔
+const THROTTLE_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RagIngestPhase {
+    DocumentStarted,
+    ChunksEmbedded,
+    DocumentFinished,
+    DocumentFailed,
+    Completed,
+}
+
+impl RagIngestPhase {
+    /// 只有分块进度会被节流，其余事件总是发送
+    fn is_throttled(self) -> bool {
+        self == Self::ChunksEmbedded
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RagIngestProgress {
+    pub phase: RagIngestPhase,
+    /// 当前文档序号（从 1 开始）与文档总数
+    pub document_index: usize,
+    pub document_count: usize,
+    pub document_name: String,
+    /// 当前文档已嵌入与总分块数
+    pub chunks_embedded: usize,
+    pub total_chunks: usize,
+    pub elapsed_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 导入进度发射器
+///
+/// 与同步进度发射器相同的节流方式：分块进度最多每 100ms 发送一次，
+/// 文档开始/结束、错误与完成事件立即发送。不带 AppHandle 时不发送任何事件。
+/// 克隆的发射器共享节流状态，可交给嵌入进度回调使用。
+#[derive(Clone)]
+pub struct RagIngestProgressEmitter {
+    app: Option<AppHandle>,
+    started: Instant,
+    last_emit: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RagIngestProgressEmitter {
+    pub fn new(app: AppHandle) -> Self {
+        Self {
+            app: Some(app),
+            started: Instant::now(),
+            last_emit: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// 不推送事件，供同步命令使用
+    pub fn noop() -> Self {
+        Self {
+            app: None,
+            started: Instant::now(),
+            last_emit: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    pub fn emit(&self, mut progress: RagIngestProgress) {
+        let Some(app) = &self.app else {
+            return;
+        };
+        let now = Instant::now();
+        {
+            let mut last_emit = self
+                .last_emit
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if !should_emit(progress.phase, *last_emit, now) {
+                return;
+            }
+            *last_emit = Some(now);
+        }
+        progress.elapsed_ms = self.elapsed_ms();
+        if let Err(e) = app.emit(RAG_INGEST_PROGRESS_EVENT, &progress) {
+            log::warn!("[RagIngest] 推送导入进度失败: {}", e);
+        }
+    }
+}
+
+fn should_emit(phase: RagIngestPhase, last_emit: Option<Instant>, now: Instant) -> bool {
+    if !phase.is_throttled() {
+        return true;
+    }
+    match last_emit {
+        None => true,
+        Some(last) => now.duration_since(last) >= THROTTLE_INTERVAL,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RagIngestStatus {
+    /// 已写入 VFS 并完成索引
+    Ready,
+    /// 解析、写入或嵌入失败
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RagIngestDocumentResult {
+    /// VFS 资源ID；解析失败时文档不会落库，没有ID
+    pub resource_id: Option<String>,
+    pub file_path: String,
+    pub file_name: String,
+    pub status: RagIngestStatus,
+    pub embedded_chunks: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RagIngestReport {
+    /// 目标文件夹；为空表示知识库根目录
+    pub folder_id: Option<String>,
+    pub documents: Vec<RagIngestDocumentResult>,
+    pub embedded_chunks: usize,
+    pub failed_documents: usize,
+    pub elapsed_ms: u64,
+}
+
+impl RagIngestReport {
+    fn record(&mut self, result: RagIngestDocumentResult) {
+        self.embedded_chunks += result.embedded_chunks;
+        if result.status == RagIngestStatus::Failed {
+            self.failed_documents += 1;
+        }
+        self.documents.push(result);
+    }
+}
+
+fn display_name(file_path: &str) -> String {
+    Path::new(file_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file_path.to_string())
+}
+
+/// 当前文档的导入进度
+#[derive(Clone)]
+struct DocumentProgress {
+    emitter: RagIngestProgressEmitter,
+    document_index: usize,
+    document_count: usize,
+    document_name: String,
+    chunks_embedded: usize,
+    total_chunks: usize,
+}
+
+impl DocumentProgress {
+    fn emit(&self, phase: RagIngestPhase, error: Option<String>) {
+        self.emitter.emit(RagIngestProgress {
+            phase,
+            document_index: self.document_index,
+            document_count: self.document_count,
+            document_name: self.document_name.clone(),
+            chunks_embedded: self.chunks_embedded,
+            total_chunks: self.total_chunks,
+            elapsed_ms: 0,
+            error,
+        });
+    }
+
+    /// 嵌入流水线每完成一批分块回调一次，推送已嵌入数与总分块数
+    fn embedding_callback(&self) -> EmbeddingProgressCallback {
+        let progress = self.clone();
+        Box::new(move |embedded, total| {
+            let mut progress = progress.clone();
+            progress.chunks_embedded = embedded;
+            progress.total_chunks = total;
+            progress.emit(RagIngestPhase::ChunksEmbedded, None);
+        })
+    }
+}
+
+/// 在阻塞线程池中读取并解析文档，解析失败时返回错误说明
+async fn read_document(file_path: &str) -> Result<std::result::Result<(Vec<u8>, String), String>> {
+    let path = file_path.to_string();
+    tokio::task::spawn_blocking(move || {
+        let bytes = std::fs::read(&path).map_err(|e| format!("读取文件失败: {}", e))?;
+        let text = DocumentParser::new()
+            .extract_text_from_bytes(&path, bytes.clone())
+            .map_err(|e| e.to_string())?;
+        Ok((bytes, text))
+    })
+    .await
+    .map_err(|e| AppError::unknown(format!("文档解析任务失败: {}", e)))
+}
+
+/// 将原始文件与解析出的文本写入 VFS，返回文件资源ID（内容相同的文件复用已有资源）
+fn store_document(
+    vfs_db: &VfsDatabase,
+    folder_id: Option<&str>,
+    file_path: &str,
+    file_name: &str,
+    bytes: &[u8],
+    text: &str,
+) -> Result<String> {
+    let vfs_err = |e: crate::vfs::VfsError| AppError::database(e.to_string());
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let conn = vfs_db.get_conn_safe().map_err(vfs_err)?;
+    let blob = VfsBlobRepo::store_blob_with_conn(
+        &conn,
+        vfs_db.blobs_dir(),
+        bytes,
+        None,
+        extension.as_deref(),
+    )
+    .map_err(vfs_err)?;
+    let file = VfsFileRepo::create_file_with_doc_data_in_folder(
+        &conn,
+        &blob.hash,
+        file_name,
+        bytes.len() as i64,
+        "document",
+        None,
+        Some(&blob.hash),
+        Some(file_path),
+        folder_id,
+        None,
+        Some(text),
+        None,
+    )
+    .map_err(vfs_err)?;
+    file.resource_id
+        .ok_or_else(|| AppError::database(format!("文件 {} 缺少资源ID", file.id)))
+}
+
+/// 导入单个文档；失败只记录在结果中，不中断整批导入
+async fn ingest_document(
+    vfs_db: &VfsDatabase,
+    indexer: &VfsFullIndexingService,
+    folder_id: Option<&str>,
+    file_path: &str,
+    progress: &mut DocumentProgress,
+) -> Result<RagIngestDocumentResult> {
+    let mut result = RagIngestDocumentResult {
+        resource_id: None,
+        file_path: file_path.to_string(),
+        file_name: progress.document_name.clone(),
+        status: RagIngestStatus::Failed,
+        embedded_chunks: 0,
+        error: None,
+    };
+    progress.emit(RagIngestPhase::DocumentStarted, None);
+
+    let (bytes, text) = match read_document(file_path).await? {
+        Ok(document) => document,
+        Err(e) => {
+            let message = format!("文档解析失败: {}", e);
+            progress.emit(RagIngestPhase::DocumentFailed, Some(message.clone()));
+            result.error = Some(message);
+            return Ok(result);
+        }
+    };
+    if text.trim().is_empty() {
+        let message = "文档中没有可导入的文本".to_string();
+        progress.emit(RagIngestPhase::DocumentFailed, Some(message.clone()));
+        result.error = Some(message);
+        return Ok(result);
+    }
+
+    let resource_id = store_document(
+        vfs_db,
+        folder_id,
+        file_path,
+        &result.file_name,
+        &bytes,
+        &text,
+    )?;
+    result.resource_id = Some(resource_id.clone());
+
+    match indexer
+        .index_resource(&resource_id, folder_id, Some(progress.embedding_callback()))
+        .await
+    {
+        Ok((chunk_count, _)) => {
+            result.status = RagIngestStatus::Ready;
+            result.embedded_chunks = chunk_count;
+            progress.chunks_embedded = chunk_count;
+            progress.total_chunks = chunk_count;
+            progress.emit(RagIngestPhase::DocumentFinished, None);
+        }
+        Err(e) => {
+            // 索引服务已将资源标记为 failed
+            let message = format!("索引失败: {}", e);
+            progress.emit(RagIngestPhase::DocumentFailed, Some(message.clone()));
+            result.error = Some(message);
+        }
+    }
+    Ok(result)
+}
+
+/// 将文件逐个导入到知识库文件夹（`None` 为根目录），单个文档失败不影响其余文档
+pub async fn ingest_documents(
+    state: &AppState,
+    lance_store: Arc<VfsLanceStore>,
+    file_paths: Vec<String>,
+    folder_id: Option<String>,
+    emitter: &RagIngestProgressEmitter,
+) -> Result<RagIngestReport> {
+    if file_paths.is_empty() {
+        return Err(AppError::validation("没有要导入的文档"));
+    }
+    let vfs_db = state
+        .vfs_db
+        .clone()
+        .ok_or_else(|| AppError::configuration("VFS database not configured"))?;
+    if let Some(folder_id) = folder_id.as_deref() {
+        let exists = VfsFolderRepo::folder_exists(&vfs_db, folder_id)
+            .map_err(|e| AppError::database(format!("读取文件夹失败: {}", e)))?;
+        if !exists {
+            return Err(AppError::validation(format!(
+                "文件夹ID '{}' 不存在",
+                folder_id
+            )));
+        }
+    }
+    let embedding_config = state.llm_manager.require_embedding_model().await?;
+    crate::offline_mode::ensure_endpoint_allowed("嵌入模型", &embedding_config.base_url)?;
+    let indexer =
+        VfsFullIndexingService::new(vfs_db.clone(), state.llm_manager.clone(), lance_store)
+            .map_err(|e| AppError::database(format!("初始化索引服务失败: {}", e)))?;
+
+    let mut report = RagIngestReport {
+        folder_id: folder_id.clone(),
+        ..Default::default()
+    };
+    let document_count = file_paths.len();
+    for (index, file_path) in file_paths.iter().enumerate() {
+        let mut progress = DocumentProgress {
+            emitter: emitter.clone(),
+            document_index: index + 1,
+            document_count,
+            document_name: display_name(file_path),
+            chunks_embedded: 0,
+            total_chunks: 0,
+        };
+        let result = ingest_document(
+            &vfs_db,
+            &indexer,
+            folder_id.as_deref(),
+            file_path,
+            &mut progress,
+        )
+        .await?;
+        report.record(result);
+    }
+
+    report.elapsed_ms = emitter.elapsed_ms();
+    emitter.emit(RagIngestProgress {
+        phase: RagIngestPhase::Completed,
+        document_index: document_count,
+        document_count,
+        document_name: String::new(),
+        chunks_embedded: report.embedded_chunks,
+        total_chunks: report.embedded_chunks,
+        elapsed_ms: 0,
+        error: None,
+    });
+    log::info!(
+        "[RagIngest] 导入 {} 个文档到文件夹 {:?}：嵌入 {} 个分块，失败 {} 个文档，用时 {}ms",
+        document_count,
+        folder_id,
+        report.embedded_chunks,
+        report.failed_documents,
+        report.elapsed_ms
+    );
+    Ok(report)
+}
+
+/// 导入文档到知识库根目录
+#[tauri::command]
+pub async fn rag_add_documents(
+    file_paths: Vec<String>,
+    state: State<'_, AppState>,
+    lance_store: State<'_, Arc<VfsLanceStore>>,
+) -> Result<RagIngestReport> {
+    ingest_documents(
+        &state,
+        lance_store.inner().clone(),
+        file_paths,
+        None,
+        &RagIngestProgressEmitter::noop(),
+    )
+    .await
+}
+
+/// 导入文档到知识库中的指定文件夹
+#[tauri::command]
+pub async fn rag_add_documents_to_library(
+    file_paths: Vec<String>,
+    folder_id: String,
+    state: State<'_, AppState>,
+    lance_store: State<'_, Arc<VfsLanceStore>>,
+) -> Result<RagIngestReport> {
+    ingest_documents(
+        &state,
+        lance_store.inner().clone(),
+        file_paths,
+        Some(folder_id),
+        &RagIngestProgressEmitter::noop(),
+    )
+    .await
+}
+
+/// 导入文档并通过 `rag-ingest-progress` 事件推送进度；未指定文件夹时导入根目录
+#[tauri::command]
+pub async fn rag_add_documents_with_progress(
+    file_paths: Vec<String>,
+    folder_id: Option<String>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    lance_store: State<'_, Arc<VfsLanceStore>>,
+) -> Result<RagIngestReport> {
+    let folder_id = folder_id.filter(|id| !id.trim().is_empty());
+    let emitter = RagIngestProgressEmitter::new(app_handle);
+    ingest_documents(
+        &state,
+        lance_store.inner().clone(),
+        file_paths,
+        folder_id,
+        &emitter,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_chunk_progress_is_throttled() {
+        let now = Instant::now();
+        let recent = Some(now - Duration::from_millis(10));
+        let stale = Some(now - THROTTLE_INTERVAL);
+        assert!(should_emit(RagIngestPhase::ChunksEmbedded, None, now));
+        assert!(!should_emit(RagIngestPhase::ChunksEmbedded, recent, now));
+        assert!(should_emit(RagIngestPhase::ChunksEmbedded, stale, now));
+        for phase in [
+            RagIngestPhase::DocumentStarted,
+            RagIngestPhase::DocumentFailed,
+            RagIngestPhase::DocumentFinished,
+            RagIngestPhase::Completed,
+        ] {
+            assert!(should_emit(phase, recent, now));
+        }
+    }
+
+    #[test]
+    fn test_report_sums_document_results() {
+        let result = |status, embedded| RagIngestDocumentResult {
+            resource_id: Some("res_doc".to_string()),
+            file_path: "/tmp/a.pdf".to_string(),
+            file_name: "a.pdf".to_string(),
+            status,
+            embedded_chunks: embedded,
+            error: None,
+        };
+        let mut report = RagIngestReport::default();
+        report.record(result(RagIngestStatus::Ready, 5));
+        report.record(result(RagIngestStatus::Ready, 3));
+        report.record(result(RagIngestStatus::Failed, 0));
+        assert_eq!(report.documents.len(), 3);
+        assert_eq!((report.embedded_chunks, report.failed_documents), (8, 1));
+        assert_eq!(display_name("/data/讲义/第一章.pdf"), "第一章.pdf");
+    }
+}
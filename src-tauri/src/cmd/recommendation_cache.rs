@@ -0,0 +1,242 @@
+//! 错题推荐结果缓存
+//!
+//! `get_ai_recommendations` 每次都要重新读取候选错题并调用嵌入模型，界面面板反复打开时很慢。
+//! 结果按（错题、数量、权重）缓存在内存中，满足以下条件才命中：
+//!
+//! - 未超过设置项 `mistake.recommendation.cache_ttl_secs`（默认 120 秒，0 表示不缓存）；
+//! - 错题表的数据指纹（行数 + 最近修改时间）与写入缓存时相同，新增、修改、删除错题后自动失效。
+//!
+//! 合并错题等会改变推荐结果的操作会主动清空缓存；`clear_recommendation_cache` 可手动清空。
+//! 命中/未命中次数在系统诊断中给出。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+use super::mistake_recommendations::RecommendationResult;
+use crate::database::Database;
+use crate::models::AppError;
+
+pub const RECOMMENDATION_CACHE_TTL_KEY: &str = "mistake.recommendation.cache_ttl_secs";
+const DEFAULT_TTL_SECS: u64 = 120;
+/// 缓存条目上限，超出时淘汰最早写入的条目
+const MAX_ENTRIES: usize = 256;
+
+struct CacheEntry<T> {
+    stored_at: Instant,
+    fingerprint: String,
+    value: T,
+}
+
+pub struct ResultCache<T> {
+    entries: Mutex<HashMap<String, CacheEntry<T>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendationCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub ttl_secs: u64,
+}
+
+impl<T: Clone> ResultCache<T> {
+    fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CacheEntry<T>>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 读取未过期且指纹一致的条目；过期或指纹不符的条目同时移除
+    pub fn get(&self, key: &str, fingerprint: &str, ttl: Duration, now: Instant) -> Option<T> {
+        let mut entries = self.lock();
+        let fresh = entries.get(key).is_some_and(|entry| {
+            entry.fingerprint == fingerprint && now.saturating_duration_since(entry.stored_at) < ttl
+        });
+        let value = if fresh {
+            entries.get(key).map(|entry| entry.value.clone())
+        } else {
+            entries.remove(key);
+            None
+        };
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    pub fn insert(&self, key: String, fingerprint: String, value: T, now: Instant) {
+        let mut entries = self.lock();
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stored_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            CacheEntry {
+                stored_at: now,
+                fingerprint,
+                value,
+            },
+        );
+    }
+
+    /// 清空所有条目，返回清除的条目数
+    pub fn clear(&self) -> usize {
+        let mut entries = self.lock();
+        let count = entries.len();
+        entries.clear();
+        count
+    }
+
+    pub fn stats(&self, ttl: Duration) -> RecommendationCacheStats {
+        RecommendationCacheStats {
+            entries: self.lock().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ttl_secs: ttl.as_secs(),
+        }
+    }
+}
+
+pub static RECOMMENDATION_CACHE: LazyLock<ResultCache<RecommendationResult>> =
+    LazyLock::new(ResultCache::new);
+
+/// 缓存有效期；为 0 时不缓存
+pub fn load_cache_ttl(db: &Database) -> Duration {
+    let secs = db
+        .get_setting(RECOMMENDATION_CACHE_TTL_KEY)
+        .ok()
+        .flatten()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_TTL_SECS);
+    Duration::from_secs(secs)
+}
+
+/// 错题表的数据指纹：行数与最近修改时间
+pub fn data_fingerprint(conn: &Connection) -> rusqlite::Result<String> {
+    conn.query_row(
+        "SELECT COUNT(*), COALESCE(MAX(updated_at), '') FROM mistakes",
+        [],
+        |row| {
+            Ok(format!(
+                "{}|{}",
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?
+            ))
+        },
+    )
+}
+
+/// 错题数据发生了指纹无法反映的变化时调用
+pub fn invalidate_recommendation_cache() {
+    let cleared = RECOMMENDATION_CACHE.clear();
+    if cleared > 0 {
+        log::debug!("[Recommend] 已清空 {} 条推荐缓存", cleared);
+    }
+}
+
+/// 手动清空推荐缓存，返回清除的条目数
+#[tauri::command]
+pub async fn clear_recommendation_cache() -> Result<usize, AppError> {
+    Ok(RECOMMENDATION_CACHE.clear())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    #[test]
+    fn test_cache_expires_and_tracks_hits() {
+        let cache: ResultCache<u32> = ResultCache::new();
+        let ttl = Duration::from_secs(60);
+        let t0 = Instant::now();
+
+        assert_eq!(cache.get("m1", "fp1", ttl, t0), None);
+        cache.insert("m1".to_string(), "fp1".to_string(), 7, t0);
+        assert_eq!(
+            cache.get("m1", "fp1", ttl, t0 + Duration::from_secs(30)),
+            Some(7)
+        );
+        // 数据变化后指纹不同，条目失效
+        assert_eq!(
+            cache.get("m1", "fp2", ttl, t0 + Duration::from_secs(31)),
+            None
+        );
+        assert_eq!(cache.stats(ttl).entries, 0);
+
+        cache.insert("m1".to_string(), "fp2".to_string(), 8, t0);
+        assert_eq!(cache.get("m1", "fp2", ttl, t0 + ttl), None);
+        // TTL 为 0 时从不命中
+        cache.insert("m2".to_string(), "fp2".to_string(), 9, t0);
+        assert_eq!(cache.get("m2", "fp2", Duration::ZERO, t0), None);
+
+        let stats = cache.stats(ttl);
+        assert_eq!((stats.hits, stats.misses, stats.ttl_secs), (1, 4, 60));
+        cache.insert("m3".to_string(), "fp".to_string(), 1, t0);
+        assert_eq!(cache.clear(), 1);
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest_when_full() {
+        let cache: ResultCache<usize> = ResultCache::new();
+        let t0 = Instant::now();
+        for i in 0..MAX_ENTRIES {
+            cache.insert(
+                format!("k{i}"),
+                String::new(),
+                i,
+                t0 + Duration::from_millis(i as u64),
+            );
+        }
+        cache.insert(
+            "new".to_string(),
+            String::new(),
+            0,
+            t0 + Duration::from_secs(60),
+        );
+        let ttl = Duration::from_secs(600);
+        let now = t0 + Duration::from_secs(61);
+        assert_eq!(cache.get("k0", "", ttl, now), None);
+        assert_eq!(cache.get("k1", "", ttl, now), Some(1));
+        assert_eq!(cache.get("new", "", ttl, now), Some(0));
+    }
+
+    #[test]
+    fn test_fingerprint_changes_with_data() {
+        let conn = migrated_conn().unwrap();
+        insert_mistake(&conn, "a", &[]).unwrap();
+        let before = data_fingerprint(&conn).unwrap();
+        conn.execute(
+            "UPDATE mistakes SET updated_at = '2026-01-02T00:00:00Z'",
+            [],
+        )
+        .unwrap();
+        let updated = data_fingerprint(&conn).unwrap();
+        assert_ne!(before, updated);
+        insert_mistake(&conn, "b", &[]).unwrap();
+        assert_ne!(updated, data_fingerprint(&conn).unwrap());
+    }
+}
@@ -0,0 +1,57 @@
+//! 回顾分析管理命令：删除与归档
+//!
+//! 删除会一并移除分析的全部对话消息并返回实际数量，供前端确认；
+//! 归档仅隐藏已完成的分析，保留全部数据，随时可取消归档。正在生成的分析两者都拒绝。
+
+use crate::commands::AppState;
+use crate::database::ReviewAnalysisDeletion;
+use crate::models::AppError;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[tauri::command]
+pub async fn delete_review_analysis(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<ReviewAnalysisDeletion> {
+    let deletion = state
+        .database
+        .delete_review_analyses(std::slice::from_ref(&id))
+        .map_err(|e| AppError::database(format!("删除回顾分析失败: {}", e)))?;
+    if deletion.deleted_analyses == 0 {
+        return Err(AppError::not_found(format!("回顾分析不存在: {}", id)));
+    }
+    Ok(deletion)
+}
+
+/// 批量删除（单事务），不存在的 ID 跳过
+#[tauri::command]
+pub async fn batch_delete_review_analyses(
+    ids: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<ReviewAnalysisDeletion> {
+    if ids.is_empty() {
+        return Err(AppError::validation("回顾分析ID列表不能为空"));
+    }
+    state
+        .database
+        .delete_review_analyses(&ids)
+        .map_err(|e| AppError::database(format!("批量删除回顾分析失败: {}", e)))
+}
+
+#[tauri::command]
+pub async fn archive_review_analysis(id: String, state: State<'_, AppState>) -> Result<()> {
+    state
+        .database
+        .archive_review_analysis(&id)
+        .map_err(|e| AppError::database(format!("归档回顾分析失败: {}", e)))
+}
+
+#[tauri::command]
+pub async fn unarchive_review_analysis(id: String, state: State<'_, AppState>) -> Result<()> {
+    state
+        .database
+        .unarchive_review_analysis(&id)
+        .map_err(|e| AppError::database(format!("取消归档回顾分析失败: {}", e)))
+}
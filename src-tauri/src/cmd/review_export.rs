@@ -0,0 +1,686 @@
+//! 回顾分析导出命令
+//!
+//! 将一次回顾分析（`review_analyses` + `review_chat_messages`）导出为
+//! Markdown / HTML，包含合并输入、全部对话轮次、来源错题列表（含教师笔记）与参考来源。
+//!
+//! - 图片：Base64 图片内联为 data URI；`image_paths` 中的本地图片读取后同样内联，
+//!   保证导出文件脱离应用也能完整查看
+//! - 公式：保留 `$...$` / `$$...$$` 原文；HTML 中引入 KaTeX auto-render 渲染
+
+use crate::commands::AppState;
+use crate::database::has_column;
+use crate::file_manager::FileManager;
+use crate::models::{AppError, RagSourceInfo};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+const KATEX_VERSION: &str = "0.16.9";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewExportFormat {
+    #[serde(alias = "md")]
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewAnalysisExport {
+    pub file_name: String,
+    pub mime_type: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone)]
+struct ReviewSourceMistake {
+    id: String,
+    subject: Option<String>,
+    user_question: Option<String>,
+    created_at: Option<String>,
+    /// 用户（教师）批注
+    user_notes: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct ReviewExportMessage {
+    role: String,
+    content: String,
+    timestamp: String,
+    thinking_content: Option<String>,
+    images: Vec<ExportImage>,
+    rag_sources: Vec<RagSourceInfo>,
+    memory_sources: Vec<RagSourceInfo>,
+    web_search_sources: Vec<RagSourceInfo>,
+}
+
+#[derive(Debug, Clone)]
+struct ExportImage {
+    /// data URI；读取失败时为 None，仅保留原始路径说明
+    data_uri: Option<String>,
+    label: String,
+}
+
+#[derive(Debug, Clone)]
+struct ReviewExportDocument {
+    id: String,
+    name: String,
+    created_at: String,
+    status: String,
+    tags: Vec<String>,
+    user_question: String,
+    consolidated_input: String,
+    mistakes: Vec<ReviewSourceMistake>,
+    messages: Vec<ReviewExportMessage>,
+}
+
+/// 导出回顾分析为 Markdown 或 HTML
+#[tauri::command]
+pub async fn export_review_analysis(
+    id: String,
+    format: ReviewExportFormat,
+    state: State<'_, AppState>,
+) -> Result<ReviewAnalysisExport> {
+    let doc = load_review_export_document(&state, &id)?;
+    let (content, ext, mime_type) = match format {
+        ReviewExportFormat::Markdown => (render_markdown(&doc), "md", "text/markdown"),
+        ReviewExportFormat::Html => (render_html(&doc), "html", "text/html"),
+    };
+    Ok(ReviewAnalysisExport {
+        file_name: format!("{}.{}", export_file_stem(&doc.name, &doc.id), ext),
+        mime_type: mime_type.to_string(),
+        content,
+    })
+}
+
+fn load_review_export_document(state: &AppState, id: &str) -> Result<ReviewExportDocument> {
+    let conn = state
+        .database
+        .get_conn_safe()
+        .map_err(|e| AppError::database(format!("获取数据库连接失败: {}", e)))?;
+
+    type AnalysisRow = (String, String, String, String, String, String, String);
+    let row: Option<AnalysisRow> = conn
+        .query_row(
+            "SELECT name, created_at, status, tags, mistake_ids, consolidated_input, user_question
+             FROM review_analyses WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|e| AppError::database(format!("查询回顾分析失败: {}", e)))?;
+    let Some((
+        name,
+        created_at,
+        status,
+        tags_json,
+        mistake_ids_json,
+        consolidated_input,
+        user_question,
+    )) = row
+    else {
+        return Err(AppError::not_found(format!("回顾分析不存在: {}", id)));
+    };
+
+    let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+    let mistake_ids: Vec<String> = serde_json::from_str(&mistake_ids_json).unwrap_or_default();
+
+    let mut mistakes = Vec::with_capacity(mistake_ids.len());
+    {
+        // 新版 mistakes 表已不含 subject 列，仅旧库可读到科目
+        let has_subject = has_column(&conn, "mistakes", "subject").unwrap_or(false);
+        let has_user_notes = has_column(&conn, "mistakes", "user_notes").unwrap_or(false);
+        let sql = format!(
+            "SELECT {}, user_question, created_at, {} FROM mistakes WHERE id = ?1",
+            if has_subject { "subject" } else { "NULL" },
+            if has_user_notes { "user_notes" } else { "NULL" }
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| AppError::database(format!("查询来源错题失败: {}", e)))?;
+        for mistake_id in &mistake_ids {
+            let found = stmt
+                .query_row(params![mistake_id], |row| {
+                    Ok((
+                        row.get::<_, Option<String>>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })
+                .optional()
+                .map_err(|e| AppError::database(format!("查询来源错题失败: {}", e)))?;
+            let (subject, user_question, created_at, user_notes) =
+                found.unwrap_or((None, None, None, None));
+            mistakes.push(ReviewSourceMistake {
+                id: mistake_id.clone(),
+                subject,
+                user_question,
+                created_at,
+                user_notes,
+            });
+        }
+    }
+
+    let mut messages = Vec::new();
+    {
+        // 旧库可能缺少后加的来源列，缺列时按空处理
+        let has_web_sources =
+            has_column(&conn, "review_chat_messages", "web_search_sources").unwrap_or(false);
+        let sql = format!(
+            "SELECT role, content, timestamp, thinking_content, rag_sources, memory_sources, {}, image_paths, image_base64
+             FROM review_chat_messages WHERE review_analysis_id = ?1 ORDER BY timestamp ASC, id ASC",
+            if has_web_sources { "web_search_sources" } else { "NULL" }
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| AppError::database(format!("查询回顾对话失败: {}", e)))?;
+        let mut rows = stmt
+            .query(params![id])
+            .map_err(|e| AppError::database(format!("查询回顾对话失败: {}", e)))?;
+        while let Some(row) = rows
+            .next()
+            .map_err(|e| AppError::database(format!("读取回顾对话失败: {}", e)))?
+        {
+            let get_opt = |idx: usize| -> Result<Option<String>> {
+                row.get::<_, Option<String>>(idx)
+                    .map_err(|e| AppError::database(format!("读取回顾对话失败: {}", e)))
+            };
+            let role: String = row
+                .get(0)
+                .map_err(|e| AppError::database(format!("读取回顾对话失败: {}", e)))?;
+            let content: String = row
+                .get(1)
+                .map_err(|e| AppError::database(format!("读取回顾对话失败: {}", e)))?;
+            let timestamp: String = row
+                .get(2)
+                .map_err(|e| AppError::database(format!("读取回顾对话失败: {}", e)))?;
+            let image_paths: Vec<String> = parse_json_list(get_opt(7)?);
+            let image_base64: Vec<String> = parse_json_list(get_opt(8)?);
+            messages.push(ReviewExportMessage {
+                role,
+                content,
+                timestamp,
+                thinking_content: get_opt(3)?.filter(|t| !t.trim().is_empty()),
+                images: collect_export_images(&state.file_manager, &image_paths, &image_base64),
+                rag_sources: parse_json_list(get_opt(4)?),
+                memory_sources: parse_json_list(get_opt(5)?),
+                web_search_sources: parse_json_list(get_opt(6)?),
+            });
+        }
+    }
+
+    Ok(ReviewExportDocument {
+        id: id.to_string(),
+        name,
+        created_at,
+        status,
+        tags,
+        user_question,
+        consolidated_input,
+        mistakes,
+        messages,
+    })
+}
+
+fn parse_json_list<T: serde::de::DeserializeOwned>(raw: Option<String>) -> Vec<T> {
+    raw.and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn collect_export_images(
+    file_manager: &FileManager,
+    image_paths: &[String],
+    image_base64: &[String],
+) -> Vec<ExportImage> {
+    let mut images = Vec::new();
+    for path in image_paths {
+        let data_uri = match file_manager.read_file_as_base64(path) {
+            Ok(b64) => {
+                let mime = FileManager::infer_mime_from_path(std::path::Path::new(path));
+                Some(format!("data:{};base64,{}", mime, b64))
+            }
+            Err(e) => {
+                log::warn!("[ReviewExport] 读取图片失败 {}: {}", path, e);
+                None
+            }
+        };
+        images.push(ExportImage {
+            data_uri,
+            label: path.clone(),
+        });
+    }
+    for (i, b64) in image_base64.iter().enumerate() {
+        let data_uri = if b64.starts_with("data:") {
+            b64.clone()
+        } else {
+            format!("data:image/jpeg;base64,{}", b64)
+        };
+        images.push(ExportImage {
+            data_uri: Some(data_uri),
+            label: format!("image_{}", i + 1),
+        });
+    }
+    images
+}
+
+fn export_file_stem(name: &str, id: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() {
+        format!("review_{}", id)
+    } else {
+        format!("review_{}", cleaned)
+    }
+}
+
+fn role_label(role: &str) -> &str {
+    match role {
+        "user" => "用户",
+        "assistant" => "助手",
+        "system" => "系统",
+        "tool" => "工具",
+        other => other,
+    }
+}
+
+fn mistake_title(m: &ReviewSourceMistake) -> String {
+    let question = m
+        .user_question
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(|q| q.chars().take(80).collect::<String>())
+        .unwrap_or_else(|| "（错题已删除或无题干）".to_string());
+    match m.subject.as_deref().filter(|s| !s.is_empty()) {
+        Some(subject) => format!("[{}] {}", subject, question),
+        None => question,
+    }
+}
+
+/// 有批注的来源错题
+fn mistakes_with_notes(doc: &ReviewExportDocument) -> Vec<(&ReviewSourceMistake, &str)> {
+    doc.mistakes
+        .iter()
+        .filter_map(|m| {
+            m.user_notes
+                .as_deref()
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(|n| (m, n))
+        })
+        .collect()
+}
+
+/// 按 (类别, 来源) 去重后编号，正文中不插入引用标记，仅在文末集中列出
+fn collect_references(doc: &ReviewExportDocument) -> Vec<(&'static str, &RagSourceInfo)> {
+    let mut seen = std::collections::HashSet::new();
+    let mut refs = Vec::new();
+    for msg in &doc.messages {
+        let groups = [
+            ("知识库", &msg.rag_sources),
+            ("智能记忆", &msg.memory_sources),
+            ("网络搜索", &msg.web_search_sources),
+        ];
+        for (kind, sources) in groups {
+            for src in sources.iter() {
+                let key = (kind, src.document_id.clone(), src.chunk_index);
+                if seen.insert(key) {
+                    refs.push((kind, src));
+                }
+            }
+        }
+    }
+    refs
+}
+
+fn render_markdown(doc: &ReviewExportDocument) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("# {}\n\n", doc.name));
+    out.push_str(&format!("- 创建时间：{}\n", doc.created_at));
+    out.push_str(&format!("- 状态：{}\n", doc.status));
+    if !doc.tags.is_empty() {
+        out.push_str(&format!("- 标签：{}\n", doc.tags.join("、")));
+    }
+    out.push('\n');
+
+    out.push_str("## 来源错题\n\n");
+    if doc.mistakes.is_empty() {
+        out.push_str("（无）\n\n");
+    } else {
+        for m in &doc.mistakes {
+            let created = m.created_at.as_deref().unwrap_or("-");
+            out.push_str(&format!(
+                "- {}（`{}`，{}）\n",
+                mistake_title(m),
+                m.id,
+                created
+            ));
+        }
+        out.push('\n');
+    }
+
+    let noted = mistakes_with_notes(doc);
+    if !noted.is_empty() {
+        out.push_str("## 教师笔记\n\n");
+        for (m, notes) in noted {
+            out.push_str(&format!("### {}\n\n", mistake_title(m)));
+            out.push_str(notes);
+            out.push_str("\n\n");
+        }
+    }
+
+    if !doc.user_question.trim().is_empty() {
+        out.push_str("## 回顾问题\n\n");
+        out.push_str(doc.user_question.trim());
+        out.push_str("\n\n");
+    }
+
+    out.push_str("## 合并输入\n\n");
+    out.push_str(doc.consolidated_input.trim());
+    out.push_str("\n\n");
+
+    out.push_str("## 对话记录\n\n");
+    for msg in &doc.messages {
+        out.push_str(&format!(
+            "### {} · {}\n\n",
+            role_label(&msg.role),
+            msg.timestamp
+        ));
+        if let Some(thinking) = &msg.thinking_content {
+            out.push_str("<details><summary>思考过程</summary>\n\n");
+            out.push_str(thinking.trim());
+            out.push_str("\n\n</details>\n\n");
+        }
+        out.push_str(msg.content.trim());
+        out.push_str("\n\n");
+        for img in &msg.images {
+            match &img.data_uri {
+                Some(uri) => out.push_str(&format!("![{}]({})\n\n", img.label, uri)),
+                None => out.push_str(&format!("> 图片缺失：{}\n\n", img.label)),
+            }
+        }
+    }
+
+    let refs = collect_references(doc);
+    if !refs.is_empty() {
+        out.push_str("## 参考来源\n\n");
+        for (i, (kind, src)) in refs.iter().enumerate() {
+            out.push_str(&format!(
+                "{}. [{}] {}（相关度 {:.2}）\n",
+                i + 1,
+                kind,
+                src.file_name,
+                src.score
+            ));
+            let snippet = src.chunk_text.trim();
+            if !snippet.is_empty() {
+                for line in snippet.lines() {
+                    out.push_str(&format!("   > {}\n", line));
+                }
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn render_html(doc: &ReviewExportDocument) -> String {
+    let mut body = String::new();
+    body.push_str(&format!("<h1>{}</h1>\n", escape_html(&doc.name)));
+    body.push_str("<ul class=\"meta\">\n");
+    body.push_str(&format!(
+        "<li>创建时间：{}</li>\n<li>状态：{}</li>\n",
+        escape_html(&doc.created_at),
+        escape_html(&doc.status)
+    ));
+    if !doc.tags.is_empty() {
+        body.push_str(&format!(
+            "<li>标签：{}</li>\n",
+            escape_html(&doc.tags.join("、"))
+        ));
+    }
+    body.push_str("</ul>\n");
+
+    body.push_str("<h2>来源错题</h2>\n<ul>\n");
+    if doc.mistakes.is_empty() {
+        body.push_str("<li>（无）</li>\n");
+    }
+    for m in &doc.mistakes {
+        body.push_str(&format!(
+            "<li>{} <code>{}</code> {}</li>\n",
+            escape_html(&mistake_title(m)),
+            escape_html(&m.id),
+            escape_html(m.created_at.as_deref().unwrap_or("-"))
+        ));
+    }
+    body.push_str("</ul>\n");
+
+    let noted = mistakes_with_notes(doc);
+    if !noted.is_empty() {
+        body.push_str("<h2>教师笔记</h2>\n");
+        for (m, notes) in noted {
+            body.push_str(&format!(
+                "<h3>{}</h3>\n<div class=\"text\">{}</div>\n",
+                escape_html(&mistake_title(m)),
+                escape_html(notes)
+            ));
+        }
+    }
+
+    if !doc.user_question.trim().is_empty() {
+        body.push_str(&format!(
+            "<h2>回顾问题</h2>\n<div class=\"text\">{}</div>\n",
+            escape_html(doc.user_question.trim())
+        ));
+    }
+    body.push_str(&format!(
+        "<h2>合并输入</h2>\n<div class=\"text\">{}</div>\n",
+        escape_html(doc.consolidated_input.trim())
+    ));
+
+    body.push_str("<h2>对话记录</h2>\n");
+    for msg in &doc.messages {
+        body.push_str(&format!(
+            "<section class=\"msg {}\">\n<h3>{} · {}</h3>\n",
+            escape_html(&msg.role),
+            escape_html(role_label(&msg.role)),
+            escape_html(&msg.timestamp)
+        ));
+        if let Some(thinking) = &msg.thinking_content {
+            body.push_str(&format!(
+                "<details><summary>思考过程</summary><div class=\"text\">{}</div></details>\n",
+                escape_html(thinking.trim())
+            ));
+        }
+        body.push_str(&format!(
+            "<div class=\"text\">{}</div>\n",
+            escape_html(msg.content.trim())
+        ));
+        for img in &msg.images {
+            match &img.data_uri {
+                Some(uri) => body.push_str(&format!(
+                    "<img src=\"{}\" alt=\"{}\">\n",
+                    escape_html(uri),
+                    escape_html(&img.label)
+                )),
+                None => body.push_str(&format!(
+                    "<p class=\"missing\">图片缺失：{}</p>\n",
+                    escape_html(&img.label)
+                )),
+            }
+        }
+        body.push_str("</section>\n");
+    }
+
+    let refs = collect_references(doc);
+    if !refs.is_empty() {
+        body.push_str("<h2>参考来源</h2>\n<ol class=\"refs\">\n");
+        for (kind, src) in &refs {
+            body.push_str(&format!(
+                "<li><strong>[{}]</strong> {}（相关度 {:.2}）<blockquote>{}</blockquote></li>\n",
+                kind,
+                escape_html(&src.file_name),
+                src.score,
+                escape_html(src.chunk_text.trim())
+            ));
+        }
+        body.push_str("</ol>\n");
+    }
+
+    wrap_katex_html(
+        &doc.name,
+        r#".msg { border-left: 3px solid #ddd; padding-left: 1em; margin: 1.2em 0; }
+.msg.user { border-color: #4a90e2; }
+.msg.assistant { border-color: #50b37a; }
+blockquote { color: #555; border-left: 2px solid #eee; margin: 0.4em 0; padding-left: 0.8em; white-space: pre-wrap; }
+.missing { color: #c33; }"#,
+        &body,
+    )
+}
+
+/// 包装为独立 HTML 页面，引入 KaTeX auto-render 渲染 `$...$` / `$$...$$`
+pub(crate) fn wrap_katex_html(title: &str, extra_style: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/katex@{katex}/dist/katex.min.css">
+<script defer src="https://cdn.jsdelivr.net/npm/katex@{katex}/dist/katex.min.js"></script>
+<script defer src="https://cdn.jsdelivr.net/npm/katex@{katex}/dist/contrib/auto-render.min.js"
+  onload="renderMathInElement(document.body, {{delimiters: [{{left: '$$', right: '$$', display: true}}, {{left: '$', right: '$', display: false}}], throwOnError: false}});"></script>
+<style>
+body {{ font-family: -apple-system, "PingFang SC", "Microsoft YaHei", sans-serif; max-width: 880px; margin: 2em auto; padding: 0 1em; line-height: 1.6; color: #222; }}
+.text {{ white-space: pre-wrap; }}
+img {{ max-width: 100%; }}
+{extra_style}
+</style>
+</head>
+<body>
+{body}</body>
+</html>
+"#,
+        title = escape_html(title),
+        katex = KATEX_VERSION,
+        extra_style = extra_style,
+        body = body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(id: &str, name: &str) -> RagSourceInfo {
+        RagSourceInfo {
+            document_id: id.to_string(),
+            file_name: name.to_string(),
+            chunk_text: "片段内容".to_string(),
+            score: 0.9,
+            chunk_index: 0,
+        }
+    }
+
+    fn sample_doc() -> ReviewExportDocument {
+        ReviewExportDocument {
+            id: "ra-1".to_string(),
+            name: "函数专题<回顾>".to_string(),
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            status: "completed".to_string(),
+            tags: vec!["函数".to_string()],
+            user_question: "共性问题是什么？".to_string(),
+            consolidated_input: "题目一：$f(x)=x^2$".to_string(),
+            mistakes: vec![ReviewSourceMistake {
+                id: "m-1".to_string(),
+                subject: Some("数学".to_string()),
+                user_question: Some("求导".to_string()),
+                created_at: None,
+                user_notes: Some("讲评时强调<链式法则>".to_string()),
+            }],
+            messages: vec![
+                ReviewExportMessage {
+                    role: "assistant".to_string(),
+                    content: "答案是 $2x$".to_string(),
+                    timestamp: "t1".to_string(),
+                    thinking_content: None,
+                    images: vec![ExportImage {
+                        data_uri: Some("data:image/png;base64,AAA".to_string()),
+                        label: "image_1".to_string(),
+                    }],
+                    rag_sources: vec![source("d1", "教材.pdf")],
+                    memory_sources: vec![],
+                    web_search_sources: vec![source("https://example.com", "网页")],
+                },
+                ReviewExportMessage {
+                    role: "assistant".to_string(),
+                    content: "补充".to_string(),
+                    timestamp: "t2".to_string(),
+                    thinking_content: None,
+                    images: vec![],
+                    rag_sources: vec![source("d1", "教材.pdf")],
+                    memory_sources: vec![],
+                    web_search_sources: vec![],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_markdown_contains_sections_and_dedup_references() {
+        let md = render_markdown(&sample_doc());
+        assert!(md.contains("## 来源错题"));
+        assert!(md.contains("`m-1`"));
+        assert!(md.contains("$f(x)=x^2$"));
+        assert!(md.contains("![image_1](data:image/png;base64,AAA)"));
+        assert_eq!(md.matches("教材.pdf").count(), 1);
+        assert!(md.contains("[网络搜索] 网页"));
+        assert!(md.contains("## 教师笔记\n\n### [数学] 求导\n\n讲评时强调<链式法则>"));
+    }
+
+    #[test]
+    fn test_html_escapes_text_and_keeps_latex() {
+        let html = render_html(&sample_doc());
+        assert!(html.contains("函数专题&lt;回顾&gt;"));
+        assert!(html.contains("<h2>教师笔记</h2>"));
+        assert!(html.contains("讲评时强调&lt;链式法则&gt;"));
+        assert!(html.contains("$2x$"));
+        assert!(html.contains("renderMathInElement"));
+    }
+}
@@ -0,0 +1,596 @@
+//! 统计数据导出命令
+//!
+//! 按日期范围汇总错题数量（科目 / 标签 / 状态 / 时间桶 / 掌握度区间），并附带搜索日志与
+//! LLM 用量统计（对应数据存在时），导出为 CSV 或 JSON 供学校报表使用。
+//!
+//! 全部统计均由 SQL 聚合完成，不把错题逐条加载到内存。导出内容自带生成时间与
+//! 筛选参数：JSON 写在顶层字段，CSV 写在首行。
+
+use crate::commands::AppState;
+use crate::database::{has_column, table_exists};
+use crate::llm_usage::{LlmUsageDatabase, LlmUsageRepo};
+use crate::models::AppError;
+use crate::question_export_service::CsvExportService;
+use chrono::NaiveDate;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tauri::{Manager, State};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatisticsExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StatisticsTimeBucket {
+    Day,
+    Week,
+    #[default]
+    Month,
+}
+
+impl StatisticsTimeBucket {
+    fn sql_expr(self) -> &'static str {
+        match self {
+            Self::Day => "substr(created_at, 1, 10)",
+            Self::Week => "strftime('%Y-W%W', substr(created_at, 1, 10))",
+            Self::Month => "substr(created_at, 1, 7)",
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+}
+
+/// 统计范围（日期均为 `YYYY-MM-DD`，两端包含）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatisticsRange {
+    #[serde(default)]
+    pub start_date: Option<String>,
+    #[serde(default)]
+    pub end_date: Option<String>,
+    #[serde(default)]
+    pub bucket: StatisticsTimeBucket,
+}
+
+impl StatisticsRange {
+    fn parse_date(field: &str, value: Option<&str>) -> Result<Option<NaiveDate>> {
+        value
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                NaiveDate::parse_from_str(v, "%Y-%m-%d").map_err(|_| {
+                    AppError::validation(format!("{} 格式无效，应为 YYYY-MM-DD: {}", field, v))
+                })
+            })
+            .transpose()
+    }
+
+    /// 校验并返回 `[start, end_exclusive)` 的日期边界
+    fn bounds(&self) -> Result<(Option<NaiveDate>, Option<NaiveDate>)> {
+        let start = Self::parse_date("startDate", self.start_date.as_deref())?;
+        let end = Self::parse_date("endDate", self.end_date.as_deref())?;
+        if let (Some(s), Some(e)) = (start, end) {
+            if s > e {
+                return Err(AppError::validation("startDate 不能晚于 endDate"));
+            }
+        }
+        Ok((start, end.and_then(|e| e.succ_opt())))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatisticsCount {
+    pub key: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmUsageStatistic {
+    pub model: String,
+    pub request_count: u64,
+    pub total_tokens: u64,
+    pub estimated_cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatisticsReport {
+    pub generated_at: String,
+    pub range: StatisticsRange,
+    pub total_mistakes: i64,
+    /// 错题库不含科目字段时为 None
+    pub by_subject: Option<Vec<StatisticsCount>>,
+    pub by_tag: Vec<StatisticsCount>,
+    pub by_status: Vec<StatisticsCount>,
+    pub by_time_bucket: Vec<StatisticsCount>,
+    /// 掌握度区间分布（0-19 … 80-100）；错题库未迁移到掌握度字段时为 None
+    pub by_mastery: Option<Vec<StatisticsCount>>,
+    /// 平均掌握度（0-100）；无错题或无掌握度字段时为 None
+    pub average_mastery: Option<f64>,
+    /// 搜索日志不可用时为 None
+    pub search: Option<Vec<StatisticsCount>>,
+    /// LLM 用量库不可用时为 None
+    pub llm_usage: Option<Vec<LlmUsageStatistic>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatisticsExport {
+    pub file_name: String,
+    pub mime_type: String,
+    pub content: String,
+}
+
+/// 构造 `created_at` 范围条件
+fn range_clause(
+    start: Option<NaiveDate>,
+    end_exclusive: Option<NaiveDate>,
+    column: &str,
+) -> (String, Vec<Value>) {
+    let mut clauses = Vec::new();
+    let mut values = Vec::new();
+    if let Some(start) = start {
+        values.push(Value::from(start.format("%Y-%m-%d").to_string()));
+        clauses.push(format!("{} >= ?{}", column, values.len()));
+    }
+    if let Some(end) = end_exclusive {
+        values.push(Value::from(end.format("%Y-%m-%d").to_string()));
+        clauses.push(format!("{} < ?{}", column, values.len()));
+    }
+    (clauses.join(" AND "), values)
+}
+
+fn query_counts(
+    conn: &Connection,
+    sql: &str,
+    values: &[Value],
+) -> rusqlite::Result<Vec<StatisticsCount>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(values.iter()), |row| {
+        Ok(StatisticsCount {
+            key: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+            count: row.get(1)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// 掌握度区间分布与平均掌握度
+fn aggregate_mastery(
+    conn: &Connection,
+    where_sql: &str,
+    values: &[Value],
+) -> rusqlite::Result<(Vec<StatisticsCount>, Option<f64>)> {
+    let buckets = query_counts(
+        conn,
+        &format!(
+            "SELECT CASE WHEN mastery_level >= 80 THEN '80-100' \
+                         WHEN mastery_level >= 60 THEN '60-79' \
+                         WHEN mastery_level >= 40 THEN '40-59' \
+                         WHEN mastery_level >= 20 THEN '20-39' \
+                         ELSE '0-19' END AS k, COUNT(*) \
+             FROM mistakes {} GROUP BY k ORDER BY k",
+            where_sql
+        ),
+        values,
+    )?;
+    let average = conn.query_row(
+        &format!("SELECT AVG(mastery_level) FROM mistakes {}", where_sql),
+        rusqlite::params_from_iter(values.iter()),
+        |row| row.get(0),
+    )?;
+    Ok((buckets, average))
+}
+
+/// 汇总错题统计（不含搜索与 LLM 用量）
+fn aggregate_mistake_statistics(
+    conn: &Connection,
+    range: &StatisticsRange,
+) -> Result<StatisticsReport> {
+    let (start, end_exclusive) = range.bounds()?;
+    let db_err = |e: rusqlite::Error| AppError::database(format!("统计错题失败: {}", e));
+
+    let (range_sql, values) = range_clause(start, end_exclusive, "created_at");
+    let mut conditions: Vec<String> = Vec::new();
+    if !range_sql.is_empty() {
+        conditions.push(range_sql);
+    }
+    if has_column(conn, "mistakes", "deleted_at").map_err(db_err)? {
+        conditions.push("deleted_at IS NULL".to_string());
+    }
+    let where_sql = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    let total_mistakes: i64 = conn
+        .query_row(
+            &format!("SELECT COUNT(*) FROM mistakes {}", where_sql),
+            rusqlite::params_from_iter(values.iter()),
+            |row| row.get(0),
+        )
+        .map_err(db_err)?;
+
+    let group_by = |expr: &str| {
+        format!(
+            "SELECT {expr} AS k, COUNT(*) FROM mistakes {where_sql} GROUP BY k ORDER BY 2 DESC, k"
+        )
+    };
+
+    let by_subject = if has_column(conn, "mistakes", "subject").map_err(db_err)? {
+        Some(query_counts(conn, &group_by("subject"), &values).map_err(db_err)?)
+    } else {
+        None
+    };
+    let by_status = query_counts(conn, &group_by("status"), &values).map_err(db_err)?;
+    let by_time_bucket = query_counts(
+        conn,
+        &format!(
+            "SELECT {} AS k, COUNT(*) FROM mistakes {} GROUP BY k ORDER BY k",
+            range.bucket.sql_expr(),
+            where_sql
+        ),
+        &values,
+    )
+    .map_err(db_err)?;
+    let by_tag = query_counts(
+        conn,
+        &format!(
+            "SELECT je.value AS k, COUNT(*) FROM mistakes, \
+             json_each(CASE WHEN json_valid(mistakes.tags) THEN mistakes.tags ELSE '[]' END) je \
+             {} GROUP BY k ORDER BY 2 DESC, k",
+            where_sql
+        ),
+        &values,
+    )
+    .map_err(db_err)?;
+
+    let (by_mastery, average_mastery) = if has_column(conn, "mistakes", "mastery_level")
+        .map_err(db_err)?
+    {
+        let (buckets, average) = aggregate_mastery(conn, &where_sql, &values).map_err(db_err)?;
+        (Some(buckets), average)
+    } else {
+        (None, None)
+    };
+
+    Ok(StatisticsReport {
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        range: range.clone(),
+        total_mistakes,
+        by_subject,
+        by_tag,
+        by_status,
+        by_time_bucket,
+        by_mastery,
+        average_mastery,
+        search: None,
+        llm_usage: None,
+    })
+}
+
+/// 搜索日志按类型计数；表不存在或查询失败时返回 None
+fn aggregate_search_statistics(
+    conn: &Connection,
+    range: &StatisticsRange,
+) -> Option<Vec<StatisticsCount>> {
+    let (start, end_exclusive) = range.bounds().ok()?;
+    if !table_exists(conn, "search_logs").ok()? {
+        return None;
+    }
+    let (range_sql, values) = range_clause(start, end_exclusive, "created_at");
+    let where_sql = if range_sql.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", range_sql)
+    };
+    let sql = format!(
+        "SELECT search_type, COUNT(*) FROM search_logs {} GROUP BY search_type ORDER BY 2 DESC",
+        where_sql
+    );
+    query_counts(conn, &sql, &values)
+        .map_err(|e| log::warn!("[StatisticsExport] 搜索日志统计失败: {}", e))
+        .ok()
+}
+
+fn aggregate_llm_usage(
+    db: &LlmUsageDatabase,
+    range: &StatisticsRange,
+) -> Option<Vec<LlmUsageStatistic>> {
+    let start = range
+        .start_date
+        .clone()
+        .unwrap_or_else(|| "0000-01-01".to_string());
+    let end = range
+        .end_date
+        .clone()
+        .unwrap_or_else(|| "9999-12-31".to_string());
+    let conn = db.get_conn_safe().ok()?;
+    LlmUsageRepo::get_usage_by_model(&conn, &start, &end)
+        .map_err(|e| log::warn!("[StatisticsExport] LLM 用量统计失败: {}", e))
+        .ok()
+        .map(|models| {
+            models
+                .into_iter()
+                .map(|m| LlmUsageStatistic {
+                    model: m.model_id,
+                    request_count: m.request_count,
+                    total_tokens: m.total_tokens,
+                    estimated_cost_usd: m.estimated_cost_usd,
+                })
+                .collect()
+        })
+}
+
+fn render_csv(report: &StatisticsReport) -> String {
+    let cell = CsvExportService::escape_csv_cell;
+    let mut lines = vec![
+        [
+            "generated_at".to_string(),
+            cell(&report.generated_at),
+            "start_date".to_string(),
+            cell(report.range.start_date.as_deref().unwrap_or("")),
+            "end_date".to_string(),
+            cell(report.range.end_date.as_deref().unwrap_or("")),
+            "bucket".to_string(),
+            report.range.bucket.as_str().to_string(),
+        ]
+        .join(","),
+        String::new(),
+        "section,key,count,total_tokens,estimated_cost_usd".to_string(),
+        format!("total,mistakes,{},,", report.total_mistakes),
+    ];
+    if let Some(average) = report.average_mastery {
+        lines.push(format!("total,average_mastery,{:.1},,", average));
+    }
+
+    let mut push_counts = |section: &str, counts: &[StatisticsCount]| {
+        for c in counts {
+            lines.push(format!("{},{},{},,", section, cell(&c.key), c.count));
+        }
+    };
+    if let Some(subjects) = &report.by_subject {
+        push_counts("subject", subjects);
+    }
+    push_counts("tag", &report.by_tag);
+    push_counts("status", &report.by_status);
+    push_counts(report.range.bucket.as_str(), &report.by_time_bucket);
+    if let Some(mastery) = &report.by_mastery {
+        push_counts("mastery", mastery);
+    }
+    if let Some(search) = &report.search {
+        push_counts("search", search);
+    }
+    if let Some(usage) = &report.llm_usage {
+        for u in usage {
+            lines.push(format!(
+                "llm_usage,{},{},{},{}",
+                cell(&u.model),
+                u.request_count,
+                u.total_tokens,
+                u.estimated_cost_usd
+                    .map(|c| format!("{:.4}", c))
+                    .unwrap_or_default()
+            ));
+        }
+    }
+
+    let mut csv = lines.join("\n");
+    csv.push('\n');
+    csv
+}
+
+/// 导出统计数据为 CSV 或 JSON
+#[tauri::command]
+pub async fn export_statistics(
+    format: StatisticsExportFormat,
+    range: Option<StatisticsRange>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<StatisticsExport> {
+    let range = range.unwrap_or_default();
+    let mut report = {
+        let conn = state
+            .database
+            .get_conn_safe()
+            .map_err(|e| AppError::database(format!("获取数据库连接失败: {}", e)))?;
+        let mut report = aggregate_mistake_statistics(&conn, &range)?;
+        report.search = aggregate_search_statistics(&conn, &range);
+        report
+    };
+    report.llm_usage = app
+        .try_state::<Arc<LlmUsageDatabase>>()
+        .and_then(|db| aggregate_llm_usage(&db, &range));
+
+    let stamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let (content, ext, mime_type) = match format {
+        StatisticsExportFormat::Csv => (render_csv(&report), "csv", "text/csv"),
+        StatisticsExportFormat::Json => (
+            serde_json::to_string_pretty(&report)
+                .map_err(|e| AppError::internal(format!("序列化统计数据失败: {}", e)))?,
+            "json",
+            "application/json",
+        ),
+    };
+    Ok(StatisticsExport {
+        file_name: format!("statistics_{}.{}", stamp, ext),
+        mime_type: mime_type.to_string(),
+        content,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn setup() -> Connection {
+        let conn = migrated_conn().unwrap();
+        for (id, created_at, tags) in [
+            ("m1", "2026-01-05T08:00:00+00:00", r#"["函数","导数"]"#),
+            ("m2", "2026-01-20T08:00:00+00:00", r#"["函数"]"#),
+            ("m3", "2026-02-02T08:00:00+00:00", "not json"),
+            ("m4", "2026-02-03T08:00:00+00:00", r#"["函数"]"#),
+            ("m5", "2026-03-01T00:00:00+00:00", r#"["力学"]"#),
+        ] {
+            insert_mistake(&conn, id, &[("created_at", &created_at), ("tags", &tags)]).unwrap();
+        }
+        conn.execute_batch(
+            "UPDATE mistakes SET status = 'summary', subject = '数学';
+             UPDATE mistakes SET status = 'completed' WHERE id = 'm2';
+             UPDATE mistakes SET subject = '物理' WHERE id IN ('m3', 'm5');
+             UPDATE mistakes SET deleted_at = '2026-02-04' WHERE id = 'm4';",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_aggregates_respect_range_and_soft_delete() {
+        let conn = setup();
+        let range = StatisticsRange {
+            start_date: Some("2026-01-01".to_string()),
+            end_date: Some("2026-02-28".to_string()),
+            bucket: StatisticsTimeBucket::Month,
+        };
+        let report = aggregate_mistake_statistics(&conn, &range).unwrap();
+
+        assert_eq!(report.total_mistakes, 3);
+        let subjects = report.by_subject.unwrap();
+        assert_eq!(
+            subjects[0],
+            StatisticsCount {
+                key: "数学".into(),
+                count: 2
+            }
+        );
+        assert_eq!(
+            report.by_tag,
+            vec![
+                StatisticsCount {
+                    key: "函数".into(),
+                    count: 2
+                },
+                StatisticsCount {
+                    key: "导数".into(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(
+            report.by_time_bucket,
+            vec![
+                StatisticsCount {
+                    key: "2026-01".into(),
+                    count: 2
+                },
+                StatisticsCount {
+                    key: "2026-02".into(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(report.by_status[0].key, "summary");
+        assert_eq!(
+            report.by_mastery.as_deref().unwrap(),
+            [StatisticsCount {
+                key: "0-19".into(),
+                count: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn test_mastery_distribution_and_average() {
+        let conn = setup();
+        conn.execute_batch(
+            "UPDATE mistakes SET mastery_level = 90 WHERE id = 'm1';
+             UPDATE mistakes SET mastery_level = 45 WHERE id = 'm2';",
+        )
+        .unwrap();
+        let report = aggregate_mistake_statistics(&conn, &StatisticsRange::default()).unwrap();
+        assert_eq!(
+            report.by_mastery.as_deref().unwrap(),
+            [
+                StatisticsCount {
+                    key: "0-19".into(),
+                    count: 2
+                },
+                StatisticsCount {
+                    key: "40-59".into(),
+                    count: 1
+                },
+                StatisticsCount {
+                    key: "80-100".into(),
+                    count: 1
+                },
+            ]
+        );
+        // m4 已删除，不计入平均值
+        assert_eq!(report.average_mastery, Some(33.75));
+        let csv = render_csv(&report);
+        assert!(csv.contains("mastery,80-100,1,,"));
+        assert!(csv.contains("total,average_mastery,33.8,,"));
+    }
+
+    #[test]
+    fn test_invalid_range_is_rejected() {
+        let conn = setup();
+        let reversed = StatisticsRange {
+            start_date: Some("2026-03-01".to_string()),
+            end_date: Some("2026-01-01".to_string()),
+            ..Default::default()
+        };
+        assert!(aggregate_mistake_statistics(&conn, &reversed).is_err());
+        let malformed = StatisticsRange {
+            start_date: Some("2026/01/01".to_string()),
+            ..Default::default()
+        };
+        assert!(aggregate_mistake_statistics(&conn, &malformed).is_err());
+    }
+
+    #[test]
+    fn test_csv_starts_with_self_describing_header() {
+        let conn = setup();
+        let range = StatisticsRange {
+            bucket: StatisticsTimeBucket::Day,
+            ..Default::default()
+        };
+        conn.execute(
+            "INSERT INTO search_logs (id, search_type, query, result_count, execution_time_ms, created_at) \
+             VALUES ('s1', 'fulltext', '导数', 1, 5, '2026-01-05 08:00:00')",
+            [],
+        )
+        .unwrap();
+        let mut report = aggregate_mistake_statistics(&conn, &range).unwrap();
+        report.search = aggregate_search_statistics(&conn, &range);
+        assert_eq!(report.search.as_ref().map(Vec::len), Some(1));
+
+        let csv = render_csv(&report);
+        let mut lines = csv.lines();
+        let header = lines.next().unwrap();
+        assert!(header.starts_with("generated_at,"));
+        assert!(header.ends_with(",start_date,,end_date,,bucket,day"));
+        assert!(csv.contains("total,mistakes,4,,"));
+        assert!(csv.contains("tag,力学,1,,"));
+        assert!(csv.contains("day,2026-03-01,1,,"));
+        assert!(csv.contains("search,fulltext,1,,"));
+    }
+}
@@ -0,0 +1,394 @@
+//! 错题学习指南导出命令
+//!
+//! 将选中的错题整理为一份可打印的学习指南：每题包含题目、简要解析（取自 `mistake_summary`，
+//! 缺失时可选择即时生成）与关键标签。
+//!
+//! - 练习卷模式：题目下只留作答空白，解析统一放到文末答案；
+//! - 文末答案：按题号列出全部解析，练习卷模式下默认开启；
+//! - 输出 Markdown 或 HTML。HTML 复用回顾分析导出的 KaTeX 页面并附带打印样式，
+//!   在前端通过打印对话框另存为 PDF；公式保留 `$...$` / `$$...$$` 原文。
+
+use super::review_export::{escape_html, wrap_katex_html};
+use crate::commands::AppState;
+use crate::database::has_column;
+use crate::mistake_auto_summary::{self, SummaryOutcome};
+use crate::models::AppError;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 单次导出的错题数上限
+const MAX_STUDY_GUIDE_MISTAKES: usize = 200;
+/// 每题展示的标签数上限
+const MAX_TAGS_PER_MISTAKE: usize = 5;
+/// 练习卷中每题的作答空行数
+const WORKSHEET_BLANK_LINES: usize = 4;
+const DEFAULT_TITLE: &str = "错题学习指南";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StudyGuideFormat {
+    #[serde(alias = "md")]
+    Markdown,
+    /// 可打印的 HTML，另存为 PDF 使用
+    #[default]
+    Html,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StudyGuideOptions {
+    pub format: StudyGuideFormat,
+    pub title: Option<String>,
+    /// 练习卷模式：隐藏解析，只留作答空白
+    pub worksheet: bool,
+    /// 文末附答案；未指定时练习卷模式下开启
+    pub answer_key: Option<bool>,
+    /// 没有总结的错题即时生成解析（会调用模型）
+    pub generate_missing_summaries: bool,
+}
+
+impl StudyGuideOptions {
+    fn include_answer_key(&self) -> bool {
+        self.answer_key.unwrap_or(self.worksheet)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StudyGuideExport {
+    pub file_name: String,
+    pub mime_type: String,
+    pub content: String,
+    pub mistake_count: usize,
+    /// 不存在或已删除而未收录的错题
+    pub skipped_mistake_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct StudyGuideItem {
+    subject: Option<String>,
+    question: String,
+    explanation: Option<String>,
+    tags: Vec<String>,
+}
+
+/// 将选中的错题编排为学习指南（按传入顺序编号）
+#[tauri::command]
+pub async fn generate_study_guide(
+    mistake_ids: Vec<String>,
+    options: Option<StudyGuideOptions>,
+    state: State<'_, AppState>,
+) -> Result<StudyGuideExport> {
+    let options = options.unwrap_or_default();
+    let mut mistake_ids = mistake_ids;
+    let mut seen = std::collections::HashSet::new();
+    mistake_ids.retain(|id| seen.insert(id.clone()));
+    if mistake_ids.is_empty() {
+        return Err(AppError::validation("错题ID列表不能为空"));
+    }
+    if mistake_ids.len() > MAX_STUDY_GUIDE_MISTAKES {
+        return Err(AppError::validation(format!(
+            "单次最多导出 {} 道错题",
+            MAX_STUDY_GUIDE_MISTAKES
+        )));
+    }
+
+    let mut items = Vec::with_capacity(mistake_ids.len());
+    let mut skipped_mistake_ids = Vec::new();
+    {
+        let conn = state
+            .database
+            .get_conn_safe()
+            .map_err(|e| AppError::database(format!("获取数据库连接失败: {}", e)))?;
+        for id in &mistake_ids {
+            match load_study_guide_item(&conn, id)
+                .map_err(|e| AppError::database(format!("读取错题失败: {}", e)))?
+            {
+                Some(item) => items.push((id.clone(), item)),
+                None => skipped_mistake_ids.push(id.clone()),
+            }
+        }
+    }
+    if items.is_empty() {
+        return Err(AppError::not_found("选中的错题均不存在或已删除"));
+    }
+
+    if options.generate_missing_summaries {
+        let settings = mistake_auto_summary::load_auto_summary_settings(&state.database);
+        let mut generated_ids = Vec::new();
+        for (id, item) in items
+            .iter_mut()
+            .filter(|(_, item)| item.explanation.is_none())
+        {
+            match mistake_auto_summary::summarize_mistake(
+                &state.database,
+                &state.llm_manager,
+                settings.summary_model_id.as_deref(),
+                id,
+                Duration::ZERO,
+                false,
+            )
+            .await
+            {
+                Ok(SummaryOutcome::Generated {
+                    mistake_summary, ..
+                }) => {
+                    item.explanation = non_empty(Some(mistake_summary));
+                    generated_ids.push(id.clone());
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("[StudyGuide] 生成错题 {} 的解析失败: {}", id, e),
+            }
+        }
+        super::mistake_kb_sync::spawn_sync_mistakes(
+            state.database.clone(),
+            state.vfs_db.clone(),
+            state.llm_manager.clone(),
+            generated_ids,
+        );
+    }
+
+    let items: Vec<StudyGuideItem> = items.into_iter().map(|(_, item)| item).collect();
+    let title = options
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .unwrap_or(DEFAULT_TITLE)
+        .to_string();
+    let (content, ext, mime_type) = match options.format {
+        StudyGuideFormat::Markdown => (
+            render_markdown(&title, &items, &options),
+            "md",
+            "text/markdown",
+        ),
+        StudyGuideFormat::Html => (render_html(&title, &items, &options), "html", "text/html"),
+    };
+    Ok(StudyGuideExport {
+        file_name: format!("{}.{}", file_stem(&title), ext),
+        mime_type: mime_type.to_string(),
+        content,
+        mistake_count: items.len(),
+        skipped_mistake_ids,
+    })
+}
+
+fn non_empty(text: Option<String>) -> Option<String> {
+    text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty())
+}
+
+/// 读取单道错题；不存在或已软删除时返回 None
+fn load_study_guide_item(conn: &Connection, id: &str) -> rusqlite::Result<Option<StudyGuideItem>> {
+    let subject_col = if has_column(conn, "mistakes", "subject")? {
+        "subject"
+    } else {
+        "NULL"
+    };
+    let summary_col = if has_column(conn, "mistakes", "mistake_summary")? {
+        "mistake_summary"
+    } else {
+        "NULL"
+    };
+    let deleted_filter = if has_column(conn, "mistakes", "deleted_at")? {
+        " AND deleted_at IS NULL"
+    } else {
+        ""
+    };
+    let sql = format!(
+        "SELECT {}, COALESCE(user_question, ''), COALESCE(ocr_text, ''), tags, {} \
+         FROM mistakes WHERE id = ?1{}",
+        subject_col, summary_col, deleted_filter
+    );
+    conn.query_row(&sql, params![id], |row| {
+        let question: String = row.get(1)?;
+        let ocr_text: String = row.get(2)?;
+        let tags: Option<String> = row.get(3)?;
+        Ok(StudyGuideItem {
+            subject: non_empty(row.get(0)?),
+            // 没有题干时退回 OCR 原文
+            question: if question.trim().is_empty() {
+                ocr_text.trim().to_string()
+            } else {
+                question.trim().to_string()
+            },
+            explanation: non_empty(row.get(4)?),
+            tags: tags
+                .and_then(|raw| serde_json::from_str::<Vec<String>>(&raw).ok())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .take(MAX_TAGS_PER_MISTAKE)
+                .collect(),
+        })
+    })
+    .optional()
+}
+
+fn file_stem(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    format!("study_guide_{}", cleaned.trim())
+}
+
+fn item_heading(index: usize, item: &StudyGuideItem) -> String {
+    match item.subject.as_deref() {
+        Some(subject) => format!("第 {} 题（{}）", index + 1, subject),
+        None => format!("第 {} 题", index + 1),
+    }
+}
+
+fn explanation_text(item: &StudyGuideItem) -> &str {
+    item.explanation.as_deref().unwrap_or("（暂无解析）")
+}
+
+fn render_markdown(title: &str, items: &[StudyGuideItem], options: &StudyGuideOptions) -> String {
+    let mut out = format!("# {}\n\n", title);
+    out.push_str(&format!("共 {} 题\n\n", items.len()));
+    for (i, item) in items.iter().enumerate() {
+        out.push_str(&format!("## {}\n\n", item_heading(i, item)));
+        if !item.tags.is_empty() {
+            out.push_str(&format!("标签：{}\n\n", item.tags.join("、")));
+        }
+        out.push_str(&item.question);
+        out.push_str("\n\n");
+        if options.worksheet {
+            out.push_str("**作答：**\n\n");
+            for _ in 0..WORKSHEET_BLANK_LINES {
+                out.push_str("\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\\_\n\n");
+            }
+        } else if !options.include_answer_key() {
+            out.push_str("**解析：**\n\n");
+            out.push_str(explanation_text(item));
+            out.push_str("\n\n");
+        }
+    }
+
+    if options.include_answer_key() {
+        out.push_str("---\n\n## 参考答案与解析\n\n");
+        for (i, item) in items.iter().enumerate() {
+            out.push_str(&format!("### {}\n\n", item_heading(i, item)));
+            out.push_str(explanation_text(item));
+            out.push_str("\n\n");
+        }
+    }
+    out
+}
+
+fn render_html(title: &str, items: &[StudyGuideItem], options: &StudyGuideOptions) -> String {
+    let mut body = format!("<h1>{}</h1>\n", escape_html(title));
+    body.push_str(&format!("<p class=\"meta\">共 {} 题</p>\n", items.len()));
+    for (i, item) in items.iter().enumerate() {
+        body.push_str(&format!(
+            "<section class=\"item\">\n<h2>{}</h2>\n",
+            escape_html(&item_heading(i, item))
+        ));
+        if !item.tags.is_empty() {
+            body.push_str("<p class=\"tags\">");
+            for tag in &item.tags {
+                body.push_str(&format!("<span>{}</span>", escape_html(tag)));
+            }
+            body.push_str("</p>\n");
+        }
+        body.push_str(&format!(
+            "<div class=\"text\">{}</div>\n",
+            escape_html(&item.question)
+        ));
+        if options.worksheet {
+            body.push_str("<div class=\"blank\">");
+            for _ in 0..WORKSHEET_BLANK_LINES {
+                body.push_str("<div class=\"line\"></div>");
+            }
+            body.push_str("</div>\n");
+        } else if !options.include_answer_key() {
+            body.push_str(&format!(
+                "<h3>解析</h3>\n<div class=\"text\">{}</div>\n",
+                escape_html(explanation_text(item))
+            ));
+        }
+        body.push_str("</section>\n");
+    }
+
+    if options.include_answer_key() {
+        body.push_str("<section class=\"answer-key\">\n<h2>参考答案与解析</h2>\n");
+        for (i, item) in items.iter().enumerate() {
+            body.push_str(&format!(
+                "<h3>{}</h3>\n<div class=\"text\">{}</div>\n",
+                escape_html(&item_heading(i, item)),
+                escape_html(explanation_text(item))
+            ));
+        }
+        body.push_str("</section>\n");
+    }
+
+    wrap_katex_html(
+        title,
+        r#".meta { color: #666; }
+.item { border-bottom: 1px solid #eee; padding-bottom: 1em; break-inside: avoid; }
+.tags span { display: inline-block; background: #f0f3f7; border-radius: 4px; padding: 0 0.5em; margin-right: 0.4em; font-size: 0.85em; color: #456; }
+.blank .line { border-bottom: 1px solid #999; height: 2em; }
+.answer-key { break-before: page; }
+@media print { body { max-width: none; margin: 0; } .tags span { border: 1px solid #ccc; } }"#,
+        &body,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items() -> Vec<StudyGuideItem> {
+        vec![
+            StudyGuideItem {
+                subject: Some("数学".to_string()),
+                question: "求 $f(x)=x^2$ 的导数".to_string(),
+                explanation: Some("答案 $2x$，注意<幂函数>求导法则".to_string()),
+                tags: vec!["导数".to_string()],
+            },
+            StudyGuideItem {
+                subject: None,
+                question: "第二题".to_string(),
+                explanation: None,
+                tags: vec![],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_worksheet_moves_explanations_to_answer_key() {
+        let options = StudyGuideOptions {
+            worksheet: true,
+            ..Default::default()
+        };
+        let md = render_markdown("练习", &items(), &options);
+        let key_start = md.find("## 参考答案与解析").expect("应包含答案部分");
+        assert!(!md[..key_start].contains("答案 $2x$"));
+        assert!(md[..key_start].contains("**作答：**"));
+        assert!(md[key_start..].contains("答案 $2x$"));
+        assert!(md[key_start..].contains("### 第 2 题\n\n（暂无解析）"));
+    }
+
+    #[test]
+    fn test_plain_guide_inlines_explanations_and_escapes_html() {
+        let md = render_markdown(DEFAULT_TITLE, &items(), &StudyGuideOptions::default());
+        assert!(md.contains("## 第 1 题（数学）\n\n标签：导数"));
+        assert!(md.contains("**解析：**\n\n答案 $2x$"));
+        assert!(!md.contains("参考答案与解析"));
+
+        let html = render_html(DEFAULT_TITLE, &items(), &StudyGuideOptions::default());
+        assert!(html.contains("&lt;幂函数&gt;"));
+        assert!(html.contains("$f(x)=x^2$"));
+        assert!(html.contains("renderMathInElement"));
+        assert!(!html.contains("answer-key\">"));
+    }
+}
@@ -0,0 +1,394 @@
+//! 学科配置导出/导入
+//!
+//! 学科配置由两部分组成：笔记库的学科专属 RAG 分块参数（`notes.rag.config.<学科>`）
+//! 与制卡时的学科默认模板。导出为带版本号的 JSON，可在另一台设备上按学科名导入。
+//! 自定义模板的 ID 在每台设备上各不相同，因此默认模板按模板名导出，导入时再解析为本地 ID。
+//!
+//! 导入只写学科专属配置，不会改动全局 RAG 默认值；每个学科单独校验并返回结果，
+//! 单条失败不影响其余条目。
+
+use crate::cmd::notes::{
+    validate_subject_rag_config, NotesSubjectRagConfig, NOTES_SUBJECT_RAG_CONFIG_PREFIX,
+};
+use crate::commands::AppState;
+use crate::models::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use tauri::State;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// v2 起默认模板按名称导出；v1 只有 `defaultTemplateId`，导入时仍按 ID 兼容
+pub const SUBJECT_CONFIG_EXPORT_VERSION: u32 = 2;
+
+/// 单个学科的可导出配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubjectConfigEntry {
+    pub subject: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag: Option<NotesSubjectRagConfig>,
+    /// 默认模板名（跨设备可移植的键）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_template_name: Option<String>,
+    /// 本地模板 ID：导出时不写出，仅用于读取 v1 导出文件
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_template_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubjectConfigBundle {
+    pub version: u32,
+    #[serde(default)]
+    pub exported_at: Option<String>,
+    pub subjects: Vec<SubjectConfigEntry>,
+}
+
+/// 导入时遇到同名学科的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubjectImportStrategy {
+    /// 用导入内容整体替换（导入中缺失的字段会被清除）
+    Overwrite,
+    /// 保留本地配置，跳过该学科
+    Keep,
+    /// 导入中存在的字段覆盖本地，缺失的字段保留本地值
+    Merge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubjectImportStatus {
+    Created,
+    Updated,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubjectImportResult {
+    pub subject: String,
+    pub status: SubjectImportStatus,
+    pub message: Option<String>,
+}
+
+/// 按策略计算最终写入的配置；返回 None 表示跳过
+fn resolve_entry(
+    existing: Option<&SubjectConfigEntry>,
+    incoming: &SubjectConfigEntry,
+    strategy: SubjectImportStrategy,
+) -> Option<SubjectConfigEntry> {
+    match (existing, strategy) {
+        (None, _) | (Some(_), SubjectImportStrategy::Overwrite) => Some(incoming.clone()),
+        (Some(_), SubjectImportStrategy::Keep) => None,
+        (Some(current), SubjectImportStrategy::Merge) => Some(SubjectConfigEntry {
+            subject: incoming.subject.clone(),
+            rag: incoming.rag.clone().or_else(|| current.rag.clone()),
+            default_template_name: incoming
+                .default_template_name
+                .clone()
+                .or_else(|| current.default_template_name.clone()),
+            default_template_id: incoming
+                .default_template_id
+                .clone()
+                .or_else(|| current.default_template_id.clone()),
+        }),
+    }
+}
+
+/// 读取本地全部学科配置（按学科名排序）
+fn collect_subject_configs(state: &AppState) -> Result<BTreeMap<String, SubjectConfigEntry>> {
+    let mut entries: BTreeMap<String, SubjectConfigEntry> = BTreeMap::new();
+    fn entry_for<'a>(
+        entries: &'a mut BTreeMap<String, SubjectConfigEntry>,
+        subject: &str,
+    ) -> &'a mut SubjectConfigEntry {
+        entries
+            .entry(subject.to_string())
+            .or_insert_with(|| SubjectConfigEntry {
+                subject: subject.to_string(),
+                rag: None,
+                default_template_name: None,
+                default_template_id: None,
+            })
+    }
+
+    let rag_rows = state
+        .notes_database
+        .get_settings_by_prefix(NOTES_SUBJECT_RAG_CONFIG_PREFIX)
+        .map_err(|e| AppError::database(e.to_string()))?;
+    for (key, value, _) in rag_rows {
+        let Some(subject) = key.strip_prefix(NOTES_SUBJECT_RAG_CONFIG_PREFIX) else {
+            continue;
+        };
+        match serde_json::from_str::<NotesSubjectRagConfig>(&value) {
+            Ok(cfg) => entry_for(&mut entries, subject).rag = Some(cfg),
+            Err(e) => log::warn!("跳过无法解析的学科 RAG 配置 {}: {}", key, e),
+        }
+    }
+
+    let templates = template_names(state)?;
+    for (subject, template_id) in state.database.get_subject_default_templates()? {
+        let entry = entry_for(&mut entries, &subject);
+        entry.default_template_name = templates
+            .iter()
+            .find(|(id, _)| *id == template_id)
+            .map(|(_, name)| name.clone());
+        entry.default_template_id = Some(template_id);
+    }
+    Ok(entries)
+}
+
+/// 本地模板的 (ID, 名称) 列表
+fn template_names(state: &AppState) -> Result<Vec<(String, String)>> {
+    Ok(state
+        .database
+        .get_all_custom_templates()?
+        .into_iter()
+        .map(|t| (t.id, t.name))
+        .collect())
+}
+
+/// 将导入条目中的默认模板解析为本地模板 ID
+///
+/// 优先按模板名匹配；只有 ID 的 v1 条目按 ID 匹配。
+fn resolve_template_id(
+    templates: &[(String, String)],
+    entry: &SubjectConfigEntry,
+) -> Result<Option<String>> {
+    if let Some(name) = entry.default_template_name.as_deref().map(str::trim) {
+        return templates
+            .iter()
+            .find(|(_, n)| n.trim() == name)
+            .map(|(id, _)| Some(id.clone()))
+            .ok_or_else(|| AppError::not_found(format!("模板不存在: {}", name)));
+    }
+    match &entry.default_template_id {
+        Some(id) if templates.iter().any(|(local, _)| local == id) => Ok(Some(id.clone())),
+        Some(id) => Err(AppError::not_found(format!("模板不存在: {}", id))),
+        None => Ok(None),
+    }
+}
+
+/// 校验导入条目，并把默认模板解析为本地 ID 写回 `default_template_id`
+fn validate_entry(templates: &[(String, String)], entry: &mut SubjectConfigEntry) -> Result<()> {
+    if entry.subject.trim().is_empty() {
+        return Err(AppError::validation("学科名不能为空"));
+    }
+    if entry.rag.is_none()
+        && entry.default_template_name.is_none()
+        && entry.default_template_id.is_none()
+    {
+        return Err(AppError::validation(
+            "配置为空：至少需要 rag 或 defaultTemplateName",
+        ));
+    }
+    if let Some(cfg) = &entry.rag {
+        validate_subject_rag_config(cfg)?;
+    }
+    entry.default_template_id = resolve_template_id(templates, entry)?;
+    Ok(())
+}
+
+fn write_entry(
+    state: &AppState,
+    existing: Option<&SubjectConfigEntry>,
+    entry: &SubjectConfigEntry,
+) -> Result<()> {
+    let key = format!("{}{}", NOTES_SUBJECT_RAG_CONFIG_PREFIX, entry.subject);
+    match &entry.rag {
+        Some(cfg) => {
+            let json = serde_json::to_string(cfg).map_err(|e| AppError::internal(e.to_string()))?;
+            state
+                .notes_database
+                .save_setting(&key, &json)
+                .map_err(|e| AppError::database(e.to_string()))?;
+        }
+        None if existing.is_some_and(|e| e.rag.is_some()) => {
+            state
+                .notes_database
+                .delete_setting(&key)
+                .map_err(|e| AppError::database(e.to_string()))?;
+        }
+        None => {}
+    }
+    if entry.default_template_id.is_some()
+        || existing.is_some_and(|e| e.default_template_id.is_some())
+    {
+        state
+            .database
+            .set_subject_default_template(&entry.subject, entry.default_template_id.as_deref())
+            .map_err(|e| AppError::database(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// 导出全部学科配置为 JSON
+#[tauri::command]
+pub async fn export_subject_configs(state: State<'_, AppState>) -> Result<String> {
+    let bundle = SubjectConfigBundle {
+        version: SUBJECT_CONFIG_EXPORT_VERSION,
+        exported_at: Some(chrono::Utc::now().to_rfc3339()),
+        subjects: collect_subject_configs(&state)?
+            .into_values()
+            .map(|entry| SubjectConfigEntry {
+                default_template_id: None,
+                ..entry
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&bundle)
+        .map_err(|e| AppError::internal(format!("序列化学科配置失败: {}", e)))
+}
+
+/// 导入学科配置，返回每个学科的处理结果
+#[tauri::command]
+pub async fn import_subject_configs(
+    json: String,
+    strategy: SubjectImportStrategy,
+    state: State<'_, AppState>,
+) -> Result<Vec<SubjectImportResult>> {
+    let bundle: SubjectConfigBundle = serde_json::from_str(&json)
+        .map_err(|e| AppError::validation(format!("学科配置格式无效: {}", e)))?;
+    if bundle.version > SUBJECT_CONFIG_EXPORT_VERSION {
+        return Err(AppError::validation(format!(
+            "不支持的学科配置版本 {}（当前支持 {}）",
+            bundle.version, SUBJECT_CONFIG_EXPORT_VERSION
+        )));
+    }
+
+    let existing = collect_subject_configs(&state)?;
+    let templates = template_names(&state)?;
+    let mut seen = HashSet::new();
+    let mut results = Vec::with_capacity(bundle.subjects.len());
+    for mut incoming in bundle.subjects {
+        incoming.subject = incoming.subject.trim().to_string();
+        let subject = incoming.subject.clone();
+        let failed = |message: String| SubjectImportResult {
+            subject: subject.clone(),
+            status: SubjectImportStatus::Failed,
+            message: Some(message),
+        };
+
+        if !seen.insert(subject.clone()) {
+            results.push(failed("导入内容中学科重复，已忽略后出现的条目".to_string()));
+            continue;
+        }
+        if let Err(e) = validate_entry(&templates, &mut incoming) {
+            results.push(failed(e.message));
+            continue;
+        }
+
+        let current = existing.get(&subject);
+        let Some(resolved) = resolve_entry(current, &incoming, strategy) else {
+            results.push(SubjectImportResult {
+                subject: subject.clone(),
+                status: SubjectImportStatus::Skipped,
+                message: Some("本地已存在，按 keep 策略保留".to_string()),
+            });
+            continue;
+        };
+        let result = match write_entry(&state, current, &resolved) {
+            Ok(()) => SubjectImportResult {
+                subject: subject.clone(),
+                status: if current.is_some() {
+                    SubjectImportStatus::Updated
+                } else {
+                    SubjectImportStatus::Created
+                },
+                message: None,
+            },
+            Err(e) => failed(e.message),
+        };
+        results.push(result);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rag(chunk_size: i32) -> NotesSubjectRagConfig {
+        NotesSubjectRagConfig {
+            chunk_size,
+            chunk_overlap: 50,
+            min_chunk_size: 20,
+            rerank_enabled: true,
+        }
+    }
+
+    fn entry(rag_cfg: Option<NotesSubjectRagConfig>, template: Option<&str>) -> SubjectConfigEntry {
+        SubjectConfigEntry {
+            subject: "数学".to_string(),
+            rag: rag_cfg,
+            default_template_name: template.map(str::to_string),
+            default_template_id: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve_entry_strategies() {
+        let local = entry(Some(rag(512)), Some("local-tpl"));
+        let incoming = entry(Some(rag(1024)), None);
+
+        assert_eq!(
+            resolve_entry(None, &incoming, SubjectImportStrategy::Keep),
+            Some(incoming.clone())
+        );
+        assert_eq!(
+            resolve_entry(Some(&local), &incoming, SubjectImportStrategy::Keep),
+            None
+        );
+        assert_eq!(
+            resolve_entry(Some(&local), &incoming, SubjectImportStrategy::Overwrite),
+            Some(incoming.clone())
+        );
+        let merged = resolve_entry(Some(&local), &incoming, SubjectImportStrategy::Merge).unwrap();
+        assert_eq!(merged.rag.unwrap().chunk_size, 1024);
+        assert_eq!(merged.default_template_name.as_deref(), Some("local-tpl"));
+    }
+
+    #[test]
+    fn test_bundle_round_trip_uses_camel_case() {
+        let bundle = SubjectConfigBundle {
+            version: SUBJECT_CONFIG_EXPORT_VERSION,
+            exported_at: None,
+            subjects: vec![entry(None, Some("数学模板"))],
+        };
+        let json = serde_json::to_value(&bundle).unwrap();
+        assert_eq!(json["subjects"][0]["defaultTemplateName"], "数学模板");
+        assert!(json["subjects"][0].get("defaultTemplateId").is_none());
+        assert!(json["subjects"][0].get("rag").is_none());
+
+        let parsed: SubjectConfigBundle = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.subjects[0], bundle.subjects[0]);
+    }
+
+    #[test]
+    fn test_template_resolved_by_name_with_v1_id_fallback() {
+        let templates = vec![
+            ("uuid-local-1".to_string(), "数学模板".to_string()),
+            ("uuid-local-2".to_string(), "英语模板".to_string()),
+        ];
+        let by_name = entry(None, Some("英语模板"));
+        assert_eq!(
+            resolve_template_id(&templates, &by_name)
+                .unwrap()
+                .as_deref(),
+            Some("uuid-local-2")
+        );
+        assert!(resolve_template_id(&templates, &entry(None, Some("物理模板"))).is_err());
+
+        let mut v1 = entry(None, None);
+        v1.default_template_id = Some("uuid-local-1".to_string());
+        assert_eq!(
+            resolve_template_id(&templates, &v1).unwrap().as_deref(),
+            Some("uuid-local-1")
+        );
+        v1.default_template_id = Some("uuid-remote".to_string());
+        assert!(resolve_template_id(&templates, &v1).is_err());
+    }
+}
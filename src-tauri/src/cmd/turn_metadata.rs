@@ -0,0 +1,194 @@
+//! 全库回合元数据重建
+//!
+//! 导入旧版本备份后，不少错题对话的 `turn_id` / `turn_seq` 缺失或不完整。追加消息时的回填只处理
+//! 当前错题，`rebuild_all_turn_metadata` 则遍历所有错题执行同样的配对逻辑，并补齐缺少的
+//! `turn_seq`。
+//!
+//! 按批处理，每批一个事务，批间释放数据库连接并短暂等待，避免大库长时间锁库。
+//! 进度通过 `turn-metadata-rebuild-progress` 事件推送。
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::commands::AppState;
+use crate::models::{AppError, TurnMetadataFix};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+const REBUILD_PROGRESS_EVENT: &str = "turn-metadata-rebuild-progress";
+const DEFAULT_BATCH_SIZE: usize = 50;
+const MAX_BATCH_SIZE: usize = 500;
+/// 批间等待，让出数据库连接给前台操作
+const BATCH_PAUSE: Duration = Duration::from_millis(20);
+
+static REBUILD_RUNNING: AtomicBool = AtomicBool::new(false);
+
+struct RebuildGuard;
+
+impl Drop for RebuildGuard {
+    fn drop(&mut self) {
+        REBUILD_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TurnMetadataRebuildReport {
+    /// 错题总数
+    pub total_mistakes: usize,
+    /// 已处理的错题数
+    pub processed_mistakes: usize,
+    /// 有修复或仍有孤儿消息的错题
+    pub mistakes: Vec<TurnMetadataFix>,
+    /// 修复的消息总数
+    pub total_fixed: usize,
+    /// 重建后仍无法配对的助手消息总数
+    pub total_orphaned: usize,
+}
+
+impl TurnMetadataRebuildReport {
+    fn record(&mut self, batch: Vec<TurnMetadataFix>) {
+        self.processed_mistakes += batch.len();
+        for fix in batch {
+            self.total_fixed += fix.fixed();
+            self.total_orphaned += fix.orphaned;
+            if fix.fixed() > 0 || fix.orphaned > 0 {
+                self.mistakes.push(fix);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TurnMetadataRebuildProgress {
+    total_mistakes: usize,
+    processed_mistakes: usize,
+    total_fixed: usize,
+    total_orphaned: usize,
+    finished: bool,
+}
+
+/// 遍历所有错题，补齐缺失的回合配对与 `turn_seq`
+///
+/// `batch_size` 为每个事务处理的错题数（默认 50，最大 500）。
+#[tauri::command]
+pub async fn rebuild_all_turn_metadata(
+    batch_size: Option<usize>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<TurnMetadataRebuildReport> {
+    if REBUILD_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(AppError::validation("已有回合元数据重建任务在进行中"));
+    }
+    let _guard = RebuildGuard;
+
+    let batch_size = batch_size
+        .unwrap_or(DEFAULT_BATCH_SIZE)
+        .clamp(1, MAX_BATCH_SIZE);
+    let db = state.database.clone();
+
+    let total_mistakes = {
+        let conn = db
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        conn.query_row("SELECT COUNT(*) FROM mistakes", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .map_err(|e| AppError::database(format!("统计错题数量失败: {}", e)))?
+    };
+    let mut report = TurnMetadataRebuildReport {
+        total_mistakes: total_mistakes.max(0) as usize,
+        ..Default::default()
+    };
+
+    let emit = |report: &TurnMetadataRebuildReport, finished| {
+        let _ = app_handle.emit(
+            REBUILD_PROGRESS_EVENT,
+            TurnMetadataRebuildProgress {
+                total_mistakes: report.total_mistakes,
+                processed_mistakes: report.processed_mistakes,
+                total_fixed: report.total_fixed,
+                total_orphaned: report.total_orphaned,
+                finished,
+            },
+        );
+    };
+    emit(&report, false);
+
+    let mut cursor: Option<String> = None;
+    loop {
+        let db = db.clone();
+        let after_id = cursor.clone();
+        let batch = tokio::task::spawn_blocking(move || {
+            db.rebuild_turn_metadata_batch(after_id.as_deref(), batch_size)
+        })
+        .await
+        .map_err(|e| AppError::internal(format!("回合元数据重建任务异常: {}", e)))?
+        .map_err(|e| AppError::database(format!("重建回合元数据失败: {}", e)))?;
+
+        let Some(last) = batch.last() else {
+            break;
+        };
+        cursor = Some(last.mistake_id.clone());
+        let batch_len = batch.len();
+        report.record(batch);
+        emit(&report, false);
+
+        if batch_len < batch_size {
+            break;
+        }
+        tokio::time::sleep(BATCH_PAUSE).await;
+    }
+
+    // 处理期间新增的错题会被计入，总数以实际处理为准
+    report.total_mistakes = report.total_mistakes.max(report.processed_mistakes);
+    tracing::info!(
+        "[TurnMetadata] 重建完成: 错题 {}，修复消息 {}，孤儿助手消息 {}",
+        report.processed_mistakes,
+        report.total_fixed,
+        report.total_orphaned
+    );
+    emit(&report, true);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_keeps_only_affected_mistakes() {
+        let mut report = TurnMetadataRebuildReport::default();
+        report.record(vec![
+            TurnMetadataFix {
+                mistake_id: "clean".to_string(),
+                ..Default::default()
+            },
+            TurnMetadataFix {
+                mistake_id: "fixed".to_string(),
+                users_assigned: 2,
+                assistants_paired: 1,
+                turn_seq_fixed: 1,
+                orphaned: 0,
+            },
+            TurnMetadataFix {
+                mistake_id: "orphan".to_string(),
+                orphaned: 3,
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(report.processed_mistakes, 3);
+        assert_eq!(report.total_fixed, 4);
+        assert_eq!(report.total_orphaned, 3);
+        let ids: Vec<_> = report
+            .mistakes
+            .iter()
+            .map(|f| f.mistake_id.as_str())
+            .collect();
+        assert_eq!(ids, ["fixed", "orphan"]);
+    }
+}
@@ -513,6 +513,9 @@ pub async fn save_setting(key: String, value: String, state: State<'_, AppState>
     // 使用 save_secret 自动判断是否需要安全存储
     db.save_secret(&key, &value)
         .map_err(|e| AppError::database(format!("保存设置失败: {}", e)))?;
+    crate::offline_mode::apply_setting_change(&key, Some(&value));
+    crate::command_metrics::apply_setting_change(&key, Some(&value));
+    crate::stream_events::apply_setting_change(&key, Some(&value));
     Ok(true)
 }
 
@@ -529,8 +532,13 @@ pub async fn get_setting(key: String, state: State<'_, AppState>) -> Result<Opti
 #[tauri::command]
 pub async fn delete_setting(key: String, state: State<'_, AppState>) -> Result<bool> {
     let db = &state.database;
-    db.delete_secret(&key)
-        .map_err(|e| AppError::database(format!("删除设置失败: {}", e)))
+    let deleted = db
+        .delete_secret(&key)
+        .map_err(|e| AppError::database(format!("删除设置失败: {}", e)))?;
+    crate::offline_mode::apply_setting_change(&key, None);
+    crate::command_metrics::apply_setting_change(&key, None);
+    crate::stream_events::apply_setting_change(&key, None);
+    Ok(deleted)
 }
 
 /// 按前缀查询设置列表（用于工具权限管理等）
@@ -551,6 +559,9 @@ pub async fn delete_settings_by_prefix(
     state: State<'_, AppState>,
 ) -> Result<usize> {
     let db = &state.database;
-    db.delete_settings_by_prefix(&prefix)
-        .map_err(|e| AppError::database(format!("按前缀批量删除设置失败: {}", e)))
+    let deleted = db
+        .delete_settings_by_prefix(&prefix)
+        .map_err(|e| AppError::database(format!("按前缀批量删除设置失败: {}", e)))?;
+    crate::offline_mode::load_offline_mode_setting(db);
+    Ok(deleted)
 }
@@ -0,0 +1,196 @@
+//! 命令耗时统计
+//!
+//! 用于排查界面卡顿：开启设置项 `diagnostics.command_metrics` 后，检索、列表等热点命令
+//! 记录每次调用的墙钟耗时。每个命令只保留最近 [`MAX_SAMPLES`] 个样本（环形缓冲），
+//! `get_command_metrics` 按命令给出调用次数与 p50 / p95。
+//!
+//! 统计只在内存中，关闭后不再记录；未开启时计时器不读取时钟，开销可忽略。
+//! 设置项在启动时加载，通过通用设置命令修改后立即生效。
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::database::Database;
+
+pub const COMMAND_METRICS_SETTING_KEY: &str = "diagnostics.command_metrics";
+/// 每个命令保留的样本数
+pub const MAX_SAMPLES: usize = 512;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Default)]
+struct CommandSamples {
+    /// 开启以来的调用总次数（含已被挤出缓冲的样本）
+    count: u64,
+    durations: VecDeque<Duration>,
+}
+
+impl CommandSamples {
+    fn record(&mut self, duration: Duration) {
+        self.count += 1;
+        if self.durations.len() == MAX_SAMPLES {
+            self.durations.pop_front();
+        }
+        self.durations.push_back(duration);
+    }
+}
+
+static SAMPLES: LazyLock<Mutex<HashMap<&'static str, CommandSamples>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandMetric {
+    pub command: String,
+    pub count: u64,
+    /// 参与分位数计算的样本数（最多 [`MAX_SAMPLES`]）
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 关闭时同时清空已有样本
+pub fn set_enabled(enabled: bool) {
+    let previous = ENABLED.swap(enabled, Ordering::Relaxed);
+    if previous && !enabled {
+        reset();
+    }
+}
+
+/// 启动时从数据库加载；读取失败时保持关闭
+pub fn load_command_metrics_setting(db: &Database) {
+    let value = db.get_setting(COMMAND_METRICS_SETTING_KEY).ok().flatten();
+    set_enabled(
+        value
+            .as_deref()
+            .is_some_and(crate::offline_mode::parse_setting),
+    );
+}
+
+/// 通用设置命令保存或删除设置项后调用，使修改立即生效
+pub fn apply_setting_change(key: &str, value: Option<&str>) {
+    if key == COMMAND_METRICS_SETTING_KEY {
+        set_enabled(value.is_some_and(crate::offline_mode::parse_setting));
+    }
+}
+
+/// 命令计时器，离开作用域时记录耗时（包括提前返回与出错的情况）
+#[must_use = "计时器离开作用域时才会记录耗时"]
+pub struct CommandTimer {
+    command: &'static str,
+    started: Option<Instant>,
+}
+
+impl Drop for CommandTimer {
+    fn drop(&mut self) {
+        if let Some(started) = self.started {
+            record(self.command, started.elapsed());
+        }
+    }
+}
+
+/// 在命令开头调用：`let _timer = command_metrics::start("dstu_search");`
+pub fn start(command: &'static str) -> CommandTimer {
+    CommandTimer {
+        command,
+        started: is_enabled().then(Instant::now),
+    }
+}
+
+fn record(command: &'static str, duration: Duration) {
+    let mut samples = SAMPLES.lock().unwrap_or_else(|e| e.into_inner());
+    samples.entry(command).or_default().record(duration);
+}
+
+/// 最近邻秩分位数；`sorted` 需已升序
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// 各命令的统计，按命令名排序
+pub fn snapshot() -> Vec<CommandMetric> {
+    let samples = SAMPLES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut metrics: Vec<CommandMetric> = samples
+        .iter()
+        .map(|(command, entry)| {
+            let mut sorted: Vec<Duration> = entry.durations.iter().copied().collect();
+            sorted.sort_unstable();
+            CommandMetric {
+                command: command.to_string(),
+                count: entry.count,
+                samples: sorted.len(),
+                p50_ms: as_ms(percentile(&sorted, 0.50)),
+                p95_ms: as_ms(percentile(&sorted, 0.95)),
+                max_ms: as_ms(sorted.last().copied().unwrap_or_default()),
+            }
+        })
+        .collect();
+    metrics.sort_by(|a, b| a.command.cmp(&b.command));
+    metrics
+}
+
+pub fn reset() {
+    SAMPLES.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let sorted: Vec<Duration> = (1..=20).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 0.50), Duration::from_millis(10));
+        assert_eq!(percentile(&sorted, 0.95), Duration::from_millis(19));
+        assert_eq!(percentile(&sorted[..1], 0.95), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 0.5), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_recent_samples_and_total_count() {
+        let mut samples = CommandSamples::default();
+        for ms in 0..(MAX_SAMPLES as u64 + 10) {
+            samples.record(Duration::from_millis(ms));
+        }
+        assert_eq!(samples.count, MAX_SAMPLES as u64 + 10);
+        assert_eq!(samples.durations.len(), MAX_SAMPLES);
+        assert_eq!(samples.durations.front(), Some(&Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_timer_records_only_when_enabled() {
+        {
+            let _timer = start("metrics_test_disabled");
+        }
+        set_enabled(true);
+        {
+            let _timer = start("metrics_test_enabled");
+        }
+        let metrics = snapshot();
+        set_enabled(false);
+        assert!(metrics.iter().all(|m| m.command != "metrics_test_disabled"));
+        let metric = metrics
+            .iter()
+            .find(|m| m.command == "metrics_test_enabled")
+            .unwrap();
+        assert_eq!((metric.count, metric.samples), (1, 1));
+        assert!(snapshot().is_empty());
+    }
+}
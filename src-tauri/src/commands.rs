@@ -10,7 +10,8 @@ use crate::llm_manager::{ApiConfig, ModelProfile, VendorConfig};
 use crate::mcp::McpConfig;
 use crate::models::{
     AnkiDocumentGenerationRequest,
-    AnkiDocumentGenerationResponse, AnkiGenerationOptions, AppError,
+    AnkiDocumentGenerationResponse, AnkiGenerationOptions, AppError, AppliedTemplate,
+    AppliedTemplateSource,
     CreateTemplateRequest, CustomAnkiTemplate, ExamSheetSessionDetail,
     ExamSheetSessionDetailRequest, ExamSheetSessionDetailResponse, ExamSheetSessionListRequest,
     ExamSheetSessionListResponse, ModelAssignments, PdfOcrRequest,
@@ -41,14 +42,48 @@ use uuid::Uuid;
 type Result<T> = std::result::Result<T, AppError>;
 
 // Re-export from split modules
+pub use crate::cmd::analysis_bundle::*;
 pub use crate::cmd::anki_cards::*;
 pub use crate::cmd::anki_connect::*;
+pub use crate::cmd::data_location::*;
+pub use crate::cmd::database_cleanup::*;
+pub use crate::cmd::diagnostics::*;
+pub use crate::cmd::embedding_backfill::*;
+pub use crate::cmd::embedding_export::*;
 pub use crate::cmd::enhanced_anki::*;
+pub use crate::cmd::global_search::*;
+pub use crate::cmd::logs::*;
 pub use crate::cmd::mcp::*;
+pub use crate::cmd::mistake_archive::*;
+pub use crate::cmd::mistake_branches::*;
+pub use crate::cmd::mistake_dedup::*;
+pub use crate::cmd::mistake_export::*;
+pub use crate::cmd::mistake_import::*;
+pub use crate::cmd::mistake_kb_sync::*;
+pub use crate::cmd::mistake_language::*;
+pub use crate::cmd::mistake_mastery::*;
+pub use crate::cmd::mistake_recommendations::*;
+pub use crate::cmd::mistake_relations::*;
+pub use crate::cmd::mistake_notes::*;
+pub use crate::cmd::mistake_pin::*;
+pub use crate::cmd::mistake_status::*;
+pub use crate::cmd::mistake_summary::*;
+pub use crate::cmd::mistake_tags::*;
+pub use crate::cmd::subject_configs::*;
+pub use crate::cmd::mistake_chat::*;
 pub use crate::cmd::notes::*;
 pub use crate::cmd::ocr::*;
+pub use crate::cmd::privacy::*;
+pub use crate::cmd::rag_documents::*;
+pub use crate::cmd::rag_ingest::*;
+pub use crate::cmd::recommendation_cache::*;
+pub use crate::cmd::review_analyses::*;
+pub use crate::cmd::review_export::*;
+pub use crate::cmd::statistics_export::*;
+pub use crate::cmd::study_guide::*;
 pub use crate::cmd::textbooks::*;
 pub use crate::cmd::translation::*;
+pub use crate::cmd::turn_metadata::*;
 pub use crate::cmd::web_search::*; // OCR 引擎配置命令
 
 // 教材库独立数据库
@@ -304,6 +339,25 @@ pub async fn estimate_tokens(request: EstimateTokensRequest) -> Result<serde_jso
     }))
 }
 
+/// 发送前预估 token 数量
+///
+/// `model_id` 可以是 API 配置 ID 或模型名；配置 ID 会先解析为其模型名再选择编码。
+#[tauri::command]
+pub async fn count_tokens(
+    text: String,
+    model_id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<crate::llm_manager::tokenizer::TokenCount> {
+    let model_name = state
+        .llm_manager
+        .resolve_tokenizer_model(model_id.as_deref())
+        .await;
+    Ok(crate::llm_manager::tokenizer::count_tokens(
+        &text,
+        model_name.as_deref(),
+    ))
+}
+
 /// 硬停止：取消指定流事件（例如 chat_stream_{id}）
 #[tauri::command]
 pub async fn cancel_stream(streamEventName: String, state: State<'_, AppState>) -> Result<bool> {
@@ -558,6 +612,76 @@ pub fn get_template_config(
 }
 
 
+/// 解析本次制卡实际使用的模板：显式指定 > 科目默认 > 全局默认。
+///
+/// 多模板模式（`template_ids` 非空）由模型自行选择，不做回退。解析到的模板必须仍然存在，
+/// 已删除时返回明确错误；缺省的字段列表、提取规则与生成提示词从模板补齐。
+pub fn resolve_generation_template(
+    database: &Database,
+    options: &mut AnkiGenerationOptions,
+    subject: Option<&str>,
+) -> Result<Option<AppliedTemplate>> {
+    if options.template_ids.as_ref().map_or(false, |ids| !ids.is_empty()) {
+        return Ok(None);
+    }
+
+    let explicit = options
+        .template_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string);
+    let subject_default = || -> Result<Option<String>> {
+        let Some(subject) = subject.map(str::trim).filter(|s| !s.is_empty()) else {
+            return Ok(None);
+        };
+        Ok(database.get_subject_default_templates()?.remove(subject))
+    };
+    let (template_id, source) = if let Some(id) = explicit {
+        (id, AppliedTemplateSource::Explicit)
+    } else if let Some(id) = subject_default()? {
+        (id, AppliedTemplateSource::SubjectDefault)
+    } else if let Some(id) = database
+        .get_default_template()?
+        .filter(|id| !id.trim().is_empty())
+    {
+        (id, AppliedTemplateSource::GlobalDefault)
+    } else {
+        return Ok(None);
+    };
+
+    let template = database
+        .get_custom_template_by_id(&template_id)?
+        .ok_or_else(|| {
+            let origin = match source {
+                AppliedTemplateSource::Explicit => "指定的模板",
+                AppliedTemplateSource::SubjectDefault => "科目默认模板",
+                AppliedTemplateSource::GlobalDefault => "默认模板",
+            };
+            AppError::validation(format!(
+                "{}不存在或已被删除: {}，请重新选择模板",
+                origin, template_id
+            ))
+        })?;
+
+    options.template_id = Some(template.id.clone());
+    if options.template_fields.is_none() {
+        options.template_fields = Some(template.fields.clone());
+    }
+    if options.field_extraction_rules.is_none() {
+        options.field_extraction_rules = Some(template.field_extraction_rules.clone());
+    }
+    if options.custom_anki_prompt.is_none() && !template.generation_prompt.trim().is_empty() {
+        options.custom_anki_prompt = Some(template.generation_prompt.clone());
+    }
+
+    Ok(Some(AppliedTemplate {
+        template_id: template.id,
+        template_name: template.name,
+        source,
+    }))
+}
+
 // PDF OCR 命令组
 #[tauri::command]
 pub async fn init_pdf_ocr_session(
@@ -1400,6 +1524,29 @@ pub async fn save_vendor_configs(
     state.llm_manager.save_vendor_configs(&configs).await
 }
 
+/// 保存供应商自定义请求头/查询参数引用的敏感值（配置中写作 `secure:<name>`）
+#[tauri::command]
+pub async fn save_llm_request_secret(
+    name: String,
+    value: String,
+    state: State<'_, AppState>,
+) -> Result<()> {
+    let key = crate::llm_manager::custom_request_secret_key(&name)?;
+    state
+        .database
+        .save_secret(&key, value.trim())
+        .map_err(|e| AppError::database(format!("保存敏感值失败: {}", e)))
+}
+
+#[tauri::command]
+pub async fn delete_llm_request_secret(name: String, state: State<'_, AppState>) -> Result<bool> {
+    let key = crate::llm_manager::custom_request_secret_key(&name)?;
+    state
+        .database
+        .delete_secret(&key)
+        .map_err(|e| AppError::database(format!("删除敏感值失败: {}", e)))
+}
+
 #[tauri::command]
 pub async fn get_model_profiles(state: State<'_, AppState>) -> Result<Vec<ModelProfile>> {
     state.llm_manager.get_model_profiles().await
@@ -1415,6 +1562,8 @@ pub async fn save_model_profiles(
 
 /// 测试 API 连接
 ///
+/// 只验证一次非流式请求；检测已保存模型配置的流式输出请用 `test_api_config_connection`。
+///
 /// 参数说明：
 /// - api_key: API 密钥（可以是 "***" 占位符）
 /// - api_base: API 基础 URL
@@ -1435,6 +1584,7 @@ pub async fn test_api_connection(
         "[API测试] 开始测试连接: base={}, model={:?}, vendor_id={:?}",
         api_base, model, vendor_id
     );
+    crate::offline_mode::ensure_endpoint_allowed("模型连接测试", &api_base)?;
 
     // 解析真实的 API 密钥
     // 如果 api_key 是占位符（*** 或空），尝试从安全存储获取真实密钥
@@ -1548,6 +1698,23 @@ pub async fn test_api_connection(
     }
 }
 
+/// 按已保存的模型配置测试连接
+///
+/// 使用与流式调用相同的适配器、地址与自定义请求选项。`check_streaming` 为真（默认）时，
+/// 非流式请求成功后再发一个短流式请求，要求在超时内收到至少两个数据块，
+/// 以便在正式分析前发现会破坏 SSE 的反向代理。
+#[tauri::command]
+pub async fn test_api_config_connection(
+    config_id: String,
+    check_streaming: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<crate::llm_manager::ApiConnectionTestResult> {
+    state
+        .llm_manager
+        .test_api_config_connection(&config_id, check_streaming.unwrap_or(true))
+        .await
+}
+
 // ============================================================================
 // Batch Operations Commands
 // ============================================================================
@@ -1577,7 +1744,11 @@ async fn cleanup_orphan_chat_embeddings(db: Arc<Database>) -> usize {
             }
         };
 
-        let mut stmt = match conn.prepare("SELECT id FROM chat_messages") {
+        // 回填的错题向量以 `mistake:{id}` 入库，同样视为有效
+        let mut stmt = match conn.prepare(
+            "SELECT CAST(id AS TEXT) FROM chat_messages \
+             UNION ALL SELECT 'mistake:' || id FROM mistakes",
+        ) {
             Ok(s) => s,
             Err(e) => {
                 error!("[CleanupEmbeddings] 准备查询失败: {}", e);
@@ -1585,7 +1756,7 @@ async fn cleanup_orphan_chat_embeddings(db: Arc<Database>) -> usize {
             }
         };
 
-        let rows = match stmt.query_map([], |row| row.get::<_, i64>(0)) {
+        let rows = match stmt.query_map([], |row| row.get::<_, String>(0)) {
             Ok(r) => r,
             Err(e) => {
                 error!("[CleanupEmbeddings] 查询失败: {}", e);
@@ -1596,7 +1767,7 @@ async fn cleanup_orphan_chat_embeddings(db: Arc<Database>) -> usize {
         let mut ids = HashSet::new();
         for row in rows {
             if let Ok(id) = row {
-                ids.insert(id.to_string());
+                ids.insert(id);
             }
         }
         ids
@@ -1764,22 +1935,48 @@ pub async fn generate_anki_cards_from_document(
         request.document_content.len()
     );
 
+    let mut options = request.options.clone().unwrap_or_default();
+    let applied_template = match resolve_generation_template(
+        &state.database,
+        &mut options,
+        request.subject.as_deref(),
+    ) {
+        Ok(applied) => applied,
+        Err(e) => {
+            error!("制卡模板解析失败: {}", e);
+            return Ok(AnkiDocumentGenerationResponse {
+                success: false,
+                cards: vec![],
+                error_message: Some(e.to_string()),
+                applied_template: None,
+            });
+        }
+    };
+    let subject = request
+        .subject
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .unwrap_or("通用");
+
     // 调用 LLM Manager 的 Anki 制卡功能
     match state
         .llm_manager
-        .generate_anki_cards_from_document(
-            &request.document_content,
-            "通用",
-            request.options.as_ref(),
-        )
+        .generate_anki_cards_from_document(&request.document_content, subject, Some(&options))
         .await
     {
-        Ok(cards) => {
+        Ok(mut cards) => {
             info!("Anki 卡片生成成功: {} 张卡片", cards.len());
+            if let Some(applied) = applied_template.as_ref() {
+                for card in cards.iter_mut().filter(|c| c.template_id.is_none()) {
+                    card.template_id = Some(applied.template_id.clone());
+                }
+            }
             Ok(AnkiDocumentGenerationResponse {
                 success: true,
                 cards,
                 error_message: None,
+                applied_template,
             })
         }
         Err(e) => {
@@ -1788,6 +1985,7 @@ pub async fn generate_anki_cards_from_document(
                 success: false,
                 cards: vec![],
                 error_message: Some(e.to_string()),
+                applied_template,
             })
         }
     }
@@ -1810,6 +2008,7 @@ pub async fn generate_anki_cards_from_document_file(
                 success: false,
                 cards: vec![],
                 error_message: Some(format!("文档解析失败: {}", e)),
+                applied_template: None,
             });
         }
     };
@@ -1828,6 +2027,7 @@ pub async fn generate_anki_cards_from_document_file(
                 success: true,
                 cards,
                 error_message: None,
+                applied_template: None,
             })
         }
         Err(e) => {
@@ -1836,6 +2036,7 @@ pub async fn generate_anki_cards_from_document_file(
                 success: false,
                 cards: vec![],
                 error_message: Some(e.to_string()),
+                applied_template: None,
             })
         }
     }
@@ -1860,6 +2061,7 @@ pub async fn generate_anki_cards_from_document_base64(
                 success: false,
                 cards: vec![],
                 error_message: Some(format!("文档解析失败: {}", e)),
+                applied_template: None,
             });
         }
     };
@@ -1878,6 +2080,7 @@ pub async fn generate_anki_cards_from_document_base64(
                 success: true,
                 cards,
                 error_message: None,
+                applied_template: None,
             })
         }
         Err(e) => {
@@ -1886,6 +2089,7 @@ pub async fn generate_anki_cards_from_document_base64(
                 success: false,
                 cards: vec![],
                 error_message: Some(e.to_string()),
+                applied_template: None,
             })
         }
     }
@@ -2972,6 +3176,7 @@ pub fn validate_template_request(request: &CreateTemplateRequest) -> Result<()>
     if request.generation_prompt.trim().is_empty() {
         return Err(AppError::validation("生成提示词不能为空".to_string()));
     }
+    crate::anki_prompt_placeholders::validate_prompt_placeholders(&request.generation_prompt)?;
 
     // 验证字段提取规则
     for field in &request.fields {
@@ -3014,6 +3219,32 @@ pub async fn set_default_template(
 pub async fn get_default_template_id(state: tauri::State<'_, AppState>) -> Result<Option<String>> {
     Ok(state.database.get_default_template()?)
 }
+
+/// 设置科目默认模板（`template_id` 为空时清除），制卡时优先于全局默认模板
+#[tauri::command]
+pub async fn set_subject_default_template(
+    subject: String,
+    template_id: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    if let Some(id) = template_id.as_deref().filter(|id| !id.trim().is_empty()) {
+        if state.database.get_custom_template_by_id(id)?.is_none() {
+            return Err(AppError::not_found(format!("模板不存在: {}", id)));
+        }
+    }
+    state
+        .database
+        .set_subject_default_template(&subject, template_id.as_deref())
+        .map_err(|e| AppError::validation(e.to_string()))
+}
+
+/// 获取科目默认模板映射（科目名 -> 模板ID）
+#[tauri::command]
+pub async fn get_subject_default_templates(
+    state: tauri::State<'_, AppState>,
+) -> Result<HashMap<String, String>> {
+    Ok(state.database.get_subject_default_templates()?)
+}
 // ============= 测试日志相关命令 =============
 
 /// 保存测试日志到文件
@@ -4493,6 +4724,7 @@ pub async fn qbank_search_questions(
     request: SearchQuestionsRequest,
     state: State<'_, AppState>,
 ) -> Result<QuestionSearchListResult> {
+    let _timer = crate::command_metrics::start("qbank_search_questions");
     let service = state
         .question_bank_service
         .as_ref()
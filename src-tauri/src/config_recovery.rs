@@ -40,6 +40,8 @@ pub fn create_default_api_configs() -> Vec<ApiConfig> {
             enable_thinking: None,
             supports_reasoning: false,
             headers: None,
+            query_params: None,
+            allow_reserved_header_override: false,
             top_p_override: None,
             frequency_penalty_override: None,
             presence_penalty_override: None,
@@ -49,6 +51,13 @@ pub fn create_default_api_configs() -> Vec<ApiConfig> {
             reasoning_split: None,
             effort: None,
             verbosity: None,
+            stream_coalesce: None,
+            thinking_delivery: None,
+            supports_streaming: true,
+            force_non_streaming: false,
+            first_token_timeout_ms: None,
+            idle_timeout_ms: None,
+            max_request_retries: None,
         },
         // Claude 3.5 Sonnet 配置
         ApiConfig {
@@ -81,6 +90,8 @@ pub fn create_default_api_configs() -> Vec<ApiConfig> {
             include_thoughts: false,
             supports_reasoning: false,
             headers: None,
+            query_params: None,
+            allow_reserved_header_override: false,
             top_p_override: None,
             frequency_penalty_override: None,
             presence_penalty_override: None,
@@ -90,6 +101,13 @@ pub fn create_default_api_configs() -> Vec<ApiConfig> {
             reasoning_split: None,
             effort: None,
             verbosity: None,
+            stream_coalesce: None,
+            thinking_delivery: None,
+            supports_streaming: true,
+            force_non_streaming: false,
+            first_token_timeout_ms: None,
+            idle_timeout_ms: None,
+            max_request_retries: None,
         },
     ]
 }
@@ -102,6 +120,7 @@ pub fn create_default_model_assignments() -> ModelAssignments {
         anki_card_model_config_id: None,
         qbank_ai_grading_model_config_id: None,
         embedding_model_config_id: None,
+        embedding_fallback_model_config_id: None,
         reranker_model_config_id: None,
         chat_title_model_config_id: None,
         exam_sheet_ocr_model_config_id: None,
@@ -110,6 +129,7 @@ pub fn create_default_model_assignments() -> ModelAssignments {
         vl_embedding_model_config_id: None,
         vl_reranker_model_config_id: None,
         memory_decision_model_config_id: None,
+        subject_model_overrides: Default::default(),
     }
 }
 
@@ -306,6 +306,18 @@ impl From<rusqlite::Error> for BackupError {
     }
 }
 
+/// 单个数据库的恢复 schema 计划（由恢复前预检生成）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreSchemaPlan {
+    pub database_id: String,
+    /// 备份文件中的 schema 版本
+    pub backup_version: u32,
+    /// 当前应用的最新 schema 版本
+    pub app_version: u32,
+    /// 恢复后是否需要执行前向迁移
+    pub needs_migration: bool,
+}
+
 /// 备份验证结果
 ///
 /// 包含数据库和资产文件的验证结果
@@ -1914,6 +1926,100 @@ impl BackupManager {
         Ok(())
     }
 
+    /// 恢复前的 schema 预检（基于备份中数据库文件的实际内容，而非仅信任清单）
+    ///
+    /// 逐个读取备份数据库的 `refinery_schema_history`（主库额外读取旧版 `schema_version`），
+    /// 与当前应用的迁移集合对比：
+    /// - 备份 schema 高于当前应用 → 拒绝恢复（`VersionIncompatible`）
+    /// - 备份 schema 低于当前应用 → 返回的计划中 `needs_migration = true`，
+    ///   调用方需在切换数据前对恢复副本执行前向迁移
+    #[cfg(feature = "data_governance")]
+    pub(crate) fn preflight_schema_check(
+        &self,
+        backup_subdir: &Path,
+        manifest: &BackupManifest,
+    ) -> Result<Vec<RestoreSchemaPlan>, BackupError> {
+        use crate::data_governance::migration::ALL_MIGRATION_SETS;
+
+        let mut plans = Vec::new();
+        for db_id in DatabaseId::all_ordered() {
+            let backup_path = self.get_backup_database_path(backup_subdir, &db_id);
+            if !backup_path.exists() {
+                continue;
+            }
+
+            let backup_version = self.get_schema_version(&backup_path)?;
+            let app_version = ALL_MIGRATION_SETS
+                .iter()
+                .find(|set| set.database_name == db_id.as_str())
+                .map(|set| set.latest_version() as u32)
+                .ok_or_else(|| {
+                    BackupError::VersionIncompatible(format!(
+                        "当前应用缺少数据库 {} 的迁移定义",
+                        db_id.as_str()
+                    ))
+                })?;
+
+            if let Some(&recorded) = manifest.schema_versions.get(db_id.as_str()) {
+                if recorded != backup_version {
+                    warn!(
+                        "[Backup] 清单记录的 schema 版本与备份文件不一致: db={}, manifest=v{}, actual=v{}（以实际文件为准）",
+                        db_id.as_str(),
+                        recorded,
+                        backup_version
+                    );
+                }
+            }
+
+            if backup_version > app_version {
+                return Err(BackupError::VersionIncompatible(format!(
+                    "备份中数据库 {} 的 schema 版本 (v{}) 高于当前应用 (v{})，该备份来自更新版本的应用。\
+                     请升级应用后再恢复此备份。",
+                    db_id.as_str(),
+                    backup_version,
+                    app_version
+                )));
+            }
+
+            if matches!(db_id, DatabaseId::Mistakes) {
+                let legacy = Self::get_legacy_schema_version(&backup_path)?;
+                let app_legacy = crate::database::CURRENT_DB_VERSION;
+                if legacy > app_legacy {
+                    return Err(BackupError::VersionIncompatible(format!(
+                        "备份中主数据库的旧版 schema_version (v{}) 高于当前应用 (v{})。请升级应用后再恢复此备份。",
+                        legacy, app_legacy
+                    )));
+                }
+            }
+
+            plans.push(RestoreSchemaPlan {
+                database_id: db_id.as_str().to_string(),
+                backup_version,
+                app_version,
+                needs_migration: backup_version < app_version,
+            });
+        }
+
+        info!("[Backup] 恢复前 schema 预检通过: {:?}", plans);
+        Ok(plans)
+    }
+
+    /// 读取主数据库旧迁移系统的 `schema_version`（表不存在时视为 0）
+    fn get_legacy_schema_version(db_path: &Path) -> Result<u32, BackupError> {
+        let conn = Connection::open_with_flags(db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let table_exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type='table' AND name='schema_version')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !table_exists {
+            return Ok(0);
+        }
+        let version: Option<i64> =
+            conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+        Ok(version.unwrap_or(0).max(0) as u32)
+    }
+
     /// 恢复备份
     ///
     /// ## 执行步骤
@@ -3747,4 +3853,81 @@ mod tests {
             "Database should pass integrity check after rollback"
         );
     }
+
+    fn create_versioned_backup_db(path: &Path, version: i64) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE refinery_schema_history (
+                 version INTEGER PRIMARY KEY,
+                 name TEXT,
+                 applied_on TEXT,
+                 checksum TEXT
+             );",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO refinery_schema_history (version, name, applied_on, checksum)
+             VALUES (?1, 'm', '2026-01-30T00:00:00Z', 'x')",
+            [version],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "data_governance")]
+    fn test_preflight_schema_check_rejects_newer_backup() {
+        let (manager, backup_dir, _app_data_dir) = setup_test_env();
+        let backup_subdir = backup_dir.path().join("b1");
+        fs::create_dir_all(&backup_subdir).unwrap();
+        create_versioned_backup_db(&backup_subdir.join("vfs.db"), 2_100_000_000);
+
+        let manifest = BackupManifest::new("1.0.0");
+        let err = manager
+            .preflight_schema_check(&backup_subdir, &manifest)
+            .unwrap_err();
+        assert!(matches!(err, BackupError::VersionIncompatible(_)));
+    }
+
+    #[test]
+    #[cfg(feature = "data_governance")]
+    fn test_preflight_schema_check_plans_forward_migration_for_older_backup() {
+        let (manager, backup_dir, _app_data_dir) = setup_test_env();
+        let backup_subdir = backup_dir.path().join("b2");
+        fs::create_dir_all(&backup_subdir).unwrap();
+        create_versioned_backup_db(&backup_subdir.join("vfs.db"), 1);
+
+        let manifest = BackupManifest::new("1.0.0");
+        let plans = manager
+            .preflight_schema_check(&backup_subdir, &manifest)
+            .unwrap();
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0].database_id, "vfs");
+        assert_eq!(plans[0].backup_version, 1);
+        assert!(plans[0].needs_migration);
+    }
+
+    #[test]
+    #[cfg(feature = "data_governance")]
+    fn test_preflight_schema_check_rejects_newer_legacy_mistakes_schema() {
+        let (manager, backup_dir, _app_data_dir) = setup_test_env();
+        let backup_subdir = backup_dir.path().join("b3");
+        fs::create_dir_all(&backup_subdir).unwrap();
+        let db_path = backup_subdir.join("mistakes.db");
+        create_versioned_backup_db(&db_path, 1);
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch("CREATE TABLE schema_version (version INTEGER PRIMARY KEY);")
+            .unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version) VALUES (?1)",
+            [crate::database::CURRENT_DB_VERSION + 1],
+        )
+        .unwrap();
+        drop(conn);
+
+        let manifest = BackupManifest::new("1.0.0");
+        assert!(matches!(
+            manager.preflight_schema_check(&backup_subdir, &manifest),
+            Err(BackupError::VersionIncompatible(_))
+        ));
+    }
 }
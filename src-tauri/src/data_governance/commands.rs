@@ -1217,8 +1217,8 @@ mod tests {
 
     #[test]
     fn resolve_target_and_pending_uses_migration_set_when_status_missing() {
-        // Mistakes 迁移集：V20260130, V20260131, V20260201, V20260207, V20260208, V20260209
-        // 从 V20260130 开始，pending = 5（后续 5 个迁移）
+        // Mistakes 迁移集：V20260130, V20260131, V20260201, V20260207, V20260208, V20260209, V20260210, V20260211, V20260212, V20260213, V20260214, V20260215, V20260216, V20260217, V20260218, V20260219, V20260220, V20260221, V20260222, V20260223
+        // 从 V20260130 开始，pending = 19（后续 19 个迁移）
         let (target_version, pending_count) =
             resolve_target_and_pending(&DatabaseId::Mistakes, 20260130, None);
 
@@ -1407,6 +1407,10 @@ mod tests {
             infer_database_from_table("exam_sheet_sessions"),
             Some("mistakes")
         );
+        assert_eq!(
+            infer_database_from_table("doc_attachment_blobs"),
+            Some("mistakes")
+        );
     }
 
     #[test]
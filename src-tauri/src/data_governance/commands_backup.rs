@@ -64,7 +64,7 @@ pub(super) fn get_backup_dir(app_data_dir: &PathBuf) -> PathBuf {
 /// - ChatV2: `<active_dir>/chat_v2.db`
 /// - Mistakes: `<active_dir>/mistakes.db`
 /// - LlmUsage: `<active_dir>/llm_usage.db`
-pub(super) fn resolve_database_path(db_id: &DatabaseId, active_dir: &Path) -> PathBuf {
+pub(crate) fn resolve_database_path(db_id: &DatabaseId, active_dir: &Path) -> PathBuf {
     match db_id {
         DatabaseId::Vfs => active_dir.join("databases").join("vfs.db"),
         DatabaseId::ChatV2 => active_dir.join("chat_v2.db"),
@@ -101,7 +101,10 @@ pub(super) fn infer_database_from_table(table_name: &str) -> Option<&'static str
         }
         // mistakes 主数据库
         "mistakes"
+        | "mistake_relations"
+        | "mistake_tag_index"
         | "chat_messages"
+        | "doc_attachment_blobs"
         | "temp_sessions"
         | "review_analyses"
         | "review_chat_messages"
@@ -1027,6 +1030,14 @@ pub async fn data_governance_run_backup(
         backup_type, include_assets
     );
 
+    // 备份目录所在磁盘剩余空间低于阈值时拒绝启动（DISK_FULL）
+    if let Some(state) = app.try_state::<crate::commands::AppState>() {
+        let backup_dir = get_backup_dir(&get_app_data_dir(&app)?);
+        crate::disk_guard::DiskSpaceGuard::from_db(&state.database, backup_dir)
+            .preflight(0)
+            .map_err(|e| e.message)?;
+    }
+
     // 使用全局单例备份任务管理器
     let job_manager = backup_job_state.get();
     let job_ctx = job_manager.create_job(BackupJobKind::Export);
@@ -92,6 +92,96 @@ pub async fn data_governance_restore_backup(
     })
 }
 
+/// 恢复期间的维护模式守卫
+///
+/// 进入时将主数据库切到维护模式，阻止恢复过程中的并发写入；
+/// 离开作用域（成功、失败或取消）时自动退出维护模式，保证原数据库可继续使用。
+struct RestoreMaintenanceGuard {
+    database: std::sync::Arc<crate::database::Database>,
+}
+
+impl RestoreMaintenanceGuard {
+    fn enter(app: &tauri::AppHandle) -> Result<Option<Self>, String> {
+        let Some(state) = app.try_state::<crate::commands::AppState>() else {
+            return Ok(None);
+        };
+        if state.database.is_in_maintenance_mode() {
+            return Err("数据治理操作正在进行（维护模式），请稍后再试。".to_string());
+        }
+        state
+            .database
+            .enter_maintenance_mode()
+            .map_err(|e| format!("进入维护模式失败: {}", e))?;
+        Ok(Some(Self {
+            database: state.database.clone(),
+        }))
+    }
+}
+
+impl Drop for RestoreMaintenanceGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.database.exit_maintenance_mode() {
+            error!("[data_governance] 恢复结束后退出维护模式失败: {}", e);
+        }
+    }
+}
+
+/// 对恢复副本执行前向迁移，并对所有恢复的数据库做完整性检查
+///
+/// 仅作用于恢复目标目录（非活跃插槽），任何失败都不会影响当前正在使用的数据。
+#[cfg(feature = "data_governance")]
+fn migrate_and_verify_restored(
+    target_dir: &std::path::Path,
+    plans: &[super::backup::RestoreSchemaPlan],
+) -> Result<Vec<String>, String> {
+    use super::backup::BackupManager;
+    use super::migration::MigrationCoordinator;
+
+    let mut migrated = Vec::new();
+    let mut coordinator = MigrationCoordinator::new(target_dir.to_path_buf()).with_audit_db(None);
+    for db_id in DatabaseId::all_ordered() {
+        let Some(plan) = plans.iter().find(|p| p.database_id == db_id.as_str()) else {
+            continue;
+        };
+        if !plan.needs_migration {
+            continue;
+        }
+        let report = coordinator
+            .migrate_single(db_id.clone())
+            .map_err(|e| format!("恢复副本迁移失败 ({}): {}", db_id.as_str(), e))?;
+        info!(
+            "[data_governance] 恢复副本迁移完成: {} v{} -> v{}",
+            db_id.as_str(),
+            report.from_version,
+            report.to_version
+        );
+        migrated.push(db_id.as_str().to_string());
+    }
+
+    for plan in plans {
+        let Some(db_id) = DatabaseId::all_ordered()
+            .into_iter()
+            .find(|id| id.as_str() == plan.database_id)
+        else {
+            continue;
+        };
+        let path = BackupManager::resolve_database_path_in_dir(target_dir, &db_id);
+        let conn = rusqlite::Connection::open(&path)
+            .map_err(|e| format!("打开恢复副本失败 ({}): {}", plan.database_id, e))?;
+        let result: String = conn
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+            .map_err(|e| format!("恢复副本完整性检查失败 ({}): {}", plan.database_id, e))?;
+        if result != "ok" {
+            return Err(format!(
+                "恢复副本完整性检查未通过 ({}): {}",
+                plan.database_id, result
+            ));
+        }
+    }
+
+    Ok(migrated)
+}
+
 /// 执行恢复（内部函数，带细粒度进度回调）
 ///
 /// 进度阶段设计（细粒度，每个数据库/资产文件独立上报）：
@@ -312,6 +402,17 @@ async fn execute_restore_with_progress(
 
     info!("[data_governance] 备份文件完整性验证通过: {} 个文件", verify_total);
 
+    // Schema 预检：以备份文件实际内容为准，拒绝来自更新版本应用的备份
+    #[cfg(feature = "data_governance")]
+    let schema_plans = match manager.preflight_schema_check(&backup_subdir, &manifest) {
+        Ok(plans) => plans,
+        Err(e) => {
+            error!("[data_governance] 恢复前 schema 预检失败: {}", e);
+            job_ctx.fail(format!("备份版本不兼容: {}", e));
+            return;
+        }
+    };
+
     // ============ 阶段 3: Replace (15-80%) - 逐数据库恢复 ============
     // 获取非活跃插槽目录：恢复写入非活跃插槽，避免 Windows OS error 32
     // （活跃插槽的数据库文件被连接池持有，Windows 上无法写入/删除）
@@ -357,6 +458,15 @@ async fn execute_restore_with_progress(
         }
     }
 
+    // 进入维护模式（任务结束时由守卫自动退出）
+    let _maintenance_guard = match RestoreMaintenanceGuard::enter(&app) {
+        Ok(guard) => guard,
+        Err(e) => {
+            job_ctx.fail(e);
+            return;
+        }
+    };
+
     // 确保目标目录存在
     if let Err(e) = std::fs::create_dir_all(&inactive_dir) {
         job_ctx.fail(format!("创建恢复目标目录失败: {}", e));
@@ -452,7 +562,40 @@ async fn execute_restore_with_progress(
         return;
     }
 
-    // ============ 阶段 3a: 恢复加密密钥（跨设备恢复支持） ============
+    // ============ 阶段 3a: 恢复副本前向迁移 + 完整性检查 ============
+    // 仅在全部通过后才会标记插槽切换；失败时当前数据保持不变
+    #[cfg(feature = "data_governance")]
+    {
+        job_ctx.mark_running(
+            BackupJobPhase::Replace,
+            80.0,
+            Some("正在迁移并校验恢复的数据库...".to_string()),
+            total_databases,
+            total_items,
+        );
+        let target = inactive_dir.clone();
+        let plans = schema_plans.clone();
+        match tokio::task::spawn_blocking(move || migrate_and_verify_restored(&target, &plans))
+            .await
+        {
+            Ok(Ok(migrated)) => {
+                if !migrated.is_empty() {
+                    info!("[data_governance] 已对恢复副本执行前向迁移: {:?}", migrated);
+                }
+            }
+            Ok(Err(e)) => {
+                error!("[data_governance] {}", e);
+                job_ctx.fail(format!("{}（当前数据未受影响）", e));
+                return;
+            }
+            Err(e) => {
+                job_ctx.fail(format!("恢复副本迁移任务异常: {}（当前数据未受影响）", e));
+                return;
+            }
+        }
+    }
+
+    // ============ 阶段 3b: 恢复加密密钥（跨设备恢复支持） ============
     match manager.restore_crypto_keys(&backup_subdir) {
         Ok(count) => {
             if count > 0 {
@@ -471,7 +614,7 @@ async fn execute_restore_with_progress(
         }
     }
 
-    // ============ 阶段 3b: Replace/Assets (80-92%) - 恢复资产文件 ============
+    // ============ 阶段 3c: Replace/Assets (80-92%) - 恢复资产文件 ============
     let should_restore_assets = restore_assets.unwrap_or_else(|| {
         manifest
             .assets
@@ -10,8 +10,9 @@ use tracing::{debug, error, info, warn};
 use super::audit::{AuditLog, AuditOperation};
 use super::schema_registry::DatabaseId;
 use super::sync::{
-    ChangeLogEntry, DatabaseSyncState, MergeStrategy, PendingChanges, SyncChangeWithData, SyncDirection,
-    SyncExecutionResult, SyncManager, SyncManifest,
+    ChangeLogEntry, ConflictDetectionResult, DatabaseSyncState, MergeStrategy, PendingChanges,
+    SyncChangeWithData, SyncDirection, SyncExecutionResult, SyncManager, SyncManifest, SyncPreview,
+    SyncRecordRef, SyncTablePreview,
 };
 use crate::backup_common::BACKUP_GLOBAL_LIMITER;
 use crate::cloud_storage::{create_storage, CloudStorage, CloudStorageConfig};
@@ -637,6 +638,7 @@ pub async fn data_governance_run_sync(
 
     // 创建同步管理器
     let manager = SyncManager::new(device_id.clone());
+    let upload_options = ChunkedUploadOptions::from_config(&config);
 
     // 构建本地同步清单（遍历所有治理数据库）
     let mut local_databases: HashMap<String, DatabaseSyncState> = HashMap::new();
@@ -722,10 +724,16 @@ pub async fn data_governance_run_sync(
     // 执行同步（异步操作），返回 (结果, 跳过数量)
     let result: Result<(SyncExecutionResult, usize), String> = match sync_direction {
         SyncDirection::Upload => {
-            manager
-                .upload_enriched_changes(storage.as_ref(), &all_enriched, None)
-                .await
-                .map_err(|e| format!("上传同步失败: {}", e))?;
+            upload_changes_resumable(
+                &manager,
+                storage.as_ref(),
+                &all_enriched,
+                &app_data_dir,
+                &upload_options,
+                &OptionalEmitter::none(),
+            )
+            .await
+            .map_err(|e| format!("上传同步失败: {}", e))?;
 
             // 先标记变更为已同步
             for db_id in DatabaseId::all_ordered() {
@@ -823,10 +831,16 @@ pub async fn data_governance_run_sync(
 
             // 上传带完整数据的变更（唯一上传点，避免重复）
             if !all_enriched.is_empty() {
-                manager
-                    .upload_enriched_changes(storage.as_ref(), &all_enriched, None)
-                    .await
-                    .map_err(|e| format!("上传变更失败: {}", e))?;
+                upload_changes_resumable(
+                    &manager,
+                    storage.as_ref(),
+                    &all_enriched,
+                    &app_data_dir,
+                    &upload_options,
+                    &OptionalEmitter::none(),
+                )
+                .await
+                .map_err(|e| format!("上传变更失败: {}", e))?;
             }
 
             // 先应用下载的变更，标记后再重建 manifest 上传
@@ -1364,7 +1378,10 @@ pub struct SyncImportResponse {
 
 // ==================== 带进度回调的同步命令 ====================
 
-use super::sync::{OptionalEmitter, SyncPhase, SyncProgress, SyncProgressEmitter};
+use super::sync::{
+    exclude_uploaded, ChunkedUploadOptions, OptionalEmitter, SpeedCalculator, SyncPhase,
+    SyncProgress, SyncProgressEmitter, UPLOAD_STAGING_DIR,
+};
 
 /// 执行带进度回调的同步
 ///
@@ -1641,6 +1658,7 @@ pub async fn data_governance_run_sync_with_progress(
 
     // 使用 OptionalEmitter 包装
     let opt_emitter = OptionalEmitter::with_emitter(emitter.clone());
+    let upload_options = ChunkedUploadOptions::from_config(&config);
 
     // 执行同步（带进度回调）
     let result = match sync_direction {
@@ -1653,6 +1671,7 @@ pub async fn data_governance_run_sync_with_progress(
                 &local_manifest,
                 &active_dir,
                 &app_data_dir,
+                &upload_options,
                 &opt_emitter.clone(),
             )
             .await
@@ -1679,6 +1698,7 @@ pub async fn data_governance_run_sync_with_progress(
                 merge_strategy,
                 &active_dir,
                 &app_data_dir,
+                &upload_options,
                 &opt_emitter,
             )
             .await
@@ -1799,10 +1819,270 @@ pub async fn data_governance_run_sync_with_progress(
     }
 }
 
+/// 同步预演响应
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SyncPreviewResponse {
+    /// 同步方向
+    pub direction: String,
+    /// 各表将上传 / 下载 / 冲突的记录数
+    pub tables: Vec<SyncTablePreview>,
+    /// 将上传的记录总数
+    pub total_upload: usize,
+    /// 将下载的记录总数
+    pub total_download: usize,
+    /// 本地与云端都有改动的记录
+    pub conflicting_records: Vec<SyncRecordRef>,
+    /// 数据库级冲突数量（使用 manual 策略时真正同步会要求先解决）
+    pub database_conflicts: usize,
+    /// 是否需要先迁移（Schema 不一致，真正同步会失败）
+    pub needs_migration: bool,
+    /// 预演耗时（毫秒）
+    pub duration_ms: u64,
+    /// 设备 ID
+    pub device_id: String,
+}
+
+/// 同步预演（dry-run）
+///
+/// 计算本地与云端的清单差异，按表返回真正同步时将上传、下载和冲突的记录数，
+/// 以及冲突记录的 ID。与 `data_governance_run_sync_with_progress` 使用相同的
+/// 增量水位线与进度事件，但以只读方式打开数据库，不上传、不应用、不标记任何变更。
+/// 用户确认后再调用真正的同步命令。
+///
+/// ## 参数
+/// - `app`: Tauri AppHandle
+/// - `direction`: 同步方向 ("upload", "download", "bidirectional")
+/// - `cloud_config`: 云存储配置
+///
+/// ## 返回
+/// - `SyncPreviewResponse`: 预演结果
+#[tauri::command]
+pub async fn data_governance_preview_sync(
+    app: tauri::AppHandle,
+    direction: String,
+    cloud_config: Option<CloudStorageConfig>,
+) -> Result<SyncPreviewResponse, String> {
+    info!("[data_governance] 开始同步预演: direction={}", direction);
+
+    check_maintenance_mode(&app)?;
+
+    let start = Instant::now();
+    let emitter = SyncProgressEmitter::new(app.clone());
+    emitter.emit_preparing().await;
+
+    let result = preview_sync_inner(&app, &direction, cloud_config, &emitter).await;
+    match result {
+        Ok(mut response) => {
+            response.duration_ms = start.elapsed().as_millis() as u64;
+            emitter.emit_completed().await;
+            info!(
+                "[data_governance] 同步预演完成: direction={}, upload={}, download={}, conflicts={}, duration={}ms",
+                response.direction,
+                response.total_upload,
+                response.total_download,
+                response.conflicting_records.len(),
+                response.duration_ms
+            );
+            Ok(response)
+        }
+        Err(e) => {
+            emitter.emit_failed(&e).await;
+            error!("[data_governance] 同步预演失败: {}", e);
+            Err(e)
+        }
+    }
+}
+
+async fn preview_sync_inner(
+    app: &tauri::AppHandle,
+    direction: &str,
+    cloud_config: Option<CloudStorageConfig>,
+    emitter: &SyncProgressEmitter,
+) -> Result<SyncPreviewResponse, String> {
+    let sync_direction = SyncDirection::from_str(direction).ok_or_else(|| {
+        format!(
+            "无效的同步方向: {}。可选值: upload, download, bidirectional",
+            direction
+        )
+    })?;
+    let config =
+        cloud_config.ok_or_else(|| "未提供云存储配置。请在调用前配置云存储。".to_string())?;
+
+    emitter.emit_detecting_changes().await;
+
+    let active_dir = get_active_data_dir(app)?;
+    let device_id = get_device_id(app);
+    let manager = SyncManager::new(device_id.clone());
+
+    // 只读打开各数据库：构建本地清单并收集待上传变更
+    let mut local_databases: HashMap<String, DatabaseSyncState> = HashMap::new();
+    let mut local_changes: Vec<SyncChangeWithData> = Vec::new();
+    let all_db_ids = DatabaseId::all_ordered();
+    let total_dbs = all_db_ids.len() as u64;
+    for (db_index, db_id) in all_db_ids.iter().enumerate() {
+        let db_path = resolve_database_path(db_id, &active_dir);
+        if !db_path.exists() {
+            continue;
+        }
+        emitter
+            .emit(SyncProgress {
+                phase: SyncPhase::DetectingChanges,
+                percent: 5.0,
+                current: db_index as u64 + 1,
+                total: total_dbs,
+                current_item: Some(db_id.as_str().to_string()),
+                speed_bytes_per_sec: None,
+                eta_seconds: None,
+                error: None,
+            })
+            .await;
+
+        let conn = rusqlite::Connection::open_with_flags(
+            &db_path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .map_err(|e| format!("打开数据库 {} 失败: {}", db_id.as_str(), e))?;
+        if let Ok(state) = SyncManager::get_database_sync_state(&conn, db_id.as_str()) {
+            local_databases.insert(db_id.as_str().to_string(), state);
+        }
+
+        let table_exists: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type='table' AND name='__change_log')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
+        if !table_exists {
+            continue;
+        }
+        let pending = SyncManager::get_pending_changes(&conn, None, None)
+            .map_err(|e| format!("读取数据库 {} 待同步变更失败: {}", db_id.as_str(), e))?;
+        local_changes.extend(pending.entries.iter().map(|entry| {
+            let mut change = SyncChangeWithData::from_entry(entry);
+            change.database_name = Some(db_id.as_str().to_string());
+            change
+        }));
+    }
+    let local_manifest = manager.create_manifest(local_databases);
+
+    // 读取云端清单与增量变更（仅上传时不需要）
+    let (detection, remote_changes) = if sync_direction == SyncDirection::Upload {
+        (ConflictDetectionResult::empty(), Vec::new())
+    } else {
+        emitter.emit_downloading(0, 0, None).await;
+        let storage = create_storage(&config)
+            .await
+            .map_err(|e| format!("创建云存储失败: {}", e))?;
+        let preview = manager
+            .preview_download(storage.as_ref(), &local_manifest)
+            .await
+            .map_err(|e| format!("读取云端变更失败: {}", e))?;
+        let total = preview.1.len() as u64;
+        emitter.emit_downloading(total, total, None).await;
+        preview
+    };
+
+    let preview = SyncPreview::build(sync_direction, &local_changes, &remote_changes);
+    Ok(SyncPreviewResponse {
+        direction: sync_direction.as_str().to_string(),
+        total_upload: preview.total_upload(),
+        total_download: preview.total_download(),
+        tables: preview.tables,
+        conflicting_records: preview.conflicting_records,
+        database_conflicts: detection.database_conflicts.len(),
+        needs_migration: detection.needs_migration,
+        duration_ms: 0,
+        device_id,
+    })
+}
+
 // ============================================================================
 // 同步进度辅助函数（多库 + 完整数据载荷）
 // ============================================================================
 
+/// 字节级上传进度回调（节流 100ms），附带速度与剩余时间估算
+fn byte_progress_callback(emitter: &OptionalEmitter) -> Box<dyn Fn(u64, u64) + Send + Sync> {
+    let emitter_cb = emitter.clone();
+    let last_emit_ms = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+    let speed = std::sync::Mutex::new(SpeedCalculator::default_window());
+    Box::new(move |done, total_bytes| {
+        let is_final = total_bytes > 0 && done >= total_bytes;
+        if !is_final {
+            let now_ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0);
+            let last = last_emit_ms.load(std::sync::atomic::Ordering::Relaxed);
+            if now_ms.saturating_sub(last) < 100 {
+                return;
+            }
+            last_emit_ms.store(now_ms, std::sync::atomic::Ordering::Relaxed);
+        }
+        let (speed_bytes_per_sec, eta_seconds) = match speed.lock() {
+            Ok(mut calc) => {
+                calc.add_sample(done);
+                (
+                    calc.calculate_speed(),
+                    calc.calculate_eta(total_bytes.saturating_sub(done)),
+                )
+            }
+            Err(_) => (None, None),
+        };
+        let pct = if total_bytes > 0 {
+            10.0_f32 + (done as f32 / total_bytes as f32) * 40.0
+        } else {
+            10.0
+        };
+        emitter_cb.emit_force_sync(SyncProgress {
+            phase: SyncPhase::Uploading,
+            percent: pct,
+            current: done,
+            total: total_bytes,
+            current_item: None,
+            speed_bytes_per_sec,
+            eta_seconds,
+            error: None,
+        });
+    })
+}
+
+/// 上传带完整数据的变更：先续传上次中断的分块事务，再分块上传其余变更
+///
+/// 续传事务中已包含的变更不再重复上传；调用方仍按 `enriched` 全量标记为已同步。
+async fn upload_changes_resumable(
+    manager: &SyncManager,
+    storage: &dyn CloudStorage,
+    enriched: &[SyncChangeWithData],
+    app_data_dir: &std::path::Path,
+    upload_options: &ChunkedUploadOptions,
+    emitter: &OptionalEmitter,
+) -> Result<(), super::sync::SyncError> {
+    let staging_dir = app_data_dir.join(UPLOAD_STAGING_DIR);
+    let progress = || {
+        emitter
+            .has_emitter()
+            .then(|| byte_progress_callback(emitter))
+    };
+
+    let remaining = match manager
+        .resume_interrupted_upload(storage, &staging_dir, upload_options, progress())
+        .await?
+    {
+        Some(uploaded) => exclude_uploaded(enriched, &uploaded),
+        None => enriched.to_vec(),
+    };
+    manager
+        .upload_enriched_changes_resumable(
+            storage,
+            &remaining,
+            &staging_dir,
+            upload_options,
+            progress(),
+        )
+        .await
+}
+
 /// 执行上传同步（v2：带进度、多库、完整数据载荷）
 async fn execute_upload_with_progress_v2(
     manager: &SyncManager,
@@ -1812,6 +2092,7 @@ async fn execute_upload_with_progress_v2(
     local_manifest: &SyncManifest,
     active_dir: &std::path::Path,
     app_data_dir: &std::path::Path,
+    upload_options: &ChunkedUploadOptions,
     emitter: &OptionalEmitter,
 ) -> Result<(SyncExecutionResult, usize), String> {
     let start = std::time::Instant::now();
@@ -1834,44 +2115,17 @@ async fn execute_upload_with_progress_v2(
 
     emitter.emit_uploading(0, total, None).await;
 
-    // 上传带完整数据的变更（带字节级进度回调，节流 100ms）
-    {
-        let emitter_cb = emitter.clone();
-        let last_emit_ms = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
-        let byte_progress_cb: Box<dyn Fn(u64, u64) + Send + Sync> = Box::new(move |done, total_bytes| {
-            let is_final = total_bytes > 0 && done >= total_bytes;
-            if !is_final {
-                let now_ms = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .map(|d| d.as_millis() as u64)
-                    .unwrap_or(0);
-                let last = last_emit_ms.load(std::sync::atomic::Ordering::Relaxed);
-                if now_ms.saturating_sub(last) < 100 {
-                    return;
-                }
-                last_emit_ms.store(now_ms, std::sync::atomic::Ordering::Relaxed);
-            }
-            let pct = if total_bytes > 0 {
-                10.0_f32 + (done as f32 / total_bytes as f32) * 40.0
-            } else {
-                10.0
-            };
-            emitter_cb.emit_force_sync(SyncProgress {
-                phase: SyncPhase::Uploading,
-                percent: pct,
-                current: done,
-                total: total_bytes,
-                current_item: None,
-                speed_bytes_per_sec: None,
-                eta_seconds: None,
-                error: None,
-            });
-        });
-        manager
-            .upload_enriched_changes(storage, enriched, Some(byte_progress_cb))
-            .await
-            .map_err(|e| format!("上传同步失败: {}", e))?
-    }
+    // 上传带完整数据的变更（分块续传，带字节级进度回调）
+    upload_changes_resumable(
+        manager,
+        storage,
+        enriched,
+        app_data_dir,
+        upload_options,
+        emitter,
+    )
+    .await
+    .map_err(|e| format!("上传同步失败: {}", e))?;
 
     emitter.emit_uploading(total, total, None).await;
 
@@ -2047,6 +2301,7 @@ async fn execute_bidirectional_with_progress_v2(
     merge_strategy: MergeStrategy,
     active_dir: &std::path::Path,
     app_data_dir: &std::path::Path,
+    upload_options: &ChunkedUploadOptions,
     emitter: &OptionalEmitter,
 ) -> Result<(SyncExecutionResult, usize), String> {
     let _start = std::time::Instant::now();
@@ -2068,43 +2323,16 @@ async fn execute_bidirectional_with_progress_v2(
         let upload_total = enriched.len() as u64;
         emitter.emit_uploading(0, upload_total, None).await;
 
-        // 字节级进度回调——通过流式 PUT 实时上报已传输字节数（节流 100ms）
-        let emitter_cb = emitter.clone();
-        let last_emit_ms = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
-        let byte_progress_cb: Box<dyn Fn(u64, u64) + Send + Sync> = Box::new(move |done, total_bytes| {
-            let is_final = total_bytes > 0 && done >= total_bytes;
-            if !is_final {
-                let now_ms = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .map(|d| d.as_millis() as u64)
-                    .unwrap_or(0);
-                let last = last_emit_ms.load(std::sync::atomic::Ordering::Relaxed);
-                if now_ms.saturating_sub(last) < 100 {
-                    return;
-                }
-                last_emit_ms.store(now_ms, std::sync::atomic::Ordering::Relaxed);
-            }
-            let pct = if total_bytes > 0 {
-                10.0_f32 + (done as f32 / total_bytes as f32) * 40.0
-            } else {
-                10.0
-            };
-            emitter_cb.emit_force_sync(SyncProgress {
-                phase: SyncPhase::Uploading,
-                percent: pct,
-                current: done,
-                total: total_bytes,
-                current_item: None,
-                speed_bytes_per_sec: None,
-                eta_seconds: None,
-                error: None,
-            });
-        });
-
-        manager
-            .upload_enriched_changes(storage, enriched, Some(byte_progress_cb))
-            .await
-            .map_err(|e| format!("上传变更失败: {}", e))?;
+        upload_changes_resumable(
+            manager,
+            storage,
+            enriched,
+            app_data_dir,
+            upload_options,
+            emitter,
+        )
+        .await
+        .map_err(|e| format!("上传变更失败: {}", e))?;
 
         emitter
             .emit_uploading(upload_total, upload_total, None)
@@ -0,0 +1,647 @@
+//! 错题定时导出到云端
+//!
+//! 比完整同步更轻量：按配置的间隔把错题导出为 Markdown 或 JSON，通过 `CloudStorage`
+//! 上传到云端目录，并只保留最近 N 份（滚动窗口）。
+//!
+//! - 以 `mistakes` 的 `MAX(updated_at)` + 记录数作为水位线，数据未变化时跳过；
+//! - 维护模式（备份/恢复/迁移）期间跳过；
+//! - 离线模式下跳过（云存储地址为本机时除外）；
+//! - 云存储凭据不写入设置，运行时从安全存储读取；
+//! - 最近一次运行结果通过 `get_export_schedule` 返回。
+
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::State;
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+use crate::cloud_storage::{create_storage, CloudStorageConfig};
+use crate::cmd::mistake_tags::mistake_filter;
+use crate::commands::AppState;
+use crate::database::{has_column, Database};
+use crate::secure_store::{SecureStore, SecureStoreConfig};
+
+/// 导出计划配置存储键
+const EXPORT_SCHEDULE_CONFIG_KEY: &str = "data_governance.export_schedule.config";
+/// 最近一次运行状态存储键
+const EXPORT_SCHEDULE_STATUS_KEY: &str = "data_governance.export_schedule.status";
+/// 导出文件名前缀（滚动清理只处理此前缀的文件）
+const EXPORT_FILE_PREFIX: &str = "mistakes-export-";
+
+/// 防止定时导出重入
+static EXPORT_RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduledExportFormat {
+    #[default]
+    Markdown,
+    Json,
+}
+
+impl ScheduledExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ScheduledExportFormat::Markdown => "md",
+            ScheduledExportFormat::Json => "json",
+        }
+    }
+}
+
+/// 定时导出配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportScheduleConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 导出间隔（小时），默认 24
+    #[serde(default = "default_interval_hours")]
+    pub interval_hours: u32,
+    #[serde(default)]
+    pub format: ScheduledExportFormat,
+    /// 云端保留的导出份数（滚动窗口），默认 7
+    #[serde(default = "default_keep_count")]
+    pub keep_count: u32,
+    /// 云端目录（相对于存储根目录）
+    #[serde(default = "default_remote_dir")]
+    pub remote_dir: String,
+    /// 云存储配置（保存时去除密码/密钥，运行时从安全存储补全）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloud: Option<CloudStorageConfig>,
+}
+
+fn default_interval_hours() -> u32 {
+    24
+}
+
+fn default_keep_count() -> u32 {
+    7
+}
+
+fn default_remote_dir() -> String {
+    "mistake-exports".to_string()
+}
+
+impl Default for ExportScheduleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: default_interval_hours(),
+            format: ScheduledExportFormat::default(),
+            keep_count: default_keep_count(),
+            remote_dir: default_remote_dir(),
+            cloud: None,
+        }
+    }
+}
+
+impl ExportScheduleConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_hours == 0 {
+            return Err("导出间隔必须大于 0 小时".to_string());
+        }
+        if self.keep_count == 0 {
+            return Err("保留份数必须大于 0".to_string());
+        }
+        if self.remote_dir.trim().trim_matches('/').is_empty() {
+            return Err("云端目录不能为空".to_string());
+        }
+        if self.enabled && self.cloud.is_none() {
+            return Err("启用定时导出前请先配置云存储".to_string());
+        }
+        Ok(())
+    }
+
+    fn remote_dir(&self) -> String {
+        self.remote_dir.trim().trim_matches('/').to_string()
+    }
+
+    pub fn load(database: &Database) -> Self {
+        database
+            .get_setting(EXPORT_SCHEDULE_CONFIG_KEY)
+            .ok()
+            .flatten()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, database: &Database) -> Result<(), String> {
+        self.validate()?;
+        let mut stored = self.clone();
+        if let Some(cloud) = stored.cloud.as_mut() {
+            strip_secrets(cloud);
+        }
+        let json =
+            serde_json::to_string(&stored).map_err(|e| format!("序列化导出计划失败: {}", e))?;
+        database
+            .save_setting(EXPORT_SCHEDULE_CONFIG_KEY, &json)
+            .map_err(|e| format!("保存导出计划失败: {}", e))
+    }
+}
+
+fn strip_secrets(cloud: &mut CloudStorageConfig) {
+    if let Some(webdav) = cloud.webdav.as_mut() {
+        webdav.password.clear();
+    }
+    if let Some(s3) = cloud.s3.as_mut() {
+        s3.secret_access_key.clear();
+    }
+}
+
+/// 从安全存储补全云存储凭据
+fn fill_secrets(cloud: &mut CloudStorageConfig) -> Result<(), String> {
+    let credentials = SecureStore::new(SecureStoreConfig::default())
+        .get_cloud_credentials()
+        .map_err(|e| format!("读取云存储凭据失败: {}", e))?;
+    let Some(credentials) = credentials else {
+        return Ok(());
+    };
+    if let (Some(webdav), Some(password)) = (cloud.webdav.as_mut(), credentials.webdav_password) {
+        webdav.password = password;
+    }
+    if let (Some(s3), Some(secret)) = (cloud.s3.as_mut(), credentials.s3_secret_access_key) {
+        s3.secret_access_key = secret;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportRunOutcome {
+    Success,
+    /// 自上次导出后数据未变化
+    SkippedUnchanged,
+    /// 处于维护模式
+    SkippedMaintenance,
+    /// 离线模式已开启，云存储不在本机
+    SkippedOffline,
+    Failed,
+}
+
+/// 最近一次运行状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportScheduleStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_outcome: Option<ExportRunOutcome>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// 最近一次成功导出的时间（用于判断间隔）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_remote_key: Option<String>,
+    #[serde(default)]
+    pub last_exported_count: usize,
+    /// 最近一次成功导出时的数据水位线
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<String>,
+}
+
+impl ExportScheduleStatus {
+    fn load(database: &Database) -> Self {
+        database
+            .get_setting(EXPORT_SCHEDULE_STATUS_KEY)
+            .ok()
+            .flatten()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, database: &Database) {
+        match serde_json::to_string(self) {
+            Ok(json) => {
+                if let Err(e) = database.save_setting(EXPORT_SCHEDULE_STATUS_KEY, &json) {
+                    warn!("[ExportSchedule] 保存运行状态失败: {}", e);
+                }
+            }
+            Err(e) => warn!("[ExportSchedule] 序列化运行状态失败: {}", e),
+        }
+    }
+
+    fn record(&mut self, outcome: ExportRunOutcome, now: DateTime<Utc>, error: Option<String>) {
+        self.last_run_at = Some(now.to_rfc3339());
+        self.last_outcome = Some(outcome);
+        self.last_error = error;
+    }
+
+    fn is_due(&self, interval_hours: u32, now: DateTime<Utc>) -> bool {
+        match self
+            .last_success_at
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        {
+            Some(last) => (now - last.with_timezone(&Utc)).num_hours() >= interval_hours as i64,
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportScheduleInfo {
+    pub config: ExportScheduleConfig,
+    pub status: ExportScheduleStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExportedMistake {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<String>,
+    created_at: String,
+    updated_at: String,
+    mistake_type: String,
+    status: String,
+    tags: Vec<String>,
+    question: String,
+    ocr_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_analysis: Option<String>,
+}
+
+/// 数据水位线：`MAX(updated_at)|COUNT(*)`（计数用于感知删除）
+fn current_watermark(conn: &Connection) -> rusqlite::Result<String> {
+    let (where_sql, values) = mistake_filter(conn, None)?;
+    let (max_updated, count): (Option<String>, i64) = conn.query_row(
+        &format!(
+            "SELECT MAX(updated_at), COUNT(*) FROM mistakes {}",
+            where_sql
+        ),
+        rusqlite::params_from_iter(values),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(format!("{}|{}", max_updated.unwrap_or_default(), count))
+}
+
+fn load_mistakes(conn: &Connection) -> rusqlite::Result<Vec<ExportedMistake>> {
+    let (where_sql, values) = mistake_filter(conn, None)?;
+    let optional = |column: &str| -> rusqlite::Result<String> {
+        Ok(if has_column(conn, "mistakes", column)? {
+            column.to_string()
+        } else {
+            "NULL".to_string()
+        })
+    };
+    let sql = format!(
+        "SELECT id, {}, created_at, updated_at, mistake_type, status, COALESCE(tags, '[]'), \
+         COALESCE(user_question, ''), COALESCE(ocr_text, ''), {}, {} \
+         FROM mistakes {} ORDER BY created_at",
+        optional("subject")?,
+        optional("mistake_summary")?,
+        optional("user_error_analysis")?,
+        where_sql
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+        let tags_json: String = row.get(6)?;
+        Ok(ExportedMistake {
+            id: row.get(0)?,
+            subject: row.get(1)?,
+            created_at: row.get(2)?,
+            updated_at: row.get(3)?,
+            mistake_type: row.get(4)?,
+            status: row.get(5)?,
+            tags: serde_json::from_str(&tags_json).unwrap_or_default(),
+            question: row.get(7)?,
+            ocr_text: row.get(8)?,
+            summary: row
+                .get::<_, Option<String>>(9)?
+                .filter(|s| !s.trim().is_empty()),
+            error_analysis: row
+                .get::<_, Option<String>>(10)?
+                .filter(|s| !s.trim().is_empty()),
+        })
+    })?;
+    rows.collect()
+}
+
+fn render_markdown(mistakes: &[ExportedMistake], exported_at: &str) -> String {
+    let mut out = format!(
+        "# 错题导出\n\n导出时间：{}  \n共 {} 道错题\n",
+        exported_at,
+        mistakes.len()
+    );
+    for (idx, m) in mistakes.iter().enumerate() {
+        out.push_str(&format!("\n## {}. {}\n\n", idx + 1, m.mistake_type));
+        if let Some(subject) = &m.subject {
+            out.push_str(&format!("- 科目：{}\n", subject));
+        }
+        out.push_str(&format!(
+            "- 创建：{}\n- 更新：{}\n",
+            m.created_at, m.updated_at
+        ));
+        if !m.tags.is_empty() {
+            out.push_str(&format!("- 标签：{}\n", m.tags.join("、")));
+        }
+        out.push_str(&format!("- ID：`{}`\n", m.id));
+        if !m.question.trim().is_empty() {
+            out.push_str(&format!("\n### 题目\n\n{}\n", m.question.trim()));
+        }
+        if !m.ocr_text.trim().is_empty() {
+            out.push_str(&format!("\n### 识别文本\n\n{}\n", m.ocr_text.trim()));
+        }
+        if let Some(summary) = &m.summary {
+            out.push_str(&format!("\n### 总结\n\n{}\n", summary.trim()));
+        }
+        if let Some(analysis) = &m.error_analysis {
+            out.push_str(&format!("\n### 错因分析\n\n{}\n", analysis.trim()));
+        }
+    }
+    out
+}
+
+fn render_export(
+    format: ScheduledExportFormat,
+    mistakes: &[ExportedMistake],
+    exported_at: &str,
+) -> Result<Vec<u8>, String> {
+    match format {
+        ScheduledExportFormat::Markdown => Ok(render_markdown(mistakes, exported_at).into_bytes()),
+        ScheduledExportFormat::Json => serde_json::to_vec_pretty(&serde_json::json!({
+            "exportedAt": exported_at,
+            "count": mistakes.len(),
+            "mistakes": mistakes,
+        }))
+        .map_err(|e| format!("序列化导出内容失败: {}", e)),
+    }
+}
+
+/// 滚动窗口：按文件名（含时间戳）倒序，返回需要删除的文件名
+fn files_to_prune(mut names: Vec<String>, keep_count: u32) -> Vec<String> {
+    names.retain(|n| n.starts_with(EXPORT_FILE_PREFIX));
+    names.sort_by(|a, b| b.cmp(a));
+    names.into_iter().skip(keep_count as usize).collect()
+}
+
+/// 执行一次导出；`force` 为 true 时忽略间隔与水位线（手动触发）
+async fn run_export(database: &Database, force: bool) -> ExportScheduleStatus {
+    let config = ExportScheduleConfig::load(database);
+    let mut status = ExportScheduleStatus::load(database);
+    let now = Utc::now();
+
+    if !force && (!config.enabled || !status.is_due(config.interval_hours, now)) {
+        return status;
+    }
+
+    if database.is_in_maintenance_mode() {
+        info!("[ExportSchedule] 维护模式中，跳过本次导出");
+        status.record(ExportRunOutcome::SkippedMaintenance, now, None);
+        status.save(database);
+        return status;
+    }
+
+    let cloud_is_local = config
+        .cloud
+        .as_ref()
+        .and_then(|c| c.endpoint())
+        .is_some_and(crate::offline_mode::is_local_url);
+    if crate::offline_mode::is_enabled() && !cloud_is_local {
+        info!("[ExportSchedule] 离线模式已开启，跳过本次导出");
+        status.record(ExportRunOutcome::SkippedOffline, now, None);
+        status.save(database);
+        return status;
+    }
+
+    match export_once(database, &config, &status, force, now).await {
+        Ok(Some((remote_key, count, watermark))) => {
+            info!(
+                "[ExportSchedule] 导出完成: {} ({} 道错题)",
+                remote_key, count
+            );
+            status.record(ExportRunOutcome::Success, now, None);
+            status.last_success_at = Some(now.to_rfc3339());
+            status.last_remote_key = Some(remote_key);
+            status.last_exported_count = count;
+            status.watermark = Some(watermark);
+        }
+        Ok(None) => {
+            info!("[ExportSchedule] 数据自上次导出后未变化，跳过");
+            status.record(ExportRunOutcome::SkippedUnchanged, now, None);
+        }
+        Err(e) => {
+            warn!("[ExportSchedule] 导出失败: {}", e);
+            status.record(ExportRunOutcome::Failed, now, Some(e));
+        }
+    }
+    status.save(database);
+    status
+}
+
+/// 返回 `(云端文件, 导出数量, 水位线)`；数据未变化时返回 None
+async fn export_once(
+    database: &Database,
+    config: &ExportScheduleConfig,
+    status: &ExportScheduleStatus,
+    force: bool,
+    now: DateTime<Utc>,
+) -> Result<Option<(String, usize, String)>, String> {
+    let mut cloud = config
+        .cloud
+        .clone()
+        .ok_or_else(|| "未配置云存储".to_string())?;
+
+    let (watermark, mistakes) = {
+        let conn = database
+            .get_conn_safe()
+            .map_err(|e| format!("获取数据库连接失败: {}", e))?;
+        let watermark = current_watermark(&conn).map_err(|e| format!("读取水位线失败: {}", e))?;
+        if !force && status.watermark.as_deref() == Some(watermark.as_str()) {
+            return Ok(None);
+        }
+        let mistakes = load_mistakes(&conn).map_err(|e| format!("读取错题失败: {}", e))?;
+        (watermark, mistakes)
+    };
+
+    let exported_at = now.to_rfc3339();
+    let data = render_export(config.format, &mistakes, &exported_at)?;
+
+    fill_secrets(&mut cloud)?;
+    let storage = create_storage(&cloud)
+        .await
+        .map_err(|e| format!("连接云存储失败: {}", e))?;
+
+    let dir = config.remote_dir();
+    let file_name = format!(
+        "{}{}.{}",
+        EXPORT_FILE_PREFIX,
+        now.format("%Y%m%d-%H%M%S"),
+        config.format.extension()
+    );
+    let remote_key = format!("{}/{}", dir, file_name);
+    storage
+        .put(&remote_key, &data)
+        .await
+        .map_err(|e| format!("上传导出文件失败: {}", e))?;
+
+    // 清理失败不影响本次导出结果
+    match storage.list(&format!("{}/", dir)).await {
+        Ok(files) => {
+            let names = files
+                .iter()
+                .filter_map(|f| f.key.rsplit('/').next().map(str::to_string))
+                .collect();
+            for name in files_to_prune(names, config.keep_count) {
+                if let Err(e) = storage.delete(&format!("{}/{}", dir, name)).await {
+                    warn!("[ExportSchedule] 删除旧导出失败 {}: {}", name, e);
+                }
+            }
+        }
+        Err(e) => warn!("[ExportSchedule] 列出云端导出失败: {}", e),
+    }
+
+    Ok(Some((remote_key, mistakes.len(), watermark)))
+}
+
+/// 定时导出调度器 - 在应用启动时调用，每小时检查一次是否到期
+pub async fn start_export_schedule_scheduler(database: Arc<Database>) {
+    info!("[ExportSchedule] 定时导出调度器已启动");
+
+    // 首次延迟 3 分钟，错开自动备份
+    sleep(Duration::from_secs(180)).await;
+
+    loop {
+        if EXPORT_RUNNING
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            run_export(&database, false).await;
+            EXPORT_RUNNING.store(false, Ordering::SeqCst);
+        }
+        sleep(Duration::from_secs(3600)).await;
+    }
+}
+
+// ============================================================================
+// Tauri 命令
+// ============================================================================
+
+/// 获取定时导出配置与最近一次运行状态
+#[tauri::command]
+pub async fn get_export_schedule(state: State<'_, AppState>) -> Result<ExportScheduleInfo, String> {
+    Ok(ExportScheduleInfo {
+        config: ExportScheduleConfig::load(&state.database),
+        status: ExportScheduleStatus::load(&state.database),
+    })
+}
+
+/// 保存定时导出配置（云存储密码/密钥请通过安全存储保存）
+#[tauri::command]
+pub async fn set_export_schedule(
+    config: ExportScheduleConfig,
+    state: State<'_, AppState>,
+) -> Result<ExportScheduleInfo, String> {
+    config.save(&state.database)?;
+    info!(
+        "[ExportSchedule] 配置已更新: enabled={}, interval={}h, format={:?}, keep={}",
+        config.enabled, config.interval_hours, config.format, config.keep_count
+    );
+    Ok(ExportScheduleInfo {
+        config: ExportScheduleConfig::load(&state.database),
+        status: ExportScheduleStatus::load(&state.database),
+    })
+}
+
+/// 立即执行一次导出（忽略间隔与水位线）
+#[tauri::command]
+pub async fn run_export_schedule_now(
+    state: State<'_, AppState>,
+) -> Result<ExportScheduleStatus, String> {
+    if EXPORT_RUNNING
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return Err("导出正在进行中，请稍后再试".to_string());
+    }
+    let status = run_export(&state.database, true).await;
+    EXPORT_RUNNING.store(false, Ordering::SeqCst);
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    #[test]
+    fn test_config_validation_and_secret_stripping() {
+        let mut config = ExportScheduleConfig::default();
+        assert!(config.validate().is_ok());
+        config.enabled = true;
+        assert!(config.validate().is_err());
+
+        let mut cloud: CloudStorageConfig = serde_json::from_value(serde_json::json!({
+            "provider": "webdav",
+            "webdav": { "endpoint": "https://dav.example.com", "username": "u", "password": "p" }
+        }))
+        .unwrap();
+        strip_secrets(&mut cloud);
+        assert!(cloud.webdav.unwrap().password.is_empty());
+    }
+
+    #[test]
+    fn test_due_and_pruning() {
+        let now = Utc::now();
+        let mut status = ExportScheduleStatus::default();
+        assert!(status.is_due(24, now));
+        status.last_success_at = Some((now - chrono::Duration::hours(3)).to_rfc3339());
+        assert!(!status.is_due(24, now));
+        assert!(status.is_due(2, now));
+
+        let names = vec![
+            "mistakes-export-20260101-000000.md".to_string(),
+            "mistakes-export-20260103-000000.md".to_string(),
+            "notes.md".to_string(),
+            "mistakes-export-20260102-000000.json".to_string(),
+        ];
+        assert_eq!(
+            files_to_prune(names, 2),
+            vec!["mistakes-export-20260101-000000.md".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_watermark_changes_with_updates_and_deletes() {
+        let conn = migrated_conn().unwrap();
+        insert_mistake(
+            &conn,
+            "a",
+            &[
+                ("updated_at", &"2026-01-01"),
+                ("mistake_type", &"计算"),
+                ("tags", &r#"["函数"]"#),
+                ("user_question", &"求导"),
+            ],
+        )
+        .unwrap();
+        insert_mistake(
+            &conn,
+            "b",
+            &[
+                ("updated_at", &"2026-01-02"),
+                ("mistake_type", &"概念"),
+                ("user_question", &"定义"),
+            ],
+        )
+        .unwrap();
+        let before = current_watermark(&conn).unwrap();
+        assert_eq!(before, "2026-01-02|2");
+
+        conn.execute("DELETE FROM mistakes WHERE id = 'a'", [])
+            .unwrap();
+        assert_ne!(current_watermark(&conn).unwrap(), before);
+
+        let mistakes = load_mistakes(&conn).unwrap();
+        assert_eq!(mistakes.len(), 1);
+        assert!(mistakes[0].subject.is_none());
+        let markdown = render_markdown(&mistakes, "2026-01-03T00:00:00Z");
+        assert!(markdown.contains("## 1. 概念") && markdown.contains("定义"));
+    }
+}
@@ -4,7 +4,8 @@
 //!
 //! ## 表结构概览
 //!
-//! - 核心表：mistakes, chat_messages, temp_sessions
+//! - 核心表：mistakes, chat_messages, temp_sessions, mistake_relations（V20260217）,
+//!   mistake_tag_index（V20260221）
 //! - 回顾分析：review_analyses, review_chat_messages, review_sessions, review_session_mistakes
 //! - 配置表：settings, rag_configurations
 //! - Anki卡片：document_tasks, anki_cards, custom_anki_templates, document_control_states
@@ -85,6 +86,138 @@ pub const V20260209_ANKI_CARD_DEDUP_UNIQUE: MigrationDef = MigrationDef::new(
 .with_expected_indexes(MISTAKES_V20260209_DEDUP_INDEXES)
 .idempotent();
 
+/// V20260210: 文档附件内容寻址存储
+///
+/// 聊天消息中的文档附件载荷按 SHA-256 指纹只存一份，消息仅保留 `content_hash` 引用
+pub const V20260210_DOC_ATTACHMENT_BLOBS: MigrationDef = MigrationDef::new(
+    20260210,
+    "add_doc_attachment_blobs",
+    include_str!("../../../migrations/mistakes/V20260210__add_doc_attachment_blobs.sql"),
+)
+.with_expected_tables(&["doc_attachment_blobs"])
+.idempotent();
+
+/// V20260211: 错题置顶与星级
+pub const V20260211_MISTAKE_PIN_FIELDS: MigrationDef = MigrationDef::new(
+    20260211,
+    "add_mistake_pin_fields",
+    include_str!("../../../migrations/mistakes/V20260211__add_mistake_pin_fields.sql"),
+)
+.with_expected_columns(&[("mistakes", "pinned"), ("mistakes", "star_rating")])
+.with_expected_indexes(&["idx_mistakes_pinned"]);
+
+/// V20260212: 错题归档时间
+pub const V20260212_MISTAKE_ARCHIVED_AT: MigrationDef = MigrationDef::new(
+    20260212,
+    "add_mistake_archived_at",
+    include_str!("../../../migrations/mistakes/V20260212__add_mistake_archived_at.sql"),
+)
+.with_expected_columns(&[("mistakes", "archived_at")])
+.with_expected_indexes(&["idx_mistakes_archived_at"]);
+
+/// V20260213: 错题用户批注
+pub const V20260213_MISTAKE_USER_NOTES: MigrationDef = MigrationDef::new(
+    20260213,
+    "add_mistake_user_notes",
+    include_str!("../../../migrations/mistakes/V20260213__add_mistake_user_notes.sql"),
+)
+.with_expected_columns(&[("mistakes", "user_notes")]);
+
+/// V20260214: 错题难度与掌握度
+pub const V20260214_MISTAKE_MASTERY: MigrationDef = MigrationDef::new(
+    20260214,
+    "add_mistake_mastery",
+    include_str!("../../../migrations/mistakes/V20260214__add_mistake_mastery.sql"),
+)
+.with_expected_columns(&[
+    ("mistakes", "difficulty"),
+    ("mistakes", "mastery_level"),
+    ("mistakes", "review_count"),
+    ("mistakes", "last_reviewed_at"),
+])
+.with_expected_indexes(&["idx_mistakes_mastery_level"]);
+
+/// V20260215: 错题总结生成追踪
+pub const V20260215_MISTAKE_SUMMARY_TRACKING: MigrationDef = MigrationDef::new(
+    20260215,
+    "add_mistake_summary_tracking",
+    include_str!("../../../migrations/mistakes/V20260215__add_mistake_summary_tracking.sql"),
+)
+.with_expected_columns(&[
+    ("mistakes", "summary_content_hash"),
+    ("mistakes", "last_summarized_at"),
+]);
+
+/// V20260216: 回顾分析归档时间
+pub const V20260216_REVIEW_ANALYSIS_ARCHIVED_AT: MigrationDef = MigrationDef::new(
+    20260216,
+    "add_review_analysis_archived_at",
+    include_str!("../../../migrations/mistakes/V20260216__add_review_analysis_archived_at.sql"),
+)
+.with_expected_columns(&[("review_analyses", "archived_at")])
+.with_expected_indexes(&["idx_review_analyses_archived_at"]);
+
+/// V20260217: 错题手动关联
+pub const V20260217_MISTAKE_RELATIONS: MigrationDef = MigrationDef::new(
+    20260217,
+    "add_mistake_relations",
+    include_str!("../../../migrations/mistakes/V20260217__add_mistake_relations.sql"),
+)
+.with_expected_tables(&["mistake_relations"])
+.with_expected_indexes(&["idx_mistake_relations_to"]);
+
+/// V20260218: 题目语言
+pub const V20260218_MISTAKE_LANGUAGE: MigrationDef = MigrationDef::new(
+    20260218,
+    "add_mistake_language",
+    include_str!("../../../migrations/mistakes/V20260218__add_mistake_language.sql"),
+)
+.with_expected_columns(&[("mistakes", "language")]);
+
+/// V20260219: 错题级覆盖信息（AI 分配的标签等）
+pub const V20260219_MISTAKE_OVERRIDES: MigrationDef = MigrationDef::new(
+    20260219,
+    "add_mistake_overrides",
+    include_str!("../../../migrations/mistakes/V20260219__add_mistake_overrides.sql"),
+)
+.with_expected_columns(&[("mistakes", "overrides")]);
+
+/// V20260220: RAG 嵌入批大小
+pub const V20260220_RAG_EMBEDDING_BATCH_SIZE: MigrationDef = MigrationDef::new(
+    20260220,
+    "add_rag_embedding_batch_size",
+    include_str!("../../../migrations/mistakes/V20260220__add_rag_embedding_batch_size.sql"),
+)
+.with_expected_columns(&[("rag_configurations", "embedding_batch_size")]);
+
+/// V20260221: 错题标签索引（触发器维护）
+pub const V20260221_MISTAKE_TAG_INDEX: MigrationDef = MigrationDef::new(
+    20260221,
+    "add_mistake_tag_index",
+    include_str!("../../../migrations/mistakes/V20260221__add_mistake_tag_index.sql"),
+)
+.with_expected_tables(&["mistake_tag_index"])
+.with_expected_indexes(&["idx_mistake_tag_index_tag"]);
+
+/// V20260222: 制卡任务模型原始输出
+pub const V20260222_TASK_RAW_OUTPUT: MigrationDef = MigrationDef::new(
+    20260222,
+    "add_task_raw_output",
+    include_str!("../../../migrations/mistakes/V20260222__add_task_raw_output.sql"),
+)
+.with_expected_columns(&[
+    ("document_tasks", "raw_output"),
+    ("document_tasks", "raw_output_bytes"),
+]);
+
+/// V20260223: 错题科目
+pub const V20260223_MISTAKE_SUBJECT: MigrationDef = MigrationDef::new(
+    20260223,
+    "add_mistake_subject",
+    include_str!("../../../migrations/mistakes/V20260223__add_mistake_subject.sql"),
+)
+.with_expected_columns(&[("mistakes", "subject")]);
+
 /// V20260201 同步字段索引
 const MISTAKES_V20260201_SYNC_INDEXES: &[&str] = &[
     // mistakes 表同步索引
@@ -209,6 +342,20 @@ pub const MISTAKES_MIGRATIONS: MigrationSet = MigrationSet {
         V20260207_TEMPLATE_PREVIEW_DATA,
         V20260208_HOT_QUERY_INDEXES,
         V20260209_ANKI_CARD_DEDUP_UNIQUE,
+        V20260210_DOC_ATTACHMENT_BLOBS,
+        V20260211_MISTAKE_PIN_FIELDS,
+        V20260212_MISTAKE_ARCHIVED_AT,
+        V20260213_MISTAKE_USER_NOTES,
+        V20260214_MISTAKE_MASTERY,
+        V20260215_MISTAKE_SUMMARY_TRACKING,
+        V20260216_REVIEW_ANALYSIS_ARCHIVED_AT,
+        V20260217_MISTAKE_RELATIONS,
+        V20260218_MISTAKE_LANGUAGE,
+        V20260219_MISTAKE_OVERRIDES,
+        V20260220_RAG_EMBEDDING_BATCH_SIZE,
+        V20260221_MISTAKE_TAG_INDEX,
+        V20260222_TASK_RAW_OUTPUT,
+        V20260223_MISTAKE_SUBJECT,
     ],
 };
 
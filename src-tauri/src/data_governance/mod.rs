@@ -42,6 +42,7 @@ pub mod commands_restore;
 pub mod commands_asset;
 pub mod commands_sync;
 pub mod dto;
+pub mod export_schedule;
 pub mod init;
 pub mod migration;
 pub mod plugin;
@@ -106,11 +107,15 @@ pub use commands_sync::{
     data_governance_export_sync_data,
     data_governance_get_sync_status,
     data_governance_import_sync_data,
+    data_governance_preview_sync,
     data_governance_resolve_conflicts,
     data_governance_run_sync,
     data_governance_run_sync_with_progress,
 };
 
+// Re-exports - 定时导出命令（export_schedule.rs）
+pub use export_schedule::{get_export_schedule, run_export_schedule_now, set_export_schedule};
+
 // Re-exports - 同步进度相关
 pub use init::{initialize, initialize_with_report, InitializationReport, InitializationResult};
 pub use migration::MigrationCoordinator;
@@ -0,0 +1,815 @@
+//! 变更数据的分块续传上传
+//!
+//! 压缩后超过一个分块的变更数据按 `CloudStorageConfig` 配置的分块大小切分，以有限并发上传到
+//! `data_governance/chunked_changes/{device_id}/{transaction_id}/`，全部分块确认后再上传分块
+//! 索引 `{version}-{transaction_id}.index.json`。下载端只读取索引，未完成的事务对其他设备
+//! 不可见。
+//!
+//! 上传前把压缩数据与检查点（事务、已确认的分块、所含变更）写入本地暂存目录，每确认一个
+//! 分块就更新检查点。同步中断后，下一次上传先续传暂存的事务，只补传缺失的分块。
+//!
+//! 旧版客户端不读取分块变更。支持的客户端在同步清单中声明 [`CHUNKED_CHANGES_CAPABILITY`]，
+//! 只要有其他设备的清单未声明该能力，就退回单文件上传，并放弃尚未完成的分块事务。
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{retry_async, SyncChangeWithData, SyncError, SyncManager};
+use crate::cloud_storage::{CloudStorage, CloudStorageConfig};
+
+/// 字节进度回调 (uploaded_bytes, total_bytes)
+pub type ChunkProgressCallback = Box<dyn Fn(u64, u64) + Send + Sync>;
+
+/// 分块变更在云端的路径前缀（与单文件变更分开，旧版本客户端不会读取）
+pub const CHUNKED_CHANGES_PREFIX: &str = "data_governance/chunked_changes";
+/// 本地暂存目录名（位于应用数据目录下）
+pub const UPLOAD_STAGING_DIR: &str = "sync_upload_staging";
+/// 同步清单中声明“可读取分块变更”的能力标识
+pub const CHUNKED_CHANGES_CAPABILITY: &str = "chunked_changes";
+
+const INDEX_SUFFIX: &str = ".index.json";
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+const PAYLOAD_FILE: &str = "payload.json.zst";
+const CHUNK_MAX_RETRIES: u32 = 3;
+
+/// 分块上传参数
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkedUploadOptions {
+    /// 分块大小（字节）
+    pub chunk_size: usize,
+    /// 同时上传的分块数
+    pub max_concurrency: usize,
+}
+
+impl ChunkedUploadOptions {
+    pub fn from_config(config: &CloudStorageConfig) -> Self {
+        Self {
+            chunk_size: config.upload_chunk_size(),
+            max_concurrency: config.max_concurrent_uploads(),
+        }
+    }
+}
+
+/// 事务中包含的一条本地变更
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeRef {
+    pub database_name: Option<String>,
+    pub change_log_id: i64,
+}
+
+impl ChangeRef {
+    pub fn of(change: &SyncChangeWithData) -> Option<Self> {
+        change.change_log_id.map(|change_log_id| Self {
+            database_name: change.database_name.clone(),
+            change_log_id,
+        })
+    }
+}
+
+/// 排除已随续传事务上传的变更
+pub fn exclude_uploaded(
+    changes: &[SyncChangeWithData],
+    uploaded: &[ChangeRef],
+) -> Vec<SyncChangeWithData> {
+    let uploaded: HashSet<&ChangeRef> = uploaded.iter().collect();
+    changes
+        .iter()
+        .filter(|change| ChangeRef::of(change).is_none_or(|r| !uploaded.contains(&r)))
+        .cloned()
+        .collect()
+}
+
+/// 云端分块索引
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkedChangeIndex {
+    pub sync_transaction_id: String,
+    pub device_id: String,
+    pub total_size: u64,
+    /// 完整载荷的 SHA256
+    pub sha256: String,
+    pub chunks: Vec<ChunkEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkEntry {
+    pub key: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// 本地续传检查点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadCheckpoint {
+    pub sync_transaction_id: String,
+    pub device_id: String,
+    /// 变更版本（秒级时间戳，与单文件变更同一版本空间）
+    pub version: u64,
+    /// 续传时沿用开始上传时的分块大小
+    pub chunk_size: usize,
+    pub total_size: u64,
+    pub sha256: String,
+    pub confirmed_chunks: BTreeSet<usize>,
+    pub changes: Vec<ChangeRef>,
+    pub created_at: String,
+}
+
+impl UploadCheckpoint {
+    fn chunk_count(&self) -> usize {
+        (self.total_size as usize).div_ceil(self.chunk_size)
+    }
+
+    fn chunk_range(&self, index: usize) -> Range<usize> {
+        let start = index * self.chunk_size;
+        start..(start + self.chunk_size).min(self.total_size as usize)
+    }
+
+    fn chunk_prefix(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            CHUNKED_CHANGES_PREFIX, self.device_id, self.sync_transaction_id
+        )
+    }
+
+    fn chunk_key(&self, index: usize) -> String {
+        format!("{}/{:06}.part", self.chunk_prefix(), index)
+    }
+
+    fn index_key(&self) -> String {
+        format!(
+            "{}/{}/{}-{}{}",
+            CHUNKED_CHANGES_PREFIX,
+            self.device_id,
+            self.version,
+            self.sync_transaction_id,
+            INDEX_SUFFIX
+        )
+    }
+
+    fn confirmed_bytes(&self) -> u64 {
+        self.confirmed_chunks
+            .iter()
+            .map(|&index| self.chunk_range(index).len() as u64)
+            .sum()
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn checkpoint_path(staging_dir: &Path) -> PathBuf {
+    staging_dir.join(CHECKPOINT_FILE)
+}
+
+fn payload_path(staging_dir: &Path) -> PathBuf {
+    staging_dir.join(PAYLOAD_FILE)
+}
+
+fn load_checkpoint(staging_dir: &Path) -> Option<UploadCheckpoint> {
+    let raw = std::fs::read(checkpoint_path(staging_dir)).ok()?;
+    match serde_json::from_slice(&raw) {
+        Ok(checkpoint) => Some(checkpoint),
+        Err(e) => {
+            tracing::warn!("[sync] 续传检查点无法解析，已丢弃: {}", e);
+            clear_staging(staging_dir);
+            None
+        }
+    }
+}
+
+/// 先写临时文件再重命名，避免中断时留下半个检查点
+fn save_checkpoint(staging_dir: &Path, checkpoint: &UploadCheckpoint) -> Result<(), SyncError> {
+    let json = serde_json::to_vec(checkpoint)
+        .map_err(|e| SyncError::Database(format!("序列化续传检查点失败: {}", e)))?;
+    let tmp = staging_dir.join(format!("{}.tmp", CHECKPOINT_FILE));
+    std::fs::write(&tmp, json)
+        .and_then(|_| std::fs::rename(&tmp, checkpoint_path(staging_dir)))
+        .map_err(|e| SyncError::Database(format!("写入续传检查点失败: {}", e)))
+}
+
+fn clear_staging(staging_dir: &Path) {
+    if let Err(e) = std::fs::remove_dir_all(staging_dir) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("[sync] 清理续传暂存目录失败: {}", e);
+        }
+    }
+}
+
+impl SyncManager {
+    /// 上传带完整数据的变更（可续传）
+    ///
+    /// 压缩后不超过一个分块、或有设备不支持分块变更时沿用单文件上传；否则暂存到
+    /// `staging_dir` 并分块上传，中断后可由 [`SyncManager::resume_interrupted_upload`] 续传。
+    pub async fn upload_enriched_changes_resumable(
+        &self,
+        storage: &dyn CloudStorage,
+        changes: &[SyncChangeWithData],
+        staging_dir: &Path,
+        options: &ChunkedUploadOptions,
+        progress: Option<ChunkProgressCallback>,
+    ) -> Result<(), SyncError> {
+        if changes.is_empty() {
+            tracing::debug!("[sync] 没有变更需要上传");
+            return Ok(());
+        }
+
+        let compressed = self.encode_changes_payload(changes)?;
+        if compressed.len() <= options.chunk_size
+            || !self.peers_read_chunked_changes(storage).await?
+        {
+            return self
+                .upload_encoded_changes(storage, compressed, changes.len(), progress)
+                .await;
+        }
+
+        let checkpoint = UploadCheckpoint {
+            sync_transaction_id: uuid::Uuid::new_v4().to_string(),
+            device_id: self.device_id.clone(),
+            version: chrono::Utc::now().timestamp() as u64,
+            chunk_size: options.chunk_size,
+            total_size: compressed.len() as u64,
+            sha256: sha256_hex(&compressed),
+            confirmed_chunks: BTreeSet::new(),
+            changes: changes.iter().filter_map(ChangeRef::of).collect(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        std::fs::create_dir_all(staging_dir)
+            .and_then(|_| std::fs::write(payload_path(staging_dir), &compressed))
+            .map_err(|e| SyncError::Database(format!("暂存变更数据失败: {}", e)))?;
+        save_checkpoint(staging_dir, &checkpoint)?;
+
+        self.upload_checkpointed(
+            storage,
+            staging_dir,
+            checkpoint,
+            &compressed,
+            options.max_concurrency,
+            progress,
+        )
+        .await
+    }
+
+    /// 续传上次中断的分块上传
+    ///
+    /// 返回续传完成的事务所含变更；没有待续传的事务时返回 `None`。
+    /// 暂存数据损坏、属于其他设备或已有设备不支持分块变更时丢弃暂存，
+    /// 相应变更仍未标记为已同步，会随本次上传重新上传。
+    pub async fn resume_interrupted_upload(
+        &self,
+        storage: &dyn CloudStorage,
+        staging_dir: &Path,
+        options: &ChunkedUploadOptions,
+        progress: Option<ChunkProgressCallback>,
+    ) -> Result<Option<Vec<ChangeRef>>, SyncError> {
+        let Some(checkpoint) = load_checkpoint(staging_dir) else {
+            return Ok(None);
+        };
+        let payload = std::fs::read(payload_path(staging_dir))
+            .ok()
+            .filter(|payload| sha256_hex(payload) == checkpoint.sha256);
+        let payload = match payload {
+            Some(payload)
+                if checkpoint.device_id == self.device_id && checkpoint.chunk_size > 0 =>
+            {
+                payload
+            }
+            _ => {
+                tracing::warn!(
+                    "[sync] 续传暂存无效，放弃事务 {}",
+                    checkpoint.sync_transaction_id
+                );
+                self.discard_remote_chunks(storage, &checkpoint).await;
+                clear_staging(staging_dir);
+                return Ok(None);
+            }
+        };
+        if !self.peers_read_chunked_changes(storage).await? {
+            tracing::warn!(
+                "[sync] 有设备不支持分块变更，放弃事务 {} 并改为单文件上传",
+                checkpoint.sync_transaction_id
+            );
+            self.discard_remote_chunks(storage, &checkpoint).await;
+            clear_staging(staging_dir);
+            return Ok(None);
+        }
+
+        tracing::info!(
+            "[sync] 续传中断的变更上传: tx={}, confirmed={}/{}",
+            checkpoint.sync_transaction_id,
+            checkpoint.confirmed_chunks.len(),
+            checkpoint.chunk_count()
+        );
+        let changes = checkpoint.changes.clone();
+        self.upload_checkpointed(
+            storage,
+            staging_dir,
+            checkpoint,
+            &payload,
+            options.max_concurrency,
+            progress,
+        )
+        .await?;
+        Ok(Some(changes))
+    }
+
+    /// 其他设备的同步清单是否都声明了 [`CHUNKED_CHANGES_CAPABILITY`]
+    async fn peers_read_chunked_changes(
+        &self,
+        storage: &dyn CloudStorage,
+    ) -> Result<bool, SyncError> {
+        let manifest = self.download_manifest(storage).await?;
+        Ok(manifest.supports(CHUNKED_CHANGES_CAPABILITY))
+    }
+
+    /// 补传检查点中未确认的分块，全部确认后上传索引并清理暂存
+    async fn upload_checkpointed(
+        &self,
+        storage: &dyn CloudStorage,
+        staging_dir: &Path,
+        mut checkpoint: UploadCheckpoint,
+        payload: &[u8],
+        max_concurrency: usize,
+        progress: Option<ChunkProgressCallback>,
+    ) -> Result<(), SyncError> {
+        // 以云端实际存在且大小一致的分块为准
+        if !checkpoint.confirmed_chunks.is_empty() {
+            let remote: HashMap<String, u64> = storage
+                .list(&checkpoint.chunk_prefix())
+                .await
+                .map_err(|e| SyncError::Network(format!("列出已上传分块失败: {}", e)))?
+                .into_iter()
+                .map(|file| (file.key, file.size))
+                .collect();
+            let verified: BTreeSet<usize> = checkpoint
+                .confirmed_chunks
+                .iter()
+                .copied()
+                .filter(|&index| {
+                    remote.get(&checkpoint.chunk_key(index))
+                        == Some(&(checkpoint.chunk_range(index).len() as u64))
+                })
+                .collect();
+            checkpoint.confirmed_chunks = verified;
+        }
+
+        let total = checkpoint.total_size;
+        let mut done = checkpoint.confirmed_bytes();
+        let report = |done: u64| {
+            if let Some(cb) = &progress {
+                cb(done, total);
+            }
+        };
+        report(done);
+
+        let pending: Vec<(usize, String, Range<usize>)> = (0..checkpoint.chunk_count())
+            .filter(|index| !checkpoint.confirmed_chunks.contains(index))
+            .map(|index| {
+                (
+                    index,
+                    checkpoint.chunk_key(index),
+                    checkpoint.chunk_range(index),
+                )
+            })
+            .collect();
+        let mut uploads = stream::iter(pending)
+            .map(|(index, key, range)| async move {
+                let bytes = &payload[range];
+                let result = retry_async("上传变更分块", CHUNK_MAX_RETRIES, || async {
+                    storage
+                        .put(&key, bytes)
+                        .await
+                        .map_err(|e| SyncError::Network(format!("上传变更分块失败: {}", e)))
+                })
+                .await;
+                (index, bytes.len() as u64, result)
+            })
+            .buffer_unordered(max_concurrency.max(1));
+
+        while let Some((index, size, result)) = uploads.next().await {
+            if let Err(e) = result {
+                // 已确认的分块记录在检查点中，下次同步从这里续传
+                tracing::warn!(
+                    "[sync] 分块上传中断: tx={}, confirmed={}/{}",
+                    checkpoint.sync_transaction_id,
+                    checkpoint.confirmed_chunks.len(),
+                    checkpoint.chunk_count()
+                );
+                return Err(e);
+            }
+            checkpoint.confirmed_chunks.insert(index);
+            if let Err(e) = save_checkpoint(staging_dir, &checkpoint) {
+                tracing::warn!("[sync] {}", e);
+            }
+            done += size;
+            report(done);
+        }
+        drop(uploads);
+
+        let index = ChunkedChangeIndex {
+            sync_transaction_id: checkpoint.sync_transaction_id.clone(),
+            device_id: checkpoint.device_id.clone(),
+            total_size: total,
+            sha256: checkpoint.sha256.clone(),
+            chunks: (0..checkpoint.chunk_count())
+                .map(|index| {
+                    let bytes = &payload[checkpoint.chunk_range(index)];
+                    ChunkEntry {
+                        key: checkpoint.chunk_key(index),
+                        size: bytes.len() as u64,
+                        sha256: sha256_hex(bytes),
+                    }
+                })
+                .collect(),
+        };
+        let json = serde_json::to_vec(&index)
+            .map_err(|e| SyncError::Database(format!("序列化分块索引失败: {}", e)))?;
+        let index_key = checkpoint.index_key();
+        retry_async("上传分块索引", CHUNK_MAX_RETRIES, || async {
+            storage
+                .put(&index_key, &json)
+                .await
+                .map_err(|e| SyncError::Network(format!("上传分块索引失败: {}", e)))
+        })
+        .await?;
+        clear_staging(staging_dir);
+
+        tracing::info!(
+            "[sync] 分块变更已上传: device={}, tx={}, chunks={}, size={}, changes={}",
+            self.device_id,
+            checkpoint.sync_transaction_id,
+            index.chunks.len(),
+            total,
+            checkpoint.changes.len()
+        );
+        Ok(())
+    }
+
+    /// 尽力删除放弃的事务已上传的分块
+    async fn discard_remote_chunks(
+        &self,
+        storage: &dyn CloudStorage,
+        checkpoint: &UploadCheckpoint,
+    ) {
+        if checkpoint.device_id != self.device_id {
+            return;
+        }
+        let Ok(files) = storage.list(&checkpoint.chunk_prefix()).await else {
+            return;
+        };
+        for file in files {
+            if let Err(e) = storage.delete(&file.key).await {
+                tracing::warn!("[sync] 删除放弃的分块失败（跳过）: {}: {}", file.key, e);
+            }
+        }
+    }
+
+    /// 下载其他设备已完成上传的分块变更，校验后拼装为完整载荷
+    ///
+    /// 返回 (索引键, 版本, 压缩载荷)，按单文件变更的方式解码。
+    pub(super) async fn download_chunked_changes(
+        &self,
+        storage: &dyn CloudStorage,
+        since_version: u64,
+    ) -> Result<Vec<(String, u64, Vec<u8>)>, SyncError> {
+        let files = storage
+            .list(CHUNKED_CHANGES_PREFIX)
+            .await
+            .map_err(|e| SyncError::Network(format!("列出分块变更失败: {}", e)))?;
+
+        let mut payloads = Vec::new();
+        for file in files {
+            if !file.key.ends_with(INDEX_SUFFIX)
+                || Self::is_own_change_file(&file.key, &self.device_id)
+            {
+                continue;
+            }
+            let Some(version) = Self::parse_version_from_key(&file.key) else {
+                continue;
+            };
+            if version < since_version {
+                continue;
+            }
+            let Some(raw) = storage
+                .get(&file.key)
+                .await
+                .map_err(|e| SyncError::Network(format!("下载分块索引失败: {}", e)))?
+            else {
+                continue;
+            };
+            let index: ChunkedChangeIndex = serde_json::from_slice(&raw).map_err(|e| {
+                SyncError::Database(format!("解析分块索引失败 {}: {}", file.key, e))
+            })?;
+
+            let mut payload = Vec::with_capacity(index.total_size as usize);
+            for chunk in &index.chunks {
+                let bytes = storage
+                    .get(&chunk.key)
+                    .await
+                    .map_err(|e| SyncError::Network(format!("下载变更分块失败: {}", e)))?
+                    .ok_or_else(|| SyncError::Network(format!("变更分块缺失: {}", chunk.key)))?;
+                if bytes.len() as u64 != chunk.size || sha256_hex(&bytes) != chunk.sha256 {
+                    return Err(SyncError::Database(format!(
+                        "变更分块校验失败: {}",
+                        chunk.key
+                    )));
+                }
+                payload.extend_from_slice(&bytes);
+            }
+            if sha256_hex(&payload) != index.sha256 {
+                return Err(SyncError::Database(format!(
+                    "分块变更校验失败: {}",
+                    file.key
+                )));
+            }
+            payloads.push((file.key, version, payload));
+        }
+        Ok(payloads)
+    }
+
+    /// 清理本设备早于 `cutoff_version` 的分块变更（索引及其分块），返回删除的事务数
+    pub(super) async fn prune_old_chunked_changes(
+        &self,
+        storage: &dyn CloudStorage,
+        cutoff_version: u64,
+    ) -> Result<usize, SyncError> {
+        let own_prefix = format!("{}/{}/", CHUNKED_CHANGES_PREFIX, self.device_id);
+        let files = storage
+            .list(CHUNKED_CHANGES_PREFIX)
+            .await
+            .map_err(|e| SyncError::Network(format!("列出分块变更失败: {}", e)))?;
+
+        let mut deleted = 0usize;
+        for index_file in files
+            .iter()
+            .filter(|f| f.key.starts_with(&own_prefix) && f.key.ends_with(INDEX_SUFFIX))
+        {
+            let Some(version) = Self::parse_version_from_key(&index_file.key) else {
+                continue;
+            };
+            if version >= cutoff_version {
+                continue;
+            }
+            // 索引文件名为 {version}-{transaction_id}.index.json
+            let Some(transaction_id) = index_file
+                .key
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.strip_suffix(INDEX_SUFFIX))
+                .and_then(|stem| stem.split_once('-'))
+                .map(|(_, tx)| tx)
+            else {
+                continue;
+            };
+            let chunk_prefix = format!("{}{}/", own_prefix, transaction_id);
+            // 先删索引，使其他设备不再读取该事务
+            if let Err(e) = storage.delete(&index_file.key).await {
+                tracing::warn!("[sync] 清理分块索引失败（跳过）: {}: {}", index_file.key, e);
+                continue;
+            }
+            for chunk in files.iter().filter(|f| f.key.starts_with(&chunk_prefix)) {
+                if let Err(e) = storage.delete(&chunk.key).await {
+                    tracing::warn!("[sync] 清理变更分块失败（跳过）: {}: {}", chunk.key, e);
+                }
+            }
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloud_storage::FileInfo;
+    use crate::models::AppError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// 内存存储；`fail_after` 次分块写入成功后，后续分块写入失败
+    #[derive(Default)]
+    struct MemoryStorage {
+        files: Mutex<HashMap<String, Vec<u8>>>,
+        chunk_puts: AtomicUsize,
+        fail_after: Option<usize>,
+    }
+
+    #[async_trait::async_trait]
+    impl CloudStorage for MemoryStorage {
+        fn provider_name(&self) -> &'static str {
+            "memory"
+        }
+
+        async fn check_connection(&self) -> crate::cloud_storage::Result<()> {
+            Ok(())
+        }
+
+        async fn put(&self, key: &str, data: &[u8]) -> crate::cloud_storage::Result<()> {
+            if key.ends_with(".part") {
+                let n = self.chunk_puts.fetch_add(1, Ordering::SeqCst);
+                if self.fail_after.is_some_and(|limit| n >= limit) {
+                    return Err(AppError::network("模拟网络中断"));
+                }
+            }
+            self.files
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        async fn get(&self, key: &str) -> crate::cloud_storage::Result<Option<Vec<u8>>> {
+            Ok(self.files.lock().unwrap().get(key).cloned())
+        }
+
+        async fn list(&self, prefix: &str) -> crate::cloud_storage::Result<Vec<FileInfo>> {
+            Ok(self
+                .files
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .map(|(key, data)| FileInfo {
+                    key: key.clone(),
+                    size: data.len() as u64,
+                    last_modified: chrono::Utc::now(),
+                    etag: None,
+                })
+                .collect())
+        }
+
+        async fn delete(&self, key: &str) -> crate::cloud_storage::Result<()> {
+            self.files.lock().unwrap().remove(key);
+            Ok(())
+        }
+
+        async fn stat(&self, key: &str) -> crate::cloud_storage::Result<Option<FileInfo>> {
+            Ok(self.list(key).await?.into_iter().find(|f| f.key == key))
+        }
+    }
+
+    fn changes(count: usize) -> Vec<SyncChangeWithData> {
+        (0..count)
+            .map(|i| SyncChangeWithData {
+                table_name: "notes".to_string(),
+                record_id: format!("note-{i}"),
+                operation: super::super::ChangeOperation::Insert,
+                // 高熵内容，保证压缩后仍跨越多个分块
+                data: Some(serde_json::json!({ "body": sha256_hex(i.to_string().as_bytes()) })),
+                changed_at: "2026-01-01T00:00:00Z".to_string(),
+                change_log_id: Some(i as i64 + 1),
+                database_name: Some("vfs".to_string()),
+                suppress_change_log: None,
+            })
+            .collect()
+    }
+
+    const OPTIONS: ChunkedUploadOptions = ChunkedUploadOptions {
+        chunk_size: 1024,
+        max_concurrency: 2,
+    };
+
+    #[tokio::test]
+    async fn interrupted_upload_resumes_missing_chunks_only() {
+        let staging = tempfile::tempdir().unwrap();
+        let storage = MemoryStorage {
+            fail_after: Some(2),
+            ..Default::default()
+        };
+        let uploader = SyncManager::new("device-a".to_string());
+        let changes = changes(200);
+
+        let err = uploader
+            .upload_enriched_changes_resumable(&storage, &changes, staging.path(), &OPTIONS, None)
+            .await;
+        assert!(err.is_err());
+        let checkpoint = load_checkpoint(staging.path()).expect("checkpoint kept");
+        assert_eq!(checkpoint.confirmed_chunks.len(), 2);
+        assert!(checkpoint.chunk_count() > 3);
+
+        let storage = MemoryStorage {
+            files: Mutex::new(storage.files.into_inner().unwrap()),
+            ..Default::default()
+        };
+        let resumed = uploader
+            .resume_interrupted_upload(&storage, staging.path(), &OPTIONS, None)
+            .await
+            .unwrap()
+            .expect("transaction resumed");
+        assert_eq!(resumed.len(), changes.len());
+        assert_eq!(
+            storage.chunk_puts.load(Ordering::SeqCst),
+            checkpoint.chunk_count() - 2
+        );
+        assert!(load_checkpoint(staging.path()).is_none());
+        assert!(exclude_uploaded(&changes, &resumed).is_empty());
+
+        let downloader = SyncManager::new("device-b".to_string());
+        let payloads = downloader
+            .download_chunked_changes(&storage, 0)
+            .await
+            .unwrap();
+        assert_eq!(payloads.len(), 1);
+        let mut downloaded = Vec::new();
+        let (key, version, data) = &payloads[0];
+        assert!(SyncManager::collect_changes_from_file(
+            key,
+            *version,
+            data,
+            None,
+            &mut downloaded
+        ));
+        assert_eq!(downloaded.len(), changes.len());
+    }
+
+    #[tokio::test]
+    async fn legacy_peer_falls_back_to_single_change_file() {
+        let staging = tempfile::tempdir().unwrap();
+        let storage = MemoryStorage::default();
+        // 旧版客户端的清单没有 capabilities 字段
+        storage
+            .put(
+                "data_governance/manifests/device-old.json",
+                br#"{"sync_transaction_id":"tx","databases":{},"status":"Complete",
+                     "created_at":"2026-01-01T00:00:00Z","device_id":"device-old"}"#,
+            )
+            .await
+            .unwrap();
+        let manager = SyncManager::new("device-a".to_string());
+
+        manager
+            .upload_enriched_changes_resumable(
+                &storage,
+                &changes(200),
+                staging.path(),
+                &OPTIONS,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(storage.chunk_puts.load(Ordering::SeqCst), 0);
+        assert_eq!(storage.list(CHUNKED_CHANGES_PREFIX).await.unwrap().len(), 0);
+        assert_eq!(
+            storage
+                .list("data_governance/changes/device-a")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // 所有设备升级后恢复分块上传
+        let upgraded = SyncManager::new("device-old".to_string()).create_manifest(HashMap::new());
+        storage
+            .put(
+                "data_governance/manifests/device-old.json",
+                &serde_json::to_vec(&upgraded).unwrap(),
+            )
+            .await
+            .unwrap();
+        manager
+            .upload_enriched_changes_resumable(
+                &storage,
+                &changes(200),
+                staging.path(),
+                &OPTIONS,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(storage.chunk_puts.load(Ordering::SeqCst) > 0);
+    }
+
+    #[tokio::test]
+    async fn small_payload_uses_single_change_file() {
+        let staging = tempfile::tempdir().unwrap();
+        let storage = MemoryStorage::default();
+        let manager = SyncManager::new("device-a".to_string());
+        let options = ChunkedUploadOptions {
+            chunk_size: 1024 * 1024,
+            max_concurrency: 2,
+        };
+
+        manager
+            .upload_enriched_changes_resumable(
+                &storage,
+                &changes(3),
+                staging.path(),
+                &options,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(storage.chunk_puts.load(Ordering::SeqCst), 0);
+        assert!(!staging.path().join(CHECKPOINT_FILE).exists());
+        assert_eq!(storage.list(CHUNKED_CHANGES_PREFIX).await.unwrap().len(), 0);
+    }
+}
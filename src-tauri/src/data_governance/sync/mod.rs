@@ -27,6 +27,7 @@
 //! - `merge`: 合并策略
 //! - `progress`: 同步进度管理
 //! - `emitter`: 进度事件发射器
+//! - `chunked_upload`: 大批量变更的分块续传上传
 //!
 //! ## 云存储集成
 //!
@@ -37,10 +38,12 @@
 //! - 进度回调和实时状态更新
 
 // 子模块声明
+pub mod chunked_upload;
 pub mod emitter;
 pub mod progress;
 
 // 重新导出常用类型
+pub use chunked_upload::{exclude_uploaded, ChangeRef, ChunkedUploadOptions, UPLOAD_STAGING_DIR};
 pub use emitter::{OptionalEmitter, SyncProgressCallback, SyncProgressEmitter, EVENT_NAME};
 pub use progress::{ProgressTracker, SpeedCalculator, SyncPhase, SyncProgress};
 
@@ -115,6 +118,21 @@ pub struct SyncManifest {
     pub created_at: String,
     /// 设备 ID
     pub device_id: String,
+    /// 客户端支持的同步能力（如 [`chunked_upload::CHUNKED_CHANGES_CAPABILITY`]）；
+    /// 旧版客户端的清单中缺失，视为不支持任何能力
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl SyncManifest {
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+}
+
+/// 本客户端支持的同步能力
+fn local_capabilities() -> Vec<String> {
+    vec![chunked_upload::CHUNKED_CHANGES_CAPABILITY.to_string()]
 }
 
 /// 数据库同步状态
@@ -646,6 +664,7 @@ impl SyncManager {
             status: SyncTransactionStatus::Complete,
             created_at: chrono::Utc::now().to_rfc3339(),
             device_id: self.device_id.clone(),
+            capabilities: local_capabilities(),
         }
     }
 
@@ -719,6 +738,8 @@ impl SyncManager {
         let mut any_found = false;
         let mut latest_created_at: Option<chrono::DateTime<chrono::Utc>> = None;
         let mut latest_created_at_raw = String::new();
+        // 所有其他设备共同支持的能力
+        let mut peer_capabilities: Option<Vec<String>> = None;
 
         for file in &files {
             let file_device_id = file
@@ -741,6 +762,13 @@ impl SyncManager {
                     SyncError::Database(format!("解析设备清单失败 {}: {}", file.key, e))
                 })?;
                 any_found = true;
+                peer_capabilities = Some(match peer_capabilities {
+                    None => manifest.capabilities.clone(),
+                    Some(common) => common
+                        .into_iter()
+                        .filter(|c| manifest.supports(c))
+                        .collect(),
+                });
                 if let Some(dt) = Self::parse_flexible_timestamp(&manifest.created_at) {
                     if latest_created_at.map_or(true, |prev| dt > prev) {
                         latest_created_at = Some(dt);
@@ -793,6 +821,8 @@ impl SyncManager {
                 status: SyncTransactionStatus::Complete,
                 created_at: chrono::Utc::now().to_rfc3339(),
                 device_id: String::new(),
+                // 没有其他设备，不存在读不懂新格式的客户端
+                capabilities: local_capabilities(),
             });
         }
 
@@ -812,6 +842,7 @@ impl SyncManager {
                 latest_created_at_raw
             },
             device_id: "merged".to_string(),
+            capabilities: peer_capabilities.unwrap_or_default(),
         })
     }
 
@@ -879,10 +910,13 @@ impl SyncManager {
             return Ok(());
         }
 
-        // 版本使用秒级时间戳，与 legacy 文件同一版本空间
-        let version = chrono::Utc::now().timestamp() as u64;
-        let key = self.build_change_key(version);
+        let compressed = self.encode_changes_payload(changes)?;
+        self.upload_encoded_changes(storage, compressed, changes.len(), progress)
+            .await
+    }
 
+    /// 将变更序列化为 v2 载荷（紧凑 JSON + Zstd 压缩）
+    fn encode_changes_payload(&self, changes: &[SyncChangeWithData]) -> Result<Vec<u8>, SyncError> {
         // 序列化为带完整数据的新格式
         let payload = SyncChangesPayload {
             changes: changes.to_vec(),
@@ -897,11 +931,22 @@ impl SyncManager {
             .map_err(|e| SyncError::Database(format!("序列化变更数据失败: {}", e)))?;
 
         // 2. Compress using Zstd (default level 0 is usually 3)
-        let compressed = zstd::stream::encode_all(std::io::Cursor::new(json), 0)
-            .map_err(|e| SyncError::Database(format!("压缩变更数据失败: {}", e)))?;
+        zstd::stream::encode_all(std::io::Cursor::new(json), 0)
+            .map_err(|e| SyncError::Database(format!("压缩变更数据失败: {}", e)))
+    }
 
+    /// 以单个变更文件上传已压缩的载荷
+    async fn upload_encoded_changes(
+        &self,
+        storage: &dyn CloudStorage,
+        compressed: Vec<u8>,
+        total_count: usize,
+        progress: Option<Box<dyn Fn(u64, u64) + Send + Sync>>,
+    ) -> Result<(), SyncError> {
+        // 版本使用秒级时间戳，与 legacy 文件同一版本空间
+        let version = chrono::Utc::now().timestamp() as u64;
+        let key = self.build_change_key(version);
         let compressed_size = compressed.len();
-        let total_count = payload.total_count;
 
         if let Some(cb) = progress {
             // 有进度回调：写入临时文件，通过 put_file 流式上传以实时汇报字节进度
@@ -929,11 +974,10 @@ impl SyncManager {
         }
 
         tracing::info!(
-            "[sync] 带完整数据的变更已上传(Compressed): device={}, count={}, key={}, original_size={}, compressed_size={}",
+            "[sync] 带完整数据的变更已上传(Compressed): device={}, count={}, key={}, compressed_size={}",
             self.device_id,
-            changes.len(),
-            key,
             total_count,
+            key,
             compressed_size
         );
 
@@ -988,55 +1032,36 @@ impl SyncManager {
                         .await
                         .map_err(|e| SyncError::Network(format!("下载变更文件失败: {}", e)))?
                     {
-                        let decoded_data = zstd::stream::decode_all(std::io::Cursor::new(&data))
-                            .unwrap_or_else(|_| data.clone());
-
-                        if let Ok(payload) =
-                            serde_json::from_slice::<SyncChangesPayload>(&decoded_data)
-                        {
-                            tracing::debug!(
-                                "[sync] 下载变更文件(v2): key={}, count={}",
-                                file.key,
-                                payload.total_count
-                            );
-                            for change in payload.changes {
-                                if let Some(db) = change.database_name.as_deref() {
-                                    if let Some(db_since) = per_db_since.and_then(|m| m.get(db)) {
-                                        if version < *db_since {
-                                            continue;
-                                        }
-                                    }
-                                }
-                                all_changes.push(change);
-                            }
-                        } else if let Ok(changes) =
-                            serde_json::from_slice::<PendingChanges>(&decoded_data)
-                        {
-                            tracing::warn!(
-                                "[sync] 下载变更文件(v1/旧格式，数据不完整): key={}, count={}",
-                                file.key,
-                                changes.total_count
-                            );
-                            for entry in &changes.entries {
-                                let change = SyncChangeWithData::from_entry(entry);
-                                if let Some(db) = change.database_name.as_deref() {
-                                    if let Some(db_since) = per_db_since.and_then(|m| m.get(db)) {
-                                        if version < *db_since {
-                                            continue;
-                                        }
-                                    }
-                                }
-                                all_changes.push(change);
-                            }
-                        } else {
+                        if !Self::collect_changes_from_file(
+                            &file.key,
+                            version,
+                            &data,
+                            per_db_since,
+                            &mut all_changes,
+                        ) {
                             decode_failures.push(file.key.clone());
-                            tracing::error!("[sync] 无法解析变更文件: key={}", file.key);
                         }
                     }
                 }
             }
         }
 
+        // 分块上传的变更（已校验并拼装为完整载荷）
+        let chunked = self
+            .download_chunked_changes(storage, since_version)
+            .await?;
+        for (key, version, data) in chunked {
+            if !Self::collect_changes_from_file(
+                &key,
+                version,
+                &data,
+                per_db_since,
+                &mut all_changes,
+            ) {
+                decode_failures.push(key);
+            }
+        }
+
         if !decode_failures.is_empty() {
             let samples = decode_failures
                 .iter()
@@ -1080,6 +1105,58 @@ impl SyncManager {
         Ok(all_changes)
     }
 
+    /// 解码一个变更文件（新旧两种格式）并按库水位过滤后追加到 `all_changes`，
+    /// 无法解析时返回 false
+    fn collect_changes_from_file(
+        key: &str,
+        version: u64,
+        data: &[u8],
+        per_db_since: Option<&HashMap<String, u64>>,
+        all_changes: &mut Vec<SyncChangeWithData>,
+    ) -> bool {
+        let decoded_data =
+            zstd::stream::decode_all(std::io::Cursor::new(data)).unwrap_or_else(|_| data.to_vec());
+
+        if let Ok(payload) = serde_json::from_slice::<SyncChangesPayload>(&decoded_data) {
+            tracing::debug!(
+                "[sync] 下载变更文件(v2): key={}, count={}",
+                key,
+                payload.total_count
+            );
+            for change in payload.changes {
+                if let Some(db) = change.database_name.as_deref() {
+                    if let Some(db_since) = per_db_since.and_then(|m| m.get(db)) {
+                        if version < *db_since {
+                            continue;
+                        }
+                    }
+                }
+                all_changes.push(change);
+            }
+        } else if let Ok(changes) = serde_json::from_slice::<PendingChanges>(&decoded_data) {
+            tracing::warn!(
+                "[sync] 下载变更文件(v1/旧格式，数据不完整): key={}, count={}",
+                key,
+                changes.total_count
+            );
+            for entry in &changes.entries {
+                let change = SyncChangeWithData::from_entry(entry);
+                if let Some(db) = change.database_name.as_deref() {
+                    if let Some(db_since) = per_db_since.and_then(|m| m.get(db)) {
+                        if version < *db_since {
+                            continue;
+                        }
+                    }
+                }
+                all_changes.push(change);
+            }
+        } else {
+            tracing::error!("[sync] 无法解析变更文件: key={}", key);
+            return false;
+        }
+        true
+    }
+
     /// 判断变更文件是否属于本设备
     fn is_own_change_file(key: &str, self_device_id: &str) -> bool {
         // 路径: data_governance/changes/{device_id}/{version}-{nonce}.json[.zst]
@@ -1177,6 +1254,14 @@ impl SyncManager {
             }
         }
 
+        match self
+            .prune_old_chunked_changes(storage, cutoff_version)
+            .await
+        {
+            Ok(count) => deleted += count,
+            Err(e) => tracing::warn!("[sync] 清理分块变更失败（跳过）: {}", e),
+        }
+
         if deleted > 0 {
             tracing::info!(
                 "[sync] 云端变更文件清理完成: 删除 {} 个本设备旧文件（保留 {} 天内）",
@@ -1315,13 +1400,7 @@ impl SyncManager {
         }
 
         // 4. 下载变更数据
-        // 使用最小数据版本作为文件级过滤，并按库进一步过滤
-        let per_db_since: HashMap<String, u64> = local_manifest
-            .databases
-            .iter()
-            .map(|(name, state)| (name.clone(), state.data_version))
-            .collect();
-        let since_version = per_db_since.values().min().copied().unwrap_or(0);
+        let (since_version, per_db_since) = Self::download_watermarks(local_manifest);
 
         let changes = self
             .download_changes(storage, since_version, Some(&per_db_since))
@@ -1347,6 +1426,43 @@ impl SyncManager {
         ))
     }
 
+    /// 增量下载水位线：最小数据版本作为文件级过滤，各库的数据版本用于按库进一步过滤
+    fn download_watermarks(local_manifest: &SyncManifest) -> (u64, HashMap<String, u64>) {
+        let per_db_since: HashMap<String, u64> = local_manifest
+            .databases
+            .iter()
+            .map(|(name, state)| (name.clone(), state.data_version))
+            .collect();
+        let since_version = per_db_since.values().min().copied().unwrap_or(0);
+        (since_version, per_db_since)
+    }
+
+    /// 预演下载：与 `execute_download` 使用相同的清单比较与增量水位线，
+    /// 只读取云端清单和变更文件，不应用、不上传任何内容
+    ///
+    /// 需要迁移（Schema 不一致）时不再读取变更文件，由调用方提示用户先迁移。
+    pub async fn preview_download(
+        &self,
+        storage: &dyn CloudStorage,
+        local_manifest: &SyncManifest,
+    ) -> Result<(ConflictDetectionResult, Vec<SyncChangeWithData>), SyncError> {
+        let cloud_manifest = self.download_manifest(storage).await?;
+        if cloud_manifest.sync_transaction_id.is_empty() {
+            return Ok((ConflictDetectionResult::empty(), vec![]));
+        }
+
+        let detection = Self::detect_conflicts(local_manifest, &cloud_manifest)?;
+        if detection.needs_migration {
+            return Ok((detection, vec![]));
+        }
+
+        let (since_version, per_db_since) = Self::download_watermarks(local_manifest);
+        let changes = self
+            .download_changes(storage, since_version, Some(&per_db_since))
+            .await?;
+        Ok((detection, changes))
+    }
+
     /// 执行双向同步流程
     ///
     /// 1. 先执行下载同步
@@ -2994,6 +3110,112 @@ impl SyncDirection {
     }
 }
 
+/// 同步预演中单张表的统计（按记录去重）
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SyncTablePreview {
+    /// 数据库名称（旧格式变更文件可能缺失）
+    pub database_name: Option<String>,
+    /// 表名
+    pub table_name: String,
+    /// 将上传的记录数
+    pub to_upload: usize,
+    /// 将下载的记录数
+    pub to_download: usize,
+    /// 本地与云端都有改动的记录数（同时计入上传与下载）
+    pub conflicts: usize,
+}
+
+/// 同步预演中的冲突记录
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SyncRecordRef {
+    /// 数据库名称
+    pub database_name: Option<String>,
+    /// 表名
+    pub table_name: String,
+    /// 记录 ID
+    pub record_id: String,
+}
+
+impl SyncRecordRef {
+    fn of(change: &SyncChangeWithData) -> Self {
+        Self {
+            database_name: change.database_name.clone(),
+            table_name: change.table_name.clone(),
+            record_id: change.record_id.clone(),
+        }
+    }
+}
+
+/// 同步预演结果：按表统计将上传、下载与冲突的记录
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncPreview {
+    /// 各表统计，按数据库、表名排序
+    pub tables: Vec<SyncTablePreview>,
+    /// 冲突记录列表
+    pub conflicting_records: Vec<SyncRecordRef>,
+}
+
+impl SyncPreview {
+    /// 由本地待上传变更与云端待下载变更计算预演结果
+    ///
+    /// 同一记录的多条变更只计一次；冲突指两侧都改动过的记录。仅下载时本地改动不上传，
+    /// 但仍列出会被云端覆盖的冲突记录；仅上传时不读取云端变更，因此没有冲突。
+    pub fn build(
+        direction: SyncDirection,
+        local: &[SyncChangeWithData],
+        remote: &[SyncChangeWithData],
+    ) -> Self {
+        use std::collections::{BTreeMap, BTreeSet};
+
+        let records = |changes: &[SyncChangeWithData]| -> BTreeSet<SyncRecordRef> {
+            changes.iter().map(SyncRecordRef::of).collect()
+        };
+        let local = records(local);
+        let remote = records(remote);
+
+        type Tables = BTreeMap<(Option<String>, String), SyncTablePreview>;
+        fn table<'a>(tables: &'a mut Tables, record: &SyncRecordRef) -> &'a mut SyncTablePreview {
+            tables
+                .entry((record.database_name.clone(), record.table_name.clone()))
+                .or_insert_with(|| SyncTablePreview {
+                    database_name: record.database_name.clone(),
+                    table_name: record.table_name.clone(),
+                    ..Default::default()
+                })
+        }
+
+        let mut tables = Tables::new();
+        if direction != SyncDirection::Download {
+            for record in &local {
+                table(&mut tables, record).to_upload += 1;
+            }
+        }
+        if direction != SyncDirection::Upload {
+            for record in &remote {
+                table(&mut tables, record).to_download += 1;
+            }
+        }
+        let conflicting_records: Vec<SyncRecordRef> =
+            local.intersection(&remote).cloned().collect();
+        for record in &conflicting_records {
+            table(&mut tables, record).conflicts += 1;
+        }
+
+        Self {
+            tables: tables.into_values().collect(),
+            conflicting_records,
+        }
+    }
+
+    pub fn total_upload(&self) -> usize {
+        self.tables.iter().map(|t| t.to_upload).sum()
+    }
+
+    pub fn total_download(&self) -> usize {
+        self.tables.iter().map(|t| t.to_download).sum()
+    }
+}
+
 /// 同步执行结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncExecutionResult {
@@ -3385,6 +3607,7 @@ mod tests {
             status: SyncTransactionStatus::Complete,
             created_at: "2024-01-01T00:00:00Z".to_string(),
             device_id: device_id.to_string(),
+            capabilities: Vec::new(),
         }
     }
 
@@ -4350,4 +4573,57 @@ mod tests {
             .unwrap();
         assert_eq!(unsynced, 0, "echo logs should be marked as synced");
     }
+
+    fn preview_change(db: &str, table: &str, record_id: &str) -> SyncChangeWithData {
+        SyncChangeWithData {
+            table_name: table.to_string(),
+            record_id: record_id.to_string(),
+            operation: ChangeOperation::Update,
+            data: None,
+            changed_at: "2026-02-10T00:00:00Z".to_string(),
+            change_log_id: None,
+            database_name: Some(db.to_string()),
+            suppress_change_log: None,
+        }
+    }
+
+    #[test]
+    fn test_sync_preview_counts_per_table_and_conflicts() {
+        let local = vec![
+            preview_change("vfs", "notes", "n1"),
+            preview_change("vfs", "notes", "n1"),
+            preview_change("vfs", "notes", "n2"),
+            preview_change("chat_v2", "messages", "m1"),
+        ];
+        let remote = vec![
+            preview_change("vfs", "notes", "n2"),
+            preview_change("vfs", "notes", "n3"),
+            preview_change("mistakes", "mistakes", "x1"),
+        ];
+
+        let preview = SyncPreview::build(SyncDirection::Bidirectional, &local, &remote);
+        let notes = preview
+            .tables
+            .iter()
+            .find(|t| t.table_name == "notes")
+            .unwrap();
+        assert_eq!(
+            (notes.to_upload, notes.to_download, notes.conflicts),
+            (2, 2, 1)
+        );
+        assert_eq!(preview.tables.len(), 3);
+        assert_eq!(preview.total_upload(), 3);
+        assert_eq!(preview.total_download(), 3);
+        assert_eq!(
+            preview.conflicting_records,
+            vec![SyncRecordRef::of(&preview_change("vfs", "notes", "n2"))]
+        );
+
+        let download_only = SyncPreview::build(SyncDirection::Download, &local, &remote);
+        assert_eq!(download_only.total_upload(), 0);
+        assert_eq!(download_only.conflicting_records.len(), 1);
+        let upload_only = SyncPreview::build(SyncDirection::Upload, &local, &[]);
+        assert_eq!(upload_only.total_download(), 0);
+        assert!(upload_only.conflicting_records.is_empty());
+    }
 }
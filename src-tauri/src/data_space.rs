@@ -1,6 +1,7 @@
 use crate::backup_common::{check_disk_space, copy_directory_safe, log_and_skip_entry_err};
 use crate::models::AppError;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
@@ -59,6 +60,25 @@ impl Default for SlotState {
     }
 }
 
+/// 插槽的外部存放位置（数据目录迁移后写入）
+///
+/// 与 state.json 分开保存：state.json 损坏后按目录推断活跃空间时，迁移记录不会随之丢失。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SlotLocations {
+    /// 插槽名 -> 外部目录；未记录的插槽位于 `slots/<slot>`
+    #[serde(default)]
+    dirs: BTreeMap<String, String>,
+    /// 已复制并校验、等待下次启动生效的迁移
+    #[serde(default)]
+    pending: Option<PendingRelocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingRelocation {
+    slot: String,
+    dir: String,
+}
+
 pub struct DataSpaceManager {
     base_dir: PathBuf,
 }
@@ -74,10 +94,52 @@ impl DataSpaceManager {
     fn state_path(&self) -> PathBuf {
         self.slots_dir().join("state.json")
     }
+    fn locations_path(&self) -> PathBuf {
+        self.slots_dir().join("locations.json")
+    }
+    /// 插槽的实际目录：迁移到外部目录的插槽返回迁移后的位置
     pub fn slot_dir(&self, slot: Slot) -> PathBuf {
+        self.slot_location(slot)
+            .unwrap_or_else(|| self.default_slot_dir(slot))
+    }
+    fn default_slot_dir(&self, slot: Slot) -> PathBuf {
         self.slots_dir().join(slot.name())
     }
 
+    /// 插槽迁移后的外部目录；未迁移时为 None
+    pub fn slot_location(&self, slot: Slot) -> Option<PathBuf> {
+        self.read_locations()
+            .dirs
+            .get(slot.name())
+            .map(PathBuf::from)
+    }
+
+    /// 等待下次启动生效的数据目录迁移（插槽, 目标目录）
+    pub fn pending_relocation(&self) -> Option<(Slot, PathBuf)> {
+        let pending = self.read_locations().pending?;
+        Some((Slot::from_name(&pending.slot)?, PathBuf::from(pending.dir)))
+    }
+
+    fn read_locations(&self) -> SlotLocations {
+        match fs::read_to_string(self.locations_path()) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                error!("[DataSpace] locations.json 解析失败，按默认位置处理: {}", e);
+                SlotLocations::default()
+            }),
+            Err(_) => SlotLocations::default(),
+        }
+    }
+
+    fn write_locations(&self, locations: &SlotLocations) -> std::io::Result<()> {
+        let content = serde_json::to_string_pretty(locations).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("序列化插槽位置失败: {}", e),
+            )
+        })?;
+        self.atomic_write_file(&self.locations_path(), &content)
+    }
+
     /// 获取测试插槽目录
     pub fn test_slot_dir(&self, slot: Slot) -> PathBuf {
         assert!(slot.is_test_slot(), "只能获取测试插槽 C/D 的目录");
@@ -467,10 +529,14 @@ impl DataSpaceManager {
 
     /// 原子写入 state.json：先写临时文件并 fsync，再 rename 替换，防止崩溃/断电导致文件损坏
     fn atomic_write_state_file(&self, content: &str) -> std::io::Result<()> {
+        self.atomic_write_file(&self.state_path(), content)
+    }
+
+    /// 原子写入 slots 目录下的状态文件，临时文件为 `<文件名>.tmp`
+    fn atomic_write_file(&self, target: &Path, content: &str) -> std::io::Result<()> {
         use std::io::Write;
 
-        let target = self.state_path();
-        let tmp = self.slots_dir().join("state.json.tmp");
+        let tmp = PathBuf::from(format!("{}.tmp", target.display()));
 
         // 1. 写入临时文件
         {
@@ -481,7 +547,7 @@ impl DataSpaceManager {
         }
 
         // 3. 原子性 rename：在 POSIX 系统上 rename 是原子操作
-        fs::rename(&tmp, &target)?;
+        fs::rename(&tmp, target)?;
 
         // 4. fsync 父目录，确保目录条目更新持久化（防止断电后目录项丢失）
         #[cfg(unix)]
@@ -498,6 +564,7 @@ impl DataSpaceManager {
         if self.ensure_layout().is_err() {
             return;
         }
+        self.apply_pending_relocation();
         if let Ok(mut st) = self.read_state() {
             if let Some(pending) = st.pending.take() {
                 // 在应用 pending 切换之前，验证目标 slot 目录有效
@@ -588,6 +655,78 @@ impl DataSpaceManager {
         self.write_state(&st)
     }
 
+    /// 标记下次启动时把插槽迁移到 `dir`（调用方需已完成复制与校验）
+    ///
+    /// 生效前启动流程会再次校验副本，通过后才切换位置并删除旧目录中的数据。
+    pub fn mark_pending_relocation(&self, slot: Slot, dir: &Path) -> std::io::Result<()> {
+        if slot.is_test_slot() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "测试插槽不支持迁移",
+            ));
+        }
+        if !Self::dir_has_data(dir) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("迁移目标 {} 为空，无法标记迁移", dir.display()),
+            ));
+        }
+        let mut locations = self.read_locations();
+        locations.pending = Some(PendingRelocation {
+            slot: slot.name().to_string(),
+            dir: dir.to_string_lossy().to_string(),
+        });
+        info!(
+            "[DataSpace] 标记下次启动将 {} 迁移到 {}",
+            slot.name(),
+            dir.display()
+        );
+        self.write_locations(&locations)
+    }
+
+    /// 启动时应用待生效的迁移：重新校验副本，通过后记录新位置，再清空旧目录
+    ///
+    /// 校验失败时放弃本次迁移，继续使用原位置，不删除任何数据。
+    fn apply_pending_relocation(&self) {
+        let mut locations = self.read_locations();
+        let Some(pending) = locations.pending.take() else {
+            return;
+        };
+        let Some(slot) = Slot::from_name(&pending.slot) else {
+            error!("[DataSpace] 待迁移插槽名称无效: {}，取消迁移", pending.slot);
+            let _ = self.write_locations(&locations);
+            return;
+        };
+        let old_dir = self.slot_dir(slot);
+        let new_dir = PathBuf::from(&pending.dir);
+        if let Err(e) = verify_slot_copy(&old_dir, &new_dir) {
+            error!(
+                "[DataSpace] 迁移副本校验未通过，继续使用原位置 {}: {}",
+                old_dir.display(),
+                e
+            );
+            if let Err(e) = self.write_locations(&locations) {
+                error!("[DataSpace] 清除待迁移记录失败: {}", e);
+            }
+            return;
+        }
+
+        locations.dirs.insert(slot.name().to_string(), pending.dir);
+        if let Err(e) = self.write_locations(&locations) {
+            error!("[DataSpace] 保存迁移位置失败，继续使用原位置: {}", e);
+            return;
+        }
+        info!(
+            "[DataSpace] {} 已迁移到 {}，清理旧目录 {}",
+            slot.name(),
+            new_dir.display(),
+            old_dir.display()
+        );
+        for warning in remove_dir_contents(&old_dir) {
+            warn!("[DataSpace] {}", warning);
+        }
+    }
+
     // ========================================================================
     // 测试插槽专用方法
     // ========================================================================
@@ -638,6 +777,133 @@ impl DataSpaceManager {
     }
 }
 
+/// 列出目录下所有文件（相对路径 -> 大小），跳过符号链接、SQLite 共享内存文件与空 WAL
+///
+/// 以只读方式打开 WAL 模式的数据库做完整性检查时会生成 `-shm` 与空的 `-wal`，
+/// 二者不含数据，不参与比对。
+fn list_files(root: &Path) -> std::io::Result<BTreeMap<PathBuf, u64>> {
+    let mut files = BTreeMap::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_symlink() {
+                continue;
+            }
+            if file_type.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            let name = path.to_string_lossy();
+            let len = entry.metadata()?.len();
+            if name.ends_with("-shm") || (name.ends_with("-wal") && len == 0) {
+                continue;
+            }
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            files.insert(relative, len);
+        }
+    }
+    Ok(files)
+}
+
+/// 把插槽目录中的全部内容（数据库、`lance/`、`images/`、`vfs_blobs/` 等）复制到 `dst`
+pub(crate) fn copy_slot_contents(src: &Path, dst: &Path) -> Result<u64, AppError> {
+    fs::create_dir_all(dst)
+        .map_err(|e| AppError::file_system(format!("创建目标目录失败: {}", e)))?;
+    let entries =
+        fs::read_dir(src).map_err(|e| AppError::file_system(format!("读取数据目录失败: {}", e)))?;
+    let mut copied = 0;
+    for entry in entries.filter_map(log_and_skip_entry_err) {
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .map_err(|e| AppError::file_system(format!("读取文件类型失败 {:?}: {}", path, e)))?;
+        if file_type.is_symlink() {
+            warn!("[DataSpace] 跳过符号链接: {:?}", path);
+        } else if file_type.is_dir() {
+            copied += copy_directory_safe(&path, dst)?;
+        } else {
+            copied += fs::copy(&path, dst.join(entry.file_name()))
+                .map_err(|e| AppError::file_system(format!("复制文件失败 {:?}: {}", path, e)))?;
+        }
+    }
+    Ok(copied)
+}
+
+/// 校验插槽副本：文件清单与大小一致，且所有 SQLite 数据库通过完整性检查
+pub(crate) fn verify_slot_copy(src: &Path, dst: &Path) -> Result<(), AppError> {
+    let read = |dir: &Path| {
+        list_files(dir)
+            .map_err(|e| AppError::file_system(format!("读取目录 {} 失败: {}", dir.display(), e)))
+    };
+    let (src_files, dst_files) = (read(src)?, read(dst)?);
+    if let Some((path, size)) = src_files
+        .iter()
+        .find(|(path, size)| dst_files.get(*path) != Some(size))
+    {
+        return Err(AppError::file_system(format!(
+            "{} 复制不完整: 源 {} 字节，目标 {} 字节",
+            path.display(),
+            size,
+            dst_files.get(path).copied().unwrap_or(0)
+        )));
+    }
+    if let Some(extra) = dst_files.keys().find(|path| !src_files.contains_key(*path)) {
+        return Err(AppError::file_system(format!(
+            "目标目录包含源目录中不存在的文件: {}",
+            extra.display()
+        )));
+    }
+
+    for path in dst_files.keys().filter(|p| {
+        p.extension()
+            .is_some_and(|ext| ext == "db" || ext == "sqlite" || ext == "sqlite3")
+    }) {
+        let conn = rusqlite::Connection::open_with_flags(
+            dst.join(path),
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .map_err(|e| AppError::database(format!("打开 {} 失败: {}", path.display(), e)))?;
+        let result: String = conn
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+            .map_err(|e| AppError::database(format!("{} 完整性检查失败: {}", path.display(), e)))?;
+        if result != "ok" {
+            return Err(AppError::database(format!(
+                "{} 完整性检查未通过: {}",
+                path.display(),
+                result
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 删除目录中的全部内容（保留目录本身），返回遇到的问题
+pub(crate) fn remove_dir_contents(dir: &Path) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return warnings,
+        Err(e) => {
+            warnings.push(format!("读取目录失败 {}: {}", dir.display(), e));
+            return warnings;
+        }
+    };
+    for entry in entries.filter_map(log_and_skip_entry_err) {
+        let path = entry.path();
+        let result = match entry.file_type() {
+            Ok(t) if t.is_dir() => fs::remove_dir_all(&path),
+            _ => fs::remove_file(&path),
+        };
+        if let Err(e) = result {
+            warnings.push(format!("删除失败 {}: {}", path.display(), e));
+        }
+    }
+    warnings
+}
+
 static DATA_SPACE: OnceLock<DataSpaceManager> = OnceLock::new();
 
 pub fn init_data_space_manager(base_dir: PathBuf) {
@@ -1292,6 +1558,78 @@ mod tests {
         assert_eq!(total, 30, "应递归计算总大小 (22 + 8 = 30)");
     }
 
+    /// 辅助：模拟真实的插槽布局（主库、VFS 库、VFS 向量、知识库向量、图片）
+    fn seed_full_slot(dir: &Path) {
+        fs::create_dir_all(dir.join("databases")).unwrap();
+        for db in [
+            dir.join("mistakes.db"),
+            dir.join("databases").join("vfs.db"),
+        ] {
+            let conn = rusqlite::Connection::open(db).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE t (id INTEGER PRIMARY KEY); INSERT INTO t VALUES (1);",
+            )
+            .unwrap();
+        }
+        for (sub, file) in [
+            ("lance/vfs/vfs_chunks.lance", "data.lance"),
+            ("lance/kb", "data"),
+            ("images", "a.png"),
+        ] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            fs::write(dir.join(sub).join(file), b"payload").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_relocation_moves_whole_slot_on_restart() {
+        let (tmp, mgr) = make_manager();
+        mgr.ensure_layout().unwrap();
+        let old_dir = mgr.active_dir();
+        seed_full_slot(&old_dir);
+        let new_dir = tmp.path().join("elsewhere");
+
+        copy_slot_contents(&old_dir, &new_dir).unwrap();
+        verify_slot_copy(&old_dir, &new_dir).unwrap();
+        mgr.mark_pending_relocation(Slot::A, &new_dir).unwrap();
+        // 重启前仍使用旧位置
+        assert_eq!(mgr.active_dir(), old_dir);
+
+        mgr.initialize_on_start();
+        assert_eq!(mgr.active_dir(), new_dir);
+        assert_eq!(mgr.slot_location(Slot::A), Some(new_dir.clone()));
+        assert!(mgr.pending_relocation().is_none());
+        assert!(new_dir
+            .join("lance/vfs/vfs_chunks.lance/data.lance")
+            .exists());
+        assert!(new_dir.join("databases").join("vfs.db").exists());
+        // 旧目录保留但已清空
+        assert!(old_dir.is_dir());
+        assert!(!DataSpaceManager::dir_has_data(&old_dir));
+    }
+
+    #[test]
+    fn test_relocation_keeps_old_data_when_copy_drifted() {
+        let (tmp, mgr) = make_manager();
+        mgr.ensure_layout().unwrap();
+        let old_dir = mgr.active_dir();
+        seed_full_slot(&old_dir);
+        let new_dir = tmp.path().join("elsewhere");
+        copy_slot_contents(&old_dir, &new_dir).unwrap();
+        mgr.mark_pending_relocation(Slot::A, &new_dir).unwrap();
+
+        // 副本中的 VFS 向量丢失
+        fs::remove_dir_all(new_dir.join("lance").join("vfs")).unwrap();
+        assert!(verify_slot_copy(&old_dir, &new_dir).is_err());
+
+        mgr.initialize_on_start();
+        assert_eq!(mgr.active_dir(), old_dir);
+        assert!(mgr.pending_relocation().is_none());
+        assert!(old_dir
+            .join("lance/vfs/vfs_chunks.lance/data.lance")
+            .exists());
+    }
+
     // -----------------------------------------------------------------------
     // 补充: Slot 基础方法测试
     // -----------------------------------------------------------------------
@@ -1,4 +1,6 @@
 mod manager;
+#[cfg(test)]
+pub(crate) mod test_support;
 
 pub use manager::DatabaseManager;
 
@@ -18,6 +20,52 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Mutex, RwLock};
 
+/// settings 键：科目级默认制卡模板映射
+pub const SUBJECT_DEFAULT_TEMPLATES_KEY: &str = "default_template_id_by_subject";
+
+/// 表是否存在；用于兼容尚未执行某些迁移的旧库
+pub(crate) fn table_exists(conn: &Connection, table: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )
+}
+
+/// 表中是否存在指定列；用于兼容尚未执行某些迁移的旧库
+pub(crate) fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    )
+}
+
+/// 错题引用的题目图与分析图路径；彻底删除错题后据此释放去重图片引用
+pub(crate) fn mistake_image_paths(
+    conn: &Connection,
+    mistake_id: &str,
+) -> rusqlite::Result<Vec<String>> {
+    let columns = conn
+        .query_row(
+            "SELECT question_images, analysis_images FROM mistakes WHERE id = ?1",
+            params![mistake_id],
+            |row| {
+                Ok([
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                ])
+            },
+        )
+        .optional()?;
+    Ok(columns
+        .into_iter()
+        .flatten()
+        .flatten()
+        .flat_map(|raw| serde_json::from_str::<Vec<String>>(&raw).unwrap_or_default())
+        .collect())
+}
+
 fn parse_datetime_flexible(datetime_str: &str) -> Result<DateTime<Utc>> {
     if datetime_str.is_empty() {
         return Ok(Utc::now());
@@ -139,6 +187,28 @@ fn parse_image_list(raw_json: Option<String>) -> Option<Vec<String>> {
     raw_json.and_then(|json| serde_json::from_str::<Vec<String>>(&json).ok())
 }
 
+/// 聊天消息快照签名：SHA-256(序列化后的消息列表)，用于自动保存的变更检测
+pub fn chat_messages_signature(messages: &[crate::models::ChatMessage]) -> Result<String> {
+    let serialized = serde_json::to_vec(messages)?;
+    Ok(format!("{:x}", Sha256::digest(&serialized)))
+}
+
+/// 文档附件载荷指纹：SHA-256(text_content || base64_content)
+fn doc_attachment_payload_hash(att: &crate::models::DocumentAttachment) -> String {
+    let mut payload_hasher = Sha256::new();
+    if let Some(text) = &att.text_content {
+        payload_hasher.update(text.as_bytes());
+    }
+    if let Some(b64) = &att.base64_content {
+        payload_hasher.update(b64.as_bytes());
+    }
+    format!("{:x}", payload_hasher.finalize())
+}
+
+fn doc_attachment_has_payload(att: &crate::models::DocumentAttachment) -> bool {
+    att.text_content.is_some() || att.base64_content.is_some()
+}
+
 fn canonicalize_doc_attachments_summary(raw_json: Option<String>) -> Option<String> {
     let docs: Vec<crate::models::DocumentAttachment> = raw_json
         .as_ref()
@@ -150,14 +220,11 @@ fn canonicalize_doc_attachments_summary(raw_json: Option<String>) -> Option<Stri
     let mut entries: Vec<String> = docs
         .iter()
         .map(|att| {
-            let mut payload_hasher = Sha256::new();
-            if let Some(text) = &att.text_content {
-                payload_hasher.update(text.as_bytes());
-            }
-            if let Some(b64) = &att.base64_content {
-                payload_hasher.update(b64.as_bytes());
-            }
-            let digest = format!("{:x}", payload_hasher.finalize());
+            // 已去重存储的附件只保留引用，引用本身就是载荷指纹
+            let digest = match &att.content_hash {
+                Some(hash) if !doc_attachment_has_payload(att) => hash.clone(),
+                _ => doc_attachment_payload_hash(att),
+            };
             format!(
                 "{}|{}|{}|{}",
                 att.name.trim(),
@@ -175,6 +242,136 @@ fn canonicalize_doc_attachments_summary(raw_json: Option<String>) -> Option<Stri
     }
 }
 
+/// 用户提问的 `relations` 被整体改写时保留已记录的当前分支（见 `cmd::mistake_branches`），
+/// 避免前端自动保存的快照覆盖用户的分支选择
+fn preserve_active_branch(
+    conn: &Connection,
+    row_id: i64,
+    incoming: Option<String>,
+) -> Result<Option<String>> {
+    use crate::cmd::mistake_branches::ACTIVE_BRANCH_KEY;
+    let Some(raw) = incoming else {
+        return Ok(None);
+    };
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&raw) else {
+        return Ok(Some(raw));
+    };
+    let Some(obj) = value.as_object_mut() else {
+        return Ok(Some(raw));
+    };
+    if obj.contains_key(ACTIVE_BRANCH_KEY) {
+        return Ok(Some(raw));
+    }
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT relations FROM chat_messages WHERE id = ?1",
+            params![row_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    let recorded = existing
+        .and_then(|raw| serde_json::from_str::<serde_json::Value>(&raw).ok())
+        .and_then(|v| v.get(ACTIVE_BRANCH_KEY).cloned());
+    match recorded {
+        Some(seq) => {
+            obj.insert(ACTIVE_BRANCH_KEY.to_string(), seq);
+            Ok(Some(value.to_string()))
+        }
+        None => Ok(Some(raw)),
+    }
+}
+
+pub(crate) fn ensure_doc_attachment_blobs_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS doc_attachment_blobs (
+            content_hash TEXT PRIMARY KEY NOT NULL,
+            text_content TEXT,
+            base64_content TEXT,
+            created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ','now'))
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// 将附件载荷写入内容寻址表（相同指纹只存一份），返回仅含元数据与 `content_hash` 的附件列表
+fn store_doc_attachment_payloads(
+    conn: &Connection,
+    docs: &[crate::models::DocumentAttachment],
+) -> Result<Vec<crate::models::DocumentAttachment>> {
+    let mut stored = Vec::with_capacity(docs.len());
+    for att in docs {
+        if !doc_attachment_has_payload(att) {
+            stored.push(att.clone());
+            continue;
+        }
+        let hash = doc_attachment_payload_hash(att);
+        conn.execute(
+            "INSERT OR IGNORE INTO doc_attachment_blobs (content_hash, text_content, base64_content) VALUES (?1, ?2, ?3)",
+            params![hash, att.text_content, att.base64_content],
+        )?;
+        stored.push(crate::models::DocumentAttachment {
+            text_content: None,
+            base64_content: None,
+            content_hash: Some(hash),
+            ..att.clone()
+        });
+    }
+    Ok(stored)
+}
+
+/// 按 `content_hash` 从内容寻址表回填附件载荷；找不到载荷的引用保持原样
+fn rehydrate_doc_attachments(
+    conn: &Connection,
+    docs: &mut [crate::models::DocumentAttachment],
+) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT text_content, base64_content FROM doc_attachment_blobs WHERE content_hash = ?1",
+    )?;
+    for att in docs.iter_mut() {
+        if doc_attachment_has_payload(att) {
+            continue;
+        }
+        let Some(hash) = att.content_hash.as_deref() else {
+            continue;
+        };
+        let payload = stmt
+            .query_row(params![hash], |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                ))
+            })
+            .optional()?;
+        match payload {
+            Some((text, b64)) => {
+                att.text_content = text;
+                att.base64_content = b64;
+            }
+            None => log::warn!(
+                "[DocAttachments] 附件载荷缺失: name={}, hash={}",
+                att.name,
+                hash
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// 回填一段 `doc_attachments` JSON 中去重存储的载荷，供读取旧版聊天消息时使用；无法解析的 JSON 原样返回
+pub(crate) fn rehydrate_doc_attachments_json(conn: &Connection, raw: String) -> Result<String> {
+    let Ok(mut docs) = serde_json::from_str::<Vec<crate::models::DocumentAttachment>>(&raw) else {
+        return Ok(raw);
+    };
+    if docs.iter().all(|att| att.content_hash.is_none()) {
+        return Ok(raw);
+    }
+    ensure_doc_attachment_blobs_table(conn)?;
+    rehydrate_doc_attachments(conn, &mut docs)?;
+    Ok(serde_json::to_string(&docs)?)
+}
+
 fn fingerprint_user_row(content: &str, images: Option<&[String]>, doc_fp: Option<&str>) -> String {
     let mut hasher = Sha256::new();
     hasher.update(b"user");
@@ -202,64 +399,182 @@ pub struct ChatHistorySummary {
     pub user_messages: Vec<UserMessageSummary>,
 }
 
+/// 已归档错题摘要
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ArchivedMistakeSummary {
+    pub id: String,
+    pub user_question: String,
+    pub mistake_type: String,
+    pub tags: Vec<String>,
+    pub created_at: String,
+    pub archived_at: String,
+    /// 难度 1-5，未设置为空
+    pub difficulty: Option<u8>,
+    /// 掌握度 0-100，数据库未迁移时为空
+    pub mastery_level: Option<u8>,
+    pub pinned: bool,
+    /// 星级 1-5，未设置为空
+    pub star_rating: Option<u8>,
+}
+
+/// 错题归档统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MistakeArchiveCounts {
+    pub active: i64,
+    pub archived: i64,
+    pub total: i64,
+}
+
+/// 单个错题的回合元数据补齐结果
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TurnPairingCounts {
+    users_assigned: usize,
+    assistants_paired: usize,
+    orphaned_assistants: usize,
+}
+
+/// 为缺少 `turn_id` 的消息补齐回合：user 各自开启新回合，assistant 绑定到最近一个尚无回答的 user 回合
+fn pair_unassigned_turns(conn: &Connection, mistake_id: &str) -> Result<TurnPairingCounts> {
+    let mut counts = TurnPairingCounts::default();
+
+    // 第一步：为所有未配对的 user 分配 turn_id（若缺失）
+    let mut users_stmt = conn.prepare(
+        "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND role = 'user' AND (turn_id IS NULL OR turn_id = '') ORDER BY timestamp ASC",
+    )?;
+    let user_rows = users_stmt
+        .query_map(rusqlite::params![mistake_id], |row| row.get::<_, i64>(0))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    for user_row_id in user_rows {
+        let turn_id = uuid::Uuid::new_v4().to_string();
+        conn.execute(
+            "UPDATE chat_messages SET turn_id = ?1, turn_seq = 0, reply_to_msg_id = NULL, message_kind = COALESCE(message_kind, 'user.input'), lifecycle = NULL WHERE id = ?2",
+            rusqlite::params![turn_id, user_row_id],
+        )?;
+        counts.users_assigned += 1;
+    }
+
+    // 第二步：为所有未配对的 assistant 绑定到最近的用户回合
+    let mut assistants_stmt = conn.prepare(
+        "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND role = 'assistant' AND (turn_id IS NULL OR turn_id = '') ORDER BY timestamp ASC",
+    )?;
+    let assistant_rows = assistants_stmt
+        .query_map(rusqlite::params![mistake_id], |row| row.get::<_, i64>(0))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    for assistant_row_id in assistant_rows {
+        let candidate: Option<(i64, String)> = conn
+            .query_row(
+                "SELECT u.id, u.turn_id \
+                 FROM chat_messages u \
+                 WHERE u.mistake_id = ?1 AND u.role = 'user' AND u.turn_id IS NOT NULL AND u.turn_id <> '' \
+                   AND NOT EXISTS (SELECT 1 FROM chat_messages a WHERE a.mistake_id = ?1 AND a.role = 'assistant' AND a.turn_id = u.turn_id) \
+                 ORDER BY u.timestamp DESC LIMIT 1",
+                rusqlite::params![mistake_id],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()?;
+        if let Some((user_row_id, turn_id)) = candidate {
+            conn.execute(
+                "UPDATE chat_messages SET turn_id = ?1, turn_seq = 1, reply_to_msg_id = ?2, message_kind = COALESCE(message_kind, 'assistant.answer'), lifecycle = COALESCE(lifecycle, 'complete') WHERE id = ?3",
+                rusqlite::params![turn_id, user_row_id, assistant_row_id],
+            )?;
+            counts.assistants_paired += 1;
+        } else {
+            log::warn!(
+                "[回合配对] 发现孤儿助手消息（无可配对的用户消息），mistake_id={}, assistant_row_id={}",
+                mistake_id, assistant_row_id
+            );
+            counts.orphaned_assistants += 1;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// 补齐已有 `turn_id` 但缺少 `turn_seq` 的消息：user 取 0，assistant 取回合内下一个序号
+///
+/// 逐行更新以避开 `(turn_id, turn_seq)` 唯一索引冲突；同一回合已有 0 号消息的 user 行保持不变。
+fn fill_missing_turn_seq(conn: &Connection, mistake_id: &str) -> Result<usize> {
+    let mut stmt = conn.prepare(
+        "SELECT id, role, turn_id FROM chat_messages \
+         WHERE mistake_id = ?1 AND role IN ('user', 'assistant') \
+           AND turn_id IS NOT NULL AND turn_id <> '' AND turn_seq IS NULL \
+         ORDER BY timestamp ASC, id ASC",
+    )?;
+    let rows = stmt
+        .query_map(rusqlite::params![mistake_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let mut fixed = 0usize;
+    for (row_id, role, turn_id) in rows {
+        fixed += if role == "user" {
+            conn.execute(
+                "UPDATE chat_messages SET turn_seq = 0 WHERE id = ?1 \
+                 AND NOT EXISTS (SELECT 1 FROM chat_messages o WHERE o.turn_id = ?2 AND o.turn_seq = 0)",
+                rusqlite::params![row_id, turn_id],
+            )?
+        } else {
+            conn.execute(
+                "UPDATE chat_messages SET turn_seq = \
+                    (SELECT COALESCE(MAX(o.turn_seq), 0) + 1 FROM chat_messages o WHERE o.turn_id = ?2) \
+                 WHERE id = ?1",
+                rusqlite::params![row_id, turn_id],
+            )?
+        };
+    }
+    Ok(fixed)
+}
+
 impl Database {
     fn backfill_turn_metadata(
         &self,
         tx: &rusqlite::Transaction<'_>,
         mistake_id: &str,
     ) -> Result<()> {
-        // 第一步：为所有未配对的 user 分配 turn_id（若缺失）
-        let mut users_stmt = tx.prepare(
-            "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND role = 'user' AND (turn_id IS NULL OR turn_id = '') ORDER BY timestamp ASC",
-        )?;
-        let user_rows = users_stmt
-            .query_map(rusqlite::params![mistake_id], |row| {
-                Ok(row.get::<_, i64>(0)?)
-            })?
-            .collect::<std::result::Result<Vec<_>, _>>()?;
-        for user_row_id in user_rows {
-            let turn_id = uuid::Uuid::new_v4().to_string();
-            tx.execute(
-                "UPDATE chat_messages SET turn_id = ?1, turn_seq = 0, reply_to_msg_id = NULL, message_kind = COALESCE(message_kind, 'user.input'), lifecycle = NULL WHERE id = ?2",
-                rusqlite::params![turn_id, user_row_id],
-            )?;
-        }
+        pair_unassigned_turns(tx, mistake_id)?;
+        Ok(())
+    }
 
-        // 第二步：为所有未配对的 assistant 绑定到最近的用户回合
-        let mut assistants_stmt = tx.prepare(
-            "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND role = 'assistant' AND (turn_id IS NULL OR turn_id = '') ORDER BY timestamp ASC",
+    /// 全库回合元数据重建的一批：从 `after_id` 之后按 ID 顺序取至多 `limit` 个错题，
+    /// 在同一事务内补齐回合配对与 `turn_seq`，返回每个错题的修复结果（含未修改的错题）。
+    ///
+    /// 只修改消息行，不更新错题的 `updated_at`，避免整库错题被视为已修改。
+    pub fn rebuild_turn_metadata_batch(
+        &self,
+        after_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<crate::models::TurnMetadataFix>> {
+        let mut conn = self.get_conn_safe()?;
+        let tx = conn.transaction()?;
+        let mut stmt = tx.prepare(
+            "SELECT id FROM mistakes WHERE (?1 IS NULL OR id > ?1) ORDER BY id ASC LIMIT ?2",
         )?;
-        let assistant_rows = assistants_stmt
-            .query_map(rusqlite::params![mistake_id], |row| {
-                Ok(row.get::<_, i64>(0)?)
+        let mistake_ids = stmt
+            .query_map(rusqlite::params![after_id, limit as i64], |row| {
+                row.get::<_, String>(0)
             })?
             .collect::<std::result::Result<Vec<_>, _>>()?;
-        for assistant_row_id in assistant_rows {
-            let candidate: Option<(i64, String)> = tx
-                .query_row(
-                    "SELECT u.id, u.turn_id \
-                     FROM chat_messages u \
-                     WHERE u.mistake_id = ?1 AND u.role = 'user' AND u.turn_id IS NOT NULL AND u.turn_id <> '' \
-                       AND NOT EXISTS (SELECT 1 FROM chat_messages a WHERE a.mistake_id = ?1 AND a.role = 'assistant' AND a.turn_id = u.turn_id) \
-                     ORDER BY u.timestamp DESC LIMIT 1",
-                    rusqlite::params![mistake_id],
-                    |r| Ok((r.get(0)?, r.get(1)?)),
-                )
-                .optional()?;
-            if let Some((user_row_id, turn_id)) = candidate {
-                tx.execute(
-                    "UPDATE chat_messages SET turn_id = ?1, turn_seq = 1, reply_to_msg_id = ?2, message_kind = COALESCE(message_kind, 'assistant.answer'), lifecycle = COALESCE(lifecycle, 'complete') WHERE id = ?3",
-                    rusqlite::params![turn_id, user_row_id, assistant_row_id],
-                )?;
-            } else {
-                log::warn!(
-                    "[回合配对] 发现孤儿助手消息（无可配对的用户消息），mistake_id={}, assistant_row_id={}",
-                    mistake_id, assistant_row_id
-                );
-            }
+        drop(stmt);
+
+        let mut fixes = Vec::with_capacity(mistake_ids.len());
+        for mistake_id in mistake_ids {
+            let counts = pair_unassigned_turns(&tx, &mistake_id)?;
+            let turn_seq_fixed = fill_missing_turn_seq(&tx, &mistake_id)?;
+            fixes.push(crate::models::TurnMetadataFix {
+                mistake_id,
+                users_assigned: counts.users_assigned,
+                assistants_paired: counts.assistants_paired,
+                turn_seq_fixed,
+                orphaned: counts.orphaned_assistants,
+            });
         }
-
-        Ok(())
+        tx.commit()?;
+        Ok(fixes)
     }
     // 这些方法已被弃用，请使用DatabaseManager，但为兼容保留
 
@@ -353,6 +668,13 @@ impl Database {
             .load(std::sync::atomic::Ordering::SeqCst)
     }
 
+    /// 安全存储是否可用（未初始化时敏感设置回退为数据库存储）
+    pub fn is_secure_store_available(&self) -> bool {
+        self.secure_store
+            .as_ref()
+            .is_some_and(|store| store.is_available())
+    }
+
     /// 切换数据库文件并重新初始化连接
     pub fn switch_to_path(&self, new_path: &Path) -> Result<()> {
         if let Some(parent) = new_path.parent() {
@@ -2623,302 +2945,885 @@ impl Database {
         }
     }
 
-    pub fn fetch_chat_history_summary(&self, mistake_id: &str) -> Result<ChatHistorySummary> {
+    /// 设置错题置顶与星级（star_rating 取值 1-5，None 表示清除）
+    ///
+    /// 置顶错题不会被自动归档（见 `BatchOperations::batch_archive_old_mistakes`）。
+    pub fn set_mistake_pin(
+        &self,
+        mistake_id: &str,
+        pinned: bool,
+        star_rating: Option<u8>,
+    ) -> Result<()> {
+        if let Some(rating) = star_rating {
+            if !(1..=5).contains(&rating) {
+                return Err(anyhow::anyhow!("星级必须在 1-5 之间: {}", rating));
+            }
+        }
         let conn = self.get_conn_safe()?;
-        let mut stmt = conn.prepare(
-            "SELECT role, stable_id, content, image_base64, doc_attachments \
-             FROM chat_messages WHERE mistake_id = ?1",
+        let updated = conn.execute(
+            "UPDATE mistakes SET pinned = ?1, star_rating = ?2, updated_at = ?3 WHERE id = ?4",
+            params![
+                pinned,
+                star_rating,
+                chrono::Utc::now().to_rfc3339(),
+                mistake_id
+            ],
         )?;
-        let mut assistant_count = 0usize;
-        let mut user_messages: Vec<UserMessageSummary> = Vec::new();
-        let mut rows = stmt.query(params![mistake_id])?;
-        while let Some(row) = rows.next()? {
-            let role: String = row.get(0)?;
-            let stable_id: Option<String> = row.get(1)?;
-            let content: String = row.get(2)?;
-            let image_json: Option<String> = row.get(3)?;
-            let doc_json: Option<String> = row.get(4)?;
-            if role == "assistant" {
-                assistant_count += 1;
-                continue;
-            }
-            if role != "user" {
-                continue;
-            }
-            let images = parse_image_list(image_json).unwrap_or_default();
-            let doc_fp = canonicalize_doc_attachments_summary(doc_json);
-            let fingerprint = fingerprint_user_row(
-                &content,
-                if images.is_empty() {
-                    None
-                } else {
-                    Some(&images)
-                },
-                doc_fp.as_deref(),
-            );
-            user_messages.push(UserMessageSummary {
-                stable_id,
-                fingerprint,
-            });
+        if updated == 0 {
+            return Err(anyhow::anyhow!("错题不存在: {}", mistake_id));
         }
-        Ok(ChatHistorySummary {
-            assistant_count,
-            user_messages,
-        })
+        Ok(())
     }
 
-    pub fn get_chat_message_ids(&self, mistake_id: &str) -> Result<Vec<i64>> {
+    /// 读取错题置顶与星级：(pinned, star_rating)；旧数据 NULL 视为未置顶
+    pub fn get_mistake_pin(&self, mistake_id: &str) -> Result<Option<(bool, Option<u8>)>> {
         let conn = self.get_conn_safe()?;
-        let mut stmt = conn.prepare("SELECT id FROM chat_messages WHERE mistake_id = ?1")?;
-        let rows = stmt
-            .query_map(params![mistake_id], |row| Ok(row.get::<_, i64>(0)?))?
-            .collect::<std::result::Result<Vec<_>, _>>()?;
-        Ok(rows)
+        let row = conn
+            .query_row(
+                "SELECT COALESCE(pinned, 0), star_rating FROM mistakes WHERE id = ?1",
+                params![mistake_id],
+                |row| Ok((row.get::<_, bool>(0)?, row.get::<_, Option<u8>>(1)?)),
+            )
+            .optional()?;
+        Ok(row)
     }
 
-    /// 增量追加错题聊天消息（不删除历史） - 零过滤：不做角色/字段拦截
-    /// SOTA增量保存：基于 stable_id 进行 UPSERT，避免重复插入
-    /// 新架构兼容：当 mistake 不存在时自动创建空记录
-    pub fn append_mistake_chat_messages(
-        &self,
-        mistake_id: &str,
-        messages: &[crate::models::ChatMessage],
-    ) -> Result<AppendMessagesChangeSet> {
-        self.append_mistake_chat_messages_with_context(mistake_id, messages, None, None)
+    /// 归档错题（隐藏但不删除）；已归档的保留原归档时间
+    pub fn archive_mistake(&self, mistake_id: &str) -> Result<()> {
+        let archived = self.batch_archive_mistakes(&[mistake_id.to_string()])?;
+        if archived == 0 && !self.is_mistake_archived(mistake_id)? {
+            return Err(anyhow::anyhow!("错题不存在: {}", mistake_id));
+        }
+        Ok(())
     }
 
-    /// 增量追加错题聊天消息（带上下文）
-    ///
-    /// 当 mistake 记录不存在时，使用传入的 subject 和 chat_category 自动创建空记录。
-    /// 这是为了兼容新架构（前端生成 UUID 作为 sessionId，但不预先创建记录）。
-    pub fn append_mistake_chat_messages_with_context(
-        &self,
-        mistake_id: &str,
-        messages: &[crate::models::ChatMessage],
-        _subject: Option<&str>,
-        chat_category: Option<&str>,
-    ) -> Result<AppendMessagesChangeSet> {
+    /// 批量归档错题，返回本次新归档的数量（不存在、已归档或已移入回收站的 ID 跳过）
+    pub fn batch_archive_mistakes(&self, mistake_ids: &[String]) -> Result<usize> {
+        if mistake_ids.is_empty() {
+            return Ok(0);
+        }
         let mut conn = self.get_conn_safe()?;
         let tx = conn.transaction()?;
-
-        // 检查错题是否存在，不存在则自动创建
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut archived = 0;
         {
-            let mut stmt = tx.prepare("SELECT COUNT(1) FROM mistakes WHERE id = ?1")?;
-            let exists: i64 = stmt.query_row(rusqlite::params![mistake_id], |row| row.get(0))?;
-            if exists == 0 {
-                // 新架构兼容：自动创建空 mistake 记录
-                let now = chrono::Utc::now().to_rfc3339();
-                let category_val = chat_category.unwrap_or("analysis");
-                tx.execute(
-                    "INSERT INTO mistakes (id, created_at, question_images, analysis_images, user_question, ocr_text, tags, mistake_type, status, chat_category, updated_at, last_accessed_at)
-                     VALUES (?1, ?2, '[]', '[]', '', '', '[]', 'analysis', 'active', ?3, ?2, ?2)",
-                    params![mistake_id, now, category_val],
-                )?;
-                log::info!(
-                    "[新架构兼容] 自动创建 mistake 记录: id={}, category={}",
-                    mistake_id,
-                    category_val
-                );
+            let mut stmt = tx.prepare(
+                "UPDATE mistakes SET archived_at = ?1, updated_at = ?1 \
+                 WHERE id = ?2 AND archived_at IS NULL AND deleted_at IS NULL",
+            )?;
+            for id in mistake_ids {
+                archived += stmt.execute(params![now, id])?;
             }
         }
+        tx.commit()?;
+        Ok(archived)
+    }
 
-        // 检查 stable_id 列是否存在
-        let has_stable_id_column: bool = tx
+    /// 取消归档；自动归档（status = 'archived'）的错题同时恢复为 'active'
+    pub fn unarchive_mistake(&self, mistake_id: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        let updated = conn.execute(
+            "UPDATE mistakes SET archived_at = NULL, updated_at = ?1, \
+             status = CASE WHEN status = 'archived' THEN 'active' ELSE status END \
+             WHERE id = ?2",
+            params![chrono::Utc::now().to_rfc3339(), mistake_id],
+        )?;
+        if updated == 0 {
+            return Err(anyhow::anyhow!("错题不存在: {}", mistake_id));
+        }
+        Ok(())
+    }
+
+    /// 设置错题用户批注；空白内容视为清除
+    pub fn set_mistake_user_notes(&self, mistake_id: &str, notes: Option<&str>) -> Result<()> {
+        let notes = notes.map(str::trim).filter(|n| !n.is_empty());
+        let conn = self.get_conn_safe()?;
+        let updated = conn.execute(
+            "UPDATE mistakes SET user_notes = ?1, updated_at = ?2 WHERE id = ?3",
+            params![notes, chrono::Utc::now().to_rfc3339(), mistake_id],
+        )?;
+        if updated == 0 {
+            return Err(anyhow::anyhow!("错题不存在: {}", mistake_id));
+        }
+        Ok(())
+    }
+
+    /// 读取错题用户批注；外层 None 表示错题不存在，内层 None 表示未写批注
+    pub fn get_mistake_user_notes(&self, mistake_id: &str) -> Result<Option<Option<String>>> {
+        let conn = self.get_conn_safe()?;
+        Ok(conn
             .query_row(
-                "SELECT COUNT(*) FROM pragma_table_info('chat_messages') WHERE name='stable_id'",
-                [],
-                |row| row.get::<_, i32>(0).map(|count| count > 0),
+                "SELECT user_notes FROM mistakes WHERE id = ?1",
+                params![mistake_id],
+                |row| row.get(0),
             )
-            .unwrap_or(false);
+            .optional()?)
+    }
 
-        // 读取现有消息的 stable_id -> id 映射
-        let existing_messages: ExistingMessageMap = if has_stable_id_column {
-            let mut stmt = tx.prepare(
-                    "SELECT id, stable_id FROM chat_messages WHERE mistake_id = ?1 AND stable_id IS NOT NULL AND stable_id <> ''"
-                )?;
-            let rows: Vec<(String, i64)> = stmt
-                .query_map(params![mistake_id], |row| build_existing_message_map(row))?
-                .collect::<rusqlite::Result<_>>()?;
-            rows.into_iter().collect()
+    /// 列表查询中的难度与掌握度列；未迁移的旧库返回 NULL（旧兼容列的难度 0 视为未设置）
+    fn mastery_select_columns(conn: &Connection) -> Result<&'static str> {
+        Ok(if has_column(conn, "mistakes", "mastery_level")? {
+            "NULLIF(difficulty, 0), mastery_level"
         } else {
-            std::collections::HashMap::new()
-        };
+            "NULL, NULL"
+        })
+    }
 
-        // 逐条 UPSERT 消息
-        let mut matched_ids = std::collections::HashSet::new();
-        let mut updated_ids: Vec<i64> = Vec::new();
-        let mut inserted_ids: Vec<i64> = Vec::new();
-        let mut latest_ts = None;
+    /// 列表查询中的置顶与星级列，以及让置顶错题排在最前的排序前缀；未迁移的旧库视为均未置顶
+    fn pin_select_columns(conn: &Connection) -> Result<(&'static str, &'static str)> {
+        Ok(if has_column(conn, "mistakes", "pinned")? {
+            (
+                "COALESCE(pinned, 0), star_rating",
+                "COALESCE(pinned, 0) DESC, ",
+            )
+        } else {
+            ("0, NULL", "")
+        })
+    }
 
-        // 统计信息
-        let mut assistant_count = 0usize;
-        let mut tool_count = 0usize;
-        let mut other_count = 0usize;
-        let mut missing_stable_id_count = 0usize;
+    /// 按关键词检索错题（题干、OCR 文本、用户批注），忽略已删除错题
+    ///
+    /// `tags` 非空时只返回带有这些标签的错题（`tag_match` 决定需全部命中还是任一命中），
+    /// 与关键词条件同时生效；`tags` 为空时不按标签筛选。关键词为空时仅按标签筛选，
+    /// 两者都为空时返回空结果。`total` 为满足条件的错题总数，不受分页影响。
+    pub fn search_mistakes_fulltext(
+        &self,
+        query: &str,
+        limit: Option<u32>,
+        offset: Option<u32>,
+        tags: &[String],
+        tag_match: TagMatchMode,
+    ) -> Result<MistakeSearchPage> {
+        let pattern = like_pattern(query);
+        let tags = normalize_tag_filter(tags);
+        if pattern.is_none() && tags.is_empty() {
+            return Ok(MistakeSearchPage::default());
+        }
+        let conn = self.get_conn_safe()?;
+        let (where_sql, mut values) = Self::mistake_search_where(&conn, pattern, &tags, tag_match)?;
 
-        for message in messages {
-            // 基础字段序列化
-            let image_paths_json = message
-                .image_paths
-                .as_ref()
-                .map(|paths| serde_json::to_string(paths))
-                .transpose()?;
-            let image_base64_json = message
-                .image_base64
-                .as_ref()
-                .map(|imgs| serde_json::to_string(imgs))
-                .transpose()?;
-            let doc_attachments_json = message
-                .doc_attachments
-                .as_ref()
-                .map(|docs| serde_json::to_string(docs))
-                .transpose()?;
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM mistakes WHERE {}", where_sql),
+            rusqlite::params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
 
-            // sources 字段：对所有角色保留
-            let (
-                rag_sources_json,
-                memory_sources_json,
-                graph_sources_json,
-                web_search_sources_json,
-            ) = (
-                message
-                    .rag_sources
-                    .as_ref()
-                    .map(|sources| serde_json::to_string(sources))
-                    .transpose()?,
-                message
-                    .memory_sources
-                    .as_ref()
-                    .map(|sources| serde_json::to_string(sources))
-                    .transpose()?,
-                message
-                    .graph_sources
-                    .as_ref()
-                    .map(|sources| serde_json::to_string(sources))
-                    .transpose()?,
-                message
-                    .web_search_sources
-                    .as_ref()
-                    .map(|sources| serde_json::to_string(sources))
-                    .transpose()?,
-            );
+        values.push(Value::from(limit.map(i64::from).unwrap_or(50)));
+        values.push(Value::from(offset.map(i64::from).unwrap_or(0)));
+        let (pin_columns, pin_order) = Self::pin_select_columns(&conn)?;
+        let sql = format!(
+            "SELECT id, user_question, mistake_type, tags, created_at, user_notes, \
+                    COALESCE(user_notes LIKE ?1 ESCAPE '\\', 0), {}, {} \
+             FROM mistakes WHERE {} \
+             ORDER BY {}updated_at DESC, id LIMIT ?{} OFFSET ?{}",
+            Self::mastery_select_columns(&conn)?,
+            pin_columns,
+            where_sql,
+            pin_order,
+            values.len() - 1,
+            values.len()
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            let tags_json: String = row.get(3)?;
+            Ok(MistakeSearchHit {
+                id: row.get(0)?,
+                user_question: row.get(1)?,
+                mistake_type: row.get(2)?,
+                tags: serde_json::from_str(&tags_json).unwrap_or_default(),
+                created_at: row.get(4)?,
+                user_notes: row.get(5)?,
+                matched_user_notes: row.get(6)?,
+                difficulty: row.get(7)?,
+                mastery_level: row.get(8)?,
+                pinned: row.get(9)?,
+                star_rating: row.get(10)?,
+            })
+        })?;
+        Ok(MistakeSearchPage {
+            items: rows.collect::<rusqlite::Result<Vec<_>>>()?,
+            total: total.max(0) as u64,
+        })
+    }
 
-            // 工具字段：对所有角色保留
-            let (tool_call_json, tool_result_json) = (
-                message
-                    .tool_call
-                    .as_ref()
-                    .map(|tc| serde_json::to_string(tc))
-                    .transpose()?,
-                message
-                    .tool_result
-                    .as_ref()
-                    .map(|tr| serde_json::to_string(tr))
-                    .transpose()?,
+    /// 错题检索的 WHERE 条件与参数，数据查询与计数查询共用，保证两者筛选一致
+    ///
+    /// ?1 固定为关键词（无关键词时为 NULL，批注命中列随之为 0），其余参数依次追加。
+    fn mistake_search_where(
+        conn: &Connection,
+        pattern: Option<String>,
+        tags: &[String],
+        tag_match: TagMatchMode,
+    ) -> Result<(String, Vec<Value>)> {
+        let has_deleted_at = has_column(conn, "mistakes", "deleted_at")?;
+        let mut clauses = Vec::new();
+        if pattern.is_some() {
+            clauses.push(
+                "(user_question LIKE ?1 ESCAPE '\\' OR ocr_text LIKE ?1 ESCAPE '\\' \
+                 OR user_notes LIKE ?1 ESCAPE '\\')"
+                    .to_string(),
             );
+        }
+        let mut values = vec![Value::from(pattern)];
+        if has_deleted_at {
+            clauses.push("deleted_at IS NULL".to_string());
+        }
+        if !tags.is_empty() {
+            clauses.push(Self::mistake_tag_clause(
+                conn,
+                tags,
+                tag_match,
+                &mut values,
+            )?);
+        }
+        Ok((clauses.join(" AND "), values))
+    }
 
-            // overrides：对所有角色保留
-            let overrides_json = message.overrides.as_ref().map(|v| v.to_string());
-            let (relations_json_value, relations_update_flag) = match message.relations.as_ref() {
-                Some(val) if val.is_null() => (None, true),
-                Some(val) => (Some(val.to_string()), true),
-                None => (None, false),
-            };
-            let relations_obj = message.relations.as_ref().and_then(|val| val.as_object());
+    /// 标签筛选条件，参数追加到 `values` 末尾
+    ///
+    /// 优先使用触发器维护的 `mistake_tag_index`；尚未迁移的旧库退化为逐行解析 `tags` JSON。
+    fn mistake_tag_clause(
+        conn: &Connection,
+        tags: &[String],
+        tag_match: TagMatchMode,
+        values: &mut Vec<Value>,
+    ) -> Result<String> {
+        let has_index = table_exists(conn, "mistake_tag_index")?;
+        let placeholders = tags
+            .iter()
+            .map(|tag| {
+                values.push(Value::from(tag.clone()));
+                format!("?{}", values.len())
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let required = match tag_match {
+            TagMatchMode::All => tags.len(),
+            TagMatchMode::Any => 1,
+        };
+        Ok(if has_index {
+            format!(
+                "id IN (SELECT mistake_id FROM mistake_tag_index WHERE tag IN ({}) \
+                 GROUP BY mistake_id HAVING COUNT(*) >= {})",
+                placeholders, required
+            )
+        } else {
+            format!(
+                "(SELECT COUNT(DISTINCT TRIM(je.value)) \
+                 FROM json_each(CASE WHEN json_valid(mistakes.tags) THEN mistakes.tags ELSE '[]' END) je \
+                 WHERE TRIM(je.value) IN ({})) >= {}",
+                placeholders, required
+            )
+        })
+    }
 
-            let mut turn_id_update_flag = false;
-            let mut turn_id_value: Option<String> = None;
-            if let Some(obj) = relations_obj {
-                if let Some(val) = obj.get("turn_id") {
-                    turn_id_update_flag = true;
-                    if val.is_null() {
-                        turn_id_value = None;
-                    } else if let Some(s) = val.as_str() {
-                        turn_id_value = Some(s.to_string());
-                    } else {
-                        turn_id_value = Some(val.to_string());
-                    }
-                }
+    /// 按关键词检索错题对话中的用户与模型消息，忽略已删除错题
+    pub fn search_chat_messages(
+        &self,
+        query: &str,
+        limit: Option<u32>,
+    ) -> Result<Vec<ChatMessageSearchHit>> {
+        let Some(pattern) = like_pattern(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.get_conn_safe()?;
+        let has_deleted_at = has_column(&conn, "mistakes", "deleted_at")?;
+        let sql = format!(
+            "SELECT cm.id, cm.mistake_id, cm.role, cm.content, cm.timestamp, \
+                    COALESCE(m.user_question, '') \
+             FROM chat_messages cm JOIN mistakes m ON m.id = cm.mistake_id \
+             WHERE cm.role IN ('user', 'assistant') AND cm.content LIKE ?1 ESCAPE '\\'{} \
+             ORDER BY cm.timestamp DESC LIMIT ?2",
+            if has_deleted_at {
+                " AND m.deleted_at IS NULL"
+            } else {
+                ""
             }
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(
+            params![pattern, limit.map(i64::from).unwrap_or(50)],
+            |row| {
+                Ok(ChatMessageSearchHit {
+                    message_id: row.get(0)?,
+                    mistake_id: row.get(1)?,
+                    role: row.get(2)?,
+                    content: row.get(3)?,
+                    timestamp: row.get(4)?,
+                    mistake_question: row.get(5)?,
+                })
+            },
+        )?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(Into::into)
+    }
 
-            let mut turn_seq_update_flag = false;
-            let mut turn_seq_value: Option<i64> = None;
-            if let Some(obj) = relations_obj {
-                if let Some(val) = obj.get("turn_seq") {
-                    turn_seq_update_flag = true;
-                    if val.is_null() {
-                        turn_seq_value = None;
-                    } else if let Some(n) = val.as_i64() {
-                        turn_seq_value = Some(n);
-                    } else if let Some(n) = val.as_u64() {
-                        turn_seq_value = Some(n as i64);
-                    } else if let Some(s) = val.as_str() {
-                        if let Ok(parsed) = s.parse::<i64>() {
-                            turn_seq_value = Some(parsed);
-                        }
-                    }
-                }
-            }
+    /// 按关键词检索知识库文档分块（不依赖嵌入模型）
+    pub fn search_knowledge_base_chunks(
+        &self,
+        query: &str,
+        limit: Option<u32>,
+    ) -> Result<Vec<KnowledgeChunkSearchHit>> {
+        let Some(pattern) = like_pattern(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.document_id, d.file_name, d.sub_library_id, c.chunk_index, c.text \
+             FROM rag_document_chunks c JOIN rag_documents d ON d.id = c.document_id \
+             WHERE c.text LIKE ?1 ESCAPE '\\' \
+             ORDER BY d.updated_at DESC, c.chunk_index LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(
+            params![pattern, limit.map(i64::from).unwrap_or(50)],
+            |row| {
+                Ok(KnowledgeChunkSearchHit {
+                    chunk_id: row.get(0)?,
+                    document_id: row.get(1)?,
+                    file_name: row.get(2)?,
+                    sub_library_id: row.get(3)?,
+                    chunk_index: row.get(4)?,
+                    text: row.get(5)?,
+                })
+            },
+        )?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(Into::into)
+    }
 
-            let mut reply_to_update_flag = false;
-            let mut reply_to_value: Option<i64> = None;
-            if let Some(obj) = relations_obj {
-                if let Some(val) = obj.get("reply_to_msg_id") {
-                    reply_to_update_flag = true;
-                    if val.is_null() {
-                        reply_to_value = None;
-                    } else if let Some(n) = val.as_i64() {
-                        reply_to_value = Some(n);
-                    } else if let Some(n) = val.as_u64() {
-                        reply_to_value = Some(n as i64);
-                    } else if let Some(s) = val.as_str() {
-                        if let Ok(parsed) = s.parse::<i64>() {
-                            reply_to_value = Some(parsed);
-                        }
-                    }
-                }
-            }
+    pub fn is_mistake_archived(&self, mistake_id: &str) -> Result<bool> {
+        let conn = self.get_conn_safe()?;
+        let archived: Option<bool> = conn
+            .query_row(
+                "SELECT archived_at IS NOT NULL FROM mistakes WHERE id = ?1",
+                params![mistake_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(archived.unwrap_or(false))
+    }
 
-            let mut message_kind_update_flag = false;
-            let mut message_kind_value: Option<String> = None;
-            if let Some(obj) = relations_obj {
-                if let Some(val) = obj.get("message_kind") {
-                    message_kind_update_flag = true;
-                    if val.is_null() {
-                        message_kind_value = None;
-                    } else if let Some(s) = val.as_str() {
-                        message_kind_value = Some(s.to_string());
-                    } else {
-                        message_kind_value = Some(val.to_string());
-                    }
-                }
-            }
+    /// 列出已归档错题（置顶在前，其余按归档时间倒序）
+    pub fn list_archived_mistakes(
+        &self,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> Result<Vec<ArchivedMistakeSummary>> {
+        let conn = self.get_conn_safe()?;
+        let (pin_columns, pin_order) = Self::pin_select_columns(&conn)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, user_question, mistake_type, tags, created_at, archived_at, {}, {} \
+             FROM mistakes WHERE archived_at IS NOT NULL AND deleted_at IS NULL \
+             ORDER BY {}archived_at DESC LIMIT ?1 OFFSET ?2",
+            Self::mastery_select_columns(&conn)?,
+            pin_columns,
+            pin_order
+        ))?;
+        let rows = stmt.query_map(
+            params![limit.map(i64::from).unwrap_or(-1), offset.unwrap_or(0)],
+            |row| {
+                let tags_json: String = row.get(3)?;
+                Ok(ArchivedMistakeSummary {
+                    id: row.get(0)?,
+                    user_question: row.get(1)?,
+                    mistake_type: row.get(2)?,
+                    tags: serde_json::from_str(&tags_json).unwrap_or_default(),
+                    created_at: row.get(4)?,
+                    archived_at: row.get(5)?,
+                    difficulty: row.get(6)?,
+                    mastery_level: row.get(7)?,
+                    pinned: row.get(8)?,
+                    star_rating: row.get(9)?,
+                })
+            },
+        )?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(Into::into)
+    }
 
-            let mut lifecycle_update_flag = false;
-            let mut lifecycle_value: Option<String> = None;
-            if let Some(obj) = relations_obj {
-                if let Some(val) = obj.get("lifecycle") {
-                    lifecycle_update_flag = true;
-                    if val.is_null() {
-                        lifecycle_value = None;
-                    } else if let Some(s) = val.as_str() {
-                        lifecycle_value = Some(s.to_string());
-                    } else {
-                        lifecycle_value = Some(val.to_string());
-                    }
+    /// 错题数量统计：活跃 / 已归档 / 合计（不含回收站中的错题）
+    pub fn get_mistake_archive_counts(&self) -> Result<MistakeArchiveCounts> {
+        let conn = self.get_conn_safe()?;
+        let (active, archived): (i64, i64) = conn.query_row(
+            "SELECT COALESCE(SUM(CASE WHEN archived_at IS NULL THEN 1 ELSE 0 END), 0), \
+                    COALESCE(SUM(CASE WHEN archived_at IS NOT NULL THEN 1 ELSE 0 END), 0) \
+             FROM mistakes WHERE deleted_at IS NULL",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(MistakeArchiveCounts {
+            active,
+            archived,
+            total: active + archived,
+        })
+    }
+
+    /// 批量删除回顾分析及其对话消息（单事务），返回实际删除的数量
+    ///
+    /// 消息显式删除而不依赖 `ON DELETE CASCADE`：外键只在开启 `foreign_keys` 的连接上生效，
+    /// 维护模式下的临时连接并未开启。任一分析正在生成时整体拒绝，不删除任何数据；
+    /// 不存在的 ID 跳过。
+    pub fn delete_review_analyses(&self, ids: &[String]) -> Result<ReviewAnalysisDeletion> {
+        let mut conn = self.get_conn_safe()?;
+        let tx = conn.transaction()?;
+        ensure_review_analyses_idle(&tx, ids)?;
+
+        let mut deletion = ReviewAnalysisDeletion::default();
+        {
+            let mut delete_messages =
+                tx.prepare("DELETE FROM review_chat_messages WHERE review_analysis_id = ?1")?;
+            let mut delete_analysis = tx.prepare("DELETE FROM review_analyses WHERE id = ?1")?;
+            let mut seen = HashSet::new();
+            for id in ids {
+                if !seen.insert(id.as_str()) {
+                    continue;
                 }
+                deletion.deleted_messages += delete_messages.execute(params![id])?;
+                deletion.deleted_analyses += delete_analysis.execute(params![id])?;
             }
+        }
+        tx.commit()?;
+        Ok(deletion)
+    }
 
-            let relations_json_for_update = relations_json_value.clone();
-            let relations_json_for_insert = relations_json_value.clone();
-            let turn_id_value_for_update = turn_id_value.clone();
-            let turn_id_value_for_insert = turn_id_value.clone();
-            let message_kind_value_for_update = message_kind_value.clone();
-            let message_kind_value_for_insert = message_kind_value.clone();
-            let lifecycle_value_for_update = lifecycle_value.clone();
-            let lifecycle_value_for_insert = lifecycle_value.clone();
-
-            // SOTA: 获取稳定ID（与save_mistake保持一致）
+    /// 归档回顾分析（隐藏但不删除）；已归档的保留原归档时间
+    pub fn archive_review_analysis(&self, id: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        ensure_review_analyses_idle(&conn, &[id.to_string()])?;
+        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE review_analyses SET archived_at = ?1, updated_at = ?1 \
+             WHERE id = ?2 AND archived_at IS NULL",
+            params![now, id],
+        )?;
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM review_analyses WHERE id = ?1)",
+            params![id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(anyhow::anyhow!("回顾分析不存在: {}", id));
+        }
+        Ok(())
+    }
+
+    pub fn unarchive_review_analysis(&self, id: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        let updated = conn.execute(
+            "UPDATE review_analyses SET archived_at = NULL, updated_at = ?1 WHERE id = ?2",
+            params![chrono::Utc::now().to_rfc3339(), id],
+        )?;
+        if updated == 0 {
+            return Err(anyhow::anyhow!("回顾分析不存在: {}", id));
+        }
+        Ok(())
+    }
+
+    pub fn fetch_chat_history_summary(&self, mistake_id: &str) -> Result<ChatHistorySummary> {
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            "SELECT role, stable_id, content, image_base64, doc_attachments \
+             FROM chat_messages WHERE mistake_id = ?1",
+        )?;
+        let mut assistant_count = 0usize;
+        let mut user_messages: Vec<UserMessageSummary> = Vec::new();
+        let mut rows = stmt.query(params![mistake_id])?;
+        while let Some(row) = rows.next()? {
+            let role: String = row.get(0)?;
+            let stable_id: Option<String> = row.get(1)?;
+            let content: String = row.get(2)?;
+            let image_json: Option<String> = row.get(3)?;
+            let doc_json: Option<String> = row.get(4)?;
+            if role == "assistant" {
+                assistant_count += 1;
+                continue;
+            }
+            if role != "user" {
+                continue;
+            }
+            let images = parse_image_list(image_json).unwrap_or_default();
+            let doc_fp = canonicalize_doc_attachments_summary(doc_json);
+            let fingerprint = fingerprint_user_row(
+                &content,
+                if images.is_empty() {
+                    None
+                } else {
+                    Some(&images)
+                },
+                doc_fp.as_deref(),
+            );
+            user_messages.push(UserMessageSummary {
+                stable_id,
+                fingerprint,
+            });
+        }
+        Ok(ChatHistorySummary {
+            assistant_count,
+            user_messages,
+        })
+    }
+
+    /// 读取单条聊天消息的文档附件，透明回填去重存储的载荷
+    pub fn get_chat_message_doc_attachments(
+        &self,
+        message_id: i64,
+    ) -> Result<Option<Vec<crate::models::DocumentAttachment>>> {
+        let conn = self.get_conn_safe()?;
+        let raw: Option<String> = conn
+            .query_row(
+                "SELECT doc_attachments FROM chat_messages WHERE id = ?1",
+                params![message_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        let Some(raw) = raw else {
+            return Ok(None);
+        };
+        let mut docs: Vec<crate::models::DocumentAttachment> = serde_json::from_str(&raw)?;
+        ensure_doc_attachment_blobs_table(&conn)?;
+        rehydrate_doc_attachments(&conn, &mut docs)?;
+        Ok(Some(docs))
+    }
+
+    /// 清理不再被任何消息引用的文档附件载荷，返回删除条数
+    pub fn gc_orphan_attachments(&self) -> Result<usize> {
+        use crate::batch_operations::BatchOperationExt;
+        let removed = self.with_batch_operations(|ops| ops.gc_orphan_attachments())?;
+        if removed > 0 {
+            log::info!("[DocAttachments] 清理孤立附件载荷 {} 条", removed);
+        }
+        Ok(removed)
+    }
+
+    pub fn get_chat_message_ids(&self, mistake_id: &str) -> Result<Vec<i64>> {
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare("SELECT id FROM chat_messages WHERE mistake_id = ?1")?;
+        let rows = stmt
+            .query_map(params![mistake_id], |row| Ok(row.get::<_, i64>(0)?))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// 增量追加错题聊天消息（不删除历史） - 零过滤：不做角色/字段拦截
+    /// SOTA增量保存：基于 stable_id 进行 UPSERT，避免重复插入
+    /// 新架构兼容：当 mistake 不存在时自动创建空记录
+    pub fn append_mistake_chat_messages(
+        &self,
+        mistake_id: &str,
+        messages: &[crate::models::ChatMessage],
+    ) -> Result<AppendMessagesChangeSet> {
+        self.append_mistake_chat_messages_with_context(mistake_id, messages, None, None)
+    }
+
+    /// 自动保存入口：快照签名与 `mistakes.autosave_signature` 相同则跳过写入
+    ///
+    /// 返回 `None` 表示内容未变、未写库。
+    pub fn append_mistake_chat_messages_if_changed(
+        &self,
+        mistake_id: &str,
+        messages: &[crate::models::ChatMessage],
+        chat_category: Option<&str>,
+    ) -> Result<Option<AppendMessagesChangeSet>> {
+        let signature = chat_messages_signature(messages)?;
+        let stored: Option<String> = {
+            let conn = self.get_conn_safe()?;
+            conn.query_row(
+                "SELECT autosave_signature FROM mistakes WHERE id = ?1",
+                params![mistake_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten()
+        };
+        if stored.as_deref() == Some(signature.as_str()) {
+            return Ok(None);
+        }
+
+        self.append_mistake_chat_messages_with_context(mistake_id, messages, None, chat_category)
+            .map(Some)
+    }
+
+    /// 增量追加错题聊天消息（带上下文）
+    ///
+    /// 当 mistake 记录不存在时，使用传入的 subject 和 chat_category 自动创建空记录。
+    /// 这是为了兼容新架构（前端生成 UUID 作为 sessionId，但不预先创建记录）。
+    pub fn append_mistake_chat_messages_with_context(
+        &self,
+        mistake_id: &str,
+        messages: &[crate::models::ChatMessage],
+        _subject: Option<&str>,
+        chat_category: Option<&str>,
+    ) -> Result<AppendMessagesChangeSet> {
+        let mut conn = self.get_conn_safe()?;
+        let tx = conn.transaction()?;
+        let change_set =
+            self.append_mistake_chat_messages_in_tx(&tx, mistake_id, messages, chat_category)?;
+        tx.commit()?;
+
+        let updated_count = change_set.updated_user_message_ids.len();
+        let inserted_count = change_set.inserted_user_message_ids.len();
+        let skipped_count = messages.len() - (updated_count + inserted_count);
+        if skipped_count > 0 {
+            log::debug!(
+                "[Append-NoChange] 跳过 {} 条无变更消息 (mistake_id={})",
+                skipped_count,
+                mistake_id
+            );
+        }
+        if updated_count > 0 {
+            log::debug!(
+                "[Append-Updated] 更新 {} 条已变更消息 (mistake_id={})",
+                updated_count,
+                mistake_id
+            );
+        }
+        if inserted_count > 0 {
+            log::debug!(
+                "[Append-Inserted] 插入 {} 条新消息 (mistake_id={})",
+                inserted_count,
+                mistake_id
+            );
+        }
+
+        Ok(change_set)
+    }
+
+    /// 在调用方的事务内追加错题聊天消息，由调用方决定提交或回滚
+    pub(crate) fn append_mistake_chat_messages_in_tx(
+        &self,
+        tx: &rusqlite::Transaction<'_>,
+        mistake_id: &str,
+        messages: &[crate::models::ChatMessage],
+        chat_category: Option<&str>,
+    ) -> Result<AppendMessagesChangeSet> {
+        // 检查错题是否存在，不存在则自动创建
+        {
+            let mut stmt = tx.prepare("SELECT COUNT(1) FROM mistakes WHERE id = ?1")?;
+            let exists: i64 = stmt.query_row(rusqlite::params![mistake_id], |row| row.get(0))?;
+            if exists == 0 {
+                // 新架构兼容：自动创建空 mistake 记录
+                let now = chrono::Utc::now().to_rfc3339();
+                let category_val = chat_category.unwrap_or("analysis");
+                tx.execute(
+                    "INSERT INTO mistakes (id, created_at, question_images, analysis_images, user_question, ocr_text, tags, mistake_type, status, chat_category, updated_at, last_accessed_at)
+                     VALUES (?1, ?2, '[]', '[]', '', '', '[]', 'analysis', 'active', ?3, ?2, ?2)",
+                    params![mistake_id, now, category_val],
+                )?;
+                log::info!(
+                    "[新架构兼容] 自动创建 mistake 记录: id={}, category={}",
+                    mistake_id,
+                    category_val
+                );
+            }
+        }
+
+        // 检查 stable_id 列是否存在
+        let has_stable_id_column: bool = tx
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('chat_messages') WHERE name='stable_id'",
+                [],
+                |row| row.get::<_, i32>(0).map(|count| count > 0),
+            )
+            .unwrap_or(false);
+
+        // 读取现有消息的 stable_id -> id 映射
+        let existing_messages: ExistingMessageMap = if has_stable_id_column {
+            let mut stmt = tx.prepare(
+                    "SELECT id, stable_id FROM chat_messages WHERE mistake_id = ?1 AND stable_id IS NOT NULL AND stable_id <> ''"
+                )?;
+            let rows: Vec<(String, i64)> = stmt
+                .query_map(params![mistake_id], |row| build_existing_message_map(row))?
+                .collect::<rusqlite::Result<_>>()?;
+            rows.into_iter().collect()
+        } else {
+            std::collections::HashMap::new()
+        };
+
+        // 逐条 UPSERT 消息
+        let mut matched_ids = std::collections::HashSet::new();
+        let mut updated_ids: Vec<i64> = Vec::new();
+        let mut inserted_ids: Vec<i64> = Vec::new();
+        let mut latest_ts = None;
+
+        // 统计信息
+        let mut assistant_count = 0usize;
+        let mut tool_count = 0usize;
+        let mut other_count = 0usize;
+        let mut missing_stable_id_count = 0usize;
+        let mut blob_table_ready = false;
+
+        for message in messages {
+            // 基础字段序列化
+            let image_paths_json = message
+                .image_paths
+                .as_ref()
+                .map(|paths| serde_json::to_string(paths))
+                .transpose()?;
+            let image_base64_json = message
+                .image_base64
+                .as_ref()
+                .map(|imgs| serde_json::to_string(imgs))
+                .transpose()?;
+            // 附件载荷按指纹去重存储，消息中只保留引用
+            let doc_attachments_json = match message.doc_attachments.as_ref() {
+                Some(docs) => {
+                    if !blob_table_ready {
+                        ensure_doc_attachment_blobs_table(&tx)?;
+                        blob_table_ready = true;
+                    }
+                    Some(serde_json::to_string(&store_doc_attachment_payloads(
+                        &tx, docs,
+                    )?)?)
+                }
+                None => None,
+            };
+
+            // sources 字段：对所有角色保留
+            let (
+                rag_sources_json,
+                memory_sources_json,
+                graph_sources_json,
+                web_search_sources_json,
+            ) = (
+                message
+                    .rag_sources
+                    .as_ref()
+                    .map(|sources| serde_json::to_string(sources))
+                    .transpose()?,
+                message
+                    .memory_sources
+                    .as_ref()
+                    .map(|sources| serde_json::to_string(sources))
+                    .transpose()?,
+                message
+                    .graph_sources
+                    .as_ref()
+                    .map(|sources| serde_json::to_string(sources))
+                    .transpose()?,
+                message
+                    .web_search_sources
+                    .as_ref()
+                    .map(|sources| serde_json::to_string(sources))
+                    .transpose()?,
+            );
+
+            // 工具字段：对所有角色保留
+            let (tool_call_json, tool_result_json) = (
+                message
+                    .tool_call
+                    .as_ref()
+                    .map(|tc| serde_json::to_string(tc))
+                    .transpose()?,
+                message
+                    .tool_result
+                    .as_ref()
+                    .map(|tr| serde_json::to_string(tr))
+                    .transpose()?,
+            );
+
+            // overrides：对所有角色保留
+            let overrides_json = message.overrides.as_ref().map(|v| v.to_string());
+            let (relations_json_value, relations_update_flag) = match message.relations.as_ref() {
+                Some(val) if val.is_null() => (None, true),
+                Some(val) => (Some(val.to_string()), true),
+                None => (None, false),
+            };
+            let relations_obj = message.relations.as_ref().and_then(|val| val.as_object());
+
+            let mut turn_id_update_flag = false;
+            let mut turn_id_value: Option<String> = None;
+            if let Some(obj) = relations_obj {
+                if let Some(val) = obj.get("turn_id") {
+                    turn_id_update_flag = true;
+                    if val.is_null() {
+                        turn_id_value = None;
+                    } else if let Some(s) = val.as_str() {
+                        turn_id_value = Some(s.to_string());
+                    } else {
+                        turn_id_value = Some(val.to_string());
+                    }
+                }
+            }
+
+            let mut turn_seq_update_flag = false;
+            let mut turn_seq_value: Option<i64> = None;
+            if let Some(obj) = relations_obj {
+                if let Some(val) = obj.get("turn_seq") {
+                    turn_seq_update_flag = true;
+                    if val.is_null() {
+                        turn_seq_value = None;
+                    } else if let Some(n) = val.as_i64() {
+                        turn_seq_value = Some(n);
+                    } else if let Some(n) = val.as_u64() {
+                        turn_seq_value = Some(n as i64);
+                    } else if let Some(s) = val.as_str() {
+                        if let Ok(parsed) = s.parse::<i64>() {
+                            turn_seq_value = Some(parsed);
+                        }
+                    }
+                }
+            }
+
+            let mut reply_to_update_flag = false;
+            let mut reply_to_value: Option<i64> = None;
+            if let Some(obj) = relations_obj {
+                if let Some(val) = obj.get("reply_to_msg_id") {
+                    reply_to_update_flag = true;
+                    if val.is_null() {
+                        reply_to_value = None;
+                    } else if let Some(n) = val.as_i64() {
+                        reply_to_value = Some(n);
+                    } else if let Some(n) = val.as_u64() {
+                        reply_to_value = Some(n as i64);
+                    } else if let Some(s) = val.as_str() {
+                        if let Ok(parsed) = s.parse::<i64>() {
+                            reply_to_value = Some(parsed);
+                        }
+                    }
+                }
+            }
+
+            let mut message_kind_update_flag = false;
+            let mut message_kind_value: Option<String> = None;
+            if let Some(obj) = relations_obj {
+                if let Some(val) = obj.get("message_kind") {
+                    message_kind_update_flag = true;
+                    if val.is_null() {
+                        message_kind_value = None;
+                    } else if let Some(s) = val.as_str() {
+                        message_kind_value = Some(s.to_string());
+                    } else {
+                        message_kind_value = Some(val.to_string());
+                    }
+                }
+            }
+
+            let mut lifecycle_update_flag = false;
+            let mut lifecycle_value: Option<String> = None;
+            if let Some(obj) = relations_obj {
+                if let Some(val) = obj.get("lifecycle") {
+                    lifecycle_update_flag = true;
+                    if val.is_null() {
+                        lifecycle_value = None;
+                    } else if let Some(s) = val.as_str() {
+                        lifecycle_value = Some(s.to_string());
+                    } else {
+                        lifecycle_value = Some(val.to_string());
+                    }
+                }
+            }
+
+            let relations_json_for_update = relations_json_value.clone();
+            let relations_json_for_insert = relations_json_value.clone();
+            let turn_id_value_for_update = turn_id_value.clone();
+            let turn_id_value_for_insert = turn_id_value.clone();
+            let message_kind_value_for_update = message_kind_value.clone();
+            let message_kind_value_for_insert = message_kind_value.clone();
+            let lifecycle_value_for_update = lifecycle_value.clone();
+            let lifecycle_value_for_insert = lifecycle_value.clone();
+
+            // SOTA: 获取稳定ID（与save_mistake保持一致）
             let stable_id = message.persistent_stable_id.clone();
 
             let metadata_json = message
@@ -2931,6 +3836,11 @@ impl Database {
                 if let Some(&existing_id) = existing_messages.get(stable_id_ref) {
                     // 已存在：执行 UPDATE（包含 thinking_content 等所有字段）
                     matched_ids.insert(existing_id);
+                    let relations_json_for_update = if message.role == "user" {
+                        preserve_active_branch(&tx, existing_id, relations_json_for_update)?
+                    } else {
+                        relations_json_for_update
+                    };
                     tx.execute(
                         "UPDATE chat_messages SET role = ?1, content = ?2, timestamp = ?3, thinking_content = ?4, rag_sources = ?5, memory_sources = ?6, graph_sources = ?7, web_search_sources = ?8, image_paths = ?9, image_base64 = ?10, doc_attachments = ?11, tool_call = ?12, tool_result = ?13, overrides = ?14, metadata = ?15, relations = CASE WHEN ?16 THEN ?17 ELSE relations END, turn_id = CASE WHEN ?18 THEN ?19 ELSE turn_id END, turn_seq = CASE WHEN ?20 THEN ?21 ELSE turn_seq END, reply_to_msg_id = CASE WHEN ?22 THEN ?23 ELSE reply_to_msg_id END, message_kind = CASE WHEN ?24 THEN ?25 ELSE message_kind END, lifecycle = CASE WHEN ?26 THEN ?27 ELSE lifecycle END WHERE id = ?28",
                         rusqlite::params![
@@ -3017,2823 +3927,4500 @@ impl Database {
                     missing_stable_id_count += 1;
                 }
 
-                // 兼容模式：列不存在或没有 stable_id，直接 INSERT
-                if has_stable_id_column {
+                // 兼容模式：列不存在或没有 stable_id，直接 INSERT
+                if has_stable_id_column {
+                    tx.execute(
+                    "INSERT INTO chat_messages \
+                     (mistake_id, role, content, timestamp, thinking_content, rag_sources, memory_sources, graph_sources, web_search_sources, image_paths, image_base64, doc_attachments, tool_call, tool_result, overrides, relations, turn_id, turn_seq, reply_to_msg_id, message_kind, lifecycle, stable_id, metadata) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, NULL, ?22)",
+                        rusqlite::params![
+                            mistake_id,
+                            message.role,
+                            message.content,
+                            message.timestamp.to_rfc3339(),
+                            message.thinking_content,
+                            rag_sources_json,
+                            memory_sources_json,
+                            graph_sources_json,
+                            web_search_sources_json,
+                            image_paths_json,
+                            image_base64_json,
+                            doc_attachments_json,
+                            tool_call_json,
+                            tool_result_json,
+                            overrides_json,
+                            relations_json_for_insert,
+                            turn_id_value_for_insert,
+                            turn_seq_value,
+                            reply_to_value,
+                            message_kind_value_for_insert,
+                            lifecycle_value_for_insert,
+                            metadata_json.clone(),
+                        ],
+                    )?;
+                } else {
+                    tx.execute(
+                    "INSERT INTO chat_messages \
+                     (mistake_id, role, content, timestamp, thinking_content, rag_sources, memory_sources, graph_sources, web_search_sources, image_paths, image_base64, doc_attachments, tool_call, tool_result, overrides, relations, turn_id, turn_seq, reply_to_msg_id, message_kind, lifecycle, metadata) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
+                    rusqlite::params![
+                        mistake_id,
+                        message.role,
+                        message.content,
+                        message.timestamp.to_rfc3339(),
+                        message.thinking_content,
+                        rag_sources_json,
+                        memory_sources_json,
+                        graph_sources_json,
+                        web_search_sources_json,
+                        image_paths_json,
+                        image_base64_json,
+                        doc_attachments_json,
+                        tool_call_json,
+                        tool_result_json,
+                        overrides_json,
+                        relations_json_for_insert,
+                        turn_id_value_for_insert,
+                        turn_seq_value,
+                        reply_to_value,
+                        message_kind_value_for_insert,
+                        lifecycle_value_for_insert,
+                            metadata_json.clone(),
+                    ],
+                )?;
+                }
+                if message.role == "user" {
+                    inserted_ids.push(tx.last_insert_rowid());
+                }
+            }
+            if latest_ts.map_or(true, |t: DateTime<Utc>| message.timestamp > t) {
+                latest_ts = Some(message.timestamp);
+            }
+        }
+
+        if let Some(ts) = latest_ts {
+            tx.execute(
+                "UPDATE mistakes SET updated_at = ?1, last_accessed_at = ?1 WHERE id = ?2",
+                rusqlite::params![ts.to_rfc3339(), mistake_id],
+            )?;
+        }
+
+        self.backfill_turn_metadata(&tx, mistake_id)?;
+
+        // 更新 updated_at，并记录本次写入内容的签名：所有追加路径都刷新签名，
+        // 自动保存只有在快照与最近一次写入完全相同时才会跳过
+        tx.execute(
+            "UPDATE mistakes SET updated_at = ?1, autosave_signature = ?2 WHERE id = ?3",
+            rusqlite::params![
+                chrono::Utc::now().to_rfc3339(),
+                chat_messages_signature(messages)?,
+                mistake_id
+            ],
+        )?;
+
+        // 若无新的消息，保持最近访问时间
+        if latest_ts.is_none() {
+            tx.execute(
+                "UPDATE mistakes SET updated_at = CURRENT_TIMESTAMP, last_accessed_at = CURRENT_TIMESTAMP WHERE id = ?1",
+                rusqlite::params![mistake_id],
+            )?;
+        }
+
+        Ok(AppendMessagesChangeSet {
+            updated_user_message_ids: updated_ids,
+            inserted_user_message_ids: inserted_ids,
+            assistant_message_count: assistant_count,
+            tool_message_count: tool_count,
+            other_message_count: other_count,
+            missing_stable_id_count,
+            total_processed: messages.len(),
+        })
+    }
+
+    pub fn mark_chat_embedding_retry(&self, ids: &[i64], pending: bool) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let value = if pending { 1 } else { 0 };
+        let conn = self.get_conn_safe()?;
+        for id in ids {
+            let _ = conn.execute(
+                "UPDATE chat_messages SET embedding_retry = ?1 WHERE id = ?2",
+                rusqlite::params![value, id],
+            );
+        }
+        Ok(())
+    }
+
+    pub fn delete_chat_embeddings_by_ids(&self, ids: &[i64]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let conn = self.get_conn_safe()?;
+        let placeholders = vec!["?"; ids.len()].join(",");
+        let sql = format!(
+            "DELETE FROM chat_user_embeddings WHERE message_id IN ({})",
+            placeholders
+        );
+        let params = rusqlite::params_from_iter(ids.iter());
+        conn.execute(&sql, params)?;
+        Ok(())
+    }
+
+    pub fn list_turn_message_ids(
+        &self,
+        mistake_id: &str,
+        turn_id: &str,
+        include_user: bool,
+    ) -> Result<Vec<i64>> {
+        let conn = self.get_conn_safe()?;
+        let sql = if include_user {
+            "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2"
+        } else {
+            "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 \
+             AND (turn_seq >= 1 OR (turn_seq IS NULL AND role != 'user'))"
+        };
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt
+            .query_map(rusqlite::params![mistake_id, turn_id], |row| {
+                Ok(row.get::<_, i64>(0)?)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// 按回合删除：根据 turn_id 删除消息
+    /// - delete_user=true 删除整回合（user+assistant）
+    /// - 否则仅删 assistant（turn_seq>=1，含全部分支；删除单个分支见 `cmd::mistake_branches`）
+    pub fn delete_chat_turn(
+        &self,
+        mistake_id: &str,
+        turn_id: &str,
+        delete_user: bool,
+    ) -> Result<usize> {
+        let mut conn = self.get_conn_safe()?;
+        let tx = conn.transaction()?;
+        let affected = if delete_user {
+            tx.execute(
+                "DELETE FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2",
+                rusqlite::params![mistake_id, turn_id],
+            )?
+        } else {
+            tx.execute(
+                "DELETE FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq >= 1",
+                rusqlite::params![mistake_id, turn_id],
+            )?
+        };
+        // 删除后已保存的快照签名失效，下一次自动保存必须写库
+        tx.execute(
+            "UPDATE mistakes SET updated_at = ?1, autosave_signature = NULL WHERE id = ?2",
+            rusqlite::params![chrono::Utc::now().to_rfc3339(), mistake_id],
+        )?;
+        tx.commit()?;
+        Ok(affected)
+    }
+
+    /// 按回合删除（详细返回）
+    pub fn delete_chat_turn_detail(
+        &self,
+        mistake_id: &str,
+        turn_id: &str,
+        delete_user: bool,
+    ) -> Result<crate::models::DeleteChatTurnResult> {
+        let mut conn = self.get_conn_safe()?;
+        let tx = conn.transaction()?;
+
+        // 统计当前回合的 user/assistant 存在性
+        let user_exists: i64 = tx.query_row(
+            "SELECT COUNT(1) FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq = 0",
+            rusqlite::params![mistake_id, turn_id],
+            |r| r.get(0),
+        )?;
+        let assistant_exists: i64 = tx.query_row(
+            "SELECT COUNT(1) FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq >= 1",
+            rusqlite::params![mistake_id, turn_id],
+            |r| r.get(0),
+        )?;
+
+        let affected = if delete_user {
+            tx.execute(
+                "DELETE FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2",
+                rusqlite::params![mistake_id, turn_id],
+            )?
+        } else {
+            tx.execute(
+                "DELETE FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq >= 1",
+                rusqlite::params![mistake_id, turn_id],
+            )?
+        };
+
+        tx.execute(
+            "UPDATE mistakes SET updated_at = ?1, autosave_signature = NULL WHERE id = ?2",
+            rusqlite::params![chrono::Utc::now().to_rfc3339(), mistake_id],
+        )?;
+        tx.commit()?;
+
+        let mut note: Option<String> = None;
+        if delete_user && user_exists > 0 && assistant_exists == 0 {
+            note = Some("无助手侧".to_string());
+        }
+        log::debug!(
+            "[删除回合] mistake_id={}, turn_id={}, delete_user={}, deleted_count={}, note={:?}",
+            mistake_id,
+            turn_id,
+            delete_user,
+            affected,
+            note
+        );
+
+        Ok(crate::models::DeleteChatTurnResult {
+            mistake_id: mistake_id.to_string(),
+            turn_id: turn_id.to_string(),
+            deleted_count: affected as usize,
+            full_turn_deleted: delete_user,
+            note,
+        })
+    }
+
+    /// 修复未配对的回合（根据时间顺序重新分配 turn_id 并配对）
+    pub fn repair_unpaired_turns(&self, mistake_id: &str) -> Result<usize> {
+        let mut conn = self.get_conn_safe()?;
+        let tx = conn.transaction()?;
+
+        let mut fixed = 0usize;
+
+        // 为所有未配对的 user 分配 turn_id（若缺失）
+        {
+            let mut users_stmt = tx.prepare(
+                "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND role = 'user' AND (turn_id IS NULL OR turn_id = '') ORDER BY timestamp ASC",
+            )?;
+            let user_rows: Vec<i64> = users_stmt
+                .query_map(rusqlite::params![mistake_id], |row| {
+                    Ok(row.get::<_, i64>(0)?)
+                })?
+                .collect::<std::result::Result<_, _>>()?;
+            drop(users_stmt);
+            for user_row_id in user_rows {
+                let turn_id = uuid::Uuid::new_v4().to_string();
+                tx.execute(
+                    "UPDATE chat_messages SET turn_id = ?1, turn_seq = 0, reply_to_msg_id = NULL, message_kind = COALESCE(message_kind, 'user.input') WHERE id = ?2",
+                    rusqlite::params![turn_id, user_row_id],
+                )?;
+                fixed += 1;
+            }
+        }
+
+        // 为所有未配对的 assistant 绑定到最近一个尚未有助手的 user 回合
+        {
+            let mut assistants_stmt = tx.prepare(
+                "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND role = 'assistant' AND (turn_id IS NULL OR turn_id = '') ORDER BY timestamp ASC",
+            )?;
+            let assistant_rows: Vec<i64> = assistants_stmt
+                .query_map(rusqlite::params![mistake_id], |row| {
+                    Ok(row.get::<_, i64>(0)?)
+                })?
+                .collect::<std::result::Result<_, _>>()?;
+            drop(assistants_stmt);
+            for assistant_row_id in assistant_rows {
+                let candidate: Option<(i64, String)> = tx
+                    .query_row(
+                        "SELECT u.id, u.turn_id \
+                         FROM chat_messages u \
+                         WHERE u.mistake_id = ?1 AND u.role = 'user' AND u.turn_id IS NOT NULL AND u.turn_id <> '' \
+                           AND NOT EXISTS (SELECT 1 FROM chat_messages a WHERE a.mistake_id = ?1 AND a.role = 'assistant' AND a.turn_id = u.turn_id) \
+                         ORDER BY u.timestamp DESC LIMIT 1",
+                        rusqlite::params![mistake_id],
+                        |r| Ok((r.get(0)?, r.get(1)?)),
+                    )
+                    .optional()?;
+                if let Some((user_row_id, turn_id)) = candidate {
                     tx.execute(
-                    "INSERT INTO chat_messages \
-                     (mistake_id, role, content, timestamp, thinking_content, rag_sources, memory_sources, graph_sources, web_search_sources, image_paths, image_base64, doc_attachments, tool_call, tool_result, overrides, relations, turn_id, turn_seq, reply_to_msg_id, message_kind, lifecycle, stable_id, metadata) \
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, NULL, ?22)",
-                        rusqlite::params![
-                            mistake_id,
-                            message.role,
-                            message.content,
-                            message.timestamp.to_rfc3339(),
-                            message.thinking_content,
-                            rag_sources_json,
-                            memory_sources_json,
-                            graph_sources_json,
-                            web_search_sources_json,
-                            image_paths_json,
-                            image_base64_json,
-                            doc_attachments_json,
-                            tool_call_json,
-                            tool_result_json,
-                            overrides_json,
-                            relations_json_for_insert,
-                            turn_id_value_for_insert,
-                            turn_seq_value,
-                            reply_to_value,
-                            message_kind_value_for_insert,
-                            lifecycle_value_for_insert,
-                            metadata_json.clone(),
-                        ],
+                        "UPDATE chat_messages SET turn_id = ?1, turn_seq = 1, reply_to_msg_id = ?2, message_kind = COALESCE(message_kind, 'assistant.answer'), lifecycle = COALESCE(lifecycle, 'complete') WHERE id = ?3",
+                        rusqlite::params![turn_id, user_row_id, assistant_row_id],
                     )?;
+                    fixed += 1;
                 } else {
-                    tx.execute(
-                    "INSERT INTO chat_messages \
-                     (mistake_id, role, content, timestamp, thinking_content, rag_sources, memory_sources, graph_sources, web_search_sources, image_paths, image_base64, doc_attachments, tool_call, tool_result, overrides, relations, turn_id, turn_seq, reply_to_msg_id, message_kind, lifecycle, metadata) \
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
-                    rusqlite::params![
+                    log::warn!(
+                        "[回合修复] 仍有孤儿助手消息，mistake_id={}, assistant_row_id={}",
                         mistake_id,
-                        message.role,
-                        message.content,
-                        message.timestamp.to_rfc3339(),
-                        message.thinking_content,
-                        rag_sources_json,
-                        memory_sources_json,
-                        graph_sources_json,
-                        web_search_sources_json,
-                        image_paths_json,
-                        image_base64_json,
-                        doc_attachments_json,
-                        tool_call_json,
-                        tool_result_json,
-                        overrides_json,
-                        relations_json_for_insert,
-                        turn_id_value_for_insert,
-                        turn_seq_value,
-                        reply_to_value,
-                        message_kind_value_for_insert,
-                        lifecycle_value_for_insert,
-                            metadata_json.clone(),
-                    ],
-                )?;
-                }
-                if message.role == "user" {
-                    inserted_ids.push(tx.last_insert_rowid());
+                        assistant_row_id
+                    );
                 }
             }
-            if latest_ts.map_or(true, |t: DateTime<Utc>| message.timestamp > t) {
-                latest_ts = Some(message.timestamp);
-            }
         }
 
-        if let Some(ts) = latest_ts {
-            tx.execute(
-                "UPDATE mistakes SET updated_at = ?1, last_accessed_at = ?1 WHERE id = ?2",
-                rusqlite::params![ts.to_rfc3339(), mistake_id],
-            )?;
+        tx.execute(
+            "UPDATE mistakes SET updated_at = ?1 WHERE id = ?2",
+            rusqlite::params![chrono::Utc::now().to_rfc3339(), mistake_id],
+        )?;
+
+        tx.commit()?;
+        log::debug!(
+            "[repair_unpaired_turns] mistake_id={}, 修复条目数={}",
+            mistake_id,
+            fixed
+        );
+        Ok(fixed)
+    }
+
+    /// 管理工具：列出孤儿助手行（无 reply_to_msg_id）
+    pub fn list_orphan_assistants(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<crate::models::OrphanAssistantRow>> {
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, mistake_id, timestamp, content FROM chat_messages WHERE role = 'assistant' AND (reply_to_msg_id IS NULL) ORDER BY timestamp DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(rusqlite::params![limit as i64], |row| {
+            let ts: String = row.get(2)?;
+            let ts_parsed = chrono::DateTime::parse_from_rfc3339(&ts)
+                .map_err(|_| {
+                    rusqlite::Error::InvalidColumnType(
+                        2,
+                        "timestamp".to_string(),
+                        rusqlite::types::Type::Text,
+                    )
+                })?
+                .with_timezone(&Utc);
+            let content: String = row.get(3)?;
+            let preview = content.chars().take(80).collect::<String>();
+            Ok(crate::models::OrphanAssistantRow {
+                id: row.get(0)?,
+                mistake_id: row.get(1)?,
+                timestamp: ts_parsed,
+                content_preview: preview,
+            })
+        })?;
+        let mut out = Vec::new();
+        for r in rows {
+            out.push(r?);
         }
+        Ok(out)
+    }
 
-        self.backfill_turn_metadata(&tx, mistake_id)?;
+    /// 管理工具：列出遗留 tool 行样本
+    pub fn list_tool_rows_for_review(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<crate::models::ToolRowSample>> {
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, mistake_id, timestamp, role, content FROM chat_messages WHERE role = 'tool' ORDER BY timestamp DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(rusqlite::params![limit as i64], |row| {
+            let ts: String = row.get(2)?;
+            let ts_parsed = chrono::DateTime::parse_from_rfc3339(&ts)
+                .map_err(|_| {
+                    rusqlite::Error::InvalidColumnType(
+                        2,
+                        "timestamp".to_string(),
+                        rusqlite::types::Type::Text,
+                    )
+                })?
+                .with_timezone(&Utc);
+            let content: String = row.get(4)?;
+            let preview = content.chars().take(80).collect::<String>();
+            Ok(crate::models::ToolRowSample {
+                id: row.get(0)?,
+                mistake_id: row.get(1)?,
+                timestamp: ts_parsed,
+                role: row.get(3)?,
+                content_preview: preview,
+            })
+        })?;
+        let mut out = Vec::new();
+        for r in rows {
+            out.push(r?);
+        }
+        Ok(out)
+    }
+
+    /// 保存设置
+    pub fn save_setting(&self, key: &str, value: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value, updated_at) VALUES (?1, ?2, ?3)",
+            params![key, value, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// 获取设置
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.get_conn_safe()?;
+        conn.query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(Into::into)
+    }
+
+    /// 删除设置
+    pub fn delete_setting(&self, key: &str) -> Result<bool> {
+        let conn = self.get_conn_safe()?;
+        let changes = conn.execute("DELETE FROM settings WHERE key = ?1", params![key])?;
+        Ok(changes > 0)
+    }
+
+    /// 按前缀查询设置（用于工具权限管理等批量查询场景）
+    pub fn get_settings_by_prefix(&self, prefix: &str) -> Result<Vec<(String, String, String)>> {
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            "SELECT key, value, updated_at FROM settings WHERE key LIKE ?1 ORDER BY updated_at DESC",
+        )?;
+        let pattern = format!("{}%", prefix);
+        let rows = stmt.query_map(params![pattern], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut out = Vec::new();
+        for row in rows {
+            out.push(row?);
+        }
+        Ok(out)
+    }
+
+    /// 按前缀批量删除设置
+    pub fn delete_settings_by_prefix(&self, prefix: &str) -> Result<usize> {
+        let conn = self.get_conn_safe()?;
+        let pattern = format!("{}%", prefix);
+        let changes = conn.execute("DELETE FROM settings WHERE key LIKE ?1", params![pattern])?;
+        Ok(changes)
+    }
 
-        // 更新 updated_at（不改变其他字段）
-        tx.execute(
-            "UPDATE mistakes SET updated_at = ?1 WHERE id = ?2",
-            rusqlite::params![chrono::Utc::now().to_rfc3339(), mistake_id],
+    /// 新增：持久化流式上下文（首轮分析的缓存数据）
+    pub fn upsert_temp_session(&self, session: &StreamContext) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        let session_json =
+            serde_json::to_string(session).context("Failed to serialize stream context")?;
+        let now = Utc::now().to_rfc3339();
+        let last_error = session.last_error.as_deref();
+        conn.execute(
+            "INSERT INTO temp_sessions (temp_id, session_data, stream_state, created_at, updated_at, last_error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(temp_id) DO UPDATE SET
+                session_data=excluded.session_data,
+                stream_state=excluded.stream_state,
+                updated_at=excluded.updated_at,
+                last_error=excluded.last_error",
+            params![
+                &session.temp_id,
+                session_json,
+                session.stream_state.as_str(),
+                session.created_at.to_rfc3339(),
+                now,
+                last_error,
+            ],
         )?;
+        Ok(())
+    }
 
-        // 若无新的消息，保持最近访问时间
-        if latest_ts.is_none() {
-            tx.execute(
-                "UPDATE mistakes SET updated_at = CURRENT_TIMESTAMP, last_accessed_at = CURRENT_TIMESTAMP WHERE id = ?1",
-                rusqlite::params![mistake_id],
-            )?;
+    /// 读取流式上下文（首轮分析的缓存数据）
+    pub fn get_temp_session_record(&self, temp_id: &str) -> Result<Option<StreamContext>> {
+        let conn = self.get_conn_safe()?;
+        let raw: Option<String> = conn
+            .query_row(
+                "SELECT session_data FROM temp_sessions WHERE temp_id = ?1",
+                params![temp_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(json) = raw {
+            let session: StreamContext =
+                serde_json::from_str(&json).context("Failed to deserialize stream context")?;
+            // 兼容旧数据：默认状态为 in_progress
+            if matches!(session.stream_state, TempStreamState::InProgress) {
+                // no-op, exists for clarity
+            }
+            Ok(Some(session))
+        } else {
+            Ok(None)
         }
+    }
 
-        tx.commit()?;
+    /// 删除临时会话记录
+    pub fn delete_temp_session_record(&self, temp_id: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        let _ = conn.execute(
+            "DELETE FROM temp_sessions WHERE temp_id = ?1",
+            params![temp_id],
+        )?;
+        Ok(())
+    }
 
-        let skipped_count = messages.len() - (updated_ids.len() + inserted_ids.len());
-        if skipped_count > 0 {
-            log::debug!(
-                "[Append-NoChange] 跳过 {} 条无变更消息 (mistake_id={})",
-                skipped_count,
-                mistake_id
-            );
-        }
-        if !updated_ids.is_empty() {
-            log::debug!(
-                "[Append-Updated] 更新 {} 条已变更消息 (mistake_id={})",
-                updated_ids.len(),
-                mistake_id
-            );
-        }
-        if !inserted_ids.is_empty() {
-            log::debug!(
-                "[Append-Inserted] 插入 {} 条新消息 (mistake_id={})",
-                inserted_ids.len(),
-                mistake_id
-            );
+    /// 保存敏感设置（优先使用安全存储）
+    pub fn save_secret(&self, key: &str, value: &str) -> Result<()> {
+        // 检查是否为敏感键
+        if SecureStore::is_sensitive_key(key) {
+            if let Some(ref secure_store) = self.secure_store {
+                match secure_store.save_secret(key, value) {
+                    Ok(_) => {
+                        // 成功保存到安全存储，从数据库删除明文（如果存在）
+                        let _ = self.delete_setting(key);
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        // 安全存储失败，记录警告并回退到数据库
+                        if secure_store.get_config().warn_on_fallback {
+                            log::warn!("安全存储失败，回退到明文存储: {} - {}", key, e);
+                        }
+                    }
+                }
+            }
         }
 
-        Ok(AppendMessagesChangeSet {
-            updated_user_message_ids: updated_ids,
-            inserted_user_message_ids: inserted_ids,
-            assistant_message_count: assistant_count,
-            tool_message_count: tool_count,
-            other_message_count: other_count,
-            missing_stable_id_count,
-            total_processed: messages.len(),
-        })
+        // 回退到普通数据库存储
+        self.save_setting(key, value)
     }
 
-    pub fn mark_chat_embedding_retry(&self, ids: &[i64], pending: bool) -> Result<()> {
-        if ids.is_empty() {
-            return Ok(());
-        }
-        let value = if pending { 1 } else { 0 };
-        let conn = self.get_conn_safe()?;
-        for id in ids {
-            let _ = conn.execute(
-                "UPDATE chat_messages SET embedding_retry = ?1 WHERE id = ?2",
-                rusqlite::params![value, id],
-            );
+    /// 获取敏感设置（优先从安全存储获取）
+    pub fn get_secret(&self, key: &str) -> Result<Option<String>> {
+        // 检查是否为敏感键且安全存储可用
+        if SecureStore::is_sensitive_key(key) {
+            if let Some(ref secure_store) = self.secure_store {
+                match secure_store.get_secret(key) {
+                    Ok(Some(value)) => {
+                        // 从安全存储成功获取
+                        return Ok(Some(value));
+                    }
+                    Ok(None) => {
+                        // 安全存储中没有，继续尝试数据库
+                    }
+                    Err(e) => {
+                        // 安全存储访问失败，记录警告并回退
+                        log::warn!("安全存储读取失败，回退到数据库: {} - {}", key, e);
+                    }
+                }
+            }
         }
-        Ok(())
+
+        // 回退到普通数据库存储
+        self.get_setting(key)
     }
 
-    pub fn delete_chat_embeddings_by_ids(&self, ids: &[i64]) -> Result<()> {
-        if ids.is_empty() {
-            return Ok(());
+    /// 删除敏感设置（同时从安全存储和数据库删除）
+    pub fn delete_secret(&self, key: &str) -> Result<bool> {
+        let mut deleted = false;
+
+        // 从安全存储删除
+        if SecureStore::is_sensitive_key(key) {
+            if let Some(ref secure_store) = self.secure_store {
+                if let Err(e) = secure_store.delete_secret(key) {
+                    log::warn!("从安全存储删除失败: {} - {}", key, e);
+                } else {
+                    deleted = true;
+                }
+            }
         }
-        let conn = self.get_conn_safe()?;
-        let placeholders = vec!["?"; ids.len()].join(",");
-        let sql = format!(
-            "DELETE FROM chat_user_embeddings WHERE message_id IN ({})",
-            placeholders
-        );
-        let params = rusqlite::params_from_iter(ids.iter());
-        conn.execute(&sql, params)?;
-        Ok(())
+
+        // 从数据库删除
+        let db_deleted = self.delete_setting(key)?;
+
+        Ok(deleted || db_deleted)
     }
 
-    pub fn list_turn_message_ids(
+    // ================= Research reports =================
+
+    pub fn insert_research_report(
         &self,
-        mistake_id: &str,
-        turn_id: &str,
-        include_user: bool,
-    ) -> Result<Vec<i64>> {
+        subject: &str,
+        segments: i32,
+        context_window: i32,
+        report: &str,
+        metadata_json: Option<&str>,
+    ) -> Result<String> {
+        let id = uuid::Uuid::new_v4().to_string();
         let conn = self.get_conn_safe()?;
-        let sql = if include_user {
-            "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2"
-        } else {
-            "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 \
-             AND (turn_seq = 1 OR (turn_seq IS NULL AND role != 'user'))"
-        };
-        let mut stmt = conn.prepare(sql)?;
-        let rows = stmt
-            .query_map(rusqlite::params![mistake_id, turn_id], |row| {
-                Ok(row.get::<_, i64>(0)?)
-            })?
-            .collect::<rusqlite::Result<Vec<_>>>()?;
-        Ok(rows)
+        conn.execute(
+            "INSERT INTO research_reports (id, subject, created_at, segments, context_window, report, metadata) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![id, subject, chrono::Utc::now().to_rfc3339(), segments, context_window, report, metadata_json]
+        )?;
+        Ok(id)
     }
 
-    /// 按回合删除：根据 turn_id 删除消息
-    /// - delete_user=true 删除整回合（user+assistant）
-    /// - 否则仅删 assistant（turn_seq=1）
-    pub fn delete_chat_turn(
+    pub fn list_research_reports(
         &self,
-        mistake_id: &str,
-        turn_id: &str,
-        delete_user: bool,
-    ) -> Result<usize> {
-        let mut conn = self.get_conn_safe()?;
-        let tx = conn.transaction()?;
-        let affected = if delete_user {
-            tx.execute(
-                "DELETE FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2",
-                rusqlite::params![mistake_id, turn_id],
-            )?
-        } else {
-            tx.execute(
-                "DELETE FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq = 1",
-                rusqlite::params![mistake_id, turn_id],
-            )?
-        };
-        tx.execute(
-            "UPDATE mistakes SET updated_at = ?1 WHERE id = ?2",
-            rusqlite::params![chrono::Utc::now().to_rfc3339(), mistake_id],
+        limit: Option<u32>,
+    ) -> Result<Vec<crate::models::ResearchReportSummary>> {
+        let conn = self.get_conn_safe()?;
+        let mut sql = String::from(
+            "SELECT id, subject, created_at, segments, context_window FROM research_reports ORDER BY created_at DESC",
+        );
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        if let Some(l) = limit {
+            sql.push_str(" LIMIT ?");
+            params.push(Box::new(l as i64));
+        }
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(
+            rusqlite::params_from_iter(params.iter().map(|p| p.as_ref())),
+            |row| {
+                let created_at_str: String = row.get(2)?;
+                let created_at = parse_datetime_flexible(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidQuery)?;
+                Ok(crate::models::ResearchReportSummary {
+                    id: row.get(0)?,
+                    created_at,
+                    segments: row.get(3)?,
+                    context_window: row.get(4)?,
+                })
+            },
         )?;
-        tx.commit()?;
-        Ok(affected)
+        let mut out = Vec::new();
+        for r in rows {
+            out.push(r?);
+        }
+        Ok(out)
     }
 
-    /// 按回合删除（详细返回）
-    pub fn delete_chat_turn_detail(
-        &self,
-        mistake_id: &str,
-        turn_id: &str,
-        delete_user: bool,
-    ) -> Result<crate::models::DeleteChatTurnResult> {
-        let mut conn = self.get_conn_safe()?;
-        let tx = conn.transaction()?;
+    pub fn get_research_report(&self, id: &str) -> Result<Option<crate::models::ResearchReport>> {
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare("SELECT id, subject, created_at, segments, context_window, report, metadata FROM research_reports WHERE id = ?1")?;
+        let opt = stmt
+            .query_row(params![id], |row| {
+                let created_at_str: String = row.get(2)?;
+                let created_at = parse_datetime_flexible(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidQuery)?;
+                let metadata_str: Option<String> = row.get(6).ok();
+                Ok(crate::models::ResearchReport {
+                    id: row.get(0)?,
+                    created_at,
+                    segments: row.get(3)?,
+                    context_window: row.get(4)?,
+                    report: row.get(5)?,
+                    metadata: metadata_str.and_then(|s| serde_json::from_str(&s).ok()),
+                })
+            })
+            .optional()?;
+        Ok(opt)
+    }
 
-        // 统计当前回合的 user/assistant 存在性
-        let user_exists: i64 = tx.query_row(
-            "SELECT COUNT(1) FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq = 0",
-            rusqlite::params![mistake_id, turn_id],
-            |r| r.get(0),
-        )?;
-        let assistant_exists: i64 = tx.query_row(
-            "SELECT COUNT(1) FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq = 1",
-            rusqlite::params![mistake_id, turn_id],
-            |r| r.get(0),
-        )?;
+    pub fn delete_research_report(&self, id: &str) -> Result<bool> {
+        let conn = self.get_conn_safe()?;
+        let n = conn.execute("DELETE FROM research_reports WHERE id = ?1", params![id])?;
+        Ok(n > 0)
+    }
 
-        let affected = if delete_user {
-            tx.execute(
-                "DELETE FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2",
-                rusqlite::params![mistake_id, turn_id],
-            )?
-        } else {
-            tx.execute(
-                "DELETE FROM chat_messages WHERE mistake_id = ?1 AND turn_id = ?2 AND turn_seq = 1",
-                rusqlite::params![mistake_id, turn_id],
-            )?
-        };
+    // 文档31清理：所有 get_*_prompts 函数已删除，SubjectPrompts 类型已废弃
 
-        tx.execute(
-            "UPDATE mistakes SET updated_at = ?1 WHERE id = ?2",
-            rusqlite::params![chrono::Utc::now().to_rfc3339(), mistake_id],
-        )?;
-        tx.commit()?;
+    /// 保存模型分配配置
+    pub fn save_model_assignments(
+        &self,
+        assignments: &crate::models::ModelAssignments,
+    ) -> Result<()> {
+        let assignments_json = serde_json::to_string(assignments)?;
+        self.save_setting("model_assignments", &assignments_json)
+    }
 
-        let mut note: Option<String> = None;
-        if delete_user && user_exists > 0 && assistant_exists == 0 {
-            note = Some("无助手侧".to_string());
+    /// 获取模型分配配置
+    pub fn get_model_assignments(&self) -> Result<Option<crate::models::ModelAssignments>> {
+        match self.get_setting("model_assignments")? {
+            Some(json_str) => {
+                let assignments: crate::models::ModelAssignments = serde_json::from_str(&json_str)?;
+                Ok(Some(assignments))
+            }
+            None => Ok(None),
         }
-        log::debug!(
-            "[删除回合] mistake_id={}, turn_id={}, delete_user={}, deleted_count={}, note={:?}",
-            mistake_id,
-            turn_id,
-            delete_user,
-            affected,
-            note
-        );
-
-        Ok(crate::models::DeleteChatTurnResult {
-            mistake_id: mistake_id.to_string(),
-            turn_id: turn_id.to_string(),
-            deleted_count: affected as usize,
-            full_turn_deleted: delete_user,
-            note,
-        })
     }
 
-    /// 修复未配对的回合（根据时间顺序重新分配 turn_id 并配对）
-    pub fn repair_unpaired_turns(&self, mistake_id: &str) -> Result<usize> {
-        let mut conn = self.get_conn_safe()?;
-        let tx = conn.transaction()?;
-
-        let mut fixed = 0usize;
+    /// 保存API配置列表
+    pub fn save_api_configs(&self, configs: &[crate::llm_manager::ApiConfig]) -> Result<()> {
+        let configs_json = serde_json::to_string(configs)?;
+        self.save_setting("api_configs", &configs_json)
+    }
 
-        // 为所有未配对的 user 分配 turn_id（若缺失）
-        {
-            let mut users_stmt = tx.prepare(
-                "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND role = 'user' AND (turn_id IS NULL OR turn_id = '') ORDER BY timestamp ASC",
-            )?;
-            let user_rows: Vec<i64> = users_stmt
-                .query_map(rusqlite::params![mistake_id], |row| {
-                    Ok(row.get::<_, i64>(0)?)
-                })?
-                .collect::<std::result::Result<_, _>>()?;
-            drop(users_stmt);
-            for user_row_id in user_rows {
-                let turn_id = uuid::Uuid::new_v4().to_string();
-                tx.execute(
-                    "UPDATE chat_messages SET turn_id = ?1, turn_seq = 0, reply_to_msg_id = NULL, message_kind = COALESCE(message_kind, 'user.input') WHERE id = ?2",
-                    rusqlite::params![turn_id, user_row_id],
-                )?;
-                fixed += 1;
+    /// 获取API配置列表
+    pub fn get_api_configs(&self) -> Result<Vec<crate::llm_manager::ApiConfig>> {
+        match self.get_setting("api_configs")? {
+            Some(json_str) => {
+                let configs: Vec<crate::llm_manager::ApiConfig> = serde_json::from_str(&json_str)?;
+                // 兼容旧字段（supports_tools）已在反序列化时通过别名处理，这里无需额外转换。
+                Ok(configs)
             }
+            None => Ok(Vec::new()),
         }
+    }
 
-        // 为所有未配对的 assistant 绑定到最近一个尚未有助手的 user 回合
-        {
-            let mut assistants_stmt = tx.prepare(
-                "SELECT id FROM chat_messages WHERE mistake_id = ?1 AND role = 'assistant' AND (turn_id IS NULL OR turn_id = '') ORDER BY timestamp ASC",
-            )?;
-            let assistant_rows: Vec<i64> = assistants_stmt
-                .query_map(rusqlite::params![mistake_id], |row| {
-                    Ok(row.get::<_, i64>(0)?)
-                })?
-                .collect::<std::result::Result<_, _>>()?;
-            drop(assistants_stmt);
-            for assistant_row_id in assistant_rows {
-                let candidate: Option<(i64, String)> = tx
-                    .query_row(
-                        "SELECT u.id, u.turn_id \
-                         FROM chat_messages u \
-                         WHERE u.mistake_id = ?1 AND u.role = 'user' AND u.turn_id IS NOT NULL AND u.turn_id <> '' \
-                           AND NOT EXISTS (SELECT 1 FROM chat_messages a WHERE a.mistake_id = ?1 AND a.role = 'assistant' AND a.turn_id = u.turn_id) \
-                         ORDER BY u.timestamp DESC LIMIT 1",
-                        rusqlite::params![mistake_id],
-                        |r| Ok((r.get(0)?, r.get(1)?)),
-                    )
-                    .optional()?;
-                if let Some((user_row_id, turn_id)) = candidate {
-                    tx.execute(
-                        "UPDATE chat_messages SET turn_id = ?1, turn_seq = 1, reply_to_msg_id = ?2, message_kind = COALESCE(message_kind, 'assistant.answer'), lifecycle = COALESCE(lifecycle, 'complete') WHERE id = ?3",
-                        rusqlite::params![turn_id, user_row_id, assistant_row_id],
-                    )?;
-                    fixed += 1;
-                } else {
-                    log::warn!(
-                        "[回合修复] 仍有孤儿助手消息，mistake_id={}, assistant_row_id={}",
-                        mistake_id,
-                        assistant_row_id
-                    );
-                }
-            }
-        }
+    // =================== Anki Enhancement Functions ===================
 
-        tx.execute(
-            "UPDATE mistakes SET updated_at = ?1 WHERE id = ?2",
-            rusqlite::params![chrono::Utc::now().to_rfc3339(), mistake_id],
-        )?;
+    /// 插入文档任务
+    /// 🔧 兼容性处理：支持新旧两种表结构（有/无 subject_name 字段）
+    pub fn insert_document_task(&self, task: &DocumentTask) -> Result<()> {
+        tracing::info!(
+            "[insert_document_task] task_id={}, document_id={}, doc_name={}, db_path={:?}",
+            task.id,
+            task.document_id,
+            task.original_document_name,
+            self.db_path()
+        );
+        let conn = self.get_conn_safe()?;
 
-        tx.commit()?;
-        log::debug!(
-            "[repair_unpaired_turns] mistake_id={}, 修复条目数={}",
-            mistake_id,
-            fixed
+        // 检查表是否还有旧的 subject_name 字段
+        let has_subject_name: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('document_tasks') WHERE name='subject_name'",
+                [],
+                |row| row.get::<_, i32>(0).map(|count| count > 0),
+            )
+            .unwrap_or(false);
+
+        if has_subject_name {
+            // 旧表结构：包含 subject_name 字段，使用默认值 "通用"
+            conn.execute(
+                "INSERT INTO document_tasks
+                 (id, document_id, original_document_name, subject_name, segment_index, content_segment,
+                  status, created_at, updated_at, error_message, anki_generation_options_json)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    task.id,
+                    task.document_id,
+                    task.original_document_name,
+                    "通用", // 默认值，兼容旧表结构
+                    task.segment_index,
+                    task.content_segment,
+                    task.status.to_db_string(),
+                    task.created_at,
+                    task.updated_at,
+                    task.error_message,
+                    task.anki_generation_options_json
+                ]
+            )?;
+        } else {
+            // 新表结构：不包含 subject_name 字段
+            conn.execute(
+                "INSERT INTO document_tasks
+                 (id, document_id, original_document_name, segment_index, content_segment,
+                  status, created_at, updated_at, error_message, anki_generation_options_json)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    task.id,
+                    task.document_id,
+                    task.original_document_name,
+                    task.segment_index,
+                    task.content_segment,
+                    task.status.to_db_string(),
+                    task.created_at,
+                    task.updated_at,
+                    task.error_message,
+                    task.anki_generation_options_json
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 🔧 Phase 1: 为指定 document_id 的所有任务设置 source_session_id
+    pub fn set_document_session_source(&self, document_id: &str, session_id: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        // 确保列存在
+        let _ = conn.execute(
+            "ALTER TABLE document_tasks ADD COLUMN source_session_id TEXT",
+            [],
         );
-        Ok(fixed)
+        conn.execute(
+            "UPDATE document_tasks SET source_session_id = ?1 WHERE document_id = ?2 AND source_session_id IS NULL",
+            params![session_id, document_id],
+        )?;
+        Ok(())
     }
 
-    /// 管理工具：列出孤儿助手行（无 reply_to_msg_id）
-    pub fn list_orphan_assistants(
+    /// 更新文档任务状态
+    pub fn update_document_task_status(
         &self,
-        limit: usize,
-    ) -> Result<Vec<crate::models::OrphanAssistantRow>> {
+        task_id: &str,
+        status: TaskStatus,
+        error_message: Option<String>,
+    ) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        let updated_at = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE document_tasks SET status = ?1, error_message = ?2, updated_at = ?3 WHERE id = ?4",
+            params![
+                status.to_db_string(),
+                error_message,
+                updated_at,
+                task_id
+            ]
+        )?;
+        Ok(())
+    }
+
+    /// 获取单个文档任务
+    pub fn get_document_task(&self, task_id: &str) -> Result<DocumentTask> {
         let conn = self.get_conn_safe()?;
         let mut stmt = conn.prepare(
-            "SELECT id, mistake_id, timestamp, content FROM chat_messages WHERE role = 'assistant' AND (reply_to_msg_id IS NULL) ORDER BY timestamp DESC LIMIT ?1",
+            "SELECT id, document_id, original_document_name, segment_index, content_segment,
+                    status, created_at, updated_at, error_message, anki_generation_options_json
+             FROM document_tasks WHERE id = ?1",
         )?;
-        let rows = stmt.query_map(rusqlite::params![limit as i64], |row| {
-            let ts: String = row.get(2)?;
-            let ts_parsed = chrono::DateTime::parse_from_rfc3339(&ts)
-                .map_err(|_| {
-                    rusqlite::Error::InvalidColumnType(
-                        2,
-                        "timestamp".to_string(),
-                        rusqlite::types::Type::Text,
-                    )
-                })?
-                .with_timezone(&Utc);
-            let content: String = row.get(3)?;
-            let preview = content.chars().take(80).collect::<String>();
-            Ok(crate::models::OrphanAssistantRow {
+
+        let task = stmt.query_row(params![task_id], |row| {
+            let status_str: String = row.get(5)?;
+            let status: TaskStatus = TaskStatus::from_str(&status_str);
+            Ok(DocumentTask {
                 id: row.get(0)?,
-                mistake_id: row.get(1)?,
-                timestamp: ts_parsed,
-                content_preview: preview,
+                document_id: row.get(1)?,
+                original_document_name: row.get(2)?,
+                segment_index: row.get(3)?,
+                content_segment: row.get(4)?,
+                status,
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
+                error_message: row.get(8)?,
+                anki_generation_options_json: row.get(9)?,
             })
         })?;
-        let mut out = Vec::new();
-        for r in rows {
-            out.push(r?);
-        }
-        Ok(out)
+
+        Ok(task)
     }
 
-    /// 管理工具：列出遗留 tool 行样本
-    pub fn list_tool_rows_for_review(
+    /// 保存任务的模型原始输出（已按上限截断，`original_bytes` 为截断前的字节数）
+    pub fn save_task_raw_output(
         &self,
-        limit: usize,
-    ) -> Result<Vec<crate::models::ToolRowSample>> {
+        task_id: &str,
+        raw_output: &str,
+        original_bytes: usize,
+    ) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        conn.execute(
+            "UPDATE document_tasks SET raw_output = ?1, raw_output_bytes = ?2 WHERE id = ?3",
+            params![raw_output, original_bytes as i64, task_id],
+        )?;
+        Ok(())
+    }
+
+    /// 获取任务保存的模型原始输出；任务不存在或未保存时返回 None
+    pub fn get_task_raw_output(&self, task_id: &str) -> Result<Option<TaskRawOutput>> {
+        let conn = self.get_conn_safe()?;
+        let row = conn
+            .query_row(
+                "SELECT document_id, status, raw_output, raw_output_bytes
+                 FROM document_tasks WHERE id = ?1 AND raw_output IS NOT NULL",
+                params![task_id],
+                |row| {
+                    let raw_output: String = row.get(2)?;
+                    let original_bytes = row
+                        .get::<_, Option<i64>>(3)?
+                        .map(|n| n.max(0) as usize)
+                        .unwrap_or(raw_output.len());
+                    Ok(TaskRawOutput {
+                        task_id: task_id.to_string(),
+                        document_id: row.get(0)?,
+                        status: TaskStatus::from_str(&row.get::<_, String>(1)?),
+                        truncated: original_bytes > raw_output.len(),
+                        original_bytes,
+                        raw_output,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(row)
+    }
+
+    /// 清空所有任务保存的模型原始输出，返回清空的任务数
+    pub fn clear_task_raw_outputs(&self) -> Result<usize> {
+        let conn = self.get_conn_safe()?;
+        let cleared = conn.execute(
+            "UPDATE document_tasks SET raw_output = NULL, raw_output_bytes = NULL
+             WHERE raw_output IS NOT NULL",
+            [],
+        )?;
+        Ok(cleared)
+    }
+
+    /// 获取指定文档的所有任务
+    pub fn get_tasks_for_document(&self, document_id: &str) -> Result<Vec<DocumentTask>> {
         let conn = self.get_conn_safe()?;
         let mut stmt = conn.prepare(
-            "SELECT id, mistake_id, timestamp, role, content FROM chat_messages WHERE role = 'tool' ORDER BY timestamp DESC LIMIT ?1",
+            "SELECT id, document_id, original_document_name, segment_index, content_segment,
+                    status, created_at, updated_at, error_message, anki_generation_options_json
+             FROM document_tasks WHERE document_id = ?1 ORDER BY segment_index",
         )?;
-        let rows = stmt.query_map(rusqlite::params![limit as i64], |row| {
-            let ts: String = row.get(2)?;
-            let ts_parsed = chrono::DateTime::parse_from_rfc3339(&ts)
-                .map_err(|_| {
-                    rusqlite::Error::InvalidColumnType(
-                        2,
-                        "timestamp".to_string(),
-                        rusqlite::types::Type::Text,
-                    )
-                })?
-                .with_timezone(&Utc);
-            let content: String = row.get(4)?;
-            let preview = content.chars().take(80).collect::<String>();
-            Ok(crate::models::ToolRowSample {
+
+        let task_iter = stmt.query_map(params![document_id], |row| {
+            let status_str: String = row.get(5)?;
+            let status: TaskStatus = TaskStatus::from_str(&status_str);
+            Ok(DocumentTask {
                 id: row.get(0)?,
-                mistake_id: row.get(1)?,
-                timestamp: ts_parsed,
-                role: row.get(3)?,
-                content_preview: preview,
+                document_id: row.get(1)?,
+                original_document_name: row.get(2)?,
+                segment_index: row.get(3)?,
+                content_segment: row.get(4)?,
+                status,
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
+                error_message: row.get(8)?,
+                anki_generation_options_json: row.get(9)?,
             })
         })?;
-        let mut out = Vec::new();
-        for r in rows {
-            out.push(r?);
+
+        let mut tasks = Vec::new();
+        for task in task_iter {
+            tasks.push(task?);
         }
-        Ok(out)
+
+        Ok(tasks)
     }
 
-    /// 保存设置
-    pub fn save_setting(&self, key: &str, value: &str) -> Result<()> {
+    /// 插入Anki卡片（返回是否成功插入）
+    pub fn insert_anki_card(&self, card: &AnkiCard) -> Result<bool> {
         let conn = self.get_conn_safe()?;
-        conn.execute(
-            "INSERT OR REPLACE INTO settings (key, value, updated_at) VALUES (?1, ?2, ?3)",
-            params![key, value, Utc::now().to_rfc3339()],
+        let document_id: Option<String> = conn
+            .query_row(
+                "SELECT document_id FROM document_tasks WHERE id = ?1",
+                params![card.task_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let (source_type, source_id) = if let Some(document_id) = document_id {
+            ("document".to_string(), document_id)
+        } else {
+            ("task".to_string(), card.task_id.clone())
+        };
+
+        let rows_affected = conn.execute(
+            "INSERT OR IGNORE INTO anki_cards
+             (id, task_id, front, back, text, tags_json, images_json,
+              is_error_card, error_content, card_order_in_task, created_at, updated_at,
+              extra_fields_json, template_id, source_type, source_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            params![
+                card.id,
+                card.task_id,
+                card.front,
+                card.back,
+                card.text,
+                serde_json::to_string(&card.tags)?,
+                serde_json::to_string(&card.images)?,
+                if card.is_error_card { 1 } else { 0 },
+                card.error_content,
+                0, // card_order_in_task will be calculated
+                card.created_at,
+                card.updated_at,
+                serde_json::to_string(&card.extra_fields)?,
+                card.template_id,
+                source_type,
+                source_id
+            ],
         )?;
-        Ok(())
+        Ok(rows_affected > 0)
     }
 
-    /// 获取设置
-    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+    /// 获取指定任务的所有卡片
+    pub fn get_cards_for_task(&self, task_id: &str) -> Result<Vec<AnkiCard>> {
         let conn = self.get_conn_safe()?;
-        conn.query_row(
-            "SELECT value FROM settings WHERE key = ?1",
-            params![key],
-            |row| row.get(0),
-        )
-        .optional()
-        .map_err(Into::into)
+        let mut stmt = conn.prepare(
+            "SELECT id, task_id, front, back, text, tags_json, images_json,
+                    is_error_card, error_content, created_at, updated_at,
+                    COALESCE(extra_fields_json, '{}') as extra_fields_json,
+                    template_id
+             FROM anki_cards WHERE task_id = ?1 ORDER BY card_order_in_task, created_at",
+        )?;
+
+        let card_iter = stmt.query_map(params![task_id], |row| {
+            let tags_json: String = row.get(5)?;
+            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+
+            let images_json: String = row.get(6)?;
+            let images: Vec<String> = serde_json::from_str(&images_json).unwrap_or_default();
+
+            let extra_fields_json: String = row.get(11)?;
+            let extra_fields: std::collections::HashMap<String, String> =
+                serde_json::from_str(&extra_fields_json).unwrap_or_default();
+
+            Ok(AnkiCard {
+                id: row.get(0)?,
+                task_id: row.get(1)?,
+                front: row.get(2)?,
+                back: row.get(3)?,
+                text: row.get(4)?,
+                tags,
+                images,
+                is_error_card: row.get::<_, i32>(7)? != 0,
+                error_content: row.get(8)?,
+                created_at: row.get(9)?,
+                updated_at: row.get(10)?,
+                extra_fields,
+                template_id: row.get(12)?,
+            })
+        })?;
+
+        let mut cards = Vec::new();
+        for card in card_iter {
+            cards.push(card?);
+        }
+
+        Ok(cards)
     }
 
-    /// 删除设置
-    pub fn delete_setting(&self, key: &str) -> Result<bool> {
+    /// 获取指定文档的所有卡片
+    /// 文档下已保存的有效卡片数（不含错误卡片），用于文档级卡片上限
+    pub fn count_valid_cards_for_document(&self, document_id: &str) -> Result<u32> {
         let conn = self.get_conn_safe()?;
-        let changes = conn.execute("DELETE FROM settings WHERE key = ?1", params![key])?;
-        Ok(changes > 0)
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM anki_cards ac
+             JOIN document_tasks dt ON ac.task_id = dt.id
+             WHERE dt.document_id = ?1 AND ac.is_error_card = 0",
+            params![document_id],
+            |row| row.get(0),
+        )?;
+        Ok(count as u32)
     }
 
-    /// 按前缀查询设置（用于工具权限管理等批量查询场景）
-    pub fn get_settings_by_prefix(&self, prefix: &str) -> Result<Vec<(String, String, String)>> {
+    pub fn get_cards_for_document(&self, document_id: &str) -> Result<Vec<AnkiCard>> {
         let conn = self.get_conn_safe()?;
         let mut stmt = conn.prepare(
-            "SELECT key, value, updated_at FROM settings WHERE key LIKE ?1 ORDER BY updated_at DESC",
+            "SELECT ac.id, ac.task_id, ac.front, ac.back, ac.text, ac.tags_json, ac.images_json,
+                    ac.is_error_card, ac.error_content, ac.created_at, ac.updated_at,
+                    COALESCE(ac.extra_fields_json, '{}') as extra_fields_json,
+                    ac.template_id
+             FROM anki_cards ac
+             JOIN document_tasks dt ON ac.task_id = dt.id
+             WHERE dt.document_id = ?1
+             ORDER BY dt.segment_index, ac.card_order_in_task, ac.created_at",
         )?;
-        let pattern = format!("{}%", prefix);
-        let rows = stmt.query_map(params![pattern], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-            ))
-        })?;
-        let mut out = Vec::new();
-        for row in rows {
-            out.push(row?);
-        }
-        Ok(out)
-    }
 
-    /// 按前缀批量删除设置
-    pub fn delete_settings_by_prefix(&self, prefix: &str) -> Result<usize> {
-        let conn = self.get_conn_safe()?;
-        let pattern = format!("{}%", prefix);
-        let changes = conn.execute("DELETE FROM settings WHERE key LIKE ?1", params![pattern])?;
-        Ok(changes)
-    }
+        let card_iter = stmt.query_map(params![document_id], |row| {
+            let tags_json: String = row.get(5)?;
+            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
 
-    /// 新增：持久化流式上下文（首轮分析的缓存数据）
-    pub fn upsert_temp_session(&self, session: &StreamContext) -> Result<()> {
-        let conn = self.get_conn_safe()?;
-        let session_json =
-            serde_json::to_string(session).context("Failed to serialize stream context")?;
-        let now = Utc::now().to_rfc3339();
-        let last_error = session.last_error.as_deref();
-        conn.execute(
-            "INSERT INTO temp_sessions (temp_id, session_data, stream_state, created_at, updated_at, last_error)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-             ON CONFLICT(temp_id) DO UPDATE SET
-                session_data=excluded.session_data,
-                stream_state=excluded.stream_state,
-                updated_at=excluded.updated_at,
-                last_error=excluded.last_error",
-            params![
-                &session.temp_id,
-                session_json,
-                session.stream_state.as_str(),
-                session.created_at.to_rfc3339(),
-                now,
-                last_error,
-            ],
-        )?;
-        Ok(())
-    }
+            let images_json: String = row.get(6)?;
+            let images: Vec<String> = serde_json::from_str(&images_json).unwrap_or_default();
 
-    /// 读取流式上下文（首轮分析的缓存数据）
-    pub fn get_temp_session_record(&self, temp_id: &str) -> Result<Option<StreamContext>> {
-        let conn = self.get_conn_safe()?;
-        let raw: Option<String> = conn
-            .query_row(
-                "SELECT session_data FROM temp_sessions WHERE temp_id = ?1",
-                params![temp_id],
-                |row| row.get(0),
-            )
-            .optional()?;
-        if let Some(json) = raw {
-            let session: StreamContext =
-                serde_json::from_str(&json).context("Failed to deserialize stream context")?;
-            // 兼容旧数据：默认状态为 in_progress
-            if matches!(session.stream_state, TempStreamState::InProgress) {
-                // no-op, exists for clarity
-            }
-            Ok(Some(session))
-        } else {
-            Ok(None)
-        }
-    }
+            let extra_fields_json: String = row.get(11)?;
+            let extra_fields: std::collections::HashMap<String, String> =
+                serde_json::from_str(&extra_fields_json).unwrap_or_default();
 
-    /// 删除临时会话记录
-    pub fn delete_temp_session_record(&self, temp_id: &str) -> Result<()> {
-        let conn = self.get_conn_safe()?;
-        let _ = conn.execute(
-            "DELETE FROM temp_sessions WHERE temp_id = ?1",
-            params![temp_id],
-        )?;
-        Ok(())
-    }
+            Ok(AnkiCard {
+                id: row.get(0)?,
+                task_id: row.get(1)?,
+                front: row.get(2)?,
+                back: row.get(3)?,
+                text: row.get(4)?,
+                tags,
+                images,
+                is_error_card: row.get::<_, i32>(7)? != 0,
+                error_content: row.get(8)?,
+                created_at: row.get(9)?,
+                updated_at: row.get(10)?,
+                extra_fields,
+                template_id: row.get(12)?,
+            })
+        })?;
 
-    /// 保存敏感设置（优先使用安全存储）
-    pub fn save_secret(&self, key: &str, value: &str) -> Result<()> {
-        // 检查是否为敏感键
-        if SecureStore::is_sensitive_key(key) {
-            if let Some(ref secure_store) = self.secure_store {
-                match secure_store.save_secret(key, value) {
-                    Ok(_) => {
-                        // 成功保存到安全存储，从数据库删除明文（如果存在）
-                        let _ = self.delete_setting(key);
-                        return Ok(());
-                    }
-                    Err(e) => {
-                        // 安全存储失败，记录警告并回退到数据库
-                        if secure_store.get_config().warn_on_fallback {
-                            log::warn!("安全存储失败，回退到明文存储: {} - {}", key, e);
-                        }
-                    }
-                }
-            }
+        let mut cards = Vec::new();
+        for card in card_iter {
+            cards.push(card?);
         }
 
-        // 回退到普通数据库存储
-        self.save_setting(key, value)
+        Ok(cards)
     }
 
-    /// 获取敏感设置（优先从安全存储获取）
-    pub fn get_secret(&self, key: &str) -> Result<Option<String>> {
-        // 检查是否为敏感键且安全存储可用
-        if SecureStore::is_sensitive_key(key) {
-            if let Some(ref secure_store) = self.secure_store {
-                match secure_store.get_secret(key) {
-                    Ok(Some(value)) => {
-                        // 从安全存储成功获取
-                        return Ok(Some(value));
-                    }
-                    Ok(None) => {
-                        // 安全存储中没有，继续尝试数据库
-                    }
-                    Err(e) => {
-                        // 安全存储访问失败，记录警告并回退
-                        log::warn!("安全存储读取失败，回退到数据库: {} - {}", key, e);
-                    }
-                }
-            }
+    /// 根据ID列表获取卡片
+    pub fn get_cards_by_ids(&self, card_ids: &[String]) -> Result<Vec<AnkiCard>> {
+        if card_ids.is_empty() {
+            return Ok(Vec::new());
         }
 
-        // 回退到普通数据库存储
-        self.get_setting(key)
-    }
+        let conn = self.get_conn_safe()?;
+        let placeholders: Vec<&str> = card_ids.iter().map(|_| "?").collect();
+        let sql = format!(
+            "SELECT id, task_id, front, back, text, tags_json, images_json,
+                    is_error_card, error_content, created_at, updated_at,
+                    COALESCE(extra_fields_json, '{{}}') as extra_fields_json,
+                    template_id
+             FROM anki_cards WHERE id IN ({}) ORDER BY created_at",
+            placeholders.join(",")
+        );
 
-    /// 删除敏感设置（同时从安全存储和数据库删除）
-    pub fn delete_secret(&self, key: &str) -> Result<bool> {
-        let mut deleted = false;
+        let mut stmt = conn.prepare(&sql)?;
+        let card_iter = stmt.query_map(rusqlite::params_from_iter(card_ids), |row| {
+            let tags_json: String = row.get(5)?;
+            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
 
-        // 从安全存储删除
-        if SecureStore::is_sensitive_key(key) {
-            if let Some(ref secure_store) = self.secure_store {
-                if let Err(e) = secure_store.delete_secret(key) {
-                    log::warn!("从安全存储删除失败: {} - {}", key, e);
-                } else {
-                    deleted = true;
-                }
-            }
+            let images_json: String = row.get(6)?;
+            let images: Vec<String> = serde_json::from_str(&images_json).unwrap_or_default();
+
+            let extra_fields_json: String = row.get(11)?;
+            let extra_fields: std::collections::HashMap<String, String> =
+                serde_json::from_str(&extra_fields_json).unwrap_or_default();
+
+            Ok(AnkiCard {
+                id: row.get(0)?,
+                task_id: row.get(1)?,
+                front: row.get(2)?,
+                back: row.get(3)?,
+                text: row.get(4)?,
+                tags,
+                images,
+                is_error_card: row.get::<_, i32>(7)? != 0,
+                error_content: row.get(8)?,
+                created_at: row.get(9)?,
+                updated_at: row.get(10)?,
+                extra_fields,
+                template_id: row.get(12)?,
+            })
+        })?;
+
+        let mut cards = Vec::new();
+        for card in card_iter {
+            cards.push(card?);
         }
 
-        // 从数据库删除
-        let db_deleted = self.delete_setting(key)?;
+        Ok(cards)
+    }
+
+    /// 更新Anki卡片
+    pub fn update_anki_card(&self, card: &AnkiCard) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        let updated_at = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE anki_cards SET
+             front = ?1, back = ?2, text = ?3, tags_json = ?4, images_json = ?5,
+             is_error_card = ?6, error_content = ?7, updated_at = ?8,
+             extra_fields_json = ?9, template_id = ?10
+             WHERE id = ?11",
+            params![
+                card.front,
+                card.back,
+                card.text,
+                serde_json::to_string(&card.tags)?,
+                serde_json::to_string(&card.images)?,
+                if card.is_error_card { 1 } else { 0 },
+                card.error_content,
+                updated_at,
+                serde_json::to_string(&card.extra_fields)?,
+                card.template_id,
+                card.id
+            ],
+        )?;
+        Ok(())
+    }
 
-        Ok(deleted || db_deleted)
+    /// 删除Anki卡片
+    pub fn delete_anki_card(&self, card_id: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        conn.execute("DELETE FROM anki_cards WHERE id = ?1", params![card_id])?;
+        Ok(())
     }
 
-    // ================= Research reports =================
+    /// 删除文档任务及其所有卡片
+    pub fn delete_document_task(&self, task_id: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        // 由于设置了ON DELETE CASCADE，删除任务会自动删除关联的卡片
+        conn.execute("DELETE FROM document_tasks WHERE id = ?1", params![task_id])?;
+        Ok(())
+    }
 
-    pub fn insert_research_report(
-        &self,
-        subject: &str,
-        segments: i32,
-        context_window: i32,
-        report: &str,
-        metadata_json: Option<&str>,
-    ) -> Result<String> {
-        let id = uuid::Uuid::new_v4().to_string();
+    /// 删除整个文档会话（所有任务和卡片）
+    pub fn delete_document_session(&self, document_id: &str) -> Result<()> {
         let conn = self.get_conn_safe()?;
+        // 由于设置了ON DELETE CASCADE，删除任务会自动删除关联的卡片
         conn.execute(
-            "INSERT INTO research_reports (id, subject, created_at, segments, context_window, report, metadata) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![id, subject, chrono::Utc::now().to_rfc3339(), segments, context_window, report, metadata_json]
+            "DELETE FROM document_tasks WHERE document_id = ?1",
+            params![document_id],
         )?;
-        Ok(id)
+        Ok(())
     }
 
-    pub fn list_research_reports(
+    /// 清理早于 `older_than` 的已完成制卡任务（含原文片段），保留其卡片
+    ///
+    /// 仍有未结束任务（排队/处理中/暂停）的文档整体跳过，避免影响断点续传。
+    /// `anki_cards.task_id` 带 `ON DELETE CASCADE`，删除任务前先把卡片移到
+    /// [`ARCHIVED_CARDS_TASK_ID`] 归档任务下；卡片的来源改写为所属文档，卡片库归属不变。
+    pub fn prune_completed_document_tasks(
         &self,
-        limit: Option<u32>,
-    ) -> Result<Vec<crate::models::ResearchReportSummary>> {
-        let conn = self.get_conn_safe()?;
-        let mut sql = String::from(
-            "SELECT id, subject, created_at, segments, context_window FROM research_reports ORDER BY created_at DESC",
-        );
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
-        if let Some(l) = limit {
-            sql.push_str(" LIMIT ?");
-            params.push(Box::new(l as i64));
+        older_than: DateTime<Utc>,
+    ) -> Result<DocumentTaskPruneReport> {
+        let mut conn = self.get_conn_safe()?;
+        let tx = conn.transaction()?;
+        let cutoff = older_than.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let candidates: Vec<(String, String)> = {
+            let mut stmt = tx.prepare(
+                "SELECT id, document_id FROM document_tasks
+                 WHERE status = 'Completed' AND updated_at < ?1 AND id <> ?2
+                   AND document_id NOT IN (
+                       SELECT document_id FROM document_tasks
+                       WHERE status IN ('Pending', 'Processing', 'Streaming', 'Paused'))",
+            )?;
+            let rows = stmt.query_map(params![cutoff, ARCHIVED_CARDS_TASK_ID], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let mut report = DocumentTaskPruneReport::default();
+        if candidates.is_empty() {
+            return Ok(report);
         }
-        let mut stmt = conn.prepare(&sql)?;
-        let rows = stmt.query_map(
-            rusqlite::params_from_iter(params.iter().map(|p| p.as_ref())),
-            |row| {
-                let created_at_str: String = row.get(2)?;
-                let created_at = parse_datetime_flexible(&created_at_str)
-                    .map_err(|_| rusqlite::Error::InvalidQuery)?;
-                Ok(crate::models::ResearchReportSummary {
-                    id: row.get(0)?,
-                    created_at,
-                    segments: row.get(3)?,
-                    context_window: row.get(4)?,
-                })
-            },
+        tx.execute(
+            "INSERT OR IGNORE INTO document_tasks
+             (id, document_id, original_document_name, segment_index, content_segment, status,
+              anki_generation_options_json)
+             VALUES (?1, ?1, '已清理任务的卡片', 0, '', 'Completed', '{}')",
+            params![ARCHIVED_CARDS_TASK_ID],
         )?;
-        let mut out = Vec::new();
-        for r in rows {
-            out.push(r?);
+
+        let mut document_ids = HashSet::new();
+        for (task_id, document_id) in &candidates {
+            // 早期卡片的来源为空或指向任务本身，改写为所属文档
+            tx.execute(
+                "UPDATE anki_cards SET source_type = 'document', source_id = ?2
+                 WHERE task_id = ?1 AND (source_type = '' OR source_type = 'task')",
+                params![task_id, document_id],
+            )?;
+            report.preserved_cards += tx.execute(
+                "UPDATE anki_cards SET task_id = ?2 WHERE task_id = ?1",
+                params![task_id, ARCHIVED_CARDS_TASK_ID],
+            )?;
+            report.pruned_tasks +=
+                tx.execute("DELETE FROM document_tasks WHERE id = ?1", params![task_id])?;
+            document_ids.insert(document_id.clone());
+        }
+        // 任务已全部清理的文档不再需要断点续传状态
+        for document_id in &document_ids {
+            let remaining: i64 = tx.query_row(
+                "SELECT COUNT(*) FROM document_tasks WHERE document_id = ?1",
+                params![document_id],
+                |row| row.get(0),
+            )?;
+            if remaining == 0 {
+                tx.execute(
+                    "DELETE FROM document_control_states WHERE document_id = ?1",
+                    params![document_id],
+                )?;
+                report.cleared_documents += 1;
+            }
         }
-        Ok(out)
+        tx.commit()?;
+        Ok(report)
     }
 
-    pub fn get_research_report(&self, id: &str) -> Result<Option<crate::models::ResearchReport>> {
+    // ==================== RAG配置管理 ====================
+
+    /// 获取RAG配置
+    pub fn get_rag_configuration(&self) -> Result<Option<crate::models::RagConfiguration>> {
         let conn = self.get_conn_safe()?;
-        let mut stmt = conn.prepare("SELECT id, subject, created_at, segments, context_window, report, metadata FROM research_reports WHERE id = ?1")?;
-        let opt = stmt
-            .query_row(params![id], |row| {
-                let created_at_str: String = row.get(2)?;
-                let created_at = parse_datetime_flexible(&created_at_str)
-                    .map_err(|_| rusqlite::Error::InvalidQuery)?;
-                let metadata_str: Option<String> = row.get(6).ok();
-                Ok(crate::models::ResearchReport {
+        let mut stmt = conn.prepare(
+            "SELECT id, chunk_size, chunk_overlap, chunking_strategy, min_chunk_size,
+                    default_top_k, default_rerank_enabled, created_at, updated_at,
+                    embedding_batch_size
+             FROM rag_configurations WHERE id = 'default'",
+        )?;
+
+        let result = stmt
+            .query_row([], |row| {
+                let created_at_str: String = row.get(7)?;
+                let updated_at_str: String = row.get(8)?;
+
+                let created_at = DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| {
+                        rusqlite::Error::InvalidColumnType(
+                            7,
+                            "created_at".to_string(),
+                            rusqlite::types::Type::Text,
+                        )
+                    })?
+                    .with_timezone(&Utc);
+                let updated_at = DateTime::parse_from_rfc3339(&updated_at_str)
+                    .map_err(|_| {
+                        rusqlite::Error::InvalidColumnType(
+                            8,
+                            "updated_at".to_string(),
+                            rusqlite::types::Type::Text,
+                        )
+                    })?
+                    .with_timezone(&Utc);
+
+                Ok(crate::models::RagConfiguration {
                     id: row.get(0)?,
+                    chunk_size: row.get(1)?,
+                    chunk_overlap: row.get(2)?,
+                    chunking_strategy: row.get(3)?,
+                    min_chunk_size: row.get(4)?,
+                    default_top_k: row.get(5)?,
+                    default_rerank_enabled: row.get::<_, i32>(6)? != 0,
+                    embedding_batch_size: row.get(9)?,
                     created_at,
-                    segments: row.get(3)?,
-                    context_window: row.get(4)?,
-                    report: row.get(5)?,
-                    metadata: metadata_str.and_then(|s| serde_json::from_str(&s).ok()),
+                    updated_at,
                 })
             })
             .optional()?;
-        Ok(opt)
-    }
-
-    pub fn delete_research_report(&self, id: &str) -> Result<bool> {
-        let conn = self.get_conn_safe()?;
-        let n = conn.execute("DELETE FROM research_reports WHERE id = ?1", params![id])?;
-        Ok(n > 0)
-    }
-
-    // 文档31清理：所有 get_*_prompts 函数已删除，SubjectPrompts 类型已废弃
-
-    /// 保存模型分配配置
-    pub fn save_model_assignments(
-        &self,
-        assignments: &crate::models::ModelAssignments,
-    ) -> Result<()> {
-        let assignments_json = serde_json::to_string(assignments)?;
-        self.save_setting("model_assignments", &assignments_json)
-    }
-
-    /// 获取模型分配配置
-    pub fn get_model_assignments(&self) -> Result<Option<crate::models::ModelAssignments>> {
-        match self.get_setting("model_assignments")? {
-            Some(json_str) => {
-                let assignments: crate::models::ModelAssignments = serde_json::from_str(&json_str)?;
-                Ok(Some(assignments))
-            }
-            None => Ok(None),
-        }
-    }
-
-    /// 保存API配置列表
-    pub fn save_api_configs(&self, configs: &[crate::llm_manager::ApiConfig]) -> Result<()> {
-        let configs_json = serde_json::to_string(configs)?;
-        self.save_setting("api_configs", &configs_json)
-    }
 
-    /// 获取API配置列表
-    pub fn get_api_configs(&self) -> Result<Vec<crate::llm_manager::ApiConfig>> {
-        match self.get_setting("api_configs")? {
-            Some(json_str) => {
-                let configs: Vec<crate::llm_manager::ApiConfig> = serde_json::from_str(&json_str)?;
-                // 兼容旧字段（supports_tools）已在反序列化时通过别名处理，这里无需额外转换。
-                Ok(configs)
-            }
-            None => Ok(Vec::new()),
-        }
+        Ok(result)
     }
 
-    // =================== Anki Enhancement Functions ===================
-
-    /// 插入文档任务
-    /// 🔧 兼容性处理：支持新旧两种表结构（有/无 subject_name 字段）
-    pub fn insert_document_task(&self, task: &DocumentTask) -> Result<()> {
-        tracing::info!(
-            "[insert_document_task] task_id={}, document_id={}, doc_name={}, db_path={:?}",
-            task.id,
-            task.document_id,
-            task.original_document_name,
-            self.db_path()
-        );
+    /// 更新RAG配置
+    pub fn update_rag_configuration(&self, config: &crate::models::RagConfigRequest) -> Result<()> {
         let conn = self.get_conn_safe()?;
+        let now = Utc::now().to_rfc3339();
+
+        conn.execute(
+            "UPDATE rag_configurations
+             SET chunk_size = ?1, chunk_overlap = ?2, chunking_strategy = ?3,
+                 min_chunk_size = ?4, default_top_k = ?5, default_rerank_enabled = ?6,
+                 embedding_batch_size = COALESCE(?8, embedding_batch_size), updated_at = ?7
+             WHERE id = 'default'",
+            params![
+                config.chunk_size,
+                config.chunk_overlap,
+                config.chunking_strategy,
+                config.min_chunk_size,
+                config.default_top_k,
+                if config.default_rerank_enabled { 1 } else { 0 },
+                now,
+                config.embedding_batch_size
+            ],
+        )?;
 
-        // 检查表是否还有旧的 subject_name 字段
-        let has_subject_name: bool = conn
-            .query_row(
-                "SELECT COUNT(*) FROM pragma_table_info('document_tasks') WHERE name='subject_name'",
-                [],
-                |row| row.get::<_, i32>(0).map(|count| count > 0),
-            )
-            .unwrap_or(false);
-
-        if has_subject_name {
-            // 旧表结构：包含 subject_name 字段，使用默认值 "通用"
-            conn.execute(
-                "INSERT INTO document_tasks
-                 (id, document_id, original_document_name, subject_name, segment_index, content_segment,
-                  status, created_at, updated_at, error_message, anki_generation_options_json)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
-                params![
-                    task.id,
-                    task.document_id,
-                    task.original_document_name,
-                    "通用", // 默认值，兼容旧表结构
-                    task.segment_index,
-                    task.content_segment,
-                    task.status.to_db_string(),
-                    task.created_at,
-                    task.updated_at,
-                    task.error_message,
-                    task.anki_generation_options_json
-                ]
-            )?;
-        } else {
-            // 新表结构：不包含 subject_name 字段
-            conn.execute(
-                "INSERT INTO document_tasks
-                 (id, document_id, original_document_name, segment_index, content_segment,
-                  status, created_at, updated_at, error_message, anki_generation_options_json)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-                params![
-                    task.id,
-                    task.document_id,
-                    task.original_document_name,
-                    task.segment_index,
-                    task.content_segment,
-                    task.status.to_db_string(),
-                    task.created_at,
-                    task.updated_at,
-                    task.error_message,
-                    task.anki_generation_options_json
-                ],
-            )?;
-        }
         Ok(())
     }
 
-    /// 🔧 Phase 1: 为指定 document_id 的所有任务设置 source_session_id
-    pub fn set_document_session_source(&self, document_id: &str, session_id: &str) -> Result<()> {
+    /// 更新嵌入批大小（导入文档时每次嵌入请求的分块数）
+    pub fn update_rag_embedding_batch_size(&self, batch_size: i32) -> Result<()> {
         let conn = self.get_conn_safe()?;
-        // 确保列存在
-        let _ = conn.execute(
-            "ALTER TABLE document_tasks ADD COLUMN source_session_id TEXT",
-            [],
-        );
         conn.execute(
-            "UPDATE document_tasks SET source_session_id = ?1 WHERE document_id = ?2 AND source_session_id IS NULL",
-            params![session_id, document_id],
+            "UPDATE rag_configurations SET embedding_batch_size = ?1, updated_at = ?2
+             WHERE id = 'default'",
+            params![batch_size, Utc::now().to_rfc3339()],
         )?;
         Ok(())
     }
 
-    /// 更新文档任务状态
-    pub fn update_document_task_status(
-        &self,
-        task_id: &str,
-        status: TaskStatus,
-        error_message: Option<String>,
-    ) -> Result<()> {
+    /// 重置RAG配置为默认值
+    pub fn reset_rag_configuration(&self) -> Result<()> {
         let conn = self.get_conn_safe()?;
-        let updated_at = chrono::Utc::now().to_rfc3339();
+        let now = Utc::now().to_rfc3339();
+
         conn.execute(
-            "UPDATE document_tasks SET status = ?1, error_message = ?2, updated_at = ?3 WHERE id = ?4",
-            params![
-                status.to_db_string(),
-                error_message,
-                updated_at,
-                task_id
-            ]
+            "UPDATE rag_configurations
+             SET chunk_size = 512, chunk_overlap = 50, chunking_strategy = 'fixed_size',
+                 min_chunk_size = 20, default_top_k = 5, default_rerank_enabled = 1,
+                 embedding_batch_size = 16, updated_at = ?1
+             WHERE id = 'default'",
+            params![now],
         )?;
+
         Ok(())
     }
 
-    /// 获取单个文档任务
-    pub fn get_document_task(&self, task_id: &str) -> Result<DocumentTask> {
+    // ==================== RAG分库管理CRUD操作 ====================
+
+    /// 创建新的分库
+    pub fn create_sub_library(&self, request: &CreateSubLibraryRequest) -> Result<SubLibrary> {
         let conn = self.get_conn_safe()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, document_id, original_document_name, segment_index, content_segment,
-                    status, created_at, updated_at, error_message, anki_generation_options_json
-             FROM document_tasks WHERE id = ?1",
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = Utc::now();
+        let now_str = now.to_rfc3339();
+
+        // 检查名称是否已存在
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE name = ?1)",
+            params![request.name],
+            |row| row.get(0),
         )?;
 
-        let task = stmt.query_row(params![task_id], |row| {
-            let status_str: String = row.get(5)?;
-            let status: TaskStatus = TaskStatus::from_str(&status_str);
-            Ok(DocumentTask {
-                id: row.get(0)?,
-                document_id: row.get(1)?,
-                original_document_name: row.get(2)?,
-                segment_index: row.get(3)?,
-                content_segment: row.get(4)?,
-                status,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
-                error_message: row.get(8)?,
-                anki_generation_options_json: row.get(9)?,
-            })
-        })?;
+        if exists {
+            return Err(anyhow::anyhow!("分库名称 '{}' 已存在", request.name));
+        }
 
-        Ok(task)
+        conn.execute(
+            "INSERT INTO rag_sub_libraries (id, name, description, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, request.name, request.description, now_str, now_str],
+        )?;
+
+        Ok(SubLibrary {
+            id,
+            name: request.name.clone(),
+            description: request.description.clone(),
+            created_at: now,
+            updated_at: now,
+            document_count: 0,
+            chunk_count: 0,
+        })
     }
 
-    /// 获取指定文档的所有任务
-    pub fn get_tasks_for_document(&self, document_id: &str) -> Result<Vec<DocumentTask>> {
+    /// 获取所有分库列表
+    pub fn list_sub_libraries(&self) -> Result<Vec<SubLibrary>> {
         let conn = self.get_conn_safe()?;
+
         let mut stmt = conn.prepare(
-            "SELECT id, document_id, original_document_name, segment_index, content_segment,
-                    status, created_at, updated_at, error_message, anki_generation_options_json
-             FROM document_tasks WHERE document_id = ?1 ORDER BY segment_index",
+            "SELECT sl.id, sl.name, sl.description, sl.created_at, sl.updated_at,
+                    COUNT(DISTINCT rd.id) as document_count,
+                    COUNT(DISTINCT rdc.id) as chunk_count
+             FROM rag_sub_libraries sl
+             LEFT JOIN rag_documents rd ON sl.id = rd.sub_library_id
+             LEFT JOIN rag_document_chunks rdc ON rd.id = rdc.document_id
+             GROUP BY sl.id, sl.name, sl.description, sl.created_at, sl.updated_at
+             ORDER BY sl.name",
         )?;
 
-        let task_iter = stmt.query_map(params![document_id], |row| {
-            let status_str: String = row.get(5)?;
-            let status: TaskStatus = TaskStatus::from_str(&status_str);
-            Ok(DocumentTask {
+        let library_iter = stmt.query_map([], |row| {
+            let created_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
+                .map_err(|_| {
+                    rusqlite::Error::InvalidColumnType(
+                        3,
+                        "created_at".to_string(),
+                        rusqlite::types::Type::Text,
+                    )
+                })?
+                .with_timezone(&Utc);
+            let updated_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                .map_err(|_| {
+                    rusqlite::Error::InvalidColumnType(
+                        4,
+                        "updated_at".to_string(),
+                        rusqlite::types::Type::Text,
+                    )
+                })?
+                .with_timezone(&Utc);
+
+            Ok(SubLibrary {
                 id: row.get(0)?,
-                document_id: row.get(1)?,
-                original_document_name: row.get(2)?,
-                segment_index: row.get(3)?,
-                content_segment: row.get(4)?,
-                status,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
-                error_message: row.get(8)?,
-                anki_generation_options_json: row.get(9)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
+                created_at,
+                updated_at,
+                document_count: row.get::<_, i64>(5)? as usize,
+                chunk_count: row.get::<_, i64>(6)? as usize,
             })
         })?;
 
-        let mut tasks = Vec::new();
-        for task in task_iter {
-            tasks.push(task?);
+        let mut libraries = Vec::new();
+        for library in library_iter {
+            libraries.push(library?);
         }
 
-        Ok(tasks)
+        Ok(libraries)
     }
 
-    /// 插入Anki卡片（返回是否成功插入）
-    pub fn insert_anki_card(&self, card: &AnkiCard) -> Result<bool> {
+    /// 根据ID获取分库详情
+    pub fn get_sub_library_by_id(&self, id: &str) -> Result<Option<SubLibrary>> {
         let conn = self.get_conn_safe()?;
-        let document_id: Option<String> = conn
-            .query_row(
-                "SELECT document_id FROM document_tasks WHERE id = ?1",
-                params![card.task_id],
-                |row| row.get(0),
-            )
-            .optional()?;
-        let (source_type, source_id) = if let Some(document_id) = document_id {
-            ("document".to_string(), document_id)
-        } else {
-            ("task".to_string(), card.task_id.clone())
-        };
-
-        let rows_affected = conn.execute(
-            "INSERT OR IGNORE INTO anki_cards
-             (id, task_id, front, back, text, tags_json, images_json,
-              is_error_card, error_content, card_order_in_task, created_at, updated_at,
-              extra_fields_json, template_id, source_type, source_id)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
-            params![
-                card.id,
-                card.task_id,
-                card.front,
-                card.back,
-                card.text,
-                serde_json::to_string(&card.tags)?,
-                serde_json::to_string(&card.images)?,
-                if card.is_error_card { 1 } else { 0 },
-                card.error_content,
-                0, // card_order_in_task will be calculated
-                card.created_at,
-                card.updated_at,
-                serde_json::to_string(&card.extra_fields)?,
-                card.template_id,
-                source_type,
-                source_id
-            ],
-        )?;
-        Ok(rows_affected > 0)
-    }
 
-    /// 获取指定任务的所有卡片
-    pub fn get_cards_for_task(&self, task_id: &str) -> Result<Vec<AnkiCard>> {
-        let conn = self.get_conn_safe()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, task_id, front, back, text, tags_json, images_json,
-                    is_error_card, error_content, created_at, updated_at,
-                    COALESCE(extra_fields_json, '{}') as extra_fields_json,
-                    template_id
-             FROM anki_cards WHERE task_id = ?1 ORDER BY card_order_in_task, created_at",
-        )?;
+        let result = conn
+            .query_row(
+                "SELECT sl.id, sl.name, sl.description, sl.created_at, sl.updated_at,
+                    COUNT(DISTINCT rd.id) as document_count,
+                    COUNT(DISTINCT rdc.id) as chunk_count
+             FROM rag_sub_libraries sl
+             LEFT JOIN rag_documents rd ON sl.id = rd.sub_library_id
+             LEFT JOIN rag_document_chunks rdc ON rd.id = rdc.document_id
+             WHERE sl.id = ?1
+             GROUP BY sl.id, sl.name, sl.description, sl.created_at, sl.updated_at",
+                params![id],
+                |row| {
+                    let created_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
+                        .map_err(|_| {
+                            rusqlite::Error::InvalidColumnType(
+                                3,
+                                "created_at".to_string(),
+                                rusqlite::types::Type::Text,
+                            )
+                        })?
+                        .with_timezone(&Utc);
+                    let updated_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                        .map_err(|_| {
+                            rusqlite::Error::InvalidColumnType(
+                                4,
+                                "updated_at".to_string(),
+                                rusqlite::types::Type::Text,
+                            )
+                        })?
+                        .with_timezone(&Utc);
 
-        let card_iter = stmt.query_map(params![task_id], |row| {
-            let tags_json: String = row.get(5)?;
-            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+                    Ok(SubLibrary {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        description: row.get(2)?,
+                        created_at,
+                        updated_at,
+                        document_count: row.get::<_, i64>(5)? as usize,
+                        chunk_count: row.get::<_, i64>(6)? as usize,
+                    })
+                },
+            )
+            .optional()?;
 
-            let images_json: String = row.get(6)?;
-            let images: Vec<String> = serde_json::from_str(&images_json).unwrap_or_default();
+        Ok(result)
+    }
 
-            let extra_fields_json: String = row.get(11)?;
-            let extra_fields: std::collections::HashMap<String, String> =
-                serde_json::from_str(&extra_fields_json).unwrap_or_default();
+    /// 根据名称获取分库详情
+    pub fn get_sub_library_by_name(&self, name: &str) -> Result<Option<SubLibrary>> {
+        let conn = self.get_conn_safe()?;
 
-            Ok(AnkiCard {
-                id: row.get(0)?,
-                task_id: row.get(1)?,
-                front: row.get(2)?,
-                back: row.get(3)?,
-                text: row.get(4)?,
-                tags,
-                images,
-                is_error_card: row.get::<_, i32>(7)? != 0,
-                error_content: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-                extra_fields,
-                template_id: row.get(12)?,
-            })
-        })?;
+        let result = conn
+            .query_row(
+                "SELECT sl.id, sl.name, sl.description, sl.created_at, sl.updated_at,
+                    COUNT(DISTINCT rd.id) as document_count,
+                    COUNT(DISTINCT rdc.id) as chunk_count
+             FROM rag_sub_libraries sl
+             LEFT JOIN rag_documents rd ON sl.id = rd.sub_library_id
+             LEFT JOIN rag_document_chunks rdc ON rd.id = rdc.document_id
+             WHERE sl.name = ?1
+             GROUP BY sl.id, sl.name, sl.description, sl.created_at, sl.updated_at",
+                params![name],
+                |row| {
+                    let created_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
+                        .map_err(|_| {
+                            rusqlite::Error::InvalidColumnType(
+                                3,
+                                "created_at".to_string(),
+                                rusqlite::types::Type::Text,
+                            )
+                        })?
+                        .with_timezone(&Utc);
+                    let updated_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                        .map_err(|_| {
+                            rusqlite::Error::InvalidColumnType(
+                                4,
+                                "updated_at".to_string(),
+                                rusqlite::types::Type::Text,
+                            )
+                        })?
+                        .with_timezone(&Utc);
 
-        let mut cards = Vec::new();
-        for card in card_iter {
-            cards.push(card?);
-        }
+                    Ok(SubLibrary {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        description: row.get(2)?,
+                        created_at,
+                        updated_at,
+                        document_count: row.get::<_, i64>(5)? as usize,
+                        chunk_count: row.get::<_, i64>(6)? as usize,
+                    })
+                },
+            )
+            .optional()?;
 
-        Ok(cards)
+        Ok(result)
     }
 
-    /// 获取指定文档的所有卡片
-    pub fn get_cards_for_document(&self, document_id: &str) -> Result<Vec<AnkiCard>> {
+    /// 更新分库信息
+    pub fn update_sub_library(
+        &self,
+        id: &str,
+        request: &UpdateSubLibraryRequest,
+    ) -> Result<SubLibrary> {
         let conn = self.get_conn_safe()?;
-        let mut stmt = conn.prepare(
-            "SELECT ac.id, ac.task_id, ac.front, ac.back, ac.text, ac.tags_json, ac.images_json,
-                    ac.is_error_card, ac.error_content, ac.created_at, ac.updated_at,
-                    COALESCE(ac.extra_fields_json, '{}') as extra_fields_json,
-                    ac.template_id
-             FROM anki_cards ac
-             JOIN document_tasks dt ON ac.task_id = dt.id
-             WHERE dt.document_id = ?1
-             ORDER BY dt.segment_index, ac.card_order_in_task, ac.created_at",
+        let now = Utc::now().to_rfc3339();
+
+        // 检查分库是否存在
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE id = ?1)",
+            params![id],
+            |row| row.get(0),
         )?;
 
-        let card_iter = stmt.query_map(params![document_id], |row| {
-            let tags_json: String = row.get(5)?;
-            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+        if !exists {
+            return Err(anyhow::anyhow!("分库ID '{}' 不存在", id));
+        }
 
-            let images_json: String = row.get(6)?;
-            let images: Vec<String> = serde_json::from_str(&images_json).unwrap_or_default();
+        // 如果更新名称，检查新名称是否已存在
+        if let Some(new_name) = &request.name {
+            let name_exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE name = ?1 AND id != ?2)",
+                params![new_name, id],
+                |row| row.get(0),
+            )?;
 
-            let extra_fields_json: String = row.get(11)?;
-            let extra_fields: std::collections::HashMap<String, String> =
-                serde_json::from_str(&extra_fields_json).unwrap_or_default();
+            if name_exists {
+                return Err(anyhow::anyhow!("分库名称 '{}' 已存在", new_name));
+            }
+        }
 
-            Ok(AnkiCard {
-                id: row.get(0)?,
-                task_id: row.get(1)?,
-                front: row.get(2)?,
-                back: row.get(3)?,
-                text: row.get(4)?,
-                tags,
-                images,
-                is_error_card: row.get::<_, i32>(7)? != 0,
-                error_content: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-                extra_fields,
-                template_id: row.get(12)?,
-            })
-        })?;
+        // 构建动态更新SQL
+        let mut updates = Vec::new();
+        let mut params_vec = Vec::new();
 
-        let mut cards = Vec::new();
-        for card in card_iter {
-            cards.push(card?);
+        if let Some(name) = &request.name {
+            updates.push("name = ?");
+            params_vec.push(name.as_str());
         }
 
-        Ok(cards)
-    }
-
-    /// 根据ID列表获取卡片
-    pub fn get_cards_by_ids(&self, card_ids: &[String]) -> Result<Vec<AnkiCard>> {
-        if card_ids.is_empty() {
-            return Ok(Vec::new());
+        if let Some(description) = &request.description {
+            updates.push("description = ?");
+            params_vec.push(description.as_str());
         }
 
-        let conn = self.get_conn_safe()?;
-        let placeholders: Vec<&str> = card_ids.iter().map(|_| "?").collect();
+        updates.push("updated_at = ?");
+        params_vec.push(&now);
+        params_vec.push(id);
+
         let sql = format!(
-            "SELECT id, task_id, front, back, text, tags_json, images_json,
-                    is_error_card, error_content, created_at, updated_at,
-                    COALESCE(extra_fields_json, '{{}}') as extra_fields_json,
-                    template_id
-             FROM anki_cards WHERE id IN ({}) ORDER BY created_at",
-            placeholders.join(",")
+            "UPDATE rag_sub_libraries SET {} WHERE id = ?",
+            updates.join(", ")
         );
 
-        let mut stmt = conn.prepare(&sql)?;
-        let card_iter = stmt.query_map(rusqlite::params_from_iter(card_ids), |row| {
-            let tags_json: String = row.get(5)?;
-            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
-
-            let images_json: String = row.get(6)?;
-            let images: Vec<String> = serde_json::from_str(&images_json).unwrap_or_default();
-
-            let extra_fields_json: String = row.get(11)?;
-            let extra_fields: std::collections::HashMap<String, String> =
-                serde_json::from_str(&extra_fields_json).unwrap_or_default();
-
-            Ok(AnkiCard {
-                id: row.get(0)?,
-                task_id: row.get(1)?,
-                front: row.get(2)?,
-                back: row.get(3)?,
-                text: row.get(4)?,
-                tags,
-                images,
-                is_error_card: row.get::<_, i32>(7)? != 0,
-                error_content: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-                extra_fields,
-                template_id: row.get(12)?,
-            })
-        })?;
+        conn.execute(&sql, rusqlite::params_from_iter(params_vec))?;
 
-        let mut cards = Vec::new();
-        for card in card_iter {
-            cards.push(card?);
-        }
+        // 释放锁，避免递归锁导致死锁
+        drop(conn);
 
-        Ok(cards)
+        // 使用单独的只读查询获取更新后的分库信息
+        self.get_sub_library_by_id(id)?
+            .ok_or_else(|| anyhow::anyhow!("无法获取更新后的分库信息"))
     }
 
-    /// 更新Anki卡片
-    pub fn update_anki_card(&self, card: &AnkiCard) -> Result<()> {
+    /// 分库删除前的内容统计：文档数、分块数与占用字节
+    ///
+    /// 与 `delete_sub_library` 相同的校验：默认分库与不存在的分库返回错误。
+    pub fn get_sub_library_content_stats(&self, id: &str) -> Result<SubLibraryContentStats> {
         let conn = self.get_conn_safe()?;
-        let updated_at = chrono::Utc::now().to_rfc3339();
-        conn.execute(
-            "UPDATE anki_cards SET
-             front = ?1, back = ?2, text = ?3, tags_json = ?4, images_json = ?5,
-             is_error_card = ?6, error_content = ?7, updated_at = ?8,
-             extra_fields_json = ?9, template_id = ?10
-             WHERE id = ?11",
-            params![
-                card.front,
-                card.back,
-                card.text,
-                serde_json::to_string(&card.tags)?,
-                serde_json::to_string(&card.images)?,
-                if card.is_error_card { 1 } else { 0 },
-                card.error_content,
-                updated_at,
-                serde_json::to_string(&card.extra_fields)?,
-                card.template_id,
-                card.id
-            ],
-        )?;
-        Ok(())
-    }
+        ensure_sub_library_deletable(&conn, id)?;
 
-    /// 删除Anki卡片
-    pub fn delete_anki_card(&self, card_id: &str) -> Result<()> {
-        let conn = self.get_conn_safe()?;
-        conn.execute("DELETE FROM anki_cards WHERE id = ?1", params![card_id])?;
-        Ok(())
-    }
+        let (document_count, document_bytes): (i64, i64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(file_size), 0) FROM rag_documents WHERE sub_library_id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let (chunk_count, chunk_text_bytes): (i64, i64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(LENGTH(CAST(c.text AS BLOB))), 0)
+             FROM rag_document_chunks c JOIN rag_documents d ON d.id = c.document_id
+             WHERE d.sub_library_id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
 
-    /// 删除文档任务及其所有卡片
-    pub fn delete_document_task(&self, task_id: &str) -> Result<()> {
-        let conn = self.get_conn_safe()?;
-        // 由于设置了ON DELETE CASCADE，删除任务会自动删除关联的卡片
-        conn.execute("DELETE FROM document_tasks WHERE id = ?1", params![task_id])?;
-        Ok(())
+        Ok(SubLibraryContentStats {
+            document_count: document_count.max(0) as usize,
+            chunk_count: chunk_count.max(0) as usize,
+            document_bytes: document_bytes.max(0) as u64,
+            chunk_text_bytes: chunk_text_bytes.max(0) as u64,
+        })
     }
 
-    /// 删除整个文档会话（所有任务和卡片）
-    pub fn delete_document_session(&self, document_id: &str) -> Result<()> {
+    /// 各分库的内容统计（SQLite 侧），按分库名排序
+    ///
+    /// 向量数与嵌入维度在 Lance 中，由调用方另行汇总。
+    pub fn list_sub_library_index_stats(&self) -> Result<Vec<SubLibraryIndexStats>> {
         let conn = self.get_conn_safe()?;
-        // 由于设置了ON DELETE CASCADE，删除任务会自动删除关联的卡片
-        conn.execute(
-            "DELETE FROM document_tasks WHERE document_id = ?1",
-            params![document_id],
+        let mut stmt = conn.prepare(
+            "SELECT sl.id, sl.name, sl.updated_at,
+                    COALESCE(d.document_count, 0), COALESCE(d.document_bytes, 0),
+                    COALESCE(d.pending_documents, 0), d.last_updated,
+                    COALESCE(c.chunk_count, 0), COALESCE(c.chunk_text_bytes, 0)
+             FROM rag_sub_libraries sl
+             LEFT JOIN (
+                SELECT sub_library_id, COUNT(*) AS document_count,
+                       COALESCE(SUM(file_size), 0) AS document_bytes,
+                       SUM(CASE WHEN update_state <> 'ready' OR update_retry > 0 THEN 1 ELSE 0 END)
+                           AS pending_documents,
+                       MAX(updated_at) AS last_updated
+                FROM rag_documents GROUP BY sub_library_id
+             ) d ON d.sub_library_id = sl.id
+             LEFT JOIN (
+                SELECT rd.sub_library_id, COUNT(*) AS chunk_count,
+                       COALESCE(SUM(LENGTH(CAST(rdc.text AS BLOB))), 0) AS chunk_text_bytes
+                FROM rag_document_chunks rdc JOIN rag_documents rd ON rd.id = rdc.document_id
+                GROUP BY rd.sub_library_id
+             ) c ON c.sub_library_id = sl.id
+             ORDER BY sl.name",
         )?;
-        Ok(())
+        let rows = stmt.query_map([], |row| {
+            let library_updated: Option<String> = row.get(2)?;
+            let documents_updated: Option<String> = row.get(6)?;
+            Ok(SubLibraryIndexStats {
+                library_id: row.get(0)?,
+                name: row.get(1)?,
+                document_count: row.get::<_, i64>(3)?.max(0) as usize,
+                document_bytes: row.get::<_, i64>(4)?.max(0) as u64,
+                pending_documents: row.get::<_, i64>(5)?.max(0) as usize,
+                // RFC3339 时间戳可直接按字符串比较
+                last_updated: library_updated.max(documents_updated),
+                chunk_count: row.get::<_, i64>(7)?.max(0) as usize,
+                chunk_text_bytes: row.get::<_, i64>(8)?.max(0) as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
     }
 
-    // ==================== RAG配置管理 ====================
-
-    /// 获取RAG配置
-    pub fn get_rag_configuration(&self) -> Result<Option<crate::models::RagConfiguration>> {
+    /// 删除分库
+    ///
+    /// 返回受影响的文档（已删除或已移到默认分库），调用方据此同步 Lance 向量。
+    pub fn delete_sub_library(
+        &self,
+        id: &str,
+        delete_contained_documents: bool,
+    ) -> Result<DeletedSubLibrary> {
         let conn = self.get_conn_safe()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, chunk_size, chunk_overlap, chunking_strategy, min_chunk_size,
-                    default_top_k, default_rerank_enabled, created_at, updated_at
-             FROM rag_configurations WHERE id = 'default'",
-        )?;
+        ensure_sub_library_deletable(&conn, id)?;
 
-        let result = stmt
-            .query_row([], |row| {
-                let created_at_str: String = row.get(7)?;
-                let updated_at_str: String = row.get(8)?;
+        let transaction = conn.unchecked_transaction()?;
 
-                let created_at = DateTime::parse_from_rfc3339(&created_at_str)
-                    .map_err(|_| {
-                        rusqlite::Error::InvalidColumnType(
-                            7,
-                            "created_at".to_string(),
-                            rusqlite::types::Type::Text,
-                        )
-                    })?
-                    .with_timezone(&Utc);
-                let updated_at = DateTime::parse_from_rfc3339(&updated_at_str)
-                    .map_err(|_| {
-                        rusqlite::Error::InvalidColumnType(
-                            8,
-                            "updated_at".to_string(),
-                            rusqlite::types::Type::Text,
-                        )
-                    })?
-                    .with_timezone(&Utc);
+        // 分库中的所有文档ID
+        let document_ids = sub_library_document_ids(&transaction, id)?;
 
-                Ok(crate::models::RagConfiguration {
-                    id: row.get(0)?,
-                    chunk_size: row.get(1)?,
-                    chunk_overlap: row.get(2)?,
-                    chunking_strategy: row.get(3)?,
-                    min_chunk_size: row.get(4)?,
-                    default_top_k: row.get(5)?,
-                    default_rerank_enabled: row.get::<_, i32>(6)? != 0,
-                    created_at,
-                    updated_at,
-                })
-            })
-            .optional()?;
+        let mut deleted_chunk_count = 0;
+        if delete_contained_documents {
+            // 删除文档关联的块（向量由调用方在 Lance 中清理）
+            for doc_id in &document_ids {
+                deleted_chunk_count += transaction.execute(
+                    "DELETE FROM rag_document_chunks WHERE document_id = ?1",
+                    params![doc_id],
+                )?;
+            }
 
-        Ok(result)
-    }
+            // 删除分库中的所有文档
+            transaction.execute(
+                "DELETE FROM rag_documents WHERE sub_library_id = ?1",
+                params![id],
+            )?;
+        } else {
+            // 将分库中的文档移动到默认分库
+            transaction.execute(
+                "UPDATE rag_documents SET sub_library_id = 'default' WHERE sub_library_id = ?1",
+                params![id],
+            )?;
+        }
 
-    /// 更新RAG配置
-    pub fn update_rag_configuration(&self, config: &crate::models::RagConfigRequest) -> Result<()> {
-        let conn = self.get_conn_safe()?;
-        let now = Utc::now().to_rfc3339();
+        // 删除分库本身
+        transaction.execute("DELETE FROM rag_sub_libraries WHERE id = ?1", params![id])?;
 
-        conn.execute(
-            "UPDATE rag_configurations
-             SET chunk_size = ?1, chunk_overlap = ?2, chunking_strategy = ?3,
-                 min_chunk_size = ?4, default_top_k = ?5, default_rerank_enabled = ?6,
-                 updated_at = ?7
-             WHERE id = 'default'",
-            params![
-                config.chunk_size,
-                config.chunk_overlap,
-                config.chunking_strategy,
-                config.min_chunk_size,
-                config.default_top_k,
-                if config.default_rerank_enabled { 1 } else { 0 },
-                now
-            ],
-        )?;
+        transaction.commit()?;
 
-        Ok(())
+        log::info!("成功删除分库: {}", id);
+        Ok(DeletedSubLibrary {
+            document_ids,
+            documents_deleted: delete_contained_documents,
+            deleted_chunk_count,
+        })
     }
 
-    /// 重置RAG配置为默认值
-    pub fn reset_rag_configuration(&self) -> Result<()> {
+    /// 删除分库的演练：只读统计 `delete_sub_library` 将影响的内容
+    ///
+    /// 与删除使用相同的文档查询与逐文档分块范围，计数与实际删除一致。
+    /// 保留文档时只报告将移到默认分库的文档数，不删除任何分块。
+    pub fn preview_delete_sub_library(
+        &self,
+        id: &str,
+        delete_contained_documents: bool,
+    ) -> Result<SubLibraryDeletionImpact> {
         let conn = self.get_conn_safe()?;
-        let now = Utc::now().to_rfc3339();
+        ensure_sub_library_deletable(&conn, id)?;
+
+        let document_ids = sub_library_document_ids(&conn, id)?;
+        if !delete_contained_documents {
+            return Ok(SubLibraryDeletionImpact {
+                documents_deleted: false,
+                reassigned_document_count: document_ids.len(),
+                ..Default::default()
+            });
+        }
 
-        conn.execute(
-            "UPDATE rag_configurations
-             SET chunk_size = 512, chunk_overlap = 50, chunking_strategy = 'fixed_size',
-                 min_chunk_size = 20, default_top_k = 5, default_rerank_enabled = 1,
-                 updated_at = ?1
-             WHERE id = 'default'",
-            params![now],
-        )?;
+        let mut chunk_count = 0;
+        let mut document_file_names = Vec::with_capacity(document_ids.len());
+        for doc_id in &document_ids {
+            chunk_count += conn
+                .query_row(
+                    "SELECT COUNT(*) FROM rag_document_chunks WHERE document_id = ?1",
+                    params![doc_id],
+                    |row| row.get::<_, i64>(0),
+                )?
+                .max(0) as usize;
+            let file_name: Option<String> = conn.query_row(
+                "SELECT file_name FROM rag_documents WHERE id = ?1",
+                params![doc_id],
+                |row| row.get(0),
+            )?;
+            document_file_names.push(file_name.unwrap_or_else(|| doc_id.clone()));
+        }
 
-        Ok(())
+        Ok(SubLibraryDeletionImpact {
+            documents_deleted: true,
+            document_count: document_ids.len(),
+            chunk_count,
+            document_file_names,
+            reassigned_document_count: 0,
+        })
     }
 
-    // ==================== RAG分库管理CRUD操作 ====================
-
-    /// 创建新的分库
-    pub fn create_sub_library(&self, request: &CreateSubLibraryRequest) -> Result<SubLibrary> {
+    /// 可删除分库中的文档ID（默认分库与不存在的分库返回错误）
+    pub fn list_deletable_sub_library_documents(&self, id: &str) -> Result<Vec<String>> {
         let conn = self.get_conn_safe()?;
-        let id = uuid::Uuid::new_v4().to_string();
-        let now = Utc::now();
-        let now_str = now.to_rfc3339();
+        ensure_sub_library_deletable(&conn, id)?;
+        sub_library_document_ids(&conn, id)
+    }
 
-        // 检查名称是否已存在
+    /// 知识库文档的分块ID，文档不存在时返回 None
+    pub fn get_rag_document_chunk_ids(&self, document_id: &str) -> Result<Option<Vec<String>>> {
+        let conn = self.get_conn_safe()?;
         let exists: bool = conn.query_row(
-            "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE name = ?1)",
-            params![request.name],
+            "SELECT EXISTS(SELECT 1 FROM rag_documents WHERE id = ?1)",
+            params![document_id],
             |row| row.get(0),
         )?;
+        if !exists {
+            return Ok(None);
+        }
+        let mut stmt = conn.prepare("SELECT id FROM rag_document_chunks WHERE document_id = ?1")?;
+        let ids = stmt
+            .query_map(params![document_id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(ids))
+    }
 
-        if exists {
-            return Err(anyhow::anyhow!("分库名称 '{}' 已存在", request.name));
+    /// 删除知识库文档及其分块（单事务），返回删除的分块数
+    ///
+    /// 只处理 SQLite；Lance 向量由调用方先行清理。
+    pub fn delete_rag_document(&self, document_id: &str) -> Result<usize> {
+        let mut conn = self.get_conn_safe()?;
+        let tx = conn.transaction()?;
+        let deleted_chunks = tx.execute(
+            "DELETE FROM rag_document_chunks WHERE document_id = ?1",
+            params![document_id],
+        )?;
+        let deleted_documents = tx.execute(
+            "DELETE FROM rag_documents WHERE id = ?1",
+            params![document_id],
+        )?;
+        if deleted_documents == 0 {
+            return Err(anyhow::anyhow!("文档ID '{}' 不存在", document_id));
         }
+        tx.commit()?;
+        Ok(deleted_chunks)
+    }
 
-        conn.execute(
-            "INSERT INTO rag_sub_libraries (id, name, description, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![id, request.name, request.description, now_str, now_str],
+    /// 获取指定分库中的文档列表
+    pub fn get_documents_by_sub_library(
+        &self,
+        sub_library_id: &str,
+        page: Option<usize>,
+        page_size: Option<usize>,
+    ) -> Result<Vec<serde_json::Value>> {
+        let conn = self.get_conn_safe()?;
+
+        let page = page.unwrap_or(1);
+        let page_size = page_size.unwrap_or(50);
+        let offset = (page - 1) * page_size;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, file_name, file_path, file_size, total_chunks, sub_library_id, update_state, update_retry, created_at, updated_at
+             FROM rag_documents
+             WHERE sub_library_id = ?1
+             ORDER BY created_at DESC
+             LIMIT ?2 OFFSET ?3"
         )?;
 
-        Ok(SubLibrary {
-            id,
-            name: request.name.clone(),
-            description: request.description.clone(),
-            created_at: now,
-            updated_at: now,
-            document_count: 0,
-            chunk_count: 0,
-        })
+        let rows = stmt.query_map(params![sub_library_id, page_size, offset], |row| {
+            Ok(serde_json::json!({
+                "id": row.get::<_, String>(0)?,
+                "file_name": row.get::<_, String>(1)?,
+                "file_path": row.get::<_, Option<String>>(2)?,
+                "file_size": row.get::<_, Option<i64>>(3)?,
+                "total_chunks": row.get::<_, i32>(4)?,
+                "sub_library_id": row.get::<_, String>(5)?,
+                "update_state": row.get::<_, String>(6)?,
+                "update_retry": row.get::<_, i64>(7)?,
+                "created_at": row.get::<_, String>(8)?,
+                "updated_at": row.get::<_, String>(9)?
+            }))
+        })?;
+
+        let mut documents = Vec::new();
+        for row in rows {
+            documents.push(row?);
+        }
+
+        Ok(documents)
     }
 
-    /// 获取所有分库列表
-    pub fn list_sub_libraries(&self) -> Result<Vec<SubLibrary>> {
+    /// 将文档移动到指定分库
+    pub fn move_document_to_sub_library(
+        &self,
+        document_id: &str,
+        target_sub_library_id: &str,
+    ) -> Result<()> {
         let conn = self.get_conn_safe()?;
 
-        let mut stmt = conn.prepare(
-            "SELECT sl.id, sl.name, sl.description, sl.created_at, sl.updated_at,
-                    COUNT(DISTINCT rd.id) as document_count,
-                    COUNT(DISTINCT rdc.id) as chunk_count
-             FROM rag_sub_libraries sl
-             LEFT JOIN rag_documents rd ON sl.id = rd.sub_library_id
-             LEFT JOIN rag_document_chunks rdc ON rd.id = rdc.document_id
-             GROUP BY sl.id, sl.name, sl.description, sl.created_at, sl.updated_at
-             ORDER BY sl.name",
+        // 检查目标分库是否存在
+        let library_exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE id = ?1)",
+            params![target_sub_library_id],
+            |row| row.get(0),
         )?;
 
-        let library_iter = stmt.query_map([], |row| {
-            let created_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
-                .map_err(|_| {
-                    rusqlite::Error::InvalidColumnType(
-                        3,
-                        "created_at".to_string(),
-                        rusqlite::types::Type::Text,
-                    )
-                })?
-                .with_timezone(&Utc);
-            let updated_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                .map_err(|_| {
-                    rusqlite::Error::InvalidColumnType(
-                        4,
-                        "updated_at".to_string(),
-                        rusqlite::types::Type::Text,
-                    )
-                })?
-                .with_timezone(&Utc);
+        if !library_exists {
+            return Err(anyhow::anyhow!(
+                "目标分库ID '{}' 不存在",
+                target_sub_library_id
+            ));
+        }
 
-            Ok(SubLibrary {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                description: row.get(2)?,
-                created_at,
-                updated_at,
-                document_count: row.get::<_, i64>(5)? as usize,
-                chunk_count: row.get::<_, i64>(6)? as usize,
-            })
-        })?;
+        // 检查文档是否存在
+        let document_exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM rag_documents WHERE id = ?1)",
+            params![document_id],
+            |row| row.get(0),
+        )?;
 
-        let mut libraries = Vec::new();
-        for library in library_iter {
-            libraries.push(library?);
+        if !document_exists {
+            return Err(anyhow::anyhow!("文档ID '{}' 不存在", document_id));
         }
 
-        Ok(libraries)
+        // 更新文档的分库归属
+        let updated_at = Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE rag_documents SET sub_library_id = ?1, updated_at = ?2 WHERE id = ?3",
+            params![target_sub_library_id, updated_at, document_id],
+        )?;
+
+        log::info!(
+            "成功将文档 {} 移动到分库 {}",
+            document_id,
+            target_sub_library_id
+        );
+        Ok(())
     }
 
-    /// 根据ID获取分库详情
-    pub fn get_sub_library_by_id(&self, id: &str) -> Result<Option<SubLibrary>> {
+    /// 分页读取文档的已存储分块（按 chunk_index 排序），文档不存在时返回 None
+    pub fn get_rag_document_chunks_page(
+        &self,
+        document_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> Result<Option<RagDocumentChunksPage>> {
         let conn = self.get_conn_safe()?;
-
-        let result = conn
+        let state: Option<(String, i64)> = conn
             .query_row(
-                "SELECT sl.id, sl.name, sl.description, sl.created_at, sl.updated_at,
-                    COUNT(DISTINCT rd.id) as document_count,
-                    COUNT(DISTINCT rdc.id) as chunk_count
-             FROM rag_sub_libraries sl
-             LEFT JOIN rag_documents rd ON sl.id = rd.sub_library_id
-             LEFT JOIN rag_document_chunks rdc ON rd.id = rdc.document_id
-             WHERE sl.id = ?1
-             GROUP BY sl.id, sl.name, sl.description, sl.created_at, sl.updated_at",
-                params![id],
-                |row| {
-                    let created_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
-                        .map_err(|_| {
-                            rusqlite::Error::InvalidColumnType(
-                                3,
-                                "created_at".to_string(),
-                                rusqlite::types::Type::Text,
-                            )
-                        })?
-                        .with_timezone(&Utc);
-                    let updated_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                        .map_err(|_| {
-                            rusqlite::Error::InvalidColumnType(
-                                4,
-                                "updated_at".to_string(),
-                                rusqlite::types::Type::Text,
-                            )
-                        })?
-                        .with_timezone(&Utc);
-
-                    Ok(SubLibrary {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        description: row.get(2)?,
-                        created_at,
-                        updated_at,
-                        document_count: row.get::<_, i64>(5)? as usize,
-                        chunk_count: row.get::<_, i64>(6)? as usize,
-                    })
-                },
+                "SELECT update_state, update_retry FROM rag_documents WHERE id = ?1",
+                params![document_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
             )
             .optional()?;
+        let Some((update_state, update_retry)) = state else {
+            return Ok(None);
+        };
 
-        Ok(result)
+        let total_chunks: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM rag_document_chunks WHERE document_id = ?1",
+            params![document_id],
+            |row| row.get(0),
+        )?;
+        let offset = page.saturating_sub(1) * page_size;
+        let mut stmt = conn.prepare(
+            "SELECT id, chunk_index, text, metadata FROM rag_document_chunks
+             WHERE document_id = ?1 ORDER BY chunk_index ASC LIMIT ?2 OFFSET ?3",
+        )?;
+        let chunks = stmt
+            .query_map(params![document_id, page_size, offset], |row| {
+                let metadata: Option<String> = row.get(3)?;
+                Ok(crate::models::DocumentChunk {
+                    id: row.get(0)?,
+                    document_id: document_id.to_string(),
+                    chunk_index: row.get::<_, i64>(1)?.max(0) as usize,
+                    text: row.get(2)?,
+                    metadata: metadata
+                        .and_then(|m| serde_json::from_str(&m).ok())
+                        .unwrap_or_default(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Some(RagDocumentChunksPage {
+            total_chunks: total_chunks.max(0) as usize,
+            update_state,
+            update_retry,
+            chunks,
+        }))
     }
 
-    /// 根据名称获取分库详情
-    pub fn get_sub_library_by_name(&self, name: &str) -> Result<Option<SubLibrary>> {
+    /// 列出导入失败或部分完成（`update_state` 为 failed / partial）的文档
+    pub fn list_failed_rag_documents(&self) -> Result<Vec<FailedRagDocument>> {
         let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, file_name, update_state, update_retry, last_error FROM rag_documents
+             WHERE update_state IN ('failed', 'partial') ORDER BY updated_at ASC",
+        )?;
+        let documents = stmt
+            .query_map([], |row| {
+                Ok(FailedRagDocument {
+                    id: row.get(0)?,
+                    file_name: row.get(1)?,
+                    update_state: row.get(2)?,
+                    update_retry: row.get(3)?,
+                    last_error: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(documents)
+    }
 
-        let result = conn
-            .query_row(
-                "SELECT sl.id, sl.name, sl.description, sl.created_at, sl.updated_at,
-                    COUNT(DISTINCT rd.id) as document_count,
-                    COUNT(DISTINCT rdc.id) as chunk_count
-             FROM rag_sub_libraries sl
-             LEFT JOIN rag_documents rd ON sl.id = rd.sub_library_id
-             LEFT JOIN rag_document_chunks rdc ON rd.id = rdc.document_id
-             WHERE sl.name = ?1
-             GROUP BY sl.id, sl.name, sl.description, sl.created_at, sl.updated_at",
-                params![name],
-                |row| {
-                    let created_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
-                        .map_err(|_| {
-                            rusqlite::Error::InvalidColumnType(
-                                3,
-                                "created_at".to_string(),
-                                rusqlite::types::Type::Text,
-                            )
-                        })?
-                        .with_timezone(&Utc);
-                    let updated_at = DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                        .map_err(|_| {
-                            rusqlite::Error::InvalidColumnType(
-                                4,
-                                "updated_at".to_string(),
-                                rusqlite::types::Type::Text,
-                            )
-                        })?
-                        .with_timezone(&Utc);
-
-                    Ok(SubLibrary {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        description: row.get(2)?,
-                        created_at,
-                        updated_at,
-                        document_count: row.get::<_, i64>(5)? as usize,
-                        chunk_count: row.get::<_, i64>(6)? as usize,
-                    })
-                },
-            )
-            .optional()?;
+    /// 读取文档的全部已存储分块（按 chunk_index 排序）
+    pub fn list_rag_document_chunks(
+        &self,
+        document_id: &str,
+    ) -> Result<Vec<crate::models::DocumentChunk>> {
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, chunk_index, text, metadata FROM rag_document_chunks
+             WHERE document_id = ?1 ORDER BY chunk_index ASC",
+        )?;
+        let chunks = stmt
+            .query_map(params![document_id], |row| {
+                let metadata: Option<String> = row.get(3)?;
+                Ok(crate::models::DocumentChunk {
+                    id: row.get(0)?,
+                    document_id: document_id.to_string(),
+                    chunk_index: row.get::<_, i64>(1)?.max(0) as usize,
+                    text: row.get(2)?,
+                    metadata: metadata
+                        .and_then(|m| serde_json::from_str(&m).ok())
+                        .unwrap_or_default(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(chunks)
+    }
 
-        Ok(result)
+    /// 文档导入完成：恢复为 ready，清零重试次数与错误信息
+    pub fn mark_rag_document_ready(&self, document_id: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        conn.execute(
+            "UPDATE rag_documents SET update_state = 'ready', update_retry = 0, last_error = NULL,
+             updated_at = ?1 WHERE id = ?2",
+            params![chrono::Utc::now().to_rfc3339(), document_id],
+        )?;
+        Ok(())
     }
 
-    /// 更新分库信息
-    pub fn update_sub_library(
+    /// 记录文档导入失败：写入状态（failed / partial）与错误信息，重试次数加一
+    pub fn mark_rag_document_failed(
         &self,
-        id: &str,
-        request: &UpdateSubLibraryRequest,
-    ) -> Result<SubLibrary> {
+        document_id: &str,
+        update_state: &str,
+        error: &str,
+    ) -> Result<()> {
         let conn = self.get_conn_safe()?;
-        let now = Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE rag_documents SET update_state = ?1, update_retry = update_retry + 1,
+             last_error = ?2, updated_at = ?3 WHERE id = ?4",
+            params![
+                update_state,
+                error,
+                chrono::Utc::now().to_rfc3339(),
+                document_id
+            ],
+        )?;
+        Ok(())
+    }
 
-        // 检查分库是否存在
-        let exists: bool = conn.query_row(
+    /// 批量移动文档到指定分库（单事务）
+    ///
+    /// 目标分库或任一文档不存在时整体失败；已在目标分库的文档视为无操作。
+    /// 返回实际移动的文档 ID。
+    pub fn move_documents_to_sub_library(
+        &self,
+        document_ids: &[String],
+        target_sub_library_id: &str,
+    ) -> Result<Vec<String>> {
+        let mut conn = self.get_conn_safe()?;
+        let tx = conn.transaction()?;
+
+        let library_exists: bool = tx.query_row(
             "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE id = ?1)",
-            params![id],
+            params![target_sub_library_id],
             |row| row.get(0),
         )?;
-
-        if !exists {
-            return Err(anyhow::anyhow!("分库ID '{}' 不存在", id));
+        if !library_exists {
+            return Err(anyhow::anyhow!(
+                "目标分库ID '{}' 不存在",
+                target_sub_library_id
+            ));
         }
 
-        // 如果更新名称，检查新名称是否已存在
-        if let Some(new_name) = &request.name {
-            let name_exists: bool = conn.query_row(
-                "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE name = ?1 AND id != ?2)",
-                params![new_name, id],
-                |row| row.get(0),
+        let updated_at = Utc::now().to_rfc3339();
+        let mut moved = Vec::new();
+        let mut seen = HashSet::new();
+        {
+            let mut lookup =
+                tx.prepare("SELECT sub_library_id FROM rag_documents WHERE id = ?1")?;
+            let mut update = tx.prepare(
+                "UPDATE rag_documents SET sub_library_id = ?1, updated_at = ?2 WHERE id = ?3",
             )?;
-
-            if name_exists {
-                return Err(anyhow::anyhow!("分库名称 '{}' 已存在", new_name));
+            for document_id in document_ids {
+                if !seen.insert(document_id.as_str()) {
+                    continue;
+                }
+                let current: Option<String> = lookup
+                    .query_row(params![document_id], |row| row.get(0))
+                    .optional()?;
+                match current {
+                    None => return Err(anyhow::anyhow!("文档ID '{}' 不存在", document_id)),
+                    Some(current) if current == target_sub_library_id => {}
+                    Some(_) => {
+                        update.execute(params![target_sub_library_id, updated_at, document_id])?;
+                        moved.push(document_id.clone());
+                    }
+                }
             }
         }
+        tx.commit()?;
 
-        // 构建动态更新SQL
-        let mut updates = Vec::new();
-        let mut params_vec = Vec::new();
+        log::info!(
+            "批量移动文档到分库 {}：移动 {} 个，跳过 {} 个已在目标分库的文档",
+            target_sub_library_id,
+            moved.len(),
+            seen.len() - moved.len()
+        );
+        Ok(moved)
+    }
 
-        if let Some(name) = &request.name {
-            updates.push("name = ?");
-            params_vec.push(name.as_str());
+    // =================== Migration Functions ===================
+    // ============================================
+    // 已废弃：旧版本迁移函数 (v8-v30)
+    // 新系统使用 data_governance::migration
+    // 保留代码供参考，待完全验证后删除
+    // ============================================
+    /*
+    /// 版本8到版本9的数据库迁移：过去用于添加图片遮罩卡表，现在改为清理遗留结构
+    fn migrate_v8_to_v9(&self, conn: &rusqlite::Connection) -> Result<()> {
+        println!("正在迁移数据库版本8到版本9：清理图片遮罩卡遗留表...");
+
+        conn.execute_batch(
+            "DROP INDEX IF EXISTS idx_image_occlusion_cards_task_id;
+            DROP INDEX IF EXISTS idx_image_occlusion_cards_subject;
+            DROP INDEX IF EXISTS idx_image_occlusion_cards_created_at;
+            DROP TABLE IF EXISTS image_occlusion_cards;",
+        )?;
+
+        println!("数据库版本8到版本9迁移完成（已移除图片遮罩卡表）");
+        Ok(())
+    }
+
+    fn migrate_v9_to_v10(&self, conn: &rusqlite::Connection) -> Result<()> {
+        println!("正在迁移数据库版本9到版本10：为anki_cards表添加text字段支持Cloze模板...");
+
+        // 🔧 检查text字段是否已存在
+        let text_column_exists = conn
+            .prepare("PRAGMA table_info(anki_cards)")?
+            .query_map([], |row| {
+                Ok(row.get::<_, String>(1)?) // 获取列名
+            })?
+            .filter_map(Result::ok)
+            .any(|name| name == "text");
+
+        if !text_column_exists {
+            // 添加text字段到anki_cards表
+            conn.execute("ALTER TABLE anki_cards ADD COLUMN text TEXT;", [])?;
+            println!("已为anki_cards表添加text字段");
+        } else {
+            println!("text字段已存在，跳过添加");
         }
 
-        if let Some(description) = &request.description {
-            updates.push("description = ?");
-            params_vec.push(description.as_str());
-        }
+        // 添加索引以优化查询性能
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_anki_cards_text ON anki_cards(text);",
+            [],
+        )?;
 
-        updates.push("updated_at = ?");
-        params_vec.push(&now);
-        params_vec.push(id);
+        println!("数据库版本9到版本10迁移完成");
+        Ok(())
+    }
 
-        let sql = format!(
-            "UPDATE rag_sub_libraries SET {} WHERE id = ?",
-            updates.join(", ")
+    fn migrate_v10_to_v11(&self, conn: &rusqlite::Connection) -> Result<()> {
+        println!("正在迁移数据库版本10到版本11：为review_analyses表添加会话管理字段...");
+
+        // 为review_analyses表添加temp_session_data字段
+        let add_temp_session = conn.execute(
+            "ALTER TABLE review_analyses ADD COLUMN temp_session_data TEXT DEFAULT '{}'",
+            [],
         );
 
-        conn.execute(&sql, rusqlite::params_from_iter(params_vec))?;
+        // 为review_analyses表添加session_sequence字段
+        let add_session_sequence = conn.execute(
+            "ALTER TABLE review_analyses ADD COLUMN session_sequence INTEGER DEFAULT 0",
+            [],
+        );
 
-        // 释放锁，避免递归锁导致死锁
-        drop(conn);
+        match (add_temp_session, add_session_sequence) {
+            (Ok(_), Ok(_)) => {
+                println!("已为review_analyses表添加temp_session_data和session_sequence字段");
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                // 如果字段已存在，这是正常的
+                if e.to_string().contains("duplicate column name") {
+                    println!("字段已存在，跳过添加");
+                } else {
+                    return Err(e.into());
+                }
+            }
+        }
 
-        // 使用单独的只读查询获取更新后的分库信息
-        self.get_sub_library_by_id(id)?
-            .ok_or_else(|| anyhow::anyhow!("无法获取更新后的分库信息"))
+        println!("数据库版本10到版本11迁移完成");
+        Ok(())
     }
 
-    /// 删除分库
-    pub fn delete_sub_library(&self, id: &str, delete_contained_documents: bool) -> Result<()> {
-        let conn = self.get_conn_safe()?;
+    fn migrate_v11_to_v12(&self, conn: &rusqlite::Connection) -> Result<()> {
+        println!("开始数据库迁移 v11 -> v12: 插入内置模板...");
 
-        // 检查是否为默认分库
-        if id == "default" {
-            return Err(anyhow::anyhow!("不能删除默认分库"));
-        }
 
-        // 检查分库是否存在
-        let exists: bool = conn.query_row(
-            "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE id = ?1)",
-            params![id],
+
+
+        // 确保custom_anki_templates表存在
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS custom_anki_templates (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                description TEXT,
+                author TEXT,
+                version TEXT NOT NULL DEFAULT '1.0.0',
+                preview_front TEXT NOT NULL,
+                preview_back TEXT NOT NULL,
+                note_type TEXT NOT NULL DEFAULT 'Basic',
+                fields_json TEXT NOT NULL DEFAULT '[]',
+                generation_prompt TEXT NOT NULL,
+                front_template TEXT NOT NULL,
+                back_template TEXT NOT NULL,
+                css_style TEXT NOT NULL,
+                field_extraction_rules_json TEXT NOT NULL DEFAULT '{}',
+                created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+                updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+                is_active INTEGER NOT NULL DEFAULT 1,
+                is_built_in INTEGER NOT NULL DEFAULT 0
+            );",
+            [],
+        )?;
+
+        // 检查表中是否已有内置模板
+        let count: i32 = conn.query_row(
+            "SELECT COUNT(*) FROM custom_anki_templates WHERE is_built_in = 1",
+            [],
             |row| row.get(0),
         )?;
 
-        if !exists {
-            return Err(anyhow::anyhow!("分库ID '{}' 不存在", id));
+        println!("当前内置模板数量: {}", count);
+
+        // 如果已有内置模板，跳过迁移
+        if count > 0 {
+            println!("内置模板已存在，跳过迁移");
+            return Ok(());
         }
 
-        let transaction = conn.unchecked_transaction()?;
+        println!("v11->v12: 跳过硬编码模板插入，改用 JSON 导入");
+        Ok(())
+    }
 
-        if delete_contained_documents {
-            // 删除分库中的所有文档及其相关数据
-            // 首先获取分库中的所有文档ID
-            let mut stmt =
-                transaction.prepare("SELECT id FROM rag_documents WHERE sub_library_id = ?1")?;
-
-            let document_ids: Vec<String> = stmt
-                .query_map(params![id], |row| Ok(row.get::<_, String>(0)?))?
-                .collect::<Result<Vec<_>, _>>()?;
-
-            // 删除文档关联的向量和块
-            for doc_id in document_ids {
-                transaction.execute(
-                    "DELETE FROM rag_document_chunks WHERE document_id = ?1",
-                    params![doc_id],
-                )?;
-            }
+    fn migrate_v12_to_v13(&self, conn: &rusqlite::Connection) -> Result<()> {
+        println!("开始数据库迁移 v12 -> v13: 添加预览数据字段...");
 
-            // 删除分库中的所有文档
-            transaction.execute(
-                "DELETE FROM rag_documents WHERE sub_library_id = ?1",
-                params![id],
+        // 检查是否已有 preview_data_json 列
+        let has_preview_data_json: bool = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('custom_anki_templates') WHERE name='preview_data_json'",
+            [],
+            |row| row.get::<_, i32>(0).map(|count| count > 0),
+        )?;
+
+        if !has_preview_data_json {
+            conn.execute(
+                "ALTER TABLE custom_anki_templates ADD COLUMN preview_data_json TEXT",
+                [],
             )?;
+            println!("已添加 preview_data_json 字段");
         } else {
-            // 将分库中的文档移动到默认分库
-            transaction.execute(
-                "UPDATE rag_documents SET sub_library_id = 'default' WHERE sub_library_id = ?1",
-                params![id],
-            )?;
+            println!("preview_data_json 字段已存在");
         }
 
-        // 删除分库本身
-        transaction.execute("DELETE FROM rag_sub_libraries WHERE id = ?1", params![id])?;
-
-        transaction.commit()?;
+        // 注意：内置模板导入将通过前端的导入按钮或应用启动时自动处理
+        println!("内置模板将通过独立的导入机制处理");
 
-        log::info!("成功删除分库: {}", id);
+        println!("数据库迁移 v12 -> v13 完成");
         Ok(())
     }
 
-    /// 获取指定分库中的文档列表
-    pub fn get_documents_by_sub_library(
-        &self,
-        sub_library_id: &str,
-        page: Option<usize>,
-        page_size: Option<usize>,
-    ) -> Result<Vec<serde_json::Value>> {
-        let conn = self.get_conn_safe()?;
+    fn migrate_v13_to_v14(&self, conn: &rusqlite::Connection) -> Result<()> {
+        println!("开始数据库迁移 v13 -> v14: 添加向量化表、子库表和错题笔记整理会话表...");
 
-        let page = page.unwrap_or(1);
-        let page_size = page_size.unwrap_or(50);
-        let offset = (page - 1) * page_size;
+        // 创建向量化数据表
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS vectorized_data (
+                id TEXT PRIMARY KEY,
+                mistake_id TEXT NOT NULL,
+                text_content TEXT NOT NULL,
+                embedding_json TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (mistake_id) REFERENCES mistakes(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-        let mut stmt = conn.prepare(
-            "SELECT id, file_name, file_path, file_size, total_chunks, sub_library_id, update_state, update_retry, created_at, updated_at
-             FROM rag_documents
-             WHERE sub_library_id = ?1
-             ORDER BY created_at DESC
-             LIMIT ?2 OFFSET ?3"
+        // 创建分库表
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS rag_sub_libraries (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                description TEXT,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
         )?;
 
-        let rows = stmt.query_map(params![sub_library_id, page_size, offset], |row| {
-            Ok(serde_json::json!({
-                "id": row.get::<_, String>(0)?,
-                "file_name": row.get::<_, String>(1)?,
-                "file_path": row.get::<_, Option<String>>(2)?,
-                "file_size": row.get::<_, Option<i64>>(3)?,
-                "total_chunks": row.get::<_, i32>(4)?,
-                "sub_library_id": row.get::<_, String>(5)?,
-                "update_state": row.get::<_, String>(6)?,
-                "update_retry": row.get::<_, i64>(7)?,
-                "created_at": row.get::<_, String>(8)?,
-                "updated_at": row.get::<_, String>(9)?
-            }))
-        })?;
+        // 创建整理会话表
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS review_sessions (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                start_date TEXT NOT NULL,
+                end_date TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        // 创建会话错题关联表
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS review_session_mistakes (
+                session_id TEXT NOT NULL,
+                mistake_id TEXT NOT NULL,
+                added_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (session_id, mistake_id),
+                FOREIGN KEY (session_id) REFERENCES review_sessions(id) ON DELETE CASCADE,
+                FOREIGN KEY (mistake_id) REFERENCES mistakes(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // 创建索引以提高查询性能
+        conn.execute("CREATE INDEX IF NOT EXISTS idx_vectorized_data_mistake_id ON vectorized_data(mistake_id)", [])?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_review_session_mistakes_session_id ON review_session_mistakes(session_id)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_review_session_mistakes_mistake_id ON review_session_mistakes(mistake_id)",
+            [],
+        )?;
+
+        println!("数据库迁移 v13 -> v14 完成");
+        Ok(())
+    }
+
+    fn migrate_v14_to_v15(&self, conn: &rusqlite::Connection) -> Result<()> {
+        println!("开始数据库迁移 v14 -> v15: 添加搜索日志表...");
+
+        // 创建搜索日志表
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS search_logs (
+                id TEXT PRIMARY KEY,
+                search_type TEXT NOT NULL,
+                query TEXT NOT NULL,
+                result_count INTEGER NOT NULL,
+                execution_time_ms INTEGER NOT NULL,
+                mistake_ids_json TEXT,
+                error_message TEXT,
+                user_feedback TEXT,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
 
-        let mut documents = Vec::new();
-        for row in rows {
-            documents.push(row?);
-        }
+        // 创建索引以提高查询性能
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_search_logs_created_at ON search_logs (created_at)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_search_logs_search_type ON search_logs (search_type)",
+            [],
+        )?;
 
-        Ok(documents)
+        println!("搜索日志表创建成功");
+        println!("数据库迁移 v14 -> v15 完成");
+        Ok(())
     }
 
-    /// 将文档移动到指定分库
-    pub fn move_document_to_sub_library(
-        &self,
-        document_id: &str,
-        target_sub_library_id: &str,
-    ) -> Result<()> {
-        let conn = self.get_conn_safe()?;
+    fn migrate_v15_to_v16(&self, conn: &rusqlite::Connection) -> Result<()> {
+        // 添加文档控制状态表
+        println!("开始数据库迁移 v15 -> v16: 添加文档控制状态持久化...");
 
-        // 检查目标分库是否存在
-        let library_exists: bool = conn.query_row(
-            "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE id = ?1)",
-            params![target_sub_library_id],
-            |row| row.get(0),
+        // 创建文档控制状态表
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS document_control_states (
+                document_id TEXT PRIMARY KEY,
+                state TEXT NOT NULL,
+                pending_tasks_json TEXT NOT NULL DEFAULT '[]',
+                running_tasks_json TEXT NOT NULL DEFAULT '{}',
+                completed_tasks_json TEXT NOT NULL DEFAULT '[]',
+                failed_tasks_json TEXT NOT NULL DEFAULT '{}',
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
         )?;
 
-        if !library_exists {
-            return Err(anyhow::anyhow!(
-                "目标分库ID '{}' 不存在",
-                target_sub_library_id
-            ));
-        }
-
-        // 检查文档是否存在
-        let document_exists: bool = conn.query_row(
-            "SELECT EXISTS(SELECT 1 FROM rag_documents WHERE id = ?1)",
-            params![document_id],
-            |row| row.get(0),
+        // 创建索引以提高查询性能
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_document_control_states_state ON document_control_states (state)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_document_control_states_updated_at ON document_control_states (updated_at)",
+            [],
         )?;
 
-        if !document_exists {
-            return Err(anyhow::anyhow!("文档ID '{}' 不存在", document_id));
-        }
-
-        // 更新文档的分库归属
-        let updated_at = Utc::now().to_rfc3339();
+        // 创建触发器自动更新 updated_at
         conn.execute(
-            "UPDATE rag_documents SET sub_library_id = ?1, updated_at = ?2 WHERE id = ?3",
-            params![target_sub_library_id, updated_at, document_id],
+            "CREATE TRIGGER IF NOT EXISTS update_document_control_states_timestamp
+             AFTER UPDATE ON document_control_states
+             BEGIN
+                 UPDATE document_control_states SET updated_at = CURRENT_TIMESTAMP WHERE document_id = NEW.document_id;
+             END",
+            [],
         )?;
 
-        log::info!(
-            "成功将文档 {} 移动到分库 {}",
-            document_id,
-            target_sub_library_id
-        );
+        println!("文档控制状态表创建成功");
+        println!("数据库迁移 v15 -> v16 完成");
         Ok(())
     }
 
-    // =================== Migration Functions ===================
-    // ============================================
-    // 已废弃：旧版本迁移函数 (v8-v30)
-    // 新系统使用 data_governance::migration
-    // 保留代码供参考，待完全验证后删除
-    // ============================================
-    /*
-    /// 版本8到版本9的数据库迁移：过去用于添加图片遮罩卡表，现在改为清理遗留结构
-    fn migrate_v8_to_v9(&self, conn: &rusqlite::Connection) -> Result<()> {
-        println!("正在迁移数据库版本8到版本9：清理图片遮罩卡遗留表...");
 
-        conn.execute_batch(
-            "DROP INDEX IF EXISTS idx_image_occlusion_cards_task_id;
-            DROP INDEX IF EXISTS idx_image_occlusion_cards_subject;
-            DROP INDEX IF EXISTS idx_image_occlusion_cards_created_at;
-            DROP TABLE IF EXISTS image_occlusion_cards;",
-        )?;
+    fn migrate_v17_to_v18(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
+        println!("开始数据库迁移 v17 -> v18: 添加数学工作流图片存储...");
 
-        println!("数据库版本8到版本9迁移完成（已移除图片遮罩卡表）");
+        // 🔧 检查 kg_problem_cards 表是否存在（它属于irec数据库，在主数据库中可能不存在）
+        let kg_table_exists = conn
+            .prepare(
+                "SELECT name FROM sqlite_master WHERE type='table' AND name='kg_problem_cards';",
+            )?
+            .query_map([], |_| Ok(()))?
+            .any(|_| true);
+
+        if kg_table_exists {
+            // 为 kg_problem_cards 表添加原始图片路径字段
+            match conn.execute(
+                "ALTER TABLE kg_problem_cards ADD COLUMN original_image_path TEXT NULL",
+                [],
+            ) {
+                Ok(_) => println!("kg_problem_cards.original_image_path 字段添加成功"),
+                Err(e) => {
+                    if e.to_string().contains("duplicate column name") {
+                        println!("kg_problem_cards.original_image_path 字段已存在");
+                    } else {
+                        println!("添加 original_image_path 字段失败: {}", e);
+                    }
+                }
+            }
+        } else {
+            println!("kg_problem_cards表不存在（属于irec数据库），跳过相关字段添加");
+        }
+
+        println!("数学工作流图片路径字段添加成功");
+        println!("数据库迁移 v17 -> v18 完成");
         Ok(())
     }
 
-    fn migrate_v9_to_v10(&self, conn: &rusqlite::Connection) -> Result<()> {
-        println!("正在迁移数据库版本9到版本10：为anki_cards表添加text字段支持Cloze模板...");
-
-        // 🔧 检查text字段是否已存在
-        let text_column_exists = conn
-            .prepare("PRAGMA table_info(anki_cards)")?
-            .query_map([], |row| {
-                Ok(row.get::<_, String>(1)?) // 获取列名
-            })?
-            .filter_map(Result::ok)
-            .any(|name| name == "text");
+    fn migrate_v18_to_v19(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
+        println!("开始数据库迁移 v18 -> v19: 添加文档附件支持...");
 
-        if !text_column_exists {
-            // 添加text字段到anki_cards表
-            conn.execute("ALTER TABLE anki_cards ADD COLUMN text TEXT;", [])?;
-            println!("已为anki_cards表添加text字段");
-        } else {
-            println!("text字段已存在，跳过添加");
+        // 为 chat_messages 表添加文档附件字段
+        match conn.execute(
+            "ALTER TABLE chat_messages ADD COLUMN doc_attachments TEXT",
+            [],
+        ) {
+            Ok(_) => println!("chat_messages.doc_attachments 字段添加成功"),
+            Err(e) => {
+                if e.to_string().contains("duplicate column name") {
+                    println!("chat_messages.doc_attachments 字段已存在");
+                } else {
+                    println!("添加 doc_attachments 字段失败: {}", e);
+                }
+            }
         }
 
-        // 添加索引以优化查询性能
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_anki_cards_text ON anki_cards(text);",
+        // 为 review_chat_messages 表添加文档附件字段
+        match conn.execute(
+            "ALTER TABLE review_chat_messages ADD COLUMN doc_attachments TEXT",
             [],
-        )?;
+        ) {
+            Ok(_) => println!("review_chat_messages.doc_attachments 字段添加成功"),
+            Err(e) => {
+                if e.to_string().contains("duplicate column name") {
+                    println!("review_chat_messages.doc_attachments 字段已存在");
+                } else {
+                    println!(
+                        "添加 doc_attachments 字段到 review_chat_messages 失败: {}",
+                        e
+                    );
+                }
+            }
+        }
 
-        println!("数据库版本9到版本10迁移完成");
+        println!("数据库迁移 v18 -> v19 完成");
         Ok(())
     }
 
-    fn migrate_v10_to_v11(&self, conn: &rusqlite::Connection) -> Result<()> {
-        println!("正在迁移数据库版本10到版本11：为review_analyses表添加会话管理字段...");
+    fn migrate_v19_to_v20(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
+        println!("开始数据库迁移 v19 -> v20: 为review_chat_messages表添加多模态支持...");
 
-        // 为review_analyses表添加temp_session_data字段
-        let add_temp_session = conn.execute(
-            "ALTER TABLE review_analyses ADD COLUMN temp_session_data TEXT DEFAULT '{}'",
+        // 为 review_chat_messages 表添加 image_paths 字段
+        match conn.execute(
+            "ALTER TABLE review_chat_messages ADD COLUMN image_paths TEXT",
             [],
-        );
+        ) {
+            Ok(_) => println!("review_chat_messages.image_paths 字段添加成功"),
+            Err(e) => {
+                if e.to_string().contains("duplicate column name") {
+                    println!("review_chat_messages.image_paths 字段已存在");
+                } else {
+                    println!(
+                        "添加 image_paths 字段到 review_chat_messages 失败: {}",
+                        e
+                    );
+                }
+            }
+        }
 
-        // 为review_analyses表添加session_sequence字段
-        let add_session_sequence = conn.execute(
-            "ALTER TABLE review_analyses ADD COLUMN session_sequence INTEGER DEFAULT 0",
+        // 为 review_chat_messages 表添加 image_base64 字段
+        match conn.execute(
+            "ALTER TABLE review_chat_messages ADD COLUMN image_base64 TEXT",
             [],
-        );
-
-        match (add_temp_session, add_session_sequence) {
-            (Ok(_), Ok(_)) => {
-                println!("已为review_analyses表添加temp_session_data和session_sequence字段");
-            }
-            (Err(e), _) | (_, Err(e)) => {
-                // 如果字段已存在，这是正常的
+        ) {
+            Ok(_) => println!("review_chat_messages.image_base64 字段添加成功"),
+            Err(e) => {
                 if e.to_string().contains("duplicate column name") {
-                    println!("字段已存在，跳过添加");
+                    println!("review_chat_messages.image_base64 字段已存在");
                 } else {
-                    return Err(e.into());
+                    println!(
+                        "添加 image_base64 字段到 review_chat_messages 失败: {}",
+                        e
+                    );
                 }
             }
         }
 
-        println!("数据库版本10到版本11迁移完成");
+        println!("数据库迁移 v19 -> v20 完成");
+        Ok(())
+    }
+
+    fn migrate_v26_to_v27(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
+        println!("开始数据库迁移 v26 -> v27: 添加题目集识别关联字段...");
+
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('mistakes') WHERE name='exam_sheet'",
+                [],
+                |row| row.get::<_, i32>(0).map(|count| count > 0),
+            )
+            .unwrap_or(false);
+
+        if !has_column {
+            conn.execute("ALTER TABLE mistakes ADD COLUMN exam_sheet TEXT", [])?;
+            println!("已为 mistakes 表添加 exam_sheet 列");
+        } else {
+            println!("mistakes 表已包含 exam_sheet 列，跳过添加");
+        }
+
+        println!("数据库迁移 v26 -> v27 完成");
         Ok(())
     }
 
-    fn migrate_v11_to_v12(&self, conn: &rusqlite::Connection) -> Result<()> {
-        println!("开始数据库迁移 v11 -> v12: 插入内置模板...");
+    fn migrate_v27_to_v28(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
+        println!("开始数据库迁移 v27 -> v28: 为 mistakes 表添加 last_accessed_at 字段...");
+
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('mistakes') WHERE name='last_accessed_at'",
+                [],
+                |row| row.get::<_, i32>(0).map(|count| count > 0),
+            )
+            .unwrap_or(false);
 
+        if !has_column {
+            conn.execute(
+                "ALTER TABLE mistakes ADD COLUMN last_accessed_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z'",
+                [],
+            )?;
+            conn.execute(
+                "UPDATE mistakes SET last_accessed_at = updated_at WHERE last_accessed_at IS NULL OR last_accessed_at = '1970-01-01T00:00:00Z'",
+                [],
+            )?;
+            println!("已为 mistakes 表添加 last_accessed_at 列");
+        } else {
+            println!("mistakes 表已包含 last_accessed_at 列，跳过添加");
+        }
 
+        println!("数据库迁移 v27 -> v28 完成");
+        Ok(())
+    }
 
+    fn migrate_v28_to_v29(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
+        println!("开始数据库迁移 v28 -> v29: 创建 exam_sheet_sessions 表...");
 
-        // 确保custom_anki_templates表存在
         conn.execute(
-            "CREATE TABLE IF NOT EXISTS custom_anki_templates (
+            "CREATE TABLE IF NOT EXISTS exam_sheet_sessions (
                 id TEXT PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                description TEXT,
-                author TEXT,
-                version TEXT NOT NULL DEFAULT '1.0.0',
-                preview_front TEXT NOT NULL,
-                preview_back TEXT NOT NULL,
-                note_type TEXT NOT NULL DEFAULT 'Basic',
-                fields_json TEXT NOT NULL DEFAULT '[]',
-                generation_prompt TEXT NOT NULL,
-                front_template TEXT NOT NULL,
-                back_template TEXT NOT NULL,
-                css_style TEXT NOT NULL,
-                field_extraction_rules_json TEXT NOT NULL DEFAULT '{}',
-                created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
-                updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
-                is_active INTEGER NOT NULL DEFAULT 1,
-                is_built_in INTEGER NOT NULL DEFAULT 0
-            );",
+                exam_name TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                temp_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                metadata_json TEXT NOT NULL,
+                preview_json TEXT NOT NULL,
+                linked_mistake_ids TEXT
+            )",
             [],
         )?;
 
-        // 检查表中是否已有内置模板
-        let count: i32 = conn.query_row(
-            "SELECT COUNT(*) FROM custom_anki_templates WHERE is_built_in = 1",
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_exam_sheet_sessions_status
+                ON exam_sheet_sessions (status)",
             [],
-            |row| row.get(0),
         )?;
 
-        println!("当前内置模板数量: {}", count);
-
-        // 如果已有内置模板，跳过迁移
-        if count > 0 {
-            println!("内置模板已存在，跳过迁移");
-            return Ok(());
-        }
-
-        println!("v11->v12: 跳过硬编码模板插入，改用 JSON 导入");
+        println!("数据库迁移 v28 -> v29 完成");
         Ok(())
     }
 
-    fn migrate_v12_to_v13(&self, conn: &rusqlite::Connection) -> Result<()> {
-        println!("开始数据库迁移 v12 -> v13: 添加预览数据字段...");
+    fn migrate_v29_to_v30(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
+        println!(
+            "开始数据库迁移 v29 -> v30: 校验 exam_sheet_sessions 表的 linked_mistake_ids 列..."
+        );
 
-        // 检查是否已有 preview_data_json 列
-        let has_preview_data_json: bool = conn.query_row(
-            "SELECT COUNT(*) FROM pragma_table_info('custom_anki_templates') WHERE name='preview_data_json'",
-            [],
-            |row| row.get::<_, i32>(0).map(|count| count > 0),
-        )?;
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('exam_sheet_sessions') WHERE name='linked_mistake_ids'",
+                [],
+                |row| row.get::<_, i32>(0).map(|count| count > 0),
+            )
+            .unwrap_or(false);
 
-        if !has_preview_data_json {
+        if !has_column {
             conn.execute(
-                "ALTER TABLE custom_anki_templates ADD COLUMN preview_data_json TEXT",
+                "ALTER TABLE exam_sheet_sessions ADD COLUMN linked_mistake_ids TEXT",
                 [],
             )?;
-            println!("已添加 preview_data_json 字段");
+            println!("已为 exam_sheet_sessions 表添加 linked_mistake_ids 列");
         } else {
-            println!("preview_data_json 字段已存在");
+            println!("exam_sheet_sessions 表已包含 linked_mistake_ids 列，跳过添加");
         }
 
-        // 注意：内置模板导入将通过前端的导入按钮或应用启动时自动处理
-        println!("内置模板将通过独立的导入机制处理");
-
-        println!("数据库迁移 v12 -> v13 完成");
+        println!("数据库迁移 v29 -> v30 完成");
         Ok(())
     }
+    */
+    // ============================================
+    // 旧版本迁移函数 (v8-v30) 结束
+    // ============================================
+}
 
-    fn migrate_v13_to_v14(&self, conn: &rusqlite::Connection) -> Result<()> {
-        println!("开始数据库迁移 v13 -> v14: 添加向量化表、子库表和错题笔记整理会话表...");
+impl DatabaseManager {
+    // ============================================
+    // 已废弃：重复的迁移函数
+    // 新系统使用 data_governance::migration
+    // 保留代码供参考，待完全验证后删除
+    // ============================================
+    /*
+    fn migrate_v26_to_v27(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
+        println!("开始数据库迁移 v26 -> v27: 添加题目集识别关联字段...");
 
-        // 创建向量化数据表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS vectorized_data (
-                id TEXT PRIMARY KEY,
-                mistake_id TEXT NOT NULL,
-                text_content TEXT NOT NULL,
-                embedding_json TEXT NOT NULL,
-                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (mistake_id) REFERENCES mistakes(id) ON DELETE CASCADE
-            )",
-            [],
-        )?;
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('mistakes') WHERE name='exam_sheet'",
+                [],
+                |row| row.get::<_, i32>(0).map(|count| count > 0),
+            )
+            .unwrap_or(false);
 
-        // 创建分库表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS rag_sub_libraries (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                description TEXT,
-                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
+        if !has_column {
+            conn.execute("ALTER TABLE mistakes ADD COLUMN exam_sheet TEXT", [])?;
+            println!("已为 mistakes 表添加 exam_sheet 列");
+        } else {
+            println!("mistakes 表已包含 exam_sheet 列，跳过添加");
+        }
 
-        // 创建整理会话表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS review_sessions (
-                id TEXT PRIMARY KEY,
-                title TEXT NOT NULL,
-                start_date TEXT NOT NULL,
-                end_date TEXT NOT NULL,
-                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
+        println!("数据库迁移 v26 -> v27 完成");
+        Ok(())
+    }
+    /// 导入包含预览数据的内置模板
+    fn import_builtin_templates_with_preview_data(
+        &self,
+        conn: &SqlitePooledConnection,
+    ) -> Result<()> {
+        println!("跳过硬编码内置模板（含预览数据）的导入，改用 JSON 导入");
+        return Ok(());
 
-        // 创建会话错题关联表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS review_session_mistakes (
-                session_id TEXT NOT NULL,
-                mistake_id TEXT NOT NULL,
-                added_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                PRIMARY KEY (session_id, mistake_id),
-                FOREIGN KEY (session_id) REFERENCES review_sessions(id) ON DELETE CASCADE,
-                FOREIGN KEY (mistake_id) REFERENCES mistakes(id) ON DELETE CASCADE
-            )",
-            [],
-        )?;
+    }
+    */
+    // ============================================
+    // 重复的迁移函数结束
+    // ============================================
+}
+
+impl Database {
+    /// 设置默认模板ID
+    pub fn set_default_template(&self, template_id: &str) -> Result<()> {
+        let conn = self.get_conn_safe()?;
+        let now = Utc::now().to_rfc3339();
 
-        // 创建索引以提高查询性能
-        conn.execute("CREATE INDEX IF NOT EXISTS idx_vectorized_data_mistake_id ON vectorized_data(mistake_id)", [])?;
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_review_session_mistakes_session_id ON review_session_mistakes(session_id)",
-            [],
-        )?;
         conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_review_session_mistakes_mistake_id ON review_session_mistakes(mistake_id)",
-            [],
+            "INSERT OR REPLACE INTO settings (key, value, updated_at) VALUES ('default_template_id', ?1, ?2)",
+            params![template_id, now]
         )?;
 
-        println!("数据库迁移 v13 -> v14 完成");
         Ok(())
     }
 
-    fn migrate_v14_to_v15(&self, conn: &rusqlite::Connection) -> Result<()> {
-        println!("开始数据库迁移 v14 -> v15: 添加搜索日志表...");
-
-        // 创建搜索日志表
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS search_logs (
-                id TEXT PRIMARY KEY,
-                search_type TEXT NOT NULL,
-                query TEXT NOT NULL,
-                result_count INTEGER NOT NULL,
-                execution_time_ms INTEGER NOT NULL,
-                mistake_ids_json TEXT,
-                error_message TEXT,
-                user_feedback TEXT,
-                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
+    /// 获取默认模板ID
+    pub fn get_default_template(&self) -> Result<Option<String>> {
+        let conn = self.get_conn_safe()?;
 
-        // 创建索引以提高查询性能
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_search_logs_created_at ON search_logs (created_at)",
-            [],
-        )?;
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_search_logs_search_type ON search_logs (search_type)",
+        match conn.query_row(
+            "SELECT value FROM settings WHERE key = 'default_template_id'",
             [],
-        )?;
+            |row| row.get::<_, String>(0),
+        ) {
+            Ok(template_id) => Ok(Some(template_id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
 
-        println!("搜索日志表创建成功");
-        println!("数据库迁移 v14 -> v15 完成");
-        Ok(())
+    /// 科目级默认模板映射（科目名 -> 模板ID），存于 settings 的 JSON 值中
+    pub fn get_subject_default_templates(
+        &self,
+    ) -> Result<std::collections::HashMap<String, String>> {
+        match self.get_setting(SUBJECT_DEFAULT_TEMPLATES_KEY)? {
+            Some(raw) if !raw.trim().is_empty() => Ok(serde_json::from_str(&raw)?),
+            _ => Ok(std::collections::HashMap::new()),
+        }
     }
 
-    fn migrate_v15_to_v16(&self, conn: &rusqlite::Connection) -> Result<()> {
-        // 添加文档控制状态表
-        println!("开始数据库迁移 v15 -> v16: 添加文档控制状态持久化...");
+    /// 设置或清除（`template_id` 为 None）某科目的默认模板
+    pub fn set_subject_default_template(
+        &self,
+        subject: &str,
+        template_id: Option<&str>,
+    ) -> Result<()> {
+        let subject = subject.trim();
+        if subject.is_empty() {
+            return Err(anyhow::anyhow!("科目名称不能为空"));
+        }
+        let mut mapping = self.get_subject_default_templates()?;
+        match template_id.map(str::trim).filter(|id| !id.is_empty()) {
+            Some(id) => {
+                mapping.insert(subject.to_string(), id.to_string());
+            }
+            None => {
+                mapping.remove(subject);
+            }
+        }
+        self.save_setting(
+            SUBJECT_DEFAULT_TEMPLATES_KEY,
+            &serde_json::to_string(&mapping)?,
+        )
+    }
 
-        // 创建文档控制状态表
+    /// 记录搜索日志
+    pub fn log_search(
+        &self,
+        query: &str,
+        search_type: &str,
+        results_count: u32,
+        response_time_ms: Option<u64>,
+    ) -> Result<()> {
+        let conn = self.get_conn_safe()?;
         conn.execute(
-            "CREATE TABLE IF NOT EXISTS document_control_states (
-                document_id TEXT PRIMARY KEY,
-                state TEXT NOT NULL,
-                pending_tasks_json TEXT NOT NULL DEFAULT '[]',
-                running_tasks_json TEXT NOT NULL DEFAULT '{}',
-                completed_tasks_json TEXT NOT NULL DEFAULT '[]',
-                failed_tasks_json TEXT NOT NULL DEFAULT '{}',
-                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
+            "INSERT INTO search_logs (query, search_type, results_count, response_time_ms, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                query,
+                search_type,
+                results_count,
+                response_time_ms,
+                chrono::Utc::now().to_rfc3339()
+            ],
         )?;
+        Ok(())
+    }
 
-        // 创建索引以提高查询性能
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_document_control_states_state ON document_control_states (state)",
+    /// 获取搜索日志统计
+    pub fn get_search_statistics(&self) -> Result<SearchStatistics> {
+        let conn = self.get_conn_safe()?;
+
+        // 获取总搜索次数
+        let total_searches: i64 =
+            conn.query_row("SELECT COUNT(*) FROM search_logs", [], |row| row.get(0))?;
+
+        // 获取最近7天的搜索次数
+        let recent_searches: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM search_logs
+             WHERE created_at >= datetime('now', '-7 days')",
             [],
+            |row| row.get(0),
         )?;
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_document_control_states_updated_at ON document_control_states (updated_at)",
-            [],
+
+        // 获取平均响应时间
+        let avg_response_time: Option<f64> = conn
+            .query_row(
+                "SELECT AVG(response_time_ms) FROM search_logs
+             WHERE response_time_ms IS NOT NULL",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        // 获取搜索类型分布
+        let mut search_type_distribution = std::collections::HashMap::new();
+        let mut stmt = conn.prepare(
+            "SELECT search_type, COUNT(*) as count
+             FROM search_logs
+             GROUP BY search_type",
         )?;
+        let type_iter = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        for type_result in type_iter {
+            let (search_type, count) = type_result?;
+            search_type_distribution.insert(search_type, count);
+        }
 
-        // 创建触发器自动更新 updated_at
-        conn.execute(
-            "CREATE TRIGGER IF NOT EXISTS update_document_control_states_timestamp
-             AFTER UPDATE ON document_control_states
-             BEGIN
-                 UPDATE document_control_states SET updated_at = CURRENT_TIMESTAMP WHERE document_id = NEW.document_id;
-             END",
-            [],
+        // 获取热门搜索查询
+        let mut popular_queries = Vec::new();
+        let mut stmt = conn.prepare(
+            "SELECT query, COUNT(*) as count
+             FROM search_logs
+             GROUP BY query
+             ORDER BY count DESC
+             LIMIT 10",
         )?;
+        let query_iter = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        for query_result in query_iter {
+            popular_queries.push(query_result?);
+        }
 
-        println!("文档控制状态表创建成功");
-        println!("数据库迁移 v15 -> v16 完成");
-        Ok(())
+        Ok(SearchStatistics {
+            total_searches,
+            recent_searches,
+            avg_response_time_ms: avg_response_time.unwrap_or(0.0),
+            search_type_distribution,
+            popular_queries,
+        })
     }
 
+    /// 获取最近的文档任务（用于状态恢复）
+    pub fn get_recent_document_tasks(&self, limit: u32) -> Result<Vec<DocumentTask>> {
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, document_id, original_document_name, segment_index, content_segment,
+                    status, created_at, updated_at, error_message, anki_generation_options_json
+             FROM document_tasks
+             WHERE id <> ?1
+             ORDER BY updated_at DESC
+             LIMIT ?2",
+        )?;
 
-    fn migrate_v17_to_v18(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
-        println!("开始数据库迁移 v17 -> v18: 添加数学工作流图片存储...");
-
-        // 🔧 检查 kg_problem_cards 表是否存在（它属于irec数据库，在主数据库中可能不存在）
-        let kg_table_exists = conn
-            .prepare(
-                "SELECT name FROM sqlite_master WHERE type='table' AND name='kg_problem_cards';",
-            )?
-            .query_map([], |_| Ok(()))?
-            .any(|_| true);
-
-        if kg_table_exists {
-            // 为 kg_problem_cards 表添加原始图片路径字段
-            match conn.execute(
-                "ALTER TABLE kg_problem_cards ADD COLUMN original_image_path TEXT NULL",
-                [],
-            ) {
-                Ok(_) => println!("kg_problem_cards.original_image_path 字段添加成功"),
-                Err(e) => {
-                    if e.to_string().contains("duplicate column name") {
-                        println!("kg_problem_cards.original_image_path 字段已存在");
-                    } else {
-                        println!("添加 original_image_path 字段失败: {}", e);
-                    }
-                }
-            }
-        } else {
-            println!("kg_problem_cards表不存在（属于irec数据库），跳过相关字段添加");
-        }
+        let tasks = stmt
+            .query_map(params![ARCHIVED_CARDS_TASK_ID, limit], |row| {
+                let status_str: String = row.get(5)?;
+                Ok(DocumentTask {
+                    id: row.get(0)?,
+                    document_id: row.get(1)?,
+                    original_document_name: row.get(2)?,
+                    segment_index: row.get(3)?,
+                    content_segment: row.get(4)?,
+                    status: TaskStatus::from_str(&status_str),
+                    created_at: row.get(6)?,
+                    updated_at: row.get(7)?,
+                    error_message: row.get(8)?,
+                    anki_generation_options_json: row.get(9)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<DocumentTask>>>()?;
 
-        println!("数学工作流图片路径字段添加成功");
-        println!("数据库迁移 v17 -> v18 完成");
-        Ok(())
+        Ok(tasks)
     }
 
-    fn migrate_v18_to_v19(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
-        println!("开始数据库迁移 v18 -> v19: 添加文档附件支持...");
+    /// 🔧 Phase 1: 恢复卡住的制卡任务
+    /// 将 Processing/Streaming 状态超过 1 小时的任务重置为 Pending
+    pub fn recover_stuck_document_tasks(&self) -> Result<u32> {
+        let conn = self.get_conn_safe()?;
+        let count = conn.execute(
+            r#"UPDATE document_tasks
+               SET status = 'Pending', error_message = 'Recovered after app restart', updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+               WHERE status IN ('Processing', 'Streaming')
+               AND updated_at < datetime('now', '-1 hour')"#,
+            [],
+        )?;
+        Ok(count as u32)
+    }
 
-        // 为 chat_messages 表添加文档附件字段
-        match conn.execute(
-            "ALTER TABLE chat_messages ADD COLUMN doc_attachments TEXT",
+    /// 🔧 Phase 1: 按 document_id 分组汇总任务信息（用于任务管理页面）
+    pub fn list_document_sessions(&self, limit: u32) -> Result<Vec<serde_json::Value>> {
+        let conn = self.get_conn_safe()?;
+        // 确保 source_session_id 列存在（兼容旧数据库）
+        let _ = conn.execute(
+            "ALTER TABLE document_tasks ADD COLUMN source_session_id TEXT",
             [],
-        ) {
-            Ok(_) => println!("chat_messages.doc_attachments 字段添加成功"),
-            Err(e) => {
-                if e.to_string().contains("duplicate column name") {
-                    println!("chat_messages.doc_attachments 字段已存在");
-                } else {
-                    println!("添加 doc_attachments 字段失败: {}", e);
-                }
-            }
-        }
+        );
+        // 使用 LEFT JOIN + COUNT(DISTINCT) 代替关联子查询，提升大数据量下的性能
+        let mut stmt = conn.prepare(
+            r#"SELECT
+                 dt.document_id,
+                 dt.original_document_name,
+                 dt.source_session_id,
+                 COUNT(DISTINCT dt.id) AS total_tasks,
+                 COUNT(DISTINCT CASE WHEN dt.status = 'Completed' THEN dt.id END) AS completed_tasks,
+                 COUNT(DISTINCT CASE WHEN dt.status IN ('Failed', 'Truncated') THEN dt.id END) AS failed_tasks,
+                 COUNT(DISTINCT CASE WHEN dt.status IN ('Processing', 'Streaming', 'Pending') THEN dt.id END) AS active_tasks,
+                 COUNT(DISTINCT CASE WHEN dt.status = 'Paused' THEN dt.id END) AS paused_tasks,
+                 MAX(dt.updated_at) AS last_updated,
+                 MIN(dt.created_at) AS created_at,
+                 COUNT(DISTINCT ac.id) AS total_cards
+               FROM document_tasks dt
+               LEFT JOIN anki_cards ac ON ac.task_id = dt.id
+               WHERE dt.id <> ?2
+               GROUP BY dt.document_id
+               ORDER BY MAX(dt.updated_at) DESC
+               LIMIT ?1"#,
+        )?;
+
+        let rows = stmt
+            .query_map(params![limit, ARCHIVED_CARDS_TASK_ID], |row| {
+                Ok(serde_json::json!({
+                    "documentId": row.get::<_, String>(0)?,
+                    "documentName": row.get::<_, String>(1)?,
+                    "sourceSessionId": row.get::<_, Option<String>>(2)?,
+                    "totalTasks": row.get::<_, i64>(3)?,
+                    "completedTasks": row.get::<_, i64>(4)?,
+                    "failedTasks": row.get::<_, i64>(5)?,
+                    "activeTasks": row.get::<_, i64>(6)?,
+                    "pausedTasks": row.get::<_, i64>(7)?,
+                    "lastUpdated": row.get::<_, String>(8)?,
+                    "createdAt": row.get::<_, String>(9)?,
+                    "totalCards": row.get::<_, i64>(10)?,
+                }))
+            })?
+            .collect::<rusqlite::Result<Vec<serde_json::Value>>>()?;
 
-        // 为 review_chat_messages 表添加文档附件字段
-        match conn.execute(
-            "ALTER TABLE review_chat_messages ADD COLUMN doc_attachments TEXT",
-            [],
-        ) {
-            Ok(_) => println!("review_chat_messages.doc_attachments 字段添加成功"),
-            Err(e) => {
-                if e.to_string().contains("duplicate column name") {
-                    println!("review_chat_messages.doc_attachments 字段已存在");
-                } else {
-                    println!(
-                        "添加 doc_attachments 字段到 review_chat_messages 失败: {}",
-                        e
-                    );
-                }
-            }
-        }
+        Ok(rows)
+    }
 
-        println!("数据库迁移 v18 -> v19 完成");
-        Ok(())
+    /// 🔧 Phase 2: 卡片库统计数据（用于任务管理页面统计卡片）
+    pub fn get_anki_stats(&self) -> Result<serde_json::Value> {
+        let conn = self.get_conn_safe()?;
+        let total_cards: i64 =
+            conn.query_row("SELECT COUNT(*) FROM anki_cards", [], |r| r.get(0))?;
+        let total_tasks: i64 = conn.query_row(
+            "SELECT COUNT(DISTINCT document_id) FROM document_tasks WHERE id <> ?1",
+            params![ARCHIVED_CARDS_TASK_ID],
+            |r| r.get(0),
+        )?;
+        let error_cards: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM anki_cards WHERE is_error_card = 1",
+            [],
+            |r| r.get(0),
+        )?;
+        let template_count: i64 = conn.query_row(
+            "SELECT COUNT(DISTINCT template_id) FROM anki_cards WHERE template_id IS NOT NULL AND template_id != ''",
+            [],
+            |r| r.get(0),
+        )?;
+        Ok(serde_json::json!({
+            "totalCards": total_cards,
+            "totalDocuments": total_tasks,
+            "errorCards": error_cards,
+            "templateCount": template_count,
+        }))
     }
 
-    fn migrate_v19_to_v20(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
-        println!("开始数据库迁移 v19 -> v20: 为review_chat_messages表添加多模态支持...");
+    /// 获取最近生成的Anki卡片（用于状态恢复）
+    pub fn get_recent_anki_cards(&self, limit: u32) -> Result<Vec<AnkiCard>> {
+        let conn = self.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, task_id, front, back, text, tags_json, images_json,
+                    is_error_card, error_content, created_at, updated_at,
+                    COALESCE(extra_fields_json, '{}') as extra_fields_json, template_id
+             FROM anki_cards
+             ORDER BY created_at DESC
+             LIMIT ?",
+        )?;
 
-        // 为 review_chat_messages 表添加 image_paths 字段
-        match conn.execute(
-            "ALTER TABLE review_chat_messages ADD COLUMN image_paths TEXT",
-            [],
-        ) {
-            Ok(_) => println!("review_chat_messages.image_paths 字段添加成功"),
-            Err(e) => {
-                if e.to_string().contains("duplicate column name") {
-                    println!("review_chat_messages.image_paths 字段已存在");
-                } else {
-                    println!(
-                        "添加 image_paths 字段到 review_chat_messages 失败: {}",
-                        e
-                    );
-                }
-            }
-        }
+        let cards = stmt
+            .query_map([limit], |row| {
+                let tags_json: String = row.get(5)?;
+                let images_json: String = row.get(6)?;
+                let extra_fields_json: String = row.get(12)?;
 
-        // 为 review_chat_messages 表添加 image_base64 字段
-        match conn.execute(
-            "ALTER TABLE review_chat_messages ADD COLUMN image_base64 TEXT",
-            [],
-        ) {
-            Ok(_) => println!("review_chat_messages.image_base64 字段添加成功"),
-            Err(e) => {
-                if e.to_string().contains("duplicate column name") {
-                    println!("review_chat_messages.image_base64 字段已存在");
-                } else {
-                    println!(
-                        "添加 image_base64 字段到 review_chat_messages 失败: {}",
-                        e
-                    );
-                }
-            }
-        }
+                Ok(AnkiCard {
+                    id: row.get(0)?,
+                    task_id: row.get(1)?,
+                    front: row.get(2)?,
+                    back: row.get(3)?,
+                    text: row.get(4)?,
+                    tags: serde_json::from_str(&tags_json).unwrap_or_default(),
+                    images: serde_json::from_str(&images_json).unwrap_or_default(),
+                    is_error_card: row.get::<_, i32>(7)? != 0,
+                    error_content: row.get(8)?,
+                    created_at: row.get(9)?,
+                    updated_at: row.get(10)?,
+                    extra_fields: serde_json::from_str(&extra_fields_json).unwrap_or_default(),
+                    template_id: row.get(13)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<AnkiCard>>>()?;
 
-        println!("数据库迁移 v19 -> v20 完成");
-        Ok(())
+        Ok(cards)
     }
 
-    fn migrate_v26_to_v27(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
-        println!("开始数据库迁移 v26 -> v27: 添加题目集识别关联字段...");
+    pub fn list_anki_library_cards(
+        &self,
+        _subject: Option<&str>,
+        template_id: Option<&str>,
+        search: Option<&str>,
+        page: u32,
+        page_size: u32,
+    ) -> Result<(Vec<AnkiLibraryCard>, u64)> {
+        let conn = self.get_conn_safe()?;
+        let mut clauses: Vec<String> = Vec::new();
+        let mut params: Vec<Value> = Vec::new();
 
-        let has_column: bool = conn
-            .query_row(
-                "SELECT COUNT(*) FROM pragma_table_info('mistakes') WHERE name='exam_sheet'",
-                [],
-                |row| row.get::<_, i32>(0).map(|count| count > 0),
-            )
-            .unwrap_or(false);
+        if let Some(template_value) = template_id
+            .map(|s| s.trim())
+            .filter(|value| !value.is_empty())
+        {
+            clauses.push("ac.template_id = ?".to_string());
+            params.push(Value::from(template_value.to_string()));
+        }
 
-        if !has_column {
-            conn.execute("ALTER TABLE mistakes ADD COLUMN exam_sheet TEXT", [])?;
-            println!("已为 mistakes 表添加 exam_sheet 列");
-        } else {
-            println!("mistakes 表已包含 exam_sheet 列，跳过添加");
+        if let Some(search_value) = search.map(|s| s.trim()).filter(|value| !value.is_empty()) {
+            clauses.push("(ac.front LIKE ? OR ac.back LIKE ? OR ac.text LIKE ?)".to_string());
+            let pattern = format!("%{}%", search_value);
+            params.push(Value::from(pattern.clone()));
+            params.push(Value::from(pattern.clone()));
+            params.push(Value::from(pattern));
         }
 
-        println!("数据库迁移 v26 -> v27 完成");
-        Ok(())
-    }
+        let where_clause = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
 
-    fn migrate_v27_to_v28(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
-        println!("开始数据库迁移 v27 -> v28: 为 mistakes 表添加 last_accessed_at 字段...");
+        let count_sql = format!(
+            "SELECT COUNT(*) FROM anki_cards ac
+             LEFT JOIN document_tasks dt ON dt.id = ac.task_id
+             {}",
+            where_clause
+        );
+        let total: i64 = conn.query_row(
+            &count_sql,
+            rusqlite::params_from_iter(params.iter()),
+            |row| row.get(0),
+        )?;
+        let total = if total < 0 { 0 } else { total as u64 };
 
-        let has_column: bool = conn
-            .query_row(
-                "SELECT COUNT(*) FROM pragma_table_info('mistakes') WHERE name='last_accessed_at'",
-                [],
-                |row| row.get::<_, i32>(0).map(|count| count > 0),
-            )
-            .unwrap_or(false);
+        let safe_page = if page == 0 { 1 } else { page };
+        let safe_page_size = page_size.clamp(1, 200);
+        let offset = (safe_page.saturating_sub(1) as i64) * (safe_page_size as i64);
 
-        if !has_column {
-            conn.execute(
-                "ALTER TABLE mistakes ADD COLUMN last_accessed_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z'",
-                [],
-            )?;
-            conn.execute(
-                "UPDATE mistakes SET last_accessed_at = updated_at WHERE last_accessed_at IS NULL OR last_accessed_at = '1970-01-01T00:00:00Z'",
-                [],
-            )?;
-            println!("已为 mistakes 表添加 last_accessed_at 列");
-        } else {
-            println!("mistakes 表已包含 last_accessed_at 列，跳过添加");
-        }
+        let mut data_params = params.clone();
+        data_params.push(Value::from(safe_page_size as i64));
+        data_params.push(Value::from(offset));
 
-        println!("数据库迁移 v27 -> v28 完成");
-        Ok(())
-    }
+        let data_sql = format!(
+            "SELECT
+                ac.id, ac.task_id, ac.front, ac.back, ac.text, ac.tags_json, ac.images_json,
+                ac.is_error_card, ac.error_content, ac.created_at, ac.updated_at,
+                COALESCE(ac.extra_fields_json, '{{}}') as extra_fields_json,
+                ac.template_id, ac.source_type, ac.source_id
+             FROM anki_cards ac
+             LEFT JOIN document_tasks dt ON dt.id = ac.task_id
+             {}
+             ORDER BY ac.created_at DESC
+             LIMIT ? OFFSET ?",
+            where_clause
+        );
 
-    fn migrate_v28_to_v29(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
-        println!("开始数据库迁移 v28 -> v29: 创建 exam_sheet_sessions 表...");
+        let mut stmt = conn.prepare(&data_sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(data_params.iter()), |row| {
+            let tags_json: String = row.get(5)?;
+            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+            let images_json: String = row.get(6)?;
+            let images: Vec<String> = serde_json::from_str(&images_json).unwrap_or_default();
+            let extra_fields_json: String = row.get(11)?;
+            let extra_fields: std::collections::HashMap<String, String> =
+                serde_json::from_str(&extra_fields_json).unwrap_or_default();
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS exam_sheet_sessions (
-                id TEXT PRIMARY KEY,
-                exam_name TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                temp_id TEXT NOT NULL,
-                status TEXT NOT NULL,
-                metadata_json TEXT NOT NULL,
-                preview_json TEXT NOT NULL,
-                linked_mistake_ids TEXT
-            )",
-            [],
-        )?;
+            let card = AnkiCard {
+                id: row.get(0)?,
+                task_id: row.get(1)?,
+                front: row.get(2)?,
+                back: row.get(3)?,
+                text: row.get(4)?,
+                tags,
+                images,
+                is_error_card: row.get::<_, i32>(7)? != 0,
+                error_content: row.get(8)?,
+                created_at: row.get(9)?,
+                updated_at: row.get(10)?,
+                extra_fields,
+                template_id: row.get(12)?,
+            };
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_exam_sheet_sessions_status
-                ON exam_sheet_sessions (status)",
-            [],
-        )?;
+            let raw_source_type: String = row.get(13)?;
+            let source_type = if raw_source_type.trim().is_empty() {
+                None
+            } else {
+                Some(raw_source_type)
+            };
+            let raw_source_id: String = row.get(14)?;
+            let source_id = if raw_source_id.trim().is_empty() {
+                None
+            } else {
+                Some(raw_source_id)
+            };
+            Ok(AnkiLibraryCard {
+                card,
+                source_type,
+                source_id,
+            })
+        })?;
 
-        println!("数据库迁移 v28 -> v29 完成");
-        Ok(())
+        let mut items = Vec::new();
+        for row in rows {
+            items.push(row?);
+        }
+
+        Ok((items, total))
     }
+}
 
-    fn migrate_v29_to_v30(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
-        println!(
-            "开始数据库迁移 v29 -> v30: 校验 exam_sheet_sessions 表的 linked_mistake_ids 列..."
-        );
+#[cfg(test)]
+mod tests {
+    use super::test_support::{
+        insert_mistake, migrated_database, migrated_database_up_to, migrated_database_with_rag,
+        upgrade_mistakes_migrations,
+    };
+    use super::*;
+    use crate::models::ChatMessage;
+    use chrono::{Duration, Utc};
+    use rusqlite::params;
+    use serde_json::json;
+    use tempfile::tempdir;
 
-        let has_column: bool = conn
-            .query_row(
-                "SELECT COUNT(*) FROM pragma_table_info('exam_sheet_sessions') WHERE name='linked_mistake_ids'",
-                [],
-                |row| row.get::<_, i32>(0).map(|count| count > 0),
-            )
-            .unwrap_or(false);
+    #[test]
+    fn append_preserves_turn_metadata_and_scoped_deletion() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db_path = dir.path().join("chat_test.db");
+        let db = Database::new(&db_path)?;
 
-        if !has_column {
+        let now = Utc::now().to_rfc3339();
+        {
+            let conn = db.get_conn_safe()?;
             conn.execute(
-                "ALTER TABLE exam_sheet_sessions ADD COLUMN linked_mistake_ids TEXT",
-                [],
+                "INSERT INTO mistakes (id, subject, created_at, question_images, analysis_images, user_question, ocr_text, tags, mistake_type, status, chat_category, updated_at, last_accessed_at)
+                 VALUES (?1, ?2, ?3, '[]', '[]', ?4, ?5, '[]', 'analysis', 'completed', 'analysis', ?3, ?3)",
+                params!["mistake-1", "math", now, "示例问题", ""],
             )?;
-            println!("已为 exam_sheet_sessions 表添加 linked_mistake_ids 列");
-        } else {
-            println!("exam_sheet_sessions 表已包含 linked_mistake_ids 列，跳过添加");
         }
 
-        println!("数据库迁移 v29 -> v30 完成");
-        Ok(())
-    }
-    */
-    // ============================================
-    // 旧版本迁移函数 (v8-v30) 结束
-    // ============================================
-}
+        let base_ts = Utc::now();
+        let turn_id = "turn-test-1";
+        let user_message = ChatMessage {
+            role: "user".to_string(),
+            content: "原始提问".to_string(),
+            timestamp: base_ts,
+            thinking_content: None,
+            thought_signature: None,
+            rag_sources: None,
+            memory_sources: None,
+            graph_sources: None,
+            web_search_sources: None,
+            image_paths: None,
+            image_base64: None,
+            doc_attachments: None,
+            tool_call: None,
+            tool_result: None,
+            overrides: None,
+            relations: Some(json!({
+                "turn_id": turn_id,
+                "turn_seq": 0,
+                "message_kind": "user.input"
+            })),
+            persistent_stable_id: Some("user-stable".to_string()),
+            metadata: None,
+            multimodal_content: None,
+        };
+        let assistant_message = ChatMessage {
+            role: "assistant".to_string(),
+            content: "助手回答".to_string(),
+            timestamp: base_ts + Duration::seconds(1),
+            thinking_content: None,
+            thought_signature: None,
+            rag_sources: None,
+            memory_sources: None,
+            graph_sources: None,
+            web_search_sources: None,
+            image_paths: None,
+            image_base64: None,
+            doc_attachments: None,
+            tool_call: None,
+            tool_result: None,
+            overrides: None,
+            relations: Some(json!({
+                "turn_id": turn_id,
+                "turn_seq": 1,
+                "message_kind": "assistant.answer",
+                "lifecycle": "complete",
+                "reply_to_msg_id": null
+            })),
+            persistent_stable_id: Some("assistant-stable".to_string()),
+            metadata: None,
+            multimodal_content: None,
+        };
 
-impl DatabaseManager {
-    // ============================================
-    // 已废弃：重复的迁移函数
-    // 新系统使用 data_governance::migration
-    // 保留代码供参考，待完全验证后删除
-    // ============================================
-    /*
-    fn migrate_v26_to_v27(&self, conn: &rusqlite::Connection) -> anyhow::Result<()> {
-        println!("开始数据库迁移 v26 -> v27: 添加题目集识别关联字段...");
+        db.append_mistake_chat_messages(
+            "mistake-1",
+            &[user_message.clone(), assistant_message.clone()],
+        )?;
 
-        let has_column: bool = conn
-            .query_row(
-                "SELECT COUNT(*) FROM pragma_table_info('mistakes') WHERE name='exam_sheet'",
-                [],
-                |row| row.get::<_, i32>(0).map(|count| count > 0),
-            )
-            .unwrap_or(false);
+        let (user_id, stored_turn_id, relations_before, turn_seq_before): (
+            i64,
+            Option<String>,
+            Option<String>,
+            Option<i64>,
+        ) = {
+            let conn = db.get_conn_safe()?;
+            conn.query_row(
+                "SELECT id, turn_id, relations, turn_seq FROM chat_messages WHERE stable_id = ?1",
+                params!["user-stable"],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )?
+        };
+        let stored_turn_id = stored_turn_id.expect("turn_id 应存在");
+        assert_eq!(turn_seq_before, Some(0));
 
-        if !has_column {
-            conn.execute("ALTER TABLE mistakes ADD COLUMN exam_sheet TEXT", [])?;
-            println!("已为 mistakes 表添加 exam_sheet 列");
-        } else {
-            println!("mistakes 表已包含 exam_sheet 列，跳过添加");
-        }
+        let updated_user = ChatMessage {
+            content: "更新后的提问".to_string(),
+            timestamp: base_ts + Duration::seconds(5),
+            persistent_stable_id: Some("user-stable".to_string()),
+            relations: None,
+            ..user_message.clone()
+        };
+        db.append_mistake_chat_messages("mistake-1", &[updated_user])?;
 
-        println!("数据库迁移 v26 -> v27 完成");
-        Ok(())
-    }
-    /// 导入包含预览数据的内置模板
-    fn import_builtin_templates_with_preview_data(
-        &self,
-        conn: &SqlitePooledConnection,
-    ) -> Result<()> {
-        println!("跳过硬编码内置模板（含预览数据）的导入，改用 JSON 导入");
-        return Ok(());
+        let (turn_id_after, relations_after, turn_seq_after): (
+            Option<String>,
+            Option<String>,
+            Option<i64>,
+        ) = {
+            let conn = db.get_conn_safe()?;
+            conn.query_row(
+                "SELECT turn_id, relations, turn_seq FROM chat_messages WHERE stable_id = ?1",
+                params!["user-stable"],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?
+        };
+        assert_eq!(turn_id_after, Some(stored_turn_id.clone()));
+        assert_eq!(relations_before, relations_after);
+        assert_eq!(turn_seq_after, Some(0));
 
-    }
-    */
-    // ============================================
-    // 重复的迁移函数结束
-    // ============================================
-}
+        let assistant_id: i64 = {
+            let conn = db.get_conn_safe()?;
+            conn.query_row(
+                "SELECT id FROM chat_messages WHERE stable_id = ?1",
+                params!["assistant-stable"],
+                |row| row.get(0),
+            )?
+        };
 
-impl Database {
-    /// 设置默认模板ID
-    pub fn set_default_template(&self, template_id: &str) -> Result<()> {
-        let conn = self.get_conn_safe()?;
-        let now = Utc::now().to_rfc3339();
+        let ids_without_user = db.list_turn_message_ids("mistake-1", &stored_turn_id, false)?;
+        assert_eq!(ids_without_user, vec![assistant_id]);
 
-        conn.execute(
-            "INSERT OR REPLACE INTO settings (key, value, updated_at) VALUES ('default_template_id', ?1, ?2)",
-            params![template_id, now]
-        )?;
+        let mut ids_with_user = db.list_turn_message_ids("mistake-1", &stored_turn_id, true)?;
+        ids_with_user.sort();
+        let mut expected_ids = vec![assistant_id, user_id];
+        expected_ids.sort();
+        assert_eq!(ids_with_user, expected_ids);
+
+        let deleted = db.delete_chat_turn("mistake-1", &stored_turn_id, false)?;
+        assert_eq!(deleted, 1);
+
+        let user_row_exists: i64 = {
+            let conn = db.get_conn_safe()?;
+            conn.query_row(
+                "SELECT COUNT(1) FROM chat_messages WHERE id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )?
+        };
+        assert_eq!(user_row_exists, 1);
+
+        let assistant_row_exists: i64 = {
+            let conn = db.get_conn_safe()?;
+            conn.query_row(
+                "SELECT COUNT(1) FROM chat_messages WHERE id = ?1",
+                params![assistant_id],
+                |row| row.get(0),
+            )?
+        };
+        assert_eq!(assistant_row_exists, 0);
 
         Ok(())
     }
 
-    /// 获取默认模板ID
-    pub fn get_default_template(&self) -> Result<Option<String>> {
-        let conn = self.get_conn_safe()?;
-
-        match conn.query_row(
-            "SELECT value FROM settings WHERE key = 'default_template_id'",
-            [],
-            |row| row.get::<_, String>(0),
-        ) {
-            Ok(template_id) => Ok(Some(template_id)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e.into()),
+    fn sample_pdf_attachment() -> crate::models::DocumentAttachment {
+        crate::models::DocumentAttachment {
+            name: "notes.pdf".to_string(),
+            mime_type: "application/pdf".to_string(),
+            size_bytes: 12,
+            text_content: Some("提取的文本".to_string()),
+            base64_content: Some("JVBERi0xLjQK".to_string()),
+            content_hash: None,
         }
     }
 
-    /// 记录搜索日志
-    pub fn log_search(
-        &self,
-        query: &str,
-        search_type: &str,
-        results_count: u32,
-        response_time_ms: Option<u64>,
-    ) -> Result<()> {
-        let conn = self.get_conn_safe()?;
-        conn.execute(
-            "INSERT INTO search_logs (query, search_type, results_count, response_time_ms, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![
-                query,
-                search_type,
-                results_count,
-                response_time_ms,
-                chrono::Utc::now().to_rfc3339()
-            ],
-        )?;
+    #[test]
+    fn doc_attachment_payloads_are_stored_once_and_rehydrated() -> anyhow::Result<()> {
+        let conn = Connection::open_in_memory()?;
+        ensure_doc_attachment_blobs_table(&conn)?;
+
+        let original = vec![sample_pdf_attachment()];
+        let first = store_doc_attachment_payloads(&conn, &original)?;
+        let second = store_doc_attachment_payloads(&conn, &original)?;
+
+        let blob_count: i64 =
+            conn.query_row("SELECT COUNT(*) FROM doc_attachment_blobs", [], |row| {
+                row.get(0)
+            })?;
+        assert_eq!(blob_count, 1);
+        assert!(first[0].base64_content.is_none() && first[0].text_content.is_none());
+        assert_eq!(first[0].content_hash, second[0].content_hash);
+
+        // 去重前后的摘要指纹必须一致，否则增量保存会把同一条消息当作变更
+        let before = canonicalize_doc_attachments_summary(Some(serde_json::to_string(&original)?));
+        let after = canonicalize_doc_attachments_summary(Some(serde_json::to_string(&first)?));
+        assert_eq!(before, after);
+
+        let mut rehydrated = first.clone();
+        rehydrate_doc_attachments(&conn, &mut rehydrated)?;
+        assert_eq!(rehydrated[0].text_content, original[0].text_content);
+        assert_eq!(rehydrated[0].base64_content, original[0].base64_content);
+
+        // 旧版消息读取路径直接回填 JSON
+        let json = rehydrate_doc_attachments_json(&conn, serde_json::to_string(&first)?)?;
+        let from_json: Vec<crate::models::DocumentAttachment> = serde_json::from_str(&json)?;
+        assert_eq!(from_json[0].base64_content, original[0].base64_content);
         Ok(())
     }
 
-    /// 获取搜索日志统计
-    pub fn get_search_statistics(&self) -> Result<SearchStatistics> {
-        let conn = self.get_conn_safe()?;
-
-        // 获取总搜索次数
-        let total_searches: i64 =
-            conn.query_row("SELECT COUNT(*) FROM search_logs", [], |row| row.get(0))?;
-
-        // 获取最近7天的搜索次数
-        let recent_searches: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM search_logs
-             WHERE created_at >= datetime('now', '-7 days')",
-            [],
-            |row| row.get(0),
-        )?;
-
-        // 获取平均响应时间
-        let avg_response_time: Option<f64> = conn
-            .query_row(
-                "SELECT AVG(response_time_ms) FROM search_logs
-             WHERE response_time_ms IS NOT NULL",
-                [],
-                |row| row.get(0),
-            )
-            .optional()?;
-
-        // 获取搜索类型分布
-        let mut search_type_distribution = std::collections::HashMap::new();
-        let mut stmt = conn.prepare(
-            "SELECT search_type, COUNT(*) as count
-             FROM search_logs
-             GROUP BY search_type",
-        )?;
-        let type_iter = stmt.query_map([], |row| {
-            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
-        })?;
-        for type_result in type_iter {
-            let (search_type, count) = type_result?;
-            search_type_distribution.insert(search_type, count);
+    #[test]
+    fn rebuild_turn_metadata_batch_pairs_legacy_rows() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        let now = "2026-01-01T00:00:00Z";
+        {
+            let conn = db.get_conn_safe()?;
+            for id in ["m-a", "m-b", "m-c"] {
+                insert_mistake(&conn, id, &[("updated_at", &now)])?;
+            }
+            conn.execute_batch(
+                "INSERT INTO chat_messages (mistake_id, role, content, timestamp) VALUES
+                    ('m-a', 'user', 'q1', '2026-01-01T00:00:01Z'),
+                    ('m-a', 'assistant', 'a1', '2026-01-01T00:00:02Z'),
+                    ('m-a', 'assistant', 'stray', '2026-01-01T00:00:03Z');
+                 INSERT INTO chat_messages (mistake_id, role, content, timestamp, turn_id) VALUES
+                    ('m-b', 'user', 'q', '2026-01-01T00:00:01Z', 'turn-b'),
+                    ('m-b', 'assistant', 'a', '2026-01-01T00:00:02Z', 'turn-b');",
+            )?;
         }
 
-        // 获取热门搜索查询
-        let mut popular_queries = Vec::new();
-        let mut stmt = conn.prepare(
-            "SELECT query, COUNT(*) as count
-             FROM search_logs
-             GROUP BY query
-             ORDER BY count DESC
-             LIMIT 10",
+        let first = db.rebuild_turn_metadata_batch(None, 2)?;
+        assert_eq!(
+            first
+                .iter()
+                .map(|f| f.mistake_id.as_str())
+                .collect::<Vec<_>>(),
+            ["m-a", "m-b"]
+        );
+        assert_eq!(
+            (first[0].users_assigned, first[0].assistants_paired),
+            (1, 1)
+        );
+        assert_eq!(first[0].orphaned, 1);
+        assert_eq!((first[1].turn_seq_fixed, first[1].fixed()), (2, 2));
+
+        let rest = db.rebuild_turn_metadata_batch(Some("m-b"), 2)?;
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].fixed(), 0);
+
+        // 已修复的数据再次重建不再变化，错题的 updated_at 保持不变
+        let again = db.rebuild_turn_metadata_batch(None, 10)?;
+        assert!(again.iter().all(|f| f.fixed() == 0));
+        let conn = db.get_conn_safe()?;
+        let seqs: Vec<(String, i64)> = conn
+            .prepare(
+                "SELECT role, turn_seq FROM chat_messages WHERE turn_id = 'turn-b' ORDER BY id",
+            )?
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<std::result::Result<_, _>>()?;
+        assert_eq!(
+            seqs,
+            [("user".to_string(), 0), ("assistant".to_string(), 1)]
+        );
+        let updated_at: String = conn.query_row(
+            "SELECT updated_at FROM mistakes WHERE id = 'm-a'",
+            [],
+            |r| r.get(0),
         )?;
-        let query_iter = stmt.query_map([], |row| {
-            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
-        })?;
-        for query_result in query_iter {
-            popular_queries.push(query_result?);
-        }
-
-        Ok(SearchStatistics {
-            total_searches,
-            recent_searches,
-            avg_response_time_ms: avg_response_time.unwrap_or(0.0),
-            search_type_distribution,
-            popular_queries,
-        })
+        assert_eq!(updated_at, now);
+        Ok(())
     }
 
-    /// 获取最近的文档任务（用于状态恢复）
-    pub fn get_recent_document_tasks(&self, limit: u32) -> Result<Vec<DocumentTask>> {
-        let conn = self.get_conn_safe()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, document_id, original_document_name, segment_index, content_segment,
-                    status, created_at, updated_at, error_message, anki_generation_options_json
-             FROM document_tasks
-             ORDER BY updated_at DESC
-             LIMIT ?",
-        )?;
+    #[test]
+    fn gc_orphan_attachments_keeps_referenced_payloads() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            insert_mistake(&conn, "m1", &[])?;
+            let kept = store_doc_attachment_payloads(&conn, &[sample_pdf_attachment()])?;
+            let orphan = crate::models::DocumentAttachment {
+                text_content: Some("已删除消息的附件".to_string()),
+                ..sample_pdf_attachment()
+            };
+            store_doc_attachment_payloads(&conn, &[orphan])?;
+            conn.execute(
+                "INSERT INTO chat_messages (mistake_id, role, content, timestamp, doc_attachments)
+                 VALUES ('m1', 'user', '', '2026-01-01T00:00:00Z', ?1)",
+                params![serde_json::to_string(&kept)?],
+            )?;
+        }
 
-        let tasks = stmt
-            .query_map([limit], |row| {
-                let status_str: String = row.get(5)?;
-                Ok(DocumentTask {
-                    id: row.get(0)?,
-                    document_id: row.get(1)?,
-                    original_document_name: row.get(2)?,
-                    segment_index: row.get(3)?,
-                    content_segment: row.get(4)?,
-                    status: TaskStatus::from_str(&status_str),
-                    created_at: row.get(6)?,
-                    updated_at: row.get(7)?,
-                    error_message: row.get(8)?,
-                    anki_generation_options_json: row.get(9)?,
-                })
-            })?
-            .collect::<rusqlite::Result<Vec<DocumentTask>>>()?;
+        assert_eq!(db.gc_orphan_attachments()?, 1);
+        assert_eq!(db.gc_orphan_attachments()?, 0);
 
-        Ok(tasks)
+        let docs = db
+            .get_chat_message_doc_attachments(1)?
+            .expect("消息应包含附件");
+        assert_eq!(docs[0].base64_content.as_deref(), Some("JVBERi0xLjQK"));
+        Ok(())
     }
 
-    /// 🔧 Phase 1: 恢复卡住的制卡任务
-    /// 将 Processing/Streaming 状态超过 1 小时的任务重置为 Pending
-    pub fn recover_stuck_document_tasks(&self) -> Result<u32> {
-        let conn = self.get_conn_safe()?;
-        let count = conn.execute(
-            r#"UPDATE document_tasks
-               SET status = 'Pending', error_message = 'Recovered after app restart', updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
-               WHERE status IN ('Processing', 'Streaming')
-               AND updated_at < datetime('now', '-1 hour')"#,
-            [],
-        )?;
-        Ok(count as u32)
+    #[test]
+    fn autosave_skips_only_snapshots_matching_the_last_write() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        let user_message = |stable_id: &str, turn_id: &str| ChatMessage {
+            role: "user".to_string(),
+            content: format!("提问 {}", turn_id),
+            timestamp: Utc::now(),
+            thinking_content: None,
+            thought_signature: None,
+            rag_sources: None,
+            memory_sources: None,
+            graph_sources: None,
+            web_search_sources: None,
+            image_paths: None,
+            image_base64: None,
+            doc_attachments: None,
+            tool_call: None,
+            tool_result: None,
+            overrides: None,
+            relations: Some(json!({ "turn_id": turn_id, "turn_seq": 0 })),
+            persistent_stable_id: Some(stable_id.to_string()),
+            metadata: None,
+            multimodal_content: None,
+        };
+        let snapshot = vec![user_message("u1", "t1")];
+
+        assert!(db
+            .append_mistake_chat_messages_if_changed("m1", &snapshot, None)?
+            .is_some());
+        assert!(db
+            .append_mistake_chat_messages_if_changed("m1", &snapshot, None)?
+            .is_none());
+
+        // 其他路径写入或删除后，同一快照不能再被当作未变化
+        db.append_mistake_chat_messages("m1", &[user_message("u2", "t2")])?;
+        assert!(db
+            .append_mistake_chat_messages_if_changed("m1", &snapshot, None)?
+            .is_some());
+        db.delete_chat_turn("m1", "t1", true)?;
+        assert!(db
+            .append_mistake_chat_messages_if_changed("m1", &snapshot, None)?
+            .is_some());
+        assert_eq!(db.get_chat_message_ids("m1")?.len(), 2);
+        Ok(())
     }
 
-    /// 🔧 Phase 1: 按 document_id 分组汇总任务信息（用于任务管理页面）
-    pub fn list_document_sessions(&self, limit: u32) -> Result<Vec<serde_json::Value>> {
-        let conn = self.get_conn_safe()?;
-        // 确保 source_session_id 列存在（兼容旧数据库）
-        let _ = conn.execute(
-            "ALTER TABLE document_tasks ADD COLUMN source_session_id TEXT",
-            [],
-        );
-        // 使用 LEFT JOIN + COUNT(DISTINCT) 代替关联子查询，提升大数据量下的性能
-        let mut stmt = conn.prepare(
-            r#"SELECT
-                 dt.document_id,
-                 dt.original_document_name,
-                 dt.source_session_id,
-                 COUNT(DISTINCT dt.id) AS total_tasks,
-                 COUNT(DISTINCT CASE WHEN dt.status = 'Completed' THEN dt.id END) AS completed_tasks,
-                 COUNT(DISTINCT CASE WHEN dt.status IN ('Failed', 'Truncated') THEN dt.id END) AS failed_tasks,
-                 COUNT(DISTINCT CASE WHEN dt.status IN ('Processing', 'Streaming', 'Pending') THEN dt.id END) AS active_tasks,
-                 COUNT(DISTINCT CASE WHEN dt.status = 'Paused' THEN dt.id END) AS paused_tasks,
-                 MAX(dt.updated_at) AS last_updated,
-                 MIN(dt.created_at) AS created_at,
-                 COUNT(DISTINCT ac.id) AS total_cards
-               FROM document_tasks dt
-               LEFT JOIN anki_cards ac ON ac.task_id = dt.id
-               GROUP BY dt.document_id
-               ORDER BY MAX(dt.updated_at) DESC
-               LIMIT ?1"#,
-        )?;
+    #[test]
+    fn pinned_mistakes_are_exempt_from_auto_archive() -> anyhow::Result<()> {
+        use crate::batch_operations::BatchOperationExt;
 
-        let rows = stmt
-            .query_map([limit], |row| {
-                Ok(serde_json::json!({
-                    "documentId": row.get::<_, String>(0)?,
-                    "documentName": row.get::<_, String>(1)?,
-                    "sourceSessionId": row.get::<_, Option<String>>(2)?,
-                    "totalTasks": row.get::<_, i64>(3)?,
-                    "completedTasks": row.get::<_, i64>(4)?,
-                    "failedTasks": row.get::<_, i64>(5)?,
-                    "activeTasks": row.get::<_, i64>(6)?,
-                    "pausedTasks": row.get::<_, i64>(7)?,
-                    "lastUpdated": row.get::<_, String>(8)?,
-                    "createdAt": row.get::<_, String>(9)?,
-                    "totalCards": row.get::<_, i64>(10)?,
-                }))
-            })?
-            .collect::<rusqlite::Result<Vec<serde_json::Value>>>()?;
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        let old = (Utc::now() - Duration::days(90)).to_rfc3339();
+        {
+            let conn = db.get_conn_safe()?;
+            for id in ["m-pinned", "m-plain"] {
+                insert_mistake(&conn, id, &[("created_at", &old), ("updated_at", &old)])?;
+            }
+        }
 
-        Ok(rows)
-    }
+        db.set_mistake_pin("m-pinned", true, Some(5))?;
+        assert!(db.set_mistake_pin("m-plain", false, Some(6)).is_err());
+        assert_eq!(db.get_mistake_pin("m-pinned")?, Some((true, Some(5))));
+        assert_eq!(db.get_mistake_pin("m-plain")?, Some((false, None)));
 
-    /// 🔧 Phase 2: 卡片库统计数据（用于任务管理页面统计卡片）
-    pub fn get_anki_stats(&self) -> Result<serde_json::Value> {
-        let conn = self.get_conn_safe()?;
-        let total_cards: i64 =
-            conn.query_row("SELECT COUNT(*) FROM anki_cards", [], |r| r.get(0))?;
-        let total_tasks: i64 = conn.query_row(
-            "SELECT COUNT(DISTINCT document_id) FROM document_tasks",
-            [],
-            |r| r.get(0),
-        )?;
-        let error_cards: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM anki_cards WHERE is_error_card = 1",
-            [],
-            |r| r.get(0),
-        )?;
-        let template_count: i64 = conn.query_row(
-            "SELECT COUNT(DISTINCT template_id) FROM anki_cards WHERE template_id IS NOT NULL AND template_id != ''",
+        let archived = db.with_batch_operations(|ops| ops.batch_archive_old_mistakes(30))?;
+        assert_eq!(archived, 1);
+        let status: String = db.get_conn_safe()?.query_row(
+            "SELECT status FROM mistakes WHERE id = 'm-pinned'",
             [],
-            |r| r.get(0),
+            |row| row.get(0),
         )?;
-        Ok(serde_json::json!({
-            "totalCards": total_cards,
-            "totalDocuments": total_tasks,
-            "errorCards": error_cards,
-            "templateCount": template_count,
-        }))
+        assert_eq!(status, "active");
+        assert!(db.is_mistake_archived("m-plain")?);
+        Ok(())
     }
 
-    /// 获取最近生成的Anki卡片（用于状态恢复）
-    pub fn get_recent_anki_cards(&self, limit: u32) -> Result<Vec<AnkiCard>> {
-        let conn = self.get_conn_safe()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, task_id, front, back, text, tags_json, images_json,
-                    is_error_card, error_content, created_at, updated_at,
-                    COALESCE(extra_fields_json, '{}') as extra_fields_json, template_id
-             FROM anki_cards
-             ORDER BY created_at DESC
-             LIMIT ?",
+    #[test]
+    fn pinned_mistakes_are_listed_first() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            for (id, updated_at) in [("m-old", "2026-01-01"), ("m-new", "2026-02-01")] {
+                insert_mistake(
+                    &conn,
+                    id,
+                    &[("user_question", &"受力分析"), ("updated_at", &updated_at)],
+                )?;
+            }
+        }
+        db.set_mistake_pin("m-old", true, Some(3))?;
+        // 置顶会刷新 updated_at，这里改回去以确认排序来自置顶本身
+        db.get_conn_safe()?
+            .execute_batch("UPDATE mistakes SET updated_at = '2026-01-01' WHERE id = 'm-old'")?;
+
+        let page = db.search_mistakes_fulltext("受力", None, None, &[], TagMatchMode::Any)?;
+        let ids: Vec<_> = page.items.iter().map(|hit| hit.id.as_str()).collect();
+        assert_eq!(ids, vec!["m-old", "m-new"]);
+        assert!(page.items[0].pinned && !page.items[1].pinned);
+        assert_eq!(page.items[0].star_rating, Some(3));
+
+        db.archive_mistake("m-old")?;
+        db.archive_mistake("m-new")?;
+        db.get_conn_safe()?.execute_batch(
+            "UPDATE mistakes SET archived_at = '2026-03-01' WHERE id = 'm-old';
+             UPDATE mistakes SET archived_at = '2026-03-02' WHERE id = 'm-new';",
         )?;
+        let archived = db.list_archived_mistakes(None, None)?;
+        let ids: Vec<_> = archived.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["m-old", "m-new"]);
+        Ok(())
+    }
 
-        let cards = stmt
-            .query_map([limit], |row| {
-                let tags_json: String = row.get(5)?;
-                let images_json: String = row.get(6)?;
-                let extra_fields_json: String = row.get(12)?;
+    #[test]
+    fn subject_default_templates_round_trip() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+
+        assert!(db.get_subject_default_templates()?.is_empty());
+        db.set_subject_default_template("数学", Some("tpl-math"))?;
+        db.set_subject_default_template(" 英语 ", Some("tpl-lang"))?;
+        let mapping = db.get_subject_default_templates()?;
+        assert_eq!(mapping.get("数学").map(String::as_str), Some("tpl-math"));
+        assert_eq!(mapping.get("英语").map(String::as_str), Some("tpl-lang"));
+
+        db.set_subject_default_template("数学", None)?;
+        assert!(!db.get_subject_default_templates()?.contains_key("数学"));
+        assert!(db
+            .set_subject_default_template("  ", Some("tpl-x"))
+            .is_err());
+        Ok(())
+    }
 
-                Ok(AnkiCard {
-                    id: row.get(0)?,
-                    task_id: row.get(1)?,
-                    front: row.get(2)?,
-                    back: row.get(3)?,
-                    text: row.get(4)?,
-                    tags: serde_json::from_str(&tags_json).unwrap_or_default(),
-                    images: serde_json::from_str(&images_json).unwrap_or_default(),
-                    is_error_card: row.get::<_, i32>(7)? != 0,
-                    error_content: row.get(8)?,
-                    created_at: row.get(9)?,
-                    updated_at: row.get(10)?,
-                    extra_fields: serde_json::from_str(&extra_fields_json).unwrap_or_default(),
-                    template_id: row.get(13)?,
-                })
-            })?
-            .collect::<rusqlite::Result<Vec<AnkiCard>>>()?;
+    #[test]
+    fn archive_and_unarchive_mistakes() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            for id in ["m1", "m2", "m3"] {
+                insert_mistake(&conn, id, &[("tags", &r#"["代数"]"#)])?;
+            }
+            // 回收站中的错题不能归档，也不计入统计
+            insert_mistake(
+                &conn,
+                "m-trashed",
+                &[("deleted_at", &"2026-01-01T00:00:00Z")],
+            )?;
+            insert_mistake(
+                &conn,
+                "m-trashed-archived",
+                &[
+                    ("deleted_at", &"2026-01-01T00:00:00Z"),
+                    ("archived_at", &"2026-01-01T00:00:00Z"),
+                ],
+            )?;
+        }
 
-        Ok(cards)
+        assert_eq!(db.batch_archive_mistakes(&["m-trashed".to_string()])?, 0);
+        db.archive_mistake("m1")?;
+        db.archive_mistake("m1")?;
+        assert!(db.archive_mistake("missing").is_err());
+        let ids = vec!["m1".to_string(), "m2".to_string(), "missing".to_string()];
+        assert_eq!(db.batch_archive_mistakes(&ids)?, 1);
+
+        let archived = db.list_archived_mistakes(None, None)?;
+        assert_eq!(archived.len(), 2);
+        assert_eq!(archived[0].tags, vec!["代数".to_string()]);
+        assert_eq!(
+            db.get_mistake_archive_counts()?,
+            MistakeArchiveCounts {
+                active: 1,
+                archived: 2,
+                total: 3
+            }
+        );
+
+        db.unarchive_mistake("m1")?;
+        assert!(!db.is_mistake_archived("m1")?);
+        assert_eq!(db.get_mistake_archive_counts()?.archived, 1);
+        Ok(())
     }
 
-    pub fn list_anki_library_cards(
-        &self,
-        _subject: Option<&str>,
-        template_id: Option<&str>,
-        search: Option<&str>,
-        page: u32,
-        page_size: u32,
-    ) -> Result<(Vec<AnkiLibraryCard>, u64)> {
-        let conn = self.get_conn_safe()?;
-        let mut clauses: Vec<String> = Vec::new();
-        let mut params: Vec<Value> = Vec::new();
+    #[test]
+    fn mistake_user_notes_are_stored_and_searchable() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            insert_mistake(&conn, "m1", &[("user_question", &"求导")])?;
+            insert_mistake(
+                &conn,
+                "m2",
+                &[
+                    ("user_question", &"链式法则求导"),
+                    ("updated_at", &"2026-01-02T00:00:00Z"),
+                ],
+            )?;
+        }
 
-        if let Some(template_value) = template_id
-            .map(|s| s.trim())
-            .filter(|value| !value.is_empty())
+        db.set_mistake_user_notes("m1", Some("  讲评时强调 100% 换元  "))?;
+        assert_eq!(
+            db.get_mistake_user_notes("m1")?.flatten().as_deref(),
+            Some("讲评时强调 100% 换元")
+        );
+        assert!(db.set_mistake_user_notes("missing", Some("x")).is_err());
+        assert_eq!(db.get_mistake_user_notes("missing")?, None);
+
+        let hits = db
+            .search_mistakes_fulltext("换元", None, None, &[], TagMatchMode::All)?
+            .items;
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].matched_user_notes);
+        // % 按字面匹配
+        assert_eq!(
+            db.search_mistakes_fulltext("100%", None, None, &[], TagMatchMode::All)?
+                .total,
+            1
+        );
+        let hits = db
+            .search_mistakes_fulltext("求导", None, None, &[], TagMatchMode::All)?
+            .items;
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|h| !h.matched_user_notes));
+
+        db.set_mistake_user_notes("m1", Some("   "))?;
+        assert_eq!(db.get_mistake_user_notes("m1")?, Some(None));
+        Ok(())
+    }
+
+    #[test]
+    fn mistake_search_filters_by_tags_with_and_or() -> anyhow::Result<()> {
+        let seed = |db: &Database| -> anyhow::Result<()> {
+            let conn = db.get_conn_safe()?;
+            for (id, question, tags, deleted_at) in [
+                ("m1", "求导", r#"["代数", " 函数 "]"#, None),
+                ("m2", "求导", r#"["代数"]"#, None),
+                ("m3", "几何", r#"["函数"]"#, None),
+                (
+                    "m4",
+                    "求导",
+                    r#"["代数", "函数"]"#,
+                    Some("2026-02-01T00:00:00Z"),
+                ),
+                ("m5", "求导", "not json", None),
+            ] {
+                let updated_at = format!("2026-01-0{}T00:00:00Z", &id[1..]);
+                insert_mistake(
+                    &conn,
+                    id,
+                    &[
+                        ("user_question", &question),
+                        ("tags", &tags),
+                        ("deleted_at", &deleted_at),
+                        ("updated_at", &updated_at),
+                    ],
+                )?;
+            }
+            Ok(())
+        };
+        let tags = vec!["代数".to_string(), "函数 ".to_string(), "代数".to_string()];
+        let ids = |page: MistakeSearchPage| {
+            assert_eq!(page.total, page.items.len() as u64);
+            let mut ids: Vec<String> = page.items.into_iter().map(|h| h.id).collect();
+            ids.sort();
+            ids
+        };
+        let check = |db: &Database| -> anyhow::Result<()> {
+            assert_eq!(
+                ids(db.search_mistakes_fulltext("", None, None, &tags, TagMatchMode::All)?),
+                vec!["m1"]
+            );
+            assert_eq!(
+                ids(db.search_mistakes_fulltext("", None, None, &tags, TagMatchMode::Any)?),
+                vec!["m1", "m2", "m3"]
+            );
+            // 关键词与标签同时生效
+            assert_eq!(
+                ids(db.search_mistakes_fulltext("求导", None, None, &tags, TagMatchMode::Any)?),
+                vec!["m1", "m2"]
+            );
+            // 空标签不筛选
+            let blank = vec!["  ".to_string()];
+            assert_eq!(
+                ids(db.search_mistakes_fulltext("求导", None, None, &blank, TagMatchMode::All)?),
+                vec!["m1", "m2", "m5"]
+            );
+            assert_eq!(
+                db.search_mistakes_fulltext("", None, None, &blank, TagMatchMode::Any)?
+                    .total,
+                0
+            );
+            // 分页不影响总数
+            let page =
+                db.search_mistakes_fulltext("", Some(1), Some(1), &tags, TagMatchMode::Any)?;
+            assert_eq!(page.total, 3);
+            assert_eq!(page.items.len(), 1);
+            assert_eq!(page.items[0].id, "m2");
+            Ok(())
+        };
+
+        // 完整迁移后的库：标签索引由触发器维护
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        seed(&db)?;
+        check(&db)?;
         {
-            clauses.push("ac.template_id = ?".to_string());
-            params.push(Value::from(template_value.to_string()));
+            let conn = db.get_conn_safe()?;
+            conn.execute(
+                "UPDATE mistakes SET tags = '[\"几何\"]' WHERE id = 'm1'",
+                [],
+            )?;
+            insert_mistake(
+                &conn,
+                "m6",
+                &[("user_question", &"极限"), ("tags", &r#"["代数", "函数"]"#)],
+            )?;
+            conn.execute("DELETE FROM mistakes WHERE id = 'm2'", [])?;
         }
+        assert_eq!(
+            ids(db.search_mistakes_fulltext("", None, None, &tags, TagMatchMode::All)?),
+            vec!["m6"]
+        );
+        assert_eq!(
+            ids(db.search_mistakes_fulltext("", None, None, &tags, TagMatchMode::Any)?),
+            vec!["m3", "m6"]
+        );
 
-        if let Some(search_value) = search.map(|s| s.trim()).filter(|value| !value.is_empty()) {
-            clauses.push("(ac.front LIKE ? OR ac.back LIKE ? OR ac.text LIKE ?)".to_string());
-            let pattern = format!("%{}%", search_value);
-            params.push(Value::from(pattern.clone()));
-            params.push(Value::from(pattern.clone()));
-            params.push(Value::from(pattern));
+        // 标签索引迁移之前的旧库逐行解析 tags JSON，升级后由迁移回填索引
+        let legacy_dir = tempdir()?;
+        let legacy = migrated_database_up_to(legacy_dir.path(), 20260220)?;
+        seed(&legacy)?;
+        check(&legacy)?;
+        upgrade_mistakes_migrations(&legacy.get_conn_safe()?, 20260220)?;
+        check(&legacy)?;
+        Ok(())
+    }
+
+    #[test]
+    fn review_analysis_deletion_removes_messages_and_skips_streaming() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            // 关闭外键，确认消息删除不依赖级联
+            conn.pragma_update(None, "foreign_keys", "OFF")?;
+            for (id, status) in [
+                ("r1", "completed"),
+                ("r2", "completed"),
+                ("live", "analyzing"),
+            ] {
+                conn.execute(
+                    "INSERT INTO review_analyses (id, name, created_at, updated_at, mistake_ids,
+                        consolidated_input, user_question, status, tags)
+                     VALUES (?1, ?1, '2026-01-01T00:00:00Z', '2026-01-01T00:00:00Z', '[]', '', '', ?2, '[]')",
+                    params![id, status],
+                )?;
+            }
+            for analysis_id in ["r1", "r1", "r2", "live"] {
+                conn.execute(
+                    "INSERT INTO review_chat_messages (review_analysis_id, role, content, timestamp)
+                     VALUES (?1, 'user', '', '2026-01-01T00:00:00Z')",
+                    params![analysis_id],
+                )?;
+            }
         }
+        let ids = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
 
-        let where_clause = if clauses.is_empty() {
-            String::new()
-        } else {
-            format!("WHERE {}", clauses.join(" AND "))
-        };
+        // 含生成中的分析时整体拒绝
+        assert!(db.delete_review_analyses(&ids(&["r1", "live"])).is_err());
+        assert!(db.archive_review_analysis("live").is_err());
 
-        let count_sql = format!(
-            "SELECT COUNT(*) FROM anki_cards ac
-             LEFT JOIN document_tasks dt ON dt.id = ac.task_id
-             {}",
-            where_clause
+        let deletion = db.delete_review_analyses(&ids(&["r1", "r2", "r1", "missing"]))?;
+        assert_eq!(
+            deletion,
+            ReviewAnalysisDeletion {
+                deleted_analyses: 2,
+                deleted_messages: 3,
+            }
         );
-        let total: i64 = conn.query_row(
-            &count_sql,
-            rusqlite::params_from_iter(params.iter()),
+        let remaining: i64 = db.get_conn_safe()?.query_row(
+            "SELECT COUNT(*) FROM review_chat_messages",
+            [],
             |row| row.get(0),
         )?;
-        let total = if total < 0 { 0 } else { total as u64 };
+        assert_eq!(remaining, 1);
 
-        let safe_page = if page == 0 { 1 } else { page };
-        let safe_page_size = page_size.clamp(1, 200);
-        let offset = (safe_page.saturating_sub(1) as i64) * (safe_page_size as i64);
+        db.get_conn_safe()?.execute(
+            "UPDATE review_analyses SET status = 'completed' WHERE id = 'live'",
+            [],
+        )?;
+        db.archive_review_analysis("live")?;
+        let archived: bool = db.get_conn_safe()?.query_row(
+            "SELECT archived_at IS NOT NULL FROM review_analyses WHERE id = 'live'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert!(archived);
+        db.unarchive_review_analysis("live")?;
+        assert!(db.archive_review_analysis("missing").is_err());
+        Ok(())
+    }
 
-        let mut data_params = params.clone();
-        data_params.push(Value::from(safe_page_size as i64));
-        data_params.push(Value::from(offset));
+    #[test]
+    fn move_documents_to_sub_library_is_atomic() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database_with_rag(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            conn.execute_batch(
+                "INSERT INTO rag_sub_libraries (id, name) VALUES ('physics', 'physics');
+                 INSERT INTO rag_documents (id, file_name, sub_library_id, created_at, updated_at) VALUES
+                    ('d1', 'a.pdf', 'default', '', ''), ('d2', 'b.pdf', 'default', '', ''),
+                    ('d3', 'c.pdf', 'physics', '', '');",
+            )?;
+        }
+        let ids = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert!(db
+            .move_documents_to_sub_library(&ids(&["d1"]), "missing")
+            .is_err());
+        // 任一文档不存在时整体回滚
+        assert!(db
+            .move_documents_to_sub_library(&ids(&["d1", "nope"]), "physics")
+            .is_err());
+        let d1_library: String = db.get_conn_safe()?.query_row(
+            "SELECT sub_library_id FROM rag_documents WHERE id = 'd1'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(d1_library, "default");
 
-        let data_sql = format!(
-            "SELECT
-                ac.id, ac.task_id, ac.front, ac.back, ac.text, ac.tags_json, ac.images_json,
-                ac.is_error_card, ac.error_content, ac.created_at, ac.updated_at,
-                COALESCE(ac.extra_fields_json, '{{}}') as extra_fields_json,
-                ac.template_id, ac.source_type, ac.source_id
-             FROM anki_cards ac
-             LEFT JOIN document_tasks dt ON dt.id = ac.task_id
-             {}
-             ORDER BY ac.created_at DESC
-             LIMIT ? OFFSET ?",
-            where_clause
+        let moved = db.move_documents_to_sub_library(&ids(&["d1", "d2", "d3"]), "physics")?;
+        assert_eq!(moved, ids(&["d1", "d2"]));
+        let in_physics: i64 = db.get_conn_safe()?.query_row(
+            "SELECT COUNT(*) FROM rag_documents WHERE sub_library_id = 'physics'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(in_physics, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn sub_library_index_stats_are_grouped_per_library() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database_with_rag(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            conn.execute_batch(
+                "UPDATE rag_sub_libraries SET name = '默认', updated_at = '2026-01-01T00:00:00Z'
+                    WHERE id = 'default';
+                 INSERT INTO rag_sub_libraries (id, name, updated_at)
+                    VALUES ('physics', 'physics', '2026-01-02T00:00:00Z');
+                 INSERT INTO rag_documents (id, file_name, sub_library_id, file_size, update_state,
+                    update_retry, created_at, updated_at) VALUES
+                    ('d1', 'a.pdf', 'physics', 100, 'ready', 0, '', '2026-03-01T00:00:00Z'),
+                    ('d2', 'b.pdf', 'physics', NULL, 'ready', 2, '', '2026-02-01T00:00:00Z'),
+                    ('d3', 'c.pdf', 'physics', 5, 'updating', 0, '', '2026-01-05T00:00:00Z');
+                 INSERT INTO rag_document_chunks (id, document_id, chunk_index, text) VALUES
+                    ('c1', 'd1', 0, 'abc'), ('c2', 'd1', 1, '力学'), ('c3', 'd2', 0, 'x');",
+            )?;
+        }
+
+        let stats = db.list_sub_library_index_stats()?;
+        assert_eq!(
+            stats
+                .iter()
+                .map(|s| s.library_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["physics", "default"]
+        );
+        let physics = &stats[0];
+        assert_eq!((physics.document_count, physics.chunk_count), (3, 3));
+        assert_eq!(physics.document_bytes, 105);
+        assert_eq!(physics.chunk_text_bytes, 3 + 6 + 1);
+        assert_eq!(physics.pending_documents, 2);
+        assert_eq!(
+            physics.last_updated.as_deref(),
+            Some("2026-03-01T00:00:00Z")
         );
+        let empty = &stats[1];
+        assert_eq!((empty.document_count, empty.chunk_count), (0, 0));
+        assert_eq!(empty.last_updated.as_deref(), Some("2026-01-01T00:00:00Z"));
+        Ok(())
+    }
 
-        let mut stmt = conn.prepare(&data_sql)?;
-        let rows = stmt.query_map(rusqlite::params_from_iter(data_params.iter()), |row| {
-            let tags_json: String = row.get(5)?;
-            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
-            let images_json: String = row.get(6)?;
-            let images: Vec<String> = serde_json::from_str(&images_json).unwrap_or_default();
-            let extra_fields_json: String = row.get(11)?;
-            let extra_fields: std::collections::HashMap<String, String> =
-                serde_json::from_str(&extra_fields_json).unwrap_or_default();
+    #[test]
+    fn sub_library_deletion_stats_match_deleted_content() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database_with_rag(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            conn.execute_batch(
+                "INSERT INTO rag_sub_libraries (id, name) VALUES ('physics', 'physics'), ('math', 'math');
+                 INSERT INTO rag_documents (id, file_name, sub_library_id, file_size, created_at, updated_at) VALUES
+                    ('d1', 'a.pdf', 'physics', 100, '', ''), ('d2', 'b.pdf', 'physics', NULL, '', ''),
+                    ('d3', 'c.pdf', 'math', 7, '', ''), ('d4', 'd.pdf', 'default', 1, '', '');
+                 INSERT INTO rag_document_chunks (id, document_id, chunk_index, text) VALUES
+                    ('c1', 'd1', 0, 'abc'), ('c2', 'd1', 1, '力学'), ('c3', 'd2', 0, 'x'), ('c4', 'd3', 0, 'y');",
+            )?;
+        }
 
-            let card = AnkiCard {
-                id: row.get(0)?,
-                task_id: row.get(1)?,
-                front: row.get(2)?,
-                back: row.get(3)?,
-                text: row.get(4)?,
-                tags,
-                images,
-                is_error_card: row.get::<_, i32>(7)? != 0,
-                error_content: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-                extra_fields,
-                template_id: row.get(12)?,
-            };
+        assert!(db.get_sub_library_content_stats("default").is_err());
+        assert!(db.get_sub_library_content_stats("missing").is_err());
+        let stats = db.get_sub_library_content_stats("physics")?;
+        assert_eq!(stats.document_count, 2);
+        assert_eq!(stats.chunk_count, 3);
+        assert_eq!(stats.document_bytes, 100);
+        assert_eq!(stats.chunk_text_bytes, 3 + 6 + 1);
 
-            let raw_source_type: String = row.get(13)?;
-            let source_type = if raw_source_type.trim().is_empty() {
-                None
-            } else {
-                Some(raw_source_type)
-            };
-            let raw_source_id: String = row.get(14)?;
-            let source_id = if raw_source_id.trim().is_empty() {
-                None
-            } else {
-                Some(raw_source_id)
-            };
-            Ok(AnkiLibraryCard {
-                card,
-                source_type,
-                source_id,
-            })
-        })?;
+        let deleted = db.delete_sub_library("physics", true)?;
+        assert_eq!(
+            deleted.document_ids,
+            vec!["d1".to_string(), "d2".to_string()]
+        );
+        assert_eq!(deleted.deleted_chunk_count, stats.chunk_count);
+        let remaining_chunks: i64 = db.get_conn_safe()?.query_row(
+            "SELECT COUNT(*) FROM rag_document_chunks",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(remaining_chunks, 1);
+
+        // 保留文档时移到默认分库，分块不动
+        let moved = db.delete_sub_library("math", false)?;
+        assert_eq!(moved.document_ids, vec!["d3".to_string()]);
+        assert_eq!(moved.deleted_chunk_count, 0);
+        let d3_library: String = db.get_conn_safe()?.query_row(
+            "SELECT sub_library_id FROM rag_documents WHERE id = 'd3'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(d3_library, "default");
 
-        let mut items = Vec::new();
-        for row in rows {
-            items.push(row?);
+        assert!(db.list_deletable_sub_library_documents("default").is_err());
+        assert_eq!(
+            db.get_rag_document_chunk_ids("d3")?,
+            Some(vec!["c4".to_string()])
+        );
+        assert_eq!(db.delete_rag_document("d3")?, 1);
+        assert_eq!(db.get_rag_document_chunk_ids("d3")?, None);
+        assert!(db.delete_rag_document("d3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sub_library_deletion_preview_matches_deletion() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database_with_rag(dir.path())?;
+        {
+            let conn = db.get_conn_safe()?;
+            conn.execute_batch(
+                "INSERT INTO rag_sub_libraries (id, name) VALUES ('physics', 'physics'), ('math', 'math');
+                 INSERT INTO rag_documents (id, sub_library_id, file_name, created_at, updated_at) VALUES
+                    ('d1', 'physics', '力学.pdf', '', ''), ('d2', 'physics', '电磁.md', '', ''),
+                    ('d3', 'math', '函数.pdf', '', '');
+                 INSERT INTO rag_document_chunks (id, document_id, chunk_index, text) VALUES
+                    ('c1', 'd1', 0, 'a'), ('c2', 'd1', 1, 'b'), ('c3', 'd2', 0, 'c'), ('c4', 'd3', 0, 'd');",
+            )?;
         }
 
-        Ok((items, total))
+        assert!(db.preview_delete_sub_library("default", true).is_err());
+        assert!(db.preview_delete_sub_library("missing", true).is_err());
+
+        let impact = db.preview_delete_sub_library("physics", true)?;
+        assert!(impact.documents_deleted);
+        assert_eq!((impact.document_count, impact.chunk_count), (2, 3));
+        let mut names = impact.document_file_names.clone();
+        names.sort();
+        assert_eq!(names, vec!["力学.pdf".to_string(), "电磁.md".to_string()]);
+        assert_eq!(impact.reassigned_document_count, 0);
+
+        let moved = db.preview_delete_sub_library("math", false)?;
+        assert!(!moved.documents_deleted);
+        assert_eq!((moved.document_count, moved.chunk_count), (0, 0));
+        assert!(moved.document_file_names.is_empty());
+        assert_eq!(moved.reassigned_document_count, 1);
+
+        // 演练不修改数据，实际删除的计数与演练一致
+        let deleted = db.delete_sub_library("physics", true)?;
+        assert_eq!(deleted.document_ids.len(), impact.document_count);
+        assert_eq!(deleted.deleted_chunk_count, impact.chunk_count);
+        let reassigned = db.delete_sub_library("math", false)?;
+        assert_eq!(
+            reassigned.document_ids.len(),
+            moved.reassigned_document_count
+        );
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::models::ChatMessage;
-    use chrono::{Duration, Utc};
-    use rusqlite::params;
-    use serde_json::json;
-    use tempfile::tempdir;
 
     #[test]
-    fn append_preserves_turn_metadata_and_scoped_deletion() -> anyhow::Result<()> {
+    fn rag_document_chunks_are_paginated_in_order() -> anyhow::Result<()> {
         let dir = tempdir()?;
-        let db_path = dir.path().join("chat_test.db");
-        let db = Database::new(&db_path)?;
-
-        let now = Utc::now().to_rfc3339();
+        let db = migrated_database_with_rag(dir.path())?;
         {
             let conn = db.get_conn_safe()?;
             conn.execute(
-                "INSERT INTO mistakes (id, subject, created_at, question_images, analysis_images, user_question, ocr_text, tags, mistake_type, status, chat_category, updated_at, last_accessed_at)
-                 VALUES (?1, ?2, ?3, '[]', '[]', ?4, ?5, '[]', 'analysis', 'completed', 'analysis', ?3, ?3)",
-                params!["mistake-1", "math", now, "示例问题", ""],
+                "INSERT INTO rag_documents (id, file_name, created_at, updated_at) VALUES ('doc', 'a.pdf', '', '')",
+                [],
             )?;
+            for i in 0..5 {
+                conn.execute(
+                    "INSERT INTO rag_document_chunks (id, document_id, chunk_index, text, metadata)
+                     VALUES (?1, 'doc', ?2, ?3, ?4)",
+                    params![
+                        format!("c{}", i),
+                        4 - i,
+                        format!("块 {}", 4 - i),
+                        json!({ "page_number": (4 - i).to_string() }).to_string()
+                    ],
+                )?;
+            }
         }
 
-        let base_ts = Utc::now();
-        let turn_id = "turn-test-1";
-        let user_message = ChatMessage {
-            role: "user".to_string(),
-            content: "原始提问".to_string(),
-            timestamp: base_ts,
-            thinking_content: None,
-            thought_signature: None,
-            rag_sources: None,
-            memory_sources: None,
-            graph_sources: None,
-            web_search_sources: None,
-            image_paths: None,
-            image_base64: None,
-            doc_attachments: None,
-            tool_call: None,
-            tool_result: None,
-            overrides: None,
-            relations: Some(json!({
-                "turn_id": turn_id,
-                "turn_seq": 0,
-                "message_kind": "user.input"
-            })),
-            persistent_stable_id: Some("user-stable".to_string()),
-            metadata: None,
-            multimodal_content: None,
-        };
-        let assistant_message = ChatMessage {
-            role: "assistant".to_string(),
-            content: "助手回答".to_string(),
-            timestamp: base_ts + Duration::seconds(1),
-            thinking_content: None,
-            thought_signature: None,
-            rag_sources: None,
-            memory_sources: None,
-            graph_sources: None,
-            web_search_sources: None,
-            image_paths: None,
-            image_base64: None,
-            doc_attachments: None,
-            tool_call: None,
-            tool_result: None,
-            overrides: None,
-            relations: Some(json!({
-                "turn_id": turn_id,
-                "turn_seq": 1,
-                "message_kind": "assistant.answer",
-                "lifecycle": "complete",
-                "reply_to_msg_id": null
-            })),
-            persistent_stable_id: Some("assistant-stable".to_string()),
-            metadata: None,
-            multimodal_content: None,
-        };
-
-        db.append_mistake_chat_messages(
-            "mistake-1",
-            &[user_message.clone(), assistant_message.clone()],
-        )?;
+        assert!(db.get_rag_document_chunks_page("missing", 1, 2)?.is_none());
+        let page = db
+            .get_rag_document_chunks_page("doc", 2, 2)?
+            .expect("文档应存在");
+        assert_eq!(page.total_chunks, 5);
+        assert_eq!(page.update_state, "ready");
+        let indexes: Vec<usize> = page.chunks.iter().map(|c| c.chunk_index).collect();
+        assert_eq!(indexes, vec![2, 3]);
+        assert_eq!(
+            page.chunks[0]
+                .metadata
+                .get("page_number")
+                .map(String::as_str),
+            Some("2")
+        );
+        Ok(())
+    }
 
-        let (user_id, stored_turn_id, relations_before, turn_seq_before): (
-            i64,
-            Option<String>,
-            Option<String>,
-            Option<i64>,
-        ) = {
+    #[test]
+    fn pruning_completed_tasks_keeps_their_cards() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        {
             let conn = db.get_conn_safe()?;
-            conn.query_row(
-                "SELECT id, turn_id, relations, turn_seq FROM chat_messages WHERE stable_id = ?1",
-                params!["user-stable"],
-                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
-            )?
-        };
-        let stored_turn_id = stored_turn_id.expect("turn_id 应存在");
-        assert_eq!(turn_seq_before, Some(0));
+            conn.execute_batch(
+                "INSERT INTO document_tasks (id, document_id, original_document_name, segment_index,
+                    content_segment, status, updated_at, anki_generation_options_json) VALUES
+                    ('old', 'doc-a', 'a.pdf', 0, '原文', 'Completed', '2020-01-01T00:00:00.000Z', '{}'),
+                    ('recent', 'doc-b', 'b.pdf', 0, '原文', 'Completed', '2099-01-01T00:00:00.000Z', '{}'),
+                    ('old-done', 'doc-c', 'c.pdf', 0, '原文', 'Completed', '2020-01-01T00:00:00.000Z', '{}'),
+                    ('paused', 'doc-c', 'c.pdf', 1, '原文', 'Paused', '2020-01-01T00:00:00.000Z', '{}');
+                 INSERT INTO anki_cards (id, task_id, front, back, source_type, source_id) VALUES
+                    ('c1', 'old', '问', '答', '', ''),
+                    ('c2', 'old', '问', '答', 'task', 'old'),
+                    ('c3', 'recent', '问', '答', 'document', 'doc-b');
+                 INSERT INTO document_control_states (document_id, state) VALUES
+                    ('doc-a', 'Completed'), ('doc-c', 'Paused');",
+            )?;
+        }
 
-        let updated_user = ChatMessage {
-            content: "更新后的提问".to_string(),
-            timestamp: base_ts + Duration::seconds(5),
-            persistent_stable_id: Some("user-stable".to_string()),
-            relations: None,
-            ..user_message.clone()
-        };
-        db.append_mistake_chat_messages("mistake-1", &[updated_user])?;
+        let report = db.prune_completed_document_tasks(Utc::now() - Duration::days(30))?;
+        assert_eq!(report.pruned_tasks, 1);
+        assert_eq!(report.preserved_cards, 2);
+        assert_eq!(report.cleared_documents, 1);
 
-        let (turn_id_after, relations_after, turn_seq_after): (
-            Option<String>,
-            Option<String>,
-            Option<i64>,
-        ) = {
-            let conn = db.get_conn_safe()?;
-            conn.query_row(
-                "SELECT turn_id, relations, turn_seq FROM chat_messages WHERE stable_id = ?1",
-                params!["user-stable"],
-                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
-            )?
-        };
-        assert_eq!(turn_id_after, Some(stored_turn_id.clone()));
-        assert_eq!(relations_before, relations_after);
-        assert_eq!(turn_seq_after, Some(0));
+        let conn = db.get_conn_safe()?;
+        let cards: Vec<(String, String, String)> = conn
+            .prepare("SELECT task_id, source_type, source_id FROM anki_cards WHERE id IN ('c1', 'c2') ORDER BY id")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        assert_eq!(cards.len(), 2);
+        for card in &cards {
+            assert_eq!(
+                card,
+                &(
+                    ARCHIVED_CARDS_TASK_ID.to_string(),
+                    "document".to_string(),
+                    "doc-a".to_string()
+                )
+            );
+        }
+        let remaining: Vec<String> = conn
+            .prepare("SELECT id FROM document_tasks ORDER BY id")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        // 有暂停任务的文档整体跳过
+        assert_eq!(
+            remaining,
+            vec![ARCHIVED_CARDS_TASK_ID, "old-done", "paused", "recent"]
+        );
+        drop(conn);
+        assert!(db
+            .get_recent_document_tasks(10)?
+            .iter()
+            .all(|t| t.id != ARCHIVED_CARDS_TASK_ID));
+        Ok(())
+    }
 
-        let assistant_id: i64 = {
-            let conn = db.get_conn_safe()?;
-            conn.query_row(
-                "SELECT id FROM chat_messages WHERE stable_id = ?1",
-                params!["assistant-stable"],
-                |row| row.get(0),
-            )?
-        };
+    #[test]
+    fn task_raw_output_is_saved_and_cleared() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        db.get_conn_safe()?.execute(
+            "INSERT INTO document_tasks (id, document_id, original_document_name, segment_index,
+                content_segment, status, anki_generation_options_json)
+             VALUES ('t1', 'doc', 'a.pdf', 0, '原文', 'Failed', '{}')",
+            [],
+        )?;
+        assert!(db.get_task_raw_output("t1")?.is_none());
 
-        let ids_without_user = db.list_turn_message_ids("mistake-1", &stored_turn_id, false)?;
-        assert_eq!(ids_without_user, vec![assistant_id]);
+        db.save_task_raw_output("t1", "{\"front\": \"问", 4096)?;
+        let raw = db.get_task_raw_output("t1")?.expect("raw output saved");
+        assert_eq!(raw.document_id, "doc");
+        assert_eq!(raw.status, TaskStatus::Failed);
+        assert!(raw.truncated);
 
-        let mut ids_with_user = db.list_turn_message_ids("mistake-1", &stored_turn_id, true)?;
-        ids_with_user.sort();
-        let mut expected_ids = vec![assistant_id, user_id];
-        expected_ids.sort();
-        assert_eq!(ids_with_user, expected_ids);
+        assert_eq!(db.clear_task_raw_outputs()?, 1);
+        assert!(db.get_task_raw_output("t1")?.is_none());
+        Ok(())
+    }
 
-        let deleted = db.delete_chat_turn("mistake-1", &stored_turn_id, false)?;
-        assert_eq!(deleted, 1);
+    #[test]
+    fn failed_rag_documents_track_retries_until_ready() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database_with_rag(dir.path())?;
+        db.get_conn_safe()?.execute_batch(
+            "INSERT INTO rag_documents (id, file_name, created_at, updated_at) VALUES
+                ('ok', 'a.pdf', '', ''), ('bad', 'b.pdf', '', '');",
+        )?;
 
-        let user_row_exists: i64 = {
-            let conn = db.get_conn_safe()?;
-            conn.query_row(
-                "SELECT COUNT(1) FROM chat_messages WHERE id = ?1",
-                params![user_id],
-                |row| row.get(0),
-            )?
-        };
-        assert_eq!(user_row_exists, 1);
+        db.mark_rag_document_failed("bad", "partial", "写入向量失败")?;
+        db.mark_rag_document_failed("bad", "failed", "嵌入超时")?;
+        let failed = db.list_failed_rag_documents()?;
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].id, "bad");
+        assert_eq!(failed[0].update_state, "failed");
+        assert_eq!(failed[0].update_retry, 2);
+        assert_eq!(failed[0].last_error.as_deref(), Some("嵌入超时"));
+
+        db.mark_rag_document_ready("bad")?;
+        assert!(db.list_failed_rag_documents()?.is_empty());
+        Ok(())
+    }
 
-        let assistant_row_exists: i64 = {
-            let conn = db.get_conn_safe()?;
-            conn.query_row(
-                "SELECT COUNT(1) FROM chat_messages WHERE id = ?1",
-                params![assistant_id],
-                |row| row.get(0),
-            )?
+    #[test]
+    fn rag_embedding_batch_size_is_kept_until_reset() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let db = migrated_database(dir.path())?;
+        db.get_conn_safe()?.execute_batch(
+            "INSERT INTO rag_configurations (id, chunk_size, chunk_overlap, chunking_strategy,
+                min_chunk_size, default_top_k, default_rerank_enabled, created_at, updated_at)
+             VALUES ('default', 512, 50, 'fixed_size', 20, 5, 1,
+                '2026-01-01T00:00:00Z', '2026-01-01T00:00:00Z');",
+        )?;
+        let batch_size = |db: &Database| -> anyhow::Result<i32> {
+            Ok(db
+                .get_rag_configuration()?
+                .expect("默认配置应存在")
+                .embedding_batch_size)
         };
-        assert_eq!(assistant_row_exists, 0);
+        assert_eq!(batch_size(&db)?, 16);
+
+        db.update_rag_embedding_batch_size(32)?;
+        assert_eq!(batch_size(&db)?, 32);
+
+        // 未提供批大小的配置更新保持原值
+        db.update_rag_configuration(&crate::models::RagConfigRequest {
+            chunk_size: 256,
+            chunk_overlap: 20,
+            chunking_strategy: "fixed_size".to_string(),
+            min_chunk_size: 10,
+            default_top_k: 5,
+            default_rerank_enabled: true,
+            embedding_batch_size: None,
+        })?;
+        assert_eq!(batch_size(&db)?, 32);
 
+        db.reset_rag_configuration()?;
+        assert_eq!(batch_size(&db)?, 16);
         Ok(())
     }
 }
+/// 默认分库与不存在的分库不可删除
+fn ensure_sub_library_deletable(conn: &Connection, id: &str) -> Result<()> {
+    if id == "default" {
+        return Err(anyhow::anyhow!("不能删除默认分库"));
+    }
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE id = ?1)",
+        params![id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(anyhow::anyhow!("分库ID '{}' 不存在", id));
+    }
+    Ok(())
+}
+
+/// 分库中的文档ID，删除与删除演练共用
+fn sub_library_document_ids(conn: &Connection, id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT id FROM rag_documents WHERE sub_library_id = ?1")?;
+    let ids = stmt
+        .query_map(params![id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ids)
+}
+
+/// 分库内容统计（删除预览）
+#[derive(Debug, Clone, Default)]
+pub struct SubLibraryContentStats {
+    pub document_count: usize,
+    pub chunk_count: usize,
+    /// 原始文件大小合计（rag_documents.file_size）
+    pub document_bytes: u64,
+    /// 分块文本字节数合计
+    pub chunk_text_bytes: u64,
+}
+
+/// 分库索引统计（SQLite 侧）
+#[derive(Debug, Clone, Default)]
+pub struct SubLibraryIndexStats {
+    pub library_id: String,
+    pub name: String,
+    pub document_count: usize,
+    pub chunk_count: usize,
+    /// 原始文件大小合计
+    pub document_bytes: u64,
+    pub chunk_text_bytes: u64,
+    /// 未就绪或有重试记录的文档数
+    pub pending_documents: usize,
+    /// 分库或其中文档的最近更新时间
+    pub last_updated: Option<String>,
+}
+
+/// 分库删除结果
+#[derive(Debug, Clone)]
+pub struct DeletedSubLibrary {
+    /// 分库中原有的文档（已删除或已移到默认分库）
+    pub document_ids: Vec<String>,
+    pub documents_deleted: bool,
+    pub deleted_chunk_count: usize,
+}
+
+/// 分库删除演练结果
+#[derive(Debug, Clone, Default)]
+pub struct SubLibraryDeletionImpact {
+    pub documents_deleted: bool,
+    /// 将被删除的文档数与分块数（保留文档时为 0）
+    pub document_count: usize,
+    pub chunk_count: usize,
+    /// 将被删除的文档文件名
+    pub document_file_names: Vec<String>,
+    /// 将移到默认分库的文档数（删除文档时为 0）
+    pub reassigned_document_count: usize,
+}
+
+/// 文档分块分页结果
+#[derive(Debug, Clone)]
+pub struct RagDocumentChunksPage {
+    pub total_chunks: usize,
+    /// 文档更新状态（默认 ready）
+    pub update_state: String,
+    pub update_retry: i64,
+    pub chunks: Vec<crate::models::DocumentChunk>,
+}
+
+/// 制卡任务保存的模型原始输出（调试用）
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRawOutput {
+    pub task_id: String,
+    pub document_id: String,
+    pub status: TaskStatus,
+    pub raw_output: String,
+    /// 截断前的字节数
+    pub original_bytes: usize,
+    pub truncated: bool,
+}
+
+/// 已清理任务的卡片归档到此任务下（不在任务列表中显示）
+pub const ARCHIVED_CARDS_TASK_ID: &str = "__archived_cards__";
+
+/// 已完成制卡任务的清理结果
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentTaskPruneReport {
+    pub pruned_tasks: usize,
+    /// 移入归档任务的卡片数
+    pub preserved_cards: usize,
+    /// 任务已全部清理的文档数
+    pub cleared_documents: usize,
+}
+
+/// 导入失败或部分完成的知识库文档
+#[derive(Debug, Clone)]
+pub struct FailedRagDocument {
+    pub id: String,
+    pub file_name: String,
+    /// failed：嵌入失败；partial：分块已存储但部分向量缺失
+    pub update_state: String,
+    pub update_retry: i64,
+    pub last_error: Option<String>,
+}
+
+/// 多个标签筛选时的匹配方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatchMode {
+    /// 同时带有全部标签
+    #[default]
+    All,
+    /// 带有任一标签
+    Any,
+}
+
+/// 去除首尾空白、空标签与重复标签
+fn normalize_tag_filter(tags: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tags.iter()
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty() && seen.insert(*tag))
+        .map(str::to_string)
+        .collect()
+}
+
+/// 错题关键词检索结果
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MistakeSearchHit {
+    pub id: String,
+    pub user_question: String,
+    pub mistake_type: String,
+    pub tags: Vec<String>,
+    pub created_at: String,
+    pub user_notes: Option<String>,
+    /// 是否命中了用户批注
+    pub matched_user_notes: bool,
+    pub difficulty: Option<u8>,
+    pub mastery_level: Option<u8>,
+    /// 置顶错题排在检索结果最前
+    pub pinned: bool,
+    pub star_rating: Option<u8>,
+}
+
+/// 错题检索的一页结果
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct MistakeSearchPage {
+    pub items: Vec<MistakeSearchHit>,
+    /// 满足筛选条件的错题总数
+    pub total: u64,
+}
+
+/// 错题对话消息关键词检索结果
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChatMessageSearchHit {
+    pub message_id: i64,
+    pub mistake_id: String,
+    pub role: String,
+    pub content: String,
+    pub timestamp: String,
+    /// 所属错题的题干，便于展示上下文
+    pub mistake_question: String,
+}
+
+/// 知识库分块关键词检索结果
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct KnowledgeChunkSearchHit {
+    pub chunk_id: String,
+    pub document_id: String,
+    pub file_name: String,
+    pub sub_library_id: String,
+    pub chunk_index: i64,
+    pub text: String,
+}
+
+/// 构造 `LIKE ... ESCAPE '\'` 的包含匹配模式；`%`、`_` 按字面匹配，空白查询返回 None
+fn like_pattern(query: &str) -> Option<String> {
+    let query = query.trim();
+    if query.is_empty() {
+        return None;
+    }
+    let escaped = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    Some(format!("%{}%", escaped))
+}
+
+/// 生成中的回顾分析状态，删除与归档前需等待其结束
+const STREAMING_REVIEW_STATUSES: &[&str] = &["analyzing", "streaming", "in_progress"];
+
+/// 任一回顾分析正在生成时返回错误
+fn ensure_review_analyses_idle(conn: &Connection, ids: &[String]) -> Result<()> {
+    let mut stmt = conn.prepare("SELECT status FROM review_analyses WHERE id = ?1")?;
+    let mut busy = Vec::new();
+    for id in ids {
+        let status: Option<String> = stmt.query_row(params![id], |row| row.get(0)).optional()?;
+        if status.is_some_and(|s| STREAMING_REVIEW_STATUSES.contains(&s.as_str())) {
+            busy.push(id.as_str());
+        }
+    }
+    if !busy.is_empty() {
+        return Err(anyhow::anyhow!(
+            "回顾分析正在生成中，请等待完成或停止后再操作: {}",
+            busy.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// 回顾分析删除结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ReviewAnalysisDeletion {
+    pub deleted_analyses: usize,
+    pub deleted_messages: usize,
+}
+
 /// 搜索统计结构体
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SearchStatistics {
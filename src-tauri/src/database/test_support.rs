@@ -0,0 +1,100 @@
+//! 测试夹具：按正式迁移脚本建表
+//!
+//! 测试不再手写局部的 `CREATE TABLE mistakes`，而是执行 `MISTAKES_MIGRATIONS` 中的迁移 SQL，
+//! 保证被测代码面对的列、约束与线上数据库一致。需要模拟未迁移旧库的测试可只迁移到指定版本。
+
+use super::Database;
+use crate::data_governance::migration::MISTAKES_MIGRATIONS;
+use rusqlite::{params_from_iter, Connection, ToSql};
+use std::path::Path;
+
+/// 依次执行版本号不超过 `up_to` 的主库迁移
+pub(crate) fn apply_mistakes_migrations(conn: &Connection, up_to: i32) -> anyhow::Result<()> {
+    apply_mistakes_migration_range(conn, i32::MIN, up_to)
+}
+
+/// 执行版本号大于 `after` 的剩余主库迁移，模拟旧库升级
+pub(crate) fn upgrade_mistakes_migrations(conn: &Connection, after: i32) -> anyhow::Result<()> {
+    apply_mistakes_migration_range(conn, after, i32::MAX)
+}
+
+fn apply_mistakes_migration_range(conn: &Connection, after: i32, up_to: i32) -> anyhow::Result<()> {
+    for migration in MISTAKES_MIGRATIONS
+        .migrations
+        .iter()
+        .filter(|m| m.refinery_version > after && m.refinery_version <= up_to)
+    {
+        conn.execute_batch(migration.sql)
+            .map_err(|e| anyhow::anyhow!("执行迁移 V{} 失败: {}", migration.refinery_version, e))?;
+    }
+    Ok(())
+}
+
+/// 已执行全部主库迁移的内存连接
+pub(crate) fn migrated_conn() -> anyhow::Result<Connection> {
+    let conn = Connection::open_in_memory()?;
+    apply_mistakes_migrations(&conn, i32::MAX)?;
+    Ok(conn)
+}
+
+/// 在 `dir` 下创建已执行全部主库迁移的数据库
+pub(crate) fn migrated_database(dir: &Path) -> anyhow::Result<Database> {
+    migrated_database_up_to(dir, i32::MAX)
+}
+
+/// 在 `dir` 下创建已迁移的数据库，并补建旧知识库表（与向量库初始化时的定义一致）
+pub(crate) fn migrated_database_with_rag(dir: &Path) -> anyhow::Result<Database> {
+    let db = migrated_database(dir)?;
+    crate::lance_vector_store::ensure_rag_document_schema(&db.get_conn_safe()?)?;
+    Ok(db)
+}
+
+/// 在 `dir` 下创建只迁移到 `up_to` 版本的数据库，用于覆盖旧库兼容分支
+pub(crate) fn migrated_database_up_to(dir: &Path, up_to: i32) -> anyhow::Result<Database> {
+    let db = Database::new(&dir.join("mistakes.db"))?;
+    apply_mistakes_migrations(&db.get_conn_safe()?, up_to)?;
+    Ok(db)
+}
+
+/// 插入一条错题：未在 `fields` 中给出的必填列使用占位值
+///
+/// `fields` 为 (列名, 值)，例如 `&[("tags", &r#"["代数"]"#), ("status", &"mastered")]`。
+pub(crate) fn insert_mistake(
+    conn: &Connection,
+    id: &str,
+    fields: &[(&str, &dyn ToSql)],
+) -> rusqlite::Result<()> {
+    const DEFAULTS: [(&str, &str); 9] = [
+        ("created_at", "2026-01-01T00:00:00Z"),
+        ("updated_at", "2026-01-01T00:00:00Z"),
+        ("question_images", "[]"),
+        ("analysis_images", "[]"),
+        ("user_question", ""),
+        ("ocr_text", ""),
+        ("tags", "[]"),
+        ("mistake_type", "analysis"),
+        ("status", "active"),
+    ];
+    let mut columns = vec!["id"];
+    let mut values: Vec<&dyn ToSql> = vec![&id];
+    for (column, value) in fields {
+        columns.push(*column);
+        values.push(*value);
+    }
+    for (column, value) in DEFAULTS.iter() {
+        if !fields.iter().any(|(name, _)| name == column) {
+            columns.push(*column);
+            values.push(value);
+        }
+    }
+    let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+    conn.execute(
+        &format!(
+            "INSERT INTO mistakes ({}) VALUES ({})",
+            columns.join(", "),
+            placeholders.join(", ")
+        ),
+        params_from_iter(values),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,242 @@
+//! 磁盘剩余空间保护
+//!
+//! 写入密集的操作（知识库索引、备份、文档分段处理、数据目录迁移）开始前检查目标磁盘的
+//! 剩余空间，低于阈值时拒绝启动并返回带 `DISK_FULL` 错误码的错误。
+//! 长任务通过 [`DiskSpaceGuard`] 在每个处理单元（资源 / 文件）之间节流复查：
+//! 空间不足时在单元边界停止，已完成的单元保持提交状态，未开始的单元回退到待处理，
+//! 不会把数据库留在半写状态。
+//!
+//! 配置保存在设置项 `storage.disk_guard.config`（JSON）。
+
+use crate::backup_common::get_available_disk_space;
+use crate::database::Database;
+use crate::models::{AppError, AppErrorType};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 磁盘空间不足时的错误码，前端据此提示用户清理磁盘或调整阈值
+pub const DISK_FULL: &str = "DISK_FULL";
+
+pub const DISK_GUARD_SETTINGS_KEY: &str = "storage.disk_guard.config";
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiskGuardSettings {
+    /// 最低剩余空间（MB），0 表示关闭检查
+    pub min_free_mb: u64,
+    /// 长任务复查间隔（秒）
+    pub recheck_interval_secs: u64,
+}
+
+impl Default for DiskGuardSettings {
+    fn default() -> Self {
+        Self {
+            min_free_mb: 512,
+            recheck_interval_secs: 30,
+        }
+    }
+}
+
+impl DiskGuardSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.min_free_mb > 1024 * 1024 {
+            return Err(AppError::validation("最低剩余空间不能超过 1 TB"));
+        }
+        if !(1..=3600).contains(&self.recheck_interval_secs) {
+            return Err(AppError::validation("复查间隔必须在 1 到 3600 秒之间"));
+        }
+        Ok(())
+    }
+
+    pub fn min_free_bytes(&self) -> u64 {
+        self.min_free_mb.saturating_mul(MB)
+    }
+}
+
+/// 读取磁盘保护配置；未设置或解析失败时使用默认值
+pub fn load_disk_guard_settings(db: &Database) -> DiskGuardSettings {
+    db.get_setting(DISK_GUARD_SETTINGS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<DiskGuardSettings>(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_disk_guard_settings(db: &Database, settings: &DiskGuardSettings) -> Result<()> {
+    settings.validate()?;
+    let json = serde_json::to_string(settings)
+        .map_err(|e| AppError::internal(format!("序列化磁盘保护配置失败: {}", e)))?;
+    db.save_setting(DISK_GUARD_SETTINGS_KEY, &json)
+        .map_err(|e| AppError::database(e.to_string()))
+}
+
+fn format_mb(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / MB as f64)
+}
+
+/// 构造 `DISK_FULL` 错误（消息以错误码开头，便于只拿到字符串的调用方识别）
+pub fn disk_full_error(path: &Path, available: u64, required: u64) -> AppError {
+    AppError::with_details(
+        AppErrorType::FileSystem,
+        format!(
+            "{}: 磁盘剩余空间不足（{} 可用 {}，至少需要 {}），请清理磁盘或在设置中调低最低剩余空间",
+            DISK_FULL,
+            path.display(),
+            format_mb(available),
+            format_mb(required)
+        ),
+        json!({
+            "code": DISK_FULL,
+            "path": path.to_string_lossy(),
+            "availableBytes": available,
+            "requiredBytes": required,
+        }),
+    )
+}
+
+/// 是否为 `DISK_FULL` 错误
+pub fn is_disk_full(err: &AppError) -> bool {
+    err.details
+        .as_ref()
+        .and_then(|d| d.get("code"))
+        .and_then(|c| c.as_str())
+        == Some(DISK_FULL)
+}
+
+/// 剩余空间 < `additional_bytes + min_free_bytes` 时返回错误
+///
+/// 无法获取磁盘信息时只记录警告并放行，避免在不支持的平台上阻断正常操作。
+pub fn ensure_free_space(path: &Path, additional_bytes: u64, min_free_bytes: u64) -> Result<()> {
+    if min_free_bytes == 0 && additional_bytes == 0 {
+        return Ok(());
+    }
+    let available = match get_available_disk_space(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!(
+                "[DiskGuard] 无法获取 {} 的剩余空间，跳过检查: {}",
+                path.display(),
+                e
+            );
+            return Ok(());
+        }
+    };
+    check_available(
+        path,
+        available,
+        additional_bytes.saturating_add(min_free_bytes),
+    )
+}
+
+fn check_available(path: &Path, available: u64, required: u64) -> Result<()> {
+    if available < required {
+        warn!(
+            "[DiskGuard] {} 剩余空间不足: 可用 {}，需要 {}",
+            path.display(),
+            format_mb(available),
+            format_mb(required)
+        );
+        return Err(disk_full_error(path, available, required));
+    }
+    Ok(())
+}
+
+/// 长任务的磁盘空间守卫：`preflight` 立即检查，`check` 按配置间隔节流复查
+pub struct DiskSpaceGuard {
+    path: PathBuf,
+    min_free_bytes: u64,
+    interval: Duration,
+    last_check: Option<Instant>,
+}
+
+impl DiskSpaceGuard {
+    pub fn new(path: impl Into<PathBuf>, settings: &DiskGuardSettings) -> Self {
+        Self {
+            path: path.into(),
+            min_free_bytes: settings.min_free_bytes(),
+            interval: Duration::from_secs(settings.recheck_interval_secs.max(1)),
+            last_check: None,
+        }
+    }
+
+    /// 按数据库中的配置创建
+    pub fn from_db(db: &Database, path: impl Into<PathBuf>) -> Self {
+        Self::new(path, &load_disk_guard_settings(db))
+    }
+
+    /// 任务开始前的检查：剩余空间需容纳 `additional_bytes` 并保留最低剩余空间
+    pub fn preflight(&mut self, additional_bytes: u64) -> Result<()> {
+        let result = ensure_free_space(&self.path, additional_bytes, self.min_free_bytes);
+        // 检查失败后不再节流，后续每次 `check` 都重新读取磁盘
+        self.last_check = result.is_ok().then(Instant::now);
+        result
+    }
+
+    /// 处理单元之间调用；距上次成功检查不足复查间隔时直接返回
+    pub fn check(&mut self) -> Result<()> {
+        if self
+            .last_check
+            .is_some_and(|last| last.elapsed() < self.interval)
+        {
+            return Ok(());
+        }
+        self.preflight(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_defaults_and_validation() {
+        let parsed: DiskGuardSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(parsed, DiskGuardSettings::default());
+        assert_eq!(parsed.min_free_bytes(), 512 * MB);
+        assert!(parsed.validate().is_ok());
+
+        let zero_interval = DiskGuardSettings {
+            recheck_interval_secs: 0,
+            ..Default::default()
+        };
+        assert!(zero_interval.validate().is_err());
+    }
+
+    #[test]
+    fn test_disk_full_error_carries_code() {
+        let path = Path::new("/data");
+        assert!(check_available(path, 2 * MB, MB).is_ok());
+
+        let err = check_available(path, MB, 2 * MB).unwrap_err();
+        assert!(is_disk_full(&err));
+        assert!(err.message.starts_with(DISK_FULL));
+        assert_eq!(err.details.unwrap()["requiredBytes"], 2 * MB);
+
+        assert!(!is_disk_full(&AppError::validation("x")));
+    }
+
+    #[test]
+    fn test_guard_throttles_rechecks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let settings = DiskGuardSettings {
+            min_free_mb: 0,
+            recheck_interval_secs: 3600,
+        };
+        let mut guard = DiskSpaceGuard::new(tmp.path(), &settings);
+        assert!(guard.check().is_ok());
+        let first = guard.last_check.unwrap();
+        assert!(guard.check().is_ok());
+        assert_eq!(guard.last_check.unwrap(), first);
+
+        // 需要的空间远超任何磁盘
+        assert!(guard.preflight(u64::MAX / 2).is_err());
+        assert!(guard.last_check.is_none());
+    }
+}
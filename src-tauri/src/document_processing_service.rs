@@ -1,4 +1,5 @@
 use crate::database::Database;
+use crate::disk_guard::DiskSpaceGuard;
 use crate::models::{AnkiGenerationOptions, AppError, DocumentTask, TaskStatus};
 use chrono::Utc;
 use std::sync::Arc;
@@ -42,6 +43,13 @@ impl DocumentProcessingService {
         // 分段文档
         let segments = self.segment_document(&document_content, &options)?;
 
+        // 分段内容写入数据库前检查剩余空间（按分段总长度的两倍估算 WAL 与索引开销）
+        if let Some(db_path) = self.db.db_path() {
+            let segment_bytes: usize = segments.iter().map(|s| s.len()).sum();
+            DiskSpaceGuard::from_db(&self.db, db_path)
+                .preflight((segment_bytes as u64).saturating_mul(2))?;
+        }
+
         let mut tasks = Vec::new();
         let segment_limits = options
             .max_cards_total
@@ -2996,6 +2996,7 @@ pub async fn dstu_search(
     options: Option<DstuListOptions>,
     vfs_db: State<'_, Arc<VfsDatabase>>,
 ) -> Result<Vec<DstuNode>, String> {
+    let _timer = crate::command_metrics::start("dstu_search");
     log::info!("[DSTU::handlers] dstu_search: query={}", query);
 
     let options = options.unwrap_or_default();
@@ -0,0 +1,280 @@
+//! 向量导出（供外部聚类/分析使用）
+//!
+//! 逐条写盘，不在内存中拼装完整结果：
+//! - JSONL：首行为 header（模型、维度、范围），之后每行一条向量及其来源 ID 与文本
+//! - NPY：`<name>.npy` 为 float32 `(N, dim)` 矩阵；同名 `<name>.meta.jsonl`
+//!   首行为 header，之后按行号与矩阵对齐记录来源信息。行数在写完后回填到预留的 NPY 头中。
+//!
+//! 单个导出文件只能包含一种维度；混合维度时需通过 `dimension` 过滤。
+
+use crate::lance_vector_store::EmbeddingExportRow;
+use crate::models::AppError;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+/// 行数字段预留宽度，保证回填前后 NPY 头长度一致
+const NPY_ROWS_WIDTH: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingExportFormat {
+    Jsonl,
+    Npy,
+}
+
+/// 导出文件头：描述向量来源与模型
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingExportHeader {
+    pub scope: String,
+    pub embedding_model_id: Option<String>,
+    pub embedding_model_config_id: Option<String>,
+    pub exported_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingExportSummary {
+    pub format: EmbeddingExportFormat,
+    pub output_path: String,
+    /// NPY 导出时的元数据文件
+    pub metadata_path: Option<String>,
+    pub row_count: usize,
+    pub dimension: Option<usize>,
+}
+
+pub struct EmbeddingExportWriter {
+    format: EmbeddingExportFormat,
+    header: EmbeddingExportHeader,
+    output_path: PathBuf,
+    metadata_path: Option<PathBuf>,
+    data: BufWriter<File>,
+    meta: Option<BufWriter<File>>,
+    dimension: Option<usize>,
+    rows: usize,
+}
+
+fn io_err(e: std::io::Error) -> AppError {
+    AppError::file_system(format!("写入向量导出文件失败: {}", e))
+}
+
+fn create_file(path: &Path) -> Result<BufWriter<File>> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(io_err)?;
+    }
+    File::create(path).map(BufWriter::new).map_err(io_err)
+}
+
+/// NPY v1.0 头：magic + 头长度 + 以换行结尾、按 64 字节对齐的字典
+fn npy_header(rows: usize, dimension: usize) -> Vec<u8> {
+    let dict = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({:>width$}, {}), }}",
+        rows,
+        dimension,
+        width = NPY_ROWS_WIDTH
+    );
+    let unpadded = NPY_MAGIC.len() + 2 + dict.len() + 1;
+    let padding = (64 - unpadded % 64) % 64;
+    let header_len = (dict.len() + padding + 1) as u16;
+
+    let mut out = Vec::with_capacity(unpadded + padding);
+    out.extend_from_slice(NPY_MAGIC);
+    out.extend_from_slice(&header_len.to_le_bytes());
+    out.extend_from_slice(dict.as_bytes());
+    out.extend(std::iter::repeat(b' ').take(padding));
+    out.push(b'\n');
+    out
+}
+
+impl EmbeddingExportWriter {
+    pub fn create(
+        output_path: &Path,
+        format: EmbeddingExportFormat,
+        header: EmbeddingExportHeader,
+    ) -> Result<Self> {
+        let data = create_file(output_path)?;
+        let (metadata_path, meta) = match format {
+            EmbeddingExportFormat::Jsonl => (None, None),
+            EmbeddingExportFormat::Npy => {
+                let path = output_path.with_extension("meta.jsonl");
+                let file = create_file(&path)?;
+                (Some(path), Some(file))
+            }
+        };
+        Ok(Self {
+            format,
+            header,
+            output_path: output_path.to_path_buf(),
+            metadata_path,
+            data,
+            meta,
+            dimension: None,
+            rows: 0,
+        })
+    }
+
+    /// 首条向量到达（或结束）时写出文件头，此时维度才确定
+    fn write_headers(&mut self, dimension: Option<usize>) -> Result<()> {
+        let mut header = serde_json::to_value(&self.header)
+            .map_err(|e| AppError::internal(format!("序列化导出头失败: {}", e)))?;
+        header["type"] = json!("header");
+        header["dimension"] = json!(dimension);
+        header["format"] = json!(self.format);
+        let line = header.to_string();
+        match self.meta.as_mut() {
+            Some(meta) => {
+                writeln!(meta, "{}", line).map_err(io_err)?;
+                self.data
+                    .write_all(&npy_header(0, dimension.unwrap_or(0)))
+                    .map_err(io_err)?;
+            }
+            None => writeln!(self.data, "{}", line).map_err(io_err)?,
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, row: &EmbeddingExportRow) -> Result<()> {
+        let dim = row.embedding.len();
+        match self.dimension {
+            None => {
+                self.write_headers(Some(dim))?;
+                self.dimension = Some(dim);
+            }
+            Some(expected) if expected != dim => {
+                return Err(AppError::validation(format!(
+                    "导出包含多种向量维度（{} 与 {}），请指定 dimension 分别导出",
+                    expected, dim
+                )));
+            }
+            Some(_) => {}
+        }
+
+        let mut record = json!({
+            "type": "embedding",
+            "row": self.rows,
+            "source": row.source,
+            "id": row.id,
+            "parentId": row.parent_id,
+            "subLibraryId": row.sub_library_id,
+            "text": row.text,
+        });
+        match self.meta.as_mut() {
+            Some(meta) => {
+                for value in &row.embedding {
+                    self.data.write_all(&value.to_le_bytes()).map_err(io_err)?;
+                }
+                writeln!(meta, "{}", record).map_err(io_err)?;
+            }
+            None => {
+                record["embedding"] = json!(row.embedding);
+                writeln!(self.data, "{}", record).map_err(io_err)?;
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<EmbeddingExportSummary> {
+        if self.dimension.is_none() {
+            self.write_headers(None)?;
+        }
+        if let Some(mut meta) = self.meta.take() {
+            meta.flush().map_err(io_err)?;
+            // 回填真实行数（头长度固定，原位覆盖）
+            self.data.flush().map_err(io_err)?;
+            let file = self.data.get_mut();
+            file.seek(SeekFrom::Start(0)).map_err(io_err)?;
+            file.write_all(&npy_header(self.rows, self.dimension.unwrap_or(0)))
+                .map_err(io_err)?;
+        }
+        self.data.flush().map_err(io_err)?;
+
+        Ok(EmbeddingExportSummary {
+            format: self.format,
+            output_path: self.output_path.to_string_lossy().to_string(),
+            metadata_path: self
+                .metadata_path
+                .as_ref()
+                .map(|p| p.to_string_lossy().to_string()),
+            row_count: self.rows,
+            dimension: self.dimension,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> EmbeddingExportHeader {
+        EmbeddingExportHeader {
+            scope: "chat".to_string(),
+            embedding_model_id: Some("bge-m3".to_string()),
+            embedding_model_config_id: None,
+            exported_at: "2026-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    fn row(id: &str, embedding: Vec<f32>) -> EmbeddingExportRow {
+        EmbeddingExportRow {
+            source: "chat",
+            id: id.to_string(),
+            parent_id: "m1".to_string(),
+            sub_library_id: None,
+            text: "题目".to_string(),
+            embedding,
+        }
+    }
+
+    #[test]
+    fn test_jsonl_export_writes_header_then_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.jsonl");
+        let mut writer =
+            EmbeddingExportWriter::create(&path, EmbeddingExportFormat::Jsonl, header()).unwrap();
+        writer.write(&row("a", vec![0.5, 1.0])).unwrap();
+        assert!(writer.write(&row("b", vec![0.5])).is_err());
+        let summary = writer.finish().unwrap();
+        assert_eq!(summary.row_count, 1);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["type"], "header");
+        assert_eq!(lines[0]["dimension"], 2);
+        assert_eq!(lines[0]["embeddingModelId"], "bge-m3");
+        assert_eq!(lines[1]["id"], "a");
+        assert_eq!(lines[1]["embedding"], json!([0.5, 1.0]));
+    }
+
+    #[test]
+    fn test_npy_export_backfills_row_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.npy");
+        let mut writer =
+            EmbeddingExportWriter::create(&path, EmbeddingExportFormat::Npy, header()).unwrap();
+        writer.write(&row("a", vec![1.0, 2.0, 3.0])).unwrap();
+        writer.write(&row("b", vec![4.0, 5.0, 6.0])).unwrap();
+        let summary = writer.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let dict = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(dict.contains("'shape': (                   2, 3)"));
+        assert_eq!(bytes.len(), 10 + header_len + 2 * 3 * 4);
+        let last = &bytes[bytes.len() - 4..];
+        assert_eq!(f32::from_le_bytes(last.try_into().unwrap()), 6.0);
+
+        let meta = std::fs::read_to_string(summary.metadata_path.unwrap()).unwrap();
+        assert_eq!(meta.lines().count(), 3);
+    }
+}
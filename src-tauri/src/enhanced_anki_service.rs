@@ -1,3 +1,8 @@
+use crate::anki_card_limits;
+use crate::anki_field_html;
+use crate::anki_field_selection::{self, CardFieldSelection};
+use crate::anki_prompt_placeholders::{resolve_prompt_placeholders, PromptPlaceholderContext};
+use crate::anki_source_tags;
 use crate::database::Database;
 use crate::document_processing_service::DocumentProcessingService;
 use crate::llm_manager::LLMManager;
@@ -78,6 +83,7 @@ impl EnhancedAnkiService {
             document_content,
             original_document_name,
             options,
+            subject,
         } = request;
 
         // 🔧 P0 修复 #4: 添加输入验证，防止注入攻击和资源耗尽
@@ -111,14 +117,24 @@ impl EnhancedAnkiService {
                     "单次生成卡片数量过多，最大支持 100 张",
                 ));
             }
+            if opts.max_cards_per_segment.is_some_and(|n| n < 0)
+                || opts.max_cards_per_document.is_some_and(|n| n < 0)
+            {
+                return Err(AppError::validation("卡片数量上限不能为负数"));
+            }
+            if let Some(facets) = opts.source_tag_facets.as_deref() {
+                anki_source_tags::validate_source_tag_facets(facets)?;
+            }
         }
 
-        let options = options.unwrap_or_else(|| AnkiGenerationOptions {
+        let mut options = options.unwrap_or_else(|| AnkiGenerationOptions {
             deck_name: "默认牌组".to_string(),
             note_type: "Basic".to_string(),
             enable_images: false,
             max_cards_per_mistake: 10,
             max_cards_total: None,
+            max_cards_per_segment: None,
+            max_cards_per_document: None,
             max_tokens: None,
             temperature: None,
             max_output_tokens_override: None,
@@ -135,6 +151,33 @@ impl EnhancedAnkiService {
             template_ids: None,
             template_descriptions: None,
             enable_llm_boundary_detection: None,
+            response_language: None,
+            output_mode: None,
+            subject: None,
+            source_tag_facets: None,
+            source_tag: None,
+            html_mode: None,
+            verbosity: None,
+        });
+
+        if options.subject.is_none() {
+            options.subject = subject
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty());
+        }
+
+        // 卡片语言在文档级别确定一次：`auto` 按全文推断，避免各分段语言不一致
+        options.response_language = crate::response_language::resolve_response_language(
+            options.response_language.as_deref(),
+            None,
+            crate::response_language::load_response_language_setting(&self.db).as_deref(),
+            trimmed_content,
+        );
+        options.verbosity = options.verbosity.or_else(|| {
+            crate::response_verbosity::load_verbosity_setting(
+                &self.db,
+                crate::response_verbosity::CARD_VERBOSITY_SETTING_KEY,
+            )
         });
 
         // 确定文档名称
@@ -197,6 +240,44 @@ impl EnhancedAnkiService {
         Ok(document_id)
     }
 
+    /// 发送前按分段补全选项：替换模板提示词中的占位符，计算来源标签
+    ///
+    /// 只作用于本次请求，数据库中的任务保留原始选项，暂停恢复与统一重试时按各自分段重新计算。
+    fn with_task_context(mut task: DocumentTask) -> DocumentTask {
+        // 选项解析失败时原样交给流式服务，由其报告错误
+        let Ok(mut options) =
+            serde_json::from_str::<AnkiGenerationOptions>(&task.anki_generation_options_json)
+        else {
+            return task;
+        };
+        let resolved_prompt = options.custom_anki_prompt.as_deref().map(|prompt| {
+            resolve_prompt_placeholders(
+                prompt,
+                &PromptPlaceholderContext {
+                    document_name: &task.original_document_name,
+                    segment: &task.content_segment,
+                    subject: options.subject.as_deref(),
+                    max_cards: anki_card_limits::requested_cards(&options),
+                },
+            )
+        });
+        let source_tag = anki_source_tags::source_tag(
+            &options,
+            &task.original_document_name,
+            task.segment_index,
+        );
+        if resolved_prompt == options.custom_anki_prompt && source_tag == options.source_tag {
+            return task;
+        }
+        options.custom_anki_prompt = resolved_prompt;
+        options.source_tag = source_tag;
+        match serde_json::to_string(&options) {
+            Ok(json) => task.anki_generation_options_json = json,
+            Err(e) => warn!("序列化替换占位符后的制卡选项失败: {}", e),
+        }
+        task
+    }
+
     /// 异步处理所有任务（支持并发执行）
     ///
     /// 并发控制策略：
@@ -244,7 +325,10 @@ impl EnhancedAnkiService {
                         let window_clone = window_clone.clone();
                         async move {
                             if let Err(e) = service
-                                .process_task_and_generate_cards_stream(task, window_clone)
+                                .process_task_and_generate_cards_stream(
+                                    Self::with_task_context(task),
+                                    window_clone,
+                                )
                                 .await
                             {
                                 warn!("任务处理失败: {}", e);
@@ -328,7 +412,10 @@ impl EnhancedAnkiService {
 
                     let handle = tokio::spawn(async move {
                         if let Err(e) = service
-                            .process_task_and_generate_cards_stream(retry_task, window_clone)
+                            .process_task_and_generate_cards_stream(
+                                Self::with_task_context(retry_task),
+                                window_clone,
+                            )
                             .await
                         {
                             warn!("统一重试任务处理失败: {}", e);
@@ -355,6 +442,8 @@ impl EnhancedAnkiService {
             }
         }
 
+        anki_card_limits::release_document(&document_id_for_check);
+
         // 调度完成，标记 running=false，如未暂停则清理状态
         if let Some(mut entry) = DOCUMENT_STATES.get_mut(&document_id_for_check) {
             entry.running = false;
@@ -558,7 +647,7 @@ impl EnhancedAnkiService {
 
         tokio::spawn(async move {
             if let Err(e) = streaming_service
-                .process_task_and_generate_cards_stream(task, window_clone)
+                .process_task_and_generate_cards_stream(Self::with_task_context(task), window_clone)
                 .await
             {
                 tracing::warn!("任务处理失败: {}", e);
@@ -631,6 +720,7 @@ impl EnhancedAnkiService {
             .map_err(|e| AppError::database(format!("删除文档会话失败: {}", e)))?;
 
         DOCUMENT_STATES.remove(&document_id);
+        anki_card_limits::release_document(&document_id);
         Ok(())
     }
 
@@ -641,6 +731,7 @@ impl EnhancedAnkiService {
         task_ids: Option<Vec<String>>,
         card_ids: Option<Vec<String>>,
         options: AnkiGenerationOptions,
+        field_selection: Option<CardFieldSelection>,
     ) -> Result<String, AppError> {
         // 根据选择获取卡片
         let cards = if let Some(ids) = card_ids {
@@ -677,7 +768,7 @@ impl EnhancedAnkiService {
 
         // 调用现有的APKG导出服务
         // 注意：这里需要将enhanced AnkiCard转换为原始AnkiCard格式
-        let simple_cards: Vec<crate::models::AnkiCard> = valid_cards
+        let mut simple_cards: Vec<crate::models::AnkiCard> = valid_cards
             .into_iter()
             .map(|card| crate::models::AnkiCard {
                 front: card.front,
@@ -695,6 +786,16 @@ impl EnhancedAnkiService {
                 template_id: card.template_id,
             })
             .collect();
+        anki_field_selection::apply_to_cards(
+            &mut simple_cards,
+            field_selection.as_ref(),
+            &anki_field_selection::template_fields(&self.db, options.template_id.as_deref()),
+        )?;
+        let html_mode = anki_field_html::resolve_html_mode(
+            options.html_mode.as_deref(),
+            anki_field_html::load_html_mode_setting(&self.db).as_deref(),
+        );
+        anki_field_html::apply_to_cards(&mut simple_cards, html_mode);
 
         // 使用现有的导出服务，支持模板
         let output_path =
@@ -865,6 +966,8 @@ mod tests {
             enable_images: false,
             max_cards_per_mistake: 2,
             max_cards_total: None,
+            max_cards_per_segment: None,
+            max_cards_per_document: None,
             max_tokens: None,
             temperature: None,
             max_output_tokens_override: None,
@@ -881,6 +984,13 @@ mod tests {
             template_ids: None,
             template_descriptions: None,
             enable_llm_boundary_detection: None,
+            response_language: None,
+            output_mode: None,
+            subject: None,
+            source_tag_facets: None,
+            source_tag: None,
+            html_mode: None,
+            verbosity: None,
         };
         let (doc_id, _tasks) = dps
             .process_document_and_create_tasks(
@@ -934,6 +1044,8 @@ mod tests {
             enable_images: false,
             max_cards_per_mistake: 2,
             max_cards_total: None,
+            max_cards_per_segment: None,
+            max_cards_per_document: None,
             max_tokens: None,
             temperature: None,
             max_output_tokens_override: None,
@@ -950,6 +1062,13 @@ mod tests {
             template_ids: None,
             template_descriptions: None,
             enable_llm_boundary_detection: None,
+            response_language: None,
+            output_mode: None,
+            subject: None,
+            source_tag_facets: None,
+            source_tag: None,
+            html_mode: None,
+            verbosity: None,
         };
         let (doc_id, _tasks) = dps
             .process_document_and_create_tasks(
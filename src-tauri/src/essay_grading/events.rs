@@ -1,6 +1,8 @@
 /// 作文批改事件发射器 - 负责发送 SSE 事件到前端
 use tauri::{Emitter, Window};
 
+use crate::stream_events::{emit_delta, emit_stream_event, StreamEvent, StreamSource};
+
 use super::types::{
     GradingStreamCancelled, GradingStreamComplete, GradingStreamData, GradingStreamError,
 };
@@ -24,14 +26,22 @@ impl GradingEventEmitter {
 
         let payload = GradingStreamData {
             event_type: "data".to_string(),
-            chunk,
+            chunk: chunk.clone(),
             accumulated,
             char_count,
         };
 
-        if let Err(e) = self.window.emit(&event_name, payload) {
-            eprintln!("❌ [EssayGrading] 发送数据事件失败: {}", e);
-        }
+        emit_delta(
+            &self.window,
+            StreamSource::EssayGrading,
+            stream_session_id,
+            StreamEvent::token(chunk),
+            || {
+                if let Err(e) = self.window.emit(&event_name, payload) {
+                    eprintln!("❌ [EssayGrading] 发送数据事件失败: {}", e);
+                }
+            },
+        );
     }
 
     /// 发送完成事件
@@ -54,6 +64,12 @@ impl GradingEventEmitter {
             created_at,
         };
 
+        emit_stream_event(
+            &self.window,
+            StreamSource::EssayGrading,
+            stream_session_id,
+            StreamEvent::done(serde_json::to_value(&payload).ok()),
+        );
         if let Err(e) = self.window.emit(&event_name, payload) {
             eprintln!("❌ [EssayGrading] 发送完成事件失败: {}", e);
         }
@@ -67,6 +83,12 @@ impl GradingEventEmitter {
             message,
         };
 
+        emit_stream_event(
+            &self.window,
+            StreamSource::EssayGrading,
+            stream_session_id,
+            StreamEvent::error(payload.message.clone()),
+        );
         if let Err(e) = self.window.emit(&event_name, payload) {
             eprintln!("❌ [EssayGrading] 发送错误事件失败: {}", e);
         }
@@ -79,6 +101,12 @@ impl GradingEventEmitter {
             event_type: "cancelled".to_string(),
         };
 
+        emit_stream_event(
+            &self.window,
+            StreamSource::EssayGrading,
+            stream_session_id,
+            StreamEvent::cancelled(),
+        );
         if let Err(e) = self.window.emit(&event_name, payload) {
             eprintln!("❌ [EssayGrading] 发送取消事件失败: {}", e);
         }
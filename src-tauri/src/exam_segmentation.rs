@@ -0,0 +1,213 @@
+//! Stage 2.5: Exam Segmentation — 按题号细分题目并生成逐题预览
+//!
+//! VLM 偶尔会把同一区域内连续编号的多道题目识别为一道（尤其是排版紧凑的选择题），
+//! 导致后续关联错题时无法定位到单题。本模块：
+//! - 按行首题号（`1.` `2、` `第3题` 等）把这类题目拆分为独立题目，
+//!   并按行数比例纵向切分边界框、重新分配配图；
+//! - 把 VLM 的 `[x1, y1, x2, y2]` 边界框转换为预览卡片使用的 `ExamCardBBox`，
+//!   使整卷预览按页、按题保存切分结果（写入 `preview_json`）。
+//!
+//! 手动调整边界沿用 `update_exam_sheet_cards`（修改 bbox / 新增 / 删除卡片）。
+
+use regex::Regex;
+use std::sync::LazyLock;
+use tracing::debug;
+
+use crate::models::ExamCardBBox;
+use crate::vlm_grounding_service::{VlmPageAnalysis, VlmQuestion};
+
+/// 行首题号：`12.` `12．` `12、` `第12题`（不匹配 `(1)` 小问与 `1.5` 小数）
+static QUESTION_NUMBER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*(?:第\s*(\d{1,3})\s*题|(\d{1,3})\s*([\.．、]))").unwrap());
+
+/// 解析行首题号
+fn leading_question_number(line: &str) -> Option<u32> {
+    let caps = QUESTION_NUMBER_RE.captures(line)?;
+    if let Some(num) = caps.get(1) {
+        return num.as_str().parse().ok();
+    }
+    let num = caps.get(2)?;
+    // `1.5` 是小数而非题号
+    if caps.get(3).map(|m| m.as_str()) == Some(".") {
+        let rest = &line[caps.get(0)?.end()..];
+        if rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+    }
+    num.as_str().parse().ok()
+}
+
+/// 找出可拆分的题号起始行：`(行号, 题号)`
+///
+/// 仅当首个非空行即为题号、题号从 `label`（若为数字）开始连续递增且至少有两道时才拆分，
+/// 避免把材料题、解答步骤误拆。
+fn numbered_starts(question: &VlmQuestion, lines: &[&str]) -> Option<Vec<(usize, u32)>> {
+    if question.is_group || !question.sub_questions.is_empty() {
+        return None;
+    }
+    let first_line = lines.iter().position(|l| !l.trim().is_empty())?;
+    let mut starts: Vec<(usize, u32)> = Vec::new();
+    for (idx, line) in lines.iter().enumerate().skip(first_line) {
+        let Some(number) = leading_question_number(line) else {
+            continue;
+        };
+        match starts.last() {
+            None if idx == first_line => starts.push((idx, number)),
+            None => return None,
+            Some((_, prev)) if number == prev + 1 => starts.push((idx, number)),
+            // 非连续题号视为题干内容（如解答步骤中的编号）
+            Some(_) => {}
+        }
+    }
+    if starts.len() < 2 {
+        return None;
+    }
+    if let Ok(label_number) = question.label.trim().parse::<u32>() {
+        if label_number != starts[0].1 {
+            return None;
+        }
+    }
+    Some(starts)
+}
+
+/// 将一道“多题合一”的题目按题号拆分；不满足拆分条件时原样返回
+pub fn split_numbered_question(question: VlmQuestion) -> Vec<VlmQuestion> {
+    let lines: Vec<&str> = question.raw_text.lines().collect();
+    let Some(starts) = numbered_starts(&question, &lines) else {
+        return vec![question];
+    };
+
+    let [x1, y1, x2, y2] = question.bbox;
+    let total_lines = lines.len().max(1) as f64;
+    let count = starts.len();
+    let mut parts = Vec::with_capacity(count);
+
+    for (i, (start, number)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map(|(s, _)| *s).unwrap_or(lines.len());
+        let top = y1 + (y2 - y1) * (*start as f64 / total_lines);
+        let bottom = y1 + (y2 - y1) * (end as f64 / total_lines);
+        let top = if i == 0 { y1 } else { top };
+        let bottom = if i + 1 == count { y2 } else { bottom };
+
+        parts.push(VlmQuestion {
+            label: number.to_string(),
+            bbox: [x1, top, x2, bottom],
+            raw_text: lines[*start..end].join("\n").trim().to_string(),
+            is_group: false,
+            sub_questions: Vec::new(),
+            figures: Vec::new(),
+            continues_from_previous: i == 0 && question.continues_from_previous,
+            continues_to_next: i + 1 == count && question.continues_to_next,
+            split_by_numbering: true,
+        });
+    }
+
+    // 配图按中心点归入所在的纵向区间，落在区间外的归入最近的一题
+    for figure in question.figures {
+        let center = (figure.bbox[1] + figure.bbox[3]) / 2.0;
+        let target = parts
+            .iter()
+            .position(|p| center >= p.bbox[1] && center <= p.bbox[3])
+            .unwrap_or(if center < y1 { 0 } else { count - 1 });
+        parts[target].figures.push(figure);
+    }
+
+    debug!(
+        "[ExamSegmentation] 题目 '{}' 按题号拆分为 {} 道",
+        question.label, count
+    );
+    parts
+}
+
+/// 对单页 VLM 分析结果做题号细分
+pub fn segment_page(analysis: VlmPageAnalysis) -> VlmPageAnalysis {
+    VlmPageAnalysis {
+        questions: analysis
+            .questions
+            .into_iter()
+            .flat_map(split_numbered_question)
+            .collect(),
+    }
+}
+
+/// VLM `[x1, y1, x2, y2]`（0~1）→ 预览卡片 `ExamCardBBox`（x, y, width, height）
+///
+/// 面积为 0 的框（VLM 未给出坐标）返回 `None`。
+pub fn to_card_bbox(bbox: [f64; 4]) -> Option<ExamCardBBox> {
+    let [x1, y1, x2, y2] = bbox.map(|v| v.clamp(0.0, 1.0));
+    let (left, right) = (x1.min(x2), x1.max(x2));
+    let (top, bottom) = (y1.min(y2), y1.max(y2));
+    if right - left <= f64::EPSILON || bottom - top <= f64::EPSILON {
+        return None;
+    }
+    Some(ExamCardBBox {
+        x: left as f32,
+        y: top as f32,
+        width: (right - left) as f32,
+        height: (bottom - top) as f32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vlm_grounding_service::VlmFigure;
+
+    fn question(label: &str, raw_text: &str) -> VlmQuestion {
+        serde_json::from_value(serde_json::json!({
+            "label": label,
+            "bbox": [0.1, 0.2, 0.9, 0.6],
+            "raw_text": raw_text,
+            "continues_to_next": true
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_split_consecutive_numbers() {
+        let mut q = question(
+            "3",
+            "3. 计算 1.5+2\nA. 3.5 B. 4\n4、下列说法正确的是\n5．第三题\n(1) 小问",
+        );
+        q.figures.push(VlmFigure {
+            bbox: [0.5, 0.55, 0.6, 0.58],
+            fig_label: "图1".to_string(),
+        });
+
+        let parts = split_numbered_question(q);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].label, "3");
+        assert_eq!(parts[0].raw_text, "3. 计算 1.5+2\nA. 3.5 B. 4");
+        assert_eq!(parts[2].raw_text, "5．第三题\n(1) 小问");
+        assert_eq!(parts[0].bbox[1], 0.2);
+        assert_eq!(parts[2].bbox[3], 0.6);
+        assert!((parts[1].bbox[1] - 0.36).abs() < 1e-9);
+        assert!(!parts[0].continues_to_next && parts[2].continues_to_next);
+        assert_eq!(parts[2].figures.len(), 1);
+        assert!(parts.iter().all(|p| p.split_by_numbering));
+    }
+
+    #[test]
+    fn test_keep_question_without_clear_numbering() {
+        // 首行不是题号（材料题）
+        assert_eq!(
+            split_numbered_question(question("", "阅读材料\n1. 问题一\n2. 问题二")).len(),
+            1
+        );
+        // 题号与标签不一致
+        assert_eq!(
+            split_numbered_question(question("7", "1. 第一步\n2. 第二步")).len(),
+            1
+        );
+        // 小数与单个题号不拆分
+        assert_eq!(
+            split_numbered_question(question("1", "1. 求值\n2.5 乘以 4")).len(),
+            1
+        );
+
+        assert!(to_card_bbox([0.0, 0.0, 0.0, 0.0]).is_none());
+        let bbox = to_card_bbox([0.9, 0.2, 0.1, 1.2]).unwrap();
+        assert_eq!((bbox.x, bbox.y), (0.1, 0.2));
+        assert!((bbox.width - 0.8).abs() < 1e-6 && (bbox.height - 0.8).abs() < 1e-6);
+    }
+}
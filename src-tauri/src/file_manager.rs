@@ -7,10 +7,10 @@ use crate::models::AppError;
 use base64::{engine::general_purpose, Engine as _};
 use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageOutputFormat};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use tracing::{debug, error, info, warn};
 use urlencoding::decode as url_decode;
-use uuid::Uuid;
 
 type Result<T> = std::result::Result<T, AppError>;
 
@@ -21,11 +21,110 @@ pub struct ImageStatistics {
     pub file_types: HashMap<String, u32>, // extension -> count
     pub oldest_file: Option<u64>,         // timestamp
     pub newest_file: Option<u64>,         // timestamp
+    /// 被多处引用的去重图片数
+    #[serde(default)]
+    pub deduplicated_files: u64,
+    /// 因去重而未重复写入的图片次数
+    #[serde(default)]
+    pub dedup_reused_count: u64,
+    /// 去重节省的磁盘空间
+    #[serde(default)]
+    pub dedup_saved_bytes: u64,
+}
+
+/// 内容寻址图片的引用计数索引。
+///
+/// 放在 images 下的隐藏子目录中：随图片目录一起迁移/备份，且不会被孤立图片清理当作图片扫描。
+const IMAGE_DEDUP_INDEX_FILE: &str = ".dedup/index.json";
+
+/// 孤立图片扫描的进度回调间隔（文件数）
+pub const ORPHAN_SCAN_PROGRESS_INTERVAL: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ImageDedupEntry {
+    /// 相对路径（images/<sha256>.<ext>）
+    path: String,
+    size_bytes: u64,
+    ref_count: u32,
+}
+
+/// SHA-256 → 图片条目
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ImageDedupIndex {
+    #[serde(default)]
+    entries: HashMap<String, ImageDedupEntry>,
+}
+
+impl ImageDedupIndex {
+    /// 登记一次引用，返回应使用的路径（命中时为已有路径）
+    fn add_reference(&mut self, hash: &str, path: &str, size_bytes: u64) -> String {
+        let entry = self
+            .entries
+            .entry(hash.to_string())
+            .or_insert_with(|| ImageDedupEntry {
+                path: path.to_string(),
+                size_bytes,
+                ref_count: 0,
+            });
+        entry.ref_count += 1;
+        entry.path.clone()
+    }
+
+    fn find_by_path(&self, path: &str) -> Option<String> {
+        self.entries
+            .iter()
+            .find(|(_, entry)| entry.path == path)
+            .map(|(hash, _)| hash.clone())
+    }
+
+    /// 增加已登记路径的引用；未登记返回 false
+    fn retain_path(&mut self, path: &str) -> bool {
+        match self.find_by_path(path) {
+            Some(hash) => {
+                if let Some(entry) = self.entries.get_mut(&hash) {
+                    entry.ref_count += 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 释放一次引用：未登记返回 None，否则返回引用是否已归零（归零时移除条目）
+    fn release_path(&mut self, path: &str) -> Option<bool> {
+        let hash = self.find_by_path(path)?;
+        let entry = self.entries.get_mut(&hash)?;
+        entry.ref_count = entry.ref_count.saturating_sub(1);
+        if entry.ref_count == 0 {
+            self.entries.remove(&hash);
+            Some(true)
+        } else {
+            Some(false)
+        }
+    }
+
+    /// 移除指向该路径的条目（文件已被删除）
+    fn forget_path(&mut self, path: &str) {
+        self.entries.retain(|_, entry| entry.path != path);
+    }
+
+    /// (被多处引用的图片数, 复用次数, 节省字节数)
+    fn savings(&self) -> (u64, u64, u64) {
+        self.entries
+            .values()
+            .filter(|entry| entry.ref_count > 1)
+            .fold((0, 0, 0), |(files, reused, bytes), entry| {
+                let extra = (entry.ref_count - 1) as u64;
+                (files + 1, reused + extra, bytes + extra * entry.size_bytes)
+            })
+    }
 }
 
 pub struct FileManager {
     app_data_dir: PathBuf,
     images_dir: PathBuf,
+    /// 串行化去重索引的读-改-写
+    dedup_lock: std::sync::Mutex<()>,
 }
 
 impl FileManager {
@@ -38,9 +137,26 @@ impl FileManager {
         Ok(FileManager {
             app_data_dir,
             images_dir,
+            dedup_lock: std::sync::Mutex::new(()),
         })
     }
 
+    fn images_dir(&self) -> PathBuf {
+        self.images_dir.clone()
+    }
+
+    /// 将 app 相对路径解析为绝对路径
+    fn resolve_data_path(&self, relative_path: &str) -> PathBuf {
+        self.app_data_dir.join(relative_path)
+    }
+
+    /// 允许访问的规范化根目录
+    fn canonical_data_roots(&self) -> Result<Vec<PathBuf>> {
+        let base = std::fs::canonicalize(&self.app_data_dir)
+            .map_err(|e| AppError::file_system(format!("解析app_data_dir失败: {}", e)))?;
+        Ok(vec![base])
+    }
+
     /// 按视觉质量策略调整 base64 图片（真正的图片压缩）
     ///
     /// ## 质量策略
@@ -220,7 +336,7 @@ impl FileManager {
 
     /// 获取 images 根目录的绝对路径
     pub fn images_directory(&self) -> PathBuf {
-        self.images_dir.clone()
+        self.images_dir()
     }
 
     /// 将 `images/` 相对路径转换为绝对路径（带路径遍历防护）
@@ -233,15 +349,16 @@ impl FileManager {
             return path.to_path_buf();
         }
 
+        let images_dir = self.images_dir();
         let trimmed = relative_path
             .strip_prefix("images/")
             .unwrap_or(relative_path)
             .trim_start_matches('/');
-        let candidate = self.images_dir.join(trimmed);
+        let candidate = images_dir.join(trimmed);
 
         // canonicalize 可能失败（文件尚不存在），此时回退到逐段检查
         if let Ok(canonical) = std::fs::canonicalize(&candidate) {
-            if let Ok(base) = std::fs::canonicalize(&self.images_dir) {
+            if let Ok(base) = std::fs::canonicalize(&images_dir) {
                 if canonical.starts_with(&base) {
                     return canonical;
                 }
@@ -250,7 +367,7 @@ impl FileManager {
                     canonical.display(),
                     base.display()
                 );
-                return self.images_dir.clone();
+                return images_dir;
             }
         }
 
@@ -258,55 +375,129 @@ impl FileManager {
         for component in candidate.components() {
             if component == std::path::Component::ParentDir {
                 warn!("路径遍历拦截: 检测到 '..' 组件 in {}", relative_path);
-                return self.images_dir.clone();
+                return images_dir;
             }
         }
         candidate
     }
 
-    /// 保存base64编码的图片文件
-    pub async fn save_image_from_base64(
-        &self,
-        base64_data: &str,
-        filename: &str,
-    ) -> Result<String> {
-        // save image
-
-        // 确保图片目录存在
-        async_fs::create_dir_all(&self.images_dir)
-            .await
-            .map_err(|e| AppError::file_system(format!("创建图片目录失败: {}", e)))?;
+    /// 按内容去重保存 base64 图片，内容相同的图片只存一份
+    ///
+    /// 文件名为内容的 SHA-256；命中已有图片时直接返回已有路径并增加引用计数。
+    /// 调用方不再引用该图片时应通过 `delete_image`/`delete_images` 释放引用。
+    pub async fn save_image_from_base64_dedup(&self, base64_data: &str) -> Result<String> {
+        let extension = self
+            .extract_extension_from_base64(base64_data)
+            .unwrap_or_else(|_| "png".to_string());
+        let image_bytes = Self::decode_base64_payload(base64_data)?;
+        self.save_image_deduplicated(&image_bytes, &extension)
+    }
 
-        // 解析base64数据
-        let data_url_prefix = "data:image/";
-        let base64_start = if base64_data.starts_with(data_url_prefix) {
-            base64_data
-                .find("base64,")
-                .ok_or_else(|| AppError::validation("无效的base64数据格式"))?
-                + 7 // "base64,".len()
+    fn save_image_deduplicated(&self, image_data: &[u8], file_extension: &str) -> Result<String> {
+        let hash = hex::encode(Sha256::digest(image_data));
+        let extension = file_extension.trim_start_matches('.').to_ascii_lowercase();
+        let filename = format!("{}.{}", hash, extension);
+
+        let _lock = self.dedup_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut index = self.load_dedup_index();
+        let existing = index.entries.get(&hash).map(|entry| entry.path.clone());
+        // 命中但文件已被外部删除时按原路径补写，保持已有引用可用
+        let relative_path = existing.unwrap_or_else(|| format!("images/{}", filename));
+        let file_path = self.resolve_data_path(&relative_path);
+        if !file_path.exists() {
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| AppError::file_system(format!("创建图片目录失败: {}", e)))?;
+            }
+            fs::write(&file_path, image_data)
+                .map_err(|e| AppError::file_system(format!("保存图片文件失败: {}", e)))?;
         } else {
-            0
-        };
+            debug!("图片去重命中: {}", relative_path);
+        }
 
-        let base64_content = &base64_data[base64_start..];
-        let image_bytes = general_purpose::STANDARD
-            .decode(base64_content)
-            .map_err(|e| AppError::validation(format!("base64解码失败: {}", e)))?;
+        let relative_path = index.add_reference(&hash, &relative_path, image_data.len() as u64);
+        self.save_dedup_index(&index)?;
+        Ok(relative_path)
+    }
 
-        // 保存文件
-        let file_path = self.images_dir.join(filename);
-        // 确保父目录存在（允许嵌套子目录，如 images/textbook_thumbs）
-        if let Some(parent) = file_path.parent() {
-            async_fs::create_dir_all(parent)
-                .await
-                .map_err(|e| AppError::file_system(format!("创建图片父目录失败: {}", e)))?;
+    /// 为已去重保存的图片增加一次引用（如卡片复用错题图片）；非去重图片返回 false
+    pub fn retain_image(&self, relative_path: &str) -> Result<bool> {
+        let _lock = self.dedup_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut index = self.load_dedup_index();
+        if !index.retain_path(relative_path) {
+            return Ok(false);
         }
-        async_fs::write(&file_path, image_bytes)
-            .await
-            .map_err(|e| AppError::file_system(format!("保存图片文件失败: {}", e)))?;
+        self.save_dedup_index(&index)?;
+        Ok(true)
+    }
 
-        // 返回相对路径
-        Ok(format!("images/{}", filename))
+    /// 释放去重图片的一次引用，返回物理文件是否可以删除
+    ///
+    /// 非去重图片总是返回 true。
+    fn release_image_reference(&self, relative_path: &str) -> Result<bool> {
+        let _lock = self.dedup_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut index = self.load_dedup_index();
+        match index.release_path(relative_path) {
+            None => Ok(true),
+            Some(orphaned) => {
+                self.save_dedup_index(&index)?;
+                if !orphaned {
+                    debug!("图片仍被其他记录引用，保留文件: {}", relative_path);
+                }
+                Ok(orphaned)
+            }
+        }
+    }
+
+    /// 错题被彻底删除或替换图片后释放其去重图片引用
+    ///
+    /// 只更新引用计数、不删除文件：引用归零的图片交给孤立图片清理回收，非去重图片忽略。
+    pub fn release_images(&self, relative_paths: &[String]) -> Result<()> {
+        if relative_paths.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.dedup_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut index = self.load_dedup_index();
+        let mut changed = false;
+        for path in relative_paths {
+            changed |= index.release_path(path).is_some();
+        }
+        if changed {
+            self.save_dedup_index(&index)?;
+        }
+        Ok(())
+    }
+
+    fn dedup_index_path(&self) -> PathBuf {
+        self.images_dir().join(IMAGE_DEDUP_INDEX_FILE)
+    }
+
+    /// 读取去重索引；不存在或损坏时视为空索引（已有图片仍按普通图片处理）
+    fn load_dedup_index(&self) -> ImageDedupIndex {
+        let path = self.dedup_index_path();
+        let Ok(raw) = fs::read_to_string(&path) else {
+            return ImageDedupIndex::default();
+        };
+        serde_json::from_str(&raw).unwrap_or_else(|e| {
+            warn!("图片去重索引损坏，已忽略: {:?} - {}", path, e);
+            ImageDedupIndex::default()
+        })
+    }
+
+    fn save_dedup_index(&self, index: &ImageDedupIndex) -> Result<()> {
+        let path = self.dedup_index_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| AppError::file_system(format!("创建去重索引目录失败: {}", e)))?;
+        }
+        let json = serde_json::to_vec(index)
+            .map_err(|e| AppError::internal(format!("序列化去重索引失败: {}", e)))?;
+        // 先写临时文件再替换，避免中途崩溃留下半截索引
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, json)
+            .map_err(|e| AppError::file_system(format!("写入去重索引失败: {}", e)))?;
+        fs::rename(&tmp_path, &path)
+            .map_err(|e| AppError::file_system(format!("替换去重索引失败: {}", e)))
     }
 
     /// 读取图片文件为base64（用于统一AI接口）
@@ -354,13 +545,12 @@ impl FileManager {
         let requested = if is_absolute {
             std::path::PathBuf::from(&pstr)
         } else {
-            self.app_data_dir.join(&pstr)
+            self.resolve_data_path(&pstr)
         };
-        let base = std::fs::canonicalize(&self.app_data_dir)
-            .map_err(|e| AppError::file_system(format!("解析app_data_dir失败: {}", e)))?;
+        let bases = self.canonical_data_roots()?;
         // canonicalize in blocking without async/await (this function is not async)
         let can = std::fs::canonicalize(&requested).unwrap_or(requested.clone());
-        if !can.starts_with(&base) {
+        if !bases.iter().any(|b| can.starts_with(b)) {
             return Err(AppError::validation("拒绝访问：超出应用数据目录"));
         }
 
@@ -419,17 +609,16 @@ impl FileManager {
         let requested = if is_absolute {
             std::path::PathBuf::from(&pstr)
         } else {
-            self.app_data_dir.join(&pstr)
+            self.resolve_data_path(&pstr)
         };
-        let base = std::fs::canonicalize(&self.app_data_dir)
-            .map_err(|e| AppError::file_system(format!("解析app_data_dir失败: {}", e)))?;
+        let bases = self.canonical_data_roots()?;
         let req_clone = requested.clone();
         let can = match tokio::task::spawn_blocking(move || std::fs::canonicalize(&req_clone)).await
         {
             Ok(Ok(path)) => path,
             _ => requested.clone(),
         };
-        if !can.starts_with(&base) {
+        if !bases.iter().any(|b| can.starts_with(b)) {
             return Err(AppError::validation("拒绝访问：超出应用数据目录"));
         }
 
@@ -456,6 +645,8 @@ impl FileManager {
     }
 
     /// 删除图片文件（带路径遍历防护）
+    ///
+    /// 去重图片仅释放一次引用，引用归零时才删除物理文件。
     pub async fn delete_image(&self, relative_path: &str) -> Result<()> {
         if relative_path.trim().is_empty() {
             return Err(AppError::validation("路径为空"));
@@ -463,8 +654,11 @@ impl FileManager {
         if std::path::Path::new(relative_path).is_absolute() {
             return Err(AppError::validation("拒绝删除：仅允许相对路径"));
         }
+        if !self.release_image_reference(relative_path)? {
+            return Ok(());
+        }
 
-        let file_path = self.app_data_dir.join(relative_path);
+        let file_path = self.resolve_data_path(relative_path);
         if !async_fs::try_exists(&file_path)
             .await
             .map_err(|e| AppError::file_system(format!("检查文件存在性失败: {}", e)))?
@@ -472,8 +666,7 @@ impl FileManager {
             return Ok(());
         }
 
-        let base = std::fs::canonicalize(&self.app_data_dir)
-            .map_err(|e| AppError::file_system(format!("解析 app_data_dir 失败: {}", e)))?;
+        let bases = self.canonical_data_roots()?;
         let fp_clone = file_path.clone();
         let canonical = match tokio::task::spawn_blocking(move || std::fs::canonicalize(&fp_clone))
             .await
@@ -481,7 +674,7 @@ impl FileManager {
             Ok(Ok(p)) => p,
             _ => file_path.clone(),
         };
-        if !canonical.starts_with(&base) {
+        if !bases.iter().any(|b| canonical.starts_with(b)) {
             return Err(AppError::validation("拒绝删除：路径越界"));
         }
 
@@ -510,23 +703,25 @@ impl FileManager {
         }
     }
 
-    /// 删除多个图片文件（带路径遍历防护）
+    /// 删除多个图片文件（带路径遍历防护，去重图片按引用计数释放）
     pub fn delete_images(&self, relative_paths: &[String]) -> Result<()> {
-        let base = std::fs::canonicalize(&self.app_data_dir)
-            .map_err(|e| AppError::file_system(format!("解析 app_data_dir 失败: {}", e)))?;
+        let bases = self.canonical_data_roots()?;
 
         for path in relative_paths {
             if path.trim().is_empty() || std::path::Path::new(path).is_absolute() {
                 warn!("delete_images: 跳过非法路径 {}", path);
                 continue;
             }
-            let file_path = self.app_data_dir.join(path);
+            if !self.release_image_reference(path)? {
+                continue;
+            }
+            let file_path = self.resolve_data_path(path);
             if !file_path.exists() {
                 continue;
             }
             let canonical = std::fs::canonicalize(&file_path)
                 .map_err(|e| AppError::file_system(format!("解析文件路径失败: {}", e)))?;
-            if !canonical.starts_with(&base) {
+            if !bases.iter().any(|b| canonical.starts_with(b)) {
                 warn!("delete_images: 路径越界拦截 {}", path);
                 continue;
             }
@@ -543,19 +738,73 @@ impl FileManager {
     ) -> Result<Vec<String>> {
         // cleanup orphan images
 
-        if !async_fs::try_exists(&self.images_dir)
+        let orphaned = self.find_orphaned_images(database, false, &|_| {}).await?;
+        let mut cleaned_files = Vec::new();
+
+        for (physical_file, _) in &orphaned {
+            info!("发现孤立图片文件: {}", physical_file);
+
+            // 删除孤立文件
+            match self.delete_orphaned_image(physical_file).await {
+                Ok(()) => {
+                    cleaned_files.push(physical_file.clone());
+                    info!("已删除孤立图片: {}", physical_file);
+                }
+                Err(e) => {
+                    error!("删除孤立图片失败: {} - {}", physical_file, e);
+                }
+            }
+        }
+
+        // 清理空的子目录
+        if async_fs::try_exists(&self.images_dir())
+            .await
+            .unwrap_or(false)
+        {
+            self.cleanup_empty_directories().await?;
+        }
+
+        info!("清理完成，删除了 {} 个孤立图片文件", cleaned_files.len());
+        Ok(cleaned_files)
+    }
+
+    /// 删除一张已确认孤立的图片：不看引用计数直接删除文件，并移除其去重索引条目
+    pub async fn delete_orphaned_image(&self, relative_path: &str) -> Result<()> {
+        let full_path = self.resolve_data_path(relative_path);
+        async_fs::remove_file(&full_path)
+            .await
+            .map_err(|e| AppError::file_system(format!("删除孤立图片失败: {}", e)))?;
+        let _lock = self.dedup_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut index = self.load_dedup_index();
+        if index.find_by_path(relative_path).is_some() {
+            index.forget_path(relative_path);
+            self.save_dedup_index(&index)?;
+        }
+        Ok(())
+    }
+
+    /// 查找孤立图片（只读），返回相对路径及文件大小
+    ///
+    /// 数据库未引用、且去重索引中没有剩余引用的顶层图片视为孤立；
+    /// `exclude_trashed` 为 true 时按回收站已清空计算（回收站错题的引用视为已释放）。
+    /// `on_scanned` 每扫描 [`ORPHAN_SCAN_PROGRESS_INTERVAL`] 个文件回调一次已扫描数。
+    pub async fn find_orphaned_images(
+        &self,
+        database: &crate::database::Database,
+        exclude_trashed: bool,
+        on_scanned: &(dyn Fn(usize) + Sync),
+    ) -> Result<Vec<(String, u64)>> {
+        if !async_fs::try_exists(&self.images_dir())
             .await
             .map_err(|e| AppError::file_system(format!("检查图片目录存在性失败: {}", e)))?
         {
-            warn!("图片目录不存在，跳过清理");
+            warn!("图片目录不存在，跳过孤立图片扫描");
             return Ok(vec![]);
         }
 
-        let mut cleaned_files = Vec::new();
-
         // 1. 收集所有物理图片文件
-        let mut all_physical_files = std::collections::HashSet::new();
-        let mut entries = async_fs::read_dir(&self.images_dir)
+        let mut all_physical_files = HashMap::new();
+        let mut entries = async_fs::read_dir(&self.images_dir())
             .await
             .map_err(|e| AppError::file_system(format!("读取图片目录失败: {}", e)))?;
 
@@ -569,79 +818,92 @@ impl FileManager {
                 if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
                     // 构建相对路径（相对于app_data_dir）
                     let relative_path = format!("images/{}", filename);
-                    all_physical_files.insert(relative_path);
+                    let size = entry.metadata().await.map(|m| m.len()).unwrap_or(0);
+                    all_physical_files.insert(relative_path, size);
+                    if all_physical_files.len() % ORPHAN_SCAN_PROGRESS_INTERVAL == 0 {
+                        on_scanned(all_physical_files.len());
+                    }
                 }
             }
         }
+        on_scanned(all_physical_files.len());
 
         info!("发现 {} 个物理图片文件", all_physical_files.len());
 
-        // 2. 从数据库获取所有被引用的图片路径
-        let referenced_images = self.get_referenced_images(database)?;
+        // 2. 从数据库获取所有被引用的图片路径，并加上去重索引中仍被引用的图片
+        let (mut referenced_images, trashed_images) =
+            self.get_referenced_images(database, exclude_trashed)?;
         info!("数据库中引用了 {} 个图片文件", referenced_images.len());
-
-        // 3. 找出孤立的图片文件
-        for physical_file in &all_physical_files {
-            if !referenced_images.contains(physical_file) {
-                info!("发现孤立图片文件: {}", physical_file);
-
-                // 删除孤立文件
-                let full_path = self.app_data_dir.join(physical_file);
-                match async_fs::remove_file(&full_path).await {
-                    Ok(()) => {
-                        cleaned_files.push(physical_file.clone());
-                        info!("已删除孤立图片: {}", physical_file);
-                    }
-                    Err(e) => {
-                        error!("删除孤立图片失败: {} - {}", physical_file, e);
-                    }
-                }
+        {
+            let _lock = self.dedup_lock.lock().unwrap_or_else(|e| e.into_inner());
+            let mut index = self.load_dedup_index();
+            // 只在内存中模拟释放，不写回索引
+            for path in &trashed_images {
+                index.release_path(path);
             }
+            referenced_images.extend(index.entries.into_values().map(|entry| entry.path));
         }
 
-        // 4. 清理空的子目录
-        self.cleanup_empty_directories().await?;
-
-        info!("清理完成，删除了 {} 个孤立图片文件", cleaned_files.len());
-        Ok(cleaned_files)
+        // 3. 找出孤立的图片文件
+        let mut orphaned: Vec<(String, u64)> = all_physical_files
+            .into_iter()
+            .filter(|(path, _)| !referenced_images.contains(path))
+            .collect();
+        orphaned.sort();
+        Ok(orphaned)
     }
 
     /// 从数据库获取所有被引用的图片路径
+    ///
+    /// `exclude_trashed` 为 true 时回收站中的错题不计入引用，其图片单独返回，
+    /// 用于预览清空回收站之后的孤立图片。
     fn get_referenced_images(
         &self,
         database: &crate::database::Database,
-    ) -> Result<std::collections::HashSet<String>> {
+        exclude_trashed: bool,
+    ) -> Result<(std::collections::HashSet<String>, Vec<String>)> {
         use rusqlite::params;
 
         let conn = database
             .get_conn_safe()
             .map_err(|e| AppError::database(e.to_string()))?;
         let mut referenced_images = std::collections::HashSet::new();
+        let mut trashed_images = Vec::new();
+
+        let trashed_sql = if exclude_trashed
+            && crate::database::has_column(&conn, "mistakes", "deleted_at")
+                .map_err(|e| AppError::database(format!("检查错题表结构失败: {}", e)))?
+        {
+            "deleted_at IS NOT NULL"
+        } else {
+            "0"
+        };
 
         // 查询所有错题的图片路径
         let mut stmt = conn
-            .prepare("SELECT question_images, analysis_images FROM mistakes")
+            .prepare(&format!(
+                "SELECT question_images, analysis_images, {} FROM mistakes",
+                trashed_sql
+            ))
             .map_err(|e| AppError::database(format!("准备查询语句失败: {}", e)))?;
 
         let rows = stmt
             .query_map(params![], |row| {
                 let question_images: String = row.get(0)?;
                 let analysis_images: String = row.get(1)?;
-                Ok((question_images, analysis_images))
+                let trashed: bool = row.get(2)?;
+                Ok((question_images, analysis_images, trashed))
             })
             .map_err(|e| AppError::database(format!("执行查询失败: {}", e)))?;
 
         for row_result in rows {
-            let (question_images_json, analysis_images_json) =
+            let (question_images_json, analysis_images_json, trashed) =
                 row_result.map_err(|e| AppError::database(format!("读取行数据失败: {}", e)))?;
+            let mut paths = Vec::new();
 
             // 解析JSON数组 - 改进错误处理以防止数据丢失
             match serde_json::from_str::<Vec<String>>(&question_images_json) {
-                Ok(question_paths) => {
-                    for path in question_paths {
-                        referenced_images.insert(path);
-                    }
-                }
+                Ok(question_paths) => paths.extend(question_paths),
                 Err(e) => {
                     warn!(
                         "解析question_images JSON失败: {} - 数据: {}",
@@ -656,11 +918,7 @@ impl FileManager {
             }
 
             match serde_json::from_str::<Vec<String>>(&analysis_images_json) {
-                Ok(analysis_paths) => {
-                    for path in analysis_paths {
-                        referenced_images.insert(path);
-                    }
-                }
+                Ok(analysis_paths) => paths.extend(analysis_paths),
                 Err(e) => {
                     warn!(
                         "解析analysis_images JSON失败: {} - 数据: {}",
@@ -673,16 +931,22 @@ impl FileManager {
                     )));
                 }
             }
+
+            if trashed {
+                trashed_images.extend(paths);
+            } else {
+                referenced_images.extend(paths);
+            }
         }
 
-        Ok(referenced_images)
+        Ok((referenced_images, trashed_images))
     }
 
     /// 清理空的子目录
     async fn cleanup_empty_directories(&self) -> Result<()> {
         debug!("清理空目录");
 
-        let mut entries = async_fs::read_dir(&self.images_dir)
+        let mut entries = async_fs::read_dir(&self.images_dir())
             .await
             .map_err(|e| AppError::file_system(format!("读取图片目录失败: {}", e)))?;
 
@@ -743,16 +1007,19 @@ impl FileManager {
             file_types: std::collections::HashMap::new(),
             oldest_file: None,
             newest_file: None,
+            deduplicated_files: 0,
+            dedup_reused_count: 0,
+            dedup_saved_bytes: 0,
         };
 
-        if !async_fs::try_exists(&self.images_dir)
+        if !async_fs::try_exists(&self.images_dir())
             .await
             .map_err(|e| AppError::file_system(format!("检查图片目录存在性失败: {}", e)))?
         {
             return Ok(stats);
         }
 
-        let mut entries = async_fs::read_dir(&self.images_dir)
+        let mut entries = async_fs::read_dir(&self.images_dir())
             .await
             .map_err(|e| AppError::file_system(format!("读取图片目录失败: {}", e)))?;
 
@@ -801,6 +1068,14 @@ impl FileManager {
             }
         }
 
+        let (deduplicated_files, reused, saved_bytes) = {
+            let _lock = self.dedup_lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.load_dedup_index().savings()
+        };
+        stats.deduplicated_files = deduplicated_files;
+        stats.dedup_reused_count = reused;
+        stats.dedup_saved_bytes = saved_bytes;
+
         Ok(stats)
     }
 
@@ -815,41 +1090,25 @@ impl FileManager {
 
     // 第一个 extract_extension_from_base64 (占位符) 已被移除，保留下面的实际实现
 
-    /// 保存图片文件（从字节数据）
+    /// 保存图片文件（从字节数据），按内容去重
     pub fn save_image_from_bytes(&self, image_data: &[u8], file_extension: &str) -> Result<String> {
-        // 确保图片目录存在
-        fs::create_dir_all(&self.images_dir)
-            .map_err(|e| AppError::file_system(format!("创建图片目录失败: {}", e)))?;
-
-        // 生成唯一文件名
-        let file_id = Uuid::new_v4().to_string();
-        let filename = format!("{}.{}", file_id, file_extension);
-        let file_path = self.images_dir.join(&filename);
-
-        // 写入文件
-        let mut file = fs::File::create(&file_path)
-            .map_err(|e| AppError::file_system(format!("创建图片文件失败: {}", e)))?;
-        file.write_all(image_data)
-            .map_err(|e| AppError::file_system(format!("写入图片文件失败: {}", e)))?;
-
-        // 返回相对路径
-        Ok(format!("images/{}", filename))
+        self.save_image_deduplicated(image_data, file_extension)
     }
 
     /// 获取图片文件的绝对路径
     pub fn get_image_absolute_path(&self, relative_path: &str) -> PathBuf {
-        self.app_data_dir.join(relative_path)
+        self.resolve_data_path(relative_path)
     }
 
     /// 检查图片文件是否存在
     pub fn image_exists(&self, relative_path: &str) -> bool {
-        let file_path = self.app_data_dir.join(relative_path);
+        let file_path = self.resolve_data_path(relative_path);
         file_path.exists()
     }
 
     /// 获取图片文件大小
     pub fn get_image_size(&self, relative_path: &str) -> Result<u64> {
-        let file_path = self.app_data_dir.join(relative_path);
+        let file_path = self.resolve_data_path(relative_path);
         let metadata = fs::metadata(&file_path)
             .map_err(|e| AppError::file_system(format!("获取文件元数据失败: {}", e)))?;
         Ok(metadata.len())
@@ -1257,8 +1516,8 @@ impl FileManager {
         }
 
         // 2. 计算图片目录大小
-        if self.images_dir.exists() {
-            let (size, count) = self.calculate_directory_size(&self.images_dir)?;
+        if self.images_dir().exists() {
+            let (size, count) = self.calculate_directory_size(&self.images_dir())?;
             images_size = size;
             images_count = count;
             total_size += images_size;
@@ -1374,3 +1633,154 @@ pub struct StorageInfo {
     pub formatted_cache: String,
     pub formatted_other: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_index_reference_counting() {
+        let mut index = ImageDedupIndex::default();
+        assert_eq!(
+            index.add_reference("h1", "images/h1.png", 100),
+            "images/h1.png"
+        );
+        // 命中时沿用首次登记的路径
+        assert_eq!(
+            index.add_reference("h1", "images/h1.jpg", 100),
+            "images/h1.png"
+        );
+        index.add_reference("h2", "images/h2.png", 40);
+        assert!(index.retain_path("images/h1.png"));
+        assert!(!index.retain_path("images/legacy.png"));
+        assert_eq!(index.savings(), (1, 2, 200));
+
+        assert_eq!(index.release_path("images/h1.png"), Some(false));
+        assert_eq!(index.release_path("images/h1.png"), Some(false));
+        assert_eq!(index.release_path("images/h1.png"), Some(true));
+        assert!(index.find_by_path("images/h1.png").is_none());
+        assert_eq!(index.release_path("images/legacy.png"), None);
+        assert_eq!(index.savings(), (0, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_identical_images_are_stored_once() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let manager = FileManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let data_url = format!(
+            "data:image/png;base64,{}",
+            general_purpose::STANDARD.encode(b"same image bytes")
+        );
+
+        let first = manager
+            .save_image_from_base64_dedup(&data_url)
+            .await
+            .unwrap();
+        let second = manager
+            .save_image_from_base64_dedup(&data_url)
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+        assert!(first.ends_with(".png"));
+
+        let stats = manager.get_image_statistics().await.unwrap();
+        assert_eq!(stats.total_files, 1);
+        assert_eq!(stats.dedup_reused_count, 1);
+        assert_eq!(stats.dedup_saved_bytes, b"same image bytes".len() as u64);
+
+        manager.delete_image(&first).await.unwrap();
+        assert!(manager.image_exists(&first));
+        manager.delete_image(&first).await.unwrap();
+        assert!(!manager.image_exists(&first));
+    }
+
+    #[tokio::test]
+    async fn test_release_images_keeps_file_until_orphan_cleanup() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let manager = FileManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let data_url = format!(
+            "data:image/png;base64,{}",
+            general_purpose::STANDARD.encode(b"shared image")
+        );
+        let path = manager
+            .save_image_from_base64_dedup(&data_url)
+            .await
+            .unwrap();
+        manager
+            .save_image_from_base64_dedup(&data_url)
+            .await
+            .unwrap();
+
+        manager.release_images(&[path.clone()]).unwrap();
+        assert!(manager.load_dedup_index().find_by_path(&path).is_some());
+        manager
+            .release_images(&[path.clone(), "images/legacy.png".to_string()])
+            .unwrap();
+        assert!(manager.load_dedup_index().find_by_path(&path).is_none());
+        assert!(manager.image_exists(&path));
+    }
+
+    #[tokio::test]
+    async fn test_orphan_scan_can_treat_trashed_mistakes_as_purged() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let manager = FileManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let database = crate::database::test_support::migrated_database(temp_dir.path()).unwrap();
+        let data_url = format!(
+            "data:image/png;base64,{}",
+            general_purpose::STANDARD.encode(b"trashed image")
+        );
+        let trashed = manager
+            .save_image_from_base64_dedup(&data_url)
+            .await
+            .unwrap();
+        fs::write(temp_dir.path().join("images/live.png"), b"live").unwrap();
+        {
+            let conn = database.get_conn_safe().unwrap();
+            let trashed_json = serde_json::to_string(&[&trashed]).unwrap();
+            crate::database::test_support::insert_mistake(
+                &conn,
+                "m-trash",
+                &[
+                    ("question_images", &trashed_json),
+                    ("deleted_at", &"2026-01-01T00:00:00Z"),
+                ],
+            )
+            .unwrap();
+            crate::database::test_support::insert_mistake(
+                &conn,
+                "m-live",
+                &[("question_images", &r#"["images/live.png"]"#)],
+            )
+            .unwrap();
+        }
+
+        let noop = |_: usize| {};
+        assert!(manager
+            .find_orphaned_images(&database, false, &noop)
+            .await
+            .unwrap()
+            .is_empty());
+        let preview = manager
+            .find_orphaned_images(&database, true, &noop)
+            .await
+            .unwrap();
+        assert_eq!(preview, vec![(trashed.clone(), 13)]);
+        // 预览不改动去重索引
+        assert!(manager.load_dedup_index().find_by_path(&trashed).is_some());
+
+        database
+            .get_conn_safe()
+            .unwrap()
+            .execute("DELETE FROM mistakes WHERE id = 'm-trash'", [])
+            .unwrap();
+        manager.release_images(&[trashed.clone()]).unwrap();
+        let orphaned = manager
+            .find_orphaned_images(&database, false, &noop)
+            .await
+            .unwrap();
+        assert_eq!(orphaned, preview);
+        manager.delete_orphaned_image(&trashed).await.unwrap();
+        assert!(!manager.image_exists(&trashed));
+        assert!(manager.image_exists("images/live.png"));
+    }
+}
@@ -2,12 +2,17 @@ use crate::database::Database;
 use crate::models::{
     AppError, DocumentChunk, DocumentChunkWithEmbedding, RetrievedChunk, VectorStoreStats,
 };
+use crate::vector_index::{
+    load_vector_index_settings, VectorIndexBuildReport, VectorIndexSettings, VectorIndexType,
+    MIN_TRAINABLE_ROWS,
+};
 use crate::vector_store::VectorStore;
 use async_trait::async_trait;
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
 use std::sync::Arc;
 use tracing::{debug, error, info, warn};
 
@@ -29,7 +34,7 @@ fn log_and_skip_err<T, E: std::fmt::Display>(result: std::result::Result<T, E>)
 
 /// 从聊天消息内容中提取纯文本（简化版本，用于迁移兼容）
 #[cfg(feature = "lance")]
-fn extract_plain_text(content: &str) -> String {
+pub(crate) fn extract_plain_text(content: &str) -> String {
     // 简单实现：移除 JSON 格式和 markdown 图片标记
     let trimmed = content.trim();
     if trimmed.starts_with('[') || trimmed.starts_with('{') {
@@ -61,9 +66,11 @@ use lancedb::index::scalar::FtsIndexBuilder;
 #[cfg(feature = "lance")]
 use lancedb::index::scalar::FullTextSearchQuery;
 #[cfg(feature = "lance")]
+use lancedb::index::vector::IvfPqIndexBuilder;
+#[cfg(feature = "lance")]
 use lancedb::index::Index;
 #[cfg(feature = "lance")]
-use lancedb::query::{ExecutableQuery, QueryBase, QueryExecutionOptions};
+use lancedb::query::{ExecutableQuery, QueryBase, QueryExecutionOptions, VectorQuery};
 #[cfg(feature = "lance")]
 use lancedb::table::{OptimizeAction, OptimizeOptions};
 #[cfg(feature = "lance")]
@@ -182,6 +189,11 @@ const CHAT_FTS_VERSION: &str = "2024-05-chat-ngram-v1";
 #[cfg(feature = "lance")]
 const OPTIMIZE_MIN_INTERVAL_CHAT_SECS: i64 = 1800; // 30min
 #[cfg(feature = "lance")]
+const OPTIMIZE_MIN_INTERVAL_KB_SECS: i64 = 86_400; // 24h
+/// 知识库表整理时保留最近 1 小时内的旧版本，避免影响进行中的读取
+#[cfg(feature = "lance")]
+const KB_OPTIMIZE_PRUNE_OLDER_THAN_HOURS: i64 = 1;
+#[cfg(feature = "lance")]
 const LANCE_RELEVANCE_COL: &str = "_relevance_score";
 #[cfg(feature = "lance")]
 const LANCE_FTS_SCORE_COL: &str = "_score";
@@ -204,6 +216,7 @@ struct LanceChunkRow {
 }
 
 #[cfg(feature = "lance")]
+#[derive(Debug, Clone)]
 pub struct LanceChatRow {
     pub message_id: String,
     pub mistake_id: String,
@@ -233,11 +246,129 @@ fn parse_bool_flag(value: &str) -> Option<bool> {
     }
 }
 
+/// 向量导出扫描条件
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddingScanFilter {
+    pub include_kb: bool,
+    pub include_chat: bool,
+    /// 仅作用于知识库块
+    pub sub_library_ids: Option<Vec<String>>,
+    /// 仅作用于错题对话向量
+    pub mistake_ids: Option<Vec<String>>,
+    /// 指定时只扫描该维度的表
+    pub dimension: Option<usize>,
+}
+
+/// 导出扫描得到的单条向量（知识库块或错题对话消息）
+#[derive(Debug, Clone)]
+pub struct EmbeddingExportRow {
+    /// "kb" | "chat"
+    pub source: &'static str,
+    /// chunk_id / message_id
+    pub id: String,
+    /// document_id / mistake_id
+    pub parent_id: String,
+    pub sub_library_id: Option<String>,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+#[cfg(feature = "lance")]
+fn sql_in_filter(column: &str, values: &[String]) -> String {
+    let quoted: Vec<String> = values
+        .iter()
+        .map(|v| format!("'{}'", v.replace("'", "''")))
+        .collect();
+    format!("{} IN ({})", column, quoted.join(", "))
+}
+
+#[cfg(feature = "lance")]
+fn string_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a StringArray> {
+    let idx = batch
+        .schema()
+        .index_of(name)
+        .map_err(|e| AppError::database(e.to_string()))?;
+    batch
+        .column(idx)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .ok_or_else(|| AppError::database(format!("{} 列类型错误", name)))
+}
+
+/// 分库向量清理结果
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VectorPurgeReport {
+    pub removed: usize,
+    /// 清理后仍匹配的行数，正常应为 0
+    pub remaining: usize,
+}
+
+/// 孤儿向量回收结果
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OrphanVectorGcReport {
+    /// 扫描的向量行数
+    pub scanned: usize,
+    /// 没有对应 SQLite 分块的 chunk_id 数
+    pub orphan_chunks: usize,
+    /// 实际删除的向量行数（同一 chunk 可能存在于多个维度表）
+    pub removed: usize,
+    pub remaining: usize,
+}
+
+/// 正在写入知识库向量表的批次数
+static KB_WRITES_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+/// 同一时间只允许一个知识库表整理任务
+static KB_OPTIMIZE_RUNNING: AtomicBool = AtomicBool::new(false);
+
+struct KbWriteGuard;
+
+impl KbWriteGuard {
+    fn enter() -> Self {
+        KB_WRITES_IN_FLIGHT.fetch_add(1, atomic::Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for KbWriteGuard {
+    fn drop(&mut self) {
+        KB_WRITES_IN_FLIGHT.fetch_sub(1, atomic::Ordering::SeqCst);
+    }
+}
+
+struct KbOptimizeGuard;
+
+impl Drop for KbOptimizeGuard {
+    fn drop(&mut self) {
+        KB_OPTIMIZE_RUNNING.store(false, atomic::Ordering::SeqCst);
+    }
+}
+
+/// 是否有知识库向量正在写入
+pub fn kb_ingestion_in_progress() -> bool {
+    KB_WRITES_IN_FLIGHT.load(atomic::Ordering::SeqCst) > 0
+}
+
+/// 知识库向量表整理（压缩 + 清理旧版本 + 重建索引）结果
+#[derive(Debug, Clone, Default)]
+pub struct VectorStoreOptimizeReport {
+    pub tables: Vec<String>,
+    /// 整理前后 Lance 知识库目录大小（字节，包含所有分库）
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub files_removed: usize,
+    pub versions_removed: u64,
+    pub bytes_removed: u64,
+    pub index_reports: Vec<VectorIndexBuildReport>,
+    pub elapsed_ms: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct LibrarySummary {
     pub chunk_count: usize,
     pub text_bytes: usize,
     pub embedding_bytes: usize,
+    /// 各嵌入维度表中的向量数（同一分块换模型后可能存在于多个维度表）
+    pub vectors_by_dimension: BTreeMap<usize, usize>,
 }
 
 impl LanceVectorStore {
@@ -355,6 +486,7 @@ impl LanceVectorStore {
         let mut chunk_count: usize = 0;
         let mut text_bytes: usize = 0;
         let mut embedding_bytes: usize = 0;
+        let mut vectors_by_dimension = BTreeMap::new();
 
         for dim in Self::candidate_dim_values() {
             let table_name = format!("{}{}", KB_V2_TABLE_PREFIX, dim);
@@ -402,6 +534,9 @@ impl LanceVectorStore {
                 let width = emb_arr.value_length() as usize;
                 chunk_count += emb_arr.len();
                 embedding_bytes += emb_arr.len() * width * std::mem::size_of::<f32>();
+                if !emb_arr.is_empty() {
+                    *vectors_by_dimension.entry(width).or_default() += emb_arr.len();
+                }
             }
         }
 
@@ -409,9 +544,403 @@ impl LanceVectorStore {
             chunk_count,
             text_bytes,
             embedding_bytes,
+            vectors_by_dimension,
         })
     }
 
+    /// 逐批扫描向量表并回调 `sink`，用于导出（不整体加载到内存）
+    ///
+    /// 返回回调的行数；`sink` 返回错误时立即中止。
+    #[cfg(feature = "lance")]
+    pub async fn scan_embeddings_for_export<F>(
+        &self,
+        filter: &EmbeddingScanFilter,
+        mut sink: F,
+    ) -> Result<usize>
+    where
+        F: FnMut(EmbeddingExportRow) -> Result<()>,
+    {
+        use futures_util::TryStreamExt;
+
+        let path = self.get_lance_path()?;
+        let db = lancedb::connect(&path)
+            .execute()
+            .await
+            .map_err(|e| AppError::database(format!("连接 LanceDB 失败: {}", e)))?;
+
+        let dims = match filter.dimension {
+            Some(dim) => vec![dim],
+            None => Self::candidate_dim_values(),
+        };
+        let kb_expr = filter
+            .sub_library_ids
+            .as_deref()
+            .map(|ids| sql_in_filter("sub_library_id", ids));
+        let chat_expr = filter
+            .mistake_ids
+            .as_deref()
+            .map(|ids| sql_in_filter("mistake_id", ids));
+
+        let mut targets: Vec<(&'static str, String, Option<&String>)> = Vec::new();
+        for dim in dims {
+            if filter.include_kb {
+                targets.push((
+                    "kb",
+                    format!("{}{}", KB_V2_TABLE_PREFIX, dim),
+                    kb_expr.as_ref(),
+                ));
+            }
+            if filter.include_chat {
+                targets.push((
+                    "chat",
+                    format!("{}{}", CHAT_V2_TABLE_PREFIX, dim),
+                    chat_expr.as_ref(),
+                ));
+            }
+        }
+
+        let mut exported = 0usize;
+        for (source, table_name, expr) in targets {
+            let tbl = match db.open_table(&table_name).execute().await {
+                Ok(tbl) => tbl,
+                Err(_) => continue,
+            };
+            let mut query = tbl.query();
+            if let Some(expr) = expr {
+                query = query.only_if(expr.as_str());
+            }
+            let mut stream = query
+                .execute()
+                .await
+                .map_err(|e| AppError::database(e.to_string()))?;
+            let (id_col, parent_col) = if source == "kb" {
+                ("chunk_id", "document_id")
+            } else {
+                ("message_id", "mistake_id")
+            };
+
+            while let Some(batch) = stream
+                .try_next()
+                .await
+                .map_err(|e| AppError::database(e.to_string()))?
+            {
+                let ids = string_column(&batch, id_col)?;
+                let parents = string_column(&batch, parent_col)?;
+                let texts = string_column(&batch, "text")?;
+                let subs = if source == "kb" {
+                    string_column(&batch, "sub_library_id").ok()
+                } else {
+                    None
+                };
+                let idx_emb = batch
+                    .schema()
+                    .index_of("embedding")
+                    .map_err(|e| AppError::database(e.to_string()))?;
+                let emb_arr = batch
+                    .column(idx_emb)
+                    .as_any()
+                    .downcast_ref::<FixedSizeListArray>()
+                    .ok_or_else(|| AppError::database("embedding 列类型错误".to_string()))?;
+
+                for i in 0..batch.num_rows() {
+                    let values = emb_arr.value(i);
+                    let Some(floats) = values.as_any().downcast_ref::<Float32Array>() else {
+                        continue;
+                    };
+                    sink(EmbeddingExportRow {
+                        source,
+                        id: ids.value(i).to_string(),
+                        parent_id: parents.value(i).to_string(),
+                        sub_library_id: subs
+                            .filter(|arr| !arr.is_null(i))
+                            .map(|arr| arr.value(i).to_string()),
+                        text: texts.value(i).to_string(),
+                        embedding: floats.values().iter().copied().collect(),
+                    })?;
+                    exported += 1;
+                }
+            }
+        }
+
+        Ok(exported)
+    }
+
+    /// 文档移动分库后同步 Lance 块上的 `sub_library_id` 与内存缓存，
+    /// 保证按分库限定的检索立即反映移动结果
+    #[cfg(feature = "lance")]
+    pub async fn reassign_documents_sub_library(
+        &self,
+        document_ids: &[String],
+        target_sub_library_id: &str,
+    ) -> Result<()> {
+        if document_ids.is_empty() {
+            return Ok(());
+        }
+        let path = self.get_lance_path()?;
+        let db = lancedb::connect(&path)
+            .execute()
+            .await
+            .map_err(|e| AppError::database(format!("连接 LanceDB 失败: {}", e)))?;
+        let target_expr = format!("'{}'", target_sub_library_id.replace("'", "''"));
+
+        for ids in document_ids.chunks(900) {
+            let filter = sql_in_filter("document_id", ids);
+            for table_name in Self::candidate_kb_table_names_for_scan() {
+                let tbl = match db.open_table(&table_name).execute().await {
+                    Ok(tbl) => tbl,
+                    Err(_) => continue,
+                };
+                tbl.update()
+                    .only_if(filter.as_str())
+                    .column("sub_library_id", target_expr.as_str())
+                    .execute()
+                    .await
+                    .map_err(|e| {
+                        AppError::database(format!("更新 Lance 分库归属失败 {}: {}", table_name, e))
+                    })?;
+            }
+        }
+
+        let chunk_ids: Vec<String> = {
+            let conn = self
+                .database
+                .get_conn_safe()
+                .map_err(|e| AppError::database(e.to_string()))?;
+            let mut stmt = conn
+                .prepare("SELECT id FROM rag_document_chunks WHERE document_id = ?1")
+                .map_err(|e| AppError::database(e.to_string()))?;
+            let mut ids = Vec::new();
+            for document_id in document_ids {
+                let rows = stmt
+                    .query_map([document_id], |row| row.get::<_, String>(0))
+                    .map_err(|e| AppError::database(e.to_string()))?;
+                ids.extend(rows.filter_map(log_and_skip_err));
+            }
+            ids
+        };
+        for chunk_id in chunk_ids {
+            if let Some(mut entry) = self.emb_cache.get_mut(&chunk_id) {
+                entry.2 = Some(target_sub_library_id.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// 删除所有知识库向量表中匹配任一过滤条件的行
+    ///
+    /// 返回删除前匹配的行数与删除后的残余行数，残余为 0 即清理完成。
+    #[cfg(feature = "lance")]
+    async fn purge_kb_vectors(&self, filters: &[String]) -> Result<VectorPurgeReport> {
+        let mut report = VectorPurgeReport::default();
+        if filters.is_empty() {
+            return Ok(report);
+        }
+        let path = self.get_lance_path()?;
+        let db = lancedb::connect(&path)
+            .execute()
+            .await
+            .map_err(|e| AppError::database(format!("连接 LanceDB 失败: {}", e)))?;
+
+        for table_name in Self::candidate_kb_table_names_for_scan() {
+            let tbl = match db.open_table(&table_name).execute().await {
+                Ok(tbl) => tbl,
+                Err(_) => continue,
+            };
+            for filter in filters {
+                let matched = tbl.count_rows(Some(filter.clone())).await.map_err(|e| {
+                    AppError::database(format!("统计 Lance 向量失败 {}: {}", table_name, e))
+                })?;
+                if matched == 0 {
+                    continue;
+                }
+                tbl.delete(filter.as_str()).await.map_err(|e| {
+                    AppError::database(format!("删除 Lance 向量失败 {}: {}", table_name, e))
+                })?;
+                report.removed += matched;
+            }
+            for filter in filters {
+                report.remaining += tbl.count_rows(Some(filter.clone())).await.map_err(|e| {
+                    AppError::database(format!("统计 Lance 向量失败 {}: {}", table_name, e))
+                })?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// 删除指定文档在 Lance 中的全部向量（不动 SQLite）
+    ///
+    /// 同时按 document_id 与 SQLite 中记录的 chunk_id 匹配，覆盖文档归属不一致的块。
+    #[cfg(feature = "lance")]
+    pub async fn purge_document_vectors(
+        &self,
+        document_ids: &[String],
+        chunk_ids: &[String],
+    ) -> Result<VectorPurgeReport> {
+        let filters: Vec<String> = document_ids
+            .chunks(900)
+            .map(|ids| sql_in_filter("document_id", ids))
+            .chain(
+                chunk_ids
+                    .chunks(900)
+                    .map(|ids| sql_in_filter("chunk_id", ids)),
+            )
+            .collect();
+        let report = self.purge_kb_vectors(&filters).await?;
+
+        let documents: HashSet<&str> = document_ids.iter().map(String::as_str).collect();
+        self.emb_cache
+            .retain(|_, (_, document_id, _)| !documents.contains(document_id.as_str()));
+        for chunk_id in chunk_ids {
+            self.emb_cache.remove(chunk_id);
+        }
+        Ok(report)
+    }
+
+    /// 删除分库后清理 Lance 中的残留向量
+    ///
+    /// 删除仍标记为该分库的所有块，以及 `document_ids` 对应文档的块（覆盖分库归属
+    /// 不一致的情况）。
+    #[cfg(feature = "lance")]
+    pub async fn purge_sub_library_vectors(
+        &self,
+        sub_library_id: &str,
+        document_ids: &[String],
+    ) -> Result<VectorPurgeReport> {
+        let library_expr = format!("sub_library_id = '{}'", sub_library_id.replace("'", "''"));
+        let mut filters = vec![library_expr];
+        filters.extend(
+            document_ids
+                .chunks(900)
+                .map(|ids| sql_in_filter("document_id", ids)),
+        );
+        let report = self.purge_kb_vectors(&filters).await?;
+
+        let documents: HashSet<&str> = document_ids.iter().map(String::as_str).collect();
+        self.emb_cache.retain(|_, (_, document_id, library)| {
+            library.as_deref() != Some(sub_library_id) && !documents.contains(document_id.as_str())
+        });
+        Ok(report)
+    }
+
+    /// 回收孤儿向量：Lance 中没有对应 `rag_document_chunks` 行的块
+    ///
+    /// 先扫描 Lance 再读取 SQLite；写入时先落 SQLite 再写 Lance，
+    /// 因此扫描期间新增的块不会被误判为孤儿。
+    #[cfg(feature = "lance")]
+    pub async fn gc_orphan_vectors(&self) -> Result<OrphanVectorGcReport> {
+        use futures_util::TryStreamExt;
+        use lancedb::query::Select;
+
+        let path = self.get_lance_path()?;
+        let db = lancedb::connect(&path)
+            .execute()
+            .await
+            .map_err(|e| AppError::database(format!("连接 LanceDB 失败: {}", e)))?;
+
+        let mut scanned = 0usize;
+        let mut lance_chunk_ids = HashSet::new();
+        for table_name in Self::candidate_kb_table_names_for_scan() {
+            let tbl = match db.open_table(&table_name).execute().await {
+                Ok(tbl) => tbl,
+                Err(_) => continue,
+            };
+            let mut stream = tbl
+                .query()
+                .select(Select::columns(&["chunk_id"]))
+                .execute()
+                .await
+                .map_err(|e| AppError::database(e.to_string()))?;
+            while let Some(batch) = stream
+                .try_next()
+                .await
+                .map_err(|e| AppError::database(e.to_string()))?
+            {
+                let chunk_arr = string_column(&batch, "chunk_id")?;
+                scanned += chunk_arr.len();
+                lance_chunk_ids
+                    .extend((0..chunk_arr.len()).map(|i| chunk_arr.value(i).to_string()));
+            }
+        }
+
+        let stored: HashSet<String> = {
+            let conn = self
+                .database
+                .get_conn_safe()
+                .map_err(|e| AppError::database(e.to_string()))?;
+            let mut stmt = conn
+                .prepare("SELECT id FROM rag_document_chunks")
+                .map_err(|e| AppError::database(e.to_string()))?;
+            // 读取失败时整体中止，避免把读不出的块误判为孤儿
+            let rows = stmt
+                .query_map([], |row| row.get::<_, String>(0))
+                .map_err(|e| AppError::database(e.to_string()))?;
+            rows.collect::<rusqlite::Result<HashSet<_>>>()
+                .map_err(|e| AppError::database(format!("读取 rag_document_chunks 失败: {}", e)))?
+        };
+
+        let mut orphans: Vec<String> = lance_chunk_ids
+            .into_iter()
+            .filter(|id| !stored.contains(id))
+            .collect();
+        orphans.sort();
+        let filters: Vec<String> = orphans
+            .chunks(900)
+            .map(|ids| sql_in_filter("chunk_id", ids))
+            .collect();
+        let purge = self.purge_kb_vectors(&filters).await?;
+        for chunk_id in &orphans {
+            self.emb_cache.remove(chunk_id);
+        }
+
+        Ok(OrphanVectorGcReport {
+            scanned,
+            orphan_chunks: orphans.len(),
+            removed: purge.removed,
+            remaining: purge.remaining,
+        })
+    }
+
+    /// 列出指定文档在 Lance 中已有向量的 chunk_id（用于排查缺失向量的块）
+    #[cfg(feature = "lance")]
+    pub async fn embedded_chunk_ids_for_document(
+        &self,
+        document_id: &str,
+    ) -> Result<HashSet<String>> {
+        use futures_util::TryStreamExt;
+        use lancedb::query::Select;
+
+        let path = self.get_lance_path()?;
+        let db = lancedb::connect(&path)
+            .execute()
+            .await
+            .map_err(|e| AppError::database(format!("连接 LanceDB 失败: {}", e)))?;
+        let filter_expr = format!("document_id = '{}'", document_id.replace("'", "''"));
+
+        let mut ids = HashSet::new();
+        for table_name in Self::candidate_kb_table_names_for_scan() {
+            let tbl = match db.open_table(&table_name).execute().await {
+                Ok(tbl) => tbl,
+                Err(_) => continue,
+            };
+            let mut stream = tbl
+                .query()
+                .only_if(filter_expr.as_str())
+                .select(Select::columns(&["chunk_id"]))
+                .execute()
+                .await
+                .map_err(|e| AppError::database(e.to_string()))?;
+            while let Some(batch) = stream
+                .try_next()
+                .await
+                .map_err(|e| AppError::database(e.to_string()))?
+            {
+                let chunk_arr = string_column(&batch, "chunk_id")?;
+                ids.extend((0..chunk_arr.len()).map(|i| chunk_arr.value(i).to_string()));
+            }
+        }
+        Ok(ids)
+    }
+
     #[cfg(feature = "lance")]
     fn candidate_kb_table_names_for_scan() -> Vec<String> {
         let mut names: Vec<String> = Vec::new();
@@ -870,6 +1399,136 @@ impl LanceVectorStore {
         Ok(optimized)
     }
 
+    /// 整理包含指定分库数据的知识库向量表：压缩小文件、清理旧版本并重建 ANN 索引
+    ///
+    /// 向量表按维度共享，同维度的其它分库也会一并整理。导入进行中、维护模式下或已有整理任务时拒绝执行。
+    #[cfg(feature = "lance")]
+    pub async fn optimize_library_tables(
+        &self,
+        sub_library_id: &str,
+    ) -> Result<VectorStoreOptimizeReport> {
+        let report = self.optimize_kb_tables(Some(sub_library_id)).await?;
+        if report.tables.is_empty() {
+            return Err(AppError::not_found(format!(
+                "分库 {} 没有已向量化的内容",
+                sub_library_id
+            )));
+        }
+        Ok(report)
+    }
+
+    /// 定时维护：设置 `lance.optimize.kb_on_maintenance` 开启时整理全部知识库向量表（每 24 小时至多一次）
+    #[cfg(feature = "lance")]
+    pub async fn run_scheduled_kb_optimization(&self) -> Result<Option<VectorStoreOptimizeReport>> {
+        let enabled = self
+            .database
+            .get_setting("lance.optimize.kb_on_maintenance")
+            .ok()
+            .flatten()
+            .and_then(|raw| parse_bool_flag(&raw))
+            .unwrap_or(false);
+        if !enabled {
+            return Ok(None);
+        }
+        let min_interval = chrono::Duration::seconds(OPTIMIZE_MIN_INTERVAL_KB_SECS);
+        if self.should_skip_optimization("kb", min_interval, false)? {
+            return Ok(None);
+        }
+        let report = self.optimize_kb_tables(None).await?;
+        if !report.tables.is_empty() {
+            self.record_optimization_timestamp("kb");
+        }
+        Ok(Some(report))
+    }
+
+    #[cfg(feature = "lance")]
+    async fn optimize_kb_tables(
+        &self,
+        sub_library_id: Option<&str>,
+    ) -> Result<VectorStoreOptimizeReport> {
+        if self.database.is_in_maintenance_mode() {
+            return Err(AppError::validation("维护模式下无法整理向量库"));
+        }
+        if kb_ingestion_in_progress() {
+            return Err(AppError::validation("知识库正在导入，请稍后再整理向量库"));
+        }
+        if KB_OPTIMIZE_RUNNING.swap(true, atomic::Ordering::SeqCst) {
+            return Err(AppError::validation("已有向量库整理任务在进行中"));
+        }
+        let _guard = KbOptimizeGuard;
+        let started = Instant::now();
+
+        let path = self.get_lance_path()?;
+        let dir_size = || crate::data_space::DataSpaceManager::calculate_dir_size(Path::new(&path));
+        let mut report = VectorStoreOptimizeReport {
+            bytes_before: dir_size().unwrap_or(0),
+            ..Default::default()
+        };
+        let conn = lancedb::connect(&path)
+            .execute()
+            .await
+            .map_err(|e| AppError::database(format!("连接 LanceDB 失败: {}", e)))?;
+        let settings = load_vector_index_settings(&self.database);
+        let filter =
+            sub_library_id.map(|id| format!("sub_library_id = '{}'", id.replace('\'', "''")));
+        let delete_unverified = self.resolve_delete_unverified(None);
+
+        for dim in Self::candidate_dim_values() {
+            let table_name = format!("{}{}", KB_V2_TABLE_PREFIX, dim);
+            let Ok(tbl) = conn.open_table(&table_name).execute().await else {
+                continue;
+            };
+            let rows = tbl.count_rows(filter.clone()).await.unwrap_or(0);
+            if rows == 0 {
+                continue;
+            }
+
+            let compact_stats = tbl
+                .optimize(OptimizeAction::Compact {
+                    options: lancedb::table::CompactionOptions::default(),
+                    remap_options: None,
+                })
+                .await
+                .map_err(|e| AppError::database(e.to_string()))?;
+            if let Some(metrics) = compact_stats.compaction {
+                report.files_removed += metrics.files_removed;
+            }
+            let prune_stats = tbl
+                .optimize(OptimizeAction::Prune {
+                    older_than: chrono::Duration::try_hours(KB_OPTIMIZE_PRUNE_OLDER_THAN_HOURS),
+                    delete_unverified: Some(delete_unverified),
+                    error_if_tagged_old_versions: Some(false),
+                })
+                .await
+                .map_err(|e| AppError::database(e.to_string()))?;
+            if let Some(metrics) = prune_stats.prune {
+                report.versions_removed += metrics.old_versions;
+                report.bytes_removed += metrics.bytes_removed;
+            }
+            // 先增量更新 FTS 等索引，再按当前配置重建 ANN 索引
+            tbl.optimize(OptimizeAction::Index(OptimizeOptions::default()))
+                .await
+                .map_err(|e| AppError::database(e.to_string()))?;
+            report.index_reports.push(
+                self.build_vector_index_on_table(&tbl, &table_name, dim, &settings, true)
+                    .await?,
+            );
+            report.tables.push(table_name);
+        }
+
+        report.bytes_after = dir_size().unwrap_or(0);
+        report.elapsed_ms = started.elapsed().as_millis() as u64;
+        info!(
+            "✅ [Lance优化] 知识库向量表整理完成: tables={:?} 删除{}个旧版本 目录 {} -> {} 字节 ({}ms)",
+            report.tables,
+            report.versions_removed,
+            report.bytes_before,
+            report.bytes_after,
+            report.elapsed_ms
+        );
+        Ok(report)
+    }
+
     #[cfg(feature = "lance")]
     fn build_sub_library_filter(ids: &[String]) -> Option<String> {
         let mut values: Vec<String> = Vec::with_capacity(ids.len());
@@ -1032,27 +1691,7 @@ impl LanceVectorStore {
                     .map_err(|e| AppError::database(format!("创建 Lance 表失败: {}", e)))?
             }
         };
-        let embed_idx_start = Instant::now();
-        let embed_res = tbl
-            .create_index(&["embedding"], Index::Auto)
-            .replace(false)
-            .execute()
-            .await;
-        if let Err(err) = embed_res {
-            let msg = err.to_string();
-            if !msg.contains("already exists") {
-                warn!(
-                    "⚠️ [LanceIndex] embedding index ensure failed on {}: {}",
-                    table_name, msg
-                );
-            }
-        } else {
-            debug!(
-                "⏱️ [LanceIndex] ensured embedding index on {} in {}ms",
-                table_name,
-                embed_idx_start.elapsed().as_millis()
-            );
-        }
+        // 向量索引不在此处创建：由写入后的行数阈值或 build_vector_index 触发
         let rebuild_fts = self.should_rebuild_fts(&table_name, KB_FTS_VERSION);
         let fts_idx_start = Instant::now();
         let fts_builder = self.build_fts_index_builder();
@@ -1260,9 +1899,153 @@ impl LanceVectorStore {
             .execute()
             .await
             .map_err(|e| AppError::database(format!("写入 Lance 扩展表失败: {}", e)))?;
+
+        let settings = load_vector_index_settings(&self.database);
+        let table_name = format!("{}{}", KB_V2_TABLE_PREFIX, dim);
+        if let Err(err) = self
+            .build_vector_index_on_table(&tbl, &table_name, dim, &settings, false)
+            .await
+        {
+            warn!(
+                "⚠️ [LanceIndex] {} 自动构建向量索引失败: {}",
+                table_name, err
+            );
+        }
         Ok(())
     }
 
+    #[cfg(feature = "lance")]
+    async fn has_vector_index(tbl: &Table) -> bool {
+        tbl.list_indices()
+            .await
+            .map(|indices| {
+                indices
+                    .iter()
+                    .any(|idx| idx.columns.iter().any(|c| c == "embedding"))
+            })
+            .unwrap_or(false)
+    }
+
+    /// 为单张知识库宽表构建 IVF_PQ 索引
+    ///
+    /// `force = false` 时仅在无索引且行数达到阈值时构建；`force = true` 时忽略阈值并重建。
+    #[cfg(feature = "lance")]
+    async fn build_vector_index_on_table(
+        &self,
+        tbl: &Table,
+        table_name: &str,
+        dim: usize,
+        settings: &VectorIndexSettings,
+        force: bool,
+    ) -> Result<VectorIndexBuildReport> {
+        let row_count = tbl
+            .count_rows(None)
+            .await
+            .map_err(|e| AppError::database(e.to_string()))?;
+        let mut report = VectorIndexBuildReport {
+            table_name: table_name.to_string(),
+            dimension: dim,
+            row_count,
+            built: false,
+            num_partitions: None,
+            num_sub_vectors: None,
+            elapsed_ms: 0,
+            message: None,
+        };
+        if !force && (!settings.should_auto_build(row_count) || Self::has_vector_index(tbl).await) {
+            return Ok(report);
+        }
+        if row_count < MIN_TRAINABLE_ROWS {
+            report.message = Some(format!(
+                "行数 {} 不足 {}，无法训练索引，继续使用精确检索",
+                row_count, MIN_TRAINABLE_ROWS
+            ));
+            return Ok(report);
+        }
+
+        let num_partitions = settings.partitions_for(row_count);
+        let num_sub_vectors = settings.sub_vectors_for(dim)?;
+        let started = Instant::now();
+        let builder = IvfPqIndexBuilder::default()
+            .distance_type(DistanceType::Cosine)
+            .num_partitions(num_partitions)
+            .num_sub_vectors(num_sub_vectors);
+        tbl.create_index(&["embedding"], Index::IvfPq(builder))
+            .replace(true)
+            .execute()
+            .await
+            .map_err(|e| AppError::database(format!("构建向量索引失败: {}", e)))?;
+
+        report.built = true;
+        report.num_partitions = Some(num_partitions);
+        report.num_sub_vectors = Some(num_sub_vectors);
+        report.elapsed_ms = started.elapsed().as_millis() as u64;
+        info!(
+            "✅ [LanceIndex] {} 构建 IVF_PQ 索引: rows={} partitions={} sub_vectors={} ({}ms)",
+            table_name, row_count, num_partitions, num_sub_vectors, report.elapsed_ms
+        );
+        if settings.index_type == VectorIndexType::Exact {
+            report.message = Some("当前配置为精确检索，查询不会使用该索引".to_string());
+        }
+        Ok(report)
+    }
+
+    /// 强制为包含指定分库数据的知识库向量表构建索引
+    ///
+    /// 索引按向量表（维度）建立，同维度的其它分库也会受益。
+    #[cfg(feature = "lance")]
+    pub async fn build_vector_index(
+        &self,
+        sub_library_id: &str,
+    ) -> Result<Vec<VectorIndexBuildReport>> {
+        let path = self.get_lance_path()?;
+        let conn = lancedb::connect(&path)
+            .execute()
+            .await
+            .map_err(|e| AppError::database(format!("连接 LanceDB 失败: {}", e)))?;
+        let settings = load_vector_index_settings(&self.database);
+        let filter = format!("sub_library_id = '{}'", sub_library_id.replace('\'', "''"));
+
+        let mut reports = Vec::new();
+        for dim in Self::candidate_dim_values() {
+            let table_name = format!("{}{}", KB_V2_TABLE_PREFIX, dim);
+            let Ok(tbl) = conn.open_table(&table_name).execute().await else {
+                continue;
+            };
+            let library_rows = tbl.count_rows(Some(filter.clone())).await.unwrap_or(0);
+            if library_rows == 0 {
+                continue;
+            }
+            reports.push(
+                self.build_vector_index_on_table(&tbl, &table_name, dim, &settings, true)
+                    .await?,
+            );
+        }
+        if reports.is_empty() {
+            return Err(AppError::not_found(format!(
+                "分库 {} 没有已向量化的内容",
+                sub_library_id
+            )));
+        }
+        Ok(reports)
+    }
+
+    /// 按索引配置调整向量查询：精确模式绕过索引，否则设置 nprobes/refine_factor
+    #[cfg(feature = "lance")]
+    fn apply_vector_index_params(
+        query: VectorQuery,
+        settings: &VectorIndexSettings,
+    ) -> VectorQuery {
+        if settings.index_type == VectorIndexType::Exact {
+            return query.bypass_vector_index();
+        }
+        let query = query.nprobes(settings.nprobes);
+        match settings.refine_factor {
+            Some(factor) => query.refine_factor(factor),
+            None => query,
+        }
+    }
+
     #[cfg(feature = "lance")]
     fn write_chunks_to_sqlite(&self, rows: &[LanceChunkRow]) -> Result<()> {
         if rows.is_empty() {
@@ -1331,11 +2114,14 @@ impl LanceVectorStore {
         );
 
         let filter_expr = sub_library_ids.and_then(Self::build_sub_library_filter);
-        let mut query = tbl
-            .vector_search(query_embedding)
-            .map_err(|e| AppError::database(e.to_string()))?
-            .distance_type(DistanceType::Cosine)
-            .limit(fetch_limit);
+        let index_settings = load_vector_index_settings(&self.database);
+        let mut query = Self::apply_vector_index_params(
+            tbl.vector_search(query_embedding)
+                .map_err(|e| AppError::database(e.to_string()))?,
+            &index_settings,
+        )
+        .distance_type(DistanceType::Cosine)
+        .limit(fetch_limit);
         if let Some(ref expr) = filter_expr {
             query = query.only_if(expr.as_str());
         }
@@ -1528,13 +2314,16 @@ impl LanceVectorStore {
         let fts_query = FullTextSearchQuery::new(query_text.to_owned());
 
         let filter_expr = sub_library_ids.and_then(Self::build_sub_library_filter);
-        let mut query = tbl
-            .query()
-            .full_text_search(fts_query)
-            .nearest_to(query_embedding.to_vec())
-            .map_err(|e| AppError::database(e.to_string()))?
-            .distance_type(DistanceType::Cosine)
-            .limit(fetch_limit);
+        let index_settings = load_vector_index_settings(&self.database);
+        let mut query = Self::apply_vector_index_params(
+            tbl.query()
+                .full_text_search(fts_query)
+                .nearest_to(query_embedding.to_vec())
+                .map_err(|e| AppError::database(e.to_string()))?,
+            &index_settings,
+        )
+        .distance_type(DistanceType::Cosine)
+        .limit(fetch_limit);
         if let Some(ref expr) = filter_expr {
             query = query.only_if(expr.as_str());
         }
@@ -2528,175 +3317,185 @@ impl LanceVectorStore {
 impl LanceVectorStore {
     #[cfg(feature = "lance")]
     fn ensure_base_rag_schema(&self) -> Result<()> {
-        use rusqlite::params;
         let conn = self
             .database
             .get_conn_safe()
             .map_err(|e| AppError::database(e.to_string()))?;
+        ensure_rag_document_schema(&conn)
+    }
+}
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS rag_sub_libraries (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                description TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )",
-            [],
-        )
-        .map_err(|e| AppError::database(format!("创建分库表失败: {}", e)))?;
-
-        let default_exists: bool = conn
-            .query_row(
-                "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE id='default')",
-                [],
-                |row| row.get(0),
-            )
-            .unwrap_or(false);
-        if !default_exists {
-            let now = chrono::Utc::now().to_rfc3339();
-            let _ = conn.execute(
-                "INSERT OR IGNORE INTO rag_sub_libraries (id, name, description, created_at, updated_at) VALUES ('default','default','默认知识库',?1,?1)",
-                params![now],
-            );
-        }
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS rag_documents (
-                id TEXT PRIMARY KEY,
-                file_name TEXT NOT NULL,
-                file_path TEXT,
-                file_size INTEGER,
-                content_type TEXT,
-                total_chunks INTEGER DEFAULT 0,
-                sub_library_id TEXT NOT NULL DEFAULT 'default',
-                update_state TEXT NOT NULL DEFAULT 'ready',
-                desired_hash TEXT,
-                update_retry INTEGER NOT NULL DEFAULT 0,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                FOREIGN KEY (sub_library_id) REFERENCES rag_sub_libraries (id) ON DELETE SET DEFAULT
-            )",
-            [],
-        )
-        .map_err(|e| AppError::database(format!("创建文档表失败: {}", e)))?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS rag_document_chunks (
-                id TEXT PRIMARY KEY,
-                document_id TEXT NOT NULL REFERENCES rag_documents(id) ON DELETE CASCADE,
-                chunk_index INTEGER NOT NULL,
-                text TEXT NOT NULL,
-                metadata TEXT NOT NULL DEFAULT '{}'
-            )",
+/// 旧知识库（kb_v2）在 SQLite 端的文档元数据表：分库、文档、分块与向量
+///
+/// 这些表不在主库迁移中，由向量库初始化时按需创建并补齐列；测试夹具复用同一份定义。
+pub(crate) fn ensure_rag_document_schema(conn: &rusqlite::Connection) -> Result<()> {
+    use rusqlite::params;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rag_sub_libraries (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            description TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| AppError::database(format!("创建分库表失败: {}", e)))?;
+
+    let default_exists: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM rag_sub_libraries WHERE id='default')",
             [],
+            |row| row.get(0),
         )
-        .map_err(|e| AppError::database(format!("创建文档分块表失败: {}", e)))?;
+        .unwrap_or(false);
+    if !default_exists {
+        let now = chrono::Utc::now().to_rfc3339();
+        let _ = conn.execute(
+            "INSERT OR IGNORE INTO rag_sub_libraries (id, name, description, created_at, updated_at) VALUES ('default','default','默认知识库',?1,?1)",
+            params![now],
+        );
+    }
 
-        if let Err(e) = conn.execute(
-            "ALTER TABLE rag_document_chunks ADD COLUMN metadata TEXT NOT NULL DEFAULT '{}'",
-            [],
-        ) {
-            if !e.to_string().contains("duplicate column name") {
-                return Err(AppError::database(format!(
-                    "补齐 rag_document_chunks.metadata 列失败: {}",
-                    e
-                )));
-            }
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rag_documents (
+            id TEXT PRIMARY KEY,
+            file_name TEXT NOT NULL,
+            file_path TEXT,
+            file_size INTEGER,
+            content_type TEXT,
+            total_chunks INTEGER DEFAULT 0,
+            sub_library_id TEXT NOT NULL DEFAULT 'default',
+            update_state TEXT NOT NULL DEFAULT 'ready',
+            desired_hash TEXT,
+            update_retry INTEGER NOT NULL DEFAULT 0,
+            last_error TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (sub_library_id) REFERENCES rag_sub_libraries (id) ON DELETE SET DEFAULT
+        )",
+        [],
+    )
+    .map_err(|e| AppError::database(format!("创建文档表失败: {}", e)))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rag_document_chunks (
+            id TEXT PRIMARY KEY,
+            document_id TEXT NOT NULL REFERENCES rag_documents(id) ON DELETE CASCADE,
+            chunk_index INTEGER NOT NULL,
+            text TEXT NOT NULL,
+            metadata TEXT NOT NULL DEFAULT '{}'
+        )",
+        [],
+    )
+    .map_err(|e| AppError::database(format!("创建文档分块表失败: {}", e)))?;
+
+    if let Err(e) = conn.execute(
+        "ALTER TABLE rag_document_chunks ADD COLUMN metadata TEXT NOT NULL DEFAULT '{}'",
+        [],
+    ) {
+        if !e.to_string().contains("duplicate column name") {
+            return Err(AppError::database(format!(
+                "补齐 rag_document_chunks.metadata 列失败: {}",
+                e
+            )));
         }
+    }
 
-        if let Err(e) = conn.execute(
-            "ALTER TABLE rag_document_chunks ADD COLUMN chunk_index INTEGER NOT NULL DEFAULT 0",
-            [],
-        ) {
-            if !e.to_string().contains("duplicate column name") {
-                return Err(AppError::database(format!(
-                    "补齐 rag_document_chunks.chunk_index 列失败: {}",
-                    e
-                )));
-            }
+    if let Err(e) = conn.execute(
+        "ALTER TABLE rag_document_chunks ADD COLUMN chunk_index INTEGER NOT NULL DEFAULT 0",
+        [],
+    ) {
+        if !e.to_string().contains("duplicate column name") {
+            return Err(AppError::database(format!(
+                "补齐 rag_document_chunks.chunk_index 列失败: {}",
+                e
+            )));
         }
+    }
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_rag_document_chunks_document ON rag_document_chunks(document_id)",
-            [],
-        )
-        .map_err(|e| AppError::database(format!("创建文档分块索引失败: {}", e)))?;
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_rag_document_chunks_doc_chunk ON rag_document_chunks(document_id, chunk_index)",
-            [],
-        )
-        .map_err(|e| AppError::database(format!("创建文档分块序索引失败: {}", e)))?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS rag_vectors (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                chunk_id TEXT NOT NULL REFERENCES rag_document_chunks(id) ON DELETE CASCADE,
-                dimension INTEGER NOT NULL DEFAULT 0,
-                embedding BLOB NOT NULL
-            )",
-            [],
-        )
-        .map_err(|e| AppError::database(format!("创建向量表失败: {}", e)))?;
-
-        if let Err(e) = conn.execute(
-            "ALTER TABLE rag_vectors ADD COLUMN dimension INTEGER NOT NULL DEFAULT 0",
-            [],
-        ) {
-            if !e.to_string().contains("duplicate column name") {
-                return Err(AppError::database(format!(
-                    "补齐 rag_vectors.dimension 列失败: {}",
-                    e
-                )));
-            }
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_rag_document_chunks_document ON rag_document_chunks(document_id)",
+        [],
+    )
+    .map_err(|e| AppError::database(format!("创建文档分块索引失败: {}", e)))?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_rag_document_chunks_doc_chunk ON rag_document_chunks(document_id, chunk_index)",
+        [],
+    )
+    .map_err(|e| AppError::database(format!("创建文档分块序索引失败: {}", e)))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rag_vectors (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chunk_id TEXT NOT NULL REFERENCES rag_document_chunks(id) ON DELETE CASCADE,
+            dimension INTEGER NOT NULL DEFAULT 0,
+            embedding BLOB NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| AppError::database(format!("创建向量表失败: {}", e)))?;
+
+    if let Err(e) = conn.execute(
+        "ALTER TABLE rag_vectors ADD COLUMN dimension INTEGER NOT NULL DEFAULT 0",
+        [],
+    ) {
+        if !e.to_string().contains("duplicate column name") {
+            return Err(AppError::database(format!(
+                "补齐 rag_vectors.dimension 列失败: {}",
+                e
+            )));
         }
-
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_rag_vectors_chunk ON rag_vectors(chunk_id)",
-            [],
-        )
-        .map_err(|e| AppError::database(format!("创建向量块索引失败: {}", e)))?;
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_rag_vectors_dimension ON rag_vectors(dimension)",
-            [],
-        )
-        .map_err(|e| AppError::database(format!("创建向量维度索引失败: {}", e)))?;
-
-        let _ = conn.prepare("SELECT sub_library_id FROM rag_documents LIMIT 1");
-        let _ = conn.execute(
-            "ALTER TABLE rag_documents ADD COLUMN sub_library_id TEXT NOT NULL DEFAULT 'default'",
-            [],
-        );
-        let _ = conn.prepare("SELECT update_state FROM rag_documents LIMIT 1");
-        let _ = conn.execute(
-            "ALTER TABLE rag_documents ADD COLUMN update_state TEXT NOT NULL DEFAULT 'ready'",
-            [],
-        );
-        let _ = conn.prepare("SELECT desired_hash FROM rag_documents LIMIT 1");
-        let _ = conn.execute("ALTER TABLE rag_documents ADD COLUMN desired_hash TEXT", []);
-        let _ = conn.prepare("SELECT update_retry FROM rag_documents LIMIT 1");
-        let _ = conn.execute(
-            "ALTER TABLE rag_documents ADD COLUMN update_retry INTEGER NOT NULL DEFAULT 0",
-            [],
-        );
-        let _ = conn.prepare("SELECT active_revision FROM rag_documents LIMIT 1");
-        let _ = conn.execute(
-            "ALTER TABLE rag_documents ADD COLUMN active_revision TEXT NOT NULL DEFAULT 'A'",
-            [],
-        );
-
-        let _ = conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_rag_documents_sub_library ON rag_documents(sub_library_id)",
-            [],
-        );
-        let _ = conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_rag_sub_libraries_name ON rag_sub_libraries(name)",
-            [],
-        );
-
-        // 静默确认，避免日志风暴
-        Ok(())
     }
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_rag_vectors_chunk ON rag_vectors(chunk_id)",
+        [],
+    )
+    .map_err(|e| AppError::database(format!("创建向量块索引失败: {}", e)))?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_rag_vectors_dimension ON rag_vectors(dimension)",
+        [],
+    )
+    .map_err(|e| AppError::database(format!("创建向量维度索引失败: {}", e)))?;
+
+    let _ = conn.prepare("SELECT sub_library_id FROM rag_documents LIMIT 1");
+    let _ = conn.execute(
+        "ALTER TABLE rag_documents ADD COLUMN sub_library_id TEXT NOT NULL DEFAULT 'default'",
+        [],
+    );
+    let _ = conn.prepare("SELECT update_state FROM rag_documents LIMIT 1");
+    let _ = conn.execute(
+        "ALTER TABLE rag_documents ADD COLUMN update_state TEXT NOT NULL DEFAULT 'ready'",
+        [],
+    );
+    let _ = conn.prepare("SELECT desired_hash FROM rag_documents LIMIT 1");
+    let _ = conn.execute("ALTER TABLE rag_documents ADD COLUMN desired_hash TEXT", []);
+    let _ = conn.prepare("SELECT update_retry FROM rag_documents LIMIT 1");
+    let _ = conn.execute(
+        "ALTER TABLE rag_documents ADD COLUMN update_retry INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+    let _ = conn.prepare("SELECT active_revision FROM rag_documents LIMIT 1");
+    let _ = conn.execute(
+        "ALTER TABLE rag_documents ADD COLUMN active_revision TEXT NOT NULL DEFAULT 'A'",
+        [],
+    );
+    let _ = conn.prepare("SELECT last_error FROM rag_documents LIMIT 1");
+    let _ = conn.execute("ALTER TABLE rag_documents ADD COLUMN last_error TEXT", []);
+
+    let _ = conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_rag_documents_sub_library ON rag_documents(sub_library_id)",
+        [],
+    );
+    let _ = conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_rag_sub_libraries_name ON rag_sub_libraries(name)",
+        [],
+    );
+
+    // 静默确认，避免日志风暴
+    Ok(())
 }
 #[async_trait]
 impl VectorStore for LanceVectorStore {
@@ -2705,6 +3504,7 @@ impl VectorStore for LanceVectorStore {
             if chunks.is_empty() {
                 return Ok(());
             }
+            let _write_guard = KbWriteGuard::enter();
             let dim = chunks[0].embedding.len();
             if dim == 0 {
                 return Err(AppError::validation("embedding 维度不可为 0"));
@@ -2800,7 +3600,19 @@ impl VectorStore for LanceVectorStore {
             }
 
             self.write_chunks_to_sqlite(&rows)?;
-            self.write_chunks_to_wide_table(dim, &rows).await?;
+            if let Err(e) = self.write_chunks_to_wide_table(dim, &rows).await {
+                // 分块已落库但向量缺失，标记为 partial 以便 retry_failed_ingestions 补齐
+                for document_id in &doc_ids {
+                    if let Err(mark_err) = self.database.mark_rag_document_failed(
+                        document_id,
+                        "partial",
+                        &e.to_string(),
+                    ) {
+                        warn!("标记文档 {} 导入状态失败: {}", document_id, mark_err);
+                    }
+                }
+                return Err(e);
+            }
             Ok(())
         }
     }
@@ -3201,7 +4013,7 @@ impl VectorStore for LanceVectorStore {
             .get_conn_safe()
             .map_err(|e| AppError::database(e.to_string()))?;
         let mut stmt = conn.prepare(
-            "SELECT id, file_name, file_path, file_size, total_chunks, created_at, updated_at FROM rag_documents ORDER BY created_at DESC"
+            "SELECT id, file_name, file_path, file_size, total_chunks, created_at, updated_at, update_state, update_retry, last_error FROM rag_documents ORDER BY created_at DESC"
         ).map_err(|e| AppError::database(format!("准备查询语句失败: {}", e)))?;
         let rows = stmt
             .query_map([], |row| {
@@ -3213,6 +4025,10 @@ impl VectorStore for LanceVectorStore {
                     "total_chunks": row.get::<_, i32>(4)?,
                     "created_at": row.get::<_, String>(5)?,
                     "updated_at": row.get::<_, String>(6)?,
+                    // failed / partial 的文档附带最近一次错误，便于前端提示重试
+                    "update_state": row.get::<_, String>(7)?,
+                    "update_retry": row.get::<_, i64>(8)?,
+                    "last_error": row.get::<_, Option<String>>(9)?,
                 }))
             })
             .map_err(|e| AppError::database(format!("查询文档列表失败: {}", e)))?;
@@ -3348,15 +4164,18 @@ impl LanceVectorStore {
         use futures_util::TryStreamExt;
         let fetch_limit: usize = std::cmp::max(1, limit).saturating_mul(10);
         let tbl = self.ensure_wide_table(query_embedding.len()).await?;
+        let index_settings = load_vector_index_settings(&self.database);
 
-        let mut stream = tbl
-            .vector_search(query_embedding)
-            .map_err(|e| AppError::database(e.to_string()))?
-            .distance_type(DistanceType::Cosine)
-            .limit(fetch_limit)
-            .execute()
-            .await
-            .map_err(|e| AppError::database(e.to_string()))?;
+        let mut stream = Self::apply_vector_index_params(
+            tbl.vector_search(query_embedding)
+                .map_err(|e| AppError::database(e.to_string()))?,
+            &index_settings,
+        )
+        .distance_type(DistanceType::Cosine)
+        .limit(fetch_limit)
+        .execute()
+        .await
+        .map_err(|e| AppError::database(e.to_string()))?;
 
         let filter_set: Option<std::collections::HashSet<&str>> =
             sub_library_ids.map(|v| v.iter().map(|s| s.as_str()).collect());
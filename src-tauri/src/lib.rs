@@ -4,11 +4,22 @@
 
 // 声明所有子模块，以便在 crate 内可见
 pub mod adapters;
+pub mod anki_card_limits;
+pub mod anki_card_output;
+pub mod anki_prompt_placeholders;
+pub mod anki_raw_output;
+pub mod anki_source_tags;
+pub mod anki_task_cleanup;
+pub mod anki_field_html;
+pub mod anki_field_mapping;
+pub mod anki_field_selection;
 pub mod anki_connect_service;
 pub mod apkg_exporter_service;
 pub mod backup_job_manager;
 pub mod batch_operations;
+pub mod chat_autosave;
 pub mod cmd;
+pub mod command_metrics;
 pub mod commands;
 pub mod config_recovery;
 pub mod crash_logger;
@@ -23,6 +34,7 @@ pub mod anr_watchdog; // ANR 看门狗（Android 主线程卡顿检测）
 pub mod deepseek_ocr_parser;
 pub mod document_parser;
 pub mod document_processing_service;
+pub mod embedding_export;
 pub mod enhanced_anki_service;
 pub mod error_details;
 pub mod error_recovery;
@@ -33,12 +45,14 @@ pub mod injection_budget;
 pub mod json_validator;
 pub mod ocr_adapters; // OCR 适配器模块（支持多种 OCR 引擎）
 pub mod ocr_circuit_breaker; // OCR 熔断器（三态：Closed/Open/HalfOpen）
+pub mod ocr_language; // OCR 语言识别与按语言路由模型
 pub mod pdf_ocr_service;
 pub mod pdf_protocol;
 pub mod pdfium_utils; // Pdfium 公共工具（库加载 + 文本提取）
 pub mod question_bank_service;
 pub mod question_export_service;
 pub mod cross_page_merger;
+pub mod exam_segmentation;
 pub mod figure_extractor;
 pub mod llm_structurer;
 pub mod page_rasterizer;
@@ -48,21 +62,35 @@ pub mod secure_store;
 pub mod backup_common;
 pub mod backup_config;
 pub mod data_space;
+pub mod disk_guard;
 pub mod lance_vector_store;
 pub mod llm_manager;
 #[cfg(feature = "mcp")]
 pub mod mcp;
 pub mod metrics_server;
+pub mod mistake_auto_summary;
+pub mod mistake_auto_tagging;
 pub mod models;
 pub mod notes_exporter;
 pub mod notes_manager;
+pub mod offline_mode;
 pub mod package_manager;
 pub mod persistent_message_queue;
+pub mod pii_redaction; // 发送云端模型前的隐私脱敏
 pub mod providers;
+pub mod rag_debug;
+pub mod rag_embedding_override;
+pub mod rag_grounding;
+pub mod rag_library_weights;
+pub mod rag_query_expansion;
+pub mod rag_settings;
 pub mod reasoning_policy; // 思维链回传策略模块（文档 29 第 7 节）
+pub mod response_language;
+pub mod response_verbosity; // 回答详略程度（对话与制卡分别设置）
 pub mod services;
 pub mod session_manager;
 pub mod startup_cleanup;
+pub mod stream_events;
 pub mod streaming_anki_service;
 pub mod textbooks_db;
 pub mod tools;
@@ -73,6 +101,7 @@ pub mod vfs; // VFS 虚拟文件系统（统一资源存储） // DSTU 访达协
 pub mod memory; // Memory-as-VFS 记忆系统（复用 VFS 基础设施）
 pub mod unified_file_manager;
 pub mod utils;
+pub mod vector_index;
 pub mod vector_store;
 pub mod workflow_error_handler;
 pub mod essay_grading;
@@ -655,7 +684,9 @@ pub fn run() {
                 let app_handle_for_mcp = app_handle.clone();
                 tauri::async_runtime::spawn(async move {
                     let mode = database_for_mcp.get_setting("mcp.mode").ok().flatten().unwrap_or_else(|| "frontend".to_string());
-                    if mode == "backend" {
+                    if mode == "backend" && crate::offline_mode::is_enabled() {
+                        info!("🔌 [MCP] 离线模式已开启，跳过后端MCP初始化");
+                    } else if mode == "backend" {
                         if let Err(e) = init_mcp_client(database_for_mcp, Some(app_handle_for_mcp)).await {
                             error!("❌ MCP 客户端初始化失败: {}", e);
                         } else {
@@ -668,7 +699,7 @@ pub fn run() {
             }
 
 
-            // 启动后异步触发一次 Lance 聊天表的轻量优化（压缩合并+清理近期旧版本+索引优化）
+            // 启动后异步触发一次 Lance 聊天表的轻量优化（压缩合并+清理近期旧版本+索引优化），并按设置整理知识库向量表
             {
                 let database_for_maint = database.clone();
                 tauri::async_runtime::spawn(async move {
@@ -676,6 +707,10 @@ pub fn run() {
                     tokio::time::sleep(std::time::Duration::from_secs(6)).await;
                     if let Ok(store) = crate::lance_vector_store::LanceVectorStore::new(database_for_maint.clone()) {
                         let _ = store.optimize_chat_tables(Some(7), None, false).await; // 默认清理 >7 天版本
+                        // 知识库向量表整理默认关闭，需开启 lance.optimize.kb_on_maintenance
+                        if let Err(e) = store.run_scheduled_kb_optimization().await {
+                            warn!("[Lance优化] 定时整理知识库向量表跳过: {}", e);
+                        }
                     }
                 });
             }
@@ -715,6 +750,17 @@ pub fn run() {
                 });
             }
 
+            // 错题定时导出调度器
+            #[cfg(feature = "data_governance")]
+            {
+                let database_for_export = database.clone();
+                tauri::async_runtime::spawn(async move {
+                    crate::data_governance::export_schedule::start_export_schedule_scheduler(
+                        database_for_export,
+                    ).await;
+                });
+            }
+
             let database_for_queue = database.clone();
 
             let llm_for_queue = app_state.inner().llm_manager.clone();
@@ -848,14 +894,18 @@ pub fn run() {
             crate::commands::save_model_assignments,
             crate::commands::get_vendor_configs,
             crate::commands::save_vendor_configs,
+            crate::commands::save_llm_request_secret,
+            crate::commands::delete_llm_request_secret,
             crate::commands::get_model_profiles,
             crate::commands::save_model_profiles,
             crate::commands::test_api_connection,
+            crate::commands::test_api_config_connection,
 
             crate::commands::get_model_adapter_options,
             crate::commands::save_model_adapter_options,
             crate::commands::reset_model_adapter_options,
             crate::commands::estimate_tokens,
+            crate::commands::count_tokens,
             // OCR 引擎配置命令
             crate::commands::get_ocr_engines,
             crate::commands::get_ocr_engine_type,
@@ -871,6 +921,8 @@ pub fn run() {
             crate::commands::update_ocr_engine_priority,
             crate::commands::add_ocr_engine,
             crate::commands::remove_ocr_engine,
+            crate::commands::get_ocr_language_routing,
+            crate::commands::save_ocr_language_routing,
             // Lance 向量表优化命令
             crate::commands::optimize_chat_embeddings_table,
             crate::commands::create_performance_indexes,
@@ -882,11 +934,18 @@ pub fn run() {
             crate::commands::generate_anki_cards_from_document_base64,
             crate::commands::call_llm_for_boundary, // CardForge 2.0 - LLM 定界
             crate::commands::check_anki_connect_status,
+            crate::commands::get_anki_connect_settings,
+            crate::commands::save_anki_connect_settings,
             crate::commands::get_anki_deck_names,
             crate::commands::get_anki_model_names,
+            crate::commands::get_anki_model_field_names,
+            crate::commands::get_anki_field_mappings,
+            crate::commands::save_anki_field_mapping,
+            crate::commands::delete_anki_field_mapping,
             crate::commands::create_anki_deck,
             crate::commands::save_anki_cards,
             crate::commands::add_cards_to_anki_connect,
+            crate::commands::add_cards_to_anki_connect_detailed,
             crate::commands::import_anki_package,
             crate::commands::export_cards_as_apkg,
             crate::commands::export_cards_as_apkg_with_template,
@@ -911,6 +970,13 @@ pub fn run() {
             crate::commands::list_anki_library_cards,
             crate::commands::export_anki_cards,
             crate::cmd::enhanced_anki::recover_stuck_document_tasks,
+            crate::cmd::enhanced_anki::prune_completed_tasks,
+            crate::cmd::enhanced_anki::get_task_cleanup_settings,
+            crate::cmd::enhanced_anki::save_task_cleanup_settings,
+            crate::cmd::enhanced_anki::get_task_raw_output,
+            crate::cmd::enhanced_anki::clear_task_raw_outputs,
+            crate::cmd::enhanced_anki::get_raw_output_capture_settings,
+            crate::cmd::enhanced_anki::save_raw_output_capture_settings,
             crate::cmd::enhanced_anki::list_document_sessions,
             crate::cmd::enhanced_anki::get_anki_stats,
             // 状态恢复相关命令
@@ -970,10 +1036,15 @@ pub fn run() {
             crate::commands::import_builtin_templates,
             crate::commands::set_default_template,
             crate::commands::get_default_template_id,
+            crate::commands::set_subject_default_template,
+            crate::commands::get_subject_default_templates,
             crate::commands::save_test_log,
             crate::commands::get_test_logs,
             crate::commands::open_log_file,
             crate::commands::open_logs_folder,
+            crate::commands::read_log_tail,
+            crate::commands::stream_logs,
+            crate::commands::stop_log_stream,
             crate::commands::report_frontend_log,
             crate::commands::save_template_debug_data,
             crate::commands::export_unified_backup_data,
@@ -1100,6 +1171,139 @@ pub fn run() {
             ,crate::commands::reset_test_database
             ,crate::commands::switch_to_production_database
             ,crate::commands::get_database_info
+            // 数据目录迁移与磁盘空间保护
+            ,crate::commands::get_data_location
+            ,crate::commands::relocate_database
+            ,crate::commands::get_disk_guard_settings
+            ,crate::commands::save_disk_guard_settings
+            // 回顾分析导出
+            ,crate::commands::export_review_analysis
+            ,crate::commands::generate_study_guide
+            // 回顾分析删除与归档
+            ,crate::commands::delete_review_analysis
+            ,crate::commands::batch_delete_review_analyses
+            ,crate::commands::archive_review_analysis
+            ,crate::commands::unarchive_review_analysis
+            // 统计数据导出
+            ,crate::commands::export_statistics
+            // 错题归档
+            ,crate::commands::archive_mistake
+            ,crate::commands::unarchive_mistake
+            ,crate::commands::batch_archive_mistakes
+            ,crate::commands::list_archived_mistakes
+            ,crate::commands::get_mistake_archive_counts
+            // 错题置顶与星级
+            ,crate::commands::set_mistake_pin
+            ,crate::commands::get_mistake_pin
+            // 错题用户批注
+            ,crate::commands::update_mistake_user_notes
+            ,crate::commands::get_mistake_user_notes
+            ,crate::commands::search_mistakes_fulltext
+            // 全局搜索（错题 / 卡片 / 错题对话 / 知识库）
+            ,crate::commands::global_search
+            // 错题语言
+            ,crate::commands::update_mistake_language
+            // 错题难度与掌握度
+            ,crate::commands::update_mistake_mastery
+            ,crate::commands::record_review_outcome
+            ,crate::commands::build_review_set
+            // 错题状态按条件批量更新
+            ,crate::commands::update_mistake_statuses_by_filter
+            // 错题标签建议
+            ,crate::commands::suggest_tags
+            ,crate::commands::auto_tag_mistake
+            ,crate::commands::get_auto_tagging_settings
+            ,crate::commands::save_auto_tagging_settings
+            // 重复错题查找与合并
+            ,crate::commands::find_duplicate_mistakes
+            ,crate::commands::merge_mistakes
+            ,crate::commands::check_mistake_duplicate
+            ,crate::commands::get_duplicate_threshold
+            ,crate::commands::save_duplicate_threshold
+            // 错题手动关联（前置 / 相关 / 重复）
+            ,crate::commands::link_mistakes
+            ,crate::commands::unlink_mistakes
+            ,crate::commands::get_related_mistakes
+            // 错题批量导入（CSV / JSON）与导出
+            ,crate::commands::import_mistakes
+            ,crate::commands::batch_export_mistakes
+            // 错题自动同步到知识库
+            ,crate::commands::get_mistake_kb_sync_settings
+            ,crate::commands::save_mistake_kb_sync_settings
+            ,crate::commands::sync_mistake_to_knowledge_base
+            ,crate::commands::sync_mistakes_to_knowledge_base
+            // 分析结果一次性保存（错题 + 对话 + 卡片）
+            ,crate::commands::save_analysis_bundle
+            // 相关错题推荐（可配置权重）
+            ,crate::commands::get_ai_recommendations
+            ,crate::commands::get_recommendation_weights
+            ,crate::commands::save_recommendation_weights_cmd
+            ,crate::commands::clear_recommendation_cache
+            // 数据库批量清理（预览 + 确认执行）
+            ,crate::commands::batch_cleanup_database
+            // 学科配置导出/导入
+            ,crate::commands::export_subject_configs
+            ,crate::commands::import_subject_configs
+            // 错题聊天自动保存（防抖合并）
+            ,crate::commands::autosave_mistake_chat
+            ,crate::commands::flush_mistake_chat_autosave
+            // 错题对话回合分支（备选回答）
+            ,crate::commands::get_mistake_chat_branches
+            ,crate::commands::set_active_chat_branch
+            ,crate::commands::delete_chat_branch
+            ,crate::commands::export_mistake_chat_branch
+            // 全库回合元数据重建（旧备份导入后的一次性修复）
+            ,crate::commands::rebuild_all_turn_metadata
+            // 错题总结（手动 / 自动生成）
+            ,crate::commands::generate_mistake_summary
+            ,crate::commands::get_mistake_summary_status
+            ,crate::commands::close_mistake_chat_session
+            ,crate::commands::get_auto_summary_settings
+            ,crate::commands::save_auto_summary_settings
+            ,crate::commands::batch_generate_summaries
+            ,crate::commands::cancel_batch_generate_summaries
+            // 知识库文档管理
+            ,crate::commands::move_documents_to_library
+            ,crate::commands::preview_sub_library_deletion
+            ,crate::commands::delete_sub_library
+            ,crate::commands::rag_delete_document
+            ,crate::commands::gc_orphan_vectors
+            ,crate::commands::get_document_chunks
+            ,crate::commands::get_knowledge_base_library_stats
+            ,crate::commands::retry_failed_ingestions
+            // 知识库文档导入（可推送进度）
+            ,crate::commands::rag_add_documents
+            ,crate::commands::rag_add_documents_to_library
+            ,crate::commands::rag_add_documents_with_progress
+            // 知识库向量索引（ANN）
+            ,crate::commands::get_vector_index_settings
+            ,crate::commands::save_vector_index_settings
+            ,crate::commands::build_vector_index
+            ,crate::commands::optimize_vector_store
+            // 知识库检索参数
+            ,crate::commands::get_rag_settings
+            ,crate::commands::update_rag_settings
+            ,crate::commands::reset_rag_settings
+            ,crate::commands::get_rag_embedding_batch_size
+            ,crate::commands::get_stale_document_count
+            ,crate::commands::reindex_stale_documents
+            ,crate::commands::rag_reindex_documents
+            ,crate::commands::cancel_reindex_stale_documents
+            // RAG 回答溯源校验
+            ,crate::commands::get_grounding_check_settings
+            ,crate::commands::save_grounding_check_settings
+            // 系统诊断快照
+            ,crate::commands::get_diagnostics
+            ,crate::commands::get_command_metrics
+            ,crate::commands::reset_command_metrics
+            // 隐私脱敏（发送云端模型前）
+            ,crate::commands::get_pii_redaction_settings
+            ,crate::commands::save_pii_redaction_settings
+            ,crate::commands::verify_rag_answer_grounding
+            // 向量导出
+            ,crate::commands::export_embeddings
+            // 历史数据嵌入回填
+            ,crate::commands::backfill_embeddings
             ,crate::commands::seed_test_database
             ,crate::commands::check_test_dependencies
             ,crate::commands::set_test_run_id
@@ -1327,6 +1531,7 @@ pub fn run() {
             ,crate::llm_usage::handlers::llm_usage_recent
             ,crate::llm_usage::handlers::llm_usage_daily
             ,crate::llm_usage::handlers::llm_usage_cleanup
+            ,crate::llm_usage::handlers::get_llm_usage_stats
             // =================================================
             // DSTU 访达协议层命令
             // =================================================
@@ -1547,8 +1752,13 @@ pub fn run() {
             ,crate::data_governance::commands_sync::data_governance_resolve_conflicts
             ,crate::data_governance::commands_sync::data_governance_run_sync
             ,crate::data_governance::commands_sync::data_governance_run_sync_with_progress
+            ,crate::data_governance::commands_sync::data_governance_preview_sync
             ,crate::data_governance::commands_sync::data_governance_export_sync_data
             ,crate::data_governance::commands_sync::data_governance_import_sync_data
+            // 错题定时导出到云端
+            ,crate::data_governance::export_schedule::get_export_schedule
+            ,crate::data_governance::export_schedule::set_export_schedule
+            ,crate::data_governance::export_schedule::run_export_schedule_now
             // 任务恢复命令（断点续传支持）
             ,crate::data_governance::commands_backup::data_governance_resume_backup_job
             ,crate::data_governance::commands_backup::data_governance_list_resumable_jobs
@@ -1721,6 +1931,21 @@ fn build_app_state(
         }
     }
 
+    // AnkiConnect 连接配置（自定义地址/重试）
+    crate::anki_connect_service::load_anki_connect_settings(&database);
+
+    // 离线模式（禁止一切出站网络请求）
+    crate::offline_mode::load_offline_mode_setting(&database);
+
+    // 命令耗时统计（排查卡顿用，默认关闭）
+    crate::command_metrics::load_command_metrics_setting(&database);
+
+    // 流式增量的旧事件兼容开关（默认开启，前端迁移到统一通道后关闭）
+    crate::stream_events::load_legacy_events_setting(&database);
+
+    // 发送云端模型前的隐私脱敏（默认关闭）
+    crate::pii_redaction::init_pii_redaction(&database);
+
     // 🔧 Phase 1: 启动时恢复卡住的 Anki 制卡任务
     match anki_database.recover_stuck_document_tasks() {
         Ok(count) if count > 0 => {
@@ -1732,6 +1957,14 @@ fn build_app_state(
         }
     }
 
+    // 按设置清理过期的已完成制卡任务（默认关闭）
+    crate::anki_task_cleanup::run_scheduled_task_cleanup(&database, &anki_database);
+
+    // 启动时回收不再被任何消息引用的文档附件载荷
+    if let Err(e) = database.gc_orphan_attachments() {
+        tracing::warn!("[AppSetup] Failed to collect orphan doc attachments: {}", e);
+    }
+
     // 设置 AppHandle 到 PdfProcessingService（供事件推送使用）
     if let Some(ref pps) = pdf_processing_service {
         let pdf_service_for_handle = pps.clone();
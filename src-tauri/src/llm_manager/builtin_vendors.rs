@@ -571,6 +571,8 @@ impl BuiltinVendor {
             base_url: self.base_url.to_string(),
             api_key: String::new(),
             headers: HashMap::new(),
+            query_params: HashMap::new(),
+            allow_reserved_header_override: false,
             rate_limit_per_minute: None,
             default_timeout_ms: None,
             notes: Some(self.notes.to_string()),
@@ -639,6 +641,13 @@ impl BuiltinModel {
             reasoning_split: None,
             effort: None,
             verbosity: None,
+            stream_coalesce: None,
+            thinking_delivery: None,
+            supports_streaming: true,
+            force_non_streaming: false,
+            first_token_timeout_ms: None,
+            idle_timeout_ms: None,
+            max_request_retries: None,
         }
     }
 }
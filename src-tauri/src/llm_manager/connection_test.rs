@@ -0,0 +1,357 @@
+//! 模型配置的连接测试（含流式输出检测）
+//!
+//! 只发非流式请求无法发现会破坏 SSE 的反向代理：测试显示连接正常，真正的流式分析却失败。
+//! 这里按已保存的模型配置（与流式调用相同的适配器、地址与自定义请求选项）先发一个极短的
+//! 非流式请求，再可选地发一个流式请求，要求在超时内收到至少两个内容数据块。
+//!
+//! 结果区分认证失败、请求失败、非流式正常但流式失败、完全正常四种情况。模型配置为
+//! 非流式模式时，实际调用本就不走 SSE，不再检测流式输出。
+
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::json;
+
+use super::{ApiConfig, LLMManager, Result, StreamMode};
+use crate::models::AppError;
+use crate::providers::{ProviderAdapter, ProviderRequest, StreamEvent};
+use crate::utils::sse_buffer::SseLineBuffer;
+
+/// 非流式测试请求的超时
+const NON_STREAM_TEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// 流式测试从发出请求到收齐数据块的超时
+const STREAM_TEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// 认定流式输出正常所需的最少内容数据块数
+const MIN_STREAM_CHUNKS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionTestStatus {
+    /// 非流式与流式请求均正常（未检测流式时仅非流式正常）
+    Ok,
+    /// 认证失败（401 / 403）
+    AuthFailed,
+    /// 非流式请求失败（网络、地址、模型名等）
+    RequestFailed,
+    /// 非流式请求正常，但流式请求失败或数据块不足
+    StreamFailed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiConnectionTestResult {
+    pub status: ConnectionTestStatus,
+    pub config_id: String,
+    pub model: String,
+    /// 该配置实际调用时使用的请求模式
+    pub stream_mode: StreamMode,
+    /// 是否执行了流式检测
+    pub stream_checked: bool,
+    /// 流式检测收到的内容数据块数
+    pub stream_chunks: usize,
+    /// 失败请求的 HTTP 状态码
+    pub http_status: Option<u16>,
+    pub message: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// 单次测试请求的失败原因
+struct ProbeFailure {
+    http_status: Option<u16>,
+    message: String,
+}
+
+impl ProbeFailure {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            http_status: None,
+            message: message.into(),
+        }
+    }
+
+    fn is_auth_error(&self) -> bool {
+        matches!(self.http_status, Some(401 | 403))
+    }
+}
+
+/// 统计一段 SSE 数据中的内容数据块，返回是否已收到结束标记
+fn count_stream_chunks(
+    adapter: &dyn ProviderAdapter,
+    sse_buffer: &mut SseLineBuffer,
+    bytes: &[u8],
+    chunks: &mut usize,
+) -> bool {
+    for line in sse_buffer.process_chunk(&String::from_utf8_lossy(bytes)) {
+        if SseLineBuffer::check_done_marker(&line) {
+            return true;
+        }
+        for event in adapter.parse_stream(&line) {
+            match event {
+                StreamEvent::ContentChunk(text) | StreamEvent::ReasoningChunk(text)
+                    if !text.is_empty() =>
+                {
+                    *chunks += 1
+                }
+                StreamEvent::Done => return true,
+                _ => {}
+            }
+        }
+    }
+    false
+}
+
+impl LLMManager {
+    /// 按已保存的模型配置测试连接，`check_streaming` 为真时额外检测流式输出
+    pub async fn test_api_config_connection(
+        &self,
+        config_id: &str,
+        check_streaming: bool,
+    ) -> Result<ApiConnectionTestResult> {
+        let config = self
+            .get_api_configs()
+            .await?
+            .into_iter()
+            .find(|c| c.id == config_id)
+            .ok_or_else(|| {
+                AppError::configuration(format!("找不到指定的模型配置: {}", config_id))
+            })?;
+        crate::offline_mode::ensure_endpoint_allowed("模型连接测试", &config.base_url)?;
+
+        let started = Instant::now();
+        let stream_mode = StreamMode::for_config(&config);
+        let mut result = ApiConnectionTestResult {
+            status: ConnectionTestStatus::Ok,
+            config_id: config.id.clone(),
+            model: config.model.clone(),
+            stream_mode,
+            stream_checked: false,
+            stream_chunks: 0,
+            http_status: None,
+            message: None,
+            elapsed_ms: 0,
+        };
+
+        if let Err(failure) = self.probe_non_stream(&config).await {
+            result.status = if failure.is_auth_error() {
+                ConnectionTestStatus::AuthFailed
+            } else {
+                ConnectionTestStatus::RequestFailed
+            };
+            result.http_status = failure.http_status;
+            result.message = Some(failure.message);
+        } else if check_streaming && stream_mode.is_streaming() {
+            result.stream_checked = true;
+            match self.probe_stream(&config).await {
+                Ok(chunks) => result.stream_chunks = chunks,
+                Err((chunks, failure)) => {
+                    result.status = ConnectionTestStatus::StreamFailed;
+                    result.stream_chunks = chunks;
+                    result.http_status = failure.http_status;
+                    result.message = Some(failure.message);
+                }
+            }
+        }
+        result.elapsed_ms = started.elapsed().as_millis() as u64;
+
+        match &result.message {
+            Some(message) => log::warn!(
+                "[API测试] 模型 {} 测试未通过 ({:?}): {}",
+                result.model,
+                result.status,
+                message
+            ),
+            None => log::info!(
+                "[API测试] 模型 {} 连接正常（流式检测: {}，数据块 {}）",
+                result.model,
+                result.stream_checked,
+                result.stream_chunks
+            ),
+        }
+        Ok(result)
+    }
+
+    /// 与流式调用相同的适配器选择
+    fn connection_test_adapter(&self, config: &ApiConfig) -> Box<dyn ProviderAdapter> {
+        if self.should_use_openai_responses(config) {
+            Box::new(crate::providers::OpenAIResponsesAdapter)
+        } else {
+            match config.model_adapter.as_str() {
+                "google" | "gemini" => Box::new(crate::providers::GeminiAdapter::new()),
+                "anthropic" | "claude" => Box::new(crate::providers::AnthropicAdapter::new()),
+                _ => Box::new(crate::providers::OpenAIAdapter),
+            }
+        }
+    }
+
+    fn build_connection_test_request(
+        &self,
+        config: &ApiConfig,
+        adapter: &dyn ProviderAdapter,
+        prompt: &str,
+        max_tokens: u32,
+        stream: bool,
+    ) -> std::result::Result<(ProviderRequest, reqwest::RequestBuilder), ProbeFailure> {
+        let body = json!({
+            "model": config.model,
+            "messages": [{"role": "user", "content": prompt}],
+            "max_tokens": max_tokens,
+            "stream": stream
+        });
+        let preq = adapter
+            .build_request(&config.base_url, &config.api_key, &config.model, &body)
+            .map_err(|e| ProbeFailure::new(format!("请求构建失败: {}", e)))?;
+        let mut builder = self.client.post(&preq.url).header(
+            "Accept",
+            if stream {
+                "text/event-stream"
+            } else {
+                "application/json"
+            },
+        );
+        for (k, v) in &preq.headers {
+            builder = builder.header(k.clone(), v.clone());
+        }
+        let builder = self
+            .apply_custom_request_options(builder, config)
+            .map_err(|e| ProbeFailure::new(e.message))?;
+        Ok((preq, builder))
+    }
+
+    async fn probe_non_stream(&self, config: &ApiConfig) -> std::result::Result<(), ProbeFailure> {
+        let adapter = self.connection_test_adapter(config);
+        let (preq, builder) =
+            self.build_connection_test_request(config, adapter.as_ref(), "Hi", 16, false)?;
+        let response =
+            tokio::time::timeout(NON_STREAM_TEST_TIMEOUT, builder.json(&preq.body).send())
+                .await
+                .map_err(|_| {
+                    ProbeFailure::new(format!(
+                        "请求超时（{} 秒）",
+                        NON_STREAM_TEST_TIMEOUT.as_secs()
+                    ))
+                })?
+                .map_err(|e| ProbeFailure::new(format!("请求失败: {}", e)))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let error_text = response.text().await.unwrap_or_default();
+        Err(ProbeFailure {
+            http_status: Some(status.as_u16()),
+            message: format!("{} - {}", status, error_text),
+        })
+    }
+
+    /// 发送流式请求并统计内容数据块；失败时同时返回已收到的数据块数
+    async fn probe_stream(
+        &self,
+        config: &ApiConfig,
+    ) -> std::result::Result<usize, (usize, ProbeFailure)> {
+        let adapter = self.connection_test_adapter(config);
+        let (preq, builder) = self
+            .build_connection_test_request(
+                config,
+                adapter.as_ref(),
+                "Count from 1 to 5, separated by spaces.",
+                32,
+                true,
+            )
+            .map_err(|failure| (0, failure))?;
+
+        let mut chunks = 0;
+        let read = async {
+            let response = builder
+                .json(&preq.body)
+                .send()
+                .await
+                .map_err(|e| ProbeFailure::new(format!("流式请求失败: {}", e)))?;
+            let status = response.status();
+            if !status.is_success() {
+                let error_text = response.text().await.unwrap_or_default();
+                return Err(ProbeFailure {
+                    http_status: Some(status.as_u16()),
+                    message: format!("流式请求失败: {} - {}", status, error_text),
+                });
+            }
+            let mut stream = response.bytes_stream();
+            let mut sse_buffer = SseLineBuffer::new();
+            while let Some(bytes) = stream.next().await {
+                let bytes =
+                    bytes.map_err(|e| ProbeFailure::new(format!("读取流式响应失败: {}", e)))?;
+                let done =
+                    count_stream_chunks(adapter.as_ref(), &mut sse_buffer, &bytes, &mut chunks);
+                if done || chunks >= MIN_STREAM_CHUNKS {
+                    break;
+                }
+            }
+            Ok(())
+        };
+        let outcome = tokio::time::timeout(STREAM_TEST_TIMEOUT, read).await;
+
+        match outcome {
+            Ok(Ok(())) if chunks >= MIN_STREAM_CHUNKS => Ok(chunks),
+            Ok(Ok(())) => Err((
+                chunks,
+                ProbeFailure::new(format!(
+                    "流式响应只收到 {} 个数据块，可能被代理缓冲或改写为非 SSE 响应",
+                    chunks
+                )),
+            )),
+            Ok(Err(failure)) => Err((chunks, failure)),
+            Err(_) => Err((
+                chunks,
+                ProbeFailure::new(format!(
+                    "{} 秒内只收到 {} 个流式数据块",
+                    STREAM_TEST_TIMEOUT.as_secs(),
+                    chunks
+                )),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::OpenAIAdapter;
+
+    fn chunks_in(body: &str) -> (usize, bool) {
+        let mut sse_buffer = SseLineBuffer::new();
+        let mut chunks = 0;
+        let done = count_stream_chunks(
+            &OpenAIAdapter,
+            &mut sse_buffer,
+            body.as_bytes(),
+            &mut chunks,
+        );
+        (chunks, done)
+    }
+
+    #[test]
+    fn test_count_stream_chunks_in_sse_response() {
+        let body = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"1\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\" 2\"}}]}\n\n",
+            "data: [DONE]\n\n"
+        );
+        assert_eq!(chunks_in(body), (2, true));
+    }
+
+    #[test]
+    fn test_plain_json_response_has_no_stream_chunks() {
+        let body = "{\"choices\":[{\"message\":{\"content\":\"1 2 3 4 5\"}}]}\n";
+        assert_eq!(chunks_in(body), (0, false));
+    }
+
+    #[test]
+    fn test_auth_failures_are_distinguished() {
+        let failure = |status| ProbeFailure {
+            http_status: Some(status),
+            message: String::new(),
+        };
+        assert!(failure(401).is_auth_error());
+        assert!(failure(403).is_auth_error());
+        assert!(!failure(404).is_auth_error());
+        assert!(!ProbeFailure::new("timeout").is_auth_error());
+    }
+}
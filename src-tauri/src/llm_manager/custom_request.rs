@@ -0,0 +1,302 @@
+//! 自定义请求头与查询参数
+//!
+//! 供应商（及其派生的 `ApiConfig`）可配置额外的请求头和查询参数，用于经由内部网关
+//! 转发的场景（如组织 ID、成本中心）。聊天、嵌入、重排序请求发出前统一合并。
+//!
+//! - 值以 `secure:<名称>` 开头时，从安全存储键 `secret.llm_request.<名称>` 读取，
+//!   避免敏感值明文保存在供应商配置中；
+//! - `Authorization` 等鉴权/协议相关请求头默认不可覆盖，需显式开启
+//!   `allow_reserved_header_override`。
+
+use super::{ApiConfig, LLMManager, Result};
+use crate::database::Database;
+use crate::models::AppError;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::RequestBuilder;
+use std::collections::HashMap;
+
+/// 引用安全存储中敏感值的前缀
+pub const SECRET_VALUE_PREFIX: &str = "secure:";
+/// 安全存储键前缀（以 `secret` 开头，会被识别为敏感键）
+const SECRET_STORE_KEY_PREFIX: &str = "secret.llm_request.";
+
+/// 默认不允许覆盖的请求头（小写）
+const RESERVED_HEADERS: &[&str] = &[
+    "authorization",
+    "x-api-key",
+    "api-key",
+    "x-goog-api-key",
+    "anthropic-version",
+    "content-type",
+    "content-length",
+    "host",
+];
+
+/// 解析后的自定义请求选项
+#[derive(Debug, Default, Clone)]
+pub struct CustomRequestOptions {
+    pub headers: HeaderMap,
+    pub query: Vec<(String, String)>,
+}
+
+impl CustomRequestOptions {
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.query.is_empty()
+    }
+
+    /// 合并到请求构建器；同名请求头会替换适配器设置的默认值
+    pub fn apply(&self, builder: RequestBuilder) -> RequestBuilder {
+        if self.is_empty() {
+            return builder;
+        }
+        builder.headers(self.headers.clone()).query(&self.query)
+    }
+
+    /// 合并到已构建的请求头表（用于先收集请求、后并发发送的场景）
+    pub fn merge_headers(&self, header_map: &mut HeaderMap) {
+        for (name, value) in self.headers.iter() {
+            header_map.insert(name.clone(), value.clone());
+        }
+    }
+
+    /// 把查询参数追加到 URL
+    pub fn apply_to_url(&self, url: &str) -> String {
+        if self.query.is_empty() {
+            return url.to_string();
+        }
+        match url::Url::parse(url) {
+            Ok(mut parsed) => {
+                parsed.query_pairs_mut().extend_pairs(self.query.iter());
+                parsed.to_string()
+            }
+            Err(_) => url.to_string(),
+        }
+    }
+}
+
+/// 安全存储中的实际键名
+pub fn secret_store_key(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(AppError::validation(
+            "敏感值名称只能包含字母、数字、下划线、连字符和点",
+        ));
+    }
+    Ok(format!("{}{}", SECRET_STORE_KEY_PREFIX, name))
+}
+
+fn is_reserved_header(name: &str) -> bool {
+    RESERVED_HEADERS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+/// 保存配置时校验：请求头名合法、保留请求头需显式允许、查询参数名非空
+pub fn validate_custom_request_options(
+    headers: &HashMap<String, String>,
+    query_params: &HashMap<String, String>,
+    allow_reserved_header_override: bool,
+) -> Result<()> {
+    for (name, value) in headers {
+        HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| AppError::validation(format!("请求头名称不合法: {}", name)))?;
+        if is_reserved_header(name.trim()) && !allow_reserved_header_override {
+            return Err(AppError::validation(format!(
+                "请求头 {} 由系统设置，如确需覆盖请开启「允许覆盖保留请求头」",
+                name.trim()
+            )));
+        }
+        match value.strip_prefix(SECRET_VALUE_PREFIX) {
+            Some(secret_name) => {
+                secret_store_key(secret_name)?;
+            }
+            None => {
+                HeaderValue::from_str(value).map_err(|_| {
+                    AppError::validation(format!("请求头 {} 的值包含非法字符", name))
+                })?;
+            }
+        }
+    }
+    for (name, value) in query_params {
+        if name.trim().is_empty() {
+            return Err(AppError::validation("查询参数名称不能为空"));
+        }
+        if let Some(secret_name) = value.strip_prefix(SECRET_VALUE_PREFIX) {
+            secret_store_key(secret_name)?;
+        }
+    }
+    Ok(())
+}
+
+/// 解析 `secure:` 引用；普通值原样返回
+fn resolve_value(value: &str, lookup_secret: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    let Some(secret_name) = value.strip_prefix(SECRET_VALUE_PREFIX) else {
+        return Ok(value.to_string());
+    };
+    let key = secret_store_key(secret_name)?;
+    lookup_secret(&key).ok_or_else(|| {
+        AppError::configuration(format!(
+            "安全存储中未找到自定义请求值: {}",
+            secret_name.trim()
+        ))
+    })
+}
+
+fn build_options(
+    headers: Option<&HashMap<String, String>>,
+    query_params: Option<&HashMap<String, String>>,
+    allow_reserved_header_override: bool,
+    lookup_secret: &dyn Fn(&str) -> Option<String>,
+) -> Result<CustomRequestOptions> {
+    let mut options = CustomRequestOptions::default();
+    for (name, value) in headers.into_iter().flatten() {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        if is_reserved_header(name) && !allow_reserved_header_override {
+            log::warn!("[CustomRequest] 忽略保留请求头 {}（未允许覆盖）", name);
+            continue;
+        }
+        let header_name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| AppError::configuration(format!("请求头名称不合法: {}", name)))?;
+        let resolved = resolve_value(value, lookup_secret)?;
+        let mut header_value = HeaderValue::from_str(&resolved)
+            .map_err(|_| AppError::configuration(format!("请求头 {} 的值包含非法字符", name)))?;
+        if value.starts_with(SECRET_VALUE_PREFIX) {
+            header_value.set_sensitive(true);
+        }
+        options.headers.insert(header_name, header_value);
+    }
+
+    let mut query: Vec<(String, String)> = Vec::new();
+    for (name, value) in query_params.into_iter().flatten() {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        query.push((name.to_string(), resolve_value(value, lookup_secret)?));
+    }
+    query.sort();
+    options.query = query;
+    Ok(options)
+}
+
+/// 解析配置上的自定义请求头与查询参数（供 LLMManager 之外自行发请求的服务使用）
+pub fn resolve_custom_request_options(
+    db: &Database,
+    config: &ApiConfig,
+) -> Result<CustomRequestOptions> {
+    build_options(
+        config.headers.as_ref(),
+        config.query_params.as_ref(),
+        config.allow_reserved_header_override,
+        &|key| db.get_secret(key).ok().flatten(),
+    )
+}
+
+impl LLMManager {
+    pub(crate) fn custom_request_options(
+        &self,
+        config: &ApiConfig,
+    ) -> Result<CustomRequestOptions> {
+        resolve_custom_request_options(&self.db, config)
+    }
+
+    /// 将配置上的自定义请求头与查询参数合并到请求（须在其他请求头设置之后调用）
+    pub(crate) fn apply_custom_request_options(
+        &self,
+        builder: RequestBuilder,
+        config: &ApiConfig,
+    ) -> Result<RequestBuilder> {
+        Ok(self.custom_request_options(config)?.apply(builder))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_rejects_reserved_and_invalid_names() {
+        let query = HashMap::new();
+        assert!(
+            validate_custom_request_options(&map(&[("X-Org-Id", "42")]), &query, false).is_ok()
+        );
+        assert!(validate_custom_request_options(
+            &map(&[("Authorization", "Bearer x")]),
+            &query,
+            false
+        )
+        .is_err());
+        assert!(validate_custom_request_options(
+            &map(&[("Authorization", "Bearer x")]),
+            &query,
+            true
+        )
+        .is_ok());
+        assert!(
+            validate_custom_request_options(&map(&[("bad header", "1")]), &query, false).is_err()
+        );
+        assert!(validate_custom_request_options(
+            &map(&[("X-Token", "secure:bad name")]),
+            &query,
+            false
+        )
+        .is_err());
+        assert!(
+            validate_custom_request_options(&HashMap::new(), &map(&[(" ", "1")]), false).is_err()
+        );
+    }
+
+    #[test]
+    fn test_build_options_resolves_secrets_and_skips_reserved() {
+        let headers = map(&[
+            ("X-Org-Id", "42"),
+            ("X-Cost-Center", "secure:cost_center"),
+            ("Authorization", "Bearer override"),
+        ]);
+        let query = map(&[("tenant", "secure:tenant"), ("region", "cn")]);
+        let lookup = |key: &str| match key {
+            "secret.llm_request.cost_center" => Some("cc-7".to_string()),
+            "secret.llm_request.tenant" => Some("t-1".to_string()),
+            _ => None,
+        };
+
+        let options = build_options(Some(&headers), Some(&query), false, &lookup).unwrap();
+        assert_eq!(options.headers.get("x-org-id").unwrap(), "42");
+        let cost_center = options.headers.get("x-cost-center").unwrap();
+        assert_eq!(cost_center, "cc-7");
+        assert!(cost_center.is_sensitive());
+        assert!(options.headers.get("authorization").is_none());
+        assert_eq!(
+            options.query,
+            vec![
+                ("region".to_string(), "cn".to_string()),
+                ("tenant".to_string(), "t-1".to_string()),
+            ]
+        );
+        assert_eq!(
+            options.apply_to_url("https://gw.example.com/v1/chat/completions?a=1"),
+            "https://gw.example.com/v1/chat/completions?a=1&region=cn&tenant=t-1"
+        );
+
+        let options = build_options(Some(&headers), None, true, &lookup).unwrap();
+        assert_eq!(
+            options.headers.get("authorization").unwrap(),
+            "Bearer override"
+        );
+
+        let missing = map(&[("X-Token", "secure:missing")]);
+        assert!(build_options(Some(&missing), None, false, &lookup).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! 嵌入请求的重试与备用模型
+//!
+//! 嵌入服务限流（429）或暂时不可用（5xx / 网络错误）时按退避策略重试，429 优先遵循
+//! `Retry-After`；重试次数与首次等待时间可通过设置项调整。主模型重试耗尽后，若在
+//! 「模型分配」中指定了备用嵌入模型（`embedding_fallback_model_config_id`），调用方可改用
+//! 备用模型，但其向量维度必须与现有索引一致，否则拒绝写入并提示重新索引。
+//!
+//! 认证失败、请求格式错误等 4xx 错误重试无益，直接返回。
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use super::{ApiConfig, LLMManager, Result};
+use crate::database::Database;
+use crate::models::{AppError, AppErrorType};
+
+/// 嵌入请求失败后的最大重试次数（设置项，0 表示不重试）
+pub const EMBEDDING_RETRY_MAX_RETRIES_KEY: &str = "embedding.retry.max_retries";
+/// 首次重试前的等待毫秒数，之后每次翻倍
+pub const EMBEDDING_RETRY_BACKOFF_MS_KEY: &str = "embedding.retry.backoff_ms";
+const DEFAULT_EMBEDDING_RETRIES: u32 = 3;
+const DEFAULT_EMBEDDING_BACKOFF_MS: u64 = 400;
+const MAX_EMBEDDING_BACKOFF: Duration = Duration::from_secs(30);
+/// 服务端 `Retry-After` 的遵循上限，避免单个批次无限期挂起
+const MAX_EMBEDDING_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmbeddingRetryPolicy {
+    pub max_retries: u32,
+    pub base_backoff: Duration,
+}
+
+impl Default for EmbeddingRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_EMBEDDING_RETRIES,
+            base_backoff: Duration::from_millis(DEFAULT_EMBEDDING_BACKOFF_MS),
+        }
+    }
+}
+
+impl EmbeddingRetryPolicy {
+    pub fn load(db: &Database) -> Self {
+        let read = |key: &str| db.get_setting(key).ok().flatten();
+        let defaults = Self::default();
+        Self {
+            max_retries: read(EMBEDDING_RETRY_MAX_RETRIES_KEY)
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.max_retries),
+            base_backoff: read(EMBEDDING_RETRY_BACKOFF_MS_KEY)
+                .and_then(|v| v.trim().parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.base_backoff),
+        }
+    }
+
+    /// 第 `attempt` 次重试（从 1 开始）前的等待时间
+    ///
+    /// 服务端给出 `Retry-After` 时以其为准（封顶 60 秒），否则指数退避，封顶 30 秒。
+    pub fn backoff_for(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(wait) = retry_after {
+            return wait.min(MAX_EMBEDDING_RETRY_AFTER);
+        }
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        self.base_backoff
+            .saturating_mul(factor)
+            .min(MAX_EMBEDDING_BACKOFF)
+    }
+}
+
+/// 嵌入 API 的 HTTP 错误（细节中携带状态码与 `Retry-After`，供重试判断）
+pub(crate) fn embedding_http_error(
+    message: &str,
+    status: u16,
+    retry_after_secs: Option<u64>,
+) -> AppError {
+    AppError::with_details(
+        AppErrorType::LLM,
+        message,
+        json!({ "status": status, "retryAfterSecs": retry_after_secs }),
+    )
+}
+
+fn error_status(err: &AppError) -> Option<u64> {
+    err.details.as_ref()?.get("status")?.as_u64()
+}
+
+/// 是否值得重试：网络错误、429、5xx 以及响应解析失败；配置错误与其余 4xx 直接失败
+pub fn is_retryable_embedding_error(err: &AppError) -> bool {
+    match err.error_type {
+        AppErrorType::Network => true,
+        AppErrorType::Configuration | AppErrorType::Validation => false,
+        _ => match error_status(err) {
+            Some(status) => status == 429 || status >= 500,
+            None => true,
+        },
+    }
+}
+
+/// 服务端通过 `Retry-After` 要求的等待时间
+pub fn embedding_retry_after(err: &AppError) -> Option<Duration> {
+    err.details
+        .as_ref()?
+        .get("retryAfterSecs")?
+        .as_u64()
+        .map(Duration::from_secs)
+}
+
+/// 备用模型的向量维度必须与现有索引一致
+pub fn ensure_fallback_dimension(
+    embeddings: &[Vec<f32>],
+    expected_dim: usize,
+    fallback_name: &str,
+) -> Result<()> {
+    match embeddings.iter().find(|v| v.len() != expected_dim) {
+        Some(mismatched) => Err(AppError::with_details(
+            AppErrorType::Configuration,
+            format!(
+                "备用嵌入模型「{}」的向量维度为 {}，与现有索引维度 {} 不一致，已拒绝混用。\
+                 请为备用模型选择同维度的模型，或更换嵌入模型后重新索引知识库。",
+                fallback_name,
+                mismatched.len(),
+                expected_dim
+            ),
+            json!({
+                "code": "EMBEDDING_FALLBACK_DIMENSION_MISMATCH",
+                "expected": expected_dim,
+                "actual": mismatched.len(),
+            }),
+        )),
+        None => Ok(()),
+    }
+}
+
+impl LLMManager {
+    pub fn embedding_retry_policy(&self) -> EmbeddingRetryPolicy {
+        EmbeddingRetryPolicy::load(&self.db)
+    }
+
+    /// 备用嵌入模型配置；未分配、与主模型相同或已停用时返回 None
+    pub async fn get_embedding_fallback_model_config(
+        &self,
+        primary_config_id: &str,
+    ) -> Result<Option<ApiConfig>> {
+        let assignments = self.get_model_assignments().await?;
+        let Some(fallback_id) = assignments
+            .embedding_fallback_model_config_id
+            .filter(|id| !id.is_empty() && id != primary_config_id)
+        else {
+            return Ok(None);
+        };
+        let configs = self.get_api_configs().await?;
+        Ok(configs
+            .into_iter()
+            .find(|config| config.id == fallback_id && config.enabled))
+    }
+
+    /// 当前默认文本嵌入维度（「嵌入维度管理」中设置），用于校验备用模型
+    pub fn default_text_embedding_dimension(&self) -> Option<usize> {
+        self.db
+            .get_setting("embedding.default_text_dimension")
+            .ok()
+            .flatten()
+            .and_then(|v| v.trim().parse().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_honours_retry_after_and_caps() {
+        let policy = EmbeddingRetryPolicy::default();
+        assert_eq!(policy.backoff_for(1, None), Duration::from_millis(400));
+        assert_eq!(policy.backoff_for(3, None), Duration::from_millis(1600));
+        assert_eq!(policy.backoff_for(20, None), MAX_EMBEDDING_BACKOFF);
+        assert_eq!(
+            policy.backoff_for(1, Some(Duration::from_secs(7))),
+            Duration::from_secs(7)
+        );
+        assert_eq!(
+            policy.backoff_for(1, Some(Duration::from_secs(3600))),
+            MAX_EMBEDDING_RETRY_AFTER
+        );
+    }
+
+    #[test]
+    fn test_retryable_classification() {
+        let rate_limited = embedding_http_error("请求过于频繁", 429, Some(5));
+        assert!(is_retryable_embedding_error(&rate_limited));
+        assert_eq!(
+            embedding_retry_after(&rate_limited),
+            Some(Duration::from_secs(5))
+        );
+        let unavailable = embedding_http_error("服务不可用", 503, None);
+        assert!(is_retryable_embedding_error(&unavailable));
+        let unauthorized = embedding_http_error("密钥无效", 401, None);
+        assert!(!is_retryable_embedding_error(&unauthorized));
+        assert!(is_retryable_embedding_error(&AppError::network("timeout")));
+        let missing = AppError::configuration("missing");
+        assert!(!is_retryable_embedding_error(&missing));
+        assert!(is_retryable_embedding_error(&AppError::llm("解析失败")));
+    }
+
+    #[test]
+    fn test_fallback_dimension_guard() {
+        let same = vec![vec![0.0; 4], vec![0.0; 4]];
+        assert!(ensure_fallback_dimension(&same, 4, "backup").is_ok());
+        let err = ensure_fallback_dimension(&[vec![0.0; 8]], 4, "backup").unwrap_err();
+        assert!(err.message.contains("重新索引"));
+        assert!(matches!(err.error_type, AppErrorType::Configuration));
+    }
+}
@@ -49,6 +49,7 @@ async fn run_single_ocr_request(
     req: PreparedOcrRequest,
     timeout_secs: u64,
 ) -> std::result::Result<String, String> {
+    crate::offline_mode::ensure_endpoint_allowed("OCR 识别", &req.url).map_err(|e| e.message)?;
     let request_future = client
         .post(&req.url)
         .headers(req.headers)
@@ -379,11 +380,13 @@ impl LLMManager {
             body_size_estimate / 1024
         );
 
+        crate::offline_mode::ensure_endpoint_allowed("OCR 识别", &preq.url)?;
         let mut request_builder = self.client.post(&preq.url);
         for (k, v) in preq.headers.iter() {
             request_builder = request_builder.header(k.as_str(), v.as_str());
         }
 
+        let request_builder = self.apply_custom_request_options(request_builder, config)?;
         let response = request_builder
             .json(&preq.body)
             .send()
@@ -501,6 +504,7 @@ impl LLMManager {
             None,
             None,
             None,
+            Some(config.id.clone()),
             None,
             true,
             None,
@@ -698,13 +702,21 @@ impl LLMManager {
                     header_map.insert(name, val);
                 }
             }
+            let custom = match self.custom_request_options(config) {
+                Ok(custom) => custom,
+                Err(e) => {
+                    warn!("[OCR-Hedge] Engine #{} ({}) custom request options invalid: {}", idx, engine_type.as_str(), e);
+                    continue;
+                }
+            };
+            custom.merge_headers(&mut header_map);
 
             prepared.push(PreparedOcrRequest {
                 idx,
                 engine_name: engine_type.as_str().to_string(),
                 model_name: config.model.clone(),
                 model_adapter: config.model_adapter.clone(),
-                url: preq.url,
+                url: custom.apply_to_url(&preq.url),
                 headers: header_map,
                 body: preq.body,
             });
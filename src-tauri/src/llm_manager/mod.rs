@@ -1,9 +1,19 @@
 pub mod adapters;
 mod builtin_vendors;
+mod connection_test;
+mod custom_request;
+mod embedding_retry;
 mod exam_engine;
 mod model2_pipeline;
 pub(crate) mod parser;
 mod rag_extension;
+mod request_retry;
+mod stream_coalescer;
+mod stream_mode;
+mod stream_timeout;
+mod thinking_delivery;
+pub mod tokenizer;
+mod usage_ledger;
 
 use crate::crypto::{CryptoService, EncryptedData};
 use crate::database::Database;
@@ -13,6 +23,23 @@ use crate::models::{
 };
 use crate::providers::{ProviderAdapter, ProviderError};
 use crate::vendors::load_builtin_api_configs;
+pub use connection_test::{ApiConnectionTestResult, ConnectionTestStatus};
+pub use custom_request::{
+    resolve_custom_request_options, secret_store_key as custom_request_secret_key,
+    validate_custom_request_options, CustomRequestOptions,
+};
+pub use embedding_retry::{
+    embedding_retry_after, ensure_fallback_dimension, is_retryable_embedding_error,
+    EmbeddingRetryPolicy, EMBEDDING_RETRY_BACKOFF_MS_KEY, EMBEDDING_RETRY_MAX_RETRIES_KEY,
+};
+pub use rag_extension::RAG_NO_EMBEDDING_MODEL;
+pub use stream_coalescer::StreamCoalesceConfig;
+pub use stream_mode::StreamMode;
+pub(crate) use stream_timeout::StreamTimer;
+pub use thinking_delivery::{
+    load_thinking_delivery_setting, ThinkingDelivery, THINKING_DELIVERY_SETTING_KEY,
+};
+pub use usage_ledger::{load_model_pricing, ModelPricing, LLM_MODEL_PRICING_KEY};
 use base64::{engine::general_purpose, Engine as _};
 use futures_util::StreamExt;
 use log::{debug, error, info, warn};
@@ -504,6 +531,12 @@ pub struct ApiConfig {
     pub supports_reasoning: bool,
     #[serde(default)]
     pub headers: Option<HashMap<String, String>>,
+    /// 自定义查询参数（网关路由等），值可用 `secure:<名称>` 引用安全存储
+    #[serde(default)]
+    pub query_params: Option<HashMap<String, String>>,
+    /// 是否允许自定义请求头覆盖 Authorization 等保留请求头
+    #[serde(default)]
+    pub allow_reserved_header_override: bool,
     /// Top-P 核采样参数（运行时覆盖用）
     #[serde(default)]
     pub top_p_override: Option<f32>,
@@ -535,6 +568,27 @@ pub struct ApiConfig {
     /// 供应商级别的 max_tokens 限制（API 最大允许值）
     #[serde(default)]
     pub max_tokens_limit: Option<u32>,
+    /// 流式输出合并发送配置（None 表示逐 token 发送）
+    #[serde(default)]
+    pub stream_coalesce: Option<StreamCoalesceConfig>,
+    /// 思维链推送/保存策略（None 表示沿用全局设置）
+    #[serde(default)]
+    pub thinking_delivery: Option<ThinkingDelivery>,
+    /// 供应商接口是否支持流式输出；为 false 时流式调用自动改为非流式请求
+    #[serde(default = "default_true")]
+    pub supports_streaming: bool,
+    /// 即使支持流式也强制使用非流式请求（网关缓冲 SSE 等场景）
+    #[serde(default)]
+    pub force_non_streaming: bool,
+    /// 流式请求等待首个 token 的最长毫秒数（None 使用默认值）
+    #[serde(default)]
+    pub first_token_timeout_ms: Option<u64>,
+    /// 流式输出相邻两个数据块之间的最长间隔毫秒数（None 使用默认值）
+    #[serde(default)]
+    pub idle_timeout_ms: Option<u64>,
+    /// 请求遇到限流、服务端暂时不可用或连接中断时的最大重试次数（None 使用默认值，0 表示不重试）
+    #[serde(default)]
+    pub max_request_retries: Option<u32>,
 }
 
 impl Default for ApiConfig {
@@ -569,6 +623,8 @@ impl Default for ApiConfig {
             enable_thinking: None,
             supports_reasoning: false,
             headers: None,
+            query_params: None,
+            allow_reserved_header_override: false,
             top_p_override: None,
             frequency_penalty_override: None,
             presence_penalty_override: None,
@@ -578,6 +634,13 @@ impl Default for ApiConfig {
             verbosity: None,
             is_favorite: false,
             max_tokens_limit: None,
+            stream_coalesce: None,
+            thinking_delivery: None,
+            supports_streaming: true,
+            force_non_streaming: false,
+            first_token_timeout_ms: None,
+            idle_timeout_ms: None,
+            max_request_retries: None,
         }
     }
 }
@@ -590,8 +653,15 @@ pub struct VendorConfig {
     pub provider_type: String,
     pub base_url: String,
     pub api_key: String,
+    /// 自定义请求头，值可用 `secure:<名称>` 引用安全存储
     #[serde(default)]
     pub headers: HashMap<String, String>,
+    /// 自定义查询参数，合并到该供应商的每个请求
+    #[serde(default)]
+    pub query_params: HashMap<String, String>,
+    /// 是否允许自定义请求头覆盖 Authorization 等保留请求头
+    #[serde(default)]
+    pub allow_reserved_header_override: bool,
     #[serde(default)]
     pub rate_limit_per_minute: Option<u32>,
     #[serde(default)]
@@ -621,6 +691,8 @@ impl Default for VendorConfig {
             base_url: String::new(),
             api_key: String::new(),
             headers: HashMap::new(),
+            query_params: HashMap::new(),
+            allow_reserved_header_override: false,
             rate_limit_per_minute: None,
             default_timeout_ms: None,
             notes: None,
@@ -696,6 +768,27 @@ pub struct ModelProfile {
     /// 模型级别的 max_tokens 限制（优先于供应商级别）
     #[serde(default)]
     pub max_tokens_limit: Option<u32>,
+    /// 流式输出合并发送配置（None 表示逐 token 发送）
+    #[serde(default)]
+    pub stream_coalesce: Option<StreamCoalesceConfig>,
+    /// 思维链推送/保存策略（None 表示沿用全局设置）
+    #[serde(default)]
+    pub thinking_delivery: Option<ThinkingDelivery>,
+    /// 供应商接口是否支持流式输出；为 false 时流式调用自动改为非流式请求
+    #[serde(default = "default_true")]
+    pub supports_streaming: bool,
+    /// 即使支持流式也强制使用非流式请求（网关缓冲 SSE 等场景）
+    #[serde(default)]
+    pub force_non_streaming: bool,
+    /// 流式请求等待首个 token 的最长毫秒数（None 使用默认值）
+    #[serde(default)]
+    pub first_token_timeout_ms: Option<u64>,
+    /// 流式输出相邻两个数据块之间的最长间隔毫秒数（None 使用默认值）
+    #[serde(default)]
+    pub idle_timeout_ms: Option<u64>,
+    /// 请求遇到限流、服务端暂时不可用或连接中断时的最大重试次数（None 使用默认值，0 表示不重试）
+    #[serde(default)]
+    pub max_request_retries: Option<u32>,
 }
 
 impl Default for ModelProfile {
@@ -731,6 +824,13 @@ impl Default for ModelProfile {
             verbosity: None,
             is_favorite: false,
             max_tokens_limit: None,
+            stream_coalesce: None,
+            thinking_delivery: None,
+            supports_streaming: true,
+            force_non_streaming: false,
+            first_token_timeout_ms: None,
+            idle_timeout_ms: None,
+            max_request_retries: None,
         }
     }
 }
@@ -913,6 +1013,9 @@ impl LLMManager {
             update_if_untouched!(verbosity);
             update_if_untouched!(is_favorite);
             update_if_untouched!(max_tokens_limit);
+            update_if_untouched!(stream_coalesce);
+            update_if_untouched!(thinking_delivery);
+            update_if_untouched!(supports_streaming);
             return;
         }
         profiles.push(builtin_profile);
@@ -1848,6 +1951,17 @@ impl LLMManager {
 
         let mut sanitized = Vec::new();
         for cfg in configs {
+            validate_custom_request_options(
+                &cfg.headers,
+                &cfg.query_params,
+                cfg.allow_reserved_header_override,
+            )
+            .map_err(|e| {
+                AppError::validation(format!(
+                    "供应商 {} 的自定义请求配置无效: {}",
+                    cfg.name, e.message
+                ))
+            })?;
             let mut clone = cfg.clone();
 
             let trimmed = cfg.api_key.trim();
@@ -2070,6 +2184,8 @@ impl LLMManager {
             enable_thinking: profile.enable_thinking,
             supports_reasoning: profile.supports_reasoning || profile.is_reasoning,
             headers: Some(vendor.headers.clone()),
+            query_params: Some(vendor.query_params.clone()),
+            allow_reserved_header_override: vendor.allow_reserved_header_override,
             top_p_override: None,
             frequency_penalty_override: None,
             presence_penalty_override: None,
@@ -2080,6 +2196,13 @@ impl LLMManager {
             is_favorite: profile.is_favorite,
             // 模型粒度自管理 max_tokens_limit，不从供应商继承
             max_tokens_limit: profile.max_tokens_limit,
+            stream_coalesce: profile.stream_coalesce.clone(),
+            thinking_delivery: profile.thinking_delivery,
+            supports_streaming: profile.supports_streaming,
+            force_non_streaming: profile.force_non_streaming,
+            first_token_timeout_ms: profile.first_token_timeout_ms,
+            idle_timeout_ms: profile.idle_timeout_ms,
+            max_request_retries: profile.max_request_retries,
         };
 
         Ok(ResolvedModelConfig {
@@ -2125,6 +2248,8 @@ impl LLMManager {
                     base_url: cfg.base_url.clone(),
                     api_key: cfg.api_key.clone(),
                     headers: cfg.headers.clone().unwrap_or_default(),
+                    query_params: cfg.query_params.clone().unwrap_or_default(),
+                    allow_reserved_header_override: cfg.allow_reserved_header_override,
                     rate_limit_per_minute: None,
                     default_timeout_ms: None,
                     notes: None,
@@ -2170,6 +2295,13 @@ impl LLMManager {
                 reasoning_split: cfg.reasoning_split,
                 effort: cfg.effort.clone(),
                 verbosity: cfg.verbosity.clone(),
+                stream_coalesce: cfg.stream_coalesce.clone(),
+                thinking_delivery: cfg.thinking_delivery,
+                supports_streaming: cfg.supports_streaming,
+                force_non_streaming: cfg.force_non_streaming,
+                first_token_timeout_ms: cfg.first_token_timeout_ms,
+                idle_timeout_ms: cfg.idle_timeout_ms,
+                max_request_retries: cfg.max_request_retries,
             });
         }
 
@@ -2244,6 +2376,8 @@ impl LLMManager {
                     vendor_name: None,
                     provider_type: None,
                     headers: None,
+                    query_params: None,
+                    allow_reserved_header_override: false,
                     top_p_override: None,
                     frequency_penalty_override: None,
                     presence_penalty_override: None,
@@ -2253,6 +2387,13 @@ impl LLMManager {
                     verbosity: None,
                     is_favorite: false,
                     max_tokens_limit: None,
+                    stream_coalesce: None,
+                    thinking_delivery: None,
+                    supports_streaming: true,
+                    force_non_streaming: false,
+                    first_token_timeout_ms: None,
+                    idle_timeout_ms: None,
+                    max_request_retries: None,
                 })
                 .collect());
         }
@@ -2292,6 +2433,8 @@ impl LLMManager {
                 vendor_name: None,
                 provider_type: None,
                 headers: None,
+                query_params: None,
+                allow_reserved_header_override: false,
                 top_p_override: None,
                 frequency_penalty_override: None,
                 presence_penalty_override: None,
@@ -2301,6 +2444,13 @@ impl LLMManager {
                 reasoning_split: None,
                 effort: None,
                 verbosity: None,
+                stream_coalesce: None,
+                thinking_delivery: None,
+                supports_streaming: true,
+                force_non_streaming: false,
+                first_token_timeout_ms: None,
+                idle_timeout_ms: None,
+                max_request_retries: None,
             })
             .collect())
     }
@@ -2339,6 +2489,8 @@ impl LLMManager {
                     base_url: cfg.base_url.clone(),
                     api_key: cfg.api_key.clone(),
                     headers: cfg.headers.clone().unwrap_or_default(),
+                    query_params: cfg.query_params.clone().unwrap_or_default(),
+                    allow_reserved_header_override: cfg.allow_reserved_header_override,
                     rate_limit_per_minute: None,
                     default_timeout_ms: None,
                     notes: None,
@@ -2386,11 +2538,67 @@ impl LLMManager {
                 reasoning_split: cfg.reasoning_split,
                 effort: cfg.effort.clone(),
                 verbosity: cfg.verbosity.clone(),
+                stream_coalesce: cfg.stream_coalesce.clone(),
+                thinking_delivery: cfg.thinking_delivery,
+                supports_streaming: cfg.supports_streaming,
+                force_non_streaming: cfg.force_non_streaming,
+                first_token_timeout_ms: cfg.first_token_timeout_ms,
+                idle_timeout_ms: cfg.idle_timeout_ms,
+                max_request_retries: cfg.max_request_retries,
             });
         }
 
         Ok((vendors_map.into_values().collect(), profiles))
     }
+    /// 解析一次对话的思维链推送/保存策略：请求覆盖 > 模型配置 > 全局设置
+    ///
+    /// `model_config_id` 为空时按对话模型分配解析；配置读取失败时忽略模型级选项。
+    pub async fn resolve_thinking_delivery(
+        &self,
+        model_config_id: Option<&str>,
+        request_override: Option<ThinkingDelivery>,
+    ) -> ThinkingDelivery {
+        if let Some(delivery) = request_override {
+            return delivery;
+        }
+        let model_option = match model_config_id.filter(|id| !id.is_empty()) {
+            Some(id) => self.get_api_configs().await.ok().and_then(|configs| {
+                configs
+                    .into_iter()
+                    .find(|c| c.id == id || c.model == id)
+                    .and_then(|c| c.thinking_delivery)
+            }),
+            None => self
+                .get_model2_config()
+                .await
+                .ok()
+                .and_then(|c| c.thinking_delivery),
+        };
+        ThinkingDelivery::resolve(None, model_option, load_thinking_delivery_setting(&self.db))
+    }
+
+    /// 解析用于选择分词编码的模型名
+    ///
+    /// `model_id` 可以是 API 配置 ID 或模型名；配置 ID 解析为其模型名，
+    /// Gemini 适配器下模型名不含 gemini 时加 `gemini:` 前缀，找不到配置时原样返回。
+    pub async fn resolve_tokenizer_model(&self, model_id: Option<&str>) -> Option<String> {
+        let id = model_id.filter(|id| !id.trim().is_empty())?;
+        self.get_api_configs()
+            .await
+            .ok()
+            .and_then(|configs| configs.into_iter().find(|c| c.id == id))
+            .map(|c| {
+                if matches!(c.model_adapter.as_str(), "google" | "gemini")
+                    && !c.model.to_lowercase().contains("gemini")
+                {
+                    format!("gemini:{}", c.model)
+                } else {
+                    c.model
+                }
+            })
+            .or_else(|| Some(id.to_string()))
+    }
+
     // 获取对话模型配置（公开方法）
     pub async fn get_model2_config(&self) -> Result<ApiConfig> {
         let assignments = self.get_model_assignments().await?;
@@ -2528,6 +2736,44 @@ impl LLMManager {
         Ok(config)
     }
 
+    /// 按科目获取 OCR 模型配置
+    ///
+    /// 科目在模型分配中设置了 OCR 覆盖时使用覆盖模型（须支持多模态），否则回退到 [`Self::get_ocr_model_config`]。
+    pub async fn get_ocr_model_config_for_subject(
+        &self,
+        subject: Option<&str>,
+    ) -> Result<ApiConfig> {
+        let assignments = self.get_model_assignments().await?;
+        let Some(model_id) = assignments.ocr_model_for_subject(subject) else {
+            return self.get_ocr_model_config().await;
+        };
+
+        let config = self
+            .get_api_configs()
+            .await?
+            .into_iter()
+            .find(|c| c.id == model_id)
+            .ok_or_else(|| {
+                AppError::configuration(format!(
+                    "科目「{}」的 OCR 模型配置不存在: {}",
+                    subject.unwrap_or_default().trim(),
+                    model_id
+                ))
+            })?;
+        if !config.is_multimodal {
+            return Err(AppError::configuration(format!(
+                "科目「{}」的 OCR 模型未启用多模态能力，请选择支持图像输入的模型",
+                subject.unwrap_or_default().trim()
+            )));
+        }
+
+        debug!(
+            "[OCR] 使用科目覆盖模型: subject={:?}, id={}, model={}",
+            subject, config.id, config.model
+        );
+        Ok(config)
+    }
+
     /// 按优先级获取所有已启用的 OCR 引擎配置列表（用于熔断重试）
     ///
     /// 根据 `task_type` 对引擎列表进行分流排序：
@@ -2814,9 +3060,10 @@ impl LLMManager {
             }
         }
 
-        let response = client
-            .post(&preq.url)
-            .headers(header_map)
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &preq.url)?;
+        let request_builder = client.post(&preq.url).headers(header_map);
+        let response = self
+            .apply_custom_request_options(request_builder, &config)?
             .json(&preq.body)
             .send()
             .await
@@ -2993,9 +3240,43 @@ impl LLMManager {
     pub async fn get_ocr_config_with_effective_engine(
         &self,
     ) -> Result<(ApiConfig, crate::ocr_adapters::OcrEngineType)> {
-        use crate::ocr_adapters::{OcrAdapterFactory, OcrEngineType};
+        self.get_ocr_config_with_effective_engine_for_subject(None)
+            .await
+    }
+
+    /// 同 [`Self::get_ocr_config_with_effective_engine`]，但优先使用科目的 OCR 模型覆盖
+    pub async fn get_ocr_config_with_effective_engine_for_subject(
+        &self,
+        subject: Option<&str>,
+    ) -> Result<(ApiConfig, crate::ocr_adapters::OcrEngineType)> {
+        let config = self.get_ocr_model_config_for_subject(subject).await?;
+        let effective_engine = self.effective_ocr_engine(&config).await;
+        Ok((config, effective_engine))
+    }
+
+    /// 按配置 ID 获取 OCR 模型配置及其有效引擎（用于按语言路由），模型须支持多模态
+    pub async fn get_ocr_config_by_id_with_effective_engine(
+        &self,
+        config_id: &str,
+    ) -> Result<(ApiConfig, crate::ocr_adapters::OcrEngineType)> {
+        let config = self
+            .get_api_configs()
+            .await?
+            .into_iter()
+            .find(|c| c.id == config_id)
+            .ok_or_else(|| AppError::configuration(format!("OCR 模型配置不存在: {}", config_id)))?;
+        if !config.is_multimodal {
+            return Err(AppError::configuration(format!(
+                "OCR 模型 {} 未启用多模态能力，请选择支持图像输入的模型",
+                config.model
+            )));
+        }
+        let effective_engine = self.effective_ocr_engine(&config).await;
+        Ok((config, effective_engine))
+    }
 
-        let config = self.get_ocr_model_config().await?;
+    async fn effective_ocr_engine(&self, config: &ApiConfig) -> crate::ocr_adapters::OcrEngineType {
+        use crate::ocr_adapters::{OcrAdapterFactory, OcrEngineType};
 
         // 从 available_models 中查找该 config_id 对应的引擎类型
         let available = self.get_available_ocr_models().await;
@@ -3018,8 +3299,7 @@ impl LLMManager {
             effective_engine.as_str(),
             config.model
         );
-
-        Ok((config, effective_engine))
+        effective_engine
     }
 
     // 获取 Anki 制卡模型配置
@@ -3163,7 +3443,9 @@ impl LLMManager {
 
     // 保存模型分配配置
     pub async fn save_model_assignments(&self, assignments: &ModelAssignments) -> Result<()> {
-        let assignments_str = serde_json::to_string(assignments)
+        let mut assignments = assignments.clone();
+        assignments.normalize_subject_overrides();
+        let assignments_str = serde_json::to_string(&assignments)
             .map_err(|e| AppError::configuration(format!("序列化模型分配配置失败: {}", e)))?;
 
         self.db
@@ -3935,14 +4217,17 @@ impl LLMManager {
         });
 
         // 发送请求
-        let response = self
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &api_config.base_url)?;
+        let request_builder = self
             .client
             .post(format!(
                 "{}/chat/completions",
                 api_config.base_url.trim_end_matches('/')
             ))
             .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
+            .header("Content-Type", "application/json");
+        let response = self
+            .apply_custom_request_options(request_builder, &api_config)?
             .json(&request_body)
             .send()
             .await
@@ -4011,26 +4296,34 @@ impl LLMManager {
             }),
         ];
 
+        let stream_mode = StreamMode::for_config(&api_config);
         let request_body = json!({
             "model": model_id,
             "messages": messages,
             "temperature": 0.3,
             "max_tokens": 8192,
-            "stream": true
+            "stream": stream_mode.is_streaming()
         });
 
-        let response = self
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &api_config.base_url)?;
+        let request_builder = self
             .client
             .post(format!(
                 "{}/chat/completions",
                 api_config.base_url.trim_end_matches('/')
             ))
             .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
+            .header("Content-Type", "application/json");
+        let timer = StreamTimer::start(&api_config, stream_mode);
+        let response = timer
+            .send(
+                self.apply_custom_request_options(request_builder, &api_config)?
+                    .json(&request_body),
+            )
             .await
-            .map_err(|e| AppError::network(format!("LLM 流式请求失败: {}", e)))?;
+            .map_err(|e| {
+                e.into_app_error(|e| AppError::network(format!("LLM 流式请求失败: {}", e)))
+            })?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -4041,8 +4334,10 @@ impl LLMManager {
             )));
         }
 
-        // 流式解析
-        let mut stream = response.bytes_stream();
+        // 流式解析（非流式模式下为合成的单段 SSE）
+        let mut stream = stream_mode
+            .into_byte_stream(response, &api_config, false, timer)
+            .await?;
         let mut sse_buffer = crate::utils::sse_buffer::SseLineBuffer::new();
 
         // 根据 provider_type 选择适配器
@@ -4053,6 +4348,7 @@ impl LLMManager {
                 "anthropic" | "claude" => Box::new(crate::providers::AnthropicAdapter::new()),
                 _ => Box::new(crate::providers::OpenAIAdapter),
             };
+        let adapter = stream_mode.parse_adapter(adapter);
 
         let mut full_content = String::new();
         let mut all_questions: Vec<Value> = Vec::new();
@@ -4066,7 +4362,11 @@ impl LLMManager {
 
             let chunk = match next {
                 Ok(b) => b,
-                Err(e) => return Err(AppError::llm(format!("读取流式响应失败: {}", e))),
+                Err(e) => {
+                    return Err(
+                        e.into_app_error(|e| AppError::llm(format!("读取流式响应失败: {}", e)))
+                    )
+                }
             };
 
             let text = String::from_utf8_lossy(&chunk);
@@ -4161,14 +4461,17 @@ impl LLMManager {
             "max_tokens": 4096
         });
 
-        let response = self
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &api_config.base_url)?;
+        let request_builder = self
             .client
             .post(format!(
                 "{}/chat/completions",
                 api_config.base_url.trim_end_matches('/')
             ))
             .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
+            .header("Content-Type", "application/json");
+        let response = self
+            .apply_custom_request_options(request_builder, &api_config)?
             .json(&request_body)
             .send()
             .await
@@ -10,6 +10,9 @@ use crate::providers::ProviderAdapter;
 use crate::reasoning_policy::{
     get_passback_policy, requires_reasoning_passback, ReasoningPassbackPolicy,
 };
+use crate::stream_events::{
+    emit_delta, emit_stream_event, StreamEvent, StreamSequenceGuard, StreamSource,
+};
 use crate::utils::chat_timing;
 use futures_util::StreamExt;
 use log::{debug, error, info, warn};
@@ -19,6 +22,12 @@ use tauri::{Emitter, Window};
 use url::Url;
 use uuid::Uuid;
 
+use super::request_retry::{
+    is_retryable_status, send_once, send_with_retry, RequestRetry, RequestRetryPolicy,
+};
+use super::stream_coalescer::{CoalesceTicker, StreamCoalescer};
+use super::stream_mode::StreamMode;
+use super::stream_timeout::StreamTimer;
 use super::{
     adapters::get_adapter, parser, ApiConfig, ImagePayload, LLMManager, MergedChatMessage, Result,
 };
@@ -144,7 +153,186 @@ pub(crate) fn log_and_emit_llm_request(
     }
 }
 
+/// 在开始输出前重试请求时通知前端（`{stream_event}_retrying`）
+fn emit_retrying_event(
+    window: &Window,
+    stream_event: &str,
+    request_id: &str,
+    retry: &RequestRetry,
+) {
+    if let Err(e) = window.emit(
+        &format!("{}_retrying", stream_event),
+        &retry.event_payload(request_id),
+    ) {
+        warn!("发送重试事件失败: {}", e);
+    }
+}
+
 impl LLMManager {
+    /// 发送一段（可能已合并的）正文增量；`use_hook` 时优先交给已注册的 hook
+    async fn emit_content_delta(
+        &self,
+        window: &Window,
+        stream_event: &str,
+        request_id: &str,
+        chunk_counter: usize,
+        content: &str,
+        use_hook: bool,
+    ) {
+        if use_hook {
+            if let Some(h) = self.get_hook(stream_event).await {
+                h.on_content_chunk(content);
+                return;
+            }
+        }
+        emit_delta(
+            window,
+            StreamSource::Model,
+            stream_event,
+            StreamEvent::token(content),
+            || {
+                let stream_chunk = StreamChunk {
+                    content: content.to_string(),
+                    is_complete: false,
+                    chunk_id: format!("{}_chunk_{}", request_id, chunk_counter),
+                };
+                if let Err(e) = window.emit(stream_event, &stream_chunk) {
+                    warn!("发送内容块失败: {}", e);
+                }
+            },
+        );
+    }
+
+    /// 发送一段（可能已合并的）思维链增量
+    async fn emit_reasoning_delta(
+        &self,
+        window: &Window,
+        stream_event: &str,
+        request_id: &str,
+        chunk_counter: usize,
+        reasoning: &str,
+        use_hook: bool,
+    ) {
+        if use_hook {
+            if let Some(h) = self.get_hook(stream_event).await {
+                h.on_reasoning_chunk(reasoning);
+                return;
+            }
+        }
+        emit_delta(
+            window,
+            StreamSource::Model,
+            stream_event,
+            StreamEvent::thinking(reasoning),
+            || {
+                let reasoning_chunk = StreamChunk {
+                    content: reasoning.to_string(),
+                    is_complete: false,
+                    chunk_id: format!("{}_reasoning_chunk_{}", request_id, chunk_counter),
+                };
+                if let Err(e) =
+                    window.emit(&format!("{}_reasoning", stream_event), &reasoning_chunk)
+                {
+                    warn!("发送思维链块失败: {}", e);
+                }
+            },
+        );
+    }
+
+    /// 在统一通道上发送类型化事件；注册了 hook 的流（Chat V2）由 hook 负责事件，不重复发送
+    async fn emit_typed_event(&self, window: &Window, stream_event: &str, event: StreamEvent) {
+        if self.get_hook(stream_event).await.is_none() {
+            emit_stream_event(window, StreamSource::Model, stream_event, event);
+        }
+    }
+
+    /// 冲刷两路合并缓冲（思维链在前），用于工具调用开始和流结束
+    #[allow(clippy::too_many_arguments)]
+    async fn flush_stream_coalescers(
+        &self,
+        window: &Window,
+        stream_event: &str,
+        request_id: &str,
+        chunk_counter: usize,
+        content_coalescer: &mut StreamCoalescer,
+        reasoning_coalescer: &mut StreamCoalescer,
+        use_hook: bool,
+    ) {
+        let reasoning = reasoning_coalescer.flush();
+        let content = content_coalescer.flush();
+        self.emit_coalesced_deltas(
+            window,
+            stream_event,
+            request_id,
+            chunk_counter,
+            reasoning,
+            content,
+            use_hook,
+        )
+        .await;
+    }
+
+    /// 定时节拍到达：冲刷已超过合并周期仍未发送的尾部缓冲，避免慢速流看似卡住
+    #[allow(clippy::too_many_arguments)]
+    async fn flush_due_stream_coalescers(
+        &self,
+        window: &Window,
+        stream_event: &str,
+        request_id: &str,
+        chunk_counter: usize,
+        content_coalescer: &mut StreamCoalescer,
+        reasoning_coalescer: &mut StreamCoalescer,
+        use_hook: bool,
+    ) {
+        let reasoning = reasoning_coalescer.flush_if_due();
+        let content = content_coalescer.flush_if_due();
+        self.emit_coalesced_deltas(
+            window,
+            stream_event,
+            request_id,
+            chunk_counter,
+            reasoning,
+            content,
+            use_hook,
+        )
+        .await;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn emit_coalesced_deltas(
+        &self,
+        window: &Window,
+        stream_event: &str,
+        request_id: &str,
+        chunk_counter: usize,
+        reasoning: Option<String>,
+        content: Option<String>,
+        use_hook: bool,
+    ) {
+        if let Some(reasoning) = reasoning {
+            self.emit_reasoning_delta(
+                window,
+                stream_event,
+                request_id,
+                chunk_counter,
+                &reasoning,
+                use_hook,
+            )
+            .await;
+        }
+        if let Some(content) = content {
+            self.emit_content_delta(
+                window,
+                stream_event,
+                request_id,
+                chunk_counter,
+                &content,
+                use_hook,
+            )
+            .await;
+        }
+    }
+
     // 统一AI接口层 - 模型二（核心解析/对话）- 流式版本
     pub async fn call_unified_model_2_stream(
         &self,
@@ -167,6 +355,7 @@ impl LLMManager {
         presence_penalty_override: Option<f32>,
         max_output_tokens_override: Option<u32>,
     ) -> Result<StandardModel2Output> {
+        let _sequence_guard = StreamSequenceGuard::new(StreamSource::Model, stream_event);
         info!(
             "调用统一模型二接口(流式): 科目={}, 思维链={}, override_model={:?}",
             subject, enable_chain_of_thought, model_override_id
@@ -614,10 +803,11 @@ impl LLMManager {
             s
         };
 
+        let stream_mode = StreamMode::for_config(&config);
         let mut request_body = json!({
             "model": config.model,
             "messages": messages,
-            "stream": true
+            "stream": stream_mode.is_streaming()
         });
 
         // 🆕 应用推理配置，优先使用传入的enable_thinking参数
@@ -858,6 +1048,18 @@ impl LLMManager {
                             if let Some(ref err) = _err {
                                 warn!("[Fallback] web_search 返回错误: {}", err);
                             }
+                            if let Some(citations) = _citations.as_ref().filter(|c| !c.is_empty()) {
+                                self.emit_typed_event(
+                                    &window,
+                                    stream_event,
+                                    StreamEvent::Sources {
+                                        kind: "web_search".to_string(),
+                                        sources: serde_json::to_value(citations)
+                                            .unwrap_or_default(),
+                                    },
+                                )
+                                .await;
+                            }
                             if let Some(text) = web_inject_text {
                                 debug!(
                                     "[Fallback] 将 web_search 注入文本加入队列，长度: {} 字符",
@@ -986,6 +1188,11 @@ impl LLMManager {
         );
         debug!("[LLM_REVIEW_DEBUG] ==> 完整请求体结束 <==");
 
+        // 隐私脱敏：发往云端模型的正文替换为占位符，回答中的占位符在输出时还原
+        let redaction =
+            crate::pii_redaction::redact_request_body(&config.base_url, &mut request_body);
+        let mut pii_restorer = crate::pii_redaction::StreamRestorer::default();
+
         // 记录请求体大小与起始时间（简化）
         let request_json_str = serde_json::to_string(&request_body).unwrap_or_default();
         let request_bytes = request_json_str.len();
@@ -1026,140 +1233,107 @@ impl LLMManager {
             &json!({
                 "id": request_id,
                 "model": config.model,
-                "request_bytes": request_bytes
+                "request_bytes": request_bytes,
+                "stream_mode": stream_mode.as_str()
             }),
         ) {
             warn!("发送开始事件失败: {}", e);
         }
 
-        // ERR-01 修复：HTTP 错误码区分处理与指数退避重试
-        const MAX_RETRIES: u32 = 3;
-        const INITIAL_BACKOFF_MS: u64 = 1000;
-        let mut retry_count = 0u32;
-        let mut backoff_ms = INITIAL_BACKOFF_MS;
-
-        let response = loop {
-            // 每次重试都需要重新构建 request_builder（因为 send() 会消耗它）
-            let mut request_builder = self.client
-                .post(&preq.url)
-                .header("Accept", "text/event-stream, application/json, text/plain, */*")
-                .header("Accept-Encoding", "identity")  // 禁用压缩，避免二进制响应
-                .header("Accept-Language", "zh-CN,zh;q=0.9,en;q=0.8")
-                // Connection 头由 reqwest 自动管理：HTTP/1.1 使用 keep-alive，HTTP/2 使用多路复用
-                .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36");
-            for (k, v) in &preq.headers {
-                request_builder = request_builder.header(k.clone(), v.clone());
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &preq.url)?;
+        let mut request_builder = self.client
+            .post(&preq.url)
+            .header("Accept", "text/event-stream, application/json, text/plain, */*")
+            .header("Accept-Encoding", "identity")  // 禁用压缩，避免二进制响应
+            .header("Accept-Language", "zh-CN,zh;q=0.9,en;q=0.8")
+            // Connection 头由 reqwest 自动管理：HTTP/1.1 使用 keep-alive，HTTP/2 使用多路复用
+            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36");
+        for (k, v) in &preq.headers {
+            request_builder = request_builder.header(k.clone(), v.clone());
+        }
+        if let Ok(parsed_url) = Url::parse(&config.base_url) {
+            if (parsed_url.scheme() == "http" || parsed_url.scheme() == "https")
+                && parsed_url.host_str().is_some()
+            {
+                let origin_val = format!(
+                    "{}://{}",
+                    parsed_url.scheme(),
+                    parsed_url.host_str().unwrap_or_default()
+                );
+                let referer_val = format!(
+                    "{}://{}/",
+                    parsed_url.scheme(),
+                    parsed_url.host_str().unwrap_or_default()
+                );
+                request_builder = request_builder
+                    .header("Origin", origin_val)
+                    .header("Referer", referer_val);
             }
-            if let Ok(parsed_url) = Url::parse(&config.base_url) {
-                if (parsed_url.scheme() == "http" || parsed_url.scheme() == "https")
-                    && parsed_url.host_str().is_some()
-                {
-                    let origin_val = format!(
-                        "{}://{}",
-                        parsed_url.scheme(),
-                        parsed_url.host_str().unwrap_or_default()
-                    );
-                    let referer_val = format!(
-                        "{}://{}/",
-                        parsed_url.scheme(),
-                        parsed_url.host_str().unwrap_or_default()
-                    );
-                    request_builder = request_builder
-                        .header("Origin", origin_val)
-                        .header("Referer", referer_val);
+        }
+        let request_builder = self.apply_custom_request_options(request_builder, &config)?;
+
+        // ERR-01 修复：429 / 5xx / 连接中断按模型配置退避重试，重试均发生在输出内容之前
+        let (response, stream_timer) = send_with_retry(
+            &config,
+            "模型二API",
+            request_builder.json(&preq.body),
+            |builder| {
+                let config = &config;
+                async move {
+                    // 每次尝试重新计时，退避等待不占用首 token 预算
+                    let timer = StreamTimer::start(config, stream_mode);
+                    let resp = timer.send(builder).await?;
+                    Ok((resp, timer))
                 }
-            }
-
-            let resp = request_builder
-                .json(&preq.body)
-                .send()
-                .await
-                .map_err(|e| AppError::network(format!("模型二API请求失败: {}", e)))?;
-
-            if resp.status().is_success() {
-                break resp;
-            }
+            },
+            |retry| emit_retrying_event(&window, stream_event, &request_id, retry),
+        )
+        .await
+        .map_err(|e| {
+            e.into_app_error(|e| AppError::network(format!("模型二API请求失败: {}", e)))
+        })?;
 
-            let status = resp.status();
+        if !response.status().is_success() {
+            let status = response.status();
             let status_code = status.as_u16();
-
-            match status_code {
-                // 429 Rate Limit：使用指数退避重试
-                429 => {
-                    // 尝试解析 Retry-After 头
-                    let retry_after = resp
-                        .headers()
-                        .get("Retry-After")
-                        .and_then(|v| v.to_str().ok())
-                        .and_then(|s| s.parse::<u64>().ok());
-
-                    let wait_ms = retry_after.map(|s| s * 1000).unwrap_or(backoff_ms);
-
-                    if retry_count < MAX_RETRIES {
-                        retry_count += 1;
-                        warn!(
-                            "[模型二API] 遇到速率限制(429)，等待 {}ms 后重试 ({}/{})",
-                            wait_ms, retry_count, MAX_RETRIES
-                        );
-                        tokio::time::sleep(tokio::time::Duration::from_millis(wait_ms)).await;
-                        backoff_ms = (backoff_ms * 2).min(30000); // 指数退避，最大30秒
-                        continue;
-                    } else {
-                        let error_text = resp.text().await.unwrap_or_default();
-                        let error_msg = format!(
-                            "模型二API请求失败: 速率限制(429)，已重试{}次仍失败 - {}",
-                            MAX_RETRIES, error_text
-                        );
-                        error!("{}", error_msg);
-                        return Err(AppError::llm(error_msg));
-                    }
-                }
+            let error_text = response.text().await.unwrap_or_default();
+            let error = match status_code {
                 // 401/403 认证错误：直接返回明确错误
-                401 | 403 => {
-                    let error_text = resp.text().await.unwrap_or_default();
-                    let error_msg = format!(
-                        "模型二API认证失败: API Key 无效或已过期 (HTTP {}) - {}",
-                        status_code, error_text
-                    );
-                    error!("{}", error_msg);
-                    return Err(AppError::configuration(error_msg));
-                }
-                // 5xx 服务端错误：可重试
-                500..=599 => {
-                    if retry_count < MAX_RETRIES {
-                        retry_count += 1;
-                        warn!(
-                            "[模型二API] 服务端错误({})，等待 {}ms 后重试 ({}/{})",
-                            status_code, backoff_ms, retry_count, MAX_RETRIES
-                        );
-                        tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
-                        backoff_ms = (backoff_ms * 2).min(30000);
-                        continue;
-                    } else {
-                        let error_text = resp.text().await.unwrap_or_default();
-                        let error_msg = format!(
-                            "模型二API服务端错误: HTTP {} - 已重试{}次仍失败 - {}",
-                            status_code, MAX_RETRIES, error_text
-                        );
-                        error!("{}", error_msg);
-                        return Err(AppError::llm(error_msg));
-                    }
-                }
-                // 其他错误：直接返回
-                _ => {
-                    let error_text = resp.text().await.unwrap_or_default();
-                    let error_msg =
-                        format!("模型二API请求失败: HTTP {} - {}", status_code, error_text);
-                    error!("模型二API请求失败: {}", error_msg);
-                    return Err(AppError::llm(error_msg));
-                }
-            }
-        };
+                401 | 403 => AppError::configuration(format!(
+                    "模型二API认证失败: API Key 无效或已过期 (HTTP {}) - {}",
+                    status_code, error_text
+                )),
+                429 => AppError::llm(format!(
+                    "模型二API请求失败: 速率限制(429)，已重试{}次仍失败 - {}",
+                    RequestRetryPolicy::for_config(&config).max_retries,
+                    error_text
+                )),
+                code if is_retryable_status(code) => AppError::llm(format!(
+                    "模型二API服务端错误: HTTP {} - 已重试{}次仍失败 - {}",
+                    status_code,
+                    RequestRetryPolicy::for_config(&config).max_retries,
+                    error_text
+                )),
+                _ => AppError::llm(format!(
+                    "模型二API请求失败: HTTP {} - {}",
+                    status_code, error_text
+                )),
+            };
+            error!("{}", error.message);
+            return Err(error);
+        }
 
-        let mut stream = response.bytes_stream();
+        let responses_api = self.should_use_openai_responses(&config);
+        let mut stream = stream_mode
+            .into_byte_stream(response, &config, responses_api, stream_timer)
+            .await?;
+        let adapter = stream_mode.parse_adapter(adapter);
         let mut full_content = String::new();
         let mut reasoning_content = String::new(); // 收集思维链内容
         let mut chunk_counter = 0;
+        // 正文与思维链分别合并发送（未配置时逐 token 透传）
+        let mut content_coalescer = StreamCoalescer::new(config.stream_coalesce.as_ref());
+        let mut reasoning_coalescer = StreamCoalescer::new(config.stream_coalesce.as_ref());
         // 已有 request_id
         let mut response_bytes: usize = 0;
         // 捕获工具调用集合
@@ -1201,7 +1375,8 @@ impl LLMManager {
             &json!({
                 "id": stream_event,
                 "model": config.model,
-                "request_bytes": request_bytes
+                "request_bytes": request_bytes,
+                "stream_mode": stream_mode.as_str()
             }),
         ) {
             warn!("发送开始事件失败: {}", e);
@@ -1237,7 +1412,27 @@ impl LLMManager {
                 .await;
         }
         let mut was_cancelled = false;
-        while let Some(chunk_result) = stream.next().await {
+        let mut coalesce_ticker = CoalesceTicker::new(&content_coalescer);
+        loop {
+            let chunk_result = tokio::select! {
+                next = stream.next() => match next {
+                    Some(chunk_result) => chunk_result,
+                    None => break,
+                },
+                _ = coalesce_ticker.tick() => {
+                    self.flush_due_stream_coalescers(
+                        &window,
+                        stream_event,
+                        &request_id,
+                        chunk_counter,
+                        &mut content_coalescer,
+                        &mut reasoning_coalescer,
+                        true,
+                    )
+                    .await;
+                    continue;
+                }
+            };
             // Hard cancel check (best-effort): proactively drain registry then check channel
             let registry_cancelled = self.take_cancellation_if_any(stream_event).await;
             let cancel_flag = *cancel_rx.borrow();
@@ -1270,6 +1465,8 @@ impl LLMManager {
                 } else {
                     debug!("[Cancel] 已发送 {}_cancelled 事件", stream_event);
                 }
+                self.emit_typed_event(&window, stream_event, StreamEvent::cancelled())
+                    .await;
                 was_cancelled = true;
                 debug!("[Cancel] 流循环已中断，退出 while 循环");
                 break;
@@ -1304,45 +1501,67 @@ impl LLMManager {
                         for event in events {
                             match event {
                                 crate::providers::StreamEvent::ContentChunk(content) => {
+                                    let content = match &redaction {
+                                        Some(redaction) => pii_restorer.push(redaction, &content),
+                                        None => content,
+                                    };
+                                    if content.is_empty() {
+                                        continue;
+                                    }
                                     full_content.push_str(&content);
                                     chunk_counter += 1;
-
-                                    let stream_chunk = StreamChunk {
-                                        content: content.clone(),
-                                        is_complete: false,
-                                        chunk_id: format!("{}_chunk_{}", request_id, chunk_counter),
+                                    // 先冲刷思维链缓冲，保证两路事件的先后顺序
+                                    if let Some(pending) = reasoning_coalescer.flush() {
+                                        self.emit_reasoning_delta(
+                                            &window,
+                                            stream_event,
+                                            &request_id,
+                                            chunk_counter,
+                                            &pending,
+                                            true,
+                                        )
+                                        .await;
+                                    }
+                                    let Some(content) = content_coalescer.push(&content) else {
+                                        continue;
                                     };
 
-                                    // 🔧 修复：当 hook 存在时由 hook 负责发送事件（正确的 BackendEvent 格式）
-                                    // 否则直接 emit StreamChunk（兼容旧调用方）
-                                    if let Some(h) = self.get_hook(stream_event).await {
-                                        h.on_content_chunk(&content);
-                                    } else if let Err(e) = window.emit(stream_event, &stream_chunk)
-                                    {
-                                        warn!("发送内容块失败: {}", e);
-                                    }
+                                    self.emit_content_delta(
+                                        &window,
+                                        stream_event,
+                                        &request_id,
+                                        chunk_counter,
+                                        &content,
+                                        true,
+                                    )
+                                    .await;
                                 }
                                 crate::providers::StreamEvent::ReasoningChunk(reasoning) => {
                                     reasoning_content.push_str(&reasoning);
-
-                                    let reasoning_chunk = StreamChunk {
-                                        content: reasoning.clone(),
-                                        is_complete: false,
-                                        chunk_id: format!(
-                                            "{}_reasoning_chunk_{}",
-                                            request_id, chunk_counter
-                                        ),
+                                    if let Some(pending) = content_coalescer.flush() {
+                                        self.emit_content_delta(
+                                            &window,
+                                            stream_event,
+                                            &request_id,
+                                            chunk_counter,
+                                            &pending,
+                                            true,
+                                        )
+                                        .await;
+                                    }
+                                    let Some(reasoning) = reasoning_coalescer.push(&reasoning) else {
+                                        continue;
                                     };
 
-                                    // 🔧 修复：当 hook 存在时由 hook 负责发送事件
-                                    if let Some(h) = self.get_hook(stream_event).await {
-                                        h.on_reasoning_chunk(&reasoning);
-                                    } else if let Err(e) = window.emit(
-                                        &format!("{}_reasoning", stream_event),
-                                        &reasoning_chunk,
-                                    ) {
-                                        warn!("发送思维链块失败: {}", e);
-                                    }
+                                    self.emit_reasoning_delta(
+                                        &window,
+                                        stream_event,
+                                        &request_id,
+                                        chunk_counter,
+                                        &reasoning,
+                                        true,
+                                    )
+                                    .await;
                                 }
                                 crate::providers::StreamEvent::ThoughtSignature(signature) => {
                                     // Gemini 3 思维签名：工具调用场景下需要缓存并回传
@@ -1356,6 +1575,16 @@ impl LLMManager {
                                     }
                                 }
                                 crate::providers::StreamEvent::ToolCall(tool_call_value) => {
+                                    self.flush_stream_coalescers(
+                                        &window,
+                                        stream_event,
+                                        &request_id,
+                                        chunk_counter,
+                                        &mut content_coalescer,
+                                        &mut reasoning_coalescer,
+                                        true,
+                                    )
+                                    .await;
                                     // 聚合分块的工具调用（不再发送原始分块事件）
                                     if let Some(index) = tool_call_value
                                         .get("index")
@@ -1454,6 +1683,14 @@ impl LLMManager {
                                     {
                                         error!("发送用量事件失败: {}", e);
                                     }
+                                    self.emit_typed_event(
+                                        &window,
+                                        stream_event,
+                                        StreamEvent::Usage {
+                                            usage: usage_value.clone(),
+                                        },
+                                    )
+                                    .await;
                                     if let Some(h) = self.get_hook(stream_event).await {
                                         h.on_usage(&usage_value);
                                     }
@@ -1477,6 +1714,16 @@ impl LLMManager {
                                     {
                                         error!("发送安全错误事件失败: {}", e);
                                     }
+                                    self.emit_typed_event(
+                                        &window,
+                                        stream_event,
+                                        StreamEvent::Error {
+                                            message: "Request blocked due to safety policies"
+                                                .to_string(),
+                                            code: Some("safety_blocked".to_string()),
+                                        },
+                                    )
+                                    .await;
                                 }
                                 crate::providers::StreamEvent::Done => {
                                     stream_ended = true;
@@ -1569,6 +1816,8 @@ impl LLMManager {
                         let error_event = format!("{}_error", stream_event);
                         let error_payload = json!({
                             "error": format!("流式请求失败: {}", e),
+                            // 首 token / 空闲超时时标明超出的预算
+                            "code": e.timeout_code(),
                             "stream_event": stream_event,
                             "timestamp": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string()
                         });
@@ -1579,7 +1828,18 @@ impl LLMManager {
                         if let Err(emit_err) = window.emit("stream_error", &error_payload) {
                             error!("发送全局错误事件失败: {}", emit_err);
                         }
-                        return Err(AppError::network(format!("流式请求失败: {}", e)));
+                        self.emit_typed_event(
+                            &window,
+                            stream_event,
+                            StreamEvent::Error {
+                                message: format!("流式请求失败: {}", e),
+                                code: e.timeout_code().map(str::to_string),
+                            },
+                        )
+                        .await;
+                        return Err(e.into_app_error(|e| {
+                            AppError::network(format!("流式请求失败: {}", e))
+                        }));
                     }
                 }
             }
@@ -1603,40 +1863,67 @@ impl LLMManager {
                 for event in events {
                     match event {
                         crate::providers::StreamEvent::ContentChunk(content) => {
+                            let content = match &redaction {
+                                Some(redaction) => pii_restorer.push(redaction, &content),
+                                None => content,
+                            };
+                            if content.is_empty() {
+                                continue;
+                            }
                             full_content.push_str(&content);
                             chunk_counter += 1;
-
-                            let stream_chunk = StreamChunk {
-                                content: content.clone(),
-                                is_complete: false,
-                                chunk_id: format!("{}_chunk_{}", request_id, chunk_counter),
+                            // 先冲刷思维链缓冲，保证两路事件的先后顺序
+                            if let Some(pending) = reasoning_coalescer.flush() {
+                                self.emit_reasoning_delta(
+                                    &window,
+                                    stream_event,
+                                    &request_id,
+                                    chunk_counter,
+                                    &pending,
+                                    true,
+                                )
+                                .await;
+                            }
+                            let Some(content) = content_coalescer.push(&content) else {
+                                continue;
                             };
 
-                            if let Some(h) = self.get_hook(stream_event).await {
-                                h.on_content_chunk(&content);
-                            } else if let Err(e) = window.emit(stream_event, &stream_chunk) {
-                                warn!("发送剩余内容块失败: {}", e);
-                            }
+                            self.emit_content_delta(
+                                &window,
+                                stream_event,
+                                &request_id,
+                                chunk_counter,
+                                &content,
+                                true,
+                            )
+                            .await;
                         }
                         crate::providers::StreamEvent::ReasoningChunk(reasoning) => {
                             reasoning_content.push_str(&reasoning);
-
-                            let reasoning_chunk = StreamChunk {
-                                content: reasoning.clone(),
-                                is_complete: false,
-                                chunk_id: format!(
-                                    "{}_reasoning_chunk_{}",
-                                    request_id, chunk_counter
-                                ),
+                            if let Some(pending) = content_coalescer.flush() {
+                                self.emit_content_delta(
+                                    &window,
+                                    stream_event,
+                                    &request_id,
+                                    chunk_counter,
+                                    &pending,
+                                    true,
+                                )
+                                .await;
+                            }
+                            let Some(reasoning) = reasoning_coalescer.push(&reasoning) else {
+                                continue;
                             };
 
-                            if let Some(h) = self.get_hook(stream_event).await {
-                                h.on_reasoning_chunk(&reasoning);
-                            } else if let Err(e) = window
-                                .emit(&format!("{}_reasoning", stream_event), &reasoning_chunk)
-                            {
-                                warn!("发送剩余思维链块失败: {}", e);
-                            }
+                            self.emit_reasoning_delta(
+                                &window,
+                                stream_event,
+                                &request_id,
+                                chunk_counter,
+                                &reasoning,
+                                true,
+                            )
+                            .await;
                         }
                         _ => { /* 忽略其他事件类型（Done/ToolCall/Usage等已在主循环处理） */
                         }
@@ -1645,6 +1932,37 @@ impl LLMManager {
             }
         }
 
+        // 还原流末尾暂存的占位符片段
+        if let Some(redaction) = &redaction {
+            let tail = pii_restorer.finish(redaction);
+            if !tail.is_empty() {
+                full_content.push_str(&tail);
+                if let Some(content) = content_coalescer.push(&tail) {
+                    self.emit_content_delta(
+                        &window,
+                        stream_event,
+                        &request_id,
+                        chunk_counter,
+                        &content,
+                        true,
+                    )
+                    .await;
+                }
+            }
+        }
+
+        // 流结束：冲刷合并缓冲中的剩余内容
+        self.flush_stream_coalescers(
+            &window,
+            stream_event,
+            &request_id,
+            chunk_counter,
+            &mut content_coalescer,
+            &mut reasoning_coalescer,
+            true,
+        )
+        .await;
+
         // 🔧 P0修复：Gemini 原生 SSE 不发送 `data: [DONE]`，流直接结束。
         // 如果 pending_tool_calls 中仍有未处理的工具调用，在此执行与 Done 处理器相同的 finalize 逻辑。
         if !pending_tool_calls.is_empty() {
@@ -1753,6 +2071,17 @@ impl LLMManager {
                         metadata: None,
                     };
                     h.on_tool_call(&chat_msg);
+                } else {
+                    emit_stream_event(
+                        &window,
+                        StreamSource::Model,
+                        stream_event,
+                        StreamEvent::ToolCall {
+                            id: tc.id.clone(),
+                            name: tc.tool_name.clone(),
+                            arguments: tc.args_json.clone(),
+                        },
+                    );
                 }
             }
         }
@@ -1797,6 +2126,12 @@ impl LLMManager {
             } else {
                 debug!("发送主内容完成信号成功，内容长度: {}", full_content.len());
             }
+            self.emit_typed_event(
+                &window,
+                stream_event,
+                StreamEvent::done(Some(json!({ "content": full_content }))),
+            )
+            .await;
         }
         // 如果有思维链内容，也发送思维链完成信号
         if !was_cancelled && enable_chain_of_thought && !reasoning_content.is_empty() {
@@ -1902,10 +2237,11 @@ impl LLMManager {
                 reasoning_tokens,
                 None,
                 Some(stream_event.to_string()),
+                Some(config.id.clone()),
                 Some(dur as u64),
                 !was_cancelled,
                 if was_cancelled {
-                    Some("cancelled".to_string())
+                    Some(crate::llm_usage::USAGE_CANCELLED_MESSAGE.to_string())
                 } else {
                     None
                 },
@@ -1918,7 +2254,7 @@ impl LLMManager {
             } else {
                 full_content
             },
-            raw_response: Some("stream_response".to_string()),
+            raw_response: Some(stream_mode.raw_response_marker().to_string()),
             chain_of_thought_details,
             cancelled: was_cancelled,
         })
@@ -1937,6 +2273,7 @@ impl LLMManager {
         stream_event: &str,
         _max_input_tokens_override: Option<usize>,
     ) -> Result<StandardModel2Output> {
+        let _sequence_guard = StreamSequenceGuard::new(StreamSource::Model, stream_event);
         info!(
             "调用通用流式接口: 模型={}, 科目={}, 思维链={}, 图片数量={}",
             config.model,
@@ -2143,10 +2480,11 @@ impl LLMManager {
         // 🔧 防御性合并：连续 user 消息合并
         Self::merge_consecutive_user_messages(&mut messages);
 
+        let stream_mode = StreamMode::for_config(config);
         let mut request_body = json!({
             "model": config.model,
             "messages": messages,
-            "stream": true
+            "stream": stream_mode.is_streaming()
         });
 
         Self::apply_reasoning_config(&mut request_body, &config, None);
@@ -2288,6 +2626,11 @@ impl LLMManager {
         request_body["max_tokens"] = json!(max_tokens);
         request_body["temperature"] = json!(config.temperature);
 
+        // 隐私脱敏：发往云端模型的正文替换为占位符，回答中的占位符在输出时还原
+        let redaction =
+            crate::pii_redaction::redact_request_body(&config.base_url, &mut request_body);
+        let mut pii_restorer = crate::pii_redaction::StreamRestorer::default();
+
         // 记录请求体大小与起始时间
         let request_json_str = serde_json::to_string(&request_body).unwrap_or_default();
         let request_bytes = request_json_str.len();
@@ -2326,6 +2669,7 @@ impl LLMManager {
             &request_body,
         );
 
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &preq.url)?;
         let mut request_builder = self.client
             .post(&preq.url)
             .header("Accept", "text/event-stream, application/json, text/plain, */*")
@@ -2370,23 +2714,40 @@ impl LLMManager {
             &json!({
                 "id": request_id,
                 "model": config.model,
-                "request_bytes": request_bytes
+                "request_bytes": request_bytes,
+                "stream_mode": stream_mode.as_str()
             }),
         ) {
             warn!("发送开始事件失败: {}", e);
         }
 
-        let response = request_builder
-            .json(&preq.body)
-            .send()
-            .await
-            .map_err(|e| AppError::network(format!("请求失败: {}", e)))?;
+        let request_builder = self.apply_custom_request_options(request_builder, config)?;
+        // 限流 / 服务端暂时不可用时在输出内容前退避重试；重试耗尽的错误响应交由下方统一处理
+        let (response, timer) = send_with_retry(
+            config,
+            "流式对话",
+            request_builder.json(&preq.body),
+            |builder| async move {
+                let timer = StreamTimer::start(config, stream_mode);
+                let resp = timer.send(builder).await?;
+                Ok((resp, timer))
+            },
+            |retry| emit_retrying_event(&window, stream_event, &request_id, retry),
+        )
+        .await
+        .map_err(|e| e.into_app_error(|e| AppError::network(format!("请求失败: {}", e))))?;
 
         // 流式处理响应（使用与call_unified_model_2_stream相同的逻辑）
-        let mut stream = response.bytes_stream();
+        let mut stream = stream_mode
+            .into_byte_stream(response, config, false, timer)
+            .await?;
+        let adapter = stream_mode.parse_adapter(adapter);
         let mut full_content = String::new();
         let mut reasoning_content = String::new();
         let mut chunk_counter = 0;
+        // 正文与思维链分别合并发送（未配置时逐 token 透传）
+        let mut content_coalescer = StreamCoalescer::new(config.stream_coalesce.as_ref());
+        let mut reasoning_coalescer = StreamCoalescer::new(config.stream_coalesce.as_ref());
         let mut response_bytes: usize = 0;
         let mut was_cancelled = false;
         let mut captured_tool_calls: Vec<crate::models::ToolCall> = Vec::new();
@@ -2400,7 +2761,27 @@ impl LLMManager {
         // 初始化SSE行缓冲器
         let mut sse_buffer = crate::utils::sse_buffer::SseLineBuffer::new();
 
-        while let Some(chunk_result) = stream.next().await {
+        let mut coalesce_ticker = CoalesceTicker::new(&content_coalescer);
+        loop {
+            let chunk_result = tokio::select! {
+                next = stream.next() => match next {
+                    Some(chunk_result) => chunk_result,
+                    None => break,
+                },
+                _ = coalesce_ticker.tick() => {
+                    self.flush_due_stream_coalescers(
+                        &window,
+                        stream_event,
+                        &request_id,
+                        chunk_counter,
+                        &mut content_coalescer,
+                        &mut reasoning_coalescer,
+                        false,
+                    )
+                    .await;
+                    continue;
+                }
+            };
             // 先主动清理一次注册表中的取消标志，再检查通道中的通知
             let registry_cancelled = self.consume_pending_cancel(stream_event).await;
             if *cancel_rx.borrow() || registry_cancelled {
@@ -2446,43 +2827,83 @@ impl LLMManager {
                         for event in events {
                             match event {
                                 crate::providers::StreamEvent::ContentChunk(content) => {
+                                    let content = match &redaction {
+                                        Some(redaction) => pii_restorer.push(redaction, &content),
+                                        None => content,
+                                    };
+                                    if content.is_empty() {
+                                        continue;
+                                    }
                                     full_content.push_str(&content);
                                     chunk_counter += 1;
-
-                                    let stream_chunk = StreamChunk {
-                                        content: content.clone(),
-                                        is_complete: false,
-                                        chunk_id: format!("{}_chunk_{}", request_id, chunk_counter),
+                                    // 先冲刷思维链缓冲，保证两路事件的先后顺序
+                                    if let Some(pending) = reasoning_coalescer.flush() {
+                                        self.emit_reasoning_delta(
+                                            &window,
+                                            stream_event,
+                                            &request_id,
+                                            chunk_counter,
+                                            &pending,
+                                            false,
+                                        )
+                                        .await;
+                                    }
+                                    let Some(content) = content_coalescer.push(&content) else {
+                                        continue;
                                     };
 
-                                    if let Err(e) = window.emit(stream_event, &stream_chunk) {
-                                        error!("发送内容块失败: {}", e);
-                                    }
+                                    self.emit_content_delta(
+                                        &window,
+                                        stream_event,
+                                        &request_id,
+                                        chunk_counter,
+                                        &content,
+                                        false,
+                                    )
+                                    .await;
                                 }
                                 crate::providers::StreamEvent::ReasoningChunk(reasoning) => {
                                     reasoning_content.push_str(&reasoning);
-
-                                    let reasoning_chunk = StreamChunk {
-                                        content: reasoning.clone(),
-                                        is_complete: false,
-                                        chunk_id: format!(
-                                            "{}_reasoning_chunk_{}",
-                                            request_id, chunk_counter
-                                        ),
+                                    if let Some(pending) = content_coalescer.flush() {
+                                        self.emit_content_delta(
+                                            &window,
+                                            stream_event,
+                                            &request_id,
+                                            chunk_counter,
+                                            &pending,
+                                            false,
+                                        )
+                                        .await;
+                                    }
+                                    let Some(reasoning) = reasoning_coalescer.push(&reasoning) else {
+                                        continue;
                                     };
 
-                                    if let Err(e) = window.emit(
-                                        &format!("{}_reasoning", stream_event),
-                                        &reasoning_chunk,
-                                    ) {
-                                        warn!("发送思维链块失败: {}", e);
-                                    }
+                                    self.emit_reasoning_delta(
+                                        &window,
+                                        stream_event,
+                                        &request_id,
+                                        chunk_counter,
+                                        &reasoning,
+                                        false,
+                                    )
+                                    .await;
                                 }
                                 crate::providers::StreamEvent::ThoughtSignature(_signature) => {
                                     // Gemini 3 思维签名（此函数不使用 hook，直接忽略）
                                     // 签名在工具调用场景下需要缓存，但此函数用于 v2 pipeline
                                 }
                                 crate::providers::StreamEvent::ToolCall(tool_call_value) => {
+                                    self.flush_stream_coalescers(
+                                        &window,
+                                        stream_event,
+                                        &request_id,
+                                        chunk_counter,
+                                        &mut content_coalescer,
+                                        &mut reasoning_coalescer,
+                                        false,
+                                    )
+                                    .await;
                                     // 聚合分块的工具调用（不再发送原始分块事件）
                                     if let Some(index) = tool_call_value
                                         .get("index")
@@ -2569,6 +2990,12 @@ impl LLMManager {
                                     {
                                         error!("发送用量事件失败: {}", e);
                                     }
+                                    self.emit_typed_event(
+                                        &window,
+                                        stream_event,
+                                        StreamEvent::Usage { usage: usage_value },
+                                    )
+                                    .await;
                                 }
                                 crate::providers::StreamEvent::SafetyBlocked(safety_info) => {
                                     // emit safety_blocked 事件
@@ -2589,6 +3016,16 @@ impl LLMManager {
                                     {
                                         error!("发送安全错误事件失败: {}", e);
                                     }
+                                    self.emit_typed_event(
+                                        &window,
+                                        stream_event,
+                                        StreamEvent::Error {
+                                            message: "Request blocked due to safety policies"
+                                                .to_string(),
+                                            code: Some("safety_blocked".to_string()),
+                                        },
+                                    )
+                                    .await;
                                 }
                                 crate::providers::StreamEvent::Done => {
                                     stream_ended = true;
@@ -2667,36 +3104,67 @@ impl LLMManager {
                 for event in events {
                     match event {
                         crate::providers::StreamEvent::ContentChunk(content) => {
+                            let content = match &redaction {
+                                Some(redaction) => pii_restorer.push(redaction, &content),
+                                None => content,
+                            };
+                            if content.is_empty() {
+                                continue;
+                            }
                             full_content.push_str(&content);
                             chunk_counter += 1;
-
-                            let stream_chunk = StreamChunk {
-                                content: content.clone(),
-                                is_complete: false,
-                                chunk_id: format!("{}_chunk_{}", request_id, chunk_counter),
+                            // 先冲刷思维链缓冲，保证两路事件的先后顺序
+                            if let Some(pending) = reasoning_coalescer.flush() {
+                                self.emit_reasoning_delta(
+                                    &window,
+                                    stream_event,
+                                    &request_id,
+                                    chunk_counter,
+                                    &pending,
+                                    false,
+                                )
+                                .await;
+                            }
+                            let Some(content) = content_coalescer.push(&content) else {
+                                continue;
                             };
 
-                            if let Err(e) = window.emit(stream_event, &stream_chunk) {
-                                error!("发送剩余内容块失败: {}", e);
-                            }
+                            self.emit_content_delta(
+                                &window,
+                                stream_event,
+                                &request_id,
+                                chunk_counter,
+                                &content,
+                                false,
+                            )
+                            .await;
                         }
                         crate::providers::StreamEvent::ReasoningChunk(reasoning) => {
                             reasoning_content.push_str(&reasoning);
-
-                            let reasoning_chunk = StreamChunk {
-                                content: reasoning.clone(),
-                                is_complete: false,
-                                chunk_id: format!(
-                                    "{}_reasoning_chunk_{}",
-                                    request_id, chunk_counter
-                                ),
+                            if let Some(pending) = content_coalescer.flush() {
+                                self.emit_content_delta(
+                                    &window,
+                                    stream_event,
+                                    &request_id,
+                                    chunk_counter,
+                                    &pending,
+                                    false,
+                                )
+                                .await;
+                            }
+                            let Some(reasoning) = reasoning_coalescer.push(&reasoning) else {
+                                continue;
                             };
 
-                            if let Err(e) = window
-                                .emit(&format!("{}_reasoning", stream_event), &reasoning_chunk)
-                            {
-                                warn!("发送剩余思维链块失败: {}", e);
-                            }
+                            self.emit_reasoning_delta(
+                                &window,
+                                stream_event,
+                                &request_id,
+                                chunk_counter,
+                                &reasoning,
+                                false,
+                            )
+                            .await;
                         }
                         _ => { /* 忽略其他事件类型（Done/ToolCall/Usage等已在主循环处理） */
                         }
@@ -2705,6 +3173,37 @@ impl LLMManager {
             }
         }
 
+        // 还原流末尾暂存的占位符片段
+        if let Some(redaction) = &redaction {
+            let tail = pii_restorer.finish(redaction);
+            if !tail.is_empty() {
+                full_content.push_str(&tail);
+                if let Some(content) = content_coalescer.push(&tail) {
+                    self.emit_content_delta(
+                        &window,
+                        stream_event,
+                        &request_id,
+                        chunk_counter,
+                        &content,
+                        false,
+                    )
+                    .await;
+                }
+            }
+        }
+
+        // 流结束：冲刷合并缓冲中的剩余内容
+        self.flush_stream_coalescers(
+            &window,
+            stream_event,
+            &request_id,
+            chunk_counter,
+            &mut content_coalescer,
+            &mut reasoning_coalescer,
+            false,
+        )
+        .await;
+
         // 清理取消通道
         self.clear_cancel_channel(stream_event).await;
 
@@ -2725,6 +3224,9 @@ impl LLMManager {
                 warn!("发送取消事件失败: {}", e);
             }
 
+            self.emit_typed_event(&window, stream_event, StreamEvent::cancelled())
+                .await;
+
             // 取消：仍发送 end 事件用于兼容性
             let duration_ms = start_instant.elapsed().as_millis();
             if let Err(e) = window.emit(
@@ -2768,6 +3270,12 @@ impl LLMManager {
                     error!("发送思维链完成信号失败: {}", e);
                 }
             }
+            self.emit_typed_event(
+                &window,
+                stream_event,
+                StreamEvent::done(Some(json!({ "content": full_content }))),
+            )
+            .await;
             // end(success) 事件在统一统计段发送
         }
 
@@ -2830,10 +3338,11 @@ impl LLMManager {
                 reasoning_tokens,
                 None,
                 Some(stream_event.to_string()),
+                Some(config.id.clone()),
                 Some(dur as u64),
                 !was_cancelled,
                 if was_cancelled {
-                    Some("cancelled".to_string())
+                    Some(crate::llm_usage::USAGE_CANCELLED_MESSAGE.to_string())
                 } else {
                     None
                 },
@@ -2863,7 +3372,7 @@ impl LLMManager {
             } else {
                 full_content
             },
-            raw_response: Some("stream_response".to_string()),
+            raw_response: Some(stream_mode.raw_response_marker().to_string()),
             chain_of_thought_details,
             cancelled: was_cancelled,
         })
@@ -3037,6 +3546,10 @@ impl LLMManager {
             request_body["temperature"] = json!(config.temperature);
         }
 
+        // 隐私脱敏：发往云端模型的正文替换为占位符，回答中的占位符再还原
+        let redaction =
+            crate::pii_redaction::redact_request_body(&config.base_url, &mut request_body);
+
         // 使用 ProviderAdapter 构建请求，确保 Gemini 模型走转换后的URL/Headers/Body
         let adapter: Box<dyn ProviderAdapter> = if self.should_use_openai_responses(&config) {
             Box::new(crate::providers::OpenAIResponsesAdapter)
@@ -3058,6 +3571,7 @@ impl LLMManager {
 
         log_llm_request_audit("CHAT_V2_STREAM", &preq.url, &config.model, &request_body);
 
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &preq.url)?;
         let mut request_builder = self.client
             .post(&preq.url)
             .header("Accept", "text/event-stream, application/json, text/plain, */*")
@@ -3088,11 +3602,19 @@ impl LLMManager {
             }
         }
 
-        let response = request_builder
-            .json(&preq.body)
-            .send()
-            .await
-            .map_err(|e| AppError::network(format!("模型二API请求失败: {}", e)))?;
+        let request_builder = self.apply_custom_request_options(request_builder, &config)?;
+        let started = std::time::Instant::now();
+        let (response, ()) = send_with_retry(
+            &config,
+            "模型二API",
+            request_builder.json(&preq.body),
+            send_once,
+            |_| {},
+        )
+        .await
+        .map_err(|e| {
+            e.into_app_error(|e| AppError::network(format!("模型二API请求失败: {}", e)))
+        })?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -3166,6 +3688,10 @@ impl LLMManager {
         let content = openai_like_json["choices"][0]["message"]["content"]
             .as_str()
             .ok_or_else(|| AppError::llm("无法解析模型二API响应"))?;
+        let content = match &redaction {
+            Some(redaction) => redaction.restore(content),
+            None => content.to_string(),
+        };
 
         // 如果启用了思维链，尝试提取思维链详情
         let chain_of_thought_details = if enable_chain_of_thought {
@@ -3182,14 +3708,22 @@ impl LLMManager {
         };
 
         // 用量日志：结束（简化控制台输出）
-        let approx_tokens_out = crate::utils::token_budget::estimate_tokens(content);
+        let approx_tokens_out = crate::utils::token_budget::estimate_tokens(&content);
         debug!(
             "[model2_non_stream] bytes_out={}, approx_tokens_out={}",
             response_bytes, approx_tokens_out
         );
+        Self::record_completion_usage(
+            &config,
+            crate::llm_usage::CallerType::Analysis,
+            &openai_like_json,
+            &request_body,
+            &content,
+            started,
+        );
 
         Ok(StandardModel2Output {
-            assistant_message: content.to_string(),
+            assistant_message: content,
             raw_response: Some(openai_like_json.to_string()),
             chain_of_thought_details,
             cancelled: false,
@@ -3274,6 +3808,7 @@ impl LLMManager {
 
         log_llm_request_audit("METADATA", &preq.url, &config.model, &request_body);
 
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &preq.url)?;
         let mut request_builder = self.client.post(&preq.url);
         for (key, value) in preq.headers.iter() {
             request_builder = request_builder.header(key, value);
@@ -3299,6 +3834,7 @@ impl LLMManager {
             }
         }
 
+        let request_builder = self.apply_custom_request_options(request_builder, &config)?;
         let response = request_builder
             .json(&preq.body)
             .send()
@@ -3496,6 +4032,7 @@ impl LLMManager {
         });
 
         let timeout_duration = std::time::Duration::from_secs(15);
+        crate::offline_mode::ensure_endpoint_allowed("嵌入模型", base_url)?;
         let request_future = self
             .client
             .post(&format!("{}/embeddings", base_url))
@@ -3552,6 +4089,7 @@ impl LLMManager {
         });
 
         let timeout_duration = std::time::Duration::from_secs(15);
+        crate::offline_mode::ensure_endpoint_allowed("重排模型", base_url)?;
         let request_future = self
             .client
             .post(&format!("{}/rerank", base_url))
@@ -3643,6 +4181,7 @@ impl LLMManager {
 
             log_llm_request_audit("TEST_CHAT", &preq.url, &model, &request_body);
 
+            crate::offline_mode::ensure_endpoint_allowed("模型调用", &preq.url)?;
             let mut request_builder = self.client
                 .post(&preq.url)
                 .header("Accept", "text/event-stream, application/json, text/plain, */*")
@@ -3809,8 +4348,36 @@ impl LLMManager {
         self.call_raw_prompt_with_config(config, user_prompt, None).await
     }
 
-    /// 内部方法：使用显式传入的 ApiConfig 执行 raw prompt 调用
-    async fn call_raw_prompt_with_config(
+    /// 使用指定模型配置调用；未指定时回退到模型二
+    pub async fn call_raw_prompt_with_model(
+        &self,
+        model_config_id: Option<&str>,
+        user_prompt: &str,
+    ) -> Result<StandardModel2Output> {
+        let config = self.resolve_chat_model_config(model_config_id).await?;
+        self.call_raw_prompt_with_config(config, user_prompt, None).await
+    }
+
+    /// 解析指定的对话模型配置；未指定时回退到模型二
+    pub async fn resolve_chat_model_config(
+        &self,
+        model_config_id: Option<&str>,
+    ) -> Result<ApiConfig> {
+        match model_config_id {
+            Some(config_id) => self
+                .get_api_configs()
+                .await?
+                .into_iter()
+                .find(|c| c.id == config_id && !c.is_embedding && !c.is_reranker)
+                .ok_or_else(|| {
+                    AppError::configuration(format!("找不到指定的对话模型配置: {}", config_id))
+                }),
+            None => self.get_model2_config().await,
+        }
+    }
+
+    /// 使用显式传入的 ApiConfig 执行 raw prompt 调用
+    pub(crate) async fn call_raw_prompt_with_config(
         &self,
         config: ApiConfig,
         user_prompt: &str,
@@ -3895,6 +4462,10 @@ impl LLMManager {
             );
         }
 
+        // 隐私脱敏：发往云端模型的正文替换为占位符，回答中的占位符再还原
+        let redaction =
+            crate::pii_redaction::redact_request_body(&config.base_url, &mut request_body);
+
         // 4. 通过 ProviderAdapter 构造 HTTP 请求
         let adapter: Box<dyn ProviderAdapter> = match config.model_adapter.as_str() {
             "google" | "gemini" => Box::new(crate::providers::GeminiAdapter::new()),
@@ -3912,6 +4483,7 @@ impl LLMManager {
 
         log_llm_request_audit("RAW_PROMPT", &preq.url, &config.model, &request_body);
 
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &preq.url)?;
         let mut request_builder = self.client
             .post(&preq.url)
             .header("Accept", "text/event-stream, application/json, text/plain, */*")
@@ -3944,11 +4516,19 @@ impl LLMManager {
         }
 
         // 5. 发送请求
-        let response = request_builder
-            .json(&preq.body)
-            .send()
-            .await
-            .map_err(|e| AppError::network(format!("RAW_PROMPT API请求失败: {}", e)))?;
+        let request_builder = self.apply_custom_request_options(request_builder, &config)?;
+        let started = std::time::Instant::now();
+        let (response, ()) = send_with_retry(
+            &config,
+            "RAW_PROMPT",
+            request_builder.json(&preq.body),
+            send_once,
+            |_| {},
+        )
+        .await
+        .map_err(|e| {
+            e.into_app_error(|e| AppError::network(format!("RAW_PROMPT API请求失败: {}", e)))
+        })?;
 
         // 6. 检查响应状态
         if !response.status().is_success() {
@@ -3984,8 +4564,19 @@ impl LLMManager {
 
         let assistant_message = openai_like_json["choices"][0]["message"]["content"]
             .as_str()
-            .unwrap_or("")
-            .to_string();
+            .unwrap_or("");
+        let assistant_message = match &redaction {
+            Some(redaction) => redaction.restore(assistant_message),
+            None => assistant_message.to_string(),
+        };
+        Self::record_completion_usage(
+            &config,
+            crate::llm_usage::CallerType::Other("raw_prompt".to_string()),
+            &openai_like_json,
+            &request_body,
+            &assistant_message,
+            started,
+        );
 
         Ok(StandardModel2Output {
             assistant_message,
@@ -4000,9 +4591,39 @@ impl LLMManager {
         &self,
         user_prompt: &str,
         image_payloads: Option<Vec<ImagePayload>>,
+    ) -> Result<StandardModel2Output> {
+        self.call_ocr_model_raw_prompt_for_subject(None, user_prompt, image_payloads)
+            .await
+    }
+
+    /// 同 [`Self::call_ocr_model_raw_prompt`]，科目设置了 OCR 模型覆盖时使用覆盖模型
+    pub async fn call_ocr_model_raw_prompt_for_subject(
+        &self,
+        subject: Option<&str>,
+        user_prompt: &str,
+        image_payloads: Option<Vec<ImagePayload>>,
     ) -> Result<StandardModel2Output> {
         // 1. 获取 OCR 模型配置及其有效引擎，确保适配器与实际模型一致
-        let (config, effective_engine) = self.get_ocr_config_with_effective_engine().await?;
+        let (config, effective_engine) = self
+            .get_ocr_config_with_effective_engine_for_subject(subject)
+            .await?;
+        self.call_ocr_model_raw_prompt_with_config(
+            &config,
+            effective_engine,
+            user_prompt,
+            image_payloads,
+        )
+        .await
+    }
+
+    /// 同 [`Self::call_ocr_model_raw_prompt`]，使用指定的 OCR 模型配置（如按识别语言路由的模型）
+    pub async fn call_ocr_model_raw_prompt_with_config(
+        &self,
+        config: &ApiConfig,
+        effective_engine: crate::ocr_adapters::OcrEngineType,
+        user_prompt: &str,
+        image_payloads: Option<Vec<ImagePayload>>,
+    ) -> Result<StandardModel2Output> {
         let ocr_adapter = crate::ocr_adapters::OcrAdapterFactory::create(effective_engine);
         let ocr_mode = crate::ocr_adapters::OcrMode::FreeOcr;
         let prompt_text = ocr_adapter.build_custom_prompt(user_prompt, ocr_mode);
@@ -4120,6 +4741,7 @@ impl LLMManager {
 
         log_llm_request_audit("OCR_RAW", &preq.url, &config.model, &request_body);
 
+        crate::offline_mode::ensure_endpoint_allowed("OCR 识别", &preq.url)?;
         let mut request_builder = self
             .client
             .post(&preq.url)
@@ -4131,6 +4753,7 @@ impl LLMManager {
         }
 
         // 5. 发送请求
+        let request_builder = self.apply_custom_request_options(request_builder, &config)?;
         let response = request_builder
             .json(&preq.body)
             .send()
@@ -4240,10 +4863,10 @@ impl LLMManager {
             }
         }
 
+        crate::offline_mode::ensure_endpoint_allowed("OCR 识别", &preq.url)?;
+        let request_builder = self.client.post(&preq.url).headers(header_map);
         let response = self
-            .client
-            .post(&preq.url)
-            .headers(header_map)
+            .apply_custom_request_options(request_builder, &config)?
             .json(&preq.body)
             .send()
             .await
@@ -4284,6 +4907,40 @@ impl LLMManager {
     /// - Gemini: promptTokenCount, candidatesTokenCount, thoughtsTokenCount
     ///
     /// 如果 API 没有返回 usage 数据，则使用估算值作为 fallback
+    /// 非流式调用完成后记录用量；响应未带 usage 时按请求体与回答长度估算
+    fn record_completion_usage(
+        config: &ApiConfig,
+        caller_type: crate::llm_usage::CallerType,
+        response: &Value,
+        request_body: &Value,
+        content: &str,
+        started: std::time::Instant,
+    ) {
+        let request_bytes = serde_json::to_string(request_body).map_or(0, |body| body.len());
+        let usage = response
+            .get("usage")
+            .filter(|usage| !usage.is_null())
+            .cloned();
+        let (prompt_tokens, completion_tokens, reasoning_tokens) = Self::extract_usage_tokens(
+            &usage,
+            crate::utils::token_budget::estimate_tokens(content),
+            (request_bytes / 4).max(1),
+        );
+        crate::llm_usage::record_llm_usage(
+            caller_type,
+            &config.model,
+            prompt_tokens,
+            completion_tokens,
+            reasoning_tokens,
+            None,
+            None,
+            Some(config.id.clone()),
+            Some(started.elapsed().as_millis() as u64),
+            true,
+            None,
+        );
+    }
+
     fn extract_usage_tokens(
         usage: &Option<serde_json::Value>,
         fallback_completion_tokens: usize,
@@ -4455,6 +5112,7 @@ impl LLMManager {
 
         log_llm_request_audit("ANKI_CARD", &preq.url, &config.model, &request_body);
 
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &preq.url)?;
         let mut request_builder = self.client
             .post(&preq.url)
             .header("Accept", "text/event-stream, application/json, text/plain, */*")
@@ -4485,6 +5143,7 @@ impl LLMManager {
             }
         }
 
+        let request_builder = self.apply_custom_request_options(request_builder, &config)?;
         let response = request_builder.json(&preq.body).send().await.map_err(|e| {
             let error_msg = if e.to_string().contains("timed out") {
                 format!("Anki制卡API请求超时: {}", e)
@@ -4660,7 +5319,7 @@ impl LLMManager {
         }
     }
 
-    fn should_use_openai_responses(&self, config: &ApiConfig) -> bool {
+    pub(super) fn should_use_openai_responses(&self, config: &ApiConfig) -> bool {
         if config.model_adapter != "general" {
             return false;
         }
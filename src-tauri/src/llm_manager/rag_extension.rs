@@ -12,9 +12,34 @@ use url::Url;
 
 use super::{ApiConfig, LLMManager, Result};
 
+/// 未分配嵌入模型时的错误码，前端据此引导用户到「模型分配」完成设置
+pub const RAG_NO_EMBEDDING_MODEL: &str = "RAG_NO_EMBEDDING_MODEL";
+
 // ==================== RAG相关扩展方法 ====================
 
 impl LLMManager {
+    /// RAG 命令入口检查：确认已分配文本嵌入模型
+    ///
+    /// 须在任何数据库 / LanceDB 操作之前调用。未配置时返回带 `RAG_NO_EMBEDDING_MODEL`
+    /// 错误码的配置错误，替代深层调用处难以理解的报错。
+    pub async fn require_embedding_model(&self) -> Result<ApiConfig> {
+        self.get_embedding_model_config().await.map_err(|e| {
+            AppError::with_details(
+                crate::models::AppErrorType::Configuration,
+                format!(
+                    "{}: 尚未分配嵌入模型，请先在「模型分配」中为文本嵌入指定模型（{}）",
+                    RAG_NO_EMBEDDING_MODEL, e.message
+                ),
+                json!({ "code": RAG_NO_EMBEDDING_MODEL }),
+            )
+        })
+    }
+
+    /// 是否已分配文本嵌入模型（供状态接口提前提示用户设置）
+    pub async fn has_embedding_model(&self) -> bool {
+        self.get_embedding_model_config().await.is_ok()
+    }
+
     /// 获取嵌入模型配置
     ///
     /// 从维度管理的默认设置中获取嵌入模型配置ID
@@ -224,6 +249,7 @@ impl LLMManager {
 
         // 发送请求
         let url = format!("{}/embeddings", config.base_url.trim_end_matches('/'));
+        crate::offline_mode::ensure_endpoint_allowed("嵌入模型", &url)?;
         let mut request_builder = self
             .client
             .post(&url)
@@ -245,6 +271,7 @@ impl LLMManager {
             }
         }
 
+        let request_builder = self.apply_custom_request_options(request_builder, &config)?;
         let response = request_builder
             .json(&request_body)
             .send()
@@ -253,6 +280,11 @@ impl LLMManager {
 
         if !response.status().is_success() {
             let status = response.status();
+            let retry_after_secs = response
+                .headers()
+                .get("Retry-After")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|s| s.trim().parse::<u64>().ok());
             let error_text = response.text().await.unwrap_or_default();
             // 记录完整错误到日志（仅开发调试用）
             error!("[MultimodalEmbedding] API error {}: {}", status, error_text);
@@ -264,7 +296,11 @@ impl LLMManager {
                 500..=599 => "嵌入服务暂时不可用，请稍后重试",
                 _ => "嵌入请求失败，请重试",
             };
-            return Err(AppError::llm(user_message.to_string()));
+            return Err(super::embedding_retry::embedding_http_error(
+                user_message,
+                status.as_u16(),
+                retry_after_secs,
+            ));
         }
 
         let response_json: Value = response
@@ -361,6 +397,7 @@ impl LLMManager {
 
         // 发送请求
         let url = format!("{}/rerank", config.base_url.trim_end_matches('/'));
+        crate::offline_mode::ensure_endpoint_allowed("重排模型", &url)?;
         let mut request_builder = self
             .client
             .post(&url)
@@ -382,6 +419,7 @@ impl LLMManager {
             }
         }
 
+        let request_builder = self.apply_custom_request_options(request_builder, &config)?;
         let response = request_builder
             .json(&request_body)
             .send()
@@ -546,10 +584,10 @@ impl LLMManager {
             }
         }
 
+        crate::offline_mode::ensure_endpoint_allowed("模型调用", &preq.url)?;
+        let request_builder = self.client.post(&preq.url).headers(header_map);
         let response = self
-            .client
-            .post(&preq.url)
-            .headers(header_map)
+            .apply_custom_request_options(request_builder, &config)?
             .json(&preq.body)
             .send()
             .await
@@ -726,6 +764,7 @@ impl LLMManager {
 
         // 发送请求
         let url = format!("{}/embeddings", config.base_url.trim_end_matches('/'));
+        crate::offline_mode::ensure_endpoint_allowed("嵌入模型", &url)?;
         let mut request_builder = self.client
             .post(&url)
             .header("Authorization", format!("Bearer {}", api_key))
@@ -756,6 +795,7 @@ impl LLMManager {
             }
         }
 
+        let request_builder = self.apply_custom_request_options(request_builder, config)?;
         let response = request_builder
             .json(&request_body)
             .send()
@@ -826,6 +866,7 @@ impl LLMManager {
             None,
             None,
             None,
+            Some(config.id.clone()),
             None,
             true,
             None,
@@ -870,6 +911,7 @@ impl LLMManager {
 
         // 发送请求
         let url = format!("{}/rerank", config.base_url.trim_end_matches('/'));
+        crate::offline_mode::ensure_endpoint_allowed("重排模型", &url)?;
         let mut request_builder = self.client
             .post(&url)
             .header("Authorization", format!("Bearer {}", api_key))
@@ -900,6 +942,7 @@ impl LLMManager {
             }
         }
 
+        let request_builder = self.apply_custom_request_options(request_builder, config)?;
         let response = request_builder
             .json(&request_body)
             .send()
@@ -969,6 +1012,7 @@ impl LLMManager {
             None,
             None,
             None,
+            Some(config.id.clone()),
             None,
             true,
             None,
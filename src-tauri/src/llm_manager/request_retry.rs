@@ -0,0 +1,281 @@
+//! 模型请求的瞬时错误重试
+//!
+//! 供应商限流（429）、网关或服务端暂时不可用（500 / 502 / 503 / 504）以及连接被重置时，
+//! 按指数退避重新发送同一请求；429 / 503 优先遵循 `Retry-After`。最大重试次数按模型配置
+//! （`max_request_retries`）调整，未设置时重试 [`DEFAULT_MAX_REQUEST_RETRIES`] 次。
+//!
+//! 重试只发生在拿到成功响应之前：流式请求一旦开始输出内容就不再重发，避免重复内容。
+//! 首 token 超时不重试（重发只会把等待时间翻倍），认证失败等其余 4xx 直接返回。
+
+use std::future::Future;
+use std::time::Duration;
+
+use log::warn;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde_json::{json, Value};
+
+use super::stream_timeout::StreamReadError;
+use super::ApiConfig;
+
+pub const DEFAULT_MAX_REQUEST_RETRIES: u32 = 3;
+/// 模型配置中重试次数的上限，避免误填过大的值导致请求长时间挂起
+const MAX_REQUEST_RETRIES_LIMIT: u32 = 10;
+const INITIAL_REQUEST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_REQUEST_BACKOFF: Duration = Duration::from_secs(30);
+/// 服务端 `Retry-After` 的遵循上限
+const MAX_REQUEST_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RequestRetryPolicy {
+    pub max_retries: u32,
+}
+
+impl RequestRetryPolicy {
+    pub fn for_config(config: &ApiConfig) -> Self {
+        Self {
+            max_retries: config
+                .max_request_retries
+                .unwrap_or(DEFAULT_MAX_REQUEST_RETRIES)
+                .min(MAX_REQUEST_RETRIES_LIMIT),
+        }
+    }
+
+    /// 第 `attempt` 次重试（从 1 开始）前的等待时间
+    ///
+    /// 服务端给出 `Retry-After` 时以其为准（封顶 60 秒），否则从 1 秒起指数退避，封顶 30 秒。
+    pub fn backoff_for(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(wait) = retry_after {
+            return wait.min(MAX_REQUEST_RETRY_AFTER);
+        }
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        INITIAL_REQUEST_BACKOFF
+            .saturating_mul(factor)
+            .min(MAX_REQUEST_BACKOFF)
+    }
+}
+
+/// 限流与服务端暂时不可用的状态码
+pub(crate) fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 429 | 500 | 502 | 503 | 504)
+}
+
+/// 连接建立失败，或连接被对端重置 / 提前关闭
+fn is_retryable_send_error(err: &reqwest::Error) -> bool {
+    err.is_connect() || is_connection_reset(err)
+}
+
+fn is_connection_reset(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(current) = source {
+        // 连接在响应完成前被关闭（如网关重启）
+        if current
+            .downcast_ref::<hyper::Error>()
+            .is_some_and(|e| e.is_incomplete_message())
+        {
+            return true;
+        }
+        if let Some(io) = current.downcast_ref::<std::io::Error>() {
+            if matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::UnexpectedEof
+            ) {
+                return true;
+            }
+        }
+        source = current.source();
+    }
+    false
+}
+
+/// 解析以秒为单位的 `Retry-After`（HTTP 日期格式不处理，回落到指数退避）
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// 一次即将进行的重试
+#[derive(Debug, Clone)]
+pub(crate) struct RequestRetry {
+    /// 第几次重试（从 1 开始）
+    pub attempt: u32,
+    pub max_retries: u32,
+    pub delay_ms: u64,
+    /// 触发重试的 HTTP 状态码；连接错误时为空
+    pub status: Option<u16>,
+    pub reason: String,
+}
+
+impl RequestRetry {
+    /// `{stream_event}_retrying` 事件的载荷
+    pub fn event_payload(&self, request_id: &str) -> Value {
+        json!({
+            "id": request_id,
+            "attempt": self.attempt,
+            "max_retries": self.max_retries,
+            "delay_ms": self.delay_ms,
+            "status": self.status,
+            "reason": self.reason,
+        })
+    }
+}
+
+/// 非流式请求的单次发送，供 [`send_with_retry`] 使用
+pub(crate) async fn send_once(
+    builder: reqwest::RequestBuilder,
+) -> std::result::Result<(reqwest::Response, ()), StreamReadError> {
+    builder
+        .send()
+        .await
+        .map(|response| (response, ()))
+        .map_err(StreamReadError::Transport)
+}
+
+/// 发送请求，遇到瞬时错误时按模型配置的策略重试
+///
+/// `builder` 为完整的请求（含请求体），每次尝试克隆一份交给 `send`；`send` 可顺带返回
+/// 本次尝试的附加状态（如流式计时器）。收到响应即返回，不论状态码：重试耗尽后最后一次
+/// 的错误响应原样交给调用方处理，此时其状态码满足 [`is_retryable_status`]。
+/// 每次重试前记录日志并调用 `on_retry`。
+pub(crate) async fn send_with_retry<T, Fut>(
+    config: &ApiConfig,
+    label: &str,
+    builder: reqwest::RequestBuilder,
+    mut send: impl FnMut(reqwest::RequestBuilder) -> Fut,
+    mut on_retry: impl FnMut(&RequestRetry),
+) -> std::result::Result<(reqwest::Response, T), StreamReadError>
+where
+    Fut: Future<Output = std::result::Result<(reqwest::Response, T), StreamReadError>>,
+{
+    let policy = RequestRetryPolicy::for_config(config);
+    let mut attempt = 0u32;
+    loop {
+        // 流式请求体无法克隆时只发送一次
+        let Some(current) = builder.try_clone() else {
+            return send(builder).await;
+        };
+        let can_retry = attempt < policy.max_retries;
+        let (status, wait, reason) = match send(current).await {
+            Ok((response, extra)) => {
+                let status = response.status();
+                if !can_retry || !is_retryable_status(status.as_u16()) {
+                    return Ok((response, extra));
+                }
+                (
+                    Some(status.as_u16()),
+                    retry_after(response.headers()),
+                    format!("HTTP {}", status),
+                )
+            }
+            Err(StreamReadError::Transport(e)) if can_retry && is_retryable_send_error(&e) => {
+                (None, None, e.to_string())
+            }
+            Err(e) => return Err(e),
+        };
+
+        attempt += 1;
+        let delay = policy.backoff_for(attempt, wait);
+        let retry = RequestRetry {
+            attempt,
+            max_retries: policy.max_retries,
+            delay_ms: delay.as_millis() as u64,
+            status,
+            reason,
+        };
+        warn!(
+            "[{}] 模型 {} 请求失败（{}），{}ms 后重试 ({}/{})",
+            label, config.model, retry.reason, retry.delay_ms, attempt, policy.max_retries
+        );
+        on_retry(&retry);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_reads_config_and_caps() {
+        let mut config = ApiConfig::default();
+        assert_eq!(
+            RequestRetryPolicy::for_config(&config).max_retries,
+            DEFAULT_MAX_REQUEST_RETRIES
+        );
+        config.max_request_retries = Some(0);
+        assert_eq!(RequestRetryPolicy::for_config(&config).max_retries, 0);
+        config.max_request_retries = Some(1000);
+        assert_eq!(
+            RequestRetryPolicy::for_config(&config).max_retries,
+            MAX_REQUEST_RETRIES_LIMIT
+        );
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_honours_retry_after() {
+        let policy = RequestRetryPolicy { max_retries: 3 };
+        assert_eq!(policy.backoff_for(1, None), Duration::from_secs(1));
+        assert_eq!(policy.backoff_for(3, None), Duration::from_secs(4));
+        assert_eq!(policy.backoff_for(10, None), MAX_REQUEST_BACKOFF);
+        assert_eq!(
+            policy.backoff_for(1, Some(Duration::from_secs(12))),
+            Duration::from_secs(12)
+        );
+        assert_eq!(
+            policy.backoff_for(1, Some(Duration::from_secs(600))),
+            MAX_REQUEST_RETRY_AFTER
+        );
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        for status in [429, 500, 502, 503, 504] {
+            assert!(is_retryable_status(status), "{}", status);
+        }
+        for status in [400, 401, 403, 404, 422, 501] {
+            assert!(!is_retryable_status(status), "{}", status);
+        }
+    }
+
+    #[test]
+    fn test_retry_after_seconds() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2026 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_connection_reset_found_in_error_chain() {
+        #[derive(Debug)]
+        struct Wrapper(std::io::Error);
+        impl std::fmt::Display for Wrapper {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "request failed")
+            }
+        }
+        impl std::error::Error for Wrapper {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let reset = Wrapper(std::io::ErrorKind::ConnectionReset.into());
+        assert!(is_connection_reset(&reset));
+        let denied = Wrapper(std::io::ErrorKind::PermissionDenied.into());
+        assert!(!is_connection_reset(&denied));
+    }
+}
@@ -0,0 +1,233 @@
+//! 流式输出合并发送
+//!
+//! 快速模型逐 token 推送会让前端频繁重渲染。此模块把 token 缓冲成
+//! 约 N 毫秒或 M 个字符的块再发送，遇到句子边界、流结束时立即冲刷。
+//! 正文与思维链各用一个独立的合并器，互不混合。
+//!
+//! 阈值只在新增量到达时检查，慢速流的尾部缓冲会一直等到下一个 token；
+//! 因此流循环另外用 [`CoalesceTicker`] 定时调用 [`StreamCoalescer::flush_if_due`]。
+//!
+//! 未配置（或配置中阈值全为空）时保持逐 token 透传，与旧行为一致。
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::time::{Interval, MissedTickBehavior};
+
+/// 句子边界字符：缓冲区以这些字符结尾时立即冲刷
+const SENTENCE_BOUNDARIES: &[char] = &['。', '！', '？', '；', '\n', '.', '!', '?', ';'];
+
+/// 仅配置字符阈值时的定时冲刷周期
+const DEFAULT_IDLE_FLUSH: Duration = Duration::from_millis(200);
+
+fn default_flush_on_sentence() -> bool {
+    true
+}
+
+/// 流式合并配置（挂在模型配置上，随模型适配器选项一起保存）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamCoalesceConfig {
+    /// 距上次发送超过该毫秒数即冲刷
+    #[serde(default, alias = "flush_interval_ms")]
+    pub flush_interval_ms: Option<u64>,
+    /// 缓冲字符数达到该值即冲刷
+    #[serde(default, alias = "flush_chars")]
+    pub flush_chars: Option<usize>,
+    /// 缓冲以句末标点或换行结尾时立即冲刷
+    #[serde(default = "default_flush_on_sentence", alias = "flush_on_sentence")]
+    pub flush_on_sentence: bool,
+}
+
+impl StreamCoalesceConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.flush_interval_ms.is_some_and(|ms| ms > 0) || self.flush_chars.is_some_and(|n| n > 1)
+    }
+}
+
+/// 单路（正文或思维链）的 token 合并器
+pub(crate) struct StreamCoalescer {
+    config: Option<StreamCoalesceConfig>,
+    buffer: String,
+    buffered_chars: usize,
+    last_flush: Instant,
+}
+
+impl StreamCoalescer {
+    pub(crate) fn new(config: Option<&StreamCoalesceConfig>) -> Self {
+        Self {
+            config: config.filter(|c| c.is_enabled()).cloned(),
+            buffer: String::new(),
+            buffered_chars: 0,
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// 追加一段增量；需要发送时返回合并后的文本
+    pub(crate) fn push(&mut self, delta: &str) -> Option<String> {
+        self.push_at(delta, Instant::now())
+    }
+
+    fn push_at(&mut self, delta: &str, now: Instant) -> Option<String> {
+        let Some(config) = self.config.as_ref() else {
+            return Some(delta.to_string());
+        };
+        if delta.is_empty() {
+            return None;
+        }
+        self.buffer.push_str(delta);
+        self.buffered_chars += delta.chars().count();
+
+        let by_chars = config
+            .flush_chars
+            .is_some_and(|limit| self.buffered_chars >= limit);
+        let by_time = config.flush_interval_ms.is_some_and(|ms| {
+            now.saturating_duration_since(self.last_flush) >= Duration::from_millis(ms)
+        });
+        let by_sentence = config.flush_on_sentence
+            && self
+                .buffer
+                .trim_end_matches(' ')
+                .ends_with(SENTENCE_BOUNDARIES);
+
+        if by_chars || by_time || by_sentence {
+            self.last_flush = now;
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    /// 取出剩余缓冲（流结束、切换事件类型或工具调用前调用）
+    pub(crate) fn flush(&mut self) -> Option<String> {
+        self.last_flush = Instant::now();
+        self.take()
+    }
+
+    /// 定时冲刷周期；未启用合并时为 `None`
+    pub(crate) fn idle_flush_period(&self) -> Option<Duration> {
+        let config = self.config.as_ref()?;
+        Some(
+            config
+                .flush_interval_ms
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_IDLE_FLUSH),
+        )
+    }
+
+    /// 距上次发送已超过定时冲刷周期时取出缓冲（由 [`CoalesceTicker`] 驱动）
+    pub(crate) fn flush_if_due(&mut self) -> Option<String> {
+        self.flush_if_due_at(Instant::now())
+    }
+
+    fn flush_if_due_at(&mut self, now: Instant) -> Option<String> {
+        let period = self.idle_flush_period()?;
+        if self.buffer.is_empty() || now.saturating_duration_since(self.last_flush) < period {
+            return None;
+        }
+        self.last_flush = now;
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<String> {
+        self.buffered_chars = 0;
+        if self.buffer.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.buffer))
+        }
+    }
+}
+
+/// 流循环中的定时冲刷节拍；未启用合并时永不触发
+pub(crate) struct CoalesceTicker(Option<Interval>);
+
+impl CoalesceTicker {
+    pub(crate) fn new(coalescer: &StreamCoalescer) -> Self {
+        Self(coalescer.idle_flush_period().map(|period| {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            interval
+        }))
+    }
+
+    /// 等待下一次节拍（可在 `select!` 中安全取消）
+    pub(crate) async fn tick(&mut self) {
+        match self.0.as_mut() {
+            Some(interval) => {
+                interval.tick().await;
+            }
+            None => std::future::pending::<()>().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(interval: Option<u64>, chars: Option<usize>, sentence: bool) -> StreamCoalesceConfig {
+        StreamCoalesceConfig {
+            flush_interval_ms: interval,
+            flush_chars: chars,
+            flush_on_sentence: sentence,
+        }
+    }
+
+    #[test]
+    fn test_disabled_passes_tokens_through() {
+        let mut c = StreamCoalescer::new(None);
+        assert_eq!(c.push("a").as_deref(), Some("a"));
+        let mut c = StreamCoalescer::new(Some(&config(None, None, true)));
+        assert_eq!(c.push("b").as_deref(), Some("b"));
+        assert_eq!(c.flush(), None);
+    }
+
+    #[test]
+    fn test_flushes_by_char_count_and_sentence() {
+        let mut c = StreamCoalescer::new(Some(&config(None, Some(6), true)));
+        assert_eq!(c.push("你好"), None);
+        assert_eq!(c.push("世界").as_deref(), None);
+        assert_eq!(c.push("。").as_deref(), Some("你好世界。"));
+        assert_eq!(c.push("abc"), None);
+        assert_eq!(c.push("def").as_deref(), Some("abcdef"));
+        assert_eq!(c.push("tail"), None);
+        assert_eq!(c.flush().as_deref(), Some("tail"));
+    }
+
+    #[test]
+    fn test_flushes_by_interval() {
+        let mut c = StreamCoalescer::new(Some(&config(Some(50), None, false)));
+        let start = c.last_flush;
+        assert_eq!(c.push_at("a", start + Duration::from_millis(10)), None);
+        assert_eq!(
+            c.push_at("b", start + Duration::from_millis(60)).as_deref(),
+            Some("ab")
+        );
+    }
+
+    #[test]
+    fn test_flush_if_due_releases_trailing_buffer() {
+        let mut c = StreamCoalescer::new(Some(&config(Some(50), None, false)));
+        let start = c.last_flush;
+        assert_eq!(c.push_at("ta", start + Duration::from_millis(10)), None);
+        assert_eq!(c.flush_if_due_at(start + Duration::from_millis(30)), None);
+        assert_eq!(
+            c.flush_if_due_at(start + Duration::from_millis(55))
+                .as_deref(),
+            Some("ta")
+        );
+        assert_eq!(c.flush_if_due_at(start + Duration::from_millis(200)), None);
+
+        let mut c = StreamCoalescer::new(Some(&config(None, Some(100), false)));
+        assert_eq!(c.idle_flush_period(), Some(DEFAULT_IDLE_FLUSH));
+        assert_eq!(StreamCoalescer::new(None).idle_flush_period(), None);
+        let start = c.last_flush;
+        assert_eq!(c.push_at("x", start), None);
+        assert_eq!(
+            c.flush_if_due_at(start + DEFAULT_IDLE_FLUSH).as_deref(),
+            Some("x")
+        );
+    }
+}
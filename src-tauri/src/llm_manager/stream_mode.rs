@@ -0,0 +1,294 @@
+//! 流式 / 非流式请求模式
+//!
+//! 部分供应商或内部网关不支持 SSE（或会把整段响应缓冲后才返回）。模型配置上
+//! `supports_streaming = false` 或开启 `force_non_streaming` 时，流式调用改发非流式请求，
+//! 再把完整响应转换为一段 OpenAI 形状的 SSE 文本，交给原有的流式解析循环处理，
+//! 前端照常收到 start / 内容块 / 完成事件，无需区分。
+
+use super::stream_timeout::{StreamReadError, StreamTimer};
+use super::{ApiConfig, Result};
+use crate::models::AppError;
+use crate::providers::{OpenAIAdapter, ProviderAdapter};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// 流式循环消费的字节流（两种模式统一为同一类型）
+pub(crate) type ResponseByteStream =
+    BoxStream<'static, std::result::Result<Vec<u8>, StreamReadError>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamMode {
+    Stream,
+    NonStream,
+}
+
+impl StreamMode {
+    pub fn for_config(config: &ApiConfig) -> Self {
+        if config.supports_streaming && !config.force_non_streaming {
+            StreamMode::Stream
+        } else {
+            StreamMode::NonStream
+        }
+    }
+
+    pub fn is_streaming(self) -> bool {
+        self == StreamMode::Stream
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StreamMode::Stream => "stream",
+            StreamMode::NonStream => "non_stream",
+        }
+    }
+
+    /// 写入 `StandardModel2Output::raw_response`，记录本次实际使用的模式
+    pub fn raw_response_marker(self) -> &'static str {
+        match self {
+            StreamMode::Stream => "stream_response",
+            StreamMode::NonStream => "non_stream_response",
+        }
+    }
+
+    /// 解析响应所用的适配器：非流式响应已被转换为 OpenAI 形状的 SSE
+    pub(crate) fn parse_adapter(
+        self,
+        adapter: Box<dyn ProviderAdapter>,
+    ) -> Box<dyn ProviderAdapter> {
+        match self {
+            StreamMode::Stream => adapter,
+            StreamMode::NonStream => Box::new(OpenAIAdapter),
+        }
+    }
+
+    /// 将响应转换为流式循环使用的字节流
+    ///
+    /// 流式模式透传并由 `timer` 施加首 token / 空闲超时；非流式模式读取完整响应、
+    /// 统一为 OpenAI 形状后合成 SSE。`responses_api` 表示请求走的是 OpenAI Responses API。
+    pub(crate) async fn into_byte_stream(
+        self,
+        response: reqwest::Response,
+        config: &ApiConfig,
+        responses_api: bool,
+        timer: StreamTimer,
+    ) -> Result<ResponseByteStream> {
+        if self.is_streaming() {
+            return Ok(timer.guard(
+                response
+                    .bytes_stream()
+                    .map(|chunk| chunk.map(|bytes| bytes.to_vec())),
+            ));
+        }
+
+        let status = response.status();
+        let response_text = response
+            .text()
+            .await
+            .map_err(|e| AppError::llm(format!("读取非流式响应失败: {}", e)))?;
+        if !status.is_success() {
+            return Err(AppError::llm(format!(
+                "API请求失败: {} - {}",
+                status, response_text
+            )));
+        }
+        let response_json: Value = serde_json::from_str(&response_text)
+            .map_err(|e| AppError::llm(format!("解析非流式响应失败: {}", e)))?;
+        let openai_like = normalize_nonstream_response(config, response_json, responses_api)?;
+        let sse = synthesize_sse_from_response(&openai_like);
+        Ok(stream::once(async move { Ok(sse.into_bytes()) }).boxed())
+    }
+}
+
+/// 将各供应商的非流式响应统一为 OpenAI Chat Completions 形状
+fn normalize_nonstream_response(
+    config: &ApiConfig,
+    response_json: Value,
+    responses_api: bool,
+) -> Result<Value> {
+    // 已是 Chat Completions 形状（OpenAI 兼容网关等）时直接使用
+    if response_json.get("choices").is_some() {
+        return Ok(response_json);
+    }
+    match config.model_adapter.as_str() {
+        "google" | "gemini" => {
+            if let Some(safety_msg) = super::LLMManager::extract_gemini_safety_error(&response_json)
+            {
+                return Err(AppError::llm(safety_msg));
+            }
+            crate::adapters::gemini_openai_converter::convert_gemini_nonstream_response_to_openai(
+                &response_json,
+                &config.model,
+            )
+            .map_err(|e| AppError::llm(format!("Gemini响应转换失败: {}", e)))
+        }
+        "anthropic" | "claude" => {
+            crate::providers::convert_anthropic_response_to_openai(&response_json, &config.model)
+                .ok_or_else(|| AppError::llm("解析Anthropic响应失败"))
+        }
+        _ if responses_api => Ok(responses_output_to_openai(&response_json)),
+        _ => Ok(response_json),
+    }
+}
+
+/// OpenAI Responses API 的非流式输出 → Chat Completions 形状（仅正文与 usage）
+fn responses_output_to_openai(response_json: &Value) -> Value {
+    let mut text = String::new();
+    for item in response_json
+        .get("output")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+    {
+        for entry in item
+            .get("content")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let entry_type = entry.get("type").and_then(|v| v.as_str()).unwrap_or("");
+            if matches!(entry_type, "output_text" | "text") {
+                if let Some(segment) = entry.get("text").and_then(|v| v.as_str()) {
+                    text.push_str(segment);
+                }
+            }
+        }
+    }
+    if text.is_empty() {
+        if let Some(output_text) = response_json.get("output_text").and_then(|v| v.as_str()) {
+            text.push_str(output_text);
+        }
+    }
+    json!({
+        "choices": [{ "message": { "content": text } }],
+        "usage": response_json.get("usage").cloned()
+    })
+}
+
+/// 消息内容可能是字符串或分段数组，统一拼为文本
+fn message_text(content: Option<&Value>) -> Option<String> {
+    match content? {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(parts) => Some(
+            parts
+                .iter()
+                .filter_map(|part| part.get("text").and_then(|v| v.as_str()))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// 把 OpenAI 形状的完整响应转换为等价的 SSE 文本：一个包含全部内容的 delta、
+/// 可选的 usage 块以及 `[DONE]`
+pub(crate) fn synthesize_sse_from_response(openai_like: &Value) -> String {
+    let choice = openai_like
+        .pointer("/choices/0")
+        .cloned()
+        .unwrap_or(Value::Null);
+    let message = choice.get("message").cloned().unwrap_or(Value::Null);
+
+    let mut delta = Map::new();
+    if let Some(content) = message_text(message.get("content")).filter(|t| !t.is_empty()) {
+        delta.insert("content".to_string(), json!(content));
+    }
+    if let Some(reasoning) = message
+        .get("reasoning_content")
+        .and_then(|v| v.as_str())
+        .filter(|t| !t.is_empty())
+    {
+        delta.insert("reasoning_content".to_string(), json!(reasoning));
+    }
+    if let Some(tool_calls) = message.get("tool_calls").and_then(|v| v.as_array()) {
+        // 流式工具调用按 index 聚合，非流式响应里没有该字段，需要补上
+        let indexed: Vec<Value> = tool_calls
+            .iter()
+            .enumerate()
+            .map(|(index, call)| {
+                let mut call = call.clone();
+                if let Some(obj) = call.as_object_mut() {
+                    obj.entry("index").or_insert(json!(index));
+                }
+                call
+            })
+            .collect();
+        if !indexed.is_empty() {
+            delta.insert("tool_calls".to_string(), Value::Array(indexed));
+        }
+    }
+
+    let chunk = json!({
+        "choices": [{
+            "index": 0,
+            "delta": delta,
+            "finish_reason": choice.get("finish_reason").cloned().unwrap_or(Value::Null)
+        }]
+    });
+    let mut sse = format!("data: {}\n\n", chunk);
+    if let Some(usage) = openai_like.get("usage").filter(|u| u.is_object()) {
+        sse.push_str(&format!(
+            "data: {}\n\n",
+            json!({ "choices": [], "usage": usage })
+        ));
+    }
+    sse.push_str("data: [DONE]\n\n");
+    sse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::StreamEvent;
+
+    #[test]
+    fn test_stream_mode_for_config() {
+        let mut config = ApiConfig::default();
+        assert_eq!(StreamMode::for_config(&config), StreamMode::Stream);
+
+        config.force_non_streaming = true;
+        assert_eq!(StreamMode::for_config(&config), StreamMode::NonStream);
+
+        config.force_non_streaming = false;
+        config.supports_streaming = false;
+        assert_eq!(StreamMode::for_config(&config), StreamMode::NonStream);
+        assert_eq!(
+            StreamMode::NonStream.raw_response_marker(),
+            "non_stream_response"
+        );
+    }
+
+    #[test]
+    fn test_synthesized_sse_parses_as_single_event_sequence() {
+        let response = json!({
+            "choices": [{
+                "message": {
+                    "content": "你好",
+                    "reasoning_content": "先想一想",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "search", "arguments": "{\"q\":\"x\"}" }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": { "prompt_tokens": 3, "completion_tokens": 5 }
+        });
+
+        let sse = synthesize_sse_from_response(&response);
+        let events: Vec<StreamEvent> = sse
+            .lines()
+            .filter(|line| !line.is_empty())
+            .flat_map(|line| OpenAIAdapter.parse_stream(line))
+            .collect();
+
+        assert!(matches!(&events[0], StreamEvent::ContentChunk(c) if c == "你好"));
+        assert!(matches!(&events[1], StreamEvent::ReasoningChunk(r) if r == "先想一想"));
+        assert!(
+            matches!(&events[2], StreamEvent::ToolCall(tc) if tc["index"] == 0 && tc["id"] == "call_1")
+        );
+        assert!(matches!(&events[3], StreamEvent::Usage(u) if u["completion_tokens"] == 5));
+        assert!(matches!(events.last(), Some(StreamEvent::Done)));
+    }
+}
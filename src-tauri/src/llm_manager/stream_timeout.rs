@@ -0,0 +1,288 @@
+//! 流式响应的首 token 超时与空闲超时
+//!
+//! 部分供应商首个 token 要等 30 秒以上，之后输出正常；单一的总超时要么截断健康的长输出，
+//! 要么在无响应的连接上等待过久。流式请求因此改用两个预算（模型配置中可分别调整）：
+//!
+//! - `first_token_timeout_ms`：从发出请求到收到首个数据块的最长等待（含等待响应头）；
+//! - `idle_timeout_ms`：相邻两个数据块之间的最长间隔。
+//!
+//! 只要数据持续到达，流就不会被截断：流式请求的单次请求超时放宽为
+//! [`STREAMING_REQUEST_TIMEOUT`]，不再受 HTTP 客户端 300 秒总超时限制。超时错误的
+//! `details.code` 标明超出的是哪个预算。非流式模式一次性返回完整响应，不适用这两个预算。
+
+use std::fmt;
+use std::time::Duration;
+
+use futures_util::stream::{self, BoxStream, StreamExt};
+use serde_json::json;
+use tokio::time::Instant;
+
+use super::stream_mode::{ResponseByteStream, StreamMode};
+use super::ApiConfig;
+use crate::models::{AppError, AppErrorType};
+
+pub const DEFAULT_FIRST_TOKEN_TIMEOUT_MS: u64 = 180_000;
+pub const DEFAULT_IDLE_TIMEOUT_MS: u64 = 90_000;
+/// 流式请求的单次请求超时，覆盖客户端总超时；实际由首 token / 空闲预算把关
+pub(crate) const STREAMING_REQUEST_TIMEOUT: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamTimeoutKind {
+    FirstToken,
+    Idle,
+}
+
+impl StreamTimeoutKind {
+    pub fn code(self) -> &'static str {
+        match self {
+            StreamTimeoutKind::FirstToken => "LLM_FIRST_TOKEN_TIMEOUT",
+            StreamTimeoutKind::Idle => "LLM_STREAM_IDLE_TIMEOUT",
+        }
+    }
+
+    fn budget_name(self) -> &'static str {
+        match self {
+            StreamTimeoutKind::FirstToken => "first_token_timeout",
+            StreamTimeoutKind::Idle => "idle_timeout",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamTimeouts {
+    pub first_token: Duration,
+    pub idle: Duration,
+}
+
+impl Default for StreamTimeouts {
+    fn default() -> Self {
+        Self {
+            first_token: Duration::from_millis(DEFAULT_FIRST_TOKEN_TIMEOUT_MS),
+            idle: Duration::from_millis(DEFAULT_IDLE_TIMEOUT_MS),
+        }
+    }
+}
+
+impl StreamTimeouts {
+    /// 读取模型配置；未设置或为 0 时使用默认值
+    pub fn for_config(config: &ApiConfig) -> Self {
+        let defaults = Self::default();
+        let budget = |ms: Option<u64>, default: Duration| {
+            ms.filter(|ms| *ms > 0)
+                .map(Duration::from_millis)
+                .unwrap_or(default)
+        };
+        Self {
+            first_token: budget(config.first_token_timeout_ms, defaults.first_token),
+            idle: budget(config.idle_timeout_ms, defaults.idle),
+        }
+    }
+}
+
+/// 流式循环读取数据块时的错误
+#[derive(Debug)]
+pub(crate) enum StreamReadError {
+    Transport(reqwest::Error),
+    Timeout {
+        kind: StreamTimeoutKind,
+        budget: Duration,
+    },
+}
+
+impl fmt::Display for StreamReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamReadError::Transport(e) => write!(f, "{}", e),
+            StreamReadError::Timeout {
+                kind: StreamTimeoutKind::FirstToken,
+                budget,
+            } => write!(
+                f,
+                "首个 token 超时：{} 秒内未收到模型输出（first_token_timeout）",
+                budget.as_secs_f32()
+            ),
+            StreamReadError::Timeout {
+                kind: StreamTimeoutKind::Idle,
+                budget,
+            } => write!(
+                f,
+                "流式输出空闲超时：超过 {} 秒未收到新的输出（idle_timeout）",
+                budget.as_secs_f32()
+            ),
+        }
+    }
+}
+
+impl StreamReadError {
+    pub(crate) fn timeout_code(&self) -> Option<&'static str> {
+        match self {
+            StreamReadError::Timeout { kind, .. } => Some(kind.code()),
+            StreamReadError::Transport(_) => None,
+        }
+    }
+
+    /// 超时转为带 `code` 的错误；传输错误交给调用方按各自的文案包装
+    pub(crate) fn into_app_error(
+        self,
+        transport: impl FnOnce(reqwest::Error) -> AppError,
+    ) -> AppError {
+        let message = self.to_string();
+        match self {
+            StreamReadError::Transport(e) => transport(e),
+            StreamReadError::Timeout { kind, budget } => AppError::with_details(
+                AppErrorType::Network,
+                message,
+                json!({
+                    "code": kind.code(),
+                    "budget": kind.budget_name(),
+                    "timeoutMs": budget.as_millis() as u64,
+                }),
+            ),
+        }
+    }
+}
+
+/// 单次流式请求的计时：首 token 截止时间从发出请求时起算
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StreamTimer {
+    timeouts: StreamTimeouts,
+    first_token_deadline: Instant,
+    enabled: bool,
+}
+
+impl StreamTimer {
+    pub(crate) fn start(config: &ApiConfig, mode: StreamMode) -> Self {
+        let timeouts = StreamTimeouts::for_config(config);
+        Self {
+            timeouts,
+            first_token_deadline: Instant::now() + timeouts.first_token,
+            enabled: mode.is_streaming(),
+        }
+    }
+
+    fn first_token_timeout(&self) -> StreamReadError {
+        StreamReadError::Timeout {
+            kind: StreamTimeoutKind::FirstToken,
+            budget: self.timeouts.first_token,
+        }
+    }
+
+    /// 发送请求；流式模式下等待响应头也计入首 token 预算
+    pub(crate) async fn send(
+        &self,
+        builder: reqwest::RequestBuilder,
+    ) -> std::result::Result<reqwest::Response, StreamReadError> {
+        if !self.enabled {
+            return builder.send().await.map_err(StreamReadError::Transport);
+        }
+        let request = builder.timeout(STREAMING_REQUEST_TIMEOUT).send();
+        match tokio::time::timeout_at(self.first_token_deadline, request).await {
+            Ok(response) => response.map_err(StreamReadError::Transport),
+            Err(_) => Err(self.first_token_timeout()),
+        }
+    }
+
+    /// 为响应字节流加上首 token / 空闲超时；超时后流结束
+    pub(crate) fn guard<S>(self, inner: S) -> ResponseByteStream
+    where
+        S: futures_util::Stream<Item = std::result::Result<Vec<u8>, reqwest::Error>>
+            + Send
+            + 'static,
+    {
+        let inner: BoxStream<'static, _> = inner.boxed();
+        stream::unfold(Some((inner, false)), move |state| async move {
+            let (mut inner, received) = state?;
+            let next = if received {
+                tokio::time::timeout(self.timeouts.idle, inner.next()).await
+            } else {
+                tokio::time::timeout_at(self.first_token_deadline, inner.next()).await
+            };
+            match next {
+                Ok(Some(Ok(chunk))) => {
+                    let received = received || !chunk.is_empty();
+                    Some((Ok(chunk), Some((inner, received))))
+                }
+                Ok(Some(Err(e))) => {
+                    Some((Err(StreamReadError::Transport(e)), Some((inner, received))))
+                }
+                Ok(None) => None,
+                Err(_) if received => Some((
+                    Err(StreamReadError::Timeout {
+                        kind: StreamTimeoutKind::Idle,
+                        budget: self.timeouts.idle,
+                    }),
+                    None,
+                )),
+                Err(_) => Some((Err(self.first_token_timeout()), None)),
+            }
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer(first_token_ms: u64, idle_ms: u64) -> StreamTimer {
+        let config = ApiConfig {
+            first_token_timeout_ms: Some(first_token_ms),
+            idle_timeout_ms: Some(idle_ms),
+            ..Default::default()
+        };
+        StreamTimer::start(&config, StreamMode::Stream)
+    }
+
+    fn chunks_after(
+        delays_ms: &[u64],
+    ) -> impl futures_util::Stream<Item = std::result::Result<Vec<u8>, reqwest::Error>> {
+        stream::iter(delays_ms.to_vec()).then(|delay| async move {
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Ok(b"data: x\n\n".to_vec())
+        })
+    }
+
+    #[tokio::test]
+    async fn test_slow_first_token_then_steady_stream_is_not_cut() {
+        // 首块晚于空闲预算到达，之后持续输出，总时长远超空闲预算
+        let mut delays = vec![150];
+        delays.extend([20; 20]);
+        let stream = timer(1_000, 100).guard(chunks_after(&delays));
+        let chunks: Vec<_> = stream.collect().await;
+        assert_eq!(chunks.len(), 21);
+        assert!(chunks.iter().all(|c| c.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_report_which_budget_was_exceeded() {
+        let silent = timer(50, 1_000).guard(chunks_after(&[1_000]));
+        let results: Vec<_> = silent.collect().await;
+        assert_eq!(results.len(), 1);
+        let err = results.into_iter().next().unwrap().unwrap_err();
+        assert_eq!(err.timeout_code(), Some("LLM_FIRST_TOKEN_TIMEOUT"));
+
+        let stalled = timer(1_000, 100).guard(chunks_after(&[5, 5, 1_000]));
+        let results: Vec<_> = stalled.collect().await;
+        assert_eq!(results.len(), 3);
+        let err = results.into_iter().last().unwrap().unwrap_err();
+        assert_eq!(err.timeout_code(), Some("LLM_STREAM_IDLE_TIMEOUT"));
+        let app_err = err.into_app_error(|e| AppError::network(e.to_string()));
+        assert_eq!(
+            app_err.details.as_ref().and_then(|d| d.get("budget")),
+            Some(&json!("idle_timeout"))
+        );
+        assert!(app_err.message.contains("idle_timeout"));
+    }
+
+    #[test]
+    fn test_zero_or_missing_budget_uses_default() {
+        let config = ApiConfig {
+            first_token_timeout_ms: Some(0),
+            idle_timeout_ms: Some(15_000),
+            ..Default::default()
+        };
+        let timeouts = StreamTimeouts::for_config(&config);
+        assert_eq!(timeouts.first_token, StreamTimeouts::default().first_token);
+        assert_eq!(timeouts.idle, Duration::from_secs(15));
+    }
+}
@@ -0,0 +1,109 @@
+//! 思维链（thinking_content）的推送与保存策略
+//!
+//! 有的用户希望实时看到模型推理过程，有的则觉得干扰且浪费带宽。策略决定思维链是否
+//! 实时推送到前端、是否随消息保存，优先级为：单次请求覆盖 > 模型配置 > 全局设置
+//! `chat.thinking_delivery`。均未设置时既推送也保存，与旧行为一致。
+//!
+//! 关闭推送但开启保存时，思维链仍在流式过程中累积，完成时随消息一起保存。
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::Database;
+
+/// 全局设置键，值为 `stream_and_persist` / `stream_only` / `persist_only` / `off`
+pub const THINKING_DELIVERY_SETTING_KEY: &str = "chat.thinking_delivery";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThinkingDelivery {
+    /// 实时推送并保存
+    #[default]
+    StreamAndPersist,
+    /// 仅实时推送，不保存
+    StreamOnly,
+    /// 不推送，完成时保存
+    PersistOnly,
+    /// 既不推送也不保存
+    Off,
+}
+
+impl ThinkingDelivery {
+    pub fn streams(self) -> bool {
+        matches!(self, Self::StreamAndPersist | Self::StreamOnly)
+    }
+
+    pub fn persists(self) -> bool {
+        matches!(self, Self::StreamAndPersist | Self::PersistOnly)
+    }
+
+    /// 按优先级合并单次请求、模型配置与全局设置
+    pub fn resolve(
+        request_override: Option<Self>,
+        model_option: Option<Self>,
+        global_setting: Option<Self>,
+    ) -> Self {
+        request_override
+            .or(model_option)
+            .or(global_setting)
+            .unwrap_or_default()
+    }
+
+    /// 解析设置值，兼容带引号的 JSON 字符串
+    fn parse_setting(raw: &str) -> Option<Self> {
+        let value = raw.trim().trim_matches('"');
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+}
+
+pub fn load_thinking_delivery_setting(db: &Database) -> Option<ThinkingDelivery> {
+    db.get_setting(THINKING_DELIVERY_SETTING_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| ThinkingDelivery::parse_setting(&raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_streams_and_persists() {
+        let delivery = ThinkingDelivery::resolve(None, None, None);
+        assert_eq!(delivery, ThinkingDelivery::StreamAndPersist);
+        assert!(delivery.streams());
+        assert!(delivery.persists());
+        assert!(!ThinkingDelivery::Off.streams());
+        assert!(!ThinkingDelivery::Off.persists());
+    }
+
+    #[test]
+    fn test_resolve_prefers_request_then_model_then_global() {
+        let resolved = ThinkingDelivery::resolve(
+            Some(ThinkingDelivery::Off),
+            Some(ThinkingDelivery::StreamOnly),
+            Some(ThinkingDelivery::PersistOnly),
+        );
+        assert_eq!(resolved, ThinkingDelivery::Off);
+        let resolved = ThinkingDelivery::resolve(
+            None,
+            Some(ThinkingDelivery::StreamOnly),
+            Some(ThinkingDelivery::PersistOnly),
+        );
+        assert_eq!(resolved, ThinkingDelivery::StreamOnly);
+        let resolved = ThinkingDelivery::resolve(None, None, Some(ThinkingDelivery::PersistOnly));
+        assert_eq!(resolved, ThinkingDelivery::PersistOnly);
+    }
+
+    #[test]
+    fn test_parse_setting_accepts_plain_and_quoted_values() {
+        assert_eq!(
+            ThinkingDelivery::parse_setting("persist_only"),
+            Some(ThinkingDelivery::PersistOnly)
+        );
+        assert_eq!(
+            ThinkingDelivery::parse_setting(" \"stream_only\" "),
+            Some(ThinkingDelivery::StreamOnly)
+        );
+        assert_eq!(ThinkingDelivery::parse_setting("sometimes"), None);
+    }
+}
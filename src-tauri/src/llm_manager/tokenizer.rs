@@ -0,0 +1,157 @@
+//! 统一 Token 计数
+//!
+//! 按模型族选择编码：GPT-4o/4.1/4.5/5 与 o 系列使用 o200k_base，其余 OpenAI 兼容模型使用 cl100k_base；
+//! Gemini 使用 SentencePiece 词表，没有可用的本地实现，这里用字符启发式近似（误差约数个百分点）。
+//! 未启用 `tokenizer_tiktoken` feature 时全部回退为启发式估算。
+//!
+//! `count_tokens` 预检命令与聊天历史的 token 预算裁剪共用本模块，保证两处计数一致。
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenEncoding {
+    O200kBase,
+    Cl100kBase,
+    /// Gemini 近似（字符启发式）
+    GeminiApprox,
+    /// 无 tokenizer 时的通用启发式
+    Heuristic,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenCount {
+    pub tokens: usize,
+    pub encoding: TokenEncoding,
+    /// 编码与模型官方 tokenizer 一致（仅 OpenAI 模型族为 true）
+    pub precise: bool,
+    /// 平均每个 token 对应的字符数，便于前端按字数粗估
+    pub chars_per_token: f64,
+}
+
+#[cfg(feature = "tokenizer_tiktoken")]
+mod bpe {
+    use std::sync::LazyLock;
+    use tiktoken_rs::{cl100k_base, o200k_base, CoreBPE};
+
+    // 构建编码器开销较大，进程内只初始化一次
+    pub(super) static CL100K: LazyLock<Option<CoreBPE>> = LazyLock::new(|| cl100k_base().ok());
+    pub(super) static O200K: LazyLock<Option<CoreBPE>> = LazyLock::new(|| o200k_base().ok());
+}
+
+/// 检查模型名称中是否存在以词边界开始的 o 系列前缀（o1/o3/o4），避免 "proto1-model" 等误匹配。
+fn has_o_series_prefix(m: &str) -> bool {
+    ["o1", "o3", "o4"].iter().any(|prefix| {
+        m.match_indices(prefix)
+            .any(|(pos, _)| pos == 0 || !m.as_bytes()[pos - 1].is_ascii_alphanumeric())
+    })
+}
+
+fn is_openai_family(m: &str) -> bool {
+    m.contains("gpt-") || m.contains("chatgpt") || has_o_series_prefix(m)
+}
+
+/// 根据模型名（或包含模型名的配置 ID）选择编码
+pub fn encoding_for_model(model: Option<&str>) -> TokenEncoding {
+    let m = model.unwrap_or_default().to_lowercase();
+    if m.contains("gemini") || m.contains("gemma") {
+        return TokenEncoding::GeminiApprox;
+    }
+    if !cfg!(feature = "tokenizer_tiktoken") {
+        return TokenEncoding::Heuristic;
+    }
+    if m.contains("o200k")
+        || m.contains("gpt-4o")
+        || m.contains("gpt-4.1")
+        || m.contains("gpt-4.5")
+        || m.contains("gpt-5")
+        || has_o_series_prefix(&m)
+    {
+        TokenEncoding::O200kBase
+    } else {
+        TokenEncoding::Cl100kBase
+    }
+}
+
+fn encode_len(text: &str, encoding: TokenEncoding) -> usize {
+    #[cfg(feature = "tokenizer_tiktoken")]
+    {
+        let bpe = match encoding {
+            TokenEncoding::O200kBase => bpe::O200K.as_ref().or(bpe::CL100K.as_ref()),
+            TokenEncoding::Cl100kBase => bpe::CL100K.as_ref(),
+            _ => None,
+        };
+        if let Some(bpe) = bpe {
+            return bpe.encode_with_special_tokens(text).len();
+        }
+    }
+    let _ = encoding;
+    crate::utils::token_budget::estimate_tokens(text)
+}
+
+/// 计算 token 数量并返回所用编码
+pub fn count_tokens(text: &str, model: Option<&str>) -> TokenCount {
+    let encoding = encoding_for_model(model);
+    let tokens = if text.is_empty() {
+        0
+    } else {
+        encode_len(text, encoding)
+    };
+    let chars = text.chars().count();
+    let precise = matches!(
+        encoding,
+        TokenEncoding::O200kBase | TokenEncoding::Cl100kBase
+    ) && model.is_some_and(|m| is_openai_family(&m.to_lowercase()));
+    TokenCount {
+        tokens,
+        encoding,
+        precise,
+        chars_per_token: if tokens == 0 {
+            0.0
+        } else {
+            chars as f64 / tokens as f64
+        },
+    }
+}
+
+/// 仅返回 token 数量
+pub fn token_len(text: &str, model: Option<&str>) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+    encode_len(text, encoding_for_model(model))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_selection_by_model_family() {
+        assert_eq!(
+            encoding_for_model(Some("gemini-2.5-pro")),
+            TokenEncoding::GeminiApprox
+        );
+        if cfg!(feature = "tokenizer_tiktoken") {
+            assert_eq!(encoding_for_model(Some("gpt-4o-mini")), TokenEncoding::O200kBase);
+            assert_eq!(encoding_for_model(Some("o3-mini")), TokenEncoding::O200kBase);
+            assert_eq!(encoding_for_model(Some("proto1-model")), TokenEncoding::Cl100kBase);
+            assert_eq!(encoding_for_model(Some("deepseek-chat")), TokenEncoding::Cl100kBase);
+            assert_eq!(encoding_for_model(None), TokenEncoding::Cl100kBase);
+        }
+    }
+
+    #[test]
+    fn test_count_tokens_reports_ratio() {
+        let empty = count_tokens("", Some("gpt-4o"));
+        assert_eq!(empty.tokens, 0);
+        assert_eq!(empty.chars_per_token, 0.0);
+
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let count = count_tokens(text, Some("gpt-4o"));
+        assert!(count.tokens > 0 && count.tokens < text.len());
+        assert!(count.chars_per_token > 1.0);
+        assert_eq!(count.tokens, token_len(text, Some("gpt-4o")));
+        assert!(!count_tokens(text, Some("deepseek-chat")).precise);
+    }
+}
@@ -0,0 +1,207 @@
+//! 按模型 / 日期 / API 配置汇总的 LLM 用量台账
+//!
+//! 用量记录在独立的 `llm_usage.db` 中（见 [`crate::llm_usage`]），这里按分组维度汇总
+//! 输入 / 输出 Token、请求次数与估算成本。入库时已写入成本的记录直接累加，其余记录按
+//! 设置项 [`LLM_MODEL_PRICING_KEY`] 中的模型单价（美元 / 百万 Token）估算；未设置单价的
+//! 模型成本记为 0。按配置分组时，标签取当前模型配置的名称。
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{LLMManager, Result};
+use crate::database::Database;
+use crate::llm_usage::repo::LlmUsageRepo;
+use crate::llm_usage::{
+    LlmUsageDatabase, UsageGroupBy, UsageLedgerEntry, UsageLedgerRow, UsageStats,
+};
+use crate::models::AppError;
+
+/// 模型单价设置项，值为 `{ "<model_id>": { "input_per_million": 2.5, "output_per_million": 10 } }`
+pub const LLM_MODEL_PRICING_KEY: &str = "llm_usage.model_pricing";
+
+/// 模型单价（美元 / 百万 Token）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    #[serde(default)]
+    pub input_per_million: f64,
+    #[serde(default)]
+    pub output_per_million: f64,
+}
+
+impl ModelPricing {
+    fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.input_per_million
+            + completion_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// 读取模型单价；设置项缺失或格式错误时视为未设置
+pub fn load_model_pricing(db: &Database) -> HashMap<String, ModelPricing> {
+    let Ok(Some(raw)) = db.get_setting(LLM_MODEL_PRICING_KEY) else {
+        return HashMap::new();
+    };
+    serde_json::from_str(&raw).unwrap_or_else(|e| {
+        log::warn!("[LLM Usage] 模型单价设置格式错误，忽略: {}", e);
+        HashMap::new()
+    })
+}
+
+/// 把按（分组键, 模型）聚合的行合并为分组，并估算成本
+fn build_usage_stats(
+    rows: Vec<UsageLedgerRow>,
+    group_by: UsageGroupBy,
+    pricing: &HashMap<String, ModelPricing>,
+    config_names: &HashMap<String, String>,
+) -> UsageStats {
+    let mut groups: Vec<UsageLedgerEntry> = Vec::new();
+    let mut total = UsageLedgerEntry {
+        key: "total".to_string(),
+        label: "total".to_string(),
+        ..Default::default()
+    };
+    for row in rows {
+        let cost = row.recorded_cost_usd
+            + pricing.get(&row.model_id).map_or(0.0, |price| {
+                price.cost(row.unpriced_prompt_tokens, row.unpriced_completion_tokens)
+            });
+        // 行已按分组键排序，同一分组的行相邻
+        if groups.last().map(|entry| &entry.key) != Some(&row.group_key) {
+            let label = match group_by {
+                UsageGroupBy::Config => config_names
+                    .get(&row.group_key)
+                    .cloned()
+                    .unwrap_or_else(|| row.group_key.clone()),
+                UsageGroupBy::Model | UsageGroupBy::Day => row.group_key.clone(),
+            };
+            groups.push(UsageLedgerEntry {
+                key: row.group_key.clone(),
+                label,
+                ..Default::default()
+            });
+        }
+        for entry in [groups.last_mut().expect("group just pushed"), &mut total] {
+            entry.request_count += row.request_count;
+            entry.prompt_tokens += row.prompt_tokens;
+            entry.completion_tokens += row.completion_tokens;
+            entry.estimated_cost_usd += cost;
+        }
+    }
+    UsageStats {
+        group_by,
+        groups,
+        total,
+    }
+}
+
+impl LLMManager {
+    /// 汇总时间范围内（起止均含）的 LLM 用量；没有记录时返回空分组与零合计
+    pub async fn get_llm_usage_stats(
+        &self,
+        usage_db: &LlmUsageDatabase,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        group_by: UsageGroupBy,
+    ) -> Result<UsageStats> {
+        if let (Some(start), Some(end)) = (start, end) {
+            if start > end {
+                return Err(AppError::validation("开始时间不能晚于结束时间"));
+            }
+        }
+        let rows = {
+            let conn = usage_db
+                .get_conn_safe()
+                .map_err(|e| AppError::database(e.to_string()))?;
+            LlmUsageRepo::get_usage_ledger_rows(
+                &conn,
+                start.map(|t| t.to_rfc3339()).as_deref(),
+                end.map(|t| t.to_rfc3339()).as_deref(),
+                group_by,
+            )
+            .map_err(|e| AppError::database(format!("查询 LLM 用量失败: {}", e)))?
+        };
+
+        let config_names = if group_by == UsageGroupBy::Config {
+            self.get_api_configs()
+                .await?
+                .into_iter()
+                .map(|config| (config.id, config.name))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        Ok(build_usage_stats(
+            rows,
+            group_by,
+            &load_model_pricing(&self.db),
+            &config_names,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(group_key: &str, model_id: &str, prompt: u64, completion: u64) -> UsageLedgerRow {
+        UsageLedgerRow {
+            group_key: group_key.to_string(),
+            model_id: model_id.to_string(),
+            request_count: 1,
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            recorded_cost_usd: 0.0,
+            unpriced_prompt_tokens: prompt,
+            unpriced_completion_tokens: completion,
+        }
+    }
+
+    #[test]
+    fn test_usage_stats_merge_models_and_price_by_model() {
+        let pricing = HashMap::from([(
+            "gpt-4o".to_string(),
+            ModelPricing {
+                input_per_million: 2.0,
+                output_per_million: 8.0,
+            },
+        )]);
+        let config_names = HashMap::from([("cfg-a".to_string(), "主力模型".to_string())]);
+        let rows = vec![
+            row("cfg-a", "gpt-4o", 1_000_000, 500_000),
+            UsageLedgerRow {
+                recorded_cost_usd: 0.25,
+                unpriced_prompt_tokens: 0,
+                unpriced_completion_tokens: 0,
+                ..row("cfg-a", "unpriced-model", 10, 5)
+            },
+            row("cfg-b", "unpriced-model", 7, 3),
+        ];
+
+        let stats = build_usage_stats(rows, UsageGroupBy::Config, &pricing, &config_names);
+        assert_eq!(stats.groups.len(), 2);
+        let first = &stats.groups[0];
+        assert_eq!(first.label, "主力模型");
+        assert_eq!(first.request_count, 2);
+        assert_eq!(first.prompt_tokens, 1_000_010);
+        assert!((first.estimated_cost_usd - 6.25).abs() < 1e-9);
+        assert_eq!(stats.groups[1].label, "cfg-b");
+        assert_eq!(stats.groups[1].estimated_cost_usd, 0.0);
+        assert_eq!(stats.total.request_count, 3);
+        assert_eq!(stats.total.completion_tokens, 500_008);
+    }
+
+    #[test]
+    fn test_usage_stats_without_rows_are_zero() {
+        let stats = build_usage_stats(
+            Vec::new(),
+            UsageGroupBy::Day,
+            &HashMap::new(),
+            &HashMap::new(),
+        );
+        assert!(stats.groups.is_empty());
+        assert_eq!(stats.total.request_count, 0);
+        assert_eq!(stats.total.estimated_cost_usd, 0.0);
+    }
+}
@@ -1,12 +1,14 @@
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
 use tauri::State;
 
 use super::database::LlmUsageDatabase;
 use super::repo::LlmUsageRepo;
 use super::types::{
-    CallerTypeSummary, DailySummary, ModelSummary, TimeGranularity, UsageRecord, UsageSummary,
-    UsageTrendPoint,
+    CallerTypeSummary, DailySummary, ModelSummary, TimeGranularity, UsageGroupBy, UsageRecord,
+    UsageStats, UsageSummary, UsageTrendPoint,
 };
+use crate::commands::AppState;
 
 #[tauri::command]
 pub async fn llm_usage_get_trends(
@@ -84,3 +86,19 @@ pub async fn llm_usage_cleanup(
     let conn = db.get_conn_safe().map_err(|e| e.to_string())?;
     LlmUsageRepo::delete_old_records(&conn, &before_date).map_err(|e| e.to_string())
 }
+
+/// 按模型 / 日期 / API 配置汇总时间范围内的 Token 用量、请求次数与估算成本
+#[tauri::command(rename_all = "camelCase")]
+pub async fn get_llm_usage_stats(
+    db: State<'_, Arc<LlmUsageDatabase>>,
+    state: State<'_, AppState>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    group_by: UsageGroupBy,
+) -> Result<UsageStats, String> {
+    state
+        .llm_manager
+        .get_llm_usage_stats(&db, start, end, group_by)
+        .await
+        .map_err(|e| e.message)
+}
@@ -25,6 +25,7 @@ struct PendingUsageRecord {
     reasoning_tokens: Option<u32>,
     cached_tokens: Option<u32>,
     session_id: Option<String>,
+    config_id: Option<String>,
     duration_ms: Option<u64>,
     success: bool,
     error_message: Option<String>,
@@ -73,7 +74,7 @@ fn flush_pending(collector: &Arc<UsageCollector>) -> usize {
             record.reasoning_tokens,
             record.cached_tokens,
             record.session_id.clone(),
-            None,
+            record.config_id.clone(),
             record.duration_ms,
             None,
             record.success,
@@ -88,6 +89,9 @@ fn flush_pending(collector: &Arc<UsageCollector>) -> usize {
 ///
 /// 此函数是 LLM 使用量记录的统一入口，所有 LLM 调用都应通过此函数记录使用量。
 /// 当 app_handle 或 UsageCollector 暂不可用时，先写入内存缓冲队列，并在后续可用时自动冲刷，避免静默丢失。
+/// `config_id` 为所用 API 配置 ID，供按配置汇总；取消的流式调用以 [`USAGE_CANCELLED_MESSAGE`]
+/// 作为错误信息记录已输出部分的用量。
+#[allow(clippy::too_many_arguments)]
 pub fn record_llm_usage(
     caller_type: CallerType,
     model_id: &str,
@@ -96,6 +100,7 @@ pub fn record_llm_usage(
     reasoning_tokens: Option<u32>,
     cached_tokens: Option<u32>,
     session_id: Option<String>,
+    config_id: Option<String>,
     duration_ms: Option<u64>,
     success: bool,
     error_message: Option<String>,
@@ -117,6 +122,7 @@ pub fn record_llm_usage(
         reasoning_tokens,
         cached_tokens,
         session_id,
+        config_id,
         duration_ms,
         success,
         error_message,
@@ -141,7 +147,7 @@ pub fn record_llm_usage(
                     record.reasoning_tokens,
                     record.cached_tokens,
                     record.session_id,
-                    None,
+                    record.config_id,
                     record.duration_ms,
                     None,
                     record.success,
@@ -195,6 +201,7 @@ mod tests {
                 reasoning_tokens: None,
                 cached_tokens: None,
                 session_id: None,
+                config_id: None,
                 duration_ms: None,
                 success: true,
                 error_message: None,
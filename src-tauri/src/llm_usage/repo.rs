@@ -4,8 +4,8 @@ use tracing::debug;
 
 use super::database::LlmUsageResult;
 use super::types::{
-    CallerType, CallerTypeSummary, DailySummary, ModelSummary, TimeGranularity, UsageRecord,
-    UsageSummary, UsageTrendPoint,
+    CallerType, CallerTypeSummary, DailySummary, ModelSummary, TimeGranularity, UsageGroupBy,
+    UsageLedgerRow, UsageRecord, UsageSummary, UsageTrendPoint, USAGE_CANCELLED_MESSAGE,
 };
 
 pub struct LlmUsageRepo;
@@ -14,7 +14,7 @@ impl LlmUsageRepo {
     pub fn insert_usage(conn: &Connection, record: &UsageRecord) -> LlmUsageResult<()> {
         debug!("[LlmUsageRepo] Inserting usage record: id={}", record.id);
 
-        let status = if record.success { "success" } else { "error" };
+        let status = Self::usage_status(record);
         let timestamp = record.created_at.to_rfc3339();
 
         // 从 model_id 推断 provider
@@ -58,6 +58,17 @@ impl LlmUsageRepo {
         Ok(())
     }
 
+    /// 记录状态：取消的流式调用单独记为 cancelled，不计入失败
+    fn usage_status(record: &UsageRecord) -> &'static str {
+        if record.success {
+            "success"
+        } else if record.error_message.as_deref() == Some(USAGE_CANCELLED_MESSAGE) {
+            "cancelled"
+        } else {
+            "error"
+        }
+    }
+
     /// 从 model_id 推断供应商名称
     fn infer_provider(model_id: &str) -> &'static str {
         let model_lower = model_id.to_lowercase();
@@ -115,7 +126,7 @@ impl LlmUsageRepo {
     }
 
     fn insert_usage_in_tx(tx: &Transaction, record: &UsageRecord) -> LlmUsageResult<()> {
-        let status = if record.success { "success" } else { "error" };
+        let status = Self::usage_status(record);
         let timestamp = record.created_at.to_rfc3339();
         let provider = Self::infer_provider(&record.model_id);
 
@@ -484,6 +495,62 @@ impl LlmUsageRepo {
 
         Ok(results)
     }
+
+    /// 按分组键与模型聚合时间范围内的用量（起止时间均含，RFC 3339）
+    ///
+    /// 同一分组内按模型拆开，调用方再按模型单价估算未写入成本的记录。
+    pub fn get_usage_ledger_rows(
+        conn: &Connection,
+        start: Option<&str>,
+        end: Option<&str>,
+        group_by: UsageGroupBy,
+    ) -> LlmUsageResult<Vec<UsageLedgerRow>> {
+        let group_expr = match group_by {
+            UsageGroupBy::Model => "model",
+            UsageGroupBy::Day => "date_key",
+            UsageGroupBy::Config => "COALESCE(api_config_id, '')",
+        };
+        let sql = format!(
+            r#"
+            SELECT
+                {} as group_key,
+                model,
+                COUNT(*) as call_count,
+                COALESCE(SUM(prompt_tokens), 0) as total_prompt_tokens,
+                COALESCE(SUM(completion_tokens), 0) as total_completion_tokens,
+                COALESCE(SUM(cost_estimate), 0) as recorded_cost,
+                COALESCE(SUM(CASE WHEN cost_estimate IS NULL THEN prompt_tokens ELSE 0 END), 0),
+                COALESCE(SUM(CASE WHEN cost_estimate IS NULL THEN completion_tokens ELSE 0 END), 0)
+            FROM llm_usage_logs
+            WHERE (?1 IS NULL OR julianday(timestamp) >= julianday(?1))
+              AND (?2 IS NULL OR julianday(timestamp) <= julianday(?2))
+            GROUP BY group_key, model
+            ORDER BY group_key ASC, model ASC
+            "#,
+            group_expr
+        );
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![start, end], |row| {
+            Ok(UsageLedgerRow {
+                group_key: row.get(0)?,
+                model_id: row.get(1)?,
+                request_count: row.get::<_, i64>(2)? as u64,
+                prompt_tokens: row.get::<_, i64>(3)? as u64,
+                completion_tokens: row.get::<_, i64>(4)? as u64,
+                recorded_cost_usd: row.get(5)?,
+                unpriced_prompt_tokens: row.get::<_, i64>(6)? as u64,
+                unpriced_completion_tokens: row.get::<_, i64>(7)? as u64,
+            })
+        })?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+
+        Ok(results)
+    }
 }
 
 #[cfg(test)]
@@ -526,4 +593,70 @@ mod tests {
         assert_eq!(summary.total_requests, 1);
         assert_eq!(summary.total_tokens, 150);
     }
+
+    #[test]
+    fn test_ledger_rows_by_config_and_range() {
+        let conn = setup_test_db();
+
+        let early = UsageRecord {
+            created_at: "2026-03-01T08:00:00Z".parse().unwrap(),
+            ..UsageRecord::new(CallerType::ChatV2, "gpt-4o".to_string(), 100, 50)
+                .with_config_id("cfg-a".to_string())
+        };
+        let late = UsageRecord {
+            created_at: "2026-03-02T08:00:00.123456789Z".parse().unwrap(),
+            ..UsageRecord::new(CallerType::ChatV2, "gpt-4o".to_string(), 10, 5)
+                .with_config_id("cfg-a".to_string())
+                .with_estimated_cost(0.5)
+        };
+        let cancelled = UsageRecord {
+            created_at: "2026-03-02T09:00:00Z".parse().unwrap(),
+            ..UsageRecord::new(CallerType::ChatV2, "deepseek-chat".to_string(), 20, 3)
+                .with_error(USAGE_CANCELLED_MESSAGE.to_string())
+        };
+        for record in [&early, &late, &cancelled] {
+            LlmUsageRepo::insert_usage(&conn, record).unwrap();
+        }
+
+        let status: String = conn
+            .query_row(
+                "SELECT status FROM llm_usage_logs WHERE id = ?1",
+                params![cancelled.id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(status, "cancelled");
+
+        let rows =
+            LlmUsageRepo::get_usage_ledger_rows(&conn, None, None, UsageGroupBy::Config).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].group_key, "");
+        assert_eq!(rows[0].completion_tokens, 3);
+        assert_eq!(rows[1].group_key, "cfg-a");
+        assert_eq!(rows[1].request_count, 2);
+        assert_eq!(rows[1].prompt_tokens, 110);
+        assert_eq!(rows[1].recorded_cost_usd, 0.5);
+        assert_eq!(rows[1].unpriced_prompt_tokens, 100);
+        assert_eq!(rows[1].unpriced_completion_tokens, 50);
+
+        let rows = LlmUsageRepo::get_usage_ledger_rows(
+            &conn,
+            Some("2026-03-02T00:00:00+00:00"),
+            Some("2026-03-02T08:00:00.5+00:00"),
+            UsageGroupBy::Day,
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].group_key, "2026-03-02");
+        assert_eq!(rows[0].prompt_tokens, 10);
+
+        let rows = LlmUsageRepo::get_usage_ledger_rows(
+            &conn,
+            Some("2027-01-01T00:00:00+00:00"),
+            None,
+            UsageGroupBy::Model,
+        )
+        .unwrap();
+        assert!(rows.is_empty());
+    }
 }
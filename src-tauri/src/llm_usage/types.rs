@@ -740,6 +740,68 @@ impl UsageQueryParams {
     }
 }
 
+// ============================================================================
+// 用量台账
+// ============================================================================
+
+/// 取消的流式调用写入的错误信息，入库时状态记为 `cancelled`（已输出部分照常计入用量）
+pub const USAGE_CANCELLED_MESSAGE: &str = "cancelled";
+
+/// 用量台账的分组维度
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGroupBy {
+    /// 按模型 ID
+    Model,
+    /// 按日期（YYYY-MM-DD，UTC）
+    Day,
+    /// 按 API 配置 ID
+    Config,
+}
+
+/// 台账查询的原始分组行（按分组键 + 模型聚合，供按模型单价估算成本）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageLedgerRow {
+    pub group_key: String,
+    pub model_id: String,
+    pub request_count: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// 入库时已写入的成本合计（美元）
+    pub recorded_cost_usd: f64,
+    /// 未写入成本的记录的输入 Token 数
+    pub unpriced_prompt_tokens: u64,
+    /// 未写入成本的记录的输出 Token 数
+    pub unpriced_completion_tokens: u64,
+}
+
+/// 用量台账的一个分组
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageLedgerEntry {
+    /// 分组键：模型 ID、日期或 API 配置 ID（未记录配置时为空字符串）
+    pub key: String,
+
+    /// 显示名称（按配置分组时为配置名称，其余与 key 相同）
+    pub label: String,
+
+    pub request_count: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+
+    /// 估算成本（美元）；未设置单价的模型不计入
+    pub estimated_cost_usd: f64,
+}
+
+/// 用量台账查询结果；时间范围内没有记录时分组为空、合计为零
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageStats {
+    pub group_by: UsageGroupBy,
+    pub groups: Vec<UsageLedgerEntry>,
+    pub total: UsageLedgerEntry,
+}
+
 // ============================================================================
 // 单元测试
 // ============================================================================
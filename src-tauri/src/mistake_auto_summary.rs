@@ -0,0 +1,784 @@
+//! 错题总结自动生成
+//!
+//! 错题对话进入稳定状态后自动生成 `mistake_summary` / `user_error_analysis`：
+//! - 轮次完成（或取消）后开始计时，`idle_secs` 内没有新轮次才生成；
+//! - 用户显式关闭会话时立即进入生成流程；
+//! - 题目与对话内容的 SHA-256 与上次总结相同则跳过；
+//! - 距上次总结不足 `min_interval_secs` 时推迟到间隔结束，避免频繁重新生成。
+//!
+//! 手动生成（`generate_mistake_summary`）复用同一流程，但不受开关与最小间隔限制。
+//! 配置保存在设置项 `mistake.auto_summary.config`（JSON）。
+
+use crate::database::Database;
+use crate::llm_manager::LLMManager;
+use crate::models::{AppError, ModelAssignments};
+use crate::vfs::database::VfsDatabase;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const AUTO_SUMMARY_SETTINGS_KEY: &str = "mistake.auto_summary.config";
+
+/// 送入模型的对话记录最大字符数（超出时保留最近的内容）
+const MAX_TRANSCRIPT_CHARS: usize = 12_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoSummarySettings {
+    pub enabled: bool,
+    /// 最后一轮对话后等待多久（秒）视为稳定
+    pub idle_secs: u64,
+    /// 同一错题两次自动总结的最小间隔（秒）
+    pub min_interval_secs: u64,
+    /// 总结模型配置 ID；为空时使用对话模型
+    pub summary_model_id: Option<String>,
+}
+
+impl Default for AutoSummarySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_secs: 120,
+            min_interval_secs: 600,
+            summary_model_id: None,
+        }
+    }
+}
+
+impl AutoSummarySettings {
+    pub fn validate(&self) -> Result<()> {
+        if !(10..=3600).contains(&self.idle_secs) {
+            return Err(AppError::validation("空闲等待时间必须在 10 到 3600 秒之间"));
+        }
+        if self.min_interval_secs > 86_400 {
+            return Err(AppError::validation("最小生成间隔不能超过 24 小时"));
+        }
+        Ok(())
+    }
+}
+
+/// 读取自动总结配置；未设置或解析失败时使用默认值（关闭）
+pub fn load_auto_summary_settings(db: &Database) -> AutoSummarySettings {
+    db.get_setting(AUTO_SUMMARY_SETTINGS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<AutoSummarySettings>(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_auto_summary_settings(db: &Database, settings: &AutoSummarySettings) -> Result<()> {
+    settings.validate()?;
+    let json = serde_json::to_string(settings)
+        .map_err(|e| AppError::internal(format!("序列化自动总结配置失败: {}", e)))?;
+    db.save_setting(AUTO_SUMMARY_SETTINGS_KEY, &json)
+        .map_err(|e| AppError::database(e.to_string()))
+}
+
+/// 生成总结所需的错题内容与上次总结状态
+#[derive(Debug, Clone)]
+struct SummarySource {
+    subject: Option<String>,
+    user_question: String,
+    ocr_text: String,
+    /// (role, content)，按时间顺序
+    turns: Vec<(String, String)>,
+    stored_hash: Option<String>,
+    last_summarized_at: Option<String>,
+    mistake_summary: Option<String>,
+    user_error_analysis: Option<String>,
+    /// 题目语言（OCR 识别或手动设置）；`mixed` 时不指定回答语言
+    language: Option<String>,
+}
+
+impl SummarySource {
+    fn content_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [&self.user_question, &self.ocr_text] {
+            hasher.update(part.as_bytes());
+            hasher.update([0u8]);
+        }
+        for (role, content) in &self.turns {
+            hasher.update(role.as_bytes());
+            hasher.update([0u8]);
+            hasher.update(content.as_bytes());
+            hasher.update([0u8]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn has_assistant_reply(&self) -> bool {
+        self.turns
+            .iter()
+            .any(|(role, content)| role == "assistant" && !content.trim().is_empty())
+    }
+}
+
+fn load_summary_source(conn: &Connection, mistake_id: &str) -> Result<SummarySource> {
+    let db_err = |e: rusqlite::Error| AppError::database(format!("读取错题内容失败: {}", e));
+    let mut source = conn
+        .query_row(
+            "SELECT COALESCE(user_question, ''), COALESCE(ocr_text, ''), summary_content_hash, \
+                    last_summarized_at, mistake_summary, user_error_analysis, subject, language \
+             FROM mistakes WHERE id = ?1",
+            params![mistake_id],
+            |row| {
+                Ok(SummarySource {
+                    subject: row.get(6)?,
+                    user_question: row.get(0)?,
+                    ocr_text: row.get(1)?,
+                    turns: Vec::new(),
+                    stored_hash: row.get(2)?,
+                    last_summarized_at: row.get(3)?,
+                    mistake_summary: row.get(4)?,
+                    user_error_analysis: row.get(5)?,
+                    language: row.get(7)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(db_err)?
+        .ok_or_else(|| AppError::not_found(format!("错题不存在: {}", mistake_id)))?;
+
+    // 同一提问有多个备选回答时只总结当前分支
+    source.turns = crate::cmd::mistake_branches::load_active_conversation(conn, mistake_id)?;
+    Ok(source)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SummaryDecision {
+    Generate,
+    /// 对话中还没有模型回复
+    SkipEmpty,
+    /// 内容与上次总结时相同
+    SkipUnchanged,
+    /// 距上次总结不足最小间隔，需再等待
+    Wait(Duration),
+}
+
+fn decide(
+    source: &SummarySource,
+    content_hash: &str,
+    min_interval: Duration,
+    force: bool,
+    now: DateTime<Utc>,
+) -> SummaryDecision {
+    if !source.has_assistant_reply() {
+        return SummaryDecision::SkipEmpty;
+    }
+    if force {
+        return SummaryDecision::Generate;
+    }
+    if source.stored_hash.as_deref() == Some(content_hash) {
+        return SummaryDecision::SkipUnchanged;
+    }
+    let elapsed = source
+        .last_summarized_at
+        .as_deref()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .and_then(|t| (now - t.with_timezone(&Utc)).to_std().ok());
+    match elapsed {
+        Some(elapsed) if elapsed < min_interval => SummaryDecision::Wait(min_interval - elapsed),
+        _ => SummaryDecision::Generate,
+    }
+}
+
+fn build_summary_prompt(source: &SummarySource) -> String {
+    let mut transcript = String::new();
+    for (role, content) in &source.turns {
+        let speaker = if role == "user" { "学生" } else { "老师" };
+        transcript.push_str(&format!("【{}】{}\n", speaker, content.trim()));
+    }
+    let chars = transcript.chars().count();
+    if chars > MAX_TRANSCRIPT_CHARS {
+        transcript = transcript
+            .chars()
+            .skip(chars - MAX_TRANSCRIPT_CHARS)
+            .collect();
+    }
+    let mut prompt = format!(
+        "下面是一道错题以及学生与老师围绕它的讨论。请据此写出：\n\
+         1. mistake_summary：题目考查的知识点与正确解题思路的简明总结；\n\
+         2. user_error_analysis：学生出错的原因分析与改进建议。\n\
+         只输出 JSON，格式如下：\n\
+         {{\"mistake_summary\": \"...\", \"user_error_analysis\": \"...\"}}\n\n\
+         【题目】\n{}\n{}\n\n【讨论记录】\n{}",
+        source.user_question.trim(),
+        source.ocr_text.trim(),
+        transcript
+    );
+    // 按题目语言撰写总结，混排题目不强制单一语言
+    if let Some(language) = source
+        .language
+        .as_deref()
+        .filter(|l| *l != crate::ocr_language::MIXED_LANGUAGE)
+    {
+        prompt.push_str("\n\n");
+        prompt.push_str(&crate::response_language::response_language_instruction(
+            language,
+        ));
+    }
+    prompt
+}
+
+#[derive(Debug, Deserialize)]
+struct SummaryResponse {
+    mistake_summary: Option<String>,
+    user_error_analysis: Option<String>,
+}
+
+fn parse_summary(response: &str) -> Option<(String, Option<String>)> {
+    let start = response.find('{')?;
+    let end = response.rfind('}')?;
+    if end < start {
+        return None;
+    }
+    let parsed: SummaryResponse = serde_json::from_str(&response[start..=end]).ok()?;
+    let summary = parsed
+        .mistake_summary
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())?;
+    let analysis = parsed
+        .user_error_analysis
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    Some((summary, analysis))
+}
+
+fn store_summary(
+    conn: &Connection,
+    mistake_id: &str,
+    summary: &str,
+    analysis: Option<&str>,
+    content_hash: &str,
+    now: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE mistakes SET mistake_summary = ?1, user_error_analysis = ?2, \
+                summary_content_hash = ?3, last_summarized_at = ?4, updated_at = ?4 \
+         WHERE id = ?5",
+        params![summary, analysis, content_hash, now, mistake_id],
+    )?;
+    Ok(())
+}
+
+/// 一次总结流程的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum SummaryOutcome {
+    Generated {
+        mistake_summary: String,
+        user_error_analysis: Option<String>,
+        /// 实际使用的模型配置 ID 与模型名称
+        model_config_id: String,
+        model_name: String,
+    },
+    /// 未生成；返回当前已有的总结
+    Skipped {
+        reason: &'static str,
+        mistake_summary: Option<String>,
+        user_error_analysis: Option<String>,
+    },
+    /// 需要在给定时间后重试
+    Deferred(Duration),
+}
+
+/// 选择分析模型：科目覆盖 → 总结配置中的模型 → 对话模型（返回 None 时由调用方回退）
+fn analysis_model_id<'a>(
+    assignments: &'a ModelAssignments,
+    subject: Option<&str>,
+    summary_model_id: Option<&'a str>,
+) -> Option<&'a str> {
+    assignments
+        .analysis_model_for_subject(subject)
+        .or(summary_model_id)
+}
+
+/// 执行一次总结流程
+///
+/// `min_interval` 为距上次总结的最小间隔（手动生成传 0）；`force` 为 true 时忽略内容哈希。
+/// 错题科目在模型分配中设置了分析模型覆盖时优先使用该模型。
+pub async fn summarize_mistake(
+    db: &Database,
+    llm_manager: &LLMManager,
+    summary_model_id: Option<&str>,
+    mistake_id: &str,
+    min_interval: Duration,
+    force: bool,
+) -> Result<SummaryOutcome> {
+    let source = {
+        let conn = db
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        load_summary_source(&conn, mistake_id)?
+    };
+    let content_hash = source.content_hash();
+    let skipped = |reason| SummaryOutcome::Skipped {
+        reason,
+        mistake_summary: source.mistake_summary.clone(),
+        user_error_analysis: source.user_error_analysis.clone(),
+    };
+    match decide(&source, &content_hash, min_interval, force, Utc::now()) {
+        SummaryDecision::SkipEmpty => return Ok(skipped("对话中还没有模型回复")),
+        SummaryDecision::SkipUnchanged => return Ok(skipped("内容自上次总结以来未变化")),
+        SummaryDecision::Wait(delay) => return Ok(SummaryOutcome::Deferred(delay)),
+        SummaryDecision::Generate => {}
+    }
+
+    let assignments = llm_manager.get_model_assignments().await?;
+    let config = llm_manager
+        .resolve_chat_model_config(analysis_model_id(
+            &assignments,
+            source.subject.as_deref(),
+            summary_model_id,
+        ))
+        .await?;
+    let (model_config_id, model_name) = (config.id.clone(), config.model.clone());
+    debug!(
+        "[AutoSummary] 错题 {} 使用模型 {} (subject={:?})",
+        mistake_id, model_name, source.subject
+    );
+    let output = llm_manager
+        .call_raw_prompt_with_config(config, &build_summary_prompt(&source), None)
+        .await?;
+    let (summary, analysis) = parse_summary(&output.assistant_message)
+        .ok_or_else(|| AppError::llm("无法解析模型返回的错题总结"))?;
+
+    let conn = db
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    store_summary(
+        &conn,
+        mistake_id,
+        &summary,
+        analysis.as_deref(),
+        &content_hash,
+        &Utc::now().to_rfc3339(),
+    )
+    .map_err(|e| AppError::database(format!("保存错题总结失败: {}", e)))?;
+    info!("[AutoSummary] 已生成错题总结: {}", mistake_id);
+    Ok(SummaryOutcome::Generated {
+        mistake_summary: summary,
+        user_error_analysis: analysis,
+        model_config_id,
+        model_name,
+    })
+}
+
+/// 错题总结状态，供界面提示总结是否过时
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MistakeSummaryStatus {
+    pub has_summary: bool,
+    pub last_summarized_at: Option<String>,
+    /// 题目或对话在上次总结后有变化（从未总结且已有对话时也为 true）
+    pub is_stale: bool,
+}
+
+pub fn get_summary_status(db: &Database, mistake_id: &str) -> Result<MistakeSummaryStatus> {
+    let conn = db
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    let source = load_summary_source(&conn, mistake_id)?;
+    Ok(summary_status(&source))
+}
+
+fn summary_status(source: &SummarySource) -> MistakeSummaryStatus {
+    let has_summary = source
+        .mistake_summary
+        .as_deref()
+        .is_some_and(|s| !s.trim().is_empty());
+    let is_stale = source.has_assistant_reply()
+        && source.stored_hash.as_deref() != Some(source.content_hash().as_str());
+    MistakeSummaryStatus {
+        has_summary,
+        last_summarized_at: source.last_summarized_at.clone(),
+        is_stale,
+    }
+}
+
+/// 批量生成总结的候选错题
+#[derive(Debug, Default, PartialEq)]
+pub struct BatchSummaryCandidates {
+    /// 缺少总结或总结已过时
+    pub pending: Vec<String>,
+    /// 对话中还没有模型回复
+    pub without_chat: Vec<String>,
+    /// 总结已是最新
+    pub up_to_date: usize,
+}
+
+/// 按科目筛选需要（重新）生成总结的错题（已删除的错题除外）
+pub fn batch_summary_candidates(
+    db: &Database,
+    subject: Option<&str>,
+) -> Result<BatchSummaryCandidates> {
+    let conn = db
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    collect_batch_candidates(&conn, subject)
+}
+
+fn collect_batch_candidates(
+    conn: &Connection,
+    subject: Option<&str>,
+) -> Result<BatchSummaryCandidates> {
+    let db_err = |e: rusqlite::Error| AppError::database(format!("筛选待总结错题失败: {}", e));
+    let (where_sql, values) =
+        crate::cmd::mistake_tags::mistake_filter(conn, subject).map_err(db_err)?;
+    let ids = conn
+        .prepare(&format!(
+            "SELECT id FROM mistakes {} ORDER BY mistakes.id",
+            where_sql
+        ))
+        .and_then(|mut stmt| {
+            stmt.query_map(rusqlite::params_from_iter(values), |row| {
+                row.get::<_, String>(0)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .map_err(db_err)?;
+
+    let mut candidates = BatchSummaryCandidates::default();
+    for id in ids {
+        let source = load_summary_source(conn, &id)?;
+        let status = summary_status(&source);
+        if !source.has_assistant_reply() {
+            candidates.without_chat.push(id);
+        } else if status.has_summary && !status.is_stale {
+            candidates.up_to_date += 1;
+        } else {
+            candidates.pending.push(id);
+        }
+    }
+    Ok(candidates)
+}
+
+/// 自动总结调度：每个错题只保留最新一次触发，新的触发使旧的等待失效
+#[derive(Default)]
+pub struct AutoSummaryScheduler {
+    generations: Mutex<HashMap<String, u64>>,
+}
+
+/// 全局调度器（按 mistake_id 区分会话）
+pub static AUTO_SUMMARY: LazyLock<AutoSummaryScheduler> =
+    LazyLock::new(AutoSummaryScheduler::default);
+
+impl AutoSummaryScheduler {
+    fn generations(&self) -> std::sync::MutexGuard<'_, HashMap<String, u64>> {
+        self.generations.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 登记一次新触发，返回其代号
+    fn bump(&self, mistake_id: &str) -> u64 {
+        let mut generations = self.generations();
+        let generation = generations.entry(mistake_id.to_string()).or_insert(0);
+        *generation += 1;
+        *generation
+    }
+
+    fn is_current(&self, mistake_id: &str, generation: u64) -> bool {
+        self.generations().get(mistake_id) == Some(&generation)
+    }
+
+    /// 流程结束后清理（期间若有新触发则保留）
+    fn finish(&self, mistake_id: &str, generation: u64) {
+        let mut generations = self.generations();
+        if generations.get(mistake_id) == Some(&generation) {
+            generations.remove(mistake_id);
+        }
+    }
+
+    /// 对话轮次完成时调用：等待 `idle_secs` 无新轮次后生成
+    pub fn on_turn_completed(
+        &'static self,
+        db: Arc<Database>,
+        vfs_db: Option<Arc<VfsDatabase>>,
+        llm: Arc<LLMManager>,
+        mistake_id: &str,
+    ) {
+        let settings = load_auto_summary_settings(&db);
+        if settings.enabled {
+            let delay = Duration::from_secs(settings.idle_secs);
+            self.schedule(db, vfs_db, llm, mistake_id, delay);
+        }
+    }
+
+    /// 会话显式关闭时调用：立即进入生成流程（仍受内容哈希与最小间隔约束）
+    pub fn on_session_closed(
+        &'static self,
+        db: Arc<Database>,
+        vfs_db: Option<Arc<VfsDatabase>>,
+        llm: Arc<LLMManager>,
+        mistake_id: &str,
+    ) {
+        if load_auto_summary_settings(&db).enabled {
+            self.schedule(db, vfs_db, llm, mistake_id, Duration::ZERO);
+        }
+    }
+
+    fn schedule(
+        &'static self,
+        db: Arc<Database>,
+        vfs_db: Option<Arc<VfsDatabase>>,
+        llm: Arc<LLMManager>,
+        mistake_id: &str,
+        delay: Duration,
+    ) {
+        let generation = self.bump(mistake_id);
+        let mistake_id = mistake_id.to_string();
+        tauri::async_runtime::spawn(async move {
+            let mut delay = delay;
+            loop {
+                tokio::time::sleep(delay).await;
+                if !self.is_current(&mistake_id, generation) {
+                    debug!("[AutoSummary] {} 有更新的触发，放弃本次", mistake_id);
+                    return;
+                }
+                // 每次执行前重新读取配置，等待期间关闭开关即取消
+                let settings = load_auto_summary_settings(&db);
+                if !settings.enabled {
+                    break;
+                }
+                let outcome = summarize_mistake(
+                    &db,
+                    &llm,
+                    settings.summary_model_id.as_deref(),
+                    &mistake_id,
+                    Duration::from_secs(settings.min_interval_secs),
+                    false,
+                )
+                .await;
+                match outcome {
+                    Ok(SummaryOutcome::Deferred(wait)) => delay = wait,
+                    Ok(SummaryOutcome::Generated { .. }) => {
+                        // 总结写入了错题文本，同步到知识库
+                        crate::cmd::mistake_kb_sync::spawn_sync_mistakes(
+                            db.clone(),
+                            vfs_db.clone(),
+                            llm.clone(),
+                            vec![mistake_id.clone()],
+                        );
+                        break;
+                    }
+                    Ok(_) => break,
+                    Err(e) => {
+                        warn!("[AutoSummary] 自动生成错题总结失败 {}: {}", mistake_id, e);
+                        break;
+                    }
+                }
+            }
+            self.finish(&mistake_id, generation);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn setup_conn() -> Connection {
+        let conn = migrated_conn().unwrap();
+        insert_mistake(
+            &conn,
+            "m1",
+            &[
+                ("subject", &"数学"),
+                ("user_question", &"求导"),
+                ("ocr_text", &"y = x^2"),
+            ],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "INSERT INTO chat_messages (mistake_id, role, content, timestamp) VALUES
+                ('m1', 'user', '为什么是 2x？', '2026-01-01T00:00:01Z'),
+                ('m1', 'tool', '忽略', '2026-01-01T00:00:02Z'),
+                ('m1', 'assistant', '幂函数求导法则', '2026-01-01T00:00:03Z');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-01-01T01:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_settings_defaults_and_validation() {
+        let parsed: AutoSummarySettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(parsed, AutoSummarySettings::default());
+        assert!(!parsed.enabled);
+        assert!(parsed.validate().is_ok());
+        let too_short = AutoSummarySettings {
+            idle_secs: 1,
+            ..Default::default()
+        };
+        assert!(too_short.validate().is_err());
+    }
+
+    #[test]
+    fn test_decision_skips_unchanged_and_defers_within_interval() {
+        let conn = setup_conn();
+        let interval = Duration::from_secs(600);
+        let mut source = load_summary_source(&conn, "m1").unwrap();
+        assert_eq!(source.turns.len(), 2);
+        let hash = source.content_hash();
+        assert_eq!(
+            decide(&source, &hash, interval, false, now()),
+            SummaryDecision::Generate
+        );
+
+        store_summary(&conn, "m1", "总结", None, &hash, "2026-01-01T00:55:00Z").unwrap();
+        source = load_summary_source(&conn, "m1").unwrap();
+        assert_eq!(
+            decide(&source, &hash, interval, false, now()),
+            SummaryDecision::SkipUnchanged
+        );
+        assert_eq!(
+            decide(&source, &hash, interval, true, now()),
+            SummaryDecision::Generate
+        );
+        assert!(!summary_status(&source).is_stale);
+
+        conn.execute(
+            "INSERT INTO chat_messages (mistake_id, role, content, timestamp) \
+             VALUES ('m1', 'user', '那 x^3 呢？', '2026-01-01T00:58:00Z')",
+            [],
+        )
+        .unwrap();
+        source = load_summary_source(&conn, "m1").unwrap();
+        let new_hash = source.content_hash();
+        assert_ne!(new_hash, hash);
+        assert!(summary_status(&source).is_stale);
+        assert_eq!(
+            decide(&source, &new_hash, interval, false, now()),
+            SummaryDecision::Wait(Duration::from_secs(300))
+        );
+    }
+
+    #[test]
+    fn test_decision_requires_assistant_reply() {
+        let conn = setup_conn();
+        conn.execute("DELETE FROM chat_messages WHERE role = 'assistant'", [])
+            .unwrap();
+        let source = load_summary_source(&conn, "m1").unwrap();
+        let hash = source.content_hash();
+        assert_eq!(
+            decide(&source, &hash, Duration::ZERO, true, now()),
+            SummaryDecision::SkipEmpty
+        );
+        assert!(!summary_status(&source).is_stale);
+    }
+
+    #[test]
+    fn test_batch_candidates_split_by_summary_state() {
+        let conn = setup_conn();
+        for (id, subject, question) in [
+            ("m2", "数学", "积分"),
+            ("m3", "数学", "极限"),
+            ("m4", "物理", "受力"),
+        ] {
+            insert_mistake(
+                &conn,
+                id,
+                &[("subject", &subject), ("user_question", &question)],
+            )
+            .unwrap();
+        }
+        conn.execute_batch(
+            "INSERT INTO chat_messages (mistake_id, role, content, timestamp) VALUES
+                ('m2', 'user', '怎么算？', '2026-01-01T00:00:01Z'),
+                ('m3', 'user', '怎么算？', '2026-01-01T00:00:01Z'),
+                ('m3', 'assistant', '洛必达法则', '2026-01-01T00:00:02Z'),
+                ('m4', 'user', '怎么画？', '2026-01-01T00:00:01Z'),
+                ('m4', 'assistant', '先画重力', '2026-01-01T00:00:02Z');",
+        )
+        .unwrap();
+        let hash = load_summary_source(&conn, "m3").unwrap().content_hash();
+        store_summary(&conn, "m3", "总结", None, &hash, "2026-01-01T00:10:00Z").unwrap();
+
+        let candidates = collect_batch_candidates(&conn, Some("数学")).unwrap();
+        assert_eq!(candidates.pending, vec!["m1".to_string()]);
+        assert_eq!(candidates.without_chat, vec!["m2".to_string()]);
+        assert_eq!(candidates.up_to_date, 1);
+
+        // 总结后对话有新内容，视为过时
+        conn.execute(
+            "INSERT INTO chat_messages (mistake_id, role, content, timestamp) \
+             VALUES ('m3', 'user', '还有别的方法吗？', '2026-01-01T00:20:00Z')",
+            [],
+        )
+        .unwrap();
+        let candidates = collect_batch_candidates(&conn, None).unwrap();
+        assert_eq!(candidates.pending, vec!["m1", "m3", "m4"]);
+        assert_eq!(candidates.up_to_date, 0);
+    }
+
+    #[test]
+    fn test_parse_summary_and_scheduler_generations() {
+        let parsed = parse_summary(
+            "```json\n{\"mistake_summary\": \" 幂函数求导 \", \"user_error_analysis\": \"\"}\n```",
+        );
+        assert_eq!(parsed, Some(("幂函数求导".to_string(), None)));
+        assert!(parse_summary("{\"user_error_analysis\": \"x\"}").is_none());
+
+        let scheduler = AutoSummaryScheduler::default();
+        let first = scheduler.bump("m1");
+        let second = scheduler.bump("m1");
+        assert!(!scheduler.is_current("m1", first));
+        assert!(scheduler.is_current("m1", second));
+        scheduler.finish("m1", first);
+        assert!(scheduler.is_current("m1", second));
+        scheduler.finish("m1", second);
+        assert!(scheduler.generations().is_empty());
+    }
+
+    #[test]
+    fn test_analysis_model_prefers_subject_override() {
+        let mut assignments: ModelAssignments =
+            serde_json::from_str(r#"{"model2_config_id": "cfg-default"}"#).unwrap();
+        assert!(assignments.subject_model_overrides.is_empty());
+        assert_eq!(analysis_model_id(&assignments, Some("物理"), None), None);
+        assert_eq!(
+            analysis_model_id(&assignments, Some("物理"), Some("cfg-summary")),
+            Some("cfg-summary")
+        );
+
+        assignments.subject_model_overrides.insert(
+            " 物理 ".to_string(),
+            crate::models::SubjectModelOverride {
+                analysis_model_config_id: Some("cfg-strong".to_string()),
+                ocr_model_config_id: Some("  ".to_string()),
+            },
+        );
+        assignments
+            .subject_model_overrides
+            .insert("语文".to_string(), Default::default());
+        assignments.normalize_subject_overrides();
+        assert_eq!(assignments.subject_model_overrides.len(), 1);
+        assert_eq!(
+            analysis_model_id(&assignments, Some("物理 "), Some("cfg-summary")),
+            Some("cfg-strong")
+        );
+        assert_eq!(assignments.ocr_model_for_subject(Some("物理")), None);
+        assert_eq!(analysis_model_id(&assignments, Some("语文"), None), None);
+        assert_eq!(analysis_model_id(&assignments, None, None), None);
+
+        let conn = setup_conn();
+        let source = load_summary_source(&conn, "m1").unwrap();
+        assert_eq!(source.subject.as_deref(), Some("数学"));
+
+        let conn = migrated_conn().unwrap();
+        insert_mistake(&conn, "m1", &[("user_question", &"求导")]).unwrap();
+        let source = load_summary_source(&conn, "m1").unwrap();
+        assert_eq!(source.subject, None);
+    }
+}
@@ -0,0 +1,583 @@
+//! 错题自动打标签（模型分类）
+//!
+//! 开启设置项 `mistake.auto_tagging.config` 后，错题保存（导入、分析打包保存，或前端编辑后
+//! 调用 `auto_tag_mistake`）时请分配的分析模型从该科目已有的标签体系中挑选标签：
+//! - 默认只接受科目内已有的标签，`allow_new_tags` 为 true 时才接受模型提出的新标签；
+//! - 只应用置信度不低于 `confidence_threshold` 的前 `max_tags` 个标签；
+//! - 用户手动填写的标签不会被改动或移除，重新分类只替换上次由模型分配的标签；
+//! - 模型分配的标签记录在错题 `overrides.ai_tags` 中，以便与手动标签区分。
+
+use crate::cmd::mistake_tags::tag_frequencies;
+use crate::database::has_column;
+use crate::database::Database;
+use crate::llm_manager::LLMManager;
+use crate::models::AppError;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const AUTO_TAGGING_SETTINGS_KEY: &str = "mistake.auto_tagging.config";
+
+/// `overrides` 中记录模型分配标签的键
+const AI_TAGS_OVERRIDE_KEY: &str = "ai_tags";
+/// 送入模型的标签体系上限（按使用频率取前若干个）
+const MAX_TAXONOMY_TAGS: usize = 200;
+/// 送入模型的题目最大字符数
+const MAX_QUESTION_CHARS: usize = 2_000;
+/// 模型提出的新标签最大字符数
+const MAX_NEW_TAG_CHARS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoTaggingSettings {
+    pub enabled: bool,
+    /// 应用标签所需的最低置信度（0~1）
+    pub confidence_threshold: f32,
+    /// 每道错题最多应用的标签数
+    pub max_tags: usize,
+    /// 是否接受科目标签体系之外的新标签
+    pub allow_new_tags: bool,
+    /// 分类模型配置 ID；为空时使用对话模型（科目设置了分析模型覆盖时优先使用覆盖）
+    pub model_id: Option<String>,
+}
+
+impl Default for AutoTaggingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            confidence_threshold: 0.7,
+            max_tags: 3,
+            allow_new_tags: false,
+            model_id: None,
+        }
+    }
+}
+
+impl AutoTaggingSettings {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.confidence_threshold) {
+            return Err(AppError::validation("置信度阈值必须在 0 到 1 之间"));
+        }
+        if !(1..=10).contains(&self.max_tags) {
+            return Err(AppError::validation("每道错题的标签数必须在 1 到 10 之间"));
+        }
+        Ok(())
+    }
+}
+
+/// 读取自动打标签配置；未设置或解析失败时使用默认值（关闭）
+pub fn load_auto_tagging_settings(db: &Database) -> AutoTaggingSettings {
+    db.get_setting(AUTO_TAGGING_SETTINGS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<AutoTaggingSettings>(&raw).ok())
+        .filter(|settings| settings.validate().is_ok())
+        .unwrap_or_default()
+}
+
+pub fn save_auto_tagging_settings(db: &Database, settings: &AutoTaggingSettings) -> Result<()> {
+    settings.validate()?;
+    let json = serde_json::to_string(settings)
+        .map_err(|e| AppError::internal(format!("序列化自动打标签配置失败: {}", e)))?;
+    db.save_setting(AUTO_TAGGING_SETTINGS_KEY, &json)
+        .map_err(|e| AppError::database(e.to_string()))
+}
+
+/// 上次由模型分配的标签
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiTagsOverride {
+    pub tags: Vec<String>,
+    /// 标签 → 置信度
+    pub confidences: BTreeMap<String, f32>,
+    pub model_config_id: String,
+    pub assigned_at: String,
+}
+
+/// 分类所需的错题内容
+#[derive(Debug, Clone)]
+struct TaggingSource {
+    subject: Option<String>,
+    user_question: String,
+    ocr_text: String,
+    /// 读取时的 tags 原文，写回时用于检测并发修改
+    tags_json: String,
+    tags: Vec<String>,
+    overrides: serde_json::Map<String, serde_json::Value>,
+}
+
+impl TaggingSource {
+    fn question_text(&self) -> String {
+        format!("{}\n{}", self.user_question.trim(), self.ocr_text.trim())
+            .trim()
+            .chars()
+            .take(MAX_QUESTION_CHARS)
+            .collect()
+    }
+
+    fn previous_ai_tags(&self) -> Vec<String> {
+        self.overrides
+            .get(AI_TAGS_OVERRIDE_KEY)
+            .and_then(|v| serde_json::from_value::<AiTagsOverride>(v.clone()).ok())
+            .map(|o| o.tags)
+            .unwrap_or_default()
+    }
+}
+
+fn db_err(e: rusqlite::Error) -> AppError {
+    AppError::database(format!("读取错题标签失败: {}", e))
+}
+
+fn load_tagging_source(conn: &Connection, mistake_id: &str) -> Result<TaggingSource> {
+    if !has_column(conn, "mistakes", "overrides").map_err(db_err)? {
+        return Err(AppError::database(
+            "错题表缺少 overrides 列，请先完成数据库迁移",
+        ));
+    }
+    let subject_sql = if has_column(conn, "mistakes", "subject").map_err(db_err)? {
+        "subject"
+    } else {
+        "NULL"
+    };
+    let (subject, user_question, ocr_text, tags_json, overrides_json) = conn
+        .query_row(
+            &format!(
+                "SELECT {}, COALESCE(user_question, ''), COALESCE(ocr_text, ''), \
+                        COALESCE(tags, '[]'), overrides \
+                 FROM mistakes WHERE id = ?1",
+                subject_sql
+            ),
+            params![mistake_id],
+            |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(db_err)?
+        .ok_or_else(|| AppError::not_found(format!("错题不存在: {}", mistake_id)))?;
+
+    let tags = serde_json::from_str::<Vec<String>>(&tags_json)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    let overrides = overrides_json
+        .and_then(|raw| serde_json::from_str::<serde_json::Value>(&raw).ok())
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_default();
+    Ok(TaggingSource {
+        subject: subject
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty()),
+        user_question,
+        ocr_text,
+        tags_json,
+        tags,
+        overrides,
+    })
+}
+
+fn build_tagging_prompt(
+    question: &str,
+    taxonomy: &[String],
+    max_tags: usize,
+    allow_new: bool,
+) -> String {
+    let taxonomy_text = if taxonomy.is_empty() {
+        "（暂无）".to_string()
+    } else {
+        taxonomy.join("、")
+    };
+    let rule = if allow_new {
+        format!(
+            "优先从已有标签中选择；只有已有标签都不合适时才可以给出新标签（每个不超过 {} 个字）。",
+            MAX_NEW_TAG_CHARS
+        )
+    } else {
+        "只能从已有标签中选择，不要创造新标签；没有合适的标签时输出空数组。".to_string()
+    };
+    format!(
+        "请为下面这道错题选择最多 {} 个知识点标签，并给出每个标签的置信度（0~1）。\n\
+         {}\n\
+         已有标签：{}\n\
+         只输出 JSON 数组，例如 [{{\"tag\": \"受力分析\", \"confidence\": 0.9}}]。\n\n\
+         【题目】\n{}",
+        max_tags, rule, taxonomy_text, question
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct TagCandidate {
+    tag: String,
+    #[serde(default)]
+    confidence: f32,
+}
+
+/// 从模型输出中提取 (标签, 置信度) 列表
+fn parse_tag_candidates(response: &str) -> Option<Vec<(String, f32)>> {
+    let start = response.find('[')?;
+    let end = response.rfind(']')?;
+    if end < start {
+        return None;
+    }
+    let candidates: Vec<TagCandidate> = serde_json::from_str(&response[start..=end]).ok()?;
+    Some(
+        candidates
+            .into_iter()
+            .map(|c| (c.tag.trim().to_string(), c.confidence.clamp(0.0, 1.0)))
+            .filter(|(tag, _)| !tag.is_empty())
+            .collect(),
+    )
+}
+
+/// 按阈值与标签体系筛选候选标签，返回按置信度降序的前 `max_tags` 个
+///
+/// 体系内的标签统一为体系中的写法（不区分大小写）；体系外的标签仅在允许新标签时保留。
+fn select_tags(
+    candidates: &[(String, f32)],
+    taxonomy: &[String],
+    settings: &AutoTaggingSettings,
+) -> Vec<(String, f32)> {
+    let mut selected: Vec<(String, f32)> = Vec::new();
+    for (tag, confidence) in candidates {
+        if *confidence < settings.confidence_threshold {
+            continue;
+        }
+        let canonical = match taxonomy
+            .iter()
+            .find(|t| t.to_lowercase() == tag.to_lowercase())
+        {
+            Some(known) => known.clone(),
+            None if settings.allow_new_tags && tag.chars().count() <= MAX_NEW_TAG_CHARS => {
+                tag.clone()
+            }
+            None => continue,
+        };
+        match selected.iter_mut().find(|(t, _)| *t == canonical) {
+            Some(existing) => existing.1 = existing.1.max(*confidence),
+            None => selected.push((canonical, *confidence)),
+        }
+    }
+    selected.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    selected.truncate(settings.max_tags);
+    selected
+}
+
+/// 合并标签：保留手动标签，替换上次由模型分配的标签
+///
+/// 返回 (新的标签列表, 本次实际由模型添加的标签)。与手动标签重复的模型标签不计为 AI 分配。
+fn merge_tags(
+    current: &[String],
+    previous_ai: &[String],
+    selected: &[(String, f32)],
+) -> (Vec<String>, Vec<String>) {
+    let previous: HashSet<String> = previous_ai.iter().map(|t| t.to_lowercase()).collect();
+    let mut tags: Vec<String> = current
+        .iter()
+        .filter(|t| !previous.contains(&t.to_lowercase()))
+        .cloned()
+        .collect();
+    let mut added = Vec::new();
+    for (tag, _) in selected {
+        if tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase()) {
+            continue;
+        }
+        tags.push(tag.clone());
+        added.push(tag.clone());
+    }
+    (tags, added)
+}
+
+/// 写回标签与 `overrides`；读取后标签被其他操作修改过时放弃写入并返回 false
+fn store_tags(
+    conn: &Connection,
+    mistake_id: &str,
+    expected_tags_json: &str,
+    tags: &[String],
+    overrides: &serde_json::Map<String, serde_json::Value>,
+    now: &str,
+) -> Result<bool> {
+    let tags_json = serde_json::to_string(tags)
+        .map_err(|e| AppError::internal(format!("序列化标签失败: {}", e)))?;
+    let overrides_json = if overrides.is_empty() {
+        None
+    } else {
+        Some(
+            serde_json::to_string(overrides)
+                .map_err(|e| AppError::internal(format!("序列化错题覆盖信息失败: {}", e)))?,
+        )
+    };
+    let updated = conn
+        .execute(
+            "UPDATE mistakes SET tags = ?1, overrides = ?2, updated_at = ?3 \
+             WHERE id = ?4 AND COALESCE(tags, '[]') = ?5",
+            params![
+                tags_json,
+                overrides_json,
+                now,
+                mistake_id,
+                expected_tags_json
+            ],
+        )
+        .map_err(|e| AppError::database(format!("保存错题标签失败: {}", e)))?;
+    Ok(updated > 0)
+}
+
+/// 一次自动打标签的结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoTagOutcome {
+    /// 错题当前的全部标签
+    pub tags: Vec<String>,
+    /// 由模型分配的标签（记录在 `overrides.ai_tags` 中）
+    pub ai_tags: Vec<String>,
+    /// 未执行分类或未写入时的原因
+    pub skipped_reason: Option<String>,
+}
+
+/// 对单道错题执行一次模型分类并应用标签
+///
+/// 是否开启由调用方判断；手动触发时同样遵循阈值与标签体系限制。
+pub async fn auto_tag_mistake(
+    db: &Database,
+    llm_manager: &LLMManager,
+    settings: &AutoTaggingSettings,
+    mistake_id: &str,
+) -> Result<AutoTagOutcome> {
+    let (source, taxonomy) = {
+        let conn = db
+            .get_conn_safe()
+            .map_err(|e| AppError::database(e.to_string()))?;
+        let source = load_tagging_source(&conn, mistake_id)?;
+        let taxonomy: Vec<String> = tag_frequencies(&conn, source.subject.as_deref())
+            .map_err(db_err)?
+            .into_iter()
+            .take(MAX_TAXONOMY_TAGS)
+            .map(|(tag, _)| tag)
+            .collect();
+        (source, taxonomy)
+    };
+    let skipped = |reason: &str| AutoTagOutcome {
+        tags: source.tags.clone(),
+        ai_tags: source.previous_ai_tags(),
+        skipped_reason: Some(reason.to_string()),
+    };
+    let question = source.question_text();
+    if question.is_empty() {
+        return Ok(skipped("题目内容为空"));
+    }
+    if taxonomy.is_empty() && !settings.allow_new_tags {
+        return Ok(skipped("该科目还没有可选的标签"));
+    }
+
+    let assignments = llm_manager.get_model_assignments().await?;
+    let config = llm_manager
+        .resolve_chat_model_config(
+            assignments
+                .analysis_model_for_subject(source.subject.as_deref())
+                .or(settings.model_id.as_deref()),
+        )
+        .await?;
+    let model_config_id = config.id.clone();
+    debug!(
+        "[AutoTagging] 错题 {} 使用模型 {} (subject={:?}, taxonomy={})",
+        mistake_id,
+        config.model,
+        source.subject,
+        taxonomy.len()
+    );
+    let prompt = build_tagging_prompt(
+        &question,
+        &taxonomy,
+        settings.max_tags,
+        settings.allow_new_tags,
+    );
+    let output = llm_manager
+        .call_raw_prompt_with_config(config, &prompt, None)
+        .await?;
+    let candidates = parse_tag_candidates(&output.assistant_message)
+        .ok_or_else(|| AppError::llm("无法解析模型返回的标签分类结果"))?;
+    let selected = select_tags(&candidates, &taxonomy, settings);
+
+    let (tags, ai_tags) = merge_tags(&source.tags, &source.previous_ai_tags(), &selected);
+    let now = Utc::now().to_rfc3339();
+    let mut overrides = source.overrides.clone();
+    if ai_tags.is_empty() {
+        overrides.remove(AI_TAGS_OVERRIDE_KEY);
+    } else {
+        let record = AiTagsOverride {
+            confidences: selected
+                .iter()
+                .filter(|(tag, _)| ai_tags.contains(tag))
+                .cloned()
+                .collect(),
+            tags: ai_tags.clone(),
+            model_config_id,
+            assigned_at: now.clone(),
+        };
+        overrides.insert(
+            AI_TAGS_OVERRIDE_KEY.to_string(),
+            serde_json::to_value(record)
+                .map_err(|e| AppError::internal(format!("序列化 AI 标签记录失败: {}", e)))?,
+        );
+    }
+
+    let conn = db
+        .get_conn_safe()
+        .map_err(|e| AppError::database(e.to_string()))?;
+    if !store_tags(
+        &conn,
+        mistake_id,
+        &source.tags_json,
+        &tags,
+        &overrides,
+        &now,
+    )? {
+        return Ok(skipped("分类期间标签已被修改，未写入"));
+    }
+    info!(
+        "[AutoTagging] 错题 {} 自动分配标签: {:?}",
+        mistake_id, ai_tags
+    );
+    Ok(AutoTagOutcome {
+        tags,
+        ai_tags,
+        skipped_reason: None,
+    })
+}
+
+/// 开启自动打标签时在后台为新保存的错题分类
+pub fn spawn_auto_tag_mistakes(
+    db: Arc<Database>,
+    llm_manager: Arc<LLMManager>,
+    mistake_ids: Vec<String>,
+) {
+    let settings = load_auto_tagging_settings(&db);
+    if mistake_ids.is_empty() || !settings.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        for mistake_id in &mistake_ids {
+            if let Err(e) = auto_tag_mistake(&db, &llm_manager, &settings, mistake_id).await {
+                warn!("[AutoTagging] 错题 {} 自动打标签失败: {}", mistake_id, e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{insert_mistake, migrated_conn};
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_settings_defaults_and_validation() {
+        let settings = AutoTaggingSettings::default();
+        assert!(!settings.enabled);
+        assert!(settings.validate().is_ok());
+        let invalid = AutoTaggingSettings {
+            confidence_threshold: 1.5,
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = AutoTaggingSettings {
+            max_tags: 0,
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_select_respects_threshold_taxonomy_and_limit() {
+        let taxonomy = tags(&["受力分析", "牛顿第二定律", "Vector"]);
+        let candidates = vec![
+            ("牛顿第二定律".to_string(), 0.8),
+            ("vector".to_string(), 0.95),
+            ("受力分析".to_string(), 0.5),
+            ("动量守恒".to_string(), 0.9),
+        ];
+        let settings = AutoTaggingSettings::default();
+        assert_eq!(
+            select_tags(&candidates, &taxonomy, &settings),
+            vec![
+                ("Vector".to_string(), 0.95),
+                ("牛顿第二定律".to_string(), 0.8)
+            ]
+        );
+
+        let settings = AutoTaggingSettings {
+            allow_new_tags: true,
+            max_tags: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            select_tags(&candidates, &taxonomy, &settings),
+            vec![("Vector".to_string(), 0.95), ("动量守恒".to_string(), 0.9)]
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_manual_tags_and_replaces_previous_ai_tags() {
+        let current = tags(&["易错", "旧AI标签", "受力分析"]);
+        let previous_ai = tags(&["旧AI标签", "受力分析"]);
+        let selected = vec![("受力分析".to_string(), 0.9), ("易错".to_string(), 0.8)];
+        let (merged, added) = merge_tags(&current, &previous_ai, &selected);
+        assert_eq!(merged, tags(&["易错", "受力分析"]));
+        // “易错”是手动标签，不计为 AI 分配
+        assert_eq!(added, tags(&["受力分析"]));
+    }
+
+    #[test]
+    fn test_parse_candidates_and_store_detects_concurrent_edit() {
+        let parsed = parse_tag_candidates(
+            "```json\n[{\"tag\": \" 电路 \", \"confidence\": 1.2}, {\"tag\": \"\"}]\n```",
+        )
+        .unwrap();
+        assert_eq!(parsed, vec![("电路".to_string(), 1.0)]);
+        assert!(parse_tag_candidates("无法分类").is_none());
+
+        let conn = migrated_conn().unwrap();
+        insert_mistake(
+            &conn,
+            "m1",
+            &[("user_question", &"串联电路"), ("tags", &r#"["易错"]"#)],
+        )
+        .unwrap();
+        let source = load_tagging_source(&conn, "m1").unwrap();
+        assert_eq!(source.tags, tags(&["易错"]));
+        assert!(source.previous_ai_tags().is_empty());
+
+        let mut overrides = source.overrides.clone();
+        overrides.insert(
+            AI_TAGS_OVERRIDE_KEY.to_string(),
+            serde_json::json!({
+                "tags": ["电路"],
+                "confidences": {"电路": 0.9},
+                "modelConfigId": "cfg",
+                "assignedAt": "2026-01-01T00:00:00Z"
+            }),
+        );
+        let merged = tags(&["易错", "电路"]);
+        assert!(!store_tags(&conn, "m1", "[]", &merged, &overrides, "now").unwrap());
+        assert!(store_tags(&conn, "m1", &source.tags_json, &merged, &overrides, "now").unwrap());
+        let reloaded = load_tagging_source(&conn, "m1").unwrap();
+        assert_eq!(reloaded.tags, merged);
+        assert_eq!(reloaded.previous_ai_tags(), tags(&["电路"]));
+    }
+}
@@ -98,6 +98,9 @@ pub struct DocumentAttachment {
     pub text_content: Option<String>, // 提取的文本内容（可选）
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub base64_content: Option<String>, // Base64 编码的原始内容（可选）
+    /// 载荷的 SHA-256 指纹；持久化时载荷移入 doc_attachment_blobs，消息中仅保留此引用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
 }
 
 /// ★ 文档25：多模态内容部分（图文交替）
@@ -1111,6 +1114,10 @@ pub struct GenerateMistakeSummaryResponse {
     pub mistake_summary: Option<String>,
     pub user_error_analysis: Option<String>,
     pub error_message: Option<String>,
+    /// 本次实际使用的模型配置 ID（按科目覆盖解析后）；未调用模型时为空
+    pub model_config_id: Option<String>,
+    /// 本次实际使用的模型名称
+    pub model_name: Option<String>,
 }
 
 // 聊天回合删除的详细返回
@@ -1124,6 +1131,26 @@ pub struct DeleteChatTurnResult {
     pub note: Option<String>,
 }
 
+// 管理工具：全库回合元数据重建中单个错题的修复结果
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TurnMetadataFix {
+    pub mistake_id: String,
+    /// 新分配 turn_id 的用户消息数
+    pub users_assigned: usize,
+    /// 绑定到用户回合的助手消息数
+    pub assistants_paired: usize,
+    /// 补齐 turn_seq 的消息数
+    pub turn_seq_fixed: usize,
+    /// 仍无法配对的助手消息数
+    pub orphaned: usize,
+}
+
+impl TurnMetadataFix {
+    pub fn fixed(&self) -> usize {
+        self.users_assigned + self.assistants_paired + self.turn_seq_fixed
+    }
+}
+
 // 管理工具：孤儿助手与遗留tool行的条目
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrphanAssistantRow {
@@ -1177,6 +1204,7 @@ pub struct ModelAssignments {
     pub anki_card_model_config_id: Option<String>,       // Anki制卡模型配置ID
     pub qbank_ai_grading_model_config_id: Option<String>, // 题库AI批改/解析模型配置ID
     pub embedding_model_config_id: Option<String>,       // 新增: 第五模型（嵌入模型）配置ID
+    pub embedding_fallback_model_config_id: Option<String>, // 备用嵌入模型（主模型重试耗尽后使用，须同维度）
     pub reranker_model_config_id: Option<String>,        // 新增: 第六模型（重排序模型）配置ID
     pub chat_title_model_config_id: Option<String>,      // 新增：常规聊天标题生成模型配置ID
     pub exam_sheet_ocr_model_config_id: Option<String>,  // 新增：题目集识别OCR专用模型配置ID
@@ -1185,6 +1213,67 @@ pub struct ModelAssignments {
     pub vl_embedding_model_config_id: Option<String>, // 多模态嵌入模型（Qwen3-VL-Embedding）
     pub vl_reranker_model_config_id: Option<String>,  // 多模态重排序模型（Qwen3-VL-Reranker）
     pub memory_decision_model_config_id: Option<String>, // 记忆决策模型（smart write 去重判断）
+    /// 按科目覆盖的分析/OCR 模型，键为科目名；旧配置缺少该字段时为空
+    #[serde(default)]
+    pub subject_model_overrides: std::collections::HashMap<String, SubjectModelOverride>,
+}
+
+/// 单个科目的模型覆盖；未设置的角色回退到全局分配
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct SubjectModelOverride {
+    #[serde(default)]
+    pub analysis_model_config_id: Option<String>,
+    #[serde(default)]
+    pub ocr_model_config_id: Option<String>,
+}
+
+impl SubjectModelOverride {
+    fn is_empty(&self) -> bool {
+        non_blank(&self.analysis_model_config_id).is_none()
+            && non_blank(&self.ocr_model_config_id).is_none()
+    }
+}
+
+fn non_blank(id: &Option<String>) -> Option<&str> {
+    id.as_deref().map(str::trim).filter(|id| !id.is_empty())
+}
+
+impl ModelAssignments {
+    fn subject_override(&self, subject: Option<&str>) -> Option<&SubjectModelOverride> {
+        let subject = subject.map(str::trim).filter(|s| !s.is_empty())?;
+        self.subject_model_overrides.get(subject)
+    }
+
+    /// 科目对应的分析模型覆盖；未设置时返回 None，由调用方回退到全局分配
+    pub fn analysis_model_for_subject(&self, subject: Option<&str>) -> Option<&str> {
+        self.subject_override(subject)
+            .and_then(|o| non_blank(&o.analysis_model_config_id))
+    }
+
+    /// 科目对应的 OCR 模型覆盖；未设置时返回 None，由调用方回退到全局分配
+    pub fn ocr_model_for_subject(&self, subject: Option<&str>) -> Option<&str> {
+        self.subject_override(subject)
+            .and_then(|o| non_blank(&o.ocr_model_config_id))
+    }
+
+    /// 保存前整理科目覆盖：去掉科目名首尾空白，丢弃空科目与未设置任何模型的条目
+    pub fn normalize_subject_overrides(&mut self) {
+        self.subject_model_overrides = std::mem::take(&mut self.subject_model_overrides)
+            .into_iter()
+            .filter_map(|(subject, entry)| {
+                let subject = subject.trim().to_string();
+                if subject.is_empty() || entry.is_empty() {
+                    return None;
+                }
+                let entry = SubjectModelOverride {
+                    analysis_model_config_id: non_blank(&entry.analysis_model_config_id)
+                        .map(str::to_string),
+                    ocr_model_config_id: non_blank(&entry.ocr_model_config_id).map(str::to_string),
+                };
+                Some((subject, entry))
+            })
+            .collect();
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -1277,6 +1366,12 @@ pub struct AnkiGenerationOptions {
     /// 全文档卡片总上限（可选）。当存在分段任务时，服务会按分段分配额度，避免总数失控。
     #[serde(default)]
     pub max_cards_total: Option<i32>,
+    /// 每个分段最多保存的卡片数（硬上限，超出部分丢弃并将任务标记为截断；None 或 0 表示不限）
+    #[serde(default)]
+    pub max_cards_per_segment: Option<i32>,
+    /// 整个文档最多保存的卡片数（各分段共享的硬上限；None 或 0 表示不限）
+    #[serde(default)]
+    pub max_cards_per_document: Option<i32>,
     #[serde(default)]
     pub max_tokens: Option<u32>,
     #[serde(default)]
@@ -1326,6 +1421,34 @@ pub struct AnkiGenerationOptions {
     /// 是否启用 LLM 智能分段边界检测
     #[serde(default)]
     pub enable_llm_boundary_detection: Option<bool>,
+
+    /// 卡片语言（语言代码或 `auto`）；未设置时使用设置项 `chat.response_language`
+    #[serde(default)]
+    pub response_language: Option<String>,
+
+    /// 输出格式（`off` / `json_object` / `json_schema` / `auto`）；未设置时使用设置项 `anki.output_mode`
+    #[serde(default)]
+    pub output_mode: Option<String>,
+
+    /// 文档所属科目，供生成提示词中的 `{subject}` 占位符使用
+    #[serde(default)]
+    pub subject: Option<String>,
+
+    /// 自动来源标签包含的维度（`subject` / `document` / `segment`）；未设置或为空时不添加
+    #[serde(default)]
+    pub source_tag_facets: Option<Vec<String>>,
+
+    /// 当前分段的来源标签，由 `EnhancedAnkiService` 在任务发送前按 `source_tag_facets` 计算
+    #[serde(default)]
+    pub source_tag: Option<String>,
+
+    /// 字段 HTML 处理方式（`preserve` / `sanitize` / `strip`）；未设置时使用设置项 `anki.html_mode`
+    #[serde(default)]
+    pub html_mode: Option<String>,
+
+    /// 卡片解释详略程度；未设置时使用设置项 `anki.verbosity`（与对话的 `chat.verbosity` 相互独立）
+    #[serde(default)]
+    pub verbosity: Option<crate::response_verbosity::Verbosity>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1406,6 +1529,9 @@ pub struct ExportAnkiCardsRequest {
     pub deck_name: Option<String>,
     pub note_type: Option<String>,
     pub template_id: Option<String>,
+    /// 导出哪些字段；未指定时导出全部字段
+    #[serde(default)]
+    pub field_selection: Option<crate::anki_field_selection::CardFieldSelection>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1673,6 +1799,8 @@ pub enum StreamedCardPayload {
         task_id: String,
         final_status: TaskStatus,
         total_cards_generated: u32,
+        /// 因卡片数量上限未保存的卡片数
+        cards_dropped: u32,
         document_id: Option<String>,
     }, // 单个任务完成信号
     DocumentProcessingStarted {
@@ -1714,6 +1842,43 @@ fn default_overlap_size() -> u32 {
     200 // 默认重叠200个字符
 }
 
+impl Default for AnkiGenerationOptions {
+    fn default() -> Self {
+        Self {
+            deck_name: "默认牌组".to_string(),
+            note_type: "Basic".to_string(),
+            enable_images: false,
+            max_cards_per_mistake: 10,
+            max_cards_total: None,
+            max_cards_per_segment: None,
+            max_cards_per_document: None,
+            max_tokens: None,
+            temperature: None,
+            max_output_tokens_override: None,
+            temperature_override: None,
+            template_id: None,
+            custom_anki_prompt: None,
+            template_fields: None,
+            field_extraction_rules: None,
+            template_fields_by_id: None,
+            field_extraction_rules_by_id: None,
+            custom_requirements: None,
+            segment_overlap_size: default_overlap_size(),
+            system_prompt: None,
+            template_ids: None,
+            template_descriptions: None,
+            enable_llm_boundary_detection: None,
+            response_language: None,
+            output_mode: None,
+            subject: None,
+            source_tag_facets: None,
+            source_tag: None,
+            html_mode: None,
+            verbosity: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnkiExportResponse {
     pub success: bool,
@@ -1736,6 +1901,26 @@ pub struct AnkiDocumentGenerationRequest {
     #[serde(default)]
     pub original_document_name: Option<String>,
     pub options: Option<AnkiGenerationOptions>,
+    /// 文档所属科目，用于解析科目级默认模板
+    #[serde(default)]
+    pub subject: Option<String>,
+}
+
+/// 模板来源：显式指定 > 科目默认 > 全局默认
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppliedTemplateSource {
+    Explicit,
+    SubjectDefault,
+    GlobalDefault,
+}
+
+/// 实际用于制卡的模板
+#[derive(Debug, Clone, Serialize)]
+pub struct AppliedTemplate {
+    pub template_id: String,
+    pub template_name: String,
+    pub source: AppliedTemplateSource,
 }
 
 // 新增：ANKI文档制卡响应结构
@@ -1744,6 +1929,8 @@ pub struct AnkiDocumentGenerationResponse {
     pub success: bool,
     pub cards: Vec<AnkiCard>,
     pub error_message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applied_template: Option<AppliedTemplate>,
 }
 
 // ==================== 智能记忆提取相关 ====================
@@ -1911,6 +2098,7 @@ pub struct RagConfiguration {
     pub min_chunk_size: i32,
     pub default_top_k: i32,
     pub default_rerank_enabled: bool,
+    pub embedding_batch_size: i32, // 每次嵌入请求的分块数
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -1924,6 +2112,8 @@ pub struct RagConfigRequest {
     pub min_chunk_size: i32,
     pub default_top_k: i32,
     pub default_rerank_enabled: bool,
+    #[serde(default)]
+    pub embedding_batch_size: Option<i32>, // 未提供时保持原值
 }
 
 // RAG配置响应
@@ -1935,6 +2125,7 @@ pub struct RagConfigResponse {
     pub min_chunk_size: i32,
     pub default_top_k: i32,
     pub default_rerank_enabled: bool,
+    pub embedding_batch_size: i32,
 }
 
 // ==================== RAG多分库相关数据结构 ====================
@@ -0,0 +1,220 @@
+//! OCR 语言识别与模型路由
+//!
+//! 识别结果的主要文字决定错题语言（`zh`、`en`、`ja`、`ko`；各语言都不占明显多数时为 `mixed`），
+//! 写入错题的 `language` 字段，供错题总结等后续处理沿用。
+//!
+//! 设置项 `ocr.language_routing`（JSON）可为语言指定 OCR 模型：开启后首次识别出的语言若配置了
+//! 其他模型，则改用该模型重新识别；未配置、`mixed` 或重新识别失败时沿用首次结果。科目已设置
+//! OCR 模型覆盖时以科目为准，不再按语言路由。
+//!
+//! 通用 VLM 的识别提示词附加“保留原文语言”的要求，中英混排的题目不会被统一翻译成一种语言。
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::Database;
+use crate::models::AppError;
+use crate::ocr_adapters::{OcrAdapterFactory, OcrEngineType, OcrMode};
+use crate::response_language::{detect_language, normalize_language};
+
+pub const OCR_LANGUAGE_ROUTING_KEY: &str = "ocr.language_routing";
+/// 多种文字都不占明显多数
+pub const MIXED_LANGUAGE: &str = "mixed";
+/// 主要文字的最低占比，低于此值视为混排
+const DOMINANT_SHARE: f64 = 0.7;
+
+const PRESERVE_LANGUAGE_INSTRUCTION: &str =
+    "保留图片中每段文字的原始语言（中英文等混排时逐段照录），不要翻译或统一语言。";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OcrLanguageRouting {
+    pub enabled: bool,
+    /// 语言代码 → OCR 模型配置 ID
+    pub models: HashMap<String, String>,
+}
+
+impl OcrLanguageRouting {
+    /// 语言代码统一为规范写法，丢弃空语言与空模型
+    pub fn normalized(self) -> Self {
+        let models = self
+            .models
+            .into_iter()
+            .filter_map(|(language, config_id)| {
+                let language = normalize_language(&language)?;
+                let config_id = config_id.trim().to_string();
+                (!config_id.is_empty()).then_some((language, config_id))
+            })
+            .collect();
+        Self {
+            enabled: self.enabled,
+            models,
+        }
+    }
+
+    /// 识别出的语言需要改用的模型；与当前模型相同时返回 None
+    pub fn route<'a>(&'a self, language: Option<&str>, current_config_id: &str) -> Option<&'a str> {
+        if !self.enabled {
+            return None;
+        }
+        let language = language.filter(|l| *l != MIXED_LANGUAGE)?;
+        self.models
+            .get(language)
+            .map(String::as_str)
+            .filter(|id| *id != current_config_id)
+    }
+}
+
+pub fn load_ocr_language_routing(db: &Database) -> OcrLanguageRouting {
+    db.get_setting(OCR_LANGUAGE_ROUTING_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<OcrLanguageRouting>(&raw).ok())
+        .map(OcrLanguageRouting::normalized)
+        .unwrap_or_default()
+}
+
+pub fn save_ocr_language_routing(
+    db: &Database,
+    routing: &OcrLanguageRouting,
+) -> Result<(), AppError> {
+    let json = serde_json::to_string(routing)
+        .map_err(|e| AppError::internal(format!("序列化 OCR 语言路由失败: {}", e)))?;
+    db.save_setting(OCR_LANGUAGE_ROUTING_KEY, &json)
+        .map_err(|e| AppError::database(format!("保存 OCR 语言路由失败: {}", e)))
+}
+
+/// 去掉 `$...$` 公式与 `\command`，避免 LaTeX 被当作英文
+fn strip_math(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_math = false;
+    let mut in_command = false;
+    for c in text.chars() {
+        if c == '$' {
+            in_math = !in_math;
+            plain.push(' ');
+            continue;
+        }
+        if in_math {
+            continue;
+        }
+        if c == '\\' {
+            in_command = true;
+            plain.push(' ');
+            continue;
+        }
+        if in_command {
+            if c.is_ascii_alphabetic() {
+                continue;
+            }
+            in_command = false;
+        }
+        plain.push(c);
+    }
+    plain
+}
+
+/// 识别文本的主要语言；没有可判断的文字时返回 None
+pub fn detect_ocr_language(text: &str) -> Option<String> {
+    let plain = strip_math(text);
+    let (mut cjk, mut latin_words) = (0usize, 0usize);
+    // 单个字母多为变量名（x、f(x)），不计入英文单词
+    let mut word_len = 0usize;
+    for c in plain.chars().chain(std::iter::once(' ')) {
+        if c.is_ascii_alphabetic() {
+            word_len += 1;
+            continue;
+        }
+        if word_len >= 2 {
+            latin_words += 1;
+        }
+        word_len = 0;
+        if matches!(c as u32,
+            0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0xF900..=0xFAFF
+            | 0x3040..=0x30FF | 0xAC00..=0xD7AF | 0x1100..=0x11FF)
+        {
+            cjk += 1;
+        }
+    }
+    // 与回答语言推断一致：一个中文词约两个字
+    let cjk_words = cjk as f64 / 2.0;
+    let total = cjk_words + latin_words as f64;
+    if total == 0.0 {
+        return None;
+    }
+    if cjk_words.max(latin_words as f64) / total < DOMINANT_SHARE {
+        return Some(MIXED_LANGUAGE.to_string());
+    }
+    if latin_words as f64 > cjk_words {
+        return Some("en".to_string());
+    }
+    // 以汉字为主时再区分中日韩
+    let cjk_only: String = plain.chars().filter(|c| !c.is_ascii()).collect();
+    detect_language(&cjk_only).map(str::to_string)
+}
+
+/// 单页自由识别的提示词；通用 VLM 附加保留原文语言的要求
+///
+/// DeepSeek-OCR、PaddleOCR-VL 使用固定的任务前缀，不追加内容。
+pub fn free_ocr_prompt(engine: OcrEngineType) -> String {
+    let prompt = OcrAdapterFactory::create(engine).build_prompt(OcrMode::FreeOcr);
+    match engine {
+        OcrEngineType::Glm4vOcr | OcrEngineType::GenericVlm => {
+            format!("{}\n{}", prompt, PRESERVE_LANGUAGE_INSTRUCTION)
+        }
+        _ => prompt,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_dominant_script_and_mixed_text() {
+        assert_eq!(
+            detect_ocr_language("已知函数 $f(x) = \\sin x + \\frac{1}{x}$，求 f(x) 的导数。")
+                .as_deref(),
+            Some("zh")
+        );
+        assert_eq!(
+            detect_ocr_language("Read the passage and answer the questions below.").as_deref(),
+            Some("en")
+        );
+        assert_eq!(
+            detect_ocr_language("阅读下面的短文 Read the passage carefully").as_deref(),
+            Some(MIXED_LANGUAGE)
+        );
+        assert_eq!(detect_ocr_language("$x^2 + \\alpha = 1$"), None);
+    }
+
+    #[test]
+    fn test_routing_skips_mixed_disabled_and_current_model() {
+        let routing = OcrLanguageRouting {
+            enabled: true,
+            models: HashMap::from([
+                ("English".to_string(), "vlm-en".to_string()),
+                ("ja".to_string(), " ".to_string()),
+            ]),
+        }
+        .normalized();
+        assert_eq!(routing.models.len(), 1);
+        assert_eq!(routing.route(Some("en"), "paddle"), Some("vlm-en"));
+        assert_eq!(routing.route(Some("en"), "vlm-en"), None);
+        assert_eq!(routing.route(Some(MIXED_LANGUAGE), "paddle"), None);
+        assert_eq!(routing.route(Some("zh"), "paddle"), None);
+        assert_eq!(routing.route(None, "paddle"), None);
+        let disabled = OcrLanguageRouting {
+            enabled: false,
+            ..routing
+        };
+        assert_eq!(disabled.route(Some("en"), "paddle"), None);
+    }
+
+    #[test]
+    fn test_prompt_keeps_task_prefix_for_dedicated_engines() {
+        assert_eq!(free_ocr_prompt(OcrEngineType::PaddleOcrVl), "OCR:");
+        assert!(free_ocr_prompt(OcrEngineType::GenericVlm).ends_with(PRESERVE_LANGUAGE_INSTRUCTION));
+    }
+}
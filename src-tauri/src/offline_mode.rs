@@ -0,0 +1,156 @@
+//! 离线模式
+//!
+//! 开启后应用不再发起任何出站网络请求：模型调用、嵌入/重排、网络搜索、网页抓取、学术检索、
+//! AnkiConnect、云同步以及 MCP 后端连接在发请求前直接返回 `OFFLINE_MODE` 错误，会联网的
+//! 后台任务（如定时导出到云端）跳过执行。
+//!
+//! 目标地址为本机回环（`localhost`、`127.0.0.0/8`、`::1`）的请求不算出站，仍然放行，
+//! 因此在本机运行的 Ollama 等本地模型与本机 AnkiConnect 可以继续使用。数据库、检索、导出等
+//! 纯本地功能不受影响。
+//!
+//! 设置项 `network.offline_mode` 在启动时加载，通过通用设置命令修改后立即生效。
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde_json::json;
+
+use crate::database::Database;
+use crate::models::{AppError, AppErrorType};
+
+pub const OFFLINE_MODE_SETTING_KEY: &str = "network.offline_mode";
+/// 离线模式拦截时返回给前端的错误码
+pub const OFFLINE_MODE_CODE: &str = "OFFLINE_MODE";
+
+static OFFLINE_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn is_enabled() -> bool {
+    OFFLINE_MODE.load(Ordering::SeqCst)
+}
+
+pub fn set_enabled(enabled: bool) {
+    let previous = OFFLINE_MODE.swap(enabled, Ordering::SeqCst);
+    if previous != enabled {
+        println!("🔌 离线模式已{}", if enabled { "开启" } else { "关闭" });
+    }
+}
+
+/// 解析设置值；`true` / `1` / `on` / `yes` 视为开启，其余视为关闭
+pub fn parse_setting(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "true" | "1" | "on" | "yes"
+    )
+}
+
+/// 启动时从数据库加载；读取失败时保持关闭
+pub fn load_offline_mode_setting(db: &Database) {
+    let value = db.get_setting(OFFLINE_MODE_SETTING_KEY).ok().flatten();
+    set_enabled(value.as_deref().is_some_and(parse_setting));
+}
+
+/// 通用设置命令保存或删除设置项后调用，使修改立即生效
+pub fn apply_setting_change(key: &str, value: Option<&str>) {
+    if key == OFFLINE_MODE_SETTING_KEY {
+        set_enabled(value.is_some_and(parse_setting));
+    }
+}
+
+pub fn offline_message(feature: &str) -> String {
+    format!(
+        "离线模式已开启，{}需要联网，已被禁止（{}）",
+        feature, OFFLINE_MODE_CODE
+    )
+}
+
+pub fn offline_error(feature: &str) -> AppError {
+    AppError::with_details(
+        AppErrorType::Network,
+        offline_message(feature),
+        json!({
+            "code": OFFLINE_MODE_CODE,
+            "feature": feature,
+        }),
+    )
+}
+
+/// 离线模式下拒绝需要联网的功能
+pub fn ensure_online(feature: &str) -> Result<(), AppError> {
+    if is_enabled() {
+        return Err(offline_error(feature));
+    }
+    Ok(())
+}
+
+/// 目标地址是否为本机回环地址；无法解析的地址视为非本机
+pub fn is_local_url(raw: &str) -> bool {
+    let Ok(parsed) = url::Url::parse(raw.trim()) else {
+        return false;
+    };
+    match parsed.host() {
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+        Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip).is_loopback(),
+        Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip).is_loopback(),
+        None => false,
+    }
+}
+
+/// 离线模式下只放行发往本机的请求
+pub fn ensure_endpoint_allowed(feature: &str, url: &str) -> Result<(), AppError> {
+    if is_enabled() && !is_local_url(url) {
+        return Err(offline_error(feature));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_local_url() {
+        for url in [
+            "http://localhost:11434/v1",
+            "http://LOCALHOST./api",
+            "http://127.0.0.1:8765",
+            "http://127.1.2.3",
+            "http://[::1]:11434/v1/chat/completions",
+            "http://ollama.localhost/v1",
+        ] {
+            assert!(is_local_url(url), "{url}");
+        }
+        for url in [
+            "https://api.openai.com/v1",
+            "http://192.168.1.10:11434",
+            "http://localhost.example.com",
+            "http://[2001:db8::1]/v1",
+            "not a url",
+            "",
+        ] {
+            assert!(!is_local_url(url), "{url}");
+        }
+    }
+
+    #[test]
+    fn test_offline_mode_blocks_remote_endpoints_only() {
+        set_enabled(false);
+        assert!(ensure_online("网络搜索").is_ok());
+        assert!(ensure_endpoint_allowed("模型调用", "https://api.openai.com/v1").is_ok());
+
+        apply_setting_change(OFFLINE_MODE_SETTING_KEY, Some("true"));
+        assert!(is_enabled());
+        let err = ensure_endpoint_allowed("模型调用", "https://api.openai.com/v1").unwrap_err();
+        assert_eq!(err.details.unwrap()["code"], OFFLINE_MODE_CODE);
+        assert!(ensure_endpoint_allowed("模型调用", "http://localhost:11434/v1").is_ok());
+        assert!(ensure_online("网络搜索").is_err());
+
+        // 其他设置项不影响离线模式
+        apply_setting_change("anki.html_mode", None);
+        assert!(is_enabled());
+        apply_setting_change(OFFLINE_MODE_SETTING_KEY, None);
+        assert!(!is_enabled());
+    }
+}
@@ -1299,15 +1299,15 @@ impl PdfOcrService {
         page: &PdfOcrPageInput,
     ) -> Result<PreparedPage> {
         let base64 = Self::normalize_base64(&page.image_base64);
-        let ext = match self.file_manager.extract_extension_from_base64(&base64) {
-            Ok(e) => e,
-            Err(_) => "png".to_string(),
-        };
-        let file_name = format!("pdfocr_{}_p{}.{}", temp_id, page.page_index, ext);
+        // 按内容去重保存：重复扫描的同一页只存一份
         let image_rel_path = self
             .file_manager
-            .save_image_from_base64(&base64, &file_name)
+            .save_image_from_base64_dedup(&base64)
             .await?;
+        debug!(
+            "PDF OCR 会话 {} 第 {} 页图片: {}",
+            temp_id, page.page_index, image_rel_path
+        );
 
         let (width, height) = match (page.width, page.height) {
             (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
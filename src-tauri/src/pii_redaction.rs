@@ -0,0 +1,409 @@
+//! 发送云端模型前的隐私脱敏
+//!
+//! 开启后，发往非本机模型的请求在离开设备前把学生姓名、学号等敏感信息替换为占位符
+//! （如 `[NAME_1]`、`[STUDENT_ID_1]`），模型回答中出现的占位符再还原为原文后展示。
+//! 同一请求内相同的原文使用同一个占位符，模型仍能分辨“同一个人”。
+//!
+//! 规则包括：
+//! - 姓名名单（设置中逐个填写，按最长优先匹配）；
+//! - 内置规则：学号 / 考号标签后的编号、身份证号、手机号、电子邮箱；
+//! - 自定义正则：含捕获组时只替换第一个捕获组，便于只脱敏“学号：”之后的部分。
+//!
+//! 发往本机回环地址（Ollama 等本地模型）的请求不做处理。日志只记录各类别的替换次数，
+//! 不记录原文。配置保存在设置项 `privacy.pii_redaction`（JSON），默认关闭。
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const PII_REDACTION_SETTINGS_KEY: &str = "privacy.pii_redaction";
+/// 姓名名单对应的类别
+pub const NAME_CATEGORY: &str = "name";
+
+const BUILTIN_RULES: &[(&str, &str)] = &[
+    (
+        "student_id",
+        r"(?i)(?:学号|考号|准考证号|学籍号|student\s*(?:id|no\.?|number))\s*[:：#]?\s*([A-Za-z0-9-]{4,20})",
+    ),
+    ("id_card", r"(?-u:\b)\d{17}[\dXx](?-u:\b)"),
+    ("phone", r"(?-u:\b)1[3-9]\d{9}(?-u:\b)"),
+    ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionRule {
+    /// 类别名，用于占位符与日志（如 `class_name`）
+    pub category: String,
+    pub pattern: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PiiRedactionSettings {
+    pub enabled: bool,
+    /// 需要脱敏的姓名
+    pub names: Vec<String>,
+    /// 是否启用内置规则（学号、身份证号、手机号、邮箱）
+    pub builtin_rules: bool,
+    pub custom_rules: Vec<RedactionRule>,
+}
+
+impl Default for PiiRedactionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            names: Vec::new(),
+            builtin_rules: true,
+            custom_rules: Vec::new(),
+        }
+    }
+}
+
+/// 编译后的脱敏规则
+#[derive(Debug)]
+pub struct Redactor {
+    rules: Vec<(String, Regex)>,
+}
+
+fn normalize_category(category: &str) -> Option<String> {
+    let category: String = category
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let category = category.trim_matches('_').to_string();
+    (!category.is_empty()).then_some(category)
+}
+
+impl Redactor {
+    pub fn from_settings(settings: &PiiRedactionSettings) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        let mut names: Vec<&str> = settings
+            .names
+            .iter()
+            .map(|n| n.trim())
+            .filter(|n| !n.is_empty())
+            .collect();
+        if !names.is_empty() {
+            // 长名字优先，避免“张三丰”只替换了“张三”
+            names.sort_by_key(|n| std::cmp::Reverse(n.chars().count()));
+            names.dedup();
+            let pattern = names
+                .iter()
+                .map(|n| regex::escape(n))
+                .collect::<Vec<_>>()
+                .join("|");
+            let regex = Regex::new(&format!("(?i){}", pattern))
+                .map_err(|e| format!("姓名名单无效: {}", e))?;
+            rules.push((NAME_CATEGORY.to_string(), regex));
+        }
+        for rule in &settings.custom_rules {
+            let category = normalize_category(&rule.category)
+                .ok_or_else(|| "自定义规则的类别不能为空".to_string())?;
+            let regex = Regex::new(&rule.pattern)
+                .map_err(|e| format!("自定义规则 {} 的正则无效: {}", category, e))?;
+            rules.push((category, regex));
+        }
+        if settings.builtin_rules {
+            for (category, pattern) in BUILTIN_RULES {
+                let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+                rules.push((category.to_string(), regex));
+            }
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+/// 一次请求的占位符映射
+#[derive(Debug, Default)]
+pub struct Redaction {
+    /// (占位符, 原文)
+    placeholders: Vec<(String, String)>,
+    counts: BTreeMap<String, usize>,
+}
+
+impl Redaction {
+    fn placeholder_for(&mut self, category: &str, original: &str) -> String {
+        let prefix = format!("[{}_", category.to_ascii_uppercase());
+        let mut index = 0;
+        for (placeholder, value) in &self.placeholders {
+            if placeholder.starts_with(&prefix) {
+                if value == original {
+                    return placeholder.clone();
+                }
+                index += 1;
+            }
+        }
+        let placeholder = format!("{}{}]", prefix, index + 1);
+        self.placeholders
+            .push((placeholder.clone(), original.to_string()));
+        placeholder
+    }
+
+    /// 按规则顺序依次替换；含捕获组的规则只替换第一个捕获组
+    pub fn redact(&mut self, redactor: &Redactor, text: &str) -> String {
+        let mut text = text.to_string();
+        for (category, regex) in &redactor.rules {
+            let mut output = String::with_capacity(text.len());
+            let mut last = 0;
+            for captures in regex.captures_iter(&text) {
+                let Some(target) = captures.get(1).or_else(|| captures.get(0)) else {
+                    continue;
+                };
+                if target.as_str().is_empty() {
+                    continue;
+                }
+                output.push_str(&text[last..target.start()]);
+                output.push_str(&self.placeholder_for(category, target.as_str()));
+                *self.counts.entry(category.clone()).or_default() += 1;
+                last = target.end();
+            }
+            if last > 0 {
+                output.push_str(&text[last..]);
+                text = output;
+            }
+        }
+        text
+    }
+
+    /// 把回答中的占位符还原为原文；只替换本次请求生成过的占位符
+    pub fn restore(&self, text: &str) -> String {
+        if self.placeholders.is_empty() {
+            return text.to_string();
+        }
+        let mut text = text.to_string();
+        for (placeholder, original) in &self.placeholders {
+            if text.contains(placeholder.as_str()) {
+                text = text.replace(placeholder.as_str(), original);
+            }
+        }
+        text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.placeholders.is_empty()
+    }
+
+    /// 各类别替换次数，供日志使用（不含原文）
+    pub fn summary(&self) -> String {
+        self.counts
+            .iter()
+            .map(|(category, count)| format!("{}×{}", category, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// 流式输出的占位符还原：占位符可能被拆在两个增量里，未闭合的部分暂存到下一段
+#[derive(Debug, Default)]
+pub struct StreamRestorer {
+    pending: String,
+}
+
+/// 占位符的最大长度（`[` + 类别 + `_` + 序号 + `]`）
+const MAX_PLACEHOLDER_LEN: usize = 48;
+
+impl StreamRestorer {
+    pub fn push(&mut self, redaction: &Redaction, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+        let restored = redaction.restore(&self.pending);
+        // 末尾未闭合的 `[...` 可能是占位符的前半段
+        let hold_from = restored
+            .rfind('[')
+            .filter(|&i| !restored[i..].contains(']') && restored.len() - i < MAX_PLACEHOLDER_LEN);
+        match hold_from {
+            Some(i) => {
+                self.pending = restored[i..].to_string();
+                restored[..i].to_string()
+            }
+            None => {
+                self.pending.clear();
+                restored
+            }
+        }
+    }
+
+    pub fn finish(&mut self, redaction: &Redaction) -> String {
+        redaction.restore(&std::mem::take(&mut self.pending))
+    }
+}
+
+static REDACTOR: LazyLock<RwLock<Option<Arc<Redactor>>>> = LazyLock::new(|| RwLock::new(None));
+
+/// 校验并启用配置；关闭或没有任何规则时清除
+pub fn apply_pii_redaction_settings(settings: &PiiRedactionSettings) -> Result<(), String> {
+    let redactor = Redactor::from_settings(settings)?;
+    let active = (settings.enabled && !redactor.is_empty()).then(|| Arc::new(redactor));
+    *REDACTOR.write().unwrap_or_else(|e| e.into_inner()) = active;
+    Ok(())
+}
+
+pub fn load_pii_redaction_settings(db: &crate::database::Database) -> PiiRedactionSettings {
+    db.get_setting(PII_REDACTION_SETTINGS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// 启动时加载；配置无效时保持关闭
+pub fn init_pii_redaction(db: &crate::database::Database) {
+    if let Err(e) = apply_pii_redaction_settings(&load_pii_redaction_settings(db)) {
+        println!("⚠️ 隐私脱敏配置无效，已关闭: {}", e);
+    }
+}
+
+fn active_redactor() -> Option<Arc<Redactor>> {
+    REDACTOR.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// 只处理消息正文：`content` 字符串与内容分段的 `text`，图片、工具参数等保持原样
+fn redact_message_value(value: &mut Value, redactor: &Redactor, redaction: &mut Redaction) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                redact_message_value(item, redactor, redaction);
+            }
+        }
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                match field {
+                    Value::String(text) if key == "content" || key == "text" => {
+                        *text = redaction.redact(redactor, text);
+                    }
+                    Value::Array(_) if key == "content" => {
+                        redact_message_value(field, redactor, redaction);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 对发往 `base_url` 的 OpenAI 格式请求体脱敏（在适配器转换之前调用）
+///
+/// 未开启、目标为本机或没有命中任何规则时返回 None。
+pub fn redact_request_body(base_url: &str, request_body: &mut Value) -> Option<Redaction> {
+    if crate::offline_mode::is_local_url(base_url) {
+        return None;
+    }
+    let redactor = active_redactor()?;
+    let mut redaction = Redaction::default();
+    if let Some(messages) = request_body.get_mut("messages") {
+        redact_message_value(messages, &redactor, &mut redaction);
+    }
+    if redaction.is_empty() {
+        return None;
+    }
+    log::info!("[PII] 已脱敏发送至云端模型的请求: {}", redaction.summary());
+    Some(redaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn redactor() -> Redactor {
+        Redactor::from_settings(&PiiRedactionSettings {
+            enabled: true,
+            names: vec![
+                "张三".to_string(),
+                "张三丰".to_string(),
+                "Li Lei".to_string(),
+            ],
+            builtin_rules: true,
+            custom_rules: vec![RedactionRule {
+                category: "Class Name".to_string(),
+                pattern: r"(高[一二三]\(\d+\)班)".to_string(),
+            }],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_redacts_names_ids_and_custom_rules_with_stable_placeholders() {
+        let redactor = redactor();
+        let mut redaction = Redaction::default();
+        let text = "张三丰和张三（学号：20230115）在高二(3)班，li lei 的电话 13812345678，\
+                    邮箱 lilei@example.com，身份证 11010519491231002X。张三又错了。";
+        let redacted = redaction.redact(&redactor, text);
+        assert_eq!(
+            redacted,
+            "[NAME_1]和[NAME_2]（学号：[STUDENT_ID_1]）在[CLASS_NAME_1]，[NAME_3] 的电话 [PHONE_1]，\
+             邮箱 [EMAIL_1]，身份证 [ID_CARD_1]。[NAME_2]又错了。"
+        );
+        assert_eq!(
+            redaction.summary(),
+            "class_name×1, email×1, id_card×1, name×4, phone×1, student_id×1"
+        );
+        assert_eq!(redaction.restore(&redacted), text);
+    }
+
+    #[test]
+    fn test_request_body_only_touches_message_text() {
+        let redactor = redactor();
+        let mut redaction = Redaction::default();
+        let mut body = json!({
+            "model": "张三",
+            "messages": [
+                {"role": "system", "content": "学生：张三"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "张三的作业"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,13812345678"}}
+                ]}
+            ]
+        });
+        redact_message_value(body.get_mut("messages").unwrap(), &redactor, &mut redaction);
+        assert_eq!(body["model"], "张三");
+        assert_eq!(body["messages"][0]["content"], "学生：[NAME_1]");
+        assert_eq!(body["messages"][1]["content"][0]["text"], "[NAME_1]的作业");
+        assert_eq!(
+            body["messages"][1]["content"][1]["image_url"]["url"],
+            "data:image/png;base64,13812345678"
+        );
+    }
+
+    #[test]
+    fn test_stream_restorer_handles_split_placeholders() {
+        let redactor = redactor();
+        let mut redaction = Redaction::default();
+        redaction.redact(&redactor, "张三");
+        let mut restorer = StreamRestorer::default();
+        let mut output = String::new();
+        for chunk in ["你好，[NA", "ME_1] 同学", "，数组 a[i", "] 越界"] {
+            output.push_str(&restorer.push(&redaction, chunk));
+        }
+        output.push_str(&restorer.finish(&redaction));
+        assert_eq!(output, "你好，张三 同学，数组 a[i] 越界");
+    }
+
+    #[test]
+    fn test_invalid_custom_rule_is_rejected() {
+        let settings = PiiRedactionSettings {
+            enabled: true,
+            custom_rules: vec![RedactionRule {
+                category: "x".to_string(),
+                pattern: "(".to_string(),
+            }],
+            ..Default::default()
+        };
+        assert!(Redactor::from_settings(&settings).is_err());
+    }
+}
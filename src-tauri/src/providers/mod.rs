@@ -253,7 +253,7 @@ impl OpenAIResponsesAdapter {
         let mut payload = json!({
             "model": model,
             "input": input_blocks,
-            "stream": true,
+            "stream": body.get("stream").and_then(|v| v.as_bool()).unwrap_or(true),
         });
 
         if !instructions.is_empty() {
@@ -1,6 +1,8 @@
 /// 题目集 AI 评判事件发射器
 use tauri::{Emitter, Window};
 
+use crate::stream_events::{emit_delta, emit_stream_event, StreamEvent, StreamSource};
+
 use super::types::{
     QbankGradingStreamCancelled, QbankGradingStreamComplete, QbankGradingStreamData,
     QbankGradingStreamError,
@@ -21,13 +23,21 @@ impl QbankGradingEmitter {
         let event_name = format!("qbank_grading_stream_{}", stream_session_id);
         let payload = QbankGradingStreamData {
             event_type: "data".to_string(),
-            chunk,
+            chunk: chunk.clone(),
             accumulated,
         };
 
-        if let Err(e) = self.window.emit(&event_name, payload) {
-            log::error!("[QbankGrading] 发送数据事件失败: {}", e);
-        }
+        emit_delta(
+            &self.window,
+            StreamSource::QbankGrading,
+            stream_session_id,
+            StreamEvent::token(chunk),
+            || {
+                if let Err(e) = self.window.emit(&event_name, payload) {
+                    log::error!("[QbankGrading] 发送数据事件失败: {}", e);
+                }
+            },
+        );
     }
 
     /// 发送完成事件
@@ -48,6 +58,12 @@ impl QbankGradingEmitter {
             feedback,
         };
 
+        emit_stream_event(
+            &self.window,
+            StreamSource::QbankGrading,
+            stream_session_id,
+            StreamEvent::done(serde_json::to_value(&payload).ok()),
+        );
         if let Err(e) = self.window.emit(&event_name, payload) {
             log::error!("[QbankGrading] 发送完成事件失败: {}", e);
         }
@@ -61,6 +77,12 @@ impl QbankGradingEmitter {
             message,
         };
 
+        emit_stream_event(
+            &self.window,
+            StreamSource::QbankGrading,
+            stream_session_id,
+            StreamEvent::error(payload.message.clone()),
+        );
         if let Err(e) = self.window.emit(&event_name, payload) {
             log::error!("[QbankGrading] 发送错误事件失败: {}", e);
         }
@@ -73,6 +95,12 @@ impl QbankGradingEmitter {
             event_type: "cancelled".to_string(),
         };
 
+        emit_stream_event(
+            &self.window,
+            StreamSource::QbankGrading,
+            stream_session_id,
+            StreamEvent::cancelled(),
+        );
         if let Err(e) = self.window.emit(&event_name, payload) {
             log::error!("[QbankGrading] 发送取消事件失败: {}", e);
         }
@@ -10,9 +10,10 @@ use regex::Regex;
 use serde_json::json;
 use std::sync::Arc;
 
-use crate::llm_manager::{ApiConfig, LLMManager};
+use crate::llm_manager::{ApiConfig, LLMManager, StreamMode, StreamTimer};
 use crate::models::AppError;
 use crate::providers::ProviderAdapter;
+use crate::stream_events::{StreamSequenceGuard, StreamSource};
 use crate::vfs::database::VfsDatabase;
 use crate::vfs::repos::VfsQuestionRepo;
 
@@ -41,6 +42,10 @@ pub async fn run_qbank_grading(
     request: QbankGradingRequest,
     deps: QbankGradingDeps,
 ) -> Result<Option<QbankGradingResponse>, AppError> {
+    // 提前返回或被中止时同样清理统一通道的序号
+    let _sequence_guard =
+        StreamSequenceGuard::new(StreamSource::QbankGrading, &request.stream_session_id);
+
     // 1. 获取题目信息
     let question = VfsQuestionRepo::get_question(&deps.vfs_db, &request.question_id)
         .map_err(|e| AppError::database(e.to_string()))?
@@ -365,12 +370,14 @@ where
             json!({ "role": "user", "content": user_prompt }),
         ];
 
+        // 模型不支持流式时改发非流式请求，响应转换为单段 SSE
+        let stream_mode = StreamMode::for_config(config);
         let request_body = json!({
             "model": config.model,
             "messages": messages,
             "temperature": 0.3,
             "max_tokens": config.max_output_tokens.min(8192),
-            "stream": true,
+            "stream": stream_mode.is_streaming(),
         });
 
         let adapter: Box<dyn ProviderAdapter> = match config.model_adapter.as_str() {
@@ -382,6 +389,8 @@ where
         let preq = adapter
             .build_request(&config.base_url, api_key, &config.model, &request_body)
             .map_err(|e| AppError::llm(format!("评判请求构建失败: {}", e)))?;
+        crate::offline_mode::ensure_endpoint_allowed("题目评判", &preq.url)?;
+        let adapter = stream_mode.parse_adapter(adapter);
 
         let mut header_map = reqwest::header::HeaderMap::new();
         for (k, v) in preq.headers.iter() {
@@ -398,13 +407,15 @@ where
         llm.consume_pending_cancel(stream_event).await;
         let mut cancel_rx = llm.subscribe_cancel_stream(stream_event).await;
 
-        let response = client
-            .post(&preq.url)
-            .headers(header_map)
-            .json(&preq.body)
-            .send()
+        let request_builder = client.post(&preq.url).headers(header_map);
+        let timer = StreamTimer::start(config, stream_mode);
+        let response = timer
+            .send(
+                llm.apply_custom_request_options(request_builder, config)?
+                    .json(&preq.body),
+            )
             .await
-            .map_err(|e| AppError::llm(format!("评判请求失败: {}", e)))?;
+            .map_err(|e| e.into_app_error(|e| AppError::llm(format!("评判请求失败: {}", e))))?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -415,7 +426,9 @@ where
             )));
         }
 
-        let mut stream = response.bytes_stream();
+        let mut stream = stream_mode
+            .into_byte_stream(response, config, false, timer)
+            .await?;
         let mut buffer = String::new();
         let mut stream_ended = false;
         let mut cancelled = false;
@@ -435,7 +448,9 @@ where
                 chunk_result = stream.next() => {
                     match chunk_result {
                         Some(chunk) => {
-                            let bytes = chunk.map_err(|e| AppError::llm(format!("读取流失败: {}", e)))?;
+                            let bytes = chunk.map_err(|e| {
+                                e.into_app_error(|e| AppError::llm(format!("读取流失败: {}", e)))
+                            })?;
                             buffer.push_str(&String::from_utf8_lossy(&bytes));
 
                             while let Some(pos) = buffer.find("\n\n") {
@@ -392,7 +392,7 @@ impl CsvExportService {
     }
 
     /// 转义 CSV 单元格（含 OWASP 公式注入防护）
-    fn escape_csv_cell(cell: &str) -> String {
+    pub(crate) fn escape_csv_cell(cell: &str) -> String {
         let mut value = cell.to_string();
 
         // OWASP CSV Injection: 以危险前缀开头的单元格需要前缀 tab 字符中和公式解析
@@ -21,6 +21,7 @@ use tokio::sync::mpsc::UnboundedSender;
 
 use crate::cross_page_merger;
 use crate::document_parser::DocumentParser;
+use crate::exam_segmentation;
 use crate::figure_extractor;
 use crate::file_manager::FileManager;
 use crate::llm_manager::LLMManager;
@@ -825,6 +826,7 @@ impl QuestionImportService {
 
             match vlm_service.analyze_page_by_blob(vfs_db, page).await {
                 Ok(analysis) => {
+                    let analysis = exam_segmentation::segment_page(analysis);
                     log::info!(
                         "[QuestionImport] VLM 页面 {}/{}: {} 道题目",
                         idx + 1,
@@ -2318,6 +2320,7 @@ impl QuestionImportService {
 
         let result = (|| -> Result<usize, AppError> {
             let mut total_saved = 0;
+            let mut cards_by_page: HashMap<usize, Vec<ExamCardPreview>> = HashMap::new();
 
             for (idx, sq) in structured.iter().enumerate() {
                 let content = sq
@@ -2338,6 +2341,15 @@ impl QuestionImportService {
                 VfsQuestionRepo::create_question_with_conn(&conn, &params)
                     .map_err(|e| AppError::database(format!("写入题目失败: {}", e)))?;
 
+                // 预览卡片与题目共用 card_id，并放回题目所在页、带上切分边界框
+                let mut card = question_to_card(&sq.json, idx);
+                card.card_id = card_id;
+                apply_segmentation(&mut card, &sq.source.merged);
+                cards_by_page
+                    .entry(card.page_index)
+                    .or_insert_with(Vec::new)
+                    .push(card);
+
                 total_saved += 1;
 
                 if let Some(tx) = progress_tx {
@@ -2349,25 +2361,12 @@ impl QuestionImportService {
                 }
             }
 
-            // 更新 preview_json（带页面图片和题目卡片）
-            let cards: Vec<ExamCardPreview> = structured
-                .iter()
-                .enumerate()
-                .filter(|(_, sq)| {
-                    sq.json
-                        .get("content")
-                        .and_then(|v| v.as_str())
-                        .map(|s| !s.trim().is_empty())
-                        .unwrap_or(false)
-                })
-                .map(|(i, sq)| question_to_card(&sq.json, i))
-                .collect();
-
-            let preview_pages: Vec<ExamSheetPreviewPage> = pages
+            // 更新 preview_json（带页面图片和逐题切分结果，重新打开会话时结构一致）
+            let mut preview_pages: Vec<ExamSheetPreviewPage> = pages
                 .iter()
                 .map(|p| ExamSheetPreviewPage {
                     page_index: p.page_index,
-                    cards: if p.page_index == 0 { cards.clone() } else { Vec::new() },
+                    cards: cards_by_page.remove(&p.page_index).unwrap_or_default(),
                     blob_hash: Some(p.blob_hash.clone()),
                     width: Some(p.width),
                     height: Some(p.height),
@@ -2377,6 +2376,15 @@ impl QuestionImportService {
                     parse_completed: true,
                 })
                 .collect();
+            // 页码对不上的卡片（理论上不应出现）放到首页，避免丢题
+            if let Some(first) = preview_pages.first_mut() {
+                let mut orphans: Vec<ExamCardPreview> =
+                    cards_by_page.into_values().flatten().collect();
+                for card in orphans.iter_mut() {
+                    card.page_index = first.page_index;
+                }
+                first.cards.extend(orphans);
+            }
 
             let preview = ExamSheetPreviewResult {
                 temp_id: session_id.to_string(),
@@ -2728,6 +2736,24 @@ fn question_to_card(q: &Value, index: usize) -> ExamCardPreview {
     }
 }
 
+/// 把 VLM 切分结果（所在页、边界框、题号）写入预览卡片
+fn apply_segmentation(card: &mut ExamCardPreview, merged: &cross_page_merger::MergedQuestion) {
+    card.page_index = merged.page_indices.first().copied().unwrap_or(0);
+    let label = merged.question.label.trim();
+    if !label.is_empty() {
+        card.question_label = label.to_string();
+    }
+    if let Some(bbox) = exam_segmentation::to_card_bbox(merged.question.bbox) {
+        card.bbox = bbox;
+    }
+    card.extra_metadata = Some(json!({
+        "segmentation": {
+            "source": if merged.question.split_by_numbering { "numbering" } else { "vlm" },
+            "page_indices": merged.page_indices,
+        }
+    }));
+}
+
 fn build_text_parse_prompt(chunk: &str) -> String {
     format!(
         r#"请将以下文本内容解析为题目列表。
@@ -0,0 +1,208 @@
+//! RAG 检索调试轨迹
+//!
+//! 知识库检索带 `debug: true` 时，除结果外额外返回一份轨迹，用于区分“检索没找到”
+//! 与“生成没用好”：实际用于检索的查询（含查询扩展）、每个候选片段的原始分数与重排序分数、
+//! 被 `min_score` 丢弃的片段，以及按对话管线格式拼装的系统提示词。
+//!
+//! 轨迹只包含用户自己的数据，不做脱敏；默认关闭，不影响普通检索的返回结构。
+
+use crate::chat_v2::prompt_builder::PromptBuilder;
+use crate::chat_v2::types::SourceInfo;
+use crate::rag_embedding_override::EmbeddingModelOverride;
+use crate::vfs::indexing::{VfsScoreTracedResults, VfsSearchResult};
+use serde::Serialize;
+use serde_json::json;
+
+/// 单个候选片段的打分记录
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RagDebugCandidate {
+    pub embedding_id: String,
+    pub resource_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_title: Option<String>,
+    pub chunk_index: i32,
+    pub chunk_text: String,
+    /// 向量 / 混合检索的原始分数
+    pub raw_score: f64,
+    /// 重排序后的分数（未执行重排序时为空）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reranked_score: Option<f64>,
+    /// 是否因低于 `min_score` 被丢弃
+    pub dropped_by_min_score: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RagDebugTrace {
+    /// 用户输入的原始查询
+    pub query: String,
+    /// 实际送入嵌入模型 / 全文检索的主查询（与原始查询一致）
+    pub effective_query: String,
+    /// 查询扩展生成的附加查询（未启用查询扩展时为空）
+    pub expanded_queries: Vec<String>,
+    /// 本次检索临时指定的查询嵌入模型（未覆盖时为空）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding_model_override: Option<EmbeddingModelOverride>,
+    pub reranked: bool,
+    /// 生效的最低分阈值（为空表示不过滤）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f32>,
+    /// 全部候选片段，按最终排序
+    pub candidates: Vec<RagDebugCandidate>,
+    pub dropped_count: usize,
+    /// 以保留下来的片段拼装的系统提示词（与对话管线注入知识库上下文的格式一致）
+    pub prompt: String,
+    /// `prompt` 是否按 `prompt_max_chars` 截断
+    pub prompt_truncated: bool,
+    /// 截断前的提示词字符数
+    pub prompt_chars: usize,
+}
+
+/// 按 `min_score` 过滤检索结果，返回保留的结果与调试轨迹
+///
+/// 过滤规则与对话检索工具一致：`min_score <= 0` 不过滤，分数 `>= min_score` 保留。
+/// 重排序后按重排序分数过滤。
+pub fn apply_min_score_with_trace(
+    query: &str,
+    expanded_queries: Vec<String>,
+    traced: VfsScoreTracedResults,
+    min_score: Option<f32>,
+    prompt_max_chars: Option<usize>,
+) -> (Vec<VfsSearchResult>, RagDebugTrace) {
+    let threshold = min_score.filter(|score| *score > 0.0);
+    let mut kept = Vec::with_capacity(traced.results.len());
+    let mut candidates = Vec::with_capacity(traced.results.len());
+
+    for result in traced.results {
+        let dropped = threshold.is_some_and(|min| result.score < min as f64);
+        let raw_score = traced
+            .raw_scores
+            .get(&result.embedding_id)
+            .copied()
+            .unwrap_or(result.score);
+        candidates.push(RagDebugCandidate {
+            embedding_id: result.embedding_id.clone(),
+            resource_id: result.resource_id.clone(),
+            resource_title: result.resource_title.clone(),
+            chunk_index: result.chunk_index,
+            chunk_text: result.chunk_text.clone(),
+            raw_score,
+            reranked_score: traced.reranked.then_some(result.score),
+            dropped_by_min_score: dropped,
+        });
+        if !dropped {
+            kept.push(result);
+        }
+    }
+
+    let full_prompt = build_prompt(&kept);
+    let prompt_chars = full_prompt.chars().count();
+    let (prompt, prompt_truncated) = match prompt_max_chars {
+        Some(max) if prompt_chars > max => (full_prompt.chars().take(max).collect(), true),
+        _ => (full_prompt, false),
+    };
+
+    let trace = RagDebugTrace {
+        query: query.to_string(),
+        effective_query: query.to_string(),
+        expanded_queries,
+        embedding_model_override: None,
+        reranked: traced.reranked,
+        min_score: threshold,
+        dropped_count: candidates.iter().filter(|c| c.dropped_by_min_score).count(),
+        candidates,
+        prompt,
+        prompt_truncated,
+        prompt_chars,
+    };
+    (kept, trace)
+}
+
+/// 用保留的片段拼装系统提示词（默认系统提示 + 知识库上下文 + 引用规范）
+fn build_prompt(results: &[VfsSearchResult]) -> String {
+    let sources: Vec<SourceInfo> = results
+        .iter()
+        .map(|r| SourceInfo {
+            title: r.resource_title.clone(),
+            url: None,
+            snippet: Some(r.chunk_text.clone()),
+            score: Some(r.score as f32),
+            metadata: Some(json!({
+                "resourceId": r.resource_id,
+                "chunkIndex": r.chunk_index,
+                "embeddingId": r.embedding_id,
+            })),
+        })
+        .collect();
+    PromptBuilder::new(None)
+        .with_rag_sources(Some(&sources))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn result(id: &str, score: f64) -> VfsSearchResult {
+        VfsSearchResult {
+            embedding_id: id.to_string(),
+            resource_id: format!("res_{}", id),
+            chunk_index: 0,
+            chunk_text: format!("片段 {}", id),
+            score,
+            resource_title: Some(format!("文档 {}", id)),
+            resource_type: None,
+            page_index: None,
+            source_id: None,
+            folder_id: None,
+        }
+    }
+
+    fn traced(reranked: bool) -> VfsScoreTracedResults {
+        VfsScoreTracedResults {
+            results: vec![result("a", 0.9), result("b", 0.2)],
+            raw_scores: HashMap::from([("a".to_string(), 0.5), ("b".to_string(), 0.6)]),
+            reranked,
+        }
+    }
+
+    #[test]
+    fn test_min_score_drops_on_final_score_and_records_both_scores() {
+        let (kept, trace) = apply_min_score_with_trace(
+            "牛顿",
+            vec!["牛顿第二定律".to_string()],
+            traced(true),
+            Some(0.3),
+            None,
+        );
+
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].embedding_id, "a");
+        assert_eq!(trace.dropped_count, 1);
+        assert_eq!(trace.expanded_queries, vec!["牛顿第二定律"]);
+        let b = &trace.candidates[1];
+        assert!(b.dropped_by_min_score);
+        assert_eq!(b.raw_score, 0.6);
+        assert_eq!(b.reranked_score, Some(0.2));
+
+        assert!(trace.prompt.contains("[知识库-1] 片段 a"));
+        assert!(!trace.prompt.contains("片段 b"));
+        assert!(!trace.prompt_truncated);
+    }
+
+    #[test]
+    fn test_no_threshold_and_prompt_truncation() {
+        let (kept, trace) =
+            apply_min_score_with_trace("q", Vec::new(), traced(false), Some(0.0), Some(10));
+
+        assert_eq!(kept.len(), 2);
+        assert_eq!(trace.min_score, None);
+        assert_eq!(trace.dropped_count, 0);
+        assert_eq!(trace.candidates[0].reranked_score, None);
+        assert!(trace.prompt_truncated);
+        assert_eq!(trace.prompt.chars().count(), 10);
+        assert!(trace.prompt_chars > 10);
+    }
+}
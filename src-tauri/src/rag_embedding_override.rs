@@ -0,0 +1,86 @@
+//! 单次检索临时指定查询嵌入模型
+//!
+//! `vfs_rag_search` 可传 `embedding_model_override`（嵌入模型配置 ID），只用该模型为本次
+//! 查询（含查询扩展）生成向量，知识库索引与全局嵌入模型分配都不变。查询向量的维度必须与
+//! 知识库中已索引的某个维度一致，否则直接报错；覆盖生效时只检索该维度，不做跨维度聚合。
+//!
+//! 这是高级用法：维度相同不代表向量空间相同，用与索引时不同的模型检索通常会明显降低召回。
+//! 实际使用的模型会记录在调试轨迹中，便于复现。
+
+use serde::Serialize;
+
+/// 调试轨迹中记录的覆盖模型
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingModelOverride {
+    pub model_config_id: String,
+    pub model_name: String,
+    pub dimension: usize,
+}
+
+/// 已校验的覆盖模型及用它生成的原查询向量
+#[derive(Debug, Clone)]
+pub struct QueryEmbeddingOverride {
+    pub model: EmbeddingModelOverride,
+    pub query_embedding: Vec<f32>,
+}
+
+/// 空字符串视为未指定
+pub fn normalize_override(model_config_id: Option<&str>) -> Option<String> {
+    model_config_id
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+/// 覆盖模型的向量维度不在知识库已索引维度中时，返回错误说明
+pub fn dimension_mismatch(
+    model_name: &str,
+    dimension: usize,
+    stored_dimensions: &[i32],
+) -> Option<String> {
+    if stored_dimensions.iter().any(|d| *d as usize == dimension) {
+        return None;
+    }
+    if stored_dimensions.is_empty() {
+        return Some(format!(
+            "嵌入模型 {} 输出 {} 维向量，但知识库中还没有已索引的向量",
+            model_name, dimension
+        ));
+    }
+    Some(format!(
+        "嵌入模型 {} 输出 {} 维向量，与知识库已索引的维度（{}）不一致；请改用同维度的嵌入模型",
+        model_name,
+        dimension,
+        stored_dimensions
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_override() {
+        assert_eq!(normalize_override(None), None);
+        assert_eq!(normalize_override(Some("  ")), None);
+        assert_eq!(
+            normalize_override(Some(" emb_bge ")),
+            Some("emb_bge".to_string())
+        );
+    }
+
+    #[test]
+    fn test_dimension_must_match_stored_dimension() {
+        assert!(dimension_mismatch("bge-m3", 1024, &[768, 1024]).is_none());
+        let message = dimension_mismatch("text-embedding-3-small", 1536, &[1024]).unwrap();
+        assert!(message.contains("1536"));
+        assert!(message.contains("（1024）"));
+        let empty = dimension_mismatch("bge-m3", 1024, &[]).unwrap();
+        assert!(empty.contains("还没有已索引的向量"));
+    }
+}
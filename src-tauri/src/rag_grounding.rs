@@ -0,0 +1,264 @@
+//! RAG 回答溯源校验（幻觉标记）
+//!
+//! 生成回答后，可选地再调用一次模型，逐句判断回答中的论断是否被引用的检索片段支持，
+//! 返回 `grounding_score`（被支持论断占比）与未被支持的句子，供教师决定采信或丢弃。
+//!
+//! 该校验会额外消耗一次模型调用，默认关闭；可单独指定更便宜的模型，
+//! 未指定时使用模型二。配置保存在设置项 `rag.grounding_check.config`（JSON）。
+
+use crate::database::Database;
+use crate::llm_manager::LLMManager;
+use crate::models::{AppError, RagSourceInfo};
+use serde::{Deserialize, Serialize};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const GROUNDING_CHECK_SETTINGS_KEY: &str = "rag.grounding_check.config";
+
+/// 单个片段送入校验提示词的最大字符数
+const MAX_SOURCE_CHARS: usize = 1500;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GroundingCheckSettings {
+    pub enabled: bool,
+    /// 校验使用的模型配置 ID；未设置时使用模型二
+    pub model_config_id: Option<String>,
+    /// 最多送入校验的片段数
+    pub max_sources: usize,
+}
+
+impl Default for GroundingCheckSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model_config_id: None,
+            max_sources: 8,
+        }
+    }
+}
+
+impl GroundingCheckSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_sources == 0 {
+            return Err(AppError::validation("max_sources 必须大于 0"));
+        }
+        Ok(())
+    }
+}
+
+/// 读取校验配置；未设置或解析失败时使用默认值（关闭）
+pub fn load_grounding_check_settings(db: &Database) -> GroundingCheckSettings {
+    db.get_setting(GROUNDING_CHECK_SETTINGS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<GroundingCheckSettings>(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_grounding_check_settings(
+    db: &Database,
+    settings: &GroundingCheckSettings,
+) -> Result<()> {
+    settings.validate()?;
+    let json = serde_json::to_string(settings)
+        .map_err(|e| AppError::internal(format!("序列化溯源校验配置失败: {}", e)))?;
+    db.save_setting(GROUNDING_CHECK_SETTINGS_KEY, &json)
+        .map_err(|e| AppError::database(e.to_string()))
+}
+
+/// 未被检索片段支持的句子
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsupportedSentence {
+    pub sentence: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingReport {
+    /// 被支持论断占比（0~1）；回答中没有可校验论断时为 1
+    pub grounding_score: f32,
+    pub checked_claims: usize,
+    pub unsupported_sentences: Vec<UnsupportedSentence>,
+    pub model_config_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaimVerdict {
+    #[serde(default)]
+    sentence: String,
+    #[serde(default)]
+    supported: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VerdictResponse {
+    #[serde(default)]
+    claims: Vec<ClaimVerdict>,
+}
+
+fn build_grounding_prompt(answer: &str, sources: &[RagSourceInfo]) -> String {
+    let mut prompt = String::from(
+        "你是一名严谨的事实核查员。下面给出若干检索片段和一段基于这些片段生成的回答。\n\
+         请把回答拆分为包含事实论断的句子（忽略寒暄、过渡语和纯推理步骤），\n\
+         逐句判断该论断能否由检索片段直接支持。不要使用片段以外的知识。\n\n\
+         只输出 JSON，格式如下：\n\
+         {\"claims\": [{\"sentence\": \"回答中的原句\", \"supported\": true, \"reason\": \"不支持时说明原因\"}]}\n\n\
+         【检索片段】\n",
+    );
+    for (idx, source) in sources.iter().enumerate() {
+        let text: String = source.chunk_text.chars().take(MAX_SOURCE_CHARS).collect();
+        prompt.push_str(&format!(
+            "[{}] 《{}》\n{}\n\n",
+            idx + 1,
+            source.file_name,
+            text
+        ));
+    }
+    prompt.push_str("【回答】\n");
+    prompt.push_str(answer);
+    prompt
+}
+
+/// 从模型输出中提取 JSON 对象（兼容代码块包裹和前后说明文字）
+fn parse_grounding_response(response: &str) -> Option<Vec<ClaimVerdict>> {
+    let start = response.find('{')?;
+    let end = response.rfind('}')?;
+    if end < start {
+        return None;
+    }
+    serde_json::from_str::<VerdictResponse>(&response[start..=end])
+        .ok()
+        .map(|parsed| {
+            parsed
+                .claims
+                .into_iter()
+                .filter(|c| !c.sentence.trim().is_empty())
+                .collect()
+        })
+}
+
+fn summarize_verdicts(
+    verdicts: Vec<ClaimVerdict>,
+    model_config_id: Option<String>,
+) -> GroundingReport {
+    let checked_claims = verdicts.len();
+    let unsupported_sentences: Vec<UnsupportedSentence> = verdicts
+        .into_iter()
+        .filter(|v| !v.supported)
+        .map(|v| UnsupportedSentence {
+            sentence: v.sentence.trim().to_string(),
+            reason: v.reason.filter(|r| !r.trim().is_empty()),
+        })
+        .collect();
+    let grounding_score = if checked_claims == 0 {
+        1.0
+    } else {
+        (checked_claims - unsupported_sentences.len()) as f32 / checked_claims as f32
+    };
+    GroundingReport {
+        grounding_score,
+        checked_claims,
+        unsupported_sentences,
+        model_config_id,
+    }
+}
+
+/// 校验回答是否被检索片段支持
+///
+/// 不检查开关，调用方需先确认 `settings.enabled`。
+pub async fn check_answer_grounding(
+    llm_manager: &LLMManager,
+    settings: &GroundingCheckSettings,
+    answer: &str,
+    sources: &[RagSourceInfo],
+) -> Result<GroundingReport> {
+    if answer.trim().is_empty() {
+        return Err(AppError::validation("回答内容为空"));
+    }
+    if sources.is_empty() {
+        return Err(AppError::validation("没有可用于校验的检索片段"));
+    }
+
+    let sources = &sources[..sources.len().min(settings.max_sources)];
+    let prompt = build_grounding_prompt(answer, sources);
+    let output = llm_manager
+        .call_raw_prompt_with_model(settings.model_config_id.as_deref(), &prompt)
+        .await?;
+    let verdicts = parse_grounding_response(&output.assistant_message).ok_or_else(|| {
+        AppError::llm(format!(
+            "无法解析溯源校验结果: {}",
+            output
+                .assistant_message
+                .chars()
+                .take(200)
+                .collect::<String>()
+        ))
+    })?;
+    Ok(summarize_verdicts(
+        verdicts,
+        settings.model_config_id.clone(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response_with_code_fence() {
+        let response = "```json\n{\"claims\": [\
+            {\"sentence\": \"光速约为 3×10^8 m/s。\", \"supported\": true},\
+            {\"sentence\": \"牛顿在 1905 年提出相对论。\", \"supported\": false, \"reason\": \"片段未提及\"},\
+            {\"sentence\": \" \", \"supported\": false}\
+        ]}\n```";
+        let verdicts = parse_grounding_response(response).unwrap();
+        assert_eq!(verdicts.len(), 2);
+
+        let report = summarize_verdicts(verdicts, None);
+        assert_eq!(report.checked_claims, 2);
+        assert!((report.grounding_score - 0.5).abs() < f32::EPSILON);
+        assert_eq!(report.unsupported_sentences.len(), 1);
+        assert_eq!(
+            report.unsupported_sentences[0].reason.as_deref(),
+            Some("片段未提及")
+        );
+    }
+
+    #[test]
+    fn test_no_claims_scores_fully_grounded_and_garbage_fails() {
+        let report =
+            summarize_verdicts(parse_grounding_response("{\"claims\": []}").unwrap(), None);
+        assert_eq!(report.grounding_score, 1.0);
+        assert!(parse_grounding_response("无法判断").is_none());
+    }
+
+    #[test]
+    fn test_prompt_truncates_long_sources() {
+        let source = RagSourceInfo {
+            document_id: "d1".to_string(),
+            file_name: "物理.md".to_string(),
+            chunk_text: "很".repeat(MAX_SOURCE_CHARS + 100),
+            score: 0.9,
+            chunk_index: 0,
+        };
+        let prompt = build_grounding_prompt("回答", &[source]);
+        assert!(prompt.contains("[1] 《物理.md》"));
+        assert_eq!(prompt.matches('很').count(), MAX_SOURCE_CHARS);
+        assert!(prompt.ends_with("【回答】\n回答"));
+    }
+
+    #[test]
+    fn test_settings_default_disabled() {
+        let parsed: GroundingCheckSettings =
+            serde_json::from_str(r#"{"enabled":true,"modelConfigId":"cheap"}"#).unwrap();
+        assert!(parsed.enabled);
+        assert_eq!(parsed.max_sources, 8);
+        assert!(!GroundingCheckSettings::default().enabled);
+    }
+}
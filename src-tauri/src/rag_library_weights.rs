@@ -0,0 +1,132 @@
+//! 多知识库检索的库权重
+//!
+//! 同时检索多个知识库（VFS 文件夹）时，可为每个库指定权重：片段分数在跨维度合并与
+//! 截断之前乘以所属库的权重，从而偏向更可信的库（如精选笔记优先于自动导入的教材）。
+//! 启用重排序时，重排序分数同样按库加权。未列出的库权重为 1，不传权重时检索行为不变；
+//! 权重为 0 的库不参与结果。
+//!
+//! 权重非均等时每个维度多取一倍候选，避免高权重库的片段在加权前就被截断。
+
+use std::collections::HashMap;
+
+use crate::models::AppError;
+use crate::vfs::indexing::VfsSearchResult;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+/// 未指定权重的库
+const DEFAULT_LIBRARY_WEIGHT: f32 = 1.0;
+
+/// 权重须为非负有限数，且至少一个为正
+pub fn validate_library_weights(weights: &HashMap<String, f32>) -> Result<()> {
+    if let Some((library, weight)) = weights.iter().find(|(_, w)| !w.is_finite() || **w < 0.0) {
+        return Err(AppError::validation(format!(
+            "知识库 {} 的权重 {} 无效，权重必须为非负数",
+            library, weight
+        )));
+    }
+    if !weights.is_empty() && !weights.values().any(|w| *w > 0.0) {
+        return Err(AppError::validation("至少需要一个知识库的权重大于 0"));
+    }
+    Ok(())
+}
+
+pub fn library_weight(weights: &HashMap<String, f32>, folder_id: Option<&str>) -> f32 {
+    folder_id
+        .and_then(|id| weights.get(id))
+        .copied()
+        .unwrap_or(DEFAULT_LIBRARY_WEIGHT)
+}
+
+fn is_weighted(weights: Option<&HashMap<String, f32>>) -> bool {
+    weights.is_some_and(|w| w.values().any(|v| *v != DEFAULT_LIBRARY_WEIGHT))
+}
+
+/// 每个维度向向量库请求的候选数
+pub fn fetch_limit(top_k: u32, weights: Option<&HashMap<String, f32>>) -> usize {
+    if is_weighted(weights) {
+        top_k as usize * 2
+    } else {
+        top_k as usize
+    }
+}
+
+/// 按所属库加权片段分数并重新排序；权重为 0 的片段被移除
+pub fn apply_library_weights(
+    results: &mut Vec<VfsSearchResult>,
+    weights: Option<&HashMap<String, f32>>,
+) {
+    let Some(weights) = weights.filter(|_| is_weighted(weights)) else {
+        return;
+    };
+    results.retain_mut(|result| {
+        let weight = library_weight(weights, result.folder_id.as_deref());
+        result.score *= weight as f64;
+        weight > 0.0
+    });
+    results.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(id: &str, folder: &str, score: f64) -> VfsSearchResult {
+        VfsSearchResult {
+            embedding_id: id.to_string(),
+            resource_id: format!("res_{}", id),
+            chunk_index: 0,
+            chunk_text: String::new(),
+            score,
+            resource_title: None,
+            resource_type: None,
+            page_index: None,
+            source_id: None,
+            folder_id: Some(folder.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_weights_reorder_and_drop_zero_weight_libraries() {
+        let mut results = vec![
+            result("textbook", "fld_book", 0.8),
+            result("note", "fld_notes", 0.6),
+            result("muted", "fld_muted", 0.9),
+            result("other", "fld_other", 0.7),
+        ];
+        let weights = HashMap::from([
+            ("fld_notes".to_string(), 2.0),
+            ("fld_book".to_string(), 1.0),
+            ("fld_muted".to_string(), 0.0),
+        ]);
+        apply_library_weights(&mut results, Some(&weights));
+        let order: Vec<&str> = results.iter().map(|r| r.embedding_id.as_str()).collect();
+        assert_eq!(order, vec!["note", "textbook", "other"]);
+        assert!((results[0].score - 1.2).abs() < 1e-6);
+        assert_eq!(fetch_limit(5, Some(&weights)), 10);
+
+        // 均等权重保持原有行为
+        let equal = HashMap::from([("fld_book".to_string(), 1.0)]);
+        let mut untouched = vec![result("a", "fld_book", 0.1), result("b", "x", 0.5)];
+        apply_library_weights(&mut untouched, Some(&equal));
+        assert_eq!(untouched[0].embedding_id, "a");
+        assert_eq!(fetch_limit(5, Some(&equal)), 5);
+    }
+
+    #[test]
+    fn test_validate_weights() {
+        assert!(validate_library_weights(&HashMap::new()).is_ok());
+        let ok = HashMap::from([("a".to_string(), 0.0), ("b".to_string(), 0.5)]);
+        assert!(validate_library_weights(&ok).is_ok());
+        let negative = HashMap::from([("a".to_string(), -1.0)]);
+        assert!(validate_library_weights(&negative).is_err());
+        let all_zero = HashMap::from([("a".to_string(), 0.0)]);
+        assert!(validate_library_weights(&all_zero).is_err());
+        let nan = HashMap::from([("a".to_string(), f32::NAN)]);
+        assert!(validate_library_weights(&nan).is_err());
+    }
+}
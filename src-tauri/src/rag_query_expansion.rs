@@ -0,0 +1,170 @@
+//! 知识库检索的查询扩展
+//!
+//! 学生的提问往往很短（如“求导”“牛二”），单条查询的召回率偏低。启用后先让模型
+//! 为原始查询生成几条同义改写 / 关键词扩展，每条单独检索，再按片段去重合并，
+//! 之后统一用原始查询重排序。
+//!
+//! 扩展失败（模型不可用、输出无法解析）只记录警告，退回单查询检索。
+
+use crate::llm_manager::LLMManager;
+use crate::rag_settings::{RagRetrievalSettings, MAX_QUERY_EXPANSIONS_LIMIT};
+use crate::vfs::indexing::VfsSearchResult;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{debug, warn};
+
+/// 单条扩展查询的最大字符数，超长输出视为无效
+const MAX_EXPANSION_CHARS: usize = 200;
+
+#[derive(Debug, Deserialize)]
+struct ExpansionResponse {
+    #[serde(default)]
+    queries: Vec<String>,
+}
+
+fn build_expansion_prompt(query: &str, max_expansions: usize) -> String {
+    format!(
+        "你是学习资料检索助手。学生在个人知识库中检索时输入了下面的查询，\
+         请生成至多 {max_expansions} 条用于检索的扩展查询：可以是同义改写、补全的完整问法，\
+         或相关的关键术语组合。保持原意，不要引入与问题无关的主题，不要重复原查询。\n\n\
+         只输出 JSON，格式如下：\n\
+         {{\"queries\": [\"扩展查询1\", \"扩展查询2\"]}}\n\n\
+         【原始查询】\n{query}"
+    )
+}
+
+/// 从模型输出中解析扩展查询：去空白、去重（忽略大小写）、排除原查询并截断到上限
+fn parse_expansions(response: &str, query: &str, max_expansions: usize) -> Option<Vec<String>> {
+    let start = response.find('{')?;
+    let end = response.rfind('}')?;
+    if end < start {
+        return None;
+    }
+    let parsed: ExpansionResponse = serde_json::from_str(&response[start..=end]).ok()?;
+
+    let mut seen = vec![query.trim().to_lowercase()];
+    let mut expansions = Vec::new();
+    for candidate in parsed.queries {
+        let candidate = candidate.trim();
+        if candidate.is_empty() || candidate.chars().count() > MAX_EXPANSION_CHARS {
+            continue;
+        }
+        let key = candidate.to_lowercase();
+        if seen.contains(&key) {
+            continue;
+        }
+        seen.push(key);
+        expansions.push(candidate.to_string());
+        if expansions.len() >= max_expansions {
+            break;
+        }
+    }
+    Some(expansions)
+}
+
+/// 按配置生成扩展查询；未启用或失败时返回空列表（即只用原查询检索）
+pub async fn expand_query(
+    llm_manager: &LLMManager,
+    settings: &RagRetrievalSettings,
+    query: &str,
+) -> Vec<String> {
+    if !settings.enable_query_expansion || query.trim().is_empty() {
+        return Vec::new();
+    }
+    let max_expansions = settings
+        .max_query_expansions
+        .clamp(1, MAX_QUERY_EXPANSIONS_LIMIT);
+    let prompt = build_expansion_prompt(query.trim(), max_expansions);
+
+    let output = match llm_manager
+        .call_raw_prompt_with_model(settings.query_expansion_model_id.as_deref(), &prompt)
+        .await
+    {
+        Ok(output) => output,
+        Err(e) => {
+            warn!("[QueryExpansion] 生成扩展查询失败，使用原查询: {}", e);
+            return Vec::new();
+        }
+    };
+    match parse_expansions(&output.assistant_message, query, max_expansions) {
+        Some(expansions) => {
+            debug!("[QueryExpansion] '{}' -> {:?}", query, expansions);
+            expansions
+        }
+        None => {
+            warn!("[QueryExpansion] 无法解析扩展查询输出，使用原查询");
+            Vec::new()
+        }
+    }
+}
+
+/// 合并多条查询的检索结果：同一片段（resource_id + chunk_index）保留最高分，按分数降序排列
+///
+/// 与跨维度检索的去重口径一致；不截断，交给后续重排序 / top_k 处理。
+pub fn merge_search_results(result_sets: Vec<Vec<VfsSearchResult>>) -> Vec<VfsSearchResult> {
+    let mut merged: Vec<VfsSearchResult> = Vec::new();
+    let mut positions: HashMap<(String, i32), usize> = HashMap::new();
+    for result in result_sets.into_iter().flatten() {
+        let key = (result.resource_id.clone(), result.chunk_index);
+        match positions.get(&key) {
+            Some(&idx) => {
+                if result.score > merged[idx].score {
+                    merged[idx] = result;
+                }
+            }
+            None => {
+                positions.insert(key, merged.len());
+                merged.push(result);
+            }
+        }
+    }
+    merged.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(id: &str, score: f64) -> VfsSearchResult {
+        VfsSearchResult {
+            embedding_id: id.to_string(),
+            resource_id: format!("res_{}", id),
+            chunk_index: 0,
+            chunk_text: String::new(),
+            score,
+            resource_title: None,
+            resource_type: None,
+            page_index: None,
+            source_id: None,
+            folder_id: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_expansions_dedupes_and_caps() {
+        let response = r#"好的：
+```json
+{"queries": ["牛顿第二定律", "  F=ma 公式含义 ", "牛二", "f=MA 公式含义", "", "加速度与合外力的关系"]}
+```"#;
+        let expansions = parse_expansions(response, "牛二", 2).unwrap();
+        assert_eq!(expansions, vec!["牛顿第二定律", "F=ma 公式含义"]);
+
+        assert!(parse_expansions("无法生成", "q", 3).is_none());
+    }
+
+    #[test]
+    fn test_merge_keeps_best_score_per_chunk() {
+        let merged = merge_search_results(vec![
+            vec![result("a", 0.5), result("b", 0.4)],
+            vec![result("b", 0.8), result("c", 0.1)],
+        ]);
+        let ids: Vec<_> = merged.iter().map(|r| r.embedding_id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "a", "c"]);
+        assert_eq!(merged[0].score, 0.8);
+    }
+}
@@ -0,0 +1,157 @@
+//! 知识库检索参数
+//!
+//! `min_score` 为送入模型前的最低相似度阈值，低于该值的知识库片段直接丢弃。
+//! 默认 0 表示不过滤：启用混合检索时得分为 RRF 融合分（通常远小于 1），
+//! 与纯向量检索的余弦相似度不在同一量纲，需按实际检索模式调整。
+//!
+//! 配置保存在设置项 `rag.retrieval.config`（JSON），单次调用可通过
+//! `SendOptions.rag_min_score` 或检索工具的 `min_score` 参数覆盖。
+//!
+//! `enable_query_expansion` 开启后，检索前用模型为查询生成至多
+//! `max_query_expansions` 条改写 / 关键词扩展，逐条检索后合并再重排序
+//! （见 `rag_query_expansion`）；关闭时检索流程不变。
+//!
+//! 嵌入批大小 `embedding_batch_size` 保存在 `rag_configurations` 表，并同步到 VFS 索引配置，
+//! 决定导入文档与重建索引时每次嵌入请求包含的分块数。
+
+use crate::database::Database;
+use crate::models::AppError;
+use serde::{Deserialize, Serialize};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const RAG_RETRIEVAL_SETTINGS_KEY: &str = "rag.retrieval.config";
+
+/// 查询扩展条数上限
+pub const MAX_QUERY_EXPANSIONS_LIMIT: usize = 5;
+
+/// 默认嵌入批大小
+pub const DEFAULT_EMBEDDING_BATCH_SIZE: usize = 16;
+/// 嵌入批大小上限，过大的请求容易触发接口的单次输入限制
+pub const MAX_EMBEDDING_BATCH_SIZE: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RagRetrievalSettings {
+    /// 最低相似度阈值（0~1），0 表示不过滤
+    pub min_score: f32,
+    /// 是否启用查询扩展（默认关闭）
+    pub enable_query_expansion: bool,
+    /// 每次检索最多生成的扩展查询条数
+    pub max_query_expansions: usize,
+    /// 生成扩展查询使用的模型配置 ID；未设置时使用模型二
+    pub query_expansion_model_id: Option<String>,
+}
+
+impl Default for RagRetrievalSettings {
+    fn default() -> Self {
+        Self {
+            min_score: 0.0,
+            enable_query_expansion: false,
+            max_query_expansions: 3,
+            query_expansion_model_id: None,
+        }
+    }
+}
+
+impl RagRetrievalSettings {
+    pub fn validate(&self) -> Result<()> {
+        validate_min_score(self.min_score)?;
+        if !(1..=MAX_QUERY_EXPANSIONS_LIMIT).contains(&self.max_query_expansions) {
+            return Err(AppError::validation(format!(
+                "max_query_expansions 必须在 1 到 {} 之间",
+                MAX_QUERY_EXPANSIONS_LIMIT
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_min_score(min_score: f32) -> Result<()> {
+    if !(0.0..=1.0).contains(&min_score) {
+        return Err(AppError::validation("min_score 必须在 0 到 1 之间"));
+    }
+    Ok(())
+}
+
+pub fn validate_embedding_batch_size(batch_size: usize) -> Result<()> {
+    if !(1..=MAX_EMBEDDING_BATCH_SIZE).contains(&batch_size) {
+        return Err(AppError::validation(format!(
+            "embedding_batch_size 必须在 1 到 {} 之间",
+            MAX_EMBEDDING_BATCH_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// 读取嵌入批大小；未配置或超出范围时使用默认值
+pub fn load_embedding_batch_size(db: &Database) -> usize {
+    db.get_rag_configuration()
+        .ok()
+        .flatten()
+        .and_then(|config| usize::try_from(config.embedding_batch_size).ok())
+        .filter(|size| validate_embedding_batch_size(*size).is_ok())
+        .unwrap_or(DEFAULT_EMBEDDING_BATCH_SIZE)
+}
+
+pub fn save_embedding_batch_size(db: &Database, batch_size: usize) -> Result<()> {
+    validate_embedding_batch_size(batch_size)?;
+    db.update_rag_embedding_batch_size(batch_size as i32)
+        .map_err(|e| AppError::database(e.to_string()))
+}
+
+/// 读取检索参数；未设置或解析失败时使用默认值
+pub fn load_rag_retrieval_settings(db: &Database) -> RagRetrievalSettings {
+    db.get_setting(RAG_RETRIEVAL_SETTINGS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<RagRetrievalSettings>(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_rag_retrieval_settings(db: &Database, settings: &RagRetrievalSettings) -> Result<()> {
+    settings.validate()?;
+    let json = serde_json::to_string(settings)
+        .map_err(|e| AppError::internal(format!("序列化检索参数失败: {}", e)))?;
+    db.save_setting(RAG_RETRIEVAL_SETTINGS_KEY, &json)
+        .map_err(|e| AppError::database(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_disables_filtering_and_validates_range() {
+        let parsed: RagRetrievalSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(parsed.min_score, 0.0);
+        assert!(parsed.validate().is_ok());
+
+        assert!(validate_min_score(0.35).is_ok());
+        assert!(validate_min_score(-0.1).is_err());
+        assert!(validate_min_score(1.5).is_err());
+        assert!(validate_min_score(f32::NAN).is_err());
+    }
+
+    #[test]
+    fn test_query_expansion_defaults_off_and_validates_cap() {
+        let parsed: RagRetrievalSettings = serde_json::from_str(r#"{"minScore":0.2}"#).unwrap();
+        assert!(!parsed.enable_query_expansion);
+        assert_eq!(parsed.max_query_expansions, 3);
+
+        let too_many = RagRetrievalSettings {
+            max_query_expansions: MAX_QUERY_EXPANSIONS_LIMIT + 1,
+            ..Default::default()
+        };
+        assert!(too_many.validate().is_err());
+    }
+
+    #[test]
+    fn test_embedding_batch_size_range() {
+        assert!(validate_embedding_batch_size(DEFAULT_EMBEDDING_BATCH_SIZE).is_ok());
+        assert!(validate_embedding_batch_size(1).is_ok());
+        assert!(validate_embedding_batch_size(MAX_EMBEDDING_BATCH_SIZE).is_ok());
+        assert!(validate_embedding_batch_size(0).is_err());
+        assert!(validate_embedding_batch_size(MAX_EMBEDDING_BATCH_SIZE + 1).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! AI 回答语言
+//!
+//! 回答语言与界面语言相互独立：设置项 `chat.response_language` 保存全局默认值，
+//! 单次请求可通过 `SendOptions.response_language` / `AnkiGenerationOptions.response_language` 覆盖。
+//! 取值为语言代码（`zh`、`en` 等，也接受“English”这类自由写法）或 `auto`；
+//! `auto` 根据用户问题（制卡时为学习材料）的文字推断语言。未设置时不注入任何语言约束，
+//! 保持原有行为。
+//!
+//! 对话中每轮实际使用的语言写入助手消息 `chatParams.responseLanguage`，
+//! 同一会话后续轮次优先沿用，避免全局设置变更后同一会话前后语言不一致。
+
+use crate::database::Database;
+
+pub const RESPONSE_LANGUAGE_SETTING_KEY: &str = "chat.response_language";
+
+/// 按问题文字自动推断
+pub const AUTO_LANGUAGE: &str = "auto";
+
+/// 常用语言：(代码, 别名, 提示词中的名称)
+const KNOWN_LANGUAGES: &[(&str, &[&str], &str)] = &[
+    (
+        "zh",
+        &["zh-cn", "zh-hans", "chinese", "中文", "简体中文"],
+        "简体中文",
+    ),
+    (
+        "zh-tw",
+        &["zh-hant", "zh-hk", "繁體中文", "繁体中文"],
+        "繁體中文",
+    ),
+    (
+        "en",
+        &["en-us", "en-gb", "english", "英文", "英语"],
+        "English",
+    ),
+    (
+        "ja",
+        &["ja-jp", "japanese", "日文", "日语", "日本語"],
+        "日本語",
+    ),
+    (
+        "ko",
+        &["ko-kr", "korean", "韩文", "韩语", "한국어"],
+        "한국어",
+    ),
+    ("fr", &["fr-fr", "french", "法语", "français"], "Français"),
+    ("de", &["de-de", "german", "德语", "deutsch"], "Deutsch"),
+    (
+        "es",
+        &["es-es", "spanish", "西班牙语", "español"],
+        "Español",
+    ),
+];
+
+/// 规范化语言取值：常用语言的别名统一为代码，空值返回 None，其余原样保留
+pub fn normalize_language(value: &str) -> Option<String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let lower = trimmed.to_lowercase().replace('_', "-");
+    if lower == AUTO_LANGUAGE {
+        return Some(AUTO_LANGUAGE.to_string());
+    }
+    KNOWN_LANGUAGES
+        .iter()
+        .find(|(code, aliases, _)| *code == lower || aliases.contains(&lower.as_str()))
+        .map(|(code, _, _)| code.to_string())
+        .or_else(|| Some(trimmed.to_string()))
+}
+
+/// 提示词中使用的语言名称
+pub fn language_display_name(code: &str) -> String {
+    KNOWN_LANGUAGES
+        .iter()
+        .find(|(known, _, _)| *known == code)
+        .map(|(_, _, name)| name.to_string())
+        .unwrap_or_else(|| code.to_string())
+}
+
+/// 根据文字推断语言
+///
+/// 以汉字 / 假名 / 谚文字符数与拉丁单词数比较（一个中文词约两个字），避免中文问题里
+/// 夹带的英文术语被判成英文；没有可判断的文字时返回 None。
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let (mut han, mut kana, mut hangul, mut latin_words) = (0usize, 0usize, 0usize, 0usize);
+    let mut in_word = false;
+    for c in text.chars() {
+        let is_latin = c.is_ascii_alphabetic();
+        if is_latin && !in_word {
+            latin_words += 1;
+        }
+        in_word = is_latin;
+        match c as u32 {
+            0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0xF900..=0xFAFF => han += 1,
+            0x3040..=0x30FF => kana += 1,
+            0xAC00..=0xD7AF | 0x1100..=0x11FF => hangul += 1,
+            _ => {}
+        }
+    }
+    let cjk = han + kana + hangul;
+    if cjk == 0 && latin_words == 0 {
+        return None;
+    }
+    if cjk < latin_words * 2 {
+        return Some("en");
+    }
+    Some(if kana > 0 {
+        "ja"
+    } else if hangul > han {
+        "ko"
+    } else {
+        "zh"
+    })
+}
+
+/// 确定本次回答语言
+///
+/// 优先级：本次请求 > 会话已记录的语言 > 全局设置；结果为 `auto` 时按 `text` 推断。
+/// 返回 None 表示不约束回答语言。
+pub fn resolve_response_language(
+    request: Option<&str>,
+    session: Option<&str>,
+    setting: Option<&str>,
+    text: &str,
+) -> Option<String> {
+    let chosen = [request, session, setting]
+        .into_iter()
+        .flatten()
+        .find_map(normalize_language)?;
+    if chosen == AUTO_LANGUAGE {
+        detect_language(text).map(str::to_string)
+    } else {
+        Some(chosen)
+    }
+}
+
+/// 注入系统提示词的语言约束
+pub fn response_language_instruction(code: &str) -> String {
+    format!(
+        "<response_language>\n请使用{}撰写全部回答（包括分析、解释与总结），不受界面语言和参考资料语言的影响；代码、公式与专有名词可保留原文。\n</response_language>",
+        language_display_name(code)
+    )
+}
+
+/// 读取全局回答语言（通用设置接口写入）；未设置时返回 None
+pub fn load_response_language_setting(db: &Database) -> Option<String> {
+    db.get_setting(RESPONSE_LANGUAGE_SETTING_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| normalize_language(&raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language_prefers_cjk_over_embedded_terms() {
+        assert_eq!(detect_language("请解释 photosynthesis 的过程"), Some("zh"));
+        assert_eq!(
+            detect_language("Why does 牛顿第二定律 hold in this case?"),
+            Some("en")
+        );
+        assert_eq!(detect_language("この問題を解いてください"), Some("ja"));
+        assert_eq!(detect_language("이 문제를 설명해 주세요"), Some("ko"));
+        assert_eq!(detect_language("1 + 1 = ?"), None);
+    }
+
+    #[test]
+    fn test_resolve_priority_and_auto() {
+        // 请求覆盖优先于会话记录
+        assert_eq!(
+            resolve_response_language(Some("English"), Some("zh"), None, "你好").as_deref(),
+            Some("en")
+        );
+        // 会话记录优先于全局设置
+        assert_eq!(
+            resolve_response_language(None, Some("zh"), Some("en"), "hello").as_deref(),
+            Some("zh")
+        );
+        // auto 按问题推断，空值跳过
+        assert_eq!(
+            resolve_response_language(Some(" "), None, Some("auto"), "What is entropy?").as_deref(),
+            Some("en")
+        );
+        assert_eq!(resolve_response_language(None, None, None, "hello"), None);
+        assert_eq!(normalize_language("Zh_CN").as_deref(), Some("zh"));
+        assert_eq!(normalize_language("Italiano").as_deref(), Some("Italiano"));
+        assert!(response_language_instruction("en").contains("English"));
+    }
+}
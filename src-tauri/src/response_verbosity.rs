@@ -0,0 +1,137 @@
+//! 回答详略程度
+//!
+//! 设置项 `chat.verbosity` 为对话默认值，`anki.verbosity` 为制卡默认值，两者相互独立：
+//! 对话选“详细”时卡片解释仍可保持简洁。单次请求可通过 `SendOptions.verbosity` /
+//! `AnkiGenerationOptions.verbosity` 覆盖。取值 `concise` / `balanced` / `detailed`
+//! （也接受首字母大写与中文写法）；未设置时不注入任何约束，保持原有行为。
+//!
+//! 详略程度映射为提示词中的篇幅要求。“简洁”在未显式设置 `max_tokens` 时另附输出上限，
+//! 避免不同模型篇幅差异过大；开启思维链时上限放宽，推理过程同样计入输出。
+//! 对话中每轮实际使用的取值写入助手消息 `chatParams.verbosity`，重试时沿用。
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::Database;
+
+pub const CHAT_VERBOSITY_SETTING_KEY: &str = "chat.verbosity";
+pub const CARD_VERBOSITY_SETTING_KEY: &str = "anki.verbosity";
+
+/// “简洁”的输出上限（tokens）
+const CONCISE_MAX_TOKENS: u32 = 2048;
+/// 开启思维链时“简洁”的输出上限
+const CONCISE_THINKING_MAX_TOKENS: u32 = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    #[serde(alias = "Concise")]
+    Concise,
+    #[serde(alias = "Balanced")]
+    Balanced,
+    #[serde(alias = "Detailed")]
+    Detailed,
+}
+
+impl Verbosity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "concise" | "简洁" => Some(Self::Concise),
+            "balanced" | "适中" => Some(Self::Balanced),
+            "detailed" | "详细" => Some(Self::Detailed),
+            _ => None,
+        }
+    }
+
+    /// 注入对话系统提示词的篇幅要求
+    pub fn chat_instruction(self) -> String {
+        let requirement = match self {
+            Self::Concise => {
+                "回答尽量简短：直接给出结论与关键步骤，省略寒暄、重复与不必要的背景，一般不超过几段。"
+            }
+            Self::Balanced => {
+                "回答篇幅适中：给出结论与必要的推理过程，避免冗长铺陈，也不要省略关键步骤。"
+            }
+            Self::Detailed => {
+                "回答尽量详细：完整展开推理过程，解释每一步的依据，必要时补充相关概念、易错点与示例。"
+            }
+        };
+        format!(
+            "<response_verbosity>\n{}\n</response_verbosity>",
+            requirement
+        )
+    }
+
+    /// 制卡提示词中对卡片解释篇幅的要求
+    pub fn card_instruction(self) -> &'static str {
+        match self {
+            Self::Concise => "解释篇幅：背面与解析只保留核心要点，每张卡片尽量一两句话说清。",
+            Self::Balanced => "解释篇幅：背面给出答案与简要理由，避免大段铺陈。",
+            Self::Detailed => "解释篇幅：背面可展开解析，说明推导过程、相关概念与易错点。",
+        }
+    }
+
+    /// 未显式设置 `max_tokens` 时附加的输出上限
+    pub fn max_tokens_hint(self, thinking: bool) -> Option<u32> {
+        match self {
+            Self::Concise if thinking => Some(CONCISE_THINKING_MAX_TOKENS),
+            Self::Concise => Some(CONCISE_MAX_TOKENS),
+            Self::Balanced | Self::Detailed => None,
+        }
+    }
+}
+
+/// 本次调用的输出上限：显式设置优先，其次为详略程度的上限
+pub fn effective_max_tokens(
+    explicit: Option<u32>,
+    verbosity: Option<Verbosity>,
+    thinking: bool,
+) -> Option<u32> {
+    explicit.or_else(|| verbosity.and_then(|v| v.max_tokens_hint(thinking)))
+}
+
+/// 读取详略程度设置（通用设置接口写入）；未设置或无法识别时返回 None
+pub fn load_verbosity_setting(db: &Database, key: &str) -> Option<Verbosity> {
+    db.get_setting(key)
+        .ok()
+        .flatten()
+        .and_then(|raw| Verbosity::parse(&raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_accepts_aliases_and_serde_forms() {
+        assert_eq!(Verbosity::parse(" Concise "), Some(Verbosity::Concise));
+        assert_eq!(Verbosity::parse("详细"), Some(Verbosity::Detailed));
+        assert_eq!(Verbosity::parse("verbose"), None);
+        let parsed: Verbosity = serde_json::from_str("\"Balanced\"").unwrap();
+        assert_eq!(parsed, Verbosity::Balanced);
+        assert_eq!(
+            serde_json::to_string(&Verbosity::Detailed).unwrap(),
+            "\"detailed\""
+        );
+    }
+
+    #[test]
+    fn test_max_tokens_hint_never_overrides_explicit_value() {
+        assert_eq!(
+            effective_max_tokens(Some(512), Some(Verbosity::Concise), false),
+            Some(512)
+        );
+        assert_eq!(
+            effective_max_tokens(None, Some(Verbosity::Concise), false),
+            Some(CONCISE_MAX_TOKENS)
+        );
+        assert_eq!(
+            effective_max_tokens(None, Some(Verbosity::Concise), true),
+            Some(CONCISE_THINKING_MAX_TOKENS)
+        );
+        assert_eq!(
+            effective_max_tokens(None, Some(Verbosity::Detailed), false),
+            None
+        );
+        assert_eq!(effective_max_tokens(None, None, false), None);
+    }
+}
@@ -0,0 +1,345 @@
+//! 统一的流式事件
+//!
+//! 各个流式命令（解析/回顾/RAG 对话、翻译、作文批改、题目批改、制卡）原先各自发送形状不同的事件，
+//! 前端只能按事件名猜测载荷结构。这里定义带类型标签的 [`StreamEvent`]，所有流统一通过
+//! [`STREAM_EVENT_CHANNEL`] 发送 [`StreamEnvelope`]：
+//!
+//! ```json
+//! { "streamId": "...", "source": "translation", "seq": 3, "type": "token", "content": "..." }
+//! ```
+//!
+//! `seq` 在同一个流内从 0 开始严格递增，前端据此排序并检测丢失的事件；`done` / `error`
+//! 为终止事件，之后该流的序号重新计数。流的执行函数持有 [`StreamSequenceGuard`]，
+//! 被中止、取消或 panic 而没有发出终止事件时，序号状态同样随流结束清理。
+//!
+//! 正文与思维链增量量大，每个增量只发一次（见 [`emit_delta`]）：兼容设置
+//! `streaming.legacy_events` 开启（默认）时走原有的各流事件，关闭后只走统一通道。
+//! 完成、错误、用量等低频事件两边都发送，前端可逐步迁移。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{Emitter, Runtime};
+
+/// 所有流共用的事件名
+pub const STREAM_EVENT_CHANNEL: &str = "stream_event";
+/// 兼容设置：增量是否仍走原有的各流事件；未设置时视为开启
+pub const LEGACY_STREAM_EVENTS_SETTING_KEY: &str = "streaming.legacy_events";
+
+static LEGACY_EVENTS: AtomicBool = AtomicBool::new(true);
+
+pub fn legacy_events_enabled() -> bool {
+    LEGACY_EVENTS.load(Ordering::Relaxed)
+}
+
+pub fn set_legacy_events_enabled(enabled: bool) {
+    LEGACY_EVENTS.store(enabled, Ordering::Relaxed);
+}
+
+fn parse_legacy_setting(value: Option<&str>) -> bool {
+    value.map_or(true, crate::offline_mode::parse_setting)
+}
+
+/// 启动时从数据库加载；未设置或读取失败时保持兼容模式
+pub fn load_legacy_events_setting(db: &crate::database::Database) {
+    let value = db
+        .get_setting(LEGACY_STREAM_EVENTS_SETTING_KEY)
+        .ok()
+        .flatten();
+    set_legacy_events_enabled(parse_legacy_setting(value.as_deref()));
+}
+
+/// 通用设置命令保存或删除设置项后调用，使修改立即生效
+pub fn apply_setting_change(key: &str, value: Option<&str>) {
+    if key == LEGACY_STREAM_EVENTS_SETTING_KEY {
+        set_legacy_events_enabled(parse_legacy_setting(value));
+    }
+}
+
+/// 事件来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamSource {
+    /// 统一模型二：错题解析、回顾分析、RAG 对话
+    Model,
+    Translation,
+    EssayGrading,
+    QbankGrading,
+    AnkiGeneration,
+}
+
+impl StreamSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Model => "model",
+            Self::Translation => "translation",
+            Self::EssayGrading => "essay_grading",
+            Self::QbankGrading => "qbank_grading",
+            Self::AnkiGeneration => "anki_generation",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// 正文增量
+    Token {
+        content: String,
+    },
+    /// 思维链增量
+    Thinking {
+        content: String,
+    },
+    ToolCall {
+        id: String,
+        name: String,
+        arguments: Value,
+    },
+    ToolResult {
+        id: String,
+        name: String,
+        result: Value,
+    },
+    /// 检索来源；`kind` 为 `rag`、`memory` 等
+    Sources {
+        kind: String,
+        sources: Value,
+    },
+    Usage {
+        usage: Value,
+    },
+    Error {
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+    },
+    /// 流结束；`result` 为该流的最终结果（如完整译文），被取消时 `cancelled` 为 true
+    Done {
+        #[serde(default)]
+        cancelled: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        result: Option<Value>,
+    },
+}
+
+impl StreamEvent {
+    pub fn token(content: impl Into<String>) -> Self {
+        Self::Token {
+            content: content.into(),
+        }
+    }
+
+    pub fn thinking(content: impl Into<String>) -> Self {
+        Self::Thinking {
+            content: content.into(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::Error {
+            message: message.into(),
+            code: None,
+        }
+    }
+
+    pub fn done(result: Option<Value>) -> Self {
+        Self::Done {
+            cancelled: false,
+            result,
+        }
+    }
+
+    pub fn cancelled() -> Self {
+        Self::Done {
+            cancelled: true,
+            result: None,
+        }
+    }
+
+    /// 终止事件之后流即结束
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Done { .. } | Self::Error { .. })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamEnvelope {
+    pub stream_id: String,
+    pub source: StreamSource,
+    pub seq: u64,
+    #[serde(flatten)]
+    pub event: StreamEvent,
+}
+
+/// 各个流的下一个序号；终止事件后或 [`StreamSequenceGuard`] 释放时移除
+static SEQUENCES: LazyLock<Mutex<HashMap<String, u64>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn sequence_key(source: StreamSource, stream_id: &str) -> String {
+    format!("{}:{}", source.as_str(), stream_id)
+}
+
+fn sequences() -> std::sync::MutexGuard<'static, HashMap<String, u64>> {
+    SEQUENCES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn next_seq(source: StreamSource, stream_id: &str, terminal: bool) -> u64 {
+    let key = sequence_key(source, stream_id);
+    let mut sequences = sequences();
+    let seq = sequences.get(&key).copied().unwrap_or(0);
+    if terminal {
+        sequences.remove(&key);
+    } else {
+        sequences.insert(key, seq + 1);
+    }
+    seq
+}
+
+/// 绑定到一次流执行的序号状态；释放时移除该流的序号
+///
+/// 须在流的所有事件（包括终止事件）发出之后才释放。
+#[must_use = "序号状态在守卫释放时清理"]
+pub struct StreamSequenceGuard {
+    key: String,
+}
+
+impl StreamSequenceGuard {
+    pub fn new(source: StreamSource, stream_id: &str) -> Self {
+        Self {
+            key: sequence_key(source, stream_id),
+        }
+    }
+}
+
+impl Drop for StreamSequenceGuard {
+    fn drop(&mut self) {
+        sequences().remove(&self.key);
+    }
+}
+
+/// 为事件分配序号并封装
+pub fn envelope(source: StreamSource, stream_id: &str, event: StreamEvent) -> StreamEnvelope {
+    StreamEnvelope {
+        stream_id: stream_id.to_string(),
+        source,
+        seq: next_seq(source, stream_id, event.is_terminal()),
+        event,
+    }
+}
+
+/// 在统一通道上发送一条流式事件；发送失败只记录日志
+pub fn emit_stream_event<R: Runtime>(
+    emitter: &impl Emitter<R>,
+    source: StreamSource,
+    stream_id: &str,
+    event: StreamEvent,
+) {
+    let envelope = envelope(source, stream_id, event);
+    if let Err(e) = emitter.emit(STREAM_EVENT_CHANNEL, &envelope) {
+        log::warn!(
+            "[StreamEvent] 发送 {} 流事件失败 ({}): {}",
+            source.as_str(),
+            stream_id,
+            e
+        );
+    }
+}
+
+/// 发送一段正文或思维链增量，只走一个通道
+///
+/// 兼容模式下调用 `emit_legacy` 发送原有事件，否则在统一通道上发送 `event`。
+pub fn emit_delta<R: Runtime>(
+    emitter: &impl Emitter<R>,
+    source: StreamSource,
+    stream_id: &str,
+    event: StreamEvent,
+    emit_legacy: impl FnOnce(),
+) {
+    if legacy_events_enabled() {
+        emit_legacy();
+    } else {
+        emit_stream_event(emitter, source, stream_id, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sequence_increases_per_stream_and_resets_after_done() {
+        let source = StreamSource::Translation;
+        let first = envelope(source, "seq-a", StreamEvent::token("你"));
+        let other = envelope(source, "seq-b", StreamEvent::token("x"));
+        let second = envelope(source, "seq-a", StreamEvent::thinking("…"));
+        let done = envelope(source, "seq-a", StreamEvent::done(None));
+        assert_eq!((first.seq, second.seq, done.seq), (0, 1, 2));
+        assert_eq!(other.seq, 0);
+        // 不同来源的同名流互不影响
+        assert_eq!(
+            envelope(StreamSource::Model, "seq-a", StreamEvent::token("")).seq,
+            0
+        );
+        assert_eq!(envelope(source, "seq-a", StreamEvent::token("")).seq, 0);
+    }
+
+    #[test]
+    fn test_sequence_guard_clears_stream_without_terminal_event() {
+        let source = StreamSource::QbankGrading;
+        {
+            let _guard = StreamSequenceGuard::new(source, "seq-aborted");
+            assert_eq!(
+                envelope(source, "seq-aborted", StreamEvent::token("a")).seq,
+                0
+            );
+            assert_eq!(
+                envelope(source, "seq-aborted", StreamEvent::token("b")).seq,
+                1
+            );
+        }
+        assert!(!sequences().contains_key(&sequence_key(source, "seq-aborted")));
+        assert_eq!(
+            envelope(source, "seq-aborted", StreamEvent::token("c")).seq,
+            0
+        );
+        envelope(source, "seq-aborted", StreamEvent::cancelled());
+    }
+
+    #[test]
+    fn test_envelope_serializes_with_type_tag() {
+        let value = serde_json::to_value(StreamEnvelope {
+            stream_id: "s1".to_string(),
+            source: StreamSource::EssayGrading,
+            seq: 4,
+            event: StreamEvent::error("超时"),
+        })
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({"streamId": "s1", "source": "essay_grading", "seq": 4,
+                   "type": "error", "message": "超时"})
+        );
+
+        let parsed: StreamEnvelope = serde_json::from_value(json!({
+            "streamId": "s1", "source": "model", "seq": 0, "type": "done", "cancelled": true
+        }))
+        .unwrap();
+        assert_eq!(parsed.event, StreamEvent::cancelled());
+        assert!(parsed.event.is_terminal());
+        assert!(!StreamEvent::token("a").is_terminal());
+    }
+
+    #[test]
+    fn test_legacy_setting_defaults_to_enabled() {
+        assert!(parse_legacy_setting(None));
+        assert!(parse_legacy_setting(Some("true")));
+        assert!(!parse_legacy_setting(Some("false")));
+        assert!(!parse_legacy_setting(Some("0")));
+    }
+}
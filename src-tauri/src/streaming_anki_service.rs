@@ -1,10 +1,17 @@
+use crate::anki_card_limits::{self, CardCapReport};
+use crate::anki_card_output::{self, CardJsonScanner, CardOutputMode};
+use crate::anki_field_html;
+use crate::anki_raw_output::{self, RawOutputCapture};
+use crate::anki_source_tags;
 use crate::database::Database;
 use crate::llm_manager::ApiConfig;
 use crate::llm_manager::LLMManager;
+use crate::llm_manager::{StreamMode, StreamTimer};
 use crate::models::{
-    AnkiCard, AnkiGenerationOptions, AppError, DocumentTask, FieldExtractionRule, FieldType, StreamedCardPayload, TaskStatus, TemplateDescription,
+    AnkiCard, AnkiGenerationOptions, AppError, AppErrorType, DocumentTask, FieldExtractionRule, FieldType, StreamedCardPayload, TaskStatus, TemplateDescription,
 };
 use crate::providers::ProviderAdapter;
+use crate::stream_events::{emit_stream_event, StreamEvent, StreamSource};
 use chrono::Utc;
 use futures_util::StreamExt;
 use reqwest::Client;
@@ -21,6 +28,16 @@ use uuid::Uuid;
 
 const RETRY_ASSIGNMENT_MARK: &str = "[RETRY_ASSIGNED]";
 
+/// 流式中断后的最大重连次数（设置项，0 表示不重连）
+pub const STREAM_RECONNECT_MAX_RETRIES_KEY: &str = "anki.stream_reconnect.max_retries";
+/// 首次重连前的等待毫秒数，之后每次翻倍
+pub const STREAM_RECONNECT_BACKOFF_MS_KEY: &str = "anki.stream_reconnect.backoff_ms";
+const DEFAULT_STREAM_RECONNECT_RETRIES: u32 = 3;
+const DEFAULT_STREAM_RECONNECT_BACKOFF_MS: u64 = 2000;
+const MAX_STREAM_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+/// 续传提示中最多列出的已生成卡片数
+const RESUME_PROMPT_MAX_CARDS: usize = 50;
+
 #[derive(Clone)]
 pub struct StreamingAnkiService {
     db: Arc<Database>,
@@ -33,6 +50,44 @@ struct PromptPayload {
     system: Option<String>,
     user: String,
     debug_preview: String,
+    output_mode: CardOutputMode,
+    response_format: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct StreamReconnectPolicy {
+    max_retries: u32,
+    base_backoff: Duration,
+}
+
+impl StreamReconnectPolicy {
+    fn load(db: &Database) -> Self {
+        let read = |key: &str| db.get_setting(key).ok().flatten();
+        Self {
+            max_retries: read(STREAM_RECONNECT_MAX_RETRIES_KEY)
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_STREAM_RECONNECT_RETRIES),
+            base_backoff: Duration::from_millis(
+                read(STREAM_RECONNECT_BACKOFF_MS_KEY)
+                    .and_then(|v| v.trim().parse().ok())
+                    .unwrap_or(DEFAULT_STREAM_RECONNECT_BACKOFF_MS),
+            ),
+        }
+    }
+
+    /// 第 `attempt` 次重连（从 1 开始）前的等待时间：指数退避，封顶 30 秒
+    fn backoff_for(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        self.base_backoff
+            .saturating_mul(factor)
+            .min(MAX_STREAM_RECONNECT_BACKOFF)
+    }
+}
+
+/// 仅传输层错误（连接失败、读流中断、空闲超时、网关错误）可重连；
+/// 鉴权、限流、请求构建等模型侧错误重试无意义，直接失败。
+fn is_recoverable_stream_error(err: &AppError) -> bool {
+    matches!(err.error_type, AppErrorType::Network)
 }
 
 // 全局取消信号寄存（确保不同实例可见）
@@ -254,7 +309,12 @@ impl StreamingAnkiService {
         }
 
         // 构建prompt
-        let prompt_payload = match self.build_prompt(&task.content_segment, &options) {
+        let output_mode = anki_card_output::resolve_output_mode(
+            options.output_mode.as_deref(),
+            anki_card_output::load_output_mode_setting(&self.db).as_deref(),
+            &api_config,
+        );
+        let prompt_payload = match self.build_prompt(&task.content_segment, &options, output_mode) {
             Ok(p) => p,
             Err(err) => {
                 self.handle_task_error(
@@ -300,8 +360,11 @@ impl StreamingAnkiService {
             let mut senders = CANCEL_SENDERS.lock().await;
             senders.insert(task_id.clone(), cancel_tx);
         }
+        let mut caps = CardCapReport::default();
+        let mut raw_capture =
+            RawOutputCapture::new(&anki_raw_output::load_raw_output_capture_settings(&self.db));
         let result = self
-            .stream_cards_from_ai(
+            .stream_cards_with_reconnect(
                 &api_config,
                 &prompt_payload,
                 max_tokens,
@@ -310,15 +373,25 @@ impl StreamingAnkiService {
                 &task.document_id,
                 &window,
                 &options,
+                &mut caps,
+                &mut raw_capture,
                 pause_rx,
                 cancel_rx,
             )
             .await;
+        self.persist_raw_output_if_failed(&raw_capture, &task_id, &result);
 
         match result {
             Ok(card_count) => {
-                self.complete_task_successfully(&task_id, card_count, &task.document_id, &window)
-                    .await?;
+                self.complete_task_successfully(
+                    &task_id,
+                    card_count,
+                    caps.dropped,
+                    caps.truncation_message(&options),
+                    &task.document_id,
+                    &window,
+                )
+                .await?;
             }
             Err(e) => {
                 if e.message == "CANCELLED_BY_USER" {
@@ -387,6 +460,7 @@ impl StreamingAnkiService {
         &self,
         content: &str,
         options: &AnkiGenerationOptions,
+        output_mode: CardOutputMode,
     ) -> Result<PromptPayload, AppError> {
         // 获取基础prompt（优先级：模板prompt > 默认prompt）
         let base_prompt = if let Some(custom_prompt) = &options.custom_anki_prompt {
@@ -471,6 +545,31 @@ impl StreamingAnkiService {
             }
         }
 
+        // 卡片语言（文档级已解析；未经文档入口的任务按本分段推断）
+        let response_language = crate::response_language::resolve_response_language(
+            options.response_language.as_deref(),
+            None,
+            crate::response_language::load_response_language_setting(&self.db).as_deref(),
+            content,
+        );
+        if let Some(language) = response_language {
+            system_sections.push(format!(
+                "卡片语言：所有字段内容请使用{}撰写，不受学习材料语言的影响；专有名词、公式与代码可保留原文。",
+                crate::response_language::language_display_name(&language)
+            ));
+        }
+
+        // 卡片解释详略程度（独立于对话的详略设置）
+        let verbosity = options.verbosity.or_else(|| {
+            crate::response_verbosity::load_verbosity_setting(
+                &self.db,
+                crate::response_verbosity::CARD_VERBOSITY_SETTING_KEY,
+            )
+        });
+        if let Some(verbosity) = verbosity {
+            system_sections.push(verbosity.card_instruction().to_string());
+        }
+
         let system_message = system_sections.join("\n\n");
 
         let multi_template = options
@@ -568,35 +667,64 @@ impl StreamingAnkiService {
 
         // 已在系统段开头处理自定义要求
 
-        // 构建卡片数量要求
-        let card_count_instruction = if options.max_cards_per_mistake > 0 {
+        // 构建卡片数量要求（期望生成数受分段硬上限约束）
+        let requested_cards = anki_card_limits::requested_cards(options);
+        let mut card_count_instruction = if requested_cards > 0 {
             format!(
                 "🚨 卡片数量硬性限制 🚨\n\
                 你必须严格生成**恰好 {} 张**卡片，不多不少。\n\
                 - 生成到第 {} 张后立即停止，不要再输出任何卡片\n\
                 - 确保每张卡片都是高质量的，覆盖内容中最重要的知识点\n\
                 - 如果内容不够生成 {} 张，则生成尽可能多但不超过 {} 张\n\n",
-                options.max_cards_per_mistake,
-                options.max_cards_per_mistake,
-                options.max_cards_per_mistake,
-                options.max_cards_per_mistake
+                requested_cards, requested_cards, requested_cards, requested_cards
             )
         } else {
             "根据内容的信息密度生成适量的高质量卡片，充分覆盖所有知识点。\n\n".to_string()
         };
+        if let Some(limits) = anki_card_limits::limit_instruction(options) {
+            card_count_instruction.push_str(&limits);
+        }
 
         // 增强prompt以支持流式输出和动态字段
-        let generation_instructions = format!(
-            "{}\
-            重要指令：\n\
-            1. 请逐个生成卡片，每个卡片必须是完整的JSON格式\n\
-            2. 每生成一个完整的卡片JSON后，立即输出分隔符：<<<ANKI_CARD_JSON_END>>>\n\
-            3. JSON格式必须包含以下字段：{}\n\
-            4. 不要使用Markdown代码块，直接输出JSON\n\
-            5. 示例输出格式：\n\
-            {}\n\
-            <<<ANKI_CARD_JSON_END>>>",
-            card_count_instruction, fields_requirement, example_json
+        let generation_instructions = if output_mode.is_json() {
+            format!(
+                "{}\
+                重要指令：\n\
+                1. 只输出一个JSON对象，所有卡片按顺序放在 \"{}\" 数组中\n\
+                2. 每个卡片对象必须包含以下字段：{}\n\
+                3. 不要输出任何JSON以外的文字，也不要使用Markdown代码块\n\
+                4. 示例输出格式：\n\
+                {{\"{}\": [{}]}}",
+                card_count_instruction,
+                anki_card_output::CARDS_KEY,
+                fields_requirement,
+                anki_card_output::CARDS_KEY,
+                example_json
+            )
+        } else {
+            format!(
+                "{}\
+                重要指令：\n\
+                1. 请逐个生成卡片，每个卡片必须是完整的JSON格式\n\
+                2. 每生成一个完整的卡片JSON后，立即输出分隔符：<<<ANKI_CARD_JSON_END>>>\n\
+                3. JSON格式必须包含以下字段：{}\n\
+                4. 不要使用Markdown代码块，直接输出JSON\n\
+                5. 示例输出格式：\n\
+                {}\n\
+                <<<ANKI_CARD_JSON_END>>>",
+                card_count_instruction, fields_requirement, example_json
+            )
+        };
+
+        let template_ids: Vec<String> =
+            match (&options.template_ids, &options.template_descriptions) {
+                (Some(ids), _) if !ids.is_empty() => ids.clone(),
+                (_, Some(descriptions)) => descriptions.iter().map(|t| t.id.clone()).collect(),
+                _ => Vec::new(),
+            };
+        let response_format = anki_card_output::response_format(
+            output_mode,
+            anki_card_output::card_output_schema(template_fields.as_deref(), &template_ids),
         );
 
         let user_message = format!(
@@ -614,10 +742,147 @@ impl StreamingAnkiService {
             },
             user: user_message,
             debug_preview,
+            output_mode,
+            response_format,
         })
     }
 
-    /// 流式处理AI响应并生成卡片
+    /// 流式制卡（带断线重连）
+    ///
+    /// 传输层中断时按退避策略重连当前分段；已保存的卡片即为断点：
+    /// 续传时从已有卡片数继续计数，并在提示词中列出已生成卡片避免重复
+    /// （重复卡片仍会被数据库唯一索引去重）。重试耗尽后返回最后一次错误。
+    async fn stream_cards_with_reconnect(
+        &self,
+        api_config: &ApiConfig,
+        prompt_payload: &PromptPayload,
+        max_tokens: u32,
+        temperature: f32,
+        task_id: &str,
+        document_id: &str,
+        window: &Window,
+        options: &AnkiGenerationOptions,
+        caps: &mut CardCapReport,
+        raw_capture: &mut RawOutputCapture,
+        pause_rx: watch::Receiver<bool>,
+        mut cancel_rx: watch::Receiver<bool>,
+    ) -> Result<u32, AppError> {
+        let policy = StreamReconnectPolicy::load(&self.db);
+        let mut attempt = 0u32;
+        let mut resume: Option<(PromptPayload, u32)> = None;
+
+        loop {
+            let (payload, initial_card_count) = match &resume {
+                Some((payload, count)) => (payload, *count),
+                None => (prompt_payload, 0),
+            };
+            let err = match self
+                .stream_cards_from_ai(
+                    api_config,
+                    payload,
+                    max_tokens,
+                    temperature,
+                    task_id,
+                    document_id,
+                    window,
+                    options,
+                    initial_card_count,
+                    caps,
+                    raw_capture,
+                    pause_rx.clone(),
+                    cancel_rx.clone(),
+                )
+                .await
+            {
+                Ok(card_count) => return Ok(card_count),
+                Err(e) => e,
+            };
+
+            if attempt >= policy.max_retries || !is_recoverable_stream_error(&err) {
+                return Err(err);
+            }
+            attempt += 1;
+            let delay = policy.backoff_for(attempt);
+            warn!(
+                "[ANKI_STREAM_RECONNECT] 任务 {} 流式中断: {}，{}ms 后第 {}/{} 次重连",
+                task_id,
+                err.message,
+                delay.as_millis(),
+                attempt,
+                policy.max_retries
+            );
+            if let Err(e) = self
+                .update_task_status(
+                    task_id,
+                    TaskStatus::Streaming,
+                    Some(format!(
+                        "连接中断，正在重连 ({}/{})…",
+                        attempt, policy.max_retries
+                    )),
+                    None,
+                    Some(document_id),
+                    window,
+                )
+                .await
+            {
+                warn!("[ANKI_STREAM_RECONNECT] 更新重连状态失败: {}", e);
+            }
+
+            if *cancel_rx.borrow() {
+                return Err(AppError::validation("CANCELLED_BY_USER".to_string()));
+            }
+            tokio::select! {
+                _ = cancel_rx.changed() => {
+                    info!("🛑 重连等待期间检测到取消信号: {}", task_id);
+                    return Err(AppError::validation("CANCELLED_BY_USER".to_string()));
+                },
+                _ = tokio::time::sleep(delay) => {}
+            }
+
+            raw_capture.push(&format!(
+                "\n\n----- 第 {}/{} 次重连后的输出 -----\n\n",
+                attempt, policy.max_retries
+            ));
+            resume = Some(self.build_resume_payload(prompt_payload, task_id)?);
+        }
+    }
+
+    /// 以已保存的卡片为断点构造续传提示词，返回 (提示词, 已有卡片数)
+    fn build_resume_payload(
+        &self,
+        prompt_payload: &PromptPayload,
+        task_id: &str,
+    ) -> Result<(PromptPayload, u32), AppError> {
+        let saved: Vec<AnkiCard> = self
+            .db
+            .get_cards_for_task(task_id)
+            .map_err(|e| AppError::database(format!("读取已生成卡片失败: {}", e)))?
+            .into_iter()
+            .filter(|card| !card.is_error_card)
+            .collect();
+
+        let mut note = String::new();
+        if !saved.is_empty() {
+            note.push_str("\n\n【续传说明】上一次输出在中途中断，以下卡片已生成并保存，请勿重复生成，继续输出其余卡片：\n");
+            for card in saved.iter().take(RESUME_PROMPT_MAX_CARDS) {
+                let front: String = card.front.chars().take(80).collect();
+                note.push_str(&format!("- {}\n", front.replace('\n', " ")));
+            }
+        }
+
+        Ok((
+            PromptPayload {
+                system: prompt_payload.system.clone(),
+                user: format!("{}{}", prompt_payload.user, note),
+                debug_preview: format!("{}{}", prompt_payload.debug_preview, note),
+                output_mode: prompt_payload.output_mode,
+                response_format: prompt_payload.response_format.clone(),
+            },
+            saved.len() as u32,
+        ))
+    }
+
+    /// 流式处理AI响应并生成卡片（单次请求）
     async fn stream_cards_from_ai(
         &self,
         api_config: &ApiConfig,
@@ -628,6 +893,9 @@ impl StreamingAnkiService {
         document_id: &str,
         window: &Window,
         options: &AnkiGenerationOptions,
+        initial_card_count: u32,
+        caps: &mut CardCapReport,
+        raw_capture: &mut RawOutputCapture,
         pause_rx: watch::Receiver<bool>,
         mut cancel_rx: watch::Receiver<bool>,
     ) -> Result<u32, AppError> {
@@ -643,13 +911,19 @@ impl StreamingAnkiService {
             "content": prompt_payload.user
         }));
 
-        let request_body = json!({
+        // 模型不支持流式时改发非流式请求，响应转换为单段 SSE 后沿用同一解析循环
+        let stream_mode = StreamMode::for_config(api_config);
+        let mut request_body = json!({
             "model": api_config.model,
             "messages": messages,
             "max_tokens": max_tokens,
             "temperature": temperature,
-            "stream": true
+            "stream": stream_mode.is_streaming()
         });
+        // JSON 模式：请求服务商按卡片 schema 输出结构化 JSON
+        if let Some(response_format) = &prompt_payload.response_format {
+            request_body["response_format"] = response_format.clone();
+        }
 
         // 使用 ProviderAdapter 构建请求（支持 Gemini 中转）
         let adapter: Box<dyn ProviderAdapter> = match api_config.model_adapter.as_str() {
@@ -665,6 +939,8 @@ impl StreamingAnkiService {
                 &request_body,
             )
             .map_err(|e| AppError::llm(format!("Anki 流式请求构建失败: {}", e)))?;
+        crate::offline_mode::ensure_endpoint_allowed("Anki 制卡", &preq.url)?;
+        let adapter = stream_mode.parse_adapter(adapter);
 
         let request_url = preq.url.clone();
         debug!(
@@ -723,12 +999,14 @@ impl StreamingAnkiService {
         for (k, v) in preq.headers {
             req_builder = req_builder.header(k, v);
         }
+        let req_builder = crate::llm_manager::resolve_custom_request_options(&self.db, api_config)?
+            .apply(req_builder);
 
-        let response = req_builder
-            .json(&preq.body)
-            .send()
+        let timer = StreamTimer::start(api_config, stream_mode);
+        let response = timer
+            .send(req_builder.json(&preq.body))
             .await
-            .map_err(|e| AppError::network(format!("AI请求失败: {}", e)))?;
+            .map_err(|e| e.into_app_error(|e| AppError::network(format!("AI请求失败: {}", e))))?;
 
         if !response.status().is_success() {
             let status_code = response.status().as_u16();
@@ -738,6 +1016,7 @@ impl StreamingAnkiService {
                 "[ANKI_API_ERROR] HTTP {} - 详细错误: {}",
                 status_code, error_text
             );
+            raw_capture.push(&format!("[HTTP {}]\n{}", status_code, error_text));
 
             // 根据状态码返回用户友好的错误消息
             let user_message = match status_code {
@@ -747,21 +1026,26 @@ impl StreamingAnkiService {
                 500..=599 => "AI 服务暂时不可用，请稍后重试",
                 _ => "AI API 请求失败，请检查网络连接或 API 配置",
             };
-            return Err(AppError::llm(format!(
-                "{} (HTTP {})",
-                user_message, status_code
-            )));
+            let message = format!("{} (HTTP {})", user_message, status_code);
+            // 网关类错误多为链路抖动，交由上层重连
+            if matches!(status_code, 502..=504) {
+                return Err(AppError::network(message));
+            }
+            return Err(AppError::llm(message));
         }
 
-        let mut stream = response.bytes_stream();
+        let mut stream = stream_mode
+            .into_byte_stream(response, api_config, false, timer)
+            .await?;
         let mut buffer = String::new();
-        let mut card_count = 0u32;
+        let mut card_count = initial_card_count;
         let mut _last_activity = std::time::Instant::now(); // Prefixed to silence warning
         const IDLE_TIMEOUT: Duration = Duration::from_secs(30); // 30秒无响应超时
         const LOG_STREAM_CHUNKS: bool = false; // 禁用逐chunk日志
                                                // 初始化SSE行缓冲器
         let mut sse_buffer = crate::utils::sse_buffer::SseLineBuffer::new();
         let mut chunk_counter: u32 = 0;
+        let mut scanner = CardJsonScanner::default();
 
         loop {
             // 同时监听取消信号与流事件
@@ -779,8 +1063,9 @@ impl StreamingAnkiService {
                 break;
             };
 
-            let chunk =
-                chunk_result.map_err(|e| AppError::network(format!("读取AI响应流失败: {}", e)))?;
+            let chunk = chunk_result.map_err(|e| {
+                e.into_app_error(|e| AppError::network(format!("读取AI响应流失败: {}", e)))
+            })?;
             _last_activity = std::time::Instant::now(); // Prefixed to silence warning
             let chunk_str = String::from_utf8_lossy(&chunk);
             // 处理SSE格式 - 使用SSE缓冲器处理chunk，获取完整的行
@@ -804,6 +1089,7 @@ impl StreamingAnkiService {
                                     chunk_counter, content
                                 );
                             }
+                            raw_capture.push(&content);
                             buffer.push_str(&content);
                             // 暂停时只累积 buffer，不生成卡片
                             if *cancel_rx.borrow() {
@@ -814,8 +1100,12 @@ impl StreamingAnkiService {
                             }
 
                             // 检查是否有完整的卡片
-                            while let Some(card_result) = self.extract_card_from_buffer(&mut buffer)
-                            {
+                            let candidates = self.take_card_candidates(
+                                &mut buffer,
+                                &mut scanner,
+                                prompt_payload.output_mode,
+                            );
+                            for candidate in candidates {
                                 // 硬截断：达到 max_cards_per_mistake 上限时停止
                                 if options.max_cards_per_mistake > 0
                                     && card_count as i32 >= options.max_cards_per_mistake
@@ -826,70 +1116,30 @@ impl StreamingAnkiService {
                                     );
                                     break;
                                 }
-                                match card_result {
-                                    Ok(card_json) => {
-                                        match self
-                                            .parse_and_save_card(&card_json, task_id, options)
-                                            .await
-                                        {
-                                            Ok(Some(card)) => {
-                                                card_count += 1;
-                                                debug!("[ANKI_CARD_DEBUG] 已生成第{}张卡片 (上限: {}张)", card_count, options.max_cards_per_mistake);
-                                                self.emit_new_card(card, document_id, window).await;
-                                            }
-                                            Ok(None) => {
-                                                // 重复或被跳过的卡片，记录日志但不中断流程
-                                                debug!("[ANKI_CARD_DEBUG] 卡片被跳过（重复或不需要保存）");
-                                            }
-                                            Err(e) => {
-                                                error!(
-                                                    "解析卡片失败: {} - 原始JSON: {}",
-                                                    e, card_json
-                                                );
-                                                match self
-                                                    .create_error_card(
-                                                        &format!("解析卡片失败: {}", e),
-                                                        task_id,
-                                                    )
-                                                    .await
-                                                {
-                                                    Ok(error_card) => {
-                                                        self.emit_error_card(
-                                                            error_card,
-                                                            document_id,
-                                                            window,
-                                                        )
-                                                        .await;
-                                                    }
-                                                    Err(create_err) => {
-                                                        let app_err =
-                                                            AppError::validation(format!(
-                                                                "解析卡片失败且无法创建错误卡: {}",
-                                                                create_err
-                                                            ));
-                                                        let _ = self
-                                                            .handle_task_error(
-                                                                &task_id,
-                                                                &app_err,
-                                                                window,
-                                                                None,
-                                                                Some(document_id),
-                                                            )
-                                                            .await;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                    Err(truncated_content) => {
-                                        if let Ok(error_card) = self
-                                            .create_error_card(&truncated_content, task_id)
-                                            .await
-                                        {
-                                            self.emit_error_card(error_card, document_id, window)
-                                                .await;
-                                        }
-                                    }
+                                // 分段硬上限：超出的卡片丢弃并计数
+                                if candidate.is_ok()
+                                    && anki_card_limits::segment_cap(options)
+                                        .is_some_and(|cap| card_count >= cap)
+                                {
+                                    caps.drop_for_segment();
+                                    continue;
+                                }
+                                if self
+                                    .handle_card_candidate(
+                                        candidate,
+                                        task_id,
+                                        document_id,
+                                        window,
+                                        options,
+                                        caps,
+                                    )
+                                    .await
+                                {
+                                    card_count += 1;
+                                    debug!(
+                                        "[ANKI_CARD_DEBUG] 已生成第{}张卡片 (上限: {}张)",
+                                        card_count, options.max_cards_per_mistake
+                                    );
                                 }
                             }
                         }
@@ -937,16 +1187,44 @@ impl StreamingAnkiService {
                                 chunk_counter, content
                             );
                         }
+                        raw_capture.push(&content);
                         buffer.push_str(&content);
                     }
                 }
             }
         }
 
-        // 处理剩余缓冲区内容
-        if !buffer.trim().is_empty() {
-            if let Ok(error_card) = self.create_error_card(&buffer, task_id).await {
-                self.emit_error_card(error_card, document_id, window).await;
+        // 处理剩余缓冲区内容：抢救其中完整的卡片（如最后一张漏写分隔符），其余作为错误卡片保留原文
+        let mut residual = if prompt_payload.output_mode.is_json() {
+            let mut candidates: Vec<Result<String, String>> =
+                scanner.push(&buffer).into_iter().map(Ok).collect();
+            candidates.push(Err(scanner.take_remainder()));
+            candidates
+        } else {
+            self.split_card_segment(&buffer)
+        };
+        residual.retain(|candidate| match candidate {
+            Ok(_) => true,
+            Err(rest) => !anki_card_output::is_insignificant_remainder(rest),
+        });
+        for candidate in residual {
+            if candidate.is_ok()
+                && options.max_cards_per_mistake > 0
+                && card_count as i32 >= options.max_cards_per_mistake
+            {
+                continue;
+            }
+            if candidate.is_ok()
+                && anki_card_limits::segment_cap(options).is_some_and(|cap| card_count >= cap)
+            {
+                caps.drop_for_segment();
+                continue;
+            }
+            if self
+                .handle_card_candidate(candidate, task_id, document_id, window, options, caps)
+                .await
+            {
+                card_count += 1;
             }
         }
 
@@ -962,6 +1240,109 @@ impl StreamingAnkiService {
         Ok(card_count)
     }
 
+    /// 从流式输出中取出已完整的候选卡片：JSON 模式交给扫描器逐张截取，
+    /// 分隔符模式按分隔符切分。`Err` 为无法构成卡片的原始片段。
+    fn take_card_candidates(
+        &self,
+        buffer: &mut String,
+        scanner: &mut CardJsonScanner,
+        output_mode: CardOutputMode,
+    ) -> Vec<Result<String, String>> {
+        let mut candidates = Vec::new();
+        if output_mode.is_json() {
+            candidates.extend(scanner.push(&std::mem::take(buffer)).into_iter().map(Ok));
+            if scanner.is_overflowing() {
+                candidates.push(Err(scanner.take_remainder()));
+            }
+        } else {
+            while let Some(card_result) = self.extract_card_from_buffer(buffer) {
+                match card_result {
+                    Ok(segment) => candidates.extend(self.split_card_segment(&segment)),
+                    Err(truncated) => candidates.push(Err(truncated)),
+                }
+            }
+        }
+        candidates
+    }
+
+    /// 分隔符片段无法整体解析时（如多张卡片挤在一起、被包成数组），抢救其中完整的卡片对象
+    fn split_card_segment(&self, segment: &str) -> Vec<Result<String, String>> {
+        if anki_card_output::parse_card_value(&self.clean_json_string(segment)).is_ok() {
+            return vec![Ok(segment.to_string())];
+        }
+        let (cards, rest) = anki_card_output::salvage_card_objects(segment);
+        if cards.is_empty() {
+            // 交由 parse_and_save_card 报告具体解析错误
+            return vec![Ok(segment.to_string())];
+        }
+        let mut candidates: Vec<Result<String, String>> = cards.into_iter().map(Ok).collect();
+        if !anki_card_output::is_insignificant_remainder(&rest) {
+            candidates.push(Err(rest));
+        }
+        candidates
+    }
+
+    /// 保存一张候选卡片；解析失败或片段不完整时保存错误卡片（保留原始输出）。
+    /// 文档已达到卡片上限时丢弃并计入 `caps`。返回是否生成了正常卡片。
+    async fn handle_card_candidate(
+        &self,
+        candidate: Result<String, String>,
+        task_id: &str,
+        document_id: &str,
+        window: &Window,
+        options: &AnkiGenerationOptions,
+        caps: &mut CardCapReport,
+    ) -> bool {
+        // 文档上限：持锁检查已保存数量并保存，避免并发分段同时越过上限
+        let _document_guard = match anki_card_limits::document_cap(options) {
+            Some(cap) if candidate.is_ok() => {
+                let guard = anki_card_limits::lock_document(document_id).await;
+                match self.db.count_valid_cards_for_document(document_id) {
+                    Ok(saved) if saved >= cap => {
+                        caps.drop_for_document();
+                        return false;
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("统计文档卡片数失败，跳过文档上限检查: {}", e),
+                }
+                Some(guard)
+            }
+            _ => None,
+        };
+        let error_content = match candidate {
+            Ok(card_json) => match self.parse_and_save_card(&card_json, task_id, options).await {
+                Ok(Some(card)) => {
+                    self.emit_new_card(card, document_id, window).await;
+                    return true;
+                }
+                Ok(None) => {
+                    // 重复或被跳过的卡片，记录日志但不中断流程
+                    debug!("[ANKI_CARD_DEBUG] 卡片被跳过（重复或不需要保存）");
+                    return false;
+                }
+                Err(e) => {
+                    error!("解析卡片失败: {} - 原始JSON: {}", e, card_json);
+                    format!("解析卡片失败: {}\n\n原始输出：\n{}", e, card_json)
+                }
+            },
+            Err(truncated_content) => truncated_content,
+        };
+
+        match self.create_error_card(&error_content, task_id).await {
+            Ok(error_card) => {
+                self.emit_error_card(error_card, document_id, window).await;
+            }
+            Err(create_err) => {
+                let app_err =
+                    AppError::validation(format!("解析卡片失败且无法创建错误卡: {}", create_err));
+                let _ = self
+                    .handle_task_error(task_id, &app_err, window, None, Some(document_id))
+                    .await;
+            }
+        }
+        false
+    }
+
     /// 从缓冲区提取卡片
     fn extract_card_from_buffer(&self, buffer: &mut String) -> Option<Result<String, String>> {
         const DELIMITER: &str = "<<<ANKI_CARD_JSON_END>>>";
@@ -1027,7 +1408,7 @@ impl StreamingAnkiService {
         let cleaned_json = self.clean_json_string(card_json);
 
         // 解析JSON
-        let json_value: Value = serde_json::from_str(&cleaned_json).map_err(|e| {
+        let json_value: Value = anki_card_output::parse_card_value(&cleaned_json).map_err(|e| {
             error!("[ANKI_PARSE_ERROR] JSON解析失败");
             error!("[ANKI_PARSE_ERROR] 错误信息: {}", e);
             error!("[ANKI_PARSE_ERROR] 原始内容: {}", card_json);
@@ -1161,11 +1542,12 @@ impl StreamingAnkiService {
         // 清理所有字段中的模板占位符
         let cleaned_front = self.clean_template_placeholders(&front);
         let cleaned_back = self.clean_template_placeholders(&back);
-        let cleaned_tags: Vec<String> = tags
+        let mut cleaned_tags: Vec<String> = tags
             .iter()
             .map(|tag| self.clean_template_placeholders(tag))
             .filter(|tag| !tag.is_empty())
             .collect();
+        anki_source_tags::apply_source_tag(&mut cleaned_tags, options.source_tag.as_deref());
         let mut cleaned_extra_fields: std::collections::HashMap<String, String> = extra_fields
             .iter()
             .map(|(k, v)| (k.clone(), self.clean_template_placeholders(v)))
@@ -1193,7 +1575,7 @@ impl StreamingAnkiService {
 
         // 创建卡片
         let now = Utc::now().to_rfc3339();
-        let card = AnkiCard {
+        let mut card = AnkiCard {
             id: Uuid::new_v4().to_string(),
             task_id: task_id.to_string(),
             front: cleaned_front,
@@ -1208,6 +1590,11 @@ impl StreamingAnkiService {
             extra_fields: cleaned_extra_fields,
             template_id: resolved_template_id,
         };
+        let html_mode = anki_field_html::resolve_html_mode(
+            options.html_mode.as_deref(),
+            anki_field_html::load_html_mode_setting(&self.db).as_deref(),
+        );
+        anki_field_html::apply_to_card(&mut card, html_mode);
 
         // 保存到数据库（DB 唯一索引保证原子去重）
         let inserted = self
@@ -1962,6 +2349,29 @@ impl StreamingAnkiService {
         Ok((front, back, tags, extra_fields))
     }
 
+    /// 任务失败（用户取消除外）或产生了错误卡片时保存原始输出
+    fn persist_raw_output_if_failed(
+        &self,
+        raw_capture: &RawOutputCapture,
+        task_id: &str,
+        result: &Result<u32, AppError>,
+    ) {
+        if !raw_capture.is_enabled() {
+            return;
+        }
+        let failed = match result {
+            Err(e) => e.message != "CANCELLED_BY_USER",
+            Ok(_) => self
+                .db
+                .get_cards_for_task(task_id)
+                .map(|cards| cards.iter().any(|card| card.is_error_card))
+                .unwrap_or(false),
+        };
+        if failed {
+            raw_capture.persist(&self.db, task_id);
+        }
+    }
+
     /// 创建错误卡片
     async fn create_error_card(
         &self,
@@ -2054,20 +2464,27 @@ impl StreamingAnkiService {
         }
     }
 
-    /// 成功完成任务
+    /// 成功完成任务；因卡片上限丢弃过卡片时标记为截断并附带说明
     async fn complete_task_successfully(
         &self,
         task_id: &str,
         card_count: u32,
+        cards_dropped: u32,
+        truncation_message: Option<String>,
         document_id: &str,
         window: &Window,
     ) -> Result<(), AppError> {
+        let final_status = if truncation_message.is_some() {
+            TaskStatus::Truncated
+        } else {
+            TaskStatus::Completed
+        };
         // For TaskCompleted, segment_index might be less critical if task_id is already real.
         // Passing None for now, as the primary use of segment_index is for the initial ID update.
         self.update_task_status(
             task_id,
-            TaskStatus::Completed,
-            None,
+            final_status.clone(),
+            truncation_message,
             None,
             Some(document_id),
             window,
@@ -2078,14 +2495,23 @@ impl StreamingAnkiService {
         // 🔧 CardForge 2.0 修复：直接发射 StreamedCardPayload
         let payload = StreamedCardPayload::TaskCompleted {
             task_id: task_id.to_string(),
-            final_status: TaskStatus::Completed,
+            final_status,
             total_cards_generated: card_count,
+            cards_dropped,
             document_id: Some(document_id.to_string()),
         };
 
+        let result = serde_json::to_value(&payload).ok();
         if let Err(e) = window.emit("anki_generation_event", &payload) {
             error!("发送任务完成事件失败: {}", e);
         }
+        // 卡片事件本身已是带类型的 StreamedCardPayload，统一通道只发送终止事件
+        emit_stream_event(
+            window,
+            StreamSource::AnkiGeneration,
+            task_id,
+            StreamEvent::done(result),
+        );
 
         Ok(())
     }
@@ -2127,6 +2553,12 @@ impl StreamingAnkiService {
         if let Err(e) = window.emit("anki_generation_event", &payload) {
             error!("发送任务错误事件失败: {}", e);
         }
+        emit_stream_event(
+            window,
+            StreamSource::AnkiGeneration,
+            task_id,
+            StreamEvent::error(error.message.clone()),
+        );
 
         Ok(())
     }
@@ -2266,6 +2698,32 @@ impl StreamingAnkiService {
 mod tests {
     use super::*;
 
+    #[test]
+    fn stream_reconnect_backoff_doubles_and_caps() {
+        let policy = StreamReconnectPolicy {
+            max_retries: 3,
+            base_backoff: Duration::from_millis(2000),
+        };
+        assert_eq!(policy.backoff_for(1), Duration::from_millis(2000));
+        assert_eq!(policy.backoff_for(2), Duration::from_millis(4000));
+        assert_eq!(policy.backoff_for(3), Duration::from_millis(8000));
+        assert_eq!(policy.backoff_for(10), MAX_STREAM_RECONNECT_BACKOFF);
+        assert_eq!(policy.backoff_for(u32::MAX), MAX_STREAM_RECONNECT_BACKOFF);
+    }
+
+    #[test]
+    fn only_transport_errors_are_reconnected() {
+        assert!(is_recoverable_stream_error(&AppError::network(
+            "AI响应超时"
+        )));
+        assert!(!is_recoverable_stream_error(&AppError::llm(
+            "API 认证失败，请检查 API 密钥配置 (HTTP 401)"
+        )));
+        assert!(!is_recoverable_stream_error(&AppError::validation(
+            "CANCELLED_BY_USER"
+        )));
+    }
+
     fn make_template(id: &str, name: &str) -> TemplateDescription {
         TemplateDescription {
             id: id.to_string(),
@@ -2638,6 +2638,13 @@ impl Provider for BochaProvider {
 // =============================
 
 pub async fn do_search(cfg: &ToolConfig, mut input: SearchInput) -> ToolResult {
+    if crate::offline_mode::is_enabled() {
+        return ToolResult::err(
+            Some(input),
+            crate::offline_mode::offline_message("网络搜索"),
+            0,
+        );
+    }
     if input.top_k == 0 {
         return ToolResult {
             name: TOOL_NAME.into(),
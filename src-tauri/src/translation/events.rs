@@ -1,6 +1,8 @@
 /// 翻译事件发射器 - 负责发送 SSE 事件到前端
 use tauri::{Emitter, Window};
 
+use crate::stream_events::{emit_delta, emit_stream_event, StreamEvent, StreamSource};
+
 use super::types::{
     TranslationStreamCancelled, TranslationStreamComplete, TranslationStreamData,
     TranslationStreamError,
@@ -35,15 +37,23 @@ impl TranslationEventEmitter {
 
         let payload = TranslationStreamData {
             event_type: "data".to_string(),
-            chunk,
+            chunk: chunk.clone(),
             accumulated,
             char_count,
             word_count,
         };
 
-        if let Err(e) = self.window.emit(&event_name, payload) {
-            eprintln!("❌ [Translation] 发送数据事件失败: {}", e);
-        }
+        emit_delta(
+            &self.window,
+            StreamSource::Translation,
+            session_id,
+            StreamEvent::token(chunk),
+            || {
+                if let Err(e) = self.window.emit(&event_name, payload) {
+                    eprintln!("❌ [Translation] 发送数据事件失败: {}", e);
+                }
+            },
+        );
     }
 
     /// 发送完成事件
@@ -62,6 +72,12 @@ impl TranslationEventEmitter {
             created_at,
         };
 
+        emit_stream_event(
+            &self.window,
+            StreamSource::Translation,
+            session_id,
+            StreamEvent::done(serde_json::to_value(&payload).ok()),
+        );
         if let Err(e) = self.window.emit(&event_name, payload) {
             eprintln!("❌ [Translation] 发送完成事件失败: {}", e);
         }
@@ -75,6 +91,12 @@ impl TranslationEventEmitter {
             message,
         };
 
+        emit_stream_event(
+            &self.window,
+            StreamSource::Translation,
+            session_id,
+            StreamEvent::error(payload.message.clone()),
+        );
         if let Err(e) = self.window.emit(&event_name, payload) {
             eprintln!("❌ [Translation] 发送错误事件失败: {}", e);
         }
@@ -87,6 +109,12 @@ impl TranslationEventEmitter {
             event_type: "cancelled".to_string(),
         };
 
+        emit_stream_event(
+            &self.window,
+            StreamSource::Translation,
+            session_id,
+            StreamEvent::cancelled(),
+        );
         if let Err(e) = self.window.emit(&event_name, payload) {
             eprintln!("❌ [Translation] 发送取消事件失败: {}", e);
         }
@@ -4,9 +4,10 @@ use serde_json::json;
 use std::sync::Arc;
 
 use crate::database::Database;
-use crate::llm_manager::{ApiConfig, LLMManager};
+use crate::llm_manager::{ApiConfig, LLMManager, StreamMode, StreamTimer};
 use crate::models::AppError;
 use crate::providers::ProviderAdapter;
+use crate::stream_events::{StreamSequenceGuard, StreamSource};
 // ★ VFS 统一存储（2025-12-07）
 use crate::vfs::database::VfsDatabase;
 
@@ -32,6 +33,9 @@ pub async fn run_translation(
     request: TranslationRequest,
     deps: TranslationDeps,
 ) -> Result<Option<TranslationResponse>, AppError> {
+    // 提前返回或被中止时同样清理统一通道的序号
+    let _sequence_guard = StreamSequenceGuard::new(StreamSource::Translation, &request.session_id);
+
     // 0. 输入验证：检查空文本
     if request.text.trim().is_empty() {
         return Err(AppError::validation("翻译文本不能为空".to_string()));
@@ -258,13 +262,14 @@ where
             }),
         ];
 
-        // 构造请求体
+        // 构造请求体；模型不支持流式时改发非流式请求，响应转换为单段 SSE
+        let stream_mode = StreamMode::for_config(config);
         let request_body = json!({
             "model": config.model,
             "messages": messages,
             "temperature": 0.3,
             "max_tokens": config.max_output_tokens,
-            "stream": true, // 关键：启用流式
+            "stream": stream_mode.is_streaming(),
         });
 
         // 选择适配器
@@ -278,6 +283,8 @@ where
         let preq = adapter
             .build_request(&config.base_url, api_key, &config.model, &request_body)
             .map_err(|e| AppError::llm(format!("翻译请求构建失败: {}", e)))?;
+        crate::offline_mode::ensure_endpoint_allowed("翻译", &preq.url)?;
+        let adapter = stream_mode.parse_adapter(adapter);
 
         let mut header_map = reqwest::header::HeaderMap::new();
         for (k, v) in preq.headers.iter() {
@@ -297,13 +304,15 @@ where
         let mut cancel_rx = llm.subscribe_cancel_stream(stream_event).await;
 
         // 发送流式请求
-        let response = client
-            .post(&preq.url)
-            .headers(header_map)
-            .json(&preq.body)
-            .send()
+        let request_builder = client.post(&preq.url).headers(header_map);
+        let timer = StreamTimer::start(config, stream_mode);
+        let response = timer
+            .send(
+                llm.apply_custom_request_options(request_builder, config)?
+                    .json(&preq.body),
+            )
             .await
-            .map_err(|e| AppError::llm(format!("翻译请求失败: {}", e)))?;
+            .map_err(|e| e.into_app_error(|e| AppError::llm(format!("翻译请求失败: {}", e))))?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -324,8 +333,10 @@ where
             return Err(AppError::llm(user_message.to_string()));
         }
 
-        // 解析 SSE 流
-        let mut stream = response.bytes_stream();
+        // 解析 SSE 流（非流式模式下为合成的单段 SSE）
+        let mut stream = stream_mode
+            .into_byte_stream(response, config, false, timer)
+            .await?;
         let mut buffer = String::new();
         let mut stream_ended = false;
         let mut cancelled = false;
@@ -345,7 +356,9 @@ where
                 chunk_result = stream.next() => {
                     match chunk_result {
                         Some(chunk) => {
-                            let bytes = chunk.map_err(|e| AppError::llm(format!("读取流失败: {}", e)))?;
+                            let bytes = chunk.map_err(|e| {
+                                e.into_app_error(|e| AppError::llm(format!("读取流失败: {}", e)))
+                            })?;
                             buffer.push_str(&String::from_utf8_lossy(&bytes));
 
                             while let Some(pos) = buffer.find("\n\n") {
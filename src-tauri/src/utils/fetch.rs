@@ -16,6 +16,7 @@ pub fn fetch_binary_with_cache(url: &str) -> Option<(Vec<u8>, Option<String>)> {
     {
         return Some((bytes, mime));
     }
+    crate::offline_mode::ensure_endpoint_allowed("图片下载", url).ok()?;
 
     let client = Client::builder()
         .timeout(Duration::from_secs(10))
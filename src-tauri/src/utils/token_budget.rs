@@ -32,71 +32,10 @@ pub fn estimate_tokens(text: &str) -> usize {
     std::cmp::max(1, v)
 }
 
-/// Model-aware estimation: when built with `tokenizer_tiktoken` feature, use actual tokenizer encodings.
-/// `model_hint` 可以是 OpenAI/兼容模型名，用于选择合适的编码（如 cl100k_base / o200k_base）。
+/// Model-aware estimation，委托给 `llm_manager::tokenizer`（按模型族选择 o200k/cl100k 编码或近似估算）。
+/// `model_hint` 可以是模型名或包含模型名的配置 ID。
 pub fn estimate_tokens_with_model(text: &str, model_hint: Option<&str>) -> usize {
-    #[cfg(feature = "tokenizer_tiktoken")]
-    {
-        use std::sync::Once;
-        use tiktoken_rs::{cl100k_base, o200k_base, CoreBPE};
-
-        // P2修复：首次调用时打印 tokenizer 状态，让用户了解当前使用精确计数
-        static INIT: Once = Once::new();
-        INIT.call_once(|| {
-            println!(
-                "✅ [TokenBudget] 使用 tiktoken 精确计数（已启用 tokenizer_tiktoken feature）"
-            );
-        });
-
-        /// 检查模型名称中是否存在以词边界开始的 o 系列前缀（o1/o3/o4）。
-        /// 避免 "proto1-model" 等误匹配。
-        fn has_o_series_prefix(m: &str) -> bool {
-            for prefix in &["o1", "o3", "o4"] {
-                if let Some(pos) = m.find(prefix) {
-                    // 确保 prefix 前面是非字母数字字符（即词边界）
-                    if pos == 0 || !m.as_bytes()[pos - 1].is_ascii_alphanumeric() {
-                        return true;
-                    }
-                }
-            }
-            false
-        }
-
-        fn pick_encoding(model: &str) -> CoreBPE {
-            let m = model.to_lowercase();
-            // o200k_base 编码器：GPT-4o/4.1/4.5/5 系列、o1/o3/o4 系列
-            if m.contains("o200k")
-                || m.contains("gpt-4o")
-                || m.contains("gpt-4.1")
-                || m.contains("gpt-4.5")
-                || m.contains("gpt-5")
-                || has_o_series_prefix(&m)
-            {
-                o200k_base().unwrap_or_else(|_| cl100k_base().unwrap())
-            } else {
-                cl100k_base().unwrap()
-            }
-        }
-        if let Some(m) = model_hint {
-            let enc = pick_encoding(m);
-            return enc.encode_with_special_tokens(text).len();
-        } else {
-            let enc = cl100k_base().unwrap();
-            return enc.encode_with_special_tokens(text).len();
-        }
-    }
-    #[cfg(not(feature = "tokenizer_tiktoken"))]
-    {
-        use std::sync::Once;
-
-        // P2修复：使用启发式估算时打印警告，建议启用精确计数
-        static INIT: Once = Once::new();
-        INIT.call_once(|| {
-            println!("⚠️ [TokenBudget] 使用启发式估算（建议启用 --features tokenizer_tiktoken 以获得精确计数）");
-        });
-
-        estimate_tokens(text)
-    }
+    crate::llm_manager::tokenizer::token_len(text, model_hint)
 }
 
 fn is_cjk(cp: u32) -> bool {
@@ -0,0 +1,188 @@
+//! 知识库向量索引（ANN）配置
+//!
+//! 知识库向量按维度存放在 LanceDB 宽表中，默认执行暴力扫描（精确检索）。
+//! 块数达到 `min_rows` 后自动为该表构建 IVF_PQ 索引，查询在索引存在时自动使用。
+//!
+//! 召回/延迟取舍：
+//! - IVF 把向量聚成 `num_partitions` 个簇，查询只扫描最近的 `nprobes` 个簇；
+//!   nprobes 越大召回越高、延迟越高。
+//! - PQ 把向量压缩为 `num_sub_vectors` 段编码，段数越多精度越高、索引越大。
+//! - `refine_factor` 会取 `top_k * refine_factor` 个候选再用原始向量精排，
+//!   以少量额外延迟换回 PQ 压缩损失的召回。
+//! - 小库（几千块以内）暴力扫描已足够快且结果精确，不值得建索引；
+//!   `index_type = exact` 时始终绕过索引。
+//!
+//! 配置保存在设置项 `rag.vector_index.config`（JSON）。
+
+use crate::database::Database;
+use crate::models::AppError;
+use serde::{Deserialize, Serialize};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+pub const VECTOR_INDEX_SETTINGS_KEY: &str = "rag.vector_index.config";
+
+/// PQ 训练至少需要的样本数（每个码本 256 个中心）
+pub const MIN_TRAINABLE_ROWS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorIndexType {
+    /// 超过阈值后构建 IVF_PQ 索引
+    #[default]
+    IvfPq,
+    /// 始终精确检索，不建索引
+    Exact,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VectorIndexSettings {
+    pub index_type: VectorIndexType,
+    /// 表内块数达到该值才自动构建索引
+    pub min_rows: usize,
+    /// IVF 分区数；未设置时按 sqrt(行数) 估算
+    pub num_partitions: Option<u32>,
+    /// PQ 子向量数，必须整除向量维度；未设置时按维度自动选择
+    pub num_sub_vectors: Option<u32>,
+    /// 查询时扫描的分区数
+    pub nprobes: usize,
+    /// 精排倍数；未设置时不精排
+    pub refine_factor: Option<u32>,
+}
+
+impl Default for VectorIndexSettings {
+    fn default() -> Self {
+        Self {
+            index_type: VectorIndexType::IvfPq,
+            min_rows: 20_000,
+            num_partitions: None,
+            num_sub_vectors: None,
+            nprobes: 20,
+            refine_factor: None,
+        }
+    }
+}
+
+impl VectorIndexSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.min_rows < MIN_TRAINABLE_ROWS {
+            return Err(AppError::validation(format!(
+                "自动建索引阈值不能小于 {}",
+                MIN_TRAINABLE_ROWS
+            )));
+        }
+        if self.num_partitions == Some(0) {
+            return Err(AppError::validation("num_partitions 必须大于 0"));
+        }
+        if self.num_sub_vectors == Some(0) {
+            return Err(AppError::validation("num_sub_vectors 必须大于 0"));
+        }
+        if self.nprobes == 0 {
+            return Err(AppError::validation("nprobes 必须大于 0"));
+        }
+        if self.refine_factor == Some(0) {
+            return Err(AppError::validation("refine_factor 必须大于 0"));
+        }
+        Ok(())
+    }
+
+    /// 是否应自动构建索引
+    pub fn should_auto_build(&self, row_count: usize) -> bool {
+        self.index_type == VectorIndexType::IvfPq && row_count >= self.min_rows
+    }
+
+    /// 实际使用的分区数（不超过行数）
+    pub fn partitions_for(&self, row_count: usize) -> u32 {
+        let rows = row_count.max(1) as u32;
+        self.num_partitions
+            .unwrap_or_else(|| (row_count as f64).sqrt() as u32)
+            .clamp(1, rows)
+    }
+
+    /// 实际使用的 PQ 子向量数；显式配置不能整除维度时报错
+    pub fn sub_vectors_for(&self, dim: usize) -> Result<u32> {
+        let dim = dim as u32;
+        match self.num_sub_vectors {
+            Some(n) if !dim.is_multiple_of(n) => Err(AppError::validation(format!(
+                "num_sub_vectors={} 不能整除向量维度 {}",
+                n, dim
+            ))),
+            Some(n) => Ok(n),
+            None if dim.is_multiple_of(16) => Ok(dim / 16),
+            None if dim.is_multiple_of(8) => Ok(dim / 8),
+            None => Ok(1),
+        }
+    }
+}
+
+/// 读取索引配置；未设置或解析失败时使用默认值
+pub fn load_vector_index_settings(db: &Database) -> VectorIndexSettings {
+    db.get_setting(VECTOR_INDEX_SETTINGS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<VectorIndexSettings>(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_vector_index_settings(db: &Database, settings: &VectorIndexSettings) -> Result<()> {
+    settings.validate()?;
+    let json = serde_json::to_string(settings)
+        .map_err(|e| AppError::internal(format!("序列化向量索引配置失败: {}", e)))?;
+    db.save_setting(VECTOR_INDEX_SETTINGS_KEY, &json)
+        .map_err(|e| AppError::database(e.to_string()))
+}
+
+/// 单张向量表的索引构建结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorIndexBuildReport {
+    pub table_name: String,
+    pub dimension: usize,
+    pub row_count: usize,
+    /// 本次是否新建/重建了索引
+    pub built: bool,
+    pub num_partitions: Option<u32>,
+    pub num_sub_vectors: Option<u32>,
+    pub elapsed_ms: u64,
+    pub message: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_build_threshold_and_exact_mode() {
+        let mut settings = VectorIndexSettings::default();
+        assert!(!settings.should_auto_build(settings.min_rows - 1));
+        assert!(settings.should_auto_build(settings.min_rows));
+
+        settings.index_type = VectorIndexType::Exact;
+        assert!(!settings.should_auto_build(1_000_000));
+    }
+
+    #[test]
+    fn test_partition_and_sub_vector_resolution() {
+        let mut settings = VectorIndexSettings::default();
+        assert_eq!(settings.partitions_for(40_000), 200);
+        settings.num_partitions = Some(512);
+        assert_eq!(settings.partitions_for(300), 300);
+
+        assert_eq!(settings.sub_vectors_for(1024).unwrap(), 64);
+        assert_eq!(settings.sub_vectors_for(1000).unwrap(), 125);
+        settings.num_sub_vectors = Some(96);
+        assert_eq!(settings.sub_vectors_for(768).unwrap(), 96);
+        assert!(settings.sub_vectors_for(1024).is_err());
+    }
+
+    #[test]
+    fn test_settings_deserialize_with_defaults() {
+        let parsed: VectorIndexSettings =
+            serde_json::from_str(r#"{"indexType":"exact","nprobes":8}"#).unwrap();
+        assert_eq!(parsed.index_type, VectorIndexType::Exact);
+        assert_eq!(parsed.nprobes, 8);
+        assert_eq!(parsed.min_rows, VectorIndexSettings::default().min_rows);
+        assert!(parsed.validate().is_ok());
+    }
+}
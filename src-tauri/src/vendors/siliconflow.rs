@@ -117,6 +117,8 @@ pub fn load_builtin_api_configs() -> Result<Vec<ApiConfig>, AppError> {
                     enable_thinking: None,
                     supports_reasoning: entry.is_reasoning,
                     headers: Some(std::collections::HashMap::new()),
+                    query_params: None,
+                    allow_reserved_header_override: false,
                     top_p_override: None,
                     frequency_penalty_override: None,
                     presence_penalty_override: None,
@@ -126,6 +128,13 @@ pub fn load_builtin_api_configs() -> Result<Vec<ApiConfig>, AppError> {
                     reasoning_split: None,
                     effort: None,
                     verbosity: None,
+                    stream_coalesce: None,
+                    thinking_delivery: None,
+                    supports_streaming: true,
+                    force_non_streaming: false,
+                    first_token_timeout_ms: None,
+                    idle_timeout_ms: None,
+                    max_request_retries: None,
                 });
             }
         }
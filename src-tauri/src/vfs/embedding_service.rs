@@ -3,7 +3,7 @@
 //! ★ 2026-01 简化：VFS 只处理文本嵌入，多模态由 Multimodal 模块统一处理
 //!
 //! ## 核心功能
-//! - 批量文本嵌入生成（带重试和退避）
+//! - 批量文本嵌入生成（带重试和退避，可回退到同维度的备用模型）
 //! - 多维度向量支持（自动适配不同嵌入模型）
 //! - 进度回调支持
 //! - 与 LLMManager 集成
@@ -16,7 +16,10 @@
 use std::sync::Arc;
 use tracing::{debug, info, warn};
 
-use crate::llm_manager::LLMManager;
+use crate::llm_manager::{
+    embedding_retry_after, ensure_fallback_dimension, is_retryable_embedding_error,
+    EmbeddingRetryPolicy, LLMManager,
+};
 use crate::vfs::error::{VfsError, VfsResult};
 use crate::vfs::indexing::TextChunk;
 use crate::vfs::lance_store::{VfsLanceRow, VfsLanceStore};
@@ -27,13 +30,7 @@ use crate::vfs::repos::{VfsEmbedding, MODALITY_TEXT};
 // ============================================================================
 
 /// 默认批处理大小
-const DEFAULT_BATCH_SIZE: usize = 16;
-
-/// 最大重试次数
-const MAX_RETRIES: usize = 3;
-
-/// 基础退避时间（毫秒）
-const BASE_BACKOFF_MS: u64 = 400;
+pub const DEFAULT_BATCH_SIZE: usize = 16;
 
 // ============================================================================
 // 类型定义
@@ -166,7 +163,7 @@ impl VfsEmbeddingService {
 
         let model_id = self.get_embedding_model_id().await?;
         let total = texts.len();
-        let mut all_embeddings = Vec::with_capacity(total);
+        let mut all_embeddings: Vec<Vec<f32>> = Vec::with_capacity(total);
         let mut start = 0usize;
 
         while start < total {
@@ -181,8 +178,9 @@ impl VfsEmbeddingService {
                 model_id
             );
 
+            let expected_dim = all_embeddings.first().map(|v| v.len());
             let batch_embeddings = self
-                .generate_batch_with_retry(&batch, &model_id, start, end, total)
+                .generate_batch_with_retry(&batch, &model_id, expected_dim, start, end, total)
                 .await?;
 
             if batch_embeddings.len() != batch.len() {
@@ -251,8 +249,9 @@ impl VfsEmbeddingService {
                 model_id
             );
 
+            let expected_dim = (embedding_dim > 0).then_some(embedding_dim);
             let batch_embeddings = self
-                .generate_batch_with_retry(&batch_texts, &model_id, start, end, total)
+                .generate_batch_with_retry(&batch_texts, &model_id, expected_dim, start, end, total)
                 .await?;
 
             if batch_embeddings.len() != batch_chunks.len() {
@@ -308,15 +307,92 @@ impl VfsEmbeddingService {
     }
 
     /// 带重试的批次嵌入生成
+    ///
+    /// 主模型按 [`EmbeddingRetryPolicy`] 重试耗尽后，若分配了备用嵌入模型则改用备用模型；
+    /// 备用模型的维度须与 `expected_dim`（本次已生成的向量维度，缺省取默认文本嵌入维度）一致，
+    /// 否则拒绝并提示重新索引。最终失败由调用方将资源标记为 failed，交给后台索引任务稍后重试。
     async fn generate_batch_with_retry(
         &self,
         texts: &[String],
         model_id: &str,
+        expected_dim: Option<usize>,
         start: usize,
         end: usize,
         total: usize,
     ) -> VfsResult<Vec<Vec<f32>>> {
-        let mut attempt = 0usize;
+        let policy = self.llm_manager.embedding_retry_policy();
+        let primary_error = match self.call_with_retry(texts, model_id, &policy).await {
+            Ok(embeddings) => return Ok(embeddings),
+            Err(e) => e,
+        };
+        let exhausted = || {
+            VfsError::Other(format!(
+                "嵌入生成失败 (批次 {}-{}/{}): {}，资源将由后台索引任务稍后重试",
+                start + 1,
+                end,
+                total,
+                primary_error
+            ))
+        };
+
+        let fallback = match self
+            .llm_manager
+            .get_embedding_fallback_model_config(model_id)
+            .await
+        {
+            Ok(Some(config)) => config,
+            Ok(None) => return Err(exhausted()),
+            Err(e) => {
+                warn!(
+                    "[VfsEmbeddingService] Failed to load fallback embedding model: {}",
+                    e
+                );
+                return Err(exhausted());
+            }
+        };
+        let Some(expected_dim) =
+            expected_dim.or_else(|| self.llm_manager.default_text_embedding_dimension())
+        else {
+            warn!(
+                "[VfsEmbeddingService] Skip fallback model {}: index dimension unknown",
+                fallback.id
+            );
+            return Err(exhausted());
+        };
+
+        warn!(
+            "[VfsEmbeddingService] Batch {}-{} exhausted retries on {}, falling back to {}",
+            start + 1,
+            end,
+            model_id,
+            fallback.id
+        );
+        let embeddings = self
+            .call_with_retry(texts, &fallback.id, &policy)
+            .await
+            .map_err(|e| {
+                VfsError::Other(format!(
+                    "嵌入生成失败 (批次 {}-{}/{}): 主模型 {}；备用模型 {}，资源将由后台索引任务稍后重试",
+                    start + 1,
+                    end,
+                    total,
+                    primary_error,
+                    e
+                ))
+            })?;
+        ensure_fallback_dimension(&embeddings, expected_dim, &fallback.name)
+            .map_err(|e| VfsError::Other(e.message))?;
+        Ok(embeddings)
+    }
+
+    /// 按策略重试单个模型的嵌入调用；不可重试的错误（认证失败等）立即返回
+    async fn call_with_retry(
+        &self,
+        texts: &[String],
+        model_id: &str,
+        policy: &EmbeddingRetryPolicy,
+    ) -> Result<Vec<Vec<f32>>, String> {
+        let mut attempt = 0u32;
 
         loop {
             match self
@@ -324,34 +400,22 @@ impl VfsEmbeddingService {
                 .call_embedding_api(texts.to_vec(), model_id)
                 .await
             {
-                Ok(embeddings) => {
-                    return Ok(embeddings);
-                }
+                Ok(embeddings) => return Ok(embeddings),
                 Err(e) => {
-                    attempt += 1;
-                    if attempt >= MAX_RETRIES {
-                        return Err(VfsError::Other(format!(
-                            "嵌入生成失败 (批次 {}-{}/{}): {} (已重试 {} 次)",
-                            start + 1,
-                            end,
-                            total,
-                            e,
-                            MAX_RETRIES
-                        )));
+                    if !is_retryable_embedding_error(&e) || attempt >= policy.max_retries {
+                        return Err(format!("{} (已重试 {} 次)", e, attempt));
                     }
-
-                    // 指数退避: 400ms, 800ms, 1600ms, ...
-                    let backoff_ms = BASE_BACKOFF_MS * (1u64 << (attempt - 1));
+                    attempt += 1;
+                    let wait = policy.backoff_for(attempt, embedding_retry_after(&e));
                     warn!(
-                        "[VfsEmbeddingService] Batch {}-{} failed, retrying in {}ms (attempt {}/{}): {}",
-                        start + 1,
-                        end,
-                        backoff_ms,
+                        "[VfsEmbeddingService] Embedding call on {} failed, retrying in {}ms (attempt {}/{}): {}",
+                        model_id,
+                        wait.as_millis(),
                         attempt,
-                        MAX_RETRIES,
+                        policy.max_retries,
                         e
                     );
-                    tokio::time::sleep(std::time::Duration::from_millis(backoff_ms)).await;
+                    tokio::time::sleep(wait).await;
                 }
             }
         }
@@ -440,6 +504,18 @@ impl VfsEmbeddingPipeline {
         }
     }
 
+    /// 创建按指定批大小请求嵌入的流水线
+    pub fn with_batch_size(
+        llm_manager: Arc<LLMManager>,
+        lance_store: Arc<VfsLanceStore>,
+        batch_size: usize,
+    ) -> Self {
+        Self {
+            embedding_service: VfsEmbeddingService::with_batch_size(llm_manager, batch_size),
+            lance_store,
+        }
+    }
+
     /// 索引资源的文本块
     ///
     /// ## 参数
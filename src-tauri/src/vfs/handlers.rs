@@ -2743,9 +2743,11 @@ pub async fn vfs_get_pdf_page_image(
 
 use crate::vfs::embedding_service::EmbeddingProgressCallback;
 use crate::vfs::indexing::{
-    VfsEmbeddingStats, VfsFullIndexingService, VfsIndexingService, VfsSearchParams,
+    IndexStage, VfsEmbeddingStats, VfsFullIndexingService, VfsIndexingService, VfsSearchParams,
     VfsSearchResult, VfsSearchService,
 };
+use futures::StreamExt;
+use tauri::ipc::Channel;
 use crate::vfs::repos::VfsIndexingConfigRepo;
 
 #[tauri::command]
@@ -2783,6 +2785,10 @@ pub async fn vfs_reindex_resource(
     if !resource_id.starts_with("res_") {
         return Err(format!("Invalid resource ID format: {}", resource_id));
     }
+    llm_manager
+        .require_embedding_model()
+        .await
+        .map_err(|e| e.message)?;
 
     // ★ 2026-02 修复：并发防护 - 检查资源是否正在索引中，避免重复执行
     {
@@ -3360,22 +3366,74 @@ pub struct BatchIndexResult {
     pub fail_count: usize,
     /// 总数
     pub total: usize,
+    /// 成功的资源 ID
+    pub succeeded: Vec<String>,
+    /// 失败的资源及原因
+    pub failures: Vec<BatchIndexFailure>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchIndexFailure {
+    pub resource_id: String,
+    pub error: String,
+}
+
+/// 批量索引的逐资源进度（通过 Channel 推送）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchIndexProgress {
+    pub resource_id: String,
+    pub stage: IndexStage,
+    /// 已结束（成功或失败）的资源数
+    pub completed: usize,
+    pub total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunks_processed: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunks_total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 批量索引并发上限（`indexing.max_concurrent` 超出时截断）
+const MAX_BATCH_INDEX_CONCURRENCY: u32 = 8;
+
 /// 批量索引待处理资源（带进度事件）
+///
+/// 按 `indexing.max_concurrent` 并发处理；单个资源失败不影响其余资源。
+/// 逐资源阶段（parsing → chunking → embedding → done/failed）通过 `on_progress` 推送，
+/// 同时保留 `vfs-index-progress` 事件。
 #[tauri::command]
 pub async fn vfs_batch_index_pending(
     batch_size: Option<u32>,
+    on_progress: Option<Channel<BatchIndexProgress>>,
     app_handle: AppHandle,
     llm_manager: State<'_, Arc<crate::llm_manager::LLMManager>>,
     vfs_db: State<'_, Arc<VfsDatabase>>,
     lance_store: State<'_, Arc<crate::vfs::lance_store::VfsLanceStore>>,
+    state: State<'_, crate::commands::AppState>,
 ) -> Result<BatchIndexResult, String> {
     let batch_size = batch_size.unwrap_or(10);
     log::info!(
         "[VFS::handlers] vfs_batch_index_pending: batch_size={}",
         batch_size
     );
+    let embedding_config = llm_manager
+        .require_embedding_model()
+        .await
+        .map_err(|e| e.message)?;
+    // 离线模式下嵌入模型不在本机时不抢占资源，避免整批被标记为失败
+    crate::offline_mode::ensure_endpoint_allowed("嵌入模型", &embedding_config.base_url)
+        .map_err(|e| e.message)?;
+
+    // 索引会写入 SQLite 与 Lance 向量库：剩余空间低于阈值时拒绝启动（DISK_FULL）
+    let mut disk_guard = crate::disk_guard::DiskSpaceGuard::from_db(
+        &state.database,
+        vfs_db.db_path().to_path_buf(),
+    );
+    disk_guard.preflight(0).map_err(|e| e.message)?;
+    let disk_guard = std::sync::Mutex::new(disk_guard);
 
     let indexing_service = VfsIndexingService::new(Arc::clone(&vfs_db));
     log::info!("[VFS::handlers] vfs_batch_index_pending: 获取索引配置...");
@@ -3398,10 +3456,13 @@ pub async fn vfs_batch_index_pending(
             success_count: 0,
             fail_count: 0,
             total: 0,
+            succeeded: Vec::new(),
+            failures: Vec::new(),
         });
     }
 
     let total = pending.len();
+    let max_concurrent = config.max_concurrent.clamp(1, MAX_BATCH_INDEX_CONCURRENCY) as usize;
 
     // 发送批量索引开始事件
     let _ = app_handle.emit(
@@ -3413,9 +3474,6 @@ pub async fn vfs_batch_index_pending(
         }),
     );
 
-    let mut success_count = 0usize;
-    let mut fail_count = 0usize;
-
     // ★ 2026-02 修复：如果服务初始化失败，必须将已 claim 的资源回退为 pending
     // 否则资源会永久卡在 indexing 状态，不再被后续批量处理拾取
     let full_indexing_service = match VfsFullIndexingService::new(
@@ -3440,89 +3498,184 @@ pub async fn vfs_batch_index_pending(
         }
     };
 
-    for (index, resource_id) in pending.iter().enumerate() {
-        // ★ P1-2 修复: 将 "processing" 改为 "resource_started" 以匹配前端期望
-        let _ = app_handle.emit(
-            "vfs-index-progress",
-            serde_json::json!({
-                "type": "resource_started",
-                "resourceId": resource_id,
-                "current": index + 1,
-                "total": total,
-                "progress": ((index as f64 / total as f64) * 100.0) as u32,
-                "message": format!("正在索引资源 {}/{}", index + 1, total)
-            }),
-        );
+    let completed = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let send_progress = {
+        let channel = on_progress;
+        move |progress: BatchIndexProgress| {
+            if let Some(channel) = &channel {
+                let _ = channel.send(progress);
+            }
+        }
+    };
 
-        // ★ 构造嵌入进度回调，按 embedding batch (每16块) 粒度上报细粒度进度
-        let cb_handle = app_handle.clone();
-        let cb_resource_id = resource_id.clone();
-        let cb_index = index;
-        let cb_total = total;
-        let progress_callback: Option<EmbeddingProgressCallback> =
-            Some(Box::new(move |chunks_done: usize, chunks_total: usize| {
-                // 整体进度 = 当前资源基准 + 当前资源内嵌入子进度
-                let base = cb_index as f64 / cb_total as f64;
-                let sub = if chunks_total > 0 {
-                    chunks_done as f64 / chunks_total as f64 / cb_total as f64
-                } else {
-                    0.0
-                };
-                let progress = ((base + sub) * 100.0).min(99.0) as u32;
-                let _ = cb_handle.emit(
-                    "vfs-index-progress",
-                    serde_json::json!({
-                        "type": "embedding_progress",
-                        "resourceId": cb_resource_id,
-                        "current": cb_index + 1,
-                        "total": cb_total,
-                        "chunksProcessed": chunks_done,
-                        "chunksTotal": chunks_total,
-                        "progress": progress,
-                        "message": format!("正在索引资源 {}/{} (嵌入 {}/{})",
-                            cb_index + 1, cb_total, chunks_done, chunks_total)
-                    }),
-                );
-            }));
+    let outcomes: Vec<(String, Result<usize, String>)> = futures::stream::iter(pending)
+        .map(|resource_id| {
+            let app_handle = app_handle.clone();
+            let completed = Arc::clone(&completed);
+            let send_progress = send_progress.clone();
+            let service = &full_indexing_service;
+            let disk_guard = &disk_guard;
+            let vfs_db = Arc::clone(&vfs_db);
+            async move {
+                // 运行中磁盘空间不足：不再开始新资源，回退为 pending，待释放空间后重新索引
+                let disk_check = disk_guard.lock().map(|mut guard| guard.check());
+                if let Ok(Err(e)) = disk_check {
+                    let _ = VfsIndexStateRepo::mark_pending(&vfs_db, &resource_id);
+                    let done = completed.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+                    send_progress(BatchIndexProgress {
+                        resource_id: resource_id.clone(),
+                        stage: IndexStage::Failed,
+                        completed: done,
+                        total,
+                        chunks_processed: None,
+                        chunks_total: None,
+                        error: Some(e.message.clone()),
+                    });
+                    return (resource_id, Err(e.message));
+                }
 
-        match full_indexing_service
-            .index_resource(resource_id, None, progress_callback)
-            .await
-        {
-            Ok((chunk_count, _)) => {
-                success_count += 1;
-                // ★ 批判性检查修复: 添加 progress/current/total 字段，与前端期望一致
-                let _ = app_handle.emit(
-                    "vfs-index-progress",
-                    serde_json::json!({
-                        "type": "resource_completed",
-                        "resourceId": resource_id,
-                        "chunkCount": chunk_count,
-                        "current": index + 1,
-                        "total": total,
-                        "progress": (((index + 1) as f64 / total as f64) * 100.0) as u32,
-                        "message": format!("资源索引完成: {} 个块", chunk_count)
-                    }),
-                );
-            }
-            Err(e) => {
-                fail_count += 1;
-                log::warn!("[VFS::handlers] Failed to index {}: {}", resource_id, e);
+                // ★ P1-2 修复: 将 "processing" 改为 "resource_started" 以匹配前端期望
                 let _ = app_handle.emit(
                     "vfs-index-progress",
                     serde_json::json!({
-                        "type": "resource_failed",
+                        "type": "resource_started",
                         "resourceId": resource_id,
-                        "error": e.to_string(),
-                        "current": index + 1,
+                        "current": completed.load(std::sync::atomic::Ordering::Relaxed) + 1,
                         "total": total,
-                        "progress": (((index + 1) as f64 / total as f64) * 100.0) as u32,
-                        "message": format!("索引失败 ({}/{}): {}", index + 1, total, e)
+                        "progress": completed.load(std::sync::atomic::Ordering::Relaxed) * 100 / total,
+                        "message": format!("正在索引资源 {}", resource_id)
                     }),
                 );
+
+                let stage_progress = {
+                    let send_progress = send_progress.clone();
+                    let completed = Arc::clone(&completed);
+                    let resource_id = resource_id.clone();
+                    move |stage: IndexStage| {
+                        send_progress(BatchIndexProgress {
+                            resource_id: resource_id.clone(),
+                            stage,
+                            completed: completed.load(std::sync::atomic::Ordering::Relaxed),
+                            total,
+                            chunks_processed: None,
+                            chunks_total: None,
+                            error: None,
+                        });
+                    }
+                };
+
+                // ★ 构造嵌入进度回调，按 embedding batch (每16块) 粒度上报细粒度进度
+                let cb_handle = app_handle.clone();
+                let cb_resource_id = resource_id.clone();
+                let cb_completed = Arc::clone(&completed);
+                let cb_send = send_progress.clone();
+                let progress_callback: Option<EmbeddingProgressCallback> =
+                    Some(Box::new(move |chunks_done: usize, chunks_total: usize| {
+                        let done = cb_completed.load(std::sync::atomic::Ordering::Relaxed);
+                        let _ = cb_handle.emit(
+                            "vfs-index-progress",
+                            serde_json::json!({
+                                "type": "embedding_progress",
+                                "resourceId": cb_resource_id,
+                                "current": done + 1,
+                                "total": total,
+                                "chunksProcessed": chunks_done,
+                                "chunksTotal": chunks_total,
+                                "progress": (done * 100 / total).min(99),
+                                "message": format!("正在索引资源 {} (嵌入 {}/{})",
+                                    cb_resource_id, chunks_done, chunks_total)
+                            }),
+                        );
+                        cb_send(BatchIndexProgress {
+                            resource_id: cb_resource_id.clone(),
+                            stage: IndexStage::Embedding,
+                            completed: done,
+                            total,
+                            chunks_processed: Some(chunks_done),
+                            chunks_total: Some(chunks_total),
+                            error: None,
+                        });
+                    }));
+
+                let result = service
+                    .index_resource_with_stages(
+                        &resource_id,
+                        None,
+                        progress_callback,
+                        Some(&stage_progress),
+                    )
+                    .await;
+                let done = completed.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+                let progress = done * 100 / total;
+                match result {
+                    Ok((chunk_count, _)) => {
+                        // ★ 批判性检查修复: 添加 progress/current/total 字段，与前端期望一致
+                        let _ = app_handle.emit(
+                            "vfs-index-progress",
+                            serde_json::json!({
+                                "type": "resource_completed",
+                                "resourceId": resource_id,
+                                "chunkCount": chunk_count,
+                                "current": done,
+                                "total": total,
+                                "progress": progress,
+                                "message": format!("资源索引完成: {} 个块", chunk_count)
+                            }),
+                        );
+                        send_progress(BatchIndexProgress {
+                            resource_id: resource_id.clone(),
+                            stage: IndexStage::Done,
+                            completed: done,
+                            total,
+                            chunks_processed: Some(chunk_count),
+                            chunks_total: Some(chunk_count),
+                            error: None,
+                        });
+                        (resource_id, Ok(chunk_count))
+                    }
+                    Err(e) => {
+                        log::warn!("[VFS::handlers] Failed to index {}: {}", resource_id, e);
+                        let error = e.to_string();
+                        let _ = app_handle.emit(
+                            "vfs-index-progress",
+                            serde_json::json!({
+                                "type": "resource_failed",
+                                "resourceId": resource_id,
+                                "error": error,
+                                "current": done,
+                                "total": total,
+                                "progress": progress,
+                                "message": format!("索引失败 ({}/{}): {}", done, total, error)
+                            }),
+                        );
+                        send_progress(BatchIndexProgress {
+                            resource_id: resource_id.clone(),
+                            stage: IndexStage::Failed,
+                            completed: done,
+                            total,
+                            chunks_processed: None,
+                            chunks_total: None,
+                            error: Some(error.clone()),
+                        });
+                        (resource_id, Err(error))
+                    }
+                }
             }
+        })
+        .buffer_unordered(max_concurrent)
+        .collect()
+        .await;
+
+    let mut succeeded = Vec::new();
+    let mut failures = Vec::new();
+    for (resource_id, outcome) in outcomes {
+        match outcome {
+            Ok(_) => succeeded.push(resource_id),
+            Err(error) => failures.push(BatchIndexFailure { resource_id, error }),
         }
     }
+    let success_count = succeeded.len();
+    let fail_count = failures.len();
 
     // 发送批量索引完成事件
     let _ = app_handle.emit(
@@ -3541,6 +3694,8 @@ pub async fn vfs_batch_index_pending(
         success_count,
         fail_count,
         total,
+        succeeded,
+        failures,
     })
 }
 
@@ -3551,7 +3706,17 @@ pub async fn vfs_set_indexing_config(
     vfs_db: State<'_, Arc<VfsDatabase>>,
 ) -> Result<(), String> {
     log::info!("[VFS::handlers] vfs_set_indexing_config: {}={}", key, value);
-    VfsIndexingConfigRepo::set_config(&vfs_db, &key, &value).map_err(|e| e.to_string())
+    // 分块参数变化只标记 stale，不自动重建
+    let stale = VfsIndexingService::new(Arc::clone(&vfs_db))
+        .set_config_value(&key, &value)
+        .map_err(|e| e.to_string())?;
+    if stale > 0 {
+        log::info!(
+            "[VFS::handlers] vfs_set_indexing_config: 分块参数变更，{} 个资源待重建索引",
+            stale
+        );
+    }
+    Ok(())
 }
 
 #[tauri::command]
@@ -3643,7 +3808,7 @@ pub struct IndexStatusSummary {
     pub failed_count: i32,
     /// 禁用数
     pub disabled_count: i32,
-    /// 索引过时数（内容已更新但索引未更新）
+    /// 索引过时数（内容已更新但索引未更新，或分块参数变更后等待重建）
     pub stale_count: i32,
     // ========== 多模态索引统计 ==========
     /// 多模态总资源数（教材/附件/题目集/图片）
@@ -3658,6 +3823,8 @@ pub struct IndexStatusSummary {
     pub mm_failed_count: i32,
     /// 多模态禁用数
     pub mm_disabled_count: i32,
+    /// 是否已分配文本嵌入模型（未分配时前端提示先完成模型分配）
+    pub embedding_model_configured: bool,
     /// 资源状态列表
     pub resources: Vec<ResourceIndexStatus>,
 }
@@ -3693,6 +3860,7 @@ pub async fn vfs_get_all_index_status(
     limit: Option<u32>,
     offset: Option<u32>,
     vfs_db: State<'_, Arc<VfsDatabase>>,
+    llm_manager: State<'_, Arc<crate::llm_manager::LLMManager>>,
 ) -> Result<IndexStatusSummary, String> {
     log::info!(
         "[VFS::handlers] vfs_get_all_index_status: folder={:?}, type={:?}, state={:?}",
@@ -3701,6 +3869,8 @@ pub async fn vfs_get_all_index_status(
         state_filter
     );
 
+    let embedding_model_configured = llm_manager.has_embedding_model().await;
+
     let conn = vfs_db.get_conn_safe().map_err(|e| e.to_string())?;
 
     // 检查必要的列和表是否存在
@@ -3726,6 +3896,7 @@ pub async fn vfs_get_all_index_status(
             mm_indexing_count: 0,
             mm_failed_count: 0,
             mm_disabled_count: 0,
+            embedding_model_configured,
             resources: vec![],
         });
     }
@@ -3947,6 +4118,7 @@ pub async fn vfs_get_all_index_status(
             {modality} as modality,
             r.updated_at,
             CASE
+                WHEN r.index_state = 'stale' THEN 1
                 WHEN COALESCE(r.index_state, 'pending') = 'indexed'
                      AND r.index_hash IS NOT NULL
                      AND r.index_hash != r.hash
@@ -4136,6 +4308,7 @@ pub async fn vfs_get_all_index_status(
             COALESCE(SUM(CASE WHEN r.index_state = 'failed' THEN 1 ELSE 0 END), 0) as failed,
             COALESCE(SUM(CASE WHEN r.index_state = 'disabled' THEN 1 ELSE 0 END), 0) as disabled,
             COALESCE(SUM(CASE
+                WHEN r.index_state = 'stale' THEN 1
                 WHEN COALESCE(r.index_state, 'pending') = 'indexed'
                      AND r.index_hash IS NOT NULL AND r.index_hash != r.hash
                 THEN 1 ELSE 0 END), 0) as stale
@@ -4266,6 +4439,7 @@ pub async fn vfs_get_all_index_status(
         mm_indexing_count: mm_indexing,
         mm_failed_count: mm_failed,
         mm_disabled_count: mm_disabled,
+        embedding_model_configured,
         resources,
     })
 }
@@ -4285,6 +4459,11 @@ pub struct VfsRagSearchInput {
     #[serde(default)]
     pub folder_ids: Option<Vec<String>>,
 
+    /// 各知识库（文件夹 ID）的检索权重（可选）：片段分数在合并前乘以所属库的权重，
+    /// 未列出的库为 1；权重须非负且至少一个为正
+    #[serde(default)]
+    pub folder_weights: Option<std::collections::HashMap<String, f32>>,
+
     /// 资源类型列表（可选，如 ["note", "textbook"]）
     #[serde(default)]
     pub resource_types: Option<Vec<String>>,
@@ -4304,6 +4483,23 @@ pub struct VfsRagSearchInput {
     /// 是否启用跨维度搜索（聚合所有已分配模型的维度，默认启用）
     #[serde(default = "default_enable_cross_dimension")]
     pub enable_cross_dimension: bool,
+
+    /// 最低分阈值（0~1，可选）：低于该分数的片段丢弃，未设置时不过滤
+    #[serde(default)]
+    pub min_score: Option<f32>,
+
+    /// 是否返回检索调试轨迹（默认关闭）
+    #[serde(default)]
+    pub debug: bool,
+
+    /// 调试轨迹中提示词的最大字符数（未设置时不截断）
+    #[serde(default)]
+    pub debug_prompt_max_chars: Option<usize>,
+
+    /// 仅本次查询使用的嵌入模型配置 ID（高级，仅文本检索）：维度须与知识库已索引维度一致，
+    /// 与索引时不同的模型通常会降低召回，见 `crate::rag_embedding_override`
+    #[serde(default)]
+    pub embedding_model_override: Option<String>,
 }
 
 fn default_modality() -> String {
@@ -4332,6 +4528,9 @@ pub struct VfsRagSearchOutput {
     pub count: usize,
     /// 检索耗时（毫秒）
     pub elapsed_ms: u64,
+    /// 调试轨迹（仅 `debug: true` 时返回）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug_trace: Option<crate::rag_debug::RagDebugTrace>,
 }
 
 /// VFS RAG 向量检索命令
@@ -4344,6 +4543,9 @@ pub struct VfsRagSearchOutput {
 /// - `input.resource_types`: 可选的资源类型列表
 /// - `input.top_k`: 返回结果数量
 /// - `input.enable_reranking`: 是否启用重排序
+/// - `input.folder_weights`: 可选的各库权重（偏向更可信的知识库）
+/// - `input.min_score`: 可选的最低分阈值
+/// - `input.debug`: 是否返回调试轨迹（原始/重排序分数、被阈值丢弃的片段、拼装后的提示词）
 ///
 /// ## 返回
 /// 检索结果列表、数量和耗时
@@ -4353,9 +4555,11 @@ pub async fn vfs_rag_search(
     vfs_db: State<'_, Arc<VfsDatabase>>,
     llm_manager: State<'_, Arc<crate::llm_manager::LLMManager>>,
     lance_store: State<'_, Arc<crate::vfs::lance_store::VfsLanceStore>>,
+    state: State<'_, crate::commands::AppState>,
 ) -> Result<VfsRagSearchOutput, String> {
     use crate::vfs::indexing::{VfsFullSearchService, VfsSearchParams};
     use crate::vfs::repos::MODALITY_TEXT;
+    let _timer = crate::command_metrics::start("vfs_rag_search");
 
     let start = std::time::Instant::now();
 
@@ -4371,6 +4575,12 @@ pub async fn vfs_rag_search(
     if input.query.trim().is_empty() {
         return Err("查询文本不能为空".to_string());
     }
+    if let Some(min_score) = input.min_score {
+        crate::rag_settings::validate_min_score(min_score).map_err(|e| e.message)?;
+    }
+    if let Some(weights) = input.folder_weights.as_ref() {
+        crate::rag_library_weights::validate_library_weights(weights).map_err(|e| e.message)?;
+    }
 
     let lance_store = Arc::clone(lance_store.inner());
 
@@ -4388,6 +4598,21 @@ pub async fn vfs_rag_search(
         }
     };
 
+    let embedding_override_id = crate::rag_embedding_override::normalize_override(
+        input.embedding_model_override.as_deref(),
+    );
+    if embedding_override_id.is_some() && modality != MODALITY_TEXT {
+        return Err("embedding_model_override 仅支持文本检索".to_string());
+    }
+
+    // 文本检索依赖嵌入模型：未分配时直接返回 RAG_NO_EMBEDDING_MODEL，不进入检索流程
+    if modality == MODALITY_TEXT && embedding_override_id.is_none() {
+        llm_manager
+            .require_embedding_model()
+            .await
+            .map_err(|e| e.message)?;
+    }
+
     // 构建搜索参数
     let params = VfsSearchParams {
         query: input.query.clone(),
@@ -4396,10 +4621,66 @@ pub async fn vfs_rag_search(
         resource_types: input.resource_types,
         modality,
         top_k: input.top_k,
+        folder_weights: input.folder_weights,
     };
 
+    let embedding_override = match embedding_override_id.as_deref() {
+        Some(model_config_id) => Some(
+            search_service
+                .resolve_embedding_override(model_config_id, &input.query, &params.modality)
+                .await
+                .map_err(|e| e.to_string())?,
+        ),
+        None => None,
+    };
+
+    // 查询扩展（仅文本检索；未启用时为空，检索流程不变）
+    let expansions = if params.modality == MODALITY_TEXT {
+        let settings = crate::rag_settings::load_rag_retrieval_settings(&state.database);
+        crate::rag_query_expansion::expand_query(&llm_manager, &settings, &input.query).await
+    } else {
+        Vec::new()
+    };
+
+    // 调试模式 / 查询扩展 / 嵌入模型覆盖：保留重排序前的分数并记录被阈值丢弃的片段
+    if input.debug || !expansions.is_empty() || embedding_override.is_some() {
+        let traced = search_service
+            .search_with_score_trace(
+                &input.query,
+                &expansions,
+                &params,
+                input.enable_reranking,
+                input.enable_cross_dimension,
+                embedding_override.as_ref(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        let (results, mut trace) = crate::rag_debug::apply_min_score_with_trace(
+            &input.query,
+            expansions,
+            traced,
+            input.min_score,
+            input.debug_prompt_max_chars,
+        );
+        trace.embedding_model_override = embedding_override.map(|ov| ov.model);
+        let elapsed = start.elapsed();
+        log::info!(
+            "[VFS::handlers] vfs_rag_search (traced) completed: {} results, {} expansions, {} dropped by min_score in {}ms",
+            results.len(),
+            trace.expanded_queries.len(),
+            trace.dropped_count,
+            elapsed.as_millis()
+        );
+        return Ok(VfsRagSearchOutput {
+            count: results.len(),
+            results,
+            elapsed_ms: elapsed.as_millis() as u64,
+            debug_trace: input.debug.then_some(trace),
+        });
+    }
+
     // 执行检索（支持跨维度搜索）
-    let results = if input.enable_cross_dimension {
+    let mut results = if input.enable_cross_dimension {
         // 跨维度搜索：聚合所有已分配模型的维度
         search_service
             .search_cross_dimension_with_resource_info(
@@ -4416,6 +4697,9 @@ pub async fn vfs_rag_search(
             .await
             .map_err(|e| e.to_string())?
     };
+    if let Some(min_score) = input.min_score.filter(|score| *score > 0.0) {
+        results.retain(|r| r.score >= min_score as f64);
+    }
 
     let elapsed = start.elapsed();
     let count = results.len();
@@ -4430,6 +4714,7 @@ pub async fn vfs_rag_search(
         results,
         count,
         elapsed_ms: elapsed.as_millis() as u64,
+        debug_trace: None,
     })
 }
 
@@ -4796,6 +5081,8 @@ pub struct IndexStateCounts {
     pub indexed: i32,
     pub failed: i32,
     pub disabled: i32,
+    /// 分块参数变更后等待重建（仍可检索旧向量）
+    pub stale: i32,
     pub null_state: i32,
 }
 
@@ -4901,6 +5188,7 @@ pub async fn vfs_debug_index_status(
             COALESCE(SUM(CASE WHEN index_state = 'indexed' THEN 1 ELSE 0 END), 0) as indexed,
             COALESCE(SUM(CASE WHEN index_state = 'failed' THEN 1 ELSE 0 END), 0) as failed,
             COALESCE(SUM(CASE WHEN index_state = 'disabled' THEN 1 ELSE 0 END), 0) as disabled,
+            COALESCE(SUM(CASE WHEN index_state = 'stale' THEN 1 ELSE 0 END), 0) as stale,
             COALESCE(SUM(CASE WHEN index_state IS NULL THEN 1 ELSE 0 END), 0) as null_state
         FROM resources
         "#,
@@ -4912,7 +5200,8 @@ pub async fn vfs_debug_index_status(
                     indexed: row.get(2)?,
                     failed: row.get(3)?,
                     disabled: row.get(4)?,
-                    null_state: row.get(5)?,
+                    stale: row.get(5)?,
+                    null_state: row.get(6)?,
                 })
             },
         )
@@ -6613,6 +6902,9 @@ pub async fn vfs_download_paper(
     if !params.url.starts_with("https://") {
         return Err("Only HTTPS URLs are allowed".to_string());
     }
+    if crate::offline_mode::is_enabled() {
+        return Err(crate::offline_mode::offline_message("论文下载"));
+    }
 
     // 下载 PDF
     let client = reqwest::Client::builder()
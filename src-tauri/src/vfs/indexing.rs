@@ -8,9 +8,11 @@ use tracing::{debug, error, info, warn};
 
 use crate::llm_manager::LLMManager;
 use crate::models::PdfOcrTextBlock;
+use crate::rag_embedding_override::{EmbeddingModelOverride, QueryEmbeddingOverride};
 use crate::vfs::database::VfsDatabase;
 use crate::vfs::embedding_service::{
     EmbeddingProgressCallback, VfsEmbeddingPipeline, VfsEmbeddingService,
+    DEFAULT_BATCH_SIZE as DEFAULT_EMBEDDING_BATCH_SIZE,
 };
 use crate::vfs::error::{VfsError, VfsResult};
 use crate::vfs::index_service::VfsIndexService;
@@ -36,7 +38,25 @@ fn log_and_skip_err<T>(result: Result<T, rusqlite::Error>) -> Option<T> {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 单个资源索引所处阶段（用于批量索引的逐资源进度上报）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexStage {
+    /// 读取/解析内容（含自动 OCR）
+    Parsing,
+    Chunking,
+    Embedding,
+    Done,
+    Failed,
+}
+
+/// 阶段回调
+pub type IndexStageCallback<'a> = &'a (dyn Fn(IndexStage) + Send + Sync);
+
+/// 分块参数变更后写入 stale 资源 `index_error` 的原因
+pub const STALE_REASON_CHUNKING_CHANGED: &str = "chunking_changed";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ChunkingConfig {
     pub strategy: String,
@@ -56,6 +76,28 @@ impl Default for ChunkingConfig {
     }
 }
 
+impl ChunkingConfig {
+    pub fn validate(&self) -> VfsResult<()> {
+        let invalid = |param: &str, reason: &str| VfsError::InvalidArgument {
+            param: param.to_string(),
+            reason: reason.to_string(),
+        };
+        if self.strategy.trim().is_empty() {
+            return Err(invalid("strategy", "分块策略不能为空"));
+        }
+        if self.chunk_size == 0 {
+            return Err(invalid("chunkSize", "分块大小必须大于 0"));
+        }
+        if self.chunk_overlap >= self.chunk_size {
+            return Err(invalid("chunkOverlap", "重叠长度必须小于分块大小"));
+        }
+        if self.min_chunk_size > self.chunk_size {
+            return Err(invalid("minChunkSize", "最小分块不能大于分块大小"));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct IndexingConfig {
@@ -1558,6 +1600,69 @@ impl VfsIndexingService {
         })
     }
 
+    /// 每次嵌入请求的分块数
+    pub fn get_embedding_batch_size(&self) -> VfsResult<usize> {
+        let batch_size = VfsIndexingConfigRepo::get_i32(
+            &self.db,
+            "embedding.batch_size",
+            DEFAULT_EMBEDDING_BATCH_SIZE as i32,
+        )?;
+        Ok(usize::try_from(batch_size).unwrap_or(0).max(1))
+    }
+
+    /// 保存嵌入批大小，之后创建的索引服务按此大小分批请求嵌入
+    pub fn update_embedding_batch_size(&self, batch_size: usize) -> VfsResult<()> {
+        VfsIndexingConfigRepo::set_config(
+            &self.db,
+            "embedding.batch_size",
+            &batch_size.max(1).to_string(),
+        )
+    }
+
+    /// 保存分块参数，返回因此新标记为 stale 的资源数
+    ///
+    /// 生效值变化时已索引资源被标记为 stale，但不会自动重建（全量重嵌入代价高），
+    /// 由用户确认后通过 `reindex_stale_documents` 触发。
+    pub fn update_chunking_config(&self, config: &ChunkingConfig) -> VfsResult<usize> {
+        config.validate()?;
+        let before = self.get_chunking_config()?;
+        VfsIndexingConfigRepo::set_config(&self.db, "chunking.strategy", &config.strategy)?;
+        VfsIndexingConfigRepo::set_config(
+            &self.db,
+            "chunking.chunk_size",
+            &config.chunk_size.to_string(),
+        )?;
+        VfsIndexingConfigRepo::set_config(
+            &self.db,
+            "chunking.chunk_overlap",
+            &config.chunk_overlap.to_string(),
+        )?;
+        VfsIndexingConfigRepo::set_config(
+            &self.db,
+            "chunking.min_chunk_size",
+            &config.min_chunk_size.to_string(),
+        )?;
+        self.mark_stale_if_chunking_changed(&before)
+    }
+
+    /// 写入单个索引配置项；`chunking.*` 的生效值变化时同样标记 stale
+    pub fn set_config_value(&self, key: &str, value: &str) -> VfsResult<usize> {
+        if !key.starts_with("chunking.") {
+            VfsIndexingConfigRepo::set_config(&self.db, key, value)?;
+            return Ok(0);
+        }
+        let before = self.get_chunking_config()?;
+        VfsIndexingConfigRepo::set_config(&self.db, key, value)?;
+        self.mark_stale_if_chunking_changed(&before)
+    }
+
+    fn mark_stale_if_chunking_changed(&self, before: &ChunkingConfig) -> VfsResult<usize> {
+        if self.get_chunking_config()? == *before {
+            return Ok(0);
+        }
+        VfsIndexStateRepo::mark_indexed_as_stale(&self.db, STALE_REASON_CHUNKING_CHANGED)
+    }
+
     pub fn get_search_config(&self) -> VfsResult<SearchConfig> {
         Ok(SearchConfig {
             default_top_k: VfsIndexingConfigRepo::get_i32(&self.db, "search.default_top_k", 10)?
@@ -1793,7 +1898,11 @@ impl VfsFullIndexingService {
     ) -> VfsResult<Self> {
         let basic_service = VfsIndexingService::new(db.clone());
         let chunking_config = basic_service.get_chunking_config()?;
-        let pipeline = VfsEmbeddingPipeline::new(llm_manager.clone(), Arc::clone(&lance_store));
+        let pipeline = VfsEmbeddingPipeline::with_batch_size(
+            llm_manager.clone(),
+            Arc::clone(&lance_store),
+            basic_service.get_embedding_batch_size()?,
+        );
 
         Ok(Self {
             db,
@@ -2018,6 +2127,23 @@ impl VfsFullIndexingService {
         folder_id: Option<&str>,
         progress_callback: Option<EmbeddingProgressCallback>,
     ) -> VfsResult<(usize, usize)> {
+        self.index_resource_with_stages(resource_id, folder_id, progress_callback, None)
+            .await
+    }
+
+    /// 同 [`Self::index_resource`]，并在进入解析/分块/嵌入阶段时回调 `on_stage`
+    pub async fn index_resource_with_stages(
+        &self,
+        resource_id: &str,
+        folder_id: Option<&str>,
+        progress_callback: Option<EmbeddingProgressCallback>,
+        on_stage: Option<IndexStageCallback<'_>>,
+    ) -> VfsResult<(usize, usize)> {
+        let stage = |s: IndexStage| {
+            if let Some(cb) = on_stage {
+                cb(s);
+            }
+        };
         // 0. 同步资源到 vfs_index_units 表（VFS 统一索引架构）
         // ★ 2026-02 修复：检查 Units 是否已存在且有效，避免删除 Pipeline 创建的完整 Units
         let conn = self.db.get_conn_safe()?;
@@ -2101,6 +2227,7 @@ impl VfsFullIndexingService {
         // 避免先删后写导致嵌入生成失败时资源在 LanceDB 中完全丢失的检索空窗。
 
         // 3. 提取内容
+        stage(IndexStage::Parsing);
         // ★ 核心修复：移除 resource.data.is_empty() 提前检查
         // external 资源的 data 字段为空是正常的，内容存储在关联表中
         // 由 resolve_indexable_content 统一处理所有资源类型的内容获取
@@ -2179,6 +2306,7 @@ impl VfsFullIndexingService {
         let content = content.unwrap();
 
         // 4. 分块
+        stage(IndexStage::Chunking);
         // ★ 2026-01 优化：优先尝试按页分块以保留 page_index 信息
         // 使用 resolve_indexable_pages 从数据库获取按页信息（支持 textbooks.ocr_pages_json 等）
         let chunks = if let Some(pages) = resolve_indexable_pages(&conn, &resource) {
@@ -2225,6 +2353,7 @@ impl VfsFullIndexingService {
         );
 
         // 5. 生成嵌入并存储到 Lance
+        stage(IndexStage::Embedding);
         let resource_type_str = resource.resource_type.to_string();
         match self
             .pipeline
@@ -3636,6 +3765,16 @@ pub struct VfsSearchParams {
     pub modality: String,
     #[serde(default = "default_top_k")]
     pub top_k: u32,
+    /// 多库检索时各库（文件夹 ID）的权重，见 `rag_library_weights`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder_weights: Option<std::collections::HashMap<String, f32>>,
+}
+
+impl VfsSearchParams {
+    /// 每个维度向向量库请求的候选数（库权重非均等时多取）
+    fn fetch_limit(&self) -> usize {
+        crate::rag_library_weights::fetch_limit(self.top_k, self.folder_weights.as_ref())
+    }
 }
 
 fn default_modality() -> String {
@@ -3664,6 +3803,20 @@ pub struct VfsSearchResult {
     /// 来源 ID（如 textbook_xxx, att_xxx）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_id: Option<String>,
+    /// 片段所属知识库（文件夹 ID）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder_id: Option<String>,
+}
+
+/// 带原始分数的检索结果（见 `VfsFullSearchService::search_with_score_trace`）
+#[derive(Debug, Clone)]
+pub struct VfsScoreTracedResults {
+    /// 最终结果（重排序后，已补充资源信息并过滤软删除资源）
+    pub results: Vec<VfsSearchResult>,
+    /// 重排序前的原始分数：embedding_id -> score
+    pub raw_scores: std::collections::HashMap<String, f64>,
+    /// 是否实际执行了重排序（未配置重排序模型时为 false）
+    pub reranked: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -3718,6 +3871,7 @@ impl VfsSearchService {
                     resource_type: None,
                     page_index: None,
                     source_id: None,
+                    folder_id: None,
                 })
             })?
             .filter_map(log_and_skip_err)
@@ -3726,6 +3880,53 @@ impl VfsSearchService {
         Ok(results)
     }
 
+    /// 按关键词检索已索引的文本分块（不调用嵌入模型），跳过已禁用的索引单元，
+    /// 并像向量检索一样补充资源信息、过滤已删除的资源
+    pub fn search_fts_with_resource_info(
+        &self,
+        query: &str,
+        top_k: u32,
+    ) -> VfsResult<Vec<VfsSearchResult>> {
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let conn = self.db.get_conn_safe()?;
+        let mut stmt = conn.prepare(
+            r#"SELECT s.id, u.resource_id, s.content_text, s.segment_index
+               FROM vfs_index_segments s
+               JOIN vfs_index_units u ON s.unit_id = u.id
+               JOIN resources r ON r.id = u.resource_id
+               WHERE s.modality = 'text'
+                 AND u.text_state != 'disabled'
+                 AND r.deleted_at IS NULL
+                 AND s.content_text LIKE '%' || ?1 || '%' ESCAPE '\'
+               ORDER BY s.updated_at DESC
+               LIMIT ?2"#,
+        )?;
+        let results: Vec<VfsSearchResult> = stmt
+            .query_map(rusqlite::params![escaped, top_k], |row| {
+                Ok(VfsSearchResult {
+                    embedding_id: row.get(0)?,
+                    resource_id: row.get(1)?,
+                    chunk_index: row.get(3)?,
+                    chunk_text: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    score: 1.0,
+                    resource_title: None,
+                    resource_type: None,
+                    page_index: None,
+                    source_id: None,
+                    folder_id: None,
+                })
+            })?
+            .filter_map(log_and_skip_err)
+            .collect();
+        drop(stmt);
+        drop(conn);
+
+        VfsFullSearchService::enrich_and_filter_results(&self.db, results)
+    }
+
     pub fn get_embedding_stats(&self) -> VfsResult<VfsEmbeddingStats> {
         let conn = self.db.get_conn_safe()?;
 
@@ -3827,7 +4028,7 @@ impl VfsFullSearchService {
             .vector_search_full(
                 &params.modality,
                 &query_embedding,
-                params.top_k as usize,
+                params.fetch_limit(),
                 folder_ids.as_deref(),
                 resource_ids.as_deref(),
                 resource_types.as_deref(),
@@ -3835,7 +4036,8 @@ impl VfsFullSearchService {
             .await?;
 
         // 3. 转换为 VfsSearchResult
-        let results = self.lance_results_to_search_results(lance_results);
+        let mut results = self.lance_results_to_search_results(lance_results);
+        Self::apply_folder_weights(&mut results, params);
 
         info!(
             "[VfsFullSearchService] Vector search '{}' returned {} results",
@@ -3873,7 +4075,7 @@ impl VfsFullSearchService {
                 &params.modality,
                 query,
                 &query_embedding,
-                params.top_k as usize,
+                params.fetch_limit(),
                 folder_ids.as_deref(),
                 resource_ids.as_deref(),
                 resource_types.as_deref(),
@@ -3881,7 +4083,8 @@ impl VfsFullSearchService {
             .await?;
 
         // 3. 转换为 VfsSearchResult
-        let results = self.lance_results_to_search_results(lance_results);
+        let mut results = self.lance_results_to_search_results(lance_results);
+        Self::apply_folder_weights(&mut results, params);
 
         info!(
             "[VfsFullSearchService] Hybrid search '{}' returned {} results",
@@ -3940,7 +4143,7 @@ impl VfsFullSearchService {
                         &params.modality,
                         query,
                         query_embedding,
-                        params.top_k as usize,
+                        params.fetch_limit(),
                         folder_ids.as_deref(),
                         resource_ids.as_deref(),
                         resource_types.as_deref(),
@@ -3954,7 +4157,7 @@ impl VfsFullSearchService {
                     .vector_search_full(
                         &params.modality,
                         query_embedding,
-                        params.top_k as usize,
+                        params.fetch_limit(),
                         folder_ids.as_deref(),
                         resource_ids.as_deref(),
                         resource_types.as_deref(),
@@ -3966,9 +4169,10 @@ impl VfsFullSearchService {
                 VfsSearchService::new(self.db.clone()).search_fts(query, params.top_k)?
             }
         };
+        Self::apply_folder_weights(&mut results, params);
 
         if enable_reranking && !results.is_empty() && config.enable_reranking {
-            results = self.rerank_results(query, results).await?;
+            results = self.rerank_results(query, results, params).await?;
         }
 
         Ok(results)
@@ -3979,20 +4183,105 @@ impl VfsFullSearchService {
         self.embedding_service.generate_embedding(query).await
     }
 
-    /// 重排序搜索结果
-    async fn rerank_results(
+    /// 用指定的嵌入模型生成查询向量（不经过全局嵌入模型分配）
+    async fn embed_query_with_model(
         &self,
         query: &str,
-        results: Vec<VfsSearchResult>,
-    ) -> VfsResult<Vec<VfsSearchResult>> {
-        // 获取重排序模型配置
+        model_config_id: &str,
+    ) -> VfsResult<Vec<f32>> {
+        let embeddings = self
+            .llm_manager
+            .call_embedding_api(vec![query.to_string()], model_config_id)
+            .await
+            .map_err(|e| VfsError::Other(format!("生成嵌入向量失败: {}", e)))?;
+        embeddings
+            .into_iter()
+            .next()
+            .ok_or_else(|| VfsError::Other("嵌入 API 返回空结果".to_string()))
+    }
+
+    /// 校验单次检索的查询嵌入模型覆盖，并生成原查询的向量
+    ///
+    /// 模型须为嵌入模型，且输出维度与该模态已索引的某个维度一致，
+    /// 见 `crate::rag_embedding_override`。
+    pub async fn resolve_embedding_override(
+        &self,
+        model_config_id: &str,
+        query: &str,
+        modality: &str,
+    ) -> VfsResult<QueryEmbeddingOverride> {
+        let invalid = |reason: String| VfsError::InvalidArgument {
+            param: "embedding_model_override".to_string(),
+            reason,
+        };
+        let config = self
+            .llm_manager
+            .get_api_configs()
+            .await
+            .map_err(|e| VfsError::Other(format!("获取模型配置失败: {}", e)))?
+            .into_iter()
+            .find(|cfg| cfg.id == model_config_id)
+            .ok_or_else(|| invalid(format!("找不到模型配置 {}", model_config_id)))?;
+        if !config.is_embedding {
+            return Err(invalid(format!("模型 {} 不是嵌入模型", config.name)));
+        }
+
+        let query_embedding = self.embed_query_with_model(query, &config.id).await?;
+        let conn = self.db.get_conn()?;
+        let stored_dimensions: Vec<i32> = embedding_dim_repo::list_by_modality(&conn, modality)?
+            .into_iter()
+            .filter(|d| d.record_count > 0)
+            .map(|d| d.dimension)
+            .collect();
+        drop(conn);
+        if let Some(reason) = crate::rag_embedding_override::dimension_mismatch(
+            &config.name,
+            query_embedding.len(),
+            &stored_dimensions,
+        ) {
+            return Err(invalid(reason));
+        }
+
+        info!(
+            "[VfsFullSearchService] Query embedding override: model={} ({}), dimension={}",
+            config.name,
+            config.id,
+            query_embedding.len()
+        );
+        Ok(QueryEmbeddingOverride {
+            model: EmbeddingModelOverride {
+                model_config_id: config.id,
+                model_name: config.name,
+                dimension: query_embedding.len(),
+            },
+            query_embedding,
+        })
+    }
+
+    /// 当前分配的重排序模型配置 ID（未配置时为 None）
+    async fn reranker_model_id(&self) -> VfsResult<Option<String>> {
         let model_assignments = self
             .llm_manager
             .get_model_assignments()
             .await
             .map_err(|e| VfsError::Other(format!("获取模型分配失败: {}", e)))?;
+        Ok(model_assignments.reranker_model_config_id)
+    }
+
+    /// 按库权重调整分数并截断到 top_k（未设置权重时不变）
+    fn apply_folder_weights(results: &mut Vec<VfsSearchResult>, params: &VfsSearchParams) {
+        crate::rag_library_weights::apply_library_weights(results, params.folder_weights.as_ref());
+        results.truncate(params.top_k as usize);
+    }
 
-        let reranker_model_id = match model_assignments.reranker_model_config_id {
+    /// 重排序搜索结果（重排序分数同样按库权重调整）
+    async fn rerank_results(
+        &self,
+        query: &str,
+        results: Vec<VfsSearchResult>,
+        params: &VfsSearchParams,
+    ) -> VfsResult<Vec<VfsSearchResult>> {
+        let reranker_model_id = match self.reranker_model_id().await? {
             Some(id) => id,
             None => {
                 debug!("[VfsFullSearchService] No reranker model configured, skipping reranking");
@@ -4023,7 +4312,7 @@ impl VfsFullSearchService {
             .map_err(|e| VfsError::Other(format!("重排序失败: {}", e)))?;
 
         // 根据重排序结果重新排序
-        let reranked_results: Vec<VfsSearchResult> = reranked
+        let mut reranked_results: Vec<VfsSearchResult> = reranked
             .into_iter()
             .filter_map(|rc| {
                 results
@@ -4036,6 +4325,11 @@ impl VfsFullSearchService {
             })
             .collect();
 
+        crate::rag_library_weights::apply_library_weights(
+            &mut reranked_results,
+            params.folder_weights.as_ref(),
+        );
+
         info!(
             "[VfsFullSearchService] Reranked {} results",
             reranked_results.len()
@@ -4061,6 +4355,7 @@ impl VfsFullSearchService {
                 resource_type: Some(lr.resource_type),
                 page_index: lr.page_index,
                 source_id: lr.source_id,
+                folder_id: lr.folder_id,
             })
             .collect()
     }
@@ -4315,7 +4610,7 @@ impl VfsFullSearchService {
                         &params.modality,
                         query,
                         &query_embedding,
-                        params.top_k as usize,
+                        params.fetch_limit(),
                         folder_ids.as_deref(),
                         resource_ids.as_deref(),
                         resource_types.as_deref(),
@@ -4334,7 +4629,7 @@ impl VfsFullSearchService {
                             .vector_search_full(
                                 &params.modality,
                                 &query_embedding,
-                                params.top_k as usize,
+                                params.fetch_limit(),
                                 folder_ids.as_deref(),
                                 resource_ids.as_deref(),
                                 resource_types.as_deref(),
@@ -4358,7 +4653,7 @@ impl VfsFullSearchService {
                     .vector_search_full(
                         &params.modality,
                         &query_embedding,
-                        params.top_k as usize,
+                        params.fetch_limit(),
                         folder_ids.as_deref(),
                         resource_ids.as_deref(),
                         resource_types.as_deref(),
@@ -4387,6 +4682,12 @@ impl VfsFullSearchService {
             all_results.extend(dim_results);
         }
 
+        // 合并前按库权重调整分数
+        crate::rag_library_weights::apply_library_weights(
+            &mut all_results,
+            params.folder_weights.as_ref(),
+        );
+
         // 4. 去重（同一 resource_id + chunk_index 只保留分数最高的）
         let mut seen: std::collections::HashMap<(String, i32), usize> =
             std::collections::HashMap::new();
@@ -4439,7 +4740,7 @@ impl VfsFullSearchService {
         if enable_reranking && !results.is_empty() {
             let config = VfsIndexingService::new(self.db.clone()).get_search_config()?;
             if config.enable_reranking {
-                results = self.rerank_results(query, results).await?;
+                results = self.rerank_results(query, results, params).await?;
             }
         }
 
@@ -4461,7 +4762,7 @@ impl VfsFullSearchService {
         if enable_reranking && !results.is_empty() {
             let config = VfsIndexingService::new(self.db.clone()).get_search_config()?;
             if config.enable_reranking {
-                results = self.rerank_results(query, results).await?;
+                results = self.rerank_results(query, results, params).await?;
             }
         }
 
@@ -4469,6 +4770,77 @@ impl VfsFullSearchService {
         Self::enrich_and_filter_results(&self.db, results)
     }
 
+    /// 检索并保留重排序前的原始分数（调试轨迹与查询扩展用）
+    ///
+    /// 与 `search_with_resource_info` / `search_cross_dimension_with_resource_info`
+    /// 的流程一致，只是在重排序前记录每个片段的原始分数。
+    /// `expansions` 非空时逐条检索并与原查询结果合并，之后用原查询重排序，
+    /// 最终截断到 `params.top_k`。
+    /// `embedding_override` 存在时所有查询都用覆盖模型生成向量，只检索其维度。
+    pub async fn search_with_score_trace(
+        &self,
+        query: &str,
+        expansions: &[String],
+        params: &VfsSearchParams,
+        enable_reranking: bool,
+        cross_dimension: bool,
+        embedding_override: Option<&QueryEmbeddingOverride>,
+    ) -> VfsResult<VfsScoreTracedResults> {
+        let mut result_sets = Vec::with_capacity(expansions.len() + 1);
+        for q in std::iter::once(query).chain(expansions.iter().map(String::as_str)) {
+            let results = match embedding_override {
+                Some(ov) if q == query => {
+                    self.search_with_embedding(q, &ov.query_embedding, params, false)
+                        .await
+                }
+                Some(ov) => match self
+                    .embed_query_with_model(q, &ov.model.model_config_id)
+                    .await
+                {
+                    Ok(embedding) => {
+                        self.search_with_embedding(q, &embedding, params, false)
+                            .await
+                    }
+                    Err(e) => Err(e),
+                },
+                None if cross_dimension => self.cross_dimension_search(q, params).await,
+                None => self.search(q, params, false).await,
+            };
+            match results {
+                Ok(results) => result_sets.push(results),
+                // 扩展查询检索失败不影响原查询结果
+                Err(e) if q != query => {
+                    warn!(
+                        "[VfsFullSearchService] Expanded query '{}' failed: {}",
+                        q, e
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let mut results = crate::rag_query_expansion::merge_search_results(result_sets);
+        let raw_scores = results
+            .iter()
+            .map(|r| (r.embedding_id.clone(), r.score))
+            .collect();
+
+        let mut reranked = false;
+        if enable_reranking && !results.is_empty() {
+            let config = VfsIndexingService::new(self.db.clone()).get_search_config()?;
+            if config.enable_reranking && self.reranker_model_id().await?.is_some() {
+                results = self.rerank_results(query, results, params).await?;
+                reranked = true;
+            }
+        }
+        results.truncate(params.top_k as usize);
+
+        Ok(VfsScoreTracedResults {
+            results: Self::enrich_and_filter_results(&self.db, results)?,
+            raw_scores,
+            reranked,
+        })
+    }
+
     // ========================================================================
     // 多模态搜索说明
     // ========================================================================
@@ -4481,6 +4853,23 @@ impl VfsFullSearchService {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_chunking_config_validate() {
+        assert!(ChunkingConfig::default().validate().is_ok());
+        let overlap_too_large = ChunkingConfig {
+            chunk_overlap: 512,
+            ..ChunkingConfig::default()
+        };
+        assert!(overlap_too_large.validate().is_err());
+        let zero_size = ChunkingConfig {
+            chunk_size: 0,
+            chunk_overlap: 0,
+            min_chunk_size: 0,
+            ..ChunkingConfig::default()
+        };
+        assert!(zero_size.validate().is_err());
+    }
+
     #[test]
     fn test_chunk_fixed_size() {
         let config = ChunkingConfig {
@@ -45,7 +45,7 @@ pub use embedding_service::{
 };
 pub use error::{VfsError, VfsResult};
 pub use indexing::{
-    ChunkingConfig, IndexingConfig, PageText, SearchConfig, TextChunk, VfsChunker,
+    ChunkingConfig, IndexStage, IndexingConfig, PageText, SearchConfig, TextChunk, VfsChunker,
     VfsContentExtractor, VfsDimensionStat, VfsEmbeddingStats, VfsFullIndexingService,
     VfsFullSearchService, VfsIndexingService, VfsSearchMode, VfsSearchParams, VfsSearchResult,
     VfsSearchService,
@@ -62,8 +62,8 @@ pub use pdf_processing_service::{
 pub use repos::{
     IndexState, VfsDimensionRepo, VfsEmbedding, VfsEmbeddingDimension, VfsIndexStateRepo,
     VfsIndexingConfigRepo, INDEX_STATE_DISABLED, INDEX_STATE_FAILED, INDEX_STATE_INDEXED,
-    INDEX_STATE_INDEXING, INDEX_STATE_PENDING, MODALITY_MULTIMODAL, MODALITY_TEXT,
-    VFS_EMB_TABLE_PREFIX,
+    INDEX_STATE_INDEXING, INDEX_STATE_PENDING, INDEX_STATE_STALE, MODALITY_MULTIMODAL,
+    MODALITY_TEXT, VFS_EMB_TABLE_PREFIX,
 };
 pub use repos::{
     VfsAttachmentRepo, VfsBlobRepo, VfsEssayRepo, VfsExamRepo, VfsFileRepo, VfsFolderRepo,
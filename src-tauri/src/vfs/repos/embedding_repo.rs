@@ -98,6 +98,8 @@ pub const INDEX_STATE_INDEXING: &str = "indexing";
 pub const INDEX_STATE_INDEXED: &str = "indexed";
 pub const INDEX_STATE_FAILED: &str = "failed";
 pub const INDEX_STATE_DISABLED: &str = "disabled";
+/// 分块参数变更后待重建：旧向量仍可检索，但不会被待索引队列自动拾取
+pub const INDEX_STATE_STALE: &str = "stale";
 
 /// 文本模态
 pub const MODALITY_TEXT: &str = "text";
@@ -558,6 +560,109 @@ impl VfsIndexStateRepo {
         Ok(ids)
     }
 
+    /// 将所有已索引资源标记为 stale（保留 indexed_at 与旧向量），返回标记数量
+    pub fn mark_indexed_as_stale(db: &VfsDatabase, reason: &str) -> VfsResult<usize> {
+        let conn = db.get_conn_safe()?;
+        let now = chrono::Utc::now().timestamp_millis();
+        let updated = conn.execute(
+            r#"
+            UPDATE resources
+            SET index_state = ?1, index_error = ?2, updated_at = ?3
+            WHERE index_state = ?4
+            "#,
+            params![INDEX_STATE_STALE, reason, now, INDEX_STATE_INDEXED],
+        )?;
+        if updated > 0 {
+            info!(
+                "[VfsIndexStateRepo] Marked {} indexed resources as stale: {}",
+                updated, reason
+            );
+        }
+        Ok(updated)
+    }
+
+    /// 统计 stale 资源数；`scope` 为 `Some` 时只统计其中列出的资源
+    pub fn count_stale_resources(db: &VfsDatabase, scope: Option<&[String]>) -> VfsResult<usize> {
+        let conn = db.get_conn_safe()?;
+        let count: i64 = conn.query_row(
+            r#"
+            SELECT COUNT(*) FROM resources
+            WHERE index_state = ?1
+              AND (?2 IS NULL OR id IN (SELECT value FROM json_each(?2)))
+            "#,
+            params![INDEX_STATE_STALE, Self::stale_scope_json(scope)],
+            |row| row.get(0),
+        )?;
+        Ok(count.max(0) as usize)
+    }
+
+    /// 原子 claim 一批 stale 资源并立即置为 indexing，与 `claim_pending_resources` 对应
+    ///
+    /// `scope` 为 `Some` 时只 claim 其中列出的资源（按知识库重建时使用）。
+    pub fn claim_stale_resources(
+        db: &VfsDatabase,
+        limit: u32,
+        scope: Option<&[String]>,
+    ) -> VfsResult<Vec<String>> {
+        let mut conn = db.get_conn_safe()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let ids: Vec<String> = {
+            let mut stmt = tx.prepare(
+                r#"
+                SELECT id FROM resources
+                WHERE index_state = ?1
+                  AND (?3 IS NULL OR id IN (SELECT value FROM json_each(?3)))
+                ORDER BY updated_at DESC
+                LIMIT ?2
+                "#,
+            )?;
+            let rows = stmt.query_map(
+                params![INDEX_STATE_STALE, limit, Self::stale_scope_json(scope)],
+                |row| row.get::<_, String>(0),
+            )?;
+            rows.filter_map(log_and_skip_err).collect()
+        };
+
+        let now = chrono::Utc::now().timestamp_millis();
+        for resource_id in &ids {
+            tx.execute(
+                r#"
+                UPDATE resources
+                SET index_state = ?1, index_error = NULL, updated_at = ?2
+                WHERE id = ?3
+                "#,
+                params![INDEX_STATE_INDEXING, now, resource_id],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    fn stale_scope_json(scope: Option<&[String]>) -> Option<String> {
+        scope.map(|ids| serde_json::to_string(ids).unwrap_or_else(|_| "[]".to_string()))
+    }
+
+    /// 将 `claim_stale_resources` 抢占但未处理的资源退回 stale（任务中止时调用）
+    ///
+    /// 只改回索引状态，旧向量与索引哈希保持不变，重建前仍可检索。
+    pub fn release_stale_claims(db: &VfsDatabase, resource_ids: &[String]) -> VfsResult<()> {
+        let conn = db.get_conn_safe()?;
+        let now = chrono::Utc::now().timestamp_millis();
+        for resource_id in resource_ids {
+            conn.execute(
+                r#"
+                UPDATE resources
+                SET index_state = ?1, updated_at = ?2
+                WHERE id = ?3 AND index_state = ?4
+                "#,
+                params![INDEX_STATE_STALE, now, resource_id, INDEX_STATE_INDEXING],
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn get_resources_needing_reindex(
         db: &VfsDatabase,
         limit: u32,
@@ -838,4 +943,57 @@ mod tests {
         assert_eq!(state.state, INDEX_STATE_INDEXED);
         assert_eq!(state.hash, Some("hash123".to_string()));
     }
+
+    #[test]
+    fn test_mark_indexed_as_stale_and_claim() {
+        let (_temp_dir, db) = setup_test_db();
+
+        let conn = db.get_conn_safe().unwrap();
+        for id in ["res_a", "res_b", "res_c"] {
+            conn.execute(
+                "INSERT INTO resources (id, hash, type, storage_mode, data, created_at, updated_at) VALUES (?1, 'h', 'note', 'inline', 'test', 0, 0)",
+                params![id],
+            ).unwrap();
+        }
+        drop(conn);
+
+        VfsIndexStateRepo::mark_indexed(&db, "res_a", "h").unwrap();
+        VfsIndexStateRepo::mark_indexed(&db, "res_b", "h").unwrap();
+        VfsIndexStateRepo::mark_pending(&db, "res_c").unwrap();
+
+        let marked = VfsIndexStateRepo::mark_indexed_as_stale(&db, "chunking_changed").unwrap();
+        assert_eq!(marked, 2);
+        assert_eq!(
+            VfsIndexStateRepo::count_stale_resources(&db, None).unwrap(),
+            2
+        );
+
+        // stale 资源不进入自动待索引队列
+        let pending = VfsIndexStateRepo::get_pending_resources(&db, 10, 3).unwrap();
+        assert_eq!(pending, vec!["res_c".to_string()]);
+
+        // 限定范围时只统计、claim 范围内的资源
+        let scope = vec!["res_b".to_string(), "res_c".to_string()];
+        assert_eq!(
+            VfsIndexStateRepo::count_stale_resources(&db, Some(&scope)).unwrap(),
+            1
+        );
+        let claimed = VfsIndexStateRepo::claim_stale_resources(&db, 10, Some(&scope)).unwrap();
+        assert_eq!(claimed, vec!["res_b".to_string()]);
+        assert_eq!(
+            VfsIndexStateRepo::count_stale_resources(&db, None).unwrap(),
+            1
+        );
+
+        let claimed = VfsIndexStateRepo::claim_stale_resources(&db, 1, None).unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(
+            VfsIndexStateRepo::count_stale_resources(&db, None).unwrap(),
+            0
+        );
+        let state = VfsIndexStateRepo::get_index_state(&db, &claimed[0])
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.state, INDEX_STATE_INDEXING);
+    }
 }
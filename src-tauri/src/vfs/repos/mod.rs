@@ -43,8 +43,8 @@ pub use blob_repo::VfsBlobRepo;
 pub use embedding_repo::{
     IndexState, VfsDimensionRepo, VfsEmbedding, VfsEmbeddingDimension, VfsIndexStateRepo,
     VfsIndexingConfigRepo, INDEX_STATE_DISABLED, INDEX_STATE_FAILED, INDEX_STATE_INDEXED,
-    INDEX_STATE_INDEXING, INDEX_STATE_PENDING, MODALITY_MULTIMODAL, MODALITY_TEXT,
-    VFS_EMB_TABLE_PREFIX,
+    INDEX_STATE_INDEXING, INDEX_STATE_PENDING, INDEX_STATE_STALE, MODALITY_MULTIMODAL,
+    MODALITY_TEXT, VFS_EMB_TABLE_PREFIX,
 };
 pub use essay_repo::VfsEssayRepo;
 pub use exam_repo::{ImportingSession, VfsExamRepo};
@@ -17,7 +17,7 @@ use serde_json::{json, Value};
 use std::sync::Arc;
 use tracing::{info, warn};
 
-use crate::llm_manager::LLMManager;
+use crate::llm_manager::{LLMManager, StreamMode, StreamTimer};
 use crate::models::AppError;
 use crate::page_rasterizer::PageSlice;
 
@@ -58,6 +58,9 @@ pub struct VlmQuestion {
     /// 是否在本页未完成、续接到下一页
     #[serde(default)]
     pub continues_to_next: bool,
+    /// 是否由题号细分拆出（见 `exam_segmentation`）
+    #[serde(default)]
+    pub split_by_numbering: bool,
 }
 
 /// VLM 识别出的图片/配图
@@ -199,6 +202,7 @@ impl VlmGroundingService {
         let preq = provider
             .build_request(&config.base_url, &api_key, &config.model, &request_body)
             .map_err(|e| AppError::llm(format!("VLM 请求构建失败: {:?}", e)))?;
+        crate::offline_mode::ensure_endpoint_allowed("VLM 识别", &preq.url)?;
 
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(180))
@@ -223,6 +227,7 @@ impl VlmGroundingService {
             for (k, v) in preq.headers.iter() {
                 rb = rb.header(k.as_str(), v.as_str());
             }
+            let rb = self.llm_manager.apply_custom_request_options(rb, &config)?;
 
             if attempt == 0 {
                 info!(
@@ -360,6 +365,7 @@ impl VlmGroundingService {
         let preq = provider
             .build_request(&config.base_url, &api_key, &config.model, &request_body)
             .map_err(|e| AppError::llm(format!("VLM 图片描述请求构建失败: {:?}", e)))?;
+        crate::offline_mode::ensure_endpoint_allowed("VLM 识别", &preq.url)?;
 
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(120))
@@ -460,12 +466,14 @@ impl VlmGroundingService {
         .max(8192)
         .min(32768);
 
+        // 模型不支持流式时改发非流式请求，响应转换为单段 SSE 后沿用增量解析
+        let stream_mode = StreamMode::for_config(&config);
         let mut request_body = json!({
             "model": config.model,
             "messages": messages,
             "temperature": 0.1,
             "max_tokens": max_tokens,
-            "stream": true,
+            "stream": stream_mode.is_streaming(),
         });
 
         if crate::llm_manager::adapters::zhipu::ZhipuAdapter::supports_thinking_static(&config.model) {
@@ -488,6 +496,8 @@ impl VlmGroundingService {
         let preq = provider
             .build_request(&config.base_url, &api_key, &config.model, &request_body)
             .map_err(|e| AppError::llm(format!("VLM DOCX 提取请求构建失败: {:?}", e)))?;
+        crate::offline_mode::ensure_endpoint_allowed("VLM 识别", &preq.url)?;
+        let provider = stream_mode.parse_adapter(provider);
 
         // 流式请求不设全局 timeout，改用 connect timeout + 读 chunk 超时
         let client = reqwest::Client::builder()
@@ -520,8 +530,10 @@ impl VlmGroundingService {
             for (k, v) in preq.headers.iter() {
                 rb = rb.header(k.as_str(), v.as_str());
             }
+            let rb = self.llm_manager.apply_custom_request_options(rb, &config)?;
 
-            let response = match rb.json(&preq.body).send().await {
+            let timer = StreamTimer::start(&config, stream_mode);
+            let response = match timer.send(rb.json(&preq.body)).await {
                 Ok(r) => r,
                 Err(e) => {
                     last_error = format!("VLM DOCX 提取请求失败: {}", e);
@@ -560,7 +572,9 @@ impl VlmGroundingService {
 
             // ===== SSE 流式读取 + 增量 JSON 解析 =====
             use futures_util::StreamExt;
-            let mut stream = response.bytes_stream();
+            let mut stream = stream_mode
+                .into_byte_stream(response, &config, false, timer)
+                .await?;
             let mut sse_buffer = crate::utils::sse_buffer::SseLineBuffer::new();
             let mut json_parser =
                 crate::llm_manager::IncrementalJsonArrayParser::new();
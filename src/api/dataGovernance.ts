@@ -29,6 +29,7 @@ import type {
   MergeStrategy,
   CloudStorageConfig,
   SyncExecutionResponse,
+  SyncPreviewResponse,
   SyncExportResponse,
   SyncImportResponse,
   SyncProgress,
@@ -865,6 +866,26 @@ export async function runSyncWithProgress(
   });
 }
 
+/**
+ * 同步预演（dry-run）
+ *
+ * 按表返回真正同步时将上传、下载和冲突的记录，不上传、不修改任何数据。
+ * 与 `runSyncWithProgress` 发送相同的进度事件，可用于展示“规划”步骤；
+ * 用户确认后再调用 `runSyncWithProgress`。
+ *
+ * @param direction 同步方向
+ * @param cloudConfig 云存储配置
+ */
+export async function previewSync(
+  direction: 'upload' | 'download' | 'bidirectional',
+  cloudConfig?: CloudStorageConfig
+): Promise<SyncPreviewResponse> {
+  return invoke<SyncPreviewResponse>('data_governance_preview_sync', {
+    direction,
+    cloudConfig,
+  });
+}
+
 /**
  * 执行同步并自动管理进度监听
  *
@@ -1264,6 +1285,7 @@ export const DataGovernanceApi = {
 
   // 带进度的同步
   listenSyncProgress,
+  previewSync,
   runSyncWithProgress,
   runSyncWithProgressTracking,
   createSyncProgressState,
@@ -40,6 +40,8 @@ export interface VfsSearchResult {
   pageIndex?: number;
   /** 来源 ID（如 textbook_xxx, att_xxx） */
   sourceId?: string;
+  /** 片段所属知识库（文件夹 ID） */
+  folderId?: string;
 }
 
 /** RAG 搜索输入参数 */
@@ -48,6 +50,8 @@ export interface VfsRagSearchInput {
   query: string;
   /** 文件夹 ID 列表（可选，用于范围过滤） */
   folderIds?: string[];
+  /** 各知识库（文件夹 ID）的检索权重，未列出的库为 1；须非负且至少一个为正 */
+  folderWeights?: Record<string, number>;
   /** 资源类型列表（可选，如 ["note", "textbook"]） */
   resourceTypes?: string[];
   /** 返回结果数量 */
@@ -56,6 +60,58 @@ export interface VfsRagSearchInput {
   enableReranking?: boolean;
   /** 是否启用跨维度搜索（聚合所有已分配模型的维度） */
   enableCrossDimension?: boolean;
+  /** 最低分阈值（0~1），低于该分数的片段丢弃；未设置时不过滤 */
+  minScore?: number;
+  /** 是否返回检索调试轨迹（默认关闭） */
+  debug?: boolean;
+  /** 调试轨迹中提示词的最大字符数（未设置时不截断） */
+  debugPromptMaxChars?: number;
+  /**
+   * 仅本次查询使用的嵌入模型配置 ID（高级，仅文本检索）。
+   * 维度须与知识库已索引维度一致；与索引时不同的模型通常会降低召回。
+   */
+  embeddingModelOverride?: string;
+}
+
+/** 调试轨迹中的候选片段 */
+export interface RagDebugCandidate {
+  embeddingId: string;
+  resourceId: string;
+  resourceTitle?: string;
+  chunkIndex: number;
+  chunkText: string;
+  /** 向量 / 混合检索的原始分数 */
+  rawScore: number;
+  /** 重排序后的分数（未执行重排序时为空） */
+  rerankedScore?: number;
+  /** 是否因低于 minScore 被丢弃 */
+  droppedByMinScore: boolean;
+}
+
+/** RAG 检索调试轨迹 */
+export interface RagDebugTrace {
+  /** 原始查询 */
+  query: string;
+  /** 实际用于检索的主查询（与原始查询一致） */
+  effectiveQuery: string;
+  /** 查询扩展生成的附加查询（未启用时为空） */
+  expandedQueries: string[];
+  /** 本次检索临时指定的查询嵌入模型（未覆盖时为空） */
+  embeddingModelOverride?: {
+    modelConfigId: string;
+    modelName: string;
+    dimension: number;
+  };
+  reranked: boolean;
+  minScore?: number;
+  /** 全部候选片段，按最终排序 */
+  candidates: RagDebugCandidate[];
+  droppedCount: number;
+  /** 以保留片段拼装的系统提示词 */
+  prompt: string;
+  promptTruncated: boolean;
+  /** 截断前的提示词字符数 */
+  promptChars: number;
 }
 
 /** RAG 搜索输出 */
@@ -66,6 +122,8 @@ export interface VfsRagSearchOutput {
   count: number;
   /** 检索耗时（毫秒） */
   elapsedMs: number;
+  /** 调试轨迹（仅 debug 为 true 时返回） */
+  debugTrace?: RagDebugTrace;
 }
 
 /** Lance 表统计 */
@@ -112,10 +170,15 @@ export async function vfsRagSearch(input: VfsRagSearchInput): Promise<VfsRagSear
       input: {
         query: input.query,
         folderIds: input.folderIds ?? null,
+        folderWeights: input.folderWeights ?? null,
         resourceTypes: input.resourceTypes ?? null,
         topK: input.topK ?? 10,
         enableReranking: input.enableReranking ?? true,
         enableCrossDimension: input.enableCrossDimension ?? true,
+        minScore: input.minScore ?? null,
+        debug: input.debug ?? false,
+        debugPromptMaxChars: input.debugPromptMaxChars ?? null,
+        embeddingModelOverride: input.embeddingModelOverride ?? null,
       },
     });
 
@@ -282,6 +345,8 @@ export interface IndexStateCounts {
   indexed: number;
   failed: number;
   disabled: number;
+  /** 分块参数变更后等待重建 */
+  stale: number;
   nullState: number;
 }
 
@@ -2,7 +2,7 @@
  * VFS 统一索引 API
  */
 
-import { invoke } from '@tauri-apps/api/core';
+import { Channel, invoke } from '@tauri-apps/api/core';
 import type {
   IndexStatusSummary,
   UnitIndexStatus,
@@ -73,6 +73,8 @@ export interface ResourceIndexStatusSummary {
   mmIndexingCount: number;
   mmFailedCount: number;
   mmDisabledCount: number;
+  /** 是否已分配文本嵌入模型 */
+  embeddingModelConfigured: boolean;
   resources: ResourceIndexStatus[];
 }
 
@@ -194,12 +196,45 @@ export async function reindexResource(resourceId: string): Promise<number> {
   return invoke<number>('vfs_reindex_resource', { resourceId });
 }
 
+/** 批量索引中单个资源的阶段 */
+export type BatchIndexStage = 'parsing' | 'chunking' | 'embedding' | 'done' | 'failed';
+
+/** 批量索引逐资源进度 */
+export interface BatchIndexProgress {
+  resourceId: string;
+  stage: BatchIndexStage;
+  completed: number;
+  total: number;
+  chunksProcessed?: number;
+  chunksTotal?: number;
+  error?: string;
+}
+
+/** 批量索引结果（含逐资源成功/失败明细） */
+export interface BatchIndexPendingResult {
+  successCount: number;
+  failCount: number;
+  total: number;
+  succeeded: string[];
+  failures: Array<{ resourceId: string; error: string }>;
+}
+
 /**
  * 批量索引待处理资源（旧 API，调用 vfs_batch_index_pending）
+ *
+ * 资源按 `indexing.max_concurrent` 并发处理，`onProgress` 接收逐资源阶段进度。
  */
-export async function batchIndexPendingLegacy(batchSize?: number): Promise<{ successCount: number; failCount: number; total: number }> {
+export async function batchIndexPendingLegacy(
+  batchSize?: number,
+  onProgress?: (progress: BatchIndexProgress) => void,
+): Promise<BatchIndexPendingResult> {
   console.log(LOG_PREFIX, 'batchIndexPendingLegacy:', batchSize);
-  return invoke('vfs_batch_index_pending', { batchSize: batchSize ?? 10 });
+  let channel: Channel<BatchIndexProgress> | undefined;
+  if (onProgress) {
+    channel = new Channel<BatchIndexProgress>();
+    channel.onmessage = onProgress;
+  }
+  return invoke('vfs_batch_index_pending', { batchSize: batchSize ?? 10, onProgress: channel });
 }
 
 /**
@@ -2996,6 +2996,7 @@ export class ChatV2TauriAdapter {
       ragLibraryIds: chatParams.ragLibraryIds,
       // 🔧 P1-35: 传递 Rerank 开关配置
       ragEnableReranking: chatParams.ragEnableReranking,
+      ragMinScore: chatParams.ragMinScore,
 
       // 🆕 多模态知识库检索配置
       // ★ 多模态索引已禁用，强制关闭多模态检索，避免后端报错。恢复时改回 chatParams.multimodalRagEnabled
@@ -36,6 +36,8 @@ export interface SendOptions {
   ragTopK?: number;
   /** 🔧 P1-35: RAG 启用重排序（Rerank）*/
   ragEnableReranking?: boolean;
+  /** RAG 最低相似度阈值（覆盖全局设置） */
+  ragMinScore?: number;
   memoryEnabled?: boolean;
 
   // 🆕 多模态知识库检索选项
@@ -134,6 +134,8 @@ export interface ChatParams {
   ragTopK?: number;
   /** RAG 启用重排序（Rerank）*/
   ragEnableReranking?: boolean;
+  /** RAG 最低相似度阈值（不设置时使用全局设置） */
+  ragMinScore?: number;
   /** RAG 选中的知识库 ID 列表 */
   ragLibraryIds?: string[];
   /** 学习模式提示词（启用学习模式时使用） */
@@ -159,6 +159,9 @@ export interface MessageMeta {
   /** 上下文快照（发送时保存的上下文引用） */
   contextSnapshot?: ContextSnapshot;
 
+  /** 消息生命周期：'cancelled' 表示用户中途取消，内容可能不完整（重试会覆盖） */
+  lifecycle?: 'cancelled';
+
   /** 完整请求体（开发者调试用） */
   rawRequest?: unknown;
 
@@ -147,6 +147,12 @@ export const knowledgeRetrievalSkill: SkillDefinition = {
             default: 0,
             minimum: 0,
           },
+          min_score: {
+            type: 'number',
+            description: '最低相关度阈值（0-1，可选），低于该分数的知识库片段会被丢弃；不填使用用户设置',
+            minimum: 0,
+            maximum: 1,
+          },
           enable_reranking: {
             type: 'boolean',
             description: '是否启用重排序优化结果质量，默认启用',
@@ -73,6 +73,8 @@ export interface TaskCompletedData {
   task_id: string;
   final_status: string;
   total_cards_generated?: number;
+  /** 因卡片数量上限未保存的卡片数 */
+  cards_dropped?: number;
   cards?: AnkiCard[];
 }
 
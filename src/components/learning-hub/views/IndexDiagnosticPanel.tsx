@@ -247,6 +247,7 @@ export const IndexDiagnosticPanel: React.FC<IndexDiagnosticPanelProps> = ({ onRe
           <div>indexed: <span className="text-emerald-500">{stateCounts.indexed}</span></div>
           <div>disabled: <span className="text-gray-500">{stateCounts.disabled}</span></div>
           <div>failed: <span className="text-red-500">{stateCounts.failed}</span></div>
+          <div>stale: <span className="text-orange-500">{stateCounts.stale}</span></div>
         </div>
         {unitsStats && unitsStats.totalCount > 0 && (
           <div className="grid grid-cols-2 gap-x-4 gap-y-1 border-t border-border/30 pt-2 mt-2">
@@ -98,6 +98,10 @@ type EditApiConfig = BaseApiConfig & {
   reasoningSplit?: boolean;
   effort?: string;
   verbosity?: string;
+  forceNonStreaming?: boolean;
+  firstTokenTimeoutMs?: number;
+  idleTimeoutMs?: number;
+  maxRequestRetries?: number;
 };
 
 type CapabilityKey = 'isMultimodal' | 'isReasoning' | 'isEmbedding' | 'isReranker' | 'supportsTools';
@@ -147,6 +151,16 @@ export const ShadApiEditModal: React.FC<ApiEditModalProps> = ({
   const [maxTokensLimitInput, setMaxTokensLimitInput] = useState<string>(() =>
     api.maxTokensLimit != null ? String(api.maxTokensLimit) : ''
   );
+  // 流式超时在界面上以秒为单位编辑，保存为毫秒
+  const [firstTokenTimeoutInput, setFirstTokenTimeoutInput] = useState<string>(() =>
+    api.firstTokenTimeoutMs != null ? String(api.firstTokenTimeoutMs / 1000) : ''
+  );
+  const [idleTimeoutInput, setIdleTimeoutInput] = useState<string>(() =>
+    api.idleTimeoutMs != null ? String(api.idleTimeoutMs / 1000) : ''
+  );
+  const [maxRequestRetriesInput, setMaxRequestRetriesInput] = useState<string>(() =>
+    api.maxRequestRetries != null ? String(api.maxRequestRetries) : ''
+  );
   const [contextWindowInput, setContextWindowInput] = useState<string>(() =>
     api.contextWindow != null ? String(api.contextWindow) : ''
   );
@@ -173,6 +187,10 @@ export const ShadApiEditModal: React.FC<ApiEditModalProps> = ({
     reasoningSplit: (api as any).reasoningSplit ?? undefined,
     effort: (api as any).effort ?? undefined,
     verbosity: (api as any).verbosity ?? undefined,
+    forceNonStreaming: api.forceNonStreaming ?? false,
+    firstTokenTimeoutMs: api.firstTokenTimeoutMs ?? undefined,
+    idleTimeoutMs: api.idleTimeoutMs ?? undefined,
+    maxRequestRetries: api.maxRequestRetries ?? undefined,
   });
 
   const inferredCaps = useMemo(
@@ -524,6 +542,10 @@ export const ShadApiEditModal: React.FC<ApiEditModalProps> = ({
       reasoningSplit: formData.reasoningSplit,
       effort: formData.effort,
       verbosity: formData.verbosity,
+      forceNonStreaming: formData.forceNonStreaming ?? false,
+      firstTokenTimeoutMs: formData.firstTokenTimeoutMs,
+      idleTimeoutMs: formData.idleTimeoutMs,
+      maxRequestRetries: formData.maxRequestRetries,
     };
     // 若用户显式开启了思维链相关任一项，则强制标记 supportsReasoning=true，避免被保存阶段清除
     if (sanitized.includeThoughts || sanitized.enableThinking || sanitized.thinkingBudget !== undefined) {
@@ -893,6 +915,98 @@ export const ShadApiEditModal: React.FC<ApiEditModalProps> = ({
                     </p>
                   </div>
 
+                  {/* 强制非流式请求（网关不支持或缓冲 SSE 时使用） */}
+                  <div className="pt-4 border-t border-border/40">
+                    <div className={cn("flex items-center justify-between p-4 rounded-xl border transition-all duration-200", formData.forceNonStreaming ? "bg-primary/5 border-primary/30" : "bg-card border-border/40 hover:border-border/60")}>
+                      <div className="space-y-1">
+                        <Label className="text-sm font-medium cursor-pointer" onClick={() => setFormData(prev => ({ ...prev, forceNonStreaming: !prev.forceNonStreaming }))}>
+                          {t('settings:api.modal.fields.force_non_streaming')}
+                        </Label>
+                        <p className="text-xs text-muted-foreground/70">
+                          {t('settings:api.modal.fields.force_non_streaming_hint')}
+                        </p>
+                      </div>
+                      <Switch
+                        checked={!!formData.forceNonStreaming || formData.supportsStreaming === false}
+                        disabled={formData.supportsStreaming === false}
+                        onCheckedChange={v => setFormData(prev => ({ ...prev, forceNonStreaming: !!v }))}
+                      />
+                    </div>
+                  </div>
+
+                  {/* 流式超时：首 token 等待与输出间隔（可选，单位秒） */}
+                  <div className="space-y-2 pt-4 border-t border-border/40">
+                    <div className="grid grid-cols-2 gap-3">
+                      {([
+                        ['firstTokenTimeoutMs', 'first_token_timeout', firstTokenTimeoutInput, setFirstTokenTimeoutInput],
+                        ['idleTimeoutMs', 'idle_timeout', idleTimeoutInput, setIdleTimeoutInput],
+                      ] as const).map(([field, labelKey, value, setValue]) => (
+                        <div key={field} className="space-y-2">
+                          <Label className="text-xs font-medium text-muted-foreground/80 uppercase tracking-wider ml-1">
+                            {t(`settings:api.modal.fields.${labelKey}`)}
+                          </Label>
+                          <Input
+                            type="number"
+                            value={value}
+                            onChange={e => setValue((e.target as HTMLInputElement).value)}
+                            onBlur={() => {
+                              const seconds = Number(value.trim());
+                              if (!value.trim() || !Number.isFinite(seconds) || seconds <= 0) {
+                                setFormData(prev => ({ ...prev, [field]: undefined }));
+                                setValue('');
+                                return;
+                              }
+                              const next = Math.max(1, Math.min(3600, Math.round(seconds)));
+                              setFormData(prev => ({ ...prev, [field]: next * 1000 }));
+                              setValue(String(next));
+                            }}
+                            min={1}
+                            max={3600}
+                            step={1}
+                            placeholder={t('settings:api.modal.fields.stream_timeout_placeholder')}
+                            disabled={!!formData.forceNonStreaming || formData.supportsStreaming === false}
+                            className="bg-muted/30 border-transparent hover:border-border/50 focus:border-primary/30 focus:bg-muted/20 focus-visible:ring-0 focus-visible:ring-offset-0 transition-all h-10"
+                          />
+                        </div>
+                      ))}
+                    </div>
+                    <p className="text-[10px] text-muted-foreground/60 ml-1">
+                      {t('settings:api.modal.fields.stream_timeout_hint')}
+                    </p>
+                  </div>
+
+                  {/* 瞬时错误重试次数（可选） */}
+                  <div className="space-y-2 pt-4 border-t border-border/40">
+                    <Label className="text-xs font-medium text-muted-foreground/80 uppercase tracking-wider ml-1">
+                      {t('settings:api.modal.fields.max_request_retries')}
+                    </Label>
+                    <Input
+                      type="number"
+                      value={maxRequestRetriesInput}
+                      onChange={e => setMaxRequestRetriesInput((e.target as HTMLInputElement).value)}
+                      onBlur={() => {
+                        const raw = maxRequestRetriesInput.trim();
+                        const retries = Number(raw);
+                        if (!raw || !Number.isFinite(retries) || retries < 0) {
+                          setFormData(prev => ({ ...prev, maxRequestRetries: undefined }));
+                          setMaxRequestRetriesInput('');
+                          return;
+                        }
+                        const next = Math.min(10, Math.round(retries));
+                        setFormData(prev => ({ ...prev, maxRequestRetries: next }));
+                        setMaxRequestRetriesInput(String(next));
+                      }}
+                      min={0}
+                      max={10}
+                      step={1}
+                      placeholder={t('settings:api.modal.fields.max_request_retries_placeholder')}
+                      className="bg-muted/30 border-transparent hover:border-border/50 focus:border-primary/30 focus:bg-muted/20 focus-visible:ring-0 focus-visible:ring-offset-0 transition-all h-10"
+                    />
+                    <p className="text-[10px] text-muted-foreground/60 ml-1">
+                      {t('settings:api.modal.fields.max_request_retries_hint')}
+                    </p>
+                  </div>
+
                   {/* 上下文窗口大小（可选） */}
                   <div className="space-y-2 pt-4 border-t border-border/40">
                     <Label className="text-xs font-medium text-muted-foreground/80 uppercase tracking-wider ml-1">
@@ -35,10 +35,17 @@ export const convertProfileToApiConfig = (profile: ModelProfile, vendor: VendorC
   topK: profile.topK,
   supportsReasoning: profile.supportsReasoning ?? profile.isReasoning,
   headers: vendor.headers,
+  queryParams: vendor.queryParams,
+  allowReservedHeaderOverride: vendor.allowReservedHeaderOverride,
   repetitionPenalty: profile.repetitionPenalty,
   reasoningSplit: profile.reasoningSplit,
   effort: profile.effort,
   verbosity: profile.verbosity,
+  supportsStreaming: profile.supportsStreaming,
+  forceNonStreaming: profile.forceNonStreaming,
+  firstTokenTimeoutMs: profile.firstTokenTimeoutMs,
+  idleTimeoutMs: profile.idleTimeoutMs,
+  maxRequestRetries: profile.maxRequestRetries,
 });
 
 export const convertApiConfigToProfile = (api: ApiConfig, vendorId: string): ModelProfile => ({
@@ -71,6 +78,11 @@ export const convertApiConfigToProfile = (api: ApiConfig, vendorId: string): Mod
   reasoningSplit: api.reasoningSplit,
   effort: api.effort,
   verbosity: api.verbosity,
+  supportsStreaming: api.supportsStreaming,
+  forceNonStreaming: api.forceNonStreaming,
+  firstTokenTimeoutMs: api.firstTokenTimeoutMs,
+  idleTimeoutMs: api.idleTimeoutMs,
+  maxRequestRetries: api.maxRequestRetries,
 });
 
 export const normalizeBaseUrl = (url: string) => url.trim().replace(/\/+$/, '');
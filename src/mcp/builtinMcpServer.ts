@@ -96,6 +96,12 @@ export const BUILTIN_TOOLS: BuiltinToolSchema[] = [
           maximum: 20,
           description: '每个文档最多返回的结果块数量，0表示不限制。当多个相似文档时，设置此参数可以获得更多样化的结果。',
         },
+        min_score: {
+          type: 'number',
+          minimum: 0,
+          maximum: 1,
+          description: '可选：最低相关度阈值（0-1），低于该分数的片段会被丢弃；不填使用用户设置',
+        },
         enable_reranking: {
           type: 'boolean',
           default: true,
@@ -202,6 +208,12 @@ export const BUILTIN_TOOLS: BuiltinToolSchema[] = [
           minimum: 0,
           description: '每个资源最多返回的片段数，0表示不限制',
         },
+        min_score: {
+          type: 'number',
+          minimum: 0,
+          maximum: 1,
+          description: '可选：最低相关度阈值（0-1），低于该分数的知识库片段会被丢弃；不填使用用户设置',
+        },
         enable_reranking: {
           type: 'boolean',
           default: true,
@@ -731,6 +731,48 @@ export interface SyncExecutionResponse {
   skipped_changes: number;
 }
 
+/** 同步预演中单张表的统计（按记录去重） */
+export interface SyncTablePreview {
+  /** 数据库名称（旧格式变更文件可能缺失） */
+  database_name: string | null;
+  table_name: string;
+  /** 将上传的记录数 */
+  to_upload: number;
+  /** 将下载的记录数 */
+  to_download: number;
+  /** 本地与云端都有改动的记录数（同时计入上传与下载） */
+  conflicts: number;
+}
+
+/** 同步预演中的冲突记录 */
+export interface SyncRecordRef {
+  database_name: string | null;
+  table_name: string;
+  record_id: string;
+}
+
+/** 同步预演响应（不修改任何数据） */
+export interface SyncPreviewResponse {
+  /** 同步方向 */
+  direction: string;
+  /** 各表统计 */
+  tables: SyncTablePreview[];
+  /** 将上传的记录总数 */
+  total_upload: number;
+  /** 将下载的记录总数 */
+  total_download: number;
+  /** 冲突记录 */
+  conflicting_records: SyncRecordRef[];
+  /** 数据库级冲突数量 */
+  database_conflicts: number;
+  /** 是否需要先迁移 */
+  needs_migration: boolean;
+  /** 耗时（毫秒） */
+  duration_ms: number;
+  /** 设备 ID */
+  device_id: string;
+}
+
 /** 同步导出响应 */
 export interface SyncExportResponse {
   /** 是否成功 */
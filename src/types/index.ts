@@ -307,11 +307,26 @@ export interface ApiConfig {
   minP?: number;
   topK?: number;
   supportsReasoning?: boolean;
+  /** 自定义请求头；值写作 `secure:<name>` 时从安全存储读取 */
   headers?: Record<string, string>;
+  /** 自定义查询参数；值同样支持 `secure:<name>` */
+  queryParams?: Record<string, string>;
+  /** 允许自定义请求头覆盖 Authorization 等保留请求头 */
+  allowReservedHeaderOverride?: boolean;
   /** 是否收藏（收藏的模型在列表中优先显示） */
   isFavorite?: boolean;
   /** 供应商级别的 max_tokens 限制（API 最大允许值） */
   maxTokensLimit?: number;
+  /** 接口是否支持流式输出；false 时流式调用自动改为非流式请求 */
+  supportsStreaming?: boolean;
+  /** 即使支持流式也强制使用非流式请求 */
+  forceNonStreaming?: boolean;
+  /** 流式请求等待首个 token 的最长毫秒数 */
+  firstTokenTimeoutMs?: number;
+  /** 流式输出相邻数据块之间的最长间隔毫秒数 */
+  idleTimeoutMs?: number;
+  /** 限流、服务端暂时不可用或连接中断时的最大重试次数（默认 3，0 表示不重试） */
+  maxRequestRetries?: number;
   /** 上下文窗口大小（tokens），推断引擎提供默认值，用户可在设置页覆盖 */
   contextWindow?: number;
   repetitionPenalty?: number;
@@ -326,7 +341,12 @@ export interface VendorConfig {
   providerType: string;
   baseUrl: string;
   apiKey: string;
+  /** 自定义请求头；值写作 `secure:<name>` 时从安全存储读取 */
   headers?: Record<string, string>;
+  /** 自定义查询参数；值同样支持 `secure:<name>` */
+  queryParams?: Record<string, string>;
+  /** 允许自定义请求头覆盖 Authorization 等保留请求头 */
+  allowReservedHeaderOverride?: boolean;
   rateLimitPerMinute?: number;
   defaultTimeoutMs?: number;
   notes?: string;
@@ -371,6 +391,11 @@ export interface ModelProfile {
   reasoningSplit?: boolean;
   effort?: string;
   verbosity?: string;
+  supportsStreaming?: boolean;
+  forceNonStreaming?: boolean;
+  firstTokenTimeoutMs?: number;
+  idleTimeoutMs?: number;
+  maxRequestRetries?: number;
 }
 
 export interface ModelAssignments {
@@ -378,6 +403,7 @@ export interface ModelAssignments {
   anki_card_model_config_id: string | null;
   qbank_ai_grading_model_config_id: string | null;
   embedding_model_config_id: string | null;
+  embedding_fallback_model_config_id?: string | null; // 备用嵌入模型（须与主模型同维度）
   reranker_model_config_id: string | null;
   chat_title_model_config_id: string | null;
   exam_sheet_ocr_model_config_id: string | null;
@@ -386,6 +412,13 @@ export interface ModelAssignments {
   vl_embedding_model_config_id: string | null;  // 多模态嵌入模型（Qwen3-VL-Embedding）
   vl_reranker_model_config_id: string | null;   // 多模态重排序模型（Qwen3-VL-Reranker）
   memory_decision_model_config_id: string | null; // 记忆决策模型（smart write 去重判断）
+  subject_model_overrides?: Record<string, SubjectModelOverride>; // 按科目覆盖分析/OCR 模型，键为科目名
+}
+
+// 单个科目的模型覆盖；未设置的角色回退到全局分配
+export interface SubjectModelOverride {
+  analysis_model_config_id?: string | null;
+  ocr_model_config_id?: string | null;
 }
 
 // 子适配器类型（与后端 ADAPTER_REGISTRY 保持一致）
@@ -439,6 +472,30 @@ export interface StreamChunk {
   chunk_id: string;
 }
 
+/** 统一流式事件（`stream_event` 通道），seq 在同一流内从 0 递增 */
+export type StreamEventSource =
+  | 'model'
+  | 'translation'
+  | 'essay_grading'
+  | 'qbank_grading'
+  | 'anki_generation';
+
+export type StreamEvent =
+  | { type: 'token'; content: string }
+  | { type: 'thinking'; content: string }
+  | { type: 'tool_call'; id: string; name: string; arguments: unknown }
+  | { type: 'tool_result'; id: string; name: string; result: unknown }
+  | { type: 'sources'; kind: string; sources: unknown }
+  | { type: 'usage'; usage: unknown }
+  | { type: 'error'; message: string; code?: string }
+  | { type: 'done'; cancelled: boolean; result?: unknown };
+
+export type StreamEventEnvelope = StreamEvent & {
+  streamId: string;
+  source: StreamEventSource;
+  seq: number;
+};
+
 export interface StreamMessage {
   id: string;
   role: 'user' | 'assistant' | 'system';
@@ -922,6 +979,10 @@ export interface AnkiGenerationOptions {
   max_cards_per_mistake?: number;
   /** ChatAnki 全流程总上限（可选） */
   max_cards_total?: number;
+  /** 每个分段最多保存的卡片数（硬上限，超出部分丢弃并标记任务截断） */
+  max_cards_per_segment?: number;
+  /** 整个文档最多保存的卡片数（各分段共享的硬上限） */
+  max_cards_per_document?: number;
   max_tokens?: number;
   temperature?: number;
   template_id?: string;
@@ -933,6 +994,10 @@ export interface AnkiGenerationOptions {
   custom_requirements?: string;
   segment_overlap_size?: number;
   system_prompt?: string;
+  /** 自动来源标签包含的维度，组成层级标签 `source::科目::文档::segment_NNN`；不设置则不添加 */
+  source_tag_facets?: Array<'subject' | 'document' | 'segment'>;
+  /** 字段 HTML 处理方式：原样保留 / 只保留安全标签（默认） / 转为纯文本；不设置则使用设置项 anki.html_mode */
+  html_mode?: 'preserve' | 'sanitize' | 'strip';
 }
 
 export interface AnkiDocumentGenerationRequest {
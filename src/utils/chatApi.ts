@@ -178,6 +178,8 @@ export async function exportAnkiCards(options: {
   deckName?: string;
   noteType?: string;
   templateId?: string | null;
+  /** 导出哪些字段（include 为空表示全部，exclude 中的字段总是去掉） */
+  fieldSelection?: { include?: string[]; exclude?: string[] };
 }): Promise<ExportAnkiCardsResult> {
   const request = {
     ids: options.ids,
@@ -185,6 +187,7 @@ export async function exportAnkiCards(options: {
     deck_name: options.deckName,
     note_type: options.noteType,
     template_id: options.templateId ?? undefined,
+    fieldSelection: options.fieldSelection,
   };
   return invoke<ExportAnkiCardsResult>('export_anki_cards', { request });
 }
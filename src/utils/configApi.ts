@@ -127,6 +127,25 @@ export async function saveVendorConfigs(configs: VendorConfig[]): Promise<void>
   }
 }
 
+/** 保存自定义请求头/查询参数引用的敏感值（在配置中写作 `secure:<name>`） */
+export async function saveLlmRequestSecret(name: string, value: string): Promise<void> {
+  try {
+    await invoke<void>('save_llm_request_secret', { name, value });
+  } catch (error) {
+    console.error('Failed to save request secret:', error);
+    throw new Error(`Failed to save request secret: ${error}`);
+  }
+}
+
+export async function deleteLlmRequestSecret(name: string): Promise<boolean> {
+  try {
+    return await invoke<boolean>('delete_llm_request_secret', { name });
+  } catch (error) {
+    console.error('Failed to delete request secret:', error);
+    throw new Error(`Failed to delete request secret: ${error}`);
+  }
+}
+
 export async function getModelProfiles(): Promise<ModelProfile[]> {
   try {
     return await invoke<ModelProfile[]>('get_model_profiles');